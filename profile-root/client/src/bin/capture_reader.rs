@@ -0,0 +1,97 @@
+//! Frame capture reader
+//!
+//! Replays a capture file written with `PROFILE_CAPTURE_FILE` against the
+//! client's message parsers and prints one line per text frame plus a
+//! summary, so parser regressions can be checked against real traffic.
+//!
+//! Usage: `capture_reader <capture.jsonl> [client|server]`
+//!
+//! The second argument names the side that wrote the capture (default
+//! `client`); it decides which frames were sent by the server. Server-bound
+//! frames are only checked for a recognizable message type.
+
+use profile_client::connection::dispatcher::{classify_message, MessageKind};
+use profile_shared::capture::{read_capture, Direction, FrameKind};
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+
+/// Describe a frame sent to the server by its message type
+fn server_bound_type(text: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    value
+        .get("message_type")
+        .or_else(|| value.get("type"))
+        .and_then(|t| t.as_str())
+        .map(str::to_string)
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(path) = args.next() else {
+        eprintln!("Usage: capture_reader <capture.jsonl> [client|server]");
+        return ExitCode::FAILURE;
+    };
+    let written_by_server = match args.next().as_deref() {
+        None | Some("client") => false,
+        Some("server") => true,
+        Some(other) => {
+            eprintln!("Unknown side '{}', expected client or server", other);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let records = match read_capture(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut unrecognized = 0;
+
+    for record in &records {
+        if record.kind != FrameKind::Text {
+            *counts
+                .entry(format!("{:?}", record.kind).to_lowercase())
+                .or_default() += 1;
+            continue;
+        }
+
+        let from_server = (record.direction == Direction::Inbound) != written_by_server;
+        let label = if from_server {
+            match classify_message(&record.data).kind() {
+                MessageKind::Unknown => None,
+                kind => Some(format!("server->client {}", kind.as_str())),
+            }
+        } else {
+            server_bound_type(&record.data).map(|t| format!("client->server {}", t))
+        };
+
+        match label {
+            Some(label) => {
+                println!("{} conn={} {}", record.ts_ms, record.connection, label);
+                *counts.entry(label).or_default() += 1;
+            }
+            None => {
+                println!(
+                    "{} conn={} UNRECOGNIZED {}",
+                    record.ts_ms, record.connection, record.data
+                );
+                unrecognized += 1;
+            }
+        }
+    }
+
+    println!();
+    println!("{} frames", records.len());
+    for (label, count) in &counts {
+        println!("  {:>6}  {}", count, label);
+    }
+    if unrecognized > 0 {
+        println!("  {:>6}  unrecognized", unrecognized);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,472 @@
+//! Headless Profile client
+//!
+//! A scriptable client without the UI, for integration tests and bots,
+//! built on [`profile_client::bot::ProfileClient`].
+//! Built only with the `cli` feature:
+//! `cargo run -p profile-client --features cli --bin profile-cli -- <command>`
+//!
+//! Commands:
+//! - `keygen <key-file>` writes a new key's recovery phrase to a new file
+//! - `whoami` prints the public key of the identity
+//! - `lobby` prints the first page of online users
+//! - `send <recipient> <message>` signs and sends a direct message
+//! - `listen` prints received messages until `--count` or `--timeout`
+//! - `audit [<event>...]` prints the local audit log (see
+//!   [`profile_client::state::audit`]), optionally only some event kinds,
+//!   records from `--since`, the last `--count`, or writes them to an
+//!   `--export` file (`.csv` for CSV, otherwise JSON)
+//! - `server list|add|remove|use` edits the server profiles in the settings
+//!   (see [`profile_client::config`]): `add <name> <url>` saves a profile,
+//!   through `--proxy <url>` if given, `remove <name>` deletes one and
+//!   `use [<name>]` makes it active (no name for the default server)
+//! - `escrow-open` opens the local key escrow with the passphrase read from
+//!   stdin, throttled and wiped as the settings say
+//!
+//! `--validate-against <url>`, in place of a command, runs the protocol
+//! conformance dry run (see [`profile_client::conformance`]) against the
+//! server at `url` with two guest identities. It prints a JSON line per
+//! check and a summary line, and exits non-zero unless every check passed.
+//!
+//! Every command but `keygen`, `audit`, `server` and `escrow-open` takes
+//! the identity from `--key <key-file>`
+//! (a recovery phrase or hex private key) or uses a throwaway `--guest`
+//! one. The server is `--server <url>`, otherwise the one the settings pick
+//! (see `profile_client::config`). Results are printed to stdout as one JSON
+//! object per line; errors go to stderr with a non-zero exit code.
+
+use futures_util::StreamExt;
+use profile_client::bot::ProfileClient;
+use profile_client::config::{default_config_path, ClientConfig, ServerProfile};
+use profile_client::conformance::{self, CheckStatus};
+use profile_client::events::ClientEvent;
+use profile_client::handlers;
+use profile_client::state::audit::{default_audit_log_path, export_records, read_audit_log};
+use profile_client::state::create_shared_key_state;
+use profile_client::state::messages::ChatMessageSerializable;
+use profile_client::state::{AuditExportFormat, AuditKind, AuditQuery};
+use profile_client::ui::lobby_state::LobbyPage;
+use serde_json::json;
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: profile-cli <command> [options]
+       profile-cli --validate-against <url> [--timeout <secs>]
+
+Commands:
+  keygen <key-file>              Create a key and save its recovery phrase
+  whoami                         Print the identity's public key
+  lobby                          Print the online users
+  send <recipient> <message>     Send a signed direct message
+  listen                         Print received messages as JSON lines
+  audit [<event>...]             Print the local audit log as JSON lines
+  server list                    Print the saved server profiles
+  server add <name> <url>        Save a server profile
+  server remove <name>           Delete a server profile
+  server use [<name>]            Connect with a profile (none: the default)
+  escrow-open                    Open the key escrow, passphrase on stdin
+
+Options:
+  --key <key-file>     Identity to use (recovery phrase or hex private key)
+  --guest              Use a throwaway guest identity instead
+  --server <url>       Server to connect to
+  --count <n>          listen: stop after n messages;
+                       audit: only the last n records
+  --since <time>       audit: only records from this RFC 3339 time on
+  --export <file>      audit: write the records to file (.csv or JSON)
+  --proxy <url>        server add: reach the server through this proxy
+  --timeout <secs>     listen: stop after this long (default: never);
+                       send: how long to wait for errors (default: 1);
+                       validation: time limit for each check (default: 5)
+  --validate-against <url>
+                       Check the server at url speaks the protocol, with a
+                       scripted conversation with an echo peer";
+
+type CliResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Parsed command line
+#[derive(Debug, Default)]
+struct Args {
+    command: String,
+    positional: Vec<String>,
+    key_file: Option<String>,
+    guest: bool,
+    server: Option<String>,
+    count: Option<usize>,
+    timeout: Option<Duration>,
+    validate_against: Option<String>,
+    since: Option<String>,
+    export: Option<String>,
+    proxy: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--key" => parsed.key_file = Some(value("--key")?),
+            "--guest" => parsed.guest = true,
+            "--server" => parsed.server = Some(value("--server")?),
+            "--validate-against" => parsed.validate_against = Some(value("--validate-against")?),
+            "--since" => parsed.since = Some(value("--since")?),
+            "--export" => parsed.export = Some(value("--export")?),
+            "--proxy" => parsed.proxy = Some(value("--proxy")?),
+            "--count" => {
+                let count = value("--count")?;
+                parsed.count = Some(count.parse().map_err(|_| "--count needs a number")?);
+            }
+            "--timeout" => {
+                let secs: f64 = value("--timeout")?
+                    .parse()
+                    .map_err(|_| "--timeout needs a number of seconds")?;
+                parsed.timeout = Some(
+                    Duration::try_from_secs_f64(secs)
+                        .map_err(|_| "--timeout needs a number of seconds")?,
+                );
+            }
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ if parsed.command.is_empty() => parsed.command = arg,
+            _ => parsed.positional.push(arg),
+        }
+    }
+    match (parsed.command.is_empty(), parsed.validate_against.is_some()) {
+        (true, false) => Err(USAGE.to_string()),
+        (false, true) => Err("--validate-against takes no command".to_string()),
+        _ => Ok(parsed),
+    }
+}
+
+/// Print one JSON result line
+fn emit(value: serde_json::Value) {
+    println!("{}", value);
+}
+
+/// Create a key and save its recovery phrase to a file that must not exist
+async fn keygen(path: &str) -> CliResult<()> {
+    use std::io::Write;
+
+    let key_state = create_shared_key_state();
+    let public_key = handlers::handle_generate_new_key(&key_state).await?;
+    let phrase = zeroize::Zeroizing::new(handlers::handle_export_mnemonic(&key_state).await?);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Cannot create {}: {}", path, e))?;
+    writeln!(file, "{}", phrase.as_str())?;
+
+    emit(json!({ "publicKey": public_key, "keyFile": path }));
+    Ok(())
+}
+
+/// Load the identity named on the command line
+async fn load_identity(args: &Args) -> CliResult<ProfileClient> {
+    let bot = match (&args.key_file, args.guest) {
+        (Some(_), true) => return Err("Use either --key or --guest, not both".into()),
+        (Some(path), false) => {
+            let secret = zeroize::Zeroizing::new(
+                std::fs::read_to_string(path)
+                    .map_err(|e| format!("Cannot read {}: {}", path, e))?,
+            );
+            ProfileClient::import(&secret).await?
+        }
+        (None, true) => ProfileClient::guest().await?,
+        (None, false) => return Err("An identity is needed: --key <key-file> or --guest".into()),
+    };
+    match &args.server {
+        Some(url) => Ok(bot.with_server(ServerProfile::new("cli", url.as_str())?)),
+        None => Ok(bot),
+    }
+}
+
+/// Connect and authenticate as the identity named on the command line
+async fn sign_in(args: &Args) -> CliResult<(ProfileClient, LobbyPage)> {
+    let mut bot = load_identity(args).await?;
+    bot.connect().await?;
+    let lobby = bot.authenticate().await?;
+    Ok((bot, lobby))
+}
+
+/// Print the first page of online users
+async fn lobby(args: &Args) -> CliResult<()> {
+    let (bot, lobby) = sign_in(args).await?;
+    let users: Vec<_> = lobby
+        .users
+        .into_iter()
+        .map(|user| {
+            json!({
+                "publicKey": user.public_key,
+                "nickname": user.nickname,
+                "name": user.verified_name,
+            })
+        })
+        .collect();
+    emit(json!({ "users": users, "total": lobby.total, "nextCursor": lobby.next_cursor }));
+    bot.close().await?;
+    Ok(())
+}
+
+/// Read events until `stop` says so for one, or `timeout`
+///
+/// # Returns
+/// Ok(()) when stopped, timed out or closed, Err if the connection failed
+async fn run_until(
+    bot: &mut ProfileClient,
+    timeout: Option<Duration>,
+    mut stop: impl FnMut(ClientEvent) -> CliResult<bool>,
+) -> CliResult<()> {
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(deadline);
+    let mut events = Box::pin(bot.events());
+    loop {
+        tokio::select! {
+            _ = &mut deadline => return Ok(()),
+            event = events.next() => match event {
+                Some(event) => {
+                    if stop(event?)? {
+                        return Ok(());
+                    }
+                }
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Sign and send a direct message, then wait briefly for the server to
+/// refuse it
+async fn send(args: &Args) -> CliResult<()> {
+    let [recipient, message] = args.positional.as_slice() else {
+        return Err("send needs a recipient and a message".into());
+    };
+    let (mut bot, _) = sign_in(args).await?;
+    let message_id = bot.send(recipient, message).await?;
+
+    let mut queued = false;
+    let wait = args.timeout.unwrap_or(Duration::from_secs(1));
+    run_until(&mut bot, Some(wait), |event| match event {
+        ClientEvent::RecipientOffline(_) => {
+            queued = true;
+            Ok(true)
+        }
+        ClientEvent::Error(error) => Err(error.into()),
+        _ => Ok(false),
+    })
+    .await?;
+
+    emit(json!({ "messageId": message_id, "recipient": recipient, "delivered": !queued }));
+    bot.close().await?;
+    if queued {
+        return Err("Recipient is offline".into());
+    }
+    Ok(())
+}
+
+/// Print verified messages as they arrive
+async fn listen(args: &Args) -> CliResult<()> {
+    let (mut bot, _) = sign_in(args).await?;
+    emit(json!({ "listening": bot.public_key() }));
+
+    let mut received = 0;
+    run_until(&mut bot, args.timeout, |event| {
+        match event {
+            ClientEvent::MessageReceived(message) => {
+                emit(serde_json::to_value(ChatMessageSerializable::from(
+                    message,
+                ))?);
+                received += 1;
+            }
+            ClientEvent::InvalidSignature(notice) => emit(json!({ "rejected": notice })),
+            _ => {}
+        }
+        Ok(args.count.is_some_and(|count| received >= count))
+    })
+    .await?;
+    bot.close().await?;
+    Ok(())
+}
+
+/// Print or export the audit log records the arguments select
+fn audit(args: &Args) -> CliResult<()> {
+    let path = default_audit_log_path().ok_or("No audit log location: set PROFILE_AUDIT_LOG")?;
+    let mut query = AuditQuery::new();
+    for name in &args.positional {
+        let kind = AuditKind::parse(name).ok_or_else(|| {
+            let kinds: Vec<_> = AuditKind::ALL.iter().map(AuditKind::as_str).collect();
+            format!(
+                "Unknown audit event '{}' (one of {})",
+                name,
+                kinds.join(", ")
+            )
+        })?;
+        query = query.with_kind(kind);
+    }
+    if let Some(since) = &args.since {
+        let since = chrono::DateTime::parse_from_rfc3339(since)
+            .map_err(|_| "--since needs an RFC 3339 time")?;
+        query = query.with_since(since.into());
+    }
+    if let Some(count) = args.count {
+        query = query.with_limit(count);
+    }
+
+    let records = query.apply(read_audit_log(&path)?);
+    match &args.export {
+        Some(export) => {
+            let format = AuditExportFormat::from_path(std::path::Path::new(export));
+            std::fs::write(export, export_records(&records, format)?)
+                .map_err(|e| format!("Cannot write {}: {}", export, e))?;
+            emit(json!({ "exported": records.len(), "file": export }));
+        }
+        None => {
+            for record in &records {
+                emit(serde_json::to_value(record)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Edit or print the server profiles in the settings file
+fn server(args: &Args) -> CliResult<()> {
+    let path = default_config_path().ok_or("No settings location: set PROFILE_CONFIG_FILE")?;
+    let mut config = ClientConfig::load(&path)?;
+    match args
+        .positional
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["list"] => {
+            for profile in &config.profiles {
+                emit(json!({
+                    "name": profile.name,
+                    "url": profile.url,
+                    "proxy": profile.proxy,
+                    "active": config.active_profile.as_deref() == Some(profile.name.as_str()),
+                }));
+            }
+            return Ok(());
+        }
+        ["add", name, url] => {
+            let mut profile = ServerProfile::new(*name, *url)?;
+            if let Some(proxy) = &args.proxy {
+                profile = profile.with_proxy(proxy.as_str())?;
+            }
+            emit(json!({ "saved": profile.name, "url": profile.url, "proxy": profile.proxy }));
+            config.save_profile(profile);
+        }
+        ["remove", name] => {
+            if !config.remove_profile(name) {
+                return Err(format!("No server profile named {}", name).into());
+            }
+            emit(json!({ "removed": name }));
+        }
+        ["use", rest @ ..] if rest.len() <= 1 => {
+            let name = rest.first().copied();
+            config.select(name)?;
+            emit(json!({ "active": name }));
+        }
+        _ => {
+            return Err("server needs list, add <name> <url>, remove <name> or use [<name>]".into())
+        }
+    }
+    config.save(&path)?;
+    Ok(())
+}
+
+/// Open the local key escrow with the passphrase on stdin's first line
+fn escrow_open() -> CliResult<()> {
+    let mut passphrase = zeroize::Zeroizing::new(String::new());
+    std::io::stdin().read_line(&mut passphrase)?;
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+    match handlers::handle_open_default_key_escrow(passphrase)? {
+        Some(keys) => {
+            emit(json!({ "opened": true, "conversations": keys.len() }));
+            Ok(())
+        }
+        None => Err("No key escrow to open".into()),
+    }
+}
+
+/// Run the conformance dry run against a server and print its checks
+async fn validate(url: &str, timeout: Option<Duration>) -> CliResult<()> {
+    let step_timeout = timeout.unwrap_or(conformance::DEFAULT_STEP_TIMEOUT);
+    let report = conformance::validate(url, step_timeout).await?;
+    for check in &report.checks {
+        emit(serde_json::to_value(check)?);
+    }
+    emit(json!({
+        "server": report.server,
+        "passed": report.count(CheckStatus::Passed),
+        "failed": report.count(CheckStatus::Failed),
+        "skipped": report.count(CheckStatus::Skipped),
+        "conformant": report.is_conformant(),
+    }));
+    if !report.is_conformant() {
+        return Err(format!("{} does not conform\n\n{}", url, report).into());
+    }
+    Ok(())
+}
+
+async fn run(args: Args) -> CliResult<()> {
+    if let Some(url) = &args.validate_against {
+        return validate(url, args.timeout).await;
+    }
+    match args.command.as_str() {
+        "keygen" => match args.positional.as_slice() {
+            [path] => keygen(path).await,
+            _ => Err("keygen needs a key file to create".into()),
+        },
+        "whoami" => {
+            let bot = load_identity(&args).await?;
+            emit(json!({ "publicKey": bot.public_key() }));
+            Ok(())
+        }
+        "lobby" => lobby(&args).await,
+        "send" => send(&args).await,
+        "listen" => listen(&args).await,
+        "audit" => audit(&args),
+        "server" => server(&args),
+        "escrow-open" => escrow_open(),
+        other => Err(format!("Unknown command '{}'\n\n{}", other, USAGE).into()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start runtime: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match runtime.block_on(run(args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! State journal replay
+//!
+//! Rebuilds the client's UI state from a journal written with
+//! `PROFILE_STATE_JOURNAL` and prints it, so a report like "the lobby showed
+//! the wrong user selected" can be reproduced from the user's journal.
+//!
+//! Usage: `state_replay <journal.jsonl> [--at <seq>] [--list]`
+//!
+//! `--at` stops after the event with that sequence number (default: the
+//! last one); `--list` prints the events applied before the state.
+
+use profile_client::state::journal::{read_journal, replay, JournalRecord};
+use profile_client::state::ConversationId;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: state_replay <journal.jsonl> [--at <seq>] [--list]";
+
+/// Event name and arguments of a record, as written in the journal
+fn describe(record: &JournalRecord) -> String {
+    let mut value = serde_json::to_value(&record.event).unwrap_or_default();
+    let name = value
+        .as_object_mut()
+        .and_then(|fields| fields.remove("event"))
+        .and_then(|name| name.as_str().map(str::to_string))
+        .unwrap_or_default();
+    match value.as_object() {
+        Some(fields) if !fields.is_empty() => format!("{} {}", name, value),
+        _ => name,
+    }
+}
+
+fn main() -> ExitCode {
+    let mut path = None;
+    let mut until_seq = None;
+    let mut list = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--at" => match args.next().and_then(|seq| seq.parse::<u64>().ok()) {
+                Some(seq) => until_seq = Some(seq),
+                None => {
+                    eprintln!("--at needs a sequence number");
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--list" => list = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    let records = match read_journal(&path) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let applied: Vec<&JournalRecord> = records
+        .iter()
+        .take_while(|record| until_seq.is_none_or(|until| record.seq <= until))
+        .collect();
+
+    if list {
+        for record in &applied {
+            println!("{:>6} {} {}", record.seq, record.ts_ms, describe(record));
+        }
+        println!();
+    }
+
+    let snapshot = replay(applied.iter().copied(), None);
+    match applied.last() {
+        Some(record) => println!(
+            "State after event {} of {} (tsMs {})",
+            record.seq,
+            records.len(),
+            record.ts_ms
+        ),
+        None => println!("Initial state (no events applied)"),
+    }
+
+    println!();
+    println!("Lobby: {} user(s)", snapshot.lobby.len());
+    for user in snapshot.lobby.users() {
+        let selected = snapshot.lobby.selected_user() == Some(user.public_key.as_str());
+        println!(
+            "  {} {} {}{}{}",
+            if selected { '>' } else { ' ' },
+            user.public_key,
+            if user.is_online { "online" } else { "offline" },
+            user.nickname
+                .as_ref()
+                .map(|nickname| format!(" \"{}\"", nickname))
+                .unwrap_or_default(),
+            match ConversationId::new(&user.public_key)
+                .map_or(0, |peer| snapshot.lobby.unread_count(&peer))
+            {
+                0 => String::new(),
+                unread => format!(" ({} unread)", unread),
+            }
+        );
+    }
+
+    println!();
+    match snapshot.chat.selected_recipient() {
+        Some(recipient) => println!("Chat with {}", recipient),
+        None => println!("Chat: no conversation open"),
+    }
+    for message in snapshot.chat.messages() {
+        println!(
+            "  {} {}{}: {}",
+            message.timestamp,
+            if message.is_self {
+                "me".to_string()
+            } else {
+                message.sender_key_short.clone()
+            },
+            if message.is_verified {
+                ""
+            } else {
+                " (unverified)"
+            },
+            message.content
+        );
+    }
+
+    println!();
+    println!(
+        "Composer: {:?}, recipient {}, draft {:?}",
+        snapshot.composer.connection_state(),
+        snapshot.composer.get_recipient().unwrap_or("none"),
+        snapshot.composer.get_draft()
+    );
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,368 @@
+//! High-level client for bots and automation
+//!
+//! [`ProfileClient`] wraps the WebSocket client, key handling and event bus
+//! behind a few async calls, so other crates can talk to a Profile server
+//! without the UI plumbing:
+//!
+//! ```no_run
+//! # async fn run() -> profile_client::bot::BotResult<()> {
+//! use futures_util::StreamExt;
+//! use profile_client::bot::ProfileClient;
+//!
+//! let mut bot = ProfileClient::guest().await?;
+//! bot.connect().await?;
+//! bot.authenticate().await?;
+//! let mut messages = Box::pin(bot.messages());
+//! while let Some(message) = messages.next().await {
+//!     let message = message?;
+//!     println!("{}: {}", message.sender_public_key, message.message);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Incoming frames are only read while [`ProfileClient::next_event`] (or one
+//! of the streams built on it) is awaited; nothing runs in the background.
+
+use crate::config::ServerProfile;
+use crate::connection::client::{AuthResponse, WebSocketClient};
+use crate::events::{ClientEvent, EventSubscriber};
+use crate::handlers;
+use crate::state::messages::ChatMessage;
+use crate::state::{create_shared_key_state, SharedKeyState};
+use crate::ui::lobby_state::{LobbyPage, LobbyUser};
+use futures_util::Stream;
+
+/// Result of a [`ProfileClient`] call
+pub type BotResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// A signed-in Profile identity with its own server connection
+pub struct ProfileClient {
+    client: WebSocketClient,
+    key_state: SharedKeyState,
+    public_key: String,
+    events: EventSubscriber,
+    /// The message loop ended; only already published events are left
+    finished: bool,
+}
+
+impl ProfileClient {
+    /// Use the key already loaded into `key_state`
+    ///
+    /// # Errors
+    /// Returns an error if `key_state` holds no key
+    pub async fn new(key_state: SharedKeyState) -> BotResult<Self> {
+        let public_key = key_state
+            .lock()
+            .await
+            .public_key()
+            .map(hex::encode)
+            .ok_or("No key loaded")?;
+        let client = WebSocketClient::new(key_state.clone());
+        let events = client.subscribe();
+        Ok(Self {
+            client,
+            key_state,
+            public_key,
+            events,
+            finished: false,
+        })
+    }
+
+    /// Use the identity from a recovery phrase or hex private key
+    pub async fn import(secret: &str) -> BotResult<Self> {
+        let key_state = create_shared_key_state();
+        handlers::handle_import_key(&key_state, secret.to_string()).await?;
+        Self::new(key_state).await
+    }
+
+    /// Use a new throwaway guest identity
+    pub async fn guest() -> BotResult<Self> {
+        let key_state = create_shared_key_state();
+        handlers::handle_generate_guest_key(&key_state).await?;
+        Self::new(key_state).await
+    }
+
+    /// Connect to `server` instead of the one the settings pick (see
+    /// [`crate::config`])
+    pub fn with_server(mut self, server: ServerProfile) -> Self {
+        self.client.use_profile(Some(server));
+        self
+    }
+
+    /// Public key of this identity (hex)
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// Shared state holding this identity's key
+    pub fn key_state(&self) -> &SharedKeyState {
+        &self.key_state
+    }
+
+    /// The underlying WebSocket client, for anything this facade lacks
+    pub fn client_mut(&mut self) -> &mut WebSocketClient {
+        &mut self.client
+    }
+
+    /// Open the connection to the server
+    pub async fn connect(&mut self) -> BotResult<()> {
+        self.finished = false;
+        self.client.connect().await
+    }
+
+    /// Sign in on the open connection
+    ///
+    /// # Returns
+    /// The first page of online users
+    ///
+    /// # Errors
+    /// Returns an error if the server refuses the identity
+    pub async fn authenticate(&mut self) -> BotResult<LobbyPage> {
+        match self.client.authenticate().await? {
+            AuthResponse::Success {
+                users,
+                total,
+                next_cursor,
+                mut nicknames,
+                mut names,
+                mut statuses,
+                ..
+            } => Ok(LobbyPage {
+                users: users
+                    .into_iter()
+                    .map(|key| {
+                        let nickname = nicknames.remove(&key);
+                        let name = names.remove(&key);
+                        let status = statuses.remove(&key).unwrap_or_default();
+                        LobbyUser::new(key, true)
+                            .with_nickname(nickname)
+                            .with_verified_name(name)
+                            .with_status(status)
+                    })
+                    .collect(),
+                next_cursor,
+                total,
+            }),
+            AuthResponse::Failed { reason, details } => {
+                Err(format!("Authentication failed ({}): {}", reason, details).into())
+            }
+        }
+    }
+
+    /// Sign and send a direct message
+    ///
+    /// # Returns
+    /// The id of the sent message
+    pub async fn send(&mut self, recipient: &str, text: &str) -> BotResult<String> {
+        let history = self.client.message_history();
+        let json = handlers::compose_and_send_message(
+            text.to_string(),
+            recipient.to_string(),
+            &self.key_state,
+            &history,
+        )
+        .await?;
+        let message_id = serde_json::from_str::<serde_json::Value>(&json)?
+            .get("messageId")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string)
+            .ok_or("Composed message has no id")?;
+        self.client.send_message(json).await?;
+        Ok(message_id)
+    }
+
+    /// Subscribe to events without reading from the connection
+    ///
+    /// The subscriber only sees events while something awaits
+    /// [`Self::next_event`] or one of the streams.
+    pub fn subscribe(&self) -> EventSubscriber {
+        self.client.subscribe()
+    }
+
+    /// Read from the connection until the next event
+    ///
+    /// # Returns
+    /// None once the connection has closed and every event was taken
+    ///
+    /// # Errors
+    /// Returns an error if the connection fails
+    pub async fn next_event(&mut self) -> BotResult<Option<ClientEvent>> {
+        if let Some(event) = self.events.try_recv() {
+            return Ok(Some(event));
+        }
+        if self.finished {
+            return Ok(None);
+        }
+        let run = self.client.run_message_loop();
+        tokio::pin!(run);
+        tokio::select! {
+            biased;
+            event = self.events.recv() => Ok(event),
+            result = &mut run => {
+                self.finished = true;
+                result?;
+                Ok(self.events.try_recv())
+            }
+        }
+    }
+
+    /// Stream of every event, ending when the connection closes or after
+    /// the first error
+    pub fn events(&mut self) -> impl Stream<Item = BotResult<ClientEvent>> + '_ {
+        futures_util::stream::unfold(Some(self), |bot| async move {
+            let bot = bot?;
+            match bot.next_event().await {
+                Ok(Some(event)) => Some((Ok(event), Some(bot))),
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    /// Stream of verified incoming chat messages
+    pub fn messages(&mut self) -> impl Stream<Item = BotResult<ChatMessage>> + '_ {
+        use futures_util::StreamExt;
+
+        self.events().filter_map(|event| async move {
+            match event {
+                Ok(ClientEvent::MessageReceived(message)) => Some(Ok(message)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            }
+        })
+    }
+
+    /// Close the connection
+    pub async fn close(mut self) -> BotResult<()> {
+        self.client.close_gracefully().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use profile_shared::canonical::{self, CanonicalVersion};
+    use profile_shared::{derive_public_key, generate_private_key, sign_message, Message};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message as Frame;
+
+    /// Accept one client, answer its auth, forward `send` to it and report
+    /// the frames it sends after authenticating
+    async fn fake_server(
+        send: Vec<Message>,
+    ) -> (ServerProfile, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (received_tx, received_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await.unwrap().unwrap();
+            ws.send(Frame::text(
+                r#"{"type":"auth_success","users":["bb"],"nicknames":{"bb":"bob"}}"#,
+            ))
+            .await
+            .unwrap();
+            for message in send {
+                let json = serde_json::to_string(&message).unwrap();
+                ws.send(Frame::text(json)).await.unwrap();
+            }
+            while let Some(Ok(frame)) = ws.next().await {
+                if let Frame::Text(text) = frame {
+                    let _ = received_tx.send(text.to_string());
+                }
+            }
+        });
+        (ServerProfile::new("test", url).unwrap(), received_rx)
+    }
+
+    fn signed_text(text: &str) -> Message {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap());
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            &private_key,
+            &canonical::message(CanonicalVersion::CURRENT, text, &timestamp),
+        )
+        .unwrap();
+        Message::new_text(
+            uuid::Uuid::new_v4(),
+            text.to_string(),
+            public_key,
+            hex::encode(signature),
+            timestamp,
+            CanonicalVersion::CURRENT,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_new_needs_a_key() {
+        assert!(ProfileClient::new(create_shared_key_state()).await.is_err());
+
+        let bot = ProfileClient::guest().await.unwrap();
+        assert_eq!(bot.public_key().len(), 64);
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_send_and_receive() {
+        let (server, mut received) = fake_server(vec![signed_text("hello bot")]).await;
+        let mut bot = ProfileClient::guest().await.unwrap().with_server(server);
+        bot.connect().await.unwrap();
+
+        let lobby = bot.authenticate().await.unwrap();
+        assert_eq!(lobby.total, 1);
+        assert_eq!(lobby.users[0].display_name(), "bob");
+
+        let message = Box::pin(bot.messages()).next().await.unwrap().unwrap();
+        assert_eq!(message.message, "hello bot");
+        assert!(message.is_verified);
+
+        let message_id = bot.send("bb", "hi").await.unwrap();
+        let sent: serde_json::Value =
+            serde_json::from_str(&received.recv().await.unwrap()).unwrap();
+        assert_eq!(sent["messageId"], message_id);
+        assert_eq!(sent["message"], "hi");
+        bot.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_cover_traffic_warns_and_reaches_server() {
+        use crate::connection::cover::CoverPolicy;
+        use std::time::Duration;
+
+        let (server, mut received) = fake_server(vec![]).await;
+        let mut bot = ProfileClient::guest().await.unwrap().with_server(server);
+        bot.client_mut().set_cover_traffic(Some(CoverPolicy {
+            min_interval: Duration::from_millis(10),
+            max_interval: Duration::from_millis(20),
+            ..CoverPolicy::default()
+        }));
+        bot.connect().await.unwrap();
+        bot.authenticate().await.unwrap();
+
+        let mut warned = false;
+        let frame = loop {
+            tokio::select! {
+                frame = received.recv() => break frame.unwrap(),
+                event = bot.next_event() => {
+                    if let Ok(Some(ClientEvent::Notification(text))) = event {
+                        warned |= text.contains("battery");
+                    }
+                }
+            }
+        };
+        assert!(warned);
+        let frame: serde_json::Value = serde_json::from_str(&frame).unwrap();
+        assert_eq!(frame["type"], "cover");
+        assert!(bot.client_mut().cover_stats().unwrap().frames >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_next_event_without_connection_fails() {
+        let mut bot = ProfileClient::guest().await.unwrap();
+        assert!(bot.next_event().await.is_err());
+        assert!(matches!(bot.next_event().await, Ok(None)));
+    }
+}
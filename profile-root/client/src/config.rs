@@ -0,0 +1,525 @@
+//! Client settings
+//!
+//! Settings are saved as JSON at `PROFILE_CONFIG_FILE`, or
+//! `~/.profile/config.json` when that is unset. They hold any number of
+//! named server profiles, each a server URL and optionally a proxy to reach
+//! it through, and which of them is active.
+//!
+//! `wipeEscrowAfter` optionally erases the key escrow, or an encrypted key
+//! file being imported, after that many consecutive wrong passphrases (see
+//! `state::unlock_attempts`).
+//!
+//! `messagePadding` lists the sizes in bytes that end-to-end encrypted
+//! payloads are padded up to (see `profile_shared::crypto::padding`); an
+//! empty list turns padding off. The shared default buckets apply when it
+//! is unset or invalid.
+//!
+//! `coverTraffic` turns on cover traffic (see `connection::cover`), which
+//! costs data and battery. `{}` uses the shared defaults; it may set
+//! `minIntervalSecs`, `maxIntervalSecs` and `maxBytesPerMinute`. Invalid
+//! values are reported and the defaults used instead.
+//!
+//! `forwardSecrecy` seals direct messages in double-ratchet sessions with
+//! their recipients (see `state::ratchet_sessions`), padded to
+//! `messagePadding`. Peers must support sealed messages to read them.
+//!
+//! At connect time the server is picked, in order, from:
+//! 1. `PROFILE_SERVER_URL`, for scripts and tests
+//! 2. The profile named by `PROFILE_SERVER_PROFILE`
+//! 3. The active profile
+//! 4. [`DEFAULT_SERVER_URL`]
+
+use crate::connection::cover::CoverPolicy;
+use crate::connection::proxy::ProxyConfig;
+use crate::state::unlock_attempts::LockoutPolicy;
+use profile_shared::crypto::padding::PaddingPolicy;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Environment variable overriding the settings file location
+pub const CONFIG_ENV_VAR: &str = "PROFILE_CONFIG_FILE";
+
+/// Environment variable with a server URL that overrides every profile
+pub const SERVER_URL_ENV_VAR: &str = "PROFILE_SERVER_URL";
+
+/// Environment variable naming the profile to connect with
+pub const SERVER_PROFILE_ENV_VAR: &str = "PROFILE_SERVER_PROFILE";
+
+/// Server used when no profile is active
+pub const DEFAULT_SERVER_URL: &str = "ws://127.0.0.1:8080";
+
+/// Error types for client settings
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// Reading or writing the settings file failed
+    Io(String),
+    /// The settings file is not valid JSON
+    Parse(String),
+    /// A server URL is not a `ws://` or `wss://` URL
+    InvalidUrl(String),
+    /// A profile's proxy URL is invalid
+    InvalidProxy(String),
+    /// A profile name is empty
+    InvalidName,
+    /// No profile has this name
+    UnknownProfile(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(msg) => write!(f, "Failed to access settings file: {}", msg),
+            ConfigError::Parse(msg) => write!(f, "Failed to parse settings file: {}", msg),
+            ConfigError::InvalidUrl(url) => write!(f, "Invalid server URL: {}", url),
+            ConfigError::InvalidProxy(msg) => write!(f, "Invalid proxy: {}", msg),
+            ConfigError::InvalidName => write!(f, "Profile name cannot be empty"),
+            ConfigError::UnknownProfile(name) => write!(f, "No server profile named {}", name),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// A named server to connect to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerProfile {
+    pub name: String,
+    /// WebSocket URL of the server
+    pub url: String,
+    /// Proxy URL to reach the server through (see [`crate::connection::proxy`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+impl ServerProfile {
+    /// Create a profile connecting directly to `url`
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Result<Self, ConfigError> {
+        let name = name.into().trim().to_string();
+        if name.is_empty() {
+            return Err(ConfigError::InvalidName);
+        }
+        let url = url.into().trim().to_string();
+        validate_server_url(&url)?;
+        Ok(Self {
+            name,
+            url,
+            proxy: None,
+        })
+    }
+
+    /// Reach the server through the proxy at `proxy_url`
+    pub fn with_proxy(mut self, proxy_url: impl Into<String>) -> Result<Self, ConfigError> {
+        let proxy_url = proxy_url.into();
+        ProxyConfig::parse(&proxy_url).map_err(|e| ConfigError::InvalidProxy(e.to_string()))?;
+        self.proxy = Some(proxy_url);
+        Ok(self)
+    }
+
+    /// The profile's proxy, if it has one
+    pub fn proxy_config(&self) -> Result<Option<ProxyConfig>, ConfigError> {
+        self.proxy
+            .as_deref()
+            .map(ProxyConfig::parse)
+            .transpose()
+            .map_err(|e| ConfigError::InvalidProxy(e.to_string()))
+    }
+}
+
+/// Check that `url` is a WebSocket URL with a host
+fn validate_server_url(url: &str) -> Result<(), ConfigError> {
+    let host = url
+        .strip_prefix("ws://")
+        .or_else(|| url.strip_prefix("wss://"))
+        .filter(|rest| !rest.is_empty() && !rest.starts_with('/'));
+    match host {
+        Some(_) if !url.contains(char::is_whitespace) => Ok(()),
+        _ => Err(ConfigError::InvalidUrl(url.to_string())),
+    }
+}
+
+/// Persisted client settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientConfig {
+    /// Name of the profile to connect with, if any
+    #[serde(
+        rename = "activeProfile",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub active_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: Vec<ServerProfile>,
+    /// Erase the key escrow or key file after this many consecutive wrong
+    /// passphrases
+    #[serde(
+        rename = "wipeEscrowAfter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub wipe_escrow_after: Option<u32>,
+    /// Sizes encrypted payloads are padded up to, None for the defaults
+    #[serde(
+        rename = "messagePadding",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub message_padding: Option<Vec<usize>>,
+    /// Cover traffic, off unless set
+    #[serde(
+        rename = "coverTraffic",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cover_traffic: Option<CoverTrafficSettings>,
+    /// Seal direct messages in ratchet sessions, off unless set
+    #[serde(
+        rename = "forwardSecrecy",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub forward_secrecy: bool,
+}
+
+/// Cover traffic settings; unset fields take the shared defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverTrafficSettings {
+    /// Shortest wait between cover frames, in seconds
+    #[serde(
+        rename = "minIntervalSecs",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub min_interval_secs: Option<u64>,
+    /// Longest wait between cover frames, in seconds
+    #[serde(
+        rename = "maxIntervalSecs",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_interval_secs: Option<u64>,
+    /// Most bytes of cover frames per minute
+    #[serde(
+        rename = "maxBytesPerMinute",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_bytes_per_minute: Option<usize>,
+}
+
+/// Default location of the settings file
+///
+/// # Returns
+/// `PROFILE_CONFIG_FILE` if set, otherwise `~/.profile/config.json`, or None
+/// if neither can be determined
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("config.json"))
+}
+
+impl ClientConfig {
+    /// Load the settings saved at `path`
+    ///
+    /// A missing file gives the default settings.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(ConfigError::Io(e.to_string())),
+        }
+    }
+
+    /// Save the settings to `path`
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| ConfigError::Io(e.to_string()))?;
+        }
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| ConfigError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| ConfigError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| ConfigError::Io(e.to_string()))
+    }
+
+    /// How wrong key escrow and key file passphrases are throttled
+    pub fn lockout_policy(&self) -> LockoutPolicy {
+        LockoutPolicy {
+            wipe_after: self.wipe_escrow_after,
+            ..LockoutPolicy::default()
+        }
+    }
+
+    /// How encrypted payloads are padded
+    pub fn padding_policy(&self) -> PaddingPolicy {
+        match &self.message_padding {
+            None => PaddingPolicy::default(),
+            Some(buckets) => PaddingPolicy::new(buckets).unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Invalid messagePadding, using the default buckets");
+                PaddingPolicy::default()
+            }),
+        }
+    }
+
+    /// Cover traffic to send, None when it is off
+    pub fn cover_policy(&self) -> Option<CoverPolicy> {
+        let settings = self.cover_traffic.as_ref()?;
+        let defaults = CoverPolicy {
+            padding: self.padding_policy(),
+            ..CoverPolicy::default()
+        };
+        let policy = CoverPolicy {
+            min_interval: settings
+                .min_interval_secs
+                .map_or(defaults.min_interval, Duration::from_secs),
+            max_interval: settings
+                .max_interval_secs
+                .map_or(defaults.max_interval, Duration::from_secs),
+            max_bytes_per_minute: settings
+                .max_bytes_per_minute
+                .unwrap_or(defaults.max_bytes_per_minute),
+            padding: defaults.padding.clone(),
+        };
+        match policy.validate() {
+            Ok(()) => Some(policy),
+            Err(e) => {
+                tracing::warn!(error = %e, "Invalid coverTraffic, using the defaults");
+                Some(defaults)
+            }
+        }
+    }
+
+    /// Padding for sealed direct messages, None when forward secrecy is off
+    pub fn forward_secrecy_policy(&self) -> Option<PaddingPolicy> {
+        self.forward_secrecy.then(|| self.padding_policy())
+    }
+
+    /// The profile named `name`
+    pub fn profile(&self, name: &str) -> Option<&ServerProfile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Add `profile`, replacing any profile with the same name
+    pub fn save_profile(&mut self, profile: ServerProfile) {
+        match self.profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    /// Remove the profile named `name`, deactivating it if it was active
+    ///
+    /// # Returns
+    /// true if there was such a profile
+    pub fn remove_profile(&mut self, name: &str) -> bool {
+        let before = self.profiles.len();
+        self.profiles.retain(|profile| profile.name != name);
+        if self.active_profile.as_deref() == Some(name) {
+            self.active_profile = None;
+        }
+        self.profiles.len() != before
+    }
+
+    /// Make the profile named `name` the active one (None for the default
+    /// server)
+    pub fn select(&mut self, name: Option<&str>) -> Result<(), ConfigError> {
+        if let Some(name) = name {
+            if self.profile(name).is_none() {
+                return Err(ConfigError::UnknownProfile(name.to_string()));
+            }
+        }
+        self.active_profile = name.map(str::to_string);
+        Ok(())
+    }
+
+    /// The active profile, if one is selected and still exists
+    pub fn active(&self) -> Option<&ServerProfile> {
+        self.active_profile
+            .as_deref()
+            .and_then(|name| self.profile(name))
+    }
+
+    /// The profile to connect with: `requested` if given, otherwise the
+    /// active one
+    ///
+    /// # Returns
+    /// Ok(None) to connect to the default server
+    pub fn connect_profile(
+        &self,
+        requested: Option<&str>,
+    ) -> Result<Option<&ServerProfile>, ConfigError> {
+        match requested {
+            Some(name) => self
+                .profile(name)
+                .map(Some)
+                .ok_or_else(|| ConfigError::UnknownProfile(name.to_string())),
+            None => Ok(self.active()),
+        }
+    }
+}
+
+/// The saved settings
+///
+/// Settings that can't be read are reported and the defaults used.
+pub fn load_settings() -> ClientConfig {
+    match default_config_path().map(|path| ClientConfig::load(&path)) {
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            tracing::warn!(error = %e, "Failed to load settings, using the defaults");
+            ClientConfig::default()
+        }
+        None => ClientConfig::default(),
+    }
+}
+
+/// Server URL and proxy to connect with, as configured
+///
+/// Follows the order in the module docs. Settings that can't be read are
+/// reported and the default server is used.
+pub fn resolve_connect_target() -> (String, Option<ProxyConfig>) {
+    let proxy_from_profile = |profile: &ServerProfile| {
+        profile.proxy_config().unwrap_or_else(|e| {
+            tracing::warn!(profile = %profile.name, error = %e, "Ignoring profile proxy");
+            None
+        })
+    };
+    if let Some(url) = std::env::var(SERVER_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+    {
+        return (url, None);
+    }
+    let requested = std::env::var(SERVER_PROFILE_ENV_VAR)
+        .ok()
+        .filter(|name| !name.is_empty());
+    let config = load_settings();
+    match config.connect_profile(requested.as_deref()) {
+        Ok(Some(profile)) => (profile.url.clone(), proxy_from_profile(profile)),
+        Ok(None) => (DEFAULT_SERVER_URL.to_string(), None),
+        Err(e) => {
+            tracing::warn!(error = %e, "Using the default server");
+            (DEFAULT_SERVER_URL.to_string(), None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_round_trip_and_switch() {
+        let path =
+            std::env::temp_dir().join(format!("profile-client-config-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut config = ClientConfig::load(&path).unwrap();
+        assert_eq!(config, ClientConfig::default());
+        assert_eq!(config.connect_profile(None).unwrap(), None);
+
+        config.save_profile(ServerProfile::new("home", "ws://10.0.0.2:8080").unwrap());
+        config.save_profile(
+            ServerProfile::new("work", "ws://chat.example:8080")
+                .unwrap()
+                .with_proxy("http://proxy.example:3128")
+                .unwrap(),
+        );
+        config.select(Some("work")).unwrap();
+        config.save(&path).unwrap();
+
+        let mut loaded = ClientConfig::load(&path).unwrap();
+        assert_eq!(loaded, config);
+        let work = loaded.connect_profile(None).unwrap().unwrap();
+        assert_eq!(work.url, "ws://chat.example:8080");
+        assert_eq!(work.proxy_config().unwrap().unwrap().port, 3128);
+        let home = loaded.connect_profile(Some("home")).unwrap().unwrap();
+        assert_eq!(home.proxy_config().unwrap(), None);
+        assert!(loaded.connect_profile(Some("cafe")).is_err());
+
+        // Saving under an existing name replaces that profile
+        loaded.save_profile(ServerProfile::new("home", "ws://10.0.0.3:8080").unwrap());
+        assert_eq!(loaded.profiles.len(), 2);
+        assert_eq!(loaded.profile("home").unwrap().url, "ws://10.0.0.3:8080");
+
+        assert!(loaded.remove_profile("work"));
+        assert_eq!(loaded.active(), None);
+        assert!(!loaded.remove_profile("work"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_padding_setting() {
+        assert_eq!(
+            ClientConfig::default().padding_policy(),
+            PaddingPolicy::default()
+        );
+
+        let config: ClientConfig = serde_json::from_str(r#"{"messagePadding":[128,512]}"#).unwrap();
+        assert_eq!(config.padding_policy().buckets(), &[128, 512]);
+
+        let config: ClientConfig = serde_json::from_str(r#"{"messagePadding":[]}"#).unwrap();
+        assert!(!config.padding_policy().is_enabled());
+
+        let config: ClientConfig = serde_json::from_str(r#"{"messagePadding":[0]}"#).unwrap();
+        assert_eq!(config.padding_policy(), PaddingPolicy::default());
+    }
+
+    #[test]
+    fn test_cover_traffic_setting() {
+        assert_eq!(ClientConfig::default().cover_policy(), None);
+
+        let config: ClientConfig = serde_json::from_str(r#"{"coverTraffic":{}}"#).unwrap();
+        assert_eq!(config.cover_policy(), Some(CoverPolicy::default()));
+
+        let config: ClientConfig = serde_json::from_str(
+            r#"{"coverTraffic":{"maxIntervalSecs":60,"maxBytesPerMinute":4096},"messagePadding":[]}"#,
+        )
+        .unwrap();
+        let policy = config.cover_policy().unwrap();
+        assert_eq!(policy.max_interval, Duration::from_secs(60));
+        assert_eq!(policy.max_bytes_per_minute, 4096);
+        assert!(!policy.padding.is_enabled());
+
+        let config: ClientConfig =
+            serde_json::from_str(r#"{"coverTraffic":{"minIntervalSecs":0}}"#).unwrap();
+        assert_eq!(config.cover_policy(), Some(CoverPolicy::default()));
+    }
+
+    #[test]
+    fn test_forward_secrecy_setting() {
+        assert_eq!(ClientConfig::default().forward_secrecy_policy(), None);
+        assert!(!serde_json::to_string(&ClientConfig::default())
+            .unwrap()
+            .contains("forwardSecrecy"));
+
+        let config: ClientConfig =
+            serde_json::from_str(r#"{"forwardSecrecy":true,"messagePadding":[128]}"#).unwrap();
+        assert_eq!(
+            config.forward_secrecy_policy(),
+            Some(PaddingPolicy::new(&[128]).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_invalid_profiles_rejected() {
+        assert_eq!(
+            ServerProfile::new(" ", "ws://host"),
+            Err(ConfigError::InvalidName)
+        );
+        assert!(ServerProfile::new("a", "http://host").is_err());
+        assert!(ServerProfile::new("a", "ws://").is_err());
+        assert!(ServerProfile::new("a", "wss://host:443/chat").is_ok());
+        assert!(matches!(
+            ServerProfile::new("a", "ws://host")
+                .unwrap()
+                .with_proxy("ftp://proxy"),
+            Err(ConfigError::InvalidProxy(_))
+        ));
+        assert_eq!(
+            ClientConfig::default().select(Some("missing")),
+            Err(ConfigError::UnknownProfile("missing".to_string()))
+        );
+    }
+}
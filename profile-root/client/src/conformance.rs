@@ -0,0 +1,417 @@
+//! Protocol conformance dry run against a server
+//!
+//! [`validate`] checks that a server speaks the protocol this client
+//! expects, which is worth doing before pointing the client at a
+//! third-party server implementation. It signs in two throwaway guest
+//! identities: an echo peer that sends every direct message it receives
+//! back to its sender, and a prober that holds a scripted conversation
+//! with it. Each step becomes a [`Check`] in the [`ConformanceReport`]:
+//!
+//! - `connect`: the WebSocket handshake
+//! - `authenticate`: the prober signs in offering MessagePack and deflate;
+//!   the encoding, compression and resume token the server picked are
+//!   reported
+//! - `peer_visible`: the echo peer signs in and the prober is told it joined
+//! - `echo:<name>`: each scripted message comes back from the peer with the
+//!   same text and a valid signature
+//! - `recipient_offline`: a message to a key nobody holds is refused as
+//!   offline
+//! - `close`: both connections close cleanly
+//!
+//! Steps that need an earlier one that failed are skipped. Each step has
+//! its own time limit, so a server that never answers fails the step
+//! instead of hanging the run. Nothing is kept: both identities are guests.
+
+use crate::bot::{BotResult, ProfileClient};
+use crate::config::ServerProfile;
+use crate::events::ClientEvent;
+use profile_shared::config::message::MAX_MESSAGE_LENGTH;
+use profile_shared::protocol::{CompressionConfig, Encoding};
+use profile_shared::{derive_public_key, generate_private_key};
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Time each step may take unless the caller picks another
+pub const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Messages the prober sends the echo peer, by check name
+fn script() -> Vec<(&'static str, String)> {
+    vec![
+        ("plain", "hello from the conformance check".to_string()),
+        ("unicode", "héllo wörld, 你好 👋".to_string()),
+        // Signed over the same bytes as "a:b" at "c" in canonical version 1
+        ("colons", "a:b:c".to_string()),
+        ("longest", "x".repeat(MAX_MESSAGE_LENGTH)),
+    ]
+}
+
+/// Outcome of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// Not run because a step it needs failed
+    Skipped,
+}
+
+impl CheckStatus {
+    fn label(self) -> &'static str {
+        match self {
+            CheckStatus::Passed => "PASS",
+            CheckStatus::Failed => "FAIL",
+            CheckStatus::Skipped => "SKIP",
+        }
+    }
+}
+
+/// One step of the dry run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    /// What the server did, or why the check failed or was skipped
+    pub detail: String,
+    #[serde(rename = "elapsedMs")]
+    pub elapsed_ms: u64,
+}
+
+/// Every check run against one server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConformanceReport {
+    pub server: String,
+    pub checks: Vec<Check>,
+}
+
+impl ConformanceReport {
+    /// Number of checks with `status`
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+
+    /// Whether every check ran and passed
+    pub fn is_conformant(&self) -> bool {
+        !self.checks.is_empty() && self.count(CheckStatus::Passed) == self.checks.len()
+    }
+
+    /// The check called `name`, if it was recorded
+    pub fn check(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|c| c.name == name)
+    }
+
+    fn record(&mut self, name: &str, started: Instant, result: StepResult) -> bool {
+        let (status, detail) = match result {
+            Ok(Ok(detail)) => (CheckStatus::Passed, detail),
+            Ok(Err(e)) => (CheckStatus::Failed, e.to_string()),
+            Err(_) => (
+                CheckStatus::Failed,
+                "No answer within the step time limit".to_string(),
+            ),
+        };
+        self.checks.push(Check {
+            name: name.to_string(),
+            status,
+            detail,
+            elapsed_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+        });
+        status == CheckStatus::Passed
+    }
+
+    fn skip(&mut self, name: &str, needs: &str) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            status: CheckStatus::Skipped,
+            detail: format!("Needs {}", needs),
+            elapsed_ms: 0,
+        });
+    }
+}
+
+impl Display for ConformanceReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Conformance report for {}", self.server)?;
+        for check in &self.checks {
+            writeln!(
+                f,
+                "  {} {} ({} ms): {}",
+                check.status.label(),
+                check.name,
+                check.elapsed_ms,
+                check.detail
+            )?;
+        }
+        write!(
+            f,
+            "{} passed, {} failed, {} skipped",
+            self.count(CheckStatus::Passed),
+            self.count(CheckStatus::Failed),
+            self.count(CheckStatus::Skipped)
+        )
+    }
+}
+
+type StepResult = Result<BotResult<String>, tokio::time::error::Elapsed>;
+
+/// Run `step` with the step time limit
+async fn timed(limit: Duration, step: impl Future<Output = BotResult<String>>) -> StepResult {
+    tokio::time::timeout(limit, step).await
+}
+
+/// Read both connections until the prober sees an event `wanted` accepts,
+/// sending every message the peer receives back to its sender
+///
+/// # Errors
+/// Returns an error if either connection closes or fails, or the prober
+/// is sent an error
+async fn converse(
+    prober: &mut ProfileClient,
+    peer: &mut ProfileClient,
+    mut wanted: impl FnMut(ClientEvent) -> Option<BotResult<String>>,
+) -> BotResult<String> {
+    loop {
+        let echo = tokio::select! {
+            event = prober.next_event() => match event? {
+                Some(ClientEvent::Error(error)) => return Err(error.into()),
+                Some(event) => match wanted(event) {
+                    Some(result) => return result,
+                    None => None,
+                },
+                None => return Err("The server closed the prober's connection".into()),
+            },
+            event = peer.next_event() => match event? {
+                Some(ClientEvent::MessageReceived(message)) => Some(message),
+                Some(_) => None,
+                None => return Err("The server closed the echo peer's connection".into()),
+            },
+        };
+        if let Some(message) = echo {
+            peer.send(&message.sender_public_key, &message.message)
+                .await?;
+        }
+    }
+}
+
+/// Hold the scripted conversation against the server at `url`
+///
+/// # Arguments
+/// * `url` - WebSocket URL of the server (`ws://` or `wss://`)
+/// * `step_timeout` - Time each step may take
+///
+/// # Errors
+/// Returns an error only if the run can't start (an invalid URL, or no
+/// guest identities); everything the server does wrong is in the report
+pub async fn validate(url: &str, step_timeout: Duration) -> BotResult<ConformanceReport> {
+    let server = ServerProfile::new("conformance", url)?;
+    let mut prober = ProfileClient::guest().await?.with_server(server.clone());
+    let mut peer = ProfileClient::guest().await?.with_server(server);
+    // Offer everything the client supports, to see what the server picks
+    prober
+        .client_mut()
+        .set_preferred_encoding(Encoding::MessagePack);
+    prober
+        .client_mut()
+        .set_compression(CompressionConfig::default());
+    let peer_key = peer.public_key().to_string();
+    let mut report = ConformanceReport {
+        server: url.to_string(),
+        checks: Vec::new(),
+    };
+
+    let started = Instant::now();
+    let connected = timed(step_timeout, async {
+        prober.connect().await?;
+        Ok("WebSocket handshake completed".to_string())
+    })
+    .await;
+    // Every check after connect, to skip if the run can't get that far
+    let remaining: Vec<String> = ["authenticate", "peer_visible"]
+        .into_iter()
+        .map(String::from)
+        .chain(
+            script()
+                .into_iter()
+                .map(|(name, _)| format!("echo:{}", name)),
+        )
+        .chain(["recipient_offline", "close"].into_iter().map(String::from))
+        .collect();
+    if !report.record("connect", started, connected) {
+        for name in &remaining {
+            report.skip(name, "connect");
+        }
+        return Ok(report);
+    }
+
+    let started = Instant::now();
+    let authenticated = timed(step_timeout, async {
+        let lobby = prober.authenticate().await?;
+        let client = prober.client_mut();
+        Ok(format!(
+            "{} users online; encoding {}, compression {}, {}",
+            lobby.total,
+            client.encoding().name(),
+            client.compression().name().unwrap_or("none"),
+            if client.can_resume() {
+                "resume token issued"
+            } else {
+                "no resume token"
+            }
+        ))
+    })
+    .await;
+    if !report.record("authenticate", started, authenticated) {
+        for name in &remaining[1..] {
+            report.skip(name, "authenticate");
+        }
+        return Ok(report);
+    }
+
+    let started = Instant::now();
+    let visible = timed(step_timeout, async {
+        peer.connect().await?;
+        peer.authenticate().await?;
+        converse(&mut prober, &mut peer, |event| match event {
+            ClientEvent::UserJoined(user) if user.public_key == peer_key => {
+                Some(Ok("The prober was told the echo peer joined".to_string()))
+            }
+            _ => None,
+        })
+        .await
+    })
+    .await;
+    let peer_ready = report.record("peer_visible", started, visible);
+
+    for (name, text) in script() {
+        let name = format!("echo:{}", name);
+        if !peer_ready {
+            report.skip(&name, "peer_visible");
+            continue;
+        }
+        let started = Instant::now();
+        let echoed = timed(step_timeout, async {
+            prober.send(&peer_key, &text).await?;
+            converse(&mut prober, &mut peer, |event| match event {
+                ClientEvent::MessageReceived(message)
+                    if message.sender_public_key == peer_key && message.message == text =>
+                {
+                    Some(if message.is_verified {
+                        Ok(format!(
+                            "{} characters came back verified",
+                            text.chars().count()
+                        ))
+                    } else {
+                        Err("The echo came back with an invalid signature".into())
+                    })
+                }
+                ClientEvent::InvalidSignature(notice) => Some(Err(notice.into())),
+                _ => None,
+            })
+            .await
+        })
+        .await;
+        report.record(&name, started, echoed);
+    }
+
+    let started = Instant::now();
+    let nobody_key = hex::encode(derive_public_key(&generate_private_key()?)?);
+    let offline = timed(step_timeout, async {
+        prober.send(&nobody_key, "anyone there?").await?;
+        converse(&mut prober, &mut peer, |event| match event {
+            ClientEvent::RecipientOffline(key) if key == nobody_key => {
+                Some(Ok("Refused as offline".to_string()))
+            }
+            _ => None,
+        })
+        .await
+    })
+    .await;
+    report.record("recipient_offline", started, offline);
+
+    let started = Instant::now();
+    let closed = timed(step_timeout, async {
+        prober.close().await?;
+        if peer_ready {
+            peer.close().await?;
+        }
+        Ok("Both connections closed".to_string())
+    })
+    .await;
+    report.record("close", started, closed);
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message as Frame;
+
+    /// A server that completes the handshake and refuses every sign-in
+    async fn refusing_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            ws.next().await.unwrap().unwrap();
+            ws.send(Frame::text(
+                r#"{"type":"error","reason":"auth_failed","details":"no"}"#,
+            ))
+            .await
+            .unwrap();
+            while ws.next().await.is_some() {}
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_refused_sign_in_fails_and_skips_the_rest() {
+        let url = refusing_server().await;
+        let report = validate(&url, Duration::from_secs(2)).await.unwrap();
+
+        assert_eq!(report.check("connect").unwrap().status, CheckStatus::Passed);
+        assert_eq!(
+            report.check("authenticate").unwrap().status,
+            CheckStatus::Failed
+        );
+        assert_eq!(report.count(CheckStatus::Skipped), 7);
+        assert!(!report.is_conformant());
+
+        let text = report.to_string();
+        assert!(text.contains("FAIL authenticate"));
+        assert!(text.ends_with("1 passed, 1 failed, 7 skipped"));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_fails_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let report = validate(&url, Duration::from_secs(2)).await.unwrap();
+        assert_eq!(report.checks[0].status, CheckStatus::Failed);
+        assert_eq!(report.count(CheckStatus::Skipped), 8);
+        assert!(validate("not a url", DEFAULT_STEP_TIMEOUT).await.is_err());
+    }
+
+    #[test]
+    fn test_report_serialized_for_the_cli() {
+        let check = Check {
+            name: "echo:plain".to_string(),
+            status: CheckStatus::Skipped,
+            detail: "Needs peer_visible".to_string(),
+            elapsed_ms: 0,
+        };
+        assert_eq!(
+            serde_json::to_value(&check).unwrap(),
+            serde_json::json!({
+                "name": "echo:plain",
+                "status": "skipped",
+                "detail": "Needs peer_visible",
+                "elapsedMs": 0,
+            })
+        );
+    }
+}
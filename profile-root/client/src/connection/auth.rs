@@ -1,66 +1,17 @@
-use hex;
-use profile_shared::{sign_message, PrivateKey};
-use serde::{Deserialize, Serialize};
-
-/// Client authentication message structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ClientAuthMessage {
-    pub r#type: String,
-    #[serde(rename = "publicKey")]
-    pub public_key: String,
-    pub signature: String,
-}
-
-impl ClientAuthMessage {
-    /// Create a new client authentication message
-    pub fn new(
-        public_key: profile_shared::PublicKey,
-        private_key: PrivateKey,
-    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        // Generate signature for "auth" message
-        let signature = sign_message(&private_key, b"auth")?;
-
-        // Encode to hex
-        let public_key_hex = hex::encode(public_key.as_slice());
-        let signature_hex = hex::encode(signature);
-
-        Ok(Self {
-            r#type: "auth".to_string(),
-            public_key: public_key_hex,
-            signature: signature_hex,
-        })
-    }
+//! Client authentication message
+//!
+//! The wire type lives in [`profile_shared::protocol::auth`] so the client
+//! and server can't disagree on its fields; this module re-exports it for
+//! the connection code.
 
-    /// Create a new client authentication message with a reference to the private key
-    pub fn new_with_ref(
-        public_key: profile_shared::PublicKey,
-        private_key: &PrivateKey,
-    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        // Generate signature for "auth" message
-        let signature = sign_message(private_key, b"auth")?;
-
-        // Encode to hex
-        let public_key_hex = hex::encode(public_key.as_slice());
-        let signature_hex = hex::encode(signature);
-
-        Ok(Self {
-            r#type: "auth".to_string(),
-            public_key: public_key_hex,
-            signature: signature_hex,
-        })
-    }
-
-    /// Serialize to JSON string
-    pub fn to_json(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-        Ok(serde_json::to_string(self)?)
-    }
-}
+pub use profile_shared::protocol::AuthMessage;
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use profile_shared::derive_public_key;
     use profile_shared::generate_private_key;
+    use profile_shared::protocol::Encoding;
 
     #[tokio::test]
     async fn test_client_auth_message_creation() {
@@ -74,7 +25,7 @@ mod tests {
         let public_key_hex = hex::encode(&public_key);
 
         // This should work now that we have the implementation
-        let result = ClientAuthMessage::new(public_key, private_key);
+        let result = AuthMessage::signed(&public_key, &private_key);
 
         // In GREEN phase, this should succeed
         assert!(
@@ -117,13 +68,13 @@ mod tests {
         let public_key_hex = hex::encode(&public_key);
 
         // 2. Create auth message
-        let auth_msg = ClientAuthMessage::new(public_key, private_key)?;
+        let auth_msg = AuthMessage::signed(&public_key, &private_key)?;
 
         // 3. Serialize to JSON
-        let json = auth_msg.to_json()?;
+        let json = serde_json::to_string(&auth_msg)?;
 
         // Verify JSON structure
-        let parsed: ClientAuthMessage = serde_json::from_str(&json)?;
+        let parsed: AuthMessage = serde_json::from_str(&json)?;
         assert_eq!(parsed.r#type, "auth");
         assert_eq!(parsed.public_key, public_key_hex);
         assert!(!parsed.signature.is_empty());
@@ -132,6 +83,19 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_preferred_encoding_offered_with_json_fallback() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let auth_msg = AuthMessage::signed(&public_key, &private_key).unwrap();
+
+        let json = serde_json::to_string(&auth_msg).unwrap();
+        assert!(!json.contains("encodings"));
+
+        let offered = auth_msg.with_preferred_encoding(Encoding::MessagePack);
+        assert_eq!(offered.encodings, vec!["msgpack", "json"]);
+    }
+
     #[tokio::test]
     async fn test_signature_determinism() {
         // Test that the same inputs produce the same signature (deterministic)
@@ -140,7 +104,7 @@ mod tests {
         let public_key = derive_public_key(&private_key).unwrap();
 
         // We can't clone PrivateKey anymore, so we'll test with two different keys
-        let msg1 = ClientAuthMessage::new(public_key.clone(), private_key).unwrap();
+        let msg1 = AuthMessage::signed(&public_key, &private_key).unwrap();
 
         // Test signature format and properties (can't compare with second message)
         assert!(!msg1.signature.is_empty());
@@ -161,8 +125,8 @@ mod tests {
         let public_key2 = derive_public_key(&private_key2).unwrap();
 
         // Create auth messages with different keys
-        let msg1 = ClientAuthMessage::new(public_key1, private_key1).unwrap();
-        let msg2 = ClientAuthMessage::new(public_key2, private_key2).unwrap();
+        let msg1 = AuthMessage::signed(&public_key1, &private_key1).unwrap();
+        let msg2 = AuthMessage::signed(&public_key2, &private_key2).unwrap();
 
         // Should have different public keys and signatures
         assert_ne!(msg1.public_key, msg2.public_key);
@@ -178,7 +142,7 @@ mod tests {
         let private_key = generate_private_key().unwrap();
         let public_key = derive_public_key(&private_key).unwrap();
 
-        let auth_msg = ClientAuthMessage::new(public_key, private_key).unwrap();
+        let auth_msg = AuthMessage::signed(&public_key, &private_key).unwrap();
 
         // Verify hex encoding format (64 chars for 32-byte keys/signatures)
         assert_eq!(auth_msg.public_key.len(), 64); // 32 bytes = 64 hex chars
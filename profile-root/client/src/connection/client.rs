@@ -1,29 +1,230 @@
+use crate::config::ServerProfile;
+use crate::connection::cover::{CoverPolicy, CoverStats, CoverTraffic};
+use crate::connection::dispatcher::{DispatchMetrics, IncomingMessage, MessageDispatcher};
+use crate::connection::health::{ConnectionHealth, HealthMonitor, HealthReport};
+use crate::connection::proxy::ProxyConfig;
+use crate::connection::retry::BusyRetries;
+use crate::connection::state::{
+    ConnectionEvent, ConnectionState, ConnectionStateMachine, ConnectionTransition,
+};
+use crate::events::{ClientEvent, EventBus, EventSubscriber};
+use crate::handlers::archive::handle_archive_message;
+use crate::handlers::attachments::{
+    compose_attachment_accept, compose_attachment_cancel, compose_attachment_offer,
+    handle_incoming_attachment, AttachmentEvent,
+};
+use crate::handlers::edit::handle_incoming_edit;
+use crate::handlers::errors::IncomingError;
+use crate::handlers::key_rollover::{
+    apply_key_rollover, complete_key_rollover, create_key_rollover, PendingKeyRollover,
+};
+use crate::handlers::presence::{create_last_seen_query, ViewingHint};
+use crate::handlers::reactions::handle_incoming_reaction;
+use crate::handlers::receipts::{apply_read_receipt, create_read_receipts_for_view, ReadReceipt};
+use crate::handlers::retract::handle_incoming_retraction;
+use crate::handlers::rooms::{handle_room_event, RoomEvent};
+use crate::handlers::sealed::{open_sealed_message, seal_direct_message};
+use crate::handlers::verify::{create_invalid_signature_notification, VerificationResult};
+use crate::state::archive::{create_shared_archive, SharedArchive};
+use crate::state::attachments::{create_shared_attachments, SharedAttachments};
+use crate::state::audit::{audit_event, AuditEvent, AuditHandle};
+use crate::state::blocklist::{create_shared_blocklist, SharedBlocklist};
+use crate::state::contacts::{create_shared_contacts, SharedContacts};
+use crate::state::conversation::ConversationId;
 use crate::state::messages::{
     create_shared_message_history, create_shared_message_history_with_capacity, ChatMessage,
     SharedMessageHistory,
 };
+use crate::state::outbox::{create_shared_outbox, OutboundMessage, SendState, SharedOutbox};
+use crate::state::ratchet_sessions::{create_shared_ratchet_sessions, SharedRatchetSessions};
+use crate::state::rooms::{create_shared_rooms_state, Conversation, SharedRoomsState};
 use crate::state::session::SharedKeyState;
-use crate::ui::lobby_state::{LobbyState, LobbyUser};
+use crate::ui::chat::{create_shared_chat_view, update_chat_view, SharedChatView};
+use crate::ui::lobby_state::{LobbyPage, LobbyState, LobbyUser};
 use futures_util::{SinkExt, StreamExt};
+use profile_shared::capture::{CaptureWriter, Direction, FrameKind};
+use profile_shared::crypto::padding::PaddingPolicy;
+use profile_shared::crypto::rollover::KeyRollover;
+use profile_shared::protocol::compression::Frame;
+use profile_shared::protocol::{
+    split_retry_hint, AuthErrorMessage, AuthSuccessMessage, Compression, CompressionConfig,
+    Encoding, ErrorMessage, FrameCodec, ResumeMessage, Status, SERVER_BUSY_REASON,
+};
 use serde::Deserialize;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio_tungstenite::{client_async, connect_async, tungstenite::Message, MaybeTlsStream};
 use tracing::{debug, info, warn};
 
 /// Type alias for recipient offline callback
 type RecipientOfflineCallback = Rc<RefCell<dyn Fn(String) + 'static>>;
 
+/// Open the debug frame capture named by `PROFILE_CAPTURE_FILE`, if any
+fn capture_from_env() -> Option<std::sync::Arc<CaptureWriter>> {
+    match CaptureWriter::from_env() {
+        Ok(capture) => {
+            if capture.is_some() {
+                warn!("Frame capture enabled - all traffic is being written to disk");
+            }
+            capture
+        }
+        Err(e) => {
+            warn!(error = %e, "Failed to open frame capture file");
+            None
+        }
+    }
+}
+
+/// Wire encoding to offer the server, named by `PROFILE_ENCODING`
+///
+/// JSON when unset or not a supported encoding.
+fn encoding_from_env() -> Encoding {
+    match std::env::var("PROFILE_ENCODING") {
+        Ok(name) => Encoding::from_name(&name).unwrap_or_else(|| {
+            warn!(encoding = %name, "Unknown PROFILE_ENCODING, using JSON");
+            Encoding::Json
+        }),
+        Err(_) => Encoding::Json,
+    }
+}
+
+/// Compression to offer the server, from `PROFILE_COMPRESSION` and
+/// `PROFILE_COMPRESSION_THRESHOLD`
+///
+/// Deflate above the default threshold when unset or invalid.
+fn compression_from_env() -> CompressionConfig {
+    CompressionConfig::from_env().unwrap_or_else(|e| {
+        warn!(error = %e, "Invalid compression settings, using defaults");
+        CompressionConfig::default()
+    })
+}
+
+/// Proxy to connect through, from `PROFILE_PROXY`
+///
+/// A direct connection when unset or invalid.
+fn proxy_from_env() -> Option<ProxyConfig> {
+    ProxyConfig::from_env().unwrap_or_else(|e| {
+        warn!(error = %e, "Ignoring PROFILE_PROXY, connecting directly");
+        None
+    })
+}
+
+/// Cover traffic, if turned on in the settings file
+fn cover_from_settings() -> Option<CoverTraffic> {
+    crate::config::load_settings()
+        .cover_policy()
+        .map(CoverTraffic::new)
+}
+
+/// Padding for sealed direct messages, if forward secrecy is turned on in
+/// the settings file
+fn forward_secrecy_from_settings() -> Option<PaddingPolicy> {
+    crate::config::load_settings().forward_secrecy_policy()
+}
+
+/// Wait before reconnection attempt `attempt`, counting from 0
+///
+/// Backs off exponentially from `backoff_ms` (1s, 2s, 4s, 8s, 16s), unless
+/// the server's last close frame carried a retry hint: the hint is spread
+/// over clients by the server, so it is used as is.
+fn reconnect_delay(
+    backoff_ms: u64,
+    attempt: u32,
+    retry_hint: Option<std::time::Duration>,
+) -> std::time::Duration {
+    retry_hint.unwrap_or_else(|| std::time::Duration::from_millis(backoff_ms * 2u64.pow(attempt)))
+}
+
+/// Open a WebSocket to `url` through `proxy`
+///
+/// Only `ws://` URLs can be tunnelled, as this build has no TLS support.
+async fn connect_through_proxy(
+    proxy: &ProxyConfig,
+    url: &str,
+) -> Result<
+    tokio_tungstenite::WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>,
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let uri: tokio_tungstenite::tungstenite::http::Uri = url.parse()?;
+    if uri.scheme_str() != Some("ws") {
+        return Err(format!("Only ws:// URLs can be used through a proxy: {}", url).into());
+    }
+    let host = uri
+        .host()
+        .ok_or("Server URL has no host")?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let stream = proxy.connect(host, uri.port_u16().unwrap_or(80)).await?;
+    let (ws_stream, _) = client_async(url, MaybeTlsStream::Plain(stream)).await?;
+    Ok(ws_stream)
+}
+
+/// Append a frame to the capture file, if capture is enabled
+fn capture_frame(
+    capture: Option<&CaptureWriter>,
+    direction: Direction,
+    connection: u64,
+    frame: &Message,
+) {
+    let Some(capture) = capture else {
+        return;
+    };
+    let (kind, data) = match frame {
+        Message::Text(text) => (FrameKind::Text, text.clone()),
+        Message::Binary(data) => (FrameKind::Binary, hex::encode(data)),
+        Message::Ping(data) => (FrameKind::Ping, hex::encode(data)),
+        Message::Pong(data) => (FrameKind::Pong, hex::encode(data)),
+        Message::Close(frame) => (
+            FrameKind::Close,
+            frame
+                .as_ref()
+                .map(|f| f.reason.to_string())
+                .unwrap_or_default(),
+        ),
+        Message::Frame(frame) => (FrameKind::Binary, hex::encode(frame.payload())),
+    };
+    if let Err(e) = capture.record(direction, connection, kind, &data) {
+        warn!(error = %e, "Failed to write frame capture");
+    }
+}
+
 /// Authentication response from server
 #[derive(Debug, Clone, PartialEq)]
 pub enum AuthResponse {
-    /// Successful authentication with list of online users
-    Success { users: Vec<String> },
+    /// Successful authentication with the first page of online users
+    Success {
+        users: Vec<String>,
+        /// Number of online users across all pages
+        total: usize,
+        /// Cursor for fetching the remaining users with a lobby page request
+        next_cursor: Option<String>,
+        /// Nicknames of users on the first page that have one
+        nicknames: HashMap<String, String>,
+        /// Verified names of users on the first page that claimed one
+        names: HashMap<String, String>,
+        /// Statuses of users on the first page who aren't simply online
+        statuses: HashMap<String, Status>,
+        /// Our own status, kept from an earlier connection if we reconnected
+        /// before the server noticed the old one was gone
+        status: Status,
+        /// Encoding the server picked for the rest of the connection
+        encoding: Encoding,
+        /// Compression the server accepted for the rest of the connection
+        compression: Compression,
+        /// Token to resume this session on the next connection
+        resume_token: Option<String>,
+    },
     /// Authentication failed with reason and details
     Failed { reason: String, details: String },
 }
 
+/// Type alias for the nickname change callback (public key, new nickname)
+type NicknameCallback = Rc<RefCell<dyn Fn(String, Option<String>)>>;
+
+/// Type alias for the delivery state callback (message ID, new state)
+type SendStateCallback = Rc<RefCell<dyn Fn(String, SendState)>>;
+
 /// Callback handler for lobby events
 #[derive(Clone)]
 pub struct LobbyEventHandler {
@@ -35,6 +236,13 @@ pub struct LobbyEventHandler {
     pub on_user_left: Rc<RefCell<dyn Fn(String)>>,
     /// Called when the selected user leaves the lobby (AC5)
     pub on_selection_lost: Rc<RefCell<dyn Fn(String)>>,
+    /// Called when a requested page of lobby users arrives
+    pub on_lobby_page: Rc<RefCell<dyn Fn(LobbyPage)>>,
+    /// Called when a user sets or clears their nickname
+    pub on_nickname_changed: NicknameCallback,
+    /// Called with the new epoch when the server restarted since the
+    /// previous connection
+    pub on_server_restarted: Rc<RefCell<dyn Fn(u64)>>,
 }
 
 /// Callback handler for chat message events
@@ -48,6 +256,12 @@ pub struct MessageEventHandler {
     pub on_error: Rc<RefCell<dyn Fn(String)>>,
     /// Called for general notifications (e.g., offline status, info messages)
     pub on_notification: Rc<RefCell<dyn Fn(String)>>,
+    /// Called when the recipient of one of our messages has read it
+    pub on_read_receipt: Rc<RefCell<dyn Fn(ReadReceipt)>>,
+    /// Called when one of our messages changes delivery state
+    pub on_send_state: SendStateCallback,
+    /// Called when a peer opens or closes our conversation
+    pub on_viewing: Rc<RefCell<dyn Fn(ViewingHint)>>,
 }
 
 impl MessageEventHandler {
@@ -59,6 +273,9 @@ impl MessageEventHandler {
             on_invalid_signature: Rc::new(RefCell::new(|_: String| {})),
             on_error: Rc::new(RefCell::new(|_: String| {})),
             on_notification: Rc::new(RefCell::new(|_: String| {})),
+            on_read_receipt: Rc::new(RefCell::new(|_: ReadReceipt| {})),
+            on_send_state: Rc::new(RefCell::new(|_: String, _: SendState| {})),
+            on_viewing: Rc::new(RefCell::new(|_: ViewingHint| {})),
         }
     }
 
@@ -75,9 +292,39 @@ impl MessageEventHandler {
             on_invalid_signature: Rc::new(RefCell::new(on_invalid_signature)),
             on_error: Rc::new(RefCell::new(on_error)),
             on_notification: Rc::new(RefCell::new(on_notification)),
+            on_read_receipt: Rc::new(RefCell::new(|_: ReadReceipt| {})),
+            on_send_state: Rc::new(RefCell::new(|_: String, _: SendState| {})),
+            on_viewing: Rc::new(RefCell::new(|_: ViewingHint| {})),
         }
     }
 
+    /// Set the read receipt callback
+    #[inline]
+    pub fn with_read_receipt_callback(
+        mut self,
+        on_read_receipt: impl Fn(ReadReceipt) + 'static,
+    ) -> Self {
+        self.on_read_receipt = Rc::new(RefCell::new(on_read_receipt));
+        self
+    }
+
+    /// Set the delivery state callback
+    #[inline]
+    pub fn with_send_state_callback(
+        mut self,
+        on_send_state: impl Fn(String, SendState) + 'static,
+    ) -> Self {
+        self.on_send_state = Rc::new(RefCell::new(on_send_state));
+        self
+    }
+
+    /// Set the viewing hint callback
+    #[inline]
+    pub fn with_viewing_callback(mut self, on_viewing: impl Fn(ViewingHint) + 'static) -> Self {
+        self.on_viewing = Rc::new(RefCell::new(on_viewing));
+        self
+    }
+
     /// Emit message received event (for verified messages)
     #[inline]
     pub fn message_received(&self, message: &ChatMessage) {
@@ -101,6 +348,40 @@ impl MessageEventHandler {
     pub fn notification(&self, message: &str) {
         (self.on_notification.borrow())(message.to_string());
     }
+
+    /// Emit read receipt event
+    #[inline]
+    pub fn read_receipt(&self, receipt: &ReadReceipt) {
+        (self.on_read_receipt.borrow())(receipt.clone());
+    }
+
+    /// Emit delivery state event
+    #[inline]
+    pub fn send_state_changed(&self, message_id: &str, state: SendState) {
+        (self.on_send_state.borrow())(message_id.to_string(), state);
+    }
+
+    /// Emit viewing hint event
+    #[inline]
+    pub fn viewing(&self, hint: &ViewingHint) {
+        (self.on_viewing.borrow())(hint.clone());
+    }
+
+    /// Call the callback for a chat or error event, if it has one
+    pub fn handle_event(&self, event: &ClientEvent) {
+        match event {
+            ClientEvent::MessageReceived(message) => self.message_received(message),
+            ClientEvent::InvalidSignature(notification) => self.invalid_signature(notification),
+            ClientEvent::Error(error) => self.error(error),
+            ClientEvent::Notification(message) => self.notification(message),
+            ClientEvent::ReadReceipt(receipt) => self.read_receipt(receipt),
+            ClientEvent::SendStateChanged { message_id, state } => {
+                self.send_state_changed(message_id, *state)
+            }
+            ClientEvent::Viewing(hint) => self.viewing(hint),
+            _ => {}
+        }
+    }
 }
 
 impl Default for MessageEventHandler {
@@ -119,6 +400,9 @@ impl LobbyEventHandler {
             on_user_joined: Rc::new(RefCell::new(|_: LobbyUser| {})),
             on_user_left: Rc::new(RefCell::new(|_: String| {})),
             on_selection_lost: Rc::new(RefCell::new(|_: String| {})),
+            on_lobby_page: Rc::new(RefCell::new(|_: LobbyPage| {})),
+            on_nickname_changed: Rc::new(RefCell::new(|_: String, _: Option<String>| {})),
+            on_server_restarted: Rc::new(RefCell::new(|_: u64| {})),
         }
     }
 
@@ -135,9 +419,39 @@ impl LobbyEventHandler {
             on_user_joined: Rc::new(RefCell::new(on_user_joined)),
             on_user_left: Rc::new(RefCell::new(on_user_left)),
             on_selection_lost: Rc::new(RefCell::new(on_selection_lost)),
+            on_lobby_page: Rc::new(RefCell::new(|_: LobbyPage| {})),
+            on_nickname_changed: Rc::new(RefCell::new(|_: String, _: Option<String>| {})),
+            on_server_restarted: Rc::new(RefCell::new(|_: u64| {})),
         }
     }
 
+    /// Set the lobby page callback
+    #[inline]
+    pub fn with_lobby_page_callback(mut self, on_lobby_page: impl Fn(LobbyPage) + 'static) -> Self {
+        self.on_lobby_page = Rc::new(RefCell::new(on_lobby_page));
+        self
+    }
+
+    /// Set the nickname changed callback
+    #[inline]
+    pub fn with_nickname_callback(
+        mut self,
+        on_nickname_changed: impl Fn(String, Option<String>) + 'static,
+    ) -> Self {
+        self.on_nickname_changed = Rc::new(RefCell::new(on_nickname_changed));
+        self
+    }
+
+    /// Set the server restarted callback
+    #[inline]
+    pub fn with_server_restarted_callback(
+        mut self,
+        on_server_restarted: impl Fn(u64) + 'static,
+    ) -> Self {
+        self.on_server_restarted = Rc::new(RefCell::new(on_server_restarted));
+        self
+    }
+
     /// Emit lobby received event
     #[inline]
     pub fn lobby_received(&self, state: &LobbyState) {
@@ -161,6 +475,41 @@ impl LobbyEventHandler {
     pub fn selection_lost(&self, public_key: &str) {
         (self.on_selection_lost.borrow())(public_key.to_string());
     }
+
+    /// Emit lobby page event
+    #[inline]
+    pub fn lobby_page(&self, page: &LobbyPage) {
+        (self.on_lobby_page.borrow())(page.clone());
+    }
+
+    /// Emit nickname changed event
+    #[inline]
+    pub fn nickname_changed(&self, public_key: &str, nickname: Option<&str>) {
+        (self.on_nickname_changed.borrow())(public_key.to_string(), nickname.map(str::to_string));
+    }
+
+    /// Emit server restarted event
+    #[inline]
+    pub fn server_restarted(&self, epoch: u64) {
+        (self.on_server_restarted.borrow())(epoch);
+    }
+
+    /// Call the callback for a lobby event, if it has one
+    pub fn handle_event(&self, event: &ClientEvent) {
+        match event {
+            ClientEvent::LobbyReceived(state) => self.lobby_received(state),
+            ClientEvent::UserJoined(user) => self.user_joined(user),
+            ClientEvent::UserLeft(public_key) => self.user_left(public_key),
+            ClientEvent::SelectionLost(public_key) => self.selection_lost(public_key),
+            ClientEvent::LobbyPage(page) => self.lobby_page(page),
+            ClientEvent::NicknameChanged {
+                public_key,
+                nickname,
+            } => self.nickname_changed(public_key, nickname.as_deref()),
+            ClientEvent::ServerRestarted(epoch) => self.server_restarted(*epoch),
+            _ => {}
+        }
+    }
 }
 
 impl Default for LobbyEventHandler {
@@ -179,6 +528,23 @@ pub enum LobbyResponse {
     UsersJoined { public_keys: Vec<String> },
     /// One or more users left the lobby
     UsersLeft { public_keys: Vec<String> },
+    /// One page of users answering a lobby page request
+    Page(LobbyPage),
+    /// A user set or cleared their nickname
+    NicknameChanged {
+        public_key: String,
+        nickname: Option<String>,
+    },
+    /// A user claimed or released a verified name
+    VerifiedNameChanged {
+        public_key: String,
+        verified_name: Option<String>,
+    },
+    /// A user who is online picked a status other than online
+    StatusChanged { public_key: String, status: Status },
+    /// The server restarted since the previous connection, so lobby state
+    /// from before is stale and should be fetched again
+    ServerRestarted { epoch: u64 },
     /// Unknown or unhandled message type
     Ignored,
 }
@@ -187,7 +553,7 @@ pub enum LobbyResponse {
 #[derive(Debug, Clone, PartialEq)]
 pub enum ChatResponse {
     /// A new message was received
-    Message(ChatMessage),
+    Message(Box<ChatMessage>),
     /// Message was ignored (e.g., already verified by server)
     Ignored,
 }
@@ -209,8 +575,12 @@ pub fn parse_lobby_message(
                 .users
                 .into_iter()
                 .map(|u| LobbyUser {
+                    is_online: u.is_online(),
+                    status: u.status.filter(|s| s.is_selectable()).unwrap_or_default(),
                     public_key: u.public_key,
-                    is_online: u.status.as_deref() == Some("online"),
+                    nickname: u.nickname,
+                    verified_name: u.name,
+                    unread_count: 0,
                 })
                 .collect();
 
@@ -261,66 +631,33 @@ pub fn parse_chat_message(
             let text_msg: profile_shared::protocol::Message = serde_json::from_str(text)?;
 
             // Extract the message data using pattern matching
-            let (message, sender_public_key, signature, timestamp) = match text_msg {
-                profile_shared::protocol::Message::Text {
-                    message,
-                    sender_public_key,
-                    signature,
-                    timestamp,
-                } => (message, sender_public_key, signature, timestamp),
-                _ => return Ok(ChatResponse::Ignored),
+            let profile_shared::protocol::Message::Text {
+                message_id,
+                message,
+                sender_public_key,
+                signature,
+                timestamp,
+                canonical,
+                sequence,
+                server_received_at,
+            } = text_msg
+            else {
+                return Ok(ChatResponse::Ignored);
             };
 
             // Create a ChatMessage (initially unverified, client will verify)
-            let chat_msg = ChatMessage::new(sender_public_key, message, signature, timestamp);
-            Ok(ChatResponse::Message(chat_msg))
+            let chat_msg = ChatMessage::new(sender_public_key, message, signature, timestamp)
+                .with_message_id(message_id)
+                .with_canonical(canonical)
+                .with_sequence(sequence)
+                .with_server_received_at(server_received_at);
+            Ok(ChatResponse::Message(Box::new(chat_msg)))
         }
         // Other message types are not chat messages
         _ => Ok(ChatResponse::Ignored),
     }
 }
 
-/// Notification response from server
-#[derive(Debug, Clone, PartialEq)]
-pub enum NotificationResponse {
-    /// Recipient is offline
-    RecipientOffline {
-        recipient_key: String,
-        message: Option<String>,
-    },
-    /// User came back online
-    UserBackOnline { public_key: String },
-    /// Unknown notification type
-    Unknown,
-}
-
-/// Parse a notification from the server
-pub fn parse_notification(
-    text: &str,
-) -> Result<NotificationResponse, Box<dyn std::error::Error + Send + Sync>> {
-    use crate::handlers::offline::OfflineNotification;
-
-    // First, determine message type
-    let msg: ServerMessage = serde_json::from_str(text)?;
-
-    match msg.r#type.as_str() {
-        "notification" => {
-            let notification: OfflineNotification = serde_json::from_str(text)?;
-            match notification.event.as_str() {
-                "recipient_offline" => Ok(NotificationResponse::RecipientOffline {
-                    recipient_key: notification.recipient,
-                    message: notification.message,
-                }),
-                "user_online" => Ok(NotificationResponse::UserBackOnline {
-                    public_key: notification.recipient,
-                }),
-                _ => Ok(NotificationResponse::Unknown),
-            }
-        }
-        _ => Ok(NotificationResponse::Unknown),
-    }
-}
-
 /// Verify and store a received chat message
 ///
 /// This function performs client-side signature verification and stores
@@ -329,30 +666,30 @@ pub fn parse_notification(
 /// # Arguments
 /// * `chat_msg` - The parsed but unverified chat message
 /// * `message_history` - Shared message history for storage
-/// * `handler` - Message event handler for callbacks
+/// * `emit` - Called with the `MessageReceived` or `InvalidSignature` event
 ///
 /// # Returns
-/// Ok(()) if message was verified and stored, Err(reason) otherwise
+/// true if the message was verified and newly stored, false if it was
+/// rejected or a duplicate
 pub async fn verify_and_store_message(
     chat_msg: &ChatMessage,
     message_history: &SharedMessageHistory,
-    handler: &Option<MessageEventHandler>,
-) {
-    use crate::handlers::verify::{
-        create_invalid_signature_notification, format_public_key, verify_chat_message,
-    };
+    emit: impl Fn(ClientEvent),
+) -> bool {
+    use crate::handlers::verify::{format_public_key, verify_chat_message};
 
     // Verify the signature
     match verify_chat_message(chat_msg) {
         crate::handlers::verify::VerificationResult::Valid(verified_msg) => {
-            // Store in message history
+            // Store in message history; a message already stored was a resend
             let mut history = message_history.lock().await;
-            history.add_message(verified_msg.clone());
-
-            // Notify handler
-            if let Some(ref h) = handler {
-                h.message_received(&verified_msg);
+            if !history.add_message((*verified_msg).clone()) {
+                debug!(message_id = %verified_msg.message_id, "Ignoring duplicate message");
+                return false;
             }
+
+            emit(ClientEvent::MessageReceived(*verified_msg));
+            true
         }
         crate::handlers::verify::VerificationResult::Invalid {
             sender_public_key,
@@ -368,10 +705,8 @@ pub async fn verify_and_store_message(
             // Create notification
             let notification = create_invalid_signature_notification(&sender_public_key, &reason);
 
-            // Notify handler
-            if let Some(ref h) = handler {
-                h.invalid_signature(&notification);
-            }
+            emit(ClientEvent::InvalidSignature(notification));
+            false
         }
     }
 }
@@ -403,7 +738,7 @@ pub fn parse_server_message(
         }
         "error" => {
             // Parse error message
-            let error_msg: ServerErrorMessage = serde_json::from_str(text)?;
+            let error_msg: ErrorMessage = serde_json::from_str(text)?;
             Ok(ServerMessageResponse::Error(error_msg))
         }
         _ => Ok(ServerMessageResponse::Unknown),
@@ -418,42 +753,19 @@ pub enum ServerMessageResponse {
     /// Chat message
     Chat(ChatResponse),
     /// Error from server
-    Error(ServerErrorMessage),
+    Error(ErrorMessage),
     /// Unknown message type
     Unknown,
 }
 
-/// Server error message structure
-#[derive(Debug, Clone, Deserialize, PartialEq)]
-pub struct ServerErrorMessage {
-    pub r#type: String,
-    pub reason: String,
-    pub details: Option<String>,
-}
-
 /// Internal message types for parsing server responses
 #[derive(Debug, Deserialize)]
 struct ServerMessage {
     r#type: String,
 }
 
-#[derive(Debug, Deserialize)]
-struct AuthSuccessMessage {
-    #[serde(default)]
-    _type: String,
-    users: Vec<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct AuthErrorMessage {
-    #[serde(default)]
-    _type: String,
-    reason: String,
-    details: String,
-}
-
 /// Parse authentication response from server
-fn parse_auth_response(
+pub(crate) fn parse_auth_response(
     text: &str,
 ) -> Result<AuthResponse, Box<dyn std::error::Error + Send + Sync>> {
     // First, determine message type
@@ -463,7 +775,17 @@ fn parse_auth_response(
         "auth_success" => {
             let success: AuthSuccessMessage = serde_json::from_str(text)?;
             Ok(AuthResponse::Success {
+                // Servers that predate paging don't send a total
+                total: success.total.max(success.users.len()),
+                encoding: success.negotiated_encoding(),
+                compression: success.negotiated_compression(),
                 users: success.users,
+                next_cursor: success.next_cursor,
+                nicknames: success.nicknames,
+                names: success.names,
+                statuses: success.statuses,
+                status: success.status.unwrap_or_default(),
+                resume_token: success.resume_token,
             })
         }
         "error" => {
@@ -477,19 +799,6 @@ fn parse_auth_response(
     }
 }
 
-/// Connection state for the WebSocket client
-#[derive(Debug, Clone, PartialEq)]
-pub enum ConnectionState {
-    /// Not connected
-    Disconnected,
-    /// Currently attempting to connect or authenticate
-    Connecting,
-    /// Fully connected and authenticated
-    Connected,
-    /// Temporarily disconnected, attempting to reconnect
-    Reconnecting { attempts: u32 },
-}
-
 /// WebSocket client for connecting to the profile server
 pub struct WebSocketClient {
     connection: Option<
@@ -499,21 +808,83 @@ pub struct WebSocketClient {
     >,
     key_state: SharedKeyState,
     message_history: SharedMessageHistory,
+    /// Joined rooms and the active conversation
+    rooms_state: SharedRoomsState,
+    /// Archived conversations, unarchived when a message arrives in them
+    archive: SharedArchive,
+    /// Blocked keys, whose direct messages are dropped on arrival
+    blocklist: SharedBlocklist,
+    /// Contacts, moved to the new key when a peer rolls theirs over
+    contacts: SharedContacts,
+    /// The direct conversation being viewed, with the read and delivery
+    /// state of our messages; received messages shown in it are answered
+    /// with read receipts
+    chat_view: SharedChatView,
     lobby_event_handler: Option<LobbyEventHandler>,
     message_event_handler: Option<MessageEventHandler>,
+    /// Every connection, lobby, chat and error event is published here
+    events: EventBus,
     /// Track currently selected recipient for selection loss detection (AC5)
     selected_recipient: Option<String>,
-    /// Current connection state (AC4 - Network Resilience)
-    connection_state: ConnectionState,
+    /// Connection lifecycle state machine (AC4 - Network Resilience)
+    connection_state: ConnectionStateMachine,
     /// Maximum reconnection attempts before giving up (AC4)
     max_reconnect_attempts: u32,
     /// Backoff multiplier for exponential backoff (AC4)
     reconnect_backoff_ms: u64,
-    /// Queue for messages to send after reconnection (AC4 - race handling)
-    /// Maps recipient public key -> list of pending messages for that recipient
-    pending_messages: std::sync::Arc<tokio::sync::Mutex<HashMap<String, Vec<String>>>>,
+    /// Direct messages sent and their delivery state; queued ones are
+    /// resent after reconnection or when their recipient comes online (AC4)
+    outbox: SharedOutbox,
+    /// Files offered to or by peers and still being transferred
+    attachments: SharedAttachments,
     /// Notification when recipient goes offline during message composition (AC4)
     recipient_offline_handler: Option<RecipientOfflineCallback>,
+    /// Last direct message sent to each recipient, re-queued if the server
+    /// reports the recipient offline
+    in_flight_messages: HashMap<String, String>,
+    /// Routes incoming frames to registered handlers and counts them per kind
+    dispatcher: MessageDispatcher,
+    /// Pings the server and tracks latency while the message loop runs
+    health: HealthMonitor,
+    /// Messages the server refused as busy, waiting to be resent
+    busy_retries: BusyRetries,
+    /// Cover traffic schedule, if the user turned it on
+    cover: Option<CoverTraffic>,
+    /// Padding for direct messages sealed in ratchet sessions, None when
+    /// they are sent plain
+    forward_secrecy: Option<PaddingPolicy>,
+    /// Ratchet sessions with peers, for sealed direct messages
+    ratchet_sessions: SharedRatchetSessions,
+    /// Debug capture of every frame sent and received (opt-in)
+    capture: Option<std::sync::Arc<CaptureWriter>>,
+    /// Number of connections opened, used to tell them apart in captures
+    connection_count: u64,
+    /// Encoding offered to the server during auth
+    preferred_encoding: Encoding,
+    /// Compression offered to the server during auth
+    compression: CompressionConfig,
+    /// Encoding and compression negotiated for the current connection
+    codec: FrameCodec,
+    /// Proxy the connection is tunnelled through, if any
+    proxy: Option<ProxyConfig>,
+    /// Server picked with `use_profile`, instead of the configured one
+    server: Option<ServerProfile>,
+    /// Token from the last auth_success, used once to resume on reconnect
+    resume_token: Option<String>,
+    /// Reconnect delay the server asked for in its last close frame, used
+    /// instead of the backoff for the next attempt
+    retry_hint: Option<std::time::Duration>,
+    /// Key rollover sent to the server and not yet broadcast back
+    pending_rollover: Option<PendingKeyRollover>,
+    /// Security events are appended here, if a log is set
+    audit: AuditHandle,
+    /// Server of the current connection, for audit records
+    connected_url: Option<String>,
+    /// Our key was rolled over: reconnect and sign in with the new one
+    rejoin_with_new_key: bool,
+    /// Let the server record when we leave, and ask when the selected
+    /// recipient was last online after they leave
+    share_last_seen: bool,
 }
 
 impl WebSocketClient {
@@ -523,14 +894,42 @@ impl WebSocketClient {
             connection: None,
             key_state,
             message_history: create_shared_message_history(),
+            rooms_state: create_shared_rooms_state(),
+            archive: create_shared_archive(),
+            blocklist: create_shared_blocklist(),
+            contacts: create_shared_contacts(),
+            chat_view: create_shared_chat_view(),
             lobby_event_handler: None,
             message_event_handler: None,
+            events: EventBus::new(),
             selected_recipient: None,
-            connection_state: ConnectionState::Disconnected,
+            connection_state: ConnectionStateMachine::new(),
             max_reconnect_attempts: 5,
             reconnect_backoff_ms: 1000,
-            pending_messages: std::sync::Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            outbox: create_shared_outbox(),
+            attachments: create_shared_attachments(),
             recipient_offline_handler: None,
+            in_flight_messages: HashMap::new(),
+            dispatcher: MessageDispatcher::new(),
+            health: HealthMonitor::default(),
+            busy_retries: BusyRetries::default(),
+            cover: cover_from_settings(),
+            forward_secrecy: forward_secrecy_from_settings(),
+            ratchet_sessions: create_shared_ratchet_sessions(),
+            capture: capture_from_env(),
+            connection_count: 0,
+            preferred_encoding: encoding_from_env(),
+            compression: compression_from_env(),
+            codec: FrameCodec::default(),
+            proxy: proxy_from_env(),
+            server: None,
+            resume_token: None,
+            retry_hint: None,
+            pending_rollover: None,
+            rejoin_with_new_key: false,
+            share_last_seen: false,
+            audit: None,
+            connected_url: None,
         }
     }
 
@@ -540,14 +939,42 @@ impl WebSocketClient {
             connection: None,
             key_state,
             message_history: create_shared_message_history_with_capacity(capacity),
+            rooms_state: create_shared_rooms_state(),
+            archive: create_shared_archive(),
+            blocklist: create_shared_blocklist(),
+            contacts: create_shared_contacts(),
+            chat_view: create_shared_chat_view(),
             lobby_event_handler: None,
             message_event_handler: None,
+            events: EventBus::new(),
             selected_recipient: None,
-            connection_state: ConnectionState::Disconnected,
+            connection_state: ConnectionStateMachine::new(),
             max_reconnect_attempts: 5,
             reconnect_backoff_ms: 1000,
-            pending_messages: std::sync::Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            outbox: create_shared_outbox(),
+            attachments: create_shared_attachments(),
             recipient_offline_handler: None,
+            in_flight_messages: HashMap::new(),
+            dispatcher: MessageDispatcher::new(),
+            health: HealthMonitor::default(),
+            busy_retries: BusyRetries::default(),
+            cover: cover_from_settings(),
+            forward_secrecy: forward_secrecy_from_settings(),
+            ratchet_sessions: create_shared_ratchet_sessions(),
+            capture: capture_from_env(),
+            connection_count: 0,
+            preferred_encoding: encoding_from_env(),
+            compression: compression_from_env(),
+            codec: FrameCodec::default(),
+            proxy: proxy_from_env(),
+            server: None,
+            resume_token: None,
+            retry_hint: None,
+            pending_rollover: None,
+            rejoin_with_new_key: false,
+            share_last_seen: false,
+            audit: None,
+            connected_url: None,
         }
     }
 
@@ -556,19 +983,373 @@ impl WebSocketClient {
         self.selected_recipient = public_key;
     }
 
+    /// Share last-seen times, e.g. from the presence settings
+    ///
+    /// The server learns the setting at the next sign-in.
+    pub fn set_share_last_seen(&mut self, enabled: bool) {
+        self.share_last_seen = enabled;
+    }
+
     /// Get the currently selected recipient
     pub fn selected_recipient(&self) -> Option<&str> {
         self.selected_recipient.as_deref()
     }
 
+    /// Open the direct conversation with `recipient` (None to leave it)
+    ///
+    /// Loads the conversation into the chat view and sends read receipts
+    /// for the received messages it shows. Messages arriving while the
+    /// conversation stays open are acknowledged as they arrive.
+    ///
+    /// # Returns
+    /// The number of read receipts sent
+    pub async fn view_conversation(
+        &mut self,
+        recipient: Option<String>,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        self.chat_view.lock().await.set_selected_recipient(
+            recipient
+                .as_deref()
+                .and_then(|key| ConversationId::new(key).ok()),
+        );
+        self.selected_recipient = recipient;
+        self.refresh_chat_view().await;
+        self.send_read_receipts().await
+    }
+
+    /// Get the chat view of the conversation being viewed
+    pub fn chat_view(&self) -> SharedChatView {
+        self.chat_view.clone()
+    }
+
+    /// Use the chat view the UI renders, so read receipts and delivery
+    /// states reach it
+    pub fn set_chat_view(&mut self, chat_view: SharedChatView) {
+        self.chat_view = chat_view;
+    }
+
     /// Get the message history
     pub fn message_history(&self) -> SharedMessageHistory {
         self.message_history.clone()
     }
 
+    /// Get the rooms state
+    pub fn rooms_state(&self) -> SharedRoomsState {
+        self.rooms_state.clone()
+    }
+
+    /// Get the outbox of sent direct messages
+    pub fn outbox(&self) -> SharedOutbox {
+        self.outbox.clone()
+    }
+
+    /// Get the file transfers in progress
+    pub fn attachments(&self) -> SharedAttachments {
+        self.attachments.clone()
+    }
+
+    /// Offer the file at `path` to `recipient_public_key`
+    ///
+    /// The file is read into memory and held until the recipient accepts
+    /// or either side cancels; its chunks are sent as the recipient asks
+    /// for them.
+    ///
+    /// # Returns
+    /// The transfer id
+    pub async fn send_attachment(
+        &mut self,
+        recipient_public_key: &str,
+        path: &std::path::Path,
+    ) -> Result<uuid::Uuid, Box<dyn std::error::Error + Send + Sync>> {
+        let data = std::fs::read(path)?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let (transfer_id, offer) = compose_attachment_offer(
+            &file_name,
+            data,
+            recipient_public_key,
+            &self.key_state,
+            &self.attachments,
+        )
+        .await?;
+        if let Err(e) = self.send_message_internal(&offer).await {
+            self.attachments
+                .lock()
+                .await
+                .cancel(transfer_id, recipient_public_key);
+            return Err(e);
+        }
+        Ok(transfer_id)
+    }
+
+    /// Accept a file offered to us; it is saved to the downloads directory
+    /// once every chunk arrives
+    ///
+    /// # Returns
+    /// false if no such file was offered or it was already accepted
+    pub async fn accept_attachment(
+        &mut self,
+        transfer_id: uuid::Uuid,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        match compose_attachment_accept(transfer_id, &self.attachments).await {
+            Some(request) => {
+                self.send_message_internal(&request).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Give up on a file transfer in either direction, telling the peer
+    ///
+    /// # Returns
+    /// false if the transfer isn't in progress
+    pub async fn cancel_attachment(
+        &mut self,
+        transfer_id: uuid::Uuid,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        match compose_attachment_cancel(transfer_id, &self.attachments).await {
+            Some(request) => {
+                self.send_message_internal(&request).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Get the ratchet sessions with peers
+    pub fn ratchet_sessions(&self) -> SharedRatchetSessions {
+        self.ratchet_sessions.clone()
+    }
+
+    /// Seal direct messages in ratchet sessions, padded with `padding`, or
+    /// send them plain with None
+    pub fn set_forward_secrecy(&mut self, padding: Option<PaddingPolicy>) {
+        self.forward_secrecy = padding;
+    }
+
+    /// Whether direct messages are sealed in ratchet sessions
+    pub fn forward_secrecy(&self) -> bool {
+        self.forward_secrecy.is_some()
+    }
+
+    /// Get the conversation archive
+    pub fn archive(&self) -> SharedArchive {
+        self.archive.clone()
+    }
+
+    /// Use a (typically persistent) conversation archive
+    pub fn set_archive(&mut self, archive: SharedArchive) {
+        self.archive = archive;
+    }
+
+    /// Get the blocklist
+    pub fn blocklist(&self) -> SharedBlocklist {
+        self.blocklist.clone()
+    }
+
+    /// Use a (typically persistent) blocklist
+    pub fn set_blocklist(&mut self, blocklist: SharedBlocklist) {
+        self.blocklist = blocklist;
+    }
+
+    /// Get the contact list
+    pub fn contacts(&self) -> SharedContacts {
+        self.contacts.clone()
+    }
+
+    /// Use a (typically persistent) contact list
+    pub fn set_contacts(&mut self, contacts: SharedContacts) {
+        self.contacts = contacts;
+    }
+
+    /// Append security events, such as failed sign-ins and invalid
+    /// signatures, to `audit` (None to stop)
+    pub fn set_audit_log(&mut self, audit: AuditHandle) {
+        self.audit = audit;
+    }
+
+    /// Get the audit log in use, if any
+    pub fn audit_log(&self) -> AuditHandle {
+        self.audit.clone()
+    }
+
+    /// Start replacing our identity key with a newly generated one
+    ///
+    /// Sends a rollover signed by both keys. The new key is only used once
+    /// the server broadcasts the rollover back, after which the client
+    /// reconnects with it and emits [`ClientEvent::KeyRolledOver`].
+    ///
+    /// # Returns
+    /// The new public key (hex)
+    pub async fn roll_over_key(
+        &mut self,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let pending = create_key_rollover(&self.key_state).await?;
+        self.send_message_internal(&pending.request_json()).await?;
+        let new_public_key = pending.rollover.new_public_key.clone();
+        self.pending_rollover = Some(pending);
+        Ok(new_public_key)
+    }
+
+    /// Get the incoming message dispatcher, e.g. to register handlers
+    pub fn dispatcher_mut(&mut self) -> &mut MessageDispatcher {
+        &mut self.dispatcher
+    }
+
+    /// Get per-kind counters for incoming messages
+    pub fn dispatch_metrics(&self) -> &DispatchMetrics {
+        self.dispatcher.metrics()
+    }
+
     /// Get the current connection state (AC4)
     pub fn connection_state(&self) -> ConnectionState {
-        self.connection_state.clone()
+        self.connection_state.state().clone()
+    }
+
+    /// Register a handler called on every connection state transition
+    pub fn set_connection_transition_handler(
+        &mut self,
+        handler: impl Fn(&ConnectionTransition) + 'static,
+    ) {
+        self.connection_state.on_transition(handler);
+    }
+
+    /// Advance the connection state machine, ignoring events that do not apply
+    fn transition(&mut self, event: ConnectionEvent) {
+        let from = self.connection_state.state().clone();
+        match self.connection_state.fire(event) {
+            Ok(to) => {
+                let transition = ConnectionTransition {
+                    from,
+                    event,
+                    to: to.clone(),
+                };
+                self.events.publish(ClientEvent::Connection(transition));
+            }
+            Err(e) => debug!(error = %e, "Ignoring connection event"),
+        }
+    }
+
+    /// The bus every event of this client is published on
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Subscribe to this client's events from now on
+    pub fn subscribe(&self) -> EventSubscriber {
+        self.events.subscribe()
+    }
+
+    /// Publish on `bus` instead, e.g. one the UI subscribed to before the
+    /// client was created
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.events = bus;
+    }
+
+    /// Publish an event and call the matching registered callback
+    fn emit(&self, event: ClientEvent) {
+        if let Some(ref handler) = self.message_event_handler {
+            handler.handle_event(&event);
+        }
+        if let Some(ref handler) = self.lobby_event_handler {
+            handler.handle_event(&event);
+        }
+        if let ClientEvent::RecipientOffline(ref recipient) = event {
+            if let Some(ref handler) = self.recipient_offline_handler {
+                handler.borrow()(recipient.clone());
+            }
+        }
+        self.events.publish(event);
+    }
+
+    /// Tee every frame to a capture file (None disables capture)
+    pub fn set_capture(&mut self, capture: Option<std::sync::Arc<CaptureWriter>>) {
+        self.capture = capture;
+    }
+
+    /// Connect to `profile`'s server from the next connection on, instead
+    /// of the one picked by the settings (None to go back to those)
+    pub fn use_profile(&mut self, profile: Option<ServerProfile>) {
+        self.server = profile;
+    }
+
+    /// Connect through `proxy` from the next connection on (None to
+    /// connect directly)
+    ///
+    /// Takes precedence over the proxy of the server profile.
+    pub fn set_proxy(&mut self, proxy: Option<ProxyConfig>) {
+        self.proxy = proxy;
+    }
+
+    /// Proxy the connection is tunnelled through, if any
+    pub fn proxy(&self) -> Option<&ProxyConfig> {
+        self.proxy.as_ref()
+    }
+
+    /// Send cover traffic by `policy` from the next sign-in on (None to
+    /// stop now)
+    ///
+    /// Overrides the `coverTraffic` setting.
+    pub fn set_cover_traffic(&mut self, policy: Option<CoverPolicy>) {
+        self.cover = policy.map(CoverTraffic::new);
+    }
+
+    /// Cover frames sent and skipped, if cover traffic is on
+    pub fn cover_stats(&self) -> Option<CoverStats> {
+        self.cover.as_ref().map(CoverTraffic::stats)
+    }
+
+    /// Offer `encoding` to the server on the next auth (JSON is always the
+    /// fallback)
+    pub fn set_preferred_encoding(&mut self, encoding: Encoding) {
+        self.preferred_encoding = encoding;
+    }
+
+    /// Encoding negotiated for the current connection
+    pub fn encoding(&self) -> Encoding {
+        self.codec.encoding
+    }
+
+    /// Offer compression on the next auth as `config` allows
+    pub fn set_compression(&mut self, config: CompressionConfig) {
+        self.compression = config;
+    }
+
+    /// Compression negotiated for the current connection
+    pub fn compression(&self) -> Compression {
+        self.codec.compression
+    }
+
+    /// Send a frame on the open connection, recording it in the capture
+    async fn send_frame(
+        &mut self,
+        frame: Message,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(connection) = &mut self.connection else {
+            return Err("No connection available".into());
+        };
+        capture_frame(
+            self.capture.as_deref(),
+            Direction::Outbound,
+            self.connection_count,
+            &frame,
+        );
+        connection.send(frame).await?;
+        Ok(())
+    }
+
+    /// Current connection quality
+    pub fn connection_health(&self) -> ConnectionHealth {
+        self.health.health()
+    }
+
+    /// Register a handler called with connection health after every pong or
+    /// missed pong
+    pub fn set_health_handler(&mut self, handler: impl Fn(&HealthReport) + 'static) {
+        self.health.set_health_callback(handler);
     }
 
     /// Set handler for recipient offline notifications (AC4)
@@ -583,23 +1364,28 @@ impl WebSocketClient {
         let mut attempts = 0;
 
         while attempts < self.max_reconnect_attempts {
-            self.connection_state = ConnectionState::Reconnecting { attempts };
-
-            // Exponential backoff: 1s, 2s, 4s, 8s, 16s
-            let backoff = self.reconnect_backoff_ms * 2u64.pow(attempts);
+            let backoff =
+                reconnect_delay(self.reconnect_backoff_ms, attempts, self.retry_hint.take());
             debug!(
-                backoff_ms = backoff,
+                backoff_ms = backoff.as_millis() as u64,
                 attempt = attempts + 1,
                 max_attempts = self.max_reconnect_attempts,
                 "Reconnecting"
             );
-            tokio::time::sleep(tokio::time::Duration::from_millis(backoff)).await;
+            tokio::time::sleep(backoff).await;
 
             // Try to reconnect
             match self.connect().await {
                 Ok(_) => {
                     info!("Reconnected successfully");
-                    return self.reconnection_flow().await;
+                    let result = self.reconnection_flow().await;
+                    // Refused with a retry hint, e.g. while the server paces
+                    // the reconnections after a restart: wait and try again
+                    if result.is_err() && self.retry_hint.is_some() {
+                        attempts += 1;
+                        continue;
+                    }
+                    return result;
                 }
                 Err(e) => {
                     warn!(attempt = attempts + 1, error = %e, "Reconnection attempt failed");
@@ -613,11 +1399,8 @@ impl WebSocketClient {
             "Failed to reconnect after {} attempts. Please reconnect manually.",
             self.max_reconnect_attempts
         );
-        self.connection_state = ConnectionState::Disconnected;
-
-        if let Some(ref handler) = self.message_event_handler {
-            handler.error(&err_msg);
-        }
+        self.transition(ConnectionEvent::GiveUp);
+        self.emit(ClientEvent::Error(err_msg.clone()));
 
         Err(err_msg.into())
     }
@@ -626,41 +1409,38 @@ impl WebSocketClient {
     ///
     /// This implements Task 5.2: "On reconnect, request full lobby state from server"
     async fn reconnection_flow(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Authenticate with server
-        match self.authenticate().await {
+        // Resume the previous session if it's still valid; the server closes
+        // the connection when it isn't, so sign in again on a fresh one
+        let resuming = self.resume_token.is_some();
+        let mut result = self.authenticate().await;
+        if resuming && result.is_err() {
+            debug!("Session resume refused, signing in again");
+            self.connect().await?;
+            result = self.authenticate().await;
+        }
+        match result {
             Ok(_) => {
                 info!("Re-authenticated successfully");
-                self.connection_state = ConnectionState::Connected;
-
-                // Send any pending messages (Task 5.3: Handle race)
-                let messages_to_send: Vec<String> = {
-                    let mut pending = self.pending_messages.lock().await;
-                    if !pending.is_empty() {
-                        let total_count: usize = pending.values().map(|v| v.len()).sum();
-                        info!(
-                            count = total_count,
-                            "Sending pending messages from reconnection"
-                        );
-                        // Flatten all pending messages into a single vector
-                        let messages: Vec<String> = pending.values().flatten().cloned().collect();
-                        // Clear the HashMap
-                        pending.clear();
-                        messages
-                    } else {
-                        vec![]
-                    }
-                };
+
+                // Send any queued messages (Task 5.3: Handle race)
+                let queued = self.outbox.lock().await.take_queued();
+                if !queued.is_empty() {
+                    info!(
+                        count = queued.len(),
+                        "Sending queued messages from reconnection"
+                    );
+                }
 
                 // Send messages after releasing lock (avoid borrow checker conflict)
-                for msg in messages_to_send {
-                    self.send_message_internal(&msg).await?;
+                for message in queued {
+                    self.deliver(&message.json).await?;
+                    self.track_outgoing(&message.json);
                 }
 
                 Ok(())
             }
             Err(e) => {
                 warn!(error = %e, "Authentication after reconnect failed");
-                self.connection_state = ConnectionState::Disconnected;
                 Err(e)
             }
         }
@@ -671,16 +1451,67 @@ impl WebSocketClient {
         &mut self,
         message: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(connection) = &mut self.connection {
-            connection.send(Message::Text(message.to_string())).await?;
-            Ok(())
-        } else {
-            Err("No connection available".into())
+        let frame = match self.codec.encode_json(message)? {
+            Frame::Text(text) => Message::Text(text),
+            Frame::Binary(data) => Message::Binary(data),
+        };
+        self.send_frame(frame).await
+    }
+
+    /// Send a direct message through the outbox
+    ///
+    /// Other messages are sent as they are. A direct message that can't be
+    /// written is queued to be resent after reconnecting.
+    async fn deliver(
+        &mut self,
+        message: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let id = self.outbox.lock().await.start_sending(message);
+        let Some(id) = id else {
+            return self.send_message_internal(message).await;
+        };
+        self.report_send_state(&id, SendState::Sending).await;
+        match self.send_message_internal(message).await {
+            Ok(()) => {
+                if self.outbox.lock().await.mark_sent(&id) {
+                    self.report_send_state(&id, SendState::Sent).await;
+                }
+                Ok(())
+            }
+            Err(e) => {
+                let changes = self.outbox.lock().await.queue(&id);
+                self.report_send_states(changes).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Report that a message changed delivery state
+    async fn report_send_state(&self, message_id: &str, state: SendState) {
+        self.chat_view
+            .lock()
+            .await
+            .set_send_state(message_id, state);
+        self.emit(ClientEvent::SendStateChanged {
+            message_id: message_id.to_string(),
+            state,
+        });
+    }
+
+    async fn report_send_states(&self, changes: Vec<(String, SendState)>) {
+        for (id, state) in changes {
+            if state == SendState::Failed {
+                warn!(message_id = %id, "Outbox full, message dropped");
+            }
+            self.report_send_state(&id, state).await;
         }
     }
 
     /// Send a message to the server (public API)
     ///
+    /// With forward secrecy on, a direct message is sealed in the ratchet
+    /// session with its recipient first.
+    ///
     /// # Arguments
     /// * `message` - The JSON message to send
     ///
@@ -688,12 +1519,132 @@ impl WebSocketClient {
     /// Ok(()) if message was sent successfully
     ///
     /// # Errors
-    /// Returns error if connection is not available or send fails
+    /// Returns error if connection is not available, sealing or send fails
     pub async fn send_message(
         &mut self,
         message: String,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        self.send_message_internal(&message).await
+        let message = match &self.forward_secrecy {
+            Some(padding) => {
+                seal_direct_message(&message, &self.key_state, &self.ratchet_sessions, padding)
+                    .await?
+                    .unwrap_or(message)
+            }
+            None => message,
+        };
+        self.deliver(&message).await?;
+        self.track_outgoing(&message);
+        Ok(())
+    }
+
+    /// Announce a sent direct message and remember it so it can be queued
+    /// if the recipient is offline
+    fn track_outgoing(&mut self, message: &str) {
+        let Some(outbound) = OutboundMessage::from_json(message) else {
+            return;
+        };
+        self.emit(ClientEvent::MessageSent {
+            message_id: outbound.id,
+            recipient_public_key: outbound.recipient.clone(),
+        });
+        if let Some(previous) = self
+            .in_flight_messages
+            .insert(outbound.recipient, message.to_string())
+        {
+            self.busy_retries.forget(&previous);
+        }
+    }
+
+    /// Number of messages queued for offline recipients or reconnection
+    pub async fn pending_message_count(&self) -> usize {
+        self.outbox.lock().await.queued_count()
+    }
+
+    /// Handle an error reported by the server (Story 3.6)
+    ///
+    /// Offline recipients get their last in-flight message queued for
+    /// delivery when they rejoin, and a message the server was too busy to
+    /// accept is resent after its retry-after hint. Any other error, or a
+    /// message refused too often, is surfaced to the UI.
+    async fn dispatch_incoming_error(&mut self, error: IncomingError) {
+        match &error {
+            IncomingError::RecipientOffline(notification) => {
+                let recipient_key = notification.recipient.clone();
+                debug!(recipient = %recipient_key.chars().take(16).collect::<String>(), "Recipient is offline");
+
+                // Queue message for delivery when recipient comes online (AC4)
+                if let Some(outbound) = self
+                    .in_flight_messages
+                    .remove(&recipient_key)
+                    .and_then(|json| OutboundMessage::from_json(&json))
+                {
+                    let changes = {
+                        let mut outbox = self.outbox.lock().await;
+                        outbox.start_sending(&outbound.json);
+                        outbox.queue(&outbound.id)
+                    };
+                    debug!(recipient = %recipient_key, "Message queued for delivery when recipient comes online");
+                    self.report_send_states(changes).await;
+                }
+
+                // Notify recipient_offline_handler (AC4)
+                self.emit(ClientEvent::RecipientOffline(recipient_key));
+
+                // A notification, not an invalid signature!
+                self.emit(ClientEvent::Notification(error.user_message()));
+            }
+            IncomingError::ServerBusy {
+                recipient,
+                retry_after,
+            } => {
+                let message = self.in_flight_messages.get(recipient).cloned();
+                let scheduled = message.as_ref().and_then(|message| {
+                    self.busy_retries
+                        .schedule(message, *retry_after, std::time::Instant::now())
+                });
+                let id = message
+                    .and_then(|m| OutboundMessage::from_json(&m))
+                    .map(|m| m.id);
+                match scheduled {
+                    Some(delay) => {
+                        debug!(
+                            delay_ms = delay.as_millis() as u64,
+                            "Server busy - message will be resent"
+                        );
+                    }
+                    None => {
+                        warn!("Server busy - giving up on message");
+                        if let Some(id) = id {
+                            if self.outbox.lock().await.mark_failed(&id) {
+                                self.report_send_state(&id, SendState::Failed).await;
+                            }
+                        }
+                        self.emit(ClientEvent::Error(error.user_message()));
+                    }
+                }
+            }
+            IncomingError::Server { reason, details } => {
+                warn!(reason = %reason, details = %details.clone().unwrap_or_default(), "Server error");
+                self.emit(ClientEvent::Error(error.user_message()));
+            }
+        }
+    }
+
+    /// Send any messages queued for a user who just came online (AC4)
+    async fn flush_pending_for(&mut self, public_key: &str) {
+        let user_messages = self.outbox.lock().await.take_queued_for(public_key);
+        if user_messages.is_empty() {
+            return;
+        }
+
+        // Send messages after releasing lock
+        for msg in &user_messages {
+            match self.deliver(&msg.json).await {
+                Ok(()) => self.track_outgoing(&msg.json),
+                Err(e) => warn!(user = %public_key, error = %e, "Failed to send queued message"),
+            }
+        }
+        info!(user = %public_key, count = user_messages.len(), "Delivered queued messages");
     }
 
     /// Set the lobby event handler
@@ -712,19 +1663,83 @@ impl WebSocketClient {
 
     /// Connect to the profile server
     pub async fn connect(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Use environment variable PROFILE_SERVER_URL if set, otherwise default to localhost
-        let url = std::env::var("PROFILE_SERVER_URL")
-            .unwrap_or_else(|_| "ws://127.0.0.1:8080".to_string());
-
-        let (ws_stream, _) = connect_async(&url).await?;
-        self.connection = Some(ws_stream);
+        // The profile picked for this client, otherwise the configured server
+        // (see crate::config for the order)
+        let (url, profile_proxy) = match &self.server {
+            Some(profile) => (profile.url.clone(), profile.proxy_config()?),
+            None => crate::config::resolve_connect_target(),
+        };
+        let proxy = self.proxy.clone().or(profile_proxy);
+
+        // Reconnection attempts are already tracked as Reconnecting
+        if !matches!(
+            self.connection_state.state(),
+            ConnectionState::Reconnecting { .. }
+        ) {
+            self.transition(ConnectionEvent::Connect);
+        }
 
-        Ok(())
+        let connected = match &proxy {
+            Some(proxy) => connect_through_proxy(proxy, &url).await,
+            None => connect_async(&url)
+                .await
+                .map(|(ws_stream, _)| ws_stream)
+                .map_err(Into::into),
+        };
+        match connected {
+            Ok(ws_stream) => {
+                self.connection = Some(ws_stream);
+                self.connection_count += 1;
+                if let Some(audit) = &self.audit {
+                    if let Err(e) = audit.record_server(&url) {
+                        warn!(error = %e, "Failed to write audit log");
+                    }
+                }
+                self.connected_url = Some(url);
+                // Every connection starts on uncompressed JSON until auth
+                // negotiates otherwise
+                self.codec = FrameCodec::default();
+                self.transition(ConnectionEvent::Connected);
+                Ok(())
+            }
+            Err(e) => {
+                self.transition(ConnectionEvent::ConnectFailed);
+                Err(e)
+            }
+        }
     }
 
     /// Perform authentication handshake
     pub async fn authenticate(
         &mut self,
+    ) -> Result<AuthResponse, Box<dyn std::error::Error + Send + Sync>> {
+        let result = self.authenticate_handshake().await;
+        if result.is_ok() {
+            self.health.reset(std::time::Instant::now());
+            self.transition(ConnectionEvent::Authenticated);
+            let cover_warning = self.cover.as_mut().and_then(|cover| {
+                cover.reset(std::time::Instant::now(), &mut rand::thread_rng());
+                cover.take_warning()
+            });
+            if let Some(warning) = cover_warning {
+                warn!("{}", warning);
+                self.emit(ClientEvent::Notification(warning));
+            }
+        } else {
+            self.transition(ConnectionEvent::AuthFailed);
+            if let Err(e) = &result {
+                audit_event(&self.audit, || AuditEvent::AuthFailed {
+                    server: self.connected_url.clone().unwrap_or_default(),
+                    reason: e.to_string(),
+                });
+            }
+        }
+        result
+    }
+
+    /// Send the auth message and wait for the server's response
+    async fn authenticate_handshake(
+        &mut self,
     ) -> Result<AuthResponse, Box<dyn std::error::Error + Send + Sync>> {
         // Get keys from shared state
         // Create authentication message using auth.rs module within the lock scope
@@ -738,18 +1753,49 @@ impl WebSocketClient {
                 .private_key()
                 .ok_or("No private key available. Generate or import a key first.")?;
 
-            super::auth::ClientAuthMessage::new_with_ref(public_key, private_key)?
+            super::auth::AuthMessage::signed(&public_key, private_key)?
+                .with_preferred_encoding(self.preferred_encoding)
+                .with_compression(&self.compression)
+                .with_ephemeral(key_state.is_ephemeral())
+                .with_share_last_seen(self.share_last_seen)
+        };
+        // A resume token is only tried once, whether or not it still works
+        let auth_json = match self.resume_token.take() {
+            Some(token) => {
+                serde_json::to_string(&ResumeMessage::new(token).with_offers_from(&auth_msg))?
+            }
+            None => serde_json::to_string(&auth_msg)?,
         };
-        let auth_json = auth_msg.to_json()?;
 
         // Send auth message and wait for response
-        if let Some(connection) = &mut self.connection {
+        if self.connection.is_some() {
             // Send auth message
-            connection.send(Message::Text(auth_json)).await?;
+            self.send_frame(Message::Text(auth_json)).await?;
 
             // Wait for server response
-            if let Some(msg) = connection.next().await {
-                match msg? {
+            let response = match &mut self.connection {
+                Some(connection) => connection.next().await,
+                None => None,
+            };
+            if let Some(msg) = response {
+                let msg = msg?;
+                capture_frame(
+                    self.capture.as_deref(),
+                    Direction::Inbound,
+                    self.connection_count,
+                    &msg,
+                );
+                // A server that accepts the offered compression may already
+                // compress auth_success, which is otherwise plain JSON
+                let msg = match msg {
+                    Message::Binary(data) if self.compression.enabled => {
+                        let codec = FrameCodec::new(Encoding::Json)
+                            .with_compression(Compression::Deflate, self.compression.threshold);
+                        Message::Text(codec.decode_binary(&data)?)
+                    }
+                    other => other,
+                };
+                match msg {
                     Message::Text(text) => {
                         let response = parse_auth_response(&text)?;
 
@@ -768,9 +1814,32 @@ impl WebSocketClient {
                                 user_message
                             };
 
+                            // A server pacing reconnections says when to come
+                            // back in the close frame that follows
+                            if reason == SERVER_BUSY_REASON {
+                                if let Some(connection) = &mut self.connection {
+                                    if let Some(Ok(Message::Close(Some(frame)))) =
+                                        connection.next().await
+                                    {
+                                        self.retry_hint = split_retry_hint(&frame.reason).1;
+                                    }
+                                }
+                            }
+
                             return Err(final_message.into());
                         }
 
+                        if let AuthResponse::Success {
+                            encoding,
+                            compression,
+                            resume_token,
+                            ..
+                        } = &response
+                        {
+                            self.codec = FrameCodec::new(*encoding)
+                                .with_compression(*compression, self.compression.threshold);
+                            self.resume_token = resume_token.clone();
+                        }
                         return Ok(response);
                     }
                     Message::Close(frame) => {
@@ -778,6 +1847,7 @@ impl WebSocketClient {
                             .as_ref()
                             .map(|f| f.reason.to_string())
                             .unwrap_or_else(|| "Unknown".to_string());
+                        self.retry_hint = split_retry_hint(&reason).1;
 
                         // Use error_display to map to user-friendly message
                         use crate::ui::error_display::display_connection_error;
@@ -806,6 +1876,15 @@ impl WebSocketClient {
         Err("No connection available".into())
     }
 
+    /// Dispatch one JSON message from the server
+    async fn handle_text(&mut self, text: &str) {
+        let incoming = self.dispatcher.dispatch(text);
+        if incoming == IncomingMessage::Unknown {
+            debug!(message = %text, "Received unknown message type");
+        }
+        self.handle_incoming(incoming).await;
+    }
+
     /// Handle disconnection with reason (AC4 - Network Resilience)
     ///
     /// If this is a temporary disconnect, attempt automatic reconnection.
@@ -815,7 +1894,6 @@ impl WebSocketClient {
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Remove connection
         self.connection = None;
-        self.connection_state = ConnectionState::Disconnected;
 
         // Check if this is a recoverable disconnection (network-level, not application-level)
         // Application-level reasons like "server_shutdown", "timeout", "auth_failed" are permanent
@@ -826,10 +1904,12 @@ impl WebSocketClient {
 
         if is_temporary {
             warn!(reason = %reason, "Temporary disconnect - attempting reconnection");
+            self.transition(ConnectionEvent::ConnectionLost);
             return self.attempt_reconnect().await;
         }
 
         // Permanent disconnect - return error that triggers UI display
+        self.transition(ConnectionEvent::Closed);
         Err(format!("Connection closed: {}", reason).into())
     }
 
@@ -837,211 +1917,561 @@ impl WebSocketClient {
     pub async fn close_gracefully(
         &mut self,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if let Some(connection) = &mut self.connection {
+        self.transition(ConnectionEvent::Drain);
+        if self.connection.is_some() {
             use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
             let close_frame = CloseFrame {
                 code: CloseCode::Normal,
                 reason: "client_disconnect".into(),
             };
-            connection.send(Message::Close(Some(close_frame))).await?;
+            let result = self.send_frame(Message::Close(Some(close_frame))).await;
+            self.connection = None;
+            self.transition(ConnectionEvent::Closed);
+            result?;
+            return Ok(());
         }
         self.connection = None;
+        self.transition(ConnectionEvent::Closed);
         Ok(())
     }
 
+    /// Log out: revoke this session's resume tokens and close the connection
+    ///
+    /// The next connection signs in again instead of resuming.
+    pub async fn logout(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.resume_token = None;
+        if self.connection.is_some() {
+            self.send_message_internal(r#"{"type":"logout"}"#).await?;
+        }
+        self.close_gracefully().await
+    }
+
+    /// Whether the server issued a token to resume this session with
+    pub fn can_resume(&self) -> bool {
+        self.resume_token.is_some()
+    }
+
     /// Check if client has an active connection
     pub fn is_connected(&self) -> bool {
         self.connection.is_some()
     }
 
+    /// Apply a classified server message to client state
+    async fn handle_incoming(&mut self, incoming: IncomingMessage) {
+        match incoming {
+            IncomingMessage::Lobby(events) => {
+                for event in events {
+                    self.handle_lobby_response(event).await;
+                }
+            }
+            IncomingMessage::Chat(message) => self.receive_chat_message(message).await,
+            IncomingMessage::Sealed(sealed) => {
+                if self
+                    .blocklist
+                    .lock()
+                    .await
+                    .is_blocked(&sealed.sender_public_key)
+                {
+                    debug!("Dropped sealed message from blocked sender");
+                    return;
+                }
+                match open_sealed_message(&sealed, &self.key_state, &self.ratchet_sessions).await {
+                    Ok(message) => self.receive_chat_message(message).await,
+                    Err(e) => {
+                        warn!(sender = %sealed.sender_public_key.chars().take(16).collect::<String>(), error = %e, "Failed to open sealed message");
+                        self.emit(ClientEvent::Error(e.to_string()));
+                    }
+                }
+            }
+            IncomingMessage::Edit(edit) => {
+                if self
+                    .blocklist
+                    .lock()
+                    .await
+                    .is_blocked(&edit.sender_public_key)
+                {
+                    debug!("Dropped edit from blocked sender");
+                    return;
+                }
+                match handle_incoming_edit(&self.message_history, &edit).await {
+                    Ok(true) => {
+                        let edited = self
+                            .message_history
+                            .lock()
+                            .await
+                            .get(edit.message_id)
+                            .cloned();
+                        if let Some(message) = edited {
+                            self.emit(ClientEvent::MessageEdited(message));
+                        }
+                    }
+                    Ok(false) => {
+                        debug!(message_id = %edit.message_id, "Ignored edit of unknown message")
+                    }
+                    Err(reason) => {
+                        audit_event(&self.audit, || AuditEvent::SignatureInvalid {
+                            sender_public_key: edit.sender_public_key.clone(),
+                            reason: reason.clone(),
+                        });
+                        self.emit(ClientEvent::InvalidSignature(reason));
+                    }
+                }
+            }
+            IncomingMessage::Retract(retraction) => {
+                if self
+                    .blocklist
+                    .lock()
+                    .await
+                    .is_blocked(&retraction.sender_public_key)
+                {
+                    debug!("Dropped retraction from blocked sender");
+                    return;
+                }
+                match handle_incoming_retraction(&self.message_history, &retraction).await {
+                    Ok(true) => {
+                        let retracted = self
+                            .message_history
+                            .lock()
+                            .await
+                            .get(retraction.message_id)
+                            .cloned();
+                        if let Some(message) = retracted {
+                            self.emit(ClientEvent::MessageRetracted(message));
+                        }
+                    }
+                    Ok(false) => {
+                        debug!(message_id = %retraction.message_id, "Ignored retraction of unknown message")
+                    }
+                    Err(reason) => {
+                        audit_event(&self.audit, || AuditEvent::SignatureInvalid {
+                            sender_public_key: retraction.sender_public_key.clone(),
+                            reason: reason.clone(),
+                        });
+                        self.emit(ClientEvent::InvalidSignature(reason));
+                    }
+                }
+            }
+            IncomingMessage::Reaction(reaction) => {
+                if self
+                    .blocklist
+                    .lock()
+                    .await
+                    .is_blocked(&reaction.sender_public_key)
+                {
+                    debug!("Dropped reaction from blocked sender");
+                    return;
+                }
+                match handle_incoming_reaction(&self.message_history, &reaction).await {
+                    Ok(true) => {
+                        let reacted = self
+                            .message_history
+                            .lock()
+                            .await
+                            .get(reaction.message_id)
+                            .cloned();
+                        if let Some(message) = reacted {
+                            self.emit(ClientEvent::ReactionsChanged(message));
+                        }
+                    }
+                    Ok(false) => {
+                        debug!(message_id = %reaction.message_id, "Ignored reaction to unknown message")
+                    }
+                    Err(reason) => {
+                        audit_event(&self.audit, || AuditEvent::SignatureInvalid {
+                            sender_public_key: reaction.sender_public_key.clone(),
+                            reason: reason.clone(),
+                        });
+                        self.emit(ClientEvent::InvalidSignature(reason));
+                    }
+                }
+            }
+            IncomingMessage::Attachment(attachment) => {
+                let sender = attachment.sender_public_key().to_string();
+                if self.blocklist.lock().await.is_blocked(&sender) {
+                    debug!("Dropped attachment message from blocked sender");
+                    return;
+                }
+                match handle_incoming_attachment(&self.attachments, &attachment).await {
+                    Ok(outcome) => {
+                        for reply in &outcome.replies {
+                            if let Err(e) = self.send_message_internal(reply).await {
+                                warn!(error = %e, "Failed to send attachment request");
+                                break;
+                            }
+                        }
+                        if let Some(event) = outcome.event {
+                            if let AttachmentEvent::Failed { reason, .. } = &event {
+                                warn!(%reason, "Attachment transfer failed");
+                            }
+                            self.emit(ClientEvent::Attachment(event));
+                        }
+                    }
+                    Err(reason) => {
+                        audit_event(&self.audit, || AuditEvent::SignatureInvalid {
+                            sender_public_key: sender.clone(),
+                            reason: reason.clone(),
+                        });
+                        self.emit(ClientEvent::InvalidSignature(reason));
+                    }
+                }
+            }
+            IncomingMessage::Error(error) => {
+                self.dispatch_incoming_error(error).await;
+            }
+            IncomingMessage::Ack(ack) => {
+                debug!(?ack, "Received acknowledgement");
+            }
+            IncomingMessage::Receipt(receipt) => {
+                debug!(message_id = %receipt.message_id, "Received read receipt");
+                apply_read_receipt(&mut *self.chat_view.lock().await, &receipt);
+                self.emit(ClientEvent::ReadReceipt(receipt));
+            }
+            IncomingMessage::Presence(hint) => {
+                debug!(viewing = hint.viewing, "Received viewing hint");
+                self.emit(ClientEvent::Viewing(hint));
+            }
+            IncomingMessage::LastSeen(reply) => {
+                debug!(online = reply.online, "Received last seen");
+                self.emit(ClientEvent::LastSeen(reply));
+            }
+            IncomingMessage::Room(event) => {
+                debug!(?event, "Received room event");
+                let my_key = {
+                    let state = self.key_state.lock().await;
+                    state.public_key().map(hex::encode).unwrap_or_default()
+                };
+                let room = match &event {
+                    RoomEvent::Message { room, .. } => Some(room.clone()),
+                    RoomEvent::Update { .. } => None,
+                };
+                match handle_room_event(&self.rooms_state, event, &my_key).await {
+                    Some(VerificationResult::Valid(_)) => {
+                        if let Some(room) = room {
+                            self.message_arrived(Conversation::Room(room)).await;
+                        }
+                    }
+                    Some(VerificationResult::Invalid {
+                        sender_public_key,
+                        reason,
+                    }) => {
+                        audit_event(&self.audit, || AuditEvent::SignatureInvalid {
+                            sender_public_key: sender_public_key.clone(),
+                            reason: reason.clone(),
+                        });
+                        self.emit(ClientEvent::InvalidSignature(
+                            create_invalid_signature_notification(&sender_public_key, &reason),
+                        ));
+                    }
+                    None => {}
+                }
+            }
+            IncomingMessage::KeyRollover(rollover) => self.receive_key_rollover(rollover).await,
+            IncomingMessage::Unknown => {}
+        }
+    }
+
+    /// Act on a key rollover: switch to our new key, or carry a peer's
+    /// contact over to theirs
+    async fn receive_key_rollover(&mut self, rollover: KeyRollover) {
+        let my_key = {
+            let state = self.key_state.lock().await;
+            state.public_key().map(hex::encode).unwrap_or_default()
+        };
+        let result = if rollover.old_public_key == my_key {
+            match self.pending_rollover.take() {
+                Some(pending) => {
+                    let result = complete_key_rollover(
+                        pending,
+                        &rollover,
+                        &self.key_state,
+                        &self.ratchet_sessions,
+                    )
+                    .await;
+                    if result.is_ok() {
+                        self.resume_token = None;
+                        self.rejoin_with_new_key = true;
+                    }
+                    result.map(|_| ())
+                }
+                None => {
+                    warn!("Server announced a key rollover this client didn't request");
+                    return;
+                }
+            }
+        } else {
+            apply_key_rollover(
+                &rollover,
+                &self.contacts,
+                &self.blocklist,
+                &self.ratchet_sessions,
+            )
+            .await
+            .map(|_| ())
+        };
+
+        match result {
+            Ok(()) => {
+                info!(
+                    new_key = %rollover.new_public_key.chars().take(16).collect::<String>(),
+                    "Key rolled over"
+                );
+                audit_event(&self.audit, || AuditEvent::KeyRolledOver {
+                    old_public_key: rollover.old_public_key.clone(),
+                    new_public_key: rollover.new_public_key.clone(),
+                });
+                self.emit(ClientEvent::KeyRolledOver {
+                    old_public_key: rollover.old_public_key,
+                    new_public_key: rollover.new_public_key,
+                });
+            }
+            Err(e) => {
+                warn!(error = %e, "Ignoring key rollover");
+                self.emit(ClientEvent::Error(e.to_string()));
+            }
+        }
+    }
+
+    /// Verify and store a direct message, unless its sender is blocked
+    async fn receive_chat_message(&mut self, message: ChatMessage) {
+        if self
+            .blocklist
+            .lock()
+            .await
+            .is_blocked(&message.sender_public_key)
+        {
+            debug!("Dropped chat message from blocked sender");
+            return;
+        }
+        // Handle chat message with verification (Story 3.3 + 3.4)
+        debug!(sender = %message.sender_public_key.chars().take(16).collect::<String>(), "Received chat message - verifying");
+        let emit = |event: ClientEvent| {
+            if let ClientEvent::InvalidSignature(notification) = &event {
+                audit_event(&self.audit, || AuditEvent::SignatureInvalid {
+                    sender_public_key: message.sender_public_key.clone(),
+                    reason: notification.clone(),
+                });
+            }
+            self.emit(event)
+        };
+        if verify_and_store_message(&message, &self.message_history, emit).await {
+            if self.selected_recipient.as_deref() == Some(message.sender_public_key.as_str()) {
+                self.refresh_chat_view().await;
+                if let Err(e) = self.send_read_receipts().await {
+                    warn!(error = %e, "Failed to send read receipts");
+                }
+            }
+            self.message_arrived(Conversation::Direct(message.sender_public_key))
+                .await;
+        }
+    }
+
+    /// Reload the viewed conversation from the message history
+    async fn refresh_chat_view(&self) {
+        let my_key = {
+            let state = self.key_state.lock().await;
+            state.public_key().map(hex::encode).unwrap_or_default()
+        };
+        update_chat_view(
+            &mut *self.chat_view.lock().await,
+            &self.message_history,
+            &my_key,
+        )
+        .await;
+    }
+
+    /// Send read receipts for received messages the chat view shows
+    ///
+    /// Each message is acknowledged once, even if sending its receipt
+    /// fails; receipts are a courtesy and are not resent.
+    async fn send_read_receipts(
+        &mut self,
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let receipts = create_read_receipts_for_view(&mut *self.chat_view.lock().await);
+        for receipt in &receipts {
+            self.send_message_internal(receipt).await?;
+        }
+        Ok(receipts.len())
+    }
+
+    /// Unarchive a conversation a new message arrived in, if configured
+    async fn message_arrived(&self, conversation: Conversation) {
+        if let Err(e) = handle_archive_message(&self.archive, &conversation).await {
+            warn!(error = %e, "Failed to save conversation archive");
+        }
+    }
+
+    /// Apply a lobby event and publish it (Story 2.2)
+    async fn handle_lobby_response(&mut self, lobby_response: LobbyResponse) {
+        debug!(?lobby_response, "Received lobby message");
+
+        // Users that came online may have queued messages (AC4)
+        let joined_keys = match &lobby_response {
+            LobbyResponse::UsersJoined { public_keys } => public_keys.clone(),
+            _ => Vec::new(),
+        };
+
+        match lobby_response {
+            LobbyResponse::LobbyState { users } => {
+                // Update lobby state with initial user list
+                let mut lobby_state = LobbyState::new();
+                lobby_state.set_users(users);
+                self.emit(ClientEvent::LobbyReceived(lobby_state));
+            }
+            LobbyResponse::UsersJoined { public_keys } => {
+                // Users joined - one event each
+                for key in public_keys {
+                    self.emit(ClientEvent::UserJoined(LobbyUser::new(key, true)));
+                }
+            }
+            LobbyResponse::UsersLeft { public_keys } => {
+                // Users left - one event each
+                for key in &public_keys {
+                    self.emit(ClientEvent::UserLeft(key.clone()));
+                }
+
+                // If selected user left, notify (AC5)
+                if let Some(sel_key) = self
+                    .selected_recipient
+                    .take_if(|sel_key| public_keys.contains(sel_key))
+                {
+                    if self.share_last_seen {
+                        let query = create_last_seen_query(&sel_key);
+                        if let Err(e) = self.send_message_internal(&query).await {
+                            warn!(error = %e, "Failed to query last seen");
+                        }
+                    }
+                    self.emit(ClientEvent::SelectionLost(sel_key));
+                }
+            }
+            LobbyResponse::Page(page) => {
+                self.emit(ClientEvent::LobbyPage(page));
+            }
+            LobbyResponse::NicknameChanged {
+                public_key,
+                nickname,
+            } => {
+                self.emit(ClientEvent::NicknameChanged {
+                    public_key,
+                    nickname,
+                });
+            }
+            LobbyResponse::VerifiedNameChanged {
+                public_key,
+                verified_name,
+            } => {
+                self.emit(ClientEvent::VerifiedNameChanged {
+                    public_key,
+                    verified_name,
+                });
+            }
+            LobbyResponse::StatusChanged { public_key, status } => {
+                self.emit(ClientEvent::StatusChanged { public_key, status });
+            }
+            LobbyResponse::ServerRestarted { epoch } => {
+                self.emit(ClientEvent::ServerRestarted(epoch));
+            }
+            LobbyResponse::Ignored => {
+                // Non-lobby message, ignore
+            }
+        }
+
+        for key in joined_keys {
+            self.flush_pending_for(&key).await;
+        }
+    }
+
     /// Run persistent message loop to handle incoming messages and close frames
     /// This should be called after successful authentication to detect disconnections during normal operation
     pub async fn run_message_loop(
         &mut self,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         loop {
+            // The old key's connection is done with once the rollover is
+            // broadcast; sign in again with the new key
+            if std::mem::take(&mut self.rejoin_with_new_key) {
+                use tokio_tungstenite::tungstenite::protocol::{
+                    frame::coding::CloseCode, CloseFrame,
+                };
+                let close_frame = CloseFrame {
+                    code: CloseCode::Normal,
+                    reason: "key_rollover".into(),
+                };
+                let _ = self.send_frame(Message::Close(Some(close_frame))).await;
+                self.connection = None;
+                self.transition(ConnectionEvent::ConnectionLost);
+                return self.attempt_reconnect().await;
+            }
+
             // Check if we have a connection
             if self.connection.is_none() {
                 return Err("No connection available".into());
             }
 
-            // Get next message
-            let msg_result = if let Some(connection) = &mut self.connection {
-                connection.next().await
+            // Get next message, waking up when a health ping, a resend or a
+            // cover frame is due
+            let now = std::time::Instant::now();
+            let wait = self
+                .health
+                .time_until_next(now)
+                .min(self.busy_retries.time_until_next(now))
+                .min(
+                    self.cover
+                        .as_ref()
+                        .map_or(std::time::Duration::MAX, |cover| cover.time_until_next(now)),
+                );
+            let next = if let Some(connection) = &mut self.connection {
+                tokio::select! {
+                    msg = connection.next() => Some(msg),
+                    _ = tokio::time::sleep(wait) => None,
+                }
             } else {
                 return Err("Connection lost unexpectedly".into());
             };
+            let Some(msg_result) = next else {
+                let now = std::time::Instant::now();
+                if let Some(payload) = self.health.on_tick(now) {
+                    self.send_frame(Message::Ping(payload)).await?;
+                }
+                for message in self.busy_retries.take_due(now) {
+                    if let Err(e) = self.deliver(&message).await {
+                        warn!(error = %e, "Failed to resend message refused as busy");
+                    }
+                }
+                let cover_frame = self
+                    .cover
+                    .as_mut()
+                    .and_then(|cover| cover.on_tick(now, &mut rand::thread_rng()));
+                if let Some(frame) = cover_frame {
+                    self.send_message_internal(&frame).await?;
+                }
+                if self.health.health() == ConnectionHealth::Lost {
+                    warn!(
+                        missed_pongs = self.health.missed_pongs(),
+                        "Server stopped answering pings - attempting reconnection"
+                    );
+                    self.connection = None;
+                    self.transition(ConnectionEvent::ConnectionLost);
+                    return self.attempt_reconnect().await;
+                }
+                continue;
+            };
+
+            if let Some(Ok(ref frame)) = msg_result {
+                capture_frame(
+                    self.capture.as_deref(),
+                    Direction::Inbound,
+                    self.connection_count,
+                    frame,
+                );
+            }
 
             // Process message
             match msg_result {
-                Some(Ok(Message::Text(text))) => {
-                    // Try to parse as lobby message first (Story 2.2)
-                    if let Ok(lobby_response) = parse_lobby_message(&text) {
-                        debug!(?lobby_response, "Received lobby message");
-
-                        // Handle lobby responses
-                        if let Some(ref handler) = self.lobby_event_handler {
-                            match lobby_response {
-                                LobbyResponse::LobbyState { users } => {
-                                    // Update lobby state with initial user list
-                                    let mut lobby_state = LobbyState::new();
-                                    lobby_state.set_users(users);
-                                    handler.lobby_received(&lobby_state);
-                                }
-                                LobbyResponse::UsersJoined { public_keys } => {
-                                    // Users joined - notify handler for each
-                                    for key in public_keys {
-                                        handler.user_joined(&LobbyUser::new(key, true));
-                                    }
-                                }
-                                LobbyResponse::UsersLeft { public_keys } => {
-                                    // Check if selected user left (AC5)
-                                    let selected_left = self
-                                        .selected_recipient
-                                        .as_ref()
-                                        .map(|sel_key| public_keys.contains(sel_key))
-                                        .unwrap_or(false);
-
-                                    // Users left - notify handler for each
-                                    for key in &public_keys {
-                                        handler.user_left(key);
-                                    }
-
-                                    // If selected user left, notify (AC5)
-                                    if selected_left {
-                                        if let Some(ref sel_key) = self.selected_recipient {
-                                            handler.selection_lost(sel_key);
-                                        }
-                                        self.selected_recipient = None;
-                                    }
-                                }
-                                LobbyResponse::Ignored => {
-                                    // Non-lobby message, ignore
-                                }
-                            }
-                        }
-                    } else if let Ok(chat_response) = parse_chat_message(&text) {
-                        // Handle chat message with verification (Story 3.3 + 3.4)
-                        match chat_response {
-                            ChatResponse::Message(message) => {
-                                debug!(sender = %message.sender_public_key.chars().take(16).collect::<String>(), "Received chat message - verifying");
-
-                                // Verify and store the message
-                                verify_and_store_message(
-                                    &message,
-                                    &self.message_history,
-                                    &self.message_event_handler,
-                                )
-                                .await;
-                            }
-                            ChatResponse::Ignored => {
-                                // Message was ignored
-                            }
-                        }
-                    } else {
-                        // Try to parse as error or other message
-                        if let Ok(server_msg) = parse_server_message(&text) {
-                            match server_msg {
-                                ServerMessageResponse::Error(error) => {
-                                    warn!(reason = %error.reason, details = %error.details.clone().unwrap_or_default(), "Server error");
-                                    if let Some(ref handler) = self.message_event_handler {
-                                        let details = error.details.unwrap_or_default();
-                                        handler.error(&format!("{}: {}", error.reason, details));
-                                    }
-                                }
-                                ServerMessageResponse::Unknown => {
-                                    debug!(message = %text, "Received unknown message type");
-                                }
-                                _ => {
-                                    // Lobby and chat already handled above
-                                }
-                            }
-                        } else if let Ok(notification) = parse_notification(&text) {
-                            // Handle notification (Story 3.6)
-                            match notification {
-                                NotificationResponse::RecipientOffline {
-                                    recipient_key,
-                                    message,
-                                } => {
-                                    debug!(recipient = %recipient_key.chars().take(16).collect::<String>(), "Recipient is offline");
-
-                                    // Format notification message (AC4 - User Notification)
-                                    let notification_msg = format!(
-                                        "User {} is offline. Message not delivered.",
-                                        &recipient_key[..std::cmp::min(16, recipient_key.len())]
-                                    );
-
-                                    // Queue message for delivery when recipient comes online (AC4)
-                                    if let Some(msg_content) = message {
-                                        let mut pending = self.pending_messages.lock().await;
-                                        const MAX_PENDING_PER_RECIPIENT: usize = 10;
-                                        const MAX_TOTAL_PENDING: usize = 100;
-
-                                        let total_count: usize =
-                                            pending.values().map(|v| v.len()).sum();
-
-                                        if total_count >= MAX_TOTAL_PENDING {
-                                            warn!(
-                                                total = total_count,
-                                                max = MAX_TOTAL_PENDING,
-                                                "Pending messages queue full, dropping message"
-                                            );
-                                        } else {
-                                            pending
-                                                .entry(recipient_key.clone())
-                                                .or_insert_with(Vec::new);
-
-                                            if let Some(msgs) = pending.get_mut(&recipient_key) {
-                                                if msgs.len() >= MAX_PENDING_PER_RECIPIENT {
-                                                    warn!(
-                                                        recipient = %recipient_key,
-                                                        count = msgs.len(),
-                                                        max = MAX_PENDING_PER_RECIPIENT,
-                                                        "Per-recipient queue full, dropping oldest"
-                                                    );
-                                                    msgs.remove(0);
-                                                }
-                                                msgs.push(msg_content.clone());
-                                                debug!(recipient = %recipient_key, message = %msg_content, "Message queued for delivery when recipient comes online");
-                                            }
-                                        }
-                                    }
-
-                                    // Notify recipient_offline_handler (AC4)
-                                    if let Some(ref handler) = self.recipient_offline_handler {
-                                        handler.borrow()(recipient_key.clone());
-                                    }
-
-                                    // Notify message event handler of notification (not invalid signature!)
-                                    if let Some(ref handler) = self.message_event_handler {
-                                        handler.notification(&notification_msg);
-                                    }
-                                }
-                                NotificationResponse::UserBackOnline { public_key } => {
-                                    info!(user = %public_key.chars().take(16).collect::<String>(), "User is back online");
-
-                                    // Send pending messages for this user (AC4 - Deliver queued messages)
-                                    let user_messages: Vec<String> = {
-                                        let mut pending = self.pending_messages.lock().await;
-                                        // Remove and return messages for this recipient
-                                        pending.remove(&public_key).unwrap_or_default()
-                                    };
-
-                                    // Send messages after releasing lock
-                                    for msg in &user_messages {
-                                        if let Err(e) = self.send_message_internal(msg).await {
-                                            warn!(user = %public_key, error = %e, "Failed to send queued message");
-                                        }
-                                    }
-                                    info!(user = %public_key, count = user_messages.len(), "Delivered queued messages");
-                                }
-                                NotificationResponse::Unknown => {
-                                    debug!(message = %text, "Received unknown notification");
-                                }
-                            }
-                        } else {
-                            debug!(message = %text, "Received unparseable message");
-                        }
+                Some(Ok(Message::Text(text))) => self.handle_text(&text).await,
+                Some(Ok(Message::Binary(data))) if self.codec.accepts_binary() => {
+                    match self.codec.decode_binary(&data) {
+                        Ok(text) => self.handle_text(&text).await,
+                        Err(e) => warn!(error = %e, "Dropping undecodable binary frame"),
                     }
                 }
                 Some(Ok(Message::Close(frame))) => {
@@ -1058,21 +2488,28 @@ impl WebSocketClient {
                     // Clean up connection state
                     self.connection = None;
 
-                    // Check if we should attempt reconnection (AC4)
-                    let is_temporary = matches!(
-                        reason.as_str(),
-                        "connection closed"
-                            | "Connection reset by peer"
-                            | "Broken pipe"
-                            | "timeout"
-                    );
+                    // Check if we should attempt reconnection (AC4); a close
+                    // frame with a retry hint, such as a server shutdown,
+                    // asks for one after the hinted delay
+                    let (reason_code, retry_hint) = split_retry_hint(&reason);
+                    self.retry_hint = retry_hint;
+                    let is_temporary = retry_hint.is_some()
+                        || matches!(
+                            reason_code,
+                            "connection closed"
+                                | "Connection reset by peer"
+                                | "Broken pipe"
+                                | "timeout"
+                        );
 
                     if is_temporary {
                         warn!(reason = %reason, "Connection closed (temporary) - attempting reconnection");
+                        self.transition(ConnectionEvent::ConnectionLost);
                         return self.attempt_reconnect().await;
                     }
 
                     // Permanent disconnect - return error with user-friendly message
+                    self.transition(ConnectionEvent::Closed);
                     let final_message =
                         if !user_message.is_empty() && !user_message.contains("Connection lost") {
                             user_message
@@ -1084,12 +2521,16 @@ impl WebSocketClient {
                 }
                 Some(Ok(Message::Ping(data))) => {
                     // Respond to ping with pong
-                    if let Some(conn) = &mut self.connection {
-                        conn.send(Message::Pong(data)).await?;
+                    if self.connection.is_some() {
+                        self.send_frame(Message::Pong(data)).await?;
                     }
                 }
-                Some(Ok(Message::Pong(_))) => {
+                Some(Ok(Message::Pong(payload))) => {
                     // Pong received, connection alive
+                    if let Some(latency) = self.health.on_pong(&payload, std::time::Instant::now())
+                    {
+                        debug!(latency_ms = latency.as_millis() as u64, "Pong received");
+                    }
                 }
                 Some(Ok(_)) => {
                     // Other message types (binary, etc.)
@@ -1098,11 +2539,13 @@ impl WebSocketClient {
                 Some(Err(e)) => {
                     // Connection error (network issue, stream closed)
                     self.connection = None;
+                    self.transition(ConnectionEvent::Closed);
                     return Err(format!("Connection lost: {}", e).into());
                 }
                 None => {
                     // Stream ended without explicit close frame
                     self.connection = None;
+                    self.transition(ConnectionEvent::Closed);
                     return Err("Connection lost. Check your network and try reconnecting.".into());
                 }
             }
@@ -1113,7 +2556,9 @@ impl WebSocketClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::connection::dispatcher::MessageKind;
     use crate::state::session::create_shared_key_state;
+    use profile_shared::canonical::CanonicalVersion;
 
     // Note: Real connection test requires running server
     // Integration test would be: spawn server, connect client, verify handshake
@@ -1125,15 +2570,103 @@ mod tests {
         let result = parse_auth_response(json).unwrap();
 
         match result {
-            AuthResponse::Success { users } => {
+            AuthResponse::Success {
+                users,
+                total,
+                next_cursor,
+                nicknames,
+                names,
+                statuses,
+                status,
+                encoding,
+                compression,
+                resume_token,
+            } => {
+                assert!(nicknames.is_empty());
+                assert!(names.is_empty());
+                assert!(statuses.is_empty());
+                assert_eq!(status, Status::Online);
+                assert_eq!(resume_token, None);
+                assert_eq!(encoding, Encoding::Json);
+                assert_eq!(compression, Compression::None);
                 assert_eq!(users.len(), 2);
                 assert_eq!(users[0], "abc123");
                 assert_eq!(users[1], "def456");
+                assert_eq!(total, 2);
+                assert_eq!(next_cursor, None);
+            }
+            _ => panic!("Expected Success response"),
+        }
+    }
+
+    #[test]
+    fn test_parse_auth_success_with_nicknames() {
+        let json = r#"{"type":"auth_success","users":["abc123","def456"],"nicknames":{"abc123":"alice"},"names":{"def456":"Bob"}}"#;
+
+        match parse_auth_response(json).unwrap() {
+            AuthResponse::Success {
+                nicknames, names, ..
+            } => {
+                assert_eq!(nicknames.len(), 1);
+                assert_eq!(nicknames.get("abc123").map(String::as_str), Some("alice"));
+                assert_eq!(names.len(), 1);
+                assert_eq!(names.get("def456").map(String::as_str), Some("Bob"));
+            }
+            _ => panic!("Expected Success response"),
+        }
+    }
+
+    #[test]
+    fn test_parse_auth_success_with_statuses() {
+        let json = r#"{"type":"auth_success","users":["abc123"],"statuses":{"abc123":"busy"},"status":"invisible"}"#;
+
+        match parse_auth_response(json).unwrap() {
+            AuthResponse::Success {
+                statuses, status, ..
+            } => {
+                assert_eq!(statuses.get("abc123"), Some(&Status::Busy));
+                assert_eq!(status, Status::Invisible);
+            }
+            _ => panic!("Expected Success response"),
+        }
+    }
+
+    #[test]
+    fn test_parse_auth_success_with_resume_token() {
+        let token = "ab".repeat(32);
+        let json = format!(
+            r#"{{"type":"auth_success","users":[],"resumeToken":"{}"}}"#,
+            token
+        );
+        match parse_auth_response(&json).unwrap() {
+            AuthResponse::Success { resume_token, .. } => {
+                assert_eq!(resume_token, Some(token));
             }
             _ => panic!("Expected Success response"),
         }
     }
 
+    #[test]
+    fn test_parse_auth_success_with_encoding() {
+        let json = r#"{"type":"auth_success","users":[],"encoding":"msgpack"}"#;
+        match parse_auth_response(json).unwrap() {
+            AuthResponse::Success { encoding, .. } => {
+                assert_eq!(encoding, Encoding::MessagePack);
+            }
+            _ => panic!("Expected Success response"),
+        }
+
+        // An encoding this client doesn't know is never used
+        let json = r#"{"type":"auth_success","users":[],"encoding":"cbor"}"#;
+        assert!(matches!(
+            parse_auth_response(json).unwrap(),
+            AuthResponse::Success {
+                encoding: Encoding::Json,
+                ..
+            }
+        ));
+    }
+
     #[test]
     fn test_parse_auth_error_response() {
         let json =
@@ -1211,6 +2744,28 @@ mod tests {
         assert!(client.connection.is_none());
     }
 
+    #[tokio::test]
+    async fn test_connection_state_ignores_events_without_connection() {
+        let key_state = create_shared_key_state();
+        let mut client = WebSocketClient::new(key_state);
+        let transitions = Rc::new(RefCell::new(Vec::new()));
+        let transitions_clone = transitions.clone();
+        client.set_connection_transition_handler(move |t| {
+            transitions_clone.borrow_mut().push(t.clone());
+        });
+
+        assert_eq!(client.connection_state(), ConnectionState::Disconnected);
+
+        // Closing or losing a connection that never existed is not a transition
+        let _ = client
+            .handle_disconnection("server_shutdown".to_string())
+            .await;
+        let _ = client.close_gracefully().await;
+
+        assert_eq!(client.connection_state(), ConnectionState::Disconnected);
+        assert!(transitions.borrow().is_empty());
+    }
+
     #[tokio::test]
     async fn test_graceful_shutdown_sends_close_frame() {
         let key_state = create_shared_key_state();
@@ -1380,6 +2935,24 @@ mod tests {
         handler.selection_lost("key");
     }
 
+    #[test]
+    fn test_message_event_handler_read_receipt_callback() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let received_clone = received.clone();
+        let handler = MessageEventHandler::new().with_read_receipt_callback(move |receipt| {
+            received_clone.borrow_mut().push(receipt.message_id);
+        });
+
+        let message_id = uuid::Uuid::new_v4();
+        handler.read_receipt(&ReadReceipt {
+            message_id,
+            reader_public_key: "reader".to_string(),
+            timestamp: "2025-12-27T10:00:00Z".to_string(),
+        });
+
+        assert_eq!(*received.borrow(), vec![message_id]);
+    }
+
     #[tokio::test]
     async fn test_client_selected_recipient_tracking() {
         use crate::state::session::create_shared_key_state;
@@ -1398,4 +2971,383 @@ mod tests {
         client.set_selected_recipient(None);
         assert!(client.selected_recipient().is_none());
     }
+
+    #[tokio::test]
+    async fn test_received_text_is_verified_stored_and_reported() {
+        use crate::state::session::create_shared_key_state;
+        use profile_shared::{derive_public_key, generate_private_key, sign_message};
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let received_clone = received.clone();
+        let rejected = Rc::new(RefCell::new(Vec::new()));
+        let rejected_clone = rejected.clone();
+        client.set_message_event_handler(MessageEventHandler::with_callbacks(
+            move |message| received_clone.borrow_mut().push(message),
+            move |notification| rejected_clone.borrow_mut().push(notification),
+            |_| {},
+            |_| {},
+        ));
+
+        let private_key = generate_private_key().unwrap();
+        let sender = hex::encode(derive_public_key(&private_key).unwrap());
+        let timestamp = "2025-12-27T10:30:00Z";
+        let signature = sign_message(
+            &private_key,
+            &profile_shared::canonical::message(CanonicalVersion::CURRENT, "hi", timestamp),
+        )
+        .unwrap();
+        let message_id = uuid::Uuid::new_v4();
+        let mut text = profile_shared::Message::new_text(
+            message_id,
+            "hi".to_string(),
+            sender.clone(),
+            hex::encode(signature),
+            timestamp.to_string(),
+            CanonicalVersion::CURRENT,
+        );
+        if let profile_shared::Message::Text {
+            server_received_at, ..
+        } = &mut text
+        {
+            *server_received_at = Some("2025-12-27T10:30:01Z".to_string());
+        }
+        let json = serde_json::to_string(&text).unwrap();
+        client.handle_text(&json).await;
+
+        let stored = client.message_history().lock().await.messages_cloned();
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].is_verified);
+        assert_eq!(stored[0].message_id, message_id);
+        assert_eq!(stored[0].sender_public_key, sender);
+        assert_eq!(
+            stored[0].server_received_at.as_deref(),
+            Some("2025-12-27T10:30:01Z")
+        );
+        assert_eq!(*received.borrow(), stored);
+
+        // A resend is not stored or reported twice
+        client.handle_text(&json).await;
+        assert_eq!(received.borrow().len(), 1);
+
+        // A tampered message is rejected
+        let tampered = json.replace("\"hi\"", "\"bye\"");
+        client.handle_text(&tampered).await;
+        assert_eq!(client.message_history().lock().await.len(), 1);
+        assert_eq!(rejected.borrow().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_viewed_conversation_tracks_read_and_delivery_state() {
+        use crate::state::session::create_shared_key_state;
+        use profile_shared::{derive_public_key, generate_private_key, sign_message};
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        let receipts = Rc::new(RefCell::new(Vec::new()));
+        let receipts_clone = receipts.clone();
+        client.set_message_event_handler(
+            MessageEventHandler::new().with_read_receipt_callback(move |receipt| {
+                receipts_clone.borrow_mut().push(receipt)
+            }),
+        );
+
+        let private_key = generate_private_key().unwrap();
+        let peer = hex::encode(derive_public_key(&private_key).unwrap());
+        assert_eq!(
+            client.view_conversation(Some(peer.clone())).await.unwrap(),
+            0
+        );
+
+        // A message arriving in the open conversation is shown and
+        // acknowledged, even though the receipt can't be sent offline
+        let timestamp = "2025-12-27T10:30:00Z";
+        let signature = sign_message(
+            &private_key,
+            &profile_shared::canonical::message(CanonicalVersion::CURRENT, "hi", timestamp),
+        )
+        .unwrap();
+        let text = profile_shared::Message::new_text(
+            uuid::Uuid::new_v4(),
+            "hi".to_string(),
+            peer.clone(),
+            hex::encode(signature),
+            timestamp.to_string(),
+            CanonicalVersion::CURRENT,
+        );
+        client
+            .handle_text(&serde_json::to_string(&text).unwrap())
+            .await;
+        let chat_view = client.chat_view();
+        assert_eq!(chat_view.lock().await.message_count(), 1);
+        assert!(create_read_receipts_for_view(&mut *chat_view.lock().await).is_empty());
+
+        // Our own message's delivery state and read receipt reach the view
+        let sent_id = uuid::Uuid::new_v4();
+        let message = format!(
+            r#"{{"type":"message","messageId":"{}","recipientPublicKey":"{}","message":"hi"}}"#,
+            sent_id, peer
+        );
+        assert!(client.send_message(message).await.is_err());
+        assert_eq!(
+            chat_view.lock().await.send_state(&sent_id.to_string()),
+            Some(SendState::Queued)
+        );
+
+        let read = profile_shared::Message::new_read(sent_id, peer, timestamp.to_string());
+        client
+            .handle_text(&serde_json::to_string(&read).unwrap())
+            .await;
+        assert!(chat_view.lock().await.is_read(&sent_id.to_string()));
+        assert_eq!(receipts.borrow().len(), 1);
+        assert_eq!(receipts.borrow()[0].message_id, sent_id);
+    }
+
+    #[tokio::test]
+    async fn test_events_published_on_bus_and_to_callbacks() {
+        use crate::state::session::create_shared_key_state;
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        let mut events = client.subscribe();
+        let left = Rc::new(RefCell::new(Vec::new()));
+        let left_clone = left.clone();
+        client.set_lobby_event_handler(LobbyEventHandler::with_callbacks(
+            |_| {},
+            |_| {},
+            move |key| left_clone.borrow_mut().push(key),
+            |_| {},
+        ));
+        client.set_selected_recipient(Some("bob".to_string()));
+
+        client.transition(ConnectionEvent::Connect);
+        let joined =
+            profile_shared::Message::new_lobby_joined(vec![profile_shared::LobbyUser::new(
+                "bob".to_string(),
+                None,
+            )]);
+        client
+            .handle_text(&serde_json::to_string(&joined).unwrap())
+            .await;
+        let gone = profile_shared::Message::new_lobby_left(vec!["bob".to_string()]);
+        client
+            .handle_text(&serde_json::to_string(&gone).unwrap())
+            .await;
+
+        assert!(matches!(
+            events.try_recv(),
+            Some(ClientEvent::Connection(ConnectionTransition {
+                event: ConnectionEvent::Connect,
+                ..
+            }))
+        ));
+        assert!(
+            matches!(events.try_recv(), Some(ClientEvent::UserJoined(user)) if user.public_key == "bob")
+        );
+        assert!(matches!(events.try_recv(), Some(ClientEvent::UserLeft(key)) if key == "bob"));
+        assert!(matches!(events.try_recv(), Some(ClientEvent::SelectionLost(key)) if key == "bob"));
+        assert!(events.try_recv().is_none());
+        assert!(client.selected_recipient().is_none());
+        // Registered callbacks see the same events
+        assert_eq!(*left.borrow(), vec!["bob".to_string()]);
+
+        // A bus set later is used from then on
+        let bus = EventBus::new();
+        let mut ui = bus.subscribe();
+        client.set_event_bus(bus);
+        client
+            .handle_text(&serde_json::to_string(&joined).unwrap())
+            .await;
+        assert!(matches!(ui.try_recv(), Some(ClientEvent::UserJoined(_))));
+        assert!(events.try_recv().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_offline_error_queues_in_flight_message() {
+        use crate::state::session::create_shared_key_state;
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        let offline = Rc::new(RefCell::new(Vec::new()));
+        let offline_clone = offline.clone();
+        client.set_recipient_offline_handler(move |key| offline_clone.borrow_mut().push(key));
+        let notifications = Rc::new(RefCell::new(Vec::new()));
+        let notifications_clone = notifications.clone();
+        client.set_message_event_handler(MessageEventHandler::with_callbacks(
+            |_| {},
+            |_| {},
+            |_| {},
+            move |text| notifications_clone.borrow_mut().push(text),
+        ));
+
+        client.track_outgoing(
+            r#"{"type":"message","recipientPublicKey":"bob","message":"hi","signature":"00","timestamp":"2025-12-27T10:00:00Z"}"#,
+        );
+        let json =
+            serde_json::to_string(&profile_shared::Message::new_recipient_offline("bob")).unwrap();
+        let error = crate::handlers::errors::parse_incoming_error(&json).unwrap();
+        client.dispatch_incoming_error(error).await;
+
+        assert_eq!(client.pending_message_count().await, 1);
+        assert_eq!(*offline.borrow(), vec!["bob".to_string()]);
+        assert_eq!(notifications.borrow().len(), 1);
+        assert!(notifications.borrow()[0].contains("offline"));
+    }
+
+    #[tokio::test]
+    async fn test_flush_pending_clears_queue_for_user() {
+        use crate::state::session::create_shared_key_state;
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        let states = Rc::new(RefCell::new(Vec::new()));
+        let states_clone = states.clone();
+        client.set_message_event_handler(
+            MessageEventHandler::new()
+                .with_send_state_callback(move |_, state| states_clone.borrow_mut().push(state)),
+        );
+        client.track_outgoing(r#"{"type":"message","recipientPublicKey":"bob","message":"hi"}"#);
+        client
+            .dispatch_incoming_error(IncomingError::RecipientOffline(
+                crate::handlers::offline::create_offline_notification("bob", None),
+            ))
+            .await;
+        assert_eq!(client.pending_message_count().await, 1);
+
+        // Not connected, so the resend fails and the message stays queued
+        // for the next reconnect instead of being dropped
+        client.flush_pending_for("bob").await;
+        assert_eq!(client.pending_message_count().await, 1);
+        assert_eq!(
+            *states.borrow(),
+            vec![SendState::Queued, SendState::Sending, SendState::Queued]
+        );
+        assert_eq!(
+            client.outbox().lock().await.take_queued_for("alice"),
+            vec![]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_while_disconnected_queues_message() {
+        use crate::state::session::create_shared_key_state;
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        let message =
+            r#"{"type":"message","messageId":"m1","recipientPublicKey":"bob","message":"hi"}"#;
+        assert!(client.send_message(message.to_string()).await.is_err());
+        assert_eq!(
+            client.outbox().lock().await.state("m1"),
+            Some(SendState::Queued)
+        );
+
+        assert!(client
+            .send_message(r#"{"type":"room_join","room":"general"}"#.to_string())
+            .await
+            .is_err());
+        assert_eq!(client.pending_message_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_server_busy_schedules_resend_then_gives_up() {
+        use crate::state::session::create_shared_key_state;
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let errors_clone = errors.clone();
+        client.set_message_event_handler(MessageEventHandler::with_callbacks(
+            |_| {},
+            |_| {},
+            move |text| errors_clone.borrow_mut().push(text),
+            |_| {},
+        ));
+        client.track_outgoing(r#"{"type":"message","recipientPublicKey":"bob","message":"hi"}"#);
+
+        let busy = serde_json::to_string(&profile_shared::Message::new_server_busy(
+            "bob",
+            std::time::Duration::from_millis(500),
+        ))
+        .unwrap();
+        let max_attempts = crate::connection::retry::RetryPolicy::default().max_attempts;
+        for _ in 0..max_attempts {
+            let incoming = client.dispatcher.dispatch(&busy);
+            client.handle_incoming(incoming).await;
+        }
+        assert_eq!(client.busy_retries.len(), max_attempts as usize);
+        assert!(errors.borrow().is_empty());
+
+        let incoming = client.dispatcher.dispatch(&busy);
+        client.handle_incoming(incoming).await;
+        assert_eq!(errors.borrow().len(), 1);
+        assert!(errors.borrow()[0].contains("busy"));
+    }
+
+    #[test]
+    fn test_reconnect_delay_uses_retry_hint_over_backoff() {
+        use std::time::Duration;
+
+        assert_eq!(reconnect_delay(1000, 0, None), Duration::from_secs(1));
+        assert_eq!(reconnect_delay(1000, 3, None), Duration::from_secs(8));
+        // The server's hint replaces the backoff, shorter or longer
+        let hint = Some(Duration::from_millis(2750));
+        assert_eq!(reconnect_delay(1000, 0, hint), Duration::from_millis(2750));
+        assert_eq!(reconnect_delay(1000, 4, hint), Duration::from_millis(2750));
+    }
+
+    #[tokio::test]
+    async fn test_dispatched_lobby_join_flushes_pending() {
+        use crate::state::session::create_shared_key_state;
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        client.track_outgoing(r#"{"type":"message","recipientPublicKey":"bob","message":"hi"}"#);
+        let offline =
+            serde_json::to_string(&profile_shared::Message::new_recipient_offline("bob")).unwrap();
+        let incoming = client.dispatcher.dispatch(&offline);
+        client.handle_incoming(incoming).await;
+        assert_eq!(client.pending_message_count().await, 1);
+
+        let joined = serde_json::to_string(&profile_shared::Message::new_lobby_joined(vec![
+            profile_shared::LobbyUser::new("bob".to_string(), None),
+        ]))
+        .unwrap();
+        let incoming = client.dispatcher.dispatch(&joined);
+        client.handle_incoming(incoming).await;
+
+        // The resend was attempted; without a connection it is queued again
+        let queued = client.outbox().lock().await.states();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].1, SendState::Queued);
+        assert_eq!(client.dispatch_metrics().count(MessageKind::Error), 1);
+        assert_eq!(client.dispatch_metrics().count(MessageKind::Lobby), 1);
+    }
+
+    #[test]
+    fn test_track_outgoing_ignores_non_chat_messages() {
+        use crate::state::session::create_shared_key_state;
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        client.track_outgoing(r#"{"type":"room_join","room":"general"}"#);
+        client.track_outgoing("not json");
+        assert!(client.in_flight_messages.is_empty());
+    }
+
+    #[test]
+    fn test_track_outgoing_announces_sent_message() {
+        use crate::state::session::create_shared_key_state;
+
+        let mut client = WebSocketClient::new(create_shared_key_state());
+        let mut events = client.subscribe();
+        client.track_outgoing(r#"{"type":"room_join","room":"general"}"#);
+        client.track_outgoing(
+            r#"{"type":"message","messageId":"m1","recipientPublicKey":"bob","message":"hi"}"#,
+        );
+
+        match events.try_recv() {
+            Some(ClientEvent::MessageSent {
+                message_id,
+                recipient_public_key,
+            }) => {
+                assert_eq!(message_id, "m1");
+                assert_eq!(recipient_public_key, "bob");
+            }
+            other => panic!("Expected MessageSent, got {:?}", other),
+        }
+        assert!(events.try_recv().is_none());
+    }
 }
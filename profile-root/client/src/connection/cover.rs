@@ -0,0 +1,303 @@
+//! Cover traffic
+//!
+//! An opt-in mode that hides when the user is active. While signed in, the
+//! client sends `cover` frames at random intervals between
+//! [`CoverPolicy::min_interval`] and [`CoverPolicy::max_interval`], and the
+//! server drops them unread. Each frame carries random bytes of a random
+//! message length, padded to the padding buckets (see
+//! `profile_shared::crypto::padding`), so its size looks like a real
+//! payload's. Frames that would take more than
+//! [`CoverPolicy::max_bytes_per_minute`] in the current minute are skipped.
+//!
+//! Cover traffic uses data and keeps the device awake even when the user
+//! does nothing; [`CoverPolicy::warning`] says how much, and the client
+//! shows it when the mode first starts.
+//!
+//! Like the health monitor, [`CoverTraffic`] takes the current time and a
+//! random number generator as arguments, so it can be driven
+//! deterministically in tests.
+
+use profile_shared::config::cover::{
+    DEFAULT_MAX_BYTES_PER_MINUTE, DEFAULT_MAX_INTERVAL, DEFAULT_MIN_INTERVAL, MIN_INTERVAL,
+};
+use profile_shared::config::message::MAX_MESSAGE_LENGTH;
+use profile_shared::crypto::padding::PaddingPolicy;
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// Value of the `type` field of a cover frame
+pub const COVER_TYPE: &str = "cover";
+
+/// Period the bandwidth cap applies to
+const CAP_WINDOW: Duration = Duration::from_secs(60);
+
+/// When cover frames are sent and how much data they may use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverPolicy {
+    /// Shortest wait between frames
+    pub min_interval: Duration,
+    /// Longest wait between frames
+    pub max_interval: Duration,
+    /// Most bytes of cover frames sent per minute
+    pub max_bytes_per_minute: usize,
+    /// Buckets frame sizes are padded to
+    pub padding: PaddingPolicy,
+}
+
+impl Default for CoverPolicy {
+    fn default() -> Self {
+        Self {
+            min_interval: DEFAULT_MIN_INTERVAL,
+            max_interval: DEFAULT_MAX_INTERVAL,
+            max_bytes_per_minute: DEFAULT_MAX_BYTES_PER_MINUTE,
+            padding: PaddingPolicy::default(),
+        }
+    }
+}
+
+impl CoverPolicy {
+    /// Check the intervals and cap are usable
+    ///
+    /// # Errors
+    /// Returns a description of the problem if the shortest interval is
+    /// below [`MIN_INTERVAL`], the longest is below the shortest, or the
+    /// cap is zero
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_interval < MIN_INTERVAL {
+            return Err(format!(
+                "intervals must be at least {} s",
+                MIN_INTERVAL.as_secs()
+            ));
+        }
+        if self.max_interval < self.min_interval {
+            return Err("the longest interval is shorter than the shortest".to_string());
+        }
+        if self.max_bytes_per_minute == 0 {
+            return Err("the bandwidth cap must be above zero".to_string());
+        }
+        Ok(())
+    }
+
+    /// What the mode costs, to tell the user before it starts
+    pub fn warning(&self) -> String {
+        let kb_per_minute = self.max_bytes_per_minute.div_ceil(1024);
+        format!(
+            "Cover traffic is on: dummy messages are sent every {}-{} s while connected, \
+             using up to {} KB of data per minute (about {} MB a day) and keeping the \
+             device awake, which drains the battery faster.",
+            self.min_interval.as_secs(),
+            self.max_interval.as_secs(),
+            kb_per_minute,
+            (kb_per_minute * 60 * 24).div_ceil(1024)
+        )
+    }
+}
+
+/// Cover frames sent and skipped so far
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoverStats {
+    /// Frames sent
+    pub frames: u64,
+    /// Their total size in bytes
+    pub bytes: u64,
+    /// Frames skipped because of the bandwidth cap
+    pub skipped: u64,
+}
+
+/// Schedules cover frames for one client
+#[derive(Debug, Clone)]
+pub struct CoverTraffic {
+    policy: CoverPolicy,
+    next_at: Option<Instant>,
+    window_start: Option<Instant>,
+    window_bytes: usize,
+    warned: bool,
+    stats: CoverStats,
+}
+
+impl CoverTraffic {
+    /// Schedule frames by `policy`, starting at the first `reset`
+    pub fn new(policy: CoverPolicy) -> Self {
+        Self {
+            policy,
+            next_at: None,
+            window_start: None,
+            window_bytes: 0,
+            warned: false,
+            stats: CoverStats::default(),
+        }
+    }
+
+    /// The policy frames are scheduled by
+    pub fn policy(&self) -> &CoverPolicy {
+        &self.policy
+    }
+
+    /// Frames sent and skipped so far
+    pub fn stats(&self) -> CoverStats {
+        self.stats
+    }
+
+    /// The cost warning, the first time it is asked for
+    pub fn take_warning(&mut self) -> Option<String> {
+        if self.warned {
+            return None;
+        }
+        self.warned = true;
+        Some(self.policy.warning())
+    }
+
+    /// Start on a fresh connection; the first frame is due a random
+    /// interval from `now`
+    pub fn reset(&mut self, now: Instant, rng: &mut impl Rng) {
+        self.next_at = Some(now + self.random_interval(rng));
+    }
+
+    /// Time until `on_tick` has work to do (Duration::MAX before `reset`)
+    pub fn time_until_next(&self, now: Instant) -> Duration {
+        match self.next_at {
+            Some(next_at) => next_at.saturating_duration_since(now),
+            None => Duration::MAX,
+        }
+    }
+
+    /// Schedule the next frame if one is due
+    ///
+    /// # Returns
+    /// The frame to send, unless none is due or it would go over the
+    /// bandwidth cap
+    pub fn on_tick(&mut self, now: Instant, rng: &mut impl Rng) -> Option<String> {
+        if now < self.next_at? {
+            return None;
+        }
+        self.next_at = Some(now + self.random_interval(rng));
+
+        if self
+            .window_start
+            .is_none_or(|start| now.duration_since(start) >= CAP_WINDOW)
+        {
+            self.window_start = Some(now);
+            self.window_bytes = 0;
+        }
+        let frame = cover_frame(&self.policy.padding, rng);
+        if self.window_bytes + frame.len() > self.policy.max_bytes_per_minute {
+            self.stats.skipped += 1;
+            return None;
+        }
+        self.window_bytes += frame.len();
+        self.stats.frames += 1;
+        self.stats.bytes += frame.len() as u64;
+        Some(frame)
+    }
+
+    fn random_interval(&self, rng: &mut impl Rng) -> Duration {
+        let min = self.policy.min_interval;
+        let max = self.policy.max_interval.max(min);
+        rng.gen_range(min..=max)
+    }
+}
+
+/// A cover frame as long as a random message would be after padding
+fn cover_frame(padding: &PaddingPolicy, rng: &mut impl Rng) -> String {
+    let len = padding.padded_len(rng.gen_range(0..=MAX_MESSAGE_LENGTH));
+    let mut bytes = vec![0u8; len];
+    rng.fill(bytes.as_mut_slice());
+    serde_json::json!({
+        "type": COVER_TYPE,
+        "padding": hex::encode(bytes),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn policy(max_bytes_per_minute: usize) -> CoverPolicy {
+        CoverPolicy {
+            min_interval: Duration::from_secs(5),
+            max_interval: Duration::from_secs(10),
+            max_bytes_per_minute,
+            padding: PaddingPolicy::new(&[64, 256]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_frames_sent_at_random_intervals_and_padded() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut cover = CoverTraffic::new(policy(usize::MAX));
+        let start = Instant::now();
+        assert_eq!(cover.time_until_next(start), Duration::MAX);
+        assert!(cover.on_tick(start, &mut rng).is_none());
+
+        cover.reset(start, &mut rng);
+        let mut now = start;
+        for _ in 0..20 {
+            let wait = cover.time_until_next(now);
+            assert!((Duration::from_secs(5)..=Duration::from_secs(10)).contains(&wait));
+            assert!(cover.on_tick(now, &mut rng).is_none());
+            now += wait;
+            let frame: serde_json::Value =
+                serde_json::from_str(&cover.on_tick(now, &mut rng).unwrap()).unwrap();
+            assert_eq!(frame["type"], "cover");
+            // Hex of a multiple of the largest bucket
+            let padding = frame["padding"].as_str().unwrap();
+            assert_eq!(padding.len() % (2 * 256), 0);
+        }
+        assert_eq!(cover.stats().frames, 20);
+        assert_eq!(cover.stats().skipped, 0);
+    }
+
+    #[test]
+    fn test_bandwidth_cap_skips_frames_until_next_minute() {
+        let mut rng = StdRng::seed_from_u64(1);
+        // Room for one frame of the longest message per minute
+        let mut cover = CoverTraffic::new(policy(10 * 1024));
+        let start = Instant::now();
+        cover.reset(start, &mut rng);
+
+        let mut now = start;
+        let mut sent_this_minute = 0;
+        while now < start + CAP_WINDOW - Duration::from_secs(10) {
+            now += cover.time_until_next(now);
+            if cover.on_tick(now, &mut rng).is_some() {
+                sent_this_minute += 1;
+            }
+        }
+        let stats = cover.stats();
+        assert!(stats.bytes <= 10 * 1024, "sent {} bytes", stats.bytes);
+        assert!(stats.skipped > 0);
+        assert_eq!(stats.frames, sent_this_minute);
+
+        // A new minute starts a new allowance
+        now = start + CAP_WINDOW + Duration::from_secs(1);
+        cover.reset(now, &mut rng);
+        now += cover.time_until_next(now);
+        assert!(cover.on_tick(now, &mut rng).is_some());
+    }
+
+    #[test]
+    fn test_policy_validated_and_warned_once() {
+        assert!(CoverPolicy::default().validate().is_ok());
+        let too_fast = CoverPolicy {
+            min_interval: Duration::from_millis(100),
+            ..CoverPolicy::default()
+        };
+        assert!(too_fast.validate().is_err());
+        let inverted = CoverPolicy {
+            max_interval: Duration::from_secs(1),
+            ..CoverPolicy::default()
+        };
+        assert!(inverted.validate().is_err());
+        assert!(policy(0).validate().is_err());
+
+        let mut cover = CoverTraffic::new(CoverPolicy::default());
+        let warning = cover.take_warning().unwrap();
+        assert!(warning.contains("5-30 s"));
+        assert!(warning.contains("16 KB of data per minute"));
+        assert!(warning.contains("battery"));
+        assert!(cover.take_warning().is_none());
+    }
+}
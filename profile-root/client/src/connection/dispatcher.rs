@@ -0,0 +1,872 @@
+//! Typed dispatch of incoming server messages
+//!
+//! Every text frame is deserialized once into [`profile_shared::Message`] and
+//! classified as an [`IncomingMessage`]. Frames that still use the legacy
+//! `{"type": ...}` shape (lobby snapshots, auth acknowledgements, auth errors)
+//! fall back to the matching parser. Handlers registered for a
+//! [`MessageKind`] run in registration order, and every dispatch is counted
+//! in [`DispatchMetrics`].
+
+use crate::connection::client::{
+    parse_auth_response, parse_chat_message, parse_lobby_message, AuthResponse, ChatResponse,
+    LobbyResponse,
+};
+use crate::handlers::attachments::{attachment_from_message, IncomingAttachment};
+use crate::handlers::edit::{edit_from_message, IncomingEdit};
+use crate::handlers::errors::{parse_incoming_error, IncomingError};
+use crate::handlers::offline::offline_notification_from_message;
+use crate::handlers::presence::{
+    last_seen_from_message, viewing_hint_from_message, LastSeenReply, ViewingHint,
+};
+use crate::handlers::reactions::{reaction_from_message, IncomingReaction};
+use crate::handlers::receipts::{read_receipt_from_message, ReadReceipt};
+use crate::handlers::retract::{retraction_from_message, IncomingRetraction};
+use crate::handlers::rooms::{room_event_from_message, RoomEvent};
+use crate::handlers::sealed::{sealed_message_from_message, SealedMessage};
+use crate::state::messages::ChatMessage;
+use crate::ui::lobby_state::{LobbyPage, LobbyUser};
+use profile_shared::crypto::rollover::KeyRollover;
+use profile_shared::protocol::Status;
+use profile_shared::Message;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Category of an incoming message, used for handler routing and metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    /// Lobby snapshot, join/leave delta or requested page
+    Lobby,
+    /// Direct chat message
+    Chat,
+    /// Error reported by the server
+    Error,
+    /// Acknowledgement of a client request (auth success)
+    Ack,
+    /// Read receipt for a message we sent
+    Receipt,
+    /// Room membership update or room message
+    Room,
+    /// Peer opened or closed this user's conversation
+    Presence,
+    /// Anything the client does not understand
+    Unknown,
+}
+
+impl MessageKind {
+    /// All message kinds, in display order
+    pub const ALL: [MessageKind; 8] = [
+        MessageKind::Lobby,
+        MessageKind::Chat,
+        MessageKind::Error,
+        MessageKind::Ack,
+        MessageKind::Receipt,
+        MessageKind::Room,
+        MessageKind::Presence,
+        MessageKind::Unknown,
+    ];
+
+    /// Stable name for logs and metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageKind::Lobby => "lobby",
+            MessageKind::Chat => "chat",
+            MessageKind::Error => "error",
+            MessageKind::Ack => "ack",
+            MessageKind::Receipt => "receipt",
+            MessageKind::Room => "room",
+            MessageKind::Presence => "presence",
+            MessageKind::Unknown => "unknown",
+        }
+    }
+}
+
+/// A classified incoming message
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncomingMessage {
+    /// Lobby events, in the order they should be applied
+    Lobby(Vec<LobbyResponse>),
+    /// Unverified chat message (the client verifies before storing)
+    Chat(ChatMessage),
+    /// Chat message sealed in a ratchet session, still to be opened
+    Sealed(SealedMessage),
+    /// New text for an earlier chat message, still to be verified
+    Edit(IncomingEdit),
+    /// Withdrawal of an earlier chat message, still to be verified
+    Retract(IncomingRetraction),
+    /// Reaction to a chat message, or its removal, still to be verified
+    Reaction(IncomingReaction),
+    /// File offer, accept, chunk or completion from a peer, still to be checked
+    Attachment(IncomingAttachment),
+    /// Server error, including offline-recipient notifications
+    Error(IncomingError),
+    /// Acknowledgement from the server
+    Ack(AuthResponse),
+    /// Read receipt from a recipient
+    Receipt(ReadReceipt),
+    /// Room event
+    Room(RoomEvent),
+    /// Viewing hint from a peer
+    Presence(ViewingHint),
+    /// When a contact was last online, answering our query
+    LastSeen(LastSeenReply),
+    /// A user replaced their identity key, still to be verified
+    KeyRollover(KeyRollover),
+    /// Unrecognized frame
+    Unknown,
+}
+
+impl IncomingMessage {
+    /// The kind this message is routed under
+    pub fn kind(&self) -> MessageKind {
+        match self {
+            IncomingMessage::Lobby(_) | IncomingMessage::KeyRollover(_) => MessageKind::Lobby,
+            IncomingMessage::Chat(_)
+            | IncomingMessage::Sealed(_)
+            | IncomingMessage::Edit(_)
+            | IncomingMessage::Retract(_)
+            | IncomingMessage::Reaction(_)
+            | IncomingMessage::Attachment(_) => MessageKind::Chat,
+            IncomingMessage::Error(_) => MessageKind::Error,
+            IncomingMessage::Ack(_) => MessageKind::Ack,
+            IncomingMessage::Receipt(_) => MessageKind::Receipt,
+            IncomingMessage::Room(_) => MessageKind::Room,
+            IncomingMessage::Presence(_) | IncomingMessage::LastSeen(_) => MessageKind::Presence,
+            IncomingMessage::Unknown => MessageKind::Unknown,
+        }
+    }
+}
+
+/// Message type discriminator for legacy frames
+#[derive(Debug, Deserialize)]
+struct TypedMessage {
+    r#type: String,
+}
+
+/// Classify a text frame from the server
+///
+/// # Arguments
+/// * `text` - Raw JSON text frame
+///
+/// # Returns
+/// The classified message, or `IncomingMessage::Unknown` if it matches no known shape
+pub fn classify_message(text: &str) -> IncomingMessage {
+    match serde_json::from_str::<Message>(text) {
+        Ok(message) => classify_shared(message),
+        Err(_) => classify_legacy(text),
+    }
+}
+
+/// Classify a message already deserialized as the shared protocol type
+fn classify_shared(message: Message) -> IncomingMessage {
+    if let Some(notification) = offline_notification_from_message(&message) {
+        return IncomingMessage::Error(IncomingError::RecipientOffline(notification));
+    }
+    if let Some((recipient, retry_after)) = message.server_busy_retry() {
+        return IncomingMessage::Error(IncomingError::ServerBusy {
+            recipient: recipient.to_string(),
+            retry_after,
+        });
+    }
+
+    match message {
+        Message::Text {
+            message_id,
+            message,
+            sender_public_key,
+            signature,
+            timestamp,
+            canonical,
+            sequence,
+            server_received_at,
+        } => IncomingMessage::Chat(
+            ChatMessage::new(sender_public_key, message, signature, timestamp)
+                .with_message_id(message_id)
+                .with_canonical(canonical)
+                .with_sequence(sequence)
+                .with_server_received_at(server_received_at),
+        ),
+        Message::LobbyUpdate { joined, left } => {
+            // Apply departures first so a reconnecting user ends up present
+            let mut events = Vec::new();
+            if !left.is_empty() {
+                events.push(LobbyResponse::UsersLeft { public_keys: left });
+            }
+            if !joined.is_empty() {
+                events.push(LobbyResponse::UsersJoined {
+                    public_keys: joined.iter().map(|u| u.public_key.clone()).collect(),
+                });
+            }
+            // Joined users who already picked a nickname, claimed a name or
+            // chose a status keep them
+            for u in joined {
+                if let Some(status) = u
+                    .status
+                    .filter(|s| *s != Status::Online && s.is_selectable())
+                {
+                    events.push(LobbyResponse::StatusChanged {
+                        public_key: u.public_key.clone(),
+                        status,
+                    });
+                }
+                if let Some(nickname) = u.nickname {
+                    events.push(LobbyResponse::NicknameChanged {
+                        public_key: u.public_key.clone(),
+                        nickname: Some(nickname),
+                    });
+                }
+                if let Some(name) = u.name {
+                    events.push(LobbyResponse::VerifiedNameChanged {
+                        public_key: u.public_key,
+                        verified_name: Some(name),
+                    });
+                }
+            }
+            IncomingMessage::Lobby(events)
+        }
+        Message::LobbyPage {
+            users,
+            next_cursor,
+            total,
+        } => IncomingMessage::Lobby(vec![LobbyResponse::Page(LobbyPage {
+            users: users
+                .into_iter()
+                .map(|u| {
+                    let status = u.status.filter(|s| s.is_selectable()).unwrap_or_default();
+                    LobbyUser::new(u.public_key, true)
+                        .with_nickname(u.nickname)
+                        .with_verified_name(u.name)
+                        .with_status(status)
+                })
+                .collect(),
+            next_cursor,
+            total,
+        })]),
+        Message::Nickname {
+            public_key,
+            nickname,
+        } => IncomingMessage::Lobby(vec![LobbyResponse::NicknameChanged {
+            public_key,
+            nickname,
+        }]),
+        Message::NameClaimed { public_key, name } => {
+            IncomingMessage::Lobby(vec![LobbyResponse::VerifiedNameChanged {
+                public_key,
+                verified_name: name,
+            }])
+        }
+        Message::Error {
+            reason, details, ..
+        } => IncomingMessage::Error(IncomingError::Server { reason, details }),
+        message @ Message::Read { .. } => read_receipt_from_message(message)
+            .map(IncomingMessage::Receipt)
+            .unwrap_or(IncomingMessage::Unknown),
+        message @ Message::Sealed { .. } => sealed_message_from_message(message)
+            .map(IncomingMessage::Sealed)
+            .unwrap_or(IncomingMessage::Unknown),
+        message @ Message::Edit { .. } => edit_from_message(message)
+            .map(IncomingMessage::Edit)
+            .unwrap_or(IncomingMessage::Unknown),
+        message @ Message::Retract { .. } => retraction_from_message(message)
+            .map(IncomingMessage::Retract)
+            .unwrap_or(IncomingMessage::Unknown),
+        message @ Message::Reaction { .. } => reaction_from_message(message)
+            .map(IncomingMessage::Reaction)
+            .unwrap_or(IncomingMessage::Unknown),
+        message @ (Message::AttachmentOffer { .. }
+        | Message::AttachmentAccept { .. }
+        | Message::AttachmentChunk { .. }
+        | Message::AttachmentComplete { .. }) => attachment_from_message(message)
+            .map(IncomingMessage::Attachment)
+            .unwrap_or(IncomingMessage::Unknown),
+        message @ Message::Viewing { .. } => viewing_hint_from_message(message)
+            .map(IncomingMessage::Presence)
+            .unwrap_or(IncomingMessage::Unknown),
+        message @ Message::LastSeen { .. } => last_seen_from_message(message)
+            .map(IncomingMessage::LastSeen)
+            .unwrap_or(IncomingMessage::Unknown),
+        message @ (Message::RoomMessage { .. } | Message::RoomUpdate { .. }) => {
+            room_event_from_message(message)
+                .map(IncomingMessage::Room)
+                .unwrap_or(IncomingMessage::Unknown)
+        }
+        Message::KeyRollover { rollover } => IncomingMessage::KeyRollover(rollover),
+        Message::ServerRestarted { epoch, .. } => {
+            IncomingMessage::Lobby(vec![LobbyResponse::ServerRestarted { epoch }])
+        }
+        // The client doesn't request server backups, set sender filters or
+        // show announcements yet
+        Message::Auth { .. }
+        | Message::Backup { .. }
+        | Message::BackupStored { .. }
+        | Message::SenderFiltersSet { .. }
+        | Message::Announcement { .. }
+        | Message::Close => IncomingMessage::Unknown,
+    }
+}
+
+/// Classify a frame using the legacy `type` discriminator
+fn classify_legacy(text: &str) -> IncomingMessage {
+    let Ok(typed) = serde_json::from_str::<TypedMessage>(text) else {
+        return IncomingMessage::Unknown;
+    };
+
+    match typed.r#type.as_str() {
+        "lobby" | "lobby_update" => match parse_lobby_message(text) {
+            Ok(LobbyResponse::Ignored) | Err(_) => IncomingMessage::Lobby(Vec::new()),
+            Ok(response) => IncomingMessage::Lobby(vec![response]),
+        },
+        "message" => match parse_chat_message(text) {
+            Ok(ChatResponse::Message(message)) => IncomingMessage::Chat(*message),
+            _ => IncomingMessage::Unknown,
+        },
+        "error" => parse_incoming_error(text)
+            .map(IncomingMessage::Error)
+            .unwrap_or(IncomingMessage::Unknown),
+        "auth_success" => parse_auth_response(text)
+            .map(IncomingMessage::Ack)
+            .unwrap_or(IncomingMessage::Unknown),
+        _ => IncomingMessage::Unknown,
+    }
+}
+
+/// Per-kind counters for dispatched messages
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DispatchMetrics {
+    counts: HashMap<MessageKind, u64>,
+}
+
+impl DispatchMetrics {
+    /// Record one dispatched message of the given kind
+    pub fn record(&mut self, kind: MessageKind) {
+        *self.counts.entry(kind).or_insert(0) += 1;
+    }
+
+    /// Number of messages dispatched for a kind
+    pub fn count(&self, kind: MessageKind) -> u64 {
+        self.counts.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// Total number of messages dispatched
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// Reset all counters
+    pub fn reset(&mut self) {
+        self.counts.clear();
+    }
+}
+
+/// Type alias for a registered incoming message handler
+type IncomingHandler = Rc<RefCell<dyn Fn(&IncomingMessage)>>;
+
+/// Routes classified server messages to registered handlers
+#[derive(Clone, Default)]
+pub struct MessageDispatcher {
+    handlers: HashMap<MessageKind, Vec<IncomingHandler>>,
+    metrics: DispatchMetrics,
+}
+
+impl MessageDispatcher {
+    /// Create a dispatcher with no registered handlers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler for one kind of message
+    ///
+    /// Handlers for the same kind are called in registration order.
+    pub fn register(&mut self, kind: MessageKind, handler: impl Fn(&IncomingMessage) + 'static) {
+        self.handlers
+            .entry(kind)
+            .or_default()
+            .push(Rc::new(RefCell::new(handler)));
+    }
+
+    /// Number of handlers registered for a kind
+    pub fn handler_count(&self, kind: MessageKind) -> usize {
+        self.handlers.get(&kind).map(Vec::len).unwrap_or(0)
+    }
+
+    /// Classify a text frame, record it and call the handlers for its kind
+    ///
+    /// # Returns
+    /// The classified message, so the caller can apply its own state changes
+    pub fn dispatch(&mut self, text: &str) -> IncomingMessage {
+        let incoming = classify_message(text);
+        let kind = incoming.kind();
+        self.metrics.record(kind);
+
+        if let Some(handlers) = self.handlers.get(&kind) {
+            for handler in handlers {
+                handler.borrow()(&incoming);
+            }
+        }
+
+        incoming
+    }
+
+    /// Counters for dispatched messages
+    pub fn metrics(&self) -> &DispatchMetrics {
+        &self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profile_shared::canonical::CanonicalVersion;
+
+    #[test]
+    fn test_classify_shared_text_as_chat() {
+        let json = serde_json::to_string(&Message::new_text(
+            uuid::Uuid::new_v4(),
+            "hello".to_string(),
+            "sender".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+            CanonicalVersion::CURRENT,
+        ))
+        .unwrap();
+
+        match classify_message(&json) {
+            IncomingMessage::Chat(message) => {
+                assert_eq!(message.message, "hello");
+                assert_eq!(message.sender_public_key, "sender");
+            }
+            other => panic!("Expected Chat, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_classify_sealed_as_chat() {
+        let envelope = profile_shared::crypto::ratchet::RatchetEnvelope {
+            session: "aa".repeat(32),
+            ratchet_key: "bb".repeat(32),
+            previous_count: 0,
+            index: 0,
+            nonce: "cc".repeat(24),
+            ciphertext: "dd".repeat(16),
+        };
+        let json = serde_json::to_string(&Message::new_sealed(
+            uuid::Uuid::new_v4(),
+            "abcd".to_string(),
+            envelope.clone(),
+        ))
+        .unwrap();
+
+        let incoming = classify_message(&json);
+        assert_eq!(incoming.kind(), MessageKind::Chat);
+        match incoming {
+            IncomingMessage::Sealed(sealed) => {
+                assert_eq!(sealed.sender_public_key, "abcd");
+                assert_eq!(sealed.envelope, envelope);
+            }
+            other => panic!("Expected Sealed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_classify_edit_as_chat() {
+        let message_id = uuid::Uuid::new_v4();
+        let json = serde_json::to_string(&Message::new_edit(
+            message_id,
+            "Fixed".to_string(),
+            "abcd".to_string(),
+            "ef01".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        ))
+        .unwrap();
+
+        let incoming = classify_message(&json);
+        assert_eq!(incoming.kind(), MessageKind::Chat);
+        match incoming {
+            IncomingMessage::Edit(edit) => {
+                assert_eq!(edit.message_id, message_id);
+                assert_eq!(edit.sender_public_key, "abcd");
+                assert_eq!(edit.edit.message, "Fixed");
+            }
+            other => panic!("Expected Edit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_classify_retract_as_chat() {
+        let message_id = uuid::Uuid::new_v4();
+        let json = serde_json::to_string(&Message::new_retract(
+            message_id,
+            "abcd".to_string(),
+            "ef01".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        ))
+        .unwrap();
+
+        let incoming = classify_message(&json);
+        assert_eq!(incoming.kind(), MessageKind::Chat);
+        match incoming {
+            IncomingMessage::Retract(retraction) => {
+                assert_eq!(retraction.message_id, message_id);
+                assert_eq!(retraction.sender_public_key, "abcd");
+            }
+            other => panic!("Expected Retract, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_classify_reaction_as_chat() {
+        let message_id = uuid::Uuid::new_v4();
+        let json = serde_json::to_string(&Message::new_reaction(
+            message_id,
+            "👍".to_string(),
+            true,
+            "abcd".to_string(),
+            "ef01".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        ))
+        .unwrap();
+
+        let incoming = classify_message(&json);
+        assert_eq!(incoming.kind(), MessageKind::Chat);
+        match incoming {
+            IncomingMessage::Reaction(reaction) => {
+                assert_eq!(reaction.message_id, message_id);
+                assert_eq!(reaction.emoji, "👍");
+                assert!(reaction.removed);
+            }
+            other => panic!("Expected Reaction, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_classify_attachment_chunk_as_chat() {
+        let transfer_id = uuid::Uuid::new_v4();
+        let json = serde_json::to_string(&Message::new_attachment_chunk(
+            transfer_id,
+            3,
+            "00ff".to_string(),
+            "abcd".to_string(),
+        ))
+        .unwrap();
+
+        let incoming = classify_message(&json);
+        assert_eq!(incoming.kind(), MessageKind::Chat);
+        assert_eq!(
+            incoming,
+            IncomingMessage::Attachment(IncomingAttachment::Chunk {
+                transfer_id,
+                index: 3,
+                data: "00ff".to_string(),
+                sender_public_key: "abcd".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_classify_key_rollover_as_lobby() {
+        let rollover = KeyRollover::sign(
+            &profile_shared::generate_private_key().unwrap(),
+            &profile_shared::generate_private_key().unwrap(),
+            "2026-01-05T09:00:00Z",
+        )
+        .unwrap();
+        let json = serde_json::to_string(&Message::new_key_rollover(rollover.clone())).unwrap();
+
+        let incoming = classify_message(&json);
+        assert_eq!(incoming.kind(), MessageKind::Lobby);
+        assert_eq!(incoming, IncomingMessage::KeyRollover(rollover));
+    }
+
+    #[test]
+    fn test_classify_shared_lobby_update_applies_left_before_joined() {
+        let json = serde_json::to_string(&Message::LobbyUpdate {
+            joined: vec![profile_shared::LobbyUser::new("alice".to_string(), None)],
+            left: vec!["alice".to_string()],
+        })
+        .unwrap();
+
+        assert_eq!(
+            classify_message(&json),
+            IncomingMessage::Lobby(vec![
+                LobbyResponse::UsersLeft {
+                    public_keys: vec!["alice".to_string()]
+                },
+                LobbyResponse::UsersJoined {
+                    public_keys: vec!["alice".to_string()]
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_classify_lobby_page() {
+        let json = serde_json::to_string(&Message::new_lobby_page(
+            vec![
+                profile_shared::LobbyUser::new("alice".to_string(), Some("Alice".to_string())),
+                profile_shared::LobbyUser::new("bob".to_string(), None),
+            ],
+            Some("bob".to_string()),
+            3,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            classify_message(&json),
+            IncomingMessage::Lobby(vec![LobbyResponse::Page(LobbyPage {
+                users: vec![
+                    LobbyUser::new("alice".to_string(), true)
+                        .with_nickname(Some("Alice".to_string())),
+                    LobbyUser::new("bob".to_string(), true),
+                ],
+                next_cursor: Some("bob".to_string()),
+                total: 3,
+            })])
+        );
+    }
+
+    #[test]
+    fn test_classify_nickname_changes() {
+        let json = serde_json::to_string(&Message::new_nickname(
+            "alice".to_string(),
+            Some("Alice".to_string()),
+        ))
+        .unwrap();
+        assert_eq!(
+            classify_message(&json),
+            IncomingMessage::Lobby(vec![LobbyResponse::NicknameChanged {
+                public_key: "alice".to_string(),
+                nickname: Some("Alice".to_string()),
+            }])
+        );
+
+        // A user rejoining with a nickname gets it back after the join
+        let json = serde_json::to_string(&Message::new_lobby_joined(vec![
+            profile_shared::LobbyUser::new("alice".to_string(), Some("Alice".to_string())),
+            profile_shared::LobbyUser::new("bob".to_string(), None),
+        ]))
+        .unwrap();
+        assert_eq!(
+            classify_message(&json),
+            IncomingMessage::Lobby(vec![
+                LobbyResponse::UsersJoined {
+                    public_keys: vec!["alice".to_string(), "bob".to_string()]
+                },
+                LobbyResponse::NicknameChanged {
+                    public_key: "alice".to_string(),
+                    nickname: Some("Alice".to_string()),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_classify_status_changes() {
+        // A user re-announced with a status gets it after the join
+        let json = serde_json::to_string(&Message::new_lobby_joined(vec![
+            profile_shared::LobbyUser::new("alice".to_string(), None).with_status(Status::Busy),
+            profile_shared::LobbyUser::new("bob".to_string(), None),
+        ]))
+        .unwrap();
+        assert_eq!(
+            classify_message(&json),
+            IncomingMessage::Lobby(vec![
+                LobbyResponse::UsersJoined {
+                    public_keys: vec!["alice".to_string(), "bob".to_string()]
+                },
+                LobbyResponse::StatusChanged {
+                    public_key: "alice".to_string(),
+                    status: Status::Busy,
+                },
+            ])
+        );
+
+        let away =
+            profile_shared::LobbyUser::new("carol".to_string(), None).with_status(Status::Away);
+        let json = serde_json::to_string(&Message::new_lobby_page(vec![away], None, 1)).unwrap();
+        let IncomingMessage::Lobby(events) = classify_message(&json) else {
+            panic!("Expected a lobby page");
+        };
+        let [LobbyResponse::Page(page)] = events.as_slice() else {
+            panic!("Expected a lobby page");
+        };
+        assert_eq!(page.users[0].status, Status::Away);
+    }
+
+    #[test]
+    fn test_classify_name_claims() {
+        let json = serde_json::to_string(&Message::new_name_claimed(
+            "alice".to_string(),
+            Some("Alice".to_string()),
+        ))
+        .unwrap();
+        assert_eq!(
+            classify_message(&json),
+            IncomingMessage::Lobby(vec![LobbyResponse::VerifiedNameChanged {
+                public_key: "alice".to_string(),
+                verified_name: Some("Alice".to_string()),
+            }])
+        );
+
+        // A claimed name is carried on joins and lobby pages
+        let alice = profile_shared::LobbyUser::new("alice".to_string(), Some("ally".to_string()))
+            .with_name(Some("Alice".to_string()));
+        let json = serde_json::to_string(&Message::new_lobby_joined(vec![alice.clone()])).unwrap();
+        assert_eq!(
+            classify_message(&json),
+            IncomingMessage::Lobby(vec![
+                LobbyResponse::UsersJoined {
+                    public_keys: vec!["alice".to_string()]
+                },
+                LobbyResponse::NicknameChanged {
+                    public_key: "alice".to_string(),
+                    nickname: Some("ally".to_string()),
+                },
+                LobbyResponse::VerifiedNameChanged {
+                    public_key: "alice".to_string(),
+                    verified_name: Some("Alice".to_string()),
+                },
+            ])
+        );
+
+        let json = serde_json::to_string(&Message::new_lobby_page(vec![alice], None, 1)).unwrap();
+        let IncomingMessage::Lobby(events) = classify_message(&json) else {
+            panic!("Expected a lobby page");
+        };
+        let [LobbyResponse::Page(page)] = events.as_slice() else {
+            panic!("Expected a lobby page");
+        };
+        assert_eq!(page.users[0].display_name(), "Alice");
+    }
+
+    #[test]
+    fn test_classify_server_restarted() {
+        let json = serde_json::to_string(&Message::new_server_restarted(
+            3,
+            "2025-12-27T10:00:00+00:00".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(
+            classify_message(&json),
+            IncomingMessage::Lobby(vec![LobbyResponse::ServerRestarted { epoch: 3 }])
+        );
+    }
+
+    #[test]
+    fn test_classify_legacy_lobby_update() {
+        let json = r#"{"type":"lobby_update","joined":[{"publicKey":"bob"}],"left":[]}"#;
+        assert_eq!(
+            classify_message(json),
+            IncomingMessage::Lobby(vec![LobbyResponse::UsersJoined {
+                public_keys: vec!["bob".to_string()]
+            }])
+        );
+    }
+
+    #[test]
+    fn test_classify_errors() {
+        let offline = serde_json::to_string(&Message::new_recipient_offline("bob")).unwrap();
+        assert!(matches!(
+            classify_message(&offline),
+            IncomingMessage::Error(IncomingError::RecipientOffline(_))
+        ));
+
+        let legacy = r#"{"type":"error","reason":"auth_failed","details":"bad signature"}"#;
+        assert_eq!(
+            classify_message(legacy),
+            IncomingMessage::Error(IncomingError::Server {
+                reason: "auth_failed".to_string(),
+                details: Some("bad signature".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_classify_receipt_room_and_ack() {
+        let receipt = serde_json::to_string(&Message::new_read(
+            uuid::Uuid::new_v4(),
+            "reader".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        ))
+        .unwrap();
+        assert_eq!(classify_message(&receipt).kind(), MessageKind::Receipt);
+
+        let room = serde_json::to_string(&Message::new_room_update(
+            "general".to_string(),
+            vec!["alice".to_string()],
+        ))
+        .unwrap();
+        assert_eq!(classify_message(&room).kind(), MessageKind::Room);
+
+        let viewing =
+            serde_json::to_string(&Message::new_viewing("alice".to_string(), true)).unwrap();
+        assert_eq!(classify_message(&viewing).kind(), MessageKind::Presence);
+        let last_seen =
+            serde_json::to_string(&Message::new_last_seen("ab".to_string(), true, None)).unwrap();
+        assert_eq!(classify_message(&last_seen).kind(), MessageKind::Presence);
+
+        let ack = r#"{"type":"auth_success","users":["alice"]}"#;
+        assert_eq!(
+            classify_message(ack),
+            IncomingMessage::Ack(AuthResponse::Success {
+                users: vec!["alice".to_string()],
+                total: 1,
+                next_cursor: None,
+                nicknames: HashMap::new(),
+                names: HashMap::new(),
+                statuses: HashMap::new(),
+                status: Status::Online,
+                encoding: profile_shared::protocol::Encoding::Json,
+                compression: profile_shared::protocol::Compression::None,
+                resume_token: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_classify_unknown() {
+        assert_eq!(classify_message("not json"), IncomingMessage::Unknown);
+        assert_eq!(
+            classify_message(r#"{"type":"mystery"}"#),
+            IncomingMessage::Unknown
+        );
+    }
+
+    #[test]
+    fn test_dispatch_routes_to_registered_handlers_only() {
+        let mut dispatcher = MessageDispatcher::new();
+        let errors = Rc::new(RefCell::new(0));
+        let errors_clone = errors.clone();
+        dispatcher.register(MessageKind::Error, move |_| *errors_clone.borrow_mut() += 1);
+        let lobby = Rc::new(RefCell::new(0));
+        let lobby_clone = lobby.clone();
+        dispatcher.register(MessageKind::Lobby, move |_| *lobby_clone.borrow_mut() += 1);
+
+        let offline = serde_json::to_string(&Message::new_recipient_offline("bob")).unwrap();
+        dispatcher.dispatch(&offline);
+        dispatcher.dispatch("not json");
+
+        assert_eq!(*errors.borrow(), 1);
+        assert_eq!(*lobby.borrow(), 0);
+        assert_eq!(dispatcher.handler_count(MessageKind::Error), 1);
+        assert_eq!(dispatcher.handler_count(MessageKind::Chat), 0);
+    }
+
+    #[test]
+    fn test_dispatch_metrics_count_per_kind() {
+        let mut dispatcher = MessageDispatcher::new();
+        let offline = serde_json::to_string(&Message::new_recipient_offline("bob")).unwrap();
+
+        dispatcher.dispatch(&offline);
+        dispatcher.dispatch(&offline);
+        dispatcher.dispatch(r#"{"type":"lobby_update","joined":[],"left":["bob"]}"#);
+        dispatcher.dispatch("garbage");
+
+        let metrics = dispatcher.metrics();
+        assert_eq!(metrics.count(MessageKind::Error), 2);
+        assert_eq!(metrics.count(MessageKind::Lobby), 1);
+        assert_eq!(metrics.count(MessageKind::Unknown), 1);
+        assert_eq!(metrics.count(MessageKind::Chat), 0);
+        assert_eq!(metrics.total(), 4);
+    }
+}
@@ -0,0 +1,350 @@
+//! Connection health monitoring
+//!
+//! The client pings the server every `ping_interval` and measures how long
+//! the matching pong takes. A pong that doesn't arrive within
+//! `pong_timeout` counts as missed. The resulting [`ConnectionHealth`] is:
+//! - `Good`: the last pong arrived and latency is below `degraded_latency`
+//! - `Degraded`: latency is high or a pong was missed
+//! - `Lost`: `lost_after_missed` pongs in a row were missed
+//!
+//! [`HealthMonitor`] takes the current time as an argument instead of
+//! reading a clock, so it can be driven deterministically in tests.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Connection quality as shown to the user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionHealth {
+    /// Pongs arrive promptly
+    Good,
+    /// Pongs are slow or a pong was missed
+    Degraded,
+    /// Too many pongs in a row were missed
+    Lost,
+}
+
+impl ConnectionHealth {
+    /// Short status text for the UI
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConnectionHealth::Good => "Good",
+            ConnectionHealth::Degraded => "Degraded",
+            ConnectionHealth::Lost => "Lost",
+        }
+    }
+}
+
+/// Timing thresholds for the health monitor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthConfig {
+    /// Time between pings
+    pub ping_interval: Duration,
+    /// Time to wait for a pong before counting it as missed
+    pub pong_timeout: Duration,
+    /// Round-trip latency above which the connection is Degraded
+    pub degraded_latency: Duration,
+    /// Consecutive missed pongs after which the connection is Lost
+    pub lost_after_missed: u32,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: profile_shared::config::connection::PING_INTERVAL,
+            pong_timeout: profile_shared::config::connection::PONG_TIMEOUT,
+            degraded_latency: Duration::from_millis(500),
+            lost_after_missed: 3,
+        }
+    }
+}
+
+/// Snapshot of connection health passed to the health callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthReport {
+    pub health: ConnectionHealth,
+    /// Round-trip time of the most recent pong
+    pub latency: Option<Duration>,
+    /// Pongs missed since the last one that arrived
+    pub missed_pongs: u32,
+}
+
+/// Type alias for the health callback
+type HealthCallback = Rc<RefCell<dyn Fn(&HealthReport)>>;
+
+/// Tracks pings, pongs and the resulting connection health
+#[derive(Clone)]
+pub struct HealthMonitor {
+    config: HealthConfig,
+    next_ping_at: Option<Instant>,
+    /// Payload and send time of the ping awaiting a pong
+    outstanding: Option<(u64, Instant)>,
+    next_sequence: u64,
+    latency: Option<Duration>,
+    missed_pongs: u32,
+    on_report: Option<HealthCallback>,
+}
+
+impl HealthMonitor {
+    /// Create a monitor with the given thresholds
+    pub fn new(config: HealthConfig) -> Self {
+        Self {
+            config,
+            next_ping_at: None,
+            outstanding: None,
+            next_sequence: 0,
+            latency: None,
+            missed_pongs: 0,
+            on_report: None,
+        }
+    }
+
+    /// Call `callback` after every pong and every missed pong
+    pub fn with_health_callback(mut self, callback: impl Fn(&HealthReport) + 'static) -> Self {
+        self.on_report = Some(Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Replace the health callback
+    pub fn set_health_callback(&mut self, callback: impl Fn(&HealthReport) + 'static) {
+        self.on_report = Some(Rc::new(RefCell::new(callback)));
+    }
+
+    /// Current connection health
+    pub fn health(&self) -> ConnectionHealth {
+        if self.missed_pongs >= self.config.lost_after_missed {
+            ConnectionHealth::Lost
+        } else if self.missed_pongs > 0
+            || self
+                .latency
+                .is_some_and(|latency| latency > self.config.degraded_latency)
+        {
+            ConnectionHealth::Degraded
+        } else {
+            ConnectionHealth::Good
+        }
+    }
+
+    /// Round-trip time of the most recent pong
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// Pongs missed since the last one that arrived
+    pub fn missed_pongs(&self) -> u32 {
+        self.missed_pongs
+    }
+
+    /// Current health, latency and missed pong count
+    pub fn report(&self) -> HealthReport {
+        HealthReport {
+            health: self.health(),
+            latency: self.latency,
+            missed_pongs: self.missed_pongs,
+        }
+    }
+
+    /// Start monitoring a fresh connection; the first ping is due one
+    /// interval from `now`
+    pub fn reset(&mut self, now: Instant) {
+        self.next_ping_at = Some(now + self.config.ping_interval);
+        self.outstanding = None;
+        self.latency = None;
+        self.missed_pongs = 0;
+    }
+
+    /// Time until `on_tick` has work to do (Duration::MAX before `reset`)
+    pub fn time_until_next(&self, now: Instant) -> Duration {
+        let due = match (self.outstanding, self.next_ping_at) {
+            (Some((_, sent_at)), _) => sent_at + self.config.pong_timeout,
+            (None, Some(next_ping_at)) => next_ping_at,
+            (None, None) => return Duration::MAX,
+        };
+        due.saturating_duration_since(now)
+    }
+
+    /// Advance timers: count an overdue pong as missed and start the next
+    /// ping if one is due
+    ///
+    /// # Returns
+    /// The payload of a ping to send, if one is due
+    pub fn on_tick(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if let Some((_, sent_at)) = self.outstanding {
+            if now < sent_at + self.config.pong_timeout {
+                return None;
+            }
+            self.outstanding = None;
+            self.missed_pongs = self.missed_pongs.saturating_add(1);
+            self.notify();
+        }
+
+        let next_ping_at = self.next_ping_at?;
+        if now < next_ping_at {
+            return None;
+        }
+
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.outstanding = Some((sequence, now));
+        self.next_ping_at = Some(now + self.config.ping_interval);
+        Some(sequence.to_be_bytes().to_vec())
+    }
+
+    /// Record a pong from the server
+    ///
+    /// Pongs that don't answer the outstanding ping (late or unsolicited)
+    /// are ignored.
+    ///
+    /// # Returns
+    /// The measured round-trip time, if the pong matched
+    pub fn on_pong(&mut self, payload: &[u8], now: Instant) -> Option<Duration> {
+        let (sequence, sent_at) = self.outstanding?;
+        if payload != sequence.to_be_bytes() {
+            return None;
+        }
+
+        let latency = now.saturating_duration_since(sent_at);
+        self.outstanding = None;
+        self.latency = Some(latency);
+        self.missed_pongs = 0;
+        self.notify();
+        Some(latency)
+    }
+
+    fn notify(&self) {
+        if let Some(ref callback) = self.on_report {
+            callback.borrow()(&self.report());
+        }
+    }
+}
+
+impl Default for HealthMonitor {
+    fn default() -> Self {
+        Self::new(HealthConfig::default())
+    }
+}
+
+impl std::fmt::Debug for HealthMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HealthMonitor")
+            .field("config", &self.config)
+            .field("report", &self.report())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> HealthConfig {
+        HealthConfig {
+            ping_interval: Duration::from_secs(10),
+            pong_timeout: Duration::from_secs(5),
+            degraded_latency: Duration::from_millis(500),
+            lost_after_missed: 2,
+        }
+    }
+
+    #[test]
+    fn test_no_pings_before_reset() {
+        let mut monitor = HealthMonitor::new(config());
+        let now = Instant::now();
+        assert_eq!(monitor.time_until_next(now), Duration::MAX);
+        assert!(monitor.on_tick(now + Duration::from_secs(60)).is_none());
+    }
+
+    #[test]
+    fn test_ping_pong_measures_latency() {
+        let mut monitor = HealthMonitor::new(config());
+        let start = Instant::now();
+        monitor.reset(start);
+
+        assert!(monitor.on_tick(start + Duration::from_secs(9)).is_none());
+        let sent_at = start + Duration::from_secs(10);
+        let payload = monitor.on_tick(sent_at).expect("ping due");
+
+        let latency = monitor.on_pong(&payload, sent_at + Duration::from_millis(40));
+        assert_eq!(latency, Some(Duration::from_millis(40)));
+        assert_eq!(monitor.health(), ConnectionHealth::Good);
+        assert_eq!(
+            monitor.time_until_next(sent_at + Duration::from_millis(40)),
+            Duration::from_millis(9960)
+        );
+    }
+
+    #[test]
+    fn test_slow_pong_is_degraded() {
+        let mut monitor = HealthMonitor::new(config());
+        let start = Instant::now();
+        monitor.reset(start);
+        let sent_at = start + Duration::from_secs(10);
+        let payload = monitor.on_tick(sent_at).unwrap();
+
+        monitor.on_pong(&payload, sent_at + Duration::from_secs(2));
+        assert_eq!(monitor.health(), ConnectionHealth::Degraded);
+    }
+
+    #[test]
+    fn test_missed_pongs_lead_to_lost_and_pong_recovers() {
+        let mut monitor = HealthMonitor::new(config());
+        let start = Instant::now();
+        monitor.reset(start);
+
+        let first = start + Duration::from_secs(10);
+        monitor.on_tick(first).unwrap();
+        // Pong overdue: counted as missed, next ping not yet due
+        assert!(monitor.on_tick(first + Duration::from_secs(5)).is_none());
+        assert_eq!(monitor.health(), ConnectionHealth::Degraded);
+
+        let second = first + Duration::from_secs(10);
+        let payload = monitor.on_tick(second).unwrap();
+        monitor.on_tick(second + Duration::from_secs(5));
+        assert_eq!(monitor.missed_pongs(), 2);
+        assert_eq!(monitor.health(), ConnectionHealth::Lost);
+
+        // A late pong for an expired ping doesn't count
+        assert!(monitor
+            .on_pong(&payload, second + Duration::from_secs(6))
+            .is_none());
+
+        let third = second + Duration::from_secs(10);
+        let payload = monitor.on_tick(third).unwrap();
+        monitor.on_pong(&payload, third + Duration::from_millis(10));
+        assert_eq!(monitor.health(), ConnectionHealth::Good);
+    }
+
+    #[test]
+    fn test_callback_receives_reports() {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let reports_clone = reports.clone();
+        let mut monitor = HealthMonitor::new(config())
+            .with_health_callback(move |report| reports_clone.borrow_mut().push(*report));
+        let start = Instant::now();
+        monitor.reset(start);
+
+        let sent_at = start + Duration::from_secs(10);
+        let payload = monitor.on_tick(sent_at).unwrap();
+        monitor.on_pong(&payload, sent_at + Duration::from_millis(20));
+        let sent_at = sent_at + Duration::from_secs(10);
+        monitor.on_tick(sent_at);
+        monitor.on_tick(sent_at + Duration::from_secs(5));
+
+        assert_eq!(
+            *reports.borrow(),
+            vec![
+                HealthReport {
+                    health: ConnectionHealth::Good,
+                    latency: Some(Duration::from_millis(20)),
+                    missed_pongs: 0,
+                },
+                HealthReport {
+                    health: ConnectionHealth::Degraded,
+                    latency: Some(Duration::from_millis(20)),
+                    missed_pongs: 1,
+                },
+            ]
+        );
+    }
+}
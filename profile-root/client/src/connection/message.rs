@@ -4,14 +4,19 @@
 //! cryptographically signed messages to other users.
 
 use hex;
+use profile_shared::canonical::{self, CanonicalVersion};
 use profile_shared::{sign_message, PrivateKey, PublicKey};
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
 
 /// Client message structure for sending to server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientMessage {
     pub r#type: String,
+    /// Unique id; kept when the message is resent so it isn't delivered twice
+    #[serde(rename = "messageId")]
+    pub message_id: Uuid,
     #[serde(rename = "recipientPublicKey")]
     pub recipient_public_key: String,
     pub message: String,
@@ -19,6 +24,9 @@ pub struct ClientMessage {
     pub sender_public_key: String,
     pub signature: String,
     pub timestamp: String,
+    /// Canonical encoding the signature covers
+    #[serde(default, skip_serializing_if = "CanonicalVersion::is_legacy")]
+    pub canonical: CanonicalVersion,
 }
 
 impl ClientMessage {
@@ -43,10 +51,11 @@ impl ClientMessage {
 
         // Create canonical message for signing (message + timestamp)
         // This ensures deterministic signatures
-        let canonical_message = format!("{}:{}", message_text, timestamp);
+        let canonical_message =
+            canonical::message(CanonicalVersion::CURRENT, &message_text, &timestamp);
 
         // Sign the canonical message
-        let signature = sign_message(&private_key, canonical_message.as_bytes())?;
+        let signature = sign_message(&private_key, &canonical_message)?;
 
         // Encode to hex
         let sender_public_key_hex = hex::encode(sender_public_key.as_bytes());
@@ -54,11 +63,13 @@ impl ClientMessage {
 
         Ok(Self {
             r#type: "message".to_string(),
+            message_id: Uuid::new_v4(),
             recipient_public_key,
             message: message_text,
             sender_public_key: sender_public_key_hex,
             signature: signature_hex,
             timestamp,
+            canonical: CanonicalVersion::CURRENT,
         })
     }
 
@@ -83,10 +94,11 @@ impl ClientMessage {
 
         // Create canonical message for signing (message + timestamp)
         // This ensures deterministic signatures
-        let canonical_message = format!("{}:{}", message_text, timestamp);
+        let canonical_message =
+            canonical::message(CanonicalVersion::CURRENT, &message_text, &timestamp);
 
         // Sign the canonical message
-        let signature = sign_message(private_key, canonical_message.as_bytes())?;
+        let signature = sign_message(private_key, &canonical_message)?;
 
         // Encode to hex
         let sender_public_key_hex = hex::encode(sender_public_key.as_slice());
@@ -94,11 +106,13 @@ impl ClientMessage {
 
         Ok(Self {
             r#type: "message".to_string(),
+            message_id: Uuid::new_v4(),
             recipient_public_key,
             message: message_text,
             sender_public_key: sender_public_key_hex,
             signature: signature_hex,
             timestamp,
+            canonical: CanonicalVersion::CURRENT,
         })
     }
 
@@ -3,8 +3,19 @@
 //! This module handles WebSocket connection lifecycle including:
 //! - Connection establishment and authentication
 //! - Message sending and receiving
-//! - Connection state tracking
+//! - Typed dispatch of incoming server messages
+//! - Connection state tracking (see [`state`])
+//! - Ping-based connection health and latency (see [`health`])
+//! - Resending messages the server refused as busy (see [`retry`])
+//! - Opt-in cover traffic hiding when the user is active (see [`cover`])
+//! - Tunnelling through a SOCKS5 or HTTP proxy (see [`proxy`])
 
 pub mod auth;
 pub mod client;
+pub mod cover;
+pub mod dispatcher;
+pub mod health;
 pub mod message;
+pub mod proxy;
+pub mod retry;
+pub mod state;
@@ -0,0 +1,180 @@
+//! Resending messages the server refused as busy
+//!
+//! When the server sheds load it answers a send with `server_busy` and a
+//! retry-after hint. [`BusyRetries`] schedules the refused message to be
+//! resent no sooner than the hint, backing off exponentially on repeated
+//! refusals, and gives up after [`RetryPolicy::max_attempts`] so a
+//! persistently overloaded server surfaces an error instead of a silent
+//! resend loop.
+//!
+//! Like the health monitor, it takes the current time as an argument so it
+//! can be driven deterministically in tests.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Most messages whose attempt counts are remembered
+const MAX_TRACKED_MESSAGES: usize = 100;
+
+/// How often and how long to keep resending a refused message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Resends of one message before giving up
+    pub max_attempts: u32,
+    /// Delay before the first resend when the server gives no hint
+    pub base_delay: Duration,
+    /// Longest delay between resends, whatever the hint
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: profile_shared::config::load::SERVER_BUSY_RETRY_AFTER,
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before resend number `attempt` (starting at 1)
+    ///
+    /// The exponential backoff never undercuts the server's hint, and
+    /// neither exceeds `max_delay`.
+    ///
+    /// # Returns
+    /// None once `attempt` exceeds `max_attempts`
+    pub fn delay(&self, attempt: u32, hint: Duration) -> Option<Duration> {
+        if attempt == 0 || attempt > self.max_attempts {
+            return None;
+        }
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1));
+        Some(backoff.max(hint).min(self.max_delay))
+    }
+}
+
+/// Refused messages waiting to be resent
+#[derive(Debug, Default)]
+pub struct BusyRetries {
+    policy: RetryPolicy,
+    /// Messages due for resending, in no particular order
+    scheduled: Vec<(Instant, String)>,
+    /// Resends so far, by message
+    attempts: HashMap<String, u32>,
+}
+
+impl BusyRetries {
+    /// Create an empty queue using `policy`
+    pub fn new(policy: RetryPolicy) -> Self {
+        Self {
+            policy,
+            ..Self::default()
+        }
+    }
+
+    /// Schedule a refused message to be resent
+    ///
+    /// # Returns
+    /// The delay before the resend, or None if the message has been
+    /// refused too often and was dropped
+    pub fn schedule(&mut self, message: &str, hint: Duration, now: Instant) -> Option<Duration> {
+        let attempt = self.attempts.get(message).copied().unwrap_or(0) + 1;
+        let Some(delay) = self.policy.delay(attempt, hint) else {
+            self.attempts.remove(message);
+            return None;
+        };
+        if !self.attempts.contains_key(message) && self.attempts.len() >= MAX_TRACKED_MESSAGES {
+            self.attempts.clear();
+        }
+        self.attempts.insert(message.to_string(), attempt);
+        self.scheduled.push((now + delay, message.to_string()));
+        Some(delay)
+    }
+
+    /// Stop tracking a message that has been superseded
+    pub fn forget(&mut self, message: &str) {
+        self.attempts.remove(message);
+    }
+
+    /// Time until a resend is due (Duration::MAX if none is scheduled)
+    pub fn time_until_next(&self, now: Instant) -> Duration {
+        self.scheduled
+            .iter()
+            .map(|(due, _)| due.saturating_duration_since(now))
+            .min()
+            .unwrap_or(Duration::MAX)
+    }
+
+    /// Take the messages whose resend is due
+    pub fn take_due(&mut self, now: Instant) -> Vec<String> {
+        let (due, waiting) = std::mem::take(&mut self.scheduled)
+            .into_iter()
+            .partition(|(at, _)| *at <= now);
+        self.scheduled = waiting;
+        due.into_iter().map(|(_, message)| message).collect()
+    }
+
+    /// Number of resends waiting
+    pub fn len(&self) -> usize {
+        self.scheduled.len()
+    }
+
+    /// Check if no resend is waiting
+    pub fn is_empty(&self) -> bool {
+        self.scheduled.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_delay_honors_hint_and_backs_off() {
+        let policy = policy();
+        assert_eq!(
+            policy.delay(1, Duration::from_millis(500)),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            policy.delay(2, Duration::ZERO),
+            Some(Duration::from_millis(400))
+        );
+        assert_eq!(
+            policy.delay(3, Duration::from_secs(30)),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(policy.delay(4, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn test_resend_due_after_hint_then_gives_up() {
+        let mut retries = BusyRetries::new(policy());
+        let start = Instant::now();
+        let hint = Duration::from_millis(300);
+
+        assert_eq!(retries.schedule("m", hint, start), Some(hint));
+        assert_eq!(retries.time_until_next(start), hint);
+        assert!(retries
+            .take_due(start + Duration::from_millis(299))
+            .is_empty());
+        assert_eq!(retries.take_due(start + hint), vec!["m".to_string()]);
+        assert!(retries.is_empty());
+
+        assert!(retries.schedule("m", hint, start).is_some());
+        assert!(retries.schedule("m", hint, start).is_some());
+        assert_eq!(retries.schedule("m", hint, start), None);
+        assert_eq!(retries.len(), 2);
+    }
+}
@@ -0,0 +1,380 @@
+//! Client connection state machine
+//!
+//! The connection lifecycle is modelled as explicit states changed only by
+//! [`ConnectionEvent`]s. [`ConnectionState::apply`] is the transition table:
+//! any event that is not valid in the current state is rejected with a
+//! [`TransitionError`] and the state is left unchanged, so the client can
+//! never report e.g. `Online` without first passing through `Authenticating`.
+//!
+//! Transitions:
+//! - `Disconnected` --Connect--> `Connecting` --Connected--> `Authenticating`
+//!   --Authenticated--> `Online`
+//! - `Online` --ConnectionLost--> `Reconnecting { attempt: 1 }`; each further
+//!   ConnectFailed increments the attempt, Connected resumes authentication
+//!   and GiveUp returns to `Disconnected`
+//! - `Online` or `Reconnecting` --Drain--> `Draining`
+//! - Closed from any connected state returns to `Disconnected`
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// Connection state for the WebSocket client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not connected
+    Disconnected,
+    /// Opening the WebSocket connection
+    Connecting,
+    /// Connected, waiting for the auth handshake to complete
+    Authenticating,
+    /// Connected and authenticated
+    Online,
+    /// Connection lost, retrying (attempt counts from 1)
+    Reconnecting { attempt: u32 },
+    /// Closing the connection on purpose
+    Draining,
+}
+
+/// Event that moves the connection between states
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// Start connecting to the server
+    Connect,
+    /// The WebSocket connection was established
+    Connected,
+    /// Opening the WebSocket connection failed
+    ConnectFailed,
+    /// The server accepted our authentication
+    Authenticated,
+    /// The server rejected our authentication
+    AuthFailed,
+    /// An established connection dropped unexpectedly
+    ConnectionLost,
+    /// Reconnection attempts were exhausted
+    GiveUp,
+    /// Begin a graceful close
+    Drain,
+    /// The connection is fully closed
+    Closed,
+}
+
+/// Error returned when an event is not valid in the current state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransitionError {
+    /// The event cannot be applied in this state
+    Illegal {
+        from: ConnectionState,
+        event: ConnectionEvent,
+    },
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionError::Illegal { from, event } => {
+                write!(f, "Cannot apply {:?} while {:?}", event, from)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+impl ConnectionState {
+    /// Compute the state after applying an event
+    ///
+    /// # Returns
+    /// The next state, or TransitionError::Illegal if the event is not valid here
+    pub fn apply(&self, event: ConnectionEvent) -> Result<ConnectionState, TransitionError> {
+        use ConnectionEvent as E;
+        use ConnectionState as S;
+
+        let next = match (self, event) {
+            (S::Disconnected, E::Connect) => S::Connecting,
+            (S::Connecting, E::Connected) => S::Authenticating,
+            (S::Connecting, E::ConnectFailed) => S::Disconnected,
+            (S::Authenticating, E::Authenticated) => S::Online,
+            (S::Authenticating, E::AuthFailed | E::ConnectionLost) => S::Disconnected,
+            (S::Online, E::ConnectionLost) => S::Reconnecting { attempt: 1 },
+            (S::Online, E::Drain) => S::Draining,
+            (S::Reconnecting { .. }, E::Connected) => S::Authenticating,
+            (S::Reconnecting { attempt }, E::ConnectFailed) => S::Reconnecting {
+                attempt: attempt.saturating_add(1),
+            },
+            (S::Reconnecting { .. }, E::GiveUp) => S::Disconnected,
+            (S::Reconnecting { .. }, E::Drain) => S::Draining,
+            (
+                S::Connecting
+                | S::Authenticating
+                | S::Online
+                | S::Reconnecting { .. }
+                | S::Draining,
+                E::Closed,
+            ) => S::Disconnected,
+            (from, event) => {
+                return Err(TransitionError::Illegal {
+                    from: from.clone(),
+                    event,
+                })
+            }
+        };
+
+        Ok(next)
+    }
+
+    /// Whether the client can send and receive chat messages
+    pub fn is_online(&self) -> bool {
+        matches!(self, ConnectionState::Online)
+    }
+
+    /// Short status text for the UI
+    pub fn label(&self) -> String {
+        match self {
+            ConnectionState::Disconnected => "Disconnected".to_string(),
+            ConnectionState::Connecting => "Connecting...".to_string(),
+            ConnectionState::Authenticating => "Authenticating...".to_string(),
+            ConnectionState::Online => "Connected".to_string(),
+            ConnectionState::Reconnecting { attempt } => {
+                format!("Reconnecting (attempt {})...", attempt)
+            }
+            ConnectionState::Draining => "Disconnecting...".to_string(),
+        }
+    }
+}
+
+/// A completed state change, delivered to transition listeners
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionTransition {
+    pub from: ConnectionState,
+    pub event: ConnectionEvent,
+    pub to: ConnectionState,
+}
+
+/// Type alias for a transition listener
+type TransitionListener = Rc<RefCell<dyn Fn(&ConnectionTransition)>>;
+
+/// Current connection state plus listeners notified on every transition
+#[derive(Clone)]
+pub struct ConnectionStateMachine {
+    state: ConnectionState,
+    listeners: Vec<TransitionListener>,
+}
+
+impl ConnectionStateMachine {
+    /// Create a state machine in the Disconnected state
+    pub fn new() -> Self {
+        Self {
+            state: ConnectionState::Disconnected,
+            listeners: Vec::new(),
+        }
+    }
+
+    /// The current state
+    pub fn state(&self) -> &ConnectionState {
+        &self.state
+    }
+
+    /// Register a listener called after every successful transition
+    pub fn on_transition(&mut self, listener: impl Fn(&ConnectionTransition) + 'static) {
+        self.listeners.push(Rc::new(RefCell::new(listener)));
+    }
+
+    /// Apply an event, notifying listeners if the state changed
+    ///
+    /// # Returns
+    /// The new state, or TransitionError::Illegal (state unchanged)
+    pub fn fire(&mut self, event: ConnectionEvent) -> Result<&ConnectionState, TransitionError> {
+        let next = self.state.apply(event)?;
+        let transition = ConnectionTransition {
+            from: std::mem::replace(&mut self.state, next.clone()),
+            event,
+            to: next,
+        };
+        for listener in &self.listeners {
+            listener.borrow()(&transition);
+        }
+        Ok(&self.state)
+    }
+}
+
+impl Default for ConnectionStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ConnectionStateMachine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionStateMachine")
+            .field("state", &self.state)
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_EVENTS: [ConnectionEvent; 9] = [
+        ConnectionEvent::Connect,
+        ConnectionEvent::Connected,
+        ConnectionEvent::ConnectFailed,
+        ConnectionEvent::Authenticated,
+        ConnectionEvent::AuthFailed,
+        ConnectionEvent::ConnectionLost,
+        ConnectionEvent::GiveUp,
+        ConnectionEvent::Drain,
+        ConnectionEvent::Closed,
+    ];
+
+    fn all_states() -> Vec<ConnectionState> {
+        vec![
+            ConnectionState::Disconnected,
+            ConnectionState::Connecting,
+            ConnectionState::Authenticating,
+            ConnectionState::Online,
+            ConnectionState::Reconnecting { attempt: 2 },
+            ConnectionState::Draining,
+        ]
+    }
+
+    #[test]
+    fn test_happy_path_to_online_and_back() {
+        let mut machine = ConnectionStateMachine::new();
+        machine.fire(ConnectionEvent::Connect).unwrap();
+        machine.fire(ConnectionEvent::Connected).unwrap();
+        assert_eq!(machine.state(), &ConnectionState::Authenticating);
+        machine.fire(ConnectionEvent::Authenticated).unwrap();
+        assert!(machine.state().is_online());
+        machine.fire(ConnectionEvent::Drain).unwrap();
+        machine.fire(ConnectionEvent::Closed).unwrap();
+        assert_eq!(machine.state(), &ConnectionState::Disconnected);
+    }
+
+    #[test]
+    fn test_reconnect_counts_attempts() {
+        let mut machine = ConnectionStateMachine::new();
+        for event in [
+            ConnectionEvent::Connect,
+            ConnectionEvent::Connected,
+            ConnectionEvent::Authenticated,
+            ConnectionEvent::ConnectionLost,
+            ConnectionEvent::ConnectFailed,
+            ConnectionEvent::ConnectFailed,
+        ] {
+            machine.fire(event).unwrap();
+        }
+        assert_eq!(
+            machine.state(),
+            &ConnectionState::Reconnecting { attempt: 3 }
+        );
+
+        machine.fire(ConnectionEvent::Connected).unwrap();
+        machine.fire(ConnectionEvent::Authenticated).unwrap();
+        assert!(machine.state().is_online());
+    }
+
+    #[test]
+    fn test_give_up_returns_to_disconnected() {
+        let state = ConnectionState::Reconnecting { attempt: 5 };
+        assert_eq!(
+            state.apply(ConnectionEvent::GiveUp),
+            Ok(ConnectionState::Disconnected)
+        );
+    }
+
+    #[test]
+    fn test_online_is_only_reachable_from_authenticating() {
+        for state in all_states() {
+            for event in ALL_EVENTS {
+                if state.apply(event) == Ok(ConnectionState::Online) {
+                    assert_eq!(state, ConnectionState::Authenticating);
+                    assert_eq!(event, ConnectionEvent::Authenticated);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_illegal_transitions_are_rejected() {
+        let illegal = [
+            (
+                ConnectionState::Disconnected,
+                ConnectionEvent::Authenticated,
+            ),
+            (ConnectionState::Disconnected, ConnectionEvent::Closed),
+            (
+                ConnectionState::Disconnected,
+                ConnectionEvent::ConnectionLost,
+            ),
+            (ConnectionState::Connecting, ConnectionEvent::Authenticated),
+            (ConnectionState::Online, ConnectionEvent::Connect),
+            (ConnectionState::Online, ConnectionEvent::Authenticated),
+            (ConnectionState::Draining, ConnectionEvent::Connect),
+            (ConnectionState::Draining, ConnectionEvent::Authenticated),
+        ];
+
+        for (state, event) in illegal {
+            assert_eq!(
+                state.apply(event),
+                Err(TransitionError::Illegal {
+                    from: state.clone(),
+                    event
+                }),
+                "{:?} + {:?} should be illegal",
+                state,
+                event
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejected_event_leaves_state_and_skips_listeners() {
+        let mut machine = ConnectionStateMachine::new();
+        let calls = Rc::new(RefCell::new(0));
+        let calls_clone = calls.clone();
+        machine.on_transition(move |_| *calls_clone.borrow_mut() += 1);
+
+        assert!(machine.fire(ConnectionEvent::Authenticated).is_err());
+        assert_eq!(machine.state(), &ConnectionState::Disconnected);
+        assert_eq!(*calls.borrow(), 0);
+    }
+
+    #[test]
+    fn test_listeners_receive_transitions() {
+        let mut machine = ConnectionStateMachine::new();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let seen_clone = seen.clone();
+        machine.on_transition(move |t| seen_clone.borrow_mut().push(t.clone()));
+
+        machine.fire(ConnectionEvent::Connect).unwrap();
+        machine.fire(ConnectionEvent::ConnectFailed).unwrap();
+
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                ConnectionTransition {
+                    from: ConnectionState::Disconnected,
+                    event: ConnectionEvent::Connect,
+                    to: ConnectionState::Connecting,
+                },
+                ConnectionTransition {
+                    from: ConnectionState::Connecting,
+                    event: ConnectionEvent::ConnectFailed,
+                    to: ConnectionState::Disconnected,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        assert_eq!(ConnectionState::Online.label(), "Connected");
+        assert_eq!(
+            ConnectionState::Reconnecting { attempt: 2 }.label(),
+            "Reconnecting (attempt 2)..."
+        );
+    }
+}
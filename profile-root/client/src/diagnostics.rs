@@ -0,0 +1,404 @@
+//! Local logs, captures and crash reports, and their retention
+//!
+//! Diagnostic files live under one directory (`PROFILE_DIAGNOSTICS_DIR`, or
+//! `~/.profile/diagnostics` when that is unset):
+//!
+//! - `logs/`: log files
+//! - `captures/`: frame captures (see [`profile_shared::capture`])
+//! - `crashes/`: crash reports written by [`install_crash_reporter`]
+//!
+//! Each kind has a retention period. [`prune`] deletes files older than
+//! that, and [`PruningTask`] runs it periodically in the background.
+//! [`clear_all_local_traces`] deletes every diagnostic file at once, along
+//! with the usage statistics and an active capture file, for users who
+//! don't want anything left behind. Contacts and keys are not touched.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+/// Environment variable overriding the diagnostics directory
+pub const DIAGNOSTICS_ENV_VAR: &str = "PROFILE_DIAGNOSTICS_DIR";
+
+/// Environment variables overriding each retention period, in days
+pub const LOG_RETENTION_ENV_VAR: &str = "PROFILE_LOG_RETENTION_DAYS";
+pub const CAPTURE_RETENTION_ENV_VAR: &str = "PROFILE_CAPTURE_RETENTION_DAYS";
+pub const CRASH_RETENTION_ENV_VAR: &str = "PROFILE_CRASH_RETENTION_DAYS";
+
+/// How often the background task prunes
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Error types for diagnostics housekeeping
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticsError {
+    /// Listing or deleting diagnostic files failed
+    Io(String),
+}
+
+impl Display for DiagnosticsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticsError::Io(msg) => write!(f, "Failed to clean up diagnostics: {}", msg),
+        }
+    }
+}
+
+impl Error for DiagnosticsError {}
+
+/// A kind of diagnostic file, stored in its own subdirectory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    Logs,
+    Captures,
+    CrashReports,
+}
+
+impl DiagnosticKind {
+    /// Every kind
+    pub const ALL: [DiagnosticKind; 3] = [
+        DiagnosticKind::Logs,
+        DiagnosticKind::Captures,
+        DiagnosticKind::CrashReports,
+    ];
+
+    /// Subdirectory of the diagnostics directory holding this kind
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            DiagnosticKind::Logs => "logs",
+            DiagnosticKind::Captures => "captures",
+            DiagnosticKind::CrashReports => "crashes",
+        }
+    }
+}
+
+/// How long each kind of diagnostic file is kept
+///
+/// None keeps files of that kind until they are cleared by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionSettings {
+    pub logs: Option<Duration>,
+    pub captures: Option<Duration>,
+    pub crash_reports: Option<Duration>,
+}
+
+impl Default for RetentionSettings {
+    /// Logs for a week, captures for a day and crash reports for a month
+    fn default() -> Self {
+        Self {
+            logs: Some(7 * DAY),
+            captures: Some(DAY),
+            crash_reports: Some(30 * DAY),
+        }
+    }
+}
+
+impl RetentionSettings {
+    /// Defaults, overridden by the `PROFILE_*_RETENTION_DAYS` variables
+    ///
+    /// `0` keeps files forever; invalid values fall back to the default.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let days = |var: &str, default: Option<Duration>| {
+            parse_retention_days(std::env::var(var).ok().as_deref(), default)
+        };
+        Self {
+            logs: days(LOG_RETENTION_ENV_VAR, defaults.logs),
+            captures: days(CAPTURE_RETENTION_ENV_VAR, defaults.captures),
+            crash_reports: days(CRASH_RETENTION_ENV_VAR, defaults.crash_reports),
+        }
+    }
+
+    /// Retention period for one kind
+    pub fn for_kind(&self, kind: DiagnosticKind) -> Option<Duration> {
+        match kind {
+            DiagnosticKind::Logs => self.logs,
+            DiagnosticKind::Captures => self.captures,
+            DiagnosticKind::CrashReports => self.crash_reports,
+        }
+    }
+}
+
+/// Parse a retention period in days, `0` meaning keep forever
+fn parse_retention_days(value: Option<&str>, default: Option<Duration>) -> Option<Duration> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None => default,
+        Some(value) => match value.parse::<u64>() {
+            Ok(0) => None,
+            Ok(days) => Some(DAY * days.min(u32::MAX as u64) as u32),
+            Err(_) => {
+                tracing::warn!(value, "Ignoring invalid retention period");
+                default
+            }
+        },
+    }
+}
+
+/// Default diagnostics directory
+///
+/// # Returns
+/// `PROFILE_DIAGNOSTICS_DIR` if set, otherwise `~/.profile/diagnostics`, or
+/// None if neither can be determined
+pub fn default_diagnostics_dir() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(DIAGNOSTICS_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("diagnostics"))
+}
+
+/// Files in `dir`, missing directories counting as empty
+fn files_in(dir: &Path) -> Result<Vec<PathBuf>, DiagnosticsError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(DiagnosticsError::Io(e.to_string())),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| DiagnosticsError::Io(e.to_string()))?;
+        if entry.file_type().is_ok_and(|t| t.is_file()) {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+fn remove_file(path: &Path) -> Result<bool, DiagnosticsError> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(DiagnosticsError::Io(e.to_string())),
+    }
+}
+
+/// Delete diagnostic files older than their retention period
+///
+/// # Arguments
+/// * `dir` - The diagnostics directory
+/// * `settings` - Retention period per kind
+/// * `now` - Current time; a file's age is measured from its last modification
+///
+/// # Returns
+/// The number of files deleted
+pub fn prune(
+    dir: &Path,
+    settings: &RetentionSettings,
+    now: SystemTime,
+) -> Result<usize, DiagnosticsError> {
+    let mut removed = 0;
+    for kind in DiagnosticKind::ALL {
+        let Some(keep_for) = settings.for_kind(kind) else {
+            continue;
+        };
+        for file in files_in(&dir.join(kind.dir_name()))? {
+            let modified = std::fs::metadata(&file)
+                .and_then(|m| m.modified())
+                .map_err(|e| DiagnosticsError::Io(e.to_string()))?;
+            let age = now.duration_since(modified).unwrap_or_default();
+            if age >= keep_for && remove_file(&file)? {
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Delete every diagnostic file plus the other local traces
+///
+/// # Arguments
+/// * `dir` - The diagnostics directory
+/// * `extra_files` - Other files to delete, such as the usage statistics
+///   file and an active capture file
+///
+/// # Returns
+/// The number of files deleted
+pub fn clear_all_local_traces(
+    dir: &Path,
+    extra_files: &[PathBuf],
+) -> Result<usize, DiagnosticsError> {
+    let mut removed = 0;
+    for kind in DiagnosticKind::ALL {
+        for file in files_in(&dir.join(kind.dir_name()))? {
+            removed += usize::from(remove_file(&file)?);
+        }
+    }
+    for file in extra_files {
+        removed += usize::from(remove_file(file)?);
+    }
+    Ok(removed)
+}
+
+/// Local trace files outside the diagnostics directory
+///
+/// The usage statistics file and the capture file named by
+/// `PROFILE_CAPTURE_FILE`, where set.
+pub fn default_extra_trace_files() -> Vec<PathBuf> {
+    let capture = std::env::var_os(profile_shared::capture::CAPTURE_ENV_VAR)
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from);
+    crate::state::stats::default_stats_path()
+        .into_iter()
+        .chain(capture)
+        .collect()
+}
+
+/// Background thread pruning diagnostics on an interval
+///
+/// Prunes once on start, then every `interval`. The thread stops when the
+/// task is stopped or dropped.
+pub struct PruningTask {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PruningTask {
+    /// Start pruning `dir` with `settings` every `interval`
+    pub fn spawn(dir: PathBuf, settings: RetentionSettings, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || loop {
+            match prune(&dir, &settings, SystemTime::now()) {
+                Ok(0) => {}
+                Ok(removed) => tracing::debug!(removed, "Pruned old diagnostics"),
+                Err(e) => tracing::warn!("{}", e),
+            }
+            match stopped.recv_timeout(interval) {
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                _ => break,
+            }
+        });
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Stop the task and wait for the thread to exit
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PruningTask {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Write a crash report to `dir/crashes` whenever the client panics
+///
+/// Reports hold the panic message and location only. The previous panic
+/// hook still runs afterwards.
+pub fn install_crash_reporter(dir: PathBuf) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let crash_dir = dir.join(DiagnosticKind::CrashReports.dir_name());
+        let now = chrono::Utc::now();
+        let report = format!("time: {}\n{}\n", now.to_rfc3339(), info);
+        let file = crash_dir.join(format!("crash-{}.txt", now.timestamp_millis()));
+        let _ = std::fs::create_dir_all(&crash_dir).and_then(|_| std::fs::write(file, report));
+        previous(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "profile-diagnostics-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        for kind in DiagnosticKind::ALL {
+            std::fs::create_dir_all(dir.join(kind.dir_name())).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_retention_days_parsing() {
+        let default = Some(DAY);
+        assert_eq!(parse_retention_days(None, default), default);
+        assert_eq!(parse_retention_days(Some(" 3 "), default), Some(3 * DAY));
+        assert_eq!(parse_retention_days(Some("0"), default), None);
+        assert_eq!(parse_retention_days(Some("soon"), default), default);
+    }
+
+    #[test]
+    fn test_prune_respects_each_retention() {
+        let dir = temp_dir("prune");
+        let log = dir.join("logs").join("client.log");
+        let capture = dir.join("captures").join("frames.jsonl");
+        let crash = dir.join("crashes").join("crash-1.txt");
+        for file in [&log, &capture, &crash] {
+            std::fs::write(file, "x").unwrap();
+        }
+
+        let settings = RetentionSettings {
+            logs: Some(7 * DAY),
+            captures: Some(DAY),
+            crash_reports: None,
+        };
+        // Two days on: only the capture has expired; crash reports are kept
+        let later = SystemTime::now() + 2 * DAY;
+        assert_eq!(prune(&dir, &settings, later).unwrap(), 1);
+        assert!(log.exists() && crash.exists() && !capture.exists());
+
+        assert_eq!(prune(&dir, &settings, later + 7 * DAY).unwrap(), 1);
+        assert!(!log.exists() && crash.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_clear_all_local_traces() {
+        let dir = temp_dir("clear");
+        std::fs::write(dir.join("logs").join("client.log"), "x").unwrap();
+        std::fs::write(dir.join("crashes").join("crash-1.txt"), "x").unwrap();
+        let stats = dir.join("stats.json");
+        std::fs::write(&stats, "{}").unwrap();
+        let missing = dir.join("never-written.jsonl");
+
+        assert_eq!(
+            clear_all_local_traces(&dir, &[stats.clone(), missing]).unwrap(),
+            3
+        );
+        assert!(!stats.exists());
+        for kind in DiagnosticKind::ALL {
+            assert!(files_in(&dir.join(kind.dir_name())).unwrap().is_empty());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pruning_task_prunes_on_start() {
+        let dir = temp_dir("task");
+        let capture = dir.join("captures").join("frames.jsonl");
+        std::fs::write(&capture, "x").unwrap();
+
+        let settings = RetentionSettings {
+            captures: Some(Duration::ZERO),
+            ..RetentionSettings::default()
+        };
+        let task = PruningTask::spawn(dir.clone(), settings, Duration::from_secs(3600));
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while capture.exists() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        task.stop();
+        assert!(!capture.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
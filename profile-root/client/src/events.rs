@@ -0,0 +1,252 @@
+//! Client event bus
+//!
+//! Everything the connection learns that the UI may care about is published
+//! as a [`ClientEvent`] on one [`EventBus`]:
+//! - connection state transitions
+//! - lobby snapshots, joins, leaves, pages, nicknames, verified names,
+//!   key rollovers and server restarts
+//! - verified chat messages, edits, retractions and reactions, read
+//!   receipts, viewing hints, last-seen times and delivery states
+//! - direct messages handed to the server
+//! - file transfers offered, sent, received or abandoned
+//! - errors and notifications
+//!
+//! The bus is a broadcast channel, so any number of subscribers each see
+//! every event in order. A subscriber that falls more than
+//! [`EVENT_BUS_CAPACITY`] events behind skips the oldest ones; the skipped
+//! events are counted in [`EventSubscriber::missed`]. Publishing never
+//! waits, and events published with no subscriber are dropped.
+//!
+//! The `LobbyEventHandler` and `MessageEventHandler` callbacks of the
+//! WebSocket client still work; they are fed from the same events.
+
+use crate::connection::state::ConnectionTransition;
+use crate::handlers::attachments::AttachmentEvent;
+use crate::handlers::presence::{LastSeenReply, ViewingHint};
+use crate::handlers::receipts::ReadReceipt;
+use crate::state::messages::ChatMessage;
+use crate::state::outbox::SendState;
+use crate::ui::lobby_state::{LobbyPage, LobbyState, LobbyUser};
+use profile_shared::protocol::Status;
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+use tracing::warn;
+
+/// Events a subscriber can fall behind by before it misses some
+pub const EVENT_BUS_CAPACITY: usize = 256;
+
+/// Something that happened on the connection
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+    /// The connection changed state
+    Connection(ConnectionTransition),
+    /// The full lobby arrived after authenticating
+    LobbyReceived(LobbyState),
+    /// A user joined the lobby
+    UserJoined(LobbyUser),
+    /// A user left the lobby
+    UserLeft(String),
+    /// The selected recipient left the lobby
+    SelectionLost(String),
+    /// A requested page of lobby users arrived
+    LobbyPage(LobbyPage),
+    /// A user set or cleared their nickname
+    NicknameChanged {
+        public_key: String,
+        nickname: Option<String>,
+    },
+    /// A user claimed or released a name verified by the server
+    VerifiedNameChanged {
+        public_key: String,
+        verified_name: Option<String>,
+    },
+    /// A user changed their status, or joined with one other than online
+    StatusChanged { public_key: String, status: Status },
+    /// A user replaced their identity key with a verified rollover; for
+    /// this user's own key, the client reconnects with the new one
+    KeyRolledOver {
+        old_public_key: String,
+        new_public_key: String,
+    },
+    /// The server restarted since the previous connection, with its new epoch
+    ServerRestarted(u64),
+    /// A chat message was verified and stored
+    MessageReceived(ChatMessage),
+    /// A verified edit was applied to a stored message; carries the message
+    /// with its edits
+    MessageEdited(ChatMessage),
+    /// A verified retraction replaced a stored message with a tombstone
+    MessageRetracted(ChatMessage),
+    /// Someone reacted to a stored message or took a reaction back; carries
+    /// the message with its reactions
+    ReactionsChanged(ChatMessage),
+    /// A file transfer was offered, finished or abandoned
+    Attachment(AttachmentEvent),
+    /// A message failed signature verification, with a user-facing notice
+    InvalidSignature(String),
+    /// The recipient of one of our messages has read it
+    ReadReceipt(ReadReceipt),
+    /// A peer opened or closed our conversation
+    Viewing(ViewingHint),
+    /// When a contact was last online, answering a query sent after they left
+    LastSeen(LastSeenReply),
+    /// One of our messages changed delivery state
+    SendStateChanged {
+        message_id: String,
+        state: SendState,
+    },
+    /// One of our direct messages was handed to the server
+    MessageSent {
+        message_id: String,
+        recipient_public_key: String,
+    },
+    /// The recipient of our last message is offline; it will be resent
+    RecipientOffline(String),
+    /// A general notice for the user
+    Notification(String),
+    /// An error to show the user
+    Error(String),
+}
+
+/// Publishing end of the event bus
+///
+/// Cloning is cheap: clones publish to the same subscribers.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ClientEvent>,
+}
+
+impl EventBus {
+    /// Create a bus holding up to [`EVENT_BUS_CAPACITY`] unread events per
+    /// subscriber
+    pub fn new() -> Self {
+        Self::with_capacity(EVENT_BUS_CAPACITY)
+    }
+
+    /// Create a bus holding up to `capacity` unread events per subscriber
+    /// (at least one)
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    /// Publish an event to every current subscriber
+    ///
+    /// # Returns
+    /// The number of subscribers the event was sent to
+    pub fn publish(&self, event: ClientEvent) -> usize {
+        self.sender.send(event).unwrap_or(0)
+    }
+
+    /// Subscribe to events published from now on
+    pub fn subscribe(&self) -> EventSubscriber {
+        EventSubscriber {
+            receiver: self.sender.subscribe(),
+            missed: 0,
+        }
+    }
+
+    /// Number of current subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Receiving end of the event bus
+#[derive(Debug)]
+pub struct EventSubscriber {
+    receiver: broadcast::Receiver<ClientEvent>,
+    missed: u64,
+}
+
+impl EventSubscriber {
+    /// Wait for the next event
+    ///
+    /// # Returns
+    /// None once every [`EventBus`] clone is gone
+    pub async fn recv(&mut self) -> Option<ClientEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(skipped)) => self.skipped(skipped),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Take the next event without waiting, None if there is none yet
+    pub fn try_recv(&mut self) -> Option<ClientEvent> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => return Some(event),
+                Err(TryRecvError::Lagged(skipped)) => self.skipped(skipped),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Events skipped because this subscriber fell behind
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    fn skipped(&mut self, skipped: u64) {
+        warn!(skipped, "Event subscriber fell behind, skipping events");
+        self.missed += skipped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice(n: usize) -> ClientEvent {
+        ClientEvent::Notification(n.to_string())
+    }
+
+    fn text(event: Option<ClientEvent>) -> String {
+        match event {
+            Some(ClientEvent::Notification(text)) => text,
+            other => panic!("Expected a notification, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_every_subscriber_sees_every_event_in_order() {
+        let bus = EventBus::new();
+        assert_eq!(bus.publish(notice(0)), 0);
+
+        let mut ui = bus.subscribe();
+        let mut log = bus.subscribe();
+        assert_eq!(bus.subscriber_count(), 2);
+        assert_eq!(bus.clone().publish(notice(1)), 2);
+        bus.publish(notice(2));
+
+        assert_eq!(text(ui.recv().await), "1");
+        assert_eq!(text(ui.recv().await), "2");
+        assert_eq!(text(log.try_recv()), "1");
+        assert_eq!(text(log.try_recv()), "2");
+        assert!(log.try_recv().is_none());
+
+        drop(bus);
+        assert!(ui.recv().await.is_none());
+    }
+
+    #[test]
+    fn test_slow_subscriber_skips_oldest_events() {
+        let bus = EventBus::with_capacity(2);
+        let mut slow = bus.subscribe();
+        for n in 0..5 {
+            bus.publish(notice(n));
+        }
+
+        assert_eq!(text(slow.try_recv()), "3");
+        assert_eq!(slow.missed(), 3);
+        assert_eq!(text(slow.try_recv()), "4");
+    }
+}
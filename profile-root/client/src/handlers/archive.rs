@@ -0,0 +1,193 @@
+//! Conversation archive handlers
+//!
+//! Each handler updates the shared archive and saves it, so archived
+//! conversations stay archived across restarts. Message history is never
+//! touched.
+
+use crate::state::archive::{ArchiveError, ArchivedConversations, Sections, SharedArchive};
+use crate::state::rooms::{Conversation, SharedRoomsState};
+use crate::state::SharedLobbyState;
+use crate::ui::lobby_state::LobbyUser;
+
+/// Apply a change to the archive and save it if anything changed
+async fn update_and_save(
+    archive: &SharedArchive,
+    change: impl FnOnce(&mut ArchivedConversations) -> Result<bool, ArchiveError>,
+) -> Result<bool, ArchiveError> {
+    let mut archive = archive.lock().await;
+    let changed = change(&mut archive)?;
+    if changed {
+        archive.save()?;
+    }
+    Ok(changed)
+}
+
+/// Hide a conversation from the main list
+///
+/// # Returns
+/// Whether the conversation was not archived before
+pub async fn handle_archive_conversation(
+    archive: &SharedArchive,
+    conversation: &Conversation,
+) -> Result<bool, ArchiveError> {
+    update_and_save(archive, |a| a.archive(conversation)).await
+}
+
+/// Move a conversation back to the main list
+///
+/// # Returns
+/// Whether the conversation was archived
+pub async fn handle_unarchive_conversation(
+    archive: &SharedArchive,
+    conversation: &Conversation,
+) -> Result<bool, ArchiveError> {
+    update_and_save(archive, |a| a.unarchive(conversation)).await
+}
+
+/// Choose whether incoming messages unarchive their conversation
+pub async fn handle_set_unarchive_on_message(
+    archive: &SharedArchive,
+    enabled: bool,
+) -> Result<(), ArchiveError> {
+    update_and_save(archive, |a| {
+        let changed = a.unarchive_on_message != enabled;
+        a.unarchive_on_message = enabled;
+        Ok(changed)
+    })
+    .await
+    .map(|_| ())
+}
+
+/// Record a message arriving in a conversation, unarchiving it if the
+/// user asked for that
+///
+/// # Returns
+/// Whether the conversation was unarchived
+pub async fn handle_archive_message(
+    archive: &SharedArchive,
+    conversation: &Conversation,
+) -> Result<bool, ArchiveError> {
+    update_and_save(archive, |a| Ok(a.message_received(conversation))).await
+}
+
+/// Lobby users split into the main list and the "Archived" section
+pub async fn lobby_sections(
+    archive: &SharedArchive,
+    lobby_state: &SharedLobbyState,
+) -> Sections<LobbyUser> {
+    let users = lobby_state.lock().await.users_cloned();
+    archive
+        .lock()
+        .await
+        .sections(users, |user| Conversation::Direct(user.public_key.clone()))
+}
+
+/// Every conversation (lobby peers, then joined rooms) split into the main
+/// list and the "Archived" section
+pub async fn conversation_sections(
+    archive: &SharedArchive,
+    lobby_state: &SharedLobbyState,
+    rooms_state: &SharedRoomsState,
+) -> Sections<Conversation> {
+    let mut conversations: Vec<Conversation> = lobby_state
+        .lock()
+        .await
+        .users()
+        .into_iter()
+        .map(|user| Conversation::Direct(user.public_key.clone()))
+        .collect();
+    conversations.extend(
+        rooms_state
+            .lock()
+            .await
+            .room_names()
+            .into_iter()
+            .map(Conversation::Room),
+    );
+    archive
+        .lock()
+        .await
+        .sections(conversations, |conversation| conversation.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::archive::create_shared_archive;
+    use crate::state::create_shared_lobby_state;
+    use crate::state::rooms::create_shared_rooms_state;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_archive_persists_and_message_unarchives() {
+        let path = std::env::temp_dir().join(format!(
+            "profile-archive-handlers-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let archive: SharedArchive =
+            Arc::new(Mutex::new(ArchivedConversations::load(&path).unwrap()));
+        let alice = Conversation::Direct("ab".repeat(32));
+
+        assert_eq!(
+            handle_archive_conversation(&archive, &alice).await,
+            Ok(true)
+        );
+        assert!(ArchivedConversations::load(&path)
+            .unwrap()
+            .is_archived(&alice));
+
+        assert_eq!(handle_archive_message(&archive, &alice).await, Ok(true));
+        assert!(!ArchivedConversations::load(&path)
+            .unwrap()
+            .is_archived(&alice));
+
+        handle_set_unarchive_on_message(&archive, false)
+            .await
+            .unwrap();
+        handle_archive_conversation(&archive, &alice).await.unwrap();
+        assert_eq!(handle_archive_message(&archive, &alice).await, Ok(false));
+        let reloaded = ArchivedConversations::load(&path).unwrap();
+        assert!(reloaded.is_archived(&alice));
+        assert!(!reloaded.unarchive_on_message);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sections_include_lobby_and_rooms() {
+        let archive = create_shared_archive();
+        let lobby_state = create_shared_lobby_state();
+        let rooms_state = create_shared_rooms_state();
+        let (alice, bob) = ("ab".repeat(32), "cd".repeat(32));
+        lobby_state.lock().await.set_users(vec![
+            LobbyUser::new(alice.clone(), true),
+            LobbyUser::new(bob.clone(), true),
+        ]);
+        rooms_state
+            .lock()
+            .await
+            .apply_room_update("general", vec!["me".to_string()], "me");
+
+        handle_archive_conversation(&archive, &Conversation::Direct(alice.clone()))
+            .await
+            .unwrap();
+        handle_unarchive_conversation(&archive, &Conversation::Room("general".to_string()))
+            .await
+            .unwrap();
+
+        let sections = conversation_sections(&archive, &lobby_state, &rooms_state).await;
+        assert_eq!(
+            sections.active,
+            vec![
+                Conversation::Direct(bob.clone()),
+                Conversation::Room("general".to_string())
+            ]
+        );
+        assert_eq!(sections.archived, vec![Conversation::Direct(alice.clone())]);
+
+        let users = lobby_sections(&archive, &lobby_state).await;
+        assert_eq!(users.active.len(), 1);
+        assert_eq!(users.archived[0].public_key, alice);
+    }
+}
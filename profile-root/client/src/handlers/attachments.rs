@@ -0,0 +1,762 @@
+//! File attachments sent to a peer in chunks
+//!
+//! The sender signs an [`AttachmentManifest`] for the file and offers it in
+//! an `attachment_offer` request. Nothing else is sent until the recipient
+//! accepts: the recipient asks for the chunks below an index
+//! (`attachment_accept`), the sender sends them (`attachment_chunk`), and
+//! the recipient asks for the next window once they have all arrived. After
+//! the last chunk the sender sends `attachment_complete`; either side can
+//! send it with `cancelled` set to give up instead.
+//!
+//! An image is offered with a small preview in its manifest (see
+//! [`crate::handlers::thumbnails`]), shown with the offer.
+//!
+//! Every chunk is checked against its hash in the manifest as it arrives,
+//! and the assembled file against the file hash, before it is saved to the
+//! downloads directory (see [`crate::state::attachments`]).
+
+use crate::handlers::compose::ComposeError;
+use crate::handlers::thumbnails::{decode_thumbnail, generate_thumbnail};
+use crate::state::attachments::{SharedAttachments, TransferDirection};
+use crate::state::session::SharedKeyState;
+use image::RgbaImage;
+use profile_shared::config::attachments::MAX_ATTACHMENT_SIZE;
+use profile_shared::protocol::AttachmentManifest;
+use profile_shared::Message;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Attachment message received from the server, not yet checked
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncomingAttachment {
+    /// A peer offers us a file
+    Offer {
+        manifest: AttachmentManifest,
+        sender_public_key: String,
+    },
+    /// The recipient of a file we offered asks for its chunks below `until`
+    Accept {
+        transfer_id: Uuid,
+        until: u32,
+        sender_public_key: String,
+    },
+    /// A chunk of a file we accepted, hex-encoded
+    Chunk {
+        transfer_id: Uuid,
+        index: u32,
+        data: String,
+        sender_public_key: String,
+    },
+    /// The other side finished or gave up on a transfer
+    Complete {
+        transfer_id: Uuid,
+        cancelled: bool,
+        sender_public_key: String,
+    },
+}
+
+impl IncomingAttachment {
+    /// The peer who sent it, as authenticated by the server
+    pub fn sender_public_key(&self) -> &str {
+        match self {
+            IncomingAttachment::Offer {
+                sender_public_key, ..
+            }
+            | IncomingAttachment::Accept {
+                sender_public_key, ..
+            }
+            | IncomingAttachment::Chunk {
+                sender_public_key, ..
+            }
+            | IncomingAttachment::Complete {
+                sender_public_key, ..
+            } => sender_public_key,
+        }
+    }
+}
+
+/// Something that happened to a transfer, for the UI
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttachmentEvent {
+    /// A peer offered us a file, waiting to be accepted
+    Offered {
+        transfer_id: Uuid,
+        sender_public_key: String,
+        file_name: String,
+        size: u64,
+        /// Preview of an image, if the sender included one that decodes
+        thumbnail: Option<RgbaImage>,
+    },
+    /// The last chunk of a file we offered was sent
+    Sent {
+        transfer_id: Uuid,
+        recipient_public_key: String,
+    },
+    /// A file arrived intact and was saved to `path`
+    Received {
+        transfer_id: Uuid,
+        sender_public_key: String,
+        path: PathBuf,
+    },
+    /// The other side gave up on a transfer
+    Cancelled { transfer_id: Uuid, peer: String },
+    /// We gave up on a transfer, with a user-facing reason
+    Failed {
+        transfer_id: Uuid,
+        peer: String,
+        reason: String,
+    },
+}
+
+/// What to do after handling an attachment message
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AttachmentOutcome {
+    /// Requests to send, in order
+    pub replies: Vec<String>,
+    /// What to tell the UI, if anything
+    pub event: Option<AttachmentEvent>,
+}
+
+/// Extract an attachment message from an already-parsed server message
+pub fn attachment_from_message(message: Message) -> Option<IncomingAttachment> {
+    match message {
+        Message::AttachmentOffer {
+            manifest,
+            sender_public_key,
+        } => Some(IncomingAttachment::Offer {
+            manifest,
+            sender_public_key,
+        }),
+        Message::AttachmentAccept {
+            transfer_id,
+            until,
+            sender_public_key,
+        } => Some(IncomingAttachment::Accept {
+            transfer_id,
+            until,
+            sender_public_key,
+        }),
+        Message::AttachmentChunk {
+            transfer_id,
+            index,
+            data,
+            sender_public_key,
+        } => Some(IncomingAttachment::Chunk {
+            transfer_id,
+            index,
+            data,
+            sender_public_key,
+        }),
+        Message::AttachmentComplete {
+            transfer_id,
+            cancelled,
+            sender_public_key,
+        } => Some(IncomingAttachment::Complete {
+            transfer_id,
+            cancelled,
+            sender_public_key,
+        }),
+        _ => None,
+    }
+}
+
+/// Sign a manifest for a file and hold it until the recipient accepts
+///
+/// # Arguments
+/// * `file_name` - Name the recipient sees; any directory is dropped
+/// * `data` - The file's contents; images get a preview in the manifest
+/// * `recipient_public_key` - Who the file is for
+/// * `key_state` - Shared state containing the user's private key
+/// * `transfers` - Transfers to hold the file in
+///
+/// # Returns
+/// The transfer id and the JSON `attachment_offer` request
+///
+/// # Errors
+/// Returns an error if the file is empty or too large, no key is loaded, or
+/// signing fails
+pub async fn compose_attachment_offer(
+    file_name: &str,
+    data: Vec<u8>,
+    recipient_public_key: &str,
+    key_state: &SharedKeyState,
+    transfers: &SharedAttachments,
+) -> Result<(Uuid, String), ComposeError> {
+    if data.is_empty() {
+        return Err(ComposeError::EmptyMessage);
+    }
+    if data.len() > MAX_ATTACHMENT_SIZE {
+        return Err(ComposeError::TooLong {
+            length: data.len(),
+            max: MAX_ATTACHMENT_SIZE,
+        });
+    }
+
+    let thumbnail = generate_thumbnail(&data);
+    let manifest = {
+        let key_guard = key_state.lock().await;
+        let private_key = key_guard.private_key().ok_or(ComposeError::NoPrivateKey)?;
+        AttachmentManifest::sign_with_thumbnail(
+            private_key,
+            Uuid::new_v4(),
+            &sanitize_file_name(file_name),
+            &data,
+            thumbnail.as_deref(),
+            &chrono::Utc::now().to_rfc3339(),
+        )
+        .map_err(|e| ComposeError::SigningError(e.to_string()))?
+    };
+    let transfer_id = manifest.transfer_id;
+
+    let request = serde_json::json!({
+        "type": "attachment_offer",
+        "recipientPublicKey": recipient_public_key,
+        "manifest": manifest
+    });
+    let json = serde_json::to_string(&request)
+        .map_err(|e| ComposeError::SerializationError(e.to_string()))?;
+
+    transfers
+        .lock()
+        .await
+        .add_outgoing(recipient_public_key.to_string(), manifest, data);
+    Ok((transfer_id, json))
+}
+
+/// Accept a file offered to us
+///
+/// # Returns
+/// JSON `attachment_accept` request for the first window of chunks, None if
+/// no such file was offered or it was already accepted
+pub async fn compose_attachment_accept(
+    transfer_id: Uuid,
+    transfers: &SharedAttachments,
+) -> Option<String> {
+    let until = transfers.lock().await.accept(transfer_id)?;
+    Some(accept_request(transfer_id, until))
+}
+
+/// Give up on a transfer in either direction
+///
+/// # Returns
+/// JSON `attachment_complete` request telling the other side, None if the
+/// transfer isn't in progress
+pub async fn compose_attachment_cancel(
+    transfer_id: Uuid,
+    transfers: &SharedAttachments,
+) -> Option<String> {
+    let mut guard = transfers.lock().await;
+    let peer = guard.peer(transfer_id)?.to_string();
+    guard.cancel(transfer_id, &peer);
+    Some(complete_request(transfer_id, true))
+}
+
+/// Handle an attachment message from a peer
+///
+/// Offers are recorded once their manifest checks out, accepts are answered
+/// with the chunks asked for, chunks are checked and collected, and a
+/// completed file is saved to the downloads directory. Messages about
+/// transfers we don't know are ignored.
+///
+/// # Errors
+/// Returns a user-facing reason if an offered manifest is malformed or its
+/// signature doesn't verify under the sender's key
+pub async fn handle_incoming_attachment(
+    transfers: &SharedAttachments,
+    attachment: &IncomingAttachment,
+) -> Result<AttachmentOutcome, String> {
+    let mut guard = transfers.lock().await;
+    let outcome = match attachment {
+        IncomingAttachment::Offer {
+            manifest,
+            sender_public_key,
+        } => {
+            manifest
+                .check()
+                .map_err(|e| format!("Attachment offer refused: {}", e))?;
+            manifest
+                .verify(sender_public_key)
+                .map_err(|e| format!("Attachment manifest signature does not verify: {}", e))?;
+            if !guard.add_incoming(sender_public_key.clone(), manifest.clone()) {
+                return Ok(AttachmentOutcome::default());
+            }
+            AttachmentOutcome {
+                replies: Vec::new(),
+                event: Some(AttachmentEvent::Offered {
+                    transfer_id: manifest.transfer_id,
+                    sender_public_key: sender_public_key.clone(),
+                    file_name: manifest.file_name.clone(),
+                    size: manifest.size,
+                    thumbnail: manifest
+                        .thumbnail_bytes()
+                        .and_then(|encoded| decode_thumbnail(&encoded)),
+                }),
+            }
+        }
+        IncomingAttachment::Accept {
+            transfer_id,
+            until,
+            sender_public_key,
+        } => {
+            let Some(sent) = guard.take_chunks(*transfer_id, sender_public_key, *until) else {
+                return Ok(AttachmentOutcome::default());
+            };
+            let mut replies: Vec<String> = sent
+                .chunks
+                .iter()
+                .map(|(index, data)| chunk_request(*transfer_id, *index, data))
+                .collect();
+            let mut event = None;
+            if sent.finished {
+                replies.push(complete_request(*transfer_id, false));
+                event = Some(AttachmentEvent::Sent {
+                    transfer_id: *transfer_id,
+                    recipient_public_key: sender_public_key.clone(),
+                });
+            }
+            AttachmentOutcome { replies, event }
+        }
+        IncomingAttachment::Chunk {
+            transfer_id,
+            index,
+            data,
+            sender_public_key,
+        } => {
+            let added = hex::decode(data)
+                .map_err(|e| format!("Chunk {} is not hex: {}", index, e))
+                .and_then(|bytes| guard.add_chunk(*transfer_id, sender_public_key, *index, &bytes));
+            match added {
+                Ok(Some(until)) => AttachmentOutcome {
+                    replies: vec![accept_request(*transfer_id, until)],
+                    event: None,
+                },
+                Ok(None) => AttachmentOutcome::default(),
+                Err(reason) => {
+                    if guard.cancel(*transfer_id, sender_public_key).is_none() {
+                        return Ok(AttachmentOutcome::default());
+                    }
+                    AttachmentOutcome {
+                        replies: vec![complete_request(*transfer_id, true)],
+                        event: Some(AttachmentEvent::Failed {
+                            transfer_id: *transfer_id,
+                            peer: sender_public_key.clone(),
+                            reason,
+                        }),
+                    }
+                }
+            }
+        }
+        IncomingAttachment::Complete {
+            transfer_id,
+            cancelled: true,
+            sender_public_key,
+        } => match guard.cancel(*transfer_id, sender_public_key) {
+            Some(_) => AttachmentOutcome {
+                replies: Vec::new(),
+                event: Some(AttachmentEvent::Cancelled {
+                    transfer_id: *transfer_id,
+                    peer: sender_public_key.clone(),
+                }),
+            },
+            None => AttachmentOutcome::default(),
+        },
+        IncomingAttachment::Complete {
+            transfer_id,
+            cancelled: false,
+            sender_public_key,
+        } => {
+            if guard.incoming(*transfer_id).is_none() {
+                return Ok(AttachmentOutcome::default());
+            }
+            let saved = guard
+                .finish_incoming(*transfer_id, sender_public_key)
+                .and_then(|(manifest, data)| {
+                    save_attachment(guard.downloads_dir(), &manifest.file_name, &data)
+                        .map_err(|e| format!("Failed to save {}: {}", manifest.file_name, e))
+                });
+            let event = match saved {
+                Ok(path) => AttachmentEvent::Received {
+                    transfer_id: *transfer_id,
+                    sender_public_key: sender_public_key.clone(),
+                    path,
+                },
+                Err(reason) => AttachmentEvent::Failed {
+                    transfer_id: *transfer_id,
+                    peer: sender_public_key.clone(),
+                    reason,
+                },
+            };
+            AttachmentOutcome {
+                replies: Vec::new(),
+                event: Some(event),
+            }
+        }
+    };
+    Ok(outcome)
+}
+
+/// Whether a transfer is one we are sending or receiving, if in progress
+pub async fn transfer_direction(
+    transfer_id: Uuid,
+    transfers: &SharedAttachments,
+) -> Option<TransferDirection> {
+    let guard = transfers.lock().await;
+    if guard.outgoing(transfer_id).is_some() {
+        Some(TransferDirection::Outgoing)
+    } else if guard.incoming(transfer_id).is_some() {
+        Some(TransferDirection::Incoming)
+    } else {
+        None
+    }
+}
+
+/// Reduce a file name to a plain name safe to save under
+///
+/// Directories are dropped, control characters removed, and names that
+/// end up empty or as `.`/`..` become `attachment`.
+pub fn sanitize_file_name(file_name: &str) -> String {
+    let base = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    let base = base.trim();
+    if base.is_empty() || base == "." || base == ".." {
+        return "attachment".to_string();
+    }
+    let mut end = base
+        .len()
+        .min(profile_shared::config::attachments::MAX_FILE_NAME_LENGTH);
+    while !base.is_char_boundary(end) {
+        end -= 1;
+    }
+    base[..end].to_string()
+}
+
+/// Write a received file into `dir` without replacing an existing file
+///
+/// A taken name gets a number before its extension: `notes (1).txt`.
+///
+/// # Returns
+/// Where the file was written
+fn save_attachment(dir: &Path, file_name: &str, data: &[u8]) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let name = sanitize_file_name(file_name);
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name.as_str(), None),
+    };
+    for attempt in 0u32.. {
+        let candidate = match (attempt, extension) {
+            (0, _) => name.clone(),
+            (n, Some(extension)) => format!("{} ({}).{}", stem, n, extension),
+            (n, None) => format!("{} ({})", stem, n),
+        };
+        let path = dir.join(candidate);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(data)?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("ran out of file name suffixes")
+}
+
+fn accept_request(transfer_id: Uuid, until: u32) -> String {
+    serde_json::json!({
+        "type": "attachment_accept",
+        "transferId": transfer_id,
+        "until": until
+    })
+    .to_string()
+}
+
+fn chunk_request(transfer_id: Uuid, index: u32, data: &[u8]) -> String {
+    serde_json::json!({
+        "type": "attachment_chunk",
+        "transferId": transfer_id,
+        "index": index,
+        "data": hex::encode(data)
+    })
+    .to_string()
+}
+
+fn complete_request(transfer_id: Uuid, cancelled: bool) -> String {
+    serde_json::json!({
+        "type": "attachment_complete",
+        "transferId": transfer_id,
+        "cancelled": cancelled
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::attachments::AttachmentTransfers;
+    use crate::state::session::create_shared_key_state;
+    use profile_shared::config::attachments::{ACCEPT_WINDOW, CHUNK_SIZE};
+    use profile_shared::{derive_public_key, generate_private_key};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    /// Empty directory under the system temp dir, unique to this test run
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "profile-attachments-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn transfers_in(dir: &Path) -> SharedAttachments {
+        Arc::new(Mutex::new(AttachmentTransfers::with_downloads_dir(
+            dir.to_path_buf(),
+        )))
+    }
+
+    /// What the server would forward for a composed request from `sender`
+    fn relayed(json: &str, sender: &str) -> IncomingAttachment {
+        let mut request: serde_json::Value = serde_json::from_str(json).unwrap();
+        let object = request.as_object_mut().unwrap();
+        object.remove("recipientPublicKey");
+        let kind = match object.remove("type").unwrap().as_str().unwrap() {
+            "attachment_offer" => "AttachmentOffer",
+            "attachment_accept" => "AttachmentAccept",
+            "attachment_chunk" => "AttachmentChunk",
+            _ => "AttachmentComplete",
+        };
+        object.insert("message_type".to_string(), kind.into());
+        object.insert("senderPublicKey".to_string(), sender.into());
+        let message: Message = serde_json::from_value(request).unwrap();
+        attachment_from_message(message).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_file_sent_in_windows_and_saved() {
+        let dir = scratch_dir("flow");
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let alice = hex::encode(public_key.as_bytes());
+        let bob = "b".repeat(64);
+        let key_state = create_shared_key_state();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key);
+        let alice_transfers = transfers_in(&dir.join("alice"));
+        let bob_transfers = transfers_in(&dir.join("bob"));
+
+        let data: Vec<u8> = (0..CHUNK_SIZE * ACCEPT_WINDOW as usize + 5)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let (transfer_id, offer) = compose_attachment_offer(
+            "../reports/q3.pdf",
+            data.clone(),
+            &bob,
+            &key_state,
+            &alice_transfers,
+        )
+        .await
+        .unwrap();
+
+        let outcome = handle_incoming_attachment(&bob_transfers, &relayed(&offer, &alice))
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome.event,
+            Some(AttachmentEvent::Offered {
+                transfer_id,
+                sender_public_key: alice.clone(),
+                file_name: "q3.pdf".to_string(),
+                size: data.len() as u64,
+                thumbnail: None,
+            })
+        );
+
+        // Relay requests back and forth until nothing is left to send
+        let mut to_alice = vec![compose_attachment_accept(transfer_id, &bob_transfers)
+            .await
+            .unwrap()];
+        let mut events = Vec::new();
+        while !to_alice.is_empty() {
+            let mut to_bob = Vec::new();
+            for request in to_alice.drain(..) {
+                let outcome =
+                    handle_incoming_attachment(&alice_transfers, &relayed(&request, &bob))
+                        .await
+                        .unwrap();
+                to_bob.extend(outcome.replies);
+                events.extend(outcome.event);
+            }
+            for request in to_bob {
+                let outcome =
+                    handle_incoming_attachment(&bob_transfers, &relayed(&request, &alice))
+                        .await
+                        .unwrap();
+                to_alice.extend(outcome.replies);
+                events.extend(outcome.event);
+            }
+        }
+
+        let path = dir.join("bob").join("q3.pdf");
+        assert_eq!(
+            events,
+            vec![
+                AttachmentEvent::Sent {
+                    transfer_id,
+                    recipient_public_key: bob.clone(),
+                },
+                AttachmentEvent::Received {
+                    transfer_id,
+                    sender_public_key: alice.clone(),
+                    path: path.clone(),
+                },
+            ]
+        );
+        assert_eq!(std::fs::read(path).unwrap(), data);
+        assert_eq!(
+            transfer_direction(transfer_id, &alice_transfers).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_image_offer_carries_preview() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let alice = hex::encode(public_key.as_bytes());
+        let key_state = create_shared_key_state();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key);
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::RgbImage::from_pixel(400, 200, image::Rgb([200, 40, 40]))
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+
+        let (_, offer) = compose_attachment_offer(
+            "sunset.png",
+            png.into_inner(),
+            "bbbb",
+            &key_state,
+            &scratch_transfers(),
+        )
+        .await
+        .unwrap();
+        let outcome = handle_incoming_attachment(&scratch_transfers(), &relayed(&offer, &alice))
+            .await
+            .unwrap();
+        match outcome.event {
+            Some(AttachmentEvent::Offered {
+                thumbnail: Some(thumbnail),
+                ..
+            }) => assert_eq!(thumbnail.dimensions(), (128, 64)),
+            other => panic!("Expected an offer with a preview, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forged_offer_refused() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let key_state = create_shared_key_state();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key);
+        let transfers = scratch_transfers();
+        let (_, offer) =
+            compose_attachment_offer("a.txt", b"hi".to_vec(), "bbbb", &key_state, &transfers)
+                .await
+                .unwrap();
+
+        // Relayed as if someone else had offered it
+        let forged = relayed(&offer, &"c".repeat(64));
+        let receiver = scratch_transfers();
+        assert!(handle_incoming_attachment(&receiver, &forged)
+            .await
+            .is_err());
+        assert!(receiver.lock().await.pending_offers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_chunk_cancels_transfer() {
+        let private_key = generate_private_key().unwrap();
+        let alice = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let data = vec![1u8; 100];
+        let manifest = AttachmentManifest::sign(
+            &private_key,
+            Uuid::new_v4(),
+            "a.bin",
+            &data,
+            "2026-03-02T09:00:00Z",
+        )
+        .unwrap();
+        let transfer_id = manifest.transfer_id;
+        let transfers = scratch_transfers();
+        handle_incoming_attachment(
+            &transfers,
+            &IncomingAttachment::Offer {
+                manifest,
+                sender_public_key: alice.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        compose_attachment_accept(transfer_id, &transfers)
+            .await
+            .unwrap();
+
+        let outcome = handle_incoming_attachment(
+            &transfers,
+            &IncomingAttachment::Chunk {
+                transfer_id,
+                index: 0,
+                data: hex::encode(vec![2u8; 100]),
+                sender_public_key: alice.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome.replies, vec![complete_request(transfer_id, true)]);
+        assert!(matches!(
+            outcome.event,
+            Some(AttachmentEvent::Failed { .. })
+        ));
+        assert_eq!(transfer_direction(transfer_id, &transfers).await, None);
+    }
+
+    #[test]
+    fn test_sanitize_and_unique_names() {
+        assert_eq!(sanitize_file_name("/etc/passwd"), "passwd");
+        assert_eq!(sanitize_file_name("C:\\Users\\me\\a.txt"), "a.txt");
+        assert_eq!(sanitize_file_name(".."), "attachment");
+        assert_eq!(sanitize_file_name("bell\u{7}.txt"), "bell.txt");
+
+        let dir = scratch_dir("names");
+        let first = save_attachment(&dir, "notes.txt", b"one").unwrap();
+        let second = save_attachment(&dir, "notes.txt", b"two").unwrap();
+        let third = save_attachment(&dir, "README", b"three").unwrap();
+        let fourth = save_attachment(&dir, "README", b"four").unwrap();
+        assert_eq!(first.file_name().unwrap(), "notes.txt");
+        assert_eq!(second.file_name().unwrap(), "notes (1).txt");
+        assert_eq!(fourth.file_name().unwrap(), "README (1)");
+        assert_eq!(std::fs::read(third).unwrap(), b"three");
+        assert_eq!(std::fs::read(first).unwrap(), b"one");
+    }
+
+    fn scratch_transfers() -> SharedAttachments {
+        transfers_in(Path::new("unused"))
+    }
+}
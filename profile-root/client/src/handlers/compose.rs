@@ -10,6 +10,7 @@ use std::fmt::{self, Display, Formatter};
 
 use crate::state::messages::{ChatMessage, SharedMessageHistory};
 use crate::state::session::SharedKeyState;
+use profile_shared::canonical::{self, CanonicalVersion};
 use profile_shared::crypto::sign_message;
 
 /// Error types for message composition operations
@@ -18,6 +19,11 @@ pub enum ComposeError {
     NoPrivateKey,
     NoPublicKey,
     EmptyMessage,
+    /// The text is longer than allowed, in bytes
+    TooLong {
+        length: usize,
+        max: usize,
+    },
     TimestampError(String),
     SerializationError(String),
     SigningError(String),
@@ -31,6 +37,9 @@ impl Display for ComposeError {
             ComposeError::NoPrivateKey => write!(f, "No private key available for signing"),
             ComposeError::NoPublicKey => write!(f, "No public key available"),
             ComposeError::EmptyMessage => write!(f, "Message cannot be empty"),
+            ComposeError::TooLong { length, max } => {
+                write!(f, "Message is {} bytes, longer than {}", length, max)
+            }
             ComposeError::TimestampError(msg) => write!(f, "Failed to generate timestamp: {}", msg),
             ComposeError::SerializationError(msg) => {
                 write!(f, "Failed to serialize message: {}", msg)
@@ -101,10 +110,11 @@ pub async fn compose_and_send_message(
 
         // Create canonical message for signing (must match server verification format)
         let timestamp = chrono::Utc::now().to_rfc3339();
-        let canonical_message = format!("{}:{}", message_text, timestamp);
+        let canonical_message =
+            canonical::message(CanonicalVersion::CURRENT, &message_text, &timestamp);
 
         // Sign the canonical message
-        let signature = sign_message(private_key, canonical_message.as_bytes())
+        let signature = sign_message(private_key, &canonical_message)
             .map_err(|e| ComposeError::SigningError(e.to_string()))?;
 
         (public_key, timestamp, signature)
@@ -120,7 +130,9 @@ pub async fn compose_and_send_message(
         message_text.clone(),
         hex::encode(signature.clone()),
         timestamp.clone(),
-    );
+    )
+    .with_canonical(CanonicalVersion::CURRENT);
+    let message_id = chat_message.message_id;
 
     // 3. Store message in SharedMessageHistory
     {
@@ -132,11 +144,13 @@ pub async fn compose_and_send_message(
     // Format matches server's SendMessageRequest structure
     let message_json = serde_json::json!({
         "type": "message",
+        "messageId": message_id,
         "recipientPublicKey": recipient_public_key,
         "message": message_text,
         "senderPublicKey": public_key_hex,
         "signature": hex::encode(signature),
-        "timestamp": timestamp
+        "timestamp": timestamp,
+        "canonical": CanonicalVersion::CURRENT
     });
 
     let message_json = serde_json::to_string(&message_json)
@@ -179,10 +193,11 @@ pub async fn compose_message_draft(
         let timestamp = Utc::now().to_rfc3339();
 
         // Create canonical message for signing (must match server verification format)
-        let canonical_message = format!("{}:{}", message_text, timestamp);
+        let canonical_message =
+            canonical::message(CanonicalVersion::CURRENT, &message_text, &timestamp);
 
         // Sign the message
-        let signature = sign_message(private_key, canonical_message.as_bytes())
+        let signature = sign_message(private_key, &canonical_message)
             .map_err(|e| ComposeError::SigningError(e.to_string()))?;
 
         (public_key_hex, timestamp, signature)
@@ -194,7 +209,8 @@ pub async fn compose_message_draft(
         message_text,
         hex::encode(signature),
         timestamp,
-    ))
+    )
+    .with_canonical(CanonicalVersion::CURRENT))
 }
 
 #[cfg(test)]
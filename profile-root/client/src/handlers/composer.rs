@@ -120,6 +120,10 @@ pub fn get_send_result_message(result: &SendMessageResult) -> String {
         SendMessageResult::Success => "Message sent successfully".to_string(),
         SendMessageResult::NoRecipient => "Please select a recipient from the lobby".to_string(),
         SendMessageResult::EmptyMessage => "Please enter a message".to_string(),
+        SendMessageResult::MessageTooLong { length, max } => format!(
+            "Message is too long ({} of {} bytes); please shorten it",
+            length, max
+        ),
         SendMessageResult::Disconnected => "Not connected to server".to_string(),
         SendMessageResult::SigningFailed(e) => format!("Signing failed: {}", e),
         SendMessageResult::TransmissionFailed(e) => format!("Failed to send: {}", e),
@@ -248,6 +252,11 @@ mod tests {
             get_send_result_message(&SendMessageResult::EmptyMessage),
             "Please enter a message"
         );
+        assert!(get_send_result_message(&SendMessageResult::MessageTooLong {
+            length: 2001,
+            max: 2000
+        })
+        .contains("too long"));
         assert_eq!(
             get_send_result_message(&SendMessageResult::Disconnected),
             "Not connected to server"
@@ -0,0 +1,348 @@
+//! Contact list handlers
+//!
+//! Each handler updates the shared contact list and saves it, so a change
+//! made in the UI survives a restart.
+
+use crate::state::contact_export::ContactExport;
+use crate::state::contacts::{
+    Contact, ContactError, Contacts, MergePolicy, MergeSummary, SharedContacts, TrustStatus,
+};
+use crate::state::{SharedKeyState, SharedLobbyState};
+use crate::ui::lobby_state::LobbyUser;
+use std::path::Path;
+
+/// Apply a change to the contact list and save it
+async fn update_and_save(
+    contacts: &SharedContacts,
+    change: impl FnOnce(&mut Contacts) -> Result<(), ContactError>,
+) -> Result<(), ContactError> {
+    let mut contacts = contacts.lock().await;
+    change(&mut contacts)?;
+    contacts.save()
+}
+
+/// Pin a public key as a new contact
+///
+/// # Arguments
+/// * `contacts` - Shared contact list
+/// * `public_key` - The contact's hex-encoded public key
+/// * `label` - Name to show for the contact
+pub async fn handle_add_contact(
+    contacts: &SharedContacts,
+    public_key: &str,
+    label: &str,
+) -> Result<(), ContactError> {
+    update_and_save(contacts, |c| c.add(public_key, label).map(|_| ())).await
+}
+
+/// Remove a contact
+pub async fn handle_remove_contact(
+    contacts: &SharedContacts,
+    public_key: &str,
+) -> Result<(), ContactError> {
+    update_and_save(contacts, |c| {
+        c.remove(public_key)
+            .map(|_| ())
+            .ok_or(ContactError::NotFound)
+    })
+    .await
+}
+
+/// Change a contact's label
+pub async fn handle_rename_contact(
+    contacts: &SharedContacts,
+    public_key: &str,
+    label: &str,
+) -> Result<(), ContactError> {
+    update_and_save(contacts, |c| c.rename(public_key, label)).await
+}
+
+/// Mark a contact's key as verified out of band, or back to pinned
+pub async fn handle_set_contact_trust(
+    contacts: &SharedContacts,
+    public_key: &str,
+    trust: TrustStatus,
+) -> Result<(), ContactError> {
+    update_and_save(contacts, |c| c.set_trust(public_key, trust)).await
+}
+
+/// Replace a contact's notes
+pub async fn handle_set_contact_notes(
+    contacts: &SharedContacts,
+    public_key: &str,
+    notes: &str,
+) -> Result<(), ContactError> {
+    update_and_save(contacts, |c| c.set_notes(public_key, notes)).await
+}
+
+/// Tag a contact
+pub async fn handle_add_contact_tag(
+    contacts: &SharedContacts,
+    public_key: &str,
+    tag: &str,
+) -> Result<(), ContactError> {
+    update_and_save(contacts, |c| c.add_tag(public_key, tag).map(|_| ())).await
+}
+
+/// Remove a tag from a contact
+pub async fn handle_remove_contact_tag(
+    contacts: &SharedContacts,
+    public_key: &str,
+    tag: &str,
+) -> Result<(), ContactError> {
+    update_and_save(contacts, |c| c.remove_tag(public_key, tag).map(|_| ())).await
+}
+
+/// Record a message sent to or received from a key
+///
+/// Sets the contact's first-contacted time the first time only, and only
+/// saves when that changes anything. Keys that aren't contacts are ignored.
+pub async fn handle_contact_message(
+    contacts: &SharedContacts,
+    public_key: &str,
+    timestamp: &str,
+) -> Result<(), ContactError> {
+    let mut contacts = contacts.lock().await;
+    if contacts.record_contacted(public_key, timestamp) {
+        contacts.save()?;
+    }
+    Ok(())
+}
+
+/// Contacts matching a search query (see [`Contacts::search`])
+pub async fn search_contacts(contacts: &SharedContacts, query: &str) -> Vec<Contact> {
+    contacts
+        .lock()
+        .await
+        .search(query)
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+/// Online lobby users whose contact entry matches a search query
+///
+/// Lets the lobby be filtered by label, notes or tag rather than by key.
+pub async fn search_lobby_contacts(
+    contacts: &SharedContacts,
+    lobby_state: &SharedLobbyState,
+    query: &str,
+) -> Vec<LobbyUser> {
+    let matches = search_contacts(contacts, query).await;
+    let lobby = lobby_state.lock().await;
+    lobby
+        .users()
+        .into_iter()
+        .filter(|user| {
+            matches
+                .iter()
+                .any(|c| c.public_key.eq_ignore_ascii_case(&user.public_key))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Export the contact list to `path`, signed by the user's identity key
+///
+/// # Returns
+/// The number of contacts exported
+pub async fn handle_export_contacts(
+    contacts: &SharedContacts,
+    key_state: &SharedKeyState,
+    path: &Path,
+) -> Result<usize, ContactError> {
+    let export = {
+        let keys = key_state.lock().await;
+        let (Some(private_key), Some(public_key)) = (keys.private_key(), keys.public_key()) else {
+            return Err(ContactError::NoIdentityKey);
+        };
+        ContactExport::create(&*contacts.lock().await, private_key, public_key)?
+    };
+    std::fs::write(path, export.to_json()?).map_err(|e| ContactError::Io(e.to_string()))?;
+    Ok(export.payload.contacts.len())
+}
+
+/// Import a contact list exported by this identity on another device
+///
+/// The file must be signed by the user's own identity key. Verified
+/// contacts are merged into the list (see [`Contacts::merge`]) and saved.
+pub async fn handle_import_contacts(
+    contacts: &SharedContacts,
+    key_state: &SharedKeyState,
+    path: &Path,
+    policy: MergePolicy,
+) -> Result<MergeSummary, ContactError> {
+    let json = std::fs::read_to_string(path).map_err(|e| ContactError::Io(e.to_string()))?;
+    let export = ContactExport::from_json(&json)?;
+    let imported = {
+        let keys = key_state.lock().await;
+        let public_key = keys.public_key().ok_or(ContactError::NoIdentityKey)?;
+        export.verify(public_key)?
+    };
+
+    let mut contacts = contacts.lock().await;
+    // Merge into a copy so a bad entry leaves the list untouched
+    let mut merged = contacts.clone();
+    let summary = merged.merge(imported, policy)?;
+    merged.save()?;
+    *contacts = merged;
+    Ok(summary)
+}
+
+/// Label for a public key, if it is a contact
+pub async fn get_contact_label(contacts: &SharedContacts, public_key: &str) -> Option<String> {
+    contacts
+        .lock()
+        .await
+        .label_for(public_key)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_handlers_persist_changes() {
+        let path = std::env::temp_dir().join(format!(
+            "profile-contacts-handlers-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let contacts: SharedContacts = Arc::new(Mutex::new(Contacts::load(&path).unwrap()));
+        let key = "ab".repeat(32);
+
+        handle_add_contact(&contacts, &key, "Alice").await.unwrap();
+        handle_rename_contact(&contacts, &key, "Ally")
+            .await
+            .unwrap();
+        handle_set_contact_trust(&contacts, &key, TrustStatus::Verified)
+            .await
+            .unwrap();
+        assert_eq!(
+            get_contact_label(&contacts, &key).await.as_deref(),
+            Some("Ally")
+        );
+
+        let reloaded = Contacts::load(&path).unwrap();
+        assert_eq!(reloaded.label_for(&key), Some("Ally"));
+        assert_eq!(reloaded.get(&key).unwrap().trust, TrustStatus::Verified);
+
+        handle_remove_contact(&contacts, &key).await.unwrap();
+        assert_eq!(
+            handle_remove_contact(&contacts, &key).await,
+            Err(ContactError::NotFound)
+        );
+        assert!(Contacts::load(&path).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_metadata_persisted_and_lobby_searchable() {
+        let path = std::env::temp_dir().join(format!(
+            "profile-contacts-metadata-{}.json",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let contacts: SharedContacts = Arc::new(Mutex::new(Contacts::load(&path).unwrap()));
+        let (alice, bob) = ("ab".repeat(32), "cd".repeat(32));
+        handle_add_contact(&contacts, &alice, "Alice")
+            .await
+            .unwrap();
+        handle_add_contact(&contacts, &bob, "Bob").await.unwrap();
+
+        handle_set_contact_notes(&contacts, &alice, "Climbing partner")
+            .await
+            .unwrap();
+        handle_add_contact_tag(&contacts, &alice, "rope")
+            .await
+            .unwrap();
+        handle_contact_message(&contacts, &alice, "2025-12-27T10:00:00Z")
+            .await
+            .unwrap();
+
+        let reloaded = Contacts::load(&path).unwrap();
+        let saved = reloaded.get(&alice).unwrap();
+        assert_eq!(saved.notes, "Climbing partner");
+        assert!(saved.tags.contains("rope"));
+        assert_eq!(
+            saved.first_contacted_at.as_deref(),
+            Some("2025-12-27T10:00:00Z")
+        );
+
+        let lobby = crate::state::create_shared_lobby_state();
+        for key in [&alice, &bob] {
+            lobby
+                .lock()
+                .await
+                .add_user(LobbyUser::new(key.to_uppercase(), true));
+        }
+        let found = search_lobby_contacts(&contacts, &lobby, "#rope").await;
+        assert_eq!(found.len(), 1);
+        assert!(found[0].public_key.eq_ignore_ascii_case(&alice));
+
+        handle_remove_contact_tag(&contacts, &alice, "rope")
+            .await
+            .unwrap();
+        assert!(search_contacts(&contacts, "#rope").await.is_empty());
+        assert_eq!(search_contacts(&contacts, "climbing").await.len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_then_import_on_another_device() {
+        let dir =
+            std::env::temp_dir().join(format!("profile-contacts-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let export_path = dir.join("roster.json");
+
+        let key_state = crate::state::create_shared_key_state();
+        let no_key = crate::state::create_shared_key_state();
+        crate::state::handle_generate_key_async(&key_state)
+            .await
+            .unwrap();
+
+        let laptop: SharedContacts = Arc::new(Mutex::new(Contacts::new()));
+        handle_add_contact(&laptop, &"ab".repeat(32), "Alice")
+            .await
+            .unwrap();
+        assert_eq!(
+            handle_export_contacts(&laptop, &no_key, &export_path).await,
+            Err(ContactError::NoIdentityKey)
+        );
+        assert_eq!(
+            handle_export_contacts(&laptop, &key_state, &export_path)
+                .await
+                .unwrap(),
+            1
+        );
+
+        let phone: SharedContacts =
+            Arc::new(Mutex::new(Contacts::load(dir.join("phone.json")).unwrap()));
+        let summary =
+            handle_import_contacts(&phone, &key_state, &export_path, MergePolicy::KeepLocal)
+                .await
+                .unwrap();
+        assert_eq!(summary.added, vec!["ab".repeat(32)]);
+        assert_eq!(
+            Contacts::load(dir.join("phone.json"))
+                .unwrap()
+                .label_for(&"ab".repeat(32)),
+            Some("Alice")
+        );
+
+        // A different identity can't import it
+        let stranger = crate::state::create_shared_key_state();
+        crate::state::handle_generate_key_async(&stranger)
+            .await
+            .unwrap();
+        assert_eq!(
+            handle_import_contacts(&phone, &stranger, &export_path, MergePolicy::KeepLocal).await,
+            Err(ContactError::WrongSigner)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -491,7 +491,8 @@ mod history_edge_tests {
         let mut history = MessageHistory::new(100);
 
         // Add messages from different senders
-        let senders = ["alice", "bob", "alice", "charlie", "alice"];
+        let (alice, bob, charlie) = ("a1".repeat(32), "b0".repeat(32), "c4".repeat(32));
+        let senders = [&alice, &bob, &alice, &charlie, &alice];
         for (i, sender) in senders.iter().enumerate() {
             let msg = ChatMessage::new(
                 sender.to_string(),
@@ -503,7 +504,7 @@ mod history_edge_tests {
         }
 
         let from_alice: Vec<&str> = history
-            .messages_from("alice")
+            .messages_from(&crate::state::ConversationId::new(&alice).unwrap())
             .iter()
             .map(|m| m.message.as_str())
             .collect();
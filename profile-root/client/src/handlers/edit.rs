@@ -0,0 +1,260 @@
+//! Editing sent direct messages
+//!
+//! An edit replaces the text of a message this user sent. It is signed over
+//! the original message id, the new text and a fresh timestamp, sent as an
+//! `edit_message` request and relayed by the server as a `Message::Edit`.
+//!
+//! Both sides keep the original message and add the edit to it (see
+//! [`crate::state::messages::MessageHistory::apply_edit`]). A received edit
+//! is only applied if its signature verifies under the sender's key and the
+//! message it names came from that same sender.
+
+use crate::handlers::compose::ComposeError;
+use crate::state::messages::{MessageEdit, SharedMessageHistory};
+use crate::state::session::SharedKeyState;
+use profile_shared::canonical;
+use profile_shared::crypto::sign_message;
+use profile_shared::{verify_signature, Message, PublicKey};
+use uuid::Uuid;
+
+/// Edit received from the server, not yet verified
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomingEdit {
+    /// Id of the message being edited
+    pub message_id: Uuid,
+    /// Sender as authenticated by the server
+    pub sender_public_key: String,
+    pub edit: MessageEdit,
+}
+
+/// Extract an edit from an already-parsed server message
+pub fn edit_from_message(message: Message) -> Option<IncomingEdit> {
+    match message {
+        Message::Edit {
+            message_id,
+            message,
+            sender_public_key,
+            signature,
+            timestamp,
+        } => Some(IncomingEdit {
+            message_id,
+            sender_public_key,
+            edit: MessageEdit {
+                message,
+                signature,
+                timestamp,
+            },
+        }),
+        _ => None,
+    }
+}
+
+/// Sign an edit of one of our messages and apply it to our own history
+///
+/// # Arguments
+/// * `message_id` - Id of the message being edited
+/// * `new_text` - The replacement text
+/// * `recipient_public_key` - Who the original message was sent to
+/// * `key_state` - Shared state containing the user's private key
+/// * `message_history` - History holding the original message
+///
+/// # Returns
+/// JSON `edit_message` request ready for WebSocket transmission
+///
+/// # Errors
+/// Returns an error if the text is empty, no key is loaded, or signing fails
+pub async fn compose_edit(
+    message_id: Uuid,
+    new_text: String,
+    recipient_public_key: &str,
+    key_state: &SharedKeyState,
+    message_history: &SharedMessageHistory,
+) -> Result<String, ComposeError> {
+    if new_text.trim().is_empty() {
+        return Err(ComposeError::EmptyMessage);
+    }
+
+    let (public_key, timestamp, signature) = {
+        let key_guard = key_state.lock().await;
+        let public_key = key_guard.public_key().ok_or(ComposeError::NoPublicKey)?;
+        let private_key = key_guard.private_key().ok_or(ComposeError::NoPrivateKey)?;
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            private_key,
+            &canonical::message_edit(message_id, &new_text, &timestamp),
+        )
+        .map_err(|e| ComposeError::SigningError(e.to_string()))?;
+        (hex::encode(public_key), timestamp, hex::encode(signature))
+    };
+
+    message_history.lock().await.apply_edit(
+        message_id,
+        &public_key,
+        MessageEdit {
+            message: new_text.clone(),
+            signature: signature.clone(),
+            timestamp: timestamp.clone(),
+        },
+    );
+
+    let request = serde_json::json!({
+        "type": "edit_message",
+        "recipientPublicKey": recipient_public_key,
+        "messageId": message_id,
+        "message": new_text,
+        "signature": signature,
+        "timestamp": timestamp
+    });
+    serde_json::to_string(&request).map_err(|e| ComposeError::SerializationError(e.to_string()))
+}
+
+/// Check an edit's signature under its sender's key
+///
+/// # Errors
+/// Returns a user-facing reason if the key or signature is malformed or the
+/// signature doesn't cover the edit
+pub fn verify_edit(edit: &IncomingEdit) -> Result<(), String> {
+    let key_bytes = hex::decode(&edit.sender_public_key)
+        .map_err(|e| format!("Invalid public key hex: {}", e))?;
+    let public_key =
+        PublicKey::new(key_bytes).map_err(|e| format!("Invalid public key format: {}", e))?;
+    let signature =
+        hex::decode(&edit.edit.signature).map_err(|e| format!("Invalid signature hex: {}", e))?;
+    let signed = canonical::message_edit(edit.message_id, &edit.edit.message, &edit.edit.timestamp);
+    verify_signature(&public_key, &signed, &signature)
+        .map_err(|e| format!("Edit signature does not verify: {}", e))
+}
+
+/// Verify a received edit and apply it to the message it names
+///
+/// # Returns
+/// Ok(true) if the edit was applied, Ok(false) if no message from its sender
+/// has the id or the edit is already stored
+///
+/// # Errors
+/// Returns a user-facing reason if the signature doesn't verify
+pub async fn handle_incoming_edit(
+    message_history: &SharedMessageHistory,
+    edit: &IncomingEdit,
+) -> Result<bool, String> {
+    verify_edit(edit)?;
+    Ok(message_history.lock().await.apply_edit(
+        edit.message_id,
+        &edit.sender_public_key,
+        edit.edit.clone(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::messages::{create_shared_message_history, ChatMessage};
+    use crate::state::session::create_shared_key_state;
+    use profile_shared::{derive_public_key, generate_private_key};
+
+    /// A sender's key state and a history holding one message from them
+    async fn sent_message() -> (SharedKeyState, SharedMessageHistory, Uuid, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let sender = hex::encode(public_key.as_bytes());
+        let key_state = create_shared_key_state();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key);
+
+        let history = create_shared_message_history();
+        let original = ChatMessage::verified(
+            sender.clone(),
+            "See you at 5".to_string(),
+            "aa".repeat(64),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let message_id = original.message_id;
+        history.lock().await.add_message(original);
+        (key_state, history, message_id, sender)
+    }
+
+    #[tokio::test]
+    async fn test_composed_edit_verifies_and_applies_on_the_recipient() {
+        let (key_state, history, message_id, sender) = sent_message().await;
+        let json = compose_edit(
+            message_id,
+            "See you at 6".to_string(),
+            "bbbb",
+            &key_state,
+            &history,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            history.lock().await.get(message_id).unwrap().current_text(),
+            "See you at 6"
+        );
+
+        let request: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(request["type"], "edit_message");
+        assert_eq!(request["recipientPublicKey"], "bbbb");
+        let incoming = IncomingEdit {
+            message_id,
+            sender_public_key: sender.clone(),
+            edit: MessageEdit {
+                message: request["message"].as_str().unwrap().to_string(),
+                signature: request["signature"].as_str().unwrap().to_string(),
+                timestamp: request["timestamp"].as_str().unwrap().to_string(),
+            },
+        };
+        // The recipient holds its own copy of the original, without the edit
+        let mut original = history.lock().await.get(message_id).cloned().unwrap();
+        original.edits.clear();
+        let recipient_history = create_shared_message_history();
+        recipient_history.lock().await.add_message(original);
+        assert_eq!(
+            handle_incoming_edit(&recipient_history, &incoming).await,
+            Ok(true)
+        );
+        // Applying the same edit again changes nothing
+        assert_eq!(
+            handle_incoming_edit(&recipient_history, &incoming).await,
+            Ok(false)
+        );
+        let stored = recipient_history
+            .lock()
+            .await
+            .get(message_id)
+            .cloned()
+            .unwrap();
+        assert_eq!(stored.message, "See you at 5");
+        assert_eq!(stored.current_text(), "See you at 6");
+    }
+
+    #[tokio::test]
+    async fn test_tampered_edit_rejected() {
+        let (key_state, history, message_id, sender) = sent_message().await;
+        let json = compose_edit(message_id, "ok".to_string(), "bbbb", &key_state, &history)
+            .await
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let incoming = IncomingEdit {
+            message_id,
+            sender_public_key: sender,
+            edit: MessageEdit {
+                message: "not ok".to_string(),
+                signature: request["signature"].as_str().unwrap().to_string(),
+                timestamp: request["timestamp"].as_str().unwrap().to_string(),
+            },
+        };
+        assert!(handle_incoming_edit(&history, &incoming).await.is_err());
+        assert_eq!(
+            history.lock().await.get(message_id).unwrap().current_text(),
+            "ok"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_empty_edit_rejected() {
+        let (key_state, history, message_id, _) = sent_message().await;
+        let result = compose_edit(message_id, "  ".to_string(), "bbbb", &key_state, &history).await;
+        assert!(matches!(result, Err(ComposeError::EmptyMessage)));
+    }
+}
@@ -0,0 +1,175 @@
+//! Incoming server error classification
+//!
+//! The server reports errors in two shapes: the shared `Message::Error`
+//! used once a session is established, and the `{"type":"error"}` shape
+//! used by the auth handshake. [`parse_incoming_error`] accepts both so the
+//! message loop has a single place to dispatch errors from.
+
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::handlers::offline::{
+    format_notification_message, offline_notification_from_message, OfflineNotification,
+};
+use profile_shared::Message;
+
+/// Error received from the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum IncomingError {
+    /// A message could not be delivered because the recipient is offline
+    RecipientOffline(OfflineNotification),
+    /// The server was too busy to accept a message; it may be resent after
+    /// `retry_after`
+    ServerBusy {
+        recipient: String,
+        retry_after: Duration,
+    },
+    /// Any other server error
+    Server {
+        reason: String,
+        details: Option<String>,
+    },
+}
+
+impl IncomingError {
+    /// User-facing text for this error
+    pub fn user_message(&self) -> String {
+        match self {
+            IncomingError::RecipientOffline(notification) => {
+                format_notification_message(notification)
+            }
+            IncomingError::ServerBusy { .. } => {
+                "Server is busy and could not deliver your message. Try again shortly.".to_string()
+            }
+            IncomingError::Server { reason, details } => {
+                format!("{}: {}", reason, details.as_deref().unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// Error shape used by the auth handshake
+#[derive(Debug, Deserialize)]
+struct TypedErrorMessage {
+    r#type: String,
+    reason: String,
+    details: Option<String>,
+}
+
+/// Parse an error from the server
+///
+/// # Returns
+/// Some(IncomingError) if the JSON is a server error in either shape,
+/// None for any other message
+pub fn parse_incoming_error(json: &str) -> Option<IncomingError> {
+    if let Ok(message) = serde_json::from_str::<Message>(json) {
+        if let Some(notification) = offline_notification_from_message(&message) {
+            return Some(IncomingError::RecipientOffline(notification));
+        }
+        if let Some((recipient, retry_after)) = message.server_busy_retry() {
+            return Some(IncomingError::ServerBusy {
+                recipient: recipient.to_string(),
+                retry_after,
+            });
+        }
+        return match message {
+            Message::Error {
+                reason, details, ..
+            } => Some(IncomingError::Server { reason, details }),
+            _ => None,
+        };
+    }
+
+    match serde_json::from_str::<TypedErrorMessage>(json) {
+        Ok(error) if error.r#type == "error" => Some(IncomingError::Server {
+            reason: error.reason,
+            details: error.details,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_shared_offline_error() {
+        let json = serde_json::to_string(&Message::new_recipient_offline("abc123")).unwrap();
+        match parse_incoming_error(&json) {
+            Some(IncomingError::RecipientOffline(notification)) => {
+                assert_eq!(notification.recipient, "abc123");
+            }
+            other => panic!("Expected RecipientOffline, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_shared_server_error() {
+        let json = serde_json::to_string(&Message::new_error(
+            "stale_timestamp".to_string(),
+            Some("too old".to_string()),
+        ))
+        .unwrap();
+        let error = parse_incoming_error(&json).unwrap();
+        assert_eq!(
+            error,
+            IncomingError::Server {
+                reason: "stale_timestamp".to_string(),
+                details: Some("too old".to_string()),
+            }
+        );
+        assert_eq!(error.user_message(), "stale_timestamp: too old");
+    }
+
+    #[test]
+    fn test_parse_server_busy_error() {
+        let json = serde_json::to_string(&Message::new_server_busy(
+            "abc123",
+            Duration::from_millis(500),
+        ))
+        .unwrap();
+        assert_eq!(
+            parse_incoming_error(&json),
+            Some(IncomingError::ServerBusy {
+                recipient: "abc123".to_string(),
+                retry_after: Duration::from_millis(500),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_typed_error() {
+        let json = r#"{"type":"error","reason":"lobby_error","details":"Unable to join lobby"}"#;
+        assert!(matches!(
+            parse_incoming_error(json),
+            Some(IncomingError::Server { reason, .. }) if reason == "lobby_error"
+        ));
+    }
+
+    #[test]
+    fn test_non_errors_ignored() {
+        assert!(parse_incoming_error(r#"{"type":"lobby","users":[]}"#).is_none());
+        assert!(parse_incoming_error("not json").is_none());
+        let text = serde_json::to_string(&Message::new_text(
+            uuid::Uuid::new_v4(),
+            "hi".to_string(),
+            "key".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+            profile_shared::canonical::CanonicalVersion::CURRENT,
+        ))
+        .unwrap();
+        assert!(parse_incoming_error(&text).is_none());
+    }
+
+    #[test]
+    fn test_offline_user_message() {
+        let error = IncomingError::RecipientOffline(OfflineNotification {
+            recipient: "abc123".to_string(),
+            message: None,
+            timestamp: None,
+        });
+        assert!(error.user_message().contains("is offline"));
+    }
+}
@@ -0,0 +1,673 @@
+//! Verifiable conversation transcripts
+//!
+//! A transcript holds every message of a conversation with its sender key,
+//! timestamp and signature, plus a manifest saying how the signatures were
+//! made. Anyone with the file can check each message with
+//! `profile_shared::verify_signature`: the signed bytes are the message and
+//! timestamp in the canonical encoding the entry names (see
+//! `profile_shared::canonical`; entries without one are version 1,
+//! `{message}:{timestamp}`), signed by the sender's ed25519 key, exactly as
+//! for live chat messages. Direct messages sent with a sequence number were
+//! signed over it too, and keep it in the entry. Nothing is re-signed by the exporter, so a
+//! transcript proves what each sender wrote, not who exported it.
+//!
+//! Transcripts are written as JSON, or as Markdown for reading with the
+//! same JSON embedded in a fenced block at the end for verification.
+//!
+//! A direct transcript holds what the chat view shows for the peer: the
+//! messages they sent. Message history doesn't record recipients, so the
+//! user's own direct messages can't be attributed to a conversation.
+//! Retracted messages are left out, since their text is gone and their
+//! signatures no longer verify.
+//!
+//! Importing a transcript re-verifies every message against the sender key
+//! it carries and merges the valid ones into the conversation's history,
+//! skipping messages already there. Invalid entries are reported, not
+//! imported.
+
+use crate::handlers::verify::{
+    format_public_key, verify_direct_message, verify_room_message, VerificationResult,
+};
+use crate::state::conversation::ConversationId;
+use crate::state::messages::{ChatMessage, MessageHistory, SharedMessageHistory};
+use crate::state::rooms::{Conversation, SharedRoomsState};
+use profile_shared::canonical::CanonicalVersion;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+use uuid::Uuid;
+
+/// Current transcript format version
+pub const TRANSCRIPT_VERSION: u32 = 1;
+
+/// How the signed bytes of each message are built
+pub const TRANSCRIPT_SIGNED_PAYLOAD: &str =
+    "profile_shared::canonical::direct_message({canonical}, {message}, {timestamp}, {sequence})";
+
+/// How the signed bytes of each message in a room transcript are built
+pub const ROOM_TRANSCRIPT_SIGNED_PAYLOAD: &str =
+    "profile_shared::canonical::room_message({canonical}, {room}, {message}, {timestamp})";
+
+/// Start of the fenced block holding the JSON transcript in Markdown
+const MARKDOWN_JSON_FENCE: &str = "```json\n";
+
+/// Error types for transcript export
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportError {
+    /// Not a conversation this user has: unknown peer key or a room not joined
+    UnknownConversation,
+    /// Writing or reading the transcript file failed
+    Io(String),
+    /// The transcript could not be serialized or parsed
+    Parse(String),
+    /// A message's signature does not match its sender, text and timestamp
+    InvalidSignature { message_id: String, reason: String },
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::UnknownConversation => write!(f, "Conversation not found"),
+            ExportError::Io(msg) => write!(f, "Failed to access transcript file: {}", msg),
+            ExportError::Parse(msg) => write!(f, "Invalid transcript: {}", msg),
+            ExportError::InvalidSignature { message_id, reason } => {
+                write!(f, "Message {} failed verification: {}", message_id, reason)
+            }
+        }
+    }
+}
+
+impl Error for ExportError {}
+
+/// Output format of a transcript file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Json,
+    Markdown,
+}
+
+impl TranscriptFormat {
+    /// Format matching a file extension: `.md` is Markdown, anything else JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("md") => TranscriptFormat::Markdown,
+            _ => TranscriptFormat::Json,
+        }
+    }
+}
+
+/// How to verify the messages of a transcript
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptManifest {
+    pub version: u32,
+    /// Signature algorithm, always `ed25519`
+    pub algorithm: String,
+    /// Template of the signed bytes (see [`TRANSCRIPT_SIGNED_PAYLOAD`] and
+    /// [`ROOM_TRANSCRIPT_SIGNED_PAYLOAD`])
+    #[serde(rename = "signedPayload")]
+    pub signed_payload: String,
+    pub conversation: Conversation,
+    /// When the transcript was exported (RFC 3339)
+    #[serde(rename = "exportedAt")]
+    pub exported_at: String,
+    #[serde(rename = "messageCount")]
+    pub message_count: usize,
+}
+
+/// One message of a transcript
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptMessage {
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    #[serde(rename = "senderPublicKey")]
+    pub sender_public_key: String,
+    pub message: String,
+    /// When the message was sent (ISO 8601), part of the signed bytes
+    pub timestamp: String,
+    /// Hex-encoded ed25519 signature
+    pub signature: String,
+    /// Canonical encoding the signature covers; absent means version 1
+    #[serde(default, skip_serializing_if = "CanonicalVersion::is_legacy")]
+    pub canonical: CanonicalVersion,
+    /// Sender's sequence number the signature covers, for direct messages
+    /// sent with one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+}
+
+impl TranscriptMessage {
+    /// Check the signature against the sender key in the entry
+    ///
+    /// Messages in a room transcript are checked as signed for that room.
+    ///
+    /// # Returns
+    /// The verified message, keeping its id
+    pub fn verify(&self, conversation: &Conversation) -> Result<ChatMessage, ExportError> {
+        let invalid = |reason: String| ExportError::InvalidSignature {
+            message_id: self.message_id.clone(),
+            reason,
+        };
+        let message_id = Uuid::parse_str(&self.message_id)
+            .map_err(|e| invalid(format!("Invalid message id: {}", e)))?;
+        let result = match conversation {
+            Conversation::Direct(_) => verify_direct_message(
+                &self.message,
+                &self.sender_public_key,
+                &self.signature,
+                &self.timestamp,
+                self.canonical,
+                self.sequence,
+            ),
+            Conversation::Room(room) => verify_room_message(
+                room,
+                &self.message,
+                &self.sender_public_key,
+                &self.signature,
+                &self.timestamp,
+                self.canonical,
+            ),
+        };
+        match result {
+            VerificationResult::Valid(message) => Ok((*message).with_message_id(message_id)),
+            VerificationResult::Invalid { reason, .. } => Err(invalid(reason)),
+        }
+    }
+}
+
+impl From<&ChatMessage> for TranscriptMessage {
+    fn from(message: &ChatMessage) -> Self {
+        Self {
+            message_id: message.message_id.to_string(),
+            sender_public_key: message.sender_public_key.clone(),
+            message: message.message.clone(),
+            timestamp: message.timestamp.clone(),
+            signature: message.signature.clone(),
+            canonical: message.canonical,
+            sequence: message.sequence,
+        }
+    }
+}
+
+/// A conversation transcript
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+    pub manifest: TranscriptManifest,
+    pub messages: Vec<TranscriptMessage>,
+}
+
+impl Transcript {
+    /// Build a transcript of `messages`, oldest first
+    pub fn new<'a>(
+        conversation: Conversation,
+        messages: impl IntoIterator<Item = &'a ChatMessage>,
+    ) -> Self {
+        let messages: Vec<TranscriptMessage> = messages.into_iter().map(Into::into).collect();
+        let signed_payload = match conversation {
+            Conversation::Direct(_) => TRANSCRIPT_SIGNED_PAYLOAD,
+            Conversation::Room(_) => ROOM_TRANSCRIPT_SIGNED_PAYLOAD,
+        };
+        Self {
+            manifest: TranscriptManifest {
+                version: TRANSCRIPT_VERSION,
+                algorithm: "ed25519".to_string(),
+                signed_payload: signed_payload.to_string(),
+                conversation,
+                exported_at: chrono::Utc::now().to_rfc3339(),
+                message_count: messages.len(),
+            },
+            messages,
+        }
+    }
+
+    /// Serialize as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String, ExportError> {
+        serde_json::to_string_pretty(self).map_err(|e| ExportError::Parse(e.to_string()))
+    }
+
+    /// Render as Markdown, with the JSON transcript in a fenced block at the end
+    pub fn to_markdown(&self) -> Result<String, ExportError> {
+        let title = match &self.manifest.conversation {
+            Conversation::Direct(key) => format!("Conversation with {}", format_public_key(key)),
+            Conversation::Room(name) => format!("Room #{}", name),
+        };
+        let mut out = format!(
+            "# {}\n\nExported {}. {} messages.\n\n",
+            title, self.manifest.exported_at, self.manifest.message_count
+        );
+        for message in &self.messages {
+            out.push_str(&format!(
+                "**{}** · {}\n\n{}\n\n",
+                format_public_key(&message.sender_public_key),
+                message.timestamp,
+                message
+                    .message
+                    .lines()
+                    .map(|line| format!("> {}", line))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ));
+        }
+        out.push_str(&format!(
+            "## Verification\n\nEach signature covers `{}` and can be checked against the \
+             sender's key with ed25519.\n\n{}{}\n```\n",
+            self.manifest.signed_payload,
+            MARKDOWN_JSON_FENCE,
+            self.to_json()?
+        ));
+        Ok(out)
+    }
+
+    /// Parse a transcript from JSON, or from the block embedded in Markdown
+    pub fn parse(text: &str) -> Result<Self, ExportError> {
+        let json = match text.rfind(MARKDOWN_JSON_FENCE) {
+            Some(start) => {
+                let body = &text[start + MARKDOWN_JSON_FENCE.len()..];
+                body.rfind("```").map_or(body, |end| &body[..end])
+            }
+            None => text,
+        };
+        serde_json::from_str(json).map_err(|e| ExportError::Parse(e.to_string()))
+    }
+
+    fn check_version(&self) -> Result<(), ExportError> {
+        if self.manifest.version != TRANSCRIPT_VERSION {
+            return Err(ExportError::Parse(format!(
+                "Unsupported transcript version {}",
+                self.manifest.version
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check the manifest and every message's signature
+    pub fn verify(&self) -> Result<(), ExportError> {
+        self.check_version()?;
+        if self.manifest.message_count != self.messages.len() {
+            return Err(ExportError::Parse(format!(
+                "Manifest lists {} messages, transcript has {}",
+                self.manifest.message_count,
+                self.messages.len()
+            )));
+        }
+        for message in &self.messages {
+            message.verify(&self.manifest.conversation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of importing a transcript
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TranscriptImportSummary {
+    /// Verified messages added to the history
+    pub imported: usize,
+    /// Verified messages already in the history
+    pub duplicates: usize,
+    /// Entries that failed verification, as [`ExportError::InvalidSignature`]
+    pub invalid: Vec<ExportError>,
+}
+
+/// Verify a transcript's messages and merge the valid ones into `history`
+///
+/// In a direct transcript, messages not sent by the peer are rejected too,
+/// so a transcript can't slip messages into another conversation.
+fn merge_transcript(
+    transcript: &Transcript,
+    history: &mut MessageHistory,
+) -> TranscriptImportSummary {
+    let peer = match &transcript.manifest.conversation {
+        Conversation::Direct(public_key) => ConversationId::new(public_key).ok(),
+        Conversation::Room(_) => None,
+    };
+    let mut summary = TranscriptImportSummary::default();
+    for entry in &transcript.messages {
+        let verified = entry
+            .verify(&transcript.manifest.conversation)
+            .and_then(|message| match &peer {
+                Some(peer) if !peer.matches(&message.sender_public_key) => {
+                    Err(ExportError::InvalidSignature {
+                        message_id: entry.message_id.clone(),
+                        reason: "Sender is not the conversation's peer".to_string(),
+                    })
+                }
+                _ => Ok(message),
+            });
+        match verified {
+            Ok(message) => {
+                if history.add_message(message) {
+                    summary.imported += 1;
+                } else {
+                    summary.duplicates += 1;
+                }
+            }
+            Err(e) => summary.invalid.push(e),
+        }
+    }
+    summary
+}
+
+/// Build the transcript of a direct conversation or joined room
+pub async fn create_transcript(
+    message_history: &SharedMessageHistory,
+    rooms_state: &SharedRoomsState,
+    conversation: &Conversation,
+) -> Result<Transcript, ExportError> {
+    match conversation {
+        Conversation::Direct(public_key) => {
+            let peer =
+                ConversationId::new(public_key).map_err(|_| ExportError::UnknownConversation)?;
+            let history = message_history.lock().await;
+            Ok(Transcript::new(
+                conversation.clone(),
+                history
+                    .messages_from(&peer)
+                    .into_iter()
+                    .filter(|message| !message.is_retracted()),
+            ))
+        }
+        Conversation::Room(name) => {
+            let rooms = rooms_state.lock().await;
+            let room = rooms.room(name).ok_or(ExportError::UnknownConversation)?;
+            Ok(Transcript::new(
+                conversation.clone(),
+                room.history.messages(),
+            ))
+        }
+    }
+}
+
+/// Export a conversation transcript to `path`
+///
+/// # Returns
+/// The number of messages exported
+pub async fn handle_export_transcript(
+    message_history: &SharedMessageHistory,
+    rooms_state: &SharedRoomsState,
+    conversation: &Conversation,
+    path: &Path,
+    format: TranscriptFormat,
+) -> Result<usize, ExportError> {
+    let transcript = create_transcript(message_history, rooms_state, conversation).await?;
+    let text = match format {
+        TranscriptFormat::Json => transcript.to_json()?,
+        TranscriptFormat::Markdown => transcript.to_markdown()?,
+    };
+    std::fs::write(path, text).map_err(|e| ExportError::Io(e.to_string()))?;
+    Ok(transcript.messages.len())
+}
+
+/// Read a transcript file and check every signature in it
+///
+/// # Returns
+/// The verified transcript
+pub fn verify_transcript_file(path: &Path) -> Result<Transcript, ExportError> {
+    let text = std::fs::read_to_string(path).map_err(|e| ExportError::Io(e.to_string()))?;
+    let transcript = Transcript::parse(&text)?;
+    transcript.verify()?;
+    Ok(transcript)
+}
+
+/// Import a transcript file into the conversation it was exported from
+///
+/// A direct transcript is merged into the message history, a room
+/// transcript into the room's history, which requires being a member.
+///
+/// # Returns
+/// How many messages were imported, already present or invalid
+pub async fn handle_import_transcript(
+    message_history: &SharedMessageHistory,
+    rooms_state: &SharedRoomsState,
+    path: &Path,
+) -> Result<TranscriptImportSummary, ExportError> {
+    let text = std::fs::read_to_string(path).map_err(|e| ExportError::Io(e.to_string()))?;
+    let transcript = Transcript::parse(&text)?;
+    transcript.check_version()?;
+    match &transcript.manifest.conversation {
+        Conversation::Direct(public_key) => {
+            ConversationId::new(public_key).map_err(|_| ExportError::UnknownConversation)?;
+            let mut history = message_history.lock().await;
+            Ok(merge_transcript(&transcript, &mut history))
+        }
+        Conversation::Room(name) => {
+            let mut rooms = rooms_state.lock().await;
+            let room = rooms
+                .room_mut(name)
+                .ok_or(ExportError::UnknownConversation)?;
+            Ok(merge_transcript(&transcript, &mut room.history))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::messages::create_shared_message_history;
+    use crate::state::rooms::create_shared_rooms_state;
+    use profile_shared::canonical;
+    use profile_shared::{derive_public_key, generate_private_key, sign_message};
+
+    fn signed(text: &str, timestamp: &str) -> ChatMessage {
+        signed_over(
+            text,
+            timestamp,
+            &canonical::message(CanonicalVersion::CURRENT, text, timestamp),
+        )
+    }
+
+    fn signed_in_room(room: &str, text: &str, timestamp: &str) -> ChatMessage {
+        signed_over(
+            text,
+            timestamp,
+            &canonical::room_message(CanonicalVersion::CURRENT, room, text, timestamp),
+        )
+    }
+
+    fn signed_over(text: &str, timestamp: &str, canonical_message: &[u8]) -> ChatMessage {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let signature = sign_message(&private_key, canonical_message).unwrap();
+        ChatMessage::verified(
+            hex::encode(&public_key),
+            text.to_string(),
+            hex::encode(signature),
+            timestamp.to_string(),
+        )
+        .with_canonical(CanonicalVersion::CURRENT)
+    }
+
+    #[tokio::test]
+    async fn test_export_direct_and_verify_both_formats() {
+        let history = create_shared_message_history();
+        let rooms = create_shared_rooms_state();
+        let message = signed("hello\nthere", "2025-12-27T10:00:00Z");
+        let peer = Conversation::Direct(message.sender_public_key.clone());
+        history.lock().await.add_message(message.clone());
+        history
+            .lock()
+            .await
+            .add_message(signed("someone else", "2025-12-27T10:01:00Z"));
+
+        for (name, format) in [
+            ("json", TranscriptFormat::Json),
+            ("md", TranscriptFormat::Markdown),
+        ] {
+            let path = std::env::temp_dir().join(format!(
+                "profile-transcript-{}.{}",
+                std::process::id(),
+                name
+            ));
+            assert_eq!(TranscriptFormat::from_path(&path), format);
+            let count = handle_export_transcript(&history, &rooms, &peer, &path, format)
+                .await
+                .unwrap();
+            assert_eq!(count, 1);
+
+            let transcript = verify_transcript_file(&path).unwrap();
+            assert_eq!(transcript.manifest.conversation, peer);
+            assert_eq!(transcript.messages[0].message, "hello\nthere");
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_merges_verified_messages_and_reports_invalid() {
+        let rooms = create_shared_rooms_state();
+        let first = signed("first", "2025-12-27T10:00:00Z");
+        let peer = first.sender_public_key.clone();
+        let mut transcript = Transcript::new(Conversation::Direct(peer.clone()), [&first]);
+        transcript
+            .messages
+            .push((&signed("from someone else", "2025-12-27T10:01:00Z")).into());
+        let mut forged = TranscriptMessage::from(&first);
+        forged.message_id = Uuid::new_v4().to_string();
+        forged.message = "forged".to_string();
+        transcript.messages.push(forged.clone());
+        transcript.manifest.message_count = transcript.messages.len();
+
+        let path = std::env::temp_dir().join(format!("profile-import-{}.json", std::process::id()));
+        std::fs::write(&path, transcript.to_json().unwrap()).unwrap();
+
+        let history = create_shared_message_history();
+        let summary = handle_import_transcript(&history, &rooms, &path)
+            .await
+            .unwrap();
+        assert_eq!(summary.imported, 1);
+        assert_eq!(summary.duplicates, 0);
+        assert_eq!(summary.invalid.len(), 2);
+        assert!(summary.invalid.iter().any(|e| matches!(
+            e,
+            ExportError::InvalidSignature { message_id, .. } if *message_id == forged.message_id
+        )));
+        {
+            let history = history.lock().await;
+            assert_eq!(history.len(), 1);
+            let stored = history.newest().unwrap();
+            assert_eq!(stored.message_id, first.message_id);
+            assert!(stored.is_verified);
+        }
+
+        let again = handle_import_transcript(&history, &rooms, &path)
+            .await
+            .unwrap();
+        assert_eq!((again.imported, again.duplicates), (0, 1));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tampered_transcript_rejected() {
+        let rooms = create_shared_rooms_state();
+        let message = signed_in_room("general", "pay 10", "2025-12-27T10:00:00Z");
+        rooms.lock().await.apply_room_update(
+            "general",
+            vec!["me".to_string(), message.sender_public_key.clone()],
+            "me",
+        );
+        rooms
+            .lock()
+            .await
+            .add_room_message("general", message.clone());
+
+        let general = Conversation::Room("general".to_string());
+        let mut transcript = create_transcript(&create_shared_message_history(), &rooms, &general)
+            .await
+            .unwrap();
+        assert_eq!(transcript.verify(), Ok(()));
+        assert_eq!(
+            transcript.manifest.signed_payload,
+            ROOM_TRANSCRIPT_SIGNED_PAYLOAD
+        );
+
+        // Signed for one room, so it can't be passed off as another's
+        let mut moved = transcript.clone();
+        moved.manifest.conversation = Conversation::Room("random".to_string());
+        assert!(matches!(
+            moved.verify(),
+            Err(ExportError::InvalidSignature { .. })
+        ));
+
+        transcript.messages[0].message = "pay 1000".to_string();
+        assert!(matches!(
+            transcript.verify(),
+            Err(ExportError::InvalidSignature { message_id, .. })
+                if message_id == message.message_id.to_string()
+        ));
+        assert_eq!(
+            create_transcript(
+                &create_shared_message_history(),
+                &rooms,
+                &Conversation::Room("random".to_string())
+            )
+            .await,
+            Err(ExportError::UnknownConversation)
+        );
+    }
+    #[test]
+    fn test_entries_without_canonical_version_are_legacy() {
+        let private_key = generate_private_key().unwrap();
+        let sender = hex::encode(derive_public_key(&private_key).unwrap());
+        let timestamp = "2025-12-27T10:00:00Z";
+        let legacy_signature = sign_message(
+            &private_key,
+            &canonical::message(CanonicalVersion::V1, "old", timestamp),
+        )
+        .unwrap();
+        let json = serde_json::json!({
+            "messageId": Uuid::new_v4().to_string(),
+            "senderPublicKey": sender,
+            "message": "old",
+            "timestamp": timestamp,
+            "signature": hex::encode(legacy_signature),
+        });
+        let entry: TranscriptMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(entry.canonical, CanonicalVersion::V1);
+        let direct = Conversation::Direct(sender.clone());
+        assert!(entry.verify(&direct).is_ok());
+
+        // Newer entries name their version, and keep it on a round trip
+        let current = TranscriptMessage::from(&signed("new", timestamp));
+        let json = serde_json::to_value(&current).unwrap();
+        assert_eq!(json["canonical"], 2);
+        let parsed: TranscriptMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            parsed.verify(&direct).unwrap().canonical,
+            CanonicalVersion::CURRENT
+        );
+    }
+
+    #[test]
+    fn test_sequenced_entries_verify_over_their_sequence() {
+        let timestamp = "2025-12-27T10:00:00Z";
+        let message = signed_over(
+            "numbered",
+            timestamp,
+            &canonical::sequenced_message("numbered", timestamp, 7),
+        )
+        .with_sequence(Some(7));
+        let direct = Conversation::Direct(message.sender_public_key.clone());
+
+        let entry = TranscriptMessage::from(&message);
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["sequence"], 7);
+        let parsed: TranscriptMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.verify(&direct).unwrap().sequence, Some(7));
+
+        // The signature doesn't cover another number, or none at all
+        for sequence in [Some(8), None] {
+            let tampered = TranscriptMessage {
+                sequence,
+                ..entry.clone()
+            };
+            assert!(matches!(
+                tampered.verify(&direct),
+                Err(ExportError::InvalidSignature { .. })
+            ));
+        }
+        // Unnumbered entries leave the field out
+        let unnumbered = TranscriptMessage::from(&signed("plain", timestamp));
+        assert!(serde_json::to_value(&unnumbered)
+            .unwrap()
+            .get("sequence")
+            .is_none());
+    }
+}
@@ -0,0 +1,273 @@
+//! Key escrow handlers
+//!
+//! Seal the user's conversation keys into the local escrow file and,
+//! optionally, build the signed `backup_store` request that keeps a copy of
+//! the sealed escrow as the user's server backup. A `backup_fetch` answer
+//! can be opened again with [`restore_key_escrow_backup`] after a reinstall.
+//!
+//! Opening the local escrow is throttled after wrong passphrases, see
+//! `state::unlock_attempts`, as the settings' `wipeEscrowAfter` says.
+
+use crate::config::ClientConfig;
+use crate::handlers::compose::ComposeError;
+use crate::state::key_escrow::{
+    default_key_escrow_path, ConversationKeys, KeyEscrow, KeyEscrowError,
+};
+use crate::state::session::SharedKeyState;
+use crate::state::unlock_attempts::{unlock_with_lockout, LockoutPolicy};
+use chrono::{DateTime, Utc};
+use profile_shared::canonical;
+use profile_shared::crypto::sign_message;
+use std::path::Path;
+
+/// Seal `keys` under `passphrase` and save the escrow to `path`
+///
+/// # Returns
+/// The saved escrow, ready for [`compose_backup_store`]
+pub fn handle_save_key_escrow(
+    keys: &ConversationKeys,
+    passphrase: &str,
+    path: &Path,
+) -> Result<KeyEscrow, KeyEscrowError> {
+    let escrow = KeyEscrow::seal(keys, passphrase)?;
+    escrow.save(path)?;
+    Ok(escrow)
+}
+
+/// Open the escrow saved at `path`
+///
+/// While a lockout from earlier wrong passphrases runs, every attempt is
+/// refused with `KeyEscrowError::Locked`, even with the right passphrase.
+/// When `config` erases the escrow (`wipeEscrowAfter`), the failure that
+/// reaches its limit deletes the escrow file and returns
+/// `KeyEscrowError::Wiped`.
+///
+/// # Returns
+/// Ok(None) if there is no escrow file
+pub fn handle_open_key_escrow(
+    passphrase: &str,
+    path: &Path,
+    config: &ClientConfig,
+) -> Result<Option<ConversationKeys>, KeyEscrowError> {
+    open_key_escrow_at(passphrase, path, &config.lockout_policy(), Utc::now())
+}
+
+/// Open the escrow at its default location, throttled as the saved
+/// settings say
+///
+/// # Returns
+/// Ok(None) if there is no escrow file or no location for one
+pub fn handle_open_default_key_escrow(
+    passphrase: &str,
+) -> Result<Option<ConversationKeys>, KeyEscrowError> {
+    match default_key_escrow_path() {
+        Some(path) => handle_open_key_escrow(passphrase, &path, &crate::config::load_settings()),
+        None => Ok(None),
+    }
+}
+
+fn open_key_escrow_at(
+    passphrase: &str,
+    path: &Path,
+    policy: &LockoutPolicy,
+    now: DateTime<Utc>,
+) -> Result<Option<ConversationKeys>, KeyEscrowError> {
+    let Some(escrow) = KeyEscrow::load(path)? else {
+        return Ok(None);
+    };
+    unlock_with_lockout(path, policy, now, || escrow.open(passphrase)).map(Some)
+}
+
+/// Sign a `backup_store` request carrying the sealed escrow
+///
+/// The signature covers [`canonical::backup_store`], with the blob
+/// hex-encoded.
+///
+/// # Arguments
+/// * `escrow` - The sealed escrow to upload
+/// * `version` - Must be greater than the version of the stored backup
+/// * `key_state` - Shared state containing the user's private key
+///
+/// # Returns
+/// Ok(String) containing the request JSON for WebSocket transmission
+pub async fn compose_backup_store(
+    escrow: &KeyEscrow,
+    version: u64,
+    key_state: &SharedKeyState,
+) -> Result<String, ComposeError> {
+    let blob = escrow
+        .to_backup_blob()
+        .map_err(|e| ComposeError::SerializationError(e.to_string()))?;
+    let blob = hex::encode(blob);
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let signature = {
+        let key_guard = key_state.lock().await;
+        let private_key = key_guard.private_key().ok_or(ComposeError::NoPrivateKey)?;
+        sign_message(
+            private_key,
+            &canonical::backup_store(version, &blob, &timestamp),
+        )
+        .map_err(|e| ComposeError::SigningError(e.to_string()))?
+    };
+
+    let request = serde_json::json!({
+        "type": "backup_store",
+        "version": version,
+        "blob": blob,
+        "signature": hex::encode(signature),
+        "timestamp": timestamp,
+    });
+    serde_json::to_string(&request).map_err(|e| ComposeError::SerializationError(e.to_string()))
+}
+
+/// Create a request for the user's stored backup
+pub fn create_backup_fetch_request() -> String {
+    serde_json::json!({ "type": "backup_fetch" }).to_string()
+}
+
+/// Open an escrow received in a `Backup` message
+///
+/// # Arguments
+/// * `blob` - The message's hex-encoded blob
+/// * `passphrase` - The passphrase the escrow was sealed with
+pub fn restore_key_escrow_backup(
+    blob: &str,
+    passphrase: &str,
+) -> Result<ConversationKeys, KeyEscrowError> {
+    let bytes = hex::decode(blob).map_err(|e| KeyEscrowError::Corrupt(e.to_string()))?;
+    KeyEscrow::from_backup_blob(&bytes)?.open(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::key_escrow::KdfParams;
+    use crate::state::rooms::Conversation;
+    use crate::state::session::create_shared_key_state;
+    use crate::state::unlock_attempts::unlock_attempts_path;
+    use profile_shared::{derive_public_key, generate_private_key, verify_signature};
+
+    #[tokio::test]
+    async fn test_backup_store_carries_sealed_escrow() {
+        let mut keys = ConversationKeys::new();
+        keys.insert(&Conversation::Room("general".to_string()), &[3u8; 32])
+            .unwrap();
+        let kdf = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let escrow = KeyEscrow::seal_with_params(&keys, "a long passphrase", kdf).unwrap();
+
+        let key_state = create_shared_key_state();
+        assert!(matches!(
+            compose_backup_store(&escrow, 1, &key_state).await,
+            Err(ComposeError::NoPrivateKey)
+        ));
+
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key.clone());
+
+        let json = compose_backup_store(&escrow, 1, &key_state).await.unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["type"], "backup_store");
+        let blob = value["blob"].as_str().unwrap();
+        let canonical = canonical::backup_store(1, blob, value["timestamp"].as_str().unwrap());
+        let signature = hex::decode(value["signature"].as_str().unwrap()).unwrap();
+        assert!(verify_signature(&public_key, &canonical, &signature).is_ok());
+
+        let restored = restore_key_escrow_backup(blob, "a long passphrase").unwrap();
+        assert_eq!(restored, keys);
+    }
+
+    #[test]
+    fn test_wrong_passphrases_lock_then_wipe_escrow() {
+        let dir = std::env::temp_dir().join(format!("profile-unlock-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("key_escrow.json");
+        let kdf = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        KeyEscrow::seal_with_params(&ConversationKeys::new(), "a long passphrase", kdf)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let policy = LockoutPolicy {
+            free_attempts: 1,
+            base_delay: std::time::Duration::from_secs(30),
+            wipe_after: Some(3),
+            ..LockoutPolicy::default()
+        };
+        let now = Utc::now();
+        let open = |passphrase, at| open_key_escrow_at(passphrase, &path, &policy, at);
+
+        assert_eq!(
+            open("wrong passphrase", now),
+            Err(KeyEscrowError::WrongPassphrase)
+        );
+        assert_eq!(
+            open("wrong passphrase", now),
+            Err(KeyEscrowError::WrongPassphrase)
+        );
+        // Locked out, even with the right passphrase
+        assert_eq!(
+            open("a long passphrase", now),
+            Err(KeyEscrowError::Locked {
+                retry_after_secs: 30
+            })
+        );
+
+        // Success clears the record
+        let later = now + chrono::Duration::seconds(30);
+        assert!(open("a long passphrase", later).unwrap().is_some());
+        assert!(!unlock_attempts_path(&path).exists());
+
+        for _ in 0..2 {
+            assert_eq!(
+                open("wrong passphrase", later),
+                Err(KeyEscrowError::WrongPassphrase)
+            );
+        }
+        let much_later = later + chrono::Duration::hours(1);
+        assert_eq!(
+            open("wrong passphrase", much_later),
+            Err(KeyEscrowError::Wiped)
+        );
+        assert!(!path.exists());
+        assert_eq!(open("a long passphrase", much_later), Ok(None));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_follows_configured_wipe_limit() {
+        let dir = std::env::temp_dir().join(format!("profile-unlock-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("key_escrow.json");
+        let kdf = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        KeyEscrow::seal_with_params(&ConversationKeys::new(), "a long passphrase", kdf)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let config = ClientConfig {
+            wipe_escrow_after: Some(1),
+            ..ClientConfig::default()
+        };
+
+        assert_eq!(
+            handle_open_key_escrow("wrong passphrase", &path, &config),
+            Err(KeyEscrowError::Wiped)
+        );
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
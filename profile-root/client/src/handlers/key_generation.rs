@@ -10,6 +10,33 @@ pub async fn handle_generate_new_key(key_state: &SharedKeyState) -> Result<Strin
     crate::state::handle_generate_key_async(key_state).await
 }
 
+/// Handle the "Continue as Guest" button press
+///
+/// Like [`handle_generate_new_key`], but the key is a throwaway guest
+/// identity that only lives in memory until the client closes
+pub async fn handle_generate_guest_key(key_state: &SharedKeyState) -> Result<String, String> {
+    crate::state::handle_generate_guest_key_async(key_state).await
+}
+
+/// Handle the "Show Recovery Phrase" button press
+///
+/// Returns the session's private key as a 24-word BIP39 mnemonic so it can
+/// be written down and later restored with `handle_import_key`. Guest
+/// identities are never exported.
+pub async fn handle_export_mnemonic(key_state: &SharedKeyState) -> Result<String, String> {
+    let state = key_state.lock().await;
+    if state.is_ephemeral() {
+        return Err(
+            "Guest identities have no recovery phrase. Generate a key to keep one.".to_string(),
+        );
+    }
+    let private_key = state
+        .private_key()
+        .ok_or_else(|| "No key to export. Generate or import a key first.".to_string())?;
+    profile_shared::export_private_key_mnemonic(private_key)
+        .map_err(|e| format!("Cannot create a recovery phrase for this key: {}", e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -98,4 +125,27 @@ mod tests {
             "State should contain last generated key"
         );
     }
+
+    #[tokio::test]
+    async fn test_export_mnemonic_round_trips_through_import() {
+        let key_state = create_shared_key_state();
+        assert!(handle_export_mnemonic(&key_state).await.is_err());
+
+        let public_key = handle_generate_new_key(&key_state).await.unwrap();
+        let phrase = handle_export_mnemonic(&key_state).await.unwrap();
+        assert_eq!(
+            phrase.split(' ').count(),
+            profile_shared::MNEMONIC_WORD_COUNT
+        );
+
+        // A guest identity cannot be exported
+        handle_generate_guest_key(&key_state).await.unwrap();
+        assert!(handle_export_mnemonic(&key_state).await.is_err());
+
+        let restored = create_shared_key_state();
+        let imported = crate::handlers::handle_import_key(&restored, phrase)
+            .await
+            .unwrap();
+        assert_eq!(imported, public_key);
+    }
 }
@@ -1,13 +1,28 @@
 //! Key import handler - validates and imports user-provided private keys
-
+//!
+//! Keys can also be moved between devices as passphrase-protected key
+//! files (see `state::key_file`). Unlocking one is throttled by the lockout
+//! policy in the client settings, like the key escrow.
+
+use crate::config::ClientConfig;
+use crate::state::key_escrow::KeyEscrowError;
+use crate::state::key_file::SealedKeyFile;
+use crate::state::unlock_attempts::{unlock_with_lockout, LockoutPolicy};
 use crate::state::SharedKeyState;
-use profile_shared::{derive_public_key, PrivateKey};
+use chrono::{DateTime, Utc};
+use profile_shared::{
+    derive_private_key_from_mnemonic, derive_public_key, PrivateKey, MNEMONIC_WORD_COUNT,
+};
+use std::path::Path;
 
 /// Handle the "Import Key" button press
 ///
 /// Validates user input through 10 steps, then imports the key into session state.
 /// Returns the derived public key as hex for UI display.
 ///
+/// Input containing more than one word is treated as a 24-word BIP39
+/// recovery phrase (see [`import_mnemonic`]) instead of a hex key.
+///
 /// # Validation Steps
 /// 1. Trim whitespace (users paste with trailing newlines)
 /// 2. Check for empty input (better error message)
@@ -28,9 +43,15 @@ pub async fn handle_import_key(
 
     // Step 2a: Check for empty input (better error message)
     if trimmed.is_empty() {
-        return Err(
-            "No private key entered. Please paste your 64-character hexadecimal key.".into(),
-        );
+        return Err(format!(
+            "No private key entered. Please paste your 64-character hexadecimal key or your {}-word recovery phrase.",
+            MNEMONIC_WORD_COUNT
+        ));
+    }
+
+    if trimmed.split_whitespace().nth(1).is_some() {
+        let private_key = import_mnemonic(trimmed)?;
+        return store_imported_key(key_state, private_key).await;
     }
 
     // Step 2b: Check length BEFORE attempting decode
@@ -70,6 +91,99 @@ pub async fn handle_import_key(
     // Wrap in zeroize-protected container (security step before validation)
     let private_key: PrivateKey = profile_shared::PrivateKey::new(key_bytes);
 
+    store_imported_key(key_state, private_key).await
+}
+
+/// Handle importing a passphrase-protected key file
+///
+/// While a lockout from earlier wrong passphrases runs, every attempt is
+/// refused, even with the right passphrase. When `config` erases files
+/// after too many failures (`wipeEscrowAfter`), the failure that reaches
+/// the limit deletes the key file.
+///
+/// # Returns
+/// The imported key's public key as hex, like [`handle_import_key`]
+pub async fn handle_import_key_file(
+    key_state: &SharedKeyState,
+    path: &Path,
+    passphrase: &str,
+    config: &ClientConfig,
+) -> Result<String, String> {
+    import_key_file_at(
+        key_state,
+        path,
+        passphrase,
+        &config.lockout_policy(),
+        Utc::now(),
+    )
+    .await
+}
+
+/// Seal the session's private key under `passphrase` and save it to `path`
+pub async fn handle_save_key_file(
+    key_state: &SharedKeyState,
+    path: &Path,
+    passphrase: &str,
+) -> Result<(), String> {
+    let sealed = {
+        let state = key_state.lock().await;
+        let private_key = state
+            .private_key()
+            .ok_or_else(|| "No key to save. Generate or import a key first.".to_string())?;
+        SealedKeyFile::seal(private_key, passphrase).map_err(|e| key_file_error(&e))?
+    };
+    sealed.save(path).map_err(|e| key_file_error(&e))
+}
+
+async fn import_key_file_at(
+    key_state: &SharedKeyState,
+    path: &Path,
+    passphrase: &str,
+    policy: &LockoutPolicy,
+    now: DateTime<Utc>,
+) -> Result<String, String> {
+    let sealed = SealedKeyFile::load(path)
+        .map_err(|e| key_file_error(&e))?
+        .ok_or_else(|| format!("No key file found at {}.", path.display()))?;
+    let private_key = unlock_with_lockout(path, policy, now, || sealed.open(passphrase))
+        .map_err(|e| key_file_error(&e))?;
+    store_imported_key(key_state, private_key).await
+}
+
+/// Describe a key file error in the terms of a key file, not the escrow
+fn key_file_error(error: &KeyEscrowError) -> String {
+    match error {
+        KeyEscrowError::WrongPassphrase => {
+            "Wrong passphrase, or the key file was modified.".to_string()
+        }
+        KeyEscrowError::Locked { retry_after_secs } => format!(
+            "Too many wrong passphrases. Try again in {} seconds.",
+            retry_after_secs
+        ),
+        KeyEscrowError::Wiped => "Too many wrong passphrases. The key file was erased.".to_string(),
+        KeyEscrowError::WeakPassphrase => error.to_string(),
+        other => format!("Cannot use the key file: {}", other),
+    }
+}
+
+/// Recover a private key from a BIP39 recovery phrase
+///
+/// Errors name the offending word by position only, never by content.
+fn import_mnemonic(phrase: &str) -> Result<PrivateKey, String> {
+    derive_private_key_from_mnemonic(phrase).map_err(|e| match e {
+        profile_shared::CryptoError::InvalidMnemonic(reason) => format!(
+            "{}. A recovery phrase is {} words from the BIP39 English word list, separated by spaces.",
+            reason, MNEMONIC_WORD_COUNT
+        ),
+        other => format!("Cannot recover a key from this phrase: {}", other),
+    })
+}
+
+/// Validate a decoded private key and store it in session state
+async fn store_imported_key(
+    key_state: &SharedKeyState,
+    private_key: PrivateKey,
+) -> Result<String, String> {
     // Step 7: Verify key derivation works (validates key is usable)
     let public_key = derive_public_key(&private_key)
         .map_err(|e| format!("Cannot derive public key from this private key: {}", e))?;
@@ -114,7 +228,12 @@ pub async fn handle_import_key(
 mod tests {
     use super::*;
     use crate::state::create_shared_key_state;
+    use crate::state::key_escrow::KdfParams;
+    use crate::state::unlock_attempts::unlock_attempts_path;
     use profile_shared::generate_private_key;
+    use std::time::Duration;
+
+    const PASSPHRASE: &str = "correct horse battery";
 
     #[tokio::test]
     async fn test_import_valid_key_success() {
@@ -279,4 +398,127 @@ mod tests {
             err
         );
     }
+
+    #[tokio::test]
+    async fn test_import_recovery_phrase() {
+        let key_state = create_shared_key_state();
+        let private_key = generate_private_key().unwrap();
+        let phrase = profile_shared::export_private_key_mnemonic(&private_key).unwrap();
+
+        let from_phrase = handle_import_key(&key_state, format!("{}\n", phrase))
+            .await
+            .unwrap();
+        let expected = hex::encode(derive_public_key(&private_key).unwrap().as_slice());
+        assert_eq!(from_phrase, expected);
+        assert!(key_state.lock().await.is_key_set());
+    }
+
+    #[tokio::test]
+    async fn test_import_recovery_phrase_errors_are_helpful() {
+        let key_state = create_shared_key_state();
+        let phrase =
+            profile_shared::export_private_key_mnemonic(&generate_private_key().unwrap()).unwrap();
+        let words: Vec<&str> = phrase.split(' ').collect();
+
+        let err = handle_import_key(&key_state, words[..23].join(" "))
+            .await
+            .unwrap_err();
+        assert!(err.contains("Expected 24 words, got 23"), "got: {}", err);
+
+        let mut typo = words.clone();
+        typo[9] = "notaword";
+        let err = handle_import_key(&key_state, typo.join(" "))
+            .await
+            .unwrap_err();
+        assert!(err.contains("Word 10"), "got: {}", err);
+        assert!(!err.contains("notaword"));
+        assert!(!key_state.lock().await.is_key_set());
+    }
+
+    #[tokio::test]
+    async fn test_key_file_import_is_throttled_then_wiped() {
+        let dir = std::env::temp_dir().join(format!("profile-key-file-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("identity.json");
+        let private_key = generate_private_key().unwrap();
+        let fast = KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+        SealedKeyFile::seal_with_params(&private_key, PASSPHRASE, fast)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let policy = LockoutPolicy {
+            free_attempts: 1,
+            base_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(60),
+            wipe_after: Some(3),
+        };
+        let key_state = create_shared_key_state();
+        let now = Utc::now();
+        let import = |passphrase: &'static str, at: DateTime<Utc>| {
+            let key_state = key_state.clone();
+            let path = path.clone();
+            let policy = policy.clone();
+            async move { import_key_file_at(&key_state, &path, passphrase, &policy, at).await }
+        };
+
+        // The free attempt, then a failure that starts the lockout
+        for _ in 0..2 {
+            let err = import("wrong passphrase!", now).await.unwrap_err();
+            assert!(err.contains("Wrong passphrase"), "got: {}", err);
+        }
+        let err = import(PASSPHRASE, now).await.unwrap_err();
+        assert!(err.contains("Try again in 60 seconds"), "got: {}", err);
+        assert!(!key_state.lock().await.is_key_set());
+
+        // Once it runs out the right passphrase works and clears the record
+        let later = now + chrono::Duration::seconds(61);
+        let public_key = import(PASSPHRASE, later).await.unwrap();
+        assert_eq!(
+            public_key,
+            hex::encode(derive_public_key(&private_key).unwrap())
+        );
+        assert!(!unlock_attempts_path(&path).exists());
+
+        // Three failures in a row erase the file
+        import("wrong passphrase!", later).await.unwrap_err();
+        import("wrong passphrase!", later).await.unwrap_err();
+        let err = import("wrong passphrase!", later + chrono::Duration::seconds(61))
+            .await
+            .unwrap_err();
+        assert!(err.contains("erased"), "got: {}", err);
+        assert!(!path.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_saved_key_file_imports_with_configured_policy() {
+        let dir = std::env::temp_dir().join(format!("profile-key-file-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("identity.json");
+        let key_state = create_shared_key_state();
+        let err = handle_save_key_file(&key_state, &path, PASSPHRASE)
+            .await
+            .unwrap_err();
+        assert!(err.contains("No key to save"), "got: {}", err);
+
+        let private_key = generate_private_key().unwrap();
+        let expected = handle_import_key(&key_state, hex::encode(private_key.as_slice()))
+            .await
+            .unwrap();
+        handle_save_key_file(&key_state, &path, PASSPHRASE)
+            .await
+            .unwrap();
+
+        let other_device = create_shared_key_state();
+        let imported =
+            handle_import_key_file(&other_device, &path, PASSPHRASE, &ClientConfig::default())
+                .await
+                .unwrap();
+        assert_eq!(imported, expected);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,255 @@
+//! Identity key rotation
+//!
+//! Rotating starts with [`create_key_rollover`]: a new key is generated and
+//! a `key_rollover` request signed by both keys is sent on the connection
+//! signed in with the old one. The new key is only stored once the server
+//! broadcasts the rollover back, with [`complete_key_rollover`]; until then
+//! the old key stays in use.
+//!
+//! A rollover from someone else is checked again here before anything
+//! changes, since the server could forge one. [`apply_key_rollover`] then
+//! moves their contact to the new key with its label and trust, blocks the
+//! new key if the old one was blocked, and drops the ratchet sessions with
+//! the old key.
+
+use crate::state::blocklist::{BlocklistError, SharedBlocklist};
+use crate::state::contacts::{Contact, ContactError, SharedContacts};
+use crate::state::conversation::ConversationId;
+use crate::state::ratchet_sessions::{RatchetSessions, SharedRatchetSessions};
+use crate::state::session::SharedKeyState;
+use profile_shared::crypto::rollover::KeyRollover;
+use profile_shared::{derive_public_key, generate_private_key, CryptoError, PrivateKey};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Value of the `type` field of a key rollover request
+pub const KEY_ROLLOVER_TYPE: &str = "key_rollover";
+
+/// Error types for key rotation
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyRolloverError {
+    /// No key pair is loaded
+    NoKeys,
+    /// Guest identities are thrown away rather than rotated
+    Ephemeral,
+    /// A signature is missing or doesn't verify
+    Crypto(String),
+    /// The rollover doesn't match the one this client sent
+    Unexpected,
+    /// The contact list couldn't be updated
+    Contacts(ContactError),
+    /// The blocklist couldn't be updated
+    Blocklist(BlocklistError),
+}
+
+impl Display for KeyRolloverError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyRolloverError::NoKeys => write!(f, "No key pair available"),
+            KeyRolloverError::Ephemeral => {
+                write!(f, "Guest identities can't be rotated")
+            }
+            KeyRolloverError::Crypto(e) => write!(f, "Key rollover not trusted: {}", e),
+            KeyRolloverError::Unexpected => {
+                write!(f, "Key rollover doesn't match the one requested")
+            }
+            KeyRolloverError::Contacts(e) => write!(f, "Failed to move contact: {}", e),
+            KeyRolloverError::Blocklist(e) => write!(f, "Failed to block new key: {}", e),
+        }
+    }
+}
+
+impl Error for KeyRolloverError {}
+
+impl From<CryptoError> for KeyRolloverError {
+    fn from(e: CryptoError) -> Self {
+        KeyRolloverError::Crypto(e.to_string())
+    }
+}
+
+/// A rotation sent to the server, waiting for its broadcast
+#[derive(Debug)]
+pub struct PendingKeyRollover {
+    /// The key replacing the current one
+    private_key: PrivateKey,
+    /// The signed rollover
+    pub rollover: KeyRollover,
+}
+
+impl PendingKeyRollover {
+    /// The `key_rollover` request to send to the server
+    pub fn request_json(&self) -> String {
+        serde_json::json!({ "type": KEY_ROLLOVER_TYPE, "rollover": self.rollover }).to_string()
+    }
+}
+
+/// Generate a new identity key and sign a rollover to it
+///
+/// The key state is not changed.
+pub async fn create_key_rollover(
+    key_state: &SharedKeyState,
+) -> Result<PendingKeyRollover, KeyRolloverError> {
+    let state = key_state.lock().await;
+    if state.is_ephemeral() {
+        return Err(KeyRolloverError::Ephemeral);
+    }
+    let old_key = state.private_key().ok_or(KeyRolloverError::NoKeys)?;
+    let private_key = generate_private_key()?;
+    let rollover = KeyRollover::sign(old_key, &private_key, &chrono::Utc::now().to_rfc3339())?;
+    Ok(PendingKeyRollover {
+        private_key,
+        rollover,
+    })
+}
+
+/// Switch to the new key once the server broadcast `rollover`
+///
+/// Ratchet sessions belong to the old key and are all dropped.
+///
+/// # Errors
+/// `Unexpected` if `rollover` is not the pending one
+pub async fn complete_key_rollover(
+    pending: PendingKeyRollover,
+    rollover: &KeyRollover,
+    key_state: &SharedKeyState,
+    sessions: &SharedRatchetSessions,
+) -> Result<(), KeyRolloverError> {
+    if pending.rollover != *rollover {
+        return Err(KeyRolloverError::Unexpected);
+    }
+    let public_key = derive_public_key(&pending.private_key)?;
+    key_state
+        .lock()
+        .await
+        .set_generated_key(pending.private_key, public_key);
+    *sessions.lock().await = RatchetSessions::new();
+    Ok(())
+}
+
+/// Carry a peer's contact and block over to the key they rolled over to
+///
+/// # Returns
+/// The migrated contact, None if the old key was not a contact
+pub async fn apply_key_rollover(
+    rollover: &KeyRollover,
+    contacts: &SharedContacts,
+    blocklist: &SharedBlocklist,
+    sessions: &SharedRatchetSessions,
+) -> Result<Option<Contact>, KeyRolloverError> {
+    rollover.verify()?;
+    let (old, new) = (&rollover.old_public_key, &rollover.new_public_key);
+
+    if let Ok(old) = ConversationId::new(old) {
+        sessions.lock().await.forget(&old);
+    }
+    {
+        let mut blocklist = blocklist.lock().await;
+        if blocklist.is_blocked(old) {
+            blocklist.block(new).map_err(KeyRolloverError::Blocklist)?;
+            blocklist.save().map_err(KeyRolloverError::Blocklist)?;
+        }
+    }
+
+    let mut contacts = contacts.lock().await;
+    if contacts.get(old).is_none() {
+        return Ok(None);
+    }
+    let contact = contacts
+        .migrate(old, new)
+        .map_err(KeyRolloverError::Contacts)?
+        .clone();
+    contacts.save().map_err(KeyRolloverError::Contacts)?;
+    Ok(Some(contact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::blocklist::create_shared_blocklist;
+    use crate::state::contacts::{create_shared_contacts, TrustStatus};
+    use crate::state::ratchet_sessions::create_shared_ratchet_sessions;
+    use crate::state::session::{create_shared_key_state, handle_generate_key_async};
+
+    #[tokio::test]
+    async fn test_own_rollover_switches_key_when_confirmed() {
+        let key_state = create_shared_key_state();
+        let old_public = handle_generate_key_async(&key_state).await.unwrap();
+        let sessions = create_shared_ratchet_sessions();
+
+        let pending = create_key_rollover(&key_state).await.unwrap();
+        let rollover = pending.rollover.clone();
+        assert_eq!(rollover.old_public_key, old_public);
+        assert!(pending.request_json().contains(KEY_ROLLOVER_TYPE));
+        let current = key_state.lock().await.public_key().map(hex::encode);
+        assert_eq!(current.as_deref(), Some(old_public.as_str()));
+
+        let mut other = rollover.clone();
+        other.timestamp = "2020-01-01T00:00:00Z".to_string();
+        let pending_again = create_key_rollover(&key_state).await.unwrap();
+        assert_eq!(
+            complete_key_rollover(pending_again, &other, &key_state, &sessions).await,
+            Err(KeyRolloverError::Unexpected)
+        );
+
+        complete_key_rollover(pending, &rollover, &key_state, &sessions)
+            .await
+            .unwrap();
+        let current = key_state.lock().await.public_key().map(hex::encode);
+        assert_eq!(current, Some(rollover.new_public_key));
+    }
+
+    #[tokio::test]
+    async fn test_peer_rollover_moves_contact_and_block() {
+        let (old, new) = (
+            generate_private_key().unwrap(),
+            generate_private_key().unwrap(),
+        );
+        let rollover = KeyRollover::sign(&old, &new, &chrono::Utc::now().to_rfc3339()).unwrap();
+        let contacts = create_shared_contacts();
+        let blocklist = create_shared_blocklist();
+        let sessions = create_shared_ratchet_sessions();
+        contacts
+            .lock()
+            .await
+            .add(&rollover.old_public_key, "Alice")
+            .unwrap();
+        contacts
+            .lock()
+            .await
+            .set_trust(&rollover.old_public_key, TrustStatus::Verified)
+            .unwrap();
+        blocklist
+            .lock()
+            .await
+            .block(&rollover.old_public_key)
+            .unwrap();
+
+        let mut forged = rollover.clone();
+        forged.new_signature = forged.old_signature.clone();
+        assert!(matches!(
+            apply_key_rollover(&forged, &contacts, &blocklist, &sessions).await,
+            Err(KeyRolloverError::Crypto(_))
+        ));
+        assert!(contacts
+            .lock()
+            .await
+            .get(&rollover.new_public_key)
+            .is_none());
+
+        let contact = apply_key_rollover(&rollover, &contacts, &blocklist, &sessions)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(contact.label, "Alice");
+        assert_eq!(contact.trust, TrustStatus::Verified);
+        assert!(blocklist.lock().await.is_blocked(&rollover.new_public_key));
+
+        // Already moved: nothing left to migrate
+        assert_eq!(
+            apply_key_rollover(&rollover, &contacts, &blocklist, &sessions)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -3,8 +3,14 @@
 //! This module provides handlers for lobby UI events including
 //! user selection, keyboard navigation, and chat activation.
 
-use crate::state::SharedLobbyState;
-use crate::ui::lobby_state::LobbyUser;
+use crate::handlers::compose::ComposeError;
+use crate::state::conversation::ConversationId;
+use crate::state::session::SharedKeyState;
+use crate::state::{LobbyCacheError, LobbyCacheWriter, SharedLobbyState};
+use crate::ui::lobby_state::{LobbyPage, LobbyUser};
+use profile_shared::canonical;
+use profile_shared::crypto::sign_message;
+use profile_shared::protocol::Status;
 
 /// Handler for lobby user selection events
 ///
@@ -87,6 +93,18 @@ pub async fn handle_lobby_state_update(lobby_state: &SharedLobbyState, users: Ve
     state.set_users(users);
 }
 
+/// Count a received message towards its sender's unread badge
+///
+/// # Returns
+/// `true` if the badge changed (the sender isn't the selected user)
+pub async fn handle_lobby_message_received(
+    lobby_state: &SharedLobbyState,
+    sender: &ConversationId,
+) -> bool {
+    let mut state = lobby_state.lock().await;
+    state.record_message(sender)
+}
+
 /// Clear lobby selection
 pub async fn clear_lobby_selection(lobby_state: &SharedLobbyState) {
     let mut state = lobby_state.lock().await;
@@ -189,11 +207,290 @@ pub async fn handle_lobby_delta(
     selected_left
 }
 
+/// Create a lobby page request
+///
+/// # Arguments
+/// * `cursor` - `next_cursor` from the previous page (None for the first page)
+/// * `limit` - Number of users to request (the server caps this)
+/// * `prefix` - Optional hex prefix to search public keys by
+///
+/// # Returns
+/// JSON string ready to send to the server
+pub fn create_lobby_page_request(
+    cursor: Option<&str>,
+    limit: Option<usize>,
+    prefix: Option<&str>,
+) -> String {
+    serde_json::json!({
+        "type": "lobby_page",
+        "cursor": cursor,
+        "limit": limit,
+        "prefix": prefix,
+    })
+    .to_string()
+}
+
+/// Apply a received lobby page to the lobby state
+///
+/// The first page of a listing (or a new search) replaces the displayed
+/// users; later pages are appended.
+pub async fn handle_lobby_page(lobby_state: &SharedLobbyState, page: LobbyPage, first_page: bool) {
+    let mut state = lobby_state.lock().await;
+    if first_page {
+        state.set_users(page.users);
+    } else {
+        state.add_users(page.users);
+    }
+}
+
+/// Save the lobby for the next run if it changed since the last save
+///
+/// # Returns
+/// `true` if the cache file was written
+pub async fn save_lobby_cache(
+    lobby_state: &SharedLobbyState,
+    writer: &mut LobbyCacheWriter,
+) -> Result<bool, LobbyCacheError> {
+    let state = lobby_state.lock().await;
+    writer.save_if_changed(&state)
+}
+
+/// Apply a nickname change announced by the server
+///
+/// # Returns
+/// `true` if the user is in the lobby and their nickname changed
+pub async fn handle_lobby_nickname_changed(
+    lobby_state: &SharedLobbyState,
+    public_key: &str,
+    nickname: Option<String>,
+) -> bool {
+    let mut state = lobby_state.lock().await;
+    state.set_nickname(public_key, nickname)
+}
+
+/// Sign a `set_nickname` request
+///
+/// The signature covers [`canonical::nickname`], with an empty nickname
+/// when clearing it.
+///
+/// # Arguments
+/// * `nickname` - The new nickname, or None to clear the current one
+/// * `key_state` - Shared state containing the user's private key
+///
+/// # Returns
+/// Ok(String) containing the request JSON for WebSocket transmission
+pub async fn compose_set_nickname(
+    nickname: Option<&str>,
+    key_state: &SharedKeyState,
+) -> Result<String, ComposeError> {
+    compose_signed_name(
+        "set_nickname",
+        "nickname",
+        nickname,
+        canonical::nickname,
+        key_state,
+    )
+    .await
+}
+
+/// Apply a status change announced by the server
+///
+/// # Returns
+/// `true` if the user is in the lobby and their status changed
+pub async fn handle_lobby_status_changed(
+    lobby_state: &SharedLobbyState,
+    public_key: &str,
+    status: Status,
+) -> bool {
+    let mut state = lobby_state.lock().await;
+    state.set_status(public_key, status)
+}
+
+/// Sign a `set_status` request
+///
+/// The signature covers [`canonical::set_status`]. Other users see
+/// an invisible user as offline; the server refuses `offline` itself.
+///
+/// # Arguments
+/// * `status` - The status to show: online, away, busy or invisible
+/// * `key_state` - Shared state containing the user's private key
+///
+/// # Returns
+/// Ok(String) containing the request JSON for WebSocket transmission
+pub async fn compose_set_status(
+    status: Status,
+    key_state: &SharedKeyState,
+) -> Result<String, ComposeError> {
+    compose_signed_name(
+        "set_status",
+        "status",
+        Some(status.as_str()),
+        |_, timestamp| canonical::set_status(status, timestamp),
+        key_state,
+    )
+    .await
+}
+
+/// Apply a verified name claimed or released on the server
+///
+/// # Returns
+/// `true` if the user is in the lobby and their verified name changed
+pub async fn handle_lobby_verified_name_changed(
+    lobby_state: &SharedLobbyState,
+    public_key: &str,
+    verified_name: Option<String>,
+) -> bool {
+    let mut state = lobby_state.lock().await;
+    state.set_verified_name(public_key, verified_name)
+}
+
+/// Sign a `claim_name` request
+///
+/// The signature covers [`canonical::claim_name`], with an empty
+/// name when releasing it. The server only accepts claims when it keeps a
+/// name registry.
+///
+/// # Arguments
+/// * `name` - The name to claim, or None to release the current one
+/// * `key_state` - Shared state containing the user's private key
+///
+/// # Returns
+/// Ok(String) containing the request JSON for WebSocket transmission
+pub async fn compose_claim_name(
+    name: Option<&str>,
+    key_state: &SharedKeyState,
+) -> Result<String, ComposeError> {
+    compose_signed_name("claim_name", "name", name, canonical::claim_name, key_state).await
+}
+
+/// Sign a request of type `kind` carrying a trimmed name in `field`, over
+/// the bytes `encode` makes of the name and timestamp
+async fn compose_signed_name(
+    kind: &str,
+    field: &str,
+    name: Option<&str>,
+    encode: impl FnOnce(&str, &str) -> Vec<u8>,
+    key_state: &SharedKeyState,
+) -> Result<String, ComposeError> {
+    let name = name.map(str::trim).unwrap_or("");
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let signature = {
+        let key_guard = key_state.lock().await;
+        let private_key = key_guard.private_key().ok_or(ComposeError::NoPrivateKey)?;
+        sign_message(private_key, &encode(name, &timestamp))
+            .map_err(|e| ComposeError::SigningError(e.to_string()))?
+    };
+
+    let request = serde_json::json!({
+        "type": kind,
+        field: name,
+        "signature": hex::encode(signature),
+        "timestamp": timestamp,
+    });
+    serde_json::to_string(&request).map_err(|e| ComposeError::SerializationError(e.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::state::create_shared_lobby_state;
 
+    #[test]
+    fn test_create_lobby_page_request() {
+        let json = create_lobby_page_request(Some("abc"), Some(10), None);
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["type"], "lobby_page");
+        assert_eq!(value["cursor"], "abc");
+        assert_eq!(value["limit"], 10);
+        assert!(value["prefix"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_handle_lobby_page_replaces_then_appends() {
+        let state = create_shared_lobby_state();
+        handle_lobby_user_joined(&state, "stale").await;
+
+        let page = |keys: &[&str], cursor: Option<&str>| LobbyPage {
+            users: keys
+                .iter()
+                .map(|k| LobbyUser::new(k.to_string(), true))
+                .collect(),
+            next_cursor: cursor.map(str::to_string),
+            total: 3,
+        };
+
+        handle_lobby_page(&state, page(&["a", "b"], Some("b")), true).await;
+        assert_eq!(get_lobby_user_count(&state).await, 2);
+        assert!(!is_user_available(&state, "stale").await);
+
+        handle_lobby_page(&state, page(&["c"], None), false).await;
+        assert_eq!(get_lobby_user_count(&state).await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_compose_set_nickname_is_signed() {
+        use crate::state::session::create_shared_key_state;
+        use profile_shared::{derive_public_key, generate_private_key, verify_signature};
+
+        let key_state = create_shared_key_state();
+        assert!(matches!(
+            compose_set_nickname(Some("alice"), &key_state).await,
+            Err(ComposeError::NoPrivateKey)
+        ));
+
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key.clone());
+
+        let json = compose_set_nickname(Some(" alice "), &key_state)
+            .await
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["type"], "set_nickname");
+        assert_eq!(value["nickname"], "alice");
+
+        let canonical = canonical::nickname("alice", value["timestamp"].as_str().unwrap());
+        let signature = hex::decode(value["signature"].as_str().unwrap()).unwrap();
+        assert!(verify_signature(&public_key, &canonical, &signature).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_compose_claim_name_is_signed() {
+        use crate::state::session::create_shared_key_state;
+        use profile_shared::{derive_public_key, generate_private_key, verify_signature};
+
+        let key_state = create_shared_key_state();
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key.clone());
+
+        let json = compose_claim_name(None, &key_state).await.unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["type"], "claim_name");
+        assert_eq!(value["name"], "");
+
+        let canonical = canonical::claim_name("", value["timestamp"].as_str().unwrap());
+        let signature = hex::decode(value["signature"].as_str().unwrap()).unwrap();
+        assert!(verify_signature(&public_key, &canonical, &signature).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_handle_lobby_nickname_changed() {
+        let state = create_shared_lobby_state();
+        handle_lobby_user_joined(&state, "alice").await;
+
+        assert!(handle_lobby_nickname_changed(&state, "alice", Some("Alice".to_string())).await);
+        assert!(!handle_lobby_nickname_changed(&state, "bob", Some("Bob".to_string())).await);
+        let lobby = state.lock().await;
+        assert_eq!(lobby.get_user("alice").unwrap().display_name(), "Alice");
+    }
+
     #[tokio::test]
     async fn test_handle_lobby_user_select() {
         let state = create_shared_lobby_state();
@@ -0,0 +1,270 @@
+//! Per-user actions from the lobby context menu
+//!
+//! Every action offered on a lobby user is a [`LobbyUserAction`] handled by
+//! [`handle_lobby_user_action`], which returns what the UI should do next.
+//! A new action is a new variant here rather than another callback threaded
+//! through `main.rs`.
+
+use crate::state::blocklist::{BlocklistError, SharedBlocklist};
+use crate::state::contacts::{ContactError, SharedContacts, TrustStatus};
+use crate::state::SharedLobbyState;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// An action on a lobby user
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyUserAction {
+    /// Open the conversation with the user
+    Message,
+    /// Show the user's key fingerprint to compare out of band
+    VerifyFingerprint,
+    /// Record that the fingerprint matched (the user must be a contact)
+    MarkVerified,
+    /// Save the user as a contact under this alias, or rename them
+    AddAlias(String),
+    /// Drop direct messages from the user
+    Block,
+    /// Accept direct messages from the user again
+    Unblock,
+    /// Copy the user's full public key
+    CopyKey,
+}
+
+impl LobbyUserAction {
+    /// Menu label for the action
+    pub fn label(&self) -> &'static str {
+        match self {
+            LobbyUserAction::Message => "Message",
+            LobbyUserAction::VerifyFingerprint => "Verify fingerprint",
+            LobbyUserAction::MarkVerified => "Mark as verified",
+            LobbyUserAction::AddAlias(_) => "Add alias",
+            LobbyUserAction::Block => "Block",
+            LobbyUserAction::Unblock => "Unblock",
+            LobbyUserAction::CopyKey => "Copy key",
+        }
+    }
+}
+
+/// What the UI should do after an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LobbyActionOutcome {
+    /// The user is now selected; open their conversation
+    Opened,
+    /// Show this fingerprint for comparison
+    Fingerprint(String),
+    /// The contact list changed
+    ContactUpdated,
+    /// Blocked; false if the user was already blocked
+    Blocked(bool),
+    /// Unblocked; false if the user was not blocked
+    Unblocked(bool),
+    /// Put this text on the clipboard
+    Copy(String),
+}
+
+/// Error types for lobby user actions
+#[derive(Debug, Clone, PartialEq)]
+pub enum LobbyActionError {
+    /// The user is not in the lobby
+    UnknownUser,
+    /// Updating the contact list failed
+    Contact(ContactError),
+    /// Updating the blocklist failed
+    Blocklist(BlocklistError),
+}
+
+impl Display for LobbyActionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LobbyActionError::UnknownUser => write!(f, "User is not in the lobby"),
+            LobbyActionError::Contact(e) => write!(f, "{}", e),
+            LobbyActionError::Blocklist(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for LobbyActionError {}
+
+impl From<ContactError> for LobbyActionError {
+    fn from(e: ContactError) -> Self {
+        LobbyActionError::Contact(e)
+    }
+}
+
+impl From<BlocklistError> for LobbyActionError {
+    fn from(e: BlocklistError) -> Self {
+        LobbyActionError::Blocklist(e)
+    }
+}
+
+/// State the lobby actions work on
+#[derive(Clone)]
+pub struct LobbyActionContext {
+    pub lobby_state: SharedLobbyState,
+    pub contacts: SharedContacts,
+    pub blocklist: SharedBlocklist,
+}
+
+/// Format a public key as a fingerprint: upper case, in groups of four
+pub fn format_fingerprint(public_key: &str) -> String {
+    public_key
+        .to_uppercase()
+        .as_bytes()
+        .chunks(4)
+        .map(|group| String::from_utf8_lossy(group).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Apply an action to a lobby user
+///
+/// # Arguments
+/// * `ctx` - Lobby, contacts and blocklist to act on
+/// * `public_key` - The user the action was chosen for
+/// * `action` - The chosen action
+///
+/// # Returns
+/// What the UI should do next, or why the action failed
+pub async fn handle_lobby_user_action(
+    ctx: &LobbyActionContext,
+    public_key: &str,
+    action: LobbyUserAction,
+) -> Result<LobbyActionOutcome, LobbyActionError> {
+    if !ctx.lobby_state.lock().await.has_user(public_key) {
+        return Err(LobbyActionError::UnknownUser);
+    }
+
+    match action {
+        LobbyUserAction::Message => {
+            ctx.lobby_state.lock().await.select(public_key);
+            Ok(LobbyActionOutcome::Opened)
+        }
+        LobbyUserAction::VerifyFingerprint => Ok(LobbyActionOutcome::Fingerprint(
+            format_fingerprint(public_key),
+        )),
+        LobbyUserAction::MarkVerified => {
+            let mut contacts = ctx.contacts.lock().await;
+            contacts.set_trust(public_key, TrustStatus::Verified)?;
+            contacts.save()?;
+            Ok(LobbyActionOutcome::ContactUpdated)
+        }
+        LobbyUserAction::AddAlias(alias) => {
+            let mut contacts = ctx.contacts.lock().await;
+            if contacts.get(public_key).is_some() {
+                contacts.rename(public_key, &alias)?;
+            } else {
+                contacts.add(public_key, &alias)?;
+            }
+            contacts.save()?;
+            Ok(LobbyActionOutcome::ContactUpdated)
+        }
+        LobbyUserAction::Block => {
+            let mut blocklist = ctx.blocklist.lock().await;
+            let changed = blocklist.block(public_key)?;
+            if changed {
+                blocklist.save()?;
+            }
+            Ok(LobbyActionOutcome::Blocked(changed))
+        }
+        LobbyUserAction::Unblock => {
+            let mut blocklist = ctx.blocklist.lock().await;
+            let changed = blocklist.unblock(public_key)?;
+            if changed {
+                blocklist.save()?;
+            }
+            Ok(LobbyActionOutcome::Unblocked(changed))
+        }
+        LobbyUserAction::CopyKey => Ok(LobbyActionOutcome::Copy(public_key.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::lobby::handle_lobby_user_joined;
+    use crate::state::blocklist::create_shared_blocklist;
+    use crate::state::contacts::create_shared_contacts;
+    use crate::state::create_shared_lobby_state;
+
+    async fn context_with(public_key: &str) -> LobbyActionContext {
+        let lobby_state = create_shared_lobby_state();
+        handle_lobby_user_joined(&lobby_state, public_key).await;
+        LobbyActionContext {
+            lobby_state,
+            contacts: create_shared_contacts(),
+            blocklist: create_shared_blocklist(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_actions_on_lobby_user() {
+        let key = "ab".repeat(32);
+        let ctx = context_with(&key).await;
+
+        assert_eq!(
+            handle_lobby_user_action(&ctx, &key, LobbyUserAction::Message).await,
+            Ok(LobbyActionOutcome::Opened)
+        );
+        assert_eq!(
+            ctx.lobby_state.lock().await.selected_user(),
+            Some(key.as_str())
+        );
+        assert_eq!(
+            handle_lobby_user_action(&ctx, &key, LobbyUserAction::CopyKey).await,
+            Ok(LobbyActionOutcome::Copy(key.clone()))
+        );
+        match handle_lobby_user_action(&ctx, &key, LobbyUserAction::VerifyFingerprint).await {
+            Ok(LobbyActionOutcome::Fingerprint(fingerprint)) => {
+                assert!(fingerprint.starts_with("ABAB ABAB "));
+                assert_eq!(fingerprint.split(' ').count(), 16);
+            }
+            other => panic!("Expected fingerprint, got {:?}", other),
+        }
+
+        assert_eq!(
+            handle_lobby_user_action(&ctx, "missing", LobbyUserAction::CopyKey).await,
+            Err(LobbyActionError::UnknownUser)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alias_verify_and_block() {
+        let key = "cd".repeat(32);
+        let ctx = context_with(&key).await;
+
+        assert_eq!(
+            handle_lobby_user_action(&ctx, &key, LobbyUserAction::MarkVerified).await,
+            Err(LobbyActionError::Contact(ContactError::NotFound))
+        );
+        for alias in ["Carol", "Caz"] {
+            assert_eq!(
+                handle_lobby_user_action(&ctx, &key, LobbyUserAction::AddAlias(alias.to_string()))
+                    .await,
+                Ok(LobbyActionOutcome::ContactUpdated)
+            );
+        }
+        handle_lobby_user_action(&ctx, &key, LobbyUserAction::MarkVerified)
+            .await
+            .unwrap();
+        {
+            let contacts = ctx.contacts.lock().await;
+            let contact = contacts.get(&key).unwrap();
+            assert_eq!(contact.label, "Caz");
+            assert_eq!(contact.trust, TrustStatus::Verified);
+        }
+
+        assert_eq!(
+            handle_lobby_user_action(&ctx, &key, LobbyUserAction::Block).await,
+            Ok(LobbyActionOutcome::Blocked(true))
+        );
+        assert!(ctx.blocklist.lock().await.is_blocked(&key));
+        assert_eq!(
+            handle_lobby_user_action(&ctx, &key, LobbyUserAction::Unblock).await,
+            Ok(LobbyActionOutcome::Unblocked(true))
+        );
+        assert_eq!(
+            handle_lobby_user_action(&ctx, &key, LobbyUserAction::Unblock).await,
+            Ok(LobbyActionOutcome::Unblocked(false))
+        );
+    }
+}
@@ -1,36 +1,119 @@
 //! UI event handlers for key generation and management
 
+pub mod archive;
+pub mod attachments;
 pub mod compose;
 pub mod composer;
+pub mod contacts;
 pub mod edge_cases;
+pub mod edit;
+pub mod errors;
+pub mod export;
+pub mod key_escrow;
 pub mod key_generation;
 pub mod key_import;
+pub mod key_rollover;
 pub mod lobby;
+pub mod lobby_actions;
 pub mod offline;
+pub mod presence;
+pub mod reactions;
+pub mod receipts;
+pub mod retract;
+pub mod rooms;
+pub mod sealed;
+pub mod starred;
+pub mod thumbnails;
 pub mod verify;
 
 pub use crate::state::composer::{
     clear_all_ephemeral_data, format_connection_notification, ConnectionState,
 };
+pub use archive::{
+    conversation_sections, handle_archive_conversation, handle_archive_message,
+    handle_set_unarchive_on_message, handle_unarchive_conversation, lobby_sections,
+};
+pub use attachments::{
+    attachment_from_message, compose_attachment_accept, compose_attachment_cancel,
+    compose_attachment_offer, handle_incoming_attachment, sanitize_file_name, AttachmentEvent,
+    AttachmentOutcome, IncomingAttachment,
+};
 pub use compose::{compose_and_send_message, compose_message_draft, ComposeError};
 pub use composer::{
     create_composer_with_state, get_send_result_message, handle_composer_can_send,
     handle_composer_clear, handle_composer_get_draft, handle_composer_set_send_callback,
     handle_composer_set_status_callback, handle_composer_text_change, handle_send_message,
 };
-pub use key_generation::handle_generate_new_key;
-pub use key_import::handle_import_key;
+pub use contacts::{
+    get_contact_label, handle_add_contact, handle_add_contact_tag, handle_contact_message,
+    handle_export_contacts, handle_import_contacts, handle_remove_contact,
+    handle_remove_contact_tag, handle_rename_contact, handle_set_contact_notes,
+    handle_set_contact_trust, search_contacts, search_lobby_contacts,
+};
+pub use edit::{compose_edit, edit_from_message, handle_incoming_edit, verify_edit, IncomingEdit};
+pub use errors::{parse_incoming_error, IncomingError};
+pub use export::{
+    create_transcript, handle_export_transcript, handle_import_transcript, verify_transcript_file,
+    ExportError, Transcript, TranscriptFormat, TranscriptImportSummary, TranscriptManifest,
+    TranscriptMessage,
+};
+pub use key_escrow::{
+    compose_backup_store, create_backup_fetch_request, handle_open_default_key_escrow,
+    handle_open_key_escrow, handle_save_key_escrow, restore_key_escrow_backup,
+};
+pub use key_generation::{
+    handle_export_mnemonic, handle_generate_guest_key, handle_generate_new_key,
+};
+pub use key_import::{handle_import_key, handle_import_key_file, handle_save_key_file};
+pub use key_rollover::{
+    apply_key_rollover, complete_key_rollover, create_key_rollover, KeyRolloverError,
+    PendingKeyRollover,
+};
 pub use lobby::{
-    clear_lobby_selection, get_lobby_selected_user, get_lobby_user_count,
-    handle_lobby_navigate_down, handle_lobby_navigate_up, handle_lobby_state_update,
-    handle_lobby_user_joined, handle_lobby_user_left, handle_lobby_user_select,
+    clear_lobby_selection, compose_claim_name, compose_set_nickname, compose_set_status,
+    create_lobby_page_request, get_lobby_selected_user, get_lobby_user_count,
+    handle_lobby_message_received, handle_lobby_navigate_down, handle_lobby_navigate_up,
+    handle_lobby_nickname_changed, handle_lobby_page, handle_lobby_state_update,
+    handle_lobby_status_changed, handle_lobby_user_joined, handle_lobby_user_left,
+    handle_lobby_user_select, handle_lobby_verified_name_changed, save_lobby_cache,
+};
+pub use lobby_actions::{
+    format_fingerprint, handle_lobby_user_action, LobbyActionContext, LobbyActionError,
+    LobbyActionOutcome, LobbyUserAction,
 };
 pub use offline::{
     add_undelivered_message, clear_undelivered_for_recipient, create_offline_notification,
     create_shared_undelivered_messages, create_undelivered_display_message, dismiss_notification,
-    format_notification_message, get_undelivered_for_recipient, parse_offline_notification,
-    OfflineNotification, SharedUndeliveredMessages, UndeliveredMessage,
+    format_notification_message, get_undelivered_for_recipient, offline_notification_from_message,
+    parse_offline_notification, OfflineNotification, SharedUndeliveredMessages, UndeliveredMessage,
+};
+pub use presence::{
+    create_last_seen_query, create_viewing_hint, handle_last_seen, handle_open_conversation,
+    handle_set_share_last_seen, handle_set_share_viewing, handle_viewer_left, handle_viewing_hint,
+    last_seen_from_message, viewing_hint_from_message, LastSeenReply, ViewingHint,
+};
+pub use reactions::{
+    compose_reaction, handle_incoming_reaction, reaction_from_message, verify_reaction,
+    IncomingReaction,
+};
+pub use receipts::{
+    apply_read_receipt, create_read_receipt, create_read_receipts_for_view, parse_read_receipt,
+    read_receipt_from_message, ReadReceipt,
+};
+pub use retract::{
+    compose_retraction, handle_incoming_retraction, retraction_from_message, verify_retraction,
+    IncomingRetraction,
+};
+pub use rooms::{
+    compose_room_message, create_room_request, handle_room_event, handle_room_select,
+    join_room_request, leave_room_request, parse_room_event, room_event_from_message, RoomEvent,
+};
+pub use sealed::{
+    open_sealed_message, seal_direct_message, sealed_message_from_message, SealedError,
+    SealedMessage,
 };
+pub use starred::{handle_jump_to_starred, handle_star_message, handle_unstar_message};
+pub use thumbnails::{decode_thumbnail, generate_thumbnail, thumbnail_image};
 pub use verify::{
     create_invalid_signature_notification, format_public_key, verify_chat_message, verify_message,
     VerificationResult,
@@ -3,23 +3,41 @@
 //! This module provides support for handling scenarios where messages
 //! cannot be delivered because the recipient is offline.
 
-use serde::{Deserialize, Serialize};
+use crate::state::conversation::ConversationId;
+use profile_shared::Message;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-/// Notification type from server
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Recipient-offline notification for a message that was not delivered
+///
+/// The server reports offline recipients with the shared
+/// `Message::Error { reason: "offline", .. }`; this is the client-side view
+/// of that error, optionally paired with the message that failed.
+#[derive(Debug, Clone, PartialEq)]
 pub struct OfflineNotification {
-    pub r#type: String,
-    pub event: String,
+    /// Public key of the offline recipient
     pub recipient: String,
-    pub message: Option<String>, // Original message that failed
+    /// Original message that failed, if known
+    pub message: Option<String>,
+    /// When the notification was created
     pub timestamp: Option<String>,
 }
 
-/// Parse an offline notification from JSON
+/// Parse an offline notification from a server error JSON
 pub fn parse_offline_notification(json: &str) -> Result<OfflineNotification, String> {
-    serde_json::from_str(json).map_err(|e| format!("Failed to parse notification: {}", e))
+    let message: Message =
+        serde_json::from_str(json).map_err(|e| format!("Failed to parse notification: {}", e))?;
+    offline_notification_from_message(&message)
+        .ok_or_else(|| "Not a recipient offline error".to_string())
+}
+
+/// Convert a shared protocol message into an offline notification
+///
+/// Returns None unless the message is a recipient-offline error.
+pub fn offline_notification_from_message(message: &Message) -> Option<OfflineNotification> {
+    message
+        .offline_recipient()
+        .map(|recipient| create_offline_notification(recipient, None))
 }
 
 /// Create an offline notification for sending to user
@@ -28,8 +46,6 @@ pub fn create_offline_notification(
     failed_message: Option<&str>,
 ) -> OfflineNotification {
     OfflineNotification {
-        r#type: "notification".to_string(),
-        event: "recipient_offline".to_string(),
         recipient: recipient_key.to_string(),
         message: failed_message.map(|s| s.to_string()),
         timestamp: Some(chrono::Utc::now().to_rfc3339()),
@@ -107,12 +123,12 @@ pub async fn add_undelivered_message(
 /// Get undelivered messages for a recipient
 pub async fn get_undelivered_for_recipient(
     store: &SharedUndeliveredMessages,
-    recipient_key: &str,
+    recipient: &ConversationId,
 ) -> Vec<UndeliveredMessage> {
     let messages = store.lock().await;
     messages
         .iter()
-        .filter(|m| m.recipient_key == recipient_key)
+        .filter(|m| recipient.matches(&m.recipient_key))
         .cloned()
         .collect()
 }
@@ -120,17 +136,17 @@ pub async fn get_undelivered_for_recipient(
 /// Clear undelivered messages for a recipient (when they come online)
 pub async fn clear_undelivered_for_recipient(
     store: &SharedUndeliveredMessages,
-    recipient_key: &str,
+    recipient: &ConversationId,
 ) {
     let mut messages = store.lock().await;
-    messages.retain(|m| m.recipient_key != recipient_key);
+    messages.retain(|m| !recipient.matches(&m.recipient_key));
 }
 
 /// Dismiss notification for a specific message
-pub async fn dismiss_notification(store: &SharedUndeliveredMessages, recipient_key: &str) {
+pub async fn dismiss_notification(store: &SharedUndeliveredMessages, recipient: &ConversationId) {
     let mut messages = store.lock().await;
     for msg in messages.iter_mut() {
-        if msg.recipient_key == recipient_key {
+        if recipient.matches(&msg.recipient_key) {
             msg.dismiss_notification();
         }
     }
@@ -160,12 +176,21 @@ pub fn create_undelivered_display_message(
         id: format!("undelivered-{}", msg.timestamp),
         sender_key: msg.recipient_key.clone(),
         sender_key_short: format_public_key_short(&msg.recipient_key),
+        sender_nickname: None,
+        sender_label: None,
         content: msg.content.clone(),
+        previous_versions: Vec::new(),
+        is_retracted: false,
+        reactions: Default::default(),
         timestamp: crate::ui::chat::format_timestamp(&msg.timestamp),
         signature: "".to_string(), // No signature for undelivered messages
         is_verified: false,        // Undelivered = not verified
         is_self,
+        is_read: false,
+        send_state: None,
+        is_starred: false,
         original_timestamp: msg.timestamp.clone(),
+        translation: None,
     }
 }
 
@@ -173,24 +198,40 @@ pub fn create_undelivered_display_message(
 mod tests {
     use super::*;
 
+    fn recipients() -> (ConversationId, ConversationId) {
+        (
+            ConversationId::new(&"a1".repeat(32)).unwrap(),
+            ConversationId::new(&"b2".repeat(32)).unwrap(),
+        )
+    }
+
     #[test]
     fn test_parse_offline_notification() {
-        let json = r#"{"type":"notification","event":"recipient_offline","recipient":"abc123","message":"Hello","timestamp":"2025-12-27T10:30:00Z"}"#;
-        let result = parse_offline_notification(json);
+        let json = serde_json::to_string(&Message::new_recipient_offline("abc123")).unwrap();
+        let result = parse_offline_notification(&json);
         assert!(result.is_ok());
         let notification = result.unwrap();
-        assert_eq!(notification.event, "recipient_offline");
         assert_eq!(notification.recipient, "abc123");
-        assert_eq!(notification.message, Some("Hello".to_string()));
+        assert_eq!(notification.message, None);
+    }
+
+    #[test]
+    fn test_parse_offline_notification_rejects_other_errors() {
+        let json = serde_json::to_string(&Message::new_error(
+            "signature_invalid".to_string(),
+            Some("bad".to_string()),
+        ))
+        .unwrap();
+        assert!(parse_offline_notification(&json).is_err());
+        assert!(parse_offline_notification("not json").is_err());
     }
 
     #[test]
     fn test_create_offline_notification() {
         let notification = create_offline_notification("recipient_key", Some("Hello"));
-        assert_eq!(notification.r#type, "notification");
-        assert_eq!(notification.event, "recipient_offline");
         assert_eq!(notification.recipient, "recipient_key");
         assert_eq!(notification.message, Some("Hello".to_string()));
+        assert!(notification.timestamp.is_some());
     }
 
     #[test]
@@ -257,37 +298,41 @@ mod tests {
     #[tokio::test]
     async fn test_get_undelivered_for_recipient() {
         let store = create_shared_undelivered_messages();
-        add_undelivered_message(&store, "Hello", "recipient1", "2025-12-27T10:30:00Z").await;
-        add_undelivered_message(&store, "World", "recipient2", "2025-12-27T10:31:00Z").await;
-        add_undelivered_message(&store, "Test", "recipient1", "2025-12-27T10:32:00Z").await;
+        let (recipient1, recipient2) = recipients();
+        add_undelivered_message(&store, "Hello", &"a1".repeat(32), "2025-12-27T10:30:00Z").await;
+        add_undelivered_message(&store, "World", &"b2".repeat(32), "2025-12-27T10:31:00Z").await;
+        add_undelivered_message(&store, "Test", &"A1".repeat(32), "2025-12-27T10:32:00Z").await;
 
-        let for_recipient1 = get_undelivered_for_recipient(&store, "recipient1").await;
+        // Both spellings of recipient1's key count as the same conversation
+        let for_recipient1 = get_undelivered_for_recipient(&store, &recipient1).await;
         assert_eq!(for_recipient1.len(), 2);
 
-        let for_recipient2 = get_undelivered_for_recipient(&store, "recipient2").await;
+        let for_recipient2 = get_undelivered_for_recipient(&store, &recipient2).await;
         assert_eq!(for_recipient2.len(), 1);
     }
 
     #[tokio::test]
     async fn test_clear_undelivered_for_recipient() {
         let store = create_shared_undelivered_messages();
-        add_undelivered_message(&store, "Hello", "recipient1", "2025-12-27T10:30:00Z").await;
-        add_undelivered_message(&store, "World", "recipient2", "2025-12-27T10:31:00Z").await;
+        let (recipient1, _) = recipients();
+        add_undelivered_message(&store, "Hello", &"a1".repeat(32), "2025-12-27T10:30:00Z").await;
+        add_undelivered_message(&store, "World", &"b2".repeat(32), "2025-12-27T10:31:00Z").await;
 
-        clear_undelivered_for_recipient(&store, "recipient1").await;
+        clear_undelivered_for_recipient(&store, &recipient1).await;
 
         let messages = store.lock().await;
         assert_eq!(messages.len(), 1);
-        assert_eq!(messages[0].recipient_key, "recipient2");
+        assert_eq!(messages[0].recipient_key, "b2".repeat(32));
     }
 
     #[tokio::test]
     async fn test_dismiss_notification() {
         let store = create_shared_undelivered_messages();
-        add_undelivered_message(&store, "Hello", "recipient1", "2025-12-27T10:30:00Z").await;
-        add_undelivered_message(&store, "World", "recipient2", "2025-12-27T10:31:00Z").await;
+        let (recipient1, _) = recipients();
+        add_undelivered_message(&store, "Hello", &"a1".repeat(32), "2025-12-27T10:30:00Z").await;
+        add_undelivered_message(&store, "World", &"b2".repeat(32), "2025-12-27T10:31:00Z").await;
 
-        dismiss_notification(&store, "recipient1").await;
+        dismiss_notification(&store, &recipient1).await;
 
         let messages = store.lock().await;
         assert!(!messages[0].should_show_notification());
@@ -0,0 +1,339 @@
+//! "Viewing your conversation" hints
+//!
+//! The client sends a `viewing` request when the user opens or leaves a
+//! direct conversation, and the server forwards it to the peer as a
+//! `Viewing` protocol message. Everything here is gated on the user's
+//! presence settings: with sharing off no request is built and incoming
+//! hints are dropped.
+//!
+//! Last-seen times follow the same rule. When the selected contact leaves,
+//! the WebSocket client sends a `query_last_seen` request, and the time in
+//! the server's `LastSeen` answer is shown if the user shares their own.
+
+use crate::state::conversation::ConversationId;
+use crate::state::presence::{
+    PresenceSettingsError, SharedViewingPresence, ViewingPresence, ViewingUpdate,
+};
+use crate::ui::chat::ChatView;
+use profile_shared::Message;
+
+/// Viewing hint received from the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViewingHint {
+    /// Peer that opened or closed this user's conversation
+    pub viewer_public_key: String,
+    /// Whether the peer now has the conversation open
+    pub viewing: bool,
+}
+
+/// Last-seen time received from the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastSeenReply {
+    /// Contact the query was about
+    pub public_key: String,
+    /// Whether the contact is in the lobby now
+    pub online: bool,
+    /// When the contact last left (RFC 3339), if they share it
+    pub last_seen: Option<String>,
+}
+
+/// Create the JSON viewing request for a peer
+///
+/// # Arguments
+/// * `recipient_public_key` - The peer whose conversation was opened or closed
+/// * `viewing` - Whether the conversation is now open
+///
+/// # Returns
+/// JSON string ready for WebSocket transmission
+pub fn create_viewing_hint(recipient_public_key: &str, viewing: bool) -> String {
+    serde_json::json!({
+        "type": "viewing",
+        "recipientPublicKey": recipient_public_key,
+        "viewing": viewing
+    })
+    .to_string()
+}
+
+/// Extract a viewing hint from an already-parsed server message
+pub fn viewing_hint_from_message(message: Message) -> Option<ViewingHint> {
+    match message {
+        Message::Viewing {
+            viewer_public_key,
+            viewing,
+        } => Some(ViewingHint {
+            viewer_public_key,
+            viewing,
+        }),
+        _ => None,
+    }
+}
+
+/// Create the JSON request for when a contact was last online
+pub fn create_last_seen_query(public_key: &str) -> String {
+    serde_json::json!({
+        "type": "query_last_seen",
+        "publicKey": public_key
+    })
+    .to_string()
+}
+
+/// Extract a last-seen answer from an already-parsed server message
+pub fn last_seen_from_message(message: Message) -> Option<LastSeenReply> {
+    match message {
+        Message::LastSeen {
+            public_key,
+            online,
+            last_seen,
+        } => Some(LastSeenReply {
+            public_key,
+            online,
+            last_seen,
+        }),
+        _ => None,
+    }
+}
+
+fn to_requests(updates: Vec<ViewingUpdate>) -> Vec<String> {
+    updates
+        .into_iter()
+        .map(|(peer, viewing)| create_viewing_hint(&peer, viewing))
+        .collect()
+}
+
+/// Show whether the chat view's peer has this user's conversation open
+fn refresh_chat_view(presence: &ViewingPresence, chat_view: &mut ChatView) -> bool {
+    let viewing = chat_view
+        .selected_recipient()
+        .is_some_and(|peer| presence.is_viewing(peer));
+    chat_view.set_peer_viewing(viewing)
+}
+
+/// Record the direct conversation the user opened (None when they left it)
+///
+/// # Returns
+/// JSON requests to send; empty while sharing is off
+pub async fn handle_open_conversation(
+    presence: &SharedViewingPresence,
+    chat_view: &mut ChatView,
+    peer: Option<&str>,
+) -> Vec<String> {
+    let mut presence = presence.lock().await;
+    let updates = presence.open(peer);
+    refresh_chat_view(&presence, chat_view);
+    to_requests(updates)
+}
+
+/// Turn sharing on or off and save the setting
+///
+/// # Returns
+/// JSON requests to send for the open conversation
+pub async fn handle_set_share_viewing(
+    presence: &SharedViewingPresence,
+    chat_view: &mut ChatView,
+    enabled: bool,
+) -> Result<Vec<String>, PresenceSettingsError> {
+    let mut presence = presence.lock().await;
+    let changed = presence.settings().share_viewing != enabled;
+    let updates = presence.set_sharing(enabled);
+    if changed {
+        presence.settings().save()?;
+    }
+    refresh_chat_view(&presence, chat_view);
+    Ok(to_requests(updates))
+}
+
+/// Apply a hint from a peer
+///
+/// # Returns
+/// true if the chat view's hint changed
+pub async fn handle_viewing_hint(
+    presence: &SharedViewingPresence,
+    chat_view: &mut ChatView,
+    hint: &ViewingHint,
+) -> bool {
+    let Ok(viewer) = ConversationId::new(&hint.viewer_public_key) else {
+        return false;
+    };
+    let mut presence = presence.lock().await;
+    presence.hint_received(&viewer, hint.viewing);
+    refresh_chat_view(&presence, chat_view)
+}
+
+/// Forget a peer's hint when they leave the lobby
+///
+/// # Returns
+/// true if the chat view's hint changed
+pub async fn handle_viewer_left(
+    presence: &SharedViewingPresence,
+    chat_view: &mut ChatView,
+    public_key: &str,
+) -> bool {
+    let Ok(viewer) = ConversationId::new(public_key) else {
+        return false;
+    };
+    let mut presence = presence.lock().await;
+    presence.viewer_left(&viewer);
+    refresh_chat_view(&presence, chat_view)
+}
+
+/// Show a last-seen answer if it is about the chat view's contact
+///
+/// Ignored while sharing is off.
+///
+/// # Returns
+/// true if the chat view's hint changed
+pub async fn handle_last_seen(
+    presence: &SharedViewingPresence,
+    chat_view: &mut ChatView,
+    reply: &LastSeenReply,
+) -> bool {
+    if !presence.lock().await.settings().share_last_seen {
+        return false;
+    }
+    let selected = chat_view
+        .selected_recipient()
+        .is_some_and(|peer| peer.as_str() == reply.public_key);
+    if !selected {
+        return false;
+    }
+    let last_seen = if reply.online {
+        None
+    } else {
+        reply.last_seen.clone()
+    };
+    chat_view.set_peer_last_seen(last_seen)
+}
+
+/// Turn last-seen sharing on or off and save the setting
+///
+/// The server learns the setting at the next sign-in.
+///
+/// # Returns
+/// true if the setting changed
+pub async fn handle_set_share_last_seen(
+    presence: &SharedViewingPresence,
+    chat_view: &mut ChatView,
+    enabled: bool,
+) -> Result<bool, PresenceSettingsError> {
+    let mut presence = presence.lock().await;
+    if presence.settings().share_last_seen == enabled {
+        return Ok(false);
+    }
+    presence.set_share_last_seen(enabled);
+    presence.settings().save()?;
+    if !enabled {
+        chat_view.set_peer_last_seen(None);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::presence::create_shared_viewing_presence;
+
+    #[tokio::test]
+    async fn test_hints_only_with_sharing_enabled() {
+        let presence = create_shared_viewing_presence();
+        let mut chat_view = ChatView::new();
+        let bob = "ab".repeat(32);
+        chat_view.set_selected_recipient(Some(ConversationId::new(&bob).unwrap()));
+        let hint = ViewingHint {
+            viewer_public_key: bob.clone(),
+            viewing: true,
+        };
+
+        assert!(
+            handle_open_conversation(&presence, &mut chat_view, Some(&bob))
+                .await
+                .is_empty()
+        );
+        assert!(!handle_viewing_hint(&presence, &mut chat_view, &hint).await);
+        assert_eq!(chat_view.viewing_hint(), "");
+
+        let requests = handle_set_share_viewing(&presence, &mut chat_view, true)
+            .await
+            .unwrap();
+        assert_eq!(requests, vec![create_viewing_hint(&bob, true)]);
+        assert!(handle_viewing_hint(&presence, &mut chat_view, &hint).await);
+        assert_eq!(chat_view.viewing_hint(), "Viewing your conversation");
+
+        assert!(handle_viewer_left(&presence, &mut chat_view, &bob).await);
+        assert_eq!(chat_view.viewing_hint(), "");
+    }
+
+    #[tokio::test]
+    async fn test_last_seen_only_with_sharing_enabled() {
+        let presence = create_shared_viewing_presence();
+        let mut chat_view = ChatView::new();
+        let bob = "ab".repeat(32);
+        chat_view.set_selected_recipient(Some(ConversationId::new(&bob).unwrap()));
+        let reply = LastSeenReply {
+            public_key: bob.clone(),
+            online: false,
+            last_seen: Some("2025-12-20T10:00:00Z".to_string()),
+        };
+
+        assert!(!handle_last_seen(&presence, &mut chat_view, &reply).await);
+        assert_eq!(chat_view.last_seen_hint(), "");
+
+        assert!(handle_set_share_last_seen(&presence, &mut chat_view, true)
+            .await
+            .unwrap());
+        assert!(handle_last_seen(&presence, &mut chat_view, &reply).await);
+        assert_eq!(chat_view.last_seen_hint(), "Last seen 2025-12-20 10:00 UTC");
+
+        // Answers about other contacts don't touch the hint
+        let other = LastSeenReply {
+            public_key: "cd".repeat(32),
+            ..reply.clone()
+        };
+        assert!(!handle_last_seen(&presence, &mut chat_view, &other).await);
+
+        // Back online clears it
+        let online = LastSeenReply {
+            online: true,
+            last_seen: None,
+            ..reply
+        };
+        assert!(handle_last_seen(&presence, &mut chat_view, &online).await);
+        assert_eq!(chat_view.last_seen_hint(), "");
+    }
+
+    #[test]
+    fn test_last_seen_round_trip() {
+        let request: serde_json::Value =
+            serde_json::from_str(&create_last_seen_query("bob")).unwrap();
+        assert_eq!(request["type"], "query_last_seen");
+        assert_eq!(request["publicKey"], "bob");
+
+        let reply = last_seen_from_message(Message::new_last_seen("ab".to_string(), true, None));
+        assert_eq!(
+            reply,
+            Some(LastSeenReply {
+                public_key: "ab".to_string(),
+                online: true,
+                last_seen: None,
+            })
+        );
+        assert_eq!(last_seen_from_message(Message::Close), None);
+    }
+
+    #[test]
+    fn test_viewing_hint_round_trip() {
+        let request: serde_json::Value =
+            serde_json::from_str(&create_viewing_hint("bob", false)).unwrap();
+        assert_eq!(request["type"], "viewing");
+        assert_eq!(request["viewing"], false);
+
+        let hint = viewing_hint_from_message(Message::new_viewing("alice".to_string(), true));
+        assert_eq!(
+            hint,
+            Some(ViewingHint {
+                viewer_public_key: "alice".to_string(),
+                viewing: true,
+            })
+        );
+        assert_eq!(viewing_hint_from_message(Message::Close), None);
+    }
+}
@@ -0,0 +1,267 @@
+//! Emoji reactions to direct messages
+//!
+//! Either side of a conversation can react to any of its messages. A
+//! reaction is signed over the message id, the emoji, whether it is added
+//! or taken back, and a fresh timestamp, sent as a `reaction` request and
+//! relayed by the server as a `Message::Reaction`.
+//!
+//! Both sides record the reaction on their copy of the message (see
+//! [`crate::state::messages::MessageHistory::apply_reaction`]) once its
+//! signature verifies under the reactor's key.
+
+use crate::handlers::compose::ComposeError;
+use crate::state::messages::SharedMessageHistory;
+use crate::state::session::SharedKeyState;
+use profile_shared::canonical;
+use profile_shared::config::message::MAX_REACTION_LENGTH;
+use profile_shared::crypto::sign_message;
+use profile_shared::{verify_signature, Message, PublicKey};
+use uuid::Uuid;
+
+/// Reaction received from the server, not yet verified
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomingReaction {
+    /// Id of the message reacted to
+    pub message_id: Uuid,
+    pub emoji: String,
+    /// Whether the reaction is taken back
+    pub removed: bool,
+    /// Reactor as authenticated by the server
+    pub sender_public_key: String,
+    pub signature: String,
+    pub timestamp: String,
+}
+
+/// Extract a reaction from an already-parsed server message
+pub fn reaction_from_message(message: Message) -> Option<IncomingReaction> {
+    match message {
+        Message::Reaction {
+            message_id,
+            emoji,
+            removed,
+            sender_public_key,
+            signature,
+            timestamp,
+        } => Some(IncomingReaction {
+            message_id,
+            emoji,
+            removed,
+            sender_public_key,
+            signature,
+            timestamp,
+        }),
+        _ => None,
+    }
+}
+
+/// Sign a reaction to a message, or its removal, and record it locally
+///
+/// # Arguments
+/// * `message_id` - Id of the message reacted to
+/// * `emoji` - The reaction
+/// * `removed` - Whether to take back an earlier reaction
+/// * `recipient_public_key` - The other side of the conversation
+/// * `key_state` - Shared state containing the user's private key
+/// * `message_history` - History holding the message
+///
+/// # Returns
+/// JSON `reaction` request ready for WebSocket transmission
+///
+/// # Errors
+/// Returns an error if the emoji is empty or too long, no key is loaded,
+/// or signing fails
+pub async fn compose_reaction(
+    message_id: Uuid,
+    emoji: &str,
+    removed: bool,
+    recipient_public_key: &str,
+    key_state: &SharedKeyState,
+    message_history: &SharedMessageHistory,
+) -> Result<String, ComposeError> {
+    if emoji.trim().is_empty() {
+        return Err(ComposeError::EmptyMessage);
+    }
+    if emoji.len() > MAX_REACTION_LENGTH {
+        return Err(ComposeError::TooLong {
+            length: emoji.len(),
+            max: MAX_REACTION_LENGTH,
+        });
+    }
+
+    let (public_key, timestamp, signature) = {
+        let key_guard = key_state.lock().await;
+        let public_key = key_guard.public_key().ok_or(ComposeError::NoPublicKey)?;
+        let private_key = key_guard.private_key().ok_or(ComposeError::NoPrivateKey)?;
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            private_key,
+            &canonical::message_reaction(message_id, emoji, removed, &timestamp),
+        )
+        .map_err(|e| ComposeError::SigningError(e.to_string()))?;
+        (hex::encode(public_key), timestamp, hex::encode(signature))
+    };
+
+    message_history
+        .lock()
+        .await
+        .apply_reaction(message_id, &public_key, emoji, removed);
+
+    let request = serde_json::json!({
+        "type": "reaction",
+        "recipientPublicKey": recipient_public_key,
+        "messageId": message_id,
+        "emoji": emoji,
+        "removed": removed,
+        "signature": signature,
+        "timestamp": timestamp
+    });
+    serde_json::to_string(&request).map_err(|e| ComposeError::SerializationError(e.to_string()))
+}
+
+/// Check a reaction's signature under the reactor's key
+///
+/// # Errors
+/// Returns a user-facing reason if the key or signature is malformed or the
+/// signature doesn't cover the reaction
+pub fn verify_reaction(reaction: &IncomingReaction) -> Result<(), String> {
+    let key_bytes = hex::decode(&reaction.sender_public_key)
+        .map_err(|e| format!("Invalid public key hex: {}", e))?;
+    let public_key =
+        PublicKey::new(key_bytes).map_err(|e| format!("Invalid public key format: {}", e))?;
+    let signature =
+        hex::decode(&reaction.signature).map_err(|e| format!("Invalid signature hex: {}", e))?;
+    let signed = canonical::message_reaction(
+        reaction.message_id,
+        &reaction.emoji,
+        reaction.removed,
+        &reaction.timestamp,
+    );
+    verify_signature(&public_key, &signed, &signature)
+        .map_err(|e| format!("Reaction signature does not verify: {}", e))
+}
+
+/// Verify a received reaction and record it on the message it names
+///
+/// # Returns
+/// Ok(true) if the message's reactions changed, Ok(false) if no message has
+/// the id or the reaction was already recorded
+///
+/// # Errors
+/// Returns a user-facing reason if the signature doesn't verify
+pub async fn handle_incoming_reaction(
+    message_history: &SharedMessageHistory,
+    reaction: &IncomingReaction,
+) -> Result<bool, String> {
+    verify_reaction(reaction)?;
+    Ok(message_history.lock().await.apply_reaction(
+        reaction.message_id,
+        &reaction.sender_public_key,
+        &reaction.emoji,
+        reaction.removed,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::messages::{create_shared_message_history, ChatMessage};
+    use crate::state::session::create_shared_key_state;
+    use profile_shared::{derive_public_key, generate_private_key};
+
+    /// What the server would forward for a composed reaction request
+    fn forwarded(json: &str, sender_public_key: &str) -> IncomingReaction {
+        let request: serde_json::Value = serde_json::from_str(json).unwrap();
+        IncomingReaction {
+            message_id: request["messageId"].as_str().unwrap().parse().unwrap(),
+            emoji: request["emoji"].as_str().unwrap().to_string(),
+            removed: request["removed"].as_bool().unwrap(),
+            sender_public_key: sender_public_key.to_string(),
+            signature: request["signature"].as_str().unwrap().to_string(),
+            timestamp: request["timestamp"].as_str().unwrap().to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reaction_added_and_removed_on_both_sides() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let reactor = hex::encode(public_key.as_bytes());
+        let key_state = create_shared_key_state();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key);
+
+        // A message from the peer, held by both sides
+        let original = ChatMessage::new(
+            "bbbb".to_string(),
+            "Shipped it".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let message_id = original.message_id;
+        let history = create_shared_message_history();
+        let peer_history = create_shared_message_history();
+        history.lock().await.add_message(original.clone());
+        peer_history.lock().await.add_message(original);
+
+        let json = compose_reaction(message_id, "🚀", false, "bbbb", &key_state, &history)
+            .await
+            .unwrap();
+        let added = forwarded(&json, &reactor);
+        assert_eq!(
+            handle_incoming_reaction(&peer_history, &added).await,
+            Ok(true)
+        );
+        assert_eq!(
+            peer_history.lock().await.get(message_id).unwrap().reactions["🚀"],
+            vec![reactor.clone()]
+        );
+
+        // A signature for adding can't be replayed as a removal
+        let mut flipped = added.clone();
+        flipped.removed = true;
+        assert!(handle_incoming_reaction(&peer_history, &flipped)
+            .await
+            .is_err());
+
+        let json = compose_reaction(message_id, "🚀", true, "bbbb", &key_state, &history)
+            .await
+            .unwrap();
+        assert!(history
+            .lock()
+            .await
+            .get(message_id)
+            .unwrap()
+            .reactions
+            .is_empty());
+        let removed = forwarded(&json, &reactor);
+        assert_eq!(
+            handle_incoming_reaction(&peer_history, &removed).await,
+            Ok(true)
+        );
+        assert!(peer_history
+            .lock()
+            .await
+            .get(message_id)
+            .unwrap()
+            .reactions
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_overlong_reaction_refused() {
+        let key_state = create_shared_key_state();
+        let history = create_shared_message_history();
+        let result = compose_reaction(
+            Uuid::new_v4(),
+            &"x".repeat(MAX_REACTION_LENGTH + 1),
+            false,
+            "bbbb",
+            &key_state,
+            &history,
+        )
+        .await;
+        assert!(matches!(result, Err(ComposeError::TooLong { .. })));
+    }
+}
@@ -0,0 +1,190 @@
+//! Read receipts for delivered messages
+//!
+//! The recipient's client emits a read receipt once a received message has
+//! been shown in the chat view. The server forwards it to the original
+//! sender as a `Read` protocol message, and the sender's client applies it
+//! to its `ChatView` so each sent message can display read state. The
+//! WebSocket client does both for the conversation opened with
+//! `WebSocketClient::view_conversation`.
+//!
+//! Messages are identified by the UUID the sender assigned when composing
+//! them, which is also the `DisplayMessage::id` on both sides.
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::ui::chat::ChatView;
+use profile_shared::Message;
+
+/// Read receipt received from the server for one of our messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadReceipt {
+    /// ID of the message that was read
+    pub message_id: Uuid,
+    /// Public key of the user who read the message
+    pub reader_public_key: String,
+    /// When the message was read (RFC3339)
+    pub timestamp: String,
+}
+
+/// Create the JSON read receipt for a received message
+///
+/// # Arguments
+/// * `message_id` - ID of the message that was read
+/// * `sender_public_key` - The original sender, who receives the receipt
+///
+/// # Returns
+/// JSON string ready for WebSocket transmission
+pub fn create_read_receipt(message_id: Uuid, sender_public_key: &str) -> String {
+    serde_json::json!({
+        "type": "read",
+        "recipientPublicKey": sender_public_key,
+        "messageId": message_id,
+        "timestamp": Utc::now().to_rfc3339()
+    })
+    .to_string()
+}
+
+/// Create read receipts for every received message shown in the chat view
+///
+/// Each message is only acknowledged once; calling this again after a view
+/// refresh returns receipts for newly displayed messages only.
+///
+/// # Arguments
+/// * `chat_view` - The chat view currently shown to the user
+///
+/// # Returns
+/// JSON read receipts to send to the server
+pub fn create_read_receipts_for_view(chat_view: &mut ChatView) -> Vec<String> {
+    // Locally generated entries (e.g. undelivered notices) have no UUID
+    let pending: Vec<(Uuid, String)> = chat_view
+        .messages()
+        .iter()
+        .filter(|msg| !msg.is_self)
+        .filter_map(|msg| Some((Uuid::parse_str(&msg.id).ok()?, msg.sender_key.clone())))
+        .collect();
+
+    pending
+        .into_iter()
+        .filter(|(id, _)| chat_view.mark_acknowledged(&id.to_string()))
+        .map(|(id, sender)| create_read_receipt(id, &sender))
+        .collect()
+}
+
+/// Parse a read receipt forwarded by the server
+///
+/// # Returns
+/// Some(ReadReceipt) if the JSON is a `Read` protocol message, None otherwise
+pub fn parse_read_receipt(json: &str) -> Option<ReadReceipt> {
+    read_receipt_from_message(serde_json::from_str::<Message>(json).ok()?)
+}
+
+/// Extract a read receipt from an already-parsed server message
+pub fn read_receipt_from_message(message: Message) -> Option<ReadReceipt> {
+    match message {
+        Message::Read {
+            message_id,
+            reader_public_key,
+            timestamp,
+        } => Some(ReadReceipt {
+            message_id,
+            reader_public_key,
+            timestamp,
+        }),
+        _ => None,
+    }
+}
+
+/// Apply a read receipt to the sender's chat view
+///
+/// # Returns
+/// true if a displayed message changed to read
+pub fn apply_read_receipt(chat_view: &mut ChatView, receipt: &ReadReceipt) -> bool {
+    chat_view.mark_read(&receipt.message_id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::messages::ChatMessage;
+    use crate::ui::chat::add_message;
+
+    fn chat_message(sender: &str, timestamp: &str) -> ChatMessage {
+        ChatMessage::new(
+            sender.to_string(),
+            "Hello".to_string(),
+            "sig".to_string(),
+            timestamp.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_create_read_receipt_format() {
+        let id = Uuid::new_v4();
+        let json = create_read_receipt(id, "sender_key");
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed["type"], "read");
+        assert_eq!(parsed["recipientPublicKey"], "sender_key");
+        assert_eq!(parsed["messageId"], id.to_string());
+        assert!(parsed["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_receipts_for_view_only_once_per_received_message() {
+        let mut view = ChatView::new();
+        let received = chat_message("peer", "2025-12-27T10:00:00Z");
+        add_message(&mut view, &received, "me");
+        add_message(&mut view, &chat_message("me", "2025-12-27T10:01:00Z"), "me");
+
+        let receipts = create_read_receipts_for_view(&mut view);
+        assert_eq!(receipts.len(), 1);
+        let parsed: serde_json::Value = serde_json::from_str(&receipts[0]).unwrap();
+        assert_eq!(parsed["messageId"], received.message_id.to_string());
+        assert_eq!(parsed["recipientPublicKey"], "peer");
+
+        assert!(create_read_receipts_for_view(&mut view).is_empty());
+    }
+
+    #[test]
+    fn test_parse_read_receipt() {
+        let id = Uuid::new_v4();
+        let json = serde_json::to_string(&Message::new_read(
+            id,
+            "reader".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        ))
+        .unwrap();
+
+        let receipt = parse_read_receipt(&json).expect("Should parse read receipt");
+        assert_eq!(receipt.message_id, id);
+        assert_eq!(receipt.reader_public_key, "reader");
+
+        assert!(parse_read_receipt(r#"{"type":"message"}"#).is_none());
+    }
+
+    #[test]
+    fn test_read_receipt_round_trip() {
+        // Recipient emits a receipt for the message it displayed
+        let sent = chat_message("sender", "2025-12-27T10:00:00Z");
+        let mut recipient_view = ChatView::new();
+        add_message(&mut recipient_view, &sent, "reader");
+        let receipts = create_read_receipts_for_view(&mut recipient_view);
+        let request: serde_json::Value = serde_json::from_str(&receipts[0]).unwrap();
+
+        // Server forwards it as a Read message
+        let forwarded = serde_json::to_string(&Message::new_read(
+            Uuid::parse_str(request["messageId"].as_str().unwrap()).unwrap(),
+            "reader".to_string(),
+            request["timestamp"].as_str().unwrap().to_string(),
+        ))
+        .unwrap();
+
+        // Sender applies it to its own view
+        let mut sender_view = ChatView::new();
+        add_message(&mut sender_view, &sent, "sender");
+        let receipt = parse_read_receipt(&forwarded).unwrap();
+        assert!(apply_read_receipt(&mut sender_view, &receipt));
+        assert_eq!(sender_view.messages()[0].read_status(), "Read");
+    }
+}
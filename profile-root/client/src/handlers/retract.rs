@@ -0,0 +1,208 @@
+//! Retracting sent direct messages
+//!
+//! A retraction withdraws a message this user sent. It is signed over the
+//! message id and a fresh timestamp, sent as a `retract_message` request
+//! and relayed by the server as a `Message::Retract`, like an edit (see
+//! [`crate::handlers::edit`]).
+//!
+//! Both sides replace the message with a tombstone (see
+//! [`crate::state::messages::MessageHistory::apply_retraction`]). A received
+//! retraction is only applied if its signature verifies under the sender's
+//! key and the message it names came from that same sender. Peers that
+//! already saved or exported the text keep their copy.
+
+use crate::handlers::compose::ComposeError;
+use crate::state::messages::{MessageRetraction, SharedMessageHistory};
+use crate::state::session::SharedKeyState;
+use profile_shared::canonical;
+use profile_shared::crypto::sign_message;
+use profile_shared::{verify_signature, Message, PublicKey};
+use uuid::Uuid;
+
+/// Retraction received from the server, not yet verified
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomingRetraction {
+    /// Id of the message being retracted
+    pub message_id: Uuid,
+    /// Sender as authenticated by the server
+    pub sender_public_key: String,
+    pub retraction: MessageRetraction,
+}
+
+/// Extract a retraction from an already-parsed server message
+pub fn retraction_from_message(message: Message) -> Option<IncomingRetraction> {
+    match message {
+        Message::Retract {
+            message_id,
+            sender_public_key,
+            signature,
+            timestamp,
+        } => Some(IncomingRetraction {
+            message_id,
+            sender_public_key,
+            retraction: MessageRetraction {
+                signature,
+                timestamp,
+            },
+        }),
+        _ => None,
+    }
+}
+
+/// Sign a retraction of one of our messages and apply it to our own history
+///
+/// # Arguments
+/// * `message_id` - Id of the message being retracted
+/// * `recipient_public_key` - Who the original message was sent to
+/// * `key_state` - Shared state containing the user's private key
+/// * `message_history` - History holding the original message
+///
+/// # Returns
+/// JSON `retract_message` request ready for WebSocket transmission
+///
+/// # Errors
+/// Returns an error if no key is loaded or signing fails
+pub async fn compose_retraction(
+    message_id: Uuid,
+    recipient_public_key: &str,
+    key_state: &SharedKeyState,
+    message_history: &SharedMessageHistory,
+) -> Result<String, ComposeError> {
+    let (public_key, timestamp, signature) = {
+        let key_guard = key_state.lock().await;
+        let public_key = key_guard.public_key().ok_or(ComposeError::NoPublicKey)?;
+        let private_key = key_guard.private_key().ok_or(ComposeError::NoPrivateKey)?;
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            private_key,
+            &canonical::message_retract(message_id, &timestamp),
+        )
+        .map_err(|e| ComposeError::SigningError(e.to_string()))?;
+        (hex::encode(public_key), timestamp, hex::encode(signature))
+    };
+
+    message_history.lock().await.apply_retraction(
+        message_id,
+        &public_key,
+        MessageRetraction {
+            signature: signature.clone(),
+            timestamp: timestamp.clone(),
+        },
+    );
+
+    let request = serde_json::json!({
+        "type": "retract_message",
+        "recipientPublicKey": recipient_public_key,
+        "messageId": message_id,
+        "signature": signature,
+        "timestamp": timestamp
+    });
+    serde_json::to_string(&request).map_err(|e| ComposeError::SerializationError(e.to_string()))
+}
+
+/// Check a retraction's signature under its sender's key
+///
+/// # Errors
+/// Returns a user-facing reason if the key or signature is malformed or the
+/// signature doesn't cover the retraction
+pub fn verify_retraction(retraction: &IncomingRetraction) -> Result<(), String> {
+    let key_bytes = hex::decode(&retraction.sender_public_key)
+        .map_err(|e| format!("Invalid public key hex: {}", e))?;
+    let public_key =
+        PublicKey::new(key_bytes).map_err(|e| format!("Invalid public key format: {}", e))?;
+    let signature = hex::decode(&retraction.retraction.signature)
+        .map_err(|e| format!("Invalid signature hex: {}", e))?;
+    let signed =
+        canonical::message_retract(retraction.message_id, &retraction.retraction.timestamp);
+    verify_signature(&public_key, &signed, &signature)
+        .map_err(|e| format!("Retraction signature does not verify: {}", e))
+}
+
+/// Verify a received retraction and apply it to the message it names
+///
+/// # Returns
+/// Ok(true) if the message was replaced with a tombstone, Ok(false) if no
+/// message from its sender has the id or it is already retracted
+///
+/// # Errors
+/// Returns a user-facing reason if the signature doesn't verify
+pub async fn handle_incoming_retraction(
+    message_history: &SharedMessageHistory,
+    retraction: &IncomingRetraction,
+) -> Result<bool, String> {
+    verify_retraction(retraction)?;
+    Ok(message_history.lock().await.apply_retraction(
+        retraction.message_id,
+        &retraction.sender_public_key,
+        retraction.retraction.clone(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::messages::{create_shared_message_history, ChatMessage, RETRACTED_TEXT};
+    use crate::state::session::create_shared_key_state;
+    use profile_shared::{derive_public_key, generate_private_key};
+
+    #[tokio::test]
+    async fn test_composed_retraction_tombstones_both_copies() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let sender = hex::encode(public_key.as_bytes());
+        let key_state = create_shared_key_state();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key);
+
+        let original = ChatMessage::verified(
+            sender.clone(),
+            "Wrong chat".to_string(),
+            "aa".repeat(64),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let message_id = original.message_id;
+        let history = create_shared_message_history();
+        let recipient_history = create_shared_message_history();
+        history.lock().await.add_message(original.clone());
+        recipient_history.lock().await.add_message(original);
+
+        let json = compose_retraction(message_id, "bbbb", &key_state, &history)
+            .await
+            .unwrap();
+        assert!(history.lock().await.get(message_id).unwrap().is_retracted());
+
+        let request: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(request["type"], "retract_message");
+        let incoming = IncomingRetraction {
+            message_id,
+            sender_public_key: sender,
+            retraction: MessageRetraction {
+                signature: request["signature"].as_str().unwrap().to_string(),
+                timestamp: request["timestamp"].as_str().unwrap().to_string(),
+            },
+        };
+
+        // Signed for another message, it doesn't verify
+        let mut moved = incoming.clone();
+        moved.message_id = Uuid::new_v4();
+        assert!(handle_incoming_retraction(&recipient_history, &moved)
+            .await
+            .is_err());
+
+        assert_eq!(
+            handle_incoming_retraction(&recipient_history, &incoming).await,
+            Ok(true)
+        );
+        let stored = recipient_history
+            .lock()
+            .await
+            .get(message_id)
+            .cloned()
+            .unwrap();
+        assert_eq!(stored.current_text(), RETRACTED_TEXT);
+        assert!(stored.is_verified);
+        assert_eq!(stored.signature, "aa".repeat(64));
+    }
+}
@@ -0,0 +1,303 @@
+//! Room handlers for group conversations
+//!
+//! Builds `room_create`, `room_join`, `room_leave` and `room_message`
+//! requests, applies room events from the server to [`RoomsState`], and
+//! selects a room as the active conversation.
+
+use crate::handlers::compose::ComposeError;
+use crate::handlers::verify::{verify_room_message, VerificationResult};
+use crate::state::messages::ChatMessage;
+use crate::state::rooms::SharedRoomsState;
+use crate::state::session::SharedKeyState;
+use profile_shared::canonical::{self, CanonicalVersion};
+use profile_shared::crypto::sign_message;
+use profile_shared::Message;
+
+/// Room event received from the server
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomEvent {
+    /// Membership snapshot after a create/join/leave
+    Update { room: String, members: Vec<String> },
+    /// Message posted to a room (unverified until handled)
+    Message {
+        room: String,
+        message: Box<ChatMessage>,
+    },
+}
+
+/// Create a `room_create` request
+pub fn create_room_request(room: &str) -> String {
+    membership_request("room_create", room)
+}
+
+/// Create a `room_join` request
+pub fn join_room_request(room: &str) -> String {
+    membership_request("room_join", room)
+}
+
+/// Create a `room_leave` request
+pub fn leave_room_request(room: &str) -> String {
+    membership_request("room_leave", room)
+}
+
+fn membership_request(request_type: &str, room: &str) -> String {
+    serde_json::json!({ "type": request_type, "room": room }).to_string()
+}
+
+/// Sign a message for a room and store it in the room's history
+///
+/// # Arguments
+/// * `message_text` - The text content of the message
+/// * `room` - The room to post to (must be joined)
+/// * `key_state` - Shared state containing the user's private key
+/// * `rooms_state` - Shared rooms state for storing the sent message
+///
+/// # Returns
+/// Ok(String) containing the `room_message` JSON for WebSocket transmission
+///
+/// # Errors
+/// Returns `ComposeError` if the message is empty, keys are missing or
+/// signing fails
+pub async fn compose_room_message(
+    message_text: String,
+    room: &str,
+    key_state: &SharedKeyState,
+    rooms_state: &SharedRoomsState,
+) -> Result<String, ComposeError> {
+    if message_text.trim().is_empty() {
+        return Err(ComposeError::EmptyMessage);
+    }
+
+    let (public_key_hex, timestamp, signature) = {
+        let key_guard = key_state.lock().await;
+        let public_key = key_guard.public_key().ok_or(ComposeError::NoPublicKey)?;
+        let private_key = key_guard.private_key().ok_or(ComposeError::NoPrivateKey)?;
+
+        // Covers the room too, so the signature can't be replayed elsewhere
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let canonical_message =
+            canonical::room_message(CanonicalVersion::CURRENT, room, &message_text, &timestamp);
+        let signature = sign_message(private_key, &canonical_message)
+            .map_err(|e| ComposeError::SigningError(e.to_string()))?;
+
+        (hex::encode(public_key), timestamp, hex::encode(signature))
+    };
+
+    rooms_state.lock().await.add_room_message(
+        room,
+        ChatMessage::verified(
+            public_key_hex.clone(),
+            message_text.clone(),
+            signature.clone(),
+            timestamp.clone(),
+        )
+        .with_canonical(CanonicalVersion::CURRENT),
+    );
+
+    let request = serde_json::json!({
+        "type": "room_message",
+        "room": room,
+        "message": message_text,
+        "senderPublicKey": public_key_hex,
+        "signature": signature,
+        "timestamp": timestamp,
+        "canonical": CanonicalVersion::CURRENT
+    });
+
+    serde_json::to_string(&request).map_err(|e| ComposeError::SerializationError(e.to_string()))
+}
+
+/// Parse a room event forwarded by the server
+///
+/// # Returns
+/// Some(RoomEvent) for `RoomUpdate` and `RoomMessage` protocol messages
+pub fn parse_room_event(json: &str) -> Option<RoomEvent> {
+    room_event_from_message(serde_json::from_str::<Message>(json).ok()?)
+}
+
+/// Extract a room event from an already-parsed server message
+pub fn room_event_from_message(message: Message) -> Option<RoomEvent> {
+    match message {
+        Message::RoomUpdate { room, members } => Some(RoomEvent::Update { room, members }),
+        Message::RoomMessage {
+            room,
+            message,
+            sender_public_key,
+            signature,
+            timestamp,
+            canonical,
+        } => Some(RoomEvent::Message {
+            room,
+            message: Box::new(
+                ChatMessage::new(sender_public_key, message, signature, timestamp)
+                    .with_canonical(canonical),
+            ),
+        }),
+        _ => None,
+    }
+}
+
+/// Apply a room event to the rooms state
+///
+/// Room messages are verified before being stored; messages with an
+/// invalid signature are dropped.
+///
+/// # Returns
+/// The verification result for room messages, None for membership updates
+pub async fn handle_room_event(
+    rooms_state: &SharedRoomsState,
+    event: RoomEvent,
+    my_public_key: &str,
+) -> Option<VerificationResult> {
+    match event {
+        RoomEvent::Update { room, members } => {
+            rooms_state
+                .lock()
+                .await
+                .apply_room_update(&room, members, my_public_key);
+            None
+        }
+        RoomEvent::Message { room, message } => {
+            let result = verify_room_message(
+                &room,
+                &message.message,
+                &message.sender_public_key,
+                &message.signature,
+                &message.timestamp,
+                message.canonical,
+            );
+            if let VerificationResult::Valid(ref verified) = result {
+                rooms_state
+                    .lock()
+                    .await
+                    .add_room_message(&room, (**verified).clone());
+            }
+            Some(result)
+        }
+    }
+}
+
+/// Select a joined room as the active conversation
+///
+/// # Returns
+/// true if the room was selected, false if the user is not a member
+pub async fn handle_room_select(rooms_state: &SharedRoomsState, room: &str) -> bool {
+    rooms_state.lock().await.select_room(room)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::rooms::{create_shared_rooms_state, Conversation};
+    use crate::state::session::create_shared_key_state;
+    use profile_shared::crypto::{derive_public_key, generate_private_key};
+
+    #[test]
+    fn test_membership_requests() {
+        let parsed: serde_json::Value =
+            serde_json::from_str(&create_room_request("general")).unwrap();
+        assert_eq!(parsed["type"], "room_create");
+        assert_eq!(parsed["room"], "general");
+
+        let parsed: serde_json::Value =
+            serde_json::from_str(&join_room_request("general")).unwrap();
+        assert_eq!(parsed["type"], "room_join");
+
+        let parsed: serde_json::Value =
+            serde_json::from_str(&leave_room_request("general")).unwrap();
+        assert_eq!(parsed["type"], "room_leave");
+    }
+
+    #[tokio::test]
+    async fn test_room_round_trip() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let my_key = hex::encode(&public_key);
+
+        let key_state = create_shared_key_state();
+        key_state
+            .lock()
+            .await
+            .set_generated_key(private_key, public_key);
+        let rooms_state = create_shared_rooms_state();
+
+        // Server confirms membership
+        let update = serde_json::to_string(&Message::new_room_update(
+            "general".to_string(),
+            vec![my_key.clone()],
+        ))
+        .unwrap();
+        let event = parse_room_event(&update).unwrap();
+        assert!(handle_room_event(&rooms_state, event, &my_key)
+            .await
+            .is_none());
+        assert!(handle_room_select(&rooms_state, "general").await);
+
+        // Compose a room message and feed it back as the server would deliver it
+        let json = compose_room_message("hello".to_string(), "general", &key_state, &rooms_state)
+            .await
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(request["type"], "room_message");
+
+        let delivered = serde_json::to_string(&Message::new_room_message(
+            "general".to_string(),
+            "hello".to_string(),
+            my_key.clone(),
+            request["signature"].as_str().unwrap().to_string(),
+            request["timestamp"].as_str().unwrap().to_string(),
+            serde_json::from_value(request["canonical"].clone()).unwrap(),
+        ))
+        .unwrap();
+        let event = parse_room_event(&delivered).unwrap();
+        let result = handle_room_event(&rooms_state, event, &my_key).await;
+        assert!(matches!(result, Some(VerificationResult::Valid(_))));
+
+        // The signature covers the room, so it can't be replayed into another
+        let replayed = delivered.replace("\"general\"", "\"random\"");
+        let event = parse_room_event(&replayed).unwrap();
+        let result = handle_room_event(&rooms_state, event, &my_key).await;
+        assert!(matches!(result, Some(VerificationResult::Invalid { .. })));
+
+        let state = rooms_state.lock().await;
+        assert_eq!(
+            state.active_conversation(),
+            Some(&Conversation::Room("general".to_string()))
+        );
+        assert_eq!(state.room("general").unwrap().history.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_room_message_dropped() {
+        let rooms_state = create_shared_rooms_state();
+        rooms_state
+            .lock()
+            .await
+            .apply_room_update("general", vec!["me".to_string()], "me");
+
+        let event = RoomEvent::Message {
+            room: "general".to_string(),
+            message: Box::new(ChatMessage::new(
+                "not_hex".to_string(),
+                "forged".to_string(),
+                "sig".to_string(),
+                "2025-12-27T10:00:00Z".to_string(),
+            )),
+        };
+        let result = handle_room_event(&rooms_state, event, "me").await;
+        assert!(matches!(result, Some(VerificationResult::Invalid { .. })));
+        assert!(rooms_state
+            .lock()
+            .await
+            .room("general")
+            .unwrap()
+            .history
+            .is_empty());
+    }
+
+    #[test]
+    fn test_parse_room_event_ignores_other_messages() {
+        let json = serde_json::to_string(&Message::new_error("offline".to_string(), None)).unwrap();
+        assert!(parse_room_event(&json).is_none());
+    }
+}
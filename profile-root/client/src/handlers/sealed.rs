@@ -0,0 +1,332 @@
+//! Sealed direct messages, for forward secrecy
+//!
+//! With forward secrecy on, a composed direct message is not sent as it is:
+//! the whole signed `message` request is encrypted in the ratchet session
+//! with its recipient (see [`crate::state::ratchet_sessions`]) and sent as
+//! a `sealed` request. The server relays the envelope without reading it.
+//!
+//! The recipient decrypts the envelope back into the signed request and
+//! checks it was written by the sender the server named and addressed to
+//! this user, then verifies and stores it like any other chat message. A
+//! message that can't be read is reported, never shown.
+
+use crate::state::conversation::ConversationId;
+use crate::state::messages::ChatMessage;
+use crate::state::ratchet_sessions::SharedRatchetSessions;
+use crate::state::session::SharedKeyState;
+use profile_shared::canonical::CanonicalVersion;
+use profile_shared::crypto::padding::PaddingPolicy;
+use profile_shared::crypto::ratchet::RatchetEnvelope;
+use profile_shared::{Message, RatchetError};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use uuid::Uuid;
+
+/// Error types for sealing and opening direct messages
+#[derive(Debug, Clone, PartialEq)]
+pub enum SealedError {
+    /// No key pair is loaded
+    NoKeys,
+    /// The ratchet session refused the message
+    Ratchet(RatchetError),
+    /// The decrypted payload is not a direct message request
+    Malformed(String),
+    /// The payload names another sender or recipient than it was sent by
+    /// or to
+    Misaddressed,
+}
+
+impl Display for SealedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SealedError::NoKeys => write!(f, "No key pair available"),
+            SealedError::Ratchet(e) => write!(f, "Sealed message unreadable: {}", e),
+            SealedError::Malformed(msg) => write!(f, "Malformed sealed message: {}", msg),
+            SealedError::Misaddressed => {
+                write!(f, "Sealed message names another sender or recipient")
+            }
+        }
+    }
+}
+
+impl Error for SealedError {}
+
+impl From<RatchetError> for SealedError {
+    fn from(e: RatchetError) -> Self {
+        SealedError::Ratchet(e)
+    }
+}
+
+/// Sealed message received from the server, not yet opened
+#[derive(Debug, Clone, PartialEq)]
+pub struct SealedMessage {
+    /// Id the sender gave the outer request
+    pub message_id: Uuid,
+    /// Sender as authenticated by the server
+    pub sender_public_key: String,
+    pub envelope: RatchetEnvelope,
+}
+
+/// Extract a sealed message from an already-parsed server message
+pub fn sealed_message_from_message(message: Message) -> Option<SealedMessage> {
+    match message {
+        Message::Sealed {
+            message_id,
+            sender_public_key,
+            envelope,
+        } => Some(SealedMessage {
+            message_id,
+            sender_public_key,
+            envelope,
+        }),
+        _ => None,
+    }
+}
+
+/// The signed `message` request carried inside an envelope
+#[derive(Debug, Deserialize)]
+struct DirectMessageRequest {
+    r#type: String,
+    #[serde(rename = "messageId", default = "Uuid::new_v4")]
+    message_id: Uuid,
+    #[serde(rename = "recipientPublicKey")]
+    recipient_public_key: String,
+    message: String,
+    #[serde(rename = "senderPublicKey")]
+    sender_public_key: String,
+    signature: String,
+    timestamp: String,
+    #[serde(default)]
+    canonical: CanonicalVersion,
+}
+
+/// Seal a composed direct message for its recipient
+///
+/// # Arguments
+/// * `request_json` - Request to send, as built by
+///   [`crate::handlers::compose::compose_and_send_message`]
+/// * `key_state` - The own keys
+/// * `sessions` - Ratchet sessions with peers
+/// * `padding` - Buckets the message is padded to
+///
+/// # Returns
+/// The `sealed` request to send instead, or None if `request_json` is not a
+/// direct message
+///
+/// # Errors
+/// Returns an error if no key is loaded or no session can be started with
+/// the recipient
+pub async fn seal_direct_message(
+    request_json: &str,
+    key_state: &SharedKeyState,
+    sessions: &SharedRatchetSessions,
+    padding: &PaddingPolicy,
+) -> Result<Option<String>, SealedError> {
+    let Ok(request) = serde_json::from_str::<serde_json::Value>(request_json) else {
+        return Ok(None);
+    };
+    if request.get("type").and_then(|t| t.as_str()) != Some("message") {
+        return Ok(None);
+    }
+    let Some(recipient) = request.get("recipientPublicKey").and_then(|r| r.as_str()) else {
+        return Ok(None);
+    };
+    let peer = ConversationId::new(recipient).map_err(|_| RatchetError::InvalidKey)?;
+
+    let envelope = {
+        let key_guard = key_state.lock().await;
+        let private_key = key_guard.private_key().ok_or(SealedError::NoKeys)?;
+        sessions
+            .lock()
+            .await
+            .seal(private_key, &peer, request_json.as_bytes(), padding)?
+    };
+
+    let mut sealed = serde_json::json!({
+        "type": "sealed",
+        "recipientPublicKey": recipient,
+        "envelope": envelope
+    });
+    if let Some(message_id) = request.get("messageId") {
+        sealed["messageId"] = message_id.clone();
+    }
+    Ok(Some(sealed.to_string()))
+}
+
+/// Open a sealed message into the chat message it carries
+///
+/// The chat message still has to be verified before it is stored.
+///
+/// # Errors
+/// Returns an error if no key is loaded, the envelope can't be decrypted,
+/// or it doesn't hold a direct message from its sender to this user
+pub async fn open_sealed_message(
+    sealed: &SealedMessage,
+    key_state: &SharedKeyState,
+    sessions: &SharedRatchetSessions,
+) -> Result<ChatMessage, SealedError> {
+    let peer =
+        ConversationId::new(&sealed.sender_public_key).map_err(|_| RatchetError::InvalidKey)?;
+    let (own_public_key, plaintext) = {
+        let key_guard = key_state.lock().await;
+        let private_key = key_guard.private_key().ok_or(SealedError::NoKeys)?;
+        let own_public_key = hex::encode(key_guard.public_key().ok_or(SealedError::NoKeys)?);
+        let own = ConversationId::new(&own_public_key).map_err(|_| RatchetError::InvalidKey)?;
+        let plaintext = sessions
+            .lock()
+            .await
+            .open(private_key, &own, &peer, &sealed.envelope)?;
+        (own_public_key, plaintext)
+    };
+
+    let request: DirectMessageRequest =
+        serde_json::from_slice(&plaintext).map_err(|e| SealedError::Malformed(e.to_string()))?;
+    if request.r#type != "message" {
+        return Err(SealedError::Malformed(format!(
+            "unexpected type {}",
+            request.r#type
+        )));
+    }
+    if !request
+        .sender_public_key
+        .eq_ignore_ascii_case(&sealed.sender_public_key)
+        || !request
+            .recipient_public_key
+            .eq_ignore_ascii_case(&own_public_key)
+    {
+        return Err(SealedError::Misaddressed);
+    }
+
+    Ok(ChatMessage::new(
+        sealed.sender_public_key.to_lowercase(),
+        request.message,
+        request.signature.to_lowercase(),
+        request.timestamp,
+    )
+    .with_message_id(request.message_id)
+    .with_canonical(request.canonical))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::compose::compose_and_send_message;
+    use crate::handlers::verify::{verify_chat_message, VerificationResult};
+    use crate::state::messages::create_shared_message_history;
+    use crate::state::ratchet_sessions::create_shared_ratchet_sessions;
+    use crate::state::session::{create_shared_key_state, handle_generate_key_async};
+
+    struct Party {
+        keys: SharedKeyState,
+        public_key: String,
+        sessions: SharedRatchetSessions,
+    }
+
+    async fn party() -> Party {
+        let keys = create_shared_key_state();
+        let public_key = handle_generate_key_async(&keys).await.unwrap();
+        Party {
+            keys,
+            public_key,
+            sessions: create_shared_ratchet_sessions(),
+        }
+    }
+
+    async fn seal(from: &Party, to: &Party, text: &str) -> String {
+        let request = compose_and_send_message(
+            text.to_string(),
+            to.public_key.clone(),
+            &from.keys,
+            &create_shared_message_history(),
+        )
+        .await
+        .unwrap();
+        seal_direct_message(
+            &request,
+            &from.keys,
+            &from.sessions,
+            &PaddingPolicy::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap()
+    }
+
+    /// What the server forwards for a sealed request from `from`
+    fn relay(from: &Party, sealed_json: &str) -> SealedMessage {
+        let request: serde_json::Value = serde_json::from_str(sealed_json).unwrap();
+        sealed_message_from_message(Message::new_sealed(
+            serde_json::from_value(request["messageId"].clone()).unwrap(),
+            from.public_key.clone(),
+            serde_json::from_value(request["envelope"].clone()).unwrap(),
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sealed_message_opens_to_verified_chat_message() {
+        let (alice, bob) = (party().await, party().await);
+        let sealed_json = seal(&alice, &bob, "secret: hello").await;
+        let request: serde_json::Value = serde_json::from_str(&sealed_json).unwrap();
+        assert_eq!(request["type"], "sealed");
+        assert_eq!(request["recipientPublicKey"], bob.public_key.as_str());
+        assert!(!sealed_json.contains("secret"));
+
+        let sealed = relay(&alice, &sealed_json);
+        let message = open_sealed_message(&sealed, &bob.keys, &bob.sessions)
+            .await
+            .unwrap();
+        assert_eq!(message.message, "secret: hello");
+        assert_eq!(message.message_id, sealed.message_id);
+        assert!(matches!(
+            verify_chat_message(&message),
+            VerificationResult::Valid(_)
+        ));
+
+        // Each message key is used once
+        assert_eq!(
+            open_sealed_message(&sealed, &bob.keys, &bob.sessions).await,
+            Err(SealedError::Ratchet(RatchetError::Replayed))
+        );
+        assert_eq!(sealed_message_from_message(Message::Close), None);
+    }
+
+    #[tokio::test]
+    async fn test_only_direct_messages_are_sealed() {
+        let alice = party().await;
+        let sessions = create_shared_ratchet_sessions();
+        let padding = PaddingPolicy::default();
+        for request in [r#"{"type":"read"}"#, "not json"] {
+            assert_eq!(
+                seal_direct_message(request, &alice.keys, &sessions, &padding).await,
+                Ok(None)
+            );
+        }
+        let no_keys = create_shared_key_state();
+        let request = serde_json::json!({
+            "type": "message",
+            "recipientPublicKey": alice.public_key
+        })
+        .to_string();
+        assert_eq!(
+            seal_direct_message(&request, &no_keys, &sessions, &padding).await,
+            Err(SealedError::NoKeys)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sealed_message_from_someone_else_refused() {
+        let (alice, bob, carol) = (party().await, party().await, party().await);
+        // Alice seals a message for Carol; the server can't make Bob read it
+        let sealed = relay(&alice, &seal(&alice, &carol, "for carol").await);
+        assert!(matches!(
+            open_sealed_message(&sealed, &bob.keys, &bob.sessions).await,
+            Err(SealedError::Ratchet(_))
+        ));
+        assert_eq!(
+            SealedError::Misaddressed.to_string(),
+            "Sealed message names another sender or recipient"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Starred message handlers
+//!
+//! Starring and unstarring save the collection straight away. Jumping to a
+//! starred message selects the conversation it was posted in and, for
+//! direct conversations, focuses the message in the chat view.
+
+use crate::state::conversation::ConversationId;
+use crate::state::messages::{ChatMessage, SharedMessageHistory};
+use crate::state::rooms::{Conversation, SharedRoomsState};
+use crate::state::starred::{SharedStarredMessages, StarredError, StarredMessages};
+use crate::state::SharedLobbyState;
+use crate::ui::chat::{update_chat_view, ChatView};
+
+/// Apply a change to the collection and save it if anything changed
+async fn update_and_save(
+    starred: &SharedStarredMessages,
+    change: impl FnOnce(&mut StarredMessages) -> bool,
+) -> Result<bool, StarredError> {
+    let mut starred = starred.lock().await;
+    let changed = change(&mut starred);
+    if changed {
+        starred.save()?;
+    }
+    Ok(changed)
+}
+
+/// Star a message posted in `conversation`
+///
+/// # Returns
+/// Whether the message was not starred before
+pub async fn handle_star_message(
+    starred: &SharedStarredMessages,
+    conversation: Conversation,
+    message: &ChatMessage,
+) -> Result<bool, StarredError> {
+    update_and_save(starred, |s| s.star(conversation, message)).await
+}
+
+/// Remove a message's star
+///
+/// # Returns
+/// Whether the message was starred
+pub async fn handle_unstar_message(
+    starred: &SharedStarredMessages,
+    message_id: &str,
+) -> Result<bool, StarredError> {
+    update_and_save(starred, |s| s.unstar(message_id)).await
+}
+
+/// Open the conversation a starred message was posted in
+///
+/// Direct conversations are selected in the lobby (if the peer is there)
+/// and the chat view is refreshed with the message focused. Room messages
+/// make the room the active conversation.
+///
+/// # Returns
+/// The conversation opened, or None if the message isn't starred or the
+/// room was left since
+pub async fn handle_jump_to_starred(
+    starred: &SharedStarredMessages,
+    lobby_state: &SharedLobbyState,
+    rooms_state: &SharedRoomsState,
+    chat_view: &mut ChatView,
+    message_history: &SharedMessageHistory,
+    message_id: &str,
+    my_public_key: &str,
+) -> Option<Conversation> {
+    let conversation = starred.lock().await.get(message_id)?.conversation.clone();
+    match &conversation {
+        Conversation::Direct(public_key) => {
+            let recipient = ConversationId::new(public_key).ok()?;
+            lobby_state.lock().await.select(public_key);
+            rooms_state.lock().await.select_direct(public_key.clone());
+            chat_view.set_selected_recipient(Some(recipient));
+            update_chat_view(chat_view, message_history, my_public_key).await;
+            chat_view.focus_message(message_id);
+        }
+        Conversation::Room(name) => {
+            if !rooms_state.lock().await.select_room(name) {
+                return None;
+            }
+        }
+    }
+    Some(conversation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::create_shared_lobby_state;
+    use crate::state::messages::create_shared_message_history;
+    use crate::state::rooms::create_shared_rooms_state;
+    use crate::state::starred::create_shared_starred_messages;
+    use crate::ui::lobby_state::LobbyUser;
+
+    fn message(sender: &str, text: &str, timestamp: &str) -> ChatMessage {
+        ChatMessage::new(
+            sender.to_string(),
+            text.to_string(),
+            "sig".to_string(),
+            timestamp.to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_jump_to_starred_direct_message_focuses_it() {
+        let starred = create_shared_starred_messages();
+        let lobby_state = create_shared_lobby_state();
+        let rooms_state = create_shared_rooms_state();
+        let message_history = create_shared_message_history();
+        let alice = "ab".repeat(32);
+        lobby_state
+            .lock()
+            .await
+            .add_user(LobbyUser::new(alice.clone(), true));
+
+        let first = message(&alice, "first", "2025-12-27T10:00:00Z");
+        let second = message(&alice, "second", "2025-12-27T10:01:00Z");
+        message_history.lock().await.add_message(first.clone());
+        message_history.lock().await.add_message(second);
+        let id = first.message_id.to_string();
+        assert_eq!(
+            handle_star_message(&starred, Conversation::Direct(alice.clone()), &first).await,
+            Ok(true)
+        );
+
+        let mut chat_view = ChatView::new();
+        let opened = handle_jump_to_starred(
+            &starred,
+            &lobby_state,
+            &rooms_state,
+            &mut chat_view,
+            &message_history,
+            &id,
+            "me",
+        )
+        .await;
+        assert_eq!(opened, Some(Conversation::Direct(alice.clone())));
+        assert_eq!(
+            lobby_state.lock().await.selected_user(),
+            Some(alice.as_str())
+        );
+        assert_eq!(chat_view.message_count(), 2);
+        assert_eq!(chat_view.focused_message(), Some(id.as_str()));
+        assert!(chat_view.is_scrolling());
+
+        assert_eq!(handle_unstar_message(&starred, &id).await, Ok(true));
+        let opened = handle_jump_to_starred(
+            &starred,
+            &lobby_state,
+            &rooms_state,
+            &mut chat_view,
+            &message_history,
+            &id,
+            "me",
+        )
+        .await;
+        assert_eq!(opened, None);
+    }
+
+    #[tokio::test]
+    async fn test_jump_to_starred_room_message_needs_membership() {
+        let starred = create_shared_starred_messages();
+        let lobby_state = create_shared_lobby_state();
+        let rooms_state = create_shared_rooms_state();
+        let message_history = create_shared_message_history();
+        let post = message("bob", "hello room", "2025-12-27T10:00:00Z");
+        let id = post.message_id.to_string();
+        let general = Conversation::Room("general".to_string());
+        handle_star_message(&starred, general.clone(), &post)
+            .await
+            .unwrap();
+
+        let mut chat_view = ChatView::new();
+        for member in [false, true] {
+            if member {
+                rooms_state
+                    .lock()
+                    .await
+                    .apply_room_update("general", vec!["me".to_string()], "me");
+            }
+            let opened = handle_jump_to_starred(
+                &starred,
+                &lobby_state,
+                &rooms_state,
+                &mut chat_view,
+                &message_history,
+                &id,
+                "me",
+            )
+            .await;
+            assert_eq!(opened, member.then(|| general.clone()));
+        }
+        assert_eq!(
+            rooms_state.lock().await.active_conversation(),
+            Some(&general)
+        );
+    }
+}
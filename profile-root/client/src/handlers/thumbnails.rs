@@ -0,0 +1,136 @@
+//! Preview images for attachments
+//!
+//! When the file offered is a PNG, JPEG, GIF or WebP image, the sender
+//! scales it down to fit [`MAX_THUMBNAIL_DIMENSION`] and encodes it as a
+//! JPEG of at most [`MAX_THUMBNAIL_SIZE`] bytes, which goes into the signed
+//! manifest (see [`crate::handlers::attachments::compose_attachment_offer`]).
+//! The recipient decodes it with the same limits, so chat can show the
+//! preview before the file is accepted. Anything that fails to decode is
+//! treated as having no preview.
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits, RgbaImage};
+use profile_shared::config::attachments::{MAX_THUMBNAIL_DIMENSION, MAX_THUMBNAIL_SIZE};
+use std::io::Cursor;
+
+/// Largest width or height of an image the sender will scale down
+const MAX_SOURCE_DIMENSION: u32 = 16_384;
+
+/// Most memory decoding an image to scale down may use, in bytes
+const MAX_SOURCE_ALLOCATION: u64 = 256 * 1024 * 1024;
+
+/// JPEG qualities tried in turn until the preview fits
+const THUMBNAIL_QUALITIES: [u8; 3] = [80, 60, 40];
+
+/// Encode a preview of `data` if it is an image
+///
+/// # Returns
+/// JPEG bytes of at most [`MAX_THUMBNAIL_SIZE`], None if `data` isn't an
+/// image in a supported format or no preview fits
+pub fn generate_thumbnail(data: &[u8]) -> Option<Vec<u8>> {
+    let format = image::guess_format(data).ok()?;
+    if !matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP
+    ) {
+        return None;
+    }
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    limits.max_alloc = Some(MAX_SOURCE_ALLOCATION);
+    let mut image = decode(data, format, limits)?;
+    if image.width() > MAX_THUMBNAIL_DIMENSION || image.height() > MAX_THUMBNAIL_DIMENSION {
+        image = image.thumbnail(MAX_THUMBNAIL_DIMENSION, MAX_THUMBNAIL_DIMENSION);
+    }
+    let preview = image.to_rgb8();
+
+    THUMBNAIL_QUALITIES.iter().find_map(|&quality| {
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, quality)
+            .encode_image(&preview)
+            .ok()?;
+        (encoded.len() <= MAX_THUMBNAIL_SIZE).then_some(encoded)
+    })
+}
+
+/// Decode a preview received in a manifest
+///
+/// # Returns
+/// The preview's pixels, None if it isn't a JPEG or PNG within
+/// [`MAX_THUMBNAIL_DIMENSION`] on both sides
+pub fn decode_thumbnail(encoded: &[u8]) -> Option<RgbaImage> {
+    if encoded.len() > MAX_THUMBNAIL_SIZE {
+        return None;
+    }
+    let format = image::guess_format(encoded).ok()?;
+    if !matches!(format, ImageFormat::Jpeg | ImageFormat::Png) {
+        return None;
+    }
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_THUMBNAIL_DIMENSION);
+    limits.max_image_height = Some(MAX_THUMBNAIL_DIMENSION);
+    Some(decode(encoded, format, limits)?.to_rgba8())
+}
+
+/// A decoded preview as an image the UI can show
+pub fn thumbnail_image(thumbnail: &RgbaImage) -> slint::Image {
+    slint::Image::from_rgba8(slint::SharedPixelBuffer::clone_from_slice(
+        thumbnail.as_raw(),
+        thumbnail.width(),
+        thumbnail.height(),
+    ))
+}
+
+fn decode(data: &[u8], format: ImageFormat, limits: Limits) -> Option<DynamicImage> {
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    reader.decode().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 256) as u8])
+        });
+        let mut encoded = Cursor::new(Vec::new());
+        image.write_to(&mut encoded, ImageFormat::Png).unwrap();
+        encoded.into_inner()
+    }
+
+    #[test]
+    fn test_large_image_scaled_to_fit() {
+        let thumbnail = generate_thumbnail(&png(1024, 512)).unwrap();
+        assert!(thumbnail.len() <= MAX_THUMBNAIL_SIZE);
+        assert_eq!(image::guess_format(&thumbnail).unwrap(), ImageFormat::Jpeg);
+
+        let decoded = decode_thumbnail(&thumbnail).unwrap();
+        assert_eq!(decoded.dimensions(), (MAX_THUMBNAIL_DIMENSION, 64));
+        let image = thumbnail_image(&decoded);
+        assert_eq!(image.size().width, MAX_THUMBNAIL_DIMENSION);
+    }
+
+    #[test]
+    fn test_small_image_not_enlarged() {
+        let thumbnail = generate_thumbnail(&png(20, 10)).unwrap();
+        let decoded = decode_thumbnail(&thumbnail).unwrap();
+        assert_eq!(decoded.dimensions(), (20, 10));
+    }
+
+    #[test]
+    fn test_non_images_and_oversized_previews_have_none() {
+        assert_eq!(generate_thumbnail(b"quarterly report"), None);
+        assert_eq!(generate_thumbnail(&[0x89, b'P', b'N', b'G']), None);
+        assert_eq!(decode_thumbnail(b"quarterly report"), None);
+
+        // A preview claiming more than the allowed dimensions is refused
+        let big = png(MAX_THUMBNAIL_DIMENSION + 1, 1);
+        assert!(big.len() <= MAX_THUMBNAIL_SIZE);
+        assert!(decode_thumbnail(&big).is_none());
+        assert_eq!(decode_thumbnail(&png(8, 8)).unwrap().dimensions(), (8, 8));
+    }
+}
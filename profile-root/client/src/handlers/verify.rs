@@ -10,13 +10,14 @@
 
 use crate::state::messages::ChatMessage;
 use hex;
+use profile_shared::canonical::{self, CanonicalVersion};
 use profile_shared::verify_signature;
 
 /// Result of message verification
 #[derive(Debug, Clone, PartialEq)]
 pub enum VerificationResult {
     /// Message signature is valid
-    Valid(ChatMessage),
+    Valid(Box<ChatMessage>),
     /// Message signature is invalid
     Invalid {
         sender_public_key: String,
@@ -35,6 +36,7 @@ pub enum VerificationResult {
 /// * `sender_public_key` - Hex-encoded public key of sender
 /// * `signature` - Hex-encoded signature
 /// * `timestamp` - ISO 8601 timestamp from message
+/// * `canonical` - Canonical encoding the sender says it signed
 ///
 /// # Returns
 /// VerificationResult indicating valid or invalid
@@ -46,6 +48,82 @@ pub fn verify_message(
     sender_public_key: &str,
     signature: &str,
     timestamp: &str,
+    canonical: CanonicalVersion,
+) -> VerificationResult {
+    verify_direct_message(
+        message,
+        sender_public_key,
+        signature,
+        timestamp,
+        canonical,
+        None,
+    )
+}
+
+/// Verify a received message signature that may cover a sequence number
+///
+/// Like [`verify_message`], but a numbered message is checked against
+/// `canonical::direct_message`, so a relay can't strip or change the number.
+pub fn verify_direct_message(
+    message: &str,
+    sender_public_key: &str,
+    signature: &str,
+    timestamp: &str,
+    canonical: CanonicalVersion,
+    sequence: Option<u64>,
+) -> VerificationResult {
+    // Create canonical message for verification (same encoding as signing)
+    let canonical_message = canonical::direct_message(canonical, message, timestamp, sequence);
+
+    match verify_signed(
+        &canonical_message,
+        message,
+        sender_public_key,
+        signature,
+        timestamp,
+    ) {
+        VerificationResult::Valid(chat_msg) => VerificationResult::Valid(Box::new(
+            chat_msg.with_canonical(canonical).with_sequence(sequence),
+        )),
+        invalid => invalid,
+    }
+}
+
+/// Verify a received room message signature
+///
+/// Like [`verify_message`], but checked against `canonical::room_message`,
+/// so a message signed for another room is rejected.
+pub fn verify_room_message(
+    room: &str,
+    message: &str,
+    sender_public_key: &str,
+    signature: &str,
+    timestamp: &str,
+    canonical: CanonicalVersion,
+) -> VerificationResult {
+    let canonical_message = canonical::room_message(canonical, room, message, timestamp);
+
+    match verify_signed(
+        &canonical_message,
+        message,
+        sender_public_key,
+        signature,
+        timestamp,
+    ) {
+        VerificationResult::Valid(chat_msg) => {
+            VerificationResult::Valid(Box::new(chat_msg.with_canonical(canonical)))
+        }
+        invalid => invalid,
+    }
+}
+
+/// Check `signature` over `canonical_message` and build the verified message
+fn verify_signed(
+    canonical_message: &[u8],
+    message: &str,
+    sender_public_key: &str,
+    signature: &str,
+    timestamp: &str,
 ) -> VerificationResult {
     // Decode hex strings
     let sender_key_bytes = match hex::decode(sender_public_key) {
@@ -78,15 +156,8 @@ pub fn verify_message(
         }
     };
 
-    // Create canonical message for verification (same format as signing)
-    let canonical_message = format!("{}:{}", message, timestamp);
-
     // Verify signature
-    match verify_signature(
-        &sender_public_key_obj,
-        canonical_message.as_bytes(),
-        &signature_bytes,
-    ) {
+    match verify_signature(&sender_public_key_obj, canonical_message, &signature_bytes) {
         Ok(()) => {
             // Signature is valid - create verified ChatMessage
             let chat_msg = ChatMessage::verified(
@@ -95,7 +166,7 @@ pub fn verify_message(
                 signature.to_string(),
                 timestamp.to_string(),
             );
-            VerificationResult::Valid(chat_msg)
+            VerificationResult::Valid(Box::new(chat_msg))
         }
         Err(e) => {
             // Signature is invalid
@@ -110,7 +181,8 @@ pub fn verify_message(
 /// Verify a ChatMessage that was parsed from JSON
 ///
 /// The ChatMessage already contains sender, message, signature, and timestamp.
-/// This function extracts these and performs verification.
+/// This function extracts these and performs verification. The verified
+/// message keeps the original message id and server receipt time.
 ///
 /// # Arguments
 /// * `chat_msg` - The parsed ChatMessage to verify
@@ -118,12 +190,21 @@ pub fn verify_message(
 /// # Returns
 /// VerificationResult indicating valid or invalid
 pub fn verify_chat_message(chat_msg: &ChatMessage) -> VerificationResult {
-    verify_message(
+    match verify_direct_message(
         &chat_msg.message,
         &chat_msg.sender_public_key,
         &chat_msg.signature,
         &chat_msg.timestamp,
-    )
+        chat_msg.canonical,
+        chat_msg.sequence,
+    ) {
+        VerificationResult::Valid(verified) => VerificationResult::Valid(Box::new(
+            verified
+                .with_message_id(chat_msg.message_id)
+                .with_server_received_at(chat_msg.server_received_at.clone()),
+        )),
+        invalid => invalid,
+    }
 }
 
 /// Create an error notification message for invalid signature
@@ -175,8 +256,8 @@ mod tests {
         // Create a signed message
         let message = "Hello, world!";
         let timestamp = "2025-12-27T10:30:00Z";
-        let canonical_message = format!("{}:{}", message, timestamp);
-        let signature = sign_message(&private_key, canonical_message.as_bytes()).unwrap();
+        let canonical_message = canonical::message(CanonicalVersion::CURRENT, message, timestamp);
+        let signature = sign_message(&private_key, &canonical_message).unwrap();
 
         // Verify the signature
         let result = verify_message(
@@ -184,6 +265,7 @@ mod tests {
             &hex::encode(&public_key),
             &hex::encode(signature),
             timestamp,
+            CanonicalVersion::CURRENT,
         );
 
         assert!(matches!(result, VerificationResult::Valid(_)));
@@ -207,6 +289,7 @@ mod tests {
             &hex::encode(&public_key),
             &hex::encode(invalid_signature),
             "2025-12-27T10:30:00Z",
+            CanonicalVersion::CURRENT,
         );
 
         assert!(matches!(result, VerificationResult::Invalid { .. }));
@@ -223,8 +306,8 @@ mod tests {
         // Sign with key1
         let message = "test";
         let timestamp = "2025-12-27T10:30:00Z";
-        let canonical_message = format!("{}:{}", message, timestamp);
-        let signature = sign_message(&private_key1, canonical_message.as_bytes()).unwrap();
+        let canonical_message = canonical::message(CanonicalVersion::CURRENT, message, timestamp);
+        let signature = sign_message(&private_key1, &canonical_message).unwrap();
 
         // Try to verify with key2's public key (should fail)
         let result = verify_message(
@@ -232,6 +315,7 @@ mod tests {
             &hex::encode(&public_key2), // Wrong key!
             &hex::encode(signature),
             timestamp,
+            CanonicalVersion::CURRENT,
         );
 
         assert!(matches!(result, VerificationResult::Invalid { .. }));
@@ -244,6 +328,7 @@ mod tests {
             "not_valid_hex",
             "valid_signature_here_000000000000000000000000000000000000000000000000000000000000",
             "2025-12-27T10:30:00Z",
+            CanonicalVersion::CURRENT,
         );
 
         match result {
@@ -262,18 +347,116 @@ mod tests {
 
         let message = "Test message";
         let timestamp = "2025-12-27T10:30:00Z";
-        let canonical_message = format!("{}:{}", message, timestamp);
-        let signature = sign_message(&private_key, canonical_message.as_bytes()).unwrap();
+        let canonical_message = canonical::message(CanonicalVersion::CURRENT, message, timestamp);
+        let signature = sign_message(&private_key, &canonical_message).unwrap();
 
         let chat_msg = ChatMessage::new(
             hex::encode(&public_key),
             message.to_string(),
             hex::encode(signature),
             timestamp.to_string(),
-        );
+        )
+        .with_canonical(CanonicalVersion::CURRENT);
 
         let result = verify_chat_message(&chat_msg);
-        assert!(matches!(result, VerificationResult::Valid(_)));
+        assert!(matches!(
+            result,
+            VerificationResult::Valid(verified) if verified.canonical == CanonicalVersion::CURRENT
+        ));
+    }
+
+    #[test]
+    fn test_verify_checks_sequence() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap());
+        let timestamp = "2025-12-27T10:30:00Z";
+        let signature = hex::encode(
+            sign_message(
+                &private_key,
+                &canonical::sequenced_message("hi", timestamp, 4),
+            )
+            .unwrap(),
+        );
+        let chat_msg = ChatMessage::new(
+            public_key,
+            "hi".to_string(),
+            signature,
+            timestamp.to_string(),
+        )
+        .with_canonical(CanonicalVersion::CURRENT)
+        .with_sequence(Some(4));
+
+        assert!(matches!(
+            verify_chat_message(&chat_msg),
+            VerificationResult::Valid(verified) if verified.sequence == Some(4)
+        ));
+        // Stripping or changing the number breaks the signature
+        for sequence in [None, Some(5)] {
+            assert!(matches!(
+                verify_chat_message(&chat_msg.clone().with_sequence(sequence)),
+                VerificationResult::Invalid { .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_checks_canonical_version() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap());
+        let timestamp = "2025-12-27T10:30:00Z";
+        let sign = |version, message| {
+            hex::encode(
+                sign_message(
+                    &private_key,
+                    &canonical::message(version, message, timestamp),
+                )
+                .unwrap(),
+            )
+        };
+
+        // Older clients sign message:timestamp and say nothing
+        let legacy = sign(CanonicalVersion::V1, "hi");
+        assert!(matches!(
+            verify_message("hi", &public_key, &legacy, timestamp, CanonicalVersion::V1),
+            VerificationResult::Valid(verified) if verified.canonical == CanonicalVersion::V1
+        ));
+
+        // A signature only holds for the version it was made over
+        let current = sign(CanonicalVersion::CURRENT, "a:b");
+        assert!(matches!(
+            verify_message(
+                "a:b",
+                &public_key,
+                &current,
+                timestamp,
+                CanonicalVersion::V1
+            ),
+            VerificationResult::Invalid { .. }
+        ));
+
+        // Version 1 can't tell where the message ends and the timestamp begins
+        let shifted = sign(CanonicalVersion::V1, "hi:2025");
+        assert!(matches!(
+            verify_message(
+                "hi",
+                &public_key,
+                &shifted,
+                &format!("2025:{}", timestamp),
+                CanonicalVersion::V1
+            ),
+            VerificationResult::Valid(_)
+        ));
+        let shifted = sign(CanonicalVersion::CURRENT, "hi:2025");
+        assert!(matches!(
+            verify_message(
+                "hi",
+                &public_key,
+                &shifted,
+                &format!("2025:{}", timestamp),
+                CanonicalVersion::CURRENT
+            ),
+            VerificationResult::Invalid { .. }
+        ));
     }
 
     #[test]
@@ -308,8 +491,8 @@ mod tests {
 
         let message = "Performance test message";
         let timestamp = "2025-12-27T10:30:00Z";
-        let canonical_message = format!("{}:{}", message, timestamp);
-        let signature = sign_message(&private_key, canonical_message.as_bytes()).unwrap();
+        let canonical_message = canonical::message(CanonicalVersion::CURRENT, message, timestamp);
+        let signature = sign_message(&private_key, &canonical_message).unwrap();
 
         // Run verification multiple times and measure
         let iterations = 100;
@@ -320,6 +503,7 @@ mod tests {
                 &hex::encode(&public_key),
                 &hex::encode(&signature),
                 timestamp,
+                CanonicalVersion::CURRENT,
             );
         }
         let elapsed = start.elapsed();
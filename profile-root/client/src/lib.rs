@@ -3,7 +3,12 @@
 //! This library crate is separate from the binary (main.rs) to enable
 //! integration tests to import internal modules.
 
+pub mod bot;
+pub mod config;
+pub mod conformance;
 pub mod connection;
+pub mod diagnostics;
+pub mod events;
 pub mod handlers;
 pub mod state;
 pub mod ui;
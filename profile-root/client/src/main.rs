@@ -1,13 +1,21 @@
 //! Profile client application (Slint UI + core crypto functionality).
 
+use profile_client::ui::chat::SharedChatView;
+use profile_client::ui::sound::{SoundCues, SoundUiBridge};
+use profile_client::ui::stats::{StatsRow, StatsUiBridge, StatsView};
+use profile_client::ui::status::{Announcement, StatusQueue, StatusUiBridge};
+use profile_client::ui::translation::MessageTranslator;
 use profile_client::{handlers, state};
 
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 const MAX_LOBBY_USERS: usize = 5;
 const MAX_CHAT_MESSAGES: usize = 10;
+const MAX_STARRED_SHOWN: usize = 5;
 
 slint::include_modules!();
 
@@ -50,34 +58,143 @@ fn copy_to_clipboard(text: &str) -> Result<(), String> {
     }
 }
 
+impl StatusUiBridge for AppWindow {
+    fn show_status(&self, text: &str, is_error: bool) {
+        self.set_status_is_error(is_error);
+        self.set_status_message(text.into());
+    }
+}
+
+impl SoundUiBridge for AppWindow {
+    fn set_sound_settings(&self, settings: &state::SoundSettings, do_not_disturb: bool) {
+        self.set_sound_enabled(settings.enabled);
+        self.set_sound_muted(settings.muted);
+        self.set_sound_do_not_disturb(do_not_disturb);
+        self.set_sound_volume(i32::from(settings.volume()));
+        self.set_sound_message_received(settings.message_received);
+        self.set_sound_user_joined(settings.user_joined);
+        self.set_sound_send_failed(settings.send_failed);
+    }
+}
+
+impl StatsUiBridge for AppWindow {
+    fn set_stats_rows(&self, rows: &[StatsRow]) {
+        let lines: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                format!(
+                    "{}: {} sent, {} received, last active {}",
+                    row.label, row.sent, row.received, row.last_active
+                )
+            })
+            .collect();
+        self.set_stats_rows_text(lines.join("\n").into());
+    }
+
+    fn set_stats_summary(&self, summary: &str) {
+        self.set_stats_summary_text(summary.into());
+    }
+
+    fn set_stats_notice(&self, notice: &str) {
+        self.set_stats_notice_text(notice.into());
+    }
+}
+
+/// Render the usage statistics, labelling peers from the saved contacts
+async fn update_stats_ui(ui: &AppWindow, usage_stats: &state::SharedUsageStats) {
+    let contacts = state::contacts::default_contacts_path()
+        .and_then(|path| state::Contacts::load(path).ok())
+        .unwrap_or_default();
+    StatsView::from_stats(&*usage_stats.lock().await, &contacts).render(ui);
+}
+
+/// Change the sound settings, save them and show the result
+fn update_sound_settings(
+    ui: &AppWindow,
+    sound_cues: &Rc<RefCell<SoundCues>>,
+    status: &Rc<RefCell<StatusQueue>>,
+    change: impl FnOnce(&mut state::SoundSettings),
+) {
+    let saved = {
+        let mut cues = sound_cues.borrow_mut();
+        change(cues.settings_mut());
+        cues.settings().save()
+    };
+    if let Err(e) = saved {
+        announce(
+            ui,
+            status,
+            Announcement::error(format!("Failed to save sound settings: {}", e)),
+        );
+    }
+    sound_cues.borrow().render(ui);
+}
+
+/// Queue a status line announcement, shown once the current one has been up
+/// long enough
+fn announce(ui: &AppWindow, status: &Rc<RefCell<StatusQueue>>, announcement: Announcement) {
+    if status.borrow_mut().push(announcement, Instant::now()) {
+        status.borrow().render(ui);
+    }
+    schedule_status_poll(ui, status);
+}
+
+/// Show the next waiting announcement when its turn comes
+fn schedule_status_poll(ui: &AppWindow, status: &Rc<RefCell<StatusQueue>>) {
+    let Some(at) = status.borrow().next_change_at() else {
+        return;
+    };
+    let ui_weak = ui.as_weak();
+    let status = status.clone();
+    slint::Timer::single_shot(at.saturating_duration_since(Instant::now()), move || {
+        let Some(ui) = ui_weak.upgrade() else {
+            return;
+        };
+        if status.borrow_mut().poll(Instant::now()) {
+            status.borrow().render(&ui);
+        }
+        schedule_status_poll(&ui, &status);
+    });
+}
+
 /// Helper function to clear all lobby UI slots
 fn clear_lobby_slots(ui: &AppWindow) {
     for i in 1..=MAX_LOBBY_USERS {
         match i {
             1 => {
                 ui.set_lobby_user_1_public_key("".into());
+                ui.set_lobby_user_1_name("".into());
                 ui.set_lobby_user_1_online(true);
                 ui.set_lobby_user_1_selected(false);
+                ui.set_lobby_user_1_unread(0);
             }
             2 => {
                 ui.set_lobby_user_2_public_key("".into());
+                ui.set_lobby_user_2_name("".into());
                 ui.set_lobby_user_2_online(true);
                 ui.set_lobby_user_2_selected(false);
+                ui.set_lobby_user_2_unread(0);
             }
             3 => {
                 ui.set_lobby_user_3_public_key("".into());
+                ui.set_lobby_user_3_name("".into());
                 ui.set_lobby_user_3_online(true);
                 ui.set_lobby_user_3_selected(false);
+                ui.set_lobby_user_3_unread(0);
             }
             4 => {
                 ui.set_lobby_user_4_public_key("".into());
+                ui.set_lobby_user_4_name("".into());
                 ui.set_lobby_user_4_online(true);
                 ui.set_lobby_user_4_selected(false);
+                ui.set_lobby_user_4_unread(0);
             }
             5 => {
                 ui.set_lobby_user_5_public_key("".into());
+                ui.set_lobby_user_5_name("".into());
                 ui.set_lobby_user_5_online(true);
                 ui.set_lobby_user_5_selected(false);
+                ui.set_lobby_user_5_unread(0);
             }
             _ => {
                 debug_assert!(false, "Unexpected slot index: {}", i);
@@ -96,28 +213,38 @@ fn set_lobby_slot(
     match slot {
         0 => {
             ui.set_lobby_user_1_public_key(user.public_key.clone().into());
+            ui.set_lobby_user_1_name(user.verified_name.clone().unwrap_or_default().into());
             ui.set_lobby_user_1_online(user.is_online);
             ui.set_lobby_user_1_selected(is_selected);
+            ui.set_lobby_user_1_unread(user.unread_count as i32);
         }
         1 => {
             ui.set_lobby_user_2_public_key(user.public_key.clone().into());
+            ui.set_lobby_user_2_name(user.verified_name.clone().unwrap_or_default().into());
             ui.set_lobby_user_2_online(user.is_online);
             ui.set_lobby_user_2_selected(is_selected);
+            ui.set_lobby_user_2_unread(user.unread_count as i32);
         }
         2 => {
             ui.set_lobby_user_3_public_key(user.public_key.clone().into());
+            ui.set_lobby_user_3_name(user.verified_name.clone().unwrap_or_default().into());
             ui.set_lobby_user_3_online(user.is_online);
             ui.set_lobby_user_3_selected(is_selected);
+            ui.set_lobby_user_3_unread(user.unread_count as i32);
         }
         3 => {
             ui.set_lobby_user_4_public_key(user.public_key.clone().into());
+            ui.set_lobby_user_4_name(user.verified_name.clone().unwrap_or_default().into());
             ui.set_lobby_user_4_online(user.is_online);
             ui.set_lobby_user_4_selected(is_selected);
+            ui.set_lobby_user_4_unread(user.unread_count as i32);
         }
         4 => {
             ui.set_lobby_user_5_public_key(user.public_key.clone().into());
+            ui.set_lobby_user_5_name(user.verified_name.clone().unwrap_or_default().into());
             ui.set_lobby_user_5_online(user.is_online);
             ui.set_lobby_user_5_selected(is_selected);
+            ui.set_lobby_user_5_unread(user.unread_count as i32);
         }
         _ => {} // Ignore slots beyond MAX_LOBBY_USERS
     }
@@ -128,29 +255,42 @@ fn set_lobby_slot(
 /// This function reads the current lobby state and updates the UI's
 /// slot-based properties (lobby_user_1_public_key, etc.) to display
 /// the users. The UI uses fixed slots (up to 5 for MVP) since
-/// Slint 1.5 doesn't support dynamic for-each loops.
+/// Slint 1.5 doesn't support dynamic for-each loops. Archived
+/// conversations fill the slots after the main list, under an
+/// "Archived" header.
 ///
 /// # Arguments
 ///
 /// * `ui` - Reference to the Slint UI window
 /// * `lobby_state` - Reference to the shared lobby state
+/// * `archive` - Conversations the user archived
 async fn update_lobby_ui(
     ui: &AppWindow,
     lobby_state: &Arc<tokio::sync::Mutex<profile_client::ui::lobby_state::LobbyState>>,
+    archive: &state::SharedArchive,
 ) {
+    let sections = handlers::lobby_sections(archive, lobby_state).await;
     let state = lobby_state.lock().await;
-    let users = state.users_cloned();
     let selected_user = state.selected_user().map(|s| s.to_string());
-    let user_count = users.len();
+    let user_count = sections.active.len() + sections.archived.len();
 
     // Update user count
     ui.set_lobby_user_count(user_count as i32);
+    ui.set_lobby_stale(state.is_stale());
+    ui.set_lobby_archived_count(sections.archived.len() as i32);
+    // 1-based slot the "Archived" header goes above; 0 when none is shown
+    ui.set_lobby_archived_start(if sections.archived.is_empty() {
+        0
+    } else {
+        sections.active.len() as i32 + 1
+    });
 
     // Clear all slots first
     clear_lobby_slots(ui);
 
     // Populate slots with user data (up to 5 for MVP)
-    for (i, user) in users.iter().enumerate().take(MAX_LOBBY_USERS) {
+    let users = sections.active.iter().chain(&sections.archived);
+    for (i, user) in users.enumerate().take(MAX_LOBBY_USERS) {
         let is_selected = selected_user.as_deref() == Some(user.public_key.as_str());
         set_lobby_slot(ui, i, user, is_selected);
     }
@@ -158,8 +298,12 @@ async fn update_lobby_ui(
     // Update selected user display text
     if let Some(ref key) = selected_user {
         ui.set_lobby_selected_user(key.clone().into());
+        ui.set_lobby_selected_archived(
+            sections.archived.iter().any(|user| user.public_key == *key),
+        );
     } else {
         ui.set_lobby_selected_user("".into());
+        ui.set_lobby_selected_archived(false);
     }
 }
 
@@ -170,12 +314,21 @@ fn clear_chat_message_slots(ui: &AppWindow) {
         id: String::new(),
         sender_key: String::new(),
         sender_key_short: String::new(),
+        sender_nickname: None,
+        sender_label: None,
         content: String::new(),
+        previous_versions: Vec::new(),
+        is_retracted: false,
+        reactions: Default::default(),
         timestamp: String::new(),
         signature: String::new(),
         is_verified: false,
         is_self: false,
+        is_read: false,
+        send_state: None,
+        is_starred: false,
         original_timestamp: String::new(),
+        translation: None,
     };
 
     for i in 1..=MAX_CHAT_MESSAGES {
@@ -191,111 +344,244 @@ fn set_chat_message_slot(
 ) {
     match slot {
         1 => {
+            ui.set_chat_msg_1_id(display_msg.id.clone().into());
             ui.set_chat_msg_1_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_1_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_1_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_1_content(display_msg.content.clone().into());
+            ui.set_chat_msg_1_translation(display_msg.translation_text().into());
             ui.set_chat_msg_1_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_1_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_1_is_self(display_msg.is_self);
             ui.set_chat_msg_1_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_1_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_1_is_read(display_msg.is_read);
+            ui.set_chat_msg_1_send_status(display_msg.send_status().into());
         }
         2 => {
+            ui.set_chat_msg_2_id(display_msg.id.clone().into());
             ui.set_chat_msg_2_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_2_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_2_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_2_content(display_msg.content.clone().into());
+            ui.set_chat_msg_2_translation(display_msg.translation_text().into());
             ui.set_chat_msg_2_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_2_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_2_is_self(display_msg.is_self);
             ui.set_chat_msg_2_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_2_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_2_is_read(display_msg.is_read);
+            ui.set_chat_msg_2_send_status(display_msg.send_status().into());
         }
         3 => {
+            ui.set_chat_msg_3_id(display_msg.id.clone().into());
             ui.set_chat_msg_3_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_3_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_3_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_3_content(display_msg.content.clone().into());
+            ui.set_chat_msg_3_translation(display_msg.translation_text().into());
             ui.set_chat_msg_3_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_3_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_3_is_self(display_msg.is_self);
             ui.set_chat_msg_3_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_3_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_3_is_read(display_msg.is_read);
+            ui.set_chat_msg_3_send_status(display_msg.send_status().into());
         }
         4 => {
+            ui.set_chat_msg_4_id(display_msg.id.clone().into());
             ui.set_chat_msg_4_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_4_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_4_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_4_content(display_msg.content.clone().into());
+            ui.set_chat_msg_4_translation(display_msg.translation_text().into());
             ui.set_chat_msg_4_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_4_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_4_is_self(display_msg.is_self);
             ui.set_chat_msg_4_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_4_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_4_is_read(display_msg.is_read);
+            ui.set_chat_msg_4_send_status(display_msg.send_status().into());
         }
         5 => {
+            ui.set_chat_msg_5_id(display_msg.id.clone().into());
             ui.set_chat_msg_5_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_5_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_5_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_5_content(display_msg.content.clone().into());
+            ui.set_chat_msg_5_translation(display_msg.translation_text().into());
             ui.set_chat_msg_5_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_5_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_5_is_self(display_msg.is_self);
             ui.set_chat_msg_5_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_5_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_5_is_read(display_msg.is_read);
+            ui.set_chat_msg_5_send_status(display_msg.send_status().into());
         }
         6 => {
+            ui.set_chat_msg_6_id(display_msg.id.clone().into());
             ui.set_chat_msg_6_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_6_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_6_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_6_content(display_msg.content.clone().into());
+            ui.set_chat_msg_6_translation(display_msg.translation_text().into());
             ui.set_chat_msg_6_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_6_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_6_is_self(display_msg.is_self);
             ui.set_chat_msg_6_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_6_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_6_is_read(display_msg.is_read);
+            ui.set_chat_msg_6_send_status(display_msg.send_status().into());
         }
         7 => {
+            ui.set_chat_msg_7_id(display_msg.id.clone().into());
             ui.set_chat_msg_7_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_7_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_7_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_7_content(display_msg.content.clone().into());
+            ui.set_chat_msg_7_translation(display_msg.translation_text().into());
             ui.set_chat_msg_7_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_7_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_7_is_self(display_msg.is_self);
             ui.set_chat_msg_7_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_7_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_7_is_read(display_msg.is_read);
+            ui.set_chat_msg_7_send_status(display_msg.send_status().into());
         }
         8 => {
+            ui.set_chat_msg_8_id(display_msg.id.clone().into());
             ui.set_chat_msg_8_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_8_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_8_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_8_content(display_msg.content.clone().into());
+            ui.set_chat_msg_8_translation(display_msg.translation_text().into());
             ui.set_chat_msg_8_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_8_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_8_is_self(display_msg.is_self);
             ui.set_chat_msg_8_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_8_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_8_is_read(display_msg.is_read);
+            ui.set_chat_msg_8_send_status(display_msg.send_status().into());
         }
         9 => {
+            ui.set_chat_msg_9_id(display_msg.id.clone().into());
             ui.set_chat_msg_9_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_9_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_9_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_9_content(display_msg.content.clone().into());
+            ui.set_chat_msg_9_translation(display_msg.translation_text().into());
             ui.set_chat_msg_9_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_9_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_9_is_self(display_msg.is_self);
             ui.set_chat_msg_9_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_9_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_9_is_read(display_msg.is_read);
+            ui.set_chat_msg_9_send_status(display_msg.send_status().into());
         }
         10 => {
+            ui.set_chat_msg_10_id(display_msg.id.clone().into());
             ui.set_chat_msg_10_sender_key(display_msg.sender_key.clone().into());
-            ui.set_chat_msg_10_sender_key_short(display_msg.sender_key_short.clone().into());
+            ui.set_chat_msg_10_sender_key_short(display_msg.sender_display_name().into());
             ui.set_chat_msg_10_content(display_msg.content.clone().into());
+            ui.set_chat_msg_10_translation(display_msg.translation_text().into());
             ui.set_chat_msg_10_timestamp(display_msg.timestamp.clone().into());
             ui.set_chat_msg_10_signature(display_msg.signature.clone().into());
             ui.set_chat_msg_10_is_self(display_msg.is_self);
             ui.set_chat_msg_10_is_verified(display_msg.is_verified);
+            ui.set_chat_msg_10_is_starred(display_msg.is_starred);
+            ui.set_chat_msg_10_is_read(display_msg.is_read);
+            ui.set_chat_msg_10_send_status(display_msg.send_status().into());
         }
         _ => {} // Ignore slots beyond MAX_CHAT_MESSAGES
     }
 }
 
+/// Message id shown in a chat message slot, empty if the slot is unused
+fn chat_message_slot_id(ui: &AppWindow, slot: i32) -> String {
+    match slot {
+        1 => ui.get_chat_msg_1_id(),
+        2 => ui.get_chat_msg_2_id(),
+        3 => ui.get_chat_msg_3_id(),
+        4 => ui.get_chat_msg_4_id(),
+        5 => ui.get_chat_msg_5_id(),
+        6 => ui.get_chat_msg_6_id(),
+        7 => ui.get_chat_msg_7_id(),
+        8 => ui.get_chat_msg_8_id(),
+        9 => ui.get_chat_msg_9_id(),
+        10 => ui.get_chat_msg_10_id(),
+        _ => Default::default(),
+    }
+    .to_string()
+}
+
+/// The starred messages the panel lists: the newest, oldest first
+fn shown_starred(starred: &state::StarredMessages) -> &[state::StarredMessage] {
+    let messages = starred.messages();
+    &messages[messages.len().saturating_sub(MAX_STARRED_SHOWN)..]
+}
+
+/// Update the starred messages panel from the starred collection
+async fn update_starred_ui(ui: &AppWindow, starred: &state::SharedStarredMessages) {
+    let starred = starred.lock().await;
+    let shown = shown_starred(&starred);
+    let text = |i: usize| -> slint::SharedString {
+        shown
+            .get(i)
+            .map(|m| {
+                format!(
+                    "{} · {}: {}",
+                    handlers::format_public_key(&m.sender_public_key),
+                    m.timestamp,
+                    m.message
+                )
+            })
+            .unwrap_or_default()
+            .into()
+    };
+    ui.set_starred_count(shown.len() as i32);
+    ui.set_starred_1_text(text(0));
+    ui.set_starred_2_text(text(1));
+    ui.set_starred_3_text(text(2));
+    ui.set_starred_4_text(text(3));
+    ui.set_starred_5_text(text(4));
+}
+
+/// Translator for received messages, configured by the environment
+///
+/// Loaded once, on first use. Translation stays off if the dictionary
+/// can't be read.
+fn message_translator() -> Option<&'static MessageTranslator> {
+    static TRANSLATOR: OnceLock<Option<MessageTranslator>> = OnceLock::new();
+    TRANSLATOR
+        .get_or_init(|| {
+            MessageTranslator::from_env().unwrap_or_else(|e| {
+                eprintln!("Message translation disabled: {}", e);
+                None
+            })
+        })
+        .as_ref()
+}
+
 /// Update chat message UI slots from message history
 ///
 /// This function converts ChatMessages to DisplayMessages and updates the UI slots.
+/// With `focus`, the slots show the messages around that one instead of the
+/// first ones, so a starred message opens in context.
 async fn update_chat_messages_ui(
     ui: &AppWindow,
     message_history: &Arc<tokio::sync::Mutex<profile_client::state::MessageHistory>>,
+    message_timings: &state::SharedMessageTimings,
+    starred: &state::SharedStarredMessages,
+    chat_view: &SharedChatView,
     my_public_key: &str,
+    focus: Option<&str>,
 ) {
     use profile_client::ui::chat::DisplayMessage;
 
     let history = message_history.lock().await;
-    let messages: Vec<_> = history.messages().collect();
+    let mut messages: Vec<_> = history.messages().collect();
+    if let Some(index) =
+        focus.and_then(|id| messages.iter().position(|m| m.message_id.to_string() == id))
+    {
+        let start = index
+            .saturating_sub(MAX_CHAT_MESSAGES / 2)
+            .min(messages.len().saturating_sub(MAX_CHAT_MESSAGES));
+        messages.drain(..start);
+    }
+    let starred = starred.lock().await;
+    let chat_view = chat_view.lock().await;
+    let shown_at = chrono::Utc::now();
     let message_count = messages.len().min(MAX_CHAT_MESSAGES);
 
     // Update message count
@@ -308,102 +594,380 @@ async fn update_chat_messages_ui(
     for (i, msg) in messages.iter().enumerate().take(MAX_CHAT_MESSAGES) {
         let slot = i + 1;
         let is_self = msg.sender_public_key == my_public_key;
-        let display_msg = DisplayMessage::from_chat_message(msg, is_self);
+        let mut display_msg = DisplayMessage::from_chat_message(msg, is_self);
+        display_msg.is_starred = starred.is_starred(&display_msg.id);
+        if is_self {
+            display_msg.is_read = chat_view.is_read(&display_msg.id);
+            display_msg.send_state = chat_view.send_state(&display_msg.id);
+        }
+        if let Some(translator) = message_translator() {
+            translator.on_message_display(&mut display_msg);
+        }
         set_chat_message_slot(ui, slot, &display_msg);
     }
+
+    let mut timings = message_timings.lock().await;
+    for msg in messages.iter().take(MAX_CHAT_MESSAGES) {
+        timings.record_displayed(msg.message_id, shown_at);
+    }
 }
 
 fn main() -> Result<(), slint::PlatformError> {
     let ui = AppWindow::new()?;
 
+    // Crash reports and background pruning of old diagnostics
+    let _pruning = profile_client::diagnostics::default_diagnostics_dir().map(|dir| {
+        profile_client::diagnostics::install_crash_reporter(dir.clone());
+        profile_client::diagnostics::PruningTask::spawn(
+            dir,
+            profile_client::diagnostics::RetentionSettings::from_env(),
+            profile_client::diagnostics::PRUNE_INTERVAL,
+        )
+    });
+
     // Key state initialization (existing code)
     let key_state = state::create_shared_key_state();
     let key_state_generate = key_state.clone();
+    let key_state_guest = key_state.clone();
     let key_state_import = key_state.clone();
 
-    // Lobby state initialization (Story 2.2)
-    let lobby_state = state::create_shared_lobby_state();
+    // Audit log of key and signature events, appended to across runs
+    let audit_log = match state::AuditLog::open_default() {
+        Ok(log) => log,
+        Err(e) => {
+            eprintln!("Failed to open audit log: {}", e);
+            None
+        }
+    };
+    let audit_generate = audit_log.clone();
+    let audit_guest = audit_log.clone();
+    let audit_import = audit_log.clone();
+
+    // State-event journal for debugging, off unless PROFILE_STATE_JOURNAL is set
+    let state_journal = match state::StateJournal::from_env() {
+        Ok(journal) => journal,
+        Err(e) => {
+            eprintln!("Failed to open state journal: {}", e);
+            None
+        }
+    };
+    if state_journal.is_some() {
+        eprintln!("State journal is on: message text and drafts are being written to disk");
+    }
+
+    // Lobby state initialization (Story 2.2), showing the lobby saved by the
+    // last run until the server sends a fresh one
+    let mut lobby = profile_client::ui::lobby_state::LobbyState::with_journal(state_journal);
+    let mut lobby_cache =
+        state::lobby_cache::default_lobby_cache_path().map(state::LobbyCacheWriter::new);
+    if let Some(ref mut writer) = lobby_cache {
+        match writer.load() {
+            Ok(Some(cache)) => lobby.restore_cached(cache),
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to load lobby cache: {}", e),
+        }
+    }
+    let lobby_state = Arc::new(tokio::sync::Mutex::new(lobby));
+    let lobby_cache = lobby_cache.map(|writer| Arc::new(tokio::sync::Mutex::new(writer)));
     let lobby_state_select = lobby_state.clone();
     let lobby_state_nav_up = lobby_state.clone();
     let lobby_state_nav_down = lobby_state.clone();
     let lobby_state_activate = lobby_state.clone();
 
+    // Save the lobby for the next start whenever it changes
+    let lobby_cache_timer = slint::Timer::default();
+    if let Some(ref writer) = lobby_cache {
+        let writer = writer.clone();
+        let lobby_state = lobby_state.clone();
+        lobby_cache_timer.start(
+            slint::TimerMode::Repeated,
+            state::lobby_cache::LOBBY_CACHE_SAVE_INTERVAL,
+            move || {
+                let writer = writer.clone();
+                let lobby_state = lobby_state.clone();
+                let _ = slint::spawn_local(async move {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = handlers::save_lobby_cache(&lobby_state, &mut writer).await {
+                        eprintln!("Failed to save lobby cache: {}", e);
+                    }
+                });
+            },
+        );
+    }
+
     // Message history initialization (Story 4.2)
     let message_history = state::create_shared_message_history();
     let message_history_select = message_history.clone();
 
-    // Message event handler for real-time message updates (Story 3.1)
-    // The callbacks are registered but the handler value is not stored since
-    // the handler manages its own internal state
-    profile_client::connection::client::MessageEventHandler::with_callbacks(
-        {
-            let message_history = message_history.clone();
-            let ui_weak = ui.as_weak();
-            let key_state = key_state.clone();
-
-            move |_message: profile_client::state::messages::ChatMessage| {
-                let ui_weak = ui_weak.clone();
-                let message_history = message_history.clone();
-                let key_state = key_state.clone();
-
-                let _ = slint::spawn_local(async move {
-                    let Some(ui) = ui_weak.upgrade() else {
-                        return;
-                    };
+    // Per-message timings for the drill-down latency breakdown
+    let message_timings = state::create_shared_message_timings();
+    let message_timings_select = message_timings.clone();
+
+    // The direct conversation shown, with the read and delivery state of
+    // this user's messages; a WebSocket client keeps it current once given
+    // it with `set_chat_view`
+    let chat_view = profile_client::ui::chat::create_shared_chat_view();
+    let chat_view_select = chat_view.clone();
+
+    // Sound cues, off unless enabled in the sound settings
+    let sound_cues = Rc::new(RefCell::new(SoundCues::new(
+        state::sound::default_sound_path()
+            .and_then(|path| state::SoundSettings::load(path).ok())
+            .unwrap_or_default(),
+    )));
+
+    // Usage statistics, kept on this device only; a file that can't be read
+    // is left alone and this run's statistics stay in memory
+    let mut stats = match state::stats::default_stats_path().map(state::UsageStats::load) {
+        Some(Ok(stats)) => stats,
+        Some(Err(e)) => {
+            eprintln!("Failed to load usage statistics: {}", e);
+            state::UsageStats::new()
+        }
+        None => state::UsageStats::new(),
+    };
+    stats.start_session(chrono::Utc::now());
+    let usage_stats = Arc::new(tokio::sync::Mutex::new(stats));
+
+    // Archived conversations; an archive file that can't be read is left
+    // alone and this run's archive stays in memory
+    let archive =
+        match state::archive::default_archive_path().map(state::ArchivedConversations::load) {
+            Some(Ok(archive)) => Arc::new(tokio::sync::Mutex::new(archive)),
+            Some(Err(e)) => {
+                eprintln!("Failed to load archived conversations: {}", e);
+                state::create_shared_archive()
+            }
+            None => state::create_shared_archive(),
+        };
 
-                    // Get user's public key for self-detection
-                    let my_key = {
-                        let state = key_state.lock().await;
-                        state.public_key().map(hex::encode).unwrap_or_default()
-                    };
+    // Starred messages from every conversation; a file that can't be read
+    // is left alone and this run's stars stay in memory
+    let starred = match state::starred::default_starred_path().map(state::StarredMessages::load) {
+        Some(Ok(starred)) => Arc::new(tokio::sync::Mutex::new(starred)),
+        Some(Err(e)) => {
+            eprintln!("Failed to load starred messages: {}", e);
+            state::create_shared_starred_messages()
+        }
+        None => state::create_shared_starred_messages(),
+    };
+    // Rooms this window has joined, for jumping to starred room messages
+    let rooms_state = state::create_shared_rooms_state();
 
-                    update_chat_messages_ui(&ui, &message_history, &my_key).await;
-                });
-            }
-        },
-        |_notification: String| {
-            // Handle invalid signature notification (can be logged or shown to user)
-        },
-        |_error: String| {
-            // Handle error (can be logged or shown to user)
-        },
-        |_notification: String| {
-            // Handle general notification (e.g., offline status)
-        },
-    );
+    // Every connection, lobby, chat and error event arrives on this bus; a
+    // WebSocket client publishes on it once given it with `set_event_bus`
+    let event_bus = profile_client::events::EventBus::new();
 
     // Initial lobby UI update (empty state)
     let ui_weak_lobby_update = ui.as_weak();
     let lobby_state_init = lobby_state.clone();
+    let archive_init = archive.clone();
     let _ = slint::spawn_local(async move {
         if let Some(ui) = ui_weak_lobby_update.upgrade() {
-            update_lobby_ui(&ui, &lobby_state_init).await;
+            update_lobby_ui(&ui, &lobby_state_init, &archive_init).await;
         }
     });
 
     // Initial chat messages UI update (empty state)
     let ui_weak_messages_update = ui.as_weak();
     let message_history_init = message_history.clone();
+    let message_timings_init = message_timings.clone();
+    let starred_init = starred.clone();
+    let chat_view_init = chat_view.clone();
     let _ = slint::spawn_local(async move {
         if let Some(ui) = ui_weak_messages_update.upgrade() {
-            update_chat_messages_ui(&ui, &message_history_init, "").await;
+            update_chat_messages_ui(
+                &ui,
+                &message_history_init,
+                &message_timings_init,
+                &starred_init,
+                &chat_view_init,
+                "",
+                None,
+            )
+            .await;
         }
     });
 
     let ui_weak_generate = ui.as_weak();
+    let ui_weak_guest = ui.as_weak();
     let ui_weak_show_import = ui.as_weak();
     let ui_weak_import_attempt = ui.as_weak();
     let ui_weak_cancel_import = ui.as_weak();
     let ui_weak_copy = ui.as_weak();
+    let status = Rc::new(RefCell::new(StatusQueue::new()));
+
+    // The one place the UI reacts to client events
+    let mut events = event_bus.subscribe();
+    {
+        let ui_weak = ui.as_weak();
+        let status = status.clone();
+        let message_history = message_history.clone();
+        let key_state = key_state.clone();
+        let sound_cues = sound_cues.clone();
+        let message_timings = message_timings.clone();
+        let chat_view = chat_view.clone();
+        let lobby_state = lobby_state.clone();
+        let usage_stats = usage_stats.clone();
+        let archive = archive.clone();
+        let starred = starred.clone();
+        let _ = slint::spawn_local(async move {
+            use profile_client::events::ClientEvent;
+
+            while let Some(event) = events.recv().await {
+                let Some(ui) = ui_weak.upgrade() else {
+                    return;
+                };
+                match event {
+                    // Story 3.1: real-time message updates
+                    ClientEvent::MessageReceived(message) => {
+                        sound_cues
+                            .borrow()
+                            .notify(state::SoundEvent::MessageReceived);
+                        message_timings.lock().await.record_message(&message);
+                        if let Ok(peer) = state::ConversationId::new(&message.sender_public_key) {
+                            usage_stats
+                                .lock()
+                                .await
+                                .record_received(&peer, chrono::Utc::now());
+                            let unread =
+                                handlers::handle_lobby_message_received(&lobby_state, &peer).await;
+                            let conversation =
+                                state::Conversation::Direct(message.sender_public_key.clone());
+                            let unarchived =
+                                match handlers::handle_archive_message(&archive, &conversation)
+                                    .await
+                                {
+                                    Ok(unarchived) => unarchived,
+                                    Err(e) => {
+                                        eprintln!("Failed to save archived conversations: {}", e);
+                                        false
+                                    }
+                                };
+                            if unread || unarchived {
+                                update_lobby_ui(&ui, &lobby_state, &archive).await;
+                            }
+                        }
+
+                        // Get user's public key for self-detection
+                        let my_key = {
+                            let state = key_state.lock().await;
+                            state.public_key().map(hex::encode).unwrap_or_default()
+                        };
+
+                        update_chat_messages_ui(
+                            &ui,
+                            &message_history,
+                            &message_timings,
+                            &starred,
+                            &chat_view,
+                            &my_key,
+                            None,
+                        )
+                        .await;
+                    }
+                    ClientEvent::MessageSent {
+                        recipient_public_key,
+                        ..
+                    } => {
+                        if let Ok(peer) = state::ConversationId::new(&recipient_public_key) {
+                            usage_stats
+                                .lock()
+                                .await
+                                .record_sent(&peer, chrono::Utc::now());
+                        }
+                    }
+                    ClientEvent::Connection(transition) => {
+                        usage_stats.lock().await.record_transition(&transition);
+                    }
+                    ClientEvent::LobbyReceived(received) => {
+                        lobby_state.lock().await.set_users(received.users_cloned());
+                        update_lobby_ui(&ui, &lobby_state, &archive).await;
+                    }
+                    ClientEvent::UserJoined(user) => {
+                        sound_cues.borrow().notify(state::SoundEvent::UserJoined);
+                        lobby_state.lock().await.add_user(user);
+                        update_lobby_ui(&ui, &lobby_state, &archive).await;
+                    }
+                    ClientEvent::UserLeft(public_key) => {
+                        lobby_state.lock().await.remove_user(&public_key);
+                        update_lobby_ui(&ui, &lobby_state, &archive).await;
+                    }
+                    ClientEvent::NicknameChanged {
+                        public_key,
+                        nickname,
+                    } => {
+                        lobby_state.lock().await.set_nickname(&public_key, nickname);
+                        update_lobby_ui(&ui, &lobby_state, &archive).await;
+                    }
+                    ClientEvent::VerifiedNameChanged {
+                        public_key,
+                        verified_name,
+                    } => {
+                        lobby_state
+                            .lock()
+                            .await
+                            .set_verified_name(&public_key, verified_name);
+                        update_lobby_ui(&ui, &lobby_state, &archive).await;
+                    }
+                    ClientEvent::StatusChanged { public_key, status } => {
+                        lobby_state.lock().await.set_status(&public_key, status);
+                        update_lobby_ui(&ui, &lobby_state, &archive).await;
+                    }
+                    ClientEvent::InvalidSignature(text) | ClientEvent::Error(text) => {
+                        announce(&ui, &status, Announcement::error(text));
+                    }
+                    ClientEvent::Notification(text) => {
+                        announce(&ui, &status, Announcement::success(text));
+                    }
+                    // The client already applied these to the shared chat view
+                    ClientEvent::ReadReceipt(_) | ClientEvent::SendStateChanged { .. } => {
+                        if let ClientEvent::SendStateChanged {
+                            state: state::SendState::Failed,
+                            ..
+                        } = event
+                        {
+                            sound_cues.borrow().notify(state::SoundEvent::SendFailed);
+                        }
+                        let my_key = {
+                            let state = key_state.lock().await;
+                            state.public_key().map(hex::encode).unwrap_or_default()
+                        };
+                        update_chat_messages_ui(
+                            &ui,
+                            &message_history,
+                            &message_timings,
+                            &starred,
+                            &chat_view,
+                            &my_key,
+                            None,
+                        )
+                        .await;
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+    let status_generate = status.clone();
+    let status_guest = status.clone();
+    let status_import = status.clone();
+    let status_copy = status.clone();
     let ui_weak_lobby_select = ui.as_weak();
     let ui_weak_lobby_nav_up = ui.as_weak();
     let ui_weak_lobby_nav_down = ui.as_weak();
     let ui_weak_lobby_activate = ui.as_weak();
     let key_state_lobby_select = key_state.clone();
+    let archive_select = archive.clone();
+    let starred_select = starred.clone();
+    let archive_nav_up = archive.clone();
+    let archive_nav_down = archive.clone();
 
     // Re-entry guards to prevent race conditions from multiple button clicks
     let generating = Arc::new(AtomicBool::new(false));
     let importing = Arc::new(AtomicBool::new(false));
+    // Generating a guest key shares the generation guard
+    let generating_guest = generating.clone();
 
     ui.on_generate_key_pressed(move || {
         // Check if already generating - prevent re-entry
@@ -411,8 +975,10 @@ fn main() -> Result<(), slint::PlatformError> {
             return; // Already generating, ignore this click
         }
         let key_state = key_state_generate.clone();
+        let audit = audit_generate.clone();
         let ui_weak = ui_weak_generate.clone();
         let generating = generating.clone();
+        let status = status_generate.clone();
 
         let _ = slint::spawn_local(async move {
             let Some(ui) = ui_weak.upgrade() else {
@@ -420,7 +986,7 @@ fn main() -> Result<(), slint::PlatformError> {
                 return;
             };
 
-            ui.set_status_message("Generating key…".into());
+            announce(&ui, &status, Announcement::progress("Generating key…"));
 
             // Add timeout to prevent indefinite hang if OsRng blocks
             // Normal key generation completes in <1ms (see test_key_generation_completes_quickly)
@@ -439,14 +1005,17 @@ fn main() -> Result<(), slint::PlatformError> {
 
             match result {
                     Ok(public_key_hex) => {
+                    state::audit_event(&audit, || state::AuditEvent::KeyGenerated {
+                        public_key: public_key_hex.clone(),
+                        guest: false,
+                    });
                     ui.set_public_key_display(public_key_hex.into());
+                    ui.set_is_guest(false);
                     ui.set_current_view("key-display".into());
-                    ui.set_status_is_error(false);
-                    ui.set_status_message("Your key has been generated. This is your identity. Keep your private key secure.".into());
+                    announce(&ui, &status, Announcement::success("Your key has been generated. This is your identity. Keep your private key secure."));
                 }
                 Err(err) => {
-                    ui.set_status_is_error(true);
-                    ui.set_status_message(err.into());
+                    announce(&ui, &status, Announcement::error(err));
                 }
             }
             // Reset guard to allow future generations
@@ -454,6 +1023,54 @@ fn main() -> Result<(), slint::PlatformError> {
         });
     });
 
+    // Continue with a throwaway guest identity that is never saved
+    ui.on_guest_key_pressed(move || {
+        if generating_guest.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let key_state = key_state_guest.clone();
+        let audit = audit_guest.clone();
+        let ui_weak = ui_weak_guest.clone();
+        let generating = generating_guest.clone();
+        let status = status_guest.clone();
+
+        let _ = slint::spawn_local(async move {
+            let Some(ui) = ui_weak.upgrade() else {
+                generating.store(false, Ordering::SeqCst);
+                return;
+            };
+
+            announce(&ui, &status, Announcement::progress("Creating guest identity…"));
+
+            let result = match tokio::time::timeout(
+                Duration::from_secs(5),
+                handlers::handle_generate_guest_key(&key_state),
+            )
+            .await
+            {
+                Ok(result) => result,
+                Err(_) => Err("Key generation took too long (>5s). This may indicate a system problem. Try closing other applications or restarting Profile.".to_string()),
+            };
+
+            match result {
+                Ok(public_key_hex) => {
+                    state::audit_event(&audit, || state::AuditEvent::KeyGenerated {
+                        public_key: public_key_hex.clone(),
+                        guest: true,
+                    });
+                    ui.set_public_key_display(public_key_hex.into());
+                    ui.set_is_guest(true);
+                    ui.set_current_view("key-display".into());
+                    announce(&ui, &status, Announcement::success("You are using a guest identity. It is not saved and will be gone when you close Profile."));
+                }
+                Err(err) => {
+                    announce(&ui, &status, Announcement::error(err));
+                }
+            }
+            generating.store(false, Ordering::SeqCst);
+        });
+    });
+
     // Show import screen
     ui.on_show_import_screen(move || {
         let Some(ui) = ui_weak_show_import.upgrade() else {
@@ -472,8 +1089,10 @@ fn main() -> Result<(), slint::PlatformError> {
             return; // Already importing, ignore this click
         }
         let key_state = key_state_import.clone();
+        let audit = audit_import.clone();
         let ui_weak = ui_weak_import_attempt.clone();
         let importing = importing.clone();
+        let status = status_import.clone();
 
         let _ = slint::spawn_local(async move {
             let Some(ui) = ui_weak.upgrade() else {
@@ -501,11 +1120,14 @@ fn main() -> Result<(), slint::PlatformError> {
 
             match result {
                 Ok(public_key_hex) => {
+                    state::audit_event(&audit, || state::AuditEvent::KeyImported {
+                        public_key: public_key_hex.clone(),
+                    });
                     // Success - show key display
                     ui.set_public_key_display(public_key_hex.into());
+                    ui.set_is_guest(false);
                     ui.set_current_view("key-display".into());
-                    ui.set_status_is_error(false);
-                    ui.set_status_message("Your key has been imported successfully.".into());
+                    announce(&ui, &status, Announcement::success("Your key has been imported successfully."));
                 }
                 Err(err) => {
                     // Show error in import screen
@@ -542,8 +1164,7 @@ fn main() -> Result<(), slint::PlatformError> {
             Ok(mut clipboard) => {
                 match clipboard.set_text(&public_key) {
                     Ok(_) => {
-                        ui.set_status_is_error(false);
-                        ui.set_status_message("Public key copied to clipboard!".into());
+                        announce(&ui, &status_copy, Announcement::success("Public key copied to clipboard!"));
                         ui.set_copy_feedback_visible(true);
 
                         // Reset feedback after 2 seconds
@@ -559,16 +1180,18 @@ fn main() -> Result<(), slint::PlatformError> {
                     Err(e) => {
                         // Parse common Windows clipboard errors into user-friendly messages
                         let user_message = parse_clipboard_error(&e.to_string());
-                        ui.set_status_is_error(true);
-                        ui.set_status_message(user_message.into());
+                        announce(&ui, &status_copy, Announcement::error(user_message));
                         ui.set_copy_feedback_visible(false);
                     }
                 }
             }
             Err(e) => {
                 let user_message = parse_clipboard_error(&e.to_string());
-                ui.set_status_is_error(true);
-                ui.set_status_message(format!("Clipboard unavailable: {}", user_message).into());
+                announce(
+                    &ui,
+                    &status_copy,
+                    Announcement::error(format!("Clipboard unavailable: {}", user_message)),
+                );
                 ui.set_copy_feedback_visible(false);
             }
         }
@@ -583,7 +1206,11 @@ fn main() -> Result<(), slint::PlatformError> {
         };
 
         let lobby_state = lobby_state_select.clone();
+        let archive = archive_select.clone();
+        let starred = starred_select.clone();
         let message_history = message_history_select.clone();
+        let message_timings = message_timings_select.clone();
+        let chat_view = chat_view_select.clone();
         let key_state = key_state_lobby_select.clone();
         let ui_weak = ui_weak_lobby_select.clone();
 
@@ -599,8 +1226,17 @@ fn main() -> Result<(), slint::PlatformError> {
 
             // Update UI to reflect selection
             if let Some(ui) = ui_weak.upgrade() {
-                update_lobby_ui(&ui, &lobby_state).await;
-                update_chat_messages_ui(&ui, &message_history, &my_key).await;
+                update_lobby_ui(&ui, &lobby_state, &archive).await;
+                update_chat_messages_ui(
+                    &ui,
+                    &message_history,
+                    &message_timings,
+                    &starred,
+                    &chat_view,
+                    &my_key,
+                    None,
+                )
+                .await;
             }
         });
     });
@@ -612,6 +1248,7 @@ fn main() -> Result<(), slint::PlatformError> {
         };
 
         let lobby_state = lobby_state_nav_up.clone();
+        let archive = archive_nav_up.clone();
         let ui_weak = ui_weak_lobby_nav_up.clone();
 
         let _ = slint::spawn_local(async move {
@@ -621,7 +1258,7 @@ fn main() -> Result<(), slint::PlatformError> {
             {
                 // Update UI to reflect new selection
                 if let Some(ui) = ui_weak.upgrade() {
-                    update_lobby_ui(&ui, &lobby_state).await;
+                    update_lobby_ui(&ui, &lobby_state, &archive).await;
                 }
             }
         });
@@ -634,6 +1271,7 @@ fn main() -> Result<(), slint::PlatformError> {
         };
 
         let lobby_state = lobby_state_nav_down.clone();
+        let archive = archive_nav_down.clone();
         let ui_weak = ui_weak_lobby_nav_down.clone();
 
         let _ = slint::spawn_local(async move {
@@ -643,7 +1281,7 @@ fn main() -> Result<(), slint::PlatformError> {
             {
                 // Update UI to reflect new selection
                 if let Some(ui) = ui_weak.upgrade() {
-                    update_lobby_ui(&ui, &lobby_state).await;
+                    update_lobby_ui(&ui, &lobby_state, &archive).await;
                 }
             }
         });
@@ -689,6 +1327,248 @@ fn main() -> Result<(), slint::PlatformError> {
         });
     });
 
+    // Archive or unarchive the selected conversation; its history is kept
+    {
+        let ui_weak = ui.as_weak();
+        let status = status.clone();
+        let lobby_state = lobby_state.clone();
+        let archive = archive.clone();
+        ui.on_toggle_archive_selected(move || {
+            let ui_weak = ui_weak.clone();
+            let status = status.clone();
+            let lobby_state = lobby_state.clone();
+            let archive = archive.clone();
+            let _ = slint::spawn_local(async move {
+                let Some(ui) = ui_weak.upgrade() else {
+                    return;
+                };
+                let Some(selected) = lobby_state
+                    .lock()
+                    .await
+                    .selected_user()
+                    .map(|s| s.to_string())
+                else {
+                    return;
+                };
+                let conversation = state::Conversation::Direct(selected);
+                let result = if ui.get_lobby_selected_archived() {
+                    handlers::handle_unarchive_conversation(&archive, &conversation)
+                        .await
+                        .map(|_| "Conversation moved back to the main list")
+                } else {
+                    handlers::handle_archive_conversation(&archive, &conversation)
+                        .await
+                        .map(|_| "Conversation archived")
+                };
+                match result {
+                    Ok(text) => announce(&ui, &status, Announcement::success(text)),
+                    Err(e) => announce(
+                        &ui,
+                        &status,
+                        Announcement::error(format!("Failed to update archive: {}", e)),
+                    ),
+                }
+                update_lobby_ui(&ui, &lobby_state, &archive).await;
+            });
+        });
+    }
+
+    // Star or unstar a chat message; own messages are starred in the
+    // selected conversation
+    {
+        let ui_weak = ui.as_weak();
+        let status = status.clone();
+        let starred = starred.clone();
+        let message_history = message_history.clone();
+        let message_timings = message_timings.clone();
+        let chat_view = chat_view.clone();
+        let lobby_state = lobby_state.clone();
+        let key_state = key_state.clone();
+        ui.on_chat_message_star_toggled(move |slot_index| {
+            let ui_weak = ui_weak.clone();
+            let status = status.clone();
+            let starred = starred.clone();
+            let message_history = message_history.clone();
+            let message_timings = message_timings.clone();
+            let chat_view = chat_view.clone();
+            let lobby_state = lobby_state.clone();
+            let key_state = key_state.clone();
+            let _ = slint::spawn_local(async move {
+                let Some(ui) = ui_weak.upgrade() else {
+                    return;
+                };
+                let message_id = chat_message_slot_id(&ui, slot_index);
+                if message_id.is_empty() {
+                    return;
+                }
+                let my_key = {
+                    let state = key_state.lock().await;
+                    state.public_key().map(hex::encode).unwrap_or_default()
+                };
+
+                let is_starred = starred.lock().await.is_starred(&message_id);
+                let result = if is_starred {
+                    handlers::handle_unstar_message(&starred, &message_id)
+                        .await
+                        .map(|_| "Message unstarred")
+                } else {
+                    let Some(message) = message_history
+                        .lock()
+                        .await
+                        .messages()
+                        .find(|m| m.message_id.to_string() == message_id)
+                        .cloned()
+                    else {
+                        return;
+                    };
+                    let peer = if message.sender_public_key == my_key {
+                        lobby_state
+                            .lock()
+                            .await
+                            .selected_user()
+                            .map(|s| s.to_string())
+                    } else {
+                        Some(message.sender_public_key.clone())
+                    };
+                    let Some(peer) = peer else {
+                        announce(
+                            &ui,
+                            &status,
+                            Announcement::error("Select the conversation to star your message"),
+                        );
+                        return;
+                    };
+                    handlers::handle_star_message(
+                        &starred,
+                        state::Conversation::Direct(peer),
+                        &message,
+                    )
+                    .await
+                    .map(|_| "Message starred")
+                };
+                match result {
+                    Ok(text) => announce(&ui, &status, Announcement::success(text)),
+                    Err(e) => announce(
+                        &ui,
+                        &status,
+                        Announcement::error(format!("Failed to update starred messages: {}", e)),
+                    ),
+                }
+                update_chat_messages_ui(
+                    &ui,
+                    &message_history,
+                    &message_timings,
+                    &starred,
+                    &chat_view,
+                    &my_key,
+                    None,
+                )
+                .await;
+                update_starred_ui(&ui, &starred).await;
+            });
+        });
+    }
+
+    // Starred messages panel
+    {
+        let ui_weak = ui.as_weak();
+        let starred = starred.clone();
+        ui.on_show_starred(move || {
+            let ui_weak = ui_weak.clone();
+            let starred = starred.clone();
+            let _ = slint::spawn_local(async move {
+                if let Some(ui) = ui_weak.upgrade() {
+                    update_starred_ui(&ui, &starred).await;
+                    ui.set_starred_visible(true);
+                }
+            });
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        ui.on_close_starred(move || {
+            if let Some(ui) = ui_weak.upgrade() {
+                ui.set_starred_visible(false);
+            }
+        });
+    }
+
+    // Open a starred message in its conversation, scrolled to it
+    {
+        let ui_weak = ui.as_weak();
+        let status = status.clone();
+        let starred = starred.clone();
+        let message_history = message_history.clone();
+        let message_timings = message_timings.clone();
+        let chat_view = chat_view.clone();
+        let lobby_state = lobby_state.clone();
+        let rooms_state = rooms_state.clone();
+        let archive = archive.clone();
+        let key_state = key_state.clone();
+        ui.on_jump_to_starred(move |index| {
+            let ui_weak = ui_weak.clone();
+            let status = status.clone();
+            let starred = starred.clone();
+            let message_history = message_history.clone();
+            let message_timings = message_timings.clone();
+            let chat_view = chat_view.clone();
+            let lobby_state = lobby_state.clone();
+            let rooms_state = rooms_state.clone();
+            let archive = archive.clone();
+            let key_state = key_state.clone();
+            let _ = slint::spawn_local(async move {
+                let Some(ui) = ui_weak.upgrade() else {
+                    return;
+                };
+                let message_id = {
+                    let starred = starred.lock().await;
+                    usize::try_from(index - 1)
+                        .ok()
+                        .and_then(|i| shown_starred(&starred).get(i))
+                        .map(|m| m.message_id.clone())
+                };
+                let Some(message_id) = message_id else {
+                    return;
+                };
+                let my_key = {
+                    let state = key_state.lock().await;
+                    state.public_key().map(hex::encode).unwrap_or_default()
+                };
+
+                let opened = handlers::handle_jump_to_starred(
+                    &starred,
+                    &lobby_state,
+                    &rooms_state,
+                    &mut *chat_view.lock().await,
+                    &message_history,
+                    &message_id,
+                    &my_key,
+                )
+                .await;
+                if opened.is_none() {
+                    announce(
+                        &ui,
+                        &status,
+                        Announcement::error("That conversation is no longer available"),
+                    );
+                    return;
+                }
+                ui.set_starred_visible(false);
+                update_lobby_ui(&ui, &lobby_state, &archive).await;
+                update_chat_messages_ui(
+                    &ui,
+                    &message_history,
+                    &message_timings,
+                    &starred,
+                    &chat_view,
+                    &my_key,
+                    Some(&message_id),
+                )
+                .await;
+            });
+        });
+    }
+
     // Drill-down modal callbacks (Story 4.1 + 4.2)
     let ui_weak_drill_down_clicked = ui.as_weak();
     let ui_weak_drill_down_close = ui.as_weak();
@@ -697,6 +1577,8 @@ fn main() -> Result<(), slint::PlatformError> {
     let ui_weak_drill_down_copy_signature = ui.as_weak();
 
     // Handle chat message click - opens drill-down modal
+    let message_history_drill_down = message_history.clone();
+    let message_timings_drill_down = message_timings.clone();
     ui.on_chat_message_clicked(move |slot_index| {
         let Some(ui) = ui_weak_drill_down_clicked.upgrade() else {
             return;
@@ -833,6 +1715,19 @@ fn main() -> Result<(), slint::PlatformError> {
             );
         }
 
+        // Latency breakdown; slots show history messages in order. Skipped
+        // if either lock is busy rather than blocking the UI thread.
+        let latency = slot_index
+            .checked_sub(1)
+            .and_then(|index| {
+                let history = message_history_drill_down.try_lock().ok()?;
+                let timings = message_timings_drill_down.try_lock().ok()?;
+                let message = history.messages().nth(index as usize)?;
+                Some(timings.get(&message.message_id)?.breakdown().format_lines())
+            })
+            .unwrap_or_default();
+        ui.set_drill_down_latency(latency.into());
+
         // Show modal
         ui.set_drill_down_modal_visible(true);
     });
@@ -850,6 +1745,7 @@ fn main() -> Result<(), slint::PlatformError> {
         ui.set_drill_down_signature("".into());
         ui.set_drill_down_verification_text("".into());
         ui.set_drill_down_verification_explanation("".into());
+        ui.set_drill_down_latency("".into());
         ui.set_drill_down_key_copied(false);
         ui.set_drill_down_message_copied(false);
         ui.set_drill_down_signature_copied(false);
@@ -981,5 +1877,205 @@ fn main() -> Result<(), slint::PlatformError> {
         }
     });
 
-    ui.run()
+    // Sound settings panel
+    {
+        let ui_weak = ui.as_weak();
+        let sound_cues = sound_cues.clone();
+        ui.on_show_sound(move || {
+            if let Some(ui) = ui_weak.upgrade() {
+                let cues = sound_cues.borrow();
+                cues.render(&ui);
+                ui.set_sound_notice_text(cues.backend().notice().into());
+                ui.set_sound_visible(true);
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        ui.on_close_sound(move || {
+            if let Some(ui) = ui_weak.upgrade() {
+                ui.set_sound_visible(false);
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let sound_cues = sound_cues.clone();
+        let status = status.clone();
+        ui.on_toggle_sound_enabled(move || {
+            if let Some(ui) = ui_weak.upgrade() {
+                update_sound_settings(&ui, &sound_cues, &status, |settings| {
+                    settings.enabled = !settings.enabled
+                });
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let sound_cues = sound_cues.clone();
+        let status = status.clone();
+        ui.on_toggle_sound_muted(move || {
+            if let Some(ui) = ui_weak.upgrade() {
+                update_sound_settings(&ui, &sound_cues, &status, |settings| {
+                    settings.muted = !settings.muted
+                });
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let sound_cues = sound_cues.clone();
+        ui.on_toggle_do_not_disturb(move || {
+            if let Some(ui) = ui_weak.upgrade() {
+                let mut cues = sound_cues.borrow_mut();
+                let on = !cues.is_do_not_disturb();
+                cues.set_do_not_disturb(on);
+                cues.render(&ui);
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let sound_cues = sound_cues.clone();
+        let status = status.clone();
+        ui.on_toggle_sound_event(move |index| {
+            let Some(event) = usize::try_from(index)
+                .ok()
+                .and_then(|index| state::SoundEvent::ALL.get(index).copied())
+            else {
+                return;
+            };
+            if let Some(ui) = ui_weak.upgrade() {
+                update_sound_settings(&ui, &sound_cues, &status, |settings| {
+                    settings.set_event_enabled(event, !settings.is_event_enabled(event))
+                });
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let sound_cues = sound_cues.clone();
+        let status = status.clone();
+        ui.on_change_sound_volume(move |delta| {
+            if let Some(ui) = ui_weak.upgrade() {
+                update_sound_settings(&ui, &sound_cues, &status, |settings| {
+                    let volume = (i32::from(settings.volume()) + delta).clamp(0, 100);
+                    settings.set_volume(u8::try_from(volume).unwrap_or(100));
+                });
+            }
+        });
+    }
+
+    // Usage statistics panel
+    {
+        let ui_weak = ui.as_weak();
+        let usage_stats = usage_stats.clone();
+        ui.on_show_stats(move || {
+            let ui_weak = ui_weak.clone();
+            let usage_stats = usage_stats.clone();
+            let _ = slint::spawn_local(async move {
+                if let Some(ui) = ui_weak.upgrade() {
+                    update_stats_ui(&ui, &usage_stats).await;
+                    ui.set_stats_visible(true);
+                }
+            });
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        ui.on_close_stats(move || {
+            if let Some(ui) = ui_weak.upgrade() {
+                ui.set_stats_visible(false);
+            }
+        });
+    }
+    {
+        let ui_weak = ui.as_weak();
+        let usage_stats = usage_stats.clone();
+        let status = status.clone();
+        ui.on_purge_stats(move || {
+            let ui_weak = ui_weak.clone();
+            let usage_stats = usage_stats.clone();
+            let status = status.clone();
+            let _ = slint::spawn_local(async move {
+                let result = usage_stats.lock().await.purge();
+                let Some(ui) = ui_weak.upgrade() else {
+                    return;
+                };
+                match result {
+                    Ok(()) => announce(
+                        &ui,
+                        &status,
+                        Announcement::success("Usage statistics purged"),
+                    ),
+                    Err(e) => announce(
+                        &ui,
+                        &status,
+                        Announcement::error(format!("Failed to purge usage statistics: {}", e)),
+                    ),
+                }
+                update_stats_ui(&ui, &usage_stats).await;
+            });
+        });
+    }
+
+    // Delete the statistics, crash reports, diagnostics and captures this
+    // device keeps
+    {
+        let ui_weak = ui.as_weak();
+        let usage_stats = usage_stats.clone();
+        let status = status.clone();
+        ui.on_clear_local_traces(move || {
+            let ui_weak = ui_weak.clone();
+            let usage_stats = usage_stats.clone();
+            let status = status.clone();
+            let _ = slint::spawn_local(async move {
+                let purged = usage_stats.lock().await.purge();
+                let extra_files = profile_client::diagnostics::default_extra_trace_files();
+                let cleared = match profile_client::diagnostics::default_diagnostics_dir() {
+                    Some(dir) => {
+                        profile_client::diagnostics::clear_all_local_traces(&dir, &extra_files)
+                            .map_err(|e| e.to_string())
+                    }
+                    None => Err("no diagnostics directory".to_string()),
+                };
+                let Some(ui) = ui_weak.upgrade() else {
+                    return;
+                };
+                match purged.map_err(|e| e.to_string()).and(cleared) {
+                    Ok(removed) => announce(
+                        &ui,
+                        &status,
+                        Announcement::success(format!("Cleared {} local trace files", removed)),
+                    ),
+                    Err(e) => announce(
+                        &ui,
+                        &status,
+                        Announcement::error(format!("Failed to clear local traces: {}", e)),
+                    ),
+                }
+                update_stats_ui(&ui, &usage_stats).await;
+            });
+        });
+    }
+
+    let result = ui.run();
+
+    // Save the last lobby seen before exiting
+    if let (Some(writer), Ok(lobby)) = (&lobby_cache, lobby_state.try_lock()) {
+        if let Ok(mut writer) = writer.try_lock() {
+            if let Err(e) = writer.save_if_changed(&lobby) {
+                eprintln!("Failed to save lobby cache: {}", e);
+            }
+        }
+    }
+
+    // Close this session and keep the statistics for the next start
+    if let Ok(mut stats) = usage_stats.try_lock() {
+        stats.end_session(chrono::Utc::now());
+        if let Err(e) = stats.save() {
+            eprintln!("Failed to save usage statistics: {}", e);
+        }
+    }
+    result
 }
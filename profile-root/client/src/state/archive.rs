@@ -0,0 +1,306 @@
+//! Archived conversations
+//!
+//! Archiving hides a conversation from the main list without touching its
+//! history; archived conversations are listed in a separate "Archived"
+//! section instead. Direct conversations are archived by peer and rooms by
+//! name, so each conversation is archived on its own.
+//!
+//! By default a new incoming message brings an archived conversation back
+//! to the main list. Users who want archived conversations to stay put can
+//! turn that off with `unarchiveOnMessage`.
+//!
+//! The archive is persisted as JSON at `PROFILE_ARCHIVE_FILE`, or
+//! `~/.profile/archive.json` when that is unset.
+
+use crate::state::conversation::ConversationId;
+use crate::state::rooms::Conversation;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Environment variable overriding the archive file location
+pub const ARCHIVE_ENV_VAR: &str = "PROFILE_ARCHIVE_FILE";
+
+/// Error types for archive operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArchiveError {
+    /// A direct conversation's public key is not 64 hex characters
+    InvalidPublicKey,
+    /// Reading or writing the archive file failed
+    Io(String),
+    /// The archive file is not valid JSON
+    Parse(String),
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::InvalidPublicKey => {
+                write!(f, "Public key must be 64 hexadecimal characters")
+            }
+            ArchiveError::Io(msg) => write!(f, "Failed to access archive file: {}", msg),
+            ArchiveError::Parse(msg) => write!(f, "Failed to parse archive file: {}", msg),
+        }
+    }
+}
+
+impl Error for ArchiveError {}
+
+/// Conversations split into the main list and the "Archived" section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sections<T> {
+    /// Shown in the main list
+    pub active: Vec<T>,
+    /// Shown under "Archived"
+    pub archived: Vec<T>,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// Conversations the user archived
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedConversations {
+    /// Peers of archived direct conversations
+    #[serde(default)]
+    direct: BTreeSet<ConversationId>,
+    /// Names of archived rooms
+    #[serde(default)]
+    rooms: BTreeSet<String>,
+    /// Whether an incoming message unarchives its conversation
+    #[serde(rename = "unarchiveOnMessage", default = "enabled_by_default")]
+    pub unarchive_on_message: bool,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Default for ArchivedConversations {
+    fn default() -> Self {
+        Self {
+            direct: BTreeSet::new(),
+            rooms: BTreeSet::new(),
+            unarchive_on_message: true,
+            path: None,
+        }
+    }
+}
+
+/// Default location of the archive file
+///
+/// # Returns
+/// `PROFILE_ARCHIVE_FILE` if set, otherwise `~/.profile/archive.json`, or
+/// None if neither can be determined
+pub fn default_archive_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(ARCHIVE_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("archive.json"))
+}
+
+impl ArchivedConversations {
+    /// Create an empty, in-memory archive
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the archive saved at `path`
+    ///
+    /// A missing file gives an empty archive that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, ArchiveError> {
+        let path = path.into();
+        let mut archive: ArchivedConversations = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| ArchiveError::Parse(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ArchivedConversations::default(),
+            Err(e) => return Err(ArchiveError::Io(e.to_string())),
+        };
+        archive.path = Some(path);
+        Ok(archive)
+    }
+
+    /// Save the archive to its file
+    ///
+    /// In-memory archives are not saved.
+    pub fn save(&self) -> Result<(), ArchiveError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| ArchiveError::Io(e.to_string()))?;
+        }
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| ArchiveError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| ArchiveError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| ArchiveError::Io(e.to_string()))
+    }
+
+    /// File the archive is saved to, if persistent
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Archive a conversation
+    ///
+    /// # Returns
+    /// Whether the conversation was not archived before
+    pub fn archive(&mut self, conversation: &Conversation) -> Result<bool, ArchiveError> {
+        Ok(match conversation {
+            Conversation::Direct(public_key) => self.direct.insert(conversation_id(public_key)?),
+            Conversation::Room(name) => self.rooms.insert(name.clone()),
+        })
+    }
+
+    /// Move a conversation back to the main list
+    ///
+    /// # Returns
+    /// Whether the conversation was archived
+    pub fn unarchive(&mut self, conversation: &Conversation) -> Result<bool, ArchiveError> {
+        Ok(match conversation {
+            Conversation::Direct(public_key) => self.direct.remove(&conversation_id(public_key)?),
+            Conversation::Room(name) => self.rooms.remove(name),
+        })
+    }
+
+    /// Whether a conversation is archived
+    pub fn is_archived(&self, conversation: &Conversation) -> bool {
+        match conversation {
+            Conversation::Direct(public_key) => ConversationId::new(public_key)
+                .map(|id| self.direct.contains(&id))
+                .unwrap_or(false),
+            Conversation::Room(name) => self.rooms.contains(name),
+        }
+    }
+
+    /// Record a message arriving in a conversation
+    ///
+    /// Unarchives the conversation if `unarchive_on_message` is set.
+    ///
+    /// # Returns
+    /// Whether the conversation was unarchived
+    pub fn message_received(&mut self, conversation: &Conversation) -> bool {
+        self.unarchive_on_message && self.unarchive(conversation).unwrap_or(false)
+    }
+
+    /// Number of archived conversations
+    pub fn len(&self) -> usize {
+        self.direct.len() + self.rooms.len()
+    }
+
+    /// Check if nothing is archived
+    pub fn is_empty(&self) -> bool {
+        self.direct.is_empty() && self.rooms.is_empty()
+    }
+
+    /// Split conversations into the main list and the "Archived" section,
+    /// keeping their order within each
+    pub fn sections<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        conversation: impl Fn(&T) -> Conversation,
+    ) -> Sections<T> {
+        let (archived, active) = items
+            .into_iter()
+            .partition(|item| self.is_archived(&conversation(item)));
+        Sections { active, archived }
+    }
+}
+
+fn conversation_id(public_key: &str) -> Result<ConversationId, ArchiveError> {
+    ConversationId::new(public_key).map_err(|_| ArchiveError::InvalidPublicKey)
+}
+
+/// Shared archive for concurrent access
+pub type SharedArchive = Arc<Mutex<ArchivedConversations>>;
+
+/// Create a new shared, in-memory archive
+#[inline]
+pub fn create_shared_archive() -> SharedArchive {
+    Arc::new(Mutex::new(ArchivedConversations::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direct(key: &str) -> Conversation {
+        Conversation::Direct(key.to_string())
+    }
+
+    #[test]
+    fn test_archive_and_unarchive_each_conversation() {
+        let mut archive = ArchivedConversations::new();
+        let alice = direct(&"ab".repeat(32));
+        let general = Conversation::Room("general".to_string());
+
+        assert_eq!(archive.archive(&alice), Ok(true));
+        assert_eq!(archive.archive(&direct(&"AB".repeat(32))), Ok(false));
+        assert_eq!(archive.archive(&general), Ok(true));
+        assert!(archive.is_archived(&alice));
+        assert!(!archive.is_archived(&direct(&"cd".repeat(32))));
+        assert_eq!(archive.len(), 2);
+
+        assert_eq!(archive.unarchive(&general), Ok(true));
+        assert!(!archive.is_archived(&general));
+        assert!(archive.is_archived(&alice));
+        assert_eq!(
+            archive.archive(&direct("abc")),
+            Err(ArchiveError::InvalidPublicKey)
+        );
+    }
+
+    #[test]
+    fn test_incoming_message_unarchives_unless_disabled() {
+        let mut archive = ArchivedConversations::new();
+        let alice = direct(&"ab".repeat(32));
+        archive.archive(&alice).unwrap();
+
+        assert!(archive.message_received(&alice));
+        assert!(!archive.is_archived(&alice));
+        assert!(!archive.message_received(&alice));
+
+        archive.unarchive_on_message = false;
+        archive.archive(&alice).unwrap();
+        assert!(!archive.message_received(&alice));
+        assert!(archive.is_archived(&alice));
+    }
+
+    #[test]
+    fn test_sections_keep_order() {
+        let mut archive = ArchivedConversations::new();
+        archive
+            .archive(&Conversation::Room("b".to_string()))
+            .unwrap();
+        let sections =
+            archive.sections(["a", "b", "c"], |name| Conversation::Room(name.to_string()));
+        assert_eq!(sections.active, vec!["a", "c"]);
+        assert_eq!(sections.archived, vec!["b"]);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("profile-archive-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut archive = ArchivedConversations::load(&path).unwrap();
+        assert!(archive.is_empty());
+        archive.archive(&direct(&"AB".repeat(32))).unwrap();
+        archive
+            .archive(&Conversation::Room("general".to_string()))
+            .unwrap();
+        archive.unarchive_on_message = false;
+        archive.save().unwrap();
+
+        let loaded = ArchivedConversations::load(&path).unwrap();
+        assert_eq!(loaded, archive);
+        assert!(loaded.is_archived(&direct(&"ab".repeat(32))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,431 @@
+//! File transfers in progress
+//!
+//! [`AttachmentTransfers`] keeps both directions of every transfer this
+//! client is part of:
+//!
+//! - Outgoing: a file we offered, held in memory until the recipient has
+//!   asked for every chunk of it
+//! - Incoming: a file offered to us, waiting to be accepted, then the
+//!   chunks received so far, each checked against its hash in the manifest
+//!
+//! A recipient asks for chunks a window at a time (see
+//! [`ACCEPT_WINDOW`]) and asks for the next window once the last one has
+//! arrived. Completed files are written to the downloads directory by
+//! [`crate::handlers::attachments`]. Nothing here outlives the session.
+
+use profile_shared::config::attachments::ACCEPT_WINDOW;
+use profile_shared::protocol::AttachmentManifest;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Environment variable overriding where received files are saved
+pub const DOWNLOADS_ENV_VAR: &str = "PROFILE_DOWNLOADS_DIR";
+
+/// A file we offered
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingTransfer {
+    pub recipient: String,
+    pub manifest: AttachmentManifest,
+    data: Vec<u8>,
+    /// Chunks handed out so far
+    sent: u32,
+}
+
+/// A file offered to us
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncomingTransfer {
+    pub sender: String,
+    pub manifest: AttachmentManifest,
+    /// Chunks below this index were asked for; 0 until accepted
+    requested: u32,
+    /// Chunks received so far, concatenated
+    data: Vec<u8>,
+    received: u32,
+}
+
+impl IncomingTransfer {
+    /// Whether we asked for the file
+    pub fn is_accepted(&self) -> bool {
+        self.requested > 0
+    }
+
+    /// Chunks received so far and the total
+    pub fn progress(&self) -> (u32, u32) {
+        (self.received, self.manifest.chunk_count())
+    }
+}
+
+/// Chunks an accept asked for, ready to send
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingChunks {
+    /// Index and contents of each chunk, in order
+    pub chunks: Vec<(u32, Vec<u8>)>,
+    /// Whether these are the last chunks of the file
+    pub finished: bool,
+}
+
+/// Which side of a transfer ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    Outgoing,
+    Incoming,
+}
+
+/// Transfers this client is sending or receiving
+#[derive(Debug)]
+pub struct AttachmentTransfers {
+    outgoing: HashMap<Uuid, OutgoingTransfer>,
+    incoming: HashMap<Uuid, IncomingTransfer>,
+    downloads_dir: PathBuf,
+}
+
+impl Default for AttachmentTransfers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AttachmentTransfers {
+    /// Create with no transfers, saving files to [`default_downloads_dir`]
+    pub fn new() -> Self {
+        Self::with_downloads_dir(
+            default_downloads_dir().unwrap_or_else(|| PathBuf::from("downloads")),
+        )
+    }
+
+    /// Create with no transfers, saving files to `downloads_dir`
+    pub fn with_downloads_dir(downloads_dir: PathBuf) -> Self {
+        Self {
+            outgoing: HashMap::new(),
+            incoming: HashMap::new(),
+            downloads_dir,
+        }
+    }
+
+    /// Where received files are saved
+    pub fn downloads_dir(&self) -> &PathBuf {
+        &self.downloads_dir
+    }
+
+    /// Save received files to `dir` from now on
+    pub fn set_downloads_dir(&mut self, dir: PathBuf) {
+        self.downloads_dir = dir;
+    }
+
+    /// Hold a file we offered until its chunks are asked for
+    pub fn add_outgoing(&mut self, recipient: String, manifest: AttachmentManifest, data: Vec<u8>) {
+        self.outgoing.insert(
+            manifest.transfer_id,
+            OutgoingTransfer {
+                recipient,
+                manifest,
+                data,
+                sent: 0,
+            },
+        );
+    }
+
+    /// A file we offered, if still in progress
+    pub fn outgoing(&self, transfer_id: Uuid) -> Option<&OutgoingTransfer> {
+        self.outgoing.get(&transfer_id)
+    }
+
+    /// Take the chunks `recipient` asked for that weren't sent yet
+    ///
+    /// The transfer is forgotten once its last chunk is taken.
+    ///
+    /// # Returns
+    /// None if we offered no such file to `recipient`
+    pub fn take_chunks(
+        &mut self,
+        transfer_id: Uuid,
+        recipient: &str,
+        until: u32,
+    ) -> Option<OutgoingChunks> {
+        let transfer = self
+            .outgoing
+            .get_mut(&transfer_id)
+            .filter(|t| t.recipient == recipient)?;
+        let chunk_size = transfer.manifest.chunk_size as usize;
+        let total = transfer.manifest.chunk_count();
+        let until = until.min(total);
+        let chunks = (transfer.sent..until)
+            .map(|index| {
+                let start = index as usize * chunk_size;
+                let end = (start + chunk_size).min(transfer.data.len());
+                (index, transfer.data[start..end].to_vec())
+            })
+            .collect();
+        transfer.sent = transfer.sent.max(until);
+        let finished = transfer.sent == total;
+        if finished {
+            self.outgoing.remove(&transfer_id);
+        }
+        Some(OutgoingChunks { chunks, finished })
+    }
+
+    /// Record a file offered to us, its manifest already verified
+    ///
+    /// # Returns
+    /// false if a transfer with the same id is already known
+    pub fn add_incoming(&mut self, sender: String, manifest: AttachmentManifest) -> bool {
+        if self.incoming.contains_key(&manifest.transfer_id) {
+            return false;
+        }
+        self.incoming.insert(
+            manifest.transfer_id,
+            IncomingTransfer {
+                sender,
+                manifest,
+                requested: 0,
+                data: Vec::new(),
+                received: 0,
+            },
+        );
+        true
+    }
+
+    /// A file offered to us, if still in progress
+    pub fn incoming(&self, transfer_id: Uuid) -> Option<&IncomingTransfer> {
+        self.incoming.get(&transfer_id)
+    }
+
+    /// Files offered to us and not accepted yet
+    pub fn pending_offers(&self) -> Vec<&IncomingTransfer> {
+        self.incoming
+            .values()
+            .filter(|t| !t.is_accepted())
+            .collect()
+    }
+
+    /// Ask for the first window of an offered file's chunks
+    ///
+    /// # Returns
+    /// The index to ask for chunks below, None if no such file was offered
+    /// or it was already accepted
+    pub fn accept(&mut self, transfer_id: Uuid) -> Option<u32> {
+        let transfer = self
+            .incoming
+            .get_mut(&transfer_id)
+            .filter(|t| !t.is_accepted())?;
+        transfer.requested = next_window(transfer.received, transfer.manifest.chunk_count());
+        Some(transfer.requested)
+    }
+
+    /// Add chunk `index` of a file we accepted from `sender`
+    ///
+    /// # Returns
+    /// The index to ask for the next window below once this chunk completes
+    /// a window and more remain, otherwise None
+    ///
+    /// # Errors
+    /// Returns a reason if we didn't ask for the chunk, it is out of order,
+    /// or it doesn't match its hash in the manifest
+    pub fn add_chunk(
+        &mut self,
+        transfer_id: Uuid,
+        sender: &str,
+        index: u32,
+        data: &[u8],
+    ) -> Result<Option<u32>, String> {
+        let transfer = self
+            .incoming
+            .get_mut(&transfer_id)
+            .filter(|t| t.sender == sender && t.is_accepted())
+            .ok_or_else(|| "Chunk for a file that wasn't accepted".to_string())?;
+        if index != transfer.received || index >= transfer.requested {
+            return Err(format!("Chunk {} arrived out of order", index));
+        }
+        if !transfer.manifest.chunk_matches(index, data) {
+            return Err(format!("Chunk {} doesn't match the signed manifest", index));
+        }
+        transfer.data.extend_from_slice(data);
+        transfer.received += 1;
+        let total = transfer.manifest.chunk_count();
+        if transfer.received == transfer.requested && transfer.received < total {
+            transfer.requested = next_window(transfer.received, total);
+            return Ok(Some(transfer.requested));
+        }
+        Ok(None)
+    }
+
+    /// Take a file from `sender` whose every chunk arrived
+    ///
+    /// The transfer is forgotten either way.
+    ///
+    /// # Errors
+    /// Returns a reason if no such file was offered, chunks are missing or
+    /// the whole file doesn't match its hash
+    pub fn finish_incoming(
+        &mut self,
+        transfer_id: Uuid,
+        sender: &str,
+    ) -> Result<(AttachmentManifest, Vec<u8>), String> {
+        let transfer = self
+            .incoming
+            .remove(&transfer_id)
+            .filter(|t| t.sender == sender)
+            .ok_or_else(|| "Completion of an unknown file".to_string())?;
+        let (received, total) = transfer.progress();
+        if received < total {
+            return Err(format!("Only {} of {} chunks arrived", received, total));
+        }
+        if profile_shared::protocol::attachment::sha256_hex(&transfer.data)
+            != transfer.manifest.file_hash
+        {
+            return Err("File doesn't match the signed manifest".to_string());
+        }
+        Ok((transfer.manifest, transfer.data))
+    }
+
+    /// Forget a transfer `peer` is on the other side of
+    ///
+    /// # Returns
+    /// Which of our transfers it was, None if there is no such transfer
+    pub fn cancel(&mut self, transfer_id: Uuid, peer: &str) -> Option<TransferDirection> {
+        if self
+            .outgoing
+            .get(&transfer_id)
+            .is_some_and(|t| t.recipient == peer)
+        {
+            self.outgoing.remove(&transfer_id);
+            return Some(TransferDirection::Outgoing);
+        }
+        if self
+            .incoming
+            .get(&transfer_id)
+            .is_some_and(|t| t.sender == peer)
+        {
+            self.incoming.remove(&transfer_id);
+            return Some(TransferDirection::Incoming);
+        }
+        None
+    }
+
+    /// The user on the other side of a transfer, if it is in progress
+    pub fn peer(&self, transfer_id: Uuid) -> Option<&str> {
+        self.outgoing
+            .get(&transfer_id)
+            .map(|t| t.recipient.as_str())
+            .or_else(|| self.incoming.get(&transfer_id).map(|t| t.sender.as_str()))
+    }
+}
+
+/// End of the window of chunks asked for after `received`
+fn next_window(received: u32, total: u32) -> u32 {
+    received.saturating_add(ACCEPT_WINDOW).min(total)
+}
+
+/// Default downloads directory
+///
+/// # Returns
+/// `PROFILE_DOWNLOADS_DIR` if set, otherwise `~/.profile/downloads`, or
+/// None if neither can be determined
+pub fn default_downloads_dir() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(DOWNLOADS_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("downloads"))
+}
+
+/// Shared attachment transfers for concurrent access
+pub type SharedAttachments = Arc<Mutex<AttachmentTransfers>>;
+
+/// Create new shared attachment transfers
+#[inline]
+pub fn create_shared_attachments() -> SharedAttachments {
+    Arc::new(Mutex::new(AttachmentTransfers::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profile_shared::config::attachments::CHUNK_SIZE;
+    use profile_shared::generate_private_key;
+
+    fn manifest(data: &[u8]) -> AttachmentManifest {
+        AttachmentManifest::sign(
+            &generate_private_key().unwrap(),
+            Uuid::new_v4(),
+            "scan.png",
+            data,
+            "2026-03-02T09:00:00Z",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_file_moves_between_transfers_a_window_at_a_time() {
+        let data: Vec<u8> = (0..CHUNK_SIZE * (ACCEPT_WINDOW as usize + 1) + 3)
+            .map(|i| i as u8)
+            .collect();
+        let manifest = manifest(&data);
+        let id = manifest.transfer_id;
+        let total = manifest.chunk_count();
+        let mut alice = AttachmentTransfers::with_downloads_dir(PathBuf::from("unused"));
+        let mut bob = AttachmentTransfers::with_downloads_dir(PathBuf::from("unused"));
+        alice.add_outgoing("bob".to_string(), manifest.clone(), data.clone());
+        assert!(bob.add_incoming("alice".to_string(), manifest));
+        assert_eq!(bob.pending_offers().len(), 1);
+
+        let mut until = bob.accept(id).unwrap();
+        assert_eq!(until, ACCEPT_WINDOW);
+        assert_eq!(bob.accept(id), None);
+        loop {
+            let sent = alice.take_chunks(id, "bob", until).unwrap();
+            let mut next = None;
+            for (index, chunk) in &sent.chunks {
+                next = bob.add_chunk(id, "alice", *index, chunk).unwrap();
+            }
+            if sent.finished {
+                assert_eq!(next, None);
+                break;
+            }
+            until = next.unwrap();
+        }
+        assert_eq!(bob.incoming(id).unwrap().progress(), (total, total));
+        assert!(alice.outgoing(id).is_none());
+
+        let (received, file) = bob.finish_incoming(id, "alice").unwrap();
+        assert_eq!(received.file_name, "scan.png");
+        assert_eq!(file, data);
+        assert!(bob.incoming(id).is_none());
+    }
+
+    #[test]
+    fn test_corrupted_or_unrequested_chunks_refused() {
+        let data = vec![9u8; CHUNK_SIZE + 1];
+        let manifest = manifest(&data);
+        let id = manifest.transfer_id;
+        let mut bob = AttachmentTransfers::new();
+        bob.add_incoming("alice".to_string(), manifest);
+
+        // Not accepted yet
+        assert!(bob.add_chunk(id, "alice", 0, &data[..CHUNK_SIZE]).is_err());
+        bob.accept(id).unwrap();
+        // From someone else, out of order, or altered
+        assert!(bob
+            .add_chunk(id, "mallory", 0, &data[..CHUNK_SIZE])
+            .is_err());
+        assert!(bob.add_chunk(id, "alice", 1, &data[CHUNK_SIZE..]).is_err());
+        assert!(bob.add_chunk(id, "alice", 0, &[0u8; CHUNK_SIZE]).is_err());
+
+        bob.add_chunk(id, "alice", 0, &data[..CHUNK_SIZE]).unwrap();
+        assert!(bob.finish_incoming(id, "alice").is_err());
+    }
+
+    #[test]
+    fn test_cancel_only_by_the_peer() {
+        let manifest = manifest(b"hello");
+        let id = manifest.transfer_id;
+        let mut alice = AttachmentTransfers::new();
+        alice.add_outgoing("bob".to_string(), manifest, b"hello".to_vec());
+        assert_eq!(alice.peer(id), Some("bob"));
+        assert_eq!(alice.cancel(id, "mallory"), None);
+        assert_eq!(alice.cancel(id, "bob"), Some(TransferDirection::Outgoing));
+        assert_eq!(alice.peer(id), None);
+    }
+}
@@ -0,0 +1,591 @@
+//! Audit log of security-relevant events
+//!
+//! Events that matter when something about an identity looks wrong are
+//! appended to a local log, one JSON object per line (JSONL), numbered in
+//! the order they happened:
+//!
+//! ```text
+//! {"seq":3,"timestamp":"2026-01-05T09:00:00Z","event":"auth_failed","server":"wss://...","reason":"..."}
+//! ```
+//!
+//! Recorded events:
+//! - a key generated, imported or rolled over
+//! - a message whose signature failed verification
+//! - the client connecting to a different server than last time
+//! - a refused sign-in
+//!
+//! The log is only ever appended to, across runs; nothing in the client
+//! rewrites or prunes it. It records public keys and server addresses,
+//! never private keys or message text. [`AuditLog::query`] filters it and
+//! [`AuditLog::export`] writes the matching records as JSON or CSV, for
+//! users checking what happened, such as when testing signatures.
+//!
+//! The log lives at `PROFILE_AUDIT_LOG`, or `~/.profile/audit.jsonl` when
+//! that is unset.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Environment variable overriding the audit log location
+pub const AUDIT_LOG_ENV_VAR: &str = "PROFILE_AUDIT_LOG";
+
+/// A security-relevant event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A new identity key was generated
+    KeyGenerated {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        /// Throwaway guest identity, never saved
+        guest: bool,
+    },
+    /// An identity key was imported from a recovery phrase or hex key
+    KeyImported {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+    },
+    /// A user, this one or a peer, replaced their key with a verified
+    /// rollover
+    KeyRolledOver {
+        #[serde(rename = "oldPublicKey")]
+        old_public_key: String,
+        #[serde(rename = "newPublicKey")]
+        new_public_key: String,
+    },
+    /// A message's signature did not verify
+    SignatureInvalid {
+        #[serde(rename = "senderPublicKey")]
+        sender_public_key: String,
+        reason: String,
+    },
+    /// The client connected to a different server than the previous time
+    ServerChanged {
+        previous: Option<String>,
+        server: String,
+    },
+    /// The server refused to sign the client in
+    AuthFailed { server: String, reason: String },
+}
+
+/// Kind of an [`AuditEvent`], for queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuditKind {
+    KeyGenerated,
+    KeyImported,
+    KeyRolledOver,
+    SignatureInvalid,
+    ServerChanged,
+    AuthFailed,
+}
+
+impl AuditKind {
+    /// All kinds, in the order events are listed above
+    pub const ALL: [AuditKind; 6] = [
+        AuditKind::KeyGenerated,
+        AuditKind::KeyImported,
+        AuditKind::KeyRolledOver,
+        AuditKind::SignatureInvalid,
+        AuditKind::ServerChanged,
+        AuditKind::AuthFailed,
+    ];
+
+    /// Name used in the log's `event` field
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditKind::KeyGenerated => "key_generated",
+            AuditKind::KeyImported => "key_imported",
+            AuditKind::KeyRolledOver => "key_rolled_over",
+            AuditKind::SignatureInvalid => "signature_invalid",
+            AuditKind::ServerChanged => "server_changed",
+            AuditKind::AuthFailed => "auth_failed",
+        }
+    }
+
+    /// Kind with the given log name
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == name)
+    }
+}
+
+impl AuditEvent {
+    /// The kind of this event
+    pub fn kind(&self) -> AuditKind {
+        match self {
+            AuditEvent::KeyGenerated { .. } => AuditKind::KeyGenerated,
+            AuditEvent::KeyImported { .. } => AuditKind::KeyImported,
+            AuditEvent::KeyRolledOver { .. } => AuditKind::KeyRolledOver,
+            AuditEvent::SignatureInvalid { .. } => AuditKind::SignatureInvalid,
+            AuditEvent::ServerChanged { .. } => AuditKind::ServerChanged,
+            AuditEvent::AuthFailed { .. } => AuditKind::AuthFailed,
+        }
+    }
+
+    /// Public keys the event names
+    pub fn public_keys(&self) -> Vec<&str> {
+        match self {
+            AuditEvent::KeyGenerated { public_key, .. }
+            | AuditEvent::KeyImported { public_key } => vec![public_key],
+            AuditEvent::KeyRolledOver {
+                old_public_key,
+                new_public_key,
+            } => vec![old_public_key, new_public_key],
+            AuditEvent::SignatureInvalid {
+                sender_public_key, ..
+            } => vec![sender_public_key],
+            AuditEvent::ServerChanged { .. } | AuditEvent::AuthFailed { .. } => Vec::new(),
+        }
+    }
+
+    /// One-line description, for CSV exports
+    fn details(&self) -> String {
+        match self {
+            AuditEvent::KeyGenerated { guest: true, .. } => "guest identity".to_string(),
+            AuditEvent::KeyGenerated { .. } | AuditEvent::KeyImported { .. } => String::new(),
+            AuditEvent::KeyRolledOver { .. } => String::new(),
+            AuditEvent::SignatureInvalid { reason, .. } => reason.clone(),
+            AuditEvent::ServerChanged { previous, server } => match previous {
+                Some(previous) => format!("{} -> {}", previous, server),
+                None => server.clone(),
+            },
+            AuditEvent::AuthFailed { server, reason } => format!("{}: {}", server, reason),
+        }
+    }
+}
+
+/// One logged event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the log, from 1
+    pub seq: u64,
+    /// When the event happened (RFC 3339)
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// Which records [`AuditLog::query`] returns
+///
+/// The default matches every record.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditQuery {
+    kinds: Vec<AuditKind>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    public_key: Option<String>,
+    limit: Option<usize>,
+}
+
+impl AuditQuery {
+    /// Match every record
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only records of `kind`; may be called more than once to match any
+    /// of several kinds
+    pub fn with_kind(mut self, kind: AuditKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Only records from `since` on
+    pub fn with_since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only records before `until`
+    pub fn with_until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Only records naming `public_key`, in any case
+    pub fn with_public_key(mut self, public_key: &str) -> Self {
+        self.public_key = Some(public_key.to_lowercase());
+        self
+    }
+
+    /// Only the newest `limit` matching records
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Whether a record matches, apart from the limit
+    ///
+    /// A record whose timestamp doesn't parse never matches a time bound.
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(&record.event.kind()) {
+            return false;
+        }
+        if self.since.is_some() || self.until.is_some() {
+            let Ok(at) = DateTime::parse_from_rfc3339(&record.timestamp) else {
+                return false;
+            };
+            if self.since.is_some_and(|since| at < since)
+                || self.until.is_some_and(|until| at >= until)
+            {
+                return false;
+            }
+        }
+        self.public_key.as_deref().is_none_or(|wanted| {
+            record
+                .event
+                .public_keys()
+                .iter()
+                .any(|key| key.eq_ignore_ascii_case(wanted))
+        })
+    }
+
+    /// The matching records, oldest first
+    pub fn apply(&self, records: Vec<AuditRecord>) -> Vec<AuditRecord> {
+        let mut matching: Vec<AuditRecord> =
+            records.into_iter().filter(|r| self.matches(r)).collect();
+        if let Some(limit) = self.limit {
+            let skip = matching.len().saturating_sub(limit);
+            matching.drain(..skip);
+        }
+        matching
+    }
+}
+
+/// Format of an exported audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditExportFormat {
+    /// A JSON array of records
+    Json,
+    /// `seq,timestamp,event,publicKeys,details` with a header row
+    Csv,
+}
+
+impl AuditExportFormat {
+    /// Format matching a file extension: `.csv` is CSV, anything else JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => AuditExportFormat::Csv,
+            _ => AuditExportFormat::Json,
+        }
+    }
+}
+
+/// Quote a CSV field if it needs it
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Render records in an export format
+pub fn export_records(records: &[AuditRecord], format: AuditExportFormat) -> io::Result<String> {
+    match format {
+        AuditExportFormat::Json => Ok(serde_json::to_string_pretty(records)?),
+        AuditExportFormat::Csv => {
+            let mut csv = String::from("seq,timestamp,event,publicKeys,details\n");
+            for record in records {
+                let fields = [
+                    record.seq.to_string(),
+                    record.timestamp.clone(),
+                    record.event.kind().as_str().to_string(),
+                    record.event.public_keys().join(" "),
+                    record.event.details(),
+                ];
+                let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                csv.push_str(&row.join(","));
+                csv.push('\n');
+            }
+            Ok(csv)
+        }
+    }
+}
+
+/// Default location of the audit log
+///
+/// # Returns
+/// `PROFILE_AUDIT_LOG` if set, otherwise `~/.profile/audit.jsonl`, or None
+/// if neither can be determined
+pub fn default_audit_log_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(AUDIT_LOG_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("audit.jsonl"))
+}
+
+/// Read every record from an audit log file
+///
+/// A missing file reads as an empty log. Blank lines are skipped; a
+/// malformed line is reported with its line number.
+pub fn read_audit_log(path: impl AsRef<Path>) -> io::Result<Vec<AuditRecord>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut records = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, e),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[derive(Debug)]
+struct AuditFile {
+    file: File,
+    next_seq: u64,
+    /// Server of the last `server_changed` record, to tell a change from a
+    /// reconnection
+    last_server: Option<String>,
+}
+
+/// Appends security events to the audit log
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<AuditFile>,
+}
+
+impl AuditLog {
+    /// Open the audit log at `path` for appending, creating it if needed
+    ///
+    /// Numbering carries on from the records already in the file.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let records = read_audit_log(&path)?;
+        let next_seq = records.last().map_or(1, |r| r.seq + 1);
+        let last_server = records.iter().rev().find_map(|r| match &r.event {
+            AuditEvent::ServerChanged { server, .. } => Some(server.clone()),
+            _ => None,
+        });
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(AuditFile {
+                file,
+                next_seq,
+                last_server,
+            }),
+        })
+    }
+
+    /// Open the log at its default location (see [`default_audit_log_path`])
+    ///
+    /// # Returns
+    /// Ok(None) if there is no location to keep it at
+    pub fn open_default() -> io::Result<Option<Arc<Self>>> {
+        default_audit_log_path()
+            .map(|path| Self::open(path).map(Arc::new))
+            .transpose()
+    }
+
+    /// File the log is kept in
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one event, stamped with the current time
+    ///
+    /// Each record is written with a single call and flushed, so records
+    /// from a crashed run are complete.
+    ///
+    /// # Returns
+    /// The event's sequence number
+    pub fn record(&self, event: AuditEvent) -> io::Result<u64> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("audit log lock poisoned"))?;
+        let record = AuditRecord {
+            seq: file.next_seq,
+            timestamp: Utc::now().to_rfc3339(),
+            event,
+        };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        file.file.write_all(line.as_bytes())?;
+        file.file.flush()?;
+        file.next_seq += 1;
+        if let AuditEvent::ServerChanged { server, .. } = &record.event {
+            file.last_server = Some(server.clone());
+        }
+        Ok(record.seq)
+    }
+
+    /// Record a connection to `server` if it differs from the previous one
+    ///
+    /// # Returns
+    /// Ok(true) if a `server_changed` record was written
+    pub fn record_server(&self, server: &str) -> io::Result<bool> {
+        let previous = {
+            let file = self
+                .file
+                .lock()
+                .map_err(|_| io::Error::other("audit log lock poisoned"))?;
+            if file.last_server.as_deref() == Some(server) {
+                return Ok(false);
+            }
+            file.last_server.clone()
+        };
+        self.record(AuditEvent::ServerChanged {
+            previous,
+            server: server.to_string(),
+        })?;
+        Ok(true)
+    }
+
+    /// Records matching `query`, oldest first
+    pub fn query(&self, query: &AuditQuery) -> io::Result<Vec<AuditRecord>> {
+        Ok(query.apply(read_audit_log(&self.path)?))
+    }
+
+    /// Records matching `query`, rendered in `format`
+    pub fn export(&self, query: &AuditQuery, format: AuditExportFormat) -> io::Result<String> {
+        export_records(&self.query(query)?, format)
+    }
+}
+
+/// Audit log in use, if any
+pub type AuditHandle = Option<Arc<AuditLog>>;
+
+/// Record an event built by `event`, which only runs if there is a log
+pub fn audit_event(audit: &AuditHandle, event: impl FnOnce() -> AuditEvent) {
+    if let Some(audit) = audit {
+        if let Err(e) = audit.record(event()) {
+            tracing::warn!(error = %e, "Failed to write audit log");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("profile-audit-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_log_appends_across_opens() {
+        let path = temp_path("append.jsonl");
+        let _ = std::fs::remove_file(&path);
+
+        let log = AuditLog::open(&path).unwrap();
+        let key = "ab".repeat(32);
+        assert_eq!(
+            log.record(AuditEvent::KeyGenerated {
+                public_key: key.clone(),
+                guest: false,
+            })
+            .unwrap(),
+            1
+        );
+        assert!(log.record_server("wss://one.example").unwrap());
+        assert!(!log.record_server("wss://one.example").unwrap());
+        drop(log);
+
+        // A new run keeps numbering and remembers the server
+        let log = AuditLog::open(&path).unwrap();
+        assert!(!log.record_server("wss://one.example").unwrap());
+        assert!(log.record_server("wss://two.example").unwrap());
+        let records = log.query(&AuditQuery::new()).unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.seq).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            records[2].event,
+            AuditEvent::ServerChanged {
+                previous: Some("wss://one.example".to_string()),
+                server: "wss://two.example".to_string(),
+            }
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_query_filters_and_export() {
+        let (alice, bob) = ("ab".repeat(32), "cd".repeat(32));
+        let record = |seq, timestamp: &str, event| AuditRecord {
+            seq,
+            timestamp: timestamp.to_string(),
+            event,
+        };
+        let records = vec![
+            record(
+                1,
+                "2026-01-01T10:00:00Z",
+                AuditEvent::KeyImported {
+                    public_key: alice.clone(),
+                },
+            ),
+            record(
+                2,
+                "2026-01-02T10:00:00Z",
+                AuditEvent::SignatureInvalid {
+                    sender_public_key: bob.clone(),
+                    reason: "bad, \"forged\"".to_string(),
+                },
+            ),
+            record(
+                3,
+                "2026-01-03T10:00:00Z",
+                AuditEvent::AuthFailed {
+                    server: "wss://one.example".to_string(),
+                    reason: "key_retired".to_string(),
+                },
+            ),
+        ];
+
+        let seqs = |query: AuditQuery| -> Vec<u64> {
+            query.apply(records.clone()).iter().map(|r| r.seq).collect()
+        };
+        assert_eq!(seqs(AuditQuery::new()), vec![1, 2, 3]);
+        assert_eq!(
+            seqs(AuditQuery::new().with_kind(AuditKind::SignatureInvalid)),
+            vec![2]
+        );
+        assert_eq!(
+            seqs(AuditQuery::new().with_public_key(&alice.to_uppercase())),
+            vec![1]
+        );
+        let since = "2026-01-02T00:00:00Z".parse().unwrap();
+        let until = "2026-01-03T10:00:00Z".parse().unwrap();
+        assert_eq!(
+            seqs(AuditQuery::new().with_since(since).with_until(until)),
+            vec![2]
+        );
+        assert_eq!(seqs(AuditQuery::new().with_limit(2)), vec![2, 3]);
+        assert_eq!(AuditKind::parse("auth_failed"), Some(AuditKind::AuthFailed));
+
+        let csv = export_records(&records[1..2], AuditExportFormat::Csv).unwrap();
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            format!(
+                "2,2026-01-02T10:00:00Z,signature_invalid,{},\"bad, \"\"forged\"\"\"",
+                bob
+            )
+        );
+        let json = export_records(&records, AuditExportFormat::Json).unwrap();
+        let parsed: Vec<AuditRecord> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, records);
+    }
+}
@@ -0,0 +1,194 @@
+//! Blocked users
+//!
+//! Direct messages from a blocked public key are dropped when they arrive,
+//! before they are stored or shown. Blocking is local to this device: the
+//! blocked user is not told, and can still see this user in the lobby.
+//!
+//! The blocklist is persisted as JSON at `PROFILE_BLOCKLIST_FILE`, or
+//! `~/.profile/blocklist.json` when that is unset.
+
+use crate::state::conversation::ConversationId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Environment variable overriding the blocklist file location
+pub const BLOCKLIST_ENV_VAR: &str = "PROFILE_BLOCKLIST_FILE";
+
+/// Error types for blocklist operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlocklistError {
+    /// Public key is not 64 hex characters
+    InvalidPublicKey,
+    /// Reading or writing the blocklist file failed
+    Io(String),
+    /// The blocklist file is not valid JSON
+    Parse(String),
+}
+
+impl Display for BlocklistError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BlocklistError::InvalidPublicKey => {
+                write!(f, "Public key must be 64 hexadecimal characters")
+            }
+            BlocklistError::Io(msg) => write!(f, "Failed to access blocklist file: {}", msg),
+            BlocklistError::Parse(msg) => write!(f, "Failed to parse blocklist file: {}", msg),
+        }
+    }
+}
+
+impl Error for BlocklistError {}
+
+/// Public keys the user blocked
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blocklist {
+    #[serde(default)]
+    blocked: BTreeSet<ConversationId>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// Default location of the blocklist file
+///
+/// # Returns
+/// `PROFILE_BLOCKLIST_FILE` if set, otherwise `~/.profile/blocklist.json`,
+/// or None if neither can be determined
+pub fn default_blocklist_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(BLOCKLIST_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("blocklist.json"))
+}
+
+impl Blocklist {
+    /// Create an empty, in-memory blocklist
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the blocklist saved at `path`
+    ///
+    /// A missing file gives an empty blocklist that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, BlocklistError> {
+        let path = path.into();
+        let mut blocklist: Blocklist = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| BlocklistError::Parse(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Blocklist::default(),
+            Err(e) => return Err(BlocklistError::Io(e.to_string())),
+        };
+        blocklist.path = Some(path);
+        Ok(blocklist)
+    }
+
+    /// Save the blocklist to its file
+    ///
+    /// In-memory blocklists are not saved.
+    pub fn save(&self) -> Result<(), BlocklistError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| BlocklistError::Io(e.to_string()))?;
+        }
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| BlocklistError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| BlocklistError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| BlocklistError::Io(e.to_string()))
+    }
+
+    /// File the blocklist is saved to, if persistent
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Block a public key
+    ///
+    /// # Returns
+    /// Whether the key was not blocked before
+    pub fn block(&mut self, public_key: &str) -> Result<bool, BlocklistError> {
+        Ok(self.blocked.insert(conversation_id(public_key)?))
+    }
+
+    /// Unblock a public key
+    ///
+    /// # Returns
+    /// Whether the key was blocked
+    pub fn unblock(&mut self, public_key: &str) -> Result<bool, BlocklistError> {
+        Ok(self.blocked.remove(&conversation_id(public_key)?))
+    }
+
+    /// Whether a public key is blocked (in any case)
+    pub fn is_blocked(&self, public_key: &str) -> bool {
+        ConversationId::new(public_key)
+            .map(|id| self.blocked.contains(&id))
+            .unwrap_or(false)
+    }
+
+    /// Number of blocked keys
+    pub fn len(&self) -> usize {
+        self.blocked.len()
+    }
+
+    /// Check if nobody is blocked
+    pub fn is_empty(&self) -> bool {
+        self.blocked.is_empty()
+    }
+}
+
+fn conversation_id(public_key: &str) -> Result<ConversationId, BlocklistError> {
+    ConversationId::new(public_key).map_err(|_| BlocklistError::InvalidPublicKey)
+}
+
+/// Shared blocklist for concurrent access
+pub type SharedBlocklist = Arc<Mutex<Blocklist>>;
+
+/// Create a new shared, in-memory blocklist
+#[inline]
+pub fn create_shared_blocklist() -> SharedBlocklist {
+    Arc::new(Mutex::new(Blocklist::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_and_unblock_any_case() {
+        let mut blocklist = Blocklist::new();
+        assert_eq!(blocklist.block(&"AB".repeat(32)), Ok(true));
+        assert_eq!(blocklist.block(&"ab".repeat(32)), Ok(false));
+        assert!(blocklist.is_blocked(&"ab".repeat(32)));
+        assert!(!blocklist.is_blocked("not a key"));
+        assert_eq!(
+            blocklist.block("abc"),
+            Err(BlocklistError::InvalidPublicKey)
+        );
+
+        assert_eq!(blocklist.unblock(&"ab".repeat(32)), Ok(true));
+        assert!(blocklist.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("profile-blocklist-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut blocklist = Blocklist::load(&path).unwrap();
+        blocklist.block(&"cd".repeat(32)).unwrap();
+        blocklist.save().unwrap();
+
+        let loaded = Blocklist::load(&path).unwrap();
+        assert_eq!(loaded, blocklist);
+        assert!(loaded.is_blocked(&"CD".repeat(32)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
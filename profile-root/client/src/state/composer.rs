@@ -8,11 +8,14 @@
 //! - Draft is preserved during network disconnections
 //! - Draft is only cleared on successful send or app close
 
+use crate::state::journal::{journal_event, JournalHandle, StateEvent};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 /// Connection state for the composer
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ConnectionState {
     /// Connected to server
     Connected,
@@ -33,6 +36,8 @@ pub struct ComposerState {
     connection_state: ConnectionState,
     /// Callback for connection state changes
     connection_callback: Option<Arc<dyn Fn(ConnectionState) + Send + Sync>>,
+    /// State-event journal every change is recorded to, if enabled
+    journal: JournalHandle,
 }
 
 impl ComposerState {
@@ -43,11 +48,20 @@ impl ComposerState {
             recipient: None,
             connection_state: ConnectionState::Connected,
             connection_callback: None,
+            journal: None,
         }
     }
 
+    /// Record every change to a state-event journal (None to stop)
+    pub fn set_journal(&mut self, journal: JournalHandle) {
+        self.journal = journal;
+    }
+
     /// Set the current draft text
     pub fn set_draft(&mut self, text: String) {
+        journal_event(&self.journal, || StateEvent::ComposerSetDraft {
+            text: text.clone(),
+        });
         self.draft_text = text;
     }
 
@@ -58,11 +72,15 @@ impl ComposerState {
 
     /// Clear the draft text (only on successful send)
     pub fn clear_draft(&mut self) {
+        journal_event(&self.journal, || StateEvent::ComposerClearDraft);
         self.draft_text.clear();
     }
 
     /// Set the selected recipient
     pub fn set_recipient(&mut self, recipient: Option<String>) {
+        journal_event(&self.journal, || StateEvent::ComposerSetRecipient {
+            recipient: recipient.clone(),
+        });
         self.recipient = recipient;
     }
 
@@ -78,6 +96,9 @@ impl ComposerState {
 
     /// Set connection state and notify if callback is set
     pub fn set_connection_state(&mut self, state: ConnectionState) {
+        journal_event(&self.journal, || StateEvent::ComposerSetConnection {
+            state: state.clone(),
+        });
         self.connection_state = state.clone();
 
         // Notify callback if registered
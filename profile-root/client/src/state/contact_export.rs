@@ -0,0 +1,167 @@
+//! Signed, portable contact list exports
+//!
+//! An export is a JSON file holding the contact list and the exporting
+//! identity's public key, signed with that identity's private key. Importing
+//! checks the signature and that the file was signed by the importing
+//! identity, so a roster moved between one's own devices can't be altered
+//! or swapped in transit. The signature covers
+//! [`canonical::contact_export`] of the payload JSON, where the payload is
+//! everything in the file except the signature.
+
+use crate::state::contacts::{Contact, ContactError, Contacts};
+use profile_shared::{canonical, sign_message, verify_signature, PrivateKey, PublicKey};
+use serde::{Deserialize, Serialize};
+
+/// Current export format version
+pub const CONTACT_EXPORT_VERSION: u32 = 1;
+
+/// Signed part of an export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContactExportPayload {
+    pub version: u32,
+    /// Public key of the identity that signed the export (hex-encoded)
+    #[serde(rename = "signerPublicKey")]
+    pub signer_public_key: String,
+    /// When the export was created (RFC 3339)
+    #[serde(rename = "exportedAt")]
+    pub exported_at: String,
+    pub contacts: Vec<Contact>,
+}
+
+/// A contact list export with its signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContactExport {
+    #[serde(flatten)]
+    pub payload: ContactExportPayload,
+    /// Hex-encoded ed25519 signature over the payload
+    pub signature: String,
+}
+
+fn signed_bytes(payload: &ContactExportPayload) -> Result<Vec<u8>, ContactError> {
+    let json = serde_json::to_string(payload).map_err(|e| ContactError::Parse(e.to_string()))?;
+    Ok(canonical::contact_export(&json))
+}
+
+impl ContactExport {
+    /// Export a contact list, signed by the user's identity key
+    pub fn create(
+        contacts: &Contacts,
+        private_key: &PrivateKey,
+        public_key: &PublicKey,
+    ) -> Result<Self, ContactError> {
+        let payload = ContactExportPayload {
+            version: CONTACT_EXPORT_VERSION,
+            signer_public_key: hex::encode(public_key.as_bytes()),
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            contacts: contacts.contacts().cloned().collect(),
+        };
+        let signature = sign_message(private_key, &signed_bytes(&payload)?)
+            .map_err(|e| ContactError::InvalidExport(e.to_string()))?;
+        Ok(Self {
+            payload,
+            signature: hex::encode(signature),
+        })
+    }
+
+    /// Parse an export file
+    pub fn from_json(json: &str) -> Result<Self, ContactError> {
+        serde_json::from_str(json).map_err(|e| ContactError::InvalidExport(e.to_string()))
+    }
+
+    /// Serialize the export for writing to a file
+    pub fn to_json(&self) -> Result<String, ContactError> {
+        serde_json::to_string_pretty(self).map_err(|e| ContactError::Parse(e.to_string()))
+    }
+
+    /// Check the export was signed by `expected_signer` and hasn't changed
+    ///
+    /// # Returns
+    /// The verified contacts, ready to merge
+    pub fn verify(self, expected_signer: &PublicKey) -> Result<Vec<Contact>, ContactError> {
+        if self.payload.version != CONTACT_EXPORT_VERSION {
+            return Err(ContactError::InvalidExport(format!(
+                "Unsupported export version {}",
+                self.payload.version
+            )));
+        }
+        if !self
+            .payload
+            .signer_public_key
+            .eq_ignore_ascii_case(&hex::encode(expected_signer.as_bytes()))
+        {
+            return Err(ContactError::WrongSigner);
+        }
+
+        let signature =
+            hex::decode(&self.signature).map_err(|_| ContactError::InvalidExportSignature)?;
+        verify_signature(expected_signer, &signed_bytes(&self.payload)?, &signature)
+            .map_err(|_| ContactError::InvalidExportSignature)?;
+        Ok(self.payload.contacts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profile_shared::{derive_public_key, generate_private_key};
+
+    fn identity() -> (PrivateKey, PublicKey) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        (private_key, public_key)
+    }
+
+    fn roster() -> Contacts {
+        let mut contacts = Contacts::new();
+        contacts.add(&"ab".repeat(32), "Alice").unwrap();
+        contacts.add_tag(&"ab".repeat(32), "work").unwrap();
+        contacts.add(&"cd".repeat(32), "Bob").unwrap();
+        contacts
+    }
+
+    #[test]
+    fn test_export_round_trip_verifies() {
+        let (private_key, public_key) = identity();
+        let export = ContactExport::create(&roster(), &private_key, &public_key).unwrap();
+
+        let parsed = ContactExport::from_json(&export.to_json().unwrap()).unwrap();
+        let contacts = parsed.verify(&public_key).unwrap();
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].label, "Alice");
+        assert!(contacts[0].tags.contains("work"));
+    }
+
+    #[test]
+    fn test_tampered_or_foreign_export_rejected() {
+        let (private_key, public_key) = identity();
+        let export = ContactExport::create(&roster(), &private_key, &public_key).unwrap();
+
+        let mut tampered: serde_json::Value =
+            serde_json::from_str(&export.to_json().unwrap()).unwrap();
+        tampered["contacts"][1]["label"] = "Mallory".into();
+        assert_eq!(
+            ContactExport::from_json(&tampered.to_string())
+                .unwrap()
+                .verify(&public_key),
+            Err(ContactError::InvalidExportSignature)
+        );
+
+        // Validly signed, but by someone else
+        let (_, other_key) = identity();
+        assert_eq!(
+            export.clone().verify(&other_key),
+            Err(ContactError::WrongSigner)
+        );
+
+        let mut future = export;
+        future.payload.version = CONTACT_EXPORT_VERSION + 1;
+        assert!(matches!(
+            future.verify(&public_key),
+            Err(ContactError::InvalidExport(_))
+        ));
+        assert!(matches!(
+            ContactExport::from_json("{}"),
+            Err(ContactError::InvalidExport(_))
+        ));
+    }
+}
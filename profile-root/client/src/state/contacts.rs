@@ -0,0 +1,823 @@
+//! Contact list of known public keys
+//!
+//! Contacts map a public key to a label chosen by this user, so chats can
+//! show "Alice" instead of a raw key. A contact starts out `Pinned` (trust
+//! on first use): the key was accepted without an out-of-band check. Once
+//! the user has compared keys another way they can mark it `Verified`.
+//!
+//! Because labels belong to pinned keys, a different key showing up under
+//! a contact's name (for example as its server nickname) can be flagged
+//! with [`Contacts::check_name`].
+//!
+//! When a contact rotates their identity key, [`Contacts::migrate`] moves
+//! the contact to the new key and keeps the old one in its history, so the
+//! label and trust carry over once the rollover is verified.
+//!
+//! Each contact can also carry free-form notes, tags and the time of the
+//! first message exchanged with it, all of which [`Contacts::search`]
+//! matches against.
+//!
+//! The list is persisted as JSON at `PROFILE_CONTACTS_FILE`, or
+//! `~/.profile/contacts.json` when that is unset.
+
+use crate::state::conversation::ConversationId;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Environment variable overriding the contacts file location
+pub const CONTACTS_ENV_VAR: &str = "PROFILE_CONTACTS_FILE";
+
+/// Maximum length of a contact label, in characters
+pub const MAX_CONTACT_LABEL_LENGTH: usize = 64;
+
+/// Maximum length of a contact's notes, in characters
+pub const MAX_CONTACT_NOTES_LENGTH: usize = 1000;
+
+/// Maximum length of a contact tag, in characters
+pub const MAX_CONTACT_TAG_LENGTH: usize = 32;
+
+/// Maximum number of tags on one contact
+pub const MAX_CONTACT_TAGS: usize = 16;
+
+/// Error types for contact list operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContactError {
+    /// Public key is not 64 hex characters
+    InvalidPublicKey,
+    /// Label is empty or too long
+    InvalidLabel,
+    /// Notes are too long
+    InvalidNotes,
+    /// Tag is empty, too long or contains whitespace
+    InvalidTag,
+    /// The contact already has the maximum number of tags
+    TooManyTags,
+    /// The key is already a contact
+    AlreadyExists,
+    /// The key is not a contact
+    NotFound,
+    /// Reading or writing the contacts file failed
+    Io(String),
+    /// The contacts file is not valid JSON
+    Parse(String),
+    /// A contact export is malformed or an unsupported version
+    InvalidExport(String),
+    /// A contact export's signature doesn't match its contents
+    InvalidExportSignature,
+    /// A contact export was signed by a different identity
+    WrongSigner,
+    /// No identity key is loaded to sign or check an export
+    NoIdentityKey,
+}
+
+impl Display for ContactError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ContactError::InvalidPublicKey => {
+                write!(f, "Public key must be 64 hexadecimal characters")
+            }
+            ContactError::InvalidLabel => write!(
+                f,
+                "Contact name must be 1 to {} characters",
+                MAX_CONTACT_LABEL_LENGTH
+            ),
+            ContactError::InvalidNotes => write!(
+                f,
+                "Contact notes must be at most {} characters",
+                MAX_CONTACT_NOTES_LENGTH
+            ),
+            ContactError::InvalidTag => write!(
+                f,
+                "Tags must be 1 to {} characters without spaces",
+                MAX_CONTACT_TAG_LENGTH
+            ),
+            ContactError::TooManyTags => {
+                write!(f, "A contact can have at most {} tags", MAX_CONTACT_TAGS)
+            }
+            ContactError::AlreadyExists => write!(f, "Contact already exists"),
+            ContactError::NotFound => write!(f, "Contact not found"),
+            ContactError::Io(msg) => write!(f, "Failed to access contacts file: {}", msg),
+            ContactError::Parse(msg) => write!(f, "Failed to parse contacts file: {}", msg),
+            ContactError::InvalidExport(msg) => write!(f, "Invalid contacts export: {}", msg),
+            ContactError::InvalidExportSignature => write!(
+                f,
+                "Contacts export signature is invalid; the file may have been modified"
+            ),
+            ContactError::WrongSigner => {
+                write!(f, "Contacts export was signed by a different identity")
+            }
+            ContactError::NoIdentityKey => {
+                write!(
+                    f,
+                    "Generate or import a key before exporting or importing contacts"
+                )
+            }
+        }
+    }
+}
+
+impl Error for ContactError {}
+
+/// How far a contact's key is trusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustStatus {
+    /// Trusted on first use, not checked out of band
+    Pinned,
+    /// Key confirmed out of band by the user
+    Verified,
+}
+
+/// Which label wins when an imported contact is labelled differently
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the label already on this device
+    KeepLocal,
+    /// Take the label from the imported list
+    PreferImported,
+}
+
+/// What merging an imported contact list changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    /// Keys that were not contacts before
+    pub added: Vec<String>,
+    /// Existing contacts that changed in any way
+    pub updated: Vec<String>,
+    /// Existing contacts whose imported label differed from the local one
+    pub label_conflicts: Vec<String>,
+}
+
+/// A known public key with a user-assigned label
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    /// The contact's public key (hex-encoded)
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    /// Name chosen by this user
+    pub label: String,
+    pub trust: TrustStatus,
+    /// When the key was pinned (RFC 3339)
+    #[serde(rename = "addedAt")]
+    pub added_at: String,
+    /// Free-form notes kept by this user
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// Lowercase tags for grouping and search
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// When a message was first exchanged with the contact (RFC 3339)
+    #[serde(
+        rename = "firstContactedAt",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub first_contacted_at: Option<String>,
+    /// Keys the contact used before rolling over to this one, oldest first
+    #[serde(
+        rename = "previousKeys",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub previous_keys: Vec<String>,
+}
+
+impl Contact {
+    /// Whether every whitespace-separated term of a lowercase query appears
+    /// in the label, notes or a tag, or starts the public key
+    fn matches(&self, terms: &[String]) -> bool {
+        let label = self.label.to_lowercase();
+        let notes = self.notes.to_lowercase();
+        terms.iter().all(|term| {
+            let term = term.strip_prefix('#').unwrap_or(term);
+            label.contains(term)
+                || notes.contains(term)
+                || self.tags.iter().any(|tag| tag.contains(term))
+                || self.public_key.starts_with(term)
+        })
+    }
+}
+
+/// Result of checking a claimed name against the contact list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameCheck {
+    /// No contact uses this name
+    Unknown,
+    /// The name belongs to this key
+    Matches,
+    /// The name belongs to a different pinned key
+    Conflict { pinned_key: String },
+}
+
+/// On-disk layout of the contacts file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ContactsFile {
+    contacts: Vec<Contact>,
+}
+
+/// The user's contact list
+#[derive(Debug, Clone, Default)]
+pub struct Contacts {
+    /// Contacts keyed by public key (sorted so the file is stable)
+    contacts: BTreeMap<ConversationId, Contact>,
+    /// File the list is saved to, if persistent
+    path: Option<PathBuf>,
+}
+
+fn conversation_id(public_key: &str) -> Result<ConversationId, ContactError> {
+    ConversationId::new(public_key).map_err(|_| ContactError::InvalidPublicKey)
+}
+
+fn normalize_label(label: &str) -> Result<String, ContactError> {
+    let label = label.trim();
+    if label.is_empty() || label.chars().count() > MAX_CONTACT_LABEL_LENGTH {
+        return Err(ContactError::InvalidLabel);
+    }
+    Ok(label.to_string())
+}
+
+fn normalize_tag(tag: &str) -> Result<String, ContactError> {
+    let tag = tag.trim().trim_start_matches('#').to_lowercase();
+    if tag.is_empty()
+        || tag.chars().count() > MAX_CONTACT_TAG_LENGTH
+        || tag.chars().any(char::is_whitespace)
+    {
+        return Err(ContactError::InvalidTag);
+    }
+    Ok(tag)
+}
+
+/// Default location of the contacts file
+///
+/// # Returns
+/// `PROFILE_CONTACTS_FILE` if set, otherwise `~/.profile/contacts.json`,
+/// or None if neither can be determined
+pub fn default_contacts_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONTACTS_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("contacts.json"))
+}
+
+impl Contacts {
+    /// Create an empty, in-memory contact list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the contact list saved at `path`
+    ///
+    /// A missing file gives an empty list that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, ContactError> {
+        let path = path.into();
+        let file: ContactsFile = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| ContactError::Parse(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ContactsFile::default(),
+            Err(e) => return Err(ContactError::Io(e.to_string())),
+        };
+
+        let contacts = file
+            .contacts
+            .into_iter()
+            .map(|mut c| {
+                let id = ConversationId::new(&c.public_key)
+                    .map_err(|e| ContactError::Parse(e.to_string()))?;
+                c.public_key = id.to_string();
+                Ok((id, c))
+            })
+            .collect::<Result<_, ContactError>>()?;
+
+        Ok(Self {
+            contacts,
+            path: Some(path),
+        })
+    }
+
+    /// Save the contact list to its file
+    ///
+    /// Writes to a temporary file first so a crash never leaves a
+    /// truncated list behind. In-memory lists are not saved.
+    pub fn save(&self) -> Result<(), ContactError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| ContactError::Io(e.to_string()))?;
+        }
+
+        let file = ContactsFile {
+            contacts: self.contacts.values().cloned().collect(),
+        };
+        let json =
+            serde_json::to_string_pretty(&file).map_err(|e| ContactError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| ContactError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| ContactError::Io(e.to_string()))
+    }
+
+    /// File the list is saved to, if persistent
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Pin a new contact
+    ///
+    /// # Arguments
+    /// * `public_key` - The contact's hex-encoded public key, in any case
+    /// * `label` - Name to show for the contact (surrounding spaces are trimmed)
+    pub fn add(&mut self, public_key: &str, label: &str) -> Result<&Contact, ContactError> {
+        let id = conversation_id(public_key)?;
+        let label = normalize_label(label)?;
+        if self.contacts.contains_key(&id) {
+            return Err(ContactError::AlreadyExists);
+        }
+
+        let contact = Contact {
+            public_key: id.to_string(),
+            label,
+            trust: TrustStatus::Pinned,
+            added_at: chrono::Utc::now().to_rfc3339(),
+            notes: String::new(),
+            tags: BTreeSet::new(),
+            first_contacted_at: None,
+            previous_keys: Vec::new(),
+        };
+        Ok(self.contacts.entry(id).or_insert(contact))
+    }
+
+    /// Remove a contact
+    ///
+    /// # Returns
+    /// The removed contact, None if the key was not a contact
+    pub fn remove(&mut self, public_key: &str) -> Option<Contact> {
+        self.contacts.remove(&ConversationId::new(public_key).ok()?)
+    }
+
+    /// Move a contact to the key it rolled over to
+    ///
+    /// Label, trust, notes, tags and times carry over; the old key is
+    /// added to the contact's previous keys. Only call this for a rollover
+    /// whose signatures were verified.
+    ///
+    /// # Errors
+    /// `NotFound` if the old key is not a contact, `AlreadyExists` if the
+    /// new key already is
+    pub fn migrate(
+        &mut self,
+        old_public_key: &str,
+        new_public_key: &str,
+    ) -> Result<&Contact, ContactError> {
+        let old_id = conversation_id(old_public_key)?;
+        let new_id = conversation_id(new_public_key)?;
+        if self.contacts.contains_key(&new_id) {
+            return Err(ContactError::AlreadyExists);
+        }
+        let mut contact = self
+            .contacts
+            .remove(&old_id)
+            .ok_or(ContactError::NotFound)?;
+        contact.previous_keys.push(old_id.to_string());
+        contact.public_key = new_id.to_string();
+        Ok(self.contacts.entry(new_id).or_insert(contact))
+    }
+
+    /// Change a contact's label
+    pub fn rename(&mut self, public_key: &str, label: &str) -> Result<(), ContactError> {
+        let label = normalize_label(label)?;
+        let contact = self.get_mut(public_key).ok_or(ContactError::NotFound)?;
+        contact.label = label;
+        Ok(())
+    }
+
+    /// Change how far a contact's key is trusted
+    pub fn set_trust(&mut self, public_key: &str, trust: TrustStatus) -> Result<(), ContactError> {
+        let contact = self.get_mut(public_key).ok_or(ContactError::NotFound)?;
+        contact.trust = trust;
+        Ok(())
+    }
+
+    /// Get a contact by public key
+    pub fn get(&self, public_key: &str) -> Option<&Contact> {
+        self.contacts.get(&ConversationId::new(public_key).ok()?)
+    }
+
+    fn get_mut(&mut self, public_key: &str) -> Option<&mut Contact> {
+        self.contacts
+            .get_mut(&ConversationId::new(public_key).ok()?)
+    }
+
+    /// Replace a contact's notes (surrounding spaces are trimmed)
+    pub fn set_notes(&mut self, public_key: &str, notes: &str) -> Result<(), ContactError> {
+        let notes = notes.trim();
+        if notes.chars().count() > MAX_CONTACT_NOTES_LENGTH {
+            return Err(ContactError::InvalidNotes);
+        }
+        let contact = self.get_mut(public_key).ok_or(ContactError::NotFound)?;
+        contact.notes = notes.to_string();
+        Ok(())
+    }
+
+    /// Tag a contact
+    ///
+    /// Tags are stored lowercase, without a leading `#`.
+    ///
+    /// # Returns
+    /// Ok(false) if the contact already had the tag
+    pub fn add_tag(&mut self, public_key: &str, tag: &str) -> Result<bool, ContactError> {
+        let tag = normalize_tag(tag)?;
+        let contact = self.get_mut(public_key).ok_or(ContactError::NotFound)?;
+        if contact.tags.contains(&tag) {
+            return Ok(false);
+        }
+        if contact.tags.len() >= MAX_CONTACT_TAGS {
+            return Err(ContactError::TooManyTags);
+        }
+        Ok(contact.tags.insert(tag))
+    }
+
+    /// Remove a tag from a contact
+    ///
+    /// # Returns
+    /// Ok(false) if the contact did not have the tag
+    pub fn remove_tag(&mut self, public_key: &str, tag: &str) -> Result<bool, ContactError> {
+        let tag = normalize_tag(tag)?;
+        let contact = self.get_mut(public_key).ok_or(ContactError::NotFound)?;
+        Ok(contact.tags.remove(&tag))
+    }
+
+    /// Record that a message was exchanged with a key
+    ///
+    /// Only the first call per contact is kept.
+    ///
+    /// # Returns
+    /// true if this set the contact's first-contacted time
+    pub fn record_contacted(&mut self, public_key: &str, timestamp: &str) -> bool {
+        match self.get_mut(public_key) {
+            Some(contact) if contact.first_contacted_at.is_none() => {
+                contact.first_contacted_at = Some(timestamp.to_string());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Contacts matching a search query, ordered by public key
+    ///
+    /// Every whitespace-separated term must appear, ignoring case, in the
+    /// label, the notes or a tag (`#work` and `work` both match the tag), or
+    /// be a prefix of the public key. An empty query matches everyone.
+    pub fn search(&self, query: &str) -> Vec<&Contact> {
+        let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        self.contacts
+            .values()
+            .filter(|c| c.matches(&terms))
+            .collect()
+    }
+
+    /// Label for a public key, if it is a contact
+    pub fn label_for(&self, public_key: &str) -> Option<&str> {
+        self.get(public_key).map(|c| c.label.as_str())
+    }
+
+    /// Labels of all contacts, keyed by public key
+    pub fn labels(&self) -> HashMap<String, String> {
+        self.contacts
+            .values()
+            .map(|c| (c.public_key.clone(), c.label.clone()))
+            .collect()
+    }
+
+    /// Check a name claimed by a key (such as its nickname) against the
+    /// labels of pinned contacts, ignoring case
+    pub fn check_name(&self, public_key: &str, name: &str) -> NameCheck {
+        let name = name.trim().to_lowercase();
+        match self
+            .contacts
+            .values()
+            .find(|c| c.label.to_lowercase() == name)
+        {
+            None => NameCheck::Unknown,
+            Some(c) if c.public_key.eq_ignore_ascii_case(public_key) => NameCheck::Matches,
+            Some(c) => NameCheck::Conflict {
+                pinned_key: c.public_key.clone(),
+            },
+        }
+    }
+
+    /// All contacts, ordered by public key
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.values()
+    }
+
+    /// Number of contacts
+    pub fn len(&self) -> usize {
+        self.contacts.len()
+    }
+
+    /// Whether the list is empty
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+
+    /// Merge contacts from another list, such as one exported on another
+    /// device
+    ///
+    /// New keys are added as they are. For keys already present, `policy`
+    /// decides the label; otherwise the most informative value wins:
+    /// `Verified` over `Pinned`, the union of tags and previous keys, local
+    /// notes unless they are empty, and the earliest added and
+    /// first-contacted times.
+    pub fn merge(
+        &mut self,
+        imported: impl IntoIterator<Item = Contact>,
+        policy: MergePolicy,
+    ) -> Result<MergeSummary, ContactError> {
+        let mut summary = MergeSummary::default();
+        for mut contact in imported {
+            let id = conversation_id(&contact.public_key)?;
+            contact.public_key = id.to_string();
+            contact.label = normalize_label(&contact.label)?;
+
+            let Some(local) = self.contacts.get_mut(&id) else {
+                summary.added.push(contact.public_key.clone());
+                self.contacts.insert(id, contact);
+                continue;
+            };
+
+            let before = local.clone();
+            if local.label != contact.label {
+                summary.label_conflicts.push(local.public_key.clone());
+                if policy == MergePolicy::PreferImported {
+                    local.label = contact.label;
+                }
+            }
+            if contact.trust == TrustStatus::Verified {
+                local.trust = TrustStatus::Verified;
+            }
+            local.tags.extend(contact.tags);
+            for key in contact.previous_keys {
+                if !local.previous_keys.contains(&key) {
+                    local.previous_keys.push(key);
+                }
+            }
+            if local.notes.is_empty() {
+                local.notes = contact.notes;
+            }
+            local.added_at = earliest(&local.added_at, &contact.added_at).to_string();
+            local.first_contacted_at =
+                match (local.first_contacted_at.take(), contact.first_contacted_at) {
+                    (Some(a), Some(b)) => Some(earliest(&a, &b).to_string()),
+                    (a, b) => a.or(b),
+                };
+            if *local != before {
+                summary.updated.push(local.public_key.clone());
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// The earlier of two RFC 3339 timestamps, `a` if either doesn't parse
+fn earliest<'a>(a: &'a str, b: &'a str) -> &'a str {
+    match (
+        chrono::DateTime::parse_from_rfc3339(a),
+        chrono::DateTime::parse_from_rfc3339(b),
+    ) {
+        (Ok(x), Ok(y)) if y < x => b,
+        _ => a,
+    }
+}
+
+/// Shared contact list for concurrent access
+pub type SharedContacts = Arc<Mutex<Contacts>>;
+
+/// Create a new shared, in-memory contact list
+#[inline]
+pub fn create_shared_contacts() -> SharedContacts {
+    Arc::new(Mutex::new(Contacts::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("profile-contacts-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_add_validates_key_and_label() {
+        let mut contacts = Contacts::new();
+        let key = "ab".repeat(32);
+
+        assert_eq!(
+            contacts.add("abc", "Alice").unwrap_err(),
+            ContactError::InvalidPublicKey
+        );
+        assert_eq!(
+            contacts.add(&key, "   ").unwrap_err(),
+            ContactError::InvalidLabel
+        );
+
+        let contact = contacts.add(&key, " Alice ").unwrap();
+        assert_eq!(contact.label, "Alice");
+        assert_eq!(contact.trust, TrustStatus::Pinned);
+        assert_eq!(
+            contacts.add(&key.to_uppercase(), "Again").unwrap_err(),
+            ContactError::AlreadyExists
+        );
+    }
+
+    #[test]
+    fn test_rename_verify_and_remove() {
+        let mut contacts = Contacts::new();
+        let key = "cd".repeat(32);
+        contacts.add(&key, "Bob").unwrap();
+
+        contacts.rename(&key, "Robert").unwrap();
+        contacts.set_trust(&key, TrustStatus::Verified).unwrap();
+        assert_eq!(contacts.label_for(&key), Some("Robert"));
+        assert_eq!(contacts.label_for(&key.to_uppercase()), Some("Robert"));
+        assert_eq!(contacts.get(&key).unwrap().trust, TrustStatus::Verified);
+
+        assert!(contacts.remove(&key).is_some());
+        assert_eq!(contacts.label_for(&key), None);
+        assert_eq!(
+            contacts.rename(&key, "Bob").unwrap_err(),
+            ContactError::NotFound
+        );
+    }
+
+    #[test]
+    fn test_migrate_keeps_label_and_trust() {
+        let mut contacts = Contacts::new();
+        let (old, new, other) = ("ab".repeat(32), "cd".repeat(32), "ef".repeat(32));
+        contacts.add(&old, "Alice").unwrap();
+        contacts.set_trust(&old, TrustStatus::Verified).unwrap();
+        contacts.add_tag(&old, "work").unwrap();
+        contacts.add(&other, "Eve").unwrap();
+
+        let migrated = contacts.migrate(&old.to_uppercase(), &new).unwrap();
+        assert_eq!(migrated.public_key, new);
+        assert_eq!(migrated.label, "Alice");
+        assert_eq!(migrated.trust, TrustStatus::Verified);
+        assert_eq!(migrated.previous_keys, vec![old.clone()]);
+        assert!(contacts.get(&old).is_none());
+        assert!(contacts.get(&new).unwrap().tags.contains("work"));
+
+        assert_eq!(
+            contacts.migrate(&old, &"12".repeat(32)).unwrap_err(),
+            ContactError::NotFound
+        );
+        assert_eq!(
+            contacts.migrate(&new, &other).unwrap_err(),
+            ContactError::AlreadyExists
+        );
+    }
+
+    #[test]
+    fn test_check_name_flags_other_keys() {
+        let mut contacts = Contacts::new();
+        let alice = "ab".repeat(32);
+        contacts.add(&alice, "Alice").unwrap();
+
+        assert_eq!(contacts.check_name(&alice, "alice"), NameCheck::Matches);
+        assert_eq!(
+            contacts.check_name(&alice.to_uppercase(), "alice"),
+            NameCheck::Matches
+        );
+        assert_eq!(contacts.check_name(&alice, "Bob"), NameCheck::Unknown);
+        assert_eq!(
+            contacts.check_name(&"ef".repeat(32), "ALICE"),
+            NameCheck::Conflict { pinned_key: alice }
+        );
+    }
+
+    #[test]
+    fn test_notes_tags_and_search() {
+        let mut contacts = Contacts::new();
+        let alice = "ab".repeat(32);
+        let bob = "cd".repeat(32);
+        contacts.add(&alice, "Alice").unwrap();
+        contacts.add(&bob, "Bob").unwrap();
+
+        contacts
+            .set_notes(&alice, " Met at the Rust meetup ")
+            .unwrap();
+        assert!(contacts.add_tag(&alice, "#Work").unwrap());
+        assert!(!contacts.add_tag(&alice, "work").unwrap());
+        contacts.add_tag(&bob, "family").unwrap();
+        assert_eq!(
+            contacts.add_tag(&bob, "two words").unwrap_err(),
+            ContactError::InvalidTag
+        );
+        assert_eq!(
+            contacts
+                .set_notes(&bob, &"x".repeat(MAX_CONTACT_NOTES_LENGTH + 1))
+                .unwrap_err(),
+            ContactError::InvalidNotes
+        );
+
+        let labels = |found: Vec<&Contact>| -> Vec<String> {
+            found.into_iter().map(|c| c.label.clone()).collect()
+        };
+        assert_eq!(labels(contacts.search("rust")), vec!["Alice"]);
+        assert_eq!(labels(contacts.search("#WORK meetup")), vec!["Alice"]);
+        assert_eq!(labels(contacts.search("work family")), Vec::<String>::new());
+        assert_eq!(labels(contacts.search("CDcd")), vec!["Bob"]);
+        assert_eq!(contacts.search("").len(), 2);
+
+        assert!(contacts.remove_tag(&alice, "WORK").unwrap());
+        assert!(contacts.search("#work").is_empty());
+    }
+
+    #[test]
+    fn test_first_contacted_only_set_once() {
+        let mut contacts = Contacts::new();
+        let key = "ab".repeat(32);
+        assert!(!contacts.record_contacted(&key, "2025-12-27T10:00:00Z"));
+
+        contacts.add(&key, "Alice").unwrap();
+        assert!(contacts.record_contacted(&key.to_uppercase(), "2025-12-27T10:00:00Z"));
+        assert!(!contacts.record_contacted(&key, "2025-12-28T10:00:00Z"));
+        assert_eq!(
+            contacts.get(&key).unwrap().first_contacted_at.as_deref(),
+            Some("2025-12-27T10:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_merge_resolves_conflicts() {
+        let (alice, bob, carol) = ("ab".repeat(32), "cd".repeat(32), "ef".repeat(32));
+        let mut local = Contacts::new();
+        local.add(&alice, "Alice").unwrap();
+        local.add(&bob, "Bob").unwrap();
+        local.set_notes(&bob, "Local notes").unwrap();
+
+        let mut other = Contacts::new();
+        other.add(&alice.to_uppercase(), "Ally").unwrap();
+        other.set_trust(&alice, TrustStatus::Verified).unwrap();
+        other.add(&bob, "Bob").unwrap();
+        other.add_tag(&bob, "family").unwrap();
+        other.set_notes(&bob, "Other notes").unwrap();
+        other.add(&carol, "Carol").unwrap();
+        let imported: Vec<Contact> = other.contacts().cloned().collect();
+
+        let mut kept = local.clone();
+        let summary = kept
+            .merge(imported.clone(), MergePolicy::KeepLocal)
+            .unwrap();
+        assert_eq!(summary.added, vec![carol.clone()]);
+        assert_eq!(summary.label_conflicts, vec![alice.clone()]);
+        assert_eq!(summary.updated, vec![alice.clone(), bob.clone()]);
+        assert_eq!(kept.label_for(&alice), Some("Alice"));
+        assert_eq!(kept.get(&alice).unwrap().trust, TrustStatus::Verified);
+        assert_eq!(kept.get(&bob).unwrap().notes, "Local notes");
+        assert!(kept.get(&bob).unwrap().tags.contains("family"));
+        assert_eq!(kept.len(), 3);
+
+        let mut preferred = local;
+        preferred
+            .merge(imported.clone(), MergePolicy::PreferImported)
+            .unwrap();
+        assert_eq!(preferred.label_for(&alice), Some("Ally"));
+
+        // Merging the same list again changes nothing
+        let again = preferred
+            .merge(imported, MergePolicy::PreferImported)
+            .unwrap();
+        assert_eq!(again, MergeSummary::default());
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = temp_path("round-trip").join("contacts.json");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+
+        let mut contacts = Contacts::load(&path).unwrap();
+        assert!(contacts.is_empty());
+        contacts.add(&"ab".repeat(32), "Alice").unwrap();
+        contacts
+            .set_trust(&"ab".repeat(32), TrustStatus::Verified)
+            .unwrap();
+        contacts.set_notes(&"ab".repeat(32), "Neighbour").unwrap();
+        contacts.add_tag(&"ab".repeat(32), "friends").unwrap();
+        contacts.record_contacted(&"ab".repeat(32), "2025-12-27T10:00:00Z");
+        contacts.save().unwrap();
+
+        let loaded = Contacts::load(&path).unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded.get(&"ab".repeat(32)), contacts.get(&"ab".repeat(32)));
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(Contacts::load(&path), Err(ContactError::Parse(_))));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+//! Conversation identifiers
+//!
+//! A one-to-one conversation is identified by the peer's public key. Keys
+//! arrive from the server, contacts file and UI in whatever case the source
+//! used, so comparing the raw strings treats "ABCD..." and "abcd..." as two
+//! different peers. [`ConversationId`] validates the key once and stores it
+//! lowercased, and every per-peer lookup goes through it.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Length of a hex-encoded ed25519 public key
+const PUBLIC_KEY_HEX_LENGTH: usize = 64;
+
+/// Error types for conversation identifiers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversationIdError {
+    /// Key is not 64 characters long
+    InvalidLength(usize),
+    /// Key contains characters other than 0-9, a-f, A-F
+    InvalidCharacters,
+}
+
+impl Display for ConversationIdError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConversationIdError::InvalidLength(len) => write!(
+                f,
+                "Public key must be {} hexadecimal characters, got {}",
+                PUBLIC_KEY_HEX_LENGTH, len
+            ),
+            ConversationIdError::InvalidCharacters => {
+                write!(f, "Public key must only contain hexadecimal characters")
+            }
+        }
+    }
+}
+
+impl Error for ConversationIdError {}
+
+/// Identifier of a conversation with one peer: their normalized public key
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ConversationId(String);
+
+impl ConversationId {
+    /// Create an identifier from a hex-encoded public key in any case
+    ///
+    /// Surrounding whitespace is ignored.
+    pub fn new(public_key: &str) -> Result<Self, ConversationIdError> {
+        let key = public_key.trim();
+        if key.len() != PUBLIC_KEY_HEX_LENGTH {
+            return Err(ConversationIdError::InvalidLength(key.len()));
+        }
+        if !key.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ConversationIdError::InvalidCharacters);
+        }
+        Ok(Self(key.to_ascii_lowercase()))
+    }
+
+    /// The normalized (lowercase) public key
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether a raw public key belongs to this conversation
+    pub fn matches(&self, public_key: &str) -> bool {
+        self.0.eq_ignore_ascii_case(public_key.trim())
+    }
+}
+
+impl Display for ConversationId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for ConversationId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ConversationId {
+    type Err = ConversationIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+impl TryFrom<String> for ConversationId {
+    type Error = ConversationIdError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::new(&value)
+    }
+}
+
+impl From<ConversationId> for String {
+    fn from(id: ConversationId) -> Self {
+        id.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_and_whitespace_are_normalized() {
+        let lower = ConversationId::new(&"ab".repeat(32)).unwrap();
+        let upper = ConversationId::new(&format!(" {}\n", "AB".repeat(32))).unwrap();
+        let mixed: ConversationId = "aB".repeat(32).parse().unwrap();
+
+        assert_eq!(lower, upper);
+        assert_eq!(lower, mixed);
+        assert_eq!(upper.as_str(), "ab".repeat(32));
+        assert!(lower.matches(&"Ab".repeat(32)));
+        assert!(!lower.matches(&"cd".repeat(32)));
+    }
+
+    #[test]
+    fn test_invalid_keys_rejected() {
+        assert_eq!(
+            ConversationId::new("abc"),
+            Err(ConversationIdError::InvalidLength(3))
+        );
+        assert_eq!(
+            ConversationId::new(&"zz".repeat(32)),
+            Err(ConversationIdError::InvalidCharacters)
+        );
+        assert!(serde_json::from_str::<ConversationId>("\"not-a-key\"").is_err());
+        let id: ConversationId = serde_json::from_str(&format!("\"{}\"", "CD".repeat(32))).unwrap();
+        assert_eq!(id.as_str(), "cd".repeat(32));
+    }
+}
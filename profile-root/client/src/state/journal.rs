@@ -0,0 +1,504 @@
+//! State-event journal for reproducing UI state
+//!
+//! When `PROFILE_STATE_JOURNAL` is set, every mutation of the lobby state,
+//! chat view and composer state is appended to that file as one JSON object
+//! per line (JSONL), numbered in the order it happened:
+//!
+//! ```text
+//! {"seq":12,"tsMs":1735293600000,"event":"lobby_select","publicKey":"..."}
+//! ```
+//!
+//! [`replay`] applies the events to fresh state up to any point, so "the
+//! lobby showed the wrong thing" can be reproduced from a user's journal
+//! with the `state_replay` tool instead of guessed at.
+//!
+//! The journal holds message text and composer drafts, which are otherwise
+//! never written to disk. It is meant for debugging sessions only; a
+//! warning is logged while it is on.
+
+use crate::state::composer::{ComposerState, ConnectionState};
+use crate::state::conversation::ConversationId;
+use crate::state::messages::ChatMessage;
+use crate::state::outbox::SendState;
+use crate::ui::chat::{self, ChatView};
+use crate::ui::lobby_state::{LobbyState, LobbyUser, LobbyUserSerializable};
+use profile_shared::protocol::Status;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable naming the journal file; journaling is off when unset
+pub const JOURNAL_ENV_VAR: &str = "PROFILE_STATE_JOURNAL";
+
+/// One state mutation
+///
+/// Each variant mirrors a mutating method of [`LobbyState`], [`ChatView`] or
+/// [`ComposerState`], with its arguments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum StateEvent {
+    LobbySetUsers {
+        users: Vec<LobbyUserSerializable>,
+    },
+    LobbyAddUsers {
+        users: Vec<LobbyUserSerializable>,
+    },
+    LobbyRemoveUser {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+    },
+    LobbySelect {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+    },
+    LobbySelectIndex {
+        index: usize,
+    },
+    LobbyClearSelection,
+    LobbySetNickname {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        nickname: Option<String>,
+    },
+    LobbySetVerifiedName {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        #[serde(rename = "verifiedName")]
+        verified_name: Option<String>,
+    },
+    LobbySetStatus {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        status: Status,
+    },
+    LobbyRecordMessage {
+        sender: String,
+    },
+    LobbyMarkRead {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+    },
+    LobbyClear,
+    LobbyDelta {
+        joined: Vec<LobbyUserSerializable>,
+        left: Vec<String>,
+    },
+    ChatSetScrolling {
+        scrolling: bool,
+    },
+    ChatSetRecipient {
+        recipient: Option<ConversationId>,
+    },
+    /// The view was rebuilt from message history
+    ChatSetMessages {
+        messages: Vec<ChatMessage>,
+        #[serde(rename = "myPublicKey")]
+        my_public_key: String,
+    },
+    ChatAddMessage {
+        message: ChatMessage,
+        #[serde(rename = "myPublicKey")]
+        my_public_key: String,
+    },
+    ChatUpdateMessage {
+        message: ChatMessage,
+        #[serde(rename = "myPublicKey")]
+        my_public_key: String,
+    },
+    ChatClear,
+    ChatMarkRead {
+        #[serde(rename = "messageId")]
+        message_id: String,
+    },
+    ChatMarkAcknowledged {
+        #[serde(rename = "messageId")]
+        message_id: String,
+    },
+    ChatSetSendState {
+        #[serde(rename = "messageId")]
+        message_id: String,
+        state: SendState,
+    },
+    ChatSetStarred {
+        #[serde(rename = "messageIds")]
+        message_ids: Vec<String>,
+    },
+    ChatFocus {
+        #[serde(rename = "messageId")]
+        message_id: String,
+    },
+    ChatSetPeerViewing {
+        viewing: bool,
+    },
+    ChatSetPeerLastSeen {
+        #[serde(rename = "lastSeen")]
+        last_seen: Option<String>,
+    },
+    ChatSetNickname {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        nickname: Option<String>,
+    },
+    ChatSetContactLabels {
+        labels: HashMap<String, String>,
+    },
+    ComposerSetDraft {
+        text: String,
+    },
+    ComposerClearDraft,
+    ComposerSetRecipient {
+        recipient: Option<String>,
+    },
+    ComposerSetConnection {
+        state: ConnectionState,
+    },
+}
+
+/// Serializable form of lobby users, for events
+pub fn serializable_users<'a>(
+    users: impl IntoIterator<Item = &'a LobbyUser>,
+) -> Vec<LobbyUserSerializable> {
+    users.into_iter().cloned().map(Into::into).collect()
+}
+
+fn lobby_users(users: &[LobbyUserSerializable]) -> Vec<LobbyUser> {
+    users.iter().cloned().map(Into::into).collect()
+}
+
+/// One journaled event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalRecord {
+    /// Position in the journal, from 1
+    pub seq: u64,
+    /// Milliseconds since the Unix epoch
+    #[serde(rename = "tsMs")]
+    pub ts_ms: u64,
+    #[serde(flatten)]
+    pub event: StateEvent,
+}
+
+#[derive(Debug)]
+struct JournalFile {
+    writer: BufWriter<File>,
+    next_seq: u64,
+}
+
+/// Appends state events to a journal file, shared by every journaled state
+#[derive(Debug)]
+pub struct StateJournal {
+    file: Mutex<JournalFile>,
+}
+
+impl StateJournal {
+    /// Create (or truncate) a journal file
+    ///
+    /// A journal starts empty, since replay starts from fresh state.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(Self {
+            file: Mutex::new(JournalFile {
+                writer: BufWriter::new(file),
+                next_seq: 1,
+            }),
+        })
+    }
+
+    /// Open the file named by `PROFILE_STATE_JOURNAL`
+    ///
+    /// # Returns
+    /// Ok(None) if journaling is not enabled
+    pub fn from_env() -> io::Result<Option<Arc<Self>>> {
+        match std::env::var_os(JOURNAL_ENV_VAR) {
+            Some(path) if !path.is_empty() => Ok(Some(Arc::new(Self::create(path)?))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Append one event
+    ///
+    /// Each record is flushed immediately so a crash doesn't lose the
+    /// events leading up to it.
+    ///
+    /// # Returns
+    /// The event's sequence number
+    pub fn record(&self, event: StateEvent) -> io::Result<u64> {
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("journal file lock poisoned"))?;
+        let record = JournalRecord {
+            seq: file.next_seq,
+            ts_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            event,
+        };
+        let line = serde_json::to_string(&record)?;
+        writeln!(file.writer, "{}", line)?;
+        file.writer.flush()?;
+        file.next_seq += 1;
+        Ok(record.seq)
+    }
+}
+
+/// Journal of a state value, if journaling is on
+pub type JournalHandle = Option<Arc<StateJournal>>;
+
+/// Record an event built by `event`, which only runs if journaling is on
+pub fn journal_event(journal: &JournalHandle, event: impl FnOnce() -> StateEvent) {
+    if let Some(journal) = journal {
+        if let Err(e) = journal.record(event()) {
+            tracing::warn!(error = %e, "Failed to write state journal");
+        }
+    }
+}
+
+/// Read every record from a journal file
+///
+/// Blank lines are skipped; a malformed line is reported with its line number.
+pub fn read_journal(path: impl AsRef<Path>) -> io::Result<Vec<JournalRecord>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, e),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// UI state reconstructed from a journal
+#[derive(Clone, Default)]
+pub struct UiSnapshot {
+    pub lobby: LobbyState,
+    pub chat: ChatView,
+    pub composer: ComposerState,
+}
+
+impl UiSnapshot {
+    /// Apply one event
+    pub fn apply(&mut self, event: &StateEvent) {
+        match event.clone() {
+            StateEvent::LobbySetUsers { users } => self.lobby.set_users(lobby_users(&users)),
+            StateEvent::LobbyAddUsers { users } => self.lobby.add_users(lobby_users(&users)),
+            StateEvent::LobbyRemoveUser { public_key } => {
+                self.lobby.remove_user(&public_key);
+            }
+            StateEvent::LobbySelect { public_key } => {
+                self.lobby.select(&public_key);
+            }
+            StateEvent::LobbySelectIndex { index } => {
+                self.lobby.select_by_index(index);
+            }
+            StateEvent::LobbyClearSelection => self.lobby.clear_selection(),
+            StateEvent::LobbySetNickname {
+                public_key,
+                nickname,
+            } => {
+                self.lobby.set_nickname(&public_key, nickname);
+            }
+            StateEvent::LobbySetVerifiedName {
+                public_key,
+                verified_name,
+            } => {
+                self.lobby.set_verified_name(&public_key, verified_name);
+            }
+            StateEvent::LobbySetStatus { public_key, status } => {
+                self.lobby.set_status(&public_key, status);
+            }
+            StateEvent::LobbyRecordMessage { sender } => {
+                if let Ok(sender) = ConversationId::new(&sender) {
+                    self.lobby.record_message(&sender);
+                }
+            }
+            StateEvent::LobbyMarkRead { public_key } => {
+                if let Ok(peer) = ConversationId::new(&public_key) {
+                    self.lobby.mark_read(&peer);
+                }
+            }
+            StateEvent::LobbyClear => self.lobby.clear(),
+            StateEvent::LobbyDelta { joined, left } => {
+                self.lobby.apply_delta(lobby_users(&joined), left);
+            }
+            StateEvent::ChatSetScrolling { scrolling } => self.chat.set_user_scrolling(scrolling),
+            StateEvent::ChatSetRecipient { recipient } => {
+                self.chat.set_selected_recipient(recipient)
+            }
+            StateEvent::ChatSetMessages {
+                messages,
+                my_public_key,
+            } => {
+                let messages: Vec<&ChatMessage> = messages.iter().collect();
+                chat::set_messages(&mut self.chat, &messages, &my_public_key)
+            }
+            StateEvent::ChatAddMessage {
+                message,
+                my_public_key,
+            } => chat::add_message(&mut self.chat, &message, &my_public_key),
+            StateEvent::ChatUpdateMessage {
+                message,
+                my_public_key,
+            } => {
+                chat::update_message(&mut self.chat, &message, &my_public_key);
+            }
+            StateEvent::ChatClear => chat::clear_chat(&mut self.chat),
+            StateEvent::ChatMarkRead { message_id } => {
+                self.chat.mark_read(&message_id);
+            }
+            StateEvent::ChatMarkAcknowledged { message_id } => {
+                self.chat.mark_acknowledged(&message_id);
+            }
+            StateEvent::ChatSetSendState { message_id, state } => {
+                self.chat.set_send_state(&message_id, state);
+            }
+            StateEvent::ChatSetStarred { message_ids } => {
+                self.chat.set_starred_ids(message_ids);
+            }
+            StateEvent::ChatFocus { message_id } => {
+                self.chat.focus_message(&message_id);
+            }
+            StateEvent::ChatSetPeerViewing { viewing } => {
+                self.chat.set_peer_viewing(viewing);
+            }
+            StateEvent::ChatSetPeerLastSeen { last_seen } => {
+                self.chat.set_peer_last_seen(last_seen);
+            }
+            StateEvent::ChatSetNickname {
+                public_key,
+                nickname,
+            } => {
+                self.chat.set_nickname(&public_key, nickname);
+            }
+            StateEvent::ChatSetContactLabels { labels } => {
+                self.chat.set_contact_labels(labels);
+            }
+            StateEvent::ComposerSetDraft { text } => self.composer.set_draft(text),
+            StateEvent::ComposerClearDraft => self.composer.clear_draft(),
+            StateEvent::ComposerSetRecipient { recipient } => {
+                self.composer.set_recipient(recipient)
+            }
+            StateEvent::ComposerSetConnection { state } => {
+                self.composer.set_connection_state(state)
+            }
+        }
+    }
+}
+
+/// Rebuild the UI state from journal records
+///
+/// # Arguments
+/// * `records` - Records in journal order
+/// * `until_seq` - Last event to apply, or None for all of them
+pub fn replay<'a>(
+    records: impl IntoIterator<Item = &'a JournalRecord>,
+    until_seq: Option<u64>,
+) -> UiSnapshot {
+    let mut snapshot = UiSnapshot::default();
+    for record in records {
+        if until_seq.is_some_and(|until| record.seq > until) {
+            break;
+        }
+        snapshot.apply(&record.event);
+    }
+    snapshot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("profile-journal-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_replay_reconstructs_state_at_any_point() {
+        let path = temp_path("replay.jsonl");
+        let journal = Some(Arc::new(StateJournal::create(&path).unwrap()));
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+
+        let mut lobby = LobbyState::new();
+        lobby.set_journal(journal.clone());
+        let mut chat_view = ChatView::new();
+        chat_view.set_journal(journal.clone());
+        let mut composer = ComposerState::new();
+        composer.set_journal(journal.clone());
+
+        lobby.add_user(LobbyUser::new(alice.clone(), true));
+        lobby.add_user(LobbyUser::new(bob.clone(), true));
+        let alice_id = ConversationId::new(&alice).unwrap();
+        lobby.record_message(&alice_id);
+        lobby.select(&alice);
+        chat_view.set_selected_recipient(ConversationId::new(&alice).ok());
+        let message = ChatMessage::new(
+            alice.clone(),
+            "hi".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        chat::add_message(&mut chat_view, &message, &bob);
+        composer.set_draft("hel".to_string());
+        lobby.apply_delta(vec![], vec![alice.clone()]);
+
+        let records = read_journal(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let seqs: Vec<u64> = records.iter().map(|r| r.seq).collect();
+        assert_eq!(seqs, (1..=8).collect::<Vec<_>>());
+
+        let latest = replay(&records, None);
+        assert_eq!(latest.lobby.len(), 1);
+        assert_eq!(latest.lobby.selected_user(), None);
+        assert_eq!(latest.chat.message_count(), 1);
+        assert_eq!(latest.composer.get_draft(), "hel");
+
+        // Just after the selection, before alice left
+        let earlier = replay(&records, Some(4));
+        assert_eq!(earlier.lobby.selected_user(), Some(alice.as_str()));
+        assert_eq!(earlier.lobby.unread_count(&alice_id), 0);
+        assert!(earlier.chat.is_empty());
+
+        let before_select = replay(&records, Some(3));
+        assert_eq!(before_select.lobby.unread_count(&alice_id), 1);
+    }
+
+    #[test]
+    fn test_nothing_recorded_without_journal() {
+        let mut lobby = LobbyState::new();
+        lobby.add_user(LobbyUser::new("a".repeat(64), true));
+        let mut called = false;
+        journal_event(&None, || {
+            called = true;
+            StateEvent::LobbyClear
+        });
+        assert!(!called);
+    }
+
+    #[test]
+    fn test_read_journal_reports_bad_line() {
+        let path = temp_path("bad-line.jsonl");
+        std::fs::write(&path, "\n{\"seq\":1}\n").unwrap();
+
+        let err = read_journal(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 2:"));
+    }
+}
@@ -0,0 +1,547 @@
+//! Passphrase-protected escrow of conversation keys
+//!
+//! Conversation keys live only on the device that negotiated them, so a
+//! reinstall would leave earlier history unreadable. The escrow keeps a
+//! copy of them sealed under a passphrase only the user knows: the
+//! passphrase is stretched with Argon2id and the keys are encrypted with
+//! XChaCha20-Poly1305. Nothing in the sealed file can be read or changed
+//! without the passphrase, and the passphrase itself is never stored.
+//!
+//! The sealed escrow is saved as JSON at `PROFILE_KEY_ESCROW_FILE`, or
+//! `~/.profile/key_escrow.json` when that is unset. Users who also want a
+//! copy off the device can upload [`KeyEscrow::to_backup_blob`] as their
+//! server backup; the server stores it as opaque bytes like any other
+//! backup and never sees a key or the passphrase.
+
+use crate::state::conversation::ConversationId;
+use crate::state::rooms::Conversation;
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use profile_shared::config::backup::MAX_BACKUP_SIZE;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Environment variable overriding the escrow file location
+pub const KEY_ESCROW_ENV_VAR: &str = "PROFILE_KEY_ESCROW_FILE";
+
+/// Version of the sealed escrow format
+pub const KEY_ESCROW_VERSION: u32 = 1;
+
+/// Shortest passphrase accepted when sealing, in characters
+pub const MIN_PASSPHRASE_LENGTH: usize = 12;
+
+/// Largest Argon2 memory cost accepted when opening, in KiB
+///
+/// Stops a tampered escrow from making the client allocate without bound.
+const MAX_MEMORY_KIB: u32 = 256 * 1024;
+
+/// Associated data binding the ciphertext to this format
+const ESCROW_AAD: &[u8] = b"profile-key-escrow-v1";
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+
+/// Error types for key escrow operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyEscrowError {
+    /// A direct conversation's public key is not 64 hex characters
+    InvalidPublicKey,
+    /// Passphrase is shorter than [`MIN_PASSPHRASE_LENGTH`]
+    WeakPassphrase,
+    /// Passphrase is wrong, or the escrow was modified
+    WrongPassphrase,
+    /// Too many wrong passphrases; no attempt is accepted for this long
+    Locked { retry_after_secs: u64 },
+    /// Too many wrong passphrases; the escrow file was erased
+    Wiped,
+    /// The escrow uses a format version this client doesn't know
+    UnsupportedVersion(u32),
+    /// The escrow's fields are missing or malformed
+    Corrupt(String),
+    /// The sealed escrow doesn't fit in a server backup
+    TooLarge { size: usize, max: usize },
+    /// Reading or writing the escrow file failed
+    Io(String),
+    /// The escrow file is not valid JSON
+    Parse(String),
+}
+
+impl Display for KeyEscrowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyEscrowError::InvalidPublicKey => {
+                write!(f, "Public key must be 64 hexadecimal characters")
+            }
+            KeyEscrowError::WeakPassphrase => write!(
+                f,
+                "Passphrase must be at least {} characters",
+                MIN_PASSPHRASE_LENGTH
+            ),
+            KeyEscrowError::WrongPassphrase => {
+                write!(f, "Wrong passphrase, or the key escrow was modified")
+            }
+            KeyEscrowError::Locked { retry_after_secs } => write!(
+                f,
+                "Too many wrong passphrases, try again in {} seconds",
+                retry_after_secs
+            ),
+            KeyEscrowError::Wiped => {
+                write!(f, "Too many wrong passphrases, the key escrow was erased")
+            }
+            KeyEscrowError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported key escrow version {}", version)
+            }
+            KeyEscrowError::Corrupt(msg) => write!(f, "Key escrow is corrupt: {}", msg),
+            KeyEscrowError::TooLarge { size, max } => {
+                write!(f, "Key escrow size {} exceeds backup maximum {}", size, max)
+            }
+            KeyEscrowError::Io(msg) => write!(f, "Failed to access key escrow file: {}", msg),
+            KeyEscrowError::Parse(msg) => write!(f, "Failed to parse key escrow file: {}", msg),
+        }
+    }
+}
+
+impl Error for KeyEscrowError {}
+
+/// Conversation keys held in memory, zeroed when dropped
+///
+/// Direct conversations are keyed by peer and rooms by name.
+#[derive(Default, PartialEq, Eq)]
+pub struct ConversationKeys {
+    keys: BTreeMap<String, Zeroizing<Vec<u8>>>,
+}
+
+impl fmt::Debug for ConversationKeys {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConversationKeys")
+            .field("conversations", &self.keys.len())
+            .finish()
+    }
+}
+
+/// Label a conversation is stored under
+fn conversation_label(conversation: &Conversation) -> Result<String, KeyEscrowError> {
+    match conversation {
+        Conversation::Direct(public_key) => {
+            let id: ConversationId = public_key
+                .parse()
+                .map_err(|_| KeyEscrowError::InvalidPublicKey)?;
+            Ok(format!("direct:{}", id))
+        }
+        Conversation::Room(name) => Ok(format!("room:{}", name)),
+    }
+}
+
+impl ConversationKeys {
+    /// Create an empty set of keys
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store a conversation's key, replacing any previous one
+    pub fn insert(
+        &mut self,
+        conversation: &Conversation,
+        key: &[u8],
+    ) -> Result<(), KeyEscrowError> {
+        self.keys.insert(
+            conversation_label(conversation)?,
+            Zeroizing::new(key.to_vec()),
+        );
+        Ok(())
+    }
+
+    /// Key for a conversation, if escrowed
+    pub fn get(&self, conversation: &Conversation) -> Option<&[u8]> {
+        let label = conversation_label(conversation).ok()?;
+        self.keys.get(&label).map(|key| key.as_slice())
+    }
+
+    /// Remove a conversation's key
+    ///
+    /// # Returns
+    /// Whether the conversation had a key
+    pub fn remove(&mut self, conversation: &Conversation) -> bool {
+        conversation_label(conversation)
+            .map(|label| self.keys.remove(&label).is_some())
+            .unwrap_or(false)
+    }
+
+    /// Number of conversations with a key
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether no keys are held
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn to_plaintext(&self) -> Result<Zeroizing<Vec<u8>>, KeyEscrowError> {
+        let encoded: BTreeMap<&str, Zeroizing<String>> = self
+            .keys
+            .iter()
+            .map(|(label, key)| (label.as_str(), Zeroizing::new(hex::encode(key.as_slice()))))
+            .collect();
+        let encoded: BTreeMap<&str, &str> = encoded
+            .iter()
+            .map(|(label, key)| (*label, key.as_str()))
+            .collect();
+        serde_json::to_vec(&encoded)
+            .map(Zeroizing::new)
+            .map_err(|e| KeyEscrowError::Parse(e.to_string()))
+    }
+
+    fn from_plaintext(plaintext: &[u8]) -> Result<Self, KeyEscrowError> {
+        let mut encoded: BTreeMap<String, String> = serde_json::from_slice(plaintext)
+            .map_err(|e| KeyEscrowError::Corrupt(e.to_string()))?;
+        let keys = encoded
+            .iter()
+            .map(|(label, key)| {
+                hex::decode(key)
+                    .map(|key| (label.clone(), Zeroizing::new(key)))
+                    .map_err(|e| KeyEscrowError::Corrupt(e.to_string()))
+            })
+            .collect::<Result<_, _>>();
+        encoded.values_mut().for_each(Zeroize::zeroize);
+        let keys = keys?;
+        Ok(Self { keys })
+    }
+}
+
+/// Argon2id cost parameters a passphrase was stretched with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    #[serde(rename = "memoryKib")]
+    pub memory_kib: u32,
+    /// Number of passes
+    pub iterations: u32,
+    /// Degree of parallelism
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    fn derive_key(
+        &self,
+        passphrase: &str,
+        salt: &[u8],
+    ) -> Result<Zeroizing<[u8; KEY_LENGTH]>, KeyEscrowError> {
+        if self.memory_kib > MAX_MEMORY_KIB {
+            return Err(KeyEscrowError::Corrupt(format!(
+                "memory cost {} KiB exceeds {}",
+                self.memory_kib, MAX_MEMORY_KIB
+            )));
+        }
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LENGTH),
+        )
+        .map_err(|e| KeyEscrowError::Corrupt(e.to_string()))?;
+        let mut key = Zeroizing::new([0u8; KEY_LENGTH]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+            .map_err(|e| KeyEscrowError::Corrupt(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+/// Bytes sealed under a passphrase, hex-encoded as they are saved
+///
+/// The passphrase is stretched with Argon2id and the bytes encrypted with
+/// XChaCha20-Poly1305, bound to associated data naming the file format.
+pub(crate) struct SealedBytes {
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl SealedBytes {
+    /// Seal `plaintext` under `passphrase`
+    ///
+    /// A fresh salt and nonce are drawn on every call.
+    pub fn seal(
+        plaintext: &[u8],
+        passphrase: &str,
+        kdf: &KdfParams,
+        aad: &[u8],
+    ) -> Result<Self, KeyEscrowError> {
+        if passphrase.chars().count() < MIN_PASSPHRASE_LENGTH {
+            return Err(KeyEscrowError::WeakPassphrase);
+        }
+        let mut salt = [0u8; SALT_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+
+        let key = kdf.derive_key(passphrase, &salt)?;
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| KeyEscrowError::Corrupt("encryption failed".to_string()))?;
+
+        Ok(Self {
+            salt: hex::encode(salt),
+            nonce: hex::encode(nonce),
+            ciphertext: hex::encode(ciphertext),
+        })
+    }
+
+    /// Decrypt bytes sealed by [`SealedBytes::seal`]
+    pub fn open(
+        salt: &str,
+        nonce: &str,
+        ciphertext: &str,
+        passphrase: &str,
+        kdf: &KdfParams,
+        aad: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, KeyEscrowError> {
+        let salt = decode_field("salt", salt, SALT_LENGTH)?;
+        let nonce = decode_field("nonce", nonce, NONCE_LENGTH)?;
+        let ciphertext = hex::decode(ciphertext)
+            .map_err(|_| KeyEscrowError::Corrupt("invalid ciphertext".to_string()))?;
+
+        let key = kdf.derive_key(passphrase, &salt)?;
+        XChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad,
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| KeyEscrowError::WrongPassphrase)
+    }
+}
+
+/// Conversation keys sealed under the user's passphrase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEscrow {
+    /// Format version, [`KEY_ESCROW_VERSION`] when sealed by this client
+    pub version: u32,
+    pub kdf: KdfParams,
+    /// Hex-encoded Argon2 salt
+    salt: String,
+    /// Hex-encoded XChaCha20-Poly1305 nonce
+    nonce: String,
+    /// Hex-encoded encrypted keys
+    ciphertext: String,
+    /// When the escrow was sealed (RFC 3339)
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+/// Default location of the escrow file
+///
+/// # Returns
+/// `PROFILE_KEY_ESCROW_FILE` if set, otherwise `~/.profile/key_escrow.json`,
+/// or None if neither can be determined
+pub fn default_key_escrow_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(KEY_ESCROW_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".profile").join("key_escrow.json"))
+}
+
+fn decode_field(name: &str, value: &str, length: usize) -> Result<Vec<u8>, KeyEscrowError> {
+    match hex::decode(value) {
+        Ok(bytes) if bytes.len() == length => Ok(bytes),
+        _ => Err(KeyEscrowError::Corrupt(format!("invalid {}", name))),
+    }
+}
+
+impl KeyEscrow {
+    /// Seal `keys` under `passphrase` with the default Argon2id costs
+    pub fn seal(keys: &ConversationKeys, passphrase: &str) -> Result<Self, KeyEscrowError> {
+        Self::seal_with_params(keys, passphrase, KdfParams::default())
+    }
+
+    /// Seal `keys` under `passphrase` with custom Argon2id costs
+    ///
+    /// A fresh salt and nonce are drawn on every call, so sealing the same
+    /// keys twice gives unrelated escrows.
+    pub fn seal_with_params(
+        keys: &ConversationKeys,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<Self, KeyEscrowError> {
+        let sealed = SealedBytes::seal(&keys.to_plaintext()?, passphrase, &kdf, ESCROW_AAD)?;
+        Ok(Self {
+            version: KEY_ESCROW_VERSION,
+            kdf,
+            salt: sealed.salt,
+            nonce: sealed.nonce,
+            ciphertext: sealed.ciphertext,
+            updated_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    /// Decrypt the escrowed keys with `passphrase`
+    pub fn open(&self, passphrase: &str) -> Result<ConversationKeys, KeyEscrowError> {
+        if self.version != KEY_ESCROW_VERSION {
+            return Err(KeyEscrowError::UnsupportedVersion(self.version));
+        }
+        let plaintext = SealedBytes::open(
+            &self.salt,
+            &self.nonce,
+            &self.ciphertext,
+            passphrase,
+            &self.kdf,
+            ESCROW_AAD,
+        )?;
+        ConversationKeys::from_plaintext(&plaintext)
+    }
+
+    /// Load the escrow saved at `path`
+    ///
+    /// # Returns
+    /// Ok(None) if there is no escrow file
+    pub fn load(path: &Path) -> Result<Option<Self>, KeyEscrowError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| KeyEscrowError::Parse(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(KeyEscrowError::Io(e.to_string())),
+        }
+    }
+
+    /// Save the escrow to `path`
+    pub fn save(&self, path: &Path) -> Result<(), KeyEscrowError> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| KeyEscrowError::Io(e.to_string()))?;
+        }
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| KeyEscrowError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| KeyEscrowError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| KeyEscrowError::Io(e.to_string()))
+    }
+
+    /// Bytes to upload as the user's server backup
+    ///
+    /// The escrow is already encrypted, so it is sent as-is.
+    pub fn to_backup_blob(&self) -> Result<Vec<u8>, KeyEscrowError> {
+        let blob = serde_json::to_vec(self).map_err(|e| KeyEscrowError::Parse(e.to_string()))?;
+        if blob.len() > MAX_BACKUP_SIZE {
+            return Err(KeyEscrowError::TooLarge {
+                size: blob.len(),
+                max: MAX_BACKUP_SIZE,
+            });
+        }
+        Ok(blob)
+    }
+
+    /// Read an escrow back from a fetched server backup
+    pub fn from_backup_blob(blob: &[u8]) -> Result<Self, KeyEscrowError> {
+        serde_json::from_slice(blob).map_err(|e| KeyEscrowError::Parse(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    /// Cheap costs so tests don't spend seconds in Argon2
+    fn fast_kdf() -> KdfParams {
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    fn keys() -> ConversationKeys {
+        let mut keys = ConversationKeys::new();
+        keys.insert(&Conversation::Direct("AB".repeat(32)), &[7u8; 32])
+            .unwrap();
+        keys.insert(&Conversation::Room("general".to_string()), &[9u8; 32])
+            .unwrap();
+        keys
+    }
+
+    #[test]
+    fn test_seal_and_open_round_trip() {
+        let escrow = KeyEscrow::seal_with_params(&keys(), PASSPHRASE, fast_kdf()).unwrap();
+        assert!(!escrow.ciphertext.contains(&hex::encode([7u8; 32])));
+
+        let opened = escrow.open(PASSPHRASE).unwrap();
+        assert_eq!(opened, keys());
+        assert_eq!(
+            opened.get(&Conversation::Direct("ab".repeat(32))),
+            Some(&[7u8; 32][..])
+        );
+
+        assert_eq!(
+            escrow.open("wrong passphrase!"),
+            Err(KeyEscrowError::WrongPassphrase)
+        );
+        assert_eq!(
+            KeyEscrow::seal_with_params(&keys(), "short", fast_kdf()),
+            Err(KeyEscrowError::WeakPassphrase)
+        );
+    }
+
+    #[test]
+    fn test_tampered_escrow_is_rejected() {
+        let mut escrow = KeyEscrow::seal_with_params(&keys(), PASSPHRASE, fast_kdf()).unwrap();
+        escrow.kdf.iterations = 2;
+        assert_eq!(
+            escrow.open(PASSPHRASE),
+            Err(KeyEscrowError::WrongPassphrase)
+        );
+
+        escrow.kdf.iterations = 1;
+        escrow.kdf.memory_kib = MAX_MEMORY_KIB + 1;
+        assert!(matches!(
+            escrow.open(PASSPHRASE),
+            Err(KeyEscrowError::Corrupt(_))
+        ));
+    }
+
+    #[test]
+    fn test_saved_and_backed_up_escrow_reopens() {
+        let dir = std::env::temp_dir().join(format!("profile-escrow-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("key_escrow.json");
+        assert_eq!(KeyEscrow::load(&path), Ok(None));
+
+        let escrow = KeyEscrow::seal_with_params(&keys(), PASSPHRASE, fast_kdf()).unwrap();
+        escrow.save(&path).unwrap();
+        let loaded = KeyEscrow::load(&path).unwrap().unwrap();
+        assert_eq!(loaded.open(PASSPHRASE).unwrap(), keys());
+
+        let blob = escrow.to_backup_blob().unwrap();
+        let restored = KeyEscrow::from_backup_blob(&blob).unwrap();
+        assert_eq!(restored.open(PASSPHRASE).unwrap(), keys());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
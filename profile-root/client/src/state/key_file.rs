@@ -0,0 +1,171 @@
+//! Passphrase-protected private key files
+//!
+//! A key file keeps the user's private key sealed under a passphrase the
+//! same way the key escrow keeps conversation keys (Argon2id, then
+//! XChaCha20-Poly1305), so the key can be moved between devices without
+//! ever being written out in the clear. The public key is stored beside
+//! the ciphertext so users can tell their files apart without unlocking
+//! them, and is checked against the opened key.
+//!
+//! Unlocking a key file is throttled like the escrow, see
+//! `state::unlock_attempts`.
+
+use crate::state::key_escrow::{KdfParams, KeyEscrowError, SealedBytes};
+use profile_shared::{derive_public_key, PrivateKey};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Version of the key file format
+pub const KEY_FILE_VERSION: u32 = 1;
+
+/// Associated data binding the ciphertext to this format
+const KEY_FILE_AAD: &[u8] = b"profile-key-file-v1";
+
+/// Length of a private key in bytes
+const PRIVATE_KEY_LENGTH: usize = 32;
+
+/// A private key sealed under the user's passphrase
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedKeyFile {
+    /// Format version, [`KEY_FILE_VERSION`] when sealed by this client
+    pub version: u32,
+    pub kdf: KdfParams,
+    /// Hex-encoded public key of the sealed private key
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    /// Hex-encoded Argon2 salt
+    salt: String,
+    /// Hex-encoded XChaCha20-Poly1305 nonce
+    nonce: String,
+    /// Hex-encoded encrypted private key
+    ciphertext: String,
+}
+
+impl SealedKeyFile {
+    /// Seal `private_key` under `passphrase` with the default Argon2id costs
+    pub fn seal(private_key: &PrivateKey, passphrase: &str) -> Result<Self, KeyEscrowError> {
+        Self::seal_with_params(private_key, passphrase, KdfParams::default())
+    }
+
+    /// Seal `private_key` under `passphrase` with custom Argon2id costs
+    pub fn seal_with_params(
+        private_key: &PrivateKey,
+        passphrase: &str,
+        kdf: KdfParams,
+    ) -> Result<Self, KeyEscrowError> {
+        let public_key =
+            derive_public_key(private_key).map_err(|e| KeyEscrowError::Corrupt(e.to_string()))?;
+        let sealed = SealedBytes::seal(private_key.as_slice(), passphrase, &kdf, KEY_FILE_AAD)?;
+        Ok(Self {
+            version: KEY_FILE_VERSION,
+            kdf,
+            public_key: hex::encode(public_key),
+            salt: sealed.salt,
+            nonce: sealed.nonce,
+            ciphertext: sealed.ciphertext,
+        })
+    }
+
+    /// Decrypt the private key with `passphrase`
+    pub fn open(&self, passphrase: &str) -> Result<PrivateKey, KeyEscrowError> {
+        if self.version != KEY_FILE_VERSION {
+            return Err(KeyEscrowError::UnsupportedVersion(self.version));
+        }
+        let plaintext = SealedBytes::open(
+            &self.salt,
+            &self.nonce,
+            &self.ciphertext,
+            passphrase,
+            &self.kdf,
+            KEY_FILE_AAD,
+        )?;
+        if plaintext.len() != PRIVATE_KEY_LENGTH {
+            return Err(KeyEscrowError::Corrupt("invalid key length".to_string()));
+        }
+        let private_key = PrivateKey::new(plaintext.to_vec());
+        let public_key =
+            derive_public_key(&private_key).map_err(|e| KeyEscrowError::Corrupt(e.to_string()))?;
+        if !hex::encode(public_key).eq_ignore_ascii_case(&self.public_key) {
+            return Err(KeyEscrowError::Corrupt(
+                "public key does not match the sealed key".to_string(),
+            ));
+        }
+        Ok(private_key)
+    }
+
+    /// Load the key file saved at `path`
+    ///
+    /// # Returns
+    /// Ok(None) if there is no file at `path`
+    pub fn load(path: &Path) -> Result<Option<Self>, KeyEscrowError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| KeyEscrowError::Parse(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(KeyEscrowError::Io(e.to_string())),
+        }
+    }
+
+    /// Save the key file to `path`
+    pub fn save(&self, path: &Path) -> Result<(), KeyEscrowError> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| KeyEscrowError::Io(e.to_string()))?;
+        }
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| KeyEscrowError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| KeyEscrowError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| KeyEscrowError::Io(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profile_shared::generate_private_key;
+
+    const PASSPHRASE: &str = "correct horse battery";
+
+    fn fast_kdf() -> KdfParams {
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn test_seal_and_open_round_trip() {
+        let private_key = generate_private_key().unwrap();
+        let sealed = SealedKeyFile::seal_with_params(&private_key, PASSPHRASE, fast_kdf()).unwrap();
+        assert!(!sealed
+            .ciphertext
+            .contains(&hex::encode(private_key.as_slice())));
+        assert_eq!(
+            sealed.public_key,
+            hex::encode(derive_public_key(&private_key).unwrap())
+        );
+
+        assert_eq!(sealed.open(PASSPHRASE).unwrap(), private_key);
+        assert_eq!(
+            sealed.open("wrong passphrase!"),
+            Err(KeyEscrowError::WrongPassphrase)
+        );
+    }
+
+    #[test]
+    fn test_swapped_public_key_is_rejected() {
+        let mut sealed = SealedKeyFile::seal_with_params(
+            &generate_private_key().unwrap(),
+            PASSPHRASE,
+            fast_kdf(),
+        )
+        .unwrap();
+        sealed.public_key = "ab".repeat(32);
+        assert!(matches!(
+            sealed.open(PASSPHRASE),
+            Err(KeyEscrowError::Corrupt(_))
+        ));
+    }
+}
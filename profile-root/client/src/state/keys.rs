@@ -20,6 +20,12 @@ pub struct KeyState {
     private_key: Option<PrivateKey>,
 
     public_key: Option<PublicKey>,
+
+    /// The keys are a throwaway guest identity
+    ///
+    /// A guest identity only ever lives in memory: there is no recovery
+    /// phrase for it, and it is gone when the client closes.
+    ephemeral: bool,
 }
 
 impl KeyState {
@@ -28,6 +34,7 @@ impl KeyState {
         Self {
             private_key: None,
             public_key: None,
+            ephemeral: false,
         }
     }
 
@@ -61,6 +68,21 @@ impl KeyState {
     pub fn set_generated_key(&mut self, private_key: PrivateKey, public_key: PublicKey) {
         self.private_key = Some(private_key);
         self.public_key = Some(public_key);
+        self.ephemeral = false;
+    }
+
+    /// Store a throwaway guest key pair
+    ///
+    /// Same as [`Self::set_generated_key`], but the keys are marked as a
+    /// guest identity that must never be saved or exported.
+    pub fn set_ephemeral_key(&mut self, private_key: PrivateKey, public_key: PublicKey) {
+        self.set_generated_key(private_key, public_key);
+        self.ephemeral = true;
+    }
+
+    /// Check if the stored keys are a throwaway guest identity
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral
     }
 
     /// Get a reference to the stored private key (if any)
@@ -123,6 +145,7 @@ impl std::fmt::Debug for KeyState {
                 },
             )
             .field("public_key", &self.public_key)
+            .field("ephemeral", &self.ephemeral)
             .finish()
     }
 }
@@ -150,6 +173,19 @@ mod tests {
         assert_eq!(state.public_key().unwrap(), &public);
     }
 
+    #[test]
+    fn test_key_state_guest_flag() {
+        let mut state = KeyState::new();
+        let public = profile_shared::PublicKey::new(vec![1u8; 32]).unwrap();
+        state.set_ephemeral_key(PrivateKey::new(vec![0u8; 32]), public.clone());
+        assert!(state.is_key_set());
+        assert!(state.is_ephemeral());
+
+        // Generating or importing a real key replaces the guest identity
+        state.set_generated_key(PrivateKey::new(vec![0u8; 32]), public);
+        assert!(!state.is_ephemeral());
+    }
+
     #[test]
     fn test_default_trait() {
         let state = KeyState::default();
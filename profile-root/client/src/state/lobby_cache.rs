@@ -0,0 +1,200 @@
+//! Lobby snapshot kept between client runs
+//!
+//! Fetching the lobby takes a connection and an authentication round trip,
+//! so a fresh start used to show an empty list until both finished. The
+//! last lobby the client saw is saved with its revision, a counter the
+//! lobby state bumps on every change, and restored on startup marked
+//! stale. The list renders immediately; deltas received once connected
+//! apply on top of it, and the first full snapshot from the server
+//! replaces it and clears the stale mark.
+//!
+//! The snapshot is saved as JSON at `PROFILE_LOBBY_CACHE_FILE`, or
+//! `~/.profile/lobby_cache.json` when that is unset. [`LobbyCacheWriter`]
+//! only rewrites it when the revision moved since the last save.
+
+use crate::ui::lobby_state::{LobbyState, LobbyUserSerializable};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Environment variable overriding the lobby cache file location
+pub const LOBBY_CACHE_ENV_VAR: &str = "PROFILE_LOBBY_CACHE_FILE";
+
+/// How often a changed lobby is written to the cache
+pub const LOBBY_CACHE_SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Error types for the lobby cache
+#[derive(Debug, Clone, PartialEq)]
+pub enum LobbyCacheError {
+    /// Reading or writing the lobby cache file failed
+    Io(String),
+    /// The lobby cache file is not valid JSON
+    Parse(String),
+}
+
+impl Display for LobbyCacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LobbyCacheError::Io(msg) => write!(f, "Failed to access lobby cache file: {}", msg),
+            LobbyCacheError::Parse(msg) => write!(f, "Failed to parse lobby cache file: {}", msg),
+        }
+    }
+}
+
+impl Error for LobbyCacheError {}
+
+/// The lobby as it was when last saved
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LobbyCache {
+    /// Lobby state revision the snapshot was taken at
+    pub revision: u64,
+    /// When the snapshot was taken (RFC 3339)
+    #[serde(rename = "savedAt", default)]
+    pub saved_at: String,
+    #[serde(default)]
+    pub users: Vec<LobbyUserSerializable>,
+}
+
+/// Default location of the lobby cache file
+///
+/// # Returns
+/// `PROFILE_LOBBY_CACHE_FILE` if set, otherwise `~/.profile/lobby_cache.json`,
+/// or None if neither can be determined
+pub fn default_lobby_cache_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(LOBBY_CACHE_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| {
+        PathBuf::from(home)
+            .join(".profile")
+            .join("lobby_cache.json")
+    })
+}
+
+impl LobbyCache {
+    /// Load the snapshot saved at `path`
+    ///
+    /// # Returns
+    /// Ok(None) if nothing has been saved yet
+    pub fn load(path: &Path) -> Result<Option<Self>, LobbyCacheError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| LobbyCacheError::Parse(e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(LobbyCacheError::Io(e.to_string())),
+        }
+    }
+
+    /// Save the snapshot to `path`
+    pub fn save(&self, path: &Path) -> Result<(), LobbyCacheError> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| LobbyCacheError::Io(e.to_string()))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| LobbyCacheError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| LobbyCacheError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| LobbyCacheError::Io(e.to_string()))
+    }
+}
+
+/// Saves the lobby to its cache file when it has changed
+#[derive(Debug, Clone)]
+pub struct LobbyCacheWriter {
+    path: PathBuf,
+    /// Revision last saved or restored, None before either
+    saved_revision: Option<u64>,
+}
+
+impl LobbyCacheWriter {
+    /// Create a writer for `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            saved_revision: None,
+        }
+    }
+
+    /// File the lobby is saved to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the saved snapshot, remembering its revision as already saved
+    pub fn load(&mut self) -> Result<Option<LobbyCache>, LobbyCacheError> {
+        let cache = LobbyCache::load(&self.path)?;
+        self.saved_revision = cache.as_ref().map(|cache| cache.revision);
+        Ok(cache)
+    }
+
+    /// Save `state` unless its revision was already saved
+    ///
+    /// # Returns
+    /// `true` if the file was written
+    pub fn save_if_changed(&mut self, state: &LobbyState) -> Result<bool, LobbyCacheError> {
+        if self.saved_revision == Some(state.revision()) {
+            return Ok(false);
+        }
+        state.to_cache().save(&self.path)?;
+        self.saved_revision = Some(state.revision());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::lobby_state::LobbyUser;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "profile-lobby-cache-{}-{}",
+            std::process::id(),
+            name
+        ))
+    }
+
+    #[test]
+    fn test_missing_cache_loads_as_none() {
+        let path = temp_path("missing.json");
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(LobbyCache::load(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_restored_lobby_is_stale_until_a_snapshot_arrives() {
+        let path = temp_path("restore.json");
+        let mut state = LobbyState::new();
+        state.set_users(vec![
+            LobbyUser::new("a".repeat(64), true).with_nickname(Some("alice".to_string())),
+            LobbyUser::new("b".repeat(64), true),
+        ]);
+        state.remove_user(&"b".repeat(64));
+        let mut writer = LobbyCacheWriter::new(&path);
+        assert!(writer.save_if_changed(&state).unwrap());
+        assert!(!writer.save_if_changed(&state).unwrap());
+
+        let mut next_run = LobbyCacheWriter::new(&path);
+        let cache = next_run.load().unwrap().unwrap();
+        let mut restored = LobbyState::new();
+        restored.restore_cached(cache);
+        assert!(restored.is_stale());
+        assert_eq!(restored.revision(), state.revision());
+        assert_eq!(restored.users_cloned(), state.users_cloned());
+        // Nothing changed since it was loaded, so there is nothing to save
+        assert!(!next_run.save_if_changed(&restored).unwrap());
+
+        // Deltas apply to the cached list without making it current
+        restored.apply_delta(vec![LobbyUser::new("c".repeat(64), true)], Vec::new());
+        assert!(restored.is_stale());
+        assert!(restored.revision() > state.revision());
+
+        restored.set_users(vec![LobbyUser::new("c".repeat(64), true)]);
+        assert!(!restored.is_stale());
+        assert!(next_run.save_if_changed(&restored).unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
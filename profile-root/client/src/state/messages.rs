@@ -2,15 +2,35 @@
 //!
 //! This module provides thread-safe message history storage
 //! that maintains messages in chronological order by timestamp.
+//! Each message carries a UUID; a message whose id is already in the
+//! history (for example a resend after reconnect) is not stored twice.
+//!
+//! A sender can later replace a message's text with signed edits. The
+//! original text and signature are kept, so the message still verifies,
+//! and the edits are kept beside it as a chain, oldest first; the newest
+//! edit is the text shown.
+//!
+//! A sender can also retract a message with a signed retraction. Its text
+//! and edits are dropped, leaving a tombstone that keeps the id, sender,
+//! timestamp and original signature, plus the signed retraction itself.
+//!
+//! Either side of a conversation can react to its messages with emoji.
+//! Each message keeps who reacted with what, by emoji; reactions are
+//! dropped with the text when a message is retracted.
 
+use crate::state::conversation::ConversationId;
+use profile_shared::canonical::CanonicalVersion;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashSet, VecDeque};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use uuid::Uuid;
 
 /// Represents a chat message in the message history
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChatMessage {
+    /// Unique id chosen by the sender, shared by every copy of the message
+    pub message_id: Uuid,
     /// The sender's public key (hex-encoded)
     pub sender_public_key: String,
     /// The message content
@@ -19,10 +39,59 @@ pub struct ChatMessage {
     pub signature: String,
     /// ISO 8601 timestamp when message was sent
     pub timestamp: String,
+    /// Canonical encoding the signature covers
+    #[serde(default)]
+    pub canonical: CanonicalVersion,
+    /// Per-connection sequence number the signature covers, if the sender
+    /// numbered the message
+    #[serde(default)]
+    pub sequence: Option<u64>,
     /// Whether this message was verified (signature valid)
     pub is_verified: bool,
+    /// When the server received the message (RFC 3339), if it said
+    #[serde(default)]
+    pub server_received_at: Option<String>,
+    /// Verified edits by the sender, in timestamp order
+    #[serde(default)]
+    pub edits: Vec<MessageEdit>,
+    /// The sender's verified retraction; the text and edits are gone
+    #[serde(default)]
+    pub retraction: Option<MessageRetraction>,
+    /// Verified reactions: the keys that reacted with each emoji, in the
+    /// order they reacted
+    #[serde(default)]
+    pub reactions: BTreeMap<String, Vec<String>>,
+}
+
+/// New text for a message, signed by its sender
+///
+/// The signature covers `canonical::message_edit` of the message id, the
+/// new text and the timestamp.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageEdit {
+    /// The replacement text
+    pub message: String,
+    /// The cryptographic signature (hex-encoded)
+    pub signature: String,
+    /// ISO 8601 timestamp when the edit was made
+    pub timestamp: String,
 }
 
+/// Withdrawal of a message, signed by its sender
+///
+/// The signature covers `canonical::message_retract` of the message id and
+/// the timestamp.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageRetraction {
+    /// The cryptographic signature (hex-encoded)
+    pub signature: String,
+    /// ISO 8601 timestamp when the message was retracted
+    pub timestamp: String,
+}
+
+/// Text shown in place of a retracted message
+pub const RETRACTED_TEXT: &str = "This message was deleted";
+
 impl ChatMessage {
     /// Create a new unverified message
     pub fn new(
@@ -32,11 +101,18 @@ impl ChatMessage {
         timestamp: String,
     ) -> Self {
         Self {
+            message_id: Uuid::new_v4(),
             sender_public_key,
             message,
             signature,
             timestamp,
+            canonical: CanonicalVersion::default(),
+            sequence: None,
             is_verified: false,
+            server_received_at: None,
+            edits: Vec::new(),
+            retraction: None,
+            reactions: BTreeMap::new(),
         }
     }
 
@@ -48,35 +124,112 @@ impl ChatMessage {
         timestamp: String,
     ) -> Self {
         Self {
+            message_id: Uuid::new_v4(),
             sender_public_key,
             message,
             signature,
             timestamp,
+            canonical: CanonicalVersion::default(),
+            sequence: None,
             is_verified: true,
+            server_received_at: None,
+            edits: Vec::new(),
+            retraction: None,
+            reactions: BTreeMap::new(),
         }
     }
+
+    /// Use the sender's message id instead of a freshly generated one
+    pub fn with_message_id(mut self, message_id: Uuid) -> Self {
+        self.message_id = message_id;
+        self
+    }
+
+    /// Record the canonical encoding the signature was made over
+    pub fn with_canonical(mut self, canonical: CanonicalVersion) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Record the sequence number the signature was made over
+    pub fn with_sequence(mut self, sequence: Option<u64>) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Record when the server received the message
+    pub fn with_server_received_at(mut self, server_received_at: Option<String>) -> Self {
+        self.server_received_at = server_received_at;
+        self
+    }
+
+    /// The text to show: a tombstone if retracted, else the newest edit's,
+    /// or the original
+    pub fn current_text(&self) -> &str {
+        if self.is_retracted() {
+            return RETRACTED_TEXT;
+        }
+        self.edits
+            .last()
+            .map_or(self.message.as_str(), |edit| edit.message.as_str())
+    }
+
+    /// Whether the sender has retracted the message
+    pub fn is_retracted(&self) -> bool {
+        self.retraction.is_some()
+    }
+
+    /// Whether the sender has edited the message
+    pub fn is_edited(&self) -> bool {
+        !self.edits.is_empty()
+    }
 }
 
 /// Serializable message for state persistence
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessageSerializable {
+    #[serde(rename = "messageId", default = "Uuid::new_v4")]
+    pub message_id: Uuid,
     #[serde(rename = "senderPublicKey")]
     pub sender_public_key: String,
     pub message: String,
     pub signature: String,
     pub timestamp: String,
+    #[serde(default, skip_serializing_if = "CanonicalVersion::is_legacy")]
+    pub canonical: CanonicalVersion,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
     #[serde(rename = "isVerified")]
     pub is_verified: bool,
+    #[serde(
+        rename = "serverReceivedAt",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub server_received_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edits: Vec<MessageEdit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retraction: Option<MessageRetraction>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub reactions: BTreeMap<String, Vec<String>>,
 }
 
 impl From<ChatMessage> for ChatMessageSerializable {
     fn from(msg: ChatMessage) -> Self {
         Self {
+            message_id: msg.message_id,
             sender_public_key: msg.sender_public_key,
             message: msg.message,
             signature: msg.signature,
             timestamp: msg.timestamp,
+            canonical: msg.canonical,
+            sequence: msg.sequence,
             is_verified: msg.is_verified,
+            server_received_at: msg.server_received_at,
+            edits: msg.edits,
+            retraction: msg.retraction,
+            reactions: msg.reactions,
         }
     }
 }
@@ -84,11 +237,18 @@ impl From<ChatMessage> for ChatMessageSerializable {
 impl From<ChatMessageSerializable> for ChatMessage {
     fn from(msg: ChatMessageSerializable) -> Self {
         Self {
+            message_id: msg.message_id,
             sender_public_key: msg.sender_public_key,
             message: msg.message,
             signature: msg.signature,
             timestamp: msg.timestamp,
+            canonical: msg.canonical,
+            sequence: msg.sequence,
             is_verified: msg.is_verified,
+            server_received_at: msg.server_received_at,
+            edits: msg.edits,
+            retraction: msg.retraction,
+            reactions: msg.reactions,
         }
     }
 }
@@ -112,6 +272,8 @@ pub struct MessageHistorySerializable {
 pub struct MessageHistory {
     /// Messages in chronological order (oldest → newest)
     messages: VecDeque<ChatMessage>,
+    /// Ids of the stored messages
+    ids: HashSet<Uuid>,
     /// Maximum number of messages to keep in history
     max_capacity: usize,
 }
@@ -125,6 +287,7 @@ impl MessageHistory {
     pub fn new(max_capacity: usize) -> Self {
         Self {
             messages: VecDeque::with_capacity(max_capacity),
+            ids: HashSet::with_capacity(max_capacity),
             max_capacity,
         }
     }
@@ -143,7 +306,14 @@ impl MessageHistory {
     ///
     /// # Arguments
     /// * `message` - The message to add
-    pub fn add_message(&mut self, message: ChatMessage) {
+    ///
+    /// # Returns
+    /// false if a message with the same id is already stored
+    pub fn add_message(&mut self, message: ChatMessage) -> bool {
+        if !self.ids.insert(message.message_id) {
+            return false;
+        }
+
         // Find the correct position based on timestamp
         let insert_pos = self
             .messages
@@ -156,12 +326,17 @@ impl MessageHistory {
 
         // Evict oldest messages if over capacity
         while self.messages.len() > self.max_capacity {
-            self.messages.pop_front();
+            if let Some(evicted) = self.messages.pop_front() {
+                self.ids.remove(&evicted.message_id);
+            }
         }
+        true
     }
 
     /// Add multiple messages (more efficient than individual adds)
     ///
+    /// Messages whose id is already stored are skipped.
+    ///
     /// # Arguments
     /// * `messages` - Iterator of messages to add
     pub fn add_messages<I>(&mut self, messages: I)
@@ -209,6 +384,151 @@ impl MessageHistory {
         self.messages.is_empty()
     }
 
+    /// Get the message with the given id
+    pub fn get(&self, message_id: Uuid) -> Option<&ChatMessage> {
+        if !self.ids.contains(&message_id) {
+            return None;
+        }
+        self.messages
+            .iter()
+            .find(|msg| msg.message_id == message_id)
+    }
+
+    /// Add an edit to a stored message
+    ///
+    /// The caller verifies the edit's signature first. Edits are kept in
+    /// timestamp order, so one arriving late doesn't replace a newer one.
+    ///
+    /// # Returns
+    /// false if no message has the id, it is from another sender or
+    /// retracted, or the edit is already stored
+    pub fn apply_edit(
+        &mut self,
+        message_id: Uuid,
+        sender_public_key: &str,
+        edit: MessageEdit,
+    ) -> bool {
+        if !self.ids.contains(&message_id) {
+            return false;
+        }
+        let Some(message) = self
+            .messages
+            .iter_mut()
+            .find(|msg| msg.message_id == message_id)
+        else {
+            return false;
+        };
+        if !message
+            .sender_public_key
+            .eq_ignore_ascii_case(sender_public_key)
+            || message.is_retracted()
+            || message.edits.iter().any(|e| e.signature == edit.signature)
+        {
+            return false;
+        }
+        let position = message
+            .edits
+            .iter()
+            .position(|e| e.timestamp > edit.timestamp)
+            .unwrap_or(message.edits.len());
+        message.edits.insert(position, edit);
+        true
+    }
+
+    /// Replace a stored message with a tombstone
+    ///
+    /// The caller verifies the retraction's signature first. The text,
+    /// edits and reactions are dropped; the id, sender, timestamp and
+    /// signature stay.
+    ///
+    /// # Returns
+    /// false if no message has the id, it is from another sender, or it is
+    /// already retracted
+    pub fn apply_retraction(
+        &mut self,
+        message_id: Uuid,
+        sender_public_key: &str,
+        retraction: MessageRetraction,
+    ) -> bool {
+        if !self.ids.contains(&message_id) {
+            return false;
+        }
+        let Some(message) = self
+            .messages
+            .iter_mut()
+            .find(|msg| msg.message_id == message_id)
+        else {
+            return false;
+        };
+        if !message
+            .sender_public_key
+            .eq_ignore_ascii_case(sender_public_key)
+            || message.is_retracted()
+        {
+            return false;
+        }
+        message.message.clear();
+        message.edits.clear();
+        message.reactions.clear();
+        message.retraction = Some(retraction);
+        true
+    }
+
+    /// Add or take back a reaction to a stored message
+    ///
+    /// The caller verifies the reaction's signature first.
+    ///
+    /// # Arguments
+    /// * `message_id` - Id of the message reacted to
+    /// * `reactor_public_key` - Who reacted
+    /// * `emoji` - The reaction
+    /// * `removed` - Whether the reaction is taken back
+    ///
+    /// # Returns
+    /// false if no message has the id, it is retracted, or the reaction
+    /// was already added or removed
+    pub fn apply_reaction(
+        &mut self,
+        message_id: Uuid,
+        reactor_public_key: &str,
+        emoji: &str,
+        removed: bool,
+    ) -> bool {
+        if !self.ids.contains(&message_id) {
+            return false;
+        }
+        let Some(message) = self
+            .messages
+            .iter_mut()
+            .find(|msg| msg.message_id == message_id)
+        else {
+            return false;
+        };
+        if message.is_retracted() {
+            return false;
+        }
+        let reactor = reactor_public_key.to_lowercase();
+        if removed {
+            let Some(reactors) = message.reactions.get_mut(emoji) else {
+                return false;
+            };
+            let Some(position) = reactors.iter().position(|key| *key == reactor) else {
+                return false;
+            };
+            reactors.remove(position);
+            if reactors.is_empty() {
+                message.reactions.remove(emoji);
+            }
+        } else {
+            let reactors = message.reactions.entry(emoji.to_string()).or_default();
+            if reactors.contains(&reactor) {
+                return false;
+            }
+            reactors.push(reactor);
+        }
+        true
+    }
+
     /// Get the newest message
     ///
     /// # Returns
@@ -231,33 +551,34 @@ impl MessageHistory {
     #[inline]
     pub fn clear(&mut self) {
         self.messages.clear();
+        self.ids.clear();
     }
 
     /// Get messages for a specific sender
     ///
     /// # Arguments
-    /// * `public_key` - The sender's public key
+    /// * `conversation` - The sender's conversation
     ///
     /// # Returns
-    /// All messages from this sender
-    pub fn messages_from(&self, public_key: &str) -> Vec<&ChatMessage> {
+    /// All messages from this sender, whatever case their key was sent in
+    pub fn messages_from(&self, conversation: &ConversationId) -> Vec<&ChatMessage> {
         self.messages
             .iter()
-            .filter(|msg| msg.sender_public_key == public_key)
+            .filter(|msg| conversation.matches(&msg.sender_public_key))
             .collect()
     }
 
     /// Check if any messages exist from a specific sender
     ///
     /// # Arguments
-    /// * `public_key` - The sender's public key
+    /// * `conversation` - The sender's conversation
     ///
     /// # Returns
     /// true if at least one message exists
-    pub fn has_messages_from(&self, public_key: &str) -> bool {
+    pub fn has_messages_from(&self, conversation: &ConversationId) -> bool {
         self.messages
             .iter()
-            .any(|msg| msg.sender_public_key == public_key)
+            .any(|msg| conversation.matches(&msg.sender_public_key))
     }
 
     /// Get messages within a time range
@@ -296,15 +617,9 @@ impl Default for MessageHistory {
 
 impl From<MessageHistorySerializable> for MessageHistory {
     fn from(serializable: MessageHistorySerializable) -> Self {
-        let messages: VecDeque<ChatMessage> = serializable
-            .messages
-            .into_iter()
-            .map(|msg| msg.into())
-            .collect();
-        Self {
-            messages,
-            max_capacity: 1000,
-        }
+        let mut history = Self::with_default_capacity();
+        history.add_messages(serializable.messages.into_iter().map(ChatMessage::from));
+        history
     }
 }
 
@@ -347,6 +662,124 @@ pub fn create_shared_message_history_with_capacity(capacity: usize) -> SharedMes
 mod tests {
     use super::*;
 
+    fn edit(text: &str, timestamp: &str) -> MessageEdit {
+        MessageEdit {
+            message: text.to_string(),
+            signature: format!("sig-{}", text),
+            timestamp: timestamp.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_edits_kept_in_order_beside_the_original() {
+        let mut history = MessageHistory::with_default_capacity();
+        let msg = ChatMessage::new(
+            "alice".to_string(),
+            "helo".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let id = msg.message_id;
+        history.add_message(msg);
+
+        // Only the sender can edit, and only messages that are stored
+        assert!(!history.apply_edit(id, "mallory", edit("pwned", "2025-12-27T10:01:00Z")));
+        assert!(!history.apply_edit(Uuid::new_v4(), "alice", edit("x", "2025-12-27T10:01:00Z")));
+
+        assert!(history.apply_edit(id, "alice", edit("hello!", "2025-12-27T10:02:00Z")));
+        // An older edit arriving late stays behind the newer one
+        assert!(history.apply_edit(id, "alice", edit("hello", "2025-12-27T10:01:00Z")));
+        assert!(!history.apply_edit(id, "alice", edit("hello", "2025-12-27T10:01:00Z")));
+
+        let stored = history.get(id).unwrap();
+        assert!(stored.is_edited());
+        assert_eq!(stored.message, "helo");
+        assert_eq!(stored.signature, "sig");
+        assert_eq!(stored.current_text(), "hello!");
+        let chain: Vec<_> = stored.edits.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(chain, vec!["hello", "hello!"]);
+
+        // The chain survives persistence
+        let restored = MessageHistory::from_json(&history.to_json().unwrap()).unwrap();
+        assert_eq!(restored.get(id), Some(stored));
+    }
+
+    #[test]
+    fn test_retraction_leaves_a_tombstone() {
+        let mut history = MessageHistory::with_default_capacity();
+        let msg = ChatMessage::new(
+            "alice".to_string(),
+            "secret".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let id = msg.message_id;
+        history.add_message(msg);
+        assert!(history.apply_edit(id, "alice", edit("secret!", "2025-12-27T10:01:00Z")));
+
+        let retraction = MessageRetraction {
+            signature: "retract-sig".to_string(),
+            timestamp: "2025-12-27T10:02:00Z".to_string(),
+        };
+        assert!(!history.apply_retraction(id, "mallory", retraction.clone()));
+        assert!(history.apply_retraction(id, "alice", retraction.clone()));
+        assert!(!history.apply_retraction(id, "alice", retraction));
+
+        let stored = history.get(id).cloned().unwrap();
+        assert!(stored.is_retracted());
+        assert_eq!(stored.current_text(), RETRACTED_TEXT);
+        assert!(stored.message.is_empty());
+        assert!(stored.edits.is_empty());
+        assert_eq!(stored.signature, "sig");
+        assert_eq!(stored.timestamp, "2025-12-27T10:00:00Z");
+
+        // A retracted message can't be edited back
+        assert!(!history.apply_edit(id, "alice", edit("back", "2025-12-27T10:03:00Z")));
+
+        let restored = MessageHistory::from_json(&history.to_json().unwrap()).unwrap();
+        assert_eq!(restored.get(id), Some(&stored));
+    }
+
+    #[test]
+    fn test_reactions_grouped_by_emoji() {
+        let mut history = MessageHistory::with_default_capacity();
+        let msg = ChatMessage::new(
+            "alice".to_string(),
+            "lunch?".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let id = msg.message_id;
+        history.add_message(msg);
+
+        assert!(history.apply_reaction(id, "BOB", "👍", false));
+        assert!(history.apply_reaction(id, "alice", "👍", false));
+        assert!(!history.apply_reaction(id, "bob", "👍", false));
+        assert!(history.apply_reaction(id, "bob", "🍕", false));
+        assert!(!history.apply_reaction(id, "bob", "🎉", true));
+        assert!(!history.apply_reaction(Uuid::new_v4(), "bob", "👍", false));
+
+        let reactions = &history.get(id).unwrap().reactions;
+        assert_eq!(reactions["👍"], vec!["bob", "alice"]);
+        assert_eq!(reactions["🍕"], vec!["bob"]);
+
+        // Taking back the last reaction with an emoji drops the emoji
+        assert!(history.apply_reaction(id, "bob", "🍕", true));
+        assert!(!history.get(id).unwrap().reactions.contains_key("🍕"));
+
+        let restored = MessageHistory::from_json(&history.to_json().unwrap()).unwrap();
+        assert_eq!(restored.get(id), history.get(id));
+
+        // A tombstone has no reactions and takes none
+        let retraction = MessageRetraction {
+            signature: "retract-sig".to_string(),
+            timestamp: "2025-12-27T10:02:00Z".to_string(),
+        };
+        assert!(history.apply_retraction(id, "alice", retraction));
+        assert!(history.get(id).unwrap().reactions.is_empty());
+        assert!(!history.apply_reaction(id, "bob", "👍", false));
+    }
+
     #[test]
     fn test_empty_history() {
         let history = MessageHistory::with_default_capacity();
@@ -463,30 +896,32 @@ mod tests {
     #[test]
     fn test_messages_from_sender() {
         let mut history = MessageHistory::with_default_capacity();
+        let sender_a = "aa".repeat(32);
 
         history.add_message(ChatMessage::new(
-            "sender_a".to_string(),
+            sender_a.clone(),
             "from A".to_string(),
             "sig".to_string(),
             "2025-12-27T10:00:00Z".to_string(),
         ));
 
         history.add_message(ChatMessage::new(
-            "sender_b".to_string(),
+            "bb".repeat(32),
             "from B".to_string(),
             "sig".to_string(),
             "2025-12-27T10:01:00Z".to_string(),
         ));
 
+        // Same sender, key in upper case
         history.add_message(ChatMessage::new(
-            "sender_a".to_string(),
+            sender_a.to_uppercase(),
             "from A again".to_string(),
             "sig".to_string(),
             "2025-12-27T10:02:00Z".to_string(),
         ));
 
         let from_a: Vec<&str> = history
-            .messages_from("sender_a")
+            .messages_from(&ConversationId::new(&sender_a).unwrap())
             .iter()
             .map(|m| m.message.as_str())
             .collect();
@@ -499,14 +934,14 @@ mod tests {
         let mut history = MessageHistory::with_default_capacity();
 
         history.add_message(ChatMessage::new(
-            "sender_a".to_string(),
+            "AA".repeat(32),
             "Hello".to_string(),
             "sig".to_string(),
             "2025-12-27T10:00:00Z".to_string(),
         ));
 
-        assert!(history.has_messages_from("sender_a"));
-        assert!(!history.has_messages_from("sender_b"));
+        assert!(history.has_messages_from(&ConversationId::new(&"aa".repeat(32)).unwrap()));
+        assert!(!history.has_messages_from(&ConversationId::new(&"bb".repeat(32)).unwrap()));
     }
 
     #[test]
@@ -526,6 +961,71 @@ mod tests {
         let msg = restored.messages().next().unwrap();
         assert_eq!(msg.message, "Hello");
         assert!(msg.is_verified);
+        assert_eq!(msg.message_id, history.newest().unwrap().message_id);
+    }
+
+    #[test]
+    fn test_duplicate_message_id_not_stored_twice() {
+        let mut history = MessageHistory::with_default_capacity();
+        let msg = ChatMessage::new(
+            "sender".to_string(),
+            "Hello".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let resend = ChatMessage::verified(
+            "sender".to_string(),
+            "Hello".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:05Z".to_string(),
+        )
+        .with_message_id(msg.message_id);
+
+        assert!(history.add_message(msg.clone()));
+        assert!(!history.add_message(resend));
+        assert_eq!(history.len(), 1);
+        assert_eq!(history.newest(), Some(&msg));
+
+        // Same content and timestamp but a different id is a different message
+        assert!(history.add_message(ChatMessage::new(
+            "sender".to_string(),
+            "Hello".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        )));
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn test_evicted_and_cleared_ids_can_be_added_again() {
+        let mut history = MessageHistory::new(1);
+        let first = ChatMessage::new(
+            "sender".to_string(),
+            "first".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        history.add_message(first.clone());
+        history.add_message(ChatMessage::new(
+            "sender".to_string(),
+            "second".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:01:00Z".to_string(),
+        ));
+        assert!(history.add_message(first.clone()));
+
+        history.clear();
+        assert!(history.add_message(first));
+    }
+
+    #[test]
+    fn test_legacy_json_without_ids_gets_fresh_ids() {
+        let json = r#"{"messages":[
+            {"senderPublicKey":"a","message":"x","signature":"s","timestamp":"2025-12-27T10:00:00Z","isVerified":true},
+            {"senderPublicKey":"a","message":"x","signature":"s","timestamp":"2025-12-27T10:00:00Z","isVerified":true}
+        ]}"#;
+        let history = MessageHistory::from_json(json).unwrap();
+        assert_eq!(history.len(), 2);
     }
 
     #[test]
@@ -1,16 +1,83 @@
 //! Client session state management
 
+pub mod archive;
+pub mod attachments;
+pub mod audit;
+pub mod blocklist;
 pub mod composer;
+pub mod contact_export;
+pub mod contacts;
+pub mod conversation;
+pub mod journal;
+pub mod key_escrow;
+pub mod key_file;
 pub mod keys;
 pub mod lobby;
+pub mod lobby_cache;
 pub mod messages;
+pub mod outbox;
+pub mod presence;
+pub mod ratchet_sessions;
+pub mod rooms;
 pub mod session;
+pub mod sound;
+pub mod starred;
+pub mod stats;
+pub mod timing;
+pub mod unlock_attempts;
 
+pub use archive::{
+    create_shared_archive, ArchiveError, ArchivedConversations, Sections, SharedArchive,
+};
+pub use attachments::{
+    create_shared_attachments, default_downloads_dir, AttachmentTransfers, IncomingTransfer,
+    OutgoingChunks, OutgoingTransfer, SharedAttachments, TransferDirection,
+};
+pub use audit::{
+    audit_event, AuditEvent, AuditExportFormat, AuditHandle, AuditKind, AuditLog, AuditQuery,
+    AuditRecord,
+};
+pub use blocklist::{create_shared_blocklist, Blocklist, BlocklistError, SharedBlocklist};
 pub use composer::{create_shared_composer_state, ComposerState, SharedComposerState};
+pub use contact_export::{ContactExport, ContactExportPayload, CONTACT_EXPORT_VERSION};
+pub use contacts::{
+    create_shared_contacts, Contact, ContactError, Contacts, MergePolicy, MergeSummary, NameCheck,
+    SharedContacts, TrustStatus,
+};
+pub use conversation::{ConversationId, ConversationIdError};
+pub use journal::{
+    read_journal, replay, JournalHandle, JournalRecord, StateEvent, StateJournal, UiSnapshot,
+};
+pub use key_escrow::{ConversationKeys, KdfParams, KeyEscrow, KeyEscrowError};
+pub use key_file::SealedKeyFile;
 pub use keys::KeyState;
 pub use lobby::{create_shared_lobby_state, SharedLobbyState};
+pub use lobby_cache::{LobbyCache, LobbyCacheError, LobbyCacheWriter};
 pub use messages::{
     create_shared_message_history, create_shared_message_history_with_capacity, ChatMessage,
     MessageHistory, SharedMessageHistory,
 };
-pub use session::{create_shared_key_state, handle_generate_key_async, SharedKeyState};
+pub use outbox::{create_shared_outbox, OutboundMessage, Outbox, SendState, SharedOutbox};
+pub use presence::{
+    create_shared_viewing_presence, PresenceSettings, PresenceSettingsError, SharedViewingPresence,
+    ViewingPresence,
+};
+pub use ratchet_sessions::{
+    create_shared_ratchet_sessions, RatchetSessions, SharedRatchetSessions,
+};
+pub use rooms::{create_shared_rooms_state, Conversation, Room, RoomsState, SharedRoomsState};
+pub use session::{
+    create_shared_key_state, handle_generate_guest_key_async, handle_generate_key_async,
+    SharedKeyState,
+};
+pub use sound::{SoundEvent, SoundSettings, SoundSettingsError};
+pub use starred::{
+    create_shared_starred_messages, SharedStarredMessages, StarredError, StarredMessage,
+    StarredMessages,
+};
+pub use stats::{create_shared_usage_stats, SharedUsageStats, StatsError, UsageStats};
+pub use timing::{
+    create_shared_message_timings, LatencyBreakdown, MessageTiming, MessageTimings,
+    SharedMessageTimings,
+};
+pub use unlock_attempts::{unlock_with_lockout, FailureAction, LockoutPolicy, UnlockAttempts};
@@ -0,0 +1,343 @@
+//! Outbound queue for direct messages
+//!
+//! Every direct message sent goes through the [`Outbox`], which tracks its
+//! delivery state from the moment it is handed to the connection:
+//!
+//! - `Queued`: waiting for the connection or for the recipient to come
+//!   online; resent on reconnect or when the recipient joins the lobby
+//! - `Sending`: being written to the server
+//! - `Sent`: accepted by the connection
+//! - `Failed`: dropped because the queue was full or the server kept
+//!   refusing it
+//!
+//! A sent message can go back to `Queued` if the server reports the
+//! recipient offline. The chat view shows these states next to the user's
+//! own messages, so a message that couldn't be delivered is never silently
+//! lost from view.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Most queued messages for one recipient; the oldest is dropped beyond this
+pub const MAX_QUEUED_PER_RECIPIENT: usize = 10;
+
+/// Most queued messages in total; new ones fail beyond this
+pub const MAX_QUEUED: usize = 100;
+
+/// Most messages whose state is remembered; the oldest finished ones are
+/// forgotten beyond this
+const MAX_TRACKED_MESSAGES: usize = 500;
+
+/// Delivery state of an outgoing message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SendState {
+    Queued,
+    Sending,
+    Sent,
+    Failed,
+}
+
+impl SendState {
+    /// Short status shown next to the message
+    pub fn label(self) -> &'static str {
+        match self {
+            SendState::Queued => "Queued",
+            SendState::Sending => "Sending…",
+            SendState::Sent => "Sent",
+            SendState::Failed => "Failed",
+        }
+    }
+
+    /// Whether the message will not be sent again on its own
+    pub fn is_finished(self) -> bool {
+        matches!(self, SendState::Sent | SendState::Failed)
+    }
+}
+
+/// A direct message in the outbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboundMessage {
+    /// The message's `messageId`, or its JSON if it has none
+    pub id: String,
+    /// Recipient's public key as written in the message
+    pub recipient: String,
+    /// The message as sent to the server
+    pub json: String,
+    pub state: SendState,
+}
+
+impl OutboundMessage {
+    /// Read a `message` or `sealed` request's id and recipient
+    ///
+    /// # Returns
+    /// None for anything other than a direct message
+    pub fn from_json(json: &str) -> Option<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).ok()?;
+        if !matches!(
+            value.get("type").and_then(|t| t.as_str()),
+            Some("message" | "sealed")
+        ) {
+            return None;
+        }
+        let recipient = value.get("recipientPublicKey")?.as_str()?.to_string();
+        let id = value
+            .get("messageId")
+            .and_then(|id| id.as_str())
+            .map_or_else(|| json.to_string(), str::to_string);
+        Some(Self {
+            id,
+            recipient,
+            json: json.to_string(),
+            state: SendState::Sending,
+        })
+    }
+}
+
+/// Outgoing direct messages and their delivery state, oldest first
+#[derive(Debug, Default)]
+pub struct Outbox {
+    messages: VecDeque<OutboundMessage>,
+}
+
+impl Outbox {
+    /// Create an empty outbox
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a direct message about to be written to the server
+    ///
+    /// A message already in the outbox (a resend) keeps its place.
+    ///
+    /// # Returns
+    /// The message's id, or None if `json` is not a direct message
+    pub fn start_sending(&mut self, json: &str) -> Option<String> {
+        let message = OutboundMessage::from_json(json)?;
+        let id = message.id.clone();
+        match self.position(&id) {
+            Some(index) => self.messages[index].state = SendState::Sending,
+            None => {
+                self.messages.push_back(message);
+                self.forget_finished();
+            }
+        }
+        Some(id)
+    }
+
+    /// Record that a message was written to the server
+    pub fn mark_sent(&mut self, id: &str) -> bool {
+        self.set_state(id, SendState::Sent)
+    }
+
+    /// Record that a message won't be sent
+    pub fn mark_failed(&mut self, id: &str) -> bool {
+        self.set_state(id, SendState::Failed)
+    }
+
+    /// Queue a message to be resent later
+    ///
+    /// If the recipient already has [`MAX_QUEUED_PER_RECIPIENT`] queued
+    /// messages the oldest fails to make room; if [`MAX_QUEUED`] messages
+    /// are queued in total this one fails instead.
+    ///
+    /// # Returns
+    /// Every message whose state changed, with its new state
+    pub fn queue(&mut self, id: &str) -> Vec<(String, SendState)> {
+        let Some(index) = self.position(id) else {
+            return Vec::new();
+        };
+        if self.messages[index].state == SendState::Queued {
+            return Vec::new();
+        }
+        if self.queued_count() >= MAX_QUEUED {
+            self.messages[index].state = SendState::Failed;
+            return vec![(id.to_string(), SendState::Failed)];
+        }
+        let mut changed = Vec::new();
+        let recipient = self.messages[index].recipient.clone();
+        let queued_for_recipient: Vec<usize> = self
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.state == SendState::Queued && m.recipient == recipient)
+            .map(|(i, _)| i)
+            .collect();
+        if queued_for_recipient.len() >= MAX_QUEUED_PER_RECIPIENT {
+            let oldest = &mut self.messages[queued_for_recipient[0]];
+            oldest.state = SendState::Failed;
+            changed.push((oldest.id.clone(), SendState::Failed));
+        }
+        self.messages[index].state = SendState::Queued;
+        changed.push((id.to_string(), SendState::Queued));
+        changed
+    }
+
+    /// Take every queued message to resend, oldest first, marking them
+    /// as sending
+    pub fn take_queued(&mut self) -> Vec<OutboundMessage> {
+        self.take_queued_where(|_| true)
+    }
+
+    /// Take the queued messages for one recipient, oldest first, marking
+    /// them as sending
+    pub fn take_queued_for(&mut self, recipient: &str) -> Vec<OutboundMessage> {
+        self.take_queued_where(|m| m.recipient == recipient)
+    }
+
+    /// Delivery state of a message
+    pub fn state(&self, id: &str) -> Option<SendState> {
+        self.position(id).map(|index| self.messages[index].state)
+    }
+
+    /// Every tracked message's id and state, oldest first
+    pub fn states(&self) -> Vec<(String, SendState)> {
+        self.messages
+            .iter()
+            .map(|m| (m.id.clone(), m.state))
+            .collect()
+    }
+
+    /// Number of messages waiting to be resent
+    pub fn queued_count(&self) -> usize {
+        self.messages
+            .iter()
+            .filter(|m| m.state == SendState::Queued)
+            .count()
+    }
+
+    fn take_queued_where(
+        &mut self,
+        filter: impl Fn(&OutboundMessage) -> bool,
+    ) -> Vec<OutboundMessage> {
+        self.messages
+            .iter_mut()
+            .filter(|m| m.state == SendState::Queued && filter(m))
+            .map(|m| {
+                m.state = SendState::Sending;
+                m.clone()
+            })
+            .collect()
+    }
+
+    fn position(&self, id: &str) -> Option<usize> {
+        self.messages.iter().position(|m| m.id == id)
+    }
+
+    fn set_state(&mut self, id: &str, state: SendState) -> bool {
+        match self.position(id) {
+            Some(index) if self.messages[index].state != state => {
+                self.messages[index].state = state;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forget the oldest sent or failed messages beyond the tracking limit
+    fn forget_finished(&mut self) {
+        while self.messages.len() > MAX_TRACKED_MESSAGES {
+            let Some(index) = self.messages.iter().position(|m| m.state.is_finished()) else {
+                break;
+            };
+            self.messages.remove(index);
+        }
+    }
+}
+
+/// Shared outbox for concurrent access
+pub type SharedOutbox = Arc<Mutex<Outbox>>;
+
+/// Create a new shared outbox
+#[inline]
+pub fn create_shared_outbox() -> SharedOutbox {
+    Arc::new(Mutex::new(Outbox::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, recipient: &str) -> String {
+        serde_json::json!({
+            "type": "message",
+            "messageId": id,
+            "recipientPublicKey": recipient,
+            "message": "hi",
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_message_moves_through_states() {
+        let mut outbox = Outbox::new();
+        assert_eq!(outbox.start_sending(r#"{"type":"room_join"}"#), None);
+
+        let id = outbox.start_sending(&message("m1", "bob")).unwrap();
+        assert_eq!(id, "m1");
+        assert_eq!(outbox.state("m1"), Some(SendState::Sending));
+        assert!(outbox.mark_sent("m1"));
+        assert!(!outbox.mark_sent("m1"));
+
+        assert_eq!(
+            outbox.queue("m1"),
+            vec![("m1".to_string(), SendState::Queued)]
+        );
+        assert_eq!(outbox.queued_count(), 1);
+        assert!(outbox.take_queued_for("alice").is_empty());
+
+        let resend = outbox.take_queued_for("bob");
+        assert_eq!(resend.len(), 1);
+        assert_eq!(resend[0].json, message("m1", "bob"));
+        assert_eq!(outbox.state("m1"), Some(SendState::Sending));
+        assert_eq!(outbox.queued_count(), 0);
+    }
+
+    #[test]
+    fn test_messages_without_id_are_keyed_by_json() {
+        let mut outbox = Outbox::new();
+        let json = r#"{"type":"message","recipientPublicKey":"bob","message":"hi"}"#;
+        assert_eq!(outbox.start_sending(json).as_deref(), Some(json));
+        assert_eq!(outbox.start_sending(json).as_deref(), Some(json));
+        assert_eq!(outbox.states().len(), 1);
+    }
+
+    #[test]
+    fn test_sealed_messages_tracked() {
+        let json = r#"{"type":"sealed","messageId":"m2","recipientPublicKey":"bob","envelope":{}}"#;
+        let message = OutboundMessage::from_json(json).unwrap();
+        assert_eq!(message.id, "m2");
+        assert_eq!(message.recipient, "bob");
+    }
+
+    #[test]
+    fn test_queue_limits_fail_messages() {
+        let mut outbox = Outbox::new();
+        for i in 0..=MAX_QUEUED_PER_RECIPIENT {
+            let id = outbox
+                .start_sending(&message(&i.to_string(), "bob"))
+                .unwrap();
+            outbox.queue(&id);
+        }
+        assert_eq!(outbox.queued_count(), MAX_QUEUED_PER_RECIPIENT);
+        assert_eq!(outbox.state("0"), Some(SendState::Failed));
+
+        for i in 0..MAX_QUEUED {
+            let id = outbox
+                .start_sending(&message(&format!("x{}", i), &format!("user{}", i)))
+                .unwrap();
+            outbox.queue(&id);
+        }
+        assert_eq!(outbox.queued_count(), MAX_QUEUED);
+        assert_eq!(
+            outbox.state(&format!("x{}", MAX_QUEUED - 1)),
+            Some(SendState::Failed)
+        );
+
+        let all = outbox.take_queued();
+        assert_eq!(all.len(), MAX_QUEUED);
+        assert_eq!(all[0].id, "1");
+    }
+}
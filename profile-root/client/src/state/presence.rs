@@ -0,0 +1,299 @@
+//! "Viewing your conversation" presence
+//!
+//! When enabled, the client tells a peer while the user has their
+//! conversation open, and shows when the peer has the user's conversation
+//! open. This is separate from typing: it only says the other side is
+//! looking, which gives context for an immediate reply.
+//!
+//! Last-seen times work the same way: with sharing on, the server is told
+//! at sign-in that it may record when the user leaves, and the client shows
+//! when an offline contact was last online.
+//!
+//! Sharing is off until the user turns it on, and it is reciprocal: with
+//! sharing off no hints are sent and hints from peers are ignored. The
+//! settings are saved to a local file (`PROFILE_PRESENCE_FILE`, or
+//! `~/.profile/presence.json` when that is unset).
+
+use crate::state::conversation::ConversationId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Environment variable overriding the presence settings file location
+pub const PRESENCE_ENV_VAR: &str = "PROFILE_PRESENCE_FILE";
+
+/// Error types for presence settings
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresenceSettingsError {
+    /// Reading or writing the settings file failed
+    Io(String),
+    /// The settings file is not valid JSON
+    Parse(String),
+}
+
+impl Display for PresenceSettingsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PresenceSettingsError::Io(msg) => {
+                write!(f, "Failed to access presence settings: {}", msg)
+            }
+            PresenceSettingsError::Parse(msg) => {
+                write!(f, "Failed to parse presence settings: {}", msg)
+            }
+        }
+    }
+}
+
+impl Error for PresenceSettingsError {}
+
+/// Presence settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceSettings {
+    /// Share and receive "viewing your conversation" hints; off by default
+    #[serde(rename = "shareViewing", default)]
+    pub share_viewing: bool,
+    /// Share and see last-seen times; off by default
+    #[serde(rename = "shareLastSeen", default)]
+    pub share_last_seen: bool,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// Default location of the presence settings file
+///
+/// # Returns
+/// `PROFILE_PRESENCE_FILE` if set, otherwise `~/.profile/presence.json`, or
+/// None if neither can be determined
+pub fn default_presence_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(PRESENCE_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("presence.json"))
+}
+
+impl PresenceSettings {
+    /// Create default, in-memory settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the settings saved at `path`
+    ///
+    /// A missing file gives default settings that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, PresenceSettingsError> {
+        let path = path.into();
+        let mut settings: PresenceSettings = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| PresenceSettingsError::Parse(e.to_string()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => PresenceSettings::default(),
+            Err(e) => return Err(PresenceSettingsError::Io(e.to_string())),
+        };
+        settings.path = Some(path);
+        Ok(settings)
+    }
+
+    /// Save the settings to their file
+    ///
+    /// In-memory settings are not saved.
+    pub fn save(&self) -> Result<(), PresenceSettingsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| PresenceSettingsError::Io(e.to_string()))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| PresenceSettingsError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| PresenceSettingsError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| PresenceSettingsError::Io(e.to_string()))
+    }
+
+    /// File the settings are saved to, if persistent
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+/// A viewing hint to send: the peer, and whether their conversation is open
+pub type ViewingUpdate = (String, bool);
+
+/// Which conversation this user has open, and which peers have this user's
+/// conversation open
+#[derive(Debug, Clone, Default)]
+pub struct ViewingPresence {
+    settings: PresenceSettings,
+    /// Peer whose conversation this user has open
+    open_conversation: Option<String>,
+    /// Peers that reported having this user's conversation open
+    viewers: HashSet<ConversationId>,
+}
+
+impl ViewingPresence {
+    /// Create presence tracking with the given settings
+    pub fn new(settings: PresenceSettings) -> Self {
+        Self {
+            settings,
+            open_conversation: None,
+            viewers: HashSet::new(),
+        }
+    }
+
+    /// Current settings
+    pub fn settings(&self) -> &PresenceSettings {
+        &self.settings
+    }
+
+    /// Turn sharing on or off
+    ///
+    /// Turning it off forgets every peer's hint.
+    ///
+    /// # Returns
+    /// The hint to send for the open conversation, if sharing changed
+    pub fn set_sharing(&mut self, enabled: bool) -> Vec<ViewingUpdate> {
+        if self.settings.share_viewing == enabled {
+            return Vec::new();
+        }
+        self.settings.share_viewing = enabled;
+        if !enabled {
+            self.viewers.clear();
+        }
+        self.open_conversation
+            .iter()
+            .map(|peer| (peer.clone(), enabled))
+            .collect()
+    }
+
+    /// Turn last-seen sharing on or off
+    pub fn set_share_last_seen(&mut self, enabled: bool) {
+        self.settings.share_last_seen = enabled;
+    }
+
+    /// Record the conversation this user has open (None for none)
+    ///
+    /// # Returns
+    /// Hints to send: closing the previous conversation, then opening the
+    /// new one; empty while sharing is off
+    pub fn open(&mut self, peer: Option<&str>) -> Vec<ViewingUpdate> {
+        if self.open_conversation.as_deref() == peer {
+            return Vec::new();
+        }
+        let previous = std::mem::replace(&mut self.open_conversation, peer.map(str::to_string));
+        if !self.settings.share_viewing {
+            return Vec::new();
+        }
+        previous
+            .map(|p| (p, false))
+            .into_iter()
+            .chain(peer.map(|p| (p.to_string(), true)))
+            .collect()
+    }
+
+    /// Peer whose conversation this user has open
+    pub fn open_conversation(&self) -> Option<&str> {
+        self.open_conversation.as_deref()
+    }
+
+    /// Apply a hint from a peer
+    ///
+    /// # Returns
+    /// Whether the peer's state changed; always false while sharing is off
+    pub fn hint_received(&mut self, viewer: &ConversationId, viewing: bool) -> bool {
+        if !self.settings.share_viewing {
+            return false;
+        }
+        if viewing {
+            self.viewers.insert(viewer.clone())
+        } else {
+            self.viewers.remove(viewer)
+        }
+    }
+
+    /// Forget a peer's hint, e.g. when they leave the lobby
+    pub fn viewer_left(&mut self, viewer: &ConversationId) -> bool {
+        self.viewers.remove(viewer)
+    }
+
+    /// Whether a peer has this user's conversation open
+    pub fn is_viewing(&self, peer: &ConversationId) -> bool {
+        self.viewers.contains(peer)
+    }
+}
+
+/// Shared viewing presence for concurrent access
+pub type SharedViewingPresence = Arc<Mutex<ViewingPresence>>;
+
+/// Create a new shared viewing presence with sharing off
+#[inline]
+pub fn create_shared_viewing_presence() -> SharedViewingPresence {
+    Arc::new(Mutex::new(ViewingPresence::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sharing() -> ViewingPresence {
+        ViewingPresence::new(PresenceSettings {
+            share_viewing: true,
+            ..PresenceSettings::default()
+        })
+    }
+
+    #[test]
+    fn test_nothing_shared_or_shown_by_default() {
+        let bob = "b".repeat(64);
+        let bob_id = ConversationId::new(&bob).unwrap();
+        let mut presence = ViewingPresence::default();
+        assert!(presence.open(Some(&bob)).is_empty());
+        assert!(!presence.hint_received(&bob_id, true));
+        assert!(!presence.is_viewing(&bob_id));
+
+        // Opting in announces the conversation already open
+        assert_eq!(presence.set_sharing(true), vec![(bob.clone(), true)]);
+        assert!(presence.hint_received(&bob_id, true));
+        assert_eq!(presence.set_sharing(false), vec![(bob.clone(), false)]);
+        assert!(!presence.is_viewing(&bob_id));
+    }
+
+    #[test]
+    fn test_switching_conversations_closes_the_previous_one() {
+        let mut presence = sharing();
+        assert_eq!(presence.open(Some("bob")), vec![("bob".to_string(), true)]);
+        assert!(presence.open(Some("bob")).is_empty());
+        assert_eq!(
+            presence.open(Some("carol")),
+            vec![("bob".to_string(), false), ("carol".to_string(), true)]
+        );
+        assert_eq!(presence.open(None), vec![("carol".to_string(), false)]);
+
+        // Hints match the conversation whatever case the key arrives in
+        let carol = ConversationId::new(&"c".repeat(64)).unwrap();
+        let shouted = ConversationId::new(&"C".repeat(64)).unwrap();
+        assert!(presence.hint_received(&shouted, true));
+        assert!(presence.is_viewing(&carol));
+        assert!(presence.viewer_left(&carol));
+        assert!(!presence.is_viewing(&carol));
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("profile-presence-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut settings = PresenceSettings::load(&path).unwrap();
+        assert!(!settings.share_viewing);
+        assert!(!settings.share_last_seen);
+        settings.share_viewing = true;
+        settings.share_last_seen = true;
+        settings.save().unwrap();
+
+        assert_eq!(PresenceSettings::load(&path).unwrap(), settings);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+//! Double-ratchet sessions with peers
+//!
+//! With forward secrecy turned on, direct messages are sealed in a
+//! [`RatchetSession`] with the recipient (see
+//! [`profile_shared::crypto::ratchet`]). Each peer has one active session,
+//! the one new messages are sent in. A message in a session this client
+//! doesn't know starts it as the responder, and that session becomes the
+//! active one: the peer started afresh, e.g. after restarting.
+//!
+//! When both sides start a session at the same time, each reads the other's
+//! first message before either is confirmed. The side with the lower public
+//! key keeps its own session and the other switches to it, so they settle
+//! on one. Replaced sessions are kept, up to [`MAX_SESSIONS_PER_PEER`], to
+//! read messages still on their way.
+//!
+//! Sessions live in memory only, so a restart forgets them and starts new
+//! ones. Messages sent in a forgotten session can't be read.
+
+use crate::state::conversation::ConversationId;
+use profile_shared::crypto::padding::PaddingPolicy;
+use profile_shared::crypto::ratchet::{RatchetEnvelope, RatchetSession};
+use profile_shared::{PrivateKey, RatchetError};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use zeroize::Zeroizing;
+
+/// Most sessions kept with one peer, the active one included
+pub const MAX_SESSIONS_PER_PEER: usize = 4;
+
+/// Sessions with one peer
+#[derive(Debug, Default)]
+struct PeerSessions {
+    /// Id of the session new messages are sent in
+    active: Option<String>,
+    /// Newest first
+    sessions: Vec<RatchetSession>,
+}
+
+impl PeerSessions {
+    fn active_mut(&mut self) -> Option<&mut RatchetSession> {
+        let active = self.active.as_deref()?;
+        self.sessions.iter_mut().find(|s| s.id() == active)
+    }
+
+    fn active(&self) -> Option<&RatchetSession> {
+        let active = self.active.as_deref()?;
+        self.sessions.iter().find(|s| s.id() == active)
+    }
+
+    fn add(&mut self, session: RatchetSession, activate: bool) {
+        if activate {
+            self.active = Some(session.id().to_string());
+        }
+        self.sessions.insert(0, session);
+        if self.sessions.len() > MAX_SESSIONS_PER_PEER {
+            // Never the active session, which is the newest or second newest
+            let active = self.active.clone();
+            if let Some(index) = self
+                .sessions
+                .iter()
+                .rposition(|s| Some(s.id()) != active.as_deref())
+            {
+                self.sessions.remove(index);
+            }
+        }
+    }
+}
+
+/// Ratchet sessions with every peer, keyed by conversation
+#[derive(Debug, Default)]
+pub struct RatchetSessions {
+    peers: HashMap<ConversationId, PeerSessions>,
+}
+
+impl RatchetSessions {
+    /// Create a store with no sessions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Encrypt a message for `peer` in the active session, starting one if
+    /// there is none
+    ///
+    /// # Arguments
+    /// * `identity` - The own identity key
+    /// * `peer` - The recipient
+    /// * `plaintext` - The message to seal
+    /// * `padding` - Buckets the message is padded to
+    ///
+    /// # Errors
+    /// Returns an error if a session can't be started with the peer's key
+    pub fn seal(
+        &mut self,
+        identity: &PrivateKey,
+        peer: &ConversationId,
+        plaintext: &[u8],
+        padding: &PaddingPolicy,
+    ) -> Result<RatchetEnvelope, RatchetError> {
+        let sessions = self.peers.entry(peer.clone()).or_default();
+        if let Some(session) = sessions.active_mut() {
+            return Ok(session.encrypt(plaintext, padding));
+        }
+        let mut session = RatchetSession::initiate(identity, peer.as_str())?;
+        let envelope = session.encrypt(plaintext, padding);
+        sessions.add(session, true);
+        Ok(envelope)
+    }
+
+    /// Decrypt a message from `peer`
+    ///
+    /// A message in a session not seen before sets up that session.
+    ///
+    /// # Arguments
+    /// * `identity` - The own identity key
+    /// * `own` - The own conversation id, to settle sessions both sides
+    ///   started at once
+    /// * `peer` - The sender
+    /// * `envelope` - The sealed message
+    ///
+    /// # Errors
+    /// Returns an error if the message can't be read; the sessions are left
+    /// as they were
+    pub fn open(
+        &mut self,
+        identity: &PrivateKey,
+        own: &ConversationId,
+        peer: &ConversationId,
+        envelope: &RatchetEnvelope,
+    ) -> Result<Zeroizing<Vec<u8>>, RatchetError> {
+        let sessions = self.peers.entry(peer.clone()).or_default();
+        if let Some(session) = sessions
+            .sessions
+            .iter_mut()
+            .find(|s| s.id().eq_ignore_ascii_case(&envelope.session))
+        {
+            return session.decrypt(envelope);
+        }
+
+        let (session, plaintext) = RatchetSession::respond(identity, peer.as_str(), envelope)?;
+        let keep_own = sessions
+            .active()
+            .is_some_and(|active| active.is_initiator() && !active.is_confirmed() && own < peer);
+        sessions.add(session, !keep_own);
+        Ok(plaintext)
+    }
+
+    /// The session new messages to `peer` are sent in
+    pub fn active_session(&self, peer: &ConversationId) -> Option<&RatchetSession> {
+        self.peers.get(peer)?.active()
+    }
+
+    /// Number of sessions kept with `peer`
+    pub fn session_count(&self, peer: &ConversationId) -> usize {
+        self.peers
+            .get(peer)
+            .map_or(0, |sessions| sessions.sessions.len())
+    }
+
+    /// Drop every session with `peer`; the next message starts a new one
+    pub fn forget(&mut self, peer: &ConversationId) {
+        self.peers.remove(peer);
+    }
+}
+
+/// Type alias for shared ratchet sessions
+pub type SharedRatchetSessions = Arc<Mutex<RatchetSessions>>;
+
+/// Create an empty shared session store
+pub fn create_shared_ratchet_sessions() -> SharedRatchetSessions {
+    Arc::new(Mutex::new(RatchetSessions::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profile_shared::{derive_public_key, generate_private_key};
+
+    struct Party {
+        key: PrivateKey,
+        public_key: ConversationId,
+        sessions: RatchetSessions,
+    }
+
+    fn party() -> Party {
+        let key = generate_private_key().unwrap();
+        let public_key =
+            ConversationId::new(&hex::encode(derive_public_key(&key).unwrap())).unwrap();
+        Party {
+            key,
+            public_key,
+            sessions: RatchetSessions::new(),
+        }
+    }
+
+    fn send(from: &mut Party, to: &Party, text: &str) -> RatchetEnvelope {
+        from.sessions
+            .seal(
+                &from.key,
+                &to.public_key,
+                text.as_bytes(),
+                &PaddingPolicy::default(),
+            )
+            .unwrap()
+    }
+
+    fn receive(to: &mut Party, from: &Party, envelope: &RatchetEnvelope) -> String {
+        let plaintext = to
+            .sessions
+            .open(&to.key, &to.public_key, &from.public_key, envelope)
+            .unwrap();
+        String::from_utf8(plaintext.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_session_started_on_first_message_and_reused() {
+        let (mut alice, mut bob) = (party(), party());
+        let first = send(&mut alice, &bob, "hi bob");
+        assert_eq!(receive(&mut bob, &alice, &first), "hi bob");
+
+        let reply = send(&mut bob, &alice, "hi alice");
+        assert_eq!(reply.session, first.session);
+        assert_eq!(receive(&mut alice, &bob, &reply), "hi alice");
+        assert_eq!(alice.sessions.session_count(&bob.public_key), 1);
+        // The same conversation whatever case the key arrives in
+        let shouted = ConversationId::new(&bob.public_key.as_str().to_uppercase()).unwrap();
+        assert!(alice
+            .sessions
+            .active_session(&shouted)
+            .unwrap()
+            .is_confirmed());
+    }
+
+    #[test]
+    fn test_simultaneous_starts_settle_on_one_session() {
+        let (mut alice, mut bob) = (party(), party());
+        let from_alice = send(&mut alice, &bob, "a1");
+        let from_bob = send(&mut bob, &alice, "b1");
+        assert_ne!(from_alice.session, from_bob.session);
+
+        assert_eq!(receive(&mut bob, &alice, &from_alice), "a1");
+        assert_eq!(receive(&mut alice, &bob, &from_bob), "b1");
+
+        // Both now send in the session the lower key started
+        let expected = if alice.public_key < bob.public_key {
+            &from_alice.session
+        } else {
+            &from_bob.session
+        };
+        let a2 = send(&mut alice, &bob, "a2");
+        let b2 = send(&mut bob, &alice, "b2");
+        assert_eq!(&a2.session, expected);
+        assert_eq!(&b2.session, expected);
+        assert_eq!(receive(&mut bob, &alice, &a2), "a2");
+        assert_eq!(receive(&mut alice, &bob, &b2), "b2");
+    }
+
+    #[test]
+    fn test_restarted_peer_starts_a_new_session() {
+        let (mut alice, mut bob) = (party(), party());
+        let first = send(&mut alice, &bob, "before");
+        receive(&mut bob, &alice, &first);
+        let late = send(&mut alice, &bob, "late");
+
+        // Alice forgets her sessions and starts again
+        alice.sessions = RatchetSessions::new();
+        let again = send(&mut alice, &bob, "after");
+        assert_ne!(again.session, first.session);
+        assert_eq!(receive(&mut bob, &alice, &again), "after");
+        assert_eq!(
+            bob.sessions.active_session(&alice.public_key).unwrap().id(),
+            again.session
+        );
+        // The replaced session still reads what was on its way
+        assert_eq!(receive(&mut bob, &alice, &late), "late");
+
+        for _ in 0..MAX_SESSIONS_PER_PEER {
+            alice.sessions.forget(&bob.public_key);
+            let envelope = send(&mut alice, &bob, "again");
+            receive(&mut bob, &alice, &envelope);
+        }
+        assert_eq!(
+            bob.sessions.session_count(&alice.public_key),
+            MAX_SESSIONS_PER_PEER
+        );
+    }
+
+    #[test]
+    fn test_unreadable_message_refused() {
+        let (mut alice, mut bob, eve) = (party(), party(), party());
+        let envelope = send(&mut alice, &bob, "for bob");
+        // Not from Eve, whatever the server claims
+        assert!(bob
+            .sessions
+            .open(&bob.key, &bob.public_key, &eve.public_key, &envelope)
+            .is_err());
+        assert_eq!(bob.sessions.session_count(&eve.public_key), 0);
+        assert_eq!(receive(&mut bob, &alice, &envelope), "for bob");
+    }
+}
@@ -0,0 +1,215 @@
+//! Room state for group conversations
+//!
+//! Tracks the rooms this user belongs to, their member lists and message
+//! history, and which conversation (direct or room) is currently active.
+//! Membership is driven entirely by `RoomUpdate` snapshots from the server.
+
+use crate::state::messages::{ChatMessage, MessageHistory};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// The conversation currently shown in the chat view
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "lowercase")]
+pub enum Conversation {
+    /// One-to-one conversation with a peer (hex-encoded public key)
+    Direct(String),
+    /// Group conversation in a named room
+    Room(String),
+}
+
+/// A room this user is a member of
+#[derive(Debug, Clone)]
+pub struct Room {
+    /// Room name
+    pub name: String,
+    /// Public keys of current members (including self)
+    pub members: Vec<String>,
+    /// Messages posted to the room
+    pub history: MessageHistory,
+}
+
+impl Room {
+    /// Create a room with the given members and empty history
+    pub fn new(name: String, members: Vec<String>) -> Self {
+        Self {
+            name,
+            members,
+            history: MessageHistory::with_default_capacity(),
+        }
+    }
+}
+
+/// All rooms this user belongs to plus the active conversation
+#[derive(Debug, Clone, Default)]
+pub struct RoomsState {
+    rooms: HashMap<String, Room>,
+    active: Option<Conversation>,
+}
+
+impl RoomsState {
+    /// Create an empty rooms state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a membership snapshot from the server
+    ///
+    /// If `my_public_key` is no longer a member the room is dropped, and
+    /// the active conversation is cleared if it was this room.
+    pub fn apply_room_update(&mut self, room: &str, members: Vec<String>, my_public_key: &str) {
+        if members.iter().any(|m| m == my_public_key) {
+            self.rooms
+                .entry(room.to_string())
+                .and_modify(|r| r.members = members.clone())
+                .or_insert_with(|| Room::new(room.to_string(), members));
+        } else {
+            self.rooms.remove(room);
+            if self.active == Some(Conversation::Room(room.to_string())) {
+                self.active = None;
+            }
+        }
+    }
+
+    /// Store a message posted to a room
+    ///
+    /// Returns false if this user is not a member of the room.
+    pub fn add_room_message(&mut self, room: &str, message: ChatMessage) -> bool {
+        match self.rooms.get_mut(room) {
+            Some(r) => {
+                r.history.add_message(message);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get a room by name
+    pub fn room(&self, name: &str) -> Option<&Room> {
+        self.rooms.get(name)
+    }
+
+    /// Get a room by name for modification
+    pub fn room_mut(&mut self, name: &str) -> Option<&mut Room> {
+        self.rooms.get_mut(name)
+    }
+
+    /// Names of all joined rooms, sorted
+    pub fn room_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.rooms.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Make a joined room the active conversation
+    ///
+    /// Returns false if this user is not a member of the room.
+    pub fn select_room(&mut self, name: &str) -> bool {
+        if self.rooms.contains_key(name) {
+            self.active = Some(Conversation::Room(name.to_string()));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Make a direct conversation with a peer the active conversation
+    pub fn select_direct(&mut self, public_key: String) {
+        self.active = Some(Conversation::Direct(public_key));
+    }
+
+    /// Clear the active conversation
+    pub fn clear_selection(&mut self) {
+        self.active = None;
+    }
+
+    /// Get the active conversation
+    pub fn active_conversation(&self) -> Option<&Conversation> {
+        self.active.as_ref()
+    }
+
+    /// Get the active room, if the active conversation is a room
+    pub fn active_room(&self) -> Option<&Room> {
+        match &self.active {
+            Some(Conversation::Room(name)) => self.rooms.get(name),
+            _ => None,
+        }
+    }
+}
+
+/// Shared rooms state for concurrent access
+pub type SharedRoomsState = Arc<Mutex<RoomsState>>;
+
+/// Create a new shared rooms state
+#[inline]
+pub fn create_shared_rooms_state() -> SharedRoomsState {
+    Arc::new(Mutex::new(RoomsState::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(sender: &str, text: &str) -> ChatMessage {
+        ChatMessage::new(
+            sender.to_string(),
+            text.to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_room_update_adds_and_removes_room() {
+        let mut state = RoomsState::new();
+        state.apply_room_update("general", vec!["me".to_string(), "bob".to_string()], "me");
+        assert_eq!(state.room_names(), vec!["general".to_string()]);
+        assert_eq!(state.room("general").unwrap().members.len(), 2);
+
+        state.apply_room_update("general", vec!["bob".to_string()], "me");
+        assert!(state.room("general").is_none());
+    }
+
+    #[test]
+    fn test_room_messages_only_for_joined_rooms() {
+        let mut state = RoomsState::new();
+        assert!(!state.add_room_message("general", message("bob", "hi")));
+
+        state.apply_room_update("general", vec!["me".to_string()], "me");
+        assert!(state.add_room_message("general", message("bob", "hi")));
+        assert_eq!(state.room("general").unwrap().history.len(), 1);
+    }
+
+    #[test]
+    fn test_select_room_and_direct() {
+        let mut state = RoomsState::new();
+        assert!(!state.select_room("general"));
+
+        state.apply_room_update("general", vec!["me".to_string()], "me");
+        assert!(state.select_room("general"));
+        assert_eq!(
+            state.active_conversation(),
+            Some(&Conversation::Room("general".to_string()))
+        );
+        assert_eq!(state.active_room().unwrap().name, "general");
+
+        state.select_direct("peer".to_string());
+        assert_eq!(
+            state.active_conversation(),
+            Some(&Conversation::Direct("peer".to_string()))
+        );
+        assert!(state.active_room().is_none());
+    }
+
+    #[test]
+    fn test_leaving_active_room_clears_selection() {
+        let mut state = RoomsState::new();
+        state.apply_room_update("general", vec!["me".to_string()], "me");
+        state.select_room("general");
+
+        state.apply_room_update("general", vec![], "me");
+        assert!(state.active_conversation().is_none());
+    }
+}
@@ -3,7 +3,7 @@
 //! CRITICAL: Uses tokio::sync::Mutex (NOT std::sync::Mutex) for async-safe, non-blocking access
 
 use crate::state::KeyState;
-use profile_shared::{derive_public_key, generate_private_key};
+use profile_shared::{derive_public_key, generate_private_key, PrivateKey, PublicKey};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -22,7 +22,24 @@ pub fn create_shared_key_state() -> SharedKeyState {
 pub async fn handle_generate_key_async(key_state: &SharedKeyState) -> Result<String, String> {
     // Lock the state using async-safe pattern (tokio::sync::Mutex)
     let mut state = key_state.lock().await;
+    let (private_key, public_key, public_key_hex) = generate_key_pair()?;
+    state.set_generated_key(private_key, public_key);
+    Ok(public_key_hex)
+}
 
+/// Generate a throwaway guest key and store it in the shared state
+///
+/// The key is marked ephemeral: it is never saved and has no recovery
+/// phrase. Returns the public key as hex string for display
+pub async fn handle_generate_guest_key_async(key_state: &SharedKeyState) -> Result<String, String> {
+    let mut state = key_state.lock().await;
+    let (private_key, public_key, public_key_hex) = generate_key_pair()?;
+    state.set_ephemeral_key(private_key, public_key);
+    Ok(public_key_hex)
+}
+
+/// Generate a key pair and check its public key before it is stored
+fn generate_key_pair() -> Result<(PrivateKey, PublicKey, String), String> {
     // Generate private key
     let private_key = generate_private_key()
         .map_err(|e| format!(
@@ -58,10 +75,7 @@ pub async fn handle_generate_key_async(key_state: &SharedKeyState) -> Result<Str
         return Err("Invalid public key: all-zero key detected".into());
     }
 
-    // Store in session state
-    state.set_generated_key(private_key, public_key);
-
-    Ok(public_key_hex)
+    Ok((private_key, public_key, public_key_hex))
 }
 
 #[cfg(test)]
@@ -125,6 +139,16 @@ mod tests {
         assert!(state.is_key_set());
     }
 
+    #[tokio::test]
+    async fn test_handle_generate_guest_key_async() {
+        let key_state = create_shared_key_state();
+        let public_key_hex = handle_generate_guest_key_async(&key_state).await.unwrap();
+
+        let state = key_state.lock().await;
+        assert!(state.is_ephemeral());
+        assert_eq!(hex::encode(state.public_key().unwrap()), public_key_hex);
+    }
+
     #[tokio::test]
     async fn test_handle_generate_key_async_randomness() {
         let key_state1 = create_shared_key_state();
@@ -0,0 +1,240 @@
+//! Sound cue settings
+//!
+//! Sound cues are off until the user turns them on. Each event has its own
+//! toggle, and one volume applies to all of them. Settings are saved to a
+//! local file (`PROFILE_SOUND_FILE`, or `~/.profile/sound.json` when that
+//! is unset).
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the sound settings file location
+pub const SOUND_ENV_VAR: &str = "PROFILE_SOUND_FILE";
+
+/// Volume used until the user picks one, in percent
+pub const DEFAULT_VOLUME: u8 = 70;
+
+/// Error types for sound settings
+#[derive(Debug, Clone, PartialEq)]
+pub enum SoundSettingsError {
+    /// Reading or writing the settings file failed
+    Io(String),
+    /// The settings file is not valid JSON
+    Parse(String),
+}
+
+impl Display for SoundSettingsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SoundSettingsError::Io(msg) => write!(f, "Failed to access sound settings: {}", msg),
+            SoundSettingsError::Parse(msg) => {
+                write!(f, "Failed to parse sound settings: {}", msg)
+            }
+        }
+    }
+}
+
+impl Error for SoundSettingsError {}
+
+/// Events that can play a sound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    MessageReceived,
+    UserJoined,
+    SendFailed,
+}
+
+impl SoundEvent {
+    /// Every event, in the order the settings list them
+    pub const ALL: [SoundEvent; 3] = [
+        SoundEvent::MessageReceived,
+        SoundEvent::UserJoined,
+        SoundEvent::SendFailed,
+    ];
+
+    /// Label shown next to the event's toggle
+    pub fn label(self) -> &'static str {
+        match self {
+            SoundEvent::MessageReceived => "Message received",
+            SoundEvent::UserJoined => "User joined",
+            SoundEvent::SendFailed => "Send failed",
+        }
+    }
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+fn default_volume() -> u8 {
+    DEFAULT_VOLUME
+}
+
+/// Sound cue settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SoundSettings {
+    /// Master switch; sound cues are off by default
+    #[serde(default)]
+    pub enabled: bool,
+    /// Silences every cue without losing the other settings
+    #[serde(default)]
+    pub muted: bool,
+    /// Volume in percent, 0-100
+    #[serde(default = "default_volume")]
+    volume: u8,
+    #[serde(rename = "messageReceived", default = "enabled_by_default")]
+    pub message_received: bool,
+    #[serde(rename = "userJoined", default = "enabled_by_default")]
+    pub user_joined: bool,
+    #[serde(rename = "sendFailed", default = "enabled_by_default")]
+    pub send_failed: bool,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            muted: false,
+            volume: DEFAULT_VOLUME,
+            message_received: true,
+            user_joined: true,
+            send_failed: true,
+            path: None,
+        }
+    }
+}
+
+/// Default location of the sound settings file
+///
+/// # Returns
+/// `PROFILE_SOUND_FILE` if set, otherwise `~/.profile/sound.json`, or None
+/// if neither can be determined
+pub fn default_sound_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(SOUND_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("sound.json"))
+}
+
+impl SoundSettings {
+    /// Create default, in-memory settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the settings saved at `path`
+    ///
+    /// A missing file gives default settings that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, SoundSettingsError> {
+        let path = path.into();
+        let mut settings: SoundSettings = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| SoundSettingsError::Parse(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SoundSettings::default(),
+            Err(e) => return Err(SoundSettingsError::Io(e.to_string())),
+        };
+        settings.volume = settings.volume.min(100);
+        settings.path = Some(path);
+        Ok(settings)
+    }
+
+    /// Save the settings to their file
+    ///
+    /// In-memory settings are not saved.
+    pub fn save(&self) -> Result<(), SoundSettingsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| SoundSettingsError::Io(e.to_string()))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| SoundSettingsError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| SoundSettingsError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| SoundSettingsError::Io(e.to_string()))
+    }
+
+    /// File the settings are saved to, if persistent
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Volume in percent
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Set the volume, clamped to 100 percent
+    pub fn set_volume(&mut self, percent: u8) {
+        self.volume = percent.min(100);
+    }
+
+    /// Whether the user turned on the cue for `event`
+    pub fn is_event_enabled(&self, event: SoundEvent) -> bool {
+        match event {
+            SoundEvent::MessageReceived => self.message_received,
+            SoundEvent::UserJoined => self.user_joined,
+            SoundEvent::SendFailed => self.send_failed,
+        }
+    }
+
+    /// Turn the cue for `event` on or off
+    pub fn set_event_enabled(&mut self, event: SoundEvent, enabled: bool) {
+        match event {
+            SoundEvent::MessageReceived => self.message_received = enabled,
+            SoundEvent::UserJoined => self.user_joined = enabled,
+            SoundEvent::SendFailed => self.send_failed = enabled,
+        }
+    }
+
+    /// Whether `event` should play, ignoring do-not-disturb
+    pub fn should_play(&self, event: SoundEvent) -> bool {
+        self.enabled && !self.muted && self.volume > 0 && self.is_event_enabled(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_are_off_with_events_enabled() {
+        let mut settings = SoundSettings::new();
+        assert!(!settings.should_play(SoundEvent::MessageReceived));
+
+        settings.enabled = true;
+        assert!(SoundEvent::ALL.iter().all(|e| settings.should_play(*e)));
+
+        settings.set_event_enabled(SoundEvent::UserJoined, false);
+        assert!(!settings.should_play(SoundEvent::UserJoined));
+        settings.muted = true;
+        assert!(!settings.should_play(SoundEvent::SendFailed));
+        settings.muted = false;
+        settings.set_volume(0);
+        assert!(!settings.should_play(SoundEvent::SendFailed));
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = std::env::temp_dir().join(format!("profile-sound-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut settings = SoundSettings::load(&path).unwrap();
+        assert_eq!(settings.volume(), DEFAULT_VOLUME);
+        settings.enabled = true;
+        settings.set_volume(250);
+        settings.set_event_enabled(SoundEvent::SendFailed, false);
+        settings.save().unwrap();
+
+        let loaded = SoundSettings::load(&path).unwrap();
+        assert_eq!(loaded, settings);
+        assert_eq!(loaded.volume(), 100);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+//! Starred messages
+//!
+//! Any message, direct or in a room, can be starred. Starred messages are
+//! collected in one list across every conversation, each remembering the
+//! conversation it came from so the chat can jump back to it in context.
+//! A copy of the text is kept, so a starred message is still listed after
+//! it has aged out of the message history.
+//!
+//! The collection is persisted as JSON at `PROFILE_STARRED_FILE`, or
+//! `~/.profile/starred.json` when that is unset.
+
+use crate::state::messages::ChatMessage;
+use crate::state::rooms::Conversation;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Environment variable overriding the starred messages file location
+pub const STARRED_ENV_VAR: &str = "PROFILE_STARRED_FILE";
+
+/// Error types for starred message operations
+#[derive(Debug, Clone, PartialEq)]
+pub enum StarredError {
+    /// Reading or writing the starred messages file failed
+    Io(String),
+    /// The starred messages file is not valid JSON
+    Parse(String),
+}
+
+impl Display for StarredError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StarredError::Io(msg) => write!(f, "Failed to access starred messages file: {}", msg),
+            StarredError::Parse(msg) => {
+                write!(f, "Failed to parse starred messages file: {}", msg)
+            }
+        }
+    }
+}
+
+impl Error for StarredError {}
+
+/// A starred message and where it was posted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StarredMessage {
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+    /// Conversation to jump to
+    pub conversation: Conversation,
+    #[serde(rename = "senderPublicKey")]
+    pub sender_public_key: String,
+    /// Copy of the message text
+    pub message: String,
+    /// When the message was sent (ISO 8601)
+    pub timestamp: String,
+}
+
+impl StarredMessage {
+    /// Star `message` from `conversation`
+    pub fn new(conversation: Conversation, message: &ChatMessage) -> Self {
+        Self {
+            message_id: message.message_id.to_string(),
+            conversation,
+            sender_public_key: message.sender_public_key.clone(),
+            message: message.message.clone(),
+            timestamp: message.timestamp.clone(),
+        }
+    }
+}
+
+/// Every starred message, ordered by when it was sent
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StarredMessages {
+    #[serde(default)]
+    messages: Vec<StarredMessage>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// Default location of the starred messages file
+///
+/// # Returns
+/// `PROFILE_STARRED_FILE` if set, otherwise `~/.profile/starred.json`, or
+/// None if neither can be determined
+pub fn default_starred_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(STARRED_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("starred.json"))
+}
+
+impl StarredMessages {
+    /// Create an empty, in-memory collection
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the starred messages saved at `path`
+    ///
+    /// A missing file gives an empty collection that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, StarredError> {
+        let path = path.into();
+        let mut starred: StarredMessages = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| StarredError::Parse(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => StarredMessages::default(),
+            Err(e) => return Err(StarredError::Io(e.to_string())),
+        };
+        starred.path = Some(path);
+        Ok(starred)
+    }
+
+    /// Save the starred messages to their file
+    ///
+    /// In-memory collections are not saved.
+    pub fn save(&self) -> Result<(), StarredError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| StarredError::Io(e.to_string()))?;
+        }
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| StarredError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| StarredError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| StarredError::Io(e.to_string()))
+    }
+
+    /// File the collection is saved to, if persistent
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Star a message
+    ///
+    /// # Returns
+    /// Whether the message was not starred before
+    pub fn star(&mut self, conversation: Conversation, message: &ChatMessage) -> bool {
+        let starred = StarredMessage::new(conversation, message);
+        if self.is_starred(&starred.message_id) {
+            return false;
+        }
+        let index = self
+            .messages
+            .partition_point(|m| m.timestamp <= starred.timestamp);
+        self.messages.insert(index, starred);
+        true
+    }
+
+    /// Remove a message's star
+    ///
+    /// # Returns
+    /// Whether the message was starred
+    pub fn unstar(&mut self, message_id: &str) -> bool {
+        let before = self.messages.len();
+        self.messages.retain(|m| m.message_id != message_id);
+        self.messages.len() != before
+    }
+
+    /// Whether a message is starred
+    pub fn is_starred(&self, message_id: &str) -> bool {
+        self.get(message_id).is_some()
+    }
+
+    /// Look up a starred message
+    pub fn get(&self, message_id: &str) -> Option<&StarredMessage> {
+        self.messages.iter().find(|m| m.message_id == message_id)
+    }
+
+    /// Every starred message across all conversations, oldest first
+    pub fn messages(&self) -> &[StarredMessage] {
+        &self.messages
+    }
+
+    /// Starred messages from one conversation, oldest first
+    pub fn in_conversation(&self, conversation: &Conversation) -> Vec<&StarredMessage> {
+        self.messages
+            .iter()
+            .filter(|m| &m.conversation == conversation)
+            .collect()
+    }
+
+    /// IDs of every starred message
+    pub fn message_ids(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|m| m.message_id.as_str())
+    }
+
+    /// Number of starred messages
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Check if nothing is starred
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// Shared starred messages for concurrent access
+pub type SharedStarredMessages = Arc<Mutex<StarredMessages>>;
+
+/// Create a new shared, in-memory starred messages collection
+#[inline]
+pub fn create_shared_starred_messages() -> SharedStarredMessages {
+    Arc::new(Mutex::new(StarredMessages::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str, timestamp: &str) -> ChatMessage {
+        ChatMessage::new(
+            "ab".repeat(32),
+            text.to_string(),
+            "sig".to_string(),
+            timestamp.to_string(),
+        )
+    }
+
+    #[test]
+    fn test_star_across_conversations_in_time_order() {
+        let mut starred = StarredMessages::new();
+        let later = message("later", "2025-12-27T11:00:00Z");
+        let earlier = message("earlier", "2025-12-27T10:00:00Z");
+        let general = Conversation::Room("general".to_string());
+
+        assert!(starred.star(general.clone(), &later));
+        assert!(starred.star(Conversation::Direct("ab".repeat(32)), &earlier));
+        assert!(!starred.star(general.clone(), &later));
+
+        let texts: Vec<&str> = starred
+            .messages()
+            .iter()
+            .map(|m| m.message.as_str())
+            .collect();
+        assert_eq!(texts, vec!["earlier", "later"]);
+        assert_eq!(starred.in_conversation(&general).len(), 1);
+
+        assert!(starred.unstar(&later.message_id.to_string()));
+        assert!(!starred.is_starred(&later.message_id.to_string()));
+        assert_eq!(starred.len(), 1);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path =
+            std::env::temp_dir().join(format!("profile-starred-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut starred = StarredMessages::load(&path).unwrap();
+        assert!(starred.is_empty());
+        starred.star(
+            Conversation::Room("general".to_string()),
+            &message("hi", "2025-12-27T10:00:00Z"),
+        );
+        starred.save().unwrap();
+
+        let loaded = StarredMessages::load(&path).unwrap();
+        assert_eq!(loaded, starred);
+        assert_eq!(
+            loaded.messages()[0].conversation,
+            Conversation::Room("general".to_string())
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
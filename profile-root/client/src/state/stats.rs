@@ -0,0 +1,423 @@
+//! Local usage statistics
+//!
+//! Counts messages sent to and received from each peer per day, records
+//! how long each session lasted and how often the connection had to be
+//! re-established. Everything here is computed from events on this device
+//! and saved only to a local file (`PROFILE_STATS_FILE`, or
+//! `~/.profile/stats.json` when that is unset). Statistics are never sent
+//! to the server and are not part of contact exports or backups.
+//! [`UsageStats::purge`] deletes them.
+
+use crate::connection::state::{ConnectionEvent, ConnectionState, ConnectionTransition};
+use crate::state::conversation::ConversationId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Environment variable overriding the statistics file location
+pub const STATS_ENV_VAR: &str = "PROFILE_STATS_FILE";
+
+/// Days of per-peer message counts kept; older days are dropped
+pub const STATS_RETENTION_DAYS: i64 = 90;
+
+/// Most recent sessions whose duration is kept
+pub const MAX_RECORDED_SESSIONS: usize = 100;
+
+/// Error types for usage statistics
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatsError {
+    /// Reading, writing or deleting the statistics file failed
+    Io(String),
+    /// The statistics file is not valid JSON
+    Parse(String),
+}
+
+impl Display for StatsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StatsError::Io(msg) => write!(f, "Failed to access statistics file: {}", msg),
+            StatsError::Parse(msg) => write!(f, "Failed to parse statistics file: {}", msg),
+        }
+    }
+}
+
+impl Error for StatsError {}
+
+/// Messages exchanged with one peer on one day
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyCounts {
+    pub sent: u64,
+    pub received: u64,
+}
+
+/// One finished session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// When the session started (RFC 3339)
+    #[serde(rename = "startedAt")]
+    pub started_at: String,
+    #[serde(rename = "durationSecs")]
+    pub duration_secs: u64,
+}
+
+/// On-disk layout of the statistics file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StatsFile {
+    #[serde(default)]
+    daily: BTreeMap<ConversationId, BTreeMap<String, DailyCounts>>,
+    #[serde(default)]
+    sessions: VecDeque<SessionRecord>,
+    #[serde(default)]
+    reconnects: u64,
+}
+
+/// Usage statistics kept on this device
+#[derive(Debug, Clone, Default)]
+pub struct UsageStats {
+    /// Message counts per peer, keyed by UTC day (YYYY-MM-DD, so they sort)
+    daily: BTreeMap<ConversationId, BTreeMap<String, DailyCounts>>,
+    /// Finished sessions, oldest first
+    sessions: VecDeque<SessionRecord>,
+    /// Times the connection was re-established
+    reconnects: u64,
+    /// Start of the session in progress, not saved
+    session_started: Option<DateTime<Utc>>,
+    /// File the statistics are saved to, if persistent
+    path: Option<PathBuf>,
+}
+
+/// Default location of the statistics file
+///
+/// # Returns
+/// `PROFILE_STATS_FILE` if set, otherwise `~/.profile/stats.json`, or None
+/// if neither can be determined
+pub fn default_stats_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(STATS_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".profile").join("stats.json"))
+}
+
+impl UsageStats {
+    /// Create empty, in-memory statistics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the statistics saved at `path`
+    ///
+    /// A missing file gives empty statistics that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, StatsError> {
+        let path = path.into();
+        let file: StatsFile = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| StatsError::Parse(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => StatsFile::default(),
+            Err(e) => return Err(StatsError::Io(e.to_string())),
+        };
+        Ok(Self {
+            daily: file.daily,
+            sessions: file.sessions,
+            reconnects: file.reconnects,
+            session_started: None,
+            path: Some(path),
+        })
+    }
+
+    /// Save the statistics to their file
+    ///
+    /// In-memory statistics are not saved.
+    pub fn save(&self) -> Result<(), StatsError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| StatsError::Io(e.to_string()))?;
+        }
+
+        let file = StatsFile {
+            daily: self.daily.clone(),
+            sessions: self.sessions.clone(),
+            reconnects: self.reconnects,
+        };
+        let json =
+            serde_json::to_string_pretty(&file).map_err(|e| StatsError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| StatsError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| StatsError::Io(e.to_string()))
+    }
+
+    /// File the statistics are saved to, if persistent
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn day_counts(&mut self, peer: &ConversationId, at: DateTime<Utc>) -> &mut DailyCounts {
+        let cutoff = (at.date_naive() - chrono::Duration::days(STATS_RETENTION_DAYS)).to_string();
+        self.daily.retain(|_, days| {
+            days.retain(|day, _| *day > cutoff);
+            !days.is_empty()
+        });
+        self.daily
+            .entry(peer.clone())
+            .or_default()
+            .entry(at.date_naive().to_string())
+            .or_default()
+    }
+
+    /// Count a message sent to `peer`
+    pub fn record_sent(&mut self, peer: &ConversationId, at: DateTime<Utc>) {
+        self.day_counts(peer, at).sent += 1;
+    }
+
+    /// Count a message received from `peer`
+    pub fn record_received(&mut self, peer: &ConversationId, at: DateTime<Utc>) {
+        self.day_counts(peer, at).received += 1;
+    }
+
+    /// Count a re-established connection
+    pub fn record_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+
+    /// Count a connection state change if it re-established a lost connection
+    ///
+    /// # Returns
+    /// `true` if it was counted as a reconnect
+    pub fn record_transition(&mut self, transition: &ConnectionTransition) -> bool {
+        let reconnected = matches!(transition.from, ConnectionState::Reconnecting { .. })
+            && transition.event == ConnectionEvent::Connected;
+        if reconnected {
+            self.record_reconnect();
+        }
+        reconnected
+    }
+
+    /// Mark the start of a session
+    ///
+    /// A session already in progress is ended first.
+    pub fn start_session(&mut self, at: DateTime<Utc>) {
+        self.end_session(at);
+        self.session_started = Some(at);
+    }
+
+    /// End the session in progress and record its duration
+    ///
+    /// # Returns
+    /// The finished session, or None if no session was in progress
+    pub fn end_session(&mut self, at: DateTime<Utc>) -> Option<SessionRecord> {
+        let started = self.session_started.take()?;
+        let record = SessionRecord {
+            started_at: started.to_rfc3339(),
+            duration_secs: at.signed_duration_since(started).num_seconds().max(0) as u64,
+        };
+        self.sessions.push_back(record.clone());
+        if self.sessions.len() > MAX_RECORDED_SESSIONS {
+            self.sessions.pop_front();
+        }
+        Some(record)
+    }
+
+    /// Message counts per day (YYYY-MM-DD) for one peer, oldest first
+    pub fn daily_counts(&self, peer: &ConversationId) -> Vec<(String, DailyCounts)> {
+        self.daily
+            .get(peer)
+            .map(|days| {
+                days.iter()
+                    .map(|(day, counts)| (day.clone(), *counts))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Totals per peer over the retention window, with the last active day
+    pub fn peer_totals(&self) -> Vec<(ConversationId, DailyCounts, String)> {
+        self.daily
+            .iter()
+            .filter_map(|(peer, days)| {
+                let last_day = days.keys().next_back()?.clone();
+                let totals = days
+                    .values()
+                    .fold(DailyCounts::default(), |acc, c| DailyCounts {
+                        sent: acc.sent + c.sent,
+                        received: acc.received + c.received,
+                    });
+                Some((peer.clone(), totals, last_day))
+            })
+            .collect()
+    }
+
+    /// Finished sessions, oldest first
+    pub fn sessions(&self) -> impl Iterator<Item = &SessionRecord> {
+        self.sessions.iter()
+    }
+
+    /// Number of re-established connections
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Check if nothing has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.daily.is_empty() && self.sessions.is_empty() && self.reconnects == 0
+    }
+
+    /// Erase all statistics and delete the statistics file
+    ///
+    /// A session in progress keeps running but its start is forgotten too,
+    /// so nothing recorded before the purge survives it.
+    pub fn purge(&mut self) -> Result<(), StatsError> {
+        self.daily.clear();
+        self.sessions.clear();
+        self.reconnects = 0;
+        self.session_started = None;
+        if let Some(path) = &self.path {
+            match std::fs::remove_file(path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(StatsError::Io(e.to_string())),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Type alias for shared usage statistics
+pub type SharedUsageStats = Arc<Mutex<UsageStats>>;
+
+/// Create new shared, in-memory usage statistics
+pub fn create_shared_usage_stats() -> SharedUsageStats {
+    Arc::new(Mutex::new(UsageStats::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn peer(byte: &str) -> ConversationId {
+        ConversationId::new(&byte.repeat(32)).unwrap()
+    }
+
+    #[test]
+    fn test_counts_per_peer_per_day() {
+        let mut stats = UsageStats::new();
+        let (alice, bob) = (peer("ab"), peer("cd"));
+
+        stats.record_sent(&alice, at("2025-03-01T09:00:00Z"));
+        stats.record_sent(&alice, at("2025-03-01T23:59:00Z"));
+        stats.record_received(&alice, at("2025-03-02T08:00:00Z"));
+        stats.record_received(&bob, at("2025-03-02T08:00:00Z"));
+
+        let days = stats.daily_counts(&alice);
+        assert_eq!(days.len(), 2);
+        assert_eq!(
+            days[0].1,
+            DailyCounts {
+                sent: 2,
+                received: 0
+            }
+        );
+        assert_eq!(
+            days[1].1,
+            DailyCounts {
+                sent: 0,
+                received: 1
+            }
+        );
+
+        let totals = stats.peer_totals();
+        assert_eq!(totals[0].0, alice);
+        assert_eq!(
+            totals[0].1,
+            DailyCounts {
+                sent: 2,
+                received: 1
+            }
+        );
+        assert_eq!(totals[0].2, "2025-03-02");
+
+        // Days past the retention window are dropped
+        stats.record_sent(&bob, at("2025-07-01T00:00:00Z"));
+        assert!(stats.daily_counts(&alice).is_empty());
+        assert_eq!(stats.daily_counts(&bob).len(), 1);
+    }
+
+    #[test]
+    fn test_sessions_and_reconnects() {
+        let mut stats = UsageStats::new();
+        assert_eq!(stats.end_session(at("2025-03-01T09:00:00Z")), None);
+
+        stats.start_session(at("2025-03-01T09:00:00Z"));
+        stats.record_reconnect();
+        // Starting again ends the open session
+        stats.start_session(at("2025-03-01T09:30:00Z"));
+        let last = stats.end_session(at("2025-03-01T09:31:00Z")).unwrap();
+
+        assert_eq!(last.duration_secs, 60);
+        let durations: Vec<u64> = stats.sessions().map(|s| s.duration_secs).collect();
+        assert_eq!(durations, vec![1800, 60]);
+        assert_eq!(stats.reconnects(), 1);
+    }
+
+    #[test]
+    fn test_only_reestablished_connections_count() {
+        let transition = |from: ConnectionState, event| ConnectionTransition {
+            to: from.apply(event).unwrap(),
+            from,
+            event,
+        };
+        let mut stats = UsageStats::new();
+
+        assert!(!stats.record_transition(&transition(
+            ConnectionState::Connecting,
+            ConnectionEvent::Connected
+        )));
+        assert!(!stats.record_transition(&transition(
+            ConnectionState::Online,
+            ConnectionEvent::ConnectionLost
+        )));
+        assert!(!stats.record_transition(&transition(
+            ConnectionState::Reconnecting { attempt: 1 },
+            ConnectionEvent::ConnectFailed
+        )));
+        assert!(stats.record_transition(&transition(
+            ConnectionState::Reconnecting { attempt: 2 },
+            ConnectionEvent::Connected
+        )));
+        assert_eq!(stats.reconnects(), 1);
+    }
+
+    #[test]
+    fn test_save_load_and_purge() {
+        let dir = std::env::temp_dir().join(format!("profile-stats-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("stats.json");
+
+        let mut stats = UsageStats::load(&path).unwrap();
+        stats.record_sent(&peer("ab"), at("2025-03-01T09:00:00Z"));
+        stats.record_reconnect();
+        stats.save().unwrap();
+
+        let mut loaded = UsageStats::load(&path).unwrap();
+        assert_eq!(loaded.daily_counts(&peer("ab"))[0].1.sent, 1);
+        assert_eq!(loaded.reconnects(), 1);
+
+        loaded.purge().unwrap();
+        assert!(loaded.is_empty());
+        assert!(!path.exists());
+        assert!(UsageStats::load(&path).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
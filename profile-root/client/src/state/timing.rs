@@ -0,0 +1,225 @@
+//! Per-message timing for latency debugging
+//!
+//! Each message can have up to four points in time recorded:
+//!
+//! - composed: when the message was signed, which is its own timestamp
+//! - sent: when this client handed its own message to the connection
+//! - server received: stamped by the server when it routed the message
+//! - displayed: when the message was first shown in the chat
+//!
+//! [`LatencyBreakdown`] turns them into per-stage delays for the
+//! drill-down view. Timings are kept in memory for the most recent
+//! [`MAX_TRACKED_MESSAGES`] messages and never saved.
+
+use crate::state::messages::ChatMessage;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Most recent messages whose timings are kept
+pub const MAX_TRACKED_MESSAGES: usize = 1000;
+
+fn parse_time(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Points in time recorded for one message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageTiming {
+    pub composed_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub server_received_at: Option<DateTime<Utc>>,
+    pub displayed_at: Option<DateTime<Utc>>,
+}
+
+impl MessageTiming {
+    /// Per-stage delays between the recorded points
+    pub fn breakdown(&self) -> LatencyBreakdown {
+        let between = |from: Option<DateTime<Utc>>, to: Option<DateTime<Utc>>| Some(to? - from?);
+        // Received messages have no local send time; the sender's composed
+        // time stands in for it
+        let left_sender = self.sent_at.or(self.composed_at);
+        // Own messages aren't echoed by the server; they're shown once sent
+        let reached = self.server_received_at.or(self.sent_at);
+        LatencyBreakdown {
+            compose_to_send: between(self.composed_at, self.sent_at),
+            send_to_server: between(left_sender, self.server_received_at),
+            server_to_display: between(reached, self.displayed_at),
+            total: between(self.composed_at, self.displayed_at),
+        }
+    }
+}
+
+/// Delays between the recorded points of one message
+///
+/// A stage is None when either end wasn't recorded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyBreakdown {
+    pub compose_to_send: Option<Duration>,
+    pub send_to_server: Option<Duration>,
+    pub server_to_display: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl LatencyBreakdown {
+    /// One line per stage, for the drill-down view
+    ///
+    /// Negative delays usually mean the two clocks disagree, so they are
+    /// flagged rather than hidden.
+    pub fn format_lines(&self) -> String {
+        let format = |delay: Option<Duration>| match delay {
+            None => "n/a".to_string(),
+            Some(d) if d < Duration::zero() => {
+                format!("-{} ms (clock skew?)", -d.num_milliseconds())
+            }
+            Some(d) => format!("{} ms", d.num_milliseconds()),
+        };
+        [
+            ("Compose to send", self.compose_to_send),
+            ("Send to server", self.send_to_server),
+            ("Server to display", self.server_to_display),
+            ("Total", self.total),
+        ]
+        .iter()
+        .map(|(stage, delay)| format!("{}: {}", stage, format(*delay)))
+        .collect::<Vec<_>>()
+        .join("\n")
+    }
+}
+
+/// Timings of recent messages, by message id
+#[derive(Debug, Clone, Default)]
+pub struct MessageTimings {
+    timings: HashMap<Uuid, MessageTiming>,
+    /// Ids in the order they were first seen, oldest first
+    order: VecDeque<Uuid>,
+}
+
+impl MessageTimings {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entry(&mut self, message_id: Uuid) -> &mut MessageTiming {
+        if !self.timings.contains_key(&message_id) {
+            if self.order.len() >= MAX_TRACKED_MESSAGES {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.timings.remove(&oldest);
+                }
+            }
+            self.order.push_back(message_id);
+        }
+        self.timings.entry(message_id).or_default()
+    }
+
+    /// Record the times a message carries: its timestamp and the server's
+    pub fn record_message(&mut self, message: &ChatMessage) {
+        let composed_at = parse_time(&message.timestamp);
+        let server_received_at = message.server_received_at.as_deref().and_then(parse_time);
+        let timing = self.entry(message.message_id);
+        timing.composed_at = timing.composed_at.or(composed_at);
+        timing.server_received_at = timing.server_received_at.or(server_received_at);
+    }
+
+    /// Record when an own message was handed to the connection
+    pub fn record_sent(&mut self, message_id: Uuid, at: DateTime<Utc>) {
+        self.entry(message_id).sent_at = Some(at);
+    }
+
+    /// Record when a message was shown; only the first time counts
+    pub fn record_displayed(&mut self, message_id: Uuid, at: DateTime<Utc>) {
+        let timing = self.entry(message_id);
+        timing.displayed_at = timing.displayed_at.or(Some(at));
+    }
+
+    /// Timing of one message
+    pub fn get(&self, message_id: &Uuid) -> Option<&MessageTiming> {
+        self.timings.get(message_id)
+    }
+
+    /// Number of messages with a timing
+    pub fn len(&self) -> usize {
+        self.timings.len()
+    }
+
+    /// Check if no timings are recorded
+    pub fn is_empty(&self) -> bool {
+        self.timings.is_empty()
+    }
+}
+
+/// Type alias for shared message timings
+pub type SharedMessageTimings = Arc<Mutex<MessageTimings>>;
+
+/// Create a new shared message timings store
+pub fn create_shared_message_timings() -> SharedMessageTimings {
+    Arc::new(Mutex::new(MessageTimings::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        parse_time(text).unwrap()
+    }
+
+    #[test]
+    fn test_received_message_breakdown() {
+        let message = ChatMessage::new(
+            "ab".repeat(32),
+            "hi".to_string(),
+            "sig".to_string(),
+            "2025-03-01T09:00:00.000Z".to_string(),
+        )
+        .with_server_received_at(Some("2025-03-01T09:00:00.120Z".to_string()));
+
+        let mut timings = MessageTimings::new();
+        timings.record_message(&message);
+        timings.record_displayed(message.message_id, at("2025-03-01T09:00:00.200Z"));
+        timings.record_displayed(message.message_id, at("2025-03-01T09:00:05Z"));
+
+        let breakdown = timings.get(&message.message_id).unwrap().breakdown();
+        assert_eq!(
+            breakdown.format_lines(),
+            "Compose to send: n/a\nSend to server: 120 ms\nServer to display: 80 ms\nTotal: 200 ms"
+        );
+    }
+
+    #[test]
+    fn test_own_message_breakdown_and_skew() {
+        let mut timing = MessageTiming {
+            composed_at: Some(at("2025-03-01T09:00:00Z")),
+            sent_at: Some(at("2025-03-01T09:00:00.015Z")),
+            server_received_at: None,
+            displayed_at: Some(at("2025-03-01T09:00:00.020Z")),
+        };
+        let breakdown = timing.breakdown();
+        assert_eq!(breakdown.compose_to_send, Some(Duration::milliseconds(15)));
+        assert_eq!(breakdown.send_to_server, None);
+        assert_eq!(breakdown.server_to_display, Some(Duration::milliseconds(5)));
+
+        timing.server_received_at = Some(at("2025-03-01T08:59:59.990Z"));
+        assert!(timing
+            .breakdown()
+            .format_lines()
+            .contains("Send to server: -25 ms (clock skew?)"));
+    }
+
+    #[test]
+    fn test_oldest_timings_evicted() {
+        let mut timings = MessageTimings::new();
+        let first = Uuid::new_v4();
+        timings.record_sent(first, Utc::now());
+        for _ in 0..MAX_TRACKED_MESSAGES {
+            timings.record_sent(Uuid::new_v4(), Utc::now());
+        }
+        assert_eq!(timings.len(), MAX_TRACKED_MESSAGES);
+        assert!(timings.get(&first).is_none());
+    }
+}
@@ -0,0 +1,316 @@
+//! Lockout after repeated wrong passphrases
+//!
+//! Argon2 makes each passphrase guess slow, but nothing stopped someone
+//! holding the device from trying one passphrase after another. Every
+//! unlock of the key escrow or of an encrypted key file now goes through
+//! [`unlock_with_lockout`] and a small record of recent failures, saved
+//! beside the file (`key_escrow.attempts.json` next to `key_escrow.json`)
+//! so restarting the client doesn't reset it. After
+//! [`FREE_UNLOCK_ATTEMPTS`] wrong passphrases, each further failure doubles
+//! the wait before another attempt is accepted, from [`BASE_UNLOCK_DELAY`]
+//! up to [`MAX_UNLOCK_DELAY`]. A correct passphrase clears the record.
+//!
+//! Users can also have the file erased after a number of consecutive
+//! failures (`wipeEscrowAfter` in the client settings). Only the local
+//! file is erased; a copy uploaded as the server backup is kept.
+
+use crate::state::key_escrow::KeyEscrowError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Wrong passphrases allowed before attempts are throttled
+pub const FREE_UNLOCK_ATTEMPTS: u32 = 3;
+
+/// Wait after the first throttled failure
+pub const BASE_UNLOCK_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between attempts
+pub const MAX_UNLOCK_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// How wrong passphrases are throttled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    /// Failures allowed before any wait
+    pub free_attempts: u32,
+    /// Wait after the first throttled failure, doubled for each one after
+    pub base_delay: Duration,
+    /// Longest wait
+    pub max_delay: Duration,
+    /// Erase the escrow after this many consecutive failures (None or zero
+    /// to never erase it)
+    pub wipe_after: Option<u32>,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            free_attempts: FREE_UNLOCK_ATTEMPTS,
+            base_delay: BASE_UNLOCK_DELAY,
+            max_delay: MAX_UNLOCK_DELAY,
+            wipe_after: None,
+        }
+    }
+}
+
+impl LockoutPolicy {
+    /// Wait imposed after `failures` consecutive wrong passphrases
+    pub fn delay_after(&self, failures: u32) -> Duration {
+        let Some(doublings) = failures.checked_sub(self.free_attempts.saturating_add(1)) else {
+            return Duration::ZERO;
+        };
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(doublings))
+            .min(self.max_delay)
+    }
+
+    fn wipes_after(&self, failures: u32) -> bool {
+        self.wipe_after
+            .is_some_and(|limit| limit > 0 && failures >= limit)
+    }
+}
+
+/// What to do after a wrong passphrase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureAction {
+    /// Refuse attempts for this long (zero while attempts are free)
+    Retry(Duration),
+    /// The policy's failure limit was reached: erase the escrow
+    Wipe,
+}
+
+/// Consecutive wrong passphrases and the lockout they caused
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnlockAttempts {
+    pub failures: u32,
+    /// RFC 3339 time before which attempts are refused
+    #[serde(
+        rename = "lockedUntil",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub locked_until: Option<String>,
+}
+
+/// Location of the attempt record for the escrow at `escrow_path`
+pub fn unlock_attempts_path(escrow_path: &Path) -> PathBuf {
+    escrow_path.with_extension("attempts.json")
+}
+
+/// Try to unlock the file at `path`, throttled by the record beside it
+///
+/// While a lockout from earlier wrong passphrases runs, `attempt` isn't
+/// called and every try is refused with `KeyEscrowError::Locked`, even with
+/// the right passphrase. A `WrongPassphrase` from `attempt` is recorded;
+/// the one that reaches the policy's wipe limit deletes the file at `path`
+/// and returns `KeyEscrowError::Wiped`. Success clears the record.
+pub fn unlock_with_lockout<T>(
+    path: &Path,
+    policy: &LockoutPolicy,
+    now: DateTime<Utc>,
+    attempt: impl FnOnce() -> Result<T, KeyEscrowError>,
+) -> Result<T, KeyEscrowError> {
+    let attempts_path = unlock_attempts_path(path);
+    let mut attempts = UnlockAttempts::load(&attempts_path)?;
+    if let Some(wait) = attempts.wait(now) {
+        return Err(KeyEscrowError::Locked {
+            retry_after_secs: wait.as_secs() + u64::from(wait.subsec_nanos() > 0),
+        });
+    }
+
+    match attempt() {
+        Ok(unlocked) => {
+            UnlockAttempts::clear(&attempts_path)?;
+            Ok(unlocked)
+        }
+        Err(KeyEscrowError::WrongPassphrase) => match attempts.record_failure(policy, now) {
+            FailureAction::Retry(_) => {
+                attempts.save(&attempts_path)?;
+                Err(KeyEscrowError::WrongPassphrase)
+            }
+            FailureAction::Wipe => {
+                tracing::warn!(
+                    path = %path.display(),
+                    failures = attempts.failures,
+                    "Erasing passphrase-protected file after too many wrong passphrases"
+                );
+                std::fs::remove_file(path).map_err(|e| KeyEscrowError::Io(e.to_string()))?;
+                UnlockAttempts::clear(&attempts_path)?;
+                Err(KeyEscrowError::Wiped)
+            }
+        },
+        Err(e) => Err(e),
+    }
+}
+
+impl UnlockAttempts {
+    /// Time left before another attempt is accepted
+    ///
+    /// # Returns
+    /// None if an attempt is allowed at `now`
+    pub fn wait(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let until = DateTime::parse_from_rfc3339(self.locked_until.as_deref()?).ok()?;
+        (until > now).then(|| {
+            (until.with_timezone(&Utc) - now)
+                .to_std()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Record a wrong passphrase entered at `now`
+    pub fn record_failure(&mut self, policy: &LockoutPolicy, now: DateTime<Utc>) -> FailureAction {
+        self.failures = self.failures.saturating_add(1);
+        if policy.wipes_after(self.failures) {
+            return FailureAction::Wipe;
+        }
+        let delay = policy.delay_after(self.failures);
+        self.locked_until = chrono::Duration::from_std(delay)
+            .ok()
+            .filter(|delay| !delay.is_zero())
+            .map(|delay| (now + delay).to_rfc3339());
+        FailureAction::Retry(delay)
+    }
+
+    /// Load the record saved at `path`
+    ///
+    /// A missing file gives an empty record.
+    pub fn load(path: &Path) -> Result<Self, KeyEscrowError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| KeyEscrowError::Parse(e.to_string()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(KeyEscrowError::Io(e.to_string())),
+        }
+    }
+
+    /// Save the record to `path`
+    pub fn save(&self, path: &Path) -> Result<(), KeyEscrowError> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| KeyEscrowError::Io(e.to_string()))?;
+        }
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| KeyEscrowError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| KeyEscrowError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| KeyEscrowError::Io(e.to_string()))
+    }
+
+    /// Delete the record saved at `path`, if any
+    pub fn clear(path: &Path) -> Result<(), KeyEscrowError> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(KeyEscrowError::Io(e.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_delay_doubles_after_free_attempts_up_to_cap() {
+        let policy = LockoutPolicy {
+            free_attempts: 2,
+            base_delay: secs(1),
+            max_delay: secs(6),
+            wipe_after: None,
+        };
+        let delays: Vec<Duration> = (0..7).map(|n| policy.delay_after(n)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::ZERO,
+                Duration::ZERO,
+                Duration::ZERO,
+                secs(1),
+                secs(2),
+                secs(4),
+                secs(6)
+            ]
+        );
+        assert_eq!(policy.delay_after(u32::MAX), secs(6));
+    }
+
+    #[test]
+    fn test_lockout_state_machine() {
+        let policy = LockoutPolicy {
+            free_attempts: 1,
+            base_delay: secs(10),
+            ..LockoutPolicy::default()
+        };
+        let now = Utc::now();
+        let mut attempts = UnlockAttempts::default();
+        assert_eq!(attempts.wait(now), None);
+
+        // The free attempt doesn't lock
+        assert_eq!(
+            attempts.record_failure(&policy, now),
+            FailureAction::Retry(Duration::ZERO)
+        );
+        assert_eq!(attempts.wait(now), None);
+
+        // Then each failure locks for twice as long as the last
+        assert_eq!(
+            attempts.record_failure(&policy, now),
+            FailureAction::Retry(secs(10))
+        );
+        assert_eq!(attempts.wait(now), Some(secs(10)));
+        assert_eq!(attempts.wait(now + chrono::Duration::seconds(10)), None);
+
+        let later = now + chrono::Duration::seconds(10);
+        assert_eq!(
+            attempts.record_failure(&policy, later),
+            FailureAction::Retry(secs(20))
+        );
+        assert_eq!(
+            attempts.wait(later + chrono::Duration::seconds(5)),
+            Some(secs(15))
+        );
+        assert_eq!(attempts.failures, 3);
+    }
+
+    #[test]
+    fn test_wipe_after_limit_and_record_round_trip() {
+        let policy = LockoutPolicy {
+            wipe_after: Some(2),
+            ..LockoutPolicy::default()
+        };
+        let now = Utc::now();
+        let mut attempts = UnlockAttempts::default();
+        assert!(matches!(
+            attempts.record_failure(&policy, now),
+            FailureAction::Retry(_)
+        ));
+        assert_eq!(attempts.record_failure(&policy, now), FailureAction::Wipe);
+
+        let never = LockoutPolicy {
+            wipe_after: Some(0),
+            ..LockoutPolicy::default()
+        };
+        assert!(matches!(
+            UnlockAttempts::default().record_failure(&never, now),
+            FailureAction::Retry(_)
+        ));
+
+        let dir = std::env::temp_dir().join(format!("profile-unlock-{}", uuid::Uuid::new_v4()));
+        let path = unlock_attempts_path(&dir.join("key_escrow.json"));
+        assert_eq!(path.file_name().unwrap(), "key_escrow.attempts.json");
+        assert_eq!(UnlockAttempts::load(&path), Ok(UnlockAttempts::default()));
+        attempts.save(&path).unwrap();
+        assert_eq!(UnlockAttempts::load(&path), Ok(attempts));
+        UnlockAttempts::clear(&path).unwrap();
+        UnlockAttempts::clear(&path).unwrap();
+        assert_eq!(UnlockAttempts::load(&path), Ok(UnlockAttempts::default()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
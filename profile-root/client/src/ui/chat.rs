@@ -8,8 +8,13 @@
 //! This module provides `ChatUi` which bridges the `ChatView` data model
 //! to the Slint UI components defined in `main.slint`.
 
+use crate::state::conversation::ConversationId;
+use crate::state::journal::{journal_event, JournalHandle, StateEvent};
 use crate::state::messages::{ChatMessage, SharedMessageHistory};
-use chrono::{DateTime, Timelike};
+use crate::state::outbox::SendState;
+use crate::ui::translation::{MessageTranslator, Translation};
+use chrono::{DateTime, Timelike, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -54,8 +59,18 @@ pub struct DisplayMessage {
     pub sender_key: String,
     /// Formatted sender key for display (first 8 chars + "...")
     pub sender_key_short: String,
-    /// The message content
+    /// Sender's nickname, if they set one
+    pub sender_nickname: Option<String>,
+    /// Label this user gave the sender in their contact list
+    pub sender_label: Option<String>,
+    /// The message content, as last edited
     pub content: String,
+    /// Earlier texts of an edited message, the original first
+    pub previous_versions: Vec<String>,
+    /// Whether the sender retracted the message; `content` is a tombstone
+    pub is_retracted: bool,
+    /// Keys that reacted with each emoji
+    pub reactions: BTreeMap<String, Vec<String>>,
     /// Formatted timestamp (HH:MM:SS)
     pub timestamp: String,
     /// The cryptographic signature (hex-encoded)
@@ -64,8 +79,16 @@ pub struct DisplayMessage {
     pub is_verified: bool,
     /// Whether the message is from self
     pub is_self: bool,
+    /// Whether the recipient has sent a read receipt (only set for own messages)
+    pub is_read: bool,
+    /// Delivery state from the outbox (only set for own messages)
+    pub send_state: Option<SendState>,
+    /// Whether the user starred this message
+    pub is_starred: bool,
     /// Original timestamp for ordering
     pub original_timestamp: String,
+    /// Rendering in the user's language, shown beside `content`
+    pub translation: Option<Translation>,
 }
 
 impl DisplayMessage {
@@ -84,18 +107,40 @@ impl DisplayMessage {
         let timestamp = format_timestamp(&msg.timestamp);
 
         Self {
-            id: format!("msg-{}", msg.timestamp),
+            id: msg.message_id.to_string(),
             sender_key: msg.sender_public_key.clone(),
             sender_key_short,
-            content: msg.message.clone(),
+            sender_nickname: None,
+            sender_label: None,
+            content: msg.current_text().to_string(),
+            previous_versions: std::iter::once(&msg.message)
+                .chain(msg.edits.iter().map(|edit| &edit.message))
+                .take(msg.edits.len())
+                .cloned()
+                .collect(),
+            is_retracted: msg.is_retracted(),
+            reactions: msg.reactions.clone(),
             timestamp,
             signature: msg.signature.clone(),
             is_verified: msg.is_verified,
             is_self,
+            is_read: false,
+            send_state: None,
+            is_starred: false,
             original_timestamp: msg.timestamp.clone(),
+            translation: None,
         }
     }
 
+    /// Name to show for the sender: the contact label, then the nickname,
+    /// then the shortened key
+    pub fn sender_display_name(&self) -> &str {
+        self.sender_label
+            .as_deref()
+            .or(self.sender_nickname.as_deref())
+            .unwrap_or(&self.sender_key_short)
+    }
+
     /// Get the verification badge text
     pub fn verification_badge(&self) -> String {
         if self.is_verified {
@@ -104,6 +149,64 @@ impl DisplayMessage {
             "".to_string()
         }
     }
+
+    /// Whether the sender edited the message
+    pub fn is_edited(&self) -> bool {
+        !self.previous_versions.is_empty()
+    }
+
+    /// Get the edit marker text
+    pub fn edit_marker(&self) -> String {
+        if self.is_edited() {
+            "(edited)".to_string()
+        } else {
+            "".to_string()
+        }
+    }
+
+    /// Get the reactions text: each emoji with how many reacted with it
+    pub fn reaction_summary(&self) -> String {
+        self.reactions
+            .iter()
+            .map(|(emoji, reactors)| format!("{} {}", emoji, reactors.len()))
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+
+    /// Get the read status text (only shown for own messages)
+    pub fn read_status(&self) -> String {
+        if self.is_self && self.is_read {
+            "Read".to_string()
+        } else {
+            "".to_string()
+        }
+    }
+
+    /// Get the star marker text
+    pub fn star_marker(&self) -> String {
+        if self.is_starred {
+            "★".to_string()
+        } else {
+            "".to_string()
+        }
+    }
+
+    /// Get the translation text, labelled with its language
+    pub fn translation_text(&self) -> String {
+        match &self.translation {
+            Some(translation) => format!("[{}] {}", translation.language, translation.text),
+            None => "".to_string(),
+        }
+    }
+
+    /// Get the delivery status text (only shown for own messages, and
+    /// replaced by the read status once read)
+    pub fn send_status(&self) -> String {
+        match self.send_state {
+            Some(state) if self.is_self && !self.is_read => state.label().to_string(),
+            _ => "".to_string(),
+        }
+    }
 }
 
 /// Format ISO 8601 timestamp to HH:MM:SS
@@ -154,8 +257,31 @@ pub struct ChatView {
     messages: Vec<DisplayMessage>,
     /// Whether user is currently scrolling (prevents auto-scroll)
     is_user_scrolling: bool,
-    /// ID of the currently selected recipient
-    selected_recipient: Option<String>,
+    /// Conversation with the currently selected recipient
+    selected_recipient: Option<ConversationId>,
+    /// IDs of own messages the recipient has read
+    read_message_ids: HashSet<String>,
+    /// IDs of received messages a read receipt was already emitted for
+    acknowledged_message_ids: HashSet<String>,
+    /// Delivery state of own messages, by message ID
+    send_states: HashMap<String, SendState>,
+    /// IDs of starred messages
+    starred_message_ids: HashSet<String>,
+    /// Message the view was asked to show, e.g. after jumping from a star
+    focused_message_id: Option<String>,
+    /// Whether the selected recipient has this user's conversation open
+    peer_viewing: bool,
+    /// When the selected recipient was last online (RFC 3339), if they
+    /// are offline and share it
+    peer_last_seen: Option<String>,
+    /// Known nicknames by public key
+    nicknames: HashMap<String, String>,
+    /// Contact labels by public key
+    contact_labels: HashMap<String, String>,
+    /// State-event journal every change is recorded to, if enabled
+    journal: JournalHandle,
+    /// Attaches translations to received messages, if enabled
+    translator: Option<MessageTranslator>,
 }
 
 impl ChatView {
@@ -165,11 +291,35 @@ impl ChatView {
             messages: Vec::new(),
             is_user_scrolling: false,
             selected_recipient: None,
+            read_message_ids: HashSet::new(),
+            acknowledged_message_ids: HashSet::new(),
+            send_states: HashMap::new(),
+            starred_message_ids: HashSet::new(),
+            focused_message_id: None,
+            peer_viewing: false,
+            peer_last_seen: None,
+            nicknames: HashMap::new(),
+            contact_labels: HashMap::new(),
+            journal: None,
+            translator: None,
         }
     }
 
+    /// Record every change to a state-event journal (None to stop)
+    pub fn set_journal(&mut self, journal: JournalHandle) {
+        self.journal = journal;
+    }
+
+    /// Translate received messages with `translator` (None to stop)
+    ///
+    /// Applies to messages displayed from now on.
+    pub fn set_translator(&mut self, translator: Option<MessageTranslator>) {
+        self.translator = translator;
+    }
+
     /// Set whether user is scrolling
     pub fn set_user_scrolling(&mut self, scrolling: bool) {
+        journal_event(&self.journal, || StateEvent::ChatSetScrolling { scrolling });
         self.is_user_scrolling = scrolling;
     }
 
@@ -179,13 +329,21 @@ impl ChatView {
     }
 
     /// Set the selected recipient
-    pub fn set_selected_recipient(&mut self, recipient_key: Option<String>) {
-        self.selected_recipient = recipient_key;
+    pub fn set_selected_recipient(&mut self, recipient: Option<ConversationId>) {
+        journal_event(&self.journal, || StateEvent::ChatSetRecipient {
+            recipient: recipient.clone(),
+        });
+        if self.selected_recipient != recipient {
+            self.focused_message_id = None;
+            self.peer_viewing = false;
+            self.peer_last_seen = None;
+        }
+        self.selected_recipient = recipient;
     }
 
     /// Get the selected recipient
-    pub fn selected_recipient(&self) -> Option<&str> {
-        self.selected_recipient.as_deref()
+    pub fn selected_recipient(&self) -> Option<&ConversationId> {
+        self.selected_recipient.as_ref()
     }
 
     /// Get all messages for display
@@ -212,6 +370,223 @@ impl ChatView {
     pub fn is_newest_message(&self, id: &str) -> bool {
         self.newest_message_id() == Some(id)
     }
+
+    /// Record that the recipient has read one of our messages
+    ///
+    /// The read state is kept across view refreshes. Returns true if a
+    /// currently displayed message changed state.
+    pub fn mark_read(&mut self, message_id: &str) -> bool {
+        journal_event(&self.journal, || StateEvent::ChatMarkRead {
+            message_id: message_id.to_string(),
+        });
+        self.read_message_ids.insert(message_id.to_string());
+        let mut changed = false;
+        for msg in self.messages.iter_mut() {
+            if msg.is_self && !msg.is_read && msg.id == message_id {
+                msg.is_read = true;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Check if a message has been read by the recipient
+    pub fn is_read(&self, message_id: &str) -> bool {
+        self.read_message_ids.contains(message_id)
+    }
+
+    /// Record that a read receipt was emitted for a received message
+    ///
+    /// Returns false if a receipt was already emitted for this ID.
+    pub fn mark_acknowledged(&mut self, message_id: &str) -> bool {
+        journal_event(&self.journal, || StateEvent::ChatMarkAcknowledged {
+            message_id: message_id.to_string(),
+        });
+        self.acknowledged_message_ids.insert(message_id.to_string())
+    }
+
+    /// Record the delivery state of one of our messages
+    ///
+    /// The state is kept across view refreshes. Returns true if a currently
+    /// displayed message changed state.
+    pub fn set_send_state(&mut self, message_id: &str, state: SendState) -> bool {
+        journal_event(&self.journal, || StateEvent::ChatSetSendState {
+            message_id: message_id.to_string(),
+            state,
+        });
+        self.send_states.insert(message_id.to_string(), state);
+        let mut changed = false;
+        for msg in self.messages.iter_mut() {
+            if msg.is_self && msg.id == message_id && msg.send_state != Some(state) {
+                msg.send_state = Some(state);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Delivery state of one of our messages, if the outbox reported one
+    pub fn send_state(&self, message_id: &str) -> Option<SendState> {
+        self.send_states.get(message_id).copied()
+    }
+
+    /// Replace the set of starred message IDs
+    ///
+    /// Returns true if a currently displayed message changed state.
+    pub fn set_starred_ids<I>(&mut self, message_ids: I) -> bool
+    where
+        I: IntoIterator<Item = String>,
+    {
+        self.starred_message_ids = message_ids.into_iter().collect();
+        journal_event(&self.journal, || StateEvent::ChatSetStarred {
+            message_ids: self.starred_message_ids.iter().cloned().collect(),
+        });
+        let mut changed = false;
+        for msg in self.messages.iter_mut() {
+            let is_starred = self.starred_message_ids.contains(&msg.id);
+            if msg.is_starred != is_starred {
+                msg.is_starred = is_starred;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Check if a message is starred
+    pub fn is_starred(&self, message_id: &str) -> bool {
+        self.starred_message_ids.contains(message_id)
+    }
+
+    /// Show a message in context instead of the newest messages
+    ///
+    /// Holds off auto-scroll so the message stays in view. Returns the
+    /// message's position, or None if it is not displayed.
+    pub fn focus_message(&mut self, message_id: &str) -> Option<usize> {
+        let index = self.messages.iter().position(|m| m.id == message_id)?;
+        journal_event(&self.journal, || StateEvent::ChatFocus {
+            message_id: message_id.to_string(),
+        });
+        self.focused_message_id = Some(message_id.to_string());
+        self.is_user_scrolling = true;
+        Some(index)
+    }
+
+    /// Message the view was last asked to show, if any
+    pub fn focused_message(&self) -> Option<&str> {
+        self.focused_message_id.as_deref()
+    }
+
+    /// Record whether the selected recipient has this user's conversation
+    /// open
+    ///
+    /// Returns true if the hint changed.
+    pub fn set_peer_viewing(&mut self, viewing: bool) -> bool {
+        journal_event(&self.journal, || StateEvent::ChatSetPeerViewing { viewing });
+        std::mem::replace(&mut self.peer_viewing, viewing) != viewing
+    }
+
+    /// Hint shown while the selected recipient has the conversation open
+    pub fn viewing_hint(&self) -> &'static str {
+        if self.peer_viewing {
+            "Viewing your conversation"
+        } else {
+            ""
+        }
+    }
+
+    /// Record when the selected recipient was last online (None to clear)
+    ///
+    /// Returns true if the hint changed.
+    pub fn set_peer_last_seen(&mut self, last_seen: Option<String>) -> bool {
+        journal_event(&self.journal, || StateEvent::ChatSetPeerLastSeen {
+            last_seen: last_seen.clone(),
+        });
+        std::mem::replace(&mut self.peer_last_seen, last_seen.clone()) != last_seen
+    }
+
+    /// Hint shown while the selected recipient is offline, e.g.
+    /// "Last seen 2025-12-20 10:00 UTC"
+    pub fn last_seen_hint(&self) -> String {
+        match self.peer_last_seen.as_deref() {
+            Some(last_seen) => match DateTime::parse_from_rfc3339(last_seen) {
+                Ok(at) => format!(
+                    "Last seen {}",
+                    at.with_timezone(&Utc).format("%Y-%m-%d %H:%M UTC")
+                ),
+                Err(_) => format!("Last seen {}", last_seen),
+            },
+            None => String::new(),
+        }
+    }
+
+    /// Record a user's nickname, or clear it
+    ///
+    /// The nickname is kept across view refreshes. Returns true if a
+    /// currently displayed message changed state.
+    pub fn set_nickname(&mut self, public_key: &str, nickname: Option<String>) -> bool {
+        journal_event(&self.journal, || StateEvent::ChatSetNickname {
+            public_key: public_key.to_string(),
+            nickname: nickname.clone(),
+        });
+        match &nickname {
+            Some(name) => self.nicknames.insert(public_key.to_string(), name.clone()),
+            None => self.nicknames.remove(public_key),
+        };
+        let mut changed = false;
+        for msg in self.messages.iter_mut() {
+            if msg.sender_key == public_key && msg.sender_nickname != nickname {
+                msg.sender_nickname = nickname.clone();
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Replace the contact labels shown for senders
+    ///
+    /// Keys may be in any case. Returns true if a currently displayed
+    /// message changed state.
+    pub fn set_contact_labels(&mut self, labels: HashMap<String, String>) -> bool {
+        journal_event(&self.journal, || StateEvent::ChatSetContactLabels {
+            labels: labels.clone(),
+        });
+        self.contact_labels = labels
+            .into_iter()
+            .map(|(key, label)| (key.to_ascii_lowercase(), label))
+            .collect();
+        let mut changed = false;
+        for msg in self.messages.iter_mut() {
+            let label = self
+                .contact_labels
+                .get(&msg.sender_key.to_ascii_lowercase())
+                .cloned();
+            if msg.sender_label != label {
+                msg.sender_label = label;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Build a display message, applying any known read and delivery state,
+    /// nickname and contact label, and translating it if enabled
+    fn display_message(&self, msg: &ChatMessage, is_self: bool) -> DisplayMessage {
+        let mut display_msg = DisplayMessage::from_chat_message(msg, is_self);
+        display_msg.is_read = is_self && self.is_read(&display_msg.id);
+        if is_self {
+            display_msg.send_state = self.send_state(&display_msg.id);
+        }
+        display_msg.is_starred = self.is_starred(&display_msg.id);
+        display_msg.sender_nickname = self.nicknames.get(&msg.sender_public_key).cloned();
+        display_msg.sender_label = self
+            .contact_labels
+            .get(&msg.sender_public_key.to_ascii_lowercase())
+            .cloned();
+        if let Some(translator) = &self.translator {
+            translator.on_message_display(&mut display_msg);
+        }
+        display_msg
+    }
 }
 
 impl Default for ChatView {
@@ -252,22 +627,34 @@ pub async fn update_chat_view(
         Some(key) => key,
         None => {
             // No recipient selected, clear messages
-            chat_view.messages.clear();
+            clear_chat(chat_view);
             return;
         }
     };
 
     // Get messages from this recipient
-    let messages: Vec<DisplayMessage> = history
-        .messages_from(recipient)
+    let messages = history.messages_from(recipient);
+    set_messages(chat_view, &messages, my_public_key);
+}
+
+/// Replace the displayed messages
+///
+/// # Arguments
+/// * `chat_view` - The chat view to update
+/// * `messages` - The conversation's messages, oldest first
+/// * `my_public_key` - Current user's public key for self-detection
+pub fn set_messages(chat_view: &mut ChatView, messages: &[&ChatMessage], my_public_key: &str) {
+    journal_event(&chat_view.journal, || StateEvent::ChatSetMessages {
+        messages: messages.iter().map(|&msg| msg.clone()).collect(),
+        my_public_key: my_public_key.to_string(),
+    });
+    chat_view.messages = messages
         .iter()
         .map(|msg| {
             let is_self = msg.sender_public_key == my_public_key;
-            DisplayMessage::from_chat_message(msg, is_self)
+            chat_view.display_message(msg, is_self)
         })
         .collect();
-
-    chat_view.messages = messages;
 }
 
 /// Add a single new message to the chat view
@@ -279,15 +666,50 @@ pub async fn update_chat_view(
 /// * `message` - The new message
 /// * `my_public_key` - Current user's public key
 pub fn add_message(chat_view: &mut ChatView, message: &ChatMessage, my_public_key: &str) {
+    // The same message can arrive twice, e.g. live and again from history
+    let id = message.message_id.to_string();
+    if chat_view.messages.iter().any(|m| m.id == id) {
+        return;
+    }
+    journal_event(&chat_view.journal, || StateEvent::ChatAddMessage {
+        message: message.clone(),
+        my_public_key: my_public_key.to_string(),
+    });
+
     let is_self = message.sender_public_key == my_public_key;
-    let display_msg = DisplayMessage::from_chat_message(message, is_self);
+    let display_msg = chat_view.display_message(message, is_self);
 
     // Add to end (newest position) maintaining order
     chat_view.messages.push(display_msg);
 }
 
+/// Redraw a displayed message after it changed in the history, e.g. was
+/// edited, retracted or reacted to
+///
+/// # Returns
+/// false if the message isn't displayed
+pub fn update_message(
+    chat_view: &mut ChatView,
+    message: &ChatMessage,
+    my_public_key: &str,
+) -> bool {
+    let id = message.message_id.to_string();
+    let Some(index) = chat_view.messages.iter().position(|m| m.id == id) else {
+        return false;
+    };
+    journal_event(&chat_view.journal, || StateEvent::ChatUpdateMessage {
+        message: message.clone(),
+        my_public_key: my_public_key.to_string(),
+    });
+
+    let is_self = message.sender_public_key == my_public_key;
+    chat_view.messages[index] = chat_view.display_message(message, is_self);
+    true
+}
+
 /// Clear all messages from chat view
 pub fn clear_chat(chat_view: &mut ChatView) {
+    journal_event(&chat_view.journal, || StateEvent::ChatClear);
     chat_view.messages.clear();
 }
 
@@ -463,8 +885,9 @@ mod tests {
         let mut view = ChatView::new();
         assert!(view.selected_recipient().is_none());
 
-        view.set_selected_recipient(Some("recipient_key".to_string()));
-        assert_eq!(view.selected_recipient(), Some("recipient_key"));
+        let recipient = ConversationId::new(&"ab".repeat(32)).unwrap();
+        view.set_selected_recipient(Some(recipient.clone()));
+        assert_eq!(view.selected_recipient(), Some(&recipient));
     }
 
     #[tokio::test]
@@ -476,21 +899,21 @@ mod tests {
         {
             let mut h = history.lock().await;
             h.add_message(ChatMessage::new(
-                "sender1".to_string(),
+                "11".repeat(32),
                 "First".to_string(),
                 "sig1".to_string(),
                 "2025-12-27T10:00:00Z".to_string(),
             ));
             h.add_message(ChatMessage::new(
-                "sender2".to_string(),
+                "2B".repeat(32),
                 "Second".to_string(),
                 "sig2".to_string(),
                 "2025-12-27T10:01:00Z".to_string(),
             ));
         }
 
-        // Select recipient
-        view.set_selected_recipient(Some("sender2".to_string()));
+        // Select recipient, key in a different case than it arrived in
+        view.set_selected_recipient(Some(ConversationId::new(&"2b".repeat(32)).unwrap()));
 
         // Update view
         update_chat_view(&mut view, &history, "me").await;
@@ -514,7 +937,13 @@ mod tests {
         add_message(&mut view, &msg, "me");
         assert_eq!(view.message_count(), 1);
 
-        add_message(&mut view, &msg, "other");
+        let reply = ChatMessage::new(
+            "other".to_string(),
+            "Test".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:30:00Z".to_string(),
+        );
+        add_message(&mut view, &reply, "me");
         assert_eq!(view.message_count(), 2);
     }
 
@@ -543,47 +972,218 @@ mod tests {
         let mut view = ChatView::new();
         assert!(view.newest_message_id().is_none());
 
-        view.messages.push(DisplayMessage::from_chat_message(
-            &ChatMessage::new(
-                "k".to_string(),
-                "m1".to_string(),
-                "s".to_string(),
-                "2025-12-27T10:00:00Z".to_string(),
-            ),
-            false,
-        ));
-        assert_eq!(view.newest_message_id(), Some("msg-2025-12-27T10:00:00Z"));
+        let first = ChatMessage::new(
+            "k".to_string(),
+            "m1".to_string(),
+            "s".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        view.messages
+            .push(DisplayMessage::from_chat_message(&first, false));
+        assert_eq!(
+            view.newest_message_id(),
+            Some(first.message_id.to_string().as_str())
+        );
 
-        view.messages.push(DisplayMessage::from_chat_message(
-            &ChatMessage::new(
-                "k".to_string(),
-                "m2".to_string(),
-                "s".to_string(),
-                "2025-12-27T10:01:00Z".to_string(),
-            ),
-            false,
-        ));
-        assert_eq!(view.newest_message_id(), Some("msg-2025-12-27T10:01:00Z"));
+        let second = ChatMessage::new(
+            "k".to_string(),
+            "m2".to_string(),
+            "s".to_string(),
+            "2025-12-27T10:01:00Z".to_string(),
+        );
+        view.messages
+            .push(DisplayMessage::from_chat_message(&second, false));
+        assert_eq!(
+            view.newest_message_id(),
+            Some(second.message_id.to_string().as_str())
+        );
     }
 
     #[test]
     fn test_is_newest_message() {
         let mut view = ChatView::new();
 
-        view.messages.push(DisplayMessage::from_chat_message(
-            &ChatMessage::new(
-                "k".to_string(),
-                "m1".to_string(),
-                "s".to_string(),
-                "2025-12-27T10:00:00Z".to_string(),
-            ),
-            false,
-        ));
+        let msg = ChatMessage::new(
+            "k".to_string(),
+            "m1".to_string(),
+            "s".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        view.messages
+            .push(DisplayMessage::from_chat_message(&msg, false));
 
-        assert!(view.is_newest_message("msg-2025-12-27T10:00:00Z"));
+        assert!(view.is_newest_message(&msg.message_id.to_string()));
         assert!(!view.is_newest_message("msg-old"));
     }
 
+    #[test]
+    fn test_mark_read_only_affects_own_messages() {
+        let mut view = ChatView::new();
+        let msg = ChatMessage::new(
+            "me".to_string(),
+            "Hello".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let id = msg.message_id.to_string();
+        add_message(&mut view, &msg, "me");
+        view.messages
+            .push(DisplayMessage::from_chat_message(&msg, false));
+
+        assert!(view.mark_read(&id));
+        assert!(view.messages()[0].is_read);
+        assert_eq!(view.messages()[0].read_status(), "Read");
+        assert!(!view.messages()[1].is_read);
+        assert_eq!(view.messages()[1].read_status(), "");
+
+        // Already read - nothing changes
+        assert!(!view.mark_read(&id));
+    }
+
+    #[test]
+    fn test_send_state_shown_until_read() {
+        let mut view = ChatView::new();
+        let msg = ChatMessage::new(
+            "me".to_string(),
+            "Hello".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        let id = msg.message_id.to_string();
+        view.set_send_state(&id, SendState::Queued);
+
+        add_message(&mut view, &msg, "me");
+        assert_eq!(view.messages()[0].send_state, Some(SendState::Queued));
+        assert_eq!(view.messages()[0].send_status(), "Queued");
+
+        assert!(view.set_send_state(&id, SendState::Sent));
+        assert!(!view.set_send_state(&id, SendState::Sent));
+        assert_eq!(view.messages()[0].send_status(), "Sent");
+
+        view.mark_read(&id);
+        assert_eq!(view.messages()[0].send_status(), "");
+        assert_eq!(view.messages()[0].read_status(), "Read");
+    }
+
+    #[test]
+    fn test_read_state_survives_refresh() {
+        let mut view = ChatView::new();
+        let msg = ChatMessage::new(
+            "me".to_string(),
+            "Hello".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        view.mark_read(&msg.message_id.to_string());
+
+        add_message(&mut view, &msg, "me");
+
+        assert!(view.is_read(&msg.message_id.to_string()));
+        assert!(view.messages()[0].is_read);
+    }
+
+    #[test]
+    fn test_nickname_applies_to_shown_and_future_messages() {
+        let mut view = ChatView::new();
+        let msg = |sender: &str| {
+            ChatMessage::new(
+                sender.to_string(),
+                "Hello".to_string(),
+                "sig".to_string(),
+                "2025-12-27T10:00:00Z".to_string(),
+            )
+        };
+        add_message(&mut view, &msg("alice"), "me");
+        add_message(&mut view, &msg("me"), "me");
+        assert_eq!(view.messages()[0].sender_display_name(), "alice");
+
+        assert!(view.set_nickname("alice", Some("Alice".to_string())));
+        assert!(!view.set_nickname("alice", Some("Alice".to_string())));
+        assert_eq!(view.messages()[0].sender_display_name(), "Alice");
+        assert_eq!(view.messages()[1].sender_nickname, None);
+
+        add_message(&mut view, &msg("alice"), "me");
+        assert_eq!(view.messages()[2].sender_display_name(), "Alice");
+
+        assert!(view.set_nickname("alice", None));
+        assert_eq!(view.messages()[2].sender_display_name(), "alice");
+    }
+
+    #[test]
+    fn test_contact_label_takes_precedence() {
+        let alice = "ab".repeat(32);
+        let mut view = ChatView::new();
+        let msg = ChatMessage::new(
+            alice.clone(),
+            "Hello".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        view.set_nickname(&alice, Some("Alice".to_string()));
+        add_message(&mut view, &msg, "me");
+
+        let mut contacts = crate::state::Contacts::new();
+        contacts.add(&alice, "Work Alice").unwrap();
+
+        assert!(view.set_contact_labels(contacts.labels()));
+        assert!(!view.set_contact_labels(contacts.labels()));
+        assert_eq!(view.messages()[0].sender_display_name(), "Work Alice");
+
+        let later = ChatMessage::new(
+            alice.clone(),
+            "Again".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:01:00Z".to_string(),
+        );
+        add_message(&mut view, &later, "me");
+        assert_eq!(
+            view.messages()[1].sender_label.as_deref(),
+            Some("Work Alice")
+        );
+
+        assert!(view.set_contact_labels(HashMap::new()));
+        assert_eq!(view.messages()[0].sender_display_name(), "Alice");
+    }
+
+    #[test]
+    fn test_update_message_shows_edits_and_reactions() {
+        let mut view = ChatView::new();
+        let mut msg = ChatMessage::new(
+            "alice".to_string(),
+            "helo".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:00:00Z".to_string(),
+        );
+        add_message(&mut view, &msg, "me");
+        assert_eq!(view.messages()[0].edit_marker(), "");
+        assert_eq!(view.messages()[0].reaction_summary(), "");
+
+        msg.edits.push(crate::state::messages::MessageEdit {
+            message: "hello".to_string(),
+            signature: "edit-sig".to_string(),
+            timestamp: "2025-12-27T10:01:00Z".to_string(),
+        });
+        msg.reactions
+            .insert("👍".to_string(), vec!["me".to_string(), "bob".to_string()]);
+        msg.reactions
+            .insert("🎉".to_string(), vec!["me".to_string()]);
+        assert!(update_message(&mut view, &msg, "me"));
+
+        let shown = &view.messages()[0];
+        assert_eq!(shown.content, "hello");
+        assert_eq!(shown.previous_versions, vec!["helo"]);
+        assert_eq!(shown.edit_marker(), "(edited)");
+        assert_eq!(shown.reaction_summary(), "🎉 1  👍 2");
+
+        let other = ChatMessage::new(
+            "alice".to_string(),
+            "Not shown".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:02:00Z".to_string(),
+        );
+        assert!(!update_message(&mut view, &other, "me"));
+    }
+
     #[test]
     fn test_display_message_short_key_truncation() {
         // Test that short keys are not truncated
@@ -663,21 +1263,38 @@ mod tests {
             ),
             false,
         ));
-        view.messages.push(DisplayMessage::from_chat_message(
-            &ChatMessage::new(
-                "k".to_string(),
-                "msg3".to_string(),
-                "s".to_string(),
-                "2025-12-27T10:02:00Z".to_string(),
-            ),
-            false,
-        ));
+        let newest = ChatMessage::new(
+            "k".to_string(),
+            "msg3".to_string(),
+            "s".to_string(),
+            "2025-12-27T10:02:00Z".to_string(),
+        );
+        view.messages
+            .push(DisplayMessage::from_chat_message(&newest, false));
 
         assert_eq!(view.message_count(), 3);
         assert_eq!(view.messages[0].content, "msg1");
         assert_eq!(view.messages[1].content, "msg2");
         assert_eq!(view.messages[2].content, "msg3");
-        assert_eq!(view.newest_message_id(), Some("msg-2025-12-27T10:02:00Z"));
+        assert_eq!(
+            view.newest_message_id(),
+            Some(newest.message_id.to_string().as_str())
+        );
+
+        // Two messages sent in the same second still get distinct IDs
+        let again = ChatMessage::new(
+            "k".to_string(),
+            "msg3".to_string(),
+            "s".to_string(),
+            "2025-12-27T10:02:00Z".to_string(),
+        );
+        add_message(&mut view, &again, "me");
+        assert_eq!(view.message_count(), 4);
+        assert_ne!(view.messages[2].id, view.messages[3].id);
+
+        // Re-adding a message that is already shown is a no-op
+        add_message(&mut view, &again, "me");
+        assert_eq!(view.message_count(), 4);
     }
 
     #[test]
@@ -703,4 +1320,27 @@ mod tests {
         let display = DisplayMessage::from_chat_message(&unverified, false);
         assert_eq!(display.verification_badge(), "");
     }
+
+    #[test]
+    fn test_translator_applies_to_received_messages() {
+        use crate::ui::translation::OfflineDictionary;
+
+        let dictionary = OfflineDictionary {
+            language: "es".to_string(),
+            words: HashMap::from([("hello".to_string(), "hola".to_string())]),
+        };
+        let mut view = ChatView::new();
+        view.set_translator(Some(MessageTranslator::new(Arc::new(dictionary), "es")));
+        let msg = ChatMessage::new(
+            "alice".to_string(),
+            "hello".to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:30:00Z".to_string(),
+        );
+        add_message(&mut view, &msg, "me");
+
+        let shown = &view.messages()[0];
+        assert_eq!(shown.content, "hello");
+        assert_eq!(shown.translation_text(), "[es] hola");
+    }
 }
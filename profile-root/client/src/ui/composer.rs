@@ -7,8 +7,10 @@ use crate::state::composer::SharedComposerState;
 use crate::state::lobby::SharedLobbyState;
 use crate::state::messages::{ChatMessage, SharedMessageHistory};
 use crate::state::session::SharedKeyState;
+use crate::state::timing::SharedMessageTimings;
 use crate::ui::lobby_state::LobbyUser;
 use hex;
+use profile_shared::config::message::MAX_MESSAGE_LENGTH;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -27,6 +29,8 @@ pub enum SendMessageResult {
     NoRecipient,
     /// No message text entered
     EmptyMessage,
+    /// Message text is longer than the server accepts
+    MessageTooLong { length: usize, max: usize },
     /// User disconnected from server
     Disconnected,
     /// Cryptographic signing failed
@@ -52,6 +56,8 @@ pub struct MessageComposer {
     send_callback: Option<SendMessageCallback>,
     /// Callback for showing status to user
     status_callback: Option<StatusCallback>,
+    /// Per-message timings, if latency is being tracked
+    message_timings: Option<SharedMessageTimings>,
 }
 
 impl MessageComposer {
@@ -69,6 +75,7 @@ impl MessageComposer {
             message_history,
             send_callback: None,
             status_callback: None,
+            message_timings: None,
         }
     }
 
@@ -90,6 +97,11 @@ impl MessageComposer {
         self.status_callback = Some(Arc::new(callback));
     }
 
+    /// Record composed and sent times of sent messages in `timings`
+    pub fn set_message_timings(&mut self, timings: SharedMessageTimings) {
+        self.message_timings = Some(timings);
+    }
+
     /// Show status message to user
     fn show_status(&self, message: &str) {
         if let Some(ref callback) = self.status_callback {
@@ -121,6 +133,19 @@ impl MessageComposer {
             return SendMessageResult::EmptyMessage;
         }
 
+        // Refuse here what the server would reject as message_too_large
+        if message_text.len() > MAX_MESSAGE_LENGTH {
+            self.show_status(&format!(
+                "Message is too long ({} of {} bytes)",
+                message_text.len(),
+                MAX_MESSAGE_LENGTH
+            ));
+            return SendMessageResult::MessageTooLong {
+                length: message_text.len(),
+                max: MAX_MESSAGE_LENGTH,
+            };
+        }
+
         // AC1: Get selected recipient
         let recipient = match self.get_selected_recipient().await {
             Some(r) => r,
@@ -185,7 +210,13 @@ impl MessageComposer {
                         message_text.to_string(),
                         client_message.signature.clone(),
                         client_message.timestamp.clone(),
-                    );
+                    )
+                    .with_message_id(client_message.message_id);
+                    if let Some(timings) = &self.message_timings {
+                        let mut timings = timings.lock().await;
+                        timings.record_message(&chat_message);
+                        timings.record_sent(chat_message.message_id, chrono::Utc::now());
+                    }
                     let mut history = self.message_history.lock().await;
                     history.add_message(chat_message);
 
@@ -318,6 +349,24 @@ mod tests {
         assert!(matches!(result, SendMessageResult::EmptyMessage));
     }
 
+    #[tokio::test]
+    async fn test_send_message_too_long() {
+        let composer = create_message_composer(
+            create_shared_key_state(),
+            create_shared_composer_state(),
+            create_shared_lobby_state(),
+            create_shared_message_history(),
+        );
+
+        let text = "x".repeat(MAX_MESSAGE_LENGTH + 1);
+        let result = composer.lock().await.send_message(&text).await;
+        assert!(matches!(
+            result,
+            SendMessageResult::MessageTooLong { length, max }
+                if length == MAX_MESSAGE_LENGTH + 1 && max == MAX_MESSAGE_LENGTH
+        ));
+    }
+
     #[tokio::test]
     async fn test_send_no_recipient() {
         let key_state = create_shared_key_state();
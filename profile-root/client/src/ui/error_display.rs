@@ -3,14 +3,25 @@
 //! Maps technical error codes to user-friendly messages
 
 /// Display user-friendly connection error message
+///
+/// A retry hint appended to the reason (see
+/// [`profile_shared::protocol::split_retry_hint`]) is ignored.
 pub fn display_connection_error(reason: &str) -> String {
+    let (reason, _) = profile_shared::protocol::split_retry_hint(reason);
     match reason {
         "auth_failed" => {
             "Authentication failed. Your signature could not be verified. Try again or check your key.".to_string()
         }
+        "resume_failed" => "Session expired. Signing in again.".to_string(),
+        "banned" => {
+            "This key has been banned from the server. Contact the server operator.".to_string()
+        }
         "server_shutdown" => {
             "Server maintenance. Reconnect to continue.".to_string()
         }
+        "server_busy" => {
+            "Server is busy with other reconnections. Retrying shortly.".to_string()
+        }
         "timeout" => {
             "Connection timeout. Check your network and try reconnecting.".to_string()
         }
@@ -36,9 +47,19 @@ mod tests {
         assert!(msg.contains("Authentication failed"));
         assert!(msg.contains("signature"));
 
+        // Test banned
+        let msg = display_connection_error("banned");
+        assert!(msg.contains("banned"));
+
         // Test server_shutdown
         let msg = display_connection_error("server_shutdown");
         assert!(msg.contains("Server maintenance"));
+        // A retry hint doesn't change the message
+        assert_eq!(
+            display_connection_error("server_shutdown; retry_after_ms=2500"),
+            msg
+        );
+        assert!(display_connection_error("server_busy; retry_after_ms=900").contains("busy"));
 
         // Test timeout
         let msg = display_connection_error("timeout");
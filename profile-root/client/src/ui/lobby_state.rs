@@ -4,6 +4,9 @@
 //! - List of users in the lobby (maintains insertion order for deterministic UI)
 //! - Selection state (which user is selected for messaging)
 //! - User operations (add, remove, select, deselect)
+//! - Unread message counts per user, cleared when the user is selected
+//! - A revision bumped on every change, and whether the list was restored
+//!   from the lobby cache and not yet refreshed by the server
 //!
 //! All lobby state changes happen through this module to ensure consistency.
 //!
@@ -14,7 +17,12 @@
 //! - UI displays users in a stable, consistent order
 //! - User selection by index is reliable
 
+use crate::state::conversation::ConversationId;
+use crate::state::journal::{journal_event, serializable_users, JournalHandle, StateEvent};
+use crate::state::lobby_cache::LobbyCache;
+use profile_shared::protocol::Status;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Represents a user displayed in the lobby
 #[derive(Debug, Clone, PartialEq)]
@@ -23,6 +31,14 @@ pub struct LobbyUser {
     pub public_key: String,
     /// Whether the user is currently online
     pub is_online: bool,
+    /// Status the user chose: online, away, busy or (for ourselves) invisible
+    pub status: Status,
+    /// Nickname chosen by the user, if any
+    pub nickname: Option<String>,
+    /// Name the user claimed, verified and kept unique by the server
+    pub verified_name: Option<String>,
+    /// Messages from this user received while they weren't selected
+    pub unread_count: usize,
 }
 
 impl LobbyUser {
@@ -32,16 +48,64 @@ impl LobbyUser {
         Self {
             public_key,
             is_online,
+            status: Status::Online,
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
+        }
+    }
+
+    /// Set the status the user chose
+    #[inline]
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Set the user's nickname
+    #[inline]
+    pub fn with_nickname(mut self, nickname: Option<String>) -> Self {
+        self.nickname = nickname;
+        self
+    }
+
+    /// Set the name the user claimed
+    #[inline]
+    pub fn with_verified_name(mut self, verified_name: Option<String>) -> Self {
+        self.verified_name = verified_name;
+        self
+    }
+
+    /// Name to show for this user: the verified name, then the nickname,
+    /// then the shortened public key
+    pub fn display_name(&self) -> String {
+        match self.verified_name.as_ref().or(self.nickname.as_ref()) {
+            Some(name) => name.clone(),
+            None => crate::handlers::verify::format_public_key(&self.public_key),
         }
     }
 }
 
 /// Serializable lobby user for state persistence
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LobbyUserSerializable {
     #[serde(rename = "publicKey")]
     pub public_key: String,
     pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    #[serde(
+        rename = "verifiedName",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub verified_name: Option<String>,
+    #[serde(rename = "unreadCount", default, skip_serializing_if = "is_zero")]
+    pub unread_count: usize,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
 }
 
 impl From<LobbyUser> for LobbyUserSerializable {
@@ -49,27 +113,50 @@ impl From<LobbyUser> for LobbyUserSerializable {
         Self {
             public_key: user.public_key,
             status: if user.is_online {
-                "online".to_string()
+                user.status.as_str().to_string()
             } else {
                 "offline".to_string()
             },
+            nickname: user.nickname,
+            verified_name: user.verified_name,
+            unread_count: user.unread_count,
         }
     }
 }
 
 impl From<LobbyUserSerializable> for LobbyUser {
     fn from(user: LobbyUserSerializable) -> Self {
+        // Anything this client doesn't know counts as offline
+        let status = serde_json::from_value(serde_json::Value::String(user.status))
+            .unwrap_or(Status::Offline);
         Self {
             public_key: user.public_key,
-            is_online: user.status == "online",
+            is_online: status != Status::Offline,
+            status: match status {
+                Status::Offline => Status::Online,
+                status => status,
+            },
+            nickname: user.nickname,
+            verified_name: user.verified_name,
+            unread_count: user.unread_count,
         }
     }
 }
 
+/// Unread counts carried by restored users, by conversation
+fn unread_counts(users: &[LobbyUser]) -> HashMap<ConversationId, usize> {
+    users
+        .iter()
+        .filter(|u| u.unread_count > 0)
+        .filter_map(|u| Some((ConversationId::new(&u.public_key).ok()?, u.unread_count)))
+        .collect()
+}
+
 impl From<LobbyStateSerializable> for LobbyState {
     fn from(serializable: LobbyStateSerializable) -> Self {
         let mut state = Self::new();
         let users: Vec<LobbyUser> = serializable.users.into_iter().map(|u| u.into()).collect();
+        state.unread = unread_counts(&users);
         state.set_users(users);
         state.selected_user = serializable.selected_user;
         state
@@ -124,6 +211,16 @@ pub struct LobbyState {
     users: Vec<LobbyUser>,
     /// Currently selected user for messaging (None if no selection)
     selected_user: Option<String>,
+    /// Unread message counts by conversation, kept while a user is away
+    /// from the lobby so the badge is still there when they return
+    unread: HashMap<ConversationId, usize>,
+    /// Bumped on every change to the users, their nicknames or unread counts
+    revision: u64,
+    /// Users were restored from the lobby cache and no snapshot has replaced
+    /// them yet
+    stale: bool,
+    /// State-event journal every change is recorded to, if enabled
+    journal: JournalHandle,
 }
 
 impl LobbyState {
@@ -133,9 +230,67 @@ impl LobbyState {
         Self {
             users: Vec::new(),
             selected_user: None,
+            unread: HashMap::new(),
+            revision: 0,
+            stale: false,
+            journal: None,
+        }
+    }
+
+    /// Record every change to a state-event journal (None to stop)
+    pub fn set_journal(&mut self, journal: JournalHandle) {
+        self.journal = journal;
+    }
+
+    /// Create an empty lobby state recording to a journal
+    pub fn with_journal(journal: JournalHandle) -> Self {
+        Self {
+            journal,
+            ..Self::new()
+        }
+    }
+
+    /// Revision of the lobby, bumped on every change
+    #[inline]
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Whether the users shown came from the lobby cache and the server
+    /// hasn't sent a snapshot since
+    #[inline]
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// Show the users saved in the lobby cache until the server sends a
+    /// snapshot
+    ///
+    /// Keeps the cached revision, so an unchanged lobby isn't saved again.
+    pub fn restore_cached(&mut self, cache: LobbyCache) {
+        let users: Vec<LobbyUser> = cache.users.into_iter().map(LobbyUser::from).collect();
+        self.unread = unread_counts(&users);
+        self.set_users(users);
+        self.revision = cache.revision;
+        self.stale = true;
+    }
+
+    /// Snapshot the lobby for the lobby cache
+    pub fn to_cache(&self) -> LobbyCache {
+        LobbyCache {
+            revision: self.revision,
+            saved_at: chrono::Utc::now().to_rfc3339(),
+            users: serializable_users(&self.users),
         }
     }
 
+    /// Copy the stored unread count onto a user entering the lobby
+    fn with_unread(&self, mut user: LobbyUser) -> LobbyUser {
+        user.unread_count =
+            ConversationId::new(&user.public_key).map_or(0, |peer| self.unread_count(&peer));
+        user
+    }
+
     /// Check if a user exists in the lobby
     ///
     /// # Arguments
@@ -163,6 +318,9 @@ impl LobbyState {
     ///
     /// * `users` - Vector of users to set
     pub fn set_users(&mut self, users: Vec<LobbyUser>) {
+        journal_event(&self.journal, || StateEvent::LobbySetUsers {
+            users: serializable_users(&users),
+        });
         // Check if selected user still exists in new user list
         let selected_user_exists = self
             .selected_user
@@ -175,6 +333,7 @@ impl LobbyState {
         for user in users {
             // Deduplicate: only insert if not already present
             if !self.has_user(&user.public_key) {
+                let user = self.with_unread(user);
                 self.users.push(user);
             }
         }
@@ -183,21 +342,31 @@ impl LobbyState {
         if !selected_user_exists {
             self.selected_user = None;
         }
+        // A full list replaces whatever was restored from the cache
+        self.revision += 1;
+        self.stale = false;
     }
 
     /// Add a single user to lobby
     ///
-    /// Performs deduplication - if user already exists,
-    /// this is a no-op.
+    /// Performs deduplication - if user already exists, only their status
+    /// is updated, since the server re-announces a user whose status changed.
     ///
     /// # Arguments
     ///
     /// * `user` - The user to add
     #[inline]
     pub fn add_user(&mut self, user: LobbyUser) {
+        journal_event(&self.journal, || StateEvent::LobbyAddUsers {
+            users: serializable_users([&user]),
+        });
         // Deduplicate before adding
         if !self.has_user(&user.public_key) {
+            let user = self.with_unread(user);
             self.users.push(user);
+            self.revision += 1;
+        } else {
+            self.update_status(&user.public_key, user.status);
         }
     }
 
@@ -215,6 +384,11 @@ impl LobbyState {
     {
         use std::collections::HashSet;
 
+        let users: Vec<LobbyUser> = users.into_iter().collect();
+        journal_event(&self.journal, || StateEvent::LobbyAddUsers {
+            users: serializable_users(&users),
+        });
+
         // Collect existing public keys into HashSet for O(1) deduplication checks
         let mut existing_keys: HashSet<String> =
             self.users.iter().map(|u| u.public_key.clone()).collect();
@@ -224,9 +398,11 @@ impl LobbyState {
             let public_key = user.public_key.clone();
             // O(1) deduplication check instead of O(n)
             if !existing_keys.contains(&public_key) {
+                let user = self.with_unread(user);
                 self.users.push(user);
                 // Track new user to prevent duplicates within batch
                 existing_keys.insert(public_key);
+                self.revision += 1;
             }
         }
     }
@@ -244,12 +420,23 @@ impl LobbyState {
     /// `true` if user was present and removed, `false` otherwise
     #[inline]
     pub fn remove_user(&mut self, public_key: &str) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbyRemoveUser {
+            public_key: public_key.to_string(),
+        });
+        self.remove_user_entry(public_key)
+    }
+
+    /// Remove a user without journaling, for changes journaled as a whole
+    fn remove_user_entry(&mut self, public_key: &str) -> bool {
         let was_present = self.users.iter().any(|u| u.public_key == public_key);
         self.users.retain(|u| u.public_key != public_key);
 
         if self.selected_user.as_deref() == Some(public_key) {
             self.selected_user = None;
         }
+        if was_present {
+            self.revision += 1;
+        }
         was_present
     }
 
@@ -327,8 +514,14 @@ impl LobbyState {
     /// `true` if user was found and selected, `false` otherwise
     #[inline]
     pub fn select(&mut self, public_key: &str) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbySelect {
+            public_key: public_key.to_string(),
+        });
         if self.has_user(public_key) {
             self.selected_user = Some(public_key.to_string());
+            if let Ok(peer) = ConversationId::new(public_key) {
+                self.clear_unread(&peer);
+            }
             true
         } else {
             false
@@ -364,6 +557,7 @@ impl LobbyState {
     /// After calling this, no user will be selected.
     #[inline]
     pub fn clear_selection(&mut self) {
+        journal_event(&self.journal, || StateEvent::LobbyClearSelection);
         self.selected_user = None;
     }
 
@@ -381,6 +575,155 @@ impl LobbyState {
         self.users.iter().find(|u| u.public_key == public_key)
     }
 
+    /// Set or clear a user's nickname
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key` - The user whose nickname changed
+    /// * `nickname` - The new nickname, or None to clear it
+    ///
+    /// # Returns
+    ///
+    /// `true` if the user is in the lobby and their nickname changed
+    pub fn set_nickname(&mut self, public_key: &str, nickname: Option<String>) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbySetNickname {
+            public_key: public_key.to_string(),
+            nickname: nickname.clone(),
+        });
+        match self.users.iter_mut().find(|u| u.public_key == public_key) {
+            Some(user) if user.nickname != nickname => {
+                user.nickname = nickname;
+                self.revision += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Set or clear the name a user claimed
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key` - The user whose claimed name changed
+    /// * `verified_name` - The name verified by the server, or None if released
+    ///
+    /// # Returns
+    ///
+    /// `true` if the user is in the lobby and their claimed name changed
+    pub fn set_verified_name(&mut self, public_key: &str, verified_name: Option<String>) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbySetVerifiedName {
+            public_key: public_key.to_string(),
+            verified_name: verified_name.clone(),
+        });
+        match self.users.iter_mut().find(|u| u.public_key == public_key) {
+            Some(user) if user.verified_name != verified_name => {
+                user.verified_name = verified_name;
+                self.revision += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Set the status a user chose
+    ///
+    /// # Arguments
+    ///
+    /// * `public_key` - The user whose status changed
+    /// * `status` - Their new status
+    ///
+    /// # Returns
+    ///
+    /// `true` if the user is in the lobby and their status changed
+    pub fn set_status(&mut self, public_key: &str, status: Status) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbySetStatus {
+            public_key: public_key.to_string(),
+            status,
+        });
+        self.update_status(public_key, status)
+    }
+
+    fn update_status(&mut self, public_key: &str, status: Status) -> bool {
+        match self.users.iter_mut().find(|u| u.public_key == public_key) {
+            Some(user) if user.status != status => {
+                user.status = status;
+                self.revision += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Count a message received from `sender`
+    ///
+    /// Messages from the selected user are read as they arrive and are not
+    /// counted. Senders not in the lobby are counted too, so the badge shows
+    /// once they join.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the sender's unread count went up
+    pub fn record_message(&mut self, sender: &ConversationId) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbyRecordMessage {
+            sender: sender.to_string(),
+        });
+        if self
+            .selected_user
+            .as_deref()
+            .is_some_and(|selected| sender.matches(selected))
+        {
+            return false;
+        }
+        let count = self.unread.entry(sender.clone()).or_insert(0);
+        *count += 1;
+        let count = *count;
+        if let Some(user) = self
+            .users
+            .iter_mut()
+            .find(|u| sender.matches(&u.public_key))
+        {
+            user.unread_count = count;
+        }
+        self.revision += 1;
+        true
+    }
+
+    /// Clear the unread count for a user
+    ///
+    /// # Returns
+    ///
+    /// `true` if the user had unread messages
+    pub fn mark_read(&mut self, peer: &ConversationId) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbyMarkRead {
+            public_key: peer.to_string(),
+        });
+        self.clear_unread(peer)
+    }
+
+    /// Clear an unread count without journaling, for changes journaled as
+    /// a whole
+    fn clear_unread(&mut self, peer: &ConversationId) -> bool {
+        if let Some(user) = self.users.iter_mut().find(|u| peer.matches(&u.public_key)) {
+            user.unread_count = 0;
+        }
+        let had_unread = self.unread.remove(peer).is_some();
+        if had_unread {
+            self.revision += 1;
+        }
+        had_unread
+    }
+
+    /// Number of unread messages in a conversation
+    #[inline]
+    pub fn unread_count(&self, peer: &ConversationId) -> usize {
+        self.unread.get(peer).copied().unwrap_or(0)
+    }
+
+    /// Number of unread messages across all conversations
+    pub fn total_unread(&self) -> usize {
+        self.unread.values().sum()
+    }
+
     /// Get the index of the selected user in the user list
     ///
     /// Used for keyboard navigation (arrow keys move selection up/down).
@@ -405,8 +748,12 @@ impl LobbyState {
     ///
     /// `true` if a user exists at that index, `false` otherwise
     pub fn select_by_index(&mut self, index: usize) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbySelectIndex { index });
         if index < self.users.len() {
             let key = self.users[index].public_key.clone();
+            if let Ok(peer) = ConversationId::new(&key) {
+                self.clear_unread(&peer);
+            }
             self.selected_user = Some(key);
             true
         } else {
@@ -492,8 +839,10 @@ impl LobbyState {
     /// Also clears selection.
     #[inline]
     pub fn clear(&mut self) {
+        journal_event(&self.journal, || StateEvent::LobbyClear);
         self.users.clear();
         self.selected_user = None;
+        self.revision += 1;
     }
 
     /// Apply a delta update to the lobby state
@@ -511,12 +860,21 @@ impl LobbyState {
     ///
     /// `true` if any changes were made, `false` if state unchanged
     pub fn apply_delta(&mut self, joined: Vec<LobbyUser>, left: Vec<String>) -> bool {
+        journal_event(&self.journal, || StateEvent::LobbyDelta {
+            joined: serializable_users(&joined),
+            left: left.clone(),
+        });
         let mut changed = false;
 
-        // Process joined users first
+        // Process joined users first; a user already present was
+        // re-announced with a new status
         for user in joined {
             if !self.has_user(&user.public_key) {
+                let user = self.with_unread(user);
                 self.users.push(user);
+                self.revision += 1;
+                changed = true;
+            } else if self.update_status(&user.public_key, user.status) {
                 changed = true;
             }
         }
@@ -526,7 +884,7 @@ impl LobbyState {
         let _selected_left = left.iter().any(|k| selected_key.as_deref() == Some(k));
 
         for key in left {
-            if self.remove_user(&key) {
+            if self.remove_user_entry(&key) {
                 changed = true;
             }
         }
@@ -554,6 +912,25 @@ impl LobbyState {
     }
 }
 
+/// One page of online users returned by a lobby page request
+#[derive(Debug, Clone, PartialEq)]
+pub struct LobbyPage {
+    /// Users on this page, ordered by public key
+    pub users: Vec<LobbyUser>,
+    /// Cursor for the following page (None on the last page)
+    pub next_cursor: Option<String>,
+    /// Number of online users matching the request across all pages
+    pub total: usize,
+}
+
+impl LobbyPage {
+    /// Whether more pages can be requested
+    #[inline]
+    pub fn has_more(&self) -> bool {
+        self.next_cursor.is_some()
+    }
+}
+
 impl Default for LobbyState {
     #[inline]
     fn default() -> Self {
@@ -834,4 +1211,109 @@ mod tests {
         state.clear_selection();
         assert!(!state.selected_user_left(&["user_a".to_string()]));
     }
+
+    #[test]
+    fn test_set_nickname_updates_display_name() {
+        let key = "a".repeat(64);
+        let mut state = LobbyState::new();
+        state.add_user(LobbyUser::new(key.clone(), true));
+        assert_eq!(
+            state.get_user(&key).unwrap().display_name(),
+            "aaaaaaaa...aaaaaaaa"
+        );
+
+        assert!(state.set_nickname(&key, Some("alice".to_string())));
+        assert!(!state.set_nickname(&key, Some("alice".to_string())));
+        assert!(!state.set_nickname("missing", Some("bob".to_string())));
+        assert_eq!(state.get_user(&key).unwrap().display_name(), "alice");
+
+        // Nicknames survive the persistence round trip
+        let restored: LobbyState = LobbyStateSerializable::from(state.clone()).into();
+        assert_eq!(
+            restored.get_user(&key).unwrap().nickname.as_deref(),
+            Some("alice")
+        );
+
+        assert!(state.set_nickname(&key, None));
+        assert_eq!(
+            state.get_user(&key).unwrap().display_name(),
+            "aaaaaaaa...aaaaaaaa"
+        );
+    }
+
+    #[test]
+    fn test_status_updated_when_user_reannounced() {
+        let key = "a".repeat(64);
+        let mut state = LobbyState::new();
+        state.add_user(LobbyUser::new(key.clone(), true));
+        assert_eq!(state.get_user(&key).unwrap().status, Status::Online);
+
+        assert!(state.set_status(&key, Status::Away));
+        assert!(!state.set_status(&key, Status::Away));
+        assert!(!state.set_status("missing", Status::Busy));
+
+        // Statuses survive the persistence round trip
+        let restored: LobbyState = LobbyStateSerializable::from(state.clone()).into();
+        let user = restored.get_user(&key).unwrap();
+        assert_eq!(user.status, Status::Away);
+        assert!(user.is_online);
+
+        // A join for someone already here carries their new status
+        assert!(state.apply_delta(vec![LobbyUser::new(key.clone(), true)], vec![]));
+        assert_eq!(state.get_user(&key).unwrap().status, Status::Online);
+        state.add_user(LobbyUser::new(key.clone(), true).with_status(Status::Busy));
+        assert_eq!(state.get_user(&key).unwrap().status, Status::Busy);
+        assert_eq!(state.len(), 1);
+    }
+
+    #[test]
+    fn test_verified_name_shown_over_nickname() {
+        let key = "a".repeat(64);
+        let mut state = LobbyState::new();
+        state.add_user(LobbyUser::new(key.clone(), true).with_nickname(Some("ally".to_string())));
+
+        assert!(state.set_verified_name(&key, Some("Alice".to_string())));
+        assert!(!state.set_verified_name(&key, Some("Alice".to_string())));
+        assert!(!state.set_verified_name("missing", Some("Bob".to_string())));
+        assert_eq!(state.get_user(&key).unwrap().display_name(), "Alice");
+
+        let restored: LobbyState = LobbyStateSerializable::from(state.clone()).into();
+        assert_eq!(
+            restored.get_user(&key).unwrap().verified_name.as_deref(),
+            Some("Alice")
+        );
+
+        // Releasing the name falls back to the nickname
+        assert!(state.set_verified_name(&key, None));
+        assert_eq!(state.get_user(&key).unwrap().display_name(), "ally");
+    }
+
+    #[test]
+    fn test_unread_counts_until_selected() {
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+        let bob_id = ConversationId::new(&bob).unwrap();
+        let mut state = LobbyState::new();
+        state.add_user(LobbyUser::new(alice.clone(), true));
+        state.select(&alice);
+
+        // Messages in the open conversation are read as they arrive
+        assert!(!state.record_message(&ConversationId::new(&alice).unwrap()));
+        assert!(state.record_message(&bob_id));
+        // The same conversation whatever case the key arrived in
+        assert!(state.record_message(&ConversationId::new(&bob.to_uppercase()).unwrap()));
+        assert_eq!(state.unread_count(&bob_id), 2);
+        assert_eq!(state.total_unread(), 2);
+
+        // A sender joining the lobby later shows their count
+        state.apply_delta(vec![LobbyUser::new(bob.clone(), true)], vec![]);
+        assert_eq!(state.get_user(&bob).unwrap().unread_count, 2);
+
+        let restored: LobbyState = LobbyStateSerializable::from(state.clone()).into();
+        assert_eq!(restored.get_user(&bob).unwrap().unread_count, 2);
+
+        assert!(state.select(&bob));
+        assert_eq!(state.get_user(&bob).unwrap().unread_count, 0);
+        assert_eq!(state.total_unread(), 0);
+        assert!(!state.mark_read(&bob_id));
+    }
 }
@@ -3,3 +3,7 @@ pub mod composer;
 pub mod error_display;
 pub mod lobby;
 pub mod lobby_state;
+pub mod sound;
+pub mod stats;
+pub mod status;
+pub mod translation;
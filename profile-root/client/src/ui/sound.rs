@@ -0,0 +1,316 @@
+//! Sound cues
+//!
+//! [`SoundCues`] decides whether an event should make a sound. That depends
+//! on the user's [`SoundSettings`] and on do-not-disturb, which silences
+//! every cue while it is on. Cues that pass are handed to a
+//! [`SoundBackend`]. The default backend, [`SystemSound`], plays a short
+//! tone per event through the system audio device at the configured volume
+//! when the client is built with the `audio` feature. Without it, or when
+//! no output device can be opened, it falls back to [`TerminalBell`], which
+//! works on every platform and is picked up by screen readers and
+//! terminals that flash on a bell.
+
+use crate::state::sound::{SoundEvent, SoundSettings};
+use std::time::Duration;
+
+/// Loudest amplitude a cue plays at, reached at 100 percent volume
+///
+/// Kept below full scale so cues stay softer than most other audio.
+const MAX_GAIN: f32 = 0.4;
+
+/// Plays sound cues
+pub trait SoundBackend {
+    /// Play the cue for `event` at `volume` percent (1-100)
+    fn play(&self, event: SoundEvent, volume: u8);
+}
+
+impl SoundBackend for () {
+    fn play(&self, _event: SoundEvent, _volume: u8) {}
+}
+
+/// Tone played for `event`: its frequency in hertz and its length
+///
+/// Messages get a short high tone, joins a softer middle one, and failed
+/// sends a longer low one so they stand out.
+pub fn cue_tone(event: SoundEvent) -> (f32, Duration) {
+    match event {
+        SoundEvent::MessageReceived => (880.0, Duration::from_millis(120)),
+        SoundEvent::UserJoined => (660.0, Duration::from_millis(150)),
+        SoundEvent::SendFailed => (220.0, Duration::from_millis(300)),
+    }
+}
+
+/// Amplitude of a cue played at `volume` percent
+pub fn cue_gain(volume: u8) -> f32 {
+    f32::from(volume.min(100)) / 100.0 * MAX_GAIN
+}
+
+/// Backend ringing the terminal bell
+///
+/// The bell has no volume control, so any non-zero volume rings it once.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalBell;
+
+impl SoundBackend for TerminalBell {
+    fn play(&self, _event: SoundEvent, _volume: u8) {
+        use std::io::Write;
+        let mut stderr = std::io::stderr();
+        let _ = stderr.write_all(b"\x07").and_then(|_| stderr.flush());
+    }
+}
+
+/// Backend playing cues through the system audio device
+///
+/// Falls back to the terminal bell when the client is built without the
+/// `audio` feature or no output device is available.
+#[derive(Default)]
+pub struct SystemSound {
+    #[cfg(feature = "audio")]
+    device: Option<device::AudioDevice>,
+}
+
+impl SystemSound {
+    /// Open the default output device
+    pub fn open() -> Self {
+        Self {
+            #[cfg(feature = "audio")]
+            device: device::AudioDevice::open(),
+        }
+    }
+
+    /// Line telling the user how cues are played
+    pub fn notice(&self) -> &'static str {
+        if self.has_device() {
+            "Cues play through your audio device."
+        } else {
+            "Cues ring the terminal bell, which ignores the volume."
+        }
+    }
+
+    /// Check if cues play through an audio device rather than the bell
+    pub fn has_device(&self) -> bool {
+        #[cfg(feature = "audio")]
+        {
+            self.device.is_some()
+        }
+        #[cfg(not(feature = "audio"))]
+        {
+            false
+        }
+    }
+}
+
+impl SoundBackend for SystemSound {
+    fn play(&self, event: SoundEvent, volume: u8) {
+        #[cfg(feature = "audio")]
+        if let Some(device) = &self.device {
+            if device.play(event, volume) {
+                return;
+            }
+        }
+        TerminalBell.play(event, volume);
+    }
+}
+
+#[cfg(feature = "audio")]
+mod device {
+    use super::{cue_gain, cue_tone};
+    use crate::state::sound::SoundEvent;
+    use rodio::source::{SineWave, Source};
+    use std::sync::mpsc::{self, Sender};
+
+    /// Handle to the thread owning the output stream
+    ///
+    /// The stream is not `Send` on every platform, so it lives on its own
+    /// thread and cues are sent to it.
+    pub(super) struct AudioDevice {
+        cues: Sender<(SoundEvent, u8)>,
+    }
+
+    impl AudioDevice {
+        /// Open the default output device
+        ///
+        /// # Returns
+        /// None if no device could be opened
+        pub(super) fn open() -> Option<Self> {
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let (cues, receiver) = mpsc::channel::<(SoundEvent, u8)>();
+            std::thread::Builder::new()
+                .name("sound".to_string())
+                .spawn(move || {
+                    let mut stream = match rodio::OutputStreamBuilder::open_default_stream() {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            tracing::debug!("No audio output device, using the bell: {}", e);
+                            let _ = ready_tx.send(false);
+                            return;
+                        }
+                    };
+                    stream.log_on_drop(false);
+                    let _ = ready_tx.send(true);
+                    for (event, volume) in receiver {
+                        let (frequency, length) = cue_tone(event);
+                        stream.mixer().add(
+                            SineWave::new(frequency)
+                                .take_duration(length)
+                                .amplify(cue_gain(volume)),
+                        );
+                    }
+                })
+                .ok()?;
+            ready_rx.recv().unwrap_or(false).then_some(Self { cues })
+        }
+
+        /// Queue the cue for `event`
+        ///
+        /// # Returns
+        /// false if the sound thread has stopped
+        pub(super) fn play(&self, event: SoundEvent, volume: u8) -> bool {
+            self.cues.send((event, volume)).is_ok()
+        }
+    }
+}
+
+/// Trait for bridging the sound settings to the UI.
+///
+/// The binary target implements this to update the specific UI framework.
+pub trait SoundUiBridge {
+    /// Show the settings and whether do-not-disturb is on
+    fn set_sound_settings(&self, settings: &SoundSettings, do_not_disturb: bool);
+}
+
+impl SoundUiBridge for () {
+    fn set_sound_settings(&self, _settings: &SoundSettings, _do_not_disturb: bool) {}
+}
+
+/// Plays sound cues according to the settings and do-not-disturb
+pub struct SoundCues<B: SoundBackend = SystemSound> {
+    settings: SoundSettings,
+    backend: B,
+    do_not_disturb: bool,
+}
+
+impl SoundCues<SystemSound> {
+    /// Create cues played through the system audio device
+    pub fn new(settings: SoundSettings) -> Self {
+        Self::with_backend(settings, SystemSound::open())
+    }
+}
+
+impl<B: SoundBackend> SoundCues<B> {
+    /// Create cues played by `backend`
+    pub fn with_backend(settings: SoundSettings, backend: B) -> Self {
+        Self {
+            settings,
+            backend,
+            do_not_disturb: false,
+        }
+    }
+
+    /// Current settings
+    pub fn settings(&self) -> &SoundSettings {
+        &self.settings
+    }
+
+    /// Settings, for the settings screen to change
+    pub fn settings_mut(&mut self) -> &mut SoundSettings {
+        &mut self.settings
+    }
+
+    /// Turn do-not-disturb on or off
+    pub fn set_do_not_disturb(&mut self, on: bool) {
+        self.do_not_disturb = on;
+    }
+
+    /// Check if do-not-disturb is on
+    pub fn is_do_not_disturb(&self) -> bool {
+        self.do_not_disturb
+    }
+
+    /// Backend playing the cues
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Show the settings and do-not-disturb through `ui`
+    pub fn render(&self, ui: &impl SoundUiBridge) {
+        ui.set_sound_settings(&self.settings, self.do_not_disturb);
+    }
+
+    /// Play the cue for `event` unless it is suppressed
+    ///
+    /// # Returns
+    /// true if the cue was played
+    pub fn notify(&self, event: SoundEvent) -> bool {
+        if self.do_not_disturb || !self.settings.should_play(event) {
+            return false;
+        }
+        self.backend.play(event, self.settings.volume());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct Recorder {
+        played: RefCell<Vec<(SoundEvent, u8)>>,
+    }
+
+    impl SoundBackend for &Recorder {
+        fn play(&self, event: SoundEvent, volume: u8) {
+            self.played.borrow_mut().push((event, volume));
+        }
+    }
+
+    #[test]
+    fn test_cue_gain_follows_volume() {
+        assert_eq!(cue_gain(0), 0.0);
+        assert!(cue_gain(40) < cue_gain(70));
+        assert_eq!(cue_gain(100), MAX_GAIN);
+        assert_eq!(cue_gain(250), MAX_GAIN);
+    }
+
+    #[test]
+    fn test_cue_tones_are_distinct() {
+        let tones: Vec<f32> = SoundEvent::ALL.iter().map(|e| cue_tone(*e).0).collect();
+        assert!(tones
+            .iter()
+            .enumerate()
+            .all(|(i, a)| tones[i + 1..].iter().all(|b| a != b)));
+    }
+
+    #[test]
+    fn test_cues_respect_settings_and_dnd() {
+        let recorder = Recorder::default();
+        let mut cues = SoundCues::with_backend(SoundSettings::new(), &recorder);
+        assert!(!cues.notify(SoundEvent::MessageReceived));
+
+        cues.settings_mut().enabled = true;
+        cues.settings_mut().set_volume(40);
+        assert!(cues.notify(SoundEvent::MessageReceived));
+
+        cues.set_do_not_disturb(true);
+        assert!(!cues.notify(SoundEvent::SendFailed));
+        cues.set_do_not_disturb(false);
+
+        cues.settings_mut().muted = true;
+        assert!(!cues.notify(SoundEvent::UserJoined));
+        cues.settings_mut().muted = false;
+        cues.settings_mut()
+            .set_event_enabled(SoundEvent::UserJoined, false);
+        assert!(!cues.notify(SoundEvent::UserJoined));
+        assert!(cues.notify(SoundEvent::SendFailed));
+
+        assert_eq!(
+            *recorder.played.borrow(),
+            vec![
+                (SoundEvent::MessageReceived, 40),
+                (SoundEvent::SendFailed, 40)
+            ]
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Usage statistics view
+//!
+//! Turns [`UsageStats`] into rows the UI can show: one per peer with their
+//! label, messages sent and received and the last day they were active,
+//! plus session and reconnect totals. The view always carries
+//! [`LOCAL_ONLY_NOTICE`] and offers a purge action that erases the
+//! statistics.
+
+use crate::handlers::verify::format_public_key;
+use crate::state::contacts::Contacts;
+use crate::state::stats::UsageStats;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Shown with the statistics so the user knows where they live
+pub const LOCAL_ONLY_NOTICE: &str =
+    "These statistics never leave this device. They are not sent to the server or included in exports or backups.";
+
+/// Trait for bridging StatsView data to the UI.
+///
+/// The binary target implements this to update the specific UI framework.
+pub trait StatsUiBridge {
+    /// Replace the per-peer rows
+    fn set_stats_rows(&self, rows: &[StatsRow]);
+
+    /// Update the totals line
+    fn set_stats_summary(&self, summary: &str);
+
+    /// Update the local-only notice
+    fn set_stats_notice(&self, notice: &str);
+}
+
+impl StatsUiBridge for () {
+    fn set_stats_rows(&self, _rows: &[StatsRow]) {}
+    fn set_stats_summary(&self, _summary: &str) {}
+    fn set_stats_notice(&self, _notice: &str) {}
+}
+
+/// One peer's statistics, ready for display
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsRow {
+    /// Contact label, or the shortened key for non-contacts
+    pub label: String,
+    pub sent: u64,
+    pub received: u64,
+    /// Last day with a message either way (YYYY-MM-DD)
+    pub last_active: String,
+}
+
+/// Statistics view model
+#[derive(Clone, Default)]
+pub struct StatsView {
+    rows: Vec<StatsRow>,
+    session_count: usize,
+    average_session_secs: u64,
+    reconnects: u64,
+    on_purge: Option<Rc<RefCell<dyn Fn()>>>,
+}
+
+impl StatsView {
+    /// Build the view from the current statistics
+    ///
+    /// Rows are ordered by total messages, busiest peer first.
+    pub fn from_stats(stats: &UsageStats, contacts: &Contacts) -> Self {
+        let mut rows: Vec<StatsRow> = stats
+            .peer_totals()
+            .into_iter()
+            .map(|(peer, totals, last_active)| StatsRow {
+                label: contacts
+                    .label_for(peer.as_str())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format_public_key(peer.as_str())),
+                sent: totals.sent,
+                received: totals.received,
+                last_active,
+            })
+            .collect();
+        rows.sort_by_key(|row| std::cmp::Reverse(row.sent + row.received));
+
+        let durations: Vec<u64> = stats.sessions().map(|s| s.duration_secs).collect();
+        let average_session_secs = match durations.len() {
+            0 => 0,
+            n => durations.iter().sum::<u64>() / n as u64,
+        };
+        Self {
+            rows,
+            session_count: durations.len(),
+            average_session_secs,
+            reconnects: stats.reconnects(),
+            on_purge: None,
+        }
+    }
+
+    /// Set the callback run when the user presses the purge button
+    pub fn with_purge_callback(mut self, callback: impl Fn() + 'static) -> Self {
+        self.on_purge = Some(Rc::new(RefCell::new(callback)));
+        self
+    }
+
+    /// Per-peer rows, busiest first
+    pub fn rows(&self) -> &[StatsRow] {
+        &self.rows
+    }
+
+    /// Sessions, average session length and reconnects in one line
+    pub fn summary(&self) -> String {
+        format!(
+            "{} sessions, average {}m {}s, {} reconnects",
+            self.session_count,
+            self.average_session_secs / 60,
+            self.average_session_secs % 60,
+            self.reconnects
+        )
+    }
+
+    /// Handle the purge button
+    pub fn request_purge(&self) {
+        if let Some(callback) = &self.on_purge {
+            (callback.borrow())();
+        }
+    }
+
+    /// Push the view to the UI
+    pub fn render(&self, bridge: &impl StatsUiBridge) {
+        bridge.set_stats_rows(&self.rows);
+        bridge.set_stats_summary(&self.summary());
+        bridge.set_stats_notice(LOCAL_ONLY_NOTICE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::conversation::ConversationId;
+    use chrono::{DateTime, Utc};
+    use std::cell::Cell;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_rows_labelled_and_ordered() {
+        let (alice, stranger) = ("ab".repeat(32), "cd".repeat(32));
+        let mut contacts = Contacts::new();
+        contacts.add(&alice, "Alice").unwrap();
+
+        let mut stats = UsageStats::new();
+        let stranger_id = ConversationId::new(&stranger).unwrap();
+        for _ in 0..3 {
+            stats.record_received(&stranger_id, at("2025-03-01T09:00:00Z"));
+        }
+        stats.record_sent(
+            &ConversationId::new(&alice).unwrap(),
+            at("2025-03-02T09:00:00Z"),
+        );
+        stats.start_session(at("2025-03-02T09:00:00Z"));
+        stats.end_session(at("2025-03-02T09:02:30Z"));
+
+        let view = StatsView::from_stats(&stats, &contacts);
+        let labels: Vec<&str> = view.rows().iter().map(|r| r.label.as_str()).collect();
+        assert_eq!(labels, vec![format_public_key(&stranger).as_str(), "Alice"]);
+        assert_eq!(view.rows()[1].last_active, "2025-03-02");
+        assert_eq!(view.summary(), "1 sessions, average 2m 30s, 0 reconnects");
+    }
+
+    #[test]
+    fn test_purge_callback() {
+        let purged = Rc::new(Cell::new(false));
+        let flag = purged.clone();
+        let view = StatsView::from_stats(&UsageStats::new(), &Contacts::new())
+            .with_purge_callback(move || flag.set(true));
+
+        view.request_purge();
+        assert!(purged.get());
+        assert!(view.rows().is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+//! Status line announcements
+//!
+//! The status line used to be set directly, so "Public key copied" could be
+//! replaced by an error a moment later, before anyone had read it, and a
+//! screen reader only caught whichever message came last. Announcements now
+//! go through a polite [`StatusQueue`] instead:
+//! - the message on screen stays for at least its kind's minimum duration
+//! - anything announced meanwhile waits its turn, errors first, then in the
+//!   order announced
+//! - a progress message ("Generating key…") is dropped from the queue as
+//!   soon as something newer is announced, since it is out of date by then
+//!
+//! The binary target implements [`StatusUiBridge`] for the status line and
+//! calls [`StatusQueue::poll`] when [`StatusQueue::next_change_at`] comes.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Shortest time a success message stays on screen
+pub const MIN_SUCCESS_DURATION: Duration = Duration::from_secs(2);
+
+/// Shortest time an error stays on screen
+pub const MIN_ERROR_DURATION: Duration = Duration::from_secs(4);
+
+/// Most announcements waiting at once; the least urgent are dropped first
+pub const MAX_PENDING_ANNOUNCEMENTS: usize = 8;
+
+/// Kind of status message, which sets its priority and minimum duration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StatusKind {
+    /// Work in progress, replaced as soon as anything else is announced
+    Progress,
+    /// Something finished as asked
+    Success,
+    /// Something went wrong
+    Error,
+}
+
+impl StatusKind {
+    /// Shortest time a message of this kind stays on screen
+    pub fn min_duration(self) -> Duration {
+        match self {
+            StatusKind::Progress => Duration::ZERO,
+            StatusKind::Success => MIN_SUCCESS_DURATION,
+            StatusKind::Error => MIN_ERROR_DURATION,
+        }
+    }
+}
+
+/// One message for the status line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    pub text: String,
+    pub kind: StatusKind,
+}
+
+impl Announcement {
+    /// Announce work in progress
+    pub fn progress(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            kind: StatusKind::Progress,
+        }
+    }
+
+    /// Announce that something finished as asked
+    pub fn success(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            kind: StatusKind::Success,
+        }
+    }
+
+    /// Announce that something went wrong
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            kind: StatusKind::Error,
+        }
+    }
+
+    /// Whether this is an error
+    pub fn is_error(&self) -> bool {
+        self.kind == StatusKind::Error
+    }
+}
+
+/// Trait for bridging the status line to the UI.
+///
+/// The binary target implements this to update the specific UI framework.
+pub trait StatusUiBridge {
+    /// Show `text` on the status line, styled as an error if `is_error`
+    fn show_status(&self, text: &str, is_error: bool);
+}
+
+impl StatusUiBridge for () {
+    fn show_status(&self, _text: &str, _is_error: bool) {}
+}
+
+/// Polite queue of status announcements
+#[derive(Debug, Default)]
+pub struct StatusQueue {
+    /// Message on screen and when it was shown
+    current: Option<(Announcement, Instant)>,
+    /// Waiting messages, most urgent first
+    pending: VecDeque<Announcement>,
+}
+
+impl StatusQueue {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Announce `announcement` at `now`
+    ///
+    /// Repeats of the message on screen or of one already waiting are
+    /// dropped.
+    ///
+    /// # Returns
+    /// true if the status line changed
+    pub fn push(&mut self, announcement: Announcement, now: Instant) -> bool {
+        if self.current() == Some(&announcement) || self.pending.contains(&announcement) {
+            return false;
+        }
+        self.pending.retain(|a| a.kind != StatusKind::Progress);
+        let position = self
+            .pending
+            .iter()
+            .position(|a| a.kind < announcement.kind)
+            .unwrap_or(self.pending.len());
+        self.pending.insert(position, announcement);
+        self.pending.truncate(MAX_PENDING_ANNOUNCEMENTS);
+        self.poll(now)
+    }
+
+    /// Show the next waiting message if the current one has been up long
+    /// enough
+    ///
+    /// # Returns
+    /// true if the status line changed
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if self.pending.is_empty() || self.free_at().is_some_and(|at| at > now) {
+            return false;
+        }
+        self.current = self.pending.pop_front().map(|next| (next, now));
+        true
+    }
+
+    /// Message on screen
+    pub fn current(&self) -> Option<&Announcement> {
+        self.current.as_ref().map(|(announcement, _)| announcement)
+    }
+
+    /// Number of messages waiting
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// When the next waiting message can be shown
+    ///
+    /// # Returns
+    /// None if nothing is waiting
+    pub fn next_change_at(&self) -> Option<Instant> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        Some(self.free_at().unwrap_or_else(Instant::now))
+    }
+
+    /// When the message on screen has been up for its minimum duration
+    fn free_at(&self) -> Option<Instant> {
+        self.current
+            .as_ref()
+            .map(|(current, shown_at)| *shown_at + current.kind.min_duration())
+    }
+
+    /// Push the message on screen to the UI
+    pub fn render(&self, bridge: &impl StatusUiBridge) {
+        match self.current() {
+            Some(current) => bridge.show_status(&current.text, current.is_error()),
+            None => bridge.show_status("", false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(queue: &StatusQueue) -> (Option<&str>, Vec<&str>) {
+        (
+            queue.current().map(|a| a.text.as_str()),
+            queue.pending.iter().map(|a| a.text.as_str()).collect(),
+        )
+    }
+
+    #[test]
+    fn test_messages_stay_for_their_minimum_duration() {
+        let start = Instant::now();
+        let mut queue = StatusQueue::new();
+        assert!(queue.push(Announcement::success("Copied"), start));
+
+        // An error right after waits for the copy feedback to be read
+        assert!(!queue.push(Announcement::error("Clipboard unavailable"), start));
+        assert_eq!(queue.next_change_at(), Some(start + MIN_SUCCESS_DURATION));
+        assert!(!queue.poll(start + Duration::from_secs(1)));
+        assert_eq!(
+            texts(&queue),
+            (Some("Copied"), vec!["Clipboard unavailable"])
+        );
+
+        assert!(queue.poll(start + MIN_SUCCESS_DURATION));
+        assert_eq!(texts(&queue), (Some("Clipboard unavailable"), vec![]));
+        assert_eq!(queue.next_change_at(), None);
+
+        // With nothing waiting the message stays up
+        assert!(!queue.poll(start + Duration::from_secs(60)));
+        assert_eq!(queue.current().unwrap().kind, StatusKind::Error);
+    }
+
+    #[test]
+    fn test_errors_jump_the_queue_and_progress_is_dropped() {
+        let start = Instant::now();
+        let mut queue = StatusQueue::new();
+        queue.push(Announcement::success("Key imported"), start);
+        queue.push(Announcement::success("Copied"), start);
+        queue.push(Announcement::progress("Generating key…"), start);
+        queue.push(Announcement::error("Import failed"), start);
+        queue.push(Announcement::success("Copied"), start);
+
+        assert_eq!(
+            texts(&queue),
+            (Some("Key imported"), vec!["Import failed", "Copied"])
+        );
+
+        // Progress on screen gives way at once
+        let mut queue = StatusQueue::new();
+        queue.push(Announcement::progress("Generating key…"), start);
+        assert!(queue.push(Announcement::success("Key generated"), start));
+        assert_eq!(texts(&queue), (Some("Key generated"), vec![]));
+    }
+
+    #[test]
+    fn test_full_queue_drops_least_urgent() {
+        let start = Instant::now();
+        let mut queue = StatusQueue::new();
+        queue.push(Announcement::error("first"), start);
+        for n in 0..MAX_PENDING_ANNOUNCEMENTS {
+            queue.push(Announcement::success(format!("info {}", n)), start);
+        }
+        queue.push(Announcement::error("second"), start);
+
+        assert_eq!(queue.pending_len(), MAX_PENDING_ANNOUNCEMENTS);
+        let (_, pending) = texts(&queue);
+        assert_eq!(pending[0], "second");
+        assert!(!pending.contains(&"info 7"));
+    }
+}
@@ -0,0 +1,321 @@
+//! Message translation
+//!
+//! [`MessageTranslator::on_message_display`] runs as each received message
+//! is turned into a [`DisplayMessage`] and asks a [`TranslationProvider`]
+//! for a rendering in the user's language. The translation is attached next
+//! to the message; the content, signature and verification state stay
+//! exactly as received, so what was signed is always what is shown first.
+//!
+//! The built-in provider, [`OfflineDictionary`], translates word by word
+//! from a JSON dictionary on disk and never sends message text anywhere.
+//! Providers backed by an external service report [`is_external`], and the
+//! translator skips them unless the user opted in with
+//! `PROFILE_TRANSLATION_ALLOW_EXTERNAL=1`, since they would see every
+//! message in plain text.
+//!
+//! Translation is off unless `PROFILE_TRANSLATE_TO` names a target
+//! language. The dictionary is read from `PROFILE_TRANSLATION_DICTIONARY`,
+//! or `~/.profile/dictionary.json` when that is unset.
+//!
+//! [`is_external`]: TranslationProvider::is_external
+
+use crate::ui::chat::DisplayMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Environment variable naming the language to translate into
+pub const TRANSLATE_TO_ENV_VAR: &str = "PROFILE_TRANSLATE_TO";
+
+/// Environment variable overriding the dictionary file location
+pub const TRANSLATION_DICTIONARY_ENV_VAR: &str = "PROFILE_TRANSLATION_DICTIONARY";
+
+/// Environment variable allowing providers that send text off the device
+pub const TRANSLATION_ALLOW_EXTERNAL_ENV_VAR: &str = "PROFILE_TRANSLATION_ALLOW_EXTERNAL";
+
+/// Error types for message translation
+#[derive(Debug, Clone, PartialEq)]
+pub enum TranslationError {
+    /// Reading the dictionary file failed
+    Io(String),
+    /// The dictionary file is not valid JSON
+    Parse(String),
+}
+
+impl Display for TranslationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TranslationError::Io(msg) => write!(f, "Failed to read dictionary: {}", msg),
+            TranslationError::Parse(msg) => write!(f, "Failed to parse dictionary: {}", msg),
+        }
+    }
+}
+
+impl Error for TranslationError {}
+
+/// A translated rendering of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+    /// Language the text was translated into
+    pub language: String,
+    /// The translated text
+    pub text: String,
+    /// Name of the provider that translated it
+    pub provider: String,
+}
+
+/// Translates message text
+///
+/// Called while messages are rendered, so implementations should answer
+/// quickly, e.g. from a cache, and return None for anything not ready yet.
+pub trait TranslationProvider: Send + Sync + fmt::Debug {
+    /// Name shown with translations from this provider
+    fn name(&self) -> &str;
+
+    /// Whether message text leaves the device to be translated
+    fn is_external(&self) -> bool {
+        false
+    }
+
+    /// Translate `text` into `language`
+    ///
+    /// # Returns
+    /// None if the provider can't translate it
+    fn translate(&self, text: &str, language: &str) -> Option<String>;
+}
+
+/// Word-by-word translation from a dictionary file
+///
+/// Matching ignores case and surrounding punctuation, which is kept.
+/// Unknown words are left as they are.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OfflineDictionary {
+    /// Language the dictionary translates into
+    pub language: String,
+    /// Lowercase words and their translations
+    #[serde(default)]
+    pub words: HashMap<String, String>,
+}
+
+/// Default location of the dictionary file
+///
+/// # Returns
+/// `PROFILE_TRANSLATION_DICTIONARY` if set, otherwise
+/// `~/.profile/dictionary.json`, or None if neither can be determined
+pub fn default_dictionary_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(TRANSLATION_DICTIONARY_ENV_VAR).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".profile").join("dictionary.json"))
+}
+
+impl OfflineDictionary {
+    /// Load the dictionary at `path`
+    pub fn load(path: &Path) -> Result<Self, TranslationError> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| TranslationError::Io(e.to_string()))?;
+        let mut dictionary: Self =
+            serde_json::from_str(&text).map_err(|e| TranslationError::Parse(e.to_string()))?;
+        dictionary.words = dictionary
+            .words
+            .into_iter()
+            .map(|(word, translation)| (word.to_lowercase(), translation))
+            .collect();
+        Ok(dictionary)
+    }
+}
+
+impl TranslationProvider for OfflineDictionary {
+    fn name(&self) -> &str {
+        "dictionary"
+    }
+
+    fn translate(&self, text: &str, language: &str) -> Option<String> {
+        if !self.language.eq_ignore_ascii_case(language) {
+            return None;
+        }
+        let mut translated_any = false;
+        let words: Vec<String> = text
+            .split_whitespace()
+            .map(|word| {
+                let core = word.trim_matches(|c: char| !c.is_alphanumeric());
+                match self.words.get(&core.to_lowercase()) {
+                    Some(translation) if !core.is_empty() => {
+                        translated_any = true;
+                        word.replacen(core, translation, 1)
+                    }
+                    _ => word.to_string(),
+                }
+            })
+            .collect();
+        translated_any.then(|| words.join(" "))
+    }
+}
+
+/// Attaches translations to messages as they are displayed
+#[derive(Debug, Clone)]
+pub struct MessageTranslator {
+    provider: Arc<dyn TranslationProvider>,
+    language: String,
+    allow_external: bool,
+}
+
+impl MessageTranslator {
+    /// Translate into `language` with `provider`
+    ///
+    /// External providers are skipped until [`Self::allow_external`].
+    pub fn new(provider: Arc<dyn TranslationProvider>, language: impl Into<String>) -> Self {
+        Self {
+            provider,
+            language: language.into(),
+            allow_external: false,
+        }
+    }
+
+    /// Let an external provider see message text
+    pub fn allow_external(mut self, allow: bool) -> Self {
+        self.allow_external = allow;
+        self
+    }
+
+    /// Language messages are translated into
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Whether the provider is used at all
+    ///
+    /// false for an external provider the user hasn't opted in to.
+    pub fn is_active(&self) -> bool {
+        self.allow_external || !self.provider.is_external()
+    }
+
+    /// Create the translator configured by the environment
+    ///
+    /// # Returns
+    /// Ok(None) when `PROFILE_TRANSLATE_TO` is unset
+    pub fn from_env() -> Result<Option<Self>, TranslationError> {
+        let Some(language) = std::env::var(TRANSLATE_TO_ENV_VAR)
+            .ok()
+            .filter(|language| !language.trim().is_empty())
+        else {
+            return Ok(None);
+        };
+        let path = default_dictionary_path()
+            .ok_or_else(|| TranslationError::Io("no dictionary path".to_string()))?;
+        let dictionary = OfflineDictionary::load(&path)?;
+        let allow_external = std::env::var(TRANSLATION_ALLOW_EXTERNAL_ENV_VAR)
+            .is_ok_and(|value| value == "1" || value.eq_ignore_ascii_case("true"));
+        Ok(Some(
+            Self::new(Arc::new(dictionary), language.trim()).allow_external(allow_external),
+        ))
+    }
+
+    /// Attach a translation to a message about to be displayed
+    ///
+    /// Own messages, and messages the provider leaves unchanged, get none.
+    /// Nothing else about the message is touched.
+    pub fn on_message_display(&self, msg: &mut DisplayMessage) {
+        msg.translation = None;
+        if msg.is_self || msg.content.trim().is_empty() || !self.is_active() {
+            return;
+        }
+        msg.translation = self
+            .provider
+            .translate(&msg.content, &self.language)
+            .filter(|text| *text != msg.content)
+            .map(|text| Translation {
+                language: self.language.clone(),
+                text,
+                provider: self.provider.name().to_string(),
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::messages::ChatMessage;
+
+    fn received(text: &str) -> DisplayMessage {
+        let msg = ChatMessage::new(
+            "a".repeat(64),
+            text.to_string(),
+            "sig".to_string(),
+            "2025-12-27T10:30:00Z".to_string(),
+        );
+        DisplayMessage::from_chat_message(&msg, false)
+    }
+
+    fn dictionary() -> OfflineDictionary {
+        OfflineDictionary {
+            language: "es".to_string(),
+            words: HashMap::from([
+                ("hello".to_string(), "hola".to_string()),
+                ("friend".to_string(), "amigo".to_string()),
+            ]),
+        }
+    }
+
+    #[derive(Debug)]
+    struct Service;
+
+    impl TranslationProvider for Service {
+        fn name(&self) -> &str {
+            "service"
+        }
+
+        fn is_external(&self) -> bool {
+            true
+        }
+
+        fn translate(&self, text: &str, _language: &str) -> Option<String> {
+            Some(text.to_uppercase())
+        }
+    }
+
+    #[test]
+    fn test_translation_attached_beside_signed_original() {
+        let translator = MessageTranslator::new(Arc::new(dictionary()), "es");
+        let mut msg = received("Hello, friend!");
+        let original = msg.clone();
+
+        translator.on_message_display(&mut msg);
+
+        let translation = msg.translation.clone().unwrap();
+        assert_eq!(translation.text, "hola, amigo!");
+        assert_eq!(translation.provider, "dictionary");
+        assert_eq!(msg.content, original.content);
+        assert_eq!(msg.signature, original.signature);
+        assert_eq!(msg.is_verified, original.is_verified);
+
+        // Nothing to translate, another language, or our own message
+        let mut msg = received("ok");
+        translator.on_message_display(&mut msg);
+        assert_eq!(msg.translation, None);
+        let mut msg = received("hello");
+        MessageTranslator::new(Arc::new(dictionary()), "fr").on_message_display(&mut msg);
+        assert_eq!(msg.translation, None);
+        let mut msg = received("hello");
+        msg.is_self = true;
+        translator.on_message_display(&mut msg);
+        assert_eq!(msg.translation, None);
+    }
+
+    #[test]
+    fn test_external_provider_needs_opt_in() {
+        let translator = MessageTranslator::new(Arc::new(Service), "en");
+        assert!(!translator.is_active());
+        let mut msg = received("hi");
+        translator.on_message_display(&mut msg);
+        assert_eq!(msg.translation, None);
+
+        let translator = translator.allow_external(true);
+        translator.on_message_display(&mut msg);
+        assert_eq!(msg.translation.unwrap().text, "HI");
+    }
+}
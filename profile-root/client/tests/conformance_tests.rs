@@ -0,0 +1,63 @@
+//! Conformance dry run against an in-process server
+//!
+//! The reference server must pass every check, so a failure here is a
+//! regression in the server or in the validator.
+
+use profile_client::conformance::{validate, CheckStatus};
+use profile_server::connection::handler::handle_connection;
+use profile_server::lobby::Lobby;
+use profile_server::rate_limiter::AuthRateLimiter;
+use profile_server::rooms::Rooms;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Start a server on an ephemeral port and return its WebSocket URL
+async fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let lobby = Arc::new(Lobby::new());
+    let rooms = Arc::new(Rooms::new());
+    let rate_limiter = Arc::new(AuthRateLimiter::new());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_connection(
+                stream,
+                Arc::clone(&lobby),
+                Arc::clone(&rooms),
+                Arc::clone(&rate_limiter),
+                None,
+                None,
+            ));
+        }
+    });
+    format!("ws://{}", addr)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_reference_server_conforms() {
+    let url = start_server().await;
+    let report = validate(&url, Duration::from_secs(10)).await.unwrap();
+
+    assert!(report.is_conformant(), "{}", report);
+    let names: Vec<_> = report.checks.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "connect",
+            "authenticate",
+            "peer_visible",
+            "echo:plain",
+            "echo:unicode",
+            "echo:colons",
+            "echo:longest",
+            "recipient_offline",
+            "close",
+        ]
+    );
+    let negotiated = &report.check("authenticate").unwrap().detail;
+    assert!(negotiated.contains("encoding msgpack"), "{}", negotiated);
+    assert!(negotiated.contains("resume token issued"), "{}", negotiated);
+    assert_eq!(report.count(CheckStatus::Failed), 0);
+}
@@ -0,0 +1,118 @@
+//! Sealed direct messages through the server's routing
+//!
+//! Alice seals composed messages for Bob, the server relays them without
+//! reading them, and Bob's client classifies, opens and verifies what
+//! arrives. Replies travel the other way in the same session.
+
+use profile_client::connection::dispatcher::{classify_message, IncomingMessage};
+use profile_client::handlers::{
+    compose_and_send_message, open_sealed_message, seal_direct_message, verify_chat_message,
+    VerificationResult,
+};
+use profile_client::state::{
+    create_shared_key_state, create_shared_message_history, create_shared_ratchet_sessions,
+    handle_generate_key_async, ConversationId, SharedKeyState, SharedRatchetSessions,
+};
+use profile_server::connection::send_queue::{send_queue, OutboundQueue};
+use profile_server::lobby::{add_user, ActiveConnection, Lobby};
+use profile_server::message::sealed::handle_sealed_request;
+use profile_shared::crypto::padding::PaddingPolicy;
+
+struct Client {
+    keys: SharedKeyState,
+    public_key: String,
+    sessions: SharedRatchetSessions,
+    inbox: OutboundQueue,
+}
+
+async fn connect(lobby: &Lobby, connection_id: u64) -> Client {
+    let keys = create_shared_key_state();
+    let public_key = handle_generate_key_async(&keys).await.unwrap();
+    let (sender, inbox) = send_queue();
+    let conn = ActiveConnection {
+        public_key: public_key.clone(),
+        sender,
+        connection_id,
+        status: Default::default(),
+    };
+    add_user(lobby, public_key.clone(), conn).await.unwrap();
+    lobby.flush_broadcasts().await.unwrap();
+    Client {
+        keys,
+        public_key,
+        sessions: create_shared_ratchet_sessions(),
+        inbox,
+    }
+}
+
+/// Compose, seal and hand a message to the server
+async fn send(lobby: &Lobby, from: &Client, to: &Client, text: &str) -> String {
+    let request = compose_and_send_message(
+        text.to_string(),
+        to.public_key.clone(),
+        &from.keys,
+        &create_shared_message_history(),
+    )
+    .await
+    .unwrap();
+    let sealed = seal_direct_message(
+        &request,
+        &from.keys,
+        &from.sessions,
+        &PaddingPolicy::default(),
+    )
+    .await
+    .unwrap()
+    .expect("a direct message is sealed");
+    handle_sealed_request(lobby, &from.public_key, &sealed)
+        .await
+        .unwrap();
+    sealed
+}
+
+/// Take the next frame the server sent `to` and read it as a client would
+async fn receive(to: &mut Client) -> String {
+    let frame = loop {
+        let message = to.inbox.try_recv().expect("a frame is waiting");
+        let json = serde_json::to_string(&message).unwrap();
+        if json.contains("Sealed") {
+            break json;
+        }
+    };
+    let IncomingMessage::Sealed(sealed) = classify_message(&frame) else {
+        panic!("Expected a sealed message, got {}", frame);
+    };
+    let message = open_sealed_message(&sealed, &to.keys, &to.sessions)
+        .await
+        .unwrap();
+    match verify_chat_message(&message) {
+        VerificationResult::Valid(verified) => verified.message,
+        VerificationResult::Invalid { reason, .. } => panic!("Invalid signature: {}", reason),
+    }
+}
+
+#[tokio::test]
+async fn test_sealed_conversation_through_server() {
+    let lobby = Lobby::new();
+    let mut alice = connect(&lobby, 1).await;
+    let mut bob = connect(&lobby, 2).await;
+
+    let sealed = send(&lobby, &alice, &bob, "the plan: meet at noon").await;
+    assert!(!sealed.contains("noon"));
+    assert_eq!(receive(&mut bob).await, "the plan: meet at noon");
+
+    for round in 0..3 {
+        let reply = format!("reply {}", round);
+        send(&lobby, &bob, &alice, &reply).await;
+        assert_eq!(receive(&mut alice).await, reply);
+        let follow_up = format!("follow-up {}", round);
+        send(&lobby, &alice, &bob, &follow_up).await;
+        assert_eq!(receive(&mut bob).await, follow_up);
+    }
+
+    let bob_id = ConversationId::new(&bob.public_key).unwrap();
+    let alice_sessions = alice.sessions.lock().await;
+    let session = alice_sessions.active_session(&bob_id).unwrap();
+    assert!(session.is_initiator() && session.is_confirmed());
+    assert_eq!(alice_sessions.session_count(&bob_id), 1);
+}
@@ -0,0 +1,100 @@
+//! Key rollovers through the server
+//!
+//! Alice rotates her key: the server accepts her rollover and broadcasts
+//! it, her client switches to the new key, and Bob's client moves her
+//! contact across so his trust in her carries over.
+
+use profile_client::connection::dispatcher::{classify_message, IncomingMessage};
+use profile_client::handlers::{apply_key_rollover, complete_key_rollover, create_key_rollover};
+use profile_client::state::{
+    create_shared_blocklist, create_shared_contacts, create_shared_key_state,
+    create_shared_ratchet_sessions, handle_generate_key_async, TrustStatus,
+};
+use profile_server::connection::send_queue::{send_queue, OutboundQueue};
+use profile_server::lobby::{add_user, ActiveConnection, Lobby};
+use profile_server::message::rollover::handle_key_rollover_request;
+use profile_shared::crypto::rollover::KeyRollover;
+
+async fn connect(lobby: &Lobby, public_key: &str, connection_id: u64) -> OutboundQueue {
+    let (sender, inbox) = send_queue();
+    let conn = ActiveConnection {
+        public_key: public_key.to_string(),
+        sender,
+        connection_id,
+        status: Default::default(),
+    };
+    add_user(lobby, public_key.to_string(), conn).await.unwrap();
+    lobby.flush_broadcasts().await.unwrap();
+    inbox
+}
+
+/// Read frames as a client would until a key rollover arrives
+fn next_rollover(inbox: &mut OutboundQueue) -> KeyRollover {
+    loop {
+        let message = inbox.try_recv().expect("a rollover is waiting");
+        let frame = serde_json::to_string(&message).unwrap();
+        if let IncomingMessage::KeyRollover(rollover) = classify_message(&frame) {
+            return rollover;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_rollover_carries_trust_across() {
+    let lobby = Lobby::new();
+    let alice_keys = create_shared_key_state();
+    let alice = handle_generate_key_async(&alice_keys).await.unwrap();
+    let bob_keys = create_shared_key_state();
+    let bob = handle_generate_key_async(&bob_keys).await.unwrap();
+    let mut alice_inbox = connect(&lobby, &alice, 1).await;
+    let mut bob_inbox = connect(&lobby, &bob, 2).await;
+
+    let bob_contacts = create_shared_contacts();
+    bob_contacts.lock().await.add(&alice, "Alice").unwrap();
+    bob_contacts
+        .lock()
+        .await
+        .set_trust(&alice, TrustStatus::Verified)
+        .unwrap();
+
+    let pending = create_key_rollover(&alice_keys).await.unwrap();
+    let new_alice = pending.rollover.new_public_key.clone();
+    handle_key_rollover_request(&lobby, &alice, &pending.request_json())
+        .await
+        .unwrap();
+    lobby.flush_broadcasts().await.unwrap();
+
+    let announced = next_rollover(&mut alice_inbox);
+    complete_key_rollover(
+        pending,
+        &announced,
+        &alice_keys,
+        &create_shared_ratchet_sessions(),
+    )
+    .await
+    .unwrap();
+    let current = alice_keys.lock().await.public_key().map(hex::encode);
+    assert_eq!(current.as_deref(), Some(new_alice.as_str()));
+
+    let announced = next_rollover(&mut bob_inbox);
+    let migrated = apply_key_rollover(
+        &announced,
+        &bob_contacts,
+        &create_shared_blocklist(),
+        &create_shared_ratchet_sessions(),
+    )
+    .await
+    .unwrap()
+    .expect("Alice was a contact");
+    assert_eq!(migrated.public_key, new_alice);
+    assert_eq!(migrated.label, "Alice");
+    assert_eq!(migrated.trust, TrustStatus::Verified);
+    assert_eq!(migrated.previous_keys, vec![alice.clone()]);
+    assert!(bob_contacts.lock().await.get(&alice).is_none());
+
+    // The old key is retired for good
+    assert_eq!(
+        lobby.retired.replacement(&alice).await.as_deref(),
+        Some(new_alice.as_str())
+    );
+}
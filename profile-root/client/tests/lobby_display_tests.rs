@@ -22,11 +22,19 @@ fn test_lobby_state_set_users_populates_lobby() {
         LobbyUser {
             public_key: "3a8f2e1cb4d9a8f2e1cb4d9a8f2e1cb4d9a8f2e1cb4d9a8f2e1cb4d9a8f2e".to_string(),
             is_online: true,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
         LobbyUser {
             public_key: "7b4d9c2a3e8f1d4c5a6b7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d5e6f7a8b9"
                 .to_string(),
             is_online: true,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
     ];
     let mut state = LobbyState::new();
@@ -42,6 +50,10 @@ fn test_lobby_state_has_user() {
     let user = LobbyUser {
         public_key: "test_public_key_123456789abcdef".to_string(),
         is_online: true,
+        status: Default::default(),
+        nickname: None,
+        verified_name: None,
+        unread_count: 0,
     };
     state.add_user(user.clone());
 
@@ -55,6 +67,10 @@ fn test_lobby_state_remove_user() {
     let user = LobbyUser {
         public_key: "key_to_remove_123456789abc".to_string(),
         is_online: true,
+        status: Default::default(),
+        nickname: None,
+        verified_name: None,
+        unread_count: 0,
     };
     state.add_user(user.clone());
     assert!(state.has_user("key_to_remove_123456789abc"));
@@ -77,6 +93,10 @@ fn test_lobby_state_select_user() {
     let user = LobbyUser {
         public_key: "selectable_key_123456789ab".to_string(),
         is_online: true,
+        status: Default::default(),
+        nickname: None,
+        verified_name: None,
+        unread_count: 0,
     };
     state.add_user(user.clone());
 
@@ -103,6 +123,10 @@ fn test_lobby_state_clear_selection() {
     let user = LobbyUser {
         public_key: "key_to_select_123456789ab".to_string(),
         is_online: true,
+        status: Default::default(),
+        nickname: None,
+        verified_name: None,
+        unread_count: 0,
     };
     state.add_user(user.clone());
     state.select("key_to_select_123456789ab");
@@ -119,6 +143,10 @@ fn test_lobby_state_selection_cleared_when_user_removed() {
     let user = LobbyUser {
         public_key: "user_to_remove_123456789a".to_string(),
         is_online: true,
+        status: Default::default(),
+        nickname: None,
+        verified_name: None,
+        unread_count: 0,
     };
     state.add_user(user.clone());
     state.select("user_to_remove_123456789a");
@@ -136,6 +164,10 @@ fn test_lobby_state_deduplication_on_add() {
     let user = LobbyUser {
         public_key: "duplicate_key_123456789ab".to_string(),
         is_online: true,
+        status: Default::default(),
+        nickname: None,
+        verified_name: None,
+        unread_count: 0,
     };
 
     state.add_user(user.clone());
@@ -153,14 +185,26 @@ fn test_lobby_state_deduplication_on_set_users() {
         LobbyUser {
             public_key: "duplicate_key_123456789ab".to_string(),
             is_online: true,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
         LobbyUser {
             public_key: "duplicate_key_123456789ab".to_string(),
             is_online: true,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
         LobbyUser {
             public_key: "unique_key_123456789abc".to_string(),
             is_online: true,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
     ];
 
@@ -177,10 +221,18 @@ fn test_lobby_state_users_iteration() {
         LobbyUser {
             public_key: "key_a_123456789abcdef012".to_string(),
             is_online: true,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
         LobbyUser {
             public_key: "key_b_123456789abcdef012".to_string(),
             is_online: false,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
     ];
     state.set_users(users.clone());
@@ -200,6 +252,10 @@ fn test_lobby_state_is_selected() {
     let user = LobbyUser {
         public_key: "select_me_123456789abcd".to_string(),
         is_online: true,
+        status: Default::default(),
+        nickname: None,
+        verified_name: None,
+        unread_count: 0,
     };
     state.add_user(user.clone());
 
@@ -218,6 +274,10 @@ fn test_lobby_state_100_users_performance() {
         .map(|i| LobbyUser {
             public_key: format!("{:064x}", i),
             is_online: true,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         })
         .collect();
     state.set_users(users);
@@ -232,10 +292,18 @@ fn test_lobby_state_online_offline_users() {
         LobbyUser {
             public_key: "online_user_key_12345678".to_string(),
             is_online: true,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
         LobbyUser {
             public_key: "offline_user_key_1234567".to_string(),
             is_online: false,
+            status: Default::default(),
+            nickname: None,
+            verified_name: None,
+            unread_count: 0,
         },
     ];
     state.set_users(users.clone());
@@ -0,0 +1,83 @@
+//! Offline notification round-trip tests for Story 3.6
+//!
+//! Integration tests that verify the full offline notification flow:
+//! - Server reports an offline recipient with the shared `Message::Error`
+//! - Client classifies it through the incoming-error dispatcher
+//! - Client records the undelivered message and renders it in the chat view
+//!
+//! Other server errors must not be mistaken for offline notifications.
+
+use profile_client::handlers::{
+    add_undelivered_message, create_shared_undelivered_messages,
+    create_undelivered_display_message, format_notification_message, get_undelivered_for_recipient,
+    parse_incoming_error, IncomingError,
+};
+use profile_client::state::ConversationId;
+use profile_shared::Message;
+
+const RECIPIENT_KEY: &str = "a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6";
+
+/// Test: Shared offline error is parsed as a recipient-offline notification
+#[test]
+fn test_server_offline_error_parses_as_recipient_offline() {
+    let json = serde_json::to_string(&Message::new_recipient_offline(RECIPIENT_KEY)).unwrap();
+
+    match parse_incoming_error(&json) {
+        Some(IncomingError::RecipientOffline(notification)) => {
+            assert_eq!(notification.recipient, RECIPIENT_KEY);
+        }
+        other => panic!("Expected RecipientOffline, got {:?}", other),
+    }
+}
+
+/// Test: Offline notification flows through to an undelivered chat entry
+#[tokio::test]
+async fn test_offline_round_trip_to_undelivered_display() {
+    let json = serde_json::to_string(&Message::new_recipient_offline(RECIPIENT_KEY)).unwrap();
+    let Some(IncomingError::RecipientOffline(notification)) = parse_incoming_error(&json) else {
+        panic!("Expected RecipientOffline");
+    };
+
+    let text = format_notification_message(&notification);
+    assert_eq!(
+        text,
+        "User a1b2c3d4...a3b4c5d6 is offline. Message not delivered."
+    );
+
+    let store = create_shared_undelivered_messages();
+    add_undelivered_message(
+        &store,
+        "Hello there",
+        &notification.recipient,
+        "2025-12-27T10:00:00Z",
+    )
+    .await;
+
+    let conversation = ConversationId::new(&RECIPIENT_KEY.to_uppercase()).unwrap();
+    let undelivered = get_undelivered_for_recipient(&store, &conversation).await;
+    assert_eq!(undelivered.len(), 1);
+
+    let display = create_undelivered_display_message(&undelivered[0], true);
+    assert_eq!(display.content, "Hello there");
+    assert_eq!(display.sender_key, RECIPIENT_KEY);
+    assert!(!display.is_verified);
+    assert!(display.is_self);
+}
+
+/// Test: Other shared errors are reported as generic server errors
+#[test]
+fn test_non_offline_error_is_not_offline_notification() {
+    let json = serde_json::to_string(&Message::new_error(
+        "invalid_signature".to_string(),
+        Some("Signature verification failed".to_string()),
+    ))
+    .unwrap();
+
+    match parse_incoming_error(&json) {
+        Some(IncomingError::Server { reason, details }) => {
+            assert_eq!(reason, "invalid_signature");
+            assert_eq!(details.as_deref(), Some("Signature verification failed"));
+        }
+        other => panic!("Expected Server error, got {:?}", other),
+    }
+}
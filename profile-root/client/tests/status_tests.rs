@@ -0,0 +1,89 @@
+//! Presence statuses through the server
+//!
+//! Alice marks herself busy and then invisible: Bob's client shows her as
+//! busy, then sees her leave, while the server keeps her connection.
+
+use profile_client::connection::client::LobbyResponse;
+use profile_client::connection::dispatcher::{classify_message, IncomingMessage};
+use profile_client::handlers::compose_set_status;
+use profile_client::state::{create_shared_key_state, handle_generate_key_async};
+use profile_client::ui::lobby_state::{LobbyState, LobbyUser};
+use profile_server::connection::send_queue::{send_queue, OutboundQueue};
+use profile_server::lobby::{add_user, ActiveConnection, Lobby};
+use profile_server::message::status::handle_set_status_request;
+use profile_shared::protocol::Status;
+
+async fn connect(lobby: &Lobby, public_key: &str, connection_id: u64) -> OutboundQueue {
+    let (sender, inbox) = send_queue();
+    let conn = ActiveConnection {
+        public_key: public_key.to_string(),
+        sender,
+        connection_id,
+        status: Default::default(),
+    };
+    add_user(lobby, public_key.to_string(), conn).await.unwrap();
+    lobby.flush_broadcasts().await.unwrap();
+    inbox
+}
+
+/// Apply every lobby event waiting in `inbox` as the client would
+fn apply_lobby_events(inbox: &mut OutboundQueue, state: &mut LobbyState) {
+    while let Ok(message) = inbox.try_recv() {
+        let frame = serde_json::to_string(&message).unwrap();
+        let IncomingMessage::Lobby(events) = classify_message(&frame) else {
+            continue;
+        };
+        for event in events {
+            match event {
+                LobbyResponse::UsersJoined { public_keys } => {
+                    for key in public_keys {
+                        state.add_user(LobbyUser::new(key, true));
+                    }
+                }
+                LobbyResponse::UsersLeft { public_keys } => {
+                    for key in public_keys {
+                        state.remove_user(&key);
+                    }
+                }
+                LobbyResponse::StatusChanged { public_key, status } => {
+                    state.set_status(&public_key, status);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_status_changes_reach_other_clients() {
+    let lobby = Lobby::new();
+    let alice_keys = create_shared_key_state();
+    let alice = handle_generate_key_async(&alice_keys).await.unwrap();
+    let bob_keys = create_shared_key_state();
+    let bob = handle_generate_key_async(&bob_keys).await.unwrap();
+    let mut bob_inbox = connect(&lobby, &bob, 1).await;
+    let _alice_inbox = connect(&lobby, &alice, 2).await;
+
+    let mut bob_lobby = LobbyState::new();
+    apply_lobby_events(&mut bob_inbox, &mut bob_lobby);
+    assert_eq!(bob_lobby.get_user(&alice).unwrap().status, Status::Online);
+
+    let request = compose_set_status(Status::Busy, &alice_keys).await.unwrap();
+    handle_set_status_request(&lobby, &alice, &request)
+        .await
+        .unwrap();
+    lobby.flush_broadcasts().await.unwrap();
+    apply_lobby_events(&mut bob_inbox, &mut bob_lobby);
+    assert_eq!(bob_lobby.get_user(&alice).unwrap().status, Status::Busy);
+
+    let request = compose_set_status(Status::Invisible, &alice_keys)
+        .await
+        .unwrap();
+    handle_set_status_request(&lobby, &alice, &request)
+        .await
+        .unwrap();
+    lobby.flush_broadcasts().await.unwrap();
+    apply_lobby_events(&mut bob_inbox, &mut bob_lobby);
+    assert!(bob_lobby.get_user(&alice).is_none());
+    assert!(lobby.user_exists(&alice).await.unwrap());
+}
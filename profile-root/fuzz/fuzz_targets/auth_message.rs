@@ -0,0 +1,36 @@
+//! The first frame of a connection
+//!
+//! Feeds the input to `handle_auth_message` as a text frame, so it is
+//! parsed as an auth or resume message and, if it parses, checked and
+//! verified like a real sign-in.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use profile_server::connection::session::handle_auth_message;
+use profile_server::lobby::Lobby;
+use profile_server::rate_limiter::AuthRateLimiter;
+use std::sync::{Arc, OnceLock};
+use tokio::runtime::Runtime;
+use tokio_tungstenite::tungstenite::Message;
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let frame = Message::Text(String::from_utf8_lossy(data).into_owned());
+
+    runtime().block_on(async {
+        let lobby = Arc::new(Lobby::new());
+        // A fresh limiter each run, so no input is turned away unparsed
+        let rate_limiter = Arc::new(AuthRateLimiter::new());
+        let _ = handle_auth_message(&frame, &lobby, &rate_limiter, "fuzz").await;
+    });
+});
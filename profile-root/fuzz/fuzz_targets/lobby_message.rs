@@ -0,0 +1,12 @@
+//! Lobby state and lobby updates as the client receives them
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use profile_client::connection::client::parse_lobby_message;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = parse_lobby_message(text);
+    }
+});
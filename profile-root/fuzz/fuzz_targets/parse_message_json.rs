@@ -0,0 +1,48 @@
+//! Direct message requests from an authenticated client
+//!
+//! Parses the input with `parse_message_json`, then runs whatever parses
+//! through the full validation pipeline and routing, from a sender that is
+//! in the lobby, so the checks after parsing see hostile fields too.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use profile_server::connection::send_queue::send_queue;
+use profile_server::lobby::{ActiveConnection, Lobby};
+use profile_server::message::{handle_incoming_message, parse_message_json, route_message};
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(json) = std::str::from_utf8(data) else {
+        return;
+    };
+    let Ok(request) = parse_message_json(json) else {
+        return;
+    };
+    let sender = request.sender_public_key.to_string();
+
+    runtime().block_on(async {
+        let lobby = Lobby::new();
+        let (queue, _outbound) = send_queue();
+        let connection = ActiveConnection {
+            public_key: sender.clone(),
+            sender: queue,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        lobby.add_user(connection).await.unwrap();
+        let validated = handle_incoming_message(&lobby, &sender, json).await;
+        let _ = route_message(&lobby, &validated).await;
+    });
+});
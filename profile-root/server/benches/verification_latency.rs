@@ -0,0 +1,163 @@
+//! Reactor latency during a burst of signature verifications
+//!
+//! Simulates a burst of connections, each with a batch of signed frames
+//! already buffered, on a two-worker runtime. The burst runs twice: once
+//! verifying inline on the async workers, where a connection works through
+//! its whole batch without yielding, and once through the
+//! [`VerificationQueue`] on the blocking pool. Meanwhile a probe task
+//! sleeps for 1 ms in a loop and records how late each wake-up is, which is
+//! how long any other connection would have waited for the event loop.
+//!
+//! Run with `cargo bench -p profile-server --bench verification_latency`.
+
+use profile_server::load::VerificationQueue;
+use profile_shared::canonical::{self, CanonicalVersion};
+use profile_shared::{derive_public_key, generate_private_key, sign_message, verify_signature};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Connections in the burst
+const CONNECTIONS: usize = 100;
+
+/// Frames each connection has buffered
+const BATCH: usize = 40;
+
+/// How often the probe asks to be woken
+const PROBE_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A signed message, as hex strings like they arrive on the wire
+struct Signed {
+    public_key: String,
+    message: String,
+    timestamp: String,
+    signature: String,
+}
+
+impl Signed {
+    fn new() -> Self {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let (message, timestamp) = ("hello".to_string(), "2025-01-01T00:00:00Z".to_string());
+        let signature = hex::encode(
+            sign_message(
+                &private_key,
+                &canonical::message(CanonicalVersion::CURRENT, &message, &timestamp),
+            )
+            .unwrap(),
+        );
+        Self {
+            public_key,
+            message,
+            timestamp,
+            signature,
+        }
+    }
+
+    /// Decode and verify on the calling thread
+    fn verify_inline(&self) {
+        let public_key =
+            profile_shared::PublicKey::new(hex::decode(&self.public_key).unwrap()).unwrap();
+        let signature = hex::decode(&self.signature).unwrap();
+        verify_signature(&public_key, &self.canonical(), &signature).unwrap();
+    }
+
+    /// The bytes the signature covers
+    fn canonical(&self) -> Vec<u8> {
+        canonical::message(CanonicalVersion::CURRENT, &self.message, &self.timestamp)
+    }
+}
+
+/// Wake-up lateness of the probe while `burst` runs
+async fn probe_during<F>(burst: F) -> Vec<Duration>
+where
+    F: std::future::Future<Output = ()>,
+{
+    let done = Arc::new(AtomicBool::new(false));
+    let probe = tokio::spawn({
+        let done = Arc::clone(&done);
+        async move {
+            let mut lateness = Vec::new();
+            while !done.load(Ordering::Relaxed) {
+                let start = Instant::now();
+                tokio::time::sleep(PROBE_INTERVAL).await;
+                lateness.push(start.elapsed().saturating_sub(PROBE_INTERVAL));
+            }
+            lateness
+        }
+    });
+    // Let the probe start sleeping before the burst lands
+    tokio::time::sleep(PROBE_INTERVAL).await;
+    burst.await;
+    done.store(true, Ordering::Relaxed);
+    probe.await.unwrap()
+}
+
+fn report(label: &str, elapsed: Duration, mut lateness: Vec<Duration>) {
+    lateness.sort();
+    let percentile = |p: usize| lateness[(lateness.len() - 1) * p / 100];
+    println!(
+        "{:<10} burst {:>8.1?}  probe lateness p50 {:>8.1?}  p99 {:>8.1?}  max {:>8.1?}  ({} samples)",
+        label,
+        elapsed,
+        percentile(50),
+        percentile(99),
+        lateness[lateness.len() - 1],
+        lateness.len()
+    );
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+    let signed = Arc::new(Signed::new());
+
+    runtime.block_on(async {
+        let start = Instant::now();
+        let lateness = probe_during(async {
+            let tasks: Vec<_> = (0..CONNECTIONS)
+                .map(|_| {
+                    let signed = Arc::clone(&signed);
+                    tokio::spawn(async move {
+                        for _ in 0..BATCH {
+                            signed.verify_inline();
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+        })
+        .await;
+        report("inline", start.elapsed(), lateness);
+
+        let queue = Arc::new(VerificationQueue::new());
+        let start = Instant::now();
+        let lateness = probe_during(async {
+            let tasks: Vec<_> = (0..CONNECTIONS)
+                .map(|_| {
+                    let (signed, queue) = (Arc::clone(&signed), Arc::clone(&queue));
+                    tokio::spawn(async move {
+                        for _ in 0..BATCH {
+                            queue
+                                .reserve()
+                                .await
+                                .verify(&signed.public_key, signed.canonical(), &signed.signature)
+                                .await
+                                .unwrap();
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+        })
+        .await;
+        report("offloaded", start.elapsed(), lateness);
+    });
+}
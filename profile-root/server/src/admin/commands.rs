@@ -0,0 +1,735 @@
+//! Admin request parsing and execution
+
+use super::synthetic::SYNTHETIC_CONNECTION_ID;
+use crate::admission::AdmissionStats;
+use crate::audit::AuditEvent;
+use crate::lobby::{normalize_key, Lobby};
+use crate::message::pipeline::StageStats;
+use crate::moderation::{Ban, ModerationError};
+use crate::rooms::Rooms;
+use profile_shared::config::admin::MAX_ANNOUNCEMENT_LENGTH;
+use profile_shared::config::lobby::MAX_LOBBY_SIZE;
+use profile_shared::Message;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// An operation requested by the server operator
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum AdminCommand {
+    /// List connected users
+    ListUsers,
+    /// Disconnect a user; they may reconnect
+    Kick {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+    },
+    /// Disconnect a user and refuse their key until unbanned, or for
+    /// `durationSecs` seconds if given
+    Ban {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        #[serde(rename = "durationSecs", default)]
+        duration_secs: Option<u64>,
+    },
+    /// List bans in force
+    ListBans,
+    /// Lift a ban
+    Unban {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+    },
+    /// Send an announcement to every online user
+    Announce { text: String },
+    /// Lobby statistics
+    Stats,
+    /// Messages passed and rejected by each direct message validation stage
+    ValidationStats,
+    /// Lobby admissions and how long they waited for a slot
+    AdmissionStats,
+    /// Add `count` synthetic users to the lobby, for client testing
+    Populate { count: usize },
+    /// Remove every synthetic user
+    Depopulate,
+}
+
+/// One admin request line: the shared token plus a command
+#[derive(Debug, Deserialize)]
+pub struct AdminRequest {
+    pub token: String,
+    #[serde(flatten)]
+    pub command: AdminCommand,
+}
+
+/// A connected user, as listed by `list_users`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AdminUser {
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    pub nickname: Option<String>,
+    #[serde(rename = "connectionId")]
+    pub connection_id: u64,
+    /// Messages waiting in the user's send queue
+    #[serde(rename = "queueDepth")]
+    pub queue_depth: usize,
+}
+
+/// A ban in force, as listed by `list_bans`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AdminBan {
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    #[serde(rename = "bannedAt")]
+    pub banned_at: String,
+    /// None for a permanent ban
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<String>,
+}
+
+impl AdminBan {
+    fn new(public_key: String, ban: &Ban) -> Self {
+        Self {
+            public_key,
+            banned_at: ban.banned_at.to_rfc3339(),
+            expires_at: ban.expires_at.map(|t| t.to_rfc3339()),
+        }
+    }
+}
+
+/// Reply to one admin request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AdminResponse {
+    Users {
+        users: Vec<AdminUser>,
+    },
+    Kicked {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        /// Whether the user was online
+        disconnected: bool,
+    },
+    Banned {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        /// Whether the user was online
+        disconnected: bool,
+        /// None for a permanent ban
+        #[serde(rename = "expiresAt")]
+        expires_at: Option<String>,
+    },
+    Bans {
+        bans: Vec<AdminBan>,
+    },
+    Unbanned {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        /// Whether the key was banned
+        #[serde(rename = "wasBanned")]
+        was_banned: bool,
+    },
+    Announced {
+        /// Users online when the announcement was queued
+        recipients: usize,
+    },
+    Stats {
+        #[serde(rename = "onlineUsers")]
+        online_users: usize,
+        rooms: usize,
+        #[serde(rename = "storedBackups")]
+        stored_backups: usize,
+        #[serde(rename = "bannedKeys")]
+        banned_keys: usize,
+        /// Messages waiting in all send queues
+        #[serde(rename = "queuedMessages")]
+        queued_messages: usize,
+        /// Messages waiting in the fullest send queue
+        #[serde(rename = "deepestQueue")]
+        deepest_queue: usize,
+        /// Messages dropped from full send queues since startup
+        #[serde(rename = "droppedMessages")]
+        dropped_messages: u64,
+        /// Connections closed for reading too slowly since startup
+        #[serde(rename = "slowConsumers")]
+        slow_consumers: u64,
+    },
+    ValidationStats {
+        /// In the order the stages run
+        stages: Vec<StageStats>,
+    },
+    AdmissionStats {
+        admission: AdmissionStats,
+    },
+    Populated {
+        /// Synthetic users added; fewer than asked if the lobby filled up
+        added: usize,
+        #[serde(rename = "onlineUsers")]
+        online_users: usize,
+    },
+    Depopulated {
+        removed: usize,
+    },
+    Error {
+        reason: String,
+        details: String,
+    },
+}
+
+/// Error types for admin requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminError {
+    /// The request line is not a valid admin request
+    MalformedRequest(String),
+    /// The token is missing or wrong
+    Unauthorized,
+    /// The public key is not 64 hex characters
+    InvalidPublicKey,
+    /// The announcement is empty or too long
+    InvalidAnnouncement { length: usize, max: usize },
+    /// The ban duration is zero or too long
+    InvalidDuration(u64),
+    /// The synthetic user count is zero or larger than the lobby
+    InvalidCount(usize),
+    /// The lobby could not carry out the command
+    Lobby(String),
+    /// The ban list could not be saved
+    Storage(ModerationError),
+}
+
+impl AdminError {
+    /// Short machine-readable reason sent in the error response
+    pub fn reason(&self) -> &'static str {
+        match self {
+            AdminError::MalformedRequest(_) => "malformed_request",
+            AdminError::Unauthorized => "unauthorized",
+            AdminError::InvalidPublicKey => "invalid_public_key",
+            AdminError::InvalidAnnouncement { .. } => "invalid_announcement",
+            AdminError::InvalidDuration(_) => "invalid_duration",
+            AdminError::InvalidCount(_) => "invalid_count",
+            AdminError::Lobby(_) => "lobby_error",
+            AdminError::Storage(_) => "storage_error",
+        }
+    }
+}
+
+impl Display for AdminError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminError::MalformedRequest(msg) => write!(f, "Malformed admin request: {}", msg),
+            AdminError::Unauthorized => write!(f, "Invalid admin token"),
+            AdminError::InvalidPublicKey => {
+                write!(f, "Public key must be 64 hexadecimal characters")
+            }
+            AdminError::InvalidAnnouncement { length, max } => write!(
+                f,
+                "Announcement must be 1 to {} characters (got {})",
+                max, length
+            ),
+            AdminError::InvalidDuration(secs) => {
+                write!(f, "Ban duration of {} seconds is out of range", secs)
+            }
+            AdminError::InvalidCount(count) => write!(
+                f,
+                "Synthetic user count must be 1 to {} (got {})",
+                MAX_LOBBY_SIZE, count
+            ),
+            AdminError::Lobby(msg) => write!(f, "Lobby error: {}", msg),
+            AdminError::Storage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for AdminError {}
+
+impl From<ModerationError> for AdminError {
+    fn from(error: ModerationError) -> Self {
+        AdminError::Storage(error)
+    }
+}
+
+impl From<AdminError> for AdminResponse {
+    fn from(error: AdminError) -> Self {
+        AdminResponse::Error {
+            reason: error.reason().to_string(),
+            details: error.to_string(),
+        }
+    }
+}
+
+/// Compare tokens in time independent of where they first differ
+pub(crate) fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Parse, authenticate and run one admin request line
+///
+/// # Arguments
+/// * `lobby` - The lobby to inspect and moderate
+/// * `rooms` - Rooms, left by kicked users
+/// * `token` - The configured admin token
+/// * `line` - Raw JSON request
+pub async fn handle_admin_request(
+    lobby: &Lobby,
+    rooms: &Rooms,
+    token: &str,
+    line: &str,
+) -> AdminResponse {
+    let request: AdminRequest = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return AdminError::MalformedRequest(e.to_string()).into(),
+    };
+    if !tokens_match(&request.token, token) {
+        tracing::warn!("Admin request with invalid token rejected");
+        return AdminError::Unauthorized.into();
+    }
+    tracing::info!(command = ?request.command, "Admin command");
+    execute(lobby, rooms, request.command)
+        .await
+        .unwrap_or_else(AdminResponse::from)
+}
+
+/// Run an authenticated admin command
+pub async fn execute(
+    lobby: &Lobby,
+    rooms: &Rooms,
+    command: AdminCommand,
+) -> Result<AdminResponse, AdminError> {
+    match command {
+        AdminCommand::ListUsers => {
+            let connections = lobby
+                .get_all_connections()
+                .await
+                .map_err(|e| AdminError::Lobby(e.to_string()))?;
+            let keys: Vec<String> = connections.iter().map(|c| c.public_key.clone()).collect();
+            let nicknames = lobby.nicknames.lookup(&keys).await;
+            let mut users: Vec<AdminUser> = connections
+                .iter()
+                .map(|conn| AdminUser {
+                    public_key: conn.public_key.clone(),
+                    nickname: nicknames.get(&conn.public_key).cloned(),
+                    connection_id: conn.connection_id,
+                    queue_depth: conn.sender.depth(),
+                })
+                .collect();
+            users.sort_by(|a, b| a.public_key.cmp(&b.public_key));
+            Ok(AdminResponse::Users { users })
+        }
+        AdminCommand::Kick { public_key } => {
+            let public_key = normalize_key(&public_key).ok_or(AdminError::InvalidPublicKey)?;
+            let disconnected = disconnect(lobby, rooms, &public_key).await?;
+            lobby.audit.record(AuditEvent::Kicked {
+                public_key: public_key.clone(),
+                disconnected,
+            });
+            Ok(AdminResponse::Kicked {
+                public_key,
+                disconnected,
+            })
+        }
+        AdminCommand::Ban {
+            public_key,
+            duration_secs,
+        } => {
+            let public_key = normalize_key(&public_key).ok_or(AdminError::InvalidPublicKey)?;
+            let duration = duration_secs
+                .map(|secs| {
+                    i64::try_from(secs)
+                        .ok()
+                        .filter(|&s| s > 0)
+                        .and_then(chrono::Duration::try_seconds)
+                        .ok_or(AdminError::InvalidDuration(secs))
+                })
+                .transpose()?;
+            // Ban first so the user can't slip back in between the two steps
+            let ban = lobby
+                .moderation
+                .ban(&public_key, duration, chrono::Utc::now())
+                .await?;
+            let disconnected = disconnect(lobby, rooms, &public_key).await?;
+            let expires_at = ban.expires_at.map(|t| t.to_rfc3339());
+            lobby.audit.record(AuditEvent::Banned {
+                public_key: public_key.clone(),
+                expires_at: expires_at.clone(),
+                disconnected,
+            });
+            Ok(AdminResponse::Banned {
+                public_key,
+                disconnected,
+                expires_at,
+            })
+        }
+        AdminCommand::ListBans => {
+            let bans = lobby
+                .moderation
+                .active_bans()
+                .await
+                .into_iter()
+                .map(|(key, ban)| AdminBan::new(key, &ban))
+                .collect();
+            Ok(AdminResponse::Bans { bans })
+        }
+        AdminCommand::Unban { public_key } => {
+            let public_key = normalize_key(&public_key).ok_or(AdminError::InvalidPublicKey)?;
+            let was_banned = lobby.moderation.unban(&public_key).await?;
+            lobby.audit.record(AuditEvent::Unbanned {
+                public_key: public_key.clone(),
+                was_banned,
+            });
+            Ok(AdminResponse::Unbanned {
+                public_key,
+                was_banned,
+            })
+        }
+        AdminCommand::Announce { text } => {
+            let length = text.trim().chars().count();
+            if length == 0 || length > MAX_ANNOUNCEMENT_LENGTH {
+                return Err(AdminError::InvalidAnnouncement {
+                    length,
+                    max: MAX_ANNOUNCEMENT_LENGTH,
+                });
+            }
+            let recipients = lobby.users.len();
+            lobby
+                .broadcast(
+                    Message::new_announcement(
+                        text.trim().to_string(),
+                        chrono::Utc::now().to_rfc3339(),
+                    ),
+                    None,
+                )
+                .map_err(|e| AdminError::Lobby(e.to_string()))?;
+            Ok(AdminResponse::Announced { recipients })
+        }
+        AdminCommand::Stats => {
+            // Synthetic users share one sink, which would be counted once each
+            let depths: Vec<usize> = lobby
+                .get_all_connections()
+                .await
+                .map_err(|e| AdminError::Lobby(e.to_string()))?
+                .iter()
+                .filter(|conn| conn.connection_id != SYNTHETIC_CONNECTION_ID)
+                .map(|conn| conn.sender.depth())
+                .collect();
+            Ok(AdminResponse::Stats {
+                online_users: lobby.users.len(),
+                rooms: rooms.room_count().await,
+                stored_backups: lobby.backups.len().await,
+                banned_keys: lobby.moderation.banned_count().await,
+                queued_messages: depths.iter().sum(),
+                deepest_queue: depths.iter().copied().max().unwrap_or(0),
+                dropped_messages: lobby.send_queues.dropped(),
+                slow_consumers: lobby.send_queues.slow_consumers(),
+            })
+        }
+        AdminCommand::ValidationStats => Ok(AdminResponse::ValidationStats {
+            stages: lobby.validation.stats(),
+        }),
+        AdminCommand::AdmissionStats => Ok(AdminResponse::AdmissionStats {
+            admission: lobby.admission.stats(),
+        }),
+        AdminCommand::Populate { count } => {
+            if count == 0 || count > MAX_LOBBY_SIZE {
+                return Err(AdminError::InvalidCount(count));
+            }
+            let added = super::synthetic::populate(lobby, count)
+                .await
+                .map_err(|e| AdminError::Lobby(e.to_string()))?;
+            Ok(AdminResponse::Populated {
+                added,
+                online_users: lobby.users.len(),
+            })
+        }
+        AdminCommand::Depopulate => {
+            let removed = super::synthetic::depopulate(lobby)
+                .await
+                .map_err(|e| AdminError::Lobby(e.to_string()))?;
+            Ok(AdminResponse::Depopulated { removed })
+        }
+    }
+}
+
+/// Remove a user from rooms and the lobby and close their connection
+///
+/// # Returns
+/// true if the user was online
+async fn disconnect(lobby: &Lobby, rooms: &Rooms, public_key: &str) -> Result<bool, AdminError> {
+    let Some(conn) = lobby.users.get(public_key).await else {
+        return Ok(false);
+    };
+    lobby.moderation.kick(conn.connection_id).await;
+    crate::rooms::remove_user_from_rooms(rooms, lobby, public_key).await;
+    crate::lobby::remove_user(lobby, public_key)
+        .await
+        .map_err(|e| AdminError::Lobby(e.to_string()))?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+
+    const TOKEN: &str = "s3cret";
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, mut receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        while receiver.try_recv().is_ok() {}
+        receiver
+    }
+
+    async fn request(lobby: &Lobby, rooms: &Rooms, body: serde_json::Value) -> AdminResponse {
+        let mut body = body;
+        body["token"] = TOKEN.into();
+        handle_admin_request(lobby, rooms, TOKEN, &body.to_string()).await
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_the_token() {
+        let (lobby, rooms) = (Lobby::new(), Rooms::new());
+        let wrong = r#"{"token":"guess","command":"stats"}"#;
+        assert!(matches!(
+            handle_admin_request(&lobby, &rooms, TOKEN, wrong).await,
+            AdminResponse::Error { reason, .. } if reason == "unauthorized"
+        ));
+        assert!(matches!(
+            handle_admin_request(&lobby, &rooms, TOKEN, r#"{"command":"stats"}"#).await,
+            AdminResponse::Error { reason, .. } if reason == "malformed_request"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_users_and_stats() {
+        let (lobby, rooms) = (Lobby::new(), Rooms::new());
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let _bob_rx = connect(&lobby, &bob, 2).await;
+        lobby
+            .moderation
+            .ban(&"c".repeat(64), None, chrono::Utc::now())
+            .await
+            .unwrap();
+        // Alice still has Bob's join waiting to be written; give Bob one too
+        let bob_conn = lobby.users.get(&bob).await.unwrap();
+        bob_conn
+            .sender
+            .send(Message::new_error("test".to_string(), None))
+            .unwrap();
+
+        match request(&lobby, &rooms, serde_json::json!({"command": "list_users"})).await {
+            AdminResponse::Users { users } => {
+                let keys: Vec<&str> = users.iter().map(|u| u.public_key.as_str()).collect();
+                assert_eq!(keys, vec![alice.as_str(), bob.as_str()]);
+                assert_eq!(users[1].connection_id, 2);
+                assert_eq!((users[0].queue_depth, users[1].queue_depth), (1, 1));
+            }
+            other => panic!("Expected Users, got {:?}", other),
+        }
+        assert_eq!(
+            request(&lobby, &rooms, serde_json::json!({"command": "stats"})).await,
+            AdminResponse::Stats {
+                online_users: 2,
+                rooms: 0,
+                stored_backups: 0,
+                banned_keys: 1,
+                queued_messages: 2,
+                deepest_queue: 1,
+                dropped_messages: 0,
+                slow_consumers: 0,
+            }
+        );
+        match request(
+            &lobby,
+            &rooms,
+            serde_json::json!({"command": "validation_stats"}),
+        )
+        .await
+        {
+            AdminResponse::ValidationStats { stages } => {
+                assert_eq!(stages.len(), lobby.validation.stage_names().len());
+                assert_eq!(stages[0].stage, "size");
+                assert!(stages.iter().all(|s| s.passed == 0 && s.rejected == 0));
+            }
+            other => panic!("Expected ValidationStats, got {:?}", other),
+        }
+        match request(
+            &lobby,
+            &rooms,
+            serde_json::json!({"command": "admission_stats"}),
+        )
+        .await
+        {
+            AdminResponse::AdmissionStats { admission } => {
+                assert!(!admission.paced);
+                assert_eq!(admission.refused, 0);
+            }
+            other => panic!("Expected AdmissionStats, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ban_disconnects_and_kick_marks_connection() {
+        let (lobby, rooms) = (Lobby::new(), Rooms::new());
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let mut bob_rx = connect(&lobby, &bob, 2).await;
+
+        let upper = alice.to_uppercase();
+        assert_eq!(
+            request(
+                &lobby,
+                &rooms,
+                serde_json::json!({"command": "ban", "publicKey": upper})
+            )
+            .await,
+            AdminResponse::Banned {
+                public_key: alice.clone(),
+                disconnected: true,
+                expires_at: None,
+            }
+        );
+        assert!(lobby.moderation.is_banned(&alice).await);
+        assert!(lobby.moderation.take_kicked(1).await);
+        assert!(!lobby.users.contains_key(&alice).await);
+        lobby.flush_broadcasts().await.unwrap();
+        assert!(matches!(bob_rx.try_recv(), Ok(Message::LobbyUpdate { .. })));
+
+        assert_eq!(
+            request(
+                &lobby,
+                &rooms,
+                serde_json::json!({"command": "kick", "publicKey": alice})
+            )
+            .await,
+            AdminResponse::Kicked {
+                public_key: alice.clone(),
+                disconnected: false,
+            }
+        );
+        assert_eq!(
+            request(
+                &lobby,
+                &rooms,
+                serde_json::json!({"command": "unban", "publicKey": alice})
+            )
+            .await,
+            AdminResponse::Unbanned {
+                public_key: alice.clone(),
+                was_banned: true,
+            }
+        );
+        assert!(matches!(
+            request(&lobby, &rooms, serde_json::json!({"command": "kick", "publicKey": "xyz"})).await,
+            AdminResponse::Error { reason, .. } if reason == "invalid_public_key"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_timed_ban_listed_with_expiry() {
+        let (lobby, rooms) = (Lobby::new(), Rooms::new());
+        let alice = "a".repeat(64);
+
+        let expires_at = match request(
+            &lobby,
+            &rooms,
+            serde_json::json!({"command": "ban", "publicKey": alice, "durationSecs": 3600}),
+        )
+        .await
+        {
+            AdminResponse::Banned {
+                disconnected: false,
+                expires_at: Some(expires_at),
+                ..
+            } => expires_at,
+            other => panic!("Expected timed Banned, got {:?}", other),
+        };
+        match request(&lobby, &rooms, serde_json::json!({"command": "list_bans"})).await {
+            AdminResponse::Bans { bans } => {
+                assert_eq!(bans.len(), 1);
+                assert_eq!(bans[0].public_key, alice);
+                assert_eq!(bans[0].expires_at, Some(expires_at));
+            }
+            other => panic!("Expected Bans, got {:?}", other),
+        }
+
+        assert!(matches!(
+            request(
+                &lobby,
+                &rooms,
+                serde_json::json!({"command": "ban", "publicKey": alice, "durationSecs": 0})
+            )
+            .await,
+            AdminResponse::Error { reason, .. } if reason == "invalid_duration"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_populate_and_depopulate_commands() {
+        let (lobby, rooms) = (Lobby::new(), Rooms::new());
+        let _alice_rx = connect(&lobby, &"a".repeat(64), 1).await;
+
+        assert_eq!(
+            request(
+                &lobby,
+                &rooms,
+                serde_json::json!({"command": "populate", "count": 1000})
+            )
+            .await,
+            AdminResponse::Populated {
+                added: 1000,
+                online_users: 1001,
+            }
+        );
+        assert!(matches!(
+            request(&lobby, &rooms, serde_json::json!({"command": "populate", "count": 0})).await,
+            AdminResponse::Error { reason, .. } if reason == "invalid_count"
+        ));
+        assert_eq!(
+            request(&lobby, &rooms, serde_json::json!({"command": "depopulate"})).await,
+            AdminResponse::Depopulated { removed: 1000 }
+        );
+        assert_eq!(lobby.users.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_announcement_reaches_everyone() {
+        let (lobby, rooms) = (Lobby::new(), Rooms::new());
+        let mut alice_rx = connect(&lobby, &"a".repeat(64), 1).await;
+
+        assert_eq!(
+            request(
+                &lobby,
+                &rooms,
+                serde_json::json!({"command": "announce", "text": " Restarting at noon "})
+            )
+            .await,
+            AdminResponse::Announced { recipients: 1 }
+        );
+        lobby.flush_broadcasts().await.unwrap();
+        match alice_rx.try_recv().unwrap() {
+            Message::Announcement { text, .. } => assert_eq!(text, "Restarting at noon"),
+            other => panic!("Expected Announcement, got {:?}", other),
+        }
+
+        assert!(matches!(
+            request(&lobby, &rooms, serde_json::json!({"command": "announce", "text": "  "})).await,
+            AdminResponse::Error { reason, .. } if reason == "invalid_announcement"
+        ));
+    }
+}
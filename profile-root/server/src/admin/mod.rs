@@ -0,0 +1,257 @@
+//! Admin control API
+//!
+//! A separate TCP listener, bound to a loopback address, lets the server
+//! operator list connected users, kick a public key, ban it permanently or
+//! for a number of seconds, list and lift bans, broadcast an announcement
+//! and query lobby statistics, including send queue depths, slow consumers
+//! and what each message validation stage passed and rejected. For client testing it can also fill the
+//! lobby with synthetic users and remove them again (see [`synthetic`]).
+//! It is off unless `PROFILE_ADMIN_TOKEN` is set; `PROFILE_ADMIN_ADDR`
+//! overrides the address (default [`DEFAULT_ADMIN_ADDRESS`]).
+//!
+//! The protocol is one JSON object per line in each direction. Every
+//! request carries the token and a command, for example
+//! `{"token":"...","command":"kick","publicKey":"..."}`; see
+//! [`AdminCommand`] for the commands and [`AdminResponse`] for the replies.
+//! The admin API is not reachable from the WebSocket port.
+
+pub mod commands;
+pub mod synthetic;
+
+pub use commands::{
+    handle_admin_request, AdminBan, AdminCommand, AdminError, AdminResponse, AdminUser,
+};
+
+use crate::lobby::Lobby;
+use crate::rooms::Rooms;
+use profile_shared::config::admin::{DEFAULT_ADMIN_ADDRESS, MAX_ADMIN_REQUEST_SIZE};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Environment variable holding the admin token; the API is off when unset
+pub const ADMIN_TOKEN_ENV_VAR: &str = "PROFILE_ADMIN_TOKEN";
+
+/// Environment variable overriding the admin listen address
+pub const ADMIN_ADDR_ENV_VAR: &str = "PROFILE_ADMIN_ADDR";
+
+/// Shortest accepted admin token
+pub const MIN_TOKEN_LENGTH: usize = 16;
+
+/// Error types for admin API configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminConfigError {
+    /// The address is not a valid socket address
+    InvalidAddress(String),
+    /// The address is not a loopback address
+    NotLoopback(SocketAddr),
+    /// The token is shorter than [`MIN_TOKEN_LENGTH`]
+    TokenTooShort,
+}
+
+impl Display for AdminConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminConfigError::InvalidAddress(addr) => write!(
+                f,
+                "Invalid {} '{}': expected an address like {}",
+                ADMIN_ADDR_ENV_VAR, addr, DEFAULT_ADMIN_ADDRESS
+            ),
+            AdminConfigError::NotLoopback(addr) => write!(
+                f,
+                "{} must be a loopback address, got {}",
+                ADMIN_ADDR_ENV_VAR, addr
+            ),
+            AdminConfigError::TokenTooShort => write!(
+                f,
+                "{} must be at least {} characters",
+                ADMIN_TOKEN_ENV_VAR, MIN_TOKEN_LENGTH
+            ),
+        }
+    }
+}
+
+impl Error for AdminConfigError {}
+
+/// Where the admin API listens and the token it expects
+#[derive(Clone, PartialEq, Eq)]
+pub struct AdminConfig {
+    pub address: SocketAddr,
+    token: String,
+}
+
+impl fmt::Debug for AdminConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminConfig")
+            .field("address", &self.address)
+            .field("token", &"[redacted]")
+            .finish()
+    }
+}
+
+impl AdminConfig {
+    /// Read the admin configuration from the environment
+    ///
+    /// # Returns
+    /// Ok(None) if `PROFILE_ADMIN_TOKEN` is unset or empty
+    pub fn from_env() -> Result<Option<Self>, AdminConfigError> {
+        let Some(token) = std::env::var(ADMIN_TOKEN_ENV_VAR)
+            .ok()
+            .filter(|t| !t.is_empty())
+        else {
+            return Ok(None);
+        };
+        let address = std::env::var(ADMIN_ADDR_ENV_VAR).ok();
+        Self::parse(address.as_deref(), &token).map(Some)
+    }
+
+    /// Build a configuration from an address (None for the default) and token
+    pub fn parse(address: Option<&str>, token: &str) -> Result<Self, AdminConfigError> {
+        let address = address.map(str::trim).filter(|a| !a.is_empty());
+        let text = address.unwrap_or(DEFAULT_ADMIN_ADDRESS);
+        let address: SocketAddr = text
+            .parse()
+            .map_err(|_| AdminConfigError::InvalidAddress(text.to_string()))?;
+        if !address.ip().is_loopback() {
+            return Err(AdminConfigError::NotLoopback(address));
+        }
+        if token.chars().count() < MIN_TOKEN_LENGTH {
+            return Err(AdminConfigError::TokenTooShort);
+        }
+        Ok(Self {
+            address,
+            token: token.to_string(),
+        })
+    }
+}
+
+/// Accept admin connections until the listener fails
+///
+/// Each connection is served on its own task, one request line at a time.
+pub async fn serve(
+    listener: TcpListener,
+    config: Arc<AdminConfig>,
+    lobby: Arc<Lobby>,
+    rooms: Arc<Rooms>,
+) -> std::io::Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        tracing::info!(client_ip = %addr, "Admin connection");
+        let config = Arc::clone(&config);
+        let lobby = Arc::clone(&lobby);
+        let rooms = Arc::clone(&rooms);
+        tokio::spawn(async move {
+            if let Err(e) = handle_admin_connection(stream, &config, &lobby, &rooms).await {
+                tracing::warn!(error = %e, "Admin connection error");
+            }
+        });
+    }
+}
+
+/// Answer request lines on one admin connection until it closes
+///
+/// A line longer than [`MAX_ADMIN_REQUEST_SIZE`] is rejected and ends the
+/// connection.
+async fn handle_admin_connection(
+    stream: TcpStream,
+    config: &AdminConfig,
+    lobby: &Lobby,
+    rooms: &Rooms,
+) -> std::io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = (&mut reader)
+            .take(MAX_ADMIN_REQUEST_SIZE as u64 + 1)
+            .read_line(&mut line)
+            .await?;
+        if read == 0 {
+            return Ok(());
+        }
+        let too_long = read > MAX_ADMIN_REQUEST_SIZE;
+        let response = if too_long {
+            AdminError::MalformedRequest(format!(
+                "request exceeds {} bytes",
+                MAX_ADMIN_REQUEST_SIZE
+            ))
+            .into()
+        } else if line.trim().is_empty() {
+            continue;
+        } else {
+            handle_admin_request(lobby, rooms, &config.token, line.trim()).await
+        };
+
+        let mut reply = serde_json::to_string(&response)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        reply.push('\n');
+        write.write_all(reply.as_bytes()).await?;
+        if too_long {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    #[test]
+    fn test_config_requires_loopback_and_long_token() {
+        let config = AdminConfig::parse(None, TOKEN).unwrap();
+        assert_eq!(config.address.to_string(), DEFAULT_ADMIN_ADDRESS);
+        assert!(!format!("{:?}", config).contains(TOKEN));
+
+        assert!(AdminConfig::parse(Some("[::1]:9000"), TOKEN).is_ok());
+        assert!(matches!(
+            AdminConfig::parse(Some("0.0.0.0:9000"), TOKEN),
+            Err(AdminConfigError::NotLoopback(_))
+        ));
+        assert!(matches!(
+            AdminConfig::parse(Some("localhost"), TOKEN),
+            Err(AdminConfigError::InvalidAddress(_))
+        ));
+        assert_eq!(
+            AdminConfig::parse(None, "short"),
+            Err(AdminConfigError::TokenTooShort)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_admin_socket_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let config = Arc::new(AdminConfig::parse(Some(&address.to_string()), TOKEN).unwrap());
+        let server = tokio::spawn(serve(
+            listener,
+            config,
+            Arc::new(Lobby::new()),
+            Arc::new(Rooms::new()),
+        ));
+
+        let stream = TcpStream::connect(address).await.unwrap();
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+
+        let request = format!("{{\"token\":\"{}\",\"command\":\"stats\"}}\n", TOKEN);
+        write.write_all(request.as_bytes()).await.unwrap();
+        let reply: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["type"], "stats");
+        assert_eq!(reply["onlineUsers"], 0);
+
+        let oversized = format!("{}\n", "x".repeat(MAX_ADMIN_REQUEST_SIZE + 1));
+        write.write_all(oversized.as_bytes()).await.unwrap();
+        let reply: serde_json::Value =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(reply["reason"], "malformed_request");
+        assert_eq!(lines.next_line().await.unwrap(), None);
+        server.abort();
+    }
+}
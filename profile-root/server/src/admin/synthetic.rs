@@ -0,0 +1,139 @@
+//! Synthetic lobby users for client testing
+//!
+//! The `populate` admin command fills the lobby with fake users so client
+//! developers can try the lobby UI and pagination against a large lobby
+//! without scripting hundreds of real connections. Each synthetic user has
+//! a generated public key, a nickname like `synthetic-0042` and a
+//! connection whose messages go to a sink that discards them. Joins are
+//! broadcast like any other, so connected clients see the lobby grow.
+//!
+//! Synthetic connections use [`SYNTHETIC_CONNECTION_ID`], which is never
+//! given to a real connection, so `depopulate` removes exactly them.
+
+use crate::connection::send_queue::{send_queue_with, SlowConsumerPolicy};
+use crate::lobby::{ActiveConnection, Lobby, UserStatus};
+use profile_shared::config::connection::SEND_QUEUE_CAPACITY;
+use profile_shared::LobbyError;
+
+/// Connection id of every synthetic user; real connection ids start at 1
+pub const SYNTHETIC_CONNECTION_ID: u64 = 0;
+
+/// First hex digits of every synthetic public key
+const SYNTHETIC_KEY_PREFIX: &str = "5e5e5e5e";
+
+/// Public key of the synthetic user numbered `index`
+pub fn synthetic_key(index: usize) -> String {
+    format!("{}{:056x}", SYNTHETIC_KEY_PREFIX, index)
+}
+
+/// Add `count` synthetic users to the lobby
+///
+/// Numbering continues after synthetic users already present, so repeated
+/// calls keep growing the lobby. Stops early if the lobby fills up.
+///
+/// # Returns
+/// The number of users added
+pub async fn populate(lobby: &Lobby, count: usize) -> Result<usize, LobbyError> {
+    // One sink for every synthetic user; the drain task ends once they are
+    // all removed and the last sender is dropped. It is shared, so it drops
+    // what it can't keep up with rather than counting as a slow consumer.
+    let (sink, mut discarded) =
+        send_queue_with(SEND_QUEUE_CAPACITY, SlowConsumerPolicy::DropNewest);
+    tokio::spawn(async move { while discarded.recv().await.is_some() {} });
+
+    let mut added = 0;
+    let mut index = 0;
+    while added < count {
+        index += 1;
+        let key = synthetic_key(index);
+        if lobby.users.contains_key(&key).await {
+            continue;
+        }
+        // Nickname first, so the join broadcast carries it
+        let _ = lobby
+            .nicknames
+            .set(&key, &format!("synthetic-{:04}", index))
+            .await;
+        let conn = ActiveConnection {
+            public_key: key.clone(),
+            sender: sink.clone(),
+            connection_id: SYNTHETIC_CONNECTION_ID,
+            status: UserStatus::default(),
+        };
+        match crate::lobby::add_user(lobby, key.clone(), conn).await {
+            Ok(()) => added += 1,
+            Err(LobbyError::LobbyFull) => {
+                lobby.nicknames.clear(&key).await;
+                break;
+            }
+            Err(e) => {
+                lobby.nicknames.clear(&key).await;
+                return Err(e);
+            }
+        }
+    }
+    Ok(added)
+}
+
+/// Remove every synthetic user from the lobby
+///
+/// # Returns
+/// The number of users removed
+pub async fn depopulate(lobby: &Lobby) -> Result<usize, LobbyError> {
+    let synthetic: Vec<String> = lobby
+        .get_all_connections()
+        .await?
+        .iter()
+        .filter(|conn| conn.connection_id == SYNTHETIC_CONNECTION_ID)
+        .map(|conn| conn.public_key.clone())
+        .collect();
+    for key in &synthetic {
+        crate::lobby::remove_user(lobby, key).await?;
+    }
+    Ok(synthetic.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::send_queue;
+    use profile_shared::Message;
+
+    #[tokio::test]
+    async fn test_populate_and_depopulate_leave_real_users() {
+        let lobby = Lobby::new();
+        let real = "a".repeat(64);
+        let (sender, mut real_rx) = send_queue();
+        let conn = ActiveConnection {
+            public_key: real.clone(),
+            sender,
+            connection_id: 7,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(&lobby, real.clone(), conn)
+            .await
+            .unwrap();
+
+        assert_eq!(populate(&lobby, 3).await.unwrap(), 3);
+        assert_eq!(populate(&lobby, 2).await.unwrap(), 2);
+        assert_eq!(lobby.users.len(), 6);
+        assert_eq!(
+            lobby.nicknames.get(&synthetic_key(5)).await.as_deref(),
+            Some("synthetic-0005")
+        );
+
+        // The real user sees the synthetic users join
+        lobby.flush_broadcasts().await.unwrap();
+        let mut joins = 0;
+        while let Ok(message) = real_rx.try_recv() {
+            if let Message::LobbyUpdate { joined, .. } = message {
+                joins += joined.len();
+            }
+        }
+        assert_eq!(joins, 5);
+
+        assert_eq!(depopulate(&lobby).await.unwrap(), 5);
+        assert_eq!(lobby.users.keys().await, vec![real]);
+        assert!(lobby.nicknames.get(&synthetic_key(1)).await.is_none());
+    }
+}
@@ -0,0 +1,328 @@
+//! Paced admission to the lobby during reconnection storms
+//!
+//! After a restart every client that was connected comes back at once.
+//! Joining the lobby is the expensive part of a connection (a join
+//! broadcast to everyone already there, the first lobby page, a resume
+//! token), so a flood of joins can stall the server just as it comes back.
+//!
+//! An [`AdmissionQueue`] spreads joins out: each authenticated connection
+//! is given a slot `1 / rate` seconds after the previous one and waits for
+//! it before joining. Recently active users, those the presence record says
+//! were online when the previous run stopped and those resuming a session
+//! with a token, are scheduled on a priority lane. Every priority admission
+//! also pushes the normal lane back one slot, so the people who were in
+//! the middle of a conversation are back first and the two lanes together
+//! keep to the rate.
+//!
+//! A lane more than `capacity` slots deep refuses further connections with
+//! a retry hint drawn at random from the time the backlog takes to drain,
+//! so refused clients come back spread out instead of all at once. The
+//! shutdown close frame carries a jittered hint too (see
+//! [`shutdown_retry_hint`]).
+//!
+//! Lobbies admit without pacing unless given a paced queue; the server
+//! paces at `PROFILE_ADMISSION_RATE` (see [`crate::runtime`]). Admission
+//! counts and wait times are reported by the admin `admission_stats`
+//! command.
+
+use profile_shared::config::load::SERVER_BUSY_RETRY_AFTER;
+use profile_shared::config::reconnect::{
+    ACTIVE_RETRY_MAX, ACTIVE_RETRY_MIN, IDLE_RETRY_MAX, IDLE_RETRY_MIN,
+};
+use rand::Rng;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// First free slot on each lane
+///
+/// A slot in the past is as good as now.
+#[derive(Debug, Default)]
+struct Lanes {
+    priority: Option<Instant>,
+    normal: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct AdmissionMetrics {
+    admitted: AtomicU64,
+    prioritized: AtomicU64,
+    refused: AtomicU64,
+    total_wait_micros: AtomicU64,
+    max_wait_micros: AtomicU64,
+}
+
+impl AdmissionMetrics {
+    fn record_admitted(&self, priority: bool, wait: Duration) {
+        self.admitted.fetch_add(1, Ordering::Relaxed);
+        if priority {
+            self.prioritized.fetch_add(1, Ordering::Relaxed);
+        }
+        let micros = u64::try_from(wait.as_micros()).unwrap_or(u64::MAX);
+        self.total_wait_micros.fetch_add(micros, Ordering::Relaxed);
+        self.max_wait_micros.fetch_max(micros, Ordering::Relaxed);
+    }
+}
+
+/// What the admission queue has done since startup, as reported by
+/// `admission_stats`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AdmissionStats {
+    /// Whether joins are paced at all
+    pub paced: bool,
+    /// Connections let into the lobby
+    pub admitted: u64,
+    /// Of those, connections admitted on the priority lane
+    pub prioritized: u64,
+    /// Connections refused because their lane was full
+    pub refused: u64,
+    /// Mean wait for an admission slot, in microseconds
+    #[serde(rename = "meanWaitMicros")]
+    pub mean_wait_micros: u64,
+    /// Longest wait for an admission slot, in microseconds
+    #[serde(rename = "maxWaitMicros")]
+    pub max_wait_micros: u64,
+}
+
+/// Schedules joins to the lobby at a steady rate
+#[derive(Debug)]
+pub struct AdmissionQueue {
+    /// Time between admissions, None when joins are not paced
+    interval: Option<Duration>,
+    capacity: usize,
+    lanes: Mutex<Lanes>,
+    metrics: AdmissionMetrics,
+}
+
+impl AdmissionQueue {
+    /// Create a queue that admits every connection straight away
+    pub fn unpaced() -> Self {
+        Self {
+            interval: None,
+            capacity: usize::MAX,
+            lanes: Mutex::new(Lanes::default()),
+            metrics: AdmissionMetrics::default(),
+        }
+    }
+
+    /// Create a queue admitting `rate` connections per second
+    ///
+    /// # Arguments
+    /// * `rate` - Admissions per second; 0 means unpaced
+    /// * `capacity` - Connections that may wait on each lane before
+    ///   further ones are refused
+    pub fn paced(rate: usize, capacity: usize) -> Self {
+        if rate == 0 {
+            return Self::unpaced();
+        }
+        Self {
+            interval: Some(Duration::from_secs(1) / u32::try_from(rate).unwrap_or(u32::MAX)),
+            capacity: capacity.max(1),
+            lanes: Mutex::new(Lanes::default()),
+            metrics: AdmissionMetrics::default(),
+        }
+    }
+
+    /// Whether joins are paced
+    pub fn is_paced(&self) -> bool {
+        self.interval.is_some()
+    }
+
+    /// Reserve the next admission slot on a lane
+    ///
+    /// # Arguments
+    /// * `priority` - Whether the user was recently active
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    /// How long to wait before joining, or Err with the time the lane's
+    /// backlog takes to drain if it is full (see [`refusal_retry_hint`])
+    pub async fn reserve(&self, priority: bool, now: Instant) -> Result<Duration, Duration> {
+        let Some(interval) = self.interval else {
+            self.metrics.record_admitted(priority, Duration::ZERO);
+            return Ok(Duration::ZERO);
+        };
+        let free = |slot: Option<Instant>| slot.map_or(now, |slot| slot.max(now));
+
+        let mut lanes = self.lanes.lock().await;
+        let slot = if priority {
+            free(lanes.priority)
+        } else {
+            free(lanes.normal)
+        };
+        let wait = slot - now;
+        let depth = wait.as_nanos() / interval.as_nanos();
+        if depth >= self.capacity as u128 {
+            self.metrics.refused.fetch_add(1, Ordering::Relaxed);
+            return Err(wait);
+        }
+        if priority {
+            lanes.priority = Some(slot + interval);
+            lanes.normal = Some(free(lanes.normal) + interval);
+        } else {
+            lanes.normal = Some(slot + interval);
+        }
+        drop(lanes);
+
+        self.metrics.record_admitted(priority, wait);
+        Ok(wait)
+    }
+
+    /// Admissions and wait times since startup
+    pub fn stats(&self) -> AdmissionStats {
+        let admitted = self.metrics.admitted.load(Ordering::Relaxed);
+        let total_wait_micros = self.metrics.total_wait_micros.load(Ordering::Relaxed);
+        AdmissionStats {
+            paced: self.is_paced(),
+            admitted,
+            prioritized: self.metrics.prioritized.load(Ordering::Relaxed),
+            refused: self.metrics.refused.load(Ordering::Relaxed),
+            mean_wait_micros: total_wait_micros.checked_div(admitted).unwrap_or(0),
+            max_wait_micros: self.metrics.max_wait_micros.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for AdmissionQueue {
+    fn default() -> Self {
+        Self::unpaced()
+    }
+}
+
+/// Delay to suggest to a connection refused by a full lane
+///
+/// Drawn between one and two times the `backlog` still to drain, and never
+/// below `SERVER_BUSY_RETRY_AFTER`.
+pub fn refusal_retry_hint(backlog: Duration, rng: &mut impl Rng) -> Duration {
+    let backlog = backlog.max(SERVER_BUSY_RETRY_AFTER);
+    random_millis(backlog, backlog * 2, rng)
+}
+
+/// Delay to suggest to a client whose connection is closed by a shutdown
+///
+/// Recently active users are asked back within a few seconds, idle ones
+/// over the next half minute, so the reconnections after a restart arrive
+/// spread out with the active users first.
+pub fn shutdown_retry_hint(recently_active: bool, rng: &mut impl Rng) -> Duration {
+    if recently_active {
+        random_millis(ACTIVE_RETRY_MIN, ACTIVE_RETRY_MAX, rng)
+    } else {
+        random_millis(IDLE_RETRY_MIN, IDLE_RETRY_MAX, rng)
+    }
+}
+
+/// A whole number of milliseconds from `min` to `max`, as hints are sent
+fn random_millis(min: Duration, max: Duration, rng: &mut impl Rng) -> Duration {
+    let min = u64::try_from(min.as_millis()).unwrap_or(u64::MAX);
+    let max = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(rng.gen_range(min..=max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[tokio::test]
+    async fn test_unpaced_queue_admits_immediately() {
+        let queue = AdmissionQueue::unpaced();
+        let now = Instant::now();
+        for _ in 0..100 {
+            assert_eq!(queue.reserve(false, now).await, Ok(Duration::ZERO));
+        }
+        let stats = queue.stats();
+        assert!(!stats.paced);
+        assert_eq!(stats.admitted, 100);
+        assert_eq!(stats.max_wait_micros, 0);
+        assert!(!AdmissionQueue::paced(0, 10).is_paced());
+    }
+
+    #[tokio::test]
+    async fn test_admissions_spaced_by_rate() {
+        // 10 per second: one every 100 ms
+        let queue = AdmissionQueue::paced(10, 100);
+        let now = Instant::now();
+        for i in 0..5 {
+            assert_eq!(
+                queue.reserve(false, now).await,
+                Ok(Duration::from_millis(100 * i))
+            );
+        }
+        // Slots that have gone by are not handed out late
+        let later = now + Duration::from_secs(10);
+        assert_eq!(queue.reserve(false, later).await, Ok(Duration::ZERO));
+
+        let stats = queue.stats();
+        assert_eq!(stats.admitted, 6);
+        assert_eq!(stats.prioritized, 0);
+        assert_eq!(stats.max_wait_micros, 400_000);
+        assert_eq!(stats.mean_wait_micros, 1_000_000 / 6);
+    }
+
+    #[tokio::test]
+    async fn test_recently_active_users_admitted_first() {
+        let queue = AdmissionQueue::paced(10, 100);
+        let now = Instant::now();
+        for _ in 0..10 {
+            queue.reserve(false, now).await.unwrap();
+        }
+        // Ahead of the ten idle users already waiting
+        assert_eq!(queue.reserve(true, now).await, Ok(Duration::ZERO));
+        assert_eq!(
+            queue.reserve(true, now).await,
+            Ok(Duration::from_millis(100))
+        );
+        // Each priority admission pushed the idle lane back one slot
+        assert_eq!(
+            queue.reserve(false, now).await,
+            Ok(Duration::from_millis(1200))
+        );
+        assert_eq!(queue.stats().prioritized, 2);
+    }
+
+    #[tokio::test]
+    async fn test_full_lane_refuses_with_backlog() {
+        let queue = AdmissionQueue::paced(10, 3);
+        let now = Instant::now();
+        for _ in 0..3 {
+            queue.reserve(false, now).await.unwrap();
+        }
+        assert_eq!(
+            queue.reserve(false, now).await,
+            Err(Duration::from_millis(300))
+        );
+        // The priority lane has its own room
+        assert!(queue.reserve(true, now).await.is_ok());
+        // Once the backlog drains there is room again
+        assert!(queue
+            .reserve(false, now + Duration::from_millis(500))
+            .await
+            .is_ok());
+
+        let stats = queue.stats();
+        assert_eq!(stats.refused, 1);
+        assert_eq!(stats.admitted, 5);
+    }
+
+    #[test]
+    fn test_retry_hints_jittered_within_bounds() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let backlog = Duration::from_secs(4);
+        let refusals: Vec<_> = (0..200)
+            .map(|_| refusal_retry_hint(backlog, &mut rng))
+            .collect();
+        assert!(refusals.iter().all(|d| (backlog..=backlog * 2).contains(d)));
+        // Spread out, not all the same
+        assert!(refusals.iter().any(|d| *d != refusals[0]));
+        assert!(refusal_retry_hint(Duration::ZERO, &mut rng) >= SERVER_BUSY_RETRY_AFTER);
+
+        for _ in 0..200 {
+            let active = shutdown_retry_hint(true, &mut rng);
+            assert!((ACTIVE_RETRY_MIN..=ACTIVE_RETRY_MAX).contains(&active));
+            let idle = shutdown_retry_hint(false, &mut rng);
+            assert!((IDLE_RETRY_MIN..=IDLE_RETRY_MAX).contains(&idle));
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! Attachment transfers in progress
+//!
+//! The server relays files between users without storing them. It keeps a
+//! small record per transfer: who is on either end, the file's chunk
+//! layout, how many chunks were relayed and how many the recipient asked
+//! for. The record lets the server check every chunk (from the sender,
+//! asked for, in order, as long as the manifest says) and bound what a
+//! sender can push: a recipient asks for at most [`ACCEPT_WINDOW`] chunks
+//! beyond those already relayed, so a transfer never fills its recipient's
+//! send queue.
+//!
+//! A transfer ends when its sender completes it, either side cancels it or
+//! leaves the lobby, or nothing happens on it for [`TRANSFER_IDLE_TIMEOUT`].
+//! Records are kept in memory, on the node both users are connected to.
+
+use crate::lobby::ServerPublicKey;
+use profile_shared::config::attachments::{
+    ACCEPT_WINDOW, MAX_TRANSFERS_PER_SENDER, TRANSFER_IDLE_TIMEOUT,
+};
+use profile_shared::protocol::AttachmentManifest;
+use profile_shared::AttachmentError;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// One transfer's sides and progress
+#[derive(Debug, Clone)]
+struct Transfer {
+    sender: ServerPublicKey,
+    recipient: ServerPublicKey,
+    /// The offered manifest without its chunk hashes, for the chunk layout
+    layout: AttachmentManifest,
+    /// Chunks relayed so far
+    sent: u32,
+    /// Chunks below this index may be sent
+    requested: u32,
+    last_activity: Instant,
+}
+
+/// How a transfer ended, as told to the other side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferEnd {
+    /// Who to tell
+    pub peer: ServerPublicKey,
+    /// Whether the transfer was abandoned rather than finished
+    pub cancelled: bool,
+}
+
+/// Thread-safe registry of attachment transfers in progress
+#[derive(Debug)]
+pub struct TransferRegistry {
+    transfers: RwLock<HashMap<Uuid, Transfer>>,
+    max_per_sender: usize,
+    idle_timeout: Duration,
+}
+
+impl Default for TransferRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TransferRegistry {
+    /// Create a registry using the configured limits
+    pub fn new() -> Self {
+        Self::with_limits(MAX_TRANSFERS_PER_SENDER, TRANSFER_IDLE_TIMEOUT)
+    }
+
+    /// Create a registry with custom limits
+    ///
+    /// # Arguments
+    /// * `max_per_sender` - Maximum transfers one sender can have in progress
+    /// * `idle_timeout` - How long a transfer may sit idle before it is
+    ///   forgotten
+    pub fn with_limits(max_per_sender: usize, idle_timeout: Duration) -> Self {
+        Self {
+            transfers: RwLock::new(HashMap::new()),
+            max_per_sender,
+            idle_timeout,
+        }
+    }
+
+    /// Record a transfer offered by `sender` to `recipient`
+    ///
+    /// The manifest must already have been checked. Idle transfers are
+    /// forgotten first, so they don't count against the sender's limit.
+    pub async fn offer(
+        &self,
+        sender: &str,
+        recipient: &str,
+        manifest: &AttachmentManifest,
+    ) -> Result<(), AttachmentError> {
+        let now = Instant::now();
+        let mut transfers = self.transfers.write().await;
+        transfers.retain(|_, t| now.duration_since(t.last_activity) < self.idle_timeout);
+        if transfers.contains_key(&manifest.transfer_id) {
+            return Err(AttachmentError::DuplicateTransfer);
+        }
+        if transfers.values().filter(|t| t.sender == sender).count() >= self.max_per_sender {
+            return Err(AttachmentError::TooManyTransfers {
+                max: self.max_per_sender,
+            });
+        }
+        let layout = AttachmentManifest {
+            chunk_hashes: Vec::new(),
+            thumbnail: None,
+            ..manifest.clone()
+        };
+        transfers.insert(
+            manifest.transfer_id,
+            Transfer {
+                sender: sender.to_string(),
+                recipient: recipient.to_string(),
+                layout,
+                sent: 0,
+                requested: 0,
+                last_activity: now,
+            },
+        );
+        Ok(())
+    }
+
+    /// The recipient asks for the chunks below `until`
+    ///
+    /// At most [`ACCEPT_WINDOW`] chunks beyond those already relayed are
+    /// granted, and never fewer than an earlier accept granted.
+    ///
+    /// # Returns
+    /// The sender to forward the accept to, and the index chunks may now be
+    /// sent up to
+    pub async fn accept(
+        &self,
+        recipient: &str,
+        transfer_id: Uuid,
+        until: u32,
+    ) -> Result<(ServerPublicKey, u32), AttachmentError> {
+        let mut transfers = self.transfers.write().await;
+        let transfer = self.live(&mut transfers, transfer_id, |t| t.recipient == recipient)?;
+        let granted = until
+            .min(transfer.layout.chunk_count())
+            .min(transfer.sent.saturating_add(ACCEPT_WINDOW));
+        transfer.requested = transfer.requested.max(granted);
+        Ok((transfer.sender.clone(), transfer.requested))
+    }
+
+    /// The sender relays chunk `index`, `data_len` bytes once decoded
+    ///
+    /// # Returns
+    /// The recipient to forward the chunk to
+    pub async fn chunk(
+        &self,
+        sender: &str,
+        transfer_id: Uuid,
+        index: u32,
+        data_len: usize,
+    ) -> Result<ServerPublicKey, AttachmentError> {
+        let mut transfers = self.transfers.write().await;
+        let transfer = self.live(&mut transfers, transfer_id, |t| t.sender == sender)?;
+        if index != transfer.sent || index >= transfer.requested {
+            return Err(AttachmentError::ChunkNotRequested { index });
+        }
+        if transfer.layout.chunk_len(index) != Some(data_len) {
+            return Err(AttachmentError::InvalidChunk { index });
+        }
+        transfer.sent += 1;
+        Ok(transfer.recipient.clone())
+    }
+
+    /// End a transfer, finished by its sender or abandoned by either side
+    ///
+    /// The recipient can only abandon a transfer; its completion always
+    /// counts as cancelled.
+    ///
+    /// # Returns
+    /// Who to tell, and whether the transfer was cancelled
+    pub async fn complete(
+        &self,
+        party: &str,
+        transfer_id: Uuid,
+        cancelled: bool,
+    ) -> Result<TransferEnd, AttachmentError> {
+        let mut transfers = self.transfers.write().await;
+        let transfer = self.live(&mut transfers, transfer_id, |t| {
+            t.sender == party || t.recipient == party
+        })?;
+        let end = if transfer.sender == party {
+            let total = transfer.layout.chunk_count();
+            if !cancelled && transfer.sent < total {
+                return Err(AttachmentError::Incomplete {
+                    sent: transfer.sent,
+                    total,
+                });
+            }
+            TransferEnd {
+                peer: transfer.recipient.clone(),
+                cancelled,
+            }
+        } else {
+            TransferEnd {
+                peer: transfer.sender.clone(),
+                cancelled: true,
+            }
+        };
+        transfers.remove(&transfer_id);
+        Ok(end)
+    }
+
+    /// Forget every transfer a user leaving the lobby is part of
+    ///
+    /// # Returns
+    /// Each forgotten transfer's id and the user on its other side
+    pub async fn forget_user(&self, public_key: &str) -> Vec<(Uuid, ServerPublicKey)> {
+        let mut forgotten = Vec::new();
+        self.transfers.write().await.retain(|id, t| {
+            let peer = if t.sender == public_key {
+                &t.recipient
+            } else if t.recipient == public_key {
+                &t.sender
+            } else {
+                return true;
+            };
+            forgotten.push((*id, peer.clone()));
+            false
+        });
+        forgotten
+    }
+
+    /// The transfer `transfer_id` if `party` may act on it and it hasn't
+    /// gone idle, marked active
+    fn live<'a>(
+        &self,
+        transfers: &'a mut HashMap<Uuid, Transfer>,
+        transfer_id: Uuid,
+        party: impl Fn(&Transfer) -> bool,
+    ) -> Result<&'a mut Transfer, AttachmentError> {
+        let now = Instant::now();
+        let state = transfers.get(&transfer_id).map(|t| {
+            (
+                now.duration_since(t.last_activity) >= self.idle_timeout,
+                party(t),
+            )
+        });
+        match state {
+            Some((true, _)) => {
+                transfers.remove(&transfer_id);
+                return Err(AttachmentError::UnknownTransfer);
+            }
+            Some((false, true)) => {}
+            _ => return Err(AttachmentError::UnknownTransfer),
+        }
+        let transfer = transfers
+            .get_mut(&transfer_id)
+            .ok_or(AttachmentError::UnknownTransfer)?;
+        transfer.last_activity = now;
+        Ok(transfer)
+    }
+}
+
+/// Error reason sent to the client for an attachment error
+pub fn attachment_error_reason(error: &AttachmentError) -> &'static str {
+    match error {
+        AttachmentError::TooLarge { .. } => "attachment_too_large",
+        AttachmentError::InvalidManifest { .. } => "invalid_attachment",
+        AttachmentError::UnknownTransfer => "unknown_transfer",
+        AttachmentError::DuplicateTransfer => "duplicate_transfer",
+        AttachmentError::TooManyTransfers { .. } => "too_many_transfers",
+        AttachmentError::ChunkNotRequested { .. } => "chunk_not_requested",
+        AttachmentError::InvalidChunk { .. } => "invalid_chunk",
+        AttachmentError::Incomplete { .. } => "transfer_incomplete",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profile_shared::config::attachments::CHUNK_SIZE;
+    use profile_shared::generate_private_key;
+
+    /// A manifest for a file of `size` bytes
+    fn manifest(size: usize) -> AttachmentManifest {
+        AttachmentManifest::sign(
+            &generate_private_key().unwrap(),
+            Uuid::new_v4(),
+            "photo.jpg",
+            &vec![1u8; size],
+            "2026-03-02T09:00:00Z",
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_chunks_relayed_in_order_within_the_accepted_window() {
+        let registry = TransferRegistry::new();
+        let manifest = manifest(CHUNK_SIZE * (ACCEPT_WINDOW as usize + 1) + 5);
+        let id = manifest.transfer_id;
+        registry.offer("alice", "bob", &manifest).await.unwrap();
+
+        // Nothing may be sent before the recipient accepts
+        assert_eq!(
+            registry.chunk("alice", id, 0, CHUNK_SIZE).await,
+            Err(AttachmentError::ChunkNotRequested { index: 0 })
+        );
+        // Only the recipient accepts, and at most a window at a time
+        assert_eq!(
+            registry.accept("alice", id, 100).await,
+            Err(AttachmentError::UnknownTransfer)
+        );
+        assert_eq!(
+            registry.accept("bob", id, u32::MAX).await,
+            Ok(("alice".to_string(), ACCEPT_WINDOW))
+        );
+
+        assert_eq!(
+            registry.chunk("alice", id, 1, CHUNK_SIZE).await,
+            Err(AttachmentError::ChunkNotRequested { index: 1 })
+        );
+        assert_eq!(
+            registry.chunk("alice", id, 0, CHUNK_SIZE - 1).await,
+            Err(AttachmentError::InvalidChunk { index: 0 })
+        );
+        assert_eq!(
+            registry.chunk("bob", id, 0, CHUNK_SIZE).await,
+            Err(AttachmentError::UnknownTransfer)
+        );
+        for index in 0..ACCEPT_WINDOW {
+            assert_eq!(
+                registry.chunk("alice", id, index, CHUNK_SIZE).await,
+                Ok("bob".to_string())
+            );
+        }
+        assert_eq!(
+            registry.complete("alice", id, false).await,
+            Err(AttachmentError::Incomplete {
+                sent: ACCEPT_WINDOW,
+                total: ACCEPT_WINDOW + 2
+            })
+        );
+
+        registry.accept("bob", id, u32::MAX).await.unwrap();
+        registry
+            .chunk("alice", id, ACCEPT_WINDOW, CHUNK_SIZE)
+            .await
+            .unwrap();
+        registry
+            .chunk("alice", id, ACCEPT_WINDOW + 1, 5)
+            .await
+            .unwrap();
+        assert_eq!(
+            registry.complete("alice", id, false).await,
+            Ok(TransferEnd {
+                peer: "bob".to_string(),
+                cancelled: false
+            })
+        );
+        assert_eq!(
+            registry.accept("bob", id, 1).await,
+            Err(AttachmentError::UnknownTransfer)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recipient_can_only_cancel() {
+        let registry = TransferRegistry::new();
+        let manifest = manifest(10);
+        registry.offer("alice", "bob", &manifest).await.unwrap();
+        assert_eq!(
+            registry.complete("bob", manifest.transfer_id, false).await,
+            Ok(TransferEnd {
+                peer: "alice".to_string(),
+                cancelled: true
+            })
+        );
+        assert_eq!(
+            registry.complete("alice", manifest.transfer_id, true).await,
+            Err(AttachmentError::UnknownTransfer)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfers_per_sender_limited_and_idle_ones_forgotten() {
+        let registry = TransferRegistry::with_limits(1, Duration::from_secs(60));
+        let first = manifest(10);
+        registry.offer("alice", "bob", &first).await.unwrap();
+        assert_eq!(
+            registry.offer("alice", "bob", &first).await,
+            Err(AttachmentError::DuplicateTransfer)
+        );
+        assert_eq!(
+            registry.offer("alice", "carol", &manifest(10)).await,
+            Err(AttachmentError::TooManyTransfers { max: 1 })
+        );
+        assert_eq!(
+            registry.forget_user("bob").await,
+            vec![(first.transfer_id, "alice".to_string())]
+        );
+        registry
+            .offer("alice", "carol", &manifest(10))
+            .await
+            .unwrap();
+
+        let idle = TransferRegistry::with_limits(1, Duration::ZERO);
+        idle.offer("alice", "bob", &first).await.unwrap();
+        assert_eq!(
+            idle.accept("bob", first.transfer_id, 1).await,
+            Err(AttachmentError::UnknownTransfer)
+        );
+        idle.offer("alice", "bob", &manifest(10)).await.unwrap();
+    }
+}
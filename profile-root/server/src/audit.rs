@@ -0,0 +1,446 @@
+//! Durable audit trail of connections, sign-ins and moderation
+//!
+//! Separate from the diagnostic `tracing` output, whose level and format
+//! operators change freely, the audit log keeps a stable record of who
+//! connected, who signed in or was refused, who was kicked or banned, and
+//! which messages could not be routed. It is off unless `PROFILE_AUDIT_LOG`
+//! names a file; each event is appended there as one JSON object per line:
+//!
+//! ```text
+//! {"timestamp":"2026-10-16T09:30:00+00:00","event":"auth_failed","connectionId":7,"reason":"banned","publicKey":"ab12..."}
+//! ```
+//!
+//! Once the file would grow past `PROFILE_AUDIT_LOG_MAX_BYTES` it is rotated:
+//! `audit.jsonl` becomes `audit.jsonl.1`, `audit.jsonl.1` becomes
+//! `audit.jsonl.2` and so on, keeping `PROFILE_AUDIT_LOG_KEEP` rotated files.
+//! Writing never holds up a connection: a failed write is reported through
+//! `tracing` and the event is lost.
+
+use profile_shared::config;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Environment variable naming the audit log file; auditing is off when unset
+pub const AUDIT_LOG_ENV_VAR: &str = "PROFILE_AUDIT_LOG";
+
+/// Environment variable setting the size in bytes at which the log rotates
+pub const AUDIT_LOG_MAX_BYTES_ENV_VAR: &str = "PROFILE_AUDIT_LOG_MAX_BYTES";
+
+/// Environment variable setting how many rotated files are kept
+pub const AUDIT_LOG_KEEP_ENV_VAR: &str = "PROFILE_AUDIT_LOG_KEEP";
+
+/// Error types for the audit log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditLogError {
+    /// Opening or writing the log failed
+    Io(String),
+    /// A setting is not a positive whole number
+    InvalidSetting { name: &'static str, value: String },
+}
+
+impl Display for AuditLogError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditLogError::Io(msg) => write!(f, "Failed to write audit log: {}", msg),
+            AuditLogError::InvalidSetting { name, value } => write!(
+                f,
+                "Invalid {}: {:?} (expected a positive number)",
+                name, value
+            ),
+        }
+    }
+}
+
+impl Error for AuditLogError {}
+
+impl From<io::Error> for AuditLogError {
+    fn from(e: io::Error) -> Self {
+        AuditLogError::Io(e.to_string())
+    }
+}
+
+/// Something the operator may need to account for later
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A client opened a connection
+    Connected {
+        #[serde(rename = "connectionId")]
+        connection_id: u64,
+        /// Remote address, if the socket still knew it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        peer: Option<String>,
+    },
+    /// A connection signed in, or resumed a session
+    AuthSucceeded {
+        #[serde(rename = "connectionId")]
+        connection_id: u64,
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        resumed: bool,
+    },
+    /// A connection was refused at sign-in
+    AuthFailed {
+        #[serde(rename = "connectionId")]
+        connection_id: u64,
+        /// Reason sent to the client, such as `auth_failed` or `banned`
+        reason: String,
+        /// Key the client claimed, if its auth message named a valid one
+        #[serde(rename = "publicKey", default, skip_serializing_if = "Option::is_none")]
+        public_key: Option<String>,
+    },
+    /// The admin API kicked a key
+    Kicked {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        /// Whether the key was online to be disconnected
+        disconnected: bool,
+    },
+    /// The admin API banned a key
+    Banned {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        /// When the ban ends, None if it is permanent
+        #[serde(rename = "expiresAt", default, skip_serializing_if = "Option::is_none")]
+        expires_at: Option<String>,
+        disconnected: bool,
+    },
+    /// The admin API lifted a ban
+    Unbanned {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        #[serde(rename = "wasBanned")]
+        was_banned: bool,
+    },
+    /// A validated message could not reach its recipient
+    RoutingFailed {
+        #[serde(rename = "senderPublicKey")]
+        sender_public_key: String,
+        #[serde(rename = "recipientPublicKey")]
+        recipient_public_key: String,
+        reason: String,
+    },
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// RFC 3339 time the event was recorded
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// Where the audit log is written and when it rotates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditLogConfig {
+    pub path: PathBuf,
+    /// Size in bytes past which the file is rotated
+    pub max_bytes: u64,
+    /// Rotated files kept besides the current one
+    pub keep: usize,
+}
+
+impl AuditLogConfig {
+    /// Log to `path` with the default rotation settings
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_bytes: config::server::DEFAULT_AUDIT_LOG_MAX_BYTES,
+            keep: config::server::DEFAULT_AUDIT_LOG_KEEP,
+        }
+    }
+
+    /// Read the settings from `PROFILE_AUDIT_LOG`, `PROFILE_AUDIT_LOG_MAX_BYTES`
+    /// and `PROFILE_AUDIT_LOG_KEEP`
+    ///
+    /// # Returns
+    /// Ok(None) if no audit log file is configured
+    pub fn from_env() -> Result<Option<Self>, AuditLogError> {
+        let Some(path) = std::env::var_os(AUDIT_LOG_ENV_VAR).filter(|p| !p.is_empty()) else {
+            return Ok(None);
+        };
+        let mut config = Self::new(path);
+        if let Some(max_bytes) = setting(AUDIT_LOG_MAX_BYTES_ENV_VAR)? {
+            config.max_bytes = max_bytes;
+        }
+        if let Some(keep) = setting(AUDIT_LOG_KEEP_ENV_VAR)? {
+            config.keep = keep as usize;
+        }
+        Ok(Some(config))
+    }
+
+    /// Path of the `index`th rotated file, 1 being the newest
+    pub fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+}
+
+/// A positive number from the environment, None if unset or empty
+fn setting(name: &'static str) -> Result<Option<u64>, AuditLogError> {
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&n: &u64| n > 0)
+            .map(Some)
+            .ok_or(AuditLogError::InvalidSetting { name, value }),
+        _ => Ok(None),
+    }
+}
+
+/// The open file and how much has been written to it
+#[derive(Debug)]
+struct AuditFile {
+    config: AuditLogConfig,
+    file: File,
+    len: u64,
+}
+
+impl AuditFile {
+    fn open(config: AuditLogConfig) -> io::Result<Self> {
+        if let Some(dir) = config.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { config, file, len })
+    }
+
+    /// Append one line, rotating first if it would not fit
+    fn append(&mut self, line: &str) -> io::Result<()> {
+        let size = line.len() as u64 + 1;
+        if self.len > 0 && self.len + size > self.config.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.file.flush()?;
+        self.len += size;
+        Ok(())
+    }
+
+    /// Shift every file one place down, dropping the oldest, and start afresh
+    fn rotate(&mut self) -> io::Result<()> {
+        let config = &self.config;
+        if config.keep == 0 {
+            std::fs::remove_file(&config.path)?;
+        } else {
+            for index in (1..config.keep).rev() {
+                let from = config.rotated_path(index);
+                if from.exists() {
+                    std::fs::rename(&from, config.rotated_path(index + 1))?;
+                }
+            }
+            std::fs::rename(&config.path, config.rotated_path(1))?;
+        }
+        *self = Self::open(self.config.clone())?;
+        Ok(())
+    }
+}
+
+/// Append-only audit log, shared by every connection
+///
+/// A disabled log (the default) accepts events and drops them.
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    file: Option<Mutex<AuditFile>>,
+}
+
+impl AuditLog {
+    /// Create a disabled log that records nothing
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Open (or create) the log described by `config` for appending
+    pub fn open(config: AuditLogConfig) -> Result<Self, AuditLogError> {
+        Ok(Self {
+            file: Some(Mutex::new(AuditFile::open(config)?)),
+        })
+    }
+
+    /// Open the log configured in the environment, or a disabled one
+    pub fn from_env() -> Result<Self, AuditLogError> {
+        match AuditLogConfig::from_env()? {
+            Some(config) => Self::open(config),
+            None => Ok(Self::disabled()),
+        }
+    }
+
+    /// Whether events are written anywhere
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// The file events are written to, None if disabled
+    pub fn path(&self) -> Option<PathBuf> {
+        let file = self.file.as_ref()?;
+        let file = file.lock().unwrap_or_else(|e| e.into_inner());
+        Some(file.config.path.clone())
+    }
+
+    /// Append `event`, stamped with the current time
+    ///
+    /// Failures are logged rather than returned: losing an audit line must
+    /// not refuse a connection or a message.
+    pub fn record(&self, event: AuditEvent) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let record = AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            event,
+        };
+        let result = serde_json::to_string(&record)
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+                file.append(&line)
+            });
+        if let Err(e) = result {
+            tracing::error!(error = %e, ?record, "Failed to write audit log");
+        }
+    }
+}
+
+/// Read every record from one audit log file
+///
+/// Blank lines are skipped; a malformed line is reported with its line number.
+pub fn read_audit_log(path: impl AsRef<Path>) -> io::Result<Vec<AuditRecord>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, e),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// The public key named in a refused auth message, if it is a valid one
+///
+/// Only well-formed keys are kept, so the log can't be filled with
+/// whatever a client puts in the field.
+pub fn claimed_public_key(text: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    let key = value.get("publicKey")?.as_str()?.to_ascii_lowercase();
+    (key.len() == 64 && key.bytes().all(|b| b.is_ascii_hexdigit())).then_some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("profile-audit-{}-{}", std::process::id(), name))
+            .join("audit.jsonl")
+    }
+
+    #[test]
+    fn test_records_round_trip() {
+        let path = temp_path("round-trip");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        let log = AuditLog::open(AuditLogConfig::new(&path)).unwrap();
+        let events = vec![
+            AuditEvent::Connected {
+                connection_id: 1,
+                peer: Some("127.0.0.1:50000".to_string()),
+            },
+            AuditEvent::AuthFailed {
+                connection_id: 1,
+                reason: "banned".to_string(),
+                public_key: Some("ab".repeat(32)),
+            },
+            AuditEvent::Banned {
+                public_key: "ab".repeat(32),
+                expires_at: None,
+                disconnected: false,
+            },
+        ];
+        for event in events.clone() {
+            log.record(event);
+        }
+        // Reopening appends rather than truncating
+        AuditLog::open(AuditLogConfig::new(&path))
+            .unwrap()
+            .record(AuditEvent::Unbanned {
+                public_key: "ab".repeat(32),
+                was_banned: true,
+            });
+
+        let records = read_audit_log(&path).unwrap();
+        assert_eq!(records.len(), 4);
+        let read: Vec<AuditEvent> = records.into_iter().map(|r| r.event).collect();
+        assert_eq!(read[..3], events[..]);
+        let line = std::fs::read_to_string(&path).unwrap();
+        assert!(line.starts_with(r#"{"timestamp":"#));
+        assert!(line.contains(r#""event":"connected","connectionId":1"#));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rotates_and_keeps_newest_files() {
+        let path = temp_path("rotation");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        let config = AuditLogConfig {
+            path: path.clone(),
+            max_bytes: 200,
+            keep: 2,
+        };
+        let log = AuditLog::open(config.clone()).unwrap();
+        for connection_id in 0..12 {
+            log.record(AuditEvent::Connected {
+                connection_id,
+                peer: None,
+            });
+        }
+
+        // Each file stays under the limit and the oldest lines are gone
+        assert!(!config.rotated_path(3).exists());
+        let mut ids = Vec::new();
+        for file in [config.rotated_path(2), config.rotated_path(1), path.clone()] {
+            assert!(std::fs::metadata(&file).unwrap().len() <= config.max_bytes);
+            ids.extend(
+                read_audit_log(&file)
+                    .unwrap()
+                    .into_iter()
+                    .map(|r| match r.event {
+                        AuditEvent::Connected { connection_id, .. } => connection_id,
+                        other => panic!("Unexpected event {:?}", other),
+                    }),
+            );
+        }
+        assert!(ids.len() < 12);
+        assert_eq!(ids, (12 - ids.len() as u64..12).collect::<Vec<_>>());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_claimed_public_key_only_when_valid() {
+        let key = "AB".repeat(32);
+        let text = format!(r#"{{"type":"auth","publicKey":"{}"}}"#, key);
+        assert_eq!(claimed_public_key(&text), Some(key.to_ascii_lowercase()));
+        assert_eq!(claimed_public_key(r#"{"publicKey":"zz"}"#), None);
+        assert_eq!(claimed_public_key("not json"), None);
+    }
+}
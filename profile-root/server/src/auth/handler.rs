@@ -4,10 +4,14 @@
 //! as specified in Story 1.5 requirements.
 
 use crate::lobby::Lobby;
-use crate::protocol::{AuthErrorMessage, AuthMessage, AuthSuccessMessage};
+use crate::protocol::{
+    AuthErrorMessage, AuthMessage, AuthSuccessMessage, ResumeMessage, AUTH_CHALLENGE,
+};
 use hex;
 use profile_shared::errors::CryptoError;
+use profile_shared::protocol::{Compression, Encoding};
 use profile_shared::{verify_signature, PublicKey};
+use std::time::SystemTime;
 
 /// Authentication result indicating success or failure
 #[derive(Debug, Clone)]
@@ -15,6 +19,14 @@ pub enum AuthResult {
     Success {
         public_key: PublicKey,
         lobby_state: Vec<String>,
+        /// Encoding picked from the ones the client offered
+        encoding: Encoding,
+        /// First offered compression the server supports
+        compression: Compression,
+        /// The client signed in with a throwaway guest identity
+        ephemeral: bool,
+        /// The client lets others ask when it was last online
+        share_last_seen: bool,
     },
     Failure {
         reason: String,
@@ -27,7 +39,8 @@ pub enum AuthResult {
 /// This function:
 /// 1. Validates input lengths and formats to prevent DoS attacks
 /// 2. Uses `hex` crate to decode `publicKey` (JSON field) and `signature` from JSON
-/// 3. Calls `shared::verify_signature` for literal string "auth"
+/// 3. Calls `shared::verify_signature` for literal string "auth" on the
+///    blocking pool, through the lobby's verification queue
 /// 4. Returns appropriate success/failure result
 pub async fn handle_authentication(auth_message: &AuthMessage, lobby: &Lobby) -> AuthResult {
     // Validate input lengths to prevent DoS attacks
@@ -120,16 +133,46 @@ pub async fn handle_authentication(auth_message: &AuthMessage, lobby: &Lobby) ->
         }
     };
 
-    // Verify signature for literal string "auth" using shared crypto module
-    let verification_result = verify_signature(&public_key_wrapper, b"auth", &signature);
+    // Verify signature for literal string "auth" using shared crypto module,
+    // on the blocking pool so a burst of logins can't stall the reactor
+    let verification_result = {
+        let public_key = public_key_wrapper.clone();
+        lobby
+            .verifications
+            .reserve()
+            .await
+            .run(move || verify_signature(&public_key, AUTH_CHALLENGE, &signature))
+            .await
+    };
+    let Ok(verification_result) = verification_result else {
+        tracing::error!("Auth signature verification task failed");
+        return AuthResult::Failure {
+            reason: "auth_failed".to_string(),
+            details: "Signature could not be verified".to_string(),
+        };
+    };
 
     match verification_result {
         Ok(_) => {
-            // Signature is valid - user authenticated successfully
+            // Signature is valid, but the operator may have banned the key
+            if let Some(ban) = lobby.moderation.active_ban(&normalized_public_key).await {
+                tracing::warn!("Refusing banned key {}...", &normalized_public_key[..8]);
+                return AuthResult::Failure {
+                    reason: "banned".to_string(),
+                    details: ban.describe(),
+                };
+            }
+            if let Some(failure) = refuse_retired(lobby, &normalized_public_key).await {
+                return failure;
+            }
             match lobby.get_full_lobby_state().await {
                 Ok(lobby_state) => AuthResult::Success {
                     public_key: public_key_wrapper,
                     lobby_state,
+                    encoding: Encoding::negotiate(&auth_message.encodings),
+                    compression: Compression::negotiate(&auth_message.compression),
+                    ephemeral: auth_message.ephemeral,
+                    share_last_seen: auth_message.share_last_seen,
                 },
                 Err(_) => AuthResult::Failure {
                     reason: "auth_failed".to_string(),
@@ -159,6 +202,75 @@ pub async fn handle_authentication(auth_message: &AuthMessage, lobby: &Lobby) ->
     }
 }
 
+/// Handle a resume message from a reconnecting client
+///
+/// Redeems the token instead of verifying a signature. The user is still
+/// refused if the operator banned them since the token was issued.
+///
+/// # Arguments
+/// * `resume_message` - The client's first frame
+/// * `lobby` - The lobby holding the resume tokens and bans
+/// * `now` - Current time, for token expiry
+pub async fn handle_resume(
+    resume_message: &ResumeMessage,
+    lobby: &Lobby,
+    now: SystemTime,
+) -> AuthResult {
+    let resume_failed = || AuthResult::Failure {
+        reason: "resume_failed".to_string(),
+        details: "Session expired, sign in again".to_string(),
+    };
+    let Some(public_key_hex) = lobby
+        .resume_tokens
+        .redeem(&resume_message.resume_token, now)
+        .await
+    else {
+        return resume_failed();
+    };
+    let Some(public_key) = hex::decode(&public_key_hex)
+        .ok()
+        .and_then(|key| PublicKey::new(key).ok())
+    else {
+        return resume_failed();
+    };
+
+    if let Some(ban) = lobby.moderation.active_ban(&public_key_hex).await {
+        tracing::warn!("Refusing resume for banned key {}...", &public_key_hex[..8]);
+        return AuthResult::Failure {
+            reason: "banned".to_string(),
+            details: ban.describe(),
+        };
+    }
+    if let Some(failure) = refuse_retired(lobby, &public_key_hex).await {
+        return failure;
+    }
+    match lobby.get_full_lobby_state().await {
+        Ok(lobby_state) => AuthResult::Success {
+            public_key,
+            lobby_state,
+            encoding: Encoding::negotiate(&resume_message.encodings),
+            compression: Compression::negotiate(&resume_message.compression),
+            ephemeral: resume_message.ephemeral,
+            share_last_seen: resume_message.share_last_seen,
+        },
+        Err(_) => AuthResult::Failure {
+            reason: "auth_failed".to_string(),
+            details: "Failed to get lobby state".to_string(),
+        },
+    }
+}
+
+/// Failure for a key its owner replaced with a key rollover, None if the
+/// key is still in use
+async fn refuse_retired(lobby: &Lobby, public_key: &str) -> Option<AuthResult> {
+    let replacement = lobby.retired.replacement(public_key).await?;
+    tracing::info!("Refusing retired key {}...", &public_key[..8]);
+    Some(AuthResult::Failure {
+        reason: "key_retired".to_string(),
+        details: format!("This key was replaced by {}", replacement),
+    })
+}
+
 /// Create success response message
 pub fn create_success_message(lobby_state: Vec<String>) -> AuthSuccessMessage {
     AuthSuccessMessage::new(lobby_state)
@@ -179,6 +291,10 @@ mod tests {
             r#type: "auth".to_string(),
             public_key: "invalid_hex!".to_string(),
             signature: "abc123".to_string(),
+            encodings: Vec::new(),
+            compression: Vec::new(),
+            ephemeral: false,
+            share_last_seen: false,
         };
 
         let lobby = Lobby::new();
@@ -206,6 +322,10 @@ mod tests {
             r#type: "auth".to_string(),
             public_key: hex::encode(&public_key),
             signature: hex::encode(&wrong_signature),
+            encodings: Vec::new(),
+            compression: Vec::new(),
+            ephemeral: false,
+            share_last_seen: false,
         };
 
         let lobby = Lobby::new();
@@ -229,6 +349,45 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_handle_resume_redeems_token_once() {
+        let lobby = Lobby::new();
+        let public_key = "ab".repeat(32);
+        let now = SystemTime::now();
+        let token = lobby.resume_tokens.issue(&public_key, now);
+        let resume = ResumeMessage::new(token);
+
+        match handle_resume(&resume, &lobby, now).await {
+            AuthResult::Success {
+                public_key: key, ..
+            } => {
+                assert_eq!(hex::encode(key.as_slice()), public_key);
+            }
+            AuthResult::Failure { details, .. } => panic!("resume refused: {}", details),
+        }
+        assert!(matches!(
+            handle_resume(&resume, &lobby, now).await,
+            AuthResult::Failure { reason, .. } if reason == "resume_failed"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retired_key_cannot_resume() {
+        let lobby = Lobby::new();
+        let (old, new) = ("ab".repeat(32), "cd".repeat(32));
+        let now = SystemTime::now();
+        let resume = ResumeMessage::new(lobby.resume_tokens.issue(&old, now));
+        assert!(lobby.retired.retire(&old, &new).await);
+
+        match handle_resume(&resume, &lobby, now).await {
+            AuthResult::Failure { reason, details } => {
+                assert_eq!(reason, "key_retired");
+                assert!(details.contains(&new));
+            }
+            AuthResult::Success { .. } => panic!("A retired key resumed"),
+        }
+    }
+
     #[test]
     fn test_message_creation() {
         let lobby_state = vec!["user1".to_string(), "user2".to_string()];
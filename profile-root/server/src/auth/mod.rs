@@ -1,7 +1,9 @@
 //! Authentication handler module
 
 pub mod handler;
+pub mod resume;
 
 pub use handler::{
-    create_error_message, create_success_message, handle_authentication, AuthResult,
+    create_error_message, create_success_message, handle_authentication, handle_resume, AuthResult,
 };
+pub use resume::ResumeTokenStore;
@@ -0,0 +1,371 @@
+//! Session resume tokens
+//!
+//! Every successful login is handed a token in its `auth_success`. A client
+//! that reconnects within `RESUME_TOKEN_TTL` can send
+//! `{"type":"resume","resumeToken":...}` as its first frame instead of a
+//! signed auth message, which skips signature verification and puts the
+//! user straight back in the lobby. The resumed session is issued a fresh
+//! token.
+//!
+//! A token is a signed envelope rather than a lookup key: it carries the
+//! user's public key and when it was issued, authenticated with
+//! HMAC-SHA256 under a server secret. Checking one costs a hash instead of
+//! an ed25519 verification, and needs no record of the tokens handed out.
+//! The server only remembers tokens already redeemed (each works once) and
+//! users who logged out (an explicit `{"type":"logout"}` revokes every
+//! token issued to them until then), and only until those tokens expire.
+//!
+//! The secret is random per process unless `PROFILE_RESUME_SECRET` sets it
+//! (at least 32 bytes, hex). Instances sharing a secret accept each other's
+//! tokens, so a client can resume after a restart or on another instance;
+//! redemptions and logouts are still only remembered by the instance that
+//! saw them.
+
+use hmac::{Hmac, Mac};
+use profile_shared::config::connection::resume::{MAX_RESUME_TOKENS, RESUME_TOKEN_TTL};
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+/// Value of the `type` field identifying a resume message
+pub const RESUME_TYPE: &str = "resume";
+
+/// Value of the `type` field identifying a logout request
+pub const LOGOUT_TYPE: &str = "logout";
+
+/// Environment variable holding the hex secret tokens are signed with
+pub const RESUME_SECRET_ENV_VAR: &str = "PROFILE_RESUME_SECRET";
+
+/// Shortest secret accepted from the environment, in bytes
+pub const MIN_SECRET_BYTES: usize = 32;
+
+/// Random bytes making each token unique
+const NONCE_BYTES: usize = 16;
+
+/// Issue time, in milliseconds since the Unix epoch
+const ISSUED_AT_BYTES: usize = 8;
+
+/// HMAC-SHA256 tag at the end of a token
+const TAG_BYTES: usize = 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Check whether a message type is a resume message
+pub fn is_resume_message(message_type: &str) -> bool {
+    message_type == RESUME_TYPE
+}
+
+/// Check whether a client message type is a logout request
+pub fn is_logout_request(message_type: &str) -> bool {
+    message_type == LOGOUT_TYPE
+}
+
+/// The configured secret, read once from `PROFILE_RESUME_SECRET`
+fn configured_secret() -> Option<&'static [u8]> {
+    static SECRET: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+    SECRET
+        .get_or_init(|| parse_secret(std::env::var(RESUME_SECRET_ENV_VAR).ok().as_deref()))
+        .as_deref()
+}
+
+/// Parse a configured secret; None (a random secret) when unset or invalid
+fn parse_secret(value: Option<&str>) -> Option<Vec<u8>> {
+    let value = value.map(str::trim).filter(|v| !v.is_empty())?;
+    match hex::decode(value) {
+        Ok(secret) if secret.len() >= MIN_SECRET_BYTES => Some(secret),
+        _ => {
+            tracing::warn!(
+                "Ignoring {}: expected at least {} bytes of hex; resume tokens won't outlive this process",
+                RESUME_SECRET_ENV_VAR,
+                MIN_SECRET_BYTES
+            );
+            None
+        }
+    }
+}
+
+fn random_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; MIN_SECRET_BYTES];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// What the server remembers about tokens it can no longer tell apart
+/// from valid ones by their signature
+#[derive(Debug, Default)]
+struct Redemptions {
+    /// Nonces of redeemed tokens, with when each expires (Unix ms)
+    spent: HashMap<[u8; NONCE_BYTES], u64>,
+    /// Users who logged out, with when (Unix ms)
+    revoked: HashMap<String, u64>,
+}
+
+/// Issues and checks signed resume tokens
+#[derive(Debug)]
+pub struct ResumeTokenStore {
+    secret: Vec<u8>,
+    redemptions: Mutex<Redemptions>,
+    ttl: Duration,
+    max_tokens: usize,
+}
+
+impl Default for ResumeTokenStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ResumeTokenStore {
+    /// Create a store using the configured lifetime, size limit and secret
+    pub fn new() -> Self {
+        let store = Self::with_limits(RESUME_TOKEN_TTL, MAX_RESUME_TOKENS);
+        match configured_secret() {
+            Some(secret) => store.with_secret(secret),
+            None => store,
+        }
+    }
+
+    /// Create a store with a custom lifetime and size limit, and a random
+    /// secret
+    ///
+    /// # Arguments
+    /// * `ttl` - How long a token stays valid after it is issued
+    /// * `max_tokens` - Redeemed tokens and logouts remembered until they
+    ///   expire; while this many are, resumes are refused
+    pub fn with_limits(ttl: Duration, max_tokens: usize) -> Self {
+        Self {
+            secret: random_secret(),
+            redemptions: Mutex::new(Redemptions::default()),
+            ttl,
+            max_tokens,
+        }
+    }
+
+    /// Sign tokens with `secret`, e.g. one shared by several instances
+    pub fn with_secret(mut self, secret: &[u8]) -> Self {
+        self.secret = secret.to_vec();
+        self
+    }
+
+    fn tag(&self, body: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC takes any key length");
+        mac.update(body);
+        mac
+    }
+
+    /// Issue a new token for `public_key`
+    ///
+    /// # Arguments
+    /// * `public_key` - The authenticated user
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    /// The hex-encoded token
+    pub fn issue(&self, public_key: &str, now: SystemTime) -> String {
+        let mut token = vec![0u8; NONCE_BYTES];
+        rand::thread_rng().fill_bytes(&mut token);
+        token.extend_from_slice(&unix_millis(now).to_be_bytes());
+        token.extend_from_slice(public_key.as_bytes());
+        let tag = self.tag(&token).finalize().into_bytes();
+        token.extend_from_slice(&tag);
+        hex::encode(token)
+    }
+
+    /// Redeem a token, which can't be used again afterwards
+    ///
+    /// # Returns
+    /// The public key the token was issued to, or None if the token is
+    /// forged, expired, already redeemed or revoked, or too many are
+    /// remembered to take another
+    pub async fn redeem(&self, token: &str, now: SystemTime) -> Option<String> {
+        let token = hex::decode(token).ok()?;
+        if token.len() < NONCE_BYTES + ISSUED_AT_BYTES + TAG_BYTES {
+            return None;
+        }
+        let (body, tag) = token.split_at(token.len() - TAG_BYTES);
+        self.tag(body).verify_slice(tag).ok()?;
+
+        let (nonce, rest) = body.split_at(NONCE_BYTES);
+        let (issued_at, public_key) = rest.split_at(ISSUED_AT_BYTES);
+        let issued_at = u64::from_be_bytes(issued_at.try_into().ok()?);
+        let expires_at = issued_at.saturating_add(self.ttl.as_millis() as u64);
+        let now = unix_millis(now);
+        if now >= expires_at {
+            return None;
+        }
+        let public_key = String::from_utf8(public_key.to_vec()).ok()?;
+
+        let mut redemptions = self.redemptions.lock().await;
+        if redemptions
+            .revoked
+            .get(&public_key)
+            .is_some_and(|&revoked_at| issued_at <= revoked_at)
+        {
+            return None;
+        }
+        self.make_room(&mut redemptions, now)?;
+        let nonce: [u8; NONCE_BYTES] = nonce.try_into().ok()?;
+        redemptions
+            .spent
+            .insert(nonce, expires_at)
+            .is_none()
+            .then_some(public_key)
+    }
+
+    /// Revoke every token issued to `public_key` until `now`
+    pub async fn revoke(&self, public_key: &str, now: SystemTime) {
+        let mut redemptions = self.redemptions.lock().await;
+        let now = unix_millis(now);
+        if self.make_room(&mut redemptions, now).is_none() {
+            // Tokens still work until they expire; better than refusing
+            // the logout
+            tracing::warn!("Too many resume redemptions remembered to revoke tokens");
+            return;
+        }
+        redemptions.revoked.insert(public_key.to_string(), now);
+    }
+
+    /// Forget what has expired if the store is full
+    ///
+    /// # Returns
+    /// None if it is still full
+    fn make_room(&self, redemptions: &mut Redemptions, now: u64) -> Option<()> {
+        if redemptions.spent.len() + redemptions.revoked.len() < self.max_tokens {
+            return Some(());
+        }
+        let ttl = self.ttl.as_millis() as u64;
+        redemptions.spent.retain(|_, expires_at| *expires_at > now);
+        redemptions
+            .revoked
+            .retain(|_, revoked_at| revoked_at.saturating_add(ttl) > now);
+        (redemptions.spent.len() + redemptions.revoked.len() < self.max_tokens).then_some(())
+    }
+
+    /// Redeemed tokens and logouts remembered, including expired ones not
+    /// yet dropped
+    pub async fn len(&self) -> usize {
+        let redemptions = self.redemptions.lock().await;
+        redemptions.spent.len() + redemptions.revoked.len()
+    }
+
+    /// Whether nothing is remembered
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "alice";
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+    }
+
+    #[tokio::test]
+    async fn test_token_redeems_once_before_expiry() {
+        let store = ResumeTokenStore::with_limits(Duration::from_secs(60), 10);
+
+        let token = store.issue(ALICE, at(0));
+        assert_eq!(store.redeem(&token, at(59)).await.as_deref(), Some(ALICE));
+        assert_eq!(store.redeem(&token, at(59)).await, None);
+
+        let token = store.issue(ALICE, at(0));
+        assert_eq!(store.redeem(&token, at(60)).await, None);
+        assert_eq!(store.redeem("unknown", at(0)).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_forged_tokens_refused() {
+        let store = ResumeTokenStore::with_limits(Duration::from_secs(60), 10);
+        let token = hex::decode(store.issue(ALICE, at(0))).unwrap();
+
+        // Claim to be someone else, or extend the lifetime
+        for at_byte in [NONCE_BYTES + ISSUED_AT_BYTES, NONCE_BYTES + 1] {
+            let mut forged = token.clone();
+            forged[at_byte] ^= 1;
+            assert_eq!(store.redeem(&hex::encode(forged), at(0)).await, None);
+        }
+        // Another server's token
+        let other = ResumeTokenStore::with_limits(Duration::from_secs(60), 10);
+        assert_eq!(store.redeem(&other.issue(ALICE, at(0)), at(0)).await, None);
+        assert_eq!(store.redeem(&hex::encode(&token[..40]), at(0)).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_shared_secret_resumes_on_another_store() {
+        let secret = [7u8; MIN_SECRET_BYTES];
+        let first = ResumeTokenStore::with_limits(Duration::from_secs(60), 10).with_secret(&secret);
+        let second =
+            ResumeTokenStore::with_limits(Duration::from_secs(60), 10).with_secret(&secret);
+
+        let token = first.issue(ALICE, at(0));
+        assert_eq!(second.redeem(&token, at(1)).await.as_deref(), Some(ALICE));
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_only_earlier_tokens_of_that_user() {
+        let store = ResumeTokenStore::with_limits(Duration::from_secs(60), 10);
+        let first = store.issue(ALICE, at(0));
+        let second = store.issue(ALICE, at(1));
+        let bob = store.issue("bob", at(1));
+
+        store.revoke(ALICE, at(2)).await;
+        let after_logout = store.issue(ALICE, at(3));
+        assert_eq!(store.redeem(&first, at(3)).await, None);
+        assert_eq!(store.redeem(&second, at(3)).await, None);
+        assert_eq!(store.redeem(&bob, at(3)).await.as_deref(), Some("bob"));
+        assert_eq!(
+            store.redeem(&after_logout, at(3)).await.as_deref(),
+            Some(ALICE)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_full_store_refuses_until_entries_expire() {
+        let store = ResumeTokenStore::with_limits(Duration::from_secs(60), 2);
+        let tokens: Vec<_> = ["a", "b", "c"]
+            .into_iter()
+            .map(|key| store.issue(key, at(0)))
+            .collect();
+
+        assert!(store.redeem(&tokens[0], at(1)).await.is_some());
+        assert!(store.redeem(&tokens[1], at(1)).await.is_some());
+        assert_eq!(store.len().await, 2);
+        // Forgetting a redemption early would let its token be replayed
+        assert_eq!(store.redeem(&tokens[2], at(1)).await, None);
+
+        let token = store.issue("d", at(61));
+        assert!(store.redeem(&token, at(61)).await.is_some());
+        assert_eq!(store.len().await, 1);
+    }
+
+    #[test]
+    fn test_secret_parsed_from_hex() {
+        assert_eq!(parse_secret(None), None);
+        assert_eq!(parse_secret(Some("  ")), None);
+        assert_eq!(parse_secret(Some("abcd")), None);
+        assert_eq!(parse_secret(Some("not hex")), None);
+        assert_eq!(
+            parse_secret(Some(&"ab".repeat(MIN_SECRET_BYTES))),
+            Some(vec![0xab; MIN_SECRET_BYTES])
+        );
+    }
+
+    #[test]
+    fn test_request_types() {
+        assert!(is_resume_message("resume"));
+        assert!(is_logout_request("logout"));
+        assert!(!is_logout_request("auth"));
+    }
+}
@@ -0,0 +1,195 @@
+//! Encrypted backup storage
+//!
+//! Each identity can keep one small backup (for example its contact list
+//! and settings) on the server, so it can be restored on a new device. The
+//! client encrypts the backup before sending it; the server only stores the
+//! bytes and never reads them. Backups are versioned: a store must carry a
+//! version greater than the stored one, so an older device can't overwrite
+//! a newer backup. Backups are kept in memory and outlive disconnects, but
+//! not a server restart unless state snapshots are enabled (see
+//! [`crate::snapshot`]).
+
+use profile_shared::config::backup::{MAX_BACKUP_SIZE, MAX_STORED_BACKUPS};
+use profile_shared::BackupError;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// One identity's stored backup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredBackup {
+    pub version: u64,
+    /// Encrypted bytes, as sent by the client
+    pub blob: Vec<u8>,
+    /// When the backup was stored (RFC 3339)
+    pub updated_at: String,
+}
+
+/// Thread-safe map from public key to that identity's backup
+#[derive(Debug)]
+pub struct BackupStore {
+    backups: RwLock<HashMap<String, StoredBackup>>,
+    max_size: usize,
+    max_backups: usize,
+}
+
+impl BackupStore {
+    /// Create a store using the configured size and count limits
+    pub fn new() -> Self {
+        Self::with_limits(MAX_BACKUP_SIZE, MAX_STORED_BACKUPS)
+    }
+
+    /// Create a store with custom limits
+    ///
+    /// # Arguments
+    /// * `max_size` - Maximum size of one backup in bytes
+    /// * `max_backups` - Maximum number of identities with a backup
+    pub fn with_limits(max_size: usize, max_backups: usize) -> Self {
+        Self {
+            backups: RwLock::new(HashMap::new()),
+            max_size,
+            max_backups,
+        }
+    }
+
+    /// Store a backup, replacing the identity's previous one
+    ///
+    /// # Arguments
+    /// * `public_key` - The identity the backup belongs to
+    /// * `version` - Must be greater than the stored backup's version (0 if none)
+    /// * `blob` - The encrypted backup
+    /// * `updated_at` - When the backup was stored
+    pub async fn store(
+        &self,
+        public_key: &str,
+        version: u64,
+        blob: Vec<u8>,
+        updated_at: String,
+    ) -> Result<(), BackupError> {
+        if blob.is_empty() {
+            return Err(BackupError::InvalidBlob);
+        }
+        if blob.len() > self.max_size {
+            return Err(BackupError::TooLarge {
+                size: blob.len(),
+                max: self.max_size,
+            });
+        }
+
+        let mut backups = self.backups.write().await;
+        let current = backups.get(public_key).map_or(0, |b| b.version);
+        if version <= current {
+            return Err(BackupError::StaleVersion { current });
+        }
+        if current == 0 && backups.len() >= self.max_backups {
+            return Err(BackupError::StorageFull);
+        }
+        backups.insert(
+            public_key.to_string(),
+            StoredBackup {
+                version,
+                blob,
+                updated_at,
+            },
+        );
+        Ok(())
+    }
+
+    /// An identity's stored backup, if any
+    pub async fn get(&self, public_key: &str) -> Option<StoredBackup> {
+        self.backups.read().await.get(public_key).cloned()
+    }
+
+    /// Every stored backup, ordered by public key
+    pub async fn entries(&self) -> Vec<(String, StoredBackup)> {
+        let mut entries: Vec<_> = self
+            .backups
+            .read()
+            .await
+            .iter()
+            .map(|(key, backup)| (key.clone(), backup.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Number of identities with a stored backup
+    pub async fn len(&self) -> usize {
+        self.backups.read().await.len()
+    }
+
+    /// Check if no backups are stored
+    pub async fn is_empty(&self) -> bool {
+        self.backups.read().await.is_empty()
+    }
+}
+
+impl Default for BackupStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Map a backup error to the protocol error reason sent to clients
+pub fn backup_error_reason(error: &BackupError) -> &'static str {
+    match error {
+        BackupError::TooLarge { .. } => "backup_too_large",
+        BackupError::InvalidBlob => "invalid_backup",
+        BackupError::StaleVersion { .. } => "backup_version_conflict",
+        BackupError::StorageFull => "backup_storage_full",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_store_requires_newer_version() {
+        let store = BackupStore::with_limits(8, 10);
+        let now = "2025-01-01T00:00:00Z".to_string();
+
+        assert_eq!(
+            store.store("alice", 0, vec![1], now.clone()).await,
+            Err(BackupError::StaleVersion { current: 0 })
+        );
+        store
+            .store("alice", 2, vec![1, 2], now.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.store("alice", 2, vec![3], now.clone()).await,
+            Err(BackupError::StaleVersion { current: 2 })
+        );
+        store.store("alice", 5, vec![3], now.clone()).await.unwrap();
+
+        let stored = store.get("alice").await.unwrap();
+        assert_eq!((stored.version, stored.blob), (5, vec![3]));
+        assert_eq!(store.get("bob").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_size_and_count_limits() {
+        let store = BackupStore::with_limits(4, 1);
+        let now = "2025-01-01T00:00:00Z".to_string();
+
+        assert_eq!(
+            store.store("alice", 1, vec![0; 5], now.clone()).await,
+            Err(BackupError::TooLarge { size: 5, max: 4 })
+        );
+        assert_eq!(
+            store.store("alice", 1, Vec::new(), now.clone()).await,
+            Err(BackupError::InvalidBlob)
+        );
+        store
+            .store("alice", 1, vec![0; 4], now.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.store("bob", 1, vec![0], now.clone()).await,
+            Err(BackupError::StorageFull)
+        );
+        // Replacing an existing backup doesn't count against the limit
+        store.store("alice", 2, vec![0], now).await.unwrap();
+        assert_eq!(store.len().await, 1);
+    }
+}
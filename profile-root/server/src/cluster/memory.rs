@@ -0,0 +1,103 @@
+//! In-process cluster backend
+//!
+//! Nodes created from one [`MemoryHub`] share presence and events the way
+//! separate servers share a Redis instance, so clustering can be tested
+//! without any external service.
+
+use super::{BackendFuture, ClusterBackend, ClusterError, ClusterEvent, NodeId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+/// Presence and subscribers shared by the nodes of one in-process cluster
+#[derive(Debug, Default)]
+pub struct MemoryHub {
+    presence: Mutex<HashMap<String, NodeId>>,
+    subscribers: Mutex<Vec<mpsc::UnboundedSender<ClusterEvent>>>,
+}
+
+impl MemoryHub {
+    /// Create an empty hub
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Create a node backed by this hub
+    pub fn backend(self: &Arc<Self>, node_id: impl Into<NodeId>) -> Arc<MemoryBackend> {
+        Arc::new(MemoryBackend {
+            node_id: node_id.into(),
+            hub: Arc::clone(self),
+        })
+    }
+
+    fn presence(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, NodeId>>, ClusterError> {
+        self.presence
+            .lock()
+            .map_err(|_| ClusterError::Unavailable("presence lock poisoned".to_string()))
+    }
+}
+
+/// One node of an in-process cluster
+#[derive(Debug)]
+pub struct MemoryBackend {
+    node_id: NodeId,
+    hub: Arc<MemoryHub>,
+}
+
+impl ClusterBackend for MemoryBackend {
+    fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    fn register<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, ()> {
+        Box::pin(async move {
+            self.hub
+                .presence()?
+                .insert(public_key.to_string(), self.node_id.clone());
+            Ok(())
+        })
+    }
+
+    fn unregister<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, bool> {
+        Box::pin(async move {
+            let mut presence = self.hub.presence()?;
+            match presence.get(public_key) {
+                Some(node) if *node != self.node_id => Ok(false),
+                _ => {
+                    presence.remove(public_key);
+                    Ok(true)
+                }
+            }
+        })
+    }
+
+    fn locate<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, Option<NodeId>> {
+        Box::pin(async move { Ok(self.hub.presence()?.get(public_key).cloned()) })
+    }
+
+    fn online_keys(&self) -> BackendFuture<'_, Vec<String>> {
+        Box::pin(async move { Ok(self.hub.presence()?.keys().cloned().collect()) })
+    }
+
+    fn publish(&self, event: ClusterEvent) -> Result<(), ClusterError> {
+        let mut subscribers = self
+            .hub
+            .subscribers
+            .lock()
+            .map_err(|_| ClusterError::Unavailable("subscriber lock poisoned".to_string()))?;
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        Ok(())
+    }
+
+    fn subscribe(&self) -> BackendFuture<'_, mpsc::UnboundedReceiver<ClusterEvent>> {
+        Box::pin(async move {
+            let (sender, events) = mpsc::unbounded_channel();
+            self.hub
+                .subscribers
+                .lock()
+                .map_err(|_| ClusterError::Unavailable("subscriber lock poisoned".to_string()))?
+                .push(sender);
+            Ok(events)
+        })
+    }
+}
@@ -0,0 +1,325 @@
+//! Multi-instance lobby
+//!
+//! A single server keeps the whole lobby in memory. To run several
+//! instances behind a load balancer, each one is given a
+//! [`ClusterBackend`] that shares two things between them:
+//!
+//! - **Presence**: which node each online public key is connected to, so
+//!   lobby pages list every user and a direct message can be routed to a
+//!   recipient on another node
+//! - **Events**: lobby-wide broadcasts and direct messages published by one
+//!   node and delivered by the others to their own connections, so every
+//!   user still receives every broadcast wherever they are connected
+//!
+//! The lobby registers keys as they join and leave, publishes its
+//! broadcasts, and [`start`] runs a relay task that delivers events from
+//! other nodes. Without a backend (the default) the server runs standalone
+//! exactly as before.
+//!
+//! Backends:
+//! - [`memory::MemoryBackend`]: nodes in one process sharing a hub, for tests
+//! - `redis::RedisBackend` (`redis` cargo feature): a Redis hash for presence
+//!   and a pub/sub channel for events, selected by setting
+//!   `PROFILE_CLUSTER_REDIS_URL`
+//!
+//! Per-user state that isn't shared stays on the node the user is connected
+//! to: nicknames of users on other nodes arrive with their join broadcast
+//! but aren't included in lobby pages, and `MAX_LOBBY_SIZE` is enforced per
+//! node.
+
+pub mod memory;
+#[cfg(feature = "redis")]
+pub mod redis;
+
+use crate::lobby::Lobby;
+use profile_shared::Message;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Environment variable with the Redis URL of a clustered deployment
+pub const CLUSTER_REDIS_URL_ENV_VAR: &str = "PROFILE_CLUSTER_REDIS_URL";
+
+/// Identifier of one server instance, unique for the life of the process
+pub type NodeId = String;
+
+/// Create an identifier for this server instance
+pub fn new_node_id() -> NodeId {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Error types for cluster backends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClusterError {
+    /// The shared store or message bus could not be reached
+    Unavailable(String),
+    /// An event could not be encoded or decoded
+    Encoding(String),
+    /// Clustering was requested but this build doesn't support the backend
+    Unsupported(String),
+}
+
+impl Display for ClusterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClusterError::Unavailable(msg) => write!(f, "Cluster backend unavailable: {}", msg),
+            ClusterError::Encoding(msg) => write!(f, "Invalid cluster event: {}", msg),
+            ClusterError::Unsupported(msg) => write!(f, "Clustering not supported: {}", msg),
+        }
+    }
+}
+
+impl Error for ClusterError {}
+
+/// Something one node asks the others to deliver
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ClusterEvent {
+    /// Send to every user on every other node, except `exclude`
+    Broadcast {
+        origin: NodeId,
+        message: Message,
+        exclude: Option<String>,
+    },
+    /// Send to `recipient`, who is connected to `node`
+    Direct {
+        origin: NodeId,
+        node: NodeId,
+        recipient: String,
+        message: Message,
+    },
+}
+
+impl ClusterEvent {
+    /// The node that published the event
+    pub fn origin(&self) -> &str {
+        match self {
+            ClusterEvent::Broadcast { origin, .. } | ClusterEvent::Direct { origin, .. } => origin,
+        }
+    }
+}
+
+/// Future returned by cluster backend operations
+pub type BackendFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ClusterError>> + Send + 'a>>;
+
+/// Shared presence and event bus for a multi-instance lobby
+pub trait ClusterBackend: Send + Sync + fmt::Debug {
+    /// This node's identifier
+    fn node_id(&self) -> &str;
+
+    /// Record that `public_key` is connected to this node, replacing any
+    /// other node it was connected to
+    fn register<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, ()>;
+
+    /// Remove `public_key` from presence if it is still registered here
+    ///
+    /// # Returns
+    /// false if the key has since been registered by another node
+    fn unregister<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, bool>;
+
+    /// The node `public_key` is connected to, if it is online anywhere
+    fn locate<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, Option<NodeId>>;
+
+    /// Every key online on any node (unordered)
+    fn online_keys(&self) -> BackendFuture<'_, Vec<String>>;
+
+    /// Queue an event for the other nodes without waiting for delivery
+    fn publish(&self, event: ClusterEvent) -> Result<(), ClusterError>;
+
+    /// Start receiving events published by any node, this one included
+    fn subscribe(&self) -> BackendFuture<'_, mpsc::UnboundedReceiver<ClusterEvent>>;
+}
+
+/// Create the backend configured by `PROFILE_CLUSTER_REDIS_URL`
+///
+/// # Returns
+/// Ok(None) to run standalone when the variable is unset
+pub async fn from_env() -> Result<Option<Arc<dyn ClusterBackend>>, ClusterError> {
+    let Some(url) = std::env::var(CLUSTER_REDIS_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+    else {
+        return Ok(None);
+    };
+    connect_redis(&url).await.map(Some)
+}
+
+#[cfg(feature = "redis")]
+async fn connect_redis(url: &str) -> Result<Arc<dyn ClusterBackend>, ClusterError> {
+    let backend = redis::RedisBackend::connect(url, new_node_id()).await?;
+    Ok(Arc::new(backend))
+}
+
+#[cfg(not(feature = "redis"))]
+async fn connect_redis(_url: &str) -> Result<Arc<dyn ClusterBackend>, ClusterError> {
+    Err(ClusterError::Unsupported(format!(
+        "{} is set but the server was built without the `redis` feature",
+        CLUSTER_REDIS_URL_ENV_VAR
+    )))
+}
+
+/// Start delivering events from other nodes to this node's users
+///
+/// Does nothing for a standalone lobby.
+pub async fn start(lobby: &Lobby) -> Result<(), ClusterError> {
+    let Some(cluster) = lobby.cluster.clone() else {
+        return Ok(());
+    };
+    let events = cluster.subscribe().await?;
+    tokio::spawn(run_relay(lobby.clone(), cluster, events));
+    Ok(())
+}
+
+/// Deliver each event meant for this node until the subscription ends
+async fn run_relay(
+    lobby: Lobby,
+    cluster: Arc<dyn ClusterBackend>,
+    mut events: mpsc::UnboundedReceiver<ClusterEvent>,
+) {
+    while let Some(event) = events.recv().await {
+        // This node already delivered its own events locally
+        if event.origin() == cluster.node_id() {
+            continue;
+        }
+        match event {
+            ClusterEvent::Broadcast {
+                message, exclude, ..
+            } => {
+                if let Err(e) = lobby.broadcast_local(message, exclude.as_deref()) {
+                    tracing::warn!(error = %e, "Failed to relay cluster broadcast");
+                }
+            }
+            ClusterEvent::Direct {
+                node,
+                recipient,
+                message,
+                ..
+            } => {
+                if node != cluster.node_id() {
+                    continue;
+                }
+                match lobby.users.get(&recipient).await {
+                    Some(conn) => {
+                        if let Some(message) = lobby.filters.screen(&recipient, message).await {
+                            let _ = conn.sender.send(message);
+                        }
+                    }
+                    None => tracing::debug!(
+                        recipient = %recipient.chars().take(16).collect::<String>(),
+                        "Relayed message recipient is no longer connected here"
+                    ),
+                }
+            }
+        }
+    }
+    tracing::warn!("Cluster event subscription ended");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::memory::MemoryHub;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+
+    async fn join(lobby: &Lobby, key: &str) -> OutboundQueue {
+        let (sender, rx) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        rx
+    }
+
+    /// Two lobbies joined through one hub, relays running
+    async fn two_nodes() -> (Lobby, Lobby) {
+        let hub = MemoryHub::new();
+        let first = Lobby::new().with_cluster(hub.backend("node-1"));
+        let second = Lobby::new().with_cluster(hub.backend("node-2"));
+        start(&first).await.unwrap();
+        start(&second).await.unwrap();
+        (first, second)
+    }
+
+    /// Let relays and broadcast tasks catch up
+    async fn settle(lobbies: &[&Lobby]) {
+        for _ in 0..3 {
+            tokio::task::yield_now().await;
+            for lobby in lobbies {
+                lobby.flush_broadcasts().await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_broadcasts_reach_users_on_every_node() {
+        let (first, second) = two_nodes().await;
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+        let mut alice_rx = join(&first, &alice).await;
+        let _bob_rx = join(&second, &bob).await;
+        settle(&[&first, &second]).await;
+
+        // Alice, on the first node, hears about bob joining the second
+        let mut joined = Vec::new();
+        while let Ok(Message::LobbyUpdate { joined: users, .. }) = alice_rx.try_recv() {
+            joined.extend(users.into_iter().map(|u| u.public_key));
+        }
+        assert_eq!(joined, vec![bob.clone()]);
+
+        // Both nodes page over the whole cluster
+        let page = first.get_page(None, 10, None).await.unwrap();
+        assert_eq!(page.users, vec![alice.clone(), bob.clone()]);
+        assert_eq!(second.get_page(None, 10, None).await.unwrap(), page);
+    }
+
+    #[tokio::test]
+    async fn test_direct_message_routed_to_other_node() {
+        let (first, second) = two_nodes().await;
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+        let _alice_rx = join(&first, &alice).await;
+        let mut bob_rx = join(&second, &bob).await;
+        settle(&[&first, &second]).await;
+        while bob_rx.try_recv().is_ok() {}
+
+        assert!(crate::message::recipient_is_online(&first, &bob).await);
+        let delivered = crate::message::deliver(
+            &first,
+            &bob,
+            Message::new_announcement("hi".to_string(), "now".to_string()),
+        )
+        .await;
+        assert!(delivered);
+        settle(&[&first, &second]).await;
+        assert!(matches!(
+            bob_rx.try_recv(),
+            Ok(Message::Announcement { text, .. }) if text == "hi"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_moving_to_another_node_is_not_reported_as_leaving() {
+        let (first, second) = two_nodes().await;
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+        let mut alice_rx = join(&first, &alice).await;
+        let _bob_old = join(&first, &bob).await;
+        let _bob_new = join(&second, &bob).await;
+        settle(&[&first, &second]).await;
+        while alice_rx.try_recv().is_ok() {}
+
+        // The stale connection on the first node goes away
+        crate::lobby::remove_user(&first, &bob).await.unwrap();
+        settle(&[&first, &second]).await;
+
+        assert!(alice_rx.try_recv().is_err());
+        assert!(crate::message::recipient_is_online(&first, &bob).await);
+    }
+}
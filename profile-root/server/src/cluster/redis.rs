@@ -0,0 +1,232 @@
+//! Redis cluster backend
+//!
+//! - Presence is the hash `profile:presence`, mapping each online public
+//!   key to the node it is connected to
+//! - Each node refreshes its score (last heartbeat, ms since the epoch) in
+//!   the sorted set `profile:nodes` every [`NODE_HEARTBEAT_INTERVAL`]; keys
+//!   of a node that missed heartbeats for [`NODE_TIMEOUT`] are treated as
+//!   offline, so a crashed node doesn't leave ghosts in the lobby
+//! - Events are JSON published on the channel `profile:events`
+//!
+//! Publishing goes through a queue drained by one task, so broadcasting
+//! never waits on Redis.
+
+use super::{BackendFuture, ClusterBackend, ClusterError, ClusterEvent, NodeId};
+use futures_util::StreamExt;
+use redis::aio::MultiplexedConnection;
+use redis::AsyncCommands;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Hash of online public keys to node ids
+const PRESENCE_KEY: &str = "profile:presence";
+
+/// Sorted set of node ids scored by their last heartbeat
+const NODES_KEY: &str = "profile:nodes";
+
+/// Pub/sub channel carrying cluster events
+const EVENTS_CHANNEL: &str = "profile:events";
+
+/// How often each node records that it is alive
+pub const NODE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long after its last heartbeat a node's users count as offline
+pub const NODE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Remove a key from presence unless another node registered it since
+const UNREGISTER_SCRIPT: &str = r#"
+local owner = redis.call('HGET', KEYS[1], ARGV[1])
+if owner == ARGV[2] then
+    redis.call('HDEL', KEYS[1], ARGV[1])
+    return 1
+end
+if owner then
+    return 0
+end
+return 1
+"#;
+
+fn unavailable(e: redis::RedisError) -> ClusterError {
+    ClusterError::Unavailable(e.to_string())
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+/// Oldest heartbeat of a node still considered alive
+fn live_since() -> i64 {
+    now_ms() - NODE_TIMEOUT.as_millis() as i64
+}
+
+/// A node of a Redis-backed cluster
+pub struct RedisBackend {
+    node_id: NodeId,
+    client: redis::Client,
+    conn: MultiplexedConnection,
+    outgoing: mpsc::UnboundedSender<String>,
+}
+
+impl fmt::Debug for RedisBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisBackend")
+            .field("node_id", &self.node_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RedisBackend {
+    /// Connect to Redis and start heartbeats and publishing for this node
+    pub async fn connect(url: &str, node_id: NodeId) -> Result<Self, ClusterError> {
+        let client = redis::Client::open(url).map_err(unavailable)?;
+        let mut conn = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(unavailable)?;
+        // Alive before any user registers, so none of them looks offline
+        conn.zadd::<_, _, _, ()>(NODES_KEY, &node_id, now_ms())
+            .await
+            .map_err(unavailable)?;
+
+        tokio::spawn(run_heartbeat(conn.clone(), node_id.clone()));
+        let (outgoing, queued) = mpsc::unbounded_channel();
+        tokio::spawn(run_publisher(conn.clone(), queued));
+
+        Ok(Self {
+            node_id,
+            client,
+            conn,
+            outgoing,
+        })
+    }
+}
+
+/// Refresh this node's heartbeat until the runtime shuts down
+async fn run_heartbeat(mut conn: MultiplexedConnection, node_id: NodeId) {
+    let mut ticks = tokio::time::interval(NODE_HEARTBEAT_INTERVAL);
+    loop {
+        ticks.tick().await;
+        if let Err(e) = conn
+            .zadd::<_, _, _, ()>(NODES_KEY, &node_id, now_ms())
+            .await
+        {
+            tracing::warn!(error = %e, "Failed to send cluster heartbeat");
+        }
+    }
+}
+
+/// Publish queued events in order until the backend is dropped
+async fn run_publisher(
+    mut conn: MultiplexedConnection,
+    mut queued: mpsc::UnboundedReceiver<String>,
+) {
+    while let Some(payload) = queued.recv().await {
+        if let Err(e) = conn.publish::<_, _, ()>(EVENTS_CHANNEL, payload).await {
+            tracing::warn!(error = %e, "Failed to publish cluster event");
+        }
+    }
+}
+
+impl ClusterBackend for RedisBackend {
+    fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    fn register<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, ()> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            conn.hset::<_, _, _, ()>(PRESENCE_KEY, public_key, &self.node_id)
+                .await
+                .map_err(unavailable)
+        })
+    }
+
+    fn unregister<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, bool> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let removed: i64 = redis::Script::new(UNREGISTER_SCRIPT)
+                .key(PRESENCE_KEY)
+                .arg(public_key)
+                .arg(&self.node_id)
+                .invoke_async(&mut conn)
+                .await
+                .map_err(unavailable)?;
+            Ok(removed == 1)
+        })
+    }
+
+    fn locate<'a>(&'a self, public_key: &'a str) -> BackendFuture<'a, Option<NodeId>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let Some(node): Option<NodeId> = conn
+                .hget(PRESENCE_KEY, public_key)
+                .await
+                .map_err(unavailable)?
+            else {
+                return Ok(None);
+            };
+            let heartbeat: Option<i64> =
+                conn.zscore(NODES_KEY, &node).await.map_err(unavailable)?;
+            Ok(heartbeat.filter(|&at| at >= live_since()).map(|_| node))
+        })
+    }
+
+    fn online_keys(&self) -> BackendFuture<'_, Vec<String>> {
+        Box::pin(async move {
+            let mut conn = self.conn.clone();
+            let presence: HashMap<String, NodeId> =
+                conn.hgetall(PRESENCE_KEY).await.map_err(unavailable)?;
+            let live: HashSet<NodeId> = conn
+                .zrangebyscore(NODES_KEY, live_since(), "+inf")
+                .await
+                .map_err(unavailable)?;
+            Ok(presence
+                .into_iter()
+                .filter(|(_, node)| live.contains(node))
+                .map(|(key, _)| key)
+                .collect())
+        })
+    }
+
+    fn publish(&self, event: ClusterEvent) -> Result<(), ClusterError> {
+        let payload =
+            serde_json::to_string(&event).map_err(|e| ClusterError::Encoding(e.to_string()))?;
+        self.outgoing
+            .send(payload)
+            .map_err(|_| ClusterError::Unavailable("publisher stopped".to_string()))
+    }
+
+    fn subscribe(&self) -> BackendFuture<'_, mpsc::UnboundedReceiver<ClusterEvent>> {
+        Box::pin(async move {
+            let mut pubsub = self.client.get_async_pubsub().await.map_err(unavailable)?;
+            pubsub
+                .subscribe(EVENTS_CHANNEL)
+                .await
+                .map_err(unavailable)?;
+            let (sender, events) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                let mut messages = pubsub.on_message();
+                while let Some(message) = messages.next().await {
+                    let event = message
+                        .get_payload::<String>()
+                        .map_err(|e| e.to_string())
+                        .and_then(|payload| {
+                            serde_json::from_str::<ClusterEvent>(&payload)
+                                .map_err(|e| e.to_string())
+                        });
+                    match event {
+                        Ok(event) => {
+                            if sender.send(event).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => tracing::warn!(error = %e, "Ignoring invalid cluster event"),
+                    }
+                }
+            });
+            Ok(events)
+        })
+    }
+}
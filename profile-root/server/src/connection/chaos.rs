@@ -0,0 +1,344 @@
+//! Fault injection for testing the server under a bad network
+//!
+//! Debug builds can inject faults into every connection, configured with a
+//! comma-separated spec in `PROFILE_CHAOS` (or built directly in tests):
+//!
+//! ```text
+//! PROFILE_CHAOS="delay=5..50,drop=0.05,disconnect=0.01,slow_write=2,seed=7"
+//! ```
+//!
+//! - `delay=MIN..MAX`: hold each inbound frame for a random MIN..MAX ms
+//! - `drop=P`: lose each inbound or outbound text frame with probability P
+//! - `disconnect=P`: on each socket write, with probability P, write only part
+//!   of the buffer and then sever the connection (a mid-frame disconnect)
+//! - `slow_write=MS`: write at most `SLOW_WRITE_CHUNK` bytes at a time,
+//!   waiting MS ms before each chunk
+//! - `seed=N`: seed for the per-connection random generators, so a failing
+//!   run can be repeated
+//!
+//! Socket-level faults live in [`ChaosIo`], which wraps the TCP stream below
+//! the WebSocket layer; frame-level faults are applied by the connection
+//! handler through [`Chaos`]. Release builds ignore `PROFILE_CHAOS`.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// Environment variable holding the chaos spec; chaos is off when unset
+pub const CHAOS_ENV_VAR: &str = "PROFILE_CHAOS";
+
+/// Largest write passed to the socket at once when `slow_write` is set
+pub const SLOW_WRITE_CHUNK: usize = 64;
+
+/// Which faults to inject and how often
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChaosConfig {
+    /// Random hold applied to each inbound frame (min, max)
+    pub delay: Option<(Duration, Duration)>,
+    /// Probability a text frame is dropped
+    pub drop_rate: f64,
+    /// Probability a socket write is cut short and the connection severed
+    pub disconnect_rate: f64,
+    /// Wait before each chunk of a socket write
+    pub slow_write: Option<Duration>,
+    /// Base seed for the per-connection random generators
+    pub seed: u64,
+}
+
+impl ChaosConfig {
+    /// Parse a chaos spec such as `delay=5..50,drop=0.1,seed=3`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        for setting in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("Expected name=value, got '{}'", setting))?;
+            let millis = |v: &str| {
+                v.trim()
+                    .parse::<u64>()
+                    .map(Duration::from_millis)
+                    .map_err(|e| format!("Invalid {} '{}': {}", name, value, e))
+            };
+            let probability = |v: &str| match v.trim().parse::<f64>() {
+                Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+                _ => Err(format!(
+                    "Invalid {} '{}': expected a probability between 0 and 1",
+                    name, value
+                )),
+            };
+
+            match name.trim() {
+                "delay" => {
+                    let (min, max) = value.split_once("..").unwrap_or((value, value));
+                    let (min, max) = (millis(min)?, millis(max)?);
+                    if min > max {
+                        return Err(format!("Invalid delay '{}': min exceeds max", value));
+                    }
+                    config.delay = Some((min, max));
+                }
+                "drop" => config.drop_rate = probability(value)?,
+                "disconnect" => config.disconnect_rate = probability(value)?,
+                "slow_write" => config.slow_write = Some(millis(value)?),
+                "seed" => {
+                    config.seed = value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid seed '{}': {}", value, e))?
+                }
+                other => return Err(format!("Unknown chaos setting '{}'", other)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read the chaos spec from `PROFILE_CHAOS` in debug builds
+    ///
+    /// # Returns
+    /// Ok(None) if the variable is unset or this is a release build
+    pub fn from_env() -> Result<Option<Arc<Self>>, String> {
+        if !cfg!(debug_assertions) {
+            return Ok(None);
+        }
+        match std::env::var(CHAOS_ENV_VAR) {
+            Ok(spec) if !spec.trim().is_empty() => Ok(Some(Arc::new(Self::parse(&spec)?))),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Fault source for one connection
+#[derive(Debug)]
+pub struct Chaos {
+    config: ChaosConfig,
+    rng: Mutex<StdRng>,
+}
+
+impl Chaos {
+    /// Create the fault source for a connection
+    ///
+    /// Each connection's generator is seeded from the config seed and the
+    /// connection id, so runs with the same seed inject the same faults.
+    pub fn for_connection(config: &ChaosConfig, connection_id: u64) -> Arc<Self> {
+        Arc::new(Self {
+            config: config.clone(),
+            rng: Mutex::new(StdRng::seed_from_u64(config.seed ^ connection_id)),
+        })
+    }
+
+    fn with_rng<T>(&self, f: impl FnOnce(&mut StdRng) -> T) -> T {
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut rng)
+    }
+
+    fn roll(&self, probability: f64) -> bool {
+        probability > 0.0 && self.with_rng(|rng| rng.gen_bool(probability))
+    }
+
+    /// How long to hold the next inbound frame, if at all
+    pub fn inbound_delay(&self) -> Option<Duration> {
+        let (min, max) = self.config.delay?;
+        Some(self.with_rng(|rng| rng.gen_range(min..=max)))
+    }
+
+    /// Whether to drop the next text frame
+    pub fn drop_frame(&self) -> bool {
+        self.roll(self.config.drop_rate)
+    }
+
+    /// Bytes of a `len`-byte write to let through before severing the
+    /// connection, or None to write normally
+    fn cut_write(&self, len: usize) -> Option<usize> {
+        if len < 2 || !self.roll(self.config.disconnect_rate) {
+            return None;
+        }
+        Some(self.with_rng(|rng| rng.gen_range(1..len)))
+    }
+}
+
+/// Socket wrapper injecting slow writes and mid-frame disconnects
+pub struct ChaosIo<S> {
+    inner: S,
+    chaos: Arc<Chaos>,
+    /// Pending wait before the next slow write chunk
+    write_delay: Option<Pin<Box<Sleep>>>,
+    /// Set once the connection has been severed
+    severed: bool,
+}
+
+impl<S> ChaosIo<S> {
+    /// Wrap a socket
+    pub fn new(inner: S, chaos: Arc<Chaos>) -> Self {
+        Self {
+            inner,
+            chaos,
+            write_delay: None,
+            severed: false,
+        }
+    }
+
+    fn severed_error() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "connection severed by chaos",
+        )
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ChaosIo<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.severed {
+            return Poll::Ready(Err(Self::severed_error()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ChaosIo<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.severed {
+            return Poll::Ready(Err(Self::severed_error()));
+        }
+
+        if let Some(delay) = self.chaos.config.slow_write {
+            let sleep = self
+                .write_delay
+                .get_or_insert_with(|| Box::pin(tokio::time::sleep(delay)));
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.write_delay = None;
+        }
+        let len = match self.chaos.config.slow_write {
+            Some(_) => buf.len().min(SLOW_WRITE_CHUNK),
+            None => buf.len(),
+        };
+
+        match self.chaos.cut_write(len) {
+            Some(partial) => {
+                let result = Pin::new(&mut self.inner).poll_write(cx, &buf[..partial]);
+                if result.is_ready() {
+                    self.severed = true;
+                    let _ = Pin::new(&mut self.inner).poll_shutdown(cx);
+                }
+                result
+            }
+            None => Pin::new(&mut self.inner).poll_write(cx, &buf[..len]),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.severed {
+            return Poll::Ready(Err(Self::severed_error()));
+        }
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_parse_full_spec() {
+        let config =
+            ChaosConfig::parse("delay=5..50, drop=0.1,disconnect=0.01,slow_write=2,seed=7")
+                .unwrap();
+        assert_eq!(
+            config.delay,
+            Some((Duration::from_millis(5), Duration::from_millis(50)))
+        );
+        assert_eq!(config.drop_rate, 0.1);
+        assert_eq!(config.disconnect_rate, 0.01);
+        assert_eq!(config.slow_write, Some(Duration::from_millis(2)));
+        assert_eq!(config.seed, 7);
+
+        assert_eq!(ChaosConfig::parse("").unwrap(), ChaosConfig::default());
+        assert_eq!(
+            ChaosConfig::parse("delay=10").unwrap().delay,
+            Some((Duration::from_millis(10), Duration::from_millis(10)))
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_settings() {
+        assert!(ChaosConfig::parse("drop=1.5").is_err());
+        assert!(ChaosConfig::parse("delay=50..5").is_err());
+        assert!(ChaosConfig::parse("jitter=3").is_err());
+        assert!(ChaosConfig::parse("seed").is_err());
+    }
+
+    #[test]
+    fn test_same_seed_injects_same_faults() {
+        let config = ChaosConfig {
+            drop_rate: 0.5,
+            delay: Some((Duration::ZERO, Duration::from_millis(100))),
+            seed: 11,
+            ..Default::default()
+        };
+        let run = |connection_id| {
+            let chaos = Chaos::for_connection(&config, connection_id);
+            (0..32)
+                .map(|_| (chaos.drop_frame(), chaos.inbound_delay()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_writes_partial_buffer_then_fails() {
+        let config = ChaosConfig {
+            disconnect_rate: 1.0,
+            ..Default::default()
+        };
+        let (client, mut server) = tokio::io::duplex(1024);
+        let mut io = ChaosIo::new(client, Chaos::for_connection(&config, 1));
+
+        let written = io.write(b"0123456789").await.unwrap();
+        assert!((1..10).contains(&written));
+        assert!(io.write(b"more").await.is_err());
+
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received.len(), written);
+    }
+
+    #[tokio::test]
+    async fn test_slow_write_chunks_output() {
+        let config = ChaosConfig {
+            slow_write: Some(Duration::from_millis(1)),
+            ..Default::default()
+        };
+        let (client, mut server) = tokio::io::duplex(1024);
+        let mut io = ChaosIo::new(client, Chaos::for_connection(&config, 1));
+
+        let payload = [7u8; 200];
+        assert_eq!(io.write(&payload).await.unwrap(), SLOW_WRITE_CHUNK);
+        io.write_all(&payload[SLOW_WRITE_CHUNK..]).await.unwrap();
+        io.shutdown().await.unwrap();
+
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, payload);
+    }
+}
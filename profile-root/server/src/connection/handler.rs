@@ -1,67 +1,43 @@
-use futures_util::{stream::StreamExt, SinkExt};
-use hex;
-use serde_json;
+//! WebSocket connection handling
+//!
+//! [`handle_connection`] owns the socket: it reads frames, feeds them to a
+//! [`ConnectionSession`] and writes back whatever the session returns,
+//! including heartbeat pings. Everything written to the socket, the
+//! session's replies and messages from the user's send queue alike, goes
+//! through the connection's writer (see [`crate::connection::writer`]),
+//! which runs alongside the read loop. All protocol decisions live in the
+//! session's state machine. When the lobby's shutdown signal fires, the
+//! session closes the connection with a jittered retry hint. With a
+//! [`CaptureWriter`], every frame is also teed to the capture file. With a
+//! [`ChaosConfig`] (debug builds), faults are injected into the socket and
+//! the frame stream.
+//!
+//! Compression is accepted from clients that offer it unless
+//! `PROFILE_COMPRESSION=off`; `PROFILE_COMPRESSION_THRESHOLD` sets the
+//! smallest frame the server compresses (see
+//! [`profile_shared::protocol::compression`]).
+
+use futures_util::stream::StreamExt;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpStream;
-use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
-use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
 use tokio_tungstenite::tungstenite::Message;
-
-use crate::auth::handler::{handle_authentication, AuthResult};
-use crate::lobby::{ActiveConnection, Lobby};
-use crate::message::{handle_incoming_message, route_message, MessageValidationResult};
-use crate::protocol::{AuthErrorMessage, AuthMessage, AuthSuccessMessage};
+use tracing::Instrument;
+
+use crate::audit::AuditEvent;
+use crate::connection::chaos::{Chaos, ChaosConfig, ChaosIo};
+use crate::connection::session::{ConnectionSession, SystemClock};
+use crate::connection::writer::{run_writer, WriterEvent, WriterInput};
+use crate::lobby::Lobby;
+use crate::logging::connection_span;
 use crate::rate_limiter::AuthRateLimiter;
-use profile_shared::LobbyError;
-use profile_shared::PublicKey;
-
-fn truncate_key(key: &str) -> &str {
-    &key[..16.min(key.len())]
-}
-
-enum CleanupResult {
-    Success,
-    BroadcastFailed,
-    LockFailed,
-    OtherError(LobbyError),
-}
-
-async fn cleanup_user_from_lobby(lobby: &Arc<Lobby>, key_hex: &str) -> CleanupResult {
-    match crate::lobby::remove_user(lobby, key_hex).await {
-        Ok(()) => {
-            tracing::debug!(
-                "User {}... removed from lobby successfully",
-                truncate_key(key_hex)
-            );
-            CleanupResult::Success
-        }
-        Err(LobbyError::LockFailed) => {
-            tracing::error!(
-                "CRITICAL: Failed to acquire lobby lock for user {}... removal. \
-                 User may remain visible to others incorrectly.",
-                truncate_key(key_hex)
-            );
-            CleanupResult::LockFailed
-        }
-        Err(LobbyError::BroadcastFailed) => {
-            tracing::warn!(
-                "User {}... removed from lobby but leave notification failed to broadcast",
-                truncate_key(key_hex)
-            );
-            CleanupResult::BroadcastFailed
-        }
-        Err(e) => {
-            tracing::error!(
-                "Failed to remove user {}... from lobby: {}",
-                truncate_key(key_hex),
-                e
-            );
-            CleanupResult::OtherError(e)
-        }
-    }
-}
+use crate::rooms::Rooms;
+use profile_shared::capture::{CaptureWriter, Direction, FrameKind};
+use profile_shared::config::connection::MAX_FRAME_SIZE;
+use profile_shared::protocol::CompressionConfig;
 
 /// Atomic counter for generating unique connection IDs
 ///
@@ -80,345 +56,210 @@ fn generate_connection_id() -> u64 {
         .unwrap_or(1)
 }
 
+/// Append a frame to the capture file, if capture is enabled
+fn capture_frame(
+    capture: Option<&CaptureWriter>,
+    direction: Direction,
+    connection_id: u64,
+    frame: &Message,
+) {
+    let Some(capture) = capture else {
+        return;
+    };
+    let (kind, data) = match frame {
+        Message::Text(text) => (FrameKind::Text, text.clone()),
+        Message::Binary(data) => (FrameKind::Binary, hex::encode(data)),
+        Message::Ping(data) => (FrameKind::Ping, hex::encode(data)),
+        Message::Pong(data) => (FrameKind::Pong, hex::encode(data)),
+        Message::Close(frame) => (
+            FrameKind::Close,
+            frame
+                .as_ref()
+                .map(|f| f.reason.to_string())
+                .unwrap_or_default(),
+        ),
+        Message::Frame(frame) => (FrameKind::Binary, hex::encode(frame.payload())),
+    };
+    if let Err(e) = capture.record(direction, connection_id, kind, &data) {
+        tracing::warn!("Failed to write frame capture: {}", e);
+    }
+}
+
+/// Serve one client connection until it closes
+///
+/// Everything logged on the connection's behalf, from auth through lobby
+/// updates and routing, happens inside its [`connection_span`].
 pub async fn handle_connection(
     stream: TcpStream,
     lobby: Arc<Lobby>,
+    rooms: Arc<Rooms>,
     rate_limiter: Arc<AuthRateLimiter>,
+    capture: Option<Arc<CaptureWriter>>,
+    chaos: Option<Arc<ChaosConfig>>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let ws_stream = tokio_tungstenite::accept_async(stream).await?;
-
-    let (mut write, mut read) = ws_stream.split();
-
     let connection_id = generate_connection_id();
-    let connection_id_str = connection_id.to_string();
-
-    // Track authenticated user's public key for cleanup
-    let mut authenticated_key: Option<PublicKey> = None;
-
-    // Wait for auth message
-    if let Some(message_result) = read.next().await {
-        let message = message_result?;
-
-        match handle_auth_message(&message, &lobby, &rate_limiter, &connection_id_str).await {
-            AuthResult::Success {
-                public_key,
-                lobby_state: _,
-            } => {
-                // NOTE: The lobby_state from auth handler is IGNORED here.
-                // We add the user to the lobby FIRST, then refetch the lobby state
-                // to ensure the newly authenticated user sees themselves and all
-                // other online users in the response.
-                //
-                // This fixes the bug where lobby_state was captured BEFORE the user
-                // was added, causing the new user to not see themselves.
-
-                // Create active connection for lobby
-                // NOTE: The sender channel is for future Epic 3 message routing.
-                // Currently messages to clients are sent directly via 'write' sink.
-                // The sender will be used when implementing broadcast notifications
-                // (Stories 2.3, 2.4) to route messages through the lobby.
-                // Receiver is intentionally dropped here - will be connected when
-                // implementing broadcast helpers in Story 2.3.
-                let (sender, _receiver) =
-                    tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
-                drop(_receiver); // Explicit drop for clarity
-                let public_key_string = hex::encode(public_key.as_slice());
-                let connection = ActiveConnection {
-                    public_key: public_key_string.clone(),
-                    sender,
-                    connection_id,
-                };
-
-                // Add user to lobby before sending auth success
-                // SECURITY: Only add to lobby after successful authentication
-                // If this fails, we should NOT send auth success - user is not in lobby
-                match crate::lobby::add_user(&lobby, public_key_string.clone(), connection).await {
-                    Ok(()) => {
-                        // User successfully added to lobby, proceed with auth success
-                        authenticated_key = Some(public_key.clone());
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to add user to lobby: {}", e);
-                        let error_msg = AuthErrorMessage {
-                            r#type: "error".to_string(),
-                            reason: "lobby_error".to_string(),
-                            details: "Unable to join lobby. Please try again.".to_string(),
-                        };
-                        let error_json = serde_json::to_string(&error_msg)?;
-                        write.send(Message::Text(error_json)).await?;
-
-                        let close_frame = CloseFrame {
-                            code: CloseCode::Policy,
-                            reason: "Failed to join lobby".into(),
-                        };
-                        write.send(Message::Close(Some(close_frame))).await?;
-                        return Ok(());
-                    }
-                }
-
-                // Refetch lobby state AFTER adding user to include self
-                let updated_lobby_state = lobby
-                    .get_full_lobby_state()
-                    .await
-                    .unwrap_or_else(|_| vec![]);
-
-                // Send success message with UPDATED lobby state (includes new user)
-                let success_msg = AuthSuccessMessage::new(updated_lobby_state);
-                let success_json = serde_json::to_string(&success_msg)?;
-                write.send(Message::Text(success_json)).await?;
-            }
-            AuthResult::Failure { reason, details } => {
-                // Send error message and close connection
-                let error_msg = AuthErrorMessage {
-                    r#type: "error".to_string(),
-                    reason: reason.clone(),
-                    details,
-                };
-                let error_json = serde_json::to_string(&error_msg)?;
-                write.send(Message::Text(error_json)).await?;
-
-                let close_frame = CloseFrame {
-                    code: CloseCode::Normal,
-                    reason: reason.into(),
-                };
-                if let Err(e) = write.send(Message::Close(Some(close_frame))).await {
-                    tracing::warn!("Failed to send close frame: {}", e);
-                }
-
-                return Ok(());
-            }
+    lobby.audit.record(AuditEvent::Connected {
+        connection_id,
+        peer: stream.peer_addr().ok().map(|addr| addr.to_string()),
+    });
+    let capture = capture.as_deref();
+    let span = connection_span(connection_id);
+    match chaos {
+        Some(config) => {
+            let chaos = Chaos::for_connection(&config, connection_id);
+            let stream = ChaosIo::new(stream, Arc::clone(&chaos));
+            serve(
+                stream,
+                lobby,
+                rooms,
+                rate_limiter,
+                connection_id,
+                capture,
+                Some(&chaos),
+            )
+            .instrument(span)
+            .await
+        }
+        None => {
+            serve(
+                stream,
+                lobby,
+                rooms,
+                rate_limiter,
+                connection_id,
+                capture,
+                None,
+            )
+            .instrument(span)
+            .await
         }
     }
+}
 
-    const AUTHENTICATED_READ_TIMEOUT_SECS: u64 = 300;
-    let read_timeout = Duration::from_secs(AUTHENTICATED_READ_TIMEOUT_SECS);
-
-    loop {
-        match tokio::time::timeout(read_timeout, read.next()).await {
-            Ok(Some(msg_result)) => {
-                match msg_result {
-                    Ok(Message::Text(text)) => {
-                        // Handle incoming message from authenticated user (Story 3.2 + 3.3)
-                        // AC1: Route validated message to recipient via real-time push
-                        // Note: Message size validation is now handled in handle_incoming_message
-                        if let Some(ref sender_key) = authenticated_key {
-                            // Validate the message (Story 3.2)
-                            let sender_key_hex = hex::encode(sender_key.as_slice());
-                            tracing::debug!(sender = %sender_key_hex, "Received message, validating and routing...");
-                            let validation_result =
-                                handle_incoming_message(&lobby, &sender_key_hex, &text).await;
-
-                            // Handle validation result
-                            match validation_result {
-                                MessageValidationResult::Valid { .. } => {
-                                    // Message is valid, route to recipient (Story 3.3)
-                                    match route_message(&lobby, &validation_result).await {
-                                        Ok(()) => {
-                                            tracing::debug!("Message routed successfully");
-                                        }
-                                        Err(e) => {
-                                            // AC7: Log failed delivery but don't return error to sender
-                                            tracing::warn!("Message delivery failed: {}", e);
-                                            // No error returned to sender - server-side issue
-                                        }
-                                    }
-                                }
-                                MessageValidationResult::Invalid { reason } => {
-                                    // Validation failed - send error response back to sender
-                                    tracing::debug!(sender = %sender_key_hex, ?reason, "Message validation failed");
-
-                                    // Get sender's connection to send error response
-                                    if let Ok(Some(sender_conn)) =
-                                        crate::lobby::get_user(&lobby, &sender_key_hex).await
-                                    {
-                                        // Create error message format matching protocol spec (AC3, AC4, AC5)
-                                        let error_response = match &reason {
-                                            crate::message::ValidationError::NotAuthenticated {
-                                                details,
-                                            } => profile_shared::Message::Error {
-                                                reason: "auth_failed".to_string(),
-                                                details: Some(details.clone()),
-                                            },
-                                            crate::message::ValidationError::MalformedJson {
-                                                details,
-                                            } => profile_shared::Message::Error {
-                                                reason: "malformed_json".to_string(),
-                                                details: Some(details.clone()),
-                                            },
-                                            crate::message::ValidationError::SignatureInvalid {
-                                                details,
-                                            } => profile_shared::Message::Error {
-                                                reason: "signature_invalid".to_string(),
-                                                details: Some(details.clone()),
-                                            },
-                                            crate::message::ValidationError::RecipientOffline {
-                                                recipient_key,
-                                            } => profile_shared::Message::Error {
-                                                reason: "offline".to_string(),
-                                                details: Some(format!(
-                                                    "User {} is not currently online",
-                                                    recipient_key
-                                                )),
-                                            },
-                                            crate::message::ValidationError::CannotMessageSelf => {
-                                                profile_shared::Message::Error {
-                                                    reason: "invalid_recipient".to_string(),
-                                                    details: Some(
-                                                        "Cannot send message to yourself"
-                                                            .to_string(),
-                                                    ),
-                                                }
-                                            }
-                                            crate::message::ValidationError::StaleTimestamp {
-                                                details,
-                                            } => profile_shared::Message::Error {
-                                                reason: "stale_timestamp".to_string(),
-                                                details: Some(details.clone()),
-                                            },
-                                            crate::message::ValidationError::MessageTooLarge {
-                                                size,
-                                                max,
-                                            } => profile_shared::Message::Error {
-                                                reason: "message_too_large".to_string(),
-                                                details: Some(format!(
-                                                    "Message size {} exceeds maximum {}",
-                                                    size, max
-                                                )),
-                                            },
-                                        };
-
-                                        // Send error via the sender's WebSocket connection
-                                        let _ = sender_conn.sender.send(error_response);
-                                    }
-                                }
-                            }
-                        }
+/// WebSocket settings that refuse frames over MAX_FRAME_SIZE while reading,
+/// before they are buffered
+fn websocket_config() -> WebSocketConfig {
+    WebSocketConfig {
+        max_message_size: Some(MAX_FRAME_SIZE),
+        max_frame_size: Some(MAX_FRAME_SIZE),
+        ..WebSocketConfig::default()
+    }
+}
+
+/// Compression settings from the environment, read once on first use
+///
+/// Invalid settings are logged and replaced by the defaults.
+fn compression_config() -> CompressionConfig {
+    static CONFIG: OnceLock<CompressionConfig> = OnceLock::new();
+    *CONFIG.get_or_init(|| {
+        CompressionConfig::from_env().unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "Ignoring invalid compression settings");
+            CompressionConfig::default()
+        })
+    })
+}
+
+/// Run the WebSocket handshake and the session loop on `stream`
+async fn serve<S>(
+    stream: S,
+    lobby: Arc<Lobby>,
+    rooms: Arc<Rooms>,
+    rate_limiter: Arc<AuthRateLimiter>,
+    connection_id: u64,
+    capture: Option<&CaptureWriter>,
+    chaos: Option<&Chaos>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let ws_stream =
+        tokio_tungstenite::accept_async_with_config(stream, Some(websocket_config())).await?;
+
+    let (write, mut read) = ws_stream.split();
+    let mut shutdown = lobby.shutdown_signal();
+
+    let mut session =
+        ConnectionSession::new(lobby, rooms, rate_limiter, SystemClock, connection_id)
+            .with_compression(compression_config());
+
+    let (to_writer, writer_inputs) = mpsc::unbounded_channel();
+    let (writer_events, mut from_writer) = mpsc::unbounded_channel();
+    let writer = run_writer(write, writer_inputs, writer_events, |frame| {
+        if matches!(frame, Message::Text(_)) && chaos.is_some_and(Chaos::drop_frame) {
+            return false;
+        }
+        capture_frame(capture, Direction::Outbound, connection_id, frame);
+        true
+    });
+
+    let reader = async {
+        // Dropped when the loop ends, which stops the writer
+        let to_writer = to_writer;
+        while !session.state().is_closing() {
+            if let Some(queue) = session.take_outbound() {
+                let codec = session.codec();
+                let _ = to_writer.send(WriterInput::Queue { queue, codec });
+            }
+            let wait = session
+                .time_until_idle()
+                .min(session.time_until_heartbeat());
+            let frames = tokio::select! {
+                Some(event) = from_writer.recv() => match event {
+                    // Still fall through to finish() so the user leaves the lobby
+                    WriterEvent::WriteFailed(e) => {
+                        session.on_write_error(&e);
+                        Vec::new()
                     }
-                    Ok(Message::Close(_frame)) => {
-                        // Log disconnect event with tracing (subscriber configured in main.rs)
-                        // Note: authenticated_key should always be Some if we reached this point
-                        // as we only enter the message loop after successful authentication
-                        let user_key = authenticated_key
-                            .as_ref()
-                            .map(|k| hex::encode(k.as_slice()))
-                            .unwrap_or_else(|| "unauthenticated".to_string());
-                        tracing::info!(
-                            "User {} disconnected, broadcasting leave notification",
-                            user_key
-                        );
-
-                        if let Some(ref key) = authenticated_key {
-                            let key_hex = hex::encode(key.as_slice());
-                            match cleanup_user_from_lobby(&lobby, &key_hex).await {
-                                CleanupResult::LockFailed => {
-                                    return Err("Lobby lock failure on disconnect".into());
-                                }
-                                CleanupResult::OtherError(e) => {
-                                    return Err(format!("Lobby removal error: {}", e).into());
-                                }
-                                _ => {}
-                            }
+                    WriterEvent::SlowConsumer => session.on_slow_consumer(),
+                },
+                Ok(()) = shutdown.changed() => session.on_shutdown(&mut rand::thread_rng()),
+                read_result = tokio::time::timeout(wait, read.next()) => match read_result {
+                    Ok(Some(frame)) => {
+                        if let Ok(ref message) = frame {
+                            capture_frame(capture, Direction::Inbound, connection_id, message);
                         }
-                        break;
-                    }
-                    Err(e) => {
-                        // Note: authenticated_key should always be Some if we reached this point
-                        // as we only enter the message loop after successful authentication
-                        let user_key = authenticated_key
-                            .as_ref()
-                            .map(|k| hex::encode(k.as_slice()))
-                            .unwrap_or_else(|| "unauthenticated".to_string());
-                        // Log the error but don't claim "disconnection" - WebSocket read errors
-                        // could be network flakiness, malformed messages, etc., not actual disconnections
-                        tracing::error!("WebSocket error for user {}: {}", user_key, e);
-
-                        if let Some(ref key) = authenticated_key {
-                            let key_hex = hex::encode(key.as_slice());
-                            match cleanup_user_from_lobby(&lobby, &key_hex).await {
-                                CleanupResult::LockFailed => {
-                                    return Err("Lobby lock failure on error disconnect".into());
-                                }
-                                CleanupResult::OtherError(e) => {
-                                    return Err(format!("Lobby removal error: {}", e).into());
-                                }
-                                _ => {}
+                        if let Some(chaos) = chaos {
+                            if let Some(delay) = chaos.inbound_delay() {
+                                tokio::time::sleep(delay).await;
+                            }
+                            if matches!(frame, Ok(Message::Text(_))) && chaos.drop_frame() {
+                                continue;
                             }
                         }
-                        break;
+                        session.on_frame(frame).await?
                     }
-                    _ => {
-                        // Handle other message types (binary, ping, pong, etc.)
-                        // Log at debug level for debugging purposes - these are normal WebSocket events
-                        tracing::debug!(
-                            "Received non-text, non-close message type for user {}: {:?}",
-                            authenticated_key
-                                .as_ref()
-                                .map(|k| hex::encode(k.as_slice()))
-                                .unwrap_or_else(|| "unauthenticated".to_string()),
-                            msg_result
-                                .as_ref()
-                                .map(|m| m.to_string())
-                                .unwrap_or_else(|e| format!("Error: {}", e))
-                        );
+                    Ok(None) => {
+                        session.on_stream_end();
+                        Vec::new()
                     }
-                }
-            }
-            Ok(None) | Err(_) => {
-                // Timeout or stream closed - treat as disconnection
-                let user_key = authenticated_key
-                    .as_ref()
-                    .map(|k| hex::encode(k.as_slice()))
-                    .unwrap_or_else(|| "unauthenticated".to_string());
-                tracing::info!("User {} disconnected (timeout or stream closed)", user_key);
-
-                if let Some(ref key) = authenticated_key {
-                    let key_hex = hex::encode(key.as_slice());
-                    let _ = cleanup_user_from_lobby(&lobby, &key_hex).await;
-                }
-                break;
+                    Err(_) if session.on_idle() => Vec::new(),
+                    Err(_) => session.on_heartbeat().into_iter().collect(),
+                },
+            };
+            if !frames.is_empty() {
+                let _ = to_writer.send(WriterInput::Frames(frames));
             }
         }
-    }
-
-    Ok(())
-}
-
-async fn handle_auth_message(
-    message: &Message,
-    lobby: &Arc<Lobby>,
-    rate_limiter: &Arc<AuthRateLimiter>,
-    client_id: &str,
-) -> AuthResult {
-    // Check rate limit first
-    if !rate_limiter.check_auth_allowed(client_id).await {
-        tracing::warn!("Authentication attempt rate limited");
-        return AuthResult::Failure {
-            reason: "rate_limited".to_string(),
-            details: "Too many authentication attempts. Please wait before trying again."
-                .to_string(),
-        };
-    }
-
-    match message {
-        Message::Text(text) => match serde_json::from_str::<AuthMessage>(text) {
-            Ok(auth_msg) => handle_authentication(&auth_msg, lobby).await,
-            Err(_) => AuthResult::Failure {
-                reason: "auth_failed".to_string(),
-                details: "Invalid JSON format".to_string(),
-            },
-        },
-        _ => AuthResult::Failure {
-            reason: "auth_failed".to_string(),
-            details: "Expected text message".to_string(),
-        },
-    }
+        Ok::<(), serde_json::Error>(())
+    };
+
+    // The writer finishes writing whatever the loop handed it, such as the
+    // close frame, before it stops
+    let (read_result, ()) = tokio::join!(reader, writer);
+    read_result?;
+
+    session
+        .finish()
+        .await
+        .map_err(|e| format!("Lobby removal error: {}", e).into())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    use std::sync::Arc;
+    use crate::auth::handler::AuthResult;
+    use crate::connection::send_queue::send_queue;
+    use crate::connection::session::handle_auth_message;
 
     #[tokio::test]
     async fn test_handle_connection_auth_flow() {
@@ -449,10 +290,7 @@ mod tests {
             AuthResult::Failure { reason, details } => {
                 // This is expected - the message parsing works, but auth fails with invalid signature
                 assert_eq!(reason, "auth_failed");
-                println!(
-                    "✅ Message parsing works, auth correctly fails: {} - {}",
-                    reason, details
-                );
+                assert!(!details.is_empty());
                 Ok(())
             }
             AuthResult::Success { .. } => {
@@ -488,8 +326,6 @@ mod tests {
         let result =
             handle_auth_message(&binary_message, &lobby, &rate_limiter, "test_client_2c").await;
         assert!(matches!(result, AuthResult::Failure { .. }));
-
-        println!("✅ All message type tests passed");
     }
 
     #[tokio::test]
@@ -502,11 +338,12 @@ mod tests {
         // Use exactly 64 hex chars (32 bytes) for valid public key
         let test_key =
             "1234567890abcdef1234567890abcdef1234567890abcdef1234567890abcdef".to_string();
-        let (sender, _) = tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
+        let (sender, _) = send_queue();
         let connection = crate::lobby::ActiveConnection {
             public_key: test_key.clone(),
             sender,
             connection_id: 1,
+            status: Default::default(),
         };
         crate::lobby::add_user(&lobby, test_key.clone(), connection)
             .await
@@ -524,15 +361,10 @@ mod tests {
             AuthResult::Success { lobby_state, .. } => {
                 // If auth succeeded, lobby state should contain users
                 assert!(!lobby_state.is_empty());
-                println!(
-                    "✅ Lobby integration works: {} users in lobby",
-                    lobby_state.len()
-                );
             }
             AuthResult::Failure { reason, .. } => {
                 // Auth failure is expected with invalid test data
                 assert_eq!(reason, "auth_failed");
-                println!("✅ Auth failed as expected with invalid data");
             }
         }
     }
@@ -547,11 +379,12 @@ mod tests {
         // Use exactly 64 hex chars (32 bytes) for valid public key - valid hex only
         let public_key =
             "abcdabcdabcdabcdabcdabcdabcdabcdabcdabcdabcdabcdabcdabcdabcdabcd".to_string();
-        let (sender, _) = tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
+        let (sender, _) = send_queue();
         let connection = crate::lobby::ActiveConnection {
             public_key: public_key.clone(),
             sender,
             connection_id: 42,
+            status: Default::default(),
         };
 
         // Add user to lobby
@@ -582,7 +415,5 @@ mod tests {
         // Verify: No ghost user remains
         let lookup_result = crate::lobby::get_user(&lobby, &public_key).await.unwrap();
         assert!(lookup_result.is_none());
-
-        println!("✅ Close frame correctly triggers lobby removal - no ghost users remain");
     }
 }
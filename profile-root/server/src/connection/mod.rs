@@ -1 +1,5 @@
+pub mod chaos;
 pub mod handler;
+pub mod send_queue;
+pub mod session;
+pub mod writer;
@@ -0,0 +1,264 @@
+//! Bounded per-connection send queues
+//!
+//! Everything sent to a user (routed messages, lobby updates, errors) is
+//! queued on their connection and written to the socket by its writer
+//! (see [`crate::connection::writer`]). A client that reads slower than messages arrive used to grow an
+//! unbounded queue on the server; a queue now holds at most
+//! `config::connection::SEND_QUEUE_CAPACITY` messages, and a message sent
+//! to a full queue is dropped. What happens to the connection is set by
+//! [`SlowConsumerPolicy`]:
+//! - `Disconnect` (the default) marks it as a slow consumer, and the
+//!   session loop closes it with the `slow_consumer` reason
+//! - `DropNewest` keeps it, so the client only misses the dropped messages
+//!
+//! Dropped messages and slow consumers are counted lobby-wide in
+//! [`SendQueueMetrics`] and reported by the admin `stats` command, along
+//! with the number of messages queued.
+
+use profile_shared::config::connection::SEND_QUEUE_CAPACITY;
+use profile_shared::Message;
+use std::fmt::{self, Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, error::TryRecvError, error::TrySendError};
+
+/// What to do with a connection whose send queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+    /// Drop the message and close the connection
+    #[default]
+    Disconnect,
+    /// Drop the message and keep the connection
+    DropNewest,
+}
+
+/// Why a message could not be queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendError {
+    /// The queue was full, so the message was dropped
+    Full,
+    /// The connection is gone
+    Closed,
+}
+
+impl Display for SendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Full => write!(f, "Send queue full"),
+            SendError::Closed => write!(f, "Connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// Lobby-wide counters for all send queues
+#[derive(Debug, Default)]
+pub struct SendQueueMetrics {
+    dropped: AtomicU64,
+    slow_consumers: AtomicU64,
+}
+
+impl SendQueueMetrics {
+    /// Create zeroed counters
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Messages dropped because a queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Connections marked for disconnection as slow consumers
+    pub fn slow_consumers(&self) -> u64 {
+        self.slow_consumers.load(Ordering::Relaxed)
+    }
+}
+
+/// State shared by a queue's senders and its receiving end
+#[derive(Debug, Default)]
+struct QueueState {
+    dropped: AtomicU64,
+    slow: AtomicBool,
+}
+
+/// Sending half of a connection's send queue
+#[derive(Debug, Clone)]
+pub struct ConnectionSender {
+    sender: mpsc::Sender<Message>,
+    state: Arc<QueueState>,
+    policy: SlowConsumerPolicy,
+    metrics: Option<Arc<SendQueueMetrics>>,
+}
+
+/// Receiving half of a connection's send queue, drained by its writer
+#[derive(Debug)]
+pub struct OutboundQueue {
+    receiver: mpsc::Receiver<Message>,
+    state: Arc<QueueState>,
+}
+
+/// Create a send queue with the configured capacity that disconnects slow
+/// consumers
+pub fn send_queue() -> (ConnectionSender, OutboundQueue) {
+    send_queue_with(SEND_QUEUE_CAPACITY, SlowConsumerPolicy::default())
+}
+
+/// Create a send queue
+///
+/// # Arguments
+/// * `capacity` - Messages the queue holds before it is full (at least one)
+/// * `policy` - What to do with the connection once it is full
+pub fn send_queue_with(
+    capacity: usize,
+    policy: SlowConsumerPolicy,
+) -> (ConnectionSender, OutboundQueue) {
+    let (sender, receiver) = mpsc::channel(capacity.max(1));
+    let state = Arc::new(QueueState::default());
+    (
+        ConnectionSender {
+            sender,
+            state: Arc::clone(&state),
+            policy,
+            metrics: None,
+        },
+        OutboundQueue { receiver, state },
+    )
+}
+
+impl ConnectionSender {
+    /// Count drops and slow consumers in `metrics` as well
+    pub fn with_metrics(mut self, metrics: Arc<SendQueueMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Queue `message` without waiting
+    ///
+    /// # Returns
+    /// Err(SendError::Full) if the message was dropped because the client
+    /// is reading too slowly
+    pub fn send(&self, message: Message) -> Result<(), SendError> {
+        match self.sender.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Closed(_)) => Err(SendError::Closed),
+            Err(TrySendError::Full(_)) => {
+                self.on_full();
+                Err(SendError::Full)
+            }
+        }
+    }
+
+    fn on_full(&self) {
+        self.state.dropped.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        if self.policy == SlowConsumerPolicy::Disconnect
+            && !self.state.slow.swap(true, Ordering::AcqRel)
+        {
+            tracing::warn!(
+                capacity = self.capacity(),
+                "Send queue full, disconnecting slow consumer"
+            );
+            if let Some(metrics) = &self.metrics {
+                metrics.slow_consumers.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Messages queued and not yet written to the socket
+    pub fn depth(&self) -> usize {
+        self.sender.max_capacity() - self.sender.capacity()
+    }
+
+    /// Messages the queue holds before it is full
+    pub fn capacity(&self) -> usize {
+        self.sender.max_capacity()
+    }
+
+    /// Messages dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.state.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Whether the connection is to be closed for reading too slowly
+    pub fn is_slow_consumer(&self) -> bool {
+        self.state.slow.load(Ordering::Acquire)
+    }
+
+    /// Whether both senders feed the same queue
+    pub fn same_channel(&self, other: &Self) -> bool {
+        self.sender.same_channel(&other.sender)
+    }
+}
+
+impl OutboundQueue {
+    /// Wait for the next queued message
+    ///
+    /// # Returns
+    /// None once every sender is gone
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await
+    }
+
+    /// Take the next queued message without waiting
+    pub fn try_recv(&mut self) -> Result<Message, TryRecvError> {
+        self.receiver.try_recv()
+    }
+
+    /// Whether the connection is to be closed for reading too slowly
+    pub fn is_slow_consumer(&self) -> bool {
+        self.state.slow.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(n: usize) -> Message {
+        Message::new_error("test".to_string(), Some(n.to_string()))
+    }
+
+    #[test]
+    fn test_full_queue_marks_slow_consumer() {
+        let metrics = Arc::new(SendQueueMetrics::new());
+        let (sender, mut queue) = send_queue_with(2, SlowConsumerPolicy::Disconnect);
+        let sender = sender.with_metrics(Arc::clone(&metrics));
+
+        sender.send(message(0)).unwrap();
+        sender.send(message(1)).unwrap();
+        assert_eq!(sender.depth(), 2);
+        assert!(!queue.is_slow_consumer());
+
+        assert_eq!(sender.send(message(2)), Err(SendError::Full));
+        assert_eq!(sender.send(message(3)), Err(SendError::Full));
+        assert!(queue.is_slow_consumer());
+        assert_eq!(sender.dropped(), 2);
+        assert_eq!(metrics.dropped(), 2);
+        assert_eq!(metrics.slow_consumers(), 1);
+
+        // What was queued before the overflow is still there, in order
+        assert_eq!(queue.try_recv(), Ok(message(0)));
+        assert_eq!(queue.try_recv(), Ok(message(1)));
+        assert_eq!(sender.depth(), 0);
+    }
+
+    #[test]
+    fn test_drop_newest_keeps_connection() {
+        let (sender, mut queue) = send_queue_with(1, SlowConsumerPolicy::DropNewest);
+        sender.send(message(0)).unwrap();
+        assert_eq!(sender.send(message(1)), Err(SendError::Full));
+        assert!(!sender.is_slow_consumer());
+
+        assert_eq!(queue.try_recv(), Ok(message(0)));
+        sender.send(message(2)).unwrap();
+        assert_eq!(queue.try_recv(), Ok(message(2)));
+
+        drop(queue);
+        assert_eq!(sender.send(message(3)), Err(SendError::Closed));
+        assert_eq!(sender.dropped(), 1);
+    }
+}
@@ -0,0 +1,1795 @@
+//! Per-connection state machine
+//!
+//! Every WebSocket connection moves through three states:
+//! - `PreAuth`: the first frame must be a valid auth message
+//! - `Authenticated`: the user is in the lobby and their frames are routed
+//! - `Closing`: the connection is done; [`ConnectionSession::finish`] removes
+//!   the user from the lobby and rooms
+//!
+//! Authenticated connections are pinged every `PING_INTERVAL`; a user who
+//! doesn't answer within the pong timeout is closed and removed from the
+//! lobby like any other disconnect, so dead TCP connections don't linger as
+//! ghost users.
+//!
+//! The first frame may also be a resume message carrying the token from a
+//! previous `auth_success` (see [`crate::auth::resume`]); a valid token
+//! takes the place of the signature. A `logout` request revokes the user's
+//! tokens and closes the connection.
+//!
+//! Frames are JSON text until the auth exchange negotiates another
+//! [`Encoding`]; after that, binary frames are decoded to JSON before
+//! routing and every frame written back is re-encoded, so the handlers only
+//! ever see JSON.
+//!
+//! Messages for the user from elsewhere in the server (routed messages,
+//! lobby updates, errors) arrive on the connection's bounded send queue
+//! (see [`crate::connection::send_queue`]). The caller takes the queue with
+//! [`ConnectionSession::take_outbound`] once the user is in the lobby and
+//! hands it to the connection's writer (see [`crate::connection::writer`]);
+//! a connection whose queue overflows is closed with
+//! [`CloseReason::SlowConsumer`].
+//!
+//! Between auth and joining the lobby, the connection waits for a slot in
+//! the lobby's admission queue (see [`crate::admission`]); recently active
+//! users get one first, and a full queue refuses the connection with a
+//! jittered retry hint. When the server shuts down, every connection is
+//! closed with a retry hint too, shorter for users who sent something
+//! recently.
+//!
+//! Each stage takes one inbound event and returns the frames to write back,
+//! so the stages can be unit tested without a socket. The lobby, rooms, rate
+//! limiter and clock are injected by the caller.
+
+use rand::Rng;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio_tungstenite::tungstenite::error::CapacityError;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+use crate::admission::{refusal_retry_hint, shutdown_retry_hint};
+use crate::audit::{claimed_public_key, AuditEvent};
+use crate::auth::handler::{handle_authentication, handle_resume, AuthResult};
+use crate::auth::resume::{is_logout_request, is_resume_message};
+use crate::connection::send_queue::{send_queue_with, OutboundQueue, SlowConsumerPolicy};
+use crate::lobby::{ActiveConnection, Lobby, UserStatus};
+use crate::message::attachments::{handle_attachment_request, is_attachment_request};
+use crate::message::backup::{handle_backup_request, is_backup_request};
+use crate::message::cover::is_cover_frame;
+use crate::message::edit::{handle_edit_message_request, is_edit_message_request};
+use crate::message::filters::{handle_filter_request, is_filter_request};
+use crate::message::last_seen::{handle_query_last_seen_request, is_query_last_seen_request};
+use crate::message::lobby::{handle_lobby_page_request, is_lobby_page_request};
+use crate::message::names::{handle_claim_name_request, is_claim_name_request};
+use crate::message::nickname::{handle_set_nickname_request, is_set_nickname_request};
+use crate::message::reactions::{handle_reaction_request, is_reaction_request};
+use crate::message::receipts::{handle_read_receipt, is_read_receipt};
+use crate::message::retract::{handle_retract_message_request, is_retract_message_request};
+use crate::message::rollover::{handle_key_rollover_request, is_key_rollover_request};
+use crate::message::rooms::{handle_room_request, is_room_request};
+use crate::message::sealed::{handle_sealed_request, is_sealed_request};
+use crate::message::status::{handle_set_status_request, is_set_status_request};
+use crate::message::viewing::{handle_viewing_request, is_viewing_request};
+use crate::message::{
+    handle_incoming_message, message_type, route_message, MessageValidationResult, ValidationError,
+};
+use crate::protocol::{AuthErrorMessage, AuthMessage, AuthSuccessMessage, ResumeMessage};
+use crate::rate_limiter::AuthRateLimiter;
+use crate::rooms::Rooms;
+use profile_shared::config::connection::{MAX_FRAME_SIZE, SEND_QUEUE_CAPACITY};
+use profile_shared::config::reconnect::RECENT_ACTIVITY;
+use profile_shared::protocol::compression::Frame;
+use profile_shared::protocol::encoding::EncodingError;
+use profile_shared::protocol::{
+    close_reason_with_retry, Compression, CompressionConfig, Encoding, FrameCodec,
+    SERVER_BUSY_REASON, SERVER_SHUTDOWN_REASON,
+};
+use profile_shared::LobbyError;
+
+/// Time a connection may stay silent before it is closed
+pub const READ_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Source of the current time, injected so idle timeouts can be tested
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+/// Clock backed by `Instant::now()`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Ping interval and pong timeout for authenticated connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    /// Silence after which the server sends a ping
+    pub interval: Duration,
+    /// Time allowed for any frame to arrive after a ping
+    pub timeout: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: profile_shared::config::connection::PING_INTERVAL,
+            timeout: profile_shared::config::connection::PONG_TIMEOUT,
+        }
+    }
+}
+
+/// Why a connection entered the Closing state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The auth message was rejected (reason as sent to the client)
+    AuthRejected { reason: String },
+    /// Authentication succeeded but the user could not join the lobby
+    LobbyRejected,
+    /// The client sent a close frame
+    ClientClosed,
+    /// The client logged out, revoking its resume tokens
+    LoggedOut,
+    /// Reading from the socket failed
+    ReadError(String),
+    /// Writing to the socket failed
+    WriteError(String),
+    /// No frame arrived within the read timeout
+    IdleTimeout,
+    /// A ping went unanswered past the pong timeout
+    HeartbeatTimeout,
+    /// The socket stream ended without a close frame
+    StreamEnded,
+    /// The server operator kicked or banned the user through the admin API
+    Kicked,
+    /// The user's send queue overflowed because the client read too slowly
+    SlowConsumer,
+    /// The admission queue was full; the client was asked to come back
+    /// after `retry_after`
+    AdmissionRefused { retry_after: Duration },
+    /// The server is shutting down; the client was asked to reconnect
+    /// after `retry_after`
+    ServerShutdown { retry_after: Duration },
+}
+
+/// Connection lifecycle state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionState {
+    /// Waiting for the auth message
+    PreAuth,
+    /// Authenticated and in the lobby
+    Authenticated { public_key: String },
+    /// Done; `public_key` is set if the user still has to be cleaned up
+    Closing {
+        public_key: Option<String>,
+        reason: CloseReason,
+    },
+}
+
+impl SessionState {
+    /// Public key of the authenticated user, if any
+    pub fn public_key(&self) -> Option<&str> {
+        match self {
+            SessionState::PreAuth => None,
+            SessionState::Authenticated { public_key } => Some(public_key),
+            SessionState::Closing { public_key, .. } => public_key.as_deref(),
+        }
+    }
+
+    /// Whether the connection should stop reading
+    pub fn is_closing(&self) -> bool {
+        matches!(self, SessionState::Closing { .. })
+    }
+}
+
+/// Turn an encoded frame into a WebSocket message
+///
+/// None if encoding failed, in which case the caller sends the JSON as is.
+fn to_message(frame: Result<Frame, EncodingError>) -> Option<Message> {
+    match frame {
+        Ok(Frame::Text(text)) => Some(Message::Text(text)),
+        Ok(Frame::Binary(data)) => Some(Message::Binary(data)),
+        Err(e) => {
+            tracing::warn!("Failed to re-encode frame, sending JSON: {}", e);
+            None
+        }
+    }
+}
+
+/// Re-encode a JSON text frame in `codec`'s encoding, compressing it if
+/// compression was negotiated and it is large enough
+pub(crate) fn encode_text(codec: &FrameCodec, text: String) -> Message {
+    if !codec.accepts_binary() {
+        return Message::Text(text);
+    }
+    to_message(codec.encode_json(&text)).unwrap_or(Message::Text(text))
+}
+
+fn truncate_key(key: &str) -> &str {
+    &key[..16.min(key.len())]
+}
+
+/// Map a validation failure to the error message sent back to the client
+///
+/// Error reasons match the protocol spec (AC3, AC4, AC5).
+fn validation_error_message(reason: &ValidationError) -> profile_shared::Message {
+    let (reason, details) = match reason {
+        ValidationError::NotAuthenticated { details } => ("auth_failed", details.clone()),
+        ValidationError::MalformedJson { details } => ("malformed_json", details.clone()),
+        ValidationError::SignatureInvalid { details } => ("signature_invalid", details.clone()),
+        ValidationError::RecipientOffline { recipient_key } => {
+            return profile_shared::Message::new_recipient_offline(recipient_key);
+        }
+        ValidationError::CannotMessageSelf => (
+            "invalid_recipient",
+            "Cannot send message to yourself".to_string(),
+        ),
+        ValidationError::StaleTimestamp { details } => ("stale_timestamp", details.clone()),
+        ValidationError::MessageTooLarge { size, max } => (
+            "message_too_large",
+            format!("Message size {} exceeds maximum {}", size, max),
+        ),
+        ValidationError::RoomRejected { error } => {
+            (crate::rooms::room_error_reason(error), error.to_string())
+        }
+        ValidationError::DuplicateMessage { message_id } => (
+            "duplicate_message",
+            format!("Message {} was already delivered", message_id),
+        ),
+        ValidationError::NicknameRejected { error } => (
+            crate::lobby::nicknames::nickname_error_reason(error),
+            error.to_string(),
+        ),
+        ValidationError::NameRejected { error } => {
+            (crate::names::name_error_reason(error), error.to_string())
+        }
+        ValidationError::ReplayDetected {
+            sequence,
+            last_seen,
+        } => (
+            "replay_detected",
+            format!(
+                "Sequence {} does not follow last sequence {}",
+                sequence, last_seen
+            ),
+        ),
+        ValidationError::SequenceMissing { last_seen } => (
+            "replay_detected",
+            format!("Sequence missing after sequence {}", last_seen),
+        ),
+        ValidationError::BackupRejected { error } => {
+            (crate::backup::backup_error_reason(error), error.to_string())
+        }
+        ValidationError::FiltersRejected { error } => (
+            crate::filters::filter_error_reason(error),
+            error.to_string(),
+        ),
+        ValidationError::SenderBanned { details } => ("banned", details.clone()),
+        ValidationError::KeyRolloverRejected { details } => {
+            ("key_rollover_rejected", details.clone())
+        }
+        ValidationError::AttachmentRejected { error } => (
+            crate::attachments::attachment_error_reason(error),
+            error.to_string(),
+        ),
+        ValidationError::ServerBusy {
+            recipient_key,
+            retry_after,
+        } => {
+            return profile_shared::Message::new_server_busy(recipient_key, *retry_after);
+        }
+    };
+    profile_shared::Message::new_error(reason.to_string(), Some(details))
+}
+
+/// Error frame plus close frame sent when a connection is refused
+fn refusal_frames(
+    reason: &str,
+    details: String,
+    code: CloseCode,
+    close_reason: &str,
+) -> Result<Vec<Message>, serde_json::Error> {
+    let error_msg = AuthErrorMessage {
+        r#type: "error".to_string(),
+        reason: reason.to_string(),
+        details,
+    };
+    Ok(vec![
+        Message::Text(serde_json::to_string(&error_msg)?),
+        Message::Close(Some(CloseFrame {
+            code,
+            reason: close_reason.to_string().into(),
+        })),
+    ])
+}
+
+/// Check the first frame of a connection: rate limit `client_id`, then
+/// parse it as a resume or auth message and verify it
+///
+/// Public so the fuzz targets (`fuzz/`) can call it directly.
+pub async fn handle_auth_message(
+    message: &Message,
+    lobby: &Arc<Lobby>,
+    rate_limiter: &Arc<AuthRateLimiter>,
+    client_id: &str,
+) -> AuthResult {
+    // Check rate limit first
+    if !rate_limiter.check_auth_allowed(client_id).await {
+        tracing::warn!("Authentication attempt rate limited");
+        return AuthResult::Failure {
+            reason: "rate_limited".to_string(),
+            details: "Too many authentication attempts. Please wait before trying again."
+                .to_string(),
+        };
+    }
+
+    match message {
+        Message::Text(text) if message_type(text).is_some_and(|kind| is_resume_message(&kind)) => {
+            match serde_json::from_str::<ResumeMessage>(text) {
+                Ok(resume_msg) => handle_resume(&resume_msg, lobby, SystemTime::now()).await,
+                Err(_) => AuthResult::Failure {
+                    reason: "resume_failed".to_string(),
+                    details: "Invalid JSON format".to_string(),
+                },
+            }
+        }
+        Message::Text(text) => match serde_json::from_str::<AuthMessage>(text) {
+            Ok(auth_msg) => handle_authentication(&auth_msg, lobby).await,
+            Err(_) => AuthResult::Failure {
+                reason: "auth_failed".to_string(),
+                details: "Invalid JSON format".to_string(),
+            },
+        },
+        _ => AuthResult::Failure {
+            reason: "auth_failed".to_string(),
+            details: "Expected text message".to_string(),
+        },
+    }
+}
+
+/// State and dependencies for one client connection
+pub struct ConnectionSession<C: Clock = SystemClock> {
+    lobby: Arc<Lobby>,
+    rooms: Arc<Rooms>,
+    rate_limiter: Arc<AuthRateLimiter>,
+    clock: C,
+    connection_id: u64,
+    read_timeout: Duration,
+    heartbeat: HeartbeatConfig,
+    last_activity: Instant,
+    /// When the user last sent a message or request, not counting pongs
+    last_message: Option<Instant>,
+    ping_sent_at: Option<Instant>,
+    compression: CompressionConfig,
+    codec: FrameCodec,
+    send_queue_capacity: usize,
+    slow_consumer_policy: SlowConsumerPolicy,
+    outbound: Option<OutboundQueue>,
+    state: SessionState,
+}
+
+impl<C: Clock> ConnectionSession<C> {
+    /// Create a session in the PreAuth state
+    pub fn new(
+        lobby: Arc<Lobby>,
+        rooms: Arc<Rooms>,
+        rate_limiter: Arc<AuthRateLimiter>,
+        clock: C,
+        connection_id: u64,
+    ) -> Self {
+        let last_activity = clock.now();
+        Self {
+            lobby,
+            rooms,
+            rate_limiter,
+            clock,
+            connection_id,
+            read_timeout: READ_TIMEOUT,
+            heartbeat: HeartbeatConfig::default(),
+            last_activity,
+            last_message: None,
+            ping_sent_at: None,
+            compression: CompressionConfig::default(),
+            codec: FrameCodec::default(),
+            send_queue_capacity: SEND_QUEUE_CAPACITY,
+            slow_consumer_policy: SlowConsumerPolicy::default(),
+            outbound: None,
+            state: SessionState::PreAuth,
+        }
+    }
+
+    /// Override the idle timeout (defaults to READ_TIMEOUT)
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> Self {
+        self.read_timeout = read_timeout;
+        self
+    }
+
+    /// Override the ping interval and pong timeout
+    pub fn with_heartbeat(mut self, heartbeat: HeartbeatConfig) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Override whether compression is accepted, and from what size
+    pub fn with_compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Override the send queue's capacity and what happens when it is full
+    pub fn with_send_queue(mut self, capacity: usize, policy: SlowConsumerPolicy) -> Self {
+        self.send_queue_capacity = capacity;
+        self.slow_consumer_policy = policy;
+        self
+    }
+
+    /// The current state
+    pub fn state(&self) -> &SessionState {
+        &self.state
+    }
+
+    /// Unique id of this connection
+    pub fn connection_id(&self) -> u64 {
+        self.connection_id
+    }
+
+    /// Encoding negotiated during auth (JSON until then)
+    pub fn encoding(&self) -> Encoding {
+        self.codec.encoding
+    }
+
+    /// Compression negotiated during auth (none until then)
+    pub fn compression(&self) -> Compression {
+        self.codec.compression
+    }
+
+    /// Time left before the connection counts as idle
+    pub fn time_until_idle(&self) -> Duration {
+        (self.last_activity + self.read_timeout).saturating_duration_since(self.clock.now())
+    }
+
+    /// Time left before on_heartbeat has work to do
+    ///
+    /// Duration::MAX unless the user is authenticated.
+    pub fn time_until_heartbeat(&self) -> Duration {
+        if !matches!(self.state, SessionState::Authenticated { .. }) {
+            return Duration::MAX;
+        }
+        let due = match self.ping_sent_at {
+            Some(sent_at) => sent_at + self.heartbeat.timeout,
+            None => self.last_activity + self.heartbeat.interval,
+        };
+        due.saturating_duration_since(self.clock.now())
+    }
+
+    /// Send a ping if the connection has been quiet, or close it if the last
+    /// ping went unanswered
+    ///
+    /// # Returns
+    /// A ping frame to write to the socket, if one is due
+    pub fn on_heartbeat(&mut self) -> Option<Message> {
+        if !self.time_until_heartbeat().is_zero() {
+            return None;
+        }
+        if self.ping_sent_at.is_some() {
+            tracing::info!(
+                "User {} missed heartbeat, dropping connection",
+                self.user_label()
+            );
+            self.close(CloseReason::HeartbeatTimeout);
+            return None;
+        }
+        self.ping_sent_at = Some(self.clock.now());
+        Some(Message::Ping(self.connection_id.to_be_bytes().to_vec()))
+    }
+
+    /// Handle one frame (or read error) from the socket
+    ///
+    /// # Returns
+    /// Frames to write back to the socket, in order
+    pub async fn on_frame(
+        &mut self,
+        frame: Result<Message, WsError>,
+    ) -> Result<Vec<Message>, serde_json::Error> {
+        // Any frame (normally the pong) proves the client is still there
+        self.last_activity = self.clock.now();
+        self.ping_sent_at = None;
+
+        match &self.state {
+            SessionState::PreAuth => match frame {
+                Ok(message) => self.authenticate(&message).await,
+                Err(WsError::Capacity(error)) => self.on_oversized_frame(&error),
+                Err(e) => {
+                    tracing::error!("WebSocket error before authentication: {}", e);
+                    self.close(CloseReason::ReadError(e.to_string()));
+                    Ok(Vec::new())
+                }
+            },
+            SessionState::Authenticated { public_key } => {
+                let public_key = public_key.clone();
+                if self.lobby.moderation.take_kicked(self.connection_id).await {
+                    return Ok(self.on_kicked(&public_key));
+                }
+                if let Err(WsError::Capacity(error)) = &frame {
+                    return self
+                        .on_oversized_frame(error)
+                        .map(|frames| self.encode_frames(frames));
+                }
+                self.on_authenticated_frame(&public_key, frame).await;
+                Ok(Vec::new())
+            }
+            SessionState::Closing { .. } => Ok(Vec::new()),
+        }
+    }
+
+    /// Take the send queue to drain, once the user is in the lobby
+    pub fn take_outbound(&mut self) -> Option<OutboundQueue> {
+        self.outbound.take()
+    }
+
+    /// Encoding and compression negotiated during auth, for encoding
+    /// messages from the send queue
+    pub fn codec(&self) -> FrameCodec {
+        self.codec
+    }
+
+    /// Close a connection whose send queue overflowed
+    ///
+    /// # Returns
+    /// The close frame to write to the socket
+    pub fn on_slow_consumer(&mut self) -> Vec<Message> {
+        if self.state.is_closing() {
+            return Vec::new();
+        }
+        tracing::warn!(
+            "User {} is reading too slowly, closing connection",
+            self.user_label()
+        );
+        self.close(CloseReason::SlowConsumer);
+        vec![Message::Close(Some(CloseFrame {
+            code: CloseCode::Policy,
+            reason: "slow_consumer".into(),
+        }))]
+    }
+
+    /// Close the connection because the server is shutting down
+    ///
+    /// The close frame asks the client to reconnect after a jittered
+    /// delay, shorter if the user sent something in the last
+    /// `RECENT_ACTIVITY`, so reconnections after the restart spread out and
+    /// active users come back first.
+    ///
+    /// # Returns
+    /// The close frame to write to the socket
+    pub fn on_shutdown(&mut self, rng: &mut impl Rng) -> Vec<Message> {
+        if self.state.is_closing() {
+            return Vec::new();
+        }
+        let retry_after = shutdown_retry_hint(self.is_recently_active(), rng);
+        self.close(CloseReason::ServerShutdown { retry_after });
+        vec![Message::Close(Some(CloseFrame {
+            code: CloseCode::Away,
+            reason: close_reason_with_retry(SERVER_SHUTDOWN_REASON, retry_after).into(),
+        }))]
+    }
+
+    /// Whether the user sent a message or request in the last
+    /// `RECENT_ACTIVITY`
+    pub fn is_recently_active(&self) -> bool {
+        self.last_message
+            .is_some_and(|at| self.clock.now().saturating_duration_since(at) < RECENT_ACTIVITY)
+    }
+
+    /// Handle the socket stream ending
+    pub fn on_stream_end(&mut self) {
+        if !self.state.is_closing() {
+            tracing::info!(
+                "User {} disconnected (timeout or stream closed)",
+                self.user_label()
+            );
+            self.close(CloseReason::StreamEnded);
+        }
+    }
+
+    /// Handle a failed write to the socket
+    ///
+    /// The client can no longer be reached, so the user is cleaned up like
+    /// any other disconnect.
+    pub fn on_write_error(&mut self, error: &str) {
+        if !self.state.is_closing() {
+            tracing::warn!("Write to user {} failed: {}", self.user_label(), error);
+            self.close(CloseReason::WriteError(error.to_string()));
+        }
+    }
+
+    /// Close the connection if the read timeout has passed
+    ///
+    /// # Returns
+    /// true if the connection is now closing because it was idle
+    pub fn on_idle(&mut self) -> bool {
+        if self.state.is_closing() || !self.time_until_idle().is_zero() {
+            return false;
+        }
+        tracing::info!(
+            "User {} disconnected (timeout or stream closed)",
+            self.user_label()
+        );
+        self.close(CloseReason::IdleTimeout);
+        true
+    }
+
+    /// Remove a closing user from rooms and the lobby
+    ///
+    /// Cleanup failures after a timeout or stream end are only logged,
+    /// since the client is gone either way.
+    ///
+    /// # Returns
+    /// Err(LobbyError) if the user may still be visible to others
+    pub async fn finish(&mut self) -> Result<(), LobbyError> {
+        let SessionState::Closing { public_key, reason } = &mut self.state else {
+            return Ok(());
+        };
+        let Some(key) = public_key.take() else {
+            return Ok(());
+        };
+
+        let result = cleanup_user_from_lobby(&self.lobby, &self.rooms, &key).await;
+        match reason {
+            CloseReason::IdleTimeout
+            | CloseReason::HeartbeatTimeout
+            | CloseReason::StreamEnded
+            | CloseReason::WriteError(_) => Ok(()),
+            _ => result,
+        }
+    }
+
+    /// PreAuth stage: verify the auth message and join the lobby
+    async fn authenticate(&mut self, message: &Message) -> Result<Vec<Message>, serde_json::Error> {
+        let client_id = self.connection_id.to_string();
+        let resuming = matches!(message, Message::Text(text) if message_type(text).is_some_and(|kind| is_resume_message(&kind)));
+        let (public_key, ephemeral, share_last_seen) =
+            match handle_auth_message(message, &self.lobby, &self.rate_limiter, &client_id).await {
+                AuthResult::Success {
+                    public_key,
+                    encoding,
+                    compression,
+                    ephemeral,
+                    share_last_seen,
+                    ..
+                } => {
+                    let compression = if self.compression.enabled {
+                        compression
+                    } else {
+                        Compression::None
+                    };
+                    self.codec = FrameCodec::new(encoding)
+                        .with_compression(compression, self.compression.threshold);
+                    (
+                        hex::encode(public_key.as_slice()),
+                        ephemeral,
+                        share_last_seen,
+                    )
+                }
+                AuthResult::Failure { reason, details } => {
+                    self.lobby.audit.record(AuditEvent::AuthFailed {
+                        connection_id: self.connection_id,
+                        reason: reason.clone(),
+                        public_key: match message {
+                            Message::Text(text) => claimed_public_key(text),
+                            _ => None,
+                        },
+                    });
+                    let code = if reason == "banned" {
+                        CloseCode::Policy
+                    } else {
+                        CloseCode::Normal
+                    };
+                    let frames = refusal_frames(&reason, details, code, &reason)?;
+                    self.close(CloseReason::AuthRejected { reason });
+                    return Ok(frames);
+                }
+            };
+
+        crate::logging::record_public_key(&public_key);
+
+        // Users who were online before a restart or are resuming a session
+        // are admitted first
+        let priority = resuming || self.lobby.presence.is_returning(&public_key).await;
+        match self
+            .lobby
+            .admission
+            .reserve(priority, self.clock.now())
+            .await
+        {
+            Ok(wait) if !wait.is_zero() => tokio::time::sleep(wait).await,
+            Ok(_) => {}
+            Err(backlog) => {
+                let retry_after = refusal_retry_hint(backlog, &mut rand::thread_rng());
+                tracing::info!(
+                    retry_after_ms = retry_after.as_millis() as u64,
+                    "Admission queue full, refusing connection"
+                );
+                self.close(CloseReason::AdmissionRefused { retry_after });
+                return refusal_frames(
+                    SERVER_BUSY_REASON,
+                    format!(
+                        "Too many connections are waiting to join. Retry after {} ms.",
+                        retry_after.as_millis()
+                    ),
+                    CloseCode::Again,
+                    &close_reason_with_retry(SERVER_BUSY_REASON, retry_after),
+                );
+            }
+        }
+
+        let (sender, outbound) =
+            send_queue_with(self.send_queue_capacity, self.slow_consumer_policy);
+        let sender = sender.with_metrics(Arc::clone(&self.lobby.send_queues));
+        let connection = ActiveConnection {
+            public_key: public_key.clone(),
+            sender,
+            connection_id: self.connection_id,
+            status: UserStatus::default(),
+        };
+
+        // Tagged before joining so the join broadcast carries the tag
+        self.lobby.ephemeral.set(&public_key, ephemeral).await;
+        self.lobby
+            .last_seen
+            .set_sharing(&public_key, share_last_seen)
+            .await;
+        // SECURITY: Only add to lobby after successful authentication
+        // If this fails, we should NOT send auth success - user is not in lobby
+        if let Err(e) = crate::lobby::add_user(&self.lobby, public_key.clone(), connection).await {
+            tracing::error!("Failed to add user to lobby: {}", e);
+            self.close(CloseReason::LobbyRejected);
+            return refusal_frames(
+                "lobby_error",
+                "Unable to join lobby. Please try again.".to_string(),
+                CloseCode::Policy,
+                "Failed to join lobby",
+            );
+        }
+
+        // Fetch lobby state AFTER adding the user so it includes themselves.
+        // Only the first page is sent; clients fetch the rest with lobby_page.
+        let success_msg = match self
+            .lobby
+            .get_page(None, profile_shared::config::lobby::MAX_PAGE_SIZE, None)
+            .await
+        {
+            Ok(page) => {
+                let nicknames = self.lobby.nicknames.lookup(&page.users).await;
+                let names = self.lobby.names.lookup(&page.users).await;
+                let guests = self.lobby.ephemeral.lookup(&page.users).await;
+                let statuses = self.lobby.statuses(&page.users).await;
+                AuthSuccessMessage::from(page)
+                    .with_nicknames(nicknames)
+                    .with_names(names)
+                    .with_ephemeral(guests)
+                    .with_statuses(statuses)
+            }
+            Err(_) => AuthSuccessMessage::new(vec![]),
+        };
+        // A user who reconnected while invisible is still invisible
+        let own_status = match self.lobby.users.get(&public_key).await {
+            Some(conn) => conn.status.get(),
+            None => Default::default(),
+        };
+        let success_msg = success_msg.with_status(own_status);
+        // auth_success is still JSON; the client switches encoding after
+        // reading it. It may already be compressed, since the client offered.
+        let resume_token = self
+            .lobby
+            .resume_tokens
+            .issue(&public_key, SystemTime::now());
+        let success_msg = success_msg
+            .with_encoding(self.codec.encoding)
+            .with_compression(self.codec.compression)
+            .with_resume_token(resume_token);
+        let auth_codec = FrameCodec {
+            encoding: Encoding::Json,
+            ..self.codec
+        };
+        let success_json = serde_json::to_string(&success_msg)?;
+        let mut frames = vec![to_message(auth_codec.encode_json(&success_json))
+            .unwrap_or(Message::Text(success_json))];
+        // Users who were online before a restart are told to refresh
+        if let Some(notice) = self.lobby.presence.take_restart_notice(&public_key).await {
+            frames.extend(self.encode_frames(vec![Message::Text(serde_json::to_string(&notice)?)]));
+        }
+
+        self.lobby.audit.record(AuditEvent::AuthSucceeded {
+            connection_id: self.connection_id,
+            public_key: public_key.clone(),
+            resumed: resuming,
+        });
+        self.outbound = Some(outbound);
+        self.state = SessionState::Authenticated { public_key };
+        Ok(frames)
+    }
+
+    /// Authenticated stage: route text frames and watch for disconnects
+    async fn on_authenticated_frame(&mut self, sender_key: &str, frame: Result<Message, WsError>) {
+        if matches!(frame, Ok(Message::Text(_) | Message::Binary(_))) {
+            self.last_message = Some(self.clock.now());
+        }
+        match frame {
+            Ok(Message::Text(text)) => self.on_text(sender_key, &text).await,
+            Ok(Message::Binary(data)) if self.codec.accepts_binary() => {
+                match self.codec.decode_binary(&data) {
+                    Ok(text) => self.on_text(sender_key, &text).await,
+                    Err(e) => {
+                        tracing::debug!(sender = %sender_key, error = %e, "Undecodable binary frame");
+                        let reason = ValidationError::MalformedJson {
+                            details: e.to_string(),
+                        };
+                        self.reply_error(sender_key, &reason).await;
+                    }
+                }
+            }
+            Ok(Message::Close(_frame)) => {
+                tracing::info!(
+                    "User {} disconnected, broadcasting leave notification",
+                    sender_key
+                );
+                self.close(CloseReason::ClientClosed);
+            }
+            Err(e) => {
+                // WebSocket read errors could be network flakiness, malformed
+                // frames, etc., not necessarily a clean disconnection
+                tracing::error!("WebSocket error for user {}: {}", sender_key, e);
+                self.close(CloseReason::ReadError(e.to_string()));
+            }
+            Ok(other) => {
+                // Binary, ping, pong, etc. are normal WebSocket events
+                tracing::debug!(
+                    "Received non-text, non-close message type for user {}: {}",
+                    sender_key,
+                    other
+                );
+            }
+        }
+    }
+
+    /// Revoke the user's resume tokens and close the connection
+    async fn on_logout(&mut self, sender_key: &str) {
+        self.lobby
+            .resume_tokens
+            .revoke(sender_key, SystemTime::now())
+            .await;
+        tracing::info!(
+            "User {} logged out, revoked their resume tokens",
+            sender_key
+        );
+        self.close(CloseReason::LoggedOut);
+    }
+
+    /// Handle a text frame from an authenticated user (Story 3.2 + 3.3)
+    async fn on_text(&mut self, sender_key: &str, text: &str) {
+        // Parse the type once and dispatch on it; the handler that takes the
+        // frame does the full parse
+        let kind = message_type(text);
+        let kind = kind.as_deref().unwrap_or_default();
+
+        // Cover traffic only exists to be seen on the wire
+        if is_cover_frame(kind) {
+            tracing::trace!(sender = %sender_key, bytes = text.len(), "Dropped cover frame");
+            return;
+        }
+
+        // Sealed messages, edits, retractions, reactions, attachments, read
+        // receipts, viewing hints, lobby pages, nicknames, statuses, last-seen
+        // queries, name claims, key rollovers, room, backup and filter
+        // requests have their own handlers
+        let side_result = match kind {
+            kind if is_logout_request(kind) => {
+                self.on_logout(sender_key).await;
+                return;
+            }
+            kind if is_sealed_request(kind) => {
+                Some(handle_sealed_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_edit_message_request(kind) => {
+                Some(handle_edit_message_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_retract_message_request(kind) => {
+                Some(handle_retract_message_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_reaction_request(kind) => {
+                Some(handle_reaction_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_attachment_request(kind) => {
+                Some(handle_attachment_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_read_receipt(kind) => {
+                Some(handle_read_receipt(&self.lobby, sender_key, text).await)
+            }
+            kind if is_viewing_request(kind) => {
+                Some(handle_viewing_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_lobby_page_request(kind) => {
+                Some(handle_lobby_page_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_set_nickname_request(kind) => {
+                Some(handle_set_nickname_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_set_status_request(kind) => {
+                Some(handle_set_status_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_query_last_seen_request(kind) => {
+                Some(handle_query_last_seen_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_claim_name_request(kind) => {
+                Some(handle_claim_name_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_key_rollover_request(kind) => {
+                Some(handle_key_rollover_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_room_request(kind) => {
+                Some(handle_room_request(&self.rooms, &self.lobby, sender_key, text).await)
+            }
+            kind if is_backup_request(kind) => {
+                Some(handle_backup_request(&self.lobby, sender_key, text).await)
+            }
+            kind if is_filter_request(kind) => {
+                Some(handle_filter_request(&self.lobby, sender_key, text).await)
+            }
+            _ => None,
+        };
+        if let Some(result) = side_result {
+            if let Err(reason) = result {
+                tracing::debug!(sender = %sender_key, ?reason, "Request rejected");
+                self.reply_error(sender_key, &reason).await;
+            }
+            return;
+        }
+
+        // Validate the message (Story 3.2)
+        tracing::debug!(sender = %sender_key, "Received message, validating and routing...");
+        let validation_result = handle_incoming_message(&self.lobby, sender_key, text).await;
+
+        match validation_result {
+            MessageValidationResult::Valid { .. } => {
+                // Message is valid, route to recipient (Story 3.3)
+                match route_message(&self.lobby, &validation_result).await {
+                    Ok(()) => tracing::debug!("Message routed successfully"),
+                    // AC7: Log failed delivery but don't return error to sender
+                    Err(e) => tracing::warn!("Message delivery failed: {}", e),
+                }
+            }
+            MessageValidationResult::Invalid { reason } => {
+                tracing::debug!(sender = %sender_key, ?reason, "Message validation failed");
+                self.reply_error(sender_key, &reason).await;
+            }
+        }
+    }
+
+    /// Send a validation error to the user through their lobby connection
+    async fn reply_error(&self, sender_key: &str, reason: &ValidationError) {
+        if let Ok(Some(sender_conn)) = crate::lobby::get_user(&self.lobby, sender_key).await {
+            let _ = sender_conn.sender.send(validation_error_message(reason));
+        }
+    }
+
+    /// Re-encode JSON text frames in the negotiated encoding, compressing
+    /// the large ones if compression was negotiated
+    fn encode_frames(&self, frames: Vec<Message>) -> Vec<Message> {
+        frames
+            .into_iter()
+            .map(|frame| match frame {
+                Message::Text(text) => encode_text(&self.codec, text),
+                other => other,
+            })
+            .collect()
+    }
+
+    /// Close a connection the admin API kicked
+    ///
+    /// The admin API already removed the user from rooms and the lobby, so
+    /// there is nothing left for [`Self::finish`] to clean up. Cleaning up
+    /// again could remove a newer connection for the same key.
+    fn on_kicked(&mut self, public_key: &str) -> Vec<Message> {
+        tracing::info!("User {} was kicked, closing connection", public_key);
+        self.state = SessionState::Closing {
+            public_key: None,
+            reason: CloseReason::Kicked,
+        };
+        vec![Message::Close(Some(CloseFrame {
+            code: CloseCode::Policy,
+            reason: "kicked".into(),
+        }))]
+    }
+
+    /// Refuse a frame over MAX_FRAME_SIZE and close the connection
+    ///
+    /// The rest of the frame was never read, so the stream can't be trusted
+    /// to line up with the next one.
+    fn on_oversized_frame(
+        &mut self,
+        error: &CapacityError,
+    ) -> Result<Vec<Message>, serde_json::Error> {
+        tracing::warn!("Oversized frame from {}: {}", self.user_label(), error);
+        self.close(CloseReason::ReadError(error.to_string()));
+        refusal_frames(
+            "message_too_large",
+            format!("Messages are limited to {} bytes", MAX_FRAME_SIZE),
+            CloseCode::Size,
+            "message_too_large",
+        )
+    }
+
+    fn close(&mut self, reason: CloseReason) {
+        let public_key = match &self.state {
+            SessionState::Authenticated { public_key } => Some(public_key.clone()),
+            _ => None,
+        };
+        self.state = SessionState::Closing { public_key, reason };
+    }
+
+    fn user_label(&self) -> String {
+        self.state
+            .public_key()
+            .map(str::to_string)
+            .unwrap_or_else(|| "unauthenticated".to_string())
+    }
+}
+
+async fn cleanup_user_from_lobby(
+    lobby: &Arc<Lobby>,
+    rooms: &Arc<Rooms>,
+    key_hex: &str,
+) -> Result<(), LobbyError> {
+    // Leave all rooms first so remaining members are notified while the
+    // user's lobby entry still resolves
+    crate::rooms::remove_user_from_rooms(rooms, lobby, key_hex).await;
+
+    match crate::lobby::remove_user(lobby, key_hex).await {
+        Ok(()) => {
+            tracing::debug!(
+                "User {}... removed from lobby successfully",
+                truncate_key(key_hex)
+            );
+            Ok(())
+        }
+        Err(LobbyError::LockFailed) => {
+            tracing::error!(
+                "CRITICAL: Failed to acquire lobby lock for user {}... removal. \
+                 User may remain visible to others incorrectly.",
+                truncate_key(key_hex)
+            );
+            Err(LobbyError::LockFailed)
+        }
+        Err(LobbyError::BroadcastFailed) => {
+            // The user is gone; only the leave notification was lost
+            tracing::warn!(
+                "User {}... removed from lobby but leave notification failed to broadcast",
+                truncate_key(key_hex)
+            );
+            Ok(())
+        }
+        Err(e) => {
+            tracing::error!(
+                "Failed to remove user {}... from lobby: {}",
+                truncate_key(key_hex),
+                e
+            );
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::send_queue;
+    use profile_shared::{derive_public_key, generate_private_key, sign_message};
+    use std::sync::Mutex;
+
+    /// Clock that only moves when the test advances it
+    #[derive(Clone)]
+    struct ManualClock(Arc<Mutex<Instant>>);
+
+    impl ManualClock {
+        fn new() -> Self {
+            Self(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn session(lobby: &Arc<Lobby>, clock: ManualClock) -> ConnectionSession<ManualClock> {
+        ConnectionSession::new(
+            lobby.clone(),
+            Arc::new(Rooms::new()),
+            Arc::new(AuthRateLimiter::new()),
+            clock,
+            7,
+        )
+    }
+
+    fn valid_auth_frame() -> (String, Message) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let signature = hex::encode(sign_message(&private_key, b"auth").unwrap());
+        let json = serde_json::json!({
+            "type": "auth",
+            "publicKey": public_key,
+            "signature": signature,
+        });
+        (public_key, Message::Text(json.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_invalid_auth_is_refused_and_closes() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session = session(&lobby, ManualClock::new());
+
+        let frames = session
+            .on_frame(Ok(Message::Text("not json".to_string())))
+            .await
+            .unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert!(matches!(&frames[0], Message::Text(text) if text.contains("auth_failed")));
+        assert!(matches!(frames[1], Message::Close(Some(_))));
+        assert_eq!(
+            session.state(),
+            &SessionState::Closing {
+                public_key: None,
+                reason: CloseReason::AuthRejected {
+                    reason: "auth_failed".to_string()
+                },
+            }
+        );
+        assert!(lobby.users.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_auth_outcomes_are_audited() {
+        use crate::audit::{read_audit_log, AuditLog, AuditLogConfig};
+
+        let dir =
+            std::env::temp_dir().join(format!("profile-session-audit-{}", std::process::id()));
+        let path = dir.join("audit.jsonl");
+        let _ = std::fs::remove_dir_all(&dir);
+        let audit = AuditLog::open(AuditLogConfig::new(&path)).unwrap();
+        let lobby = Arc::new(Lobby::new().with_audit_log(audit));
+
+        let (public_key, frame) = valid_auth_frame();
+        let forged = serde_json::json!({
+            "type": "auth",
+            "publicKey": public_key,
+            "signature": "00".repeat(64),
+        });
+        session(&lobby, ManualClock::new())
+            .on_frame(Ok(Message::Text(forged.to_string())))
+            .await
+            .unwrap();
+        session(&lobby, ManualClock::new())
+            .on_frame(Ok(frame))
+            .await
+            .unwrap();
+
+        let events: Vec<AuditEvent> = read_audit_log(&path)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.event)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                AuditEvent::AuthFailed {
+                    connection_id: 7,
+                    reason: "auth_failed".to_string(),
+                    public_key: Some(public_key.clone()),
+                },
+                AuditEvent::AuthSucceeded {
+                    connection_id: 7,
+                    public_key,
+                    resumed: false,
+                },
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_valid_auth_joins_lobby() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session = session(&lobby, ManualClock::new());
+        let (public_key, frame) = valid_auth_frame();
+
+        let frames = session.on_frame(Ok(frame)).await.unwrap();
+
+        assert_eq!(
+            session.state(),
+            &SessionState::Authenticated {
+                public_key: public_key.clone()
+            }
+        );
+        match &frames[..] {
+            [Message::Text(text)] => {
+                let success: AuthSuccessMessage = serde_json::from_str(text).unwrap();
+                assert_eq!(success.users, vec![public_key.clone()]);
+                assert!(!text.contains("encoding"));
+            }
+            other => panic!("Expected auth success, got {:?}", other),
+        }
+        assert!(lobby.user_exists(&public_key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_resume_token_restores_session_until_logout() {
+        let lobby = Arc::new(Lobby::new());
+        let mut first = session(&lobby, ManualClock::new());
+        let (public_key, frame) = valid_auth_frame();
+        let frames = first.on_frame(Ok(frame)).await.unwrap();
+        let Message::Text(text) = &frames[0] else {
+            panic!("Expected auth success, got {:?}", frames);
+        };
+        let success: AuthSuccessMessage = serde_json::from_str(text).unwrap();
+        let token = success.resume_token.unwrap();
+        first.on_frame(Ok(Message::Close(None))).await.unwrap();
+        first.finish().await.unwrap();
+        assert!(!lobby.user_exists(&public_key).await.unwrap());
+
+        // Reconnect with the token instead of a signature
+        let resume = serde_json::to_string(&ResumeMessage::new(token.clone())).unwrap();
+        let mut second = session(&lobby, ManualClock::new());
+        let frames = second.on_frame(Ok(Message::Text(resume))).await.unwrap();
+        assert_eq!(
+            second.state(),
+            &SessionState::Authenticated {
+                public_key: public_key.clone()
+            }
+        );
+        assert!(lobby.user_exists(&public_key).await.unwrap());
+        let Message::Text(text) = &frames[0] else {
+            panic!("Expected auth success, got {:?}", frames);
+        };
+        let success: AuthSuccessMessage = serde_json::from_str(text).unwrap();
+        let next_token = success.resume_token.unwrap();
+        assert_ne!(next_token, token);
+
+        // Logging out revokes the fresh token too
+        second
+            .on_frame(Ok(Message::Text(r#"{"type":"logout"}"#.to_string())))
+            .await
+            .unwrap();
+        assert!(matches!(
+            second.state(),
+            SessionState::Closing {
+                reason: CloseReason::LoggedOut,
+                ..
+            }
+        ));
+        second.finish().await.unwrap();
+
+        let resume = serde_json::to_string(&ResumeMessage::new(next_token)).unwrap();
+        let mut third = session(&lobby, ManualClock::new());
+        let frames = third.on_frame(Ok(Message::Text(resume))).await.unwrap();
+        assert!(matches!(&frames[0], Message::Text(text) if text.contains("resume_failed")));
+        assert!(third.state().is_closing());
+    }
+
+    #[tokio::test]
+    async fn test_user_online_before_restart_is_told_after_auth() {
+        let (public_key, frame) = valid_auth_frame();
+        let path = std::env::temp_dir().join(format!(
+            "profile-session-presence-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            serde_json::json!({ "epoch": 4, "connected": [public_key] }).to_string(),
+        )
+        .unwrap();
+        let presence = crate::presence::PresenceStore::load(&path, chrono::Utc::now()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lobby = Arc::new(Lobby::new().with_presence(Arc::new(presence)));
+        let mut session = session(&lobby, ManualClock::new());
+
+        let frames = session.on_frame(Ok(frame)).await.unwrap();
+
+        match &frames[..] {
+            [Message::Text(success), Message::Text(notice)] => {
+                assert!(success.contains("auth_success"));
+                match serde_json::from_str(notice).unwrap() {
+                    profile_shared::Message::ServerRestarted { epoch, .. } => assert_eq!(epoch, 5),
+                    other => panic!("Expected ServerRestarted, got {:?}", other),
+                }
+            }
+            other => panic!("Expected auth success and notice, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_negotiated_msgpack_frames_are_decoded_and_encoded() {
+        let lobby = Arc::new(Lobby::new());
+        let rooms = Arc::new(Rooms::new());
+        let mut session = ConnectionSession::new(
+            lobby.clone(),
+            rooms.clone(),
+            Arc::new(AuthRateLimiter::new()),
+            ManualClock::new(),
+            7,
+        );
+        let (public_key, Message::Text(auth)) = valid_auth_frame() else {
+            unreachable!()
+        };
+        let mut auth: serde_json::Value = serde_json::from_str(&auth).unwrap();
+        auth["encodings"] = serde_json::json!(["cbor", "msgpack"]);
+
+        let frames = session
+            .on_frame(Ok(Message::Text(auth.to_string())))
+            .await
+            .unwrap();
+
+        assert_eq!(session.encoding(), Encoding::MessagePack);
+        match &frames[..] {
+            [Message::Text(text)] => {
+                let success: AuthSuccessMessage = serde_json::from_str(text).unwrap();
+                assert_eq!(success.encoding.as_deref(), Some("msgpack"));
+            }
+            other => panic!("Expected JSON auth success, got {:?}", other),
+        }
+
+        let request = Encoding::MessagePack
+            .encode_json(r#"{"type":"room_create","room":"general"}"#)
+            .unwrap();
+        session
+            .on_frame(Ok(Message::Binary(request)))
+            .await
+            .unwrap();
+        assert!(rooms.is_member("general", &public_key).await);
+
+        let error = WsError::Capacity(CapacityError::MessageTooLong {
+            size: MAX_FRAME_SIZE + 1,
+            max_size: MAX_FRAME_SIZE,
+        });
+        let frames = session.on_frame(Err(error)).await.unwrap();
+        let Message::Binary(data) = &frames[0] else {
+            panic!("Expected a binary error frame, got {:?}", frames[0]);
+        };
+        let refusal = Encoding::MessagePack.decode_json(data).unwrap();
+        assert!(refusal.contains("message_too_large"));
+    }
+
+    #[tokio::test]
+    async fn test_negotiated_compression_deflates_large_frames() {
+        let lobby = Arc::new(Lobby::new());
+        let rooms = Arc::new(Rooms::new());
+        let codec = FrameCodec::default().with_compression(Compression::Deflate, 0);
+        let (public_key, Message::Text(auth)) = valid_auth_frame() else {
+            unreachable!()
+        };
+        let mut auth: serde_json::Value = serde_json::from_str(&auth).unwrap();
+        auth["compression"] = serde_json::json!(["deflate"]);
+
+        // Refused when the server has compression turned off
+        let mut plain = session(&Arc::new(Lobby::new()), ManualClock::new())
+            .with_compression(CompressionConfig::disabled());
+        let frames = plain
+            .on_frame(Ok(Message::Text(auth.to_string())))
+            .await
+            .unwrap();
+        assert_eq!(plain.compression(), Compression::None);
+        assert!(matches!(&frames[..], [Message::Text(text)] if !text.contains("compression")));
+
+        let mut session = ConnectionSession::new(
+            lobby.clone(),
+            rooms.clone(),
+            Arc::new(AuthRateLimiter::new()),
+            ManualClock::new(),
+            8,
+        )
+        .with_compression(CompressionConfig {
+            enabled: true,
+            threshold: 0,
+        });
+        let frames = session
+            .on_frame(Ok(Message::Text(auth.to_string())))
+            .await
+            .unwrap();
+
+        assert_eq!(session.compression(), Compression::Deflate);
+        assert_eq!(session.encoding(), Encoding::Json);
+        let [Message::Binary(data)] = &frames[..] else {
+            panic!("Expected compressed auth success, got {:?}", frames);
+        };
+        let success: AuthSuccessMessage =
+            serde_json::from_str(&codec.decode_binary(data).unwrap()).unwrap();
+        assert_eq!(success.compression.as_deref(), Some("deflate"));
+        assert_eq!(success.users, vec![public_key.clone()]);
+
+        let Frame::Binary(request) = codec
+            .encode_json(r#"{"type":"room_create","room":"general"}"#)
+            .unwrap()
+        else {
+            unreachable!()
+        };
+        session
+            .on_frame(Ok(Message::Binary(request)))
+            .await
+            .unwrap();
+        assert!(rooms.is_member("general", &public_key).await);
+    }
+
+    #[tokio::test]
+    async fn test_banned_key_is_refused() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session = session(&lobby, ManualClock::new());
+        let (public_key, frame) = valid_auth_frame();
+        lobby
+            .moderation
+            .ban(&public_key, None, chrono::Utc::now())
+            .await
+            .unwrap();
+
+        let frames = session.on_frame(Ok(frame)).await.unwrap();
+
+        assert!(matches!(&frames[0], Message::Text(text) if text.contains("banned")));
+        assert!(matches!(frames[1], Message::Close(Some(_))));
+        assert!(session.state().is_closing());
+        assert!(lobby.users.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_kicked_session_closes_without_cleanup() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session = session(&lobby, ManualClock::new());
+        let (public_key, frame) = valid_auth_frame();
+        session.on_frame(Ok(frame)).await.unwrap();
+
+        crate::admin::commands::execute(
+            &lobby,
+            &Rooms::new(),
+            crate::admin::AdminCommand::Kick {
+                public_key: public_key.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        let frames = session
+            .on_frame(Ok(Message::Text("{}".to_string())))
+            .await
+            .unwrap();
+
+        assert!(matches!(&frames[..], [Message::Close(Some(_))]));
+        assert_eq!(
+            session.state(),
+            &SessionState::Closing {
+                public_key: None,
+                reason: CloseReason::Kicked,
+            }
+        );
+        assert!(!lobby.user_exists(&public_key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_send_queue_overflow_closes_slow_consumer() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session =
+            session(&lobby, ManualClock::new()).with_send_queue(2, SlowConsumerPolicy::Disconnect);
+        assert!(session.take_outbound().is_none());
+        let (public_key, frame) = valid_auth_frame();
+        session.on_frame(Ok(frame)).await.unwrap();
+        let mut outbound = session.take_outbound().unwrap();
+
+        let conn = lobby.users.get(&public_key).await.unwrap();
+        let notice = profile_shared::Message::new_error("test".to_string(), None);
+        conn.sender.send(notice.clone()).unwrap();
+        assert_eq!(outbound.try_recv(), Ok(notice.clone()));
+
+        // A client that stops reading overflows the queue and is closed
+        for _ in 0..3 {
+            let _ = conn.sender.send(notice.clone());
+        }
+        assert!(outbound.is_slow_consumer());
+        let frames = session.on_slow_consumer();
+        assert!(matches!(
+            &frames[..],
+            [Message::Close(Some(frame))] if frame.reason == "slow_consumer"
+        ));
+        assert_eq!(
+            session.state(),
+            &SessionState::Closing {
+                public_key: Some(public_key.clone()),
+                reason: CloseReason::SlowConsumer,
+            }
+        );
+        session.finish().await.unwrap();
+        assert!(!lobby.user_exists(&public_key).await.unwrap());
+        assert_eq!(lobby.send_queues.dropped(), 1);
+        assert_eq!(lobby.send_queues.slow_consumers(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_refused_and_closed() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session = session(&lobby, ManualClock::new());
+        let (public_key, frame) = valid_auth_frame();
+        session.on_frame(Ok(frame)).await.unwrap();
+
+        let error = WsError::Capacity(CapacityError::MessageTooLong {
+            size: MAX_FRAME_SIZE + 1,
+            max_size: MAX_FRAME_SIZE,
+        });
+        let frames = session.on_frame(Err(error)).await.unwrap();
+
+        assert!(matches!(&frames[0], Message::Text(text) if text.contains("message_too_large")));
+        assert!(matches!(
+            &frames[1],
+            Message::Close(Some(close)) if close.code == CloseCode::Size
+        ));
+        // The user is still cleaned up when the session finishes
+        assert_eq!(session.state().public_key(), Some(public_key.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_close_frame_then_finish_removes_user() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session = session(&lobby, ManualClock::new());
+        let (public_key, frame) = valid_auth_frame();
+        session.on_frame(Ok(frame)).await.unwrap();
+
+        session.on_frame(Ok(Message::Close(None))).await.unwrap();
+        assert!(session.state().is_closing());
+        assert_eq!(session.state().public_key(), Some(public_key.as_str()));
+
+        session.finish().await.unwrap();
+        assert!(!lobby.user_exists(&public_key).await.unwrap());
+        assert_eq!(session.state().public_key(), None);
+    }
+
+    /// Retry hint from the close frame at the end of `frames`
+    fn close_retry_hint(frames: &[Message]) -> (CloseCode, String, Duration) {
+        match frames.last() {
+            Some(Message::Close(Some(frame))) => {
+                let (reason, hint) = profile_shared::protocol::split_retry_hint(&frame.reason);
+                (frame.code, reason.to_string(), hint.unwrap())
+            }
+            other => panic!("Expected close frame, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_asks_active_users_back_sooner() {
+        use profile_shared::config::reconnect::{
+            ACTIVE_RETRY_MAX, ACTIVE_RETRY_MIN, IDLE_RETRY_MIN,
+        };
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let lobby = Arc::new(Lobby::new());
+        let clock = ManualClock::new();
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut active = session(&lobby, clock.clone());
+        let mut idle = session(&lobby, clock.clone());
+        active.on_frame(Ok(valid_auth_frame().1)).await.unwrap();
+        idle.on_frame(Ok(valid_auth_frame().1)).await.unwrap();
+        active
+            .on_frame(Ok(Message::Text(r#"{"type":"cover"}"#.to_string())))
+            .await
+            .unwrap();
+        // Pongs keep the connection alive but are not activity
+        idle.on_frame(Ok(Message::Pong(Vec::new()))).await.unwrap();
+        assert!(active.is_recently_active());
+        assert!(!idle.is_recently_active());
+
+        let (code, reason, hint) = close_retry_hint(&active.on_shutdown(&mut rng));
+        assert_eq!(code, CloseCode::Away);
+        assert_eq!(reason, "server_shutdown");
+        assert!((ACTIVE_RETRY_MIN..=ACTIVE_RETRY_MAX).contains(&hint));
+        assert!(matches!(
+            active.state(),
+            SessionState::Closing {
+                reason: CloseReason::ServerShutdown { retry_after },
+                ..
+            } if *retry_after == hint
+        ));
+        let (_, _, hint) = close_retry_hint(&idle.on_shutdown(&mut rng));
+        assert!(hint >= IDLE_RETRY_MIN);
+        // Only one close frame per connection
+        assert!(idle.on_shutdown(&mut rng).is_empty());
+
+        active.finish().await.unwrap();
+        idle.finish().await.unwrap();
+        assert_eq!(lobby.users.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_full_admission_queue_refuses_with_retry_hint() {
+        use crate::admission::AdmissionQueue;
+
+        // One admission per second and room for one waiting connection
+        let lobby = Arc::new(Lobby::new().with_admission_queue(AdmissionQueue::paced(1, 1)));
+        let clock = ManualClock::new();
+        let mut first = session(&lobby, clock.clone());
+        first.on_frame(Ok(valid_auth_frame().1)).await.unwrap();
+        assert!(matches!(first.state(), SessionState::Authenticated { .. }));
+
+        let mut second = session(&lobby, clock.clone());
+        let (public_key, frame) = valid_auth_frame();
+        let frames = second.on_frame(Ok(frame)).await.unwrap();
+
+        match &frames[0] {
+            Message::Text(text) => assert!(text.contains("server_busy")),
+            other => panic!("Expected error, got {:?}", other),
+        }
+        let (code, reason, hint) = close_retry_hint(&frames);
+        assert_eq!(code, CloseCode::Again);
+        assert_eq!(reason, "server_busy");
+        // Between one and two times the one second backlog
+        assert!((Duration::from_secs(1)..=Duration::from_secs(2)).contains(&hint));
+        assert!(matches!(
+            second.state(),
+            SessionState::Closing {
+                public_key: None,
+                reason: CloseReason::AdmissionRefused { .. },
+            }
+        ));
+        assert!(!lobby.user_exists(&public_key).await.unwrap());
+
+        let stats = lobby.admission.stats();
+        assert_eq!((stats.admitted, stats.refused), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout_uses_injected_clock() {
+        let lobby = Arc::new(Lobby::new());
+        let clock = ManualClock::new();
+        let mut session = session(&lobby, clock.clone()).with_read_timeout(Duration::from_secs(10));
+        let (public_key, frame) = valid_auth_frame();
+        session.on_frame(Ok(frame)).await.unwrap();
+
+        clock.advance(Duration::from_secs(9));
+        assert_eq!(session.time_until_idle(), Duration::from_secs(1));
+        assert!(!session.on_idle());
+
+        clock.advance(Duration::from_secs(1));
+        assert!(session.on_idle());
+        assert!(matches!(
+            session.state(),
+            SessionState::Closing {
+                reason: CloseReason::IdleTimeout,
+                ..
+            }
+        ));
+
+        session.finish().await.unwrap();
+        assert!(!lobby.user_exists(&public_key).await.unwrap());
+    }
+
+    fn heartbeat_session(
+        lobby: &Arc<Lobby>,
+        clock: &ManualClock,
+    ) -> ConnectionSession<ManualClock> {
+        session(lobby, clock.clone()).with_heartbeat(HeartbeatConfig {
+            interval: Duration::from_secs(25),
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_pings_quiet_connection_and_pong_resets() {
+        let lobby = Arc::new(Lobby::new());
+        let clock = ManualClock::new();
+        let mut session = heartbeat_session(&lobby, &clock);
+        assert_eq!(session.time_until_heartbeat(), Duration::MAX);
+        let (_, frame) = valid_auth_frame();
+        session.on_frame(Ok(frame)).await.unwrap();
+
+        clock.advance(Duration::from_secs(24));
+        assert!(session.on_heartbeat().is_none());
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(session.on_heartbeat(), Some(Message::Ping(_))));
+        assert_eq!(session.time_until_heartbeat(), Duration::from_secs(10));
+
+        clock.advance(Duration::from_secs(3));
+        session
+            .on_frame(Ok(Message::Pong(Vec::new())))
+            .await
+            .unwrap();
+        assert!(!session.state().is_closing());
+        assert_eq!(session.time_until_heartbeat(), Duration::from_secs(25));
+    }
+
+    #[tokio::test]
+    async fn test_missed_pong_removes_user_and_broadcasts_departure() {
+        let lobby = Arc::new(Lobby::new());
+        let (watcher_tx, mut watcher_rx) = send_queue();
+        lobby
+            .add_user(ActiveConnection {
+                public_key: "f".repeat(64),
+                sender: watcher_tx,
+                connection_id: 1,
+                status: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let clock = ManualClock::new();
+        let mut session = heartbeat_session(&lobby, &clock);
+        let (public_key, frame) = valid_auth_frame();
+        session.on_frame(Ok(frame)).await.unwrap();
+
+        clock.advance(Duration::from_secs(25));
+        assert!(session.on_heartbeat().is_some());
+        clock.advance(Duration::from_secs(10));
+        assert!(session.on_heartbeat().is_none());
+        assert!(matches!(
+            session.state(),
+            SessionState::Closing {
+                reason: CloseReason::HeartbeatTimeout,
+                ..
+            }
+        ));
+
+        session.finish().await.unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        assert!(!lobby.user_exists(&public_key).await.unwrap());
+
+        let mut left = Vec::new();
+        while let Ok(message) = watcher_rx.try_recv() {
+            if let profile_shared::Message::LobbyUpdate { left: keys, .. } = message {
+                left.extend(keys);
+            }
+        }
+        assert_eq!(left, vec![public_key]);
+    }
+
+    #[tokio::test]
+    async fn test_write_error_removes_user() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session = session(&lobby, ManualClock::new());
+        let (public_key, frame) = valid_auth_frame();
+        session.on_frame(Ok(frame)).await.unwrap();
+        assert!(lobby.user_exists(&public_key).await.unwrap());
+
+        session.on_write_error("connection reset");
+        assert!(matches!(
+            session.state(),
+            SessionState::Closing {
+                reason: CloseReason::WriteError(_),
+                ..
+            }
+        ));
+        session.finish().await.unwrap();
+        assert!(!lobby.user_exists(&public_key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_frames_after_closing_are_ignored() {
+        let lobby = Arc::new(Lobby::new());
+        let mut session = session(&lobby, ManualClock::new());
+        session.on_stream_end();
+
+        let (_, frame) = valid_auth_frame();
+        let frames = session.on_frame(Ok(frame)).await.unwrap();
+
+        assert!(frames.is_empty());
+        assert!(lobby.users.is_empty());
+        assert!(matches!(
+            session.state(),
+            SessionState::Closing {
+                public_key: None,
+                reason: CloseReason::StreamEnded,
+            }
+        ));
+    }
+}
@@ -0,0 +1,212 @@
+//! The one path from a connection to its socket
+//!
+//! Each connection has a writer that owns the socket's write half. It
+//! writes two kinds of traffic in one place:
+//! - frames from the session loop (auth replies, refusals, pings, close
+//!   frames), in the order the loop hands them over
+//! - messages from the user's send queue (routed messages, lobby updates,
+//!   errors for the user), once the loop attaches the queue after auth
+//!
+//! Frames from the loop go first, so a close frame is never stuck behind a
+//! backlog of queued messages. The writer never reads the socket and the
+//! loop never writes it, so a client that reads slowly only holds up the
+//! writer; the send queue fills up meanwhile and the slow consumer policy
+//! takes over. The writer reports a failed write or a slow consumer back
+//! to the loop as a [`WriterEvent`], and stops once the loop drops its
+//! end of the input channel.
+
+use crate::connection::send_queue::OutboundQueue;
+use crate::connection::session::encode_text;
+use futures_util::{Sink, SinkExt};
+use profile_shared::protocol::FrameCodec;
+use std::fmt::Display;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+/// What the session loop hands the writer
+#[derive(Debug)]
+pub enum WriterInput {
+    /// Frames to write as they are
+    Frames(Vec<Message>),
+    /// Start writing the user's send queue, encoding messages with `codec`
+    Queue {
+        queue: OutboundQueue,
+        codec: FrameCodec,
+    },
+}
+
+/// What the writer tells the session loop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WriterEvent {
+    /// Writing to the socket failed; nothing more will be written
+    WriteFailed(String),
+    /// The send queue overflowed under the disconnect policy; it is no
+    /// longer drained
+    SlowConsumer,
+}
+
+/// Wait for the next message on the send queue, or forever if there is none
+async fn next_queued(queue: &mut Option<OutboundQueue>) -> Option<profile_shared::Message> {
+    match queue {
+        Some(queue) => queue.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Write frames to `write` until the session loop is done
+///
+/// # Arguments
+/// * `write` - The socket's write half
+/// * `inputs` - Frames and the send queue from the session loop
+/// * `events` - Failures reported back to the session loop
+/// * `tap` - Sees every frame before it is written; returning false drops it
+pub async fn run_writer<W, F>(
+    mut write: W,
+    mut inputs: mpsc::UnboundedReceiver<WriterInput>,
+    events: mpsc::UnboundedSender<WriterEvent>,
+    mut tap: F,
+) where
+    W: Sink<Message> + Unpin,
+    W::Error: Display,
+    F: FnMut(&Message) -> bool,
+{
+    let mut queue: Option<OutboundQueue> = None;
+    let mut codec = FrameCodec::default();
+    loop {
+        if queue.as_ref().is_some_and(OutboundQueue::is_slow_consumer) {
+            queue = None;
+            let _ = events.send(WriterEvent::SlowConsumer);
+        }
+        let frames = tokio::select! {
+            biased;
+            input = inputs.recv() => match input {
+                Some(WriterInput::Frames(frames)) => frames,
+                Some(WriterInput::Queue { queue: attached, codec: negotiated }) => {
+                    queue = Some(attached);
+                    codec = negotiated;
+                    continue;
+                }
+                None => break,
+            },
+            queued = next_queued(&mut queue) => match queued {
+                Some(message) => match serde_json::to_string(&message) {
+                    Ok(json) => vec![encode_text(&codec, json)],
+                    Err(e) => {
+                        tracing::error!(error = %e, "Failed to serialize queued message");
+                        continue;
+                    }
+                },
+                // The connection was replaced; nothing more will be queued
+                None => {
+                    queue = None;
+                    continue;
+                }
+            },
+        };
+        for frame in frames {
+            if !tap(&frame) {
+                continue;
+            }
+            let is_close = matches!(frame, Message::Close(_));
+            if let Err(e) = write.send(frame).await {
+                if is_close {
+                    tracing::warn!("Failed to send close frame: {}", e);
+                } else {
+                    let _ = events.send(WriterEvent::WriteFailed(e.to_string()));
+                }
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue_with, SlowConsumerPolicy};
+    use futures_util::sink;
+    use std::convert::Infallible;
+
+    /// A sink that forwards written frames to a channel
+    fn socket() -> (
+        impl Sink<Message, Error = Infallible> + Unpin,
+        mpsc::UnboundedReceiver<Message>,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let sink = sink::unfold(tx, |tx, frame: Message| async move {
+            let _ = tx.send(frame);
+            Ok::<_, Infallible>(tx)
+        });
+        (Box::pin(sink), rx)
+    }
+
+    #[tokio::test]
+    async fn test_session_frames_and_queued_messages_share_the_socket() {
+        let (write, mut written) = socket();
+        let (inputs, inputs_rx) = mpsc::unbounded_channel();
+        let (events_tx, _events) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(run_writer(
+            write,
+            inputs_rx,
+            events_tx,
+            |frame| !matches!(frame, Message::Text(text) if text == "dropped"),
+        ));
+
+        let (sender, queue) = send_queue_with(4, SlowConsumerPolicy::Disconnect);
+        let notice = profile_shared::Message::new_error("test".to_string(), None);
+        inputs
+            .send(WriterInput::Frames(vec![
+                Message::Text("auth_success".to_string()),
+                Message::Text("dropped".to_string()),
+            ]))
+            .unwrap();
+        inputs
+            .send(WriterInput::Queue {
+                queue,
+                codec: FrameCodec::default(),
+            })
+            .unwrap();
+        sender.send(notice.clone()).unwrap();
+
+        assert_eq!(
+            written.recv().await,
+            Some(Message::Text("auth_success".to_string()))
+        );
+        match written.recv().await {
+            Some(Message::Text(json)) => {
+                assert_eq!(
+                    serde_json::from_str::<profile_shared::Message>(&json).unwrap(),
+                    notice
+                );
+            }
+            other => panic!("Expected the queued message, got {:?}", other),
+        }
+
+        // Dropping the input channel stops the writer
+        drop(inputs);
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_overflowed_queue_reported_as_slow_consumer() {
+        let (write, _written) = socket();
+        let (inputs, inputs_rx) = mpsc::unbounded_channel();
+        let (events_tx, mut events) = mpsc::unbounded_channel();
+        let writer = tokio::spawn(run_writer(write, inputs_rx, events_tx, |_| true));
+
+        let (sender, queue) = send_queue_with(1, SlowConsumerPolicy::Disconnect);
+        let notice = profile_shared::Message::new_error("test".to_string(), None);
+        sender.send(notice.clone()).unwrap();
+        assert!(sender.send(notice).is_err());
+        inputs
+            .send(WriterInput::Queue {
+                queue,
+                codec: FrameCodec::default(),
+            })
+            .unwrap();
+
+        assert_eq!(events.recv().await, Some(WriterEvent::SlowConsumer));
+        drop(inputs);
+        writer.await.unwrap();
+    }
+}
@@ -0,0 +1,230 @@
+//! Echo bot for trying a client against a dev server
+//!
+//! Debug builds started with `PROFILE_DEV_ECHO_BOT=1` add a virtual user
+//! to the lobby, shown as `echo-bot`, whose key is the well-known
+//! `config::echo_bot::PUBLIC_KEY`. Every direct message it receives comes
+//! straight back to its sender as a new message with the same text, signed
+//! with the bot's key. So one developer with one client can exercise the
+//! whole send, route, receive and verify path without a second machine.
+//!
+//! The bot's reply goes through the same validation and routing as a
+//! message from a connected client. Its signing key is derived from a
+//! public seed, so anyone can sign as the bot: release builds ignore
+//! `PROFILE_DEV_ECHO_BOT`. Sealed messages can't be read by the server and
+//! get no reply; turn forward secrecy off to talk to the bot.
+
+use crate::connection::send_queue::{send_queue_with, OutboundQueue, SlowConsumerPolicy};
+use crate::lobby::{ActiveConnection, Lobby, UserStatus};
+use crate::message::{handle_incoming_message, route_message, MessageValidationResult};
+use profile_shared::canonical::{self, CanonicalVersion};
+use profile_shared::config::connection::SEND_QUEUE_CAPACITY;
+use profile_shared::config::echo_bot::{NICKNAME, PUBLIC_KEY, SEED};
+use profile_shared::{sign_message, LobbyError, Message, PrivateKey};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Environment variable that turns the echo bot on (`1` or `true`)
+pub const ECHO_BOT_ENV_VAR: &str = "PROFILE_DEV_ECHO_BOT";
+
+/// Connection id of the echo bot; real connection ids count up from 1 and
+/// never reach it
+pub const ECHO_BOT_CONNECTION_ID: u64 = u64::MAX;
+
+/// The echo bot's identity
+#[derive(Debug)]
+pub struct EchoBot {
+    private_key: PrivateKey,
+}
+
+impl Default for EchoBot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EchoBot {
+    /// Create the echo bot with its well-known key
+    pub fn new() -> Self {
+        Self {
+            private_key: PrivateKey::new(SEED.to_vec()),
+        }
+    }
+
+    /// The echo bot if `PROFILE_DEV_ECHO_BOT` turns it on
+    ///
+    /// Always None in release builds.
+    pub fn from_env() -> Option<Self> {
+        if !cfg!(debug_assertions) {
+            return None;
+        }
+        std::env::var(ECHO_BOT_ENV_VAR)
+            .ok()
+            .filter(|value| is_enabled(value))
+            .map(|_| Self::new())
+    }
+
+    /// Add the bot to the lobby and start answering its messages
+    ///
+    /// The task ends once the bot is removed from the lobby.
+    ///
+    /// # Errors
+    /// Returns an error if the lobby has no room for the bot
+    pub async fn spawn(self, lobby: Arc<Lobby>) -> Result<JoinHandle<()>, LobbyError> {
+        let inbox = self.join(&lobby).await?;
+        Ok(tokio::spawn(self.run(lobby, inbox)))
+    }
+
+    /// Add the bot to the lobby, returning the queue its messages arrive on
+    async fn join(&self, lobby: &Lobby) -> Result<OutboundQueue, LobbyError> {
+        // Lobby updates for every join and leave arrive here too; the bot
+        // never falls so far behind that it should be disconnected for it
+        let (sender, inbox) = send_queue_with(SEND_QUEUE_CAPACITY, SlowConsumerPolicy::DropNewest);
+        // Nickname first, so the join broadcast carries it
+        let _ = lobby.nicknames.set(PUBLIC_KEY, NICKNAME).await;
+        let conn = ActiveConnection {
+            public_key: PUBLIC_KEY.to_string(),
+            sender,
+            connection_id: ECHO_BOT_CONNECTION_ID,
+            status: UserStatus::default(),
+        };
+        crate::lobby::add_user(lobby, PUBLIC_KEY.to_string(), conn).await?;
+        Ok(inbox)
+    }
+
+    async fn run(self, lobby: Arc<Lobby>, mut inbox: OutboundQueue) {
+        while let Some(message) = inbox.recv().await {
+            let Message::Text {
+                message,
+                sender_public_key,
+                ..
+            } = message
+            else {
+                continue;
+            };
+            if let Err(e) = self.echo(&lobby, &sender_public_key, &message).await {
+                tracing::debug!(
+                    to = %sender_public_key.chars().take(16).collect::<String>(),
+                    error = %e,
+                    "Echo bot reply not delivered"
+                );
+            }
+        }
+        tracing::debug!("Echo bot left the lobby");
+    }
+
+    /// Sign `text` and send it to `recipient` as a client would
+    async fn echo(&self, lobby: &Lobby, recipient: &str, text: &str) -> Result<(), String> {
+        let request = self.reply_json(recipient, text)?;
+        let validated = handle_incoming_message(lobby, PUBLIC_KEY, &request).await;
+        if let MessageValidationResult::Invalid { reason } = &validated {
+            return Err(format!("{:?}", reason));
+        }
+        route_message(lobby, &validated).await
+    }
+
+    /// The signed `message` request carrying `text` back to `recipient`
+    fn reply_json(&self, recipient: &str, text: &str) -> Result<String, String> {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signed = canonical::message(CanonicalVersion::CURRENT, text, &timestamp);
+        let signature = sign_message(&self.private_key, &signed).map_err(|e| e.to_string())?;
+        Ok(serde_json::json!({
+            "type": "message",
+            "messageId": Uuid::new_v4(),
+            "recipientPublicKey": recipient,
+            "message": text,
+            "senderPublicKey": PUBLIC_KEY,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp,
+            "canonical": CanonicalVersion::CURRENT
+        })
+        .to_string())
+    }
+}
+
+fn is_enabled(value: &str) -> bool {
+    matches!(value.trim().to_lowercase().as_str(), "1" | "true")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::send_queue;
+    use profile_shared::{derive_public_key, verify_signature, PublicKey};
+    use std::time::Duration;
+
+    #[test]
+    fn test_well_known_key_matches_seed() {
+        let bot = EchoBot::new();
+        let public_key = derive_public_key(&bot.private_key).unwrap();
+        assert_eq!(hex::encode(public_key), PUBLIC_KEY);
+        assert!(is_enabled("1") && is_enabled(" TRUE "));
+        assert!(!is_enabled("0") && !is_enabled(""));
+    }
+
+    #[tokio::test]
+    async fn test_direct_message_echoed_with_signature() {
+        let lobby = Arc::new(Lobby::new());
+        let handle = EchoBot::new().spawn(Arc::clone(&lobby)).await.unwrap();
+        assert_eq!(
+            lobby.nicknames.get(PUBLIC_KEY).await.as_deref(),
+            Some(NICKNAME)
+        );
+
+        let user = "c".repeat(64);
+        let (sender, mut inbox) = send_queue();
+        let conn = ActiveConnection {
+            public_key: user.clone(),
+            sender,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(&lobby, user.clone(), conn)
+            .await
+            .unwrap();
+        let text = Message::new_text(
+            Uuid::new_v4(),
+            "ping".to_string(),
+            user.clone(),
+            "00".repeat(64),
+            chrono::Utc::now().to_rfc3339(),
+            CanonicalVersion::CURRENT,
+        );
+        assert!(crate::message::deliver(&lobby, PUBLIC_KEY, text).await);
+
+        let reply = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(Message::Text {
+                    message,
+                    sender_public_key,
+                    signature,
+                    timestamp,
+                    canonical,
+                    ..
+                }) = inbox.recv().await
+                {
+                    break (message, sender_public_key, signature, timestamp, canonical);
+                }
+            }
+        })
+        .await
+        .expect("the bot replies");
+        let (message, sender_public_key, signature, timestamp, canonical) = reply;
+        assert_eq!(message, "ping");
+        assert_eq!(sender_public_key, PUBLIC_KEY);
+        let public_key = PublicKey::new(hex::decode(PUBLIC_KEY).unwrap()).unwrap();
+        assert!(verify_signature(
+            &public_key,
+            &canonical::message(canonical, &message, &timestamp),
+            &hex::decode(signature).unwrap()
+        )
+        .is_ok());
+
+        // Removing the bot ends its task
+        crate::lobby::remove_user(&lobby, PUBLIC_KEY).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,387 @@
+//! Sender filters set by recipients
+//!
+//! Blocking a sender in the client only hides their messages after they
+//! were downloaded. A user can instead ask the server to filter senders
+//! with a signed `set_filters` list:
+//! - messages from a `drop` sender are discarded
+//! - messages from a `queue_only` sender are held on the server, up to
+//!   `config::filters::MAX_HELD_MESSAGES`, until the user sends `held_fetch`
+//!
+//! Neither kind of sender is told, so the messages look delivered to them.
+//! Filters are checked where a message is handed to the recipient's
+//! connection, so in a cluster they apply on the node the recipient sent
+//! the list to; a client resends its list after connecting elsewhere. Like
+//! backups, filters outlive disconnects but not a server restart, unless
+//! state snapshots are enabled (see [`crate::snapshot`]).
+
+use crate::lobby::ServerPublicKey;
+use profile_shared::config::filters::{MAX_HELD_MESSAGES, MAX_SENDER_FILTERS};
+use profile_shared::protocol::{FilterAction, SenderFilter};
+use profile_shared::Message;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use tokio::sync::RwLock;
+
+/// Why a filter list was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    /// The list names more senders than allowed
+    TooMany { count: usize, max: usize },
+    /// A sender is not a hex-encoded public key
+    InvalidKey(String),
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::TooMany { count, max } => {
+                write!(f, "{} sender filters exceed the maximum of {}", count, max)
+            }
+            FilterError::InvalidKey(key) => write!(f, "Invalid sender public key: {}", key),
+        }
+    }
+}
+
+impl Error for FilterError {}
+
+/// Map a filter error to the protocol error reason sent to clients
+pub fn filter_error_reason(error: &FilterError) -> &'static str {
+    match error {
+        FilterError::TooMany { .. } => "too_many_filters",
+        FilterError::InvalidKey(_) => "invalid_filter",
+    }
+}
+
+/// One recipient's filters and the messages held for them
+#[derive(Debug, Default)]
+struct RecipientFilters {
+    actions: HashMap<ServerPublicKey, FilterAction>,
+    held: VecDeque<Message>,
+}
+
+/// Thread-safe map from recipient to the senders they filter
+#[derive(Debug)]
+pub struct SenderFilters {
+    recipients: RwLock<HashMap<ServerPublicKey, RecipientFilters>>,
+    max_filters: usize,
+    max_held: usize,
+}
+
+impl SenderFilters {
+    /// Create an empty store using the configured limits
+    pub fn new() -> Self {
+        Self::with_limits(MAX_SENDER_FILTERS, MAX_HELD_MESSAGES)
+    }
+
+    /// Create an empty store with custom limits
+    ///
+    /// # Arguments
+    /// * `max_filters` - Maximum number of senders one recipient can filter
+    /// * `max_held` - Maximum number of messages held for one recipient
+    pub fn with_limits(max_filters: usize, max_held: usize) -> Self {
+        Self {
+            recipients: RwLock::new(HashMap::new()),
+            max_filters,
+            max_held,
+        }
+    }
+
+    /// Replace a recipient's filter list
+    ///
+    /// Messages already held stay held. An empty list with nothing held
+    /// forgets the recipient.
+    ///
+    /// # Returns
+    /// The number of messages held for the recipient
+    pub async fn set(
+        &self,
+        recipient: &str,
+        filters: &[SenderFilter],
+    ) -> Result<usize, FilterError> {
+        if filters.len() > self.max_filters {
+            return Err(FilterError::TooMany {
+                count: filters.len(),
+                max: self.max_filters,
+            });
+        }
+        if let Some(filter) = filters
+            .iter()
+            .find(|f| f.sender_public_key.len() != 64 || hex::decode(&f.sender_public_key).is_err())
+        {
+            return Err(FilterError::InvalidKey(filter.sender_public_key.clone()));
+        }
+
+        let mut recipients = self.recipients.write().await;
+        let entry = recipients.entry(recipient.to_string()).or_default();
+        entry.actions = filters
+            .iter()
+            .map(|f| (f.sender_public_key.clone(), f.action))
+            .collect();
+        let held = entry.held.len();
+        if entry.actions.is_empty() && held == 0 {
+            recipients.remove(recipient);
+        }
+        Ok(held)
+    }
+
+    /// What to do with messages from `sender` to `recipient`, None to
+    /// deliver them
+    pub async fn action(&self, recipient: &str, sender: &str) -> Option<FilterAction> {
+        self.recipients
+            .read()
+            .await
+            .get(recipient)?
+            .actions
+            .get(sender)
+            .copied()
+    }
+
+    /// Apply the recipient's filters to a message about to be delivered
+    ///
+    /// Only direct messages, plain or sealed, edits, retractions and
+    /// reactions to them, and attachment offers are filtered. A held message is kept until
+    /// [`Self::take_held`]; once the recipient has `max_held` messages
+    /// waiting, the oldest is dropped.
+    ///
+    /// # Returns
+    /// The message if it should be delivered now
+    pub async fn screen(&self, recipient: &str, message: Message) -> Option<Message> {
+        let (Message::Text {
+            sender_public_key, ..
+        }
+        | Message::Sealed {
+            sender_public_key, ..
+        }
+        | Message::Edit {
+            sender_public_key, ..
+        }
+        | Message::Retract {
+            sender_public_key, ..
+        }
+        | Message::Reaction {
+            sender_public_key, ..
+        }
+        | Message::AttachmentOffer {
+            sender_public_key, ..
+        }) = &message
+        else {
+            return Some(message);
+        };
+        // Most recipients filter nobody; don't take the write lock for them
+        let Some(action) = self.action(recipient, sender_public_key).await else {
+            return Some(message);
+        };
+        let sender = sender_public_key.chars().take(16).collect::<String>();
+        match action {
+            FilterAction::Drop => {
+                tracing::debug!(%sender, "Dropped message from filtered sender");
+            }
+            FilterAction::QueueOnly => {
+                tracing::debug!(%sender, "Held message from queue-only sender");
+                let mut recipients = self.recipients.write().await;
+                let held = &mut recipients.entry(recipient.to_string()).or_default().held;
+                if held.len() >= self.max_held {
+                    held.pop_front();
+                }
+                held.push_back(message);
+            }
+        }
+        None
+    }
+
+    /// Take every message held for a recipient, oldest first
+    pub async fn take_held(&self, recipient: &str) -> Vec<Message> {
+        let mut recipients = self.recipients.write().await;
+        let Some(entry) = recipients.get_mut(recipient) else {
+            return Vec::new();
+        };
+        let held = std::mem::take(&mut entry.held).into();
+        if entry.actions.is_empty() {
+            recipients.remove(recipient);
+        }
+        held
+    }
+
+    /// Every recipient's filters and held messages, ordered by recipient
+    pub async fn entries(&self) -> Vec<(ServerPublicKey, Vec<SenderFilter>, Vec<Message>)> {
+        let recipients = self.recipients.read().await;
+        let mut entries: Vec<_> = recipients
+            .iter()
+            .map(|(recipient, entry)| {
+                let mut filters: Vec<SenderFilter> = entry
+                    .actions
+                    .iter()
+                    .map(|(sender, action)| SenderFilter {
+                        sender_public_key: sender.clone(),
+                        action: *action,
+                    })
+                    .collect();
+                filters.sort_by(|a, b| a.sender_public_key.cmp(&b.sender_public_key));
+                (
+                    recipient.clone(),
+                    filters,
+                    entry.held.iter().cloned().collect(),
+                )
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Restore a recipient's filters and held messages from a state snapshot
+    ///
+    /// The filters replace the recipient's list as [`Self::set`] would, and
+    /// the held messages are queued ahead of any held since, up to
+    /// `max_held`.
+    pub async fn restore(
+        &self,
+        recipient: &str,
+        filters: &[SenderFilter],
+        held: Vec<Message>,
+    ) -> Result<(), FilterError> {
+        self.set(recipient, filters).await?;
+        if held.is_empty() {
+            return Ok(());
+        }
+        let mut recipients = self.recipients.write().await;
+        let queue = &mut recipients.entry(recipient.to_string()).or_default().held;
+        let newer = std::mem::take(queue);
+        queue.extend(held.into_iter().chain(newer));
+        while queue.len() > self.max_held {
+            queue.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Number of recipients with filters or held messages
+    pub async fn len(&self) -> usize {
+        self.recipients.read().await.len()
+    }
+
+    /// Check if no recipient filters anyone or has messages held
+    pub async fn is_empty(&self) -> bool {
+        self.recipients.read().await.is_empty()
+    }
+}
+
+impl Default for SenderFilters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn key(byte: char) -> String {
+        byte.to_string().repeat(64)
+    }
+
+    fn filter(sender: &str, action: FilterAction) -> SenderFilter {
+        SenderFilter {
+            sender_public_key: sender.to_string(),
+            action,
+        }
+    }
+
+    fn text(sender: &str, body: &str) -> Message {
+        Message::new_text(
+            Uuid::new_v4(),
+            body.to_string(),
+            sender.to_string(),
+            "00".to_string(),
+            "2025-01-01T00:00:00Z".to_string(),
+            profile_shared::canonical::CanonicalVersion::CURRENT,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_screen_drops_holds_and_delivers() {
+        let filters = SenderFilters::with_limits(4, 2);
+        let (me, troll, pest, friend) = (key('a'), key('b'), key('c'), key('d'));
+        filters
+            .set(
+                &me,
+                &[
+                    filter(&troll, FilterAction::Drop),
+                    filter(&pest, FilterAction::QueueOnly),
+                ],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(filters.screen(&me, text(&troll, "go away")).await, None);
+        let sealed = Message::new_sealed(
+            Uuid::new_v4(),
+            troll.clone(),
+            profile_shared::crypto::ratchet::RatchetEnvelope {
+                session: "00".repeat(32),
+                ratchet_key: "00".repeat(32),
+                previous_count: 0,
+                index: 0,
+                nonce: String::new(),
+                ciphertext: String::new(),
+            },
+        );
+        assert_eq!(filters.screen(&me, sealed).await, None);
+        let hello = text(&friend, "hello");
+        assert_eq!(filters.screen(&me, hello.clone()).await, Some(hello));
+        // Only direct messages are filtered
+        let notice = Message::new_nickname(troll.clone(), None);
+        assert_eq!(filters.screen(&me, notice.clone()).await, Some(notice));
+
+        // Held messages are capped, dropping the oldest
+        let held: Vec<Message> = (0..3).map(|n| text(&pest, &n.to_string())).collect();
+        for message in &held {
+            assert_eq!(filters.screen(&me, message.clone()).await, None);
+        }
+        assert_eq!(filters.take_held(&me).await, held[1..].to_vec());
+        assert_eq!(filters.take_held(&me).await, vec![]);
+
+        // Filters only apply to the recipient who set them
+        let other = text(&troll, "hi");
+        assert_eq!(filters.screen(&friend, other.clone()).await, Some(other));
+    }
+
+    #[tokio::test]
+    async fn test_set_replaces_list_and_keeps_held() {
+        let filters = SenderFilters::with_limits(1, 10);
+        let (me, pest) = (key('a'), key('c'));
+        assert_eq!(
+            filters
+                .set(&me, &[filter(&pest, FilterAction::QueueOnly)])
+                .await,
+            Ok(0)
+        );
+        filters.screen(&me, text(&pest, "later")).await;
+
+        // Clearing the list keeps what was already held
+        assert_eq!(filters.set(&me, &[]).await, Ok(1));
+        assert_eq!(filters.action(&me, &pest).await, None);
+        assert_eq!(filters.take_held(&me).await.len(), 1);
+        assert!(filters.is_empty().await);
+
+        assert_eq!(
+            filters
+                .set(
+                    &me,
+                    &[
+                        filter(&pest, FilterAction::Drop),
+                        filter(&key('d'), FilterAction::Drop)
+                    ]
+                )
+                .await,
+            Err(FilterError::TooMany { count: 2, max: 1 })
+        );
+        assert_eq!(
+            filters
+                .set(&me, &[filter("not-a-key", FilterAction::Drop)])
+                .await,
+            Err(FilterError::InvalidKey("not-a-key".to_string()))
+        );
+        assert!(filters.is_empty().await);
+    }
+}
@@ -1,8 +1,25 @@
 //! Profile server library - exposes modules for integration testing
 
+pub mod admin;
+pub mod admission;
+pub mod attachments;
+pub mod audit;
 pub mod auth;
+pub mod backup;
+pub mod cluster;
 pub mod connection;
+pub mod echo_bot;
+pub mod filters;
+pub mod load;
 pub mod lobby;
+pub mod logging;
 pub mod message;
+pub mod moderation;
+pub mod names;
+pub mod presence;
+pub mod presence_api;
 pub mod protocol;
 pub mod rate_limiter;
+pub mod rooms;
+pub mod runtime;
+pub mod snapshot;
@@ -0,0 +1,192 @@
+//! Bounded signature verification off the async reactor
+//!
+//! Verifying an ed25519 signature is the most CPU-hungry step of handling a
+//! frame. Every verification (auth, direct and room messages, nickname and
+//! backup requests) runs on the blocking thread pool so a burst of them
+//! can't stall the event loop. At most one verification per CPU runs at a
+//! time, so the blocking pool never outnumbers the cores and crowds out the
+//! async workers, and at most `MAX_PENDING_VERIFICATIONS` may be queued or
+//! running at once.
+//!
+//! When the queue is full, a direct message is refused straight away with a
+//! retryable `server_busy` error carrying a retry-after hint, instead of
+//! piling up behind the backlog. Other requests have no retry path in the
+//! client, so they wait for a slot instead.
+
+use crate::message::{validate_canonical_signature, ValidationError};
+use crate::runtime::cpu_count;
+use profile_shared::config::load::{MAX_PENDING_VERIFICATIONS, SERVER_BUSY_RETRY_AFTER};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinError;
+
+/// Bounded queue of pending signature verifications
+#[derive(Debug)]
+pub struct VerificationQueue {
+    permits: Arc<Semaphore>,
+    /// Verifications allowed to run on the blocking pool at once
+    workers: Arc<Semaphore>,
+    capacity: usize,
+    retry_after: Duration,
+}
+
+/// A reserved place in the verification queue
+#[derive(Debug)]
+pub struct VerificationSlot {
+    permit: OwnedSemaphorePermit,
+    workers: Arc<Semaphore>,
+}
+
+impl VerificationQueue {
+    /// Create a queue with the default capacity and retry hint
+    pub fn new() -> Self {
+        Self::with_capacity(MAX_PENDING_VERIFICATIONS, SERVER_BUSY_RETRY_AFTER)
+    }
+
+    /// Create a queue holding at most `capacity` verifications
+    ///
+    /// # Arguments
+    /// * `capacity` - Verifications that may be queued or running at once
+    /// * `retry_after` - Delay suggested to refused clients
+    pub fn with_capacity(capacity: usize, retry_after: Duration) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(capacity)),
+            workers: Arc::new(Semaphore::new(cpu_count())),
+            capacity,
+            retry_after,
+        }
+    }
+
+    /// Run at most `workers` verifications at once (defaults to the CPU count)
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = Arc::new(Semaphore::new(workers.max(1)));
+        self
+    }
+
+    /// Reserve a place for one verification without waiting
+    ///
+    /// # Returns
+    /// Err with the delay to suggest to the client if the queue is full
+    pub fn try_reserve(&self) -> Result<VerificationSlot, Duration> {
+        Arc::clone(&self.permits)
+            .try_acquire_owned()
+            .map(|permit| self.slot(permit))
+            .map_err(|_| self.retry_after)
+    }
+
+    /// Reserve a place for one verification, waiting for one to free up
+    pub async fn reserve(&self) -> VerificationSlot {
+        let permit = Arc::clone(&self.permits)
+            .acquire_owned()
+            .await
+            .expect("verification queue semaphore is never closed");
+        self.slot(permit)
+    }
+
+    fn slot(&self, permit: OwnedSemaphorePermit) -> VerificationSlot {
+        VerificationSlot {
+            permit,
+            workers: Arc::clone(&self.workers),
+        }
+    }
+
+    /// Number of verifications queued or running
+    pub fn pending(&self) -> usize {
+        self.capacity - self.permits.available_permits()
+    }
+}
+
+impl Default for VerificationQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VerificationSlot {
+    /// Run `f` on the blocking pool once a worker is free, freeing the
+    /// slot when it returns
+    pub async fn run<T, F>(self, f: F) -> Result<T, JoinError>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let worker = self
+            .workers
+            .acquire_owned()
+            .await
+            .expect("verification worker semaphore is never closed");
+        let permit = self.permit;
+        tokio::task::spawn_blocking(move || {
+            let _permits = (permit, worker);
+            f()
+        })
+        .await
+    }
+
+    /// Verify a signature over `canonical` on the blocking pool, freeing
+    /// the slot when done
+    ///
+    /// The canonical bytes are the only copy of the text the blocking task
+    /// needs, so callers build them once and they are moved.
+    pub async fn verify(
+        self,
+        sender_public_key: &str,
+        canonical: Vec<u8>,
+        signature: &str,
+    ) -> Result<(), ValidationError> {
+        let (sender_public_key, signature) = (sender_public_key.to_string(), signature.to_string());
+        self.run(move || validate_canonical_signature(&sender_public_key, &canonical, &signature))
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!(error = %e, "Signature verification task failed");
+                Err(ValidationError::SignatureInvalid {
+                    details: "Signature could not be verified".to_string(),
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profile_shared::canonical::{self, CanonicalVersion};
+    use profile_shared::{derive_public_key, generate_private_key, sign_message};
+
+    #[tokio::test]
+    async fn test_full_queue_refuses_with_hint() {
+        let queue = VerificationQueue::with_capacity(1, Duration::from_millis(250));
+        let slot = queue.try_reserve().unwrap();
+        assert_eq!(queue.pending(), 1);
+        assert_eq!(queue.try_reserve().unwrap_err(), Duration::from_millis(250));
+
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let timestamp = "2025-01-01T00:00:00Z";
+        let canonical = canonical::message(CanonicalVersion::CURRENT, "hi", timestamp);
+        let signature = hex::encode(sign_message(&private_key, &canonical).unwrap());
+
+        slot.verify(&public_key, canonical, &signature)
+            .await
+            .unwrap();
+        assert_eq!(queue.pending(), 0);
+        assert!(queue.try_reserve().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_reserve_waits_for_a_free_slot() {
+        let queue = Arc::new(VerificationQueue::with_capacity(1, Duration::ZERO));
+        let slot = queue.reserve().await;
+
+        let waiting = tokio::spawn({
+            let queue = Arc::clone(&queue);
+            async move { queue.reserve().await.run(|| 42).await.unwrap() }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        assert_eq!(slot.run(|| 1).await.unwrap(), 1);
+        assert_eq!(waiting.await.unwrap(), 42);
+        assert_eq!(queue.pending(), 0);
+    }
+}
@@ -0,0 +1,90 @@
+//! Keys of online users with a throwaway guest identity
+//!
+//! A client signing in with a guest identity sets the `ephemeral` flag on
+//! its auth or resume message. The server keeps the flag while the user is
+//! online and tags them in join broadcasts, lobby pages and auth replies,
+//! so others can tell the key will be gone once its owner leaves.
+
+use crate::lobby::ServerPublicKey;
+use std::collections::HashSet;
+use tokio::sync::RwLock;
+
+/// Thread-safe set of online guest keys
+#[derive(Debug, Default)]
+pub struct EphemeralKeys {
+    keys: RwLock<HashSet<ServerPublicKey>>,
+}
+
+impl EphemeralKeys {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record whether a signing-in user has a guest identity
+    pub async fn set(&self, public_key: &str, ephemeral: bool) {
+        let mut keys = self.keys.write().await;
+        if ephemeral {
+            keys.insert(public_key.to_string());
+        } else {
+            keys.remove(public_key);
+        }
+    }
+
+    /// Forget a user who left the lobby
+    pub async fn forget(&self, public_key: &str) {
+        self.keys.write().await.remove(public_key);
+    }
+
+    /// Whether a user has a guest identity
+    pub async fn contains(&self, public_key: &str) -> bool {
+        self.keys.read().await.contains(public_key)
+    }
+
+    /// Get the given users that have a guest identity, in order
+    pub async fn lookup(&self, public_keys: &[ServerPublicKey]) -> Vec<String> {
+        let keys = self.keys.read().await;
+        public_keys
+            .iter()
+            .filter(|key| keys.contains(*key))
+            .cloned()
+            .collect()
+    }
+
+    /// Number of online guests
+    pub async fn len(&self) -> usize {
+        self.keys.read().await.len()
+    }
+
+    /// Whether no online user is a guest
+    pub async fn is_empty(&self) -> bool {
+        self.keys.read().await.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_guest_keys_set_and_forgotten() {
+        let guests = EphemeralKeys::new();
+        guests.set("aa", true).await;
+        guests.set("bb", false).await;
+        assert!(guests.contains("aa").await);
+        assert!(!guests.contains("bb").await);
+        assert_eq!(
+            guests
+                .lookup(&["bb".to_string(), "aa".to_string(), "cc".to_string()])
+                .await,
+            vec!["aa"]
+        );
+
+        // Signing in again with a saved identity clears the tag
+        guests.set("aa", false).await;
+        assert!(guests.is_empty().await);
+        guests.set("aa", true).await;
+        guests.forget("aa").await;
+        assert!(guests.is_empty().await);
+    }
+}
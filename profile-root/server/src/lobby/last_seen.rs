@@ -0,0 +1,89 @@
+//! When users who share it were last online
+//!
+//! A client that lets others see when it was last online sets the
+//! `shareLastSeen` flag on its auth or resume message. When such a user
+//! leaves the lobby, or goes invisible (which looks the same to everyone
+//! else), the server records the time and answers `query_last_seen`
+//! requests for them with it.
+//!
+//! A user who doesn't share is answered with no time, the same answer as
+//! for a key the server has never seen, so a query can't tell whether
+//! someone is hiding. Signing in without the flag forgets the recorded
+//! time. Times are kept in memory and lost on restart.
+
+use crate::lobby::ServerPublicKey;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// Thread-safe last-seen times of the users who share them
+#[derive(Debug, Default)]
+pub struct LastSeen {
+    /// Users who share their last-seen time, with the time if they have
+    /// left since they started sharing
+    times: RwLock<HashMap<ServerPublicKey, Option<DateTime<Utc>>>>,
+}
+
+impl LastSeen {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record whether a signing-in user shares their last-seen time
+    pub async fn set_sharing(&self, public_key: &str, share: bool) {
+        let mut times = self.times.write().await;
+        if share {
+            times.entry(public_key.to_string()).or_default();
+        } else {
+            times.remove(public_key);
+        }
+    }
+
+    /// Record that a user was last seen at `at`, if they share it
+    pub async fn record(&self, public_key: &str, at: DateTime<Utc>) {
+        if let Some(time) = self.times.write().await.get_mut(public_key) {
+            *time = Some(at);
+        }
+    }
+
+    /// When a user was last seen, if they share it and have left before
+    pub async fn get(&self, public_key: &str) -> Option<DateTime<Utc>> {
+        self.times.read().await.get(public_key).copied().flatten()
+    }
+
+    /// Whether a user shares their last-seen time
+    pub async fn is_sharing(&self, public_key: &str) -> bool {
+        self.times.read().await.contains_key(public_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_times_only_kept_for_sharing_users() {
+        let last_seen = LastSeen::new();
+        let at = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+
+        last_seen.record("aa", at).await;
+        assert_eq!(last_seen.get("aa").await, None);
+
+        last_seen.set_sharing("aa", true).await;
+        assert!(last_seen.is_sharing("aa").await);
+        assert_eq!(last_seen.get("aa").await, None);
+        last_seen.record("aa", at).await;
+        assert_eq!(last_seen.get("aa").await, Some(at));
+
+        // Signing in again with sharing on keeps the time
+        last_seen.set_sharing("aa", true).await;
+        assert_eq!(last_seen.get("aa").await, Some(at));
+
+        // Turning sharing off forgets it
+        last_seen.set_sharing("aa", false).await;
+        assert!(!last_seen.is_sharing("aa").await);
+        assert_eq!(last_seen.get("aa").await, None);
+    }
+}
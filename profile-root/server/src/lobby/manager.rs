@@ -3,8 +3,10 @@
 //! This module implements the core lobby operations including add, remove, query,
 //! and broadcast functionality as specified in the story requirements.
 
-use crate::lobby::state::{ActiveConnection, Lobby};
-use profile_shared::{config, LobbyError, Message};
+use crate::lobby::state::{ActiveConnection, Lobby, ServerPublicKey};
+use profile_shared::protocol::Status;
+use profile_shared::{config, LobbyError, LobbyUser, Message};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 /// Add a user to the lobby with reconnection handling
@@ -32,10 +34,17 @@ pub async fn add_user(
         return Err(LobbyError::InvalidPublicKey);
     }
 
-    let mut users = lobby.users.write().await;
+    // DoS protection: the lobby size limit is enforced atomically on insert.
+    // Reconnection is allowed even if the lobby is "full" (replacing doesn't
+    // increase size).
+    let status = conn.status.clone();
+    let replaced = lobby
+        .users
+        .insert_bounded(Arc::new(conn), config::lobby::MAX_LOBBY_SIZE)
+        .await?;
 
     // Check for existing user (AC2: Reconnection case)
-    let is_reconnection = users.contains_key(&key);
+    let is_reconnection = replaced.is_some();
 
     // AC2 Requirement: On reconnection, broadcast "left" then "joined" delta
     // This allows clients to update their connection reference while maintaining
@@ -44,57 +53,90 @@ pub async fn add_user(
     // SECURITY NOTE: Reconnections are only allowed after successful authentication
     // in handle_connection(). Each new connection must provide a valid signature
     // for the "auth" message using their private key before reaching this point.
-    if is_reconnection {
-        tracing::debug!(
-            "User {} reconnecting (connection_id {}), broadcasting leave/join delta",
-            key.chars().take(16).collect::<String>(),
-            conn.connection_id
-        );
-        // Remove old connection first before broadcasting
+    if let Some(old_conn) = replaced {
+        // A reconnecting user keeps the status they chose
+        status.replace(old_conn.status.get());
         // SECURITY: Terminate old connection to prevent hijacking
-        if let Some(old_conn) = users.remove(&key) {
-            tracing::warn!(
-                "Terminating old connection {} for user {} due to reconnection",
-                old_conn.connection_id,
-                key.chars().take(16).collect::<String>()
-            );
-            // Note: In a real implementation, we would actively close the old
-            // WebSocket connection here. For now, the old connection will be
-            // cleaned up when its next heartbeat fails or it times out.
-        }
+        tracing::warn!(
+            "Terminating old connection {} for user {} due to reconnection",
+            old_conn.connection_id,
+            key.chars().take(16).collect::<String>()
+        );
+        // Note: In a real implementation, we would actively close the old
+        // WebSocket connection here. For now, the old connection will be
+        // cleaned up when its next heartbeat fails or it times out.
     } else {
         tracing::debug!(
-            "User {} joining lobby (connection_id {})",
-            key.chars().take(16).collect::<String>(),
-            conn.connection_id
+            "User {} joining lobby",
+            key.chars().take(16).collect::<String>()
         );
     }
 
-    // DoS protection: Check lobby size limit
-    // Allow reconnection even if lobby is "full" (replacing doesn't increase size)
-    if !is_reconnection && users.len() >= config::lobby::MAX_LOBBY_SIZE {
-        return Err(LobbyError::LobbyFull);
+    lobby.presence.joined(&key).await;
+    if let Some(cluster) = &lobby.cluster {
+        if let Err(e) = cluster.register(&key).await {
+            tracing::warn!(error = %e, "Failed to register user with the cluster");
+        }
     }
 
-    // Always insert the new connection (wrap in Arc)
-    users.insert(key.clone(), Arc::new(conn));
-    drop(users); // Release lock before potential async broadcast
+    // An invisible user reconnecting stays out of everyone's lobby
+    let status = status.get();
+    if status == Status::Invisible {
+        return Ok(());
+    }
 
     // AC2: Broadcast events for lobby synchronization
     // If this was a reconnection, we need to broadcast "left" first (user reconnected with new connection)
     if is_reconnection {
-        broadcast_user_left(lobby, &key)
-            .await
-            .map_err(|_| LobbyError::BroadcastFailed)?;
+        broadcast_user_left(lobby, &key)?;
     }
-    // Always broadcast "joined" for new/reconnected user
-    broadcast_user_joined(lobby, &key)
-        .await
-        .map_err(|_| LobbyError::BroadcastFailed)?;
+    // Always broadcast "joined" for new/reconnected user; a reconnecting
+    // user keeps their nickname
+    broadcast_user_joined(lobby, lobby_user(lobby, &key, status).await)?;
 
     Ok(())
 }
 
+/// The lobby entry announced for `key`, with everything the lobby knows
+/// about them
+async fn lobby_user(lobby: &Lobby, key: &str, status: Status) -> LobbyUser {
+    LobbyUser::new(key.to_string(), lobby.nicknames.get(key).await)
+        .with_name(lobby.names.get(key).await)
+        .with_ephemeral(lobby.ephemeral.contains(key).await)
+        .with_status(status)
+}
+
+/// Change an online user's status and tell the rest of the lobby
+///
+/// Away and busy users are re-announced as joined with their new status.
+/// Going invisible is announced as leaving, and coming back from it as
+/// joining, so other users can't tell an invisible user from an offline
+/// one.
+///
+/// # Returns
+/// * `Ok(true)` if the status changed
+/// * `Ok(false)` if the user already had it
+/// * `LobbyError::InvalidPublicKey` if the user is not online
+pub async fn set_status(lobby: &Lobby, key: &str, status: Status) -> Result<bool, LobbyError> {
+    let conn = lobby
+        .users
+        .get(key)
+        .await
+        .ok_or(LobbyError::InvalidPublicKey)?;
+    let previous = conn.status.replace(status);
+    if previous == status {
+        return Ok(false);
+    }
+    if status == Status::Invisible {
+        // Going invisible looks like leaving, so it's when they were last seen
+        lobby.last_seen.record(key, chrono::Utc::now()).await;
+        broadcast_user_left(lobby, key)?;
+    } else {
+        broadcast_user_joined(lobby, lobby_user(lobby, key, status).await)?;
+    }
+    Ok(true)
+}
+
 /// Remove a user from the lobby
 ///
 /// **AC3**: Handles user removal on connection close
@@ -108,21 +150,37 @@ pub async fn add_user(
 /// * `LobbyError::LockFailed` if lobby lock cannot be acquired
 #[tracing::instrument(skip(lobby), fields(public_key = %key.chars().take(16).collect::<String>()))]
 pub async fn remove_user(lobby: &Lobby, key: &str) -> Result<(), LobbyError> {
-    let mut users = lobby.users.write().await;
-
     // Remove user (idempotent - OK if user doesn't exist)
-    let user_existed = users.remove(key).is_some();
-
-    if user_existed {
+    if let Some(conn) = lobby.users.remove(key).await {
+        // Release the nickname so another user can take it
+        lobby.nicknames.clear(key).await;
+        lobby.ephemeral.forget(key).await;
+        lobby.sequences.forget(key).await;
+        lobby.presence.left(key).await;
+        // Tell anyone mid-transfer with them that it won't finish
+        for (transfer_id, peer) in lobby.attachments.forget_user(key).await {
+            let cancelled = Message::new_attachment_complete(transfer_id, true, key.to_string());
+            crate::message::deliver(lobby, &peer, cancelled).await;
+        }
+        // A user who reconnected to another node is still online
+        if let Some(cluster) = &lobby.cluster {
+            match cluster.unregister(key).await {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(e) => tracing::warn!(error = %e, "Failed to unregister user from the cluster"),
+            }
+        }
+        // Nobody saw an invisible user, so nobody is told they left
+        if conn.status.is_invisible() {
+            return Ok(());
+        }
+        lobby.last_seen.record(key, chrono::Utc::now()).await;
         tracing::debug!(
             "User {} removed from lobby, broadcasting leave notification",
             key.chars().take(16).collect::<String>()
         );
         // User was found and removed - broadcast they left
-        drop(users); // Release lock before potential async broadcast
-        broadcast_user_left(lobby, key)
-            .await
-            .map_err(|_| LobbyError::BroadcastFailed)?;
+        broadcast_user_left(lobby, key)?;
     }
 
     Ok(())
@@ -144,89 +202,178 @@ pub async fn get_user(
     lobby: &Lobby,
     key: &str,
 ) -> Result<Option<Arc<ActiveConnection>>, LobbyError> {
-    let users = lobby.users.read().await;
-    Ok(users.get(key).cloned()) // Clone the Arc (cheap), not the connection
+    Ok(lobby.users.get(key).await) // Clone the Arc (cheap), not the connection
 }
 
 pub async fn get_current_users(lobby: &Lobby) -> Result<Vec<String>, LobbyError> {
-    let users = lobby.users.read().await;
-    let mut result = Vec::with_capacity(users.len());
-    result.extend(users.keys().cloned());
-    Ok(result)
+    Ok(lobby.users.keys().await)
 }
 
 /// Broadcast that a user joined the lobby
 ///
 /// **AC1**: Notifies all other users when someone joins
 /// Constructs delta message: {"type": "lobby_update", "joined": [{"publicKey": "..."}]}
-#[tracing::instrument(skip(lobby), fields(public_key = %key.chars().take(16).collect::<String>()))]
-async fn broadcast_user_joined(lobby: &Lobby, key: &str) -> Result<(), LobbyError> {
-    let update = Message::LobbyUpdate {
-        joined: vec![profile_shared::LobbyUser {
-            public_key: key.to_string(),
-            status: None,
-        }],
-        left: vec![],
-    };
+///
+/// Delivery is handed to the lobby broadcast task, so this never waits on
+/// the other users' connections. Joins and leaves close together are sent
+/// as one update, see [`LobbyUpdateBatch`].
+fn broadcast_user_joined(lobby: &Lobby, user: LobbyUser) -> Result<(), LobbyError> {
+    lobby.broadcast_presence(PresenceChange::Joined(user))
+}
 
-    let users = lobby.users.read().await;
+/// Broadcast that a user left the lobby
+///
+/// **AC3**: Notifies all other users when someone leaves
+/// Constructs delta message: {"type": "lobby_update", "left": [{"publicKey": "..."}]}
+fn broadcast_user_left(lobby: &Lobby, key: &str) -> Result<(), LobbyError> {
+    lobby.broadcast_presence(PresenceChange::Left(key.to_string()))
+}
 
-    // Collect senders while holding the lock
-    let recipients: Vec<_> = users
-        .iter()
-        .filter(|(k, _)| *k != key) // Don't send to the user who just joined
-        .map(|(_, arc_conn)| arc_conn.sender.clone())
-        .collect();
+/// A join or leave to announce to the rest of the lobby
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresenceChange {
+    Joined(LobbyUser),
+    Left(ServerPublicKey),
+}
 
-    // Drop lock before network I/O
-    drop(users);
+impl PresenceChange {
+    /// The user the change is about
+    pub fn public_key(&self) -> &str {
+        match self {
+            PresenceChange::Joined(user) => &user.public_key,
+            PresenceChange::Left(key) => key,
+        }
+    }
 
-    // Send to all other users, skipping failed sends
-    for sender in recipients {
-        let _ = sender.send(update.clone());
-        // Ignore send failures - user may have disconnected during broadcast
+    /// The change on its own as a lobby update
+    pub fn to_update(&self) -> Message {
+        match self {
+            PresenceChange::Joined(user) => Message::LobbyUpdate {
+                joined: vec![user.clone()],
+                left: vec![],
+            },
+            PresenceChange::Left(key) => Message::LobbyUpdate {
+                joined: vec![],
+                left: vec![key.clone()],
+            },
+        }
     }
+}
 
-    Ok(())
+/// Pending changes for one user within a batch
+#[derive(Debug, Default)]
+struct PendingChange {
+    /// Fence of the user's latest leave
+    left: Option<u64>,
+    /// The user's latest join, if they haven't left since, and its fence
+    joined: Option<(LobbyUser, u64)>,
 }
 
-/// Broadcast that a user left the lobby
+/// Joins and leaves collected over one coalescing window
 ///
-/// **AC3**: Notifies all other users when someone leaves
-/// Constructs delta message: {"type": "lobby_update", "left": [{"publicKey": "..."}]}
-#[tracing::instrument(skip(lobby), fields(public_key = %key.chars().take(16).collect::<String>()))]
-async fn broadcast_user_left(lobby: &Lobby, key: &str) -> Result<(), LobbyError> {
-    let update = Message::LobbyUpdate {
-        joined: vec![],
-        left: vec![key.to_string()],
-    };
+/// Changes are merged per user so a storm of joins and leaves becomes a
+/// single `LobbyUpdate`:
+/// - joined then left: only the leave is sent
+/// - left then joined (a reconnect): both are sent, and clients apply
+///   `left` before `joined` so the user ends up present
+///
+/// Users are listed in the order of their first change. Each change keeps
+/// the fence it was queued with, so as with single broadcasts a recipient
+/// is only told about changes made after they joined, and never about
+/// their own.
+#[derive(Debug, Default)]
+pub struct LobbyUpdateBatch {
+    order: Vec<ServerPublicKey>,
+    pending: HashMap<ServerPublicKey, PendingChange>,
+    min_fence: u64,
+}
+
+impl LobbyUpdateBatch {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a change queued at `fence`
+    pub fn push(&mut self, change: PresenceChange, fence: u64) {
+        if self.pending.is_empty() {
+            self.min_fence = fence;
+        }
+        self.min_fence = self.min_fence.min(fence);
+        let key = change.public_key().to_string();
+        let pending = self.pending.entry(key.clone()).or_insert_with(|| {
+            self.order.push(key);
+            PendingChange::default()
+        });
+        match change {
+            PresenceChange::Joined(user) => pending.joined = Some((user, fence)),
+            PresenceChange::Left(_) => {
+                pending.left = Some(fence);
+                pending.joined = None;
+            }
+        }
+    }
 
-    let users = lobby.users.read().await;
+    /// Whether no changes are pending
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
 
-    // Collect senders for ALL remaining users (exclude the leaving user)
-    let recipients: Vec<_> = users
-        .iter()
-        .filter(|(k, _)| *k != key) // Don't send to the user who just left
-        .map(|(_, arc_conn)| arc_conn.sender.clone())
-        .collect();
+    /// Whether `recipient`, who joined at `seq`, gets the full [`update`]
+    ///
+    /// True for everyone who was online before the first change and isn't
+    /// part of the batch, which is usually every recipient.
+    ///
+    /// [`update`]: LobbyUpdateBatch::update
+    pub fn is_shared_with(&self, recipient: &str, seq: u64) -> bool {
+        seq < self.min_fence && !self.pending.contains_key(recipient)
+    }
 
-    // Drop lock before network I/O
-    drop(users);
+    /// The update carrying every pending change
+    ///
+    /// # Returns
+    /// None if the batch is empty
+    pub fn update(&self) -> Option<Message> {
+        self.collect(|_, _| true)
+    }
 
-    // Send to all remaining users, skipping failed sends
-    for sender in recipients {
-        let _ = sender.send(update.clone());
-        // Ignore send failures - user may have disconnected during broadcast
+    /// The update for `recipient`, who joined the lobby at `seq`
+    ///
+    /// # Returns
+    /// None if none of the changes concern them
+    pub fn update_for(&self, recipient: &str, seq: u64) -> Option<Message> {
+        self.collect(|key, fence| key != recipient && seq < fence)
     }
 
-    Ok(())
+    /// Build an update from the changes `include` accepts
+    fn collect(&self, include: impl Fn(&str, u64) -> bool) -> Option<Message> {
+        let mut joined = Vec::new();
+        let mut left = Vec::new();
+        for key in &self.order {
+            let pending = &self.pending[key];
+            if pending.left.is_some_and(|fence| include(key, fence)) {
+                left.push(key.clone());
+            }
+            if let Some((user, fence)) = &pending.joined {
+                if include(key, *fence) {
+                    joined.push(user.clone());
+                }
+            }
+        }
+        (!joined.is_empty() || !left.is_empty()).then_some(Message::LobbyUpdate { joined, left })
+    }
+
+    /// Take the pending changes, leaving the batch empty
+    pub fn take(&mut self) -> Self {
+        std::mem::take(self)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
     use profile_shared::Message as SharedMessage;
-    use tokio::sync::mpsc;
 
     fn create_test_lobby() -> Lobby {
         Lobby::new()
@@ -236,7 +383,7 @@ mod tests {
         use std::sync::atomic::{AtomicU64, Ordering};
         static CONNECTION_COUNTER: AtomicU64 = AtomicU64::new(0);
 
-        let (sender, _) = mpsc::unbounded_channel::<SharedMessage>();
+        let (sender, _) = send_queue();
         // Ensure key is exactly 64 characters (32 bytes hex-encoded) for validation
         // Use the input key as a seed to generate consistent hex
         let padded_key = if key.len() >= 64 {
@@ -263,6 +410,7 @@ mod tests {
             public_key: padded_key,
             sender,
             connection_id,
+            status: Default::default(),
         }
     }
 
@@ -279,9 +427,8 @@ mod tests {
         assert!(result.is_ok());
 
         // Verify user was added
-        let users = lobby.users.read().await;
-        assert!(users.contains_key(&connection_key));
-        assert_eq!(users.len(), 1);
+        assert!(lobby.users.contains_key(&connection_key).await);
+        assert_eq!(lobby.users.len(), 1);
     }
 
     #[tokio::test]
@@ -297,11 +444,14 @@ mod tests {
         assert!(result1.is_ok());
 
         // Verify user exists
-        let users_before = lobby.users.read().await;
-        assert_eq!(users_before.len(), 1);
-        let old_stored_id = users_before.get(&connection1_key).unwrap().connection_id;
+        assert_eq!(lobby.users.len(), 1);
+        let old_stored_id = lobby
+            .users
+            .get(&connection1_key)
+            .await
+            .unwrap()
+            .connection_id;
         assert_eq!(old_stored_id, old_connection_id);
-        drop(users_before);
 
         // Add same user again (reconnection)
         let connection2 = create_test_connection(&key);
@@ -315,9 +465,13 @@ mod tests {
         assert!(result2.is_ok());
 
         // Verify still only one user (not duplicated)
-        let users_after = lobby.users.read().await;
-        assert_eq!(users_after.len(), 1);
-        let new_stored_id = users_after.get(&connection2_key).unwrap().connection_id;
+        assert_eq!(lobby.users.len(), 1);
+        let new_stored_id = lobby
+            .users
+            .get(&connection2_key)
+            .await
+            .unwrap()
+            .connection_id;
 
         // Verify connection was replaced (different connection ID)
         assert_ne!(old_stored_id, new_stored_id);
@@ -419,16 +573,15 @@ mod tests {
         add_user(&lobby, connection_key.clone(), connection)
             .await
             .unwrap();
-        assert_eq!(lobby.users.read().await.len(), 1);
+        assert_eq!(lobby.users.len(), 1);
 
         // Remove user
         let result = remove_user(&lobby, &connection_key).await;
         assert!(result.is_ok());
 
         // Verify user was removed
-        let users = lobby.users.read().await;
-        assert!(!users.contains_key(&connection_key));
-        assert_eq!(users.len(), 0);
+        assert!(!lobby.users.contains_key(&connection_key).await);
+        assert_eq!(lobby.users.len(), 0);
     }
 
     #[tokio::test]
@@ -441,7 +594,7 @@ mod tests {
         assert!(result.is_ok()); // Should be idempotent
 
         // Verify lobby is still empty
-        assert_eq!(lobby.users.read().await.len(), 0);
+        assert!(lobby.users.is_empty());
     }
 
     #[tokio::test]
@@ -534,8 +687,7 @@ mod tests {
         let connection_key = connection.public_key.clone();
 
         // Create a test message receiver to capture broadcast messages
-        let (test_sender, mut test_receiver) =
-            tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
+        let (test_sender, mut test_receiver) = send_queue();
 
         // Create a mock connection that uses our test receiver - also use 64-char key (valid hex only)
         let mock_connection = ActiveConnection {
@@ -543,6 +695,7 @@ mod tests {
                 .to_string(),
             sender: test_sender,
             connection_id: 999,
+            status: Default::default(),
         };
 
         // Add mock user to lobby first (so they receive the broadcast)
@@ -577,15 +730,100 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_rejoin_broadcast_carries_nickname_and_name() {
+        let lobby = create_test_lobby().with_names(crate::names::NameRegistry::in_memory());
+        let (watcher_sender, mut watcher) = send_queue();
+        let watcher_key = "ab".repeat(32);
+        add_user(
+            &lobby,
+            watcher_key.clone(),
+            ActiveConnection {
+                public_key: watcher_key,
+                sender: watcher_sender,
+                connection_id: 998,
+                status: Default::default(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let connection = create_test_connection("nickname_user");
+        let key = connection.public_key.clone();
+        add_user(&lobby, key.clone(), connection).await.unwrap();
+        lobby.nicknames.set(&key, "alice").await.unwrap();
+        lobby
+            .names
+            .claim(&key, "Alice", chrono::Utc::now())
+            .await
+            .unwrap();
+
+        // Reconnecting keeps the nickname and claimed name in the join delta
+        add_user(&lobby, key.clone(), create_test_connection("nickname_user"))
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        let mut last_join = None;
+        while let Ok(message) = watcher.try_recv() {
+            if let profile_shared::Message::LobbyUpdate { joined, .. } = message {
+                last_join = joined.into_iter().next();
+            }
+        }
+        let last_join = last_join.unwrap();
+        assert_eq!(last_join.nickname.as_deref(), Some("alice"));
+        assert_eq!(last_join.name.as_deref(), Some("Alice"));
+
+        // Leaving releases the nickname but not the claimed name
+        remove_user(&lobby, &key).await.unwrap();
+        assert!(lobby.nicknames.is_empty().await);
+        assert_eq!(lobby.names.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_guest_join_broadcast_tagged() {
+        let lobby = create_test_lobby();
+        let (watcher_sender, mut watcher) = send_queue();
+        let watcher_key = "ab".repeat(32);
+        add_user(
+            &lobby,
+            watcher_key.clone(),
+            ActiveConnection {
+                public_key: watcher_key,
+                sender: watcher_sender,
+                connection_id: 998,
+                status: Default::default(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let connection = create_test_connection("guest_user");
+        let key = connection.public_key.clone();
+        lobby.ephemeral.set(&key, true).await;
+        add_user(&lobby, key.clone(), connection).await.unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        let mut last_join = None;
+        while let Ok(message) = watcher.try_recv() {
+            if let profile_shared::Message::LobbyUpdate { joined, .. } = message {
+                last_join = joined.into_iter().next().or(last_join);
+            }
+        }
+        let joined = last_join.unwrap();
+        assert_eq!(joined.public_key, key);
+        assert!(joined.ephemeral);
+
+        // Leaving drops the tag
+        remove_user(&lobby, &key).await.unwrap();
+        assert!(lobby.ephemeral.is_empty().await);
+    }
+
     #[tokio::test]
     async fn test_message_routing_uses_sender() {
         let lobby = create_test_lobby();
 
         // Create test channels to simulate WebSocket communication
-        let (sender1, mut receiver1) =
-            tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
-        let (sender2, mut receiver2) =
-            tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
+        let (sender1, mut receiver1) = send_queue();
+        let (sender2, mut receiver2) = send_queue();
 
         // Create connections with our test senders - use 64-char hex keys (valid hex only)
         let connection1 = ActiveConnection {
@@ -593,6 +831,7 @@ mod tests {
                 .to_string(),
             sender: sender1,
             connection_id: 1,
+            status: Default::default(),
         };
 
         let connection2 = ActiveConnection {
@@ -600,6 +839,7 @@ mod tests {
                 .to_string(),
             sender: sender2,
             connection_id: 2,
+            status: Default::default(),
         };
 
         // Add both users to lobby
@@ -613,6 +853,7 @@ mod tests {
             .unwrap();
 
         // Drain any broadcast messages from lobby updates
+        lobby.flush_broadcasts().await.unwrap();
         let _ = tokio::time::timeout(std::time::Duration::from_millis(10), receiver1.recv()).await;
         let _ = tokio::time::timeout(std::time::Duration::from_millis(10), receiver2.recv()).await;
 
@@ -627,10 +868,12 @@ mod tests {
         if let (Some(user1_conn), Some(user2_conn)) = (user1_result, user2_result) {
             // Test routing to user1
             let test_msg = profile_shared::Message::new_text(
+                uuid::Uuid::new_v4(),
                 "test message for user1".to_string(),
                 "test_sender".to_string(),
                 "test_signature".to_string(),
                 "2025-12-20T10:00:00Z".to_string(),
+                profile_shared::canonical::CanonicalVersion::CURRENT,
             );
             let _ = user1_conn.sender.send(test_msg.clone());
 
@@ -801,8 +1044,7 @@ mod tests {
         let lobby = create_test_lobby();
 
         // Create a test receiver to measure broadcast timing
-        let (test_sender, mut test_receiver) =
-            tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
+        let (test_sender, mut test_receiver) = send_queue();
 
         // Create a mock connection that uses our test receiver
         let mock_connection = ActiveConnection {
@@ -810,6 +1052,7 @@ mod tests {
                 .to_string(),
             sender: test_sender,
             connection_id: 999,
+            status: Default::default(),
         };
 
         // Add mock user to lobby first (so they receive the broadcast)
@@ -862,8 +1105,7 @@ mod tests {
         let lobby = create_test_lobby();
 
         // Create a test receiver to measure broadcast timing
-        let (test_sender, mut test_receiver) =
-            tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
+        let (test_sender, mut test_receiver) = send_queue();
 
         // Create a mock connection that uses our test receiver
         let mock_connection = ActiveConnection {
@@ -871,6 +1113,7 @@ mod tests {
                 .to_string(),
             sender: test_sender,
             connection_id: 999,
+            status: Default::default(),
         };
 
         // Add mock user to lobby first (so they receive the broadcast)
@@ -893,6 +1136,7 @@ mod tests {
             .unwrap();
 
         // Drain join broadcast before measuring leave
+        lobby.flush_broadcasts().await.unwrap();
         let _ =
             tokio::time::timeout(std::time::Duration::from_millis(10), test_receiver.recv()).await;
 
@@ -930,4 +1174,155 @@ mod tests {
             elapsed.as_millis()
         );
     }
+
+    /// Put a user with a hex key made of `byte` in the lobby and return
+    /// their key and the receiving end of their connection
+    async fn join(lobby: &Lobby, byte: &str) -> (String, OutboundQueue) {
+        let key = byte.repeat(32);
+        let (sender, receiver) = send_queue();
+        let connection = ActiveConnection {
+            public_key: key.clone(),
+            sender,
+            connection_id: 0,
+            status: Default::default(),
+        };
+        add_user(lobby, key.clone(), connection).await.unwrap();
+        (key, receiver)
+    }
+
+    fn updates(receiver: &mut OutboundQueue) -> Vec<(Vec<String>, Vec<String>)> {
+        let mut updates = Vec::new();
+        while let Ok(message) = receiver.try_recv() {
+            if let SharedMessage::LobbyUpdate { joined, left } = message {
+                let joined = joined.into_iter().map(|u| u.public_key).collect();
+                updates.push((joined, left));
+            }
+        }
+        updates
+    }
+
+    #[test]
+    fn test_batch_merges_changes_per_user() {
+        let mut batch = LobbyUpdateBatch::new();
+        assert!(batch.is_empty());
+        assert_eq!(batch.update(), None);
+
+        // Reconnect: left then joined keeps both
+        batch.push(PresenceChange::Left("aa".to_string()), 5);
+        batch.push(
+            PresenceChange::Joined(LobbyUser::new("aa".to_string(), None)),
+            6,
+        );
+        // Joined then left keeps only the leave
+        batch.push(
+            PresenceChange::Joined(LobbyUser::new("bb".to_string(), None)),
+            7,
+        );
+        batch.push(PresenceChange::Left("bb".to_string()), 8);
+
+        assert_eq!(
+            batch.update(),
+            Some(SharedMessage::LobbyUpdate {
+                joined: vec![LobbyUser::new("aa".to_string(), None)],
+                left: vec!["aa".to_string(), "bb".to_string()],
+            })
+        );
+        assert!(batch.is_shared_with("cc", 4));
+        assert!(!batch.is_shared_with("aa", 4));
+        assert!(!batch.is_shared_with("cc", 5));
+
+        // Nobody hears about themselves or about changes from before they joined
+        assert_eq!(
+            batch.update_for("aa", 0),
+            Some(SharedMessage::LobbyUpdate {
+                joined: vec![],
+                left: vec!["bb".to_string()],
+            })
+        );
+        assert_eq!(
+            batch.update_for("cc", 5),
+            Some(SharedMessage::LobbyUpdate {
+                joined: vec![LobbyUser::new("aa".to_string(), None)],
+                left: vec!["bb".to_string()],
+            })
+        );
+        assert_eq!(batch.update_for("cc", 8), None);
+
+        assert!(!batch.take().is_empty());
+        assert!(batch.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_join_storm_sends_one_update() {
+        let lobby = Lobby::new().with_update_window(std::time::Duration::from_secs(60));
+        let (_, mut watcher) = join(&lobby, "a0").await;
+        lobby.flush_broadcasts().await.unwrap();
+
+        let mut keys = Vec::new();
+        for byte in ["b1", "b2", "b3", "b4"] {
+            keys.push(join(&lobby, byte).await.0);
+        }
+        remove_user(&lobby, &keys[3]).await.unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+
+        assert_eq!(
+            updates(&mut watcher),
+            vec![(keys[..3].to_vec(), vec![keys[3].clone()])]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_within_window_sends_left_and_joined() {
+        let lobby = Lobby::new().with_update_window(std::time::Duration::from_secs(60));
+        let (_, mut watcher) = join(&lobby, "a0").await;
+        let (key, _) = join(&lobby, "b1").await;
+        lobby.flush_broadcasts().await.unwrap();
+        updates(&mut watcher);
+
+        let (_, mut reconnected) = join(&lobby, "b1").await;
+        lobby.flush_broadcasts().await.unwrap();
+
+        assert_eq!(
+            updates(&mut watcher),
+            vec![(vec![key.clone()], vec![key.clone()])]
+        );
+        assert!(updates(&mut reconnected).is_empty());
+        assert!(lobby.users.contains_key(&key).await);
+    }
+
+    #[tokio::test]
+    async fn test_batched_update_skips_changes_before_recipient_joined() {
+        let lobby = Lobby::new().with_update_window(std::time::Duration::from_secs(60));
+        let (_, mut alice) = join(&lobby, "a0").await;
+        lobby.flush_broadcasts().await.unwrap();
+
+        let (bob_key, mut bob) = join(&lobby, "b1").await;
+        let (carol_key, mut carol) = join(&lobby, "c2").await;
+        lobby.flush_broadcasts().await.unwrap();
+
+        assert_eq!(
+            updates(&mut alice),
+            vec![(vec![bob_key, carol_key.clone()], vec![])]
+        );
+        assert_eq!(updates(&mut bob), vec![(vec![carol_key], vec![])]);
+        assert!(updates(&mut carol).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_batched_update_sent_when_window_ends() {
+        let lobby = Lobby::new().with_update_window(std::time::Duration::from_millis(20));
+        let (_, mut watcher) = join(&lobby, "a0").await;
+        let (key, _) = join(&lobby, "b1").await;
+
+        let received = tokio::time::timeout(std::time::Duration::from_secs(1), watcher.recv())
+            .await
+            .expect("update should be sent once the window ends");
+        match received {
+            Some(SharedMessage::LobbyUpdate { joined, left }) => {
+                assert_eq!(joined[0].public_key, key);
+                assert!(left.is_empty());
+            }
+            other => panic!("Expected LobbyUpdate message, got: {:?}", other),
+        }
+    }
 }
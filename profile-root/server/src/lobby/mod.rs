@@ -3,13 +3,31 @@
 //! This module provides thread-safe lobby management for tracking
 //! authenticated users and their WebSocket connections.
 //!
-//! Follows Architecture Decision: Uses Arc<RwLock<HashMap>> pattern for concurrent access
-//! - Arc: allows multiple threads to hold references to the lobby
-//! - RwLock: multiple readers can access simultaneously, exclusive writer for modifications
+//! Follows Architecture Decision: the lobby is a sharded map so that
+//! connection handling for different users doesn't serialize on one lock
+//! - Shards: each public key hashes to one independently locked HashMap
+//! - Broadcast task: lobby-wide updates are queued on an mpsc channel and
+//!   fanned out off the connection's task
+//! - Coalescing: joins and leaves queued within a short window are merged
+//!   into one `LobbyUpdate` by the broadcast task
 //! - HashMap: O(1) lookup for message routing (critical for performance)
 
+pub mod ephemeral;
+pub mod last_seen;
 pub mod manager;
+pub mod nicknames;
+pub mod retired;
 pub mod state;
+mod sync;
 
-pub use manager::{add_user, get_current_users, get_user, remove_user};
-pub use state::{ActiveConnection, Lobby, ServerPublicKey};
+pub use ephemeral::EphemeralKeys;
+pub use last_seen::LastSeen;
+pub use manager::{
+    add_user, get_current_users, get_user, remove_user, set_status, LobbyUpdateBatch,
+    PresenceChange,
+};
+pub use nicknames::NicknameRegistry;
+pub use retired::RetiredKeys;
+pub use state::{
+    normalize_key, ActiveConnection, Lobby, LobbyPage, ServerPublicKey, UserShards, UserStatus,
+};
@@ -0,0 +1,221 @@
+//! Nickname registry for online users
+//!
+//! Users can pick a display name with a signed `set_nickname` request.
+//! Nicknames are unique among online users, compared case-insensitively,
+//! and are released when their owner leaves the lobby.
+
+use crate::lobby::ServerPublicKey;
+use profile_shared::config::nickname::MAX_NICKNAME_LENGTH;
+use profile_shared::NicknameError;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// Validate a nickname
+///
+/// Nicknames must be non-empty, at most MAX_NICKNAME_LENGTH characters,
+/// contain only alphanumerics, spaces, '-', '_' and '.', and not start or
+/// end with a space.
+pub fn validate_nickname(nickname: &str) -> Result<(), NicknameError> {
+    if nickname.is_empty()
+        || nickname.chars().count() > MAX_NICKNAME_LENGTH
+        || nickname.trim() != nickname
+        || !nickname
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+    {
+        return Err(NicknameError::InvalidNickname);
+    }
+    Ok(())
+}
+
+/// Map a nickname error to the protocol error reason sent to clients
+pub fn nickname_error_reason(error: &NicknameError) -> &'static str {
+    match error {
+        NicknameError::InvalidNickname => "invalid_nickname",
+        NicknameError::NicknameTaken => "nickname_taken",
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    /// Nickname of each user that set one
+    by_key: HashMap<ServerPublicKey, String>,
+    /// Owner of each nickname, keyed by the lowercased nickname
+    by_name: HashMap<String, ServerPublicKey>,
+}
+
+/// Thread-safe map between public keys and nicknames
+#[derive(Debug, Default)]
+pub struct NicknameRegistry {
+    registry: RwLock<Registry>,
+}
+
+impl NicknameRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a user's nickname, replacing any previous one
+    ///
+    /// # Arguments
+    /// * `public_key` - The user setting the nickname
+    /// * `nickname` - The new nickname
+    ///
+    /// # Returns
+    /// Ok(true) if the nickname changed, Ok(false) if the user already had it
+    pub async fn set(&self, public_key: &str, nickname: &str) -> Result<bool, NicknameError> {
+        validate_nickname(nickname)?;
+        let folded = nickname.to_lowercase();
+
+        let mut registry = self.registry.write().await;
+        match registry.by_name.get(&folded) {
+            Some(owner) if owner != public_key => return Err(NicknameError::NicknameTaken),
+            _ => {}
+        }
+        if registry.by_key.get(public_key).map(String::as_str) == Some(nickname) {
+            return Ok(false);
+        }
+
+        if let Some(previous) = registry
+            .by_key
+            .insert(public_key.to_string(), nickname.to_string())
+        {
+            registry.by_name.remove(&previous.to_lowercase());
+        }
+        registry.by_name.insert(folded, public_key.to_string());
+        Ok(true)
+    }
+
+    /// Clear a user's nickname
+    ///
+    /// # Returns
+    /// The nickname that was released, None if the user had none
+    pub async fn clear(&self, public_key: &str) -> Option<String> {
+        let mut registry = self.registry.write().await;
+        let previous = registry.by_key.remove(public_key)?;
+        registry.by_name.remove(&previous.to_lowercase());
+        Some(previous)
+    }
+
+    /// Move `old_public_key`'s nickname to `new_public_key`, after a key
+    /// rollover, replacing any the new key had
+    ///
+    /// # Returns
+    /// The moved nickname, None if the old key had none
+    pub async fn transfer(&self, old_public_key: &str, new_public_key: &str) -> Option<String> {
+        let mut registry = self.registry.write().await;
+        let nickname = registry.by_key.remove(old_public_key)?;
+        if let Some(replaced) = registry
+            .by_key
+            .insert(new_public_key.to_string(), nickname.clone())
+        {
+            registry.by_name.remove(&replaced.to_lowercase());
+        }
+        registry
+            .by_name
+            .insert(nickname.to_lowercase(), new_public_key.to_string());
+        Some(nickname)
+    }
+
+    /// Get a user's nickname
+    pub async fn get(&self, public_key: &str) -> Option<String> {
+        self.registry.read().await.by_key.get(public_key).cloned()
+    }
+
+    /// Get the nicknames of the given users that have one
+    pub async fn lookup(&self, public_keys: &[ServerPublicKey]) -> HashMap<String, String> {
+        let registry = self.registry.read().await;
+        public_keys
+            .iter()
+            .filter_map(|key| Some((key.clone(), registry.by_key.get(key)?.clone())))
+            .collect()
+    }
+
+    /// Number of users with a nickname
+    pub async fn len(&self) -> usize {
+        self.registry.read().await.by_key.len()
+    }
+
+    /// Whether no user has a nickname
+    pub async fn is_empty(&self) -> bool {
+        self.registry.read().await.by_key.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_nickname() {
+        assert!(validate_nickname("alice").is_ok());
+        assert!(validate_nickname("Alice Smith-Jones_2.0").is_ok());
+        assert!(validate_nickname(&"a".repeat(MAX_NICKNAME_LENGTH)).is_ok());
+
+        assert!(validate_nickname("").is_err());
+        assert!(validate_nickname(" alice").is_err());
+        assert!(validate_nickname("alice ").is_err());
+        assert!(validate_nickname("al\nice").is_err());
+        assert!(validate_nickname("<script>").is_err());
+        assert!(validate_nickname(&"a".repeat(MAX_NICKNAME_LENGTH + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_nicknames_unique_ignoring_case() {
+        let registry = NicknameRegistry::new();
+
+        assert_eq!(registry.set("aa", "Alice").await, Ok(true));
+        assert_eq!(
+            registry.set("bb", "alice").await,
+            Err(NicknameError::NicknameTaken)
+        );
+        // Re-setting your own nickname is not a conflict
+        assert_eq!(registry.set("aa", "Alice").await, Ok(false));
+        assert_eq!(registry.set("aa", "ALICE").await, Ok(true));
+        assert_eq!(registry.get("aa").await.as_deref(), Some("ALICE"));
+    }
+
+    #[tokio::test]
+    async fn test_changed_or_cleared_nickname_is_released() {
+        let registry = NicknameRegistry::new();
+        registry.set("aa", "alice").await.unwrap();
+        registry.set("aa", "ally").await.unwrap();
+
+        assert_eq!(registry.set("bb", "alice").await, Ok(true));
+        assert_eq!(registry.clear("bb").await.as_deref(), Some("alice"));
+        assert_eq!(registry.clear("bb").await, None);
+        assert_eq!(registry.set("cc", "alice").await, Ok(true));
+        assert_eq!(registry.len().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_nickname_follows_key_rollover() {
+        let registry = NicknameRegistry::new();
+        registry.set("aa", "alice").await.unwrap();
+        registry.set("bb", "temp").await.unwrap();
+
+        assert_eq!(
+            registry.transfer("aa", "bb").await.as_deref(),
+            Some("alice")
+        );
+        assert_eq!(registry.get("bb").await.as_deref(), Some("alice"));
+        assert!(registry.get("aa").await.is_none());
+        assert_eq!(registry.set("cc", "temp").await, Ok(true));
+        assert_eq!(
+            registry.set("aa", "alice").await,
+            Err(NicknameError::NicknameTaken)
+        );
+        assert_eq!(registry.transfer("aa", "dd").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_skips_users_without_nickname() {
+        let registry = NicknameRegistry::new();
+        registry.set("aa", "alice").await.unwrap();
+
+        let nicknames = registry.lookup(&["aa".to_string(), "bb".to_string()]).await;
+        assert_eq!(nicknames.len(), 1);
+        assert_eq!(nicknames.get("aa").map(String::as_str), Some("alice"));
+    }
+}
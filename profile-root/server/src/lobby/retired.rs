@@ -0,0 +1,97 @@
+//! Identity keys their owners replaced with a key rollover
+//!
+//! Once a [`profile_shared::crypto::rollover::KeyRollover`] is accepted, the
+//! old key is retired: it can't sign in or resume again, and it can't vouch
+//! for a second new key, so a stolen old key can neither come back nor fork
+//! the identity. A retired key can't be rolled over to either.
+//!
+//! Retired keys are kept in memory and forgotten when the server restarts,
+//! unless state snapshots are enabled (see [`crate::snapshot`]).
+
+use crate::lobby::ServerPublicKey;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+/// Thread-safe map from each retired key to the key that replaced it
+#[derive(Debug, Default)]
+pub struct RetiredKeys {
+    replacements: RwLock<HashMap<ServerPublicKey, ServerPublicKey>>,
+}
+
+impl RetiredKeys {
+    /// Create an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Retire `old_public_key` in favour of `new_public_key`
+    ///
+    /// # Returns
+    /// false, retiring nothing, if either key was already retired
+    pub async fn retire(&self, old_public_key: &str, new_public_key: &str) -> bool {
+        let mut replacements = self.replacements.write().await;
+        if replacements.contains_key(old_public_key) || replacements.contains_key(new_public_key) {
+            return false;
+        }
+        replacements.insert(old_public_key.to_string(), new_public_key.to_string());
+        true
+    }
+
+    /// The key that replaced `public_key`, None if it is not retired
+    pub async fn replacement(&self, public_key: &str) -> Option<ServerPublicKey> {
+        self.replacements.read().await.get(public_key).cloned()
+    }
+
+    /// Every retired key and its replacement, ordered by retired key
+    pub async fn entries(&self) -> Vec<(ServerPublicKey, ServerPublicKey)> {
+        let mut entries: Vec<_> = self
+            .replacements
+            .read()
+            .await
+            .iter()
+            .map(|(old, new)| (old.clone(), new.clone()))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    /// Retire keys taken from a state snapshot
+    ///
+    /// Unlike [`Self::retire`], a chain of rollovers can be restored in any
+    /// order. Keys already retired keep their replacement.
+    pub async fn restore(&self, entries: Vec<(ServerPublicKey, ServerPublicKey)>) {
+        let mut replacements = self.replacements.write().await;
+        for (old, new) in entries {
+            replacements.entry(old).or_insert(new);
+        }
+    }
+
+    /// Number of retired keys
+    pub async fn len(&self) -> usize {
+        self.replacements.read().await.len()
+    }
+
+    /// Whether no key was retired
+    pub async fn is_empty(&self) -> bool {
+        self.replacements.read().await.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_retired_key_replaced_once() {
+        let retired = RetiredKeys::new();
+        assert!(retired.retire("aa", "bb").await);
+        assert_eq!(retired.replacement("aa").await.as_deref(), Some("bb"));
+        assert!(retired.replacement("bb").await.is_none());
+
+        // Neither a second vouch from the old key nor a way back to it
+        assert!(!retired.retire("aa", "cc").await);
+        assert!(!retired.retire("bb", "aa").await);
+        assert!(retired.retire("bb", "cc").await);
+        assert_eq!(retired.len().await, 2);
+    }
+}
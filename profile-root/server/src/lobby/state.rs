@@ -1,80 +1,736 @@
-use profile_shared::{LobbyError, Message};
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+//! Lobby state: the sharded map of online users and the broadcast task
+//!
+//! Users are spread over `config::lobby::SHARD_COUNT` independently locked
+//! shards keyed by public key hash, so joins, leaves and lookups for
+//! different users rarely contend on the same lock. Lobby-wide broadcasts
+//! are queued on an mpsc channel and fanned out by a dedicated task instead
+//! of the connection that triggered them. Joins and leaves are coalesced
+//! by that task over `config::lobby::UPDATE_COALESCE_WINDOW`, so a storm of
+//! them fans out as a few batched updates rather than one each.
+
+use crate::admission::AdmissionQueue;
+use crate::attachments::TransferRegistry;
+use crate::audit::AuditLog;
+use crate::auth::ResumeTokenStore;
+use crate::backup::BackupStore;
+use crate::cluster::{ClusterBackend, ClusterEvent};
+use crate::connection::send_queue::{ConnectionSender, SendQueueMetrics};
+use crate::filters::SenderFilters;
+use crate::load::VerificationQueue;
+use crate::lobby::ephemeral::EphemeralKeys;
+use crate::lobby::last_seen::LastSeen;
+use crate::lobby::manager::{LobbyUpdateBatch, PresenceChange};
+use crate::lobby::nicknames::NicknameRegistry;
+use crate::lobby::retired::RetiredKeys;
+use crate::lobby::sync::{AtomicU64, AtomicUsize, RwLock};
+use crate::message::dedup::RecentMessageIds;
+use crate::message::netsim::{Netsim, NetsimConfig};
+use crate::message::pipeline::ValidationPipeline;
+use crate::message::sequence::SenderSequences;
+use crate::moderation::Moderation;
+use crate::names::NameRegistry;
+use crate::presence::PresenceStore;
+use profile_shared::protocol::Status;
+use profile_shared::{config, LobbyError, Message};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::Instant;
 
 /// Type alias for public keys for clarity and type safety
 /// This is exported for use in routing (Story 3.2)
 pub type ServerPublicKey = String;
 
+/// Lowercase a public key, checking it is 64 hex characters
+pub fn normalize_key(public_key: &str) -> Option<ServerPublicKey> {
+    let key = public_key.to_ascii_lowercase();
+    (key.len() == 64 && hex::decode(&key).is_ok()).then_some(key)
+}
+
 /// Represents an active WebSocket connection for a user in the lobby
 #[derive(Debug, Clone)]
 #[must_use]
 pub struct ActiveConnection {
     pub public_key: ServerPublicKey,
-    pub sender: mpsc::UnboundedSender<Message>,
+    pub sender: ConnectionSender,
     /// Unique identifier for this connection instance.
     /// Used to track reconnections and verify connection replacement.
     /// Updated when a user reconnects with a new WebSocket connection.
     pub connection_id: u64,
+    /// Status the user chose with `set_status`, online until they do
+    pub status: UserStatus,
+}
+
+/// A user's chosen presence status, changeable while their connection is
+/// shared with the lobby
+///
+/// Clones share the same status, so a change made through the lobby's
+/// `Arc<ActiveConnection>` is seen by every holder of the connection.
+#[derive(Debug, Clone, Default)]
+pub struct UserStatus(Arc<std::sync::Mutex<Status>>);
+
+impl UserStatus {
+    /// The current status
+    pub fn get(&self) -> Status {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Change the status, returning the previous one
+    pub fn replace(&self, status: Status) -> Status {
+        std::mem::replace(
+            &mut *self.0.lock().unwrap_or_else(|e| e.into_inner()),
+            status,
+        )
+    }
+
+    /// Whether the user is hidden from everyone else
+    pub fn is_invisible(&self) -> bool {
+        self.get() == Status::Invisible
+    }
+}
+
+/// One page of online users, ordered by public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LobbyPage {
+    /// Public keys on this page
+    pub users: Vec<ServerPublicKey>,
+    /// Last key on this page if more users follow, None on the last page
+    pub next_cursor: Option<String>,
+    /// Number of online users matching the prefix across all pages
+    pub total: usize,
+}
+
+/// A connection plus the order in which it entered the lobby
+#[derive(Debug)]
+struct Entry {
+    seq: u64,
+    conn: Arc<ActiveConnection>,
+}
+
+type Shard = RwLock<HashMap<ServerPublicKey, Entry>>;
+
+/// Online users split across independently locked shards
+///
+/// Each key always maps to the same shard, so per-user operations only lock
+/// one shard. The user count is kept in an atomic so capacity checks and
+/// `len()` never touch the shard locks. Every insert is stamped from a
+/// lobby-wide sequence so queued broadcasts can skip users that joined
+/// after the broadcast was issued.
+#[derive(Debug)]
+pub struct UserShards {
+    shards: Box<[Shard]>,
+    len: AtomicUsize,
+    seq: AtomicU64,
+}
+
+impl UserShards {
+    /// Create an empty map with the given number of shards (at least one)
+    pub fn new(shard_count: usize) -> Self {
+        Self {
+            shards: (0..shard_count.max(1))
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            len: AtomicUsize::new(0),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(&self, key: &str) -> &Shard {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[(hasher.finish() as usize) % self.shards.len()]
+    }
+
+    /// Take the next value of the lobby-wide sequence
+    fn next_seq(&self) -> u64 {
+        self.seq.fetch_add(1, Ordering::AcqRel)
+    }
+
+    fn entry(&self, conn: Arc<ActiveConnection>) -> Entry {
+        Entry {
+            seq: self.next_seq(),
+            conn,
+        }
+    }
+
+    /// Get a user's connection
+    pub async fn get(&self, key: &str) -> Option<Arc<ActiveConnection>> {
+        let shard = self.shard(key).read().await;
+        shard.get(key).map(|entry| entry.conn.clone())
+    }
+
+    /// Check whether a user is online
+    pub async fn contains_key(&self, key: &str) -> bool {
+        self.shard(key).read().await.contains_key(key)
+    }
+
+    /// Insert a connection, returning the connection it replaced
+    pub async fn insert(&self, conn: Arc<ActiveConnection>) -> Option<Arc<ActiveConnection>> {
+        let mut shard = self.shard(&conn.public_key).write().await;
+        let replaced = shard.insert(conn.public_key.clone(), self.entry(conn));
+        if replaced.is_none() {
+            self.len.fetch_add(1, Ordering::AcqRel);
+        }
+        replaced.map(|entry| entry.conn)
+    }
+
+    /// Insert a connection unless that would take a new user past `max_users`
+    ///
+    /// Replacing an existing user's connection is always allowed since it
+    /// does not grow the lobby.
+    ///
+    /// # Returns
+    /// The replaced connection (if any), or LobbyError::LobbyFull
+    pub async fn insert_bounded(
+        &self,
+        conn: Arc<ActiveConnection>,
+        max_users: usize,
+    ) -> Result<Option<Arc<ActiveConnection>>, LobbyError> {
+        let mut shard = self.shard(&conn.public_key).write().await;
+        if !shard.contains_key(&conn.public_key) {
+            self.len
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                    (n < max_users).then_some(n + 1)
+                })
+                .map_err(|_| LobbyError::LobbyFull)?;
+        }
+        let replaced = shard.insert(conn.public_key.clone(), self.entry(conn));
+        Ok(replaced.map(|entry| entry.conn))
+    }
+
+    /// Remove a user, returning their connection if they were online
+    pub async fn remove(&self, key: &str) -> Option<Arc<ActiveConnection>> {
+        let removed = self.shard(key).write().await.remove(key);
+        if removed.is_some() {
+            self.len.fetch_sub(1, Ordering::AcqRel);
+        }
+        removed.map(|entry| entry.conn)
+    }
+
+    /// Number of online users
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    /// Whether no users are online
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Public keys of all online users (unordered)
+    pub async fn keys(&self) -> Vec<ServerPublicKey> {
+        let mut keys = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            keys.extend(shard.read().await.keys().cloned());
+        }
+        keys
+    }
+
+    /// All current connections (unordered)
+    pub async fn connections(&self) -> Vec<Arc<ActiveConnection>> {
+        let mut connections = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            let shard = shard.read().await;
+            connections.extend(shard.values().map(|entry| entry.conn.clone()));
+        }
+        connections
+    }
+
+    /// Senders for every user that joined before `fence`, except `exclude`
+    ///
+    /// Shards are locked one at a time, so a broadcast never holds more than
+    /// one shard lock.
+    async fn senders_before(&self, fence: u64, exclude: Option<&str>) -> Vec<ConnectionSender> {
+        let mut senders = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            let shard = shard.read().await;
+            senders.extend(
+                shard
+                    .iter()
+                    .filter(|(key, entry)| entry.seq < fence && Some(key.as_str()) != exclude)
+                    .map(|(_, entry)| entry.conn.sender.clone()),
+            );
+        }
+        senders
+    }
+
+    /// Key, join sequence and sender of every online user
+    async fn recipients(&self) -> Vec<(ServerPublicKey, u64, ConnectionSender)> {
+        let mut recipients = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            let shard = shard.read().await;
+            recipients.extend(
+                shard
+                    .iter()
+                    .map(|(key, entry)| (key.clone(), entry.seq, entry.conn.sender.clone())),
+            );
+        }
+        recipients
+    }
+}
+
+/// Work item for the broadcast task
+enum BroadcastJob {
+    /// Send a message to every user online at `fence` except `exclude`
+    Send {
+        message: Message,
+        fence: u64,
+        exclude: Option<ServerPublicKey>,
+    },
+    /// Add a join or leave to the next coalesced lobby update
+    Presence { change: PresenceChange, fence: u64 },
+    /// Signal once every job queued before this one has been delivered
+    Flush(oneshot::Sender<()>),
+}
+
+/// Send a batch of joins and leaves, tailored to each recipient
+async fn deliver_updates(users: &UserShards, batch: LobbyUpdateBatch) {
+    if batch.is_empty() {
+        return;
+    }
+    let shared = batch.update();
+    for (key, seq, sender) in users.recipients().await {
+        let update = if batch.is_shared_with(&key, seq) {
+            shared.clone()
+        } else {
+            batch.update_for(&key, seq)
+        };
+        if let Some(update) = update {
+            // Ignore send failures - user may have disconnected during broadcast
+            let _ = sender.send(update);
+        }
+    }
+}
+
+/// Fan queued broadcasts out to all online users until the lobby is dropped
+///
+/// Presence changes wait up to `window` for more to join them. Any other
+/// job sends the pending changes first, so broadcasts are still delivered
+/// in the order they were queued.
+async fn run_broadcaster(
+    users: Arc<UserShards>,
+    mut jobs: mpsc::UnboundedReceiver<BroadcastJob>,
+    window: Duration,
+) {
+    let mut batch = LobbyUpdateBatch::new();
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let flush_at = deadline.unwrap_or_else(Instant::now);
+        let job = tokio::select! {
+            job = jobs.recv() => job,
+            () = tokio::time::sleep_until(flush_at), if deadline.is_some() => {
+                deadline = None;
+                deliver_updates(&users, batch.take()).await;
+                continue;
+            }
+        };
+        let Some(job) = job else {
+            deliver_updates(&users, batch.take()).await;
+            return;
+        };
+        match job {
+            BroadcastJob::Presence { change, fence } => {
+                batch.push(change, fence);
+                if window.is_zero() {
+                    deliver_updates(&users, batch.take()).await;
+                } else if deadline.is_none() {
+                    deadline = Some(Instant::now() + window);
+                }
+            }
+            BroadcastJob::Send {
+                message,
+                fence,
+                exclude,
+            } => {
+                deadline = None;
+                deliver_updates(&users, batch.take()).await;
+                for sender in users.senders_before(fence, exclude.as_deref()).await {
+                    // Ignore send failures - user may have disconnected during broadcast
+                    let _ = sender.send(message.clone());
+                }
+            }
+            BroadcastJob::Flush(done) => {
+                deadline = None;
+                deliver_updates(&users, batch.take()).await;
+                let _ = done.send(());
+            }
+        }
+    }
 }
 
 /// Thread-safe lobby that tracks all currently authenticated users
-/// Uses Arc<RwLock<T>> pattern for concurrent read/write access:
-/// - Arc: allows multiple threads to hold references to lobby
-/// - RwLock: multiple readers can access simultaneously, exclusive writer for modifications
-/// - HashMap: O(1) lookup for message routing (critical for performance)
-/// - Arc<ActiveConnection>: Enables efficient shared references without cloning
+///
+/// Cloning is cheap: clones share the same users and broadcast task.
+/// - `users`: sharded map from public key to connection, O(1) routing lookups
+/// - `message_ids`: ids of recently routed messages, for duplicate rejection
+/// - `sequences`: last message sequence number accepted from each sender
+/// - `nicknames`: display names chosen by online users
+/// - `names`: unique names claimed by users, kept while they are offline;
+///   empty and refusing claims unless a name registry is configured
+/// - `ephemeral`: online users signed in with a throwaway guest identity
+/// - `last_seen`: when users who share it were last online
+/// - `retired`: keys replaced by a key rollover, refused at sign-in
+/// - `backups`: encrypted backups, kept after their owner disconnects
+/// - `filters`: senders each user asked the server to drop or hold
+/// - `resume_tokens`: tokens letting a reconnecting user skip re-auth
+/// - `attachments`: file transfers being relayed between online users
+/// - broadcast queue: started on first use, so it must be used from within
+///   a Tokio runtime
+/// - `update_window`: how long the broadcast task collects joins and leaves
+///   into one update
+/// - `shutdown`: set when the server stops, closing every connection
+/// - `netsim`: simulated latency and loss for direct messages, dev servers
+///   only
+/// - `audit`: durable record of sign-ins, moderation and routing failures,
+///   disabled unless an audit log is configured
 #[derive(Debug, Clone)]
 pub struct Lobby {
-    pub users: Arc<RwLock<HashMap<ServerPublicKey, Arc<ActiveConnection>>>>,
+    pub users: Arc<UserShards>,
+    pub message_ids: Arc<RecentMessageIds>,
+    pub sequences: Arc<SenderSequences>,
+    pub nicknames: Arc<NicknameRegistry>,
+    pub names: Arc<NameRegistry>,
+    pub ephemeral: Arc<EphemeralKeys>,
+    pub last_seen: Arc<LastSeen>,
+    pub retired: Arc<RetiredKeys>,
+    pub backups: Arc<BackupStore>,
+    pub filters: Arc<SenderFilters>,
+    pub resume_tokens: Arc<ResumeTokenStore>,
+    pub attachments: Arc<TransferRegistry>,
+    pub moderation: Arc<Moderation>,
+    pub presence: Arc<PresenceStore>,
+    pub verifications: Arc<VerificationQueue>,
+    /// Paces joins so a reconnection storm after a restart spreads out
+    pub admission: Arc<AdmissionQueue>,
+    /// Dropped messages and slow consumers across all send queues
+    pub send_queues: Arc<SendQueueMetrics>,
+    /// Checks every direct message goes through before it is routed
+    pub validation: Arc<ValidationPipeline>,
+    /// Presence and event bus shared with other instances, None when standalone
+    pub cluster: Option<Arc<dyn ClusterBackend>>,
+    /// Simulated routing latency and loss, None outside dev testing
+    pub netsim: Option<Arc<Netsim>>,
+    /// Connections, sign-ins, moderation and routing failures, for operators
+    pub audit: Arc<AuditLog>,
+    broadcasts: Arc<OnceLock<mpsc::UnboundedSender<BroadcastJob>>>,
+    update_window: Duration,
+    /// Set once the server starts shutting down
+    shutdown: Arc<watch::Sender<bool>>,
 }
 
 impl Lobby {
     /// Create a new empty lobby
     pub fn new() -> Self {
         Self {
-            users: Arc::new(RwLock::new(HashMap::new())),
+            users: Arc::new(UserShards::new(config::lobby::SHARD_COUNT)),
+            message_ids: Arc::new(RecentMessageIds::new()),
+            sequences: Arc::new(SenderSequences::new()),
+            nicknames: Arc::new(NicknameRegistry::new()),
+            names: Arc::new(NameRegistry::new()),
+            ephemeral: Arc::new(EphemeralKeys::new()),
+            last_seen: Arc::new(LastSeen::new()),
+            retired: Arc::new(RetiredKeys::new()),
+            backups: Arc::new(BackupStore::new()),
+            filters: Arc::new(SenderFilters::new()),
+            resume_tokens: Arc::new(ResumeTokenStore::new()),
+            attachments: Arc::new(TransferRegistry::new()),
+            moderation: Arc::new(Moderation::new()),
+            presence: Arc::new(PresenceStore::new()),
+            verifications: Arc::new(VerificationQueue::new()),
+            admission: Arc::new(AdmissionQueue::unpaced()),
+            send_queues: Arc::new(SendQueueMetrics::new()),
+            validation: Arc::new(ValidationPipeline::standard()),
+            cluster: None,
+            netsim: None,
+            audit: Arc::new(AuditLog::disabled()),
+            broadcasts: Arc::new(OnceLock::new()),
+            update_window: config::lobby::UPDATE_COALESCE_WINDOW,
+            shutdown: Arc::new(watch::channel(false).0),
         }
     }
 
+    /// Collect joins and leaves for `window` before sending them
+    ///
+    /// A zero window sends each change as soon as it is queued. Takes no
+    /// effect once the broadcast task has started.
+    pub fn with_update_window(mut self, window: Duration) -> Self {
+        self.update_window = window;
+        self
+    }
+
+    /// Validate direct messages with `pipeline` instead of the AC1 sequence
+    pub fn with_validation(mut self, pipeline: ValidationPipeline) -> Self {
+        self.validation = Arc::new(pipeline);
+        self
+    }
+
+    /// Use `moderation` for bans instead of an empty in-memory list
+    pub fn with_moderation(mut self, moderation: Moderation) -> Self {
+        self.moderation = Arc::new(moderation);
+        self
+    }
+
+    /// Register claimed names in `names` instead of refusing claims
+    pub fn with_names(mut self, names: NameRegistry) -> Self {
+        self.names = Arc::new(names);
+        self
+    }
+
+    /// Record presence in `presence` instead of an in-memory store
+    pub fn with_presence(mut self, presence: Arc<PresenceStore>) -> Self {
+        self.presence = presence;
+        self
+    }
+
+    /// Share presence and broadcasts with other instances through `cluster`
+    pub fn with_cluster(mut self, cluster: Arc<dyn ClusterBackend>) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    /// Delay and lose direct messages to recipients as `config` says
+    pub fn with_netsim(mut self, config: NetsimConfig) -> Self {
+        self.netsim = Some(Netsim::new(config));
+        self
+    }
+
+    /// Record audit events in `audit` instead of discarding them
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Arc::new(audit);
+        self
+    }
+
+    /// Use `verifications` to bound pending signature checks
+    pub fn with_verification_queue(mut self, verifications: VerificationQueue) -> Self {
+        self.verifications = Arc::new(verifications);
+        self
+    }
+
+    /// Pace joins with `admission` instead of admitting everyone at once
+    pub fn with_admission_queue(mut self, admission: AdmissionQueue) -> Self {
+        self.admission = Arc::new(admission);
+        self
+    }
+
+    /// Tell every connection the server is shutting down
+    ///
+    /// Each connection closes with a jittered retry hint (see
+    /// [`crate::admission::shutdown_retry_hint`]) as soon as it sees the
+    /// signal from [`Self::shutdown_signal`].
+    pub fn begin_shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Changes once [`Self::begin_shutdown`] is called
+    pub fn shutdown_signal(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    /// Queue a message for every online user except `exclude`
+    ///
+    /// Delivery happens on the broadcast task, in queue order. Users who join
+    /// after this call are not sent the message; users who leave before it
+    /// is delivered are skipped. In a cluster the message is also published
+    /// for the other nodes to deliver to their users.
+    pub fn broadcast(&self, message: Message, exclude: Option<&str>) -> Result<(), LobbyError> {
+        if let Some(cluster) = &self.cluster {
+            let event = ClusterEvent::Broadcast {
+                origin: cluster.node_id().to_string(),
+                message: message.clone(),
+                exclude: exclude.map(str::to_string),
+            };
+            if let Err(e) = cluster.publish(event) {
+                tracing::warn!(error = %e, "Failed to publish broadcast to the cluster");
+            }
+        }
+        self.broadcast_local(message, exclude)
+    }
+
+    /// Queue a message for every user connected to this node except `exclude`
+    pub fn broadcast_local(
+        &self,
+        message: Message,
+        exclude: Option<&str>,
+    ) -> Result<(), LobbyError> {
+        let fence = self.users.next_seq();
+        self.broadcast_queue()
+            .send(BroadcastJob::Send {
+                message,
+                fence,
+                exclude: exclude.map(str::to_string),
+            })
+            .map_err(|_| LobbyError::BroadcastFailed)
+    }
+
+    /// Queue a join or leave for the next coalesced lobby update
+    ///
+    /// The change is sent with any others queued within the update window,
+    /// never to the user it is about, and only to users who joined before
+    /// it was queued. In a cluster it is also published to the other nodes
+    /// straight away.
+    pub fn broadcast_presence(&self, change: PresenceChange) -> Result<(), LobbyError> {
+        if let Some(cluster) = &self.cluster {
+            let event = ClusterEvent::Broadcast {
+                origin: cluster.node_id().to_string(),
+                message: change.to_update(),
+                exclude: Some(change.public_key().to_string()),
+            };
+            if let Err(e) = cluster.publish(event) {
+                tracing::warn!(error = %e, "Failed to publish presence change to the cluster");
+            }
+        }
+        let fence = self.users.next_seq();
+        self.broadcast_queue()
+            .send(BroadcastJob::Presence { change, fence })
+            .map_err(|_| LobbyError::BroadcastFailed)
+    }
+
+    /// Wait until every broadcast queued so far has been delivered
+    ///
+    /// Pending joins and leaves are sent without waiting out the window.
+    pub async fn flush_broadcasts(&self) -> Result<(), LobbyError> {
+        let Some(queue) = self.broadcasts.get() else {
+            return Ok(());
+        };
+        let (done, delivered) = oneshot::channel();
+        queue
+            .send(BroadcastJob::Flush(done))
+            .map_err(|_| LobbyError::BroadcastFailed)?;
+        delivered.await.map_err(|_| LobbyError::BroadcastFailed)
+    }
+
+    fn broadcast_queue(&self) -> &mpsc::UnboundedSender<BroadcastJob> {
+        self.broadcasts.get_or_init(|| {
+            let (queue, jobs) = mpsc::unbounded_channel();
+            tokio::spawn(run_broadcaster(
+                self.users.clone(),
+                jobs,
+                self.update_window,
+            ));
+            queue
+        })
+    }
+
     /// Add a user to lobby (wraps connection in Arc)
     pub async fn add_user(&self, connection: ActiveConnection) -> Result<(), LobbyError> {
-        let mut users = self.users.write().await;
-        users.insert(connection.public_key.clone(), Arc::new(connection));
+        self.users.insert(Arc::new(connection)).await;
         Ok(())
     }
 
     /// Remove a user from the lobby
     pub async fn remove_user(&self, public_key: &ServerPublicKey) -> Result<(), LobbyError> {
-        let mut users = self.users.write().await;
-        users.remove(public_key);
+        self.users.remove(public_key).await;
         Ok(())
     }
 
     /// Get full lobby state as public keys
     pub async fn get_full_lobby_state(&self) -> Result<Vec<String>, LobbyError> {
-        let users = self.users.read().await;
-        let online_users: Vec<String> = users.keys().cloned().collect();
-        Ok(online_users)
+        Ok(self.users.keys().await)
+    }
+
+    /// Get one page of online users, ordered by public key
+    ///
+    /// In a cluster the page covers users on every node. Invisible users on
+    /// this node are left out, and not counted in the total.
+    ///
+    /// # Arguments
+    /// * `cursor` - Return users strictly after this key (None for the first page)
+    /// * `limit` - Maximum number of users on the page
+    /// * `prefix` - Only include keys starting with this (case-insensitive) prefix
+    pub async fn get_page(
+        &self,
+        cursor: Option<&str>,
+        limit: usize,
+        prefix: Option<&str>,
+    ) -> Result<LobbyPage, LobbyError> {
+        let prefix = prefix.map(str::to_lowercase).unwrap_or_default();
+        let hidden = self.invisible_keys().await;
+        let mut matching = self.online_keys().await;
+        matching.retain(|key| key.starts_with(&prefix) && !hidden.contains(key));
+        matching.sort_unstable();
+
+        let total = matching.len();
+        let start = match cursor {
+            Some(cursor) => matching.partition_point(|key| key.as_str() <= cursor),
+            None => 0,
+        };
+        let end = start.saturating_add(limit).min(total);
+        let users = matching[start..end].to_vec();
+        let next_cursor = if end < total {
+            users.last().cloned()
+        } else {
+            None
+        };
+
+        Ok(LobbyPage {
+            users,
+            next_cursor,
+            total,
+        })
+    }
+
+    /// Keys online on any node, or on this one if the cluster is unreachable
+    async fn online_keys(&self) -> Vec<ServerPublicKey> {
+        if let Some(cluster) = &self.cluster {
+            match cluster.online_keys().await {
+                Ok(keys) => return keys,
+                Err(e) => tracing::warn!(error = %e, "Listing local users only"),
+            }
+        }
+        self.users.keys().await
+    }
+
+    /// Keys of the users on this node who chose to be invisible
+    async fn invisible_keys(&self) -> HashSet<ServerPublicKey> {
+        self.users
+            .connections()
+            .await
+            .into_iter()
+            .filter(|conn| conn.status.is_invisible())
+            .map(|conn| conn.public_key.clone())
+            .collect()
+    }
+
+    /// Whether `key` is online on this node and invisible
+    pub async fn is_invisible(&self, key: &str) -> bool {
+        self.users
+            .get(key)
+            .await
+            .is_some_and(|conn| conn.status.is_invisible())
+    }
+
+    /// Statuses of the users among `keys` who are on this node and chose
+    /// something other than online
+    pub async fn statuses(&self, keys: &[ServerPublicKey]) -> HashMap<ServerPublicKey, Status> {
+        let mut statuses = HashMap::new();
+        for key in keys {
+            if let Some(conn) = self.users.get(key).await {
+                let status = conn.status.get();
+                if status != Status::Online {
+                    statuses.insert(key.clone(), status);
+                }
+            }
+        }
+        statuses
     }
 
     /// Check if a user is in lobby
     pub async fn user_exists(&self, public_key: &ServerPublicKey) -> Result<bool, LobbyError> {
-        let users = self.users.read().await;
-        Ok(users.contains_key(public_key))
+        Ok(self.users.contains_key(public_key).await)
     }
 
     /// Get number of online users
     pub async fn user_count(&self) -> Result<usize, LobbyError> {
-        let users = self.users.read().await;
-        Ok(users.len())
+        Ok(self.users.len())
     }
 
     /// Get all current connections as Arc wrappers (for broadcasting to all users)
     pub async fn get_all_connections(&self) -> Result<Vec<Arc<ActiveConnection>>, LobbyError> {
-        let users = self.users.read().await;
-        Ok(users.values().cloned().collect())
+        Ok(self.users.connections().await)
     }
 }
 
@@ -87,7 +743,14 @@ impl Default for Lobby {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio::sync::mpsc;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+
+    #[test]
+    fn test_normalize_key() {
+        assert_eq!(normalize_key(&"AB".repeat(32)), Some("ab".repeat(32)));
+        assert_eq!(normalize_key(&"ab".repeat(31)), None);
+        assert_eq!(normalize_key(&"zz".repeat(32)), None);
+    }
 
     #[tokio::test]
     async fn test_public_key_type_alias() {
@@ -100,12 +763,13 @@ mod tests {
         let public_key = "test_key_123".to_string();
 
         // Create mpsc channel for sender
-        let (sender, _) = mpsc::unbounded_channel::<Message>();
+        let (sender, _) = send_queue();
 
         let connection = ActiveConnection {
             public_key: public_key.clone(),
             sender,
             connection_id: 42,
+            status: Default::default(),
         };
 
         assert_eq!(connection.public_key, public_key);
@@ -123,11 +787,12 @@ mod tests {
         assert!(!lobby.user_exists(&public_key).await.unwrap());
 
         // Add user
-        let (sender, _) = mpsc::unbounded_channel::<Message>();
+        let (sender, _) = send_queue();
         let connection = ActiveConnection {
             public_key: public_key.clone(),
             sender,
             connection_id: 1,
+            status: Default::default(),
         };
 
         lobby.add_user(connection).await.unwrap();
@@ -140,6 +805,169 @@ mod tests {
         assert!(!lobby.user_exists(&public_key).await.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_get_page_walks_all_users_in_order() {
+        let lobby = Lobby::new();
+        for key in ["cc", "aa", "bb", "ab", "dd"] {
+            let (sender, _) = send_queue();
+            lobby
+                .add_user(ActiveConnection {
+                    public_key: key.to_string(),
+                    sender,
+                    connection_id: 1,
+                    status: Default::default(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let first = lobby.get_page(None, 2, None).await.unwrap();
+        assert_eq!(first.users, vec!["aa", "ab"]);
+        assert_eq!(first.next_cursor.as_deref(), Some("ab"));
+        assert_eq!(first.total, 5);
+
+        let second = lobby
+            .get_page(first.next_cursor.as_deref(), 2, None)
+            .await
+            .unwrap();
+        assert_eq!(second.users, vec!["bb", "cc"]);
+
+        let last = lobby
+            .get_page(second.next_cursor.as_deref(), 2, None)
+            .await
+            .unwrap();
+        assert_eq!(last.users, vec!["dd"]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[tokio::test]
+    async fn test_get_page_filters_by_prefix() {
+        let lobby = Lobby::new();
+        for key in ["abc1", "abc2", "abd1", "bcd1"] {
+            let (sender, _) = send_queue();
+            lobby
+                .add_user(ActiveConnection {
+                    public_key: key.to_string(),
+                    sender,
+                    connection_id: 1,
+                    status: Default::default(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let page = lobby.get_page(None, 10, Some("ABC")).await.unwrap();
+        assert_eq!(page.users, vec!["abc1", "abc2"]);
+        assert_eq!(page.total, 2);
+        assert_eq!(page.next_cursor, None);
+
+        let empty = lobby.get_page(None, 10, Some("ff")).await.unwrap();
+        assert!(empty.users.is_empty());
+        assert_eq!(empty.total, 0);
+    }
+
+    fn conn(key: &str) -> (Arc<ActiveConnection>, OutboundQueue) {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        (Arc::new(conn), receiver)
+    }
+
+    #[tokio::test]
+    async fn test_shards_track_len_across_insert_and_remove() {
+        let users = UserShards::new(4);
+        for key in ["a", "b", "c", "d", "e"] {
+            assert!(users.insert(conn(key).0).await.is_none());
+        }
+        assert_eq!(users.len(), 5);
+
+        // Replacing a connection does not change the count
+        assert!(users.insert(conn("c").0).await.is_some());
+        assert_eq!(users.len(), 5);
+
+        assert!(users.remove("c").await.is_some());
+        assert!(users.remove("c").await.is_none());
+        assert_eq!(users.len(), 4);
+
+        let mut keys = users.keys().await;
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b", "d", "e"]);
+    }
+
+    #[tokio::test]
+    async fn test_insert_bounded_allows_replacement_when_full() {
+        let users = UserShards::new(2);
+        users.insert_bounded(conn("a").0, 2).await.unwrap();
+        users.insert_bounded(conn("b").0, 2).await.unwrap();
+
+        assert!(matches!(
+            users.insert_bounded(conn("c").0, 2).await,
+            Err(LobbyError::LobbyFull)
+        ));
+        assert!(users
+            .insert_bounded(conn("a").0, 2)
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(users.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_skips_excluded_user() {
+        let lobby = Lobby::new();
+        let (alice, mut alice_rx) = conn("alice");
+        let (bob, mut bob_rx) = conn("bob");
+        lobby.users.insert(alice).await;
+        lobby.users.insert(bob).await;
+
+        lobby
+            .broadcast(
+                Message::LobbyUpdate {
+                    joined: vec![],
+                    left: vec!["alice".to_string()],
+                },
+                Some("alice"),
+            )
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+
+        assert!(matches!(bob_rx.try_recv(), Ok(Message::LobbyUpdate { .. })));
+        assert!(alice_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_skips_users_joining_after_it_was_queued() {
+        let lobby = Lobby::new();
+        let (alice, mut alice_rx) = conn("alice");
+        lobby.users.insert(alice).await;
+
+        lobby
+            .broadcast(
+                Message::LobbyUpdate {
+                    joined: vec![],
+                    left: vec!["bob".to_string()],
+                },
+                None,
+            )
+            .unwrap();
+        let (carol, mut carol_rx) = conn("carol");
+        lobby.users.insert(carol).await;
+        lobby.flush_broadcasts().await.unwrap();
+
+        assert!(alice_rx.try_recv().is_ok());
+        assert!(carol_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_flush_without_broadcasts_returns_immediately() {
+        let lobby = Lobby::new();
+        lobby.flush_broadcasts().await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_lobby_creation() {
         let lobby = Lobby::new();
@@ -159,3 +987,149 @@ mod tests {
         assert_eq!(result, 0); // Empty lobby has 0 users
     }
 }
+
+/// Model-checked interleavings of the user map; see `lobby::sync` for how
+/// to run them
+#[cfg(all(test, profile_loom))]
+mod loom_tests {
+    use super::*;
+    use crate::connection::send_queue::send_queue;
+    use loom::future::block_on;
+    use loom::thread;
+
+    fn connection(key: &str) -> Arc<ActiveConnection> {
+        let (sender, _) = send_queue();
+        Arc::new(ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: 0,
+            status: Default::default(),
+        })
+    }
+
+    fn includes(senders: &[ConnectionSender], conn: &ActiveConnection) -> bool {
+        senders.iter().any(|s| s.same_channel(&conn.sender))
+    }
+
+    /// Join a user and collect the recipients of their join broadcast,
+    /// the same way `add_user` followed by `Lobby::broadcast` does
+    fn join_and_announce(users: &UserShards, conn: Arc<ActiveConnection>) -> Vec<ConnectionSender> {
+        let key = conn.public_key.clone();
+        block_on(users.insert(conn));
+        let fence = users.next_seq();
+        block_on(users.senders_before(fence, Some(&key)))
+    }
+
+    #[test]
+    fn loom_racing_removes_count_once() {
+        loom::model(|| {
+            let users = Arc::new(UserShards::new(1));
+            block_on(users.insert(connection("alice")));
+
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    let users = users.clone();
+                    thread::spawn(move || block_on(users.remove("alice")).is_some())
+                })
+                .collect();
+            let removed = handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .filter(|removed| *removed)
+                .count();
+
+            assert_eq!(removed, 1);
+            assert_eq!(users.len(), 0);
+        });
+    }
+
+    #[test]
+    fn loom_join_and_leave_keep_count_in_sync() {
+        loom::model(|| {
+            let users = Arc::new(UserShards::new(2));
+            block_on(users.insert(connection("alice")));
+
+            let joiner = {
+                let users = users.clone();
+                thread::spawn(move || block_on(users.insert(connection("bob"))))
+            };
+            block_on(users.remove("alice"));
+            joiner.join().unwrap();
+
+            assert_eq!(users.len(), 1);
+            assert_eq!(block_on(users.keys()), vec!["bob".to_string()]);
+        });
+    }
+
+    #[test]
+    fn loom_bounded_inserts_never_overfill() {
+        loom::model(|| {
+            let users = Arc::new(UserShards::new(2));
+
+            let handles: Vec<_> = ["alice", "bob"]
+                .into_iter()
+                .map(|key| {
+                    let users = users.clone();
+                    thread::spawn(move || block_on(users.insert_bounded(connection(key), 1)))
+                })
+                .collect();
+            let admitted = handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .filter(Result::is_ok)
+                .count();
+
+            assert_eq!(admitted, 1);
+            assert_eq!(users.len(), 1);
+        });
+    }
+
+    /// Two users joining at once must not both miss each other's join:
+    /// whoever took the earlier sequence number is always in the other's
+    /// broadcast
+    #[test]
+    fn loom_concurrent_joins_are_not_lost() {
+        loom::model(|| {
+            let users = Arc::new(UserShards::new(2));
+            let alice = connection("alice");
+            let bob = connection("bob");
+
+            let other = {
+                let users = users.clone();
+                let bob = bob.clone();
+                thread::spawn(move || join_and_announce(&users, bob))
+            };
+            let alice_announced_to = join_and_announce(&users, alice.clone());
+            let bob_announced_to = other.join().unwrap();
+
+            assert!(
+                includes(&alice_announced_to, &bob) || includes(&bob_announced_to, &alice),
+                "both join broadcasts missed the other user"
+            );
+        });
+    }
+
+    /// A user whose join finished before a broadcast was issued always
+    /// receives it, even while other users join and leave
+    #[test]
+    fn loom_broadcast_reaches_users_joined_before_it() {
+        loom::model(|| {
+            let users = Arc::new(UserShards::new(2));
+            let alice = connection("alice");
+            block_on(users.insert(alice.clone()));
+
+            let churn = {
+                let users = users.clone();
+                thread::spawn(move || {
+                    block_on(users.insert(connection("bob")));
+                    block_on(users.remove("bob"));
+                })
+            };
+            let fence = users.next_seq();
+            let recipients = block_on(users.senders_before(fence, None));
+            churn.join().unwrap();
+
+            assert!(includes(&recipients, &alice));
+        });
+    }
+}
@@ -0,0 +1,48 @@
+//! Synchronization primitives used by the lobby's user map
+//!
+//! Normal builds use tokio's async `RwLock` and std atomics. Building with
+//! the `profile_loom` cfg swaps in loom's versions so that join, leave and
+//! broadcast interleavings can be model-checked (see `loom_tests` in
+//! `state.rs`):
+//!
+//! ```text
+//! RUSTFLAGS="--cfg profile_loom" cargo test -p profile-server --release --lib loom_
+//! ```
+//!
+//! The cfg is crate-specific because `--cfg loom` also switches tokio into
+//! its own loom mode, which only builds inside tokio's test suite. Tokio
+//! can't run under loom, so the loom `RwLock` keeps tokio's async `read` and
+//! `write` signatures and blocks the (modeled) thread instead of yielding.
+
+#[cfg(not(profile_loom))]
+pub(crate) use std::sync::atomic::{AtomicU64, AtomicUsize};
+#[cfg(not(profile_loom))]
+pub(crate) use tokio::sync::RwLock;
+
+#[cfg(profile_loom)]
+pub(crate) use loom::sync::atomic::{AtomicU64, AtomicUsize};
+#[cfg(profile_loom)]
+pub(crate) use loom_lock::RwLock;
+
+#[cfg(profile_loom)]
+mod loom_lock {
+    use loom::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+    /// Loom `RwLock` with tokio's async locking signatures
+    #[derive(Debug)]
+    pub(crate) struct RwLock<T>(loom::sync::RwLock<T>);
+
+    impl<T> RwLock<T> {
+        pub(crate) fn new(value: T) -> Self {
+            Self(loom::sync::RwLock::new(value))
+        }
+
+        pub(crate) async fn read(&self) -> RwLockReadGuard<'_, T> {
+            self.0.read().unwrap()
+        }
+
+        pub(crate) async fn write(&self) -> RwLockWriteGuard<'_, T> {
+            self.0.write().unwrap()
+        }
+    }
+}
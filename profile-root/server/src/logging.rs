@@ -0,0 +1,155 @@
+//! Log output configuration
+//!
+//! The server logs through `tracing`. `PROFILE_LOG_LEVEL` sets the filter
+//! (a level such as `debug`, or `tracing` directives such as
+//! `info,profile_server::message=debug`) and `PROFILE_LOG_FORMAT` picks
+//! human-readable `text` (the default) or one JSON object per line for log
+//! collectors. Every connection runs in a `connection` span carrying its
+//! `connection_id`, plus the truncated `public_key` once authenticated, so
+//! each log line can be traced back to the connection that caused it.
+
+use profile_shared::config;
+use std::fmt::{self, Display, Formatter};
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable holding the log filter
+pub const LOG_LEVEL_ENV_VAR: &str = "PROFILE_LOG_LEVEL";
+
+/// Environment variable selecting the log format (`text` or `json`)
+pub const LOG_FORMAT_ENV_VAR: &str = "PROFILE_LOG_FORMAT";
+
+/// How log lines are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Error for an invalid logging setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogConfigError {
+    /// The filter could not be parsed
+    InvalidLevel(String),
+    /// The format is not `text` or `json`
+    InvalidFormat(String),
+}
+
+impl Display for LogConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LogConfigError::InvalidLevel(level) => {
+                write!(f, "Invalid {}: {:?}", LOG_LEVEL_ENV_VAR, level)
+            }
+            LogConfigError::InvalidFormat(format) => write!(
+                f,
+                "Invalid {}: {:?} (expected \"text\" or \"json\")",
+                LOG_FORMAT_ENV_VAR, format
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LogConfigError {}
+
+/// Log filter and format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+impl LogConfig {
+    /// Read the configuration from `PROFILE_LOG_LEVEL` and `PROFILE_LOG_FORMAT`
+    pub fn from_env() -> Result<Self, LogConfigError> {
+        Self::parse(
+            std::env::var(LOG_LEVEL_ENV_VAR).ok().as_deref(),
+            std::env::var(LOG_FORMAT_ENV_VAR).ok().as_deref(),
+        )
+    }
+
+    /// Build a configuration from raw setting values, None or empty for defaults
+    pub fn parse(level: Option<&str>, format: Option<&str>) -> Result<Self, LogConfigError> {
+        let filter = match level.map(str::trim).filter(|l| !l.is_empty()) {
+            Some(level) => {
+                EnvFilter::try_new(level)
+                    .map_err(|_| LogConfigError::InvalidLevel(level.to_string()))?;
+                level.to_string()
+            }
+            None => config::server::DEFAULT_LOG_LEVEL.to_string(),
+        };
+        let format = match format.map(|f| f.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("text") => LogFormat::Text,
+            Some("json") => LogFormat::Json,
+            Some(other) => return Err(LogConfigError::InvalidFormat(other.to_string())),
+        };
+        Ok(Self { filter, format })
+    }
+
+    /// Install the global subscriber
+    ///
+    /// Fails if a subscriber is already installed.
+    pub fn init(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let filter = EnvFilter::try_new(&self.filter)?;
+        let builder = tracing_subscriber::fmt().with_env_filter(filter);
+        match self.format {
+            LogFormat::Text => builder.try_init(),
+            // Include the connection span's fields on every line
+            LogFormat::Json => builder
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .try_init(),
+        }
+    }
+}
+
+/// Span for everything done on behalf of one connection
+///
+/// `public_key` starts empty and is recorded with [`record_public_key`]
+/// once the connection authenticates.
+pub fn connection_span(connection_id: u64) -> tracing::Span {
+    tracing::info_span!(
+        "connection",
+        connection_id,
+        public_key = tracing::field::Empty
+    )
+}
+
+/// Record the authenticated user's key, truncated, on the current connection span
+pub fn record_public_key(public_key: &str) {
+    tracing::Span::current().record("public_key", &public_key[..16.min(public_key.len())]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defaults_and_formats() {
+        assert_eq!(
+            LogConfig::parse(None, None),
+            Ok(LogConfig {
+                filter: "info".to_string(),
+                format: LogFormat::Text,
+            })
+        );
+        let config = LogConfig::parse(Some(" info,profile_server=debug "), Some("JSON")).unwrap();
+        assert_eq!(config.filter, "info,profile_server=debug");
+        assert_eq!(config.format, LogFormat::Json);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_values() {
+        assert!(matches!(
+            LogConfig::parse(Some("[[nope"), None),
+            Err(LogConfigError::InvalidLevel(_))
+        ));
+        assert_eq!(
+            LogConfig::parse(None, Some("xml")),
+            Err(LogConfigError::InvalidFormat("xml".to_string()))
+        );
+    }
+}
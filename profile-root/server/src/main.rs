@@ -5,24 +5,205 @@
 //!
 //! TODO: Add HTTP health check endpoint at /health for monitoring
 
+use profile_server::admin::{self, AdminConfig, ADMIN_TOKEN_ENV_VAR};
+use profile_server::audit::{AuditLog, AUDIT_LOG_ENV_VAR};
+use profile_server::cluster::{self, CLUSTER_REDIS_URL_ENV_VAR};
 use profile_server::connection;
+use profile_server::connection::chaos::{ChaosConfig, CHAOS_ENV_VAR};
+use profile_server::echo_bot::{EchoBot, ECHO_BOT_ENV_VAR};
 use profile_server::lobby::Lobby;
+use profile_server::logging::LogConfig;
+use profile_server::message::netsim::{NetsimConfig, NETSIM_ENV_VAR};
+use profile_server::moderation::{Moderation, BAN_FILE_ENV_VAR};
+use profile_server::names::{NameRegistry, NAME_FILE_ENV_VAR};
+use profile_server::presence::{self, PresenceStore, PRESENCE_FILE_ENV_VAR};
+use profile_server::presence_api::{self, PresenceApiConfig, PRESENCE_API_TOKEN_ENV_VAR};
 use profile_server::rate_limiter::AuthRateLimiter;
+use profile_server::rooms::Rooms;
+use profile_server::runtime::RuntimeConfig;
+use profile_server::snapshot::{
+    self, FileSnapshotStore, SnapshotConfig, SnapshotStore, SNAPSHOT_FILE_ENV_VAR,
+};
+use profile_shared::capture::{CaptureWriter, CAPTURE_ENV_VAR};
 use profile_shared::config;
 use std::sync::Arc;
 use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Thread pools are sized before the runtime that owns them is built
+    let runtime_config = RuntimeConfig::from_env()?;
+    runtime_config
+        .build_runtime()?
+        .block_on(run(runtime_config))
+}
+
+async fn run(
+    runtime_config: RuntimeConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    LogConfig::from_env()?.init()?;
 
     tracing::info!("Profile Server starting...");
+    tracing::info!(
+        worker_threads = runtime_config.worker_threads,
+        max_blocking_threads = runtime_config.max_blocking_threads,
+        verification_workers = runtime_config.verification_workers,
+        max_pending_verifications = runtime_config.max_pending_verifications,
+        max_connections = runtime_config.max_connections,
+        admission_rate = runtime_config.admission_rate,
+        admission_queue = runtime_config.admission_queue,
+        "Runtime topology"
+    );
 
-    let lobby = Arc::new(Lobby::new());
+    // Bans are kept across restarts only when a ban file is configured
+    let moderation = Moderation::from_env()?;
+    if std::env::var_os(BAN_FILE_ENV_VAR).is_some() {
+        tracing::info!(
+            env = BAN_FILE_ENV_VAR,
+            bans = moderation.banned_count().await,
+            "Loaded ban list"
+        );
+    }
+    // Lobby presence is kept across restarts only when a presence file is configured
+    let presence = Arc::new(PresenceStore::from_env()?);
+    if std::env::var_os(PRESENCE_FILE_ENV_VAR).is_some() {
+        tracing::info!(
+            env = PRESENCE_FILE_ENV_VAR,
+            epoch = presence.epoch(),
+            returning_users = presence.returning_count().await,
+            "Loaded lobby presence"
+        );
+        presence::spawn_flush_task(Arc::clone(&presence), presence::PRESENCE_FLUSH_INTERVAL);
+    }
+    // Names can be claimed only when a name file is configured
+    let names = NameRegistry::from_env()?;
+    if names.is_enabled() {
+        tracing::info!(
+            env = NAME_FILE_ENV_VAR,
+            names = names.len().await,
+            "Loaded name registry"
+        );
+    }
+    // Audit events are written only when an audit log file is configured
+    let audit = AuditLog::from_env()?;
+    if let Some(path) = audit.path() {
+        tracing::info!(env = AUDIT_LOG_ENV_VAR, path = %path.display(), "Audit log enabled");
+    }
+    let mut lobby = Lobby::new()
+        .with_moderation(moderation)
+        .with_audit_log(audit)
+        .with_names(names)
+        .with_presence(Arc::clone(&presence))
+        .with_verification_queue(runtime_config.verification_queue())
+        .with_admission_queue(runtime_config.admission_queue());
+    // Debug builds only: delay and lose direct messages to exercise clients
+    if let Some(config) = NetsimConfig::from_env()? {
+        tracing::warn!(
+            env = NETSIM_ENV_VAR,
+            ?config,
+            "Simulated routing latency and loss enabled"
+        );
+        lobby = lobby.with_netsim(config);
+    }
+    // Several instances share one lobby only when a cluster backend is configured
+    if let Some(backend) = cluster::from_env().await? {
+        tracing::info!(
+            env = CLUSTER_REDIS_URL_ENV_VAR,
+            node = backend.node_id(),
+            "Joined lobby cluster"
+        );
+        lobby = lobby.with_cluster(backend);
+        cluster::start(&lobby).await?;
+    }
+    // State is kept for a standby to take over only when a snapshot file is configured
+    let snapshots = match SnapshotConfig::from_env()? {
+        Some(snapshot_config) => {
+            let store: Arc<dyn SnapshotStore> =
+                Arc::new(FileSnapshotStore::new(&snapshot_config.path));
+            if let Some(state) = store.load().await? {
+                let taken_at = state.taken_at.clone();
+                let summary = snapshot::restore(&lobby, state).await?;
+                tracing::info!(
+                    env = SNAPSHOT_FILE_ENV_VAR,
+                    %taken_at,
+                    ?summary,
+                    "Restored state snapshot"
+                );
+            }
+            Some((store, snapshot_config.interval))
+        }
+        None => None,
+    };
+    let lobby = Arc::new(lobby);
+    if let Some((store, interval)) = &snapshots {
+        snapshot::spawn_snapshot_task(Arc::clone(&lobby), Arc::clone(store), *interval);
+    }
+    let connection_slots = Arc::new(Semaphore::new(runtime_config.max_connections));
+    let rooms = Arc::new(Rooms::new());
     let rate_limiter = Arc::new(AuthRateLimiter::new());
 
+    // Opt-in debug capture of every WebSocket frame (secrets redacted)
+    let capture = CaptureWriter::from_env()?;
+    if capture.is_some() {
+        tracing::warn!(
+            env = CAPTURE_ENV_VAR,
+            "Frame capture enabled - all traffic is being written to disk"
+        );
+    }
+
+    // Debug builds only: inject network faults into every connection
+    let chaos = ChaosConfig::from_env()?;
+    if let Some(ref config) = chaos {
+        tracing::warn!(
+            env = CHAOS_ENV_VAR,
+            ?config,
+            "Chaos fault injection enabled"
+        );
+    }
+
+    // Debug builds only: a virtual user that echoes every direct message
+    if let Some(bot) = EchoBot::from_env() {
+        bot.spawn(Arc::clone(&lobby)).await?;
+        tracing::warn!(
+            env = ECHO_BOT_ENV_VAR,
+            public_key = config::echo_bot::PUBLIC_KEY,
+            "Echo bot joined the lobby"
+        );
+    }
+
+    // Admin control API on a separate loopback socket, off unless a token is set
+    match AdminConfig::from_env()? {
+        Some(admin_config) => {
+            let admin_listener = TcpListener::bind(admin_config.address).await?;
+            tracing::info!(address = %admin_config.address, "Admin API listening");
+            let (lobby, rooms) = (Arc::clone(&lobby), Arc::clone(&rooms));
+            tokio::spawn(async move {
+                if let Err(e) =
+                    admin::serve(admin_listener, Arc::new(admin_config), lobby, rooms).await
+                {
+                    tracing::error!(error = %e, "Admin API stopped");
+                }
+            });
+        }
+        None => tracing::debug!(env = ADMIN_TOKEN_ENV_VAR, "Admin API disabled"),
+    }
+
+    // Read-only HTTP presence API for dashboards and bots, off unless a token is set
+    match PresenceApiConfig::from_env()? {
+        Some(api_config) => {
+            let api_listener = TcpListener::bind(api_config.address).await?;
+            tracing::info!(address = %api_config.address, "Presence API listening");
+            let lobby = Arc::clone(&lobby);
+            tokio::spawn(async move {
+                if let Err(e) = presence_api::serve(api_listener, Arc::new(api_config), lobby).await
+                {
+                    tracing::error!(error = %e, "Presence API stopped");
+                }
+            });
+        }
+        None => tracing::debug!(env = PRESENCE_API_TOKEN_ENV_VAR, "Presence API disabled"),
+    }
+
     let listener = TcpListener::bind(config::server::BIND_ADDRESS).await?;
     tracing::info!(
         bind_address = config::server::BIND_ADDRESS,
@@ -36,22 +217,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         tokio::select! {
             _ = tokio::signal::ctrl_c() => {
                 tracing::info!("Shutdown signal received, exiting gracefully");
+                // Keys still connected now are told about the restart when
+                // they return, and admitted first
+                if let Err(e) = presence.seal().await {
+                    tracing::error!(error = %e, "Failed to save lobby presence");
+                }
+                if let Some((store, _)) = &snapshots {
+                    if let Err(e) = snapshot::save_snapshot(&lobby, store.as_ref()).await {
+                        tracing::error!(error = %e, "Failed to save state snapshot");
+                    }
+                }
                 break;
             }
             result = listener.accept() => {
                 match result {
                     Ok((stream, addr)) => {
                         accept_errors = 0;
+                        let Ok(slot) = Arc::clone(&connection_slots).try_acquire_owned() else {
+                            tracing::warn!(
+                                client_ip = %addr,
+                                max_connections = runtime_config.max_connections,
+                                "Connection limit reached, refusing connection"
+                            );
+                            continue;
+                        };
                         tracing::info!(client_ip = %addr, "New connection");
 
                         let lobby_clone = Arc::clone(&lobby);
+                        let rooms_clone = Arc::clone(&rooms);
                         let rate_limiter_clone = Arc::clone(&rate_limiter);
+                        let capture_clone = capture.clone();
+                        let chaos_clone = chaos.clone();
 
                         tokio::spawn(async move {
+                            // Held until the connection closes
+                            let _slot = slot;
                             if let Err(e) = connection::handler::handle_connection(
                                 stream,
                                 lobby_clone,
+                                rooms_clone,
                                 rate_limiter_clone,
+                                capture_clone,
+                                chaos_clone,
                             )
                             .await
                             {
@@ -83,5 +290,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         }
     }
 
+    // Close every connection with a jittered retry hint, then give the close
+    // frames a moment to go out; each connection holds its slot until done
+    lobby.begin_shutdown();
+    let max_connections = u32::try_from(runtime_config.max_connections).unwrap_or(u32::MAX);
+    if tokio::time::timeout(
+        config::server::SHUTDOWN_TIMEOUT,
+        connection_slots.acquire_many(max_connections),
+    )
+    .await
+    .is_err()
+    {
+        tracing::warn!("Connections still open after the shutdown timeout");
+    }
+
     Ok(())
 }
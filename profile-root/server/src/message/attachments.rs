@@ -0,0 +1,519 @@
+//! Attachment request handling
+//!
+//! Dispatches `attachment_offer`, `attachment_accept`, `attachment_chunk`
+//! and `attachment_complete` requests to the [`crate::attachments`]
+//! registry, and forwards each to the other side of its transfer:
+//!
+//! 1. The sender offers a signed [`AttachmentManifest`]; the server checks
+//!    its limits and signature and forwards it as a
+//!    [`profile_shared::Message::AttachmentOffer`].
+//! 2. The recipient accepts by asking for the chunks below an index, a
+//!    window at a time.
+//! 3. The sender sends the chunks asked for, hex-encoded and in order. The
+//!    server checks each one's length against the manifest; the recipient
+//!    checks its hash.
+//! 4. The sender completes the transfer once every chunk is sent. Either
+//!    side can cancel it at any point with the same request.
+//!
+//! The server stores none of the file. Both users must be connected to the
+//! same node; a recipient on another node is treated as offline.
+//!
+//! [`AttachmentManifest`]: profile_shared::protocol::AttachmentManifest
+
+use crate::audit::AuditEvent;
+use crate::lobby::Lobby;
+use crate::message::pipeline::{
+    JsonRequest, MessageContext, StageFuture, Timestamped, ValidationPipeline, ValidationStage,
+};
+use crate::message::{message_type, validate_signature_queued, ValidationError};
+use crate::protocol::{
+    AttachmentAcceptRequest, AttachmentChunkRequest, AttachmentCompleteRequest,
+    AttachmentOfferRequest,
+};
+use profile_shared::config::attachments::{MAX_CHUNK_REQUEST_SIZE, MAX_OFFER_SIZE};
+use profile_shared::{AttachmentError, Message};
+
+/// Request type for offering a file
+pub const ATTACHMENT_OFFER_TYPE: &str = "attachment_offer";
+/// Request type for asking for an offered file's chunks
+pub const ATTACHMENT_ACCEPT_TYPE: &str = "attachment_accept";
+/// Request type for sending one chunk of a file
+pub const ATTACHMENT_CHUNK_TYPE: &str = "attachment_chunk";
+/// Request type for finishing or cancelling a transfer
+pub const ATTACHMENT_COMPLETE_TYPE: &str = "attachment_complete";
+
+/// Check whether a client message type is an attachment request
+pub fn is_attachment_request(message_type: &str) -> bool {
+    matches!(
+        message_type,
+        ATTACHMENT_OFFER_TYPE
+            | ATTACHMENT_ACCEPT_TYPE
+            | ATTACHMENT_CHUNK_TYPE
+            | ATTACHMENT_COMPLETE_TYPE
+    )
+}
+
+impl JsonRequest for AttachmentOfferRequest {
+    const MAX_SIZE: usize = MAX_OFFER_SIZE;
+}
+
+impl Timestamped for AttachmentOfferRequest {
+    fn timestamp(request: &Self) -> &str {
+        &request.manifest.timestamp
+    }
+}
+
+impl JsonRequest for AttachmentAcceptRequest {}
+
+impl JsonRequest for AttachmentChunkRequest {
+    const MAX_SIZE: usize = MAX_CHUNK_REQUEST_SIZE;
+}
+
+impl JsonRequest for AttachmentCompleteRequest {}
+
+/// Handle an attachment request from an authenticated user
+///
+/// Offers run the [`ValidationPipeline::timestamped`] stages, the other
+/// requests the [`ValidationPipeline::authenticated`] ones, each followed by
+/// a final stage for the request type.
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key of the requesting user
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the request was forwarded, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_attachment_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    match message_type(request_json).as_deref() {
+        Some(ATTACHMENT_OFFER_TYPE) => {
+            ValidationPipeline::<AttachmentOfferRequest>::timestamped()
+                .with_stage(OfferAttachment)
+                .validate(lobby, sender_public_key, request_json)
+                .await?;
+        }
+        Some(ATTACHMENT_ACCEPT_TYPE) => {
+            ValidationPipeline::<AttachmentAcceptRequest>::authenticated()
+                .with_stage(AcceptAttachment)
+                .validate(lobby, sender_public_key, request_json)
+                .await?;
+        }
+        Some(ATTACHMENT_CHUNK_TYPE) => {
+            ValidationPipeline::<AttachmentChunkRequest>::authenticated()
+                .with_stage(RelayChunk)
+                .validate(lobby, sender_public_key, request_json)
+                .await?;
+        }
+        Some(ATTACHMENT_COMPLETE_TYPE) => {
+            ValidationPipeline::<AttachmentCompleteRequest>::authenticated()
+                .with_stage(CompleteAttachment)
+                .validate(lobby, sender_public_key, request_json)
+                .await?;
+        }
+        _ => {
+            return Err(ValidationError::MalformedJson {
+                details: "Unknown attachment request type".to_string(),
+            })
+        }
+    }
+    Ok(())
+}
+
+fn rejected(error: AttachmentError) -> ValidationError {
+    ValidationError::AttachmentRejected { error }
+}
+
+/// Check an offer's manifest and signature, then register the transfer and
+/// forward the offer
+#[derive(Debug, Clone, Copy)]
+pub struct OfferAttachment;
+
+impl ValidationStage<AttachmentOfferRequest> for OfferAttachment {
+    fn name(&self) -> &'static str {
+        ATTACHMENT_OFFER_TYPE
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, AttachmentOfferRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.take_request()?;
+            let (lobby, sender_public_key) = (ctx.lobby, ctx.sender_public_key);
+            let manifest = request.manifest;
+            if request.recipient_public_key == sender_public_key {
+                return Err(ValidationError::CannotMessageSelf);
+            }
+            manifest.check().map_err(rejected)?;
+            validate_signature_queued(
+                lobby,
+                sender_public_key,
+                manifest.signed_bytes(),
+                &manifest.signature,
+            )
+            .await?;
+
+            // Chunks are relayed by the node holding the transfer
+            if lobby
+                .users
+                .get(&request.recipient_public_key)
+                .await
+                .is_none()
+            {
+                return Err(ValidationError::RecipientOffline {
+                    recipient_key: request.recipient_public_key,
+                });
+            }
+            lobby
+                .attachments
+                .offer(sender_public_key, &request.recipient_public_key, &manifest)
+                .await
+                .map_err(rejected)?;
+
+            let transfer_id = manifest.transfer_id;
+            let offer = Message::new_attachment_offer(manifest, sender_public_key.to_string());
+            let forwarded = forward(
+                lobby,
+                sender_public_key,
+                &request.recipient_public_key,
+                offer,
+            )
+            .await;
+            if forwarded.is_err() {
+                let _ = lobby
+                    .attachments
+                    .complete(sender_public_key, transfer_id, true)
+                    .await;
+            }
+            forwarded
+        })
+    }
+}
+
+/// Widen the chunk window of a transfer and tell the sender
+#[derive(Debug, Clone, Copy)]
+pub struct AcceptAttachment;
+
+impl ValidationStage<AttachmentAcceptRequest> for AcceptAttachment {
+    fn name(&self) -> &'static str {
+        ATTACHMENT_ACCEPT_TYPE
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, AttachmentAcceptRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.take_request()?;
+            let (lobby, sender_public_key) = (ctx.lobby, ctx.sender_public_key);
+            let (peer, until) = lobby
+                .attachments
+                .accept(sender_public_key, request.transfer_id, request.until)
+                .await
+                .map_err(rejected)?;
+            let accept = Message::new_attachment_accept(
+                request.transfer_id,
+                until,
+                sender_public_key.to_string(),
+            );
+            forward(lobby, sender_public_key, &peer, accept).await
+        })
+    }
+}
+
+/// Check a chunk against its transfer, then relay it to the recipient
+#[derive(Debug, Clone, Copy)]
+pub struct RelayChunk;
+
+impl ValidationStage<AttachmentChunkRequest> for RelayChunk {
+    fn name(&self) -> &'static str {
+        ATTACHMENT_CHUNK_TYPE
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, AttachmentChunkRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.take_request()?;
+            let (lobby, sender_public_key) = (ctx.lobby, ctx.sender_public_key);
+            let invalid = AttachmentError::InvalidChunk {
+                index: request.index,
+            };
+            if !request.data.len().is_multiple_of(2)
+                || !request.data.bytes().all(|b| b.is_ascii_hexdigit())
+            {
+                return Err(rejected(invalid));
+            }
+            let peer = lobby
+                .attachments
+                .chunk(
+                    sender_public_key,
+                    request.transfer_id,
+                    request.index,
+                    request.data.len() / 2,
+                )
+                .await
+                .map_err(rejected)?;
+            let chunk = Message::new_attachment_chunk(
+                request.transfer_id,
+                request.index,
+                request.data,
+                sender_public_key.to_string(),
+            );
+            forward(lobby, sender_public_key, &peer, chunk).await
+        })
+    }
+}
+
+/// Finish or cancel a transfer and tell the other side
+#[derive(Debug, Clone, Copy)]
+pub struct CompleteAttachment;
+
+impl ValidationStage<AttachmentCompleteRequest> for CompleteAttachment {
+    fn name(&self) -> &'static str {
+        ATTACHMENT_COMPLETE_TYPE
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, AttachmentCompleteRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.take_request()?;
+            let (lobby, sender_public_key) = (ctx.lobby, ctx.sender_public_key);
+            let end = lobby
+                .attachments
+                .complete(sender_public_key, request.transfer_id, request.cancelled)
+                .await
+                .map_err(rejected)?;
+            let complete = Message::new_attachment_complete(
+                request.transfer_id,
+                end.cancelled,
+                sender_public_key.to_string(),
+            );
+            forward(lobby, sender_public_key, &end.peer, complete).await
+        })
+    }
+}
+
+/// Deliver `message` to the other side of a transfer
+async fn forward(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    peer: &str,
+    message: Message,
+) -> Result<(), ValidationError> {
+    if crate::message::deliver(lobby, peer, message).await {
+        return Ok(());
+    }
+    lobby.audit.record(AuditEvent::RoutingFailed {
+        sender_public_key: sender_public_key.to_string(),
+        recipient_public_key: peer.to_string(),
+        reason: "Recipient went offline".to_string(),
+    });
+    Err(ValidationError::RecipientOffline {
+        recipient_key: peer.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use profile_shared::config::attachments::CHUNK_SIZE;
+    use profile_shared::protocol::AttachmentManifest;
+    use profile_shared::{derive_public_key, generate_private_key};
+    use uuid::Uuid;
+
+    const RECIPIENT_KEY: &str = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+
+    async fn add_connection(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    /// Next message in `queue` that isn't a lobby update
+    fn next_message(queue: &mut OutboundQueue) -> Message {
+        loop {
+            match queue.try_recv().unwrap() {
+                Message::LobbyUpdate { .. } => {}
+                other => return other,
+            }
+        }
+    }
+
+    /// A sender key and an offer of `data` signed with it
+    fn signed_offer(data: &[u8]) -> (String, AttachmentManifest) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let manifest = AttachmentManifest::sign(
+            &private_key,
+            Uuid::new_v4(),
+            "plan.pdf",
+            data,
+            &chrono::Utc::now().to_rfc3339(),
+        )
+        .unwrap();
+        (public_key, manifest)
+    }
+
+    fn offer_request(manifest: &AttachmentManifest) -> String {
+        serde_json::json!({
+            "type": "attachment_offer",
+            "recipientPublicKey": RECIPIENT_KEY,
+            "manifest": manifest,
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_file_relayed_from_offer_to_completion() {
+        let lobby = Lobby::new();
+        let data = vec![42u8; CHUNK_SIZE + 100];
+        let (sender_key, manifest) = signed_offer(&data);
+        let id = manifest.transfer_id;
+        let mut sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let mut recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+
+        let offer = offer_request(&manifest);
+        assert!(is_attachment_request(&message_type(&offer).unwrap()));
+        handle_attachment_request(&lobby, &sender_key, &offer)
+            .await
+            .unwrap();
+        match next_message(&mut recipient_rx) {
+            Message::AttachmentOffer {
+                manifest: offered,
+                sender_public_key,
+            } => {
+                assert_eq!(offered, manifest);
+                assert_eq!(sender_public_key, sender_key);
+            }
+            other => panic!("Expected AttachmentOffer, got {:?}", other),
+        }
+
+        let accept =
+            serde_json::json!({"type": "attachment_accept", "transferId": id, "until": 16});
+        handle_attachment_request(&lobby, RECIPIENT_KEY, &accept.to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            next_message(&mut sender_rx),
+            Message::new_attachment_accept(id, 2, RECIPIENT_KEY.to_string())
+        );
+
+        for (index, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+            let request = serde_json::json!({
+                "type": "attachment_chunk",
+                "transferId": id,
+                "index": index,
+                "data": hex::encode(chunk),
+            });
+            handle_attachment_request(&lobby, &sender_key, &request.to_string())
+                .await
+                .unwrap();
+            match next_message(&mut recipient_rx) {
+                Message::AttachmentChunk {
+                    index: received,
+                    data,
+                    ..
+                } => {
+                    assert_eq!(received as usize, index);
+                    assert!(manifest.chunk_matches(received, &hex::decode(data).unwrap()));
+                }
+                other => panic!("Expected AttachmentChunk, got {:?}", other),
+            }
+        }
+
+        let complete = serde_json::json!({"type": "attachment_complete", "transferId": id});
+        handle_attachment_request(&lobby, &sender_key, &complete.to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            next_message(&mut recipient_rx),
+            Message::new_attachment_complete(id, false, sender_key.clone())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tampered_offer_rejected() {
+        let lobby = Lobby::new();
+        let (sender_key, mut manifest) = signed_offer(b"minutes");
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let _recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+
+        manifest.file_name = "minutes.exe".to_string();
+        let result =
+            handle_attachment_request(&lobby, &sender_key, &offer_request(&manifest)).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_of_wrong_length_rejected() {
+        let lobby = Lobby::new();
+        let (sender_key, manifest) = signed_offer(b"0123456789");
+        let id = manifest.transfer_id;
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let _recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+        handle_attachment_request(&lobby, &sender_key, &offer_request(&manifest))
+            .await
+            .unwrap();
+        let accept = serde_json::json!({"type": "attachment_accept", "transferId": id, "until": 1});
+        handle_attachment_request(&lobby, RECIPIENT_KEY, &accept.to_string())
+            .await
+            .unwrap();
+
+        let padded = serde_json::json!({
+            "type": "attachment_chunk",
+            "transferId": id,
+            "index": 0,
+            "data": hex::encode(b"0123456789 and more"),
+        });
+        let result = handle_attachment_request(&lobby, &sender_key, &padded.to_string()).await;
+        assert_eq!(
+            result,
+            Err(ValidationError::AttachmentRejected {
+                error: AttachmentError::InvalidChunk { index: 0 }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leaving_cancels_transfers() {
+        let lobby = Lobby::new();
+        let (sender_key, manifest) = signed_offer(b"draft");
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let mut recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+        handle_attachment_request(&lobby, &sender_key, &offer_request(&manifest))
+            .await
+            .unwrap();
+        next_message(&mut recipient_rx);
+
+        crate::lobby::remove_user(&lobby, &sender_key)
+            .await
+            .unwrap();
+        assert_eq!(
+            next_message(&mut recipient_rx),
+            Message::new_attachment_complete(manifest.transfer_id, true, sender_key)
+        );
+    }
+}
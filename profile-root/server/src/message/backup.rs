@@ -0,0 +1,252 @@
+//! Encrypted backup request handling
+//!
+//! Authenticated clients save their encrypted backup with a signed
+//! [`BackupStoreRequest`] and fetch it back, typically on a new device, with
+//! a [`BackupFetchRequest`]. The server stores the blob as-is in the lobby's
+//! [`crate::backup::BackupStore`] and answers with
+//! [`profile_shared::Message::BackupStored`] or
+//! [`profile_shared::Message::Backup`].
+
+use crate::lobby::{ActiveConnection, Lobby};
+use crate::message::{
+    message_type, validate_signature_queued, validate_timestamp, ValidationError,
+};
+use crate::protocol::{BackupFetchRequest, BackupStoreRequest};
+use profile_shared::config::backup::MAX_BACKUP_SIZE;
+use profile_shared::{canonical, BackupError, Message};
+use std::sync::Arc;
+
+/// Value of the `type` field identifying a backup store request
+pub const BACKUP_STORE_TYPE: &str = "backup_store";
+
+/// Value of the `type` field identifying a backup fetch request
+pub const BACKUP_FETCH_TYPE: &str = "backup_fetch";
+
+/// Largest accepted request: the hex-encoded blob plus room for the other fields
+const MAX_REQUEST_SIZE: usize = 2 * MAX_BACKUP_SIZE + 1024;
+
+/// Check whether a client message type is a backup store or fetch request
+pub fn is_backup_request(message_type: &str) -> bool {
+    matches!(message_type, BACKUP_STORE_TYPE | BACKUP_FETCH_TYPE)
+}
+
+/// Handle a backup store or fetch request from an authenticated user
+///
+/// A store must be signed over [`canonical::backup_store`], so a signed
+/// chat message or nickname change can't be replayed as a backup.
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users and the backup store
+/// * `sender_public_key` - The public key of the requesting user
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the backup was stored or sent, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_backup_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    if request_json.len() > MAX_REQUEST_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_REQUEST_SIZE,
+        });
+    }
+
+    let sender_conn = match crate::lobby::get_user(lobby, sender_public_key).await {
+        Ok(Some(conn)) => conn,
+        _ => {
+            return Err(ValidationError::NotAuthenticated {
+                details: format!("User {} is not authenticated", sender_public_key),
+            });
+        }
+    };
+
+    if message_type(request_json).as_deref() == Some(BACKUP_FETCH_TYPE) {
+        serde_json::from_str::<BackupFetchRequest>(request_json).map_err(|e| {
+            ValidationError::MalformedJson {
+                details: format!("Invalid JSON: {}", e),
+            }
+        })?;
+        return send_backup(lobby, sender_public_key, &sender_conn).await;
+    }
+
+    let request: BackupStoreRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+    validate_timestamp(sender_public_key, &request.timestamp)?;
+    validate_signature_queued(
+        lobby,
+        sender_public_key,
+        canonical::backup_store(request.version, &request.blob, &request.timestamp),
+        &request.signature,
+    )
+    .await?;
+
+    let blob = hex::decode(&request.blob).map_err(|_| ValidationError::BackupRejected {
+        error: BackupError::InvalidBlob,
+    })?;
+    lobby
+        .backups
+        .store(
+            sender_public_key,
+            request.version,
+            blob,
+            chrono::Utc::now().to_rfc3339(),
+        )
+        .await
+        .map_err(|error| ValidationError::BackupRejected { error })?;
+
+    tracing::debug!(version = request.version, "Backup stored");
+    let _ = sender_conn.sender.send(Message::BackupStored {
+        version: request.version,
+    });
+    Ok(())
+}
+
+/// Send the requester their stored backup, or an empty one if there is none
+async fn send_backup(
+    lobby: &Lobby,
+    public_key: &str,
+    conn: &Arc<ActiveConnection>,
+) -> Result<(), ValidationError> {
+    let reply = match lobby.backups.get(public_key).await {
+        Some(backup) => Message::new_backup(
+            backup.version,
+            Some(hex::encode(backup.blob)),
+            Some(backup.updated_at),
+        ),
+        None => Message::new_backup(0, None, None),
+    };
+    let _ = conn.sender.send(reply);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use profile_shared::{derive_public_key, generate_private_key, sign_message, PrivateKey};
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, mut receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        while receiver.try_recv().is_ok() {}
+        receiver
+    }
+
+    fn store_request(private_key: &PrivateKey, version: u64, blob: &str) -> String {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = hex::encode(
+            sign_message(
+                private_key,
+                &canonical::backup_store(version, blob, &timestamp),
+            )
+            .unwrap(),
+        );
+        serde_json::json!({
+            "type": "backup_store",
+            "version": version,
+            "blob": blob,
+            "signature": signature,
+            "timestamp": timestamp,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_is_backup_request() {
+        assert!(is_backup_request("backup_store"));
+        assert!(is_backup_request("backup_fetch"));
+        assert!(!is_backup_request("message"));
+    }
+
+    #[tokio::test]
+    async fn test_backup_restored_on_new_connection() {
+        let lobby = Lobby::new();
+        let private_key = generate_private_key().unwrap();
+        let key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let mut laptop = connect(&lobby, &key, 1).await;
+
+        let fetch = r#"{"type":"backup_fetch"}"#;
+        handle_backup_request(&lobby, &key, fetch).await.unwrap();
+        assert!(matches!(
+            laptop.try_recv().unwrap(),
+            Message::Backup {
+                version: 0,
+                blob: None,
+                ..
+            }
+        ));
+
+        handle_backup_request(&lobby, &key, &store_request(&private_key, 1, "c0ffee"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            laptop.try_recv().unwrap(),
+            Message::BackupStored { version: 1 }
+        ));
+        assert_eq!(
+            handle_backup_request(&lobby, &key, &store_request(&private_key, 1, "beef")).await,
+            Err(ValidationError::BackupRejected {
+                error: BackupError::StaleVersion { current: 1 }
+            })
+        );
+
+        // The backup outlives the connection it was stored from
+        crate::lobby::remove_user(&lobby, &key).await.unwrap();
+        let mut phone = connect(&lobby, &key, 2).await;
+        handle_backup_request(&lobby, &key, fetch).await.unwrap();
+        match phone.try_recv().unwrap() {
+            Message::Backup { version, blob, .. } => {
+                assert_eq!(version, 1);
+                assert_eq!(blob.as_deref(), Some("c0ffee"));
+            }
+            other => panic!("Expected Backup, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_store_requests_rejected() {
+        let lobby = Lobby::new();
+        let private_key = generate_private_key().unwrap();
+        let key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let _rx = connect(&lobby, &key, 1).await;
+
+        assert_eq!(
+            handle_backup_request(&lobby, &key, &store_request(&private_key, 1, "xyz")).await,
+            Err(ValidationError::BackupRejected {
+                error: BackupError::InvalidBlob
+            })
+        );
+
+        let mut tampered: serde_json::Value =
+            serde_json::from_str(&store_request(&private_key, 1, "c0ffee")).unwrap();
+        tampered["blob"] = "deadbeef".into();
+        assert!(matches!(
+            handle_backup_request(&lobby, &key, &tampered.to_string()).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+
+        let oversized = "00".repeat(MAX_BACKUP_SIZE + 1);
+        assert!(matches!(
+            handle_backup_request(&lobby, &key, &store_request(&private_key, 1, &oversized)).await,
+            Err(ValidationError::BackupRejected {
+                error: BackupError::TooLarge { .. }
+            })
+        ));
+        assert!(lobby.backups.is_empty().await);
+    }
+}
@@ -0,0 +1,25 @@
+//! Cover traffic
+//!
+//! Clients that opted in send `cover` frames of random padding at random
+//! intervals, so someone watching the connection can't tell when the user
+//! is actually active. The frames carry nothing: the server drops them
+//! without a reply and without touching the lobby.
+
+/// Value of the `type` field identifying a cover frame
+pub const COVER_TYPE: &str = "cover";
+
+/// Check whether a client message type is a cover frame to be dropped
+pub fn is_cover_frame(message_type: &str) -> bool {
+    message_type == COVER_TYPE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cover_frames_recognized() {
+        assert!(is_cover_frame("cover"));
+        assert!(!is_cover_frame("viewing"));
+    }
+}
@@ -0,0 +1,166 @@
+//! Duplicate message id detection
+//!
+//! Clients give every direct message a UUID and reuse it when they resend
+//! (for example after a reconnect). The server remembers the ids it has
+//! routed for each sender for `DUPLICATE_ID_WINDOW` and rejects a message
+//! whose id it has already delivered, so recipients never see it twice.
+
+use profile_shared::config;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Message ids routed recently, per sender
+#[derive(Debug)]
+pub struct RecentMessageIds {
+    state: Mutex<RecentState>,
+    window: Duration,
+    max_per_sender: usize,
+}
+
+#[derive(Debug)]
+struct RecentState {
+    /// Routed ids per sender, oldest first
+    by_sender: HashMap<String, VecDeque<(Instant, Uuid)>>,
+    /// When senders with only expired ids were last dropped
+    last_sweep: Instant,
+}
+
+impl RecentMessageIds {
+    /// Create a tracker using the configured window and per-sender limit
+    pub fn new() -> Self {
+        Self::with_limits(
+            config::message::DUPLICATE_ID_WINDOW,
+            config::message::MAX_REMEMBERED_IDS_PER_SENDER,
+        )
+    }
+
+    /// Create a tracker with a custom window and per-sender limit
+    ///
+    /// # Arguments
+    /// * `window` - How long an id is remembered
+    /// * `max_per_sender` - Ids kept per sender; the oldest are forgotten first
+    pub fn with_limits(window: Duration, max_per_sender: usize) -> Self {
+        Self {
+            state: Mutex::new(RecentState {
+                by_sender: HashMap::new(),
+                last_sweep: Instant::now(),
+            }),
+            window,
+            max_per_sender,
+        }
+    }
+
+    /// Record a message id about to be routed
+    ///
+    /// # Arguments
+    /// * `sender_public_key` - The authenticated sender
+    /// * `message_id` - The id from the message
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    /// false if this sender already sent `message_id` within the window
+    pub async fn record(&self, sender_public_key: &str, message_id: Uuid, now: Instant) -> bool {
+        let mut state = self.state.lock().await;
+        let window = self.window;
+        let expired = |seen_at: &Instant| now.saturating_duration_since(*seen_at) >= window;
+
+        if now.saturating_duration_since(state.last_sweep) >= window {
+            state
+                .by_sender
+                .retain(|_, ids| ids.back().is_some_and(|(seen_at, _)| !expired(seen_at)));
+            state.last_sweep = now;
+        }
+
+        let ids = state
+            .by_sender
+            .entry(sender_public_key.to_string())
+            .or_default();
+        while ids.front().is_some_and(|(seen_at, _)| expired(seen_at)) {
+            ids.pop_front();
+        }
+        if ids.iter().any(|(_, id)| *id == message_id) {
+            return false;
+        }
+
+        ids.push_back((now, message_id));
+        if ids.len() > self.max_per_sender {
+            ids.pop_front();
+        }
+        true
+    }
+
+    /// Number of senders with remembered ids
+    pub async fn sender_count(&self) -> usize {
+        self.state.lock().await.by_sender.len()
+    }
+}
+
+impl Default for RecentMessageIds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_duplicate_rejected_within_window() {
+        let recent = RecentMessageIds::with_limits(Duration::from_secs(60), 10);
+        let now = Instant::now();
+        let id = Uuid::new_v4();
+
+        assert!(recent.record("alice", id, now).await);
+        assert!(
+            !recent
+                .record("alice", id, now + Duration::from_secs(30))
+                .await
+        );
+        // Same id from another sender is not a duplicate
+        assert!(recent.record("bob", id, now).await);
+    }
+
+    #[tokio::test]
+    async fn test_id_forgotten_after_window() {
+        let recent = RecentMessageIds::with_limits(Duration::from_secs(60), 10);
+        let now = Instant::now();
+        let id = Uuid::new_v4();
+
+        assert!(recent.record("alice", id, now).await);
+        assert!(
+            recent
+                .record("alice", id, now + Duration::from_secs(60))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oldest_ids_evicted_past_limit() {
+        let recent = RecentMessageIds::with_limits(Duration::from_secs(60), 2);
+        let now = Instant::now();
+        let first = Uuid::new_v4();
+
+        assert!(recent.record("alice", first, now).await);
+        assert!(recent.record("alice", Uuid::new_v4(), now).await);
+        assert!(recent.record("alice", Uuid::new_v4(), now).await);
+        assert!(recent.record("alice", first, now).await);
+    }
+
+    #[tokio::test]
+    async fn test_idle_senders_swept() {
+        let recent = RecentMessageIds::with_limits(Duration::from_secs(60), 10);
+        let now = Instant::now();
+
+        recent.record("alice", Uuid::new_v4(), now).await;
+        recent.record("bob", Uuid::new_v4(), now).await;
+        assert_eq!(recent.sender_count().await, 2);
+
+        recent
+            .record("bob", Uuid::new_v4(), now + Duration::from_secs(120))
+            .await;
+        assert_eq!(recent.sender_count().await, 1);
+    }
+}
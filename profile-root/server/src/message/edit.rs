@@ -0,0 +1,241 @@
+//! Message edit routing
+//!
+//! A sender replaces the text of a direct message they sent earlier with a
+//! signed [`EditMessageRequest`] naming the original message id. The server
+//! checks the signature over the id, new text and timestamp, then forwards a
+//! [`profile_shared::Message::Edit`] to the recipient through the cluster
+//! and the recipient's sender filters like any direct message.
+//!
+//! The server doesn't keep messages, so it can't tell whether the sender
+//! wrote the original; recipients only apply an edit to a message from the
+//! same key. Edits are best-effort like receipts: nothing is queued for
+//! offline recipients.
+
+use crate::audit::AuditEvent;
+use crate::lobby::Lobby;
+use crate::message::pipeline::{
+    JsonRequest, MessageContext, StageFuture, Timestamped, ValidationPipeline, ValidationStage,
+};
+use crate::message::{max_message_length, validate_signature_queued, ValidationError};
+use crate::protocol::EditMessageRequest;
+use profile_shared::canonical;
+
+/// Value of the `type` field identifying an edit request
+pub const EDIT_MESSAGE_TYPE: &str = "edit_message";
+
+/// Check whether a client message type is an edit request
+pub fn is_edit_message_request(message_type: &str) -> bool {
+    message_type == EDIT_MESSAGE_TYPE
+}
+
+impl JsonRequest for EditMessageRequest {}
+
+impl Timestamped for EditMessageRequest {
+    fn timestamp(request: &Self) -> &str {
+        &request.timestamp
+    }
+}
+
+/// Validate an edit and forward it to the recipient
+///
+/// Runs the [`ValidationPipeline::timestamped`] stages, then [`ForwardEdit`].
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key of the authenticated sender
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the edit was forwarded, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_edit_message_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    ValidationPipeline::<EditMessageRequest>::timestamped()
+        .with_stage(ForwardEdit)
+        .validate(lobby, sender_public_key, request_json)
+        .await
+        .map(|_| ())
+}
+
+/// Check the new text and the signature, then forward the edit
+#[derive(Debug, Clone, Copy)]
+pub struct ForwardEdit;
+
+impl ValidationStage<EditMessageRequest> for ForwardEdit {
+    fn name(&self) -> &'static str {
+        "edit"
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, EditMessageRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.take_request()?;
+            let (lobby, sender_public_key) = (ctx.lobby, ctx.sender_public_key);
+
+            if request.message.is_empty() {
+                return Err(ValidationError::MalformedJson {
+                    details: "Edited message must not be empty".to_string(),
+                });
+            }
+            let max_length = max_message_length();
+            if request.message.len() > max_length {
+                return Err(ValidationError::MessageTooLarge {
+                    size: request.message.len(),
+                    max: max_length,
+                });
+            }
+
+            if request.recipient_public_key == sender_public_key {
+                return Err(ValidationError::CannotMessageSelf);
+            }
+
+            validate_signature_queued(
+                lobby,
+                sender_public_key,
+                canonical::message_edit(request.message_id, &request.message, &request.timestamp),
+                &request.signature,
+            )
+            .await?;
+
+            let edit = profile_shared::Message::new_edit(
+                request.message_id,
+                request.message,
+                sender_public_key.to_string(),
+                request.signature,
+                request.timestamp,
+            );
+            if !crate::message::deliver(lobby, &request.recipient_public_key, edit).await {
+                lobby.audit.record(AuditEvent::RoutingFailed {
+                    sender_public_key: sender_public_key.to_string(),
+                    recipient_public_key: request.recipient_public_key.clone(),
+                    reason: "Recipient went offline".to_string(),
+                });
+                return Err(ValidationError::RecipientOffline {
+                    recipient_key: request.recipient_public_key,
+                });
+            }
+
+            tracing::debug!(
+                to = %request.recipient_public_key.chars().take(16).collect::<String>(),
+                message_id = %request.message_id,
+                "Message edit forwarded"
+            );
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::message::message_type;
+    use profile_shared::{derive_public_key, generate_private_key, sign_message, Message};
+    use uuid::Uuid;
+
+    const RECIPIENT_KEY: &str = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+
+    async fn add_connection(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    /// A sender key and an edit request signed with it
+    fn signed_edit(message_id: Uuid, text: &str) -> (String, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            &private_key,
+            &canonical::message_edit(message_id, text, &timestamp),
+        )
+        .unwrap();
+        let request = serde_json::json!({
+            "type": "edit_message",
+            "recipientPublicKey": RECIPIENT_KEY,
+            "messageId": message_id,
+            "message": text,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp,
+        });
+        (public_key, request.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_edit_forwarded_with_sender_from_connection() {
+        let lobby = Lobby::new();
+        let message_id = Uuid::new_v4();
+        let (sender_key, request) = signed_edit(message_id, "Fixed typo");
+        assert!(is_edit_message_request(&message_type(&request).unwrap()));
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let mut recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+
+        handle_edit_message_request(&lobby, &sender_key, &request)
+            .await
+            .unwrap();
+
+        loop {
+            match recipient_rx.try_recv().unwrap() {
+                Message::Edit {
+                    message_id: id,
+                    message,
+                    sender_public_key,
+                    ..
+                } => {
+                    assert_eq!(id, message_id);
+                    assert_eq!(message, "Fixed typo");
+                    assert_eq!(sender_public_key, sender_key);
+                    break;
+                }
+                Message::LobbyUpdate { .. } => {}
+                other => panic!("Expected Edit, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_edit_signed_for_another_message_rejected() {
+        let lobby = Lobby::new();
+        let (sender_key, request) = signed_edit(Uuid::new_v4(), "Fixed typo");
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let _recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+
+        // Moving the signature onto another message id breaks it
+        let mut moved: serde_json::Value = serde_json::from_str(&request).unwrap();
+        moved["messageId"] = Uuid::new_v4().to_string().into();
+        let result = handle_edit_message_request(&lobby, &sender_key, &moved.to_string()).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_edit_to_offline_recipient_rejected() {
+        let lobby = Lobby::new();
+        let (sender_key, request) = signed_edit(Uuid::new_v4(), "Fixed typo");
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+
+        let result = handle_edit_message_request(&lobby, &sender_key, &request).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::RecipientOffline { .. })
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+//! Sender filter request handling
+//!
+//! Authenticated clients replace their list of filtered senders with a
+//! signed [`SetFiltersRequest`], answered with
+//! [`profile_shared::Message::SenderFiltersSet`], and collect the messages
+//! held from queue-only senders with a [`HeldFetchRequest`]. Held messages
+//! are sent back as the direct messages they were, oldest first. See
+//! [`crate::filters`] for how the lists are applied.
+
+use crate::lobby::Lobby;
+use crate::message::{
+    message_type, validate_signature_queued, validate_timestamp, ValidationError,
+};
+use crate::protocol::{HeldFetchRequest, SetFiltersRequest};
+use profile_shared::config::filters::MAX_SENDER_FILTERS;
+use profile_shared::{canonical, Message};
+
+/// Value of the `type` field identifying a filter list update
+pub const SET_FILTERS_TYPE: &str = "set_filters";
+
+/// Value of the `type` field identifying a request for held messages
+pub const HELD_FETCH_TYPE: &str = "held_fetch";
+
+/// Largest accepted request: a full list of filters plus the other fields
+const MAX_REQUEST_SIZE: usize = 128 * MAX_SENDER_FILTERS + 1024;
+
+/// Check whether a client message type is a filter list update or a
+/// request for held messages
+pub fn is_filter_request(message_type: &str) -> bool {
+    matches!(message_type, SET_FILTERS_TYPE | HELD_FETCH_TYPE)
+}
+
+/// Handle a filter list update or held message request from an
+/// authenticated user
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users and their filters
+/// * `sender_public_key` - The public key of the requesting user
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the list was replaced or the held messages sent,
+/// Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_filter_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    if request_json.len() > MAX_REQUEST_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_REQUEST_SIZE,
+        });
+    }
+
+    let sender_conn = match crate::lobby::get_user(lobby, sender_public_key).await {
+        Ok(Some(conn)) => conn,
+        _ => {
+            return Err(ValidationError::NotAuthenticated {
+                details: format!("User {} is not authenticated", sender_public_key),
+            });
+        }
+    };
+
+    if message_type(request_json).as_deref() == Some(HELD_FETCH_TYPE) {
+        serde_json::from_str::<HeldFetchRequest>(request_json).map_err(|e| {
+            ValidationError::MalformedJson {
+                details: format!("Invalid JSON: {}", e),
+            }
+        })?;
+        let held = lobby.filters.take_held(sender_public_key).await;
+        tracing::debug!(count = held.len(), "Sending held messages");
+        for message in held {
+            let _ = sender_conn.sender.send(message);
+        }
+        return Ok(());
+    }
+
+    let request: SetFiltersRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+    validate_timestamp(sender_public_key, &request.timestamp)?;
+    validate_signature_queued(
+        lobby,
+        sender_public_key,
+        canonical::filters(&request.filters, &request.timestamp),
+        &request.signature,
+    )
+    .await?;
+
+    let held = lobby
+        .filters
+        .set(sender_public_key, &request.filters)
+        .await
+        .map_err(|error| ValidationError::FiltersRejected { error })?;
+
+    tracing::debug!(count = request.filters.len(), "Sender filters set");
+    let _ = sender_conn
+        .sender
+        .send(Message::new_sender_filters_set(request.filters.len(), held));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::filters::FilterError;
+    use crate::lobby::ActiveConnection;
+    use crate::message::route_message;
+    use crate::message::MessageValidationResult;
+    use crate::protocol::{FilterAction, SenderFilter};
+    use profile_shared::canonical::CanonicalVersion;
+    use profile_shared::{derive_public_key, generate_private_key, sign_message, PrivateKey};
+    use std::borrow::Cow;
+    use uuid::Uuid;
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, mut receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        while receiver.try_recv().is_ok() {}
+        receiver
+    }
+
+    fn new_user() -> (PrivateKey, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        (private_key, public_key)
+    }
+
+    fn set_request(private_key: &PrivateKey, filters: &[SenderFilter]) -> String {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = hex::encode(
+            sign_message(private_key, &canonical::filters(filters, &timestamp)).unwrap(),
+        );
+        serde_json::json!({
+            "type": "set_filters",
+            "filters": filters,
+            "signature": signature,
+            "timestamp": timestamp,
+        })
+        .to_string()
+    }
+
+    async fn send(lobby: &Lobby, from: &str, to: &str, text: &str) {
+        let validated = MessageValidationResult::Valid {
+            message_id: Uuid::new_v4(),
+            sender_public_key: Cow::Borrowed(from),
+            recipient_public_key: Cow::Borrowed(to),
+            message: Cow::Borrowed(text),
+            signature: Cow::Borrowed("00"),
+            timestamp: Cow::Borrowed("2025-01-01T00:00:00Z"),
+            canonical: CanonicalVersion::CURRENT,
+            sequence: None,
+        };
+        route_message(lobby, &validated).await.unwrap();
+    }
+
+    fn body(message: Message) -> String {
+        match message {
+            Message::Text { message, .. } => message,
+            other => panic!("Expected a direct message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_is_filter_request() {
+        assert!(is_filter_request("set_filters"));
+        assert!(is_filter_request("held_fetch"));
+        assert!(!is_filter_request("message"));
+    }
+
+    #[tokio::test]
+    async fn test_filtered_senders_dropped_or_held_until_fetched() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (_, troll) = new_user();
+        let (_, pest) = new_user();
+        let mut alice_rx = connect(&lobby, &alice, 1).await;
+        connect(&lobby, &troll, 2).await;
+        connect(&lobby, &pest, 3).await;
+        while alice_rx.try_recv().is_ok() {}
+
+        let filters = [
+            SenderFilter {
+                sender_public_key: troll.clone(),
+                action: FilterAction::Drop,
+            },
+            SenderFilter {
+                sender_public_key: pest.clone(),
+                action: FilterAction::QueueOnly,
+            },
+        ];
+        handle_filter_request(&lobby, &alice, &set_request(&alice_key, &filters))
+            .await
+            .unwrap();
+        assert_eq!(
+            alice_rx.try_recv().unwrap(),
+            Message::new_sender_filters_set(2, 0)
+        );
+
+        // Both senders see their messages as delivered
+        send(&lobby, &troll, &alice, "dropped").await;
+        send(&lobby, &pest, &alice, "held").await;
+        assert!(alice_rx.try_recv().is_err());
+
+        handle_filter_request(&lobby, &alice, r#"{"type":"held_fetch"}"#)
+            .await
+            .unwrap();
+        assert_eq!(body(alice_rx.try_recv().unwrap()), "held");
+        assert!(alice_rx.try_recv().is_err());
+
+        // Clearing the list lets both through again
+        handle_filter_request(&lobby, &alice, &set_request(&alice_key, &[]))
+            .await
+            .unwrap();
+        alice_rx.try_recv().unwrap();
+        send(&lobby, &troll, &alice, "hello").await;
+        assert_eq!(body(alice_rx.try_recv().unwrap()), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_filter_list_must_be_signed_and_valid() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (mallory_key, _) = new_user();
+        let mut alice_rx = connect(&lobby, &alice, 1).await;
+
+        let filters = [SenderFilter {
+            sender_public_key: "zz".to_string(),
+            action: FilterAction::Drop,
+        }];
+        assert!(matches!(
+            handle_filter_request(&lobby, &alice, &set_request(&mallory_key, &filters)).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+        assert_eq!(
+            handle_filter_request(&lobby, &alice, &set_request(&alice_key, &filters)).await,
+            Err(ValidationError::FiltersRejected {
+                error: FilterError::InvalidKey("zz".to_string())
+            })
+        );
+        assert!(alice_rx.try_recv().is_err());
+        assert!(lobby.filters.is_empty().await);
+    }
+}
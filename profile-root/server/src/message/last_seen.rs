@@ -0,0 +1,187 @@
+//! Last-seen queries
+//!
+//! Clients ask when a contact was last online with a
+//! [`QueryLastSeenRequest`]. The server answers with a
+//! [`profile_shared::Message::LastSeen`]: online if the contact is in the
+//! lobby and not invisible, otherwise the time recorded in
+//! [`crate::lobby::LastSeen`], which is only there if the contact shares it.
+
+use crate::lobby::Lobby;
+use crate::message::ValidationError;
+use crate::protocol::QueryLastSeenRequest;
+use chrono::SecondsFormat;
+
+/// Value of the `type` field identifying a last-seen query
+pub const QUERY_LAST_SEEN_TYPE: &str = "query_last_seen";
+
+/// Check whether a client message type is a last-seen query
+pub fn is_query_last_seen_request(message_type: &str) -> bool {
+    message_type == QUERY_LAST_SEEN_TYPE
+}
+
+/// Answer a last-seen query
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `requester_public_key` - The public key of the authenticated requester
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the answer was sent, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(requester = %requester_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_query_last_seen_request(
+    lobby: &Lobby,
+    requester_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    const MAX_MSG_SIZE: usize = profile_shared::config::message::MAX_MESSAGE_SIZE;
+    if request_json.len() > MAX_MSG_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_MSG_SIZE,
+        });
+    }
+
+    let requester_conn = match crate::lobby::get_user(lobby, requester_public_key).await {
+        Ok(Some(conn)) => conn,
+        _ => {
+            return Err(ValidationError::NotAuthenticated {
+                details: format!("User {} is not authenticated", requester_public_key),
+            });
+        }
+    };
+
+    let request: QueryLastSeenRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+
+    // An invisible user is answered as if they had left
+    let online = match lobby.users.get(&request.public_key).await {
+        Some(conn) => !conn.status.is_invisible(),
+        None => false,
+    };
+    let last_seen = if online {
+        None
+    } else {
+        lobby
+            .last_seen
+            .get(&request.public_key)
+            .await
+            .map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true))
+    };
+
+    let _ = requester_conn
+        .sender
+        .send(profile_shared::Message::new_last_seen(
+            request.public_key,
+            online,
+            last_seen,
+        ));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use profile_shared::protocol::Status;
+    use profile_shared::Message;
+
+    async fn connect(lobby: &Lobby, key: &str) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        lobby.add_user(conn).await.unwrap();
+        receiver
+    }
+
+    async fn query(
+        lobby: &Lobby,
+        receiver: &mut OutboundQueue,
+        key: &str,
+    ) -> (bool, Option<String>) {
+        let request = format!(r#"{{"type":"query_last_seen","publicKey":"{}"}}"#, key);
+        handle_query_last_seen_request(lobby, "aa01", &request)
+            .await
+            .unwrap();
+        loop {
+            match receiver.try_recv().unwrap() {
+                Message::LastSeen {
+                    public_key,
+                    online,
+                    last_seen,
+                } => {
+                    assert_eq!(public_key, key.to_lowercase());
+                    return (online, last_seen);
+                }
+                // Lobby updates from other users joining and leaving
+                Message::LobbyUpdate { .. } => {}
+                other => panic!("Expected LastSeen, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_query_last_seen_request() {
+        assert!(is_query_last_seen_request("query_last_seen"));
+        assert!(!is_query_last_seen_request("lobby_page"));
+    }
+
+    #[tokio::test]
+    async fn test_last_seen_recorded_only_for_sharing_users() {
+        let lobby = Lobby::new();
+        let mut receiver = connect(&lobby, "aa01").await;
+
+        lobby.last_seen.set_sharing("bb02", true).await;
+        let _sharing = connect(&lobby, "bb02").await;
+        let _private = connect(&lobby, "cc03").await;
+        assert_eq!(query(&lobby, &mut receiver, "bb02").await, (true, None));
+
+        crate::lobby::remove_user(&lobby, "bb02").await.unwrap();
+        crate::lobby::remove_user(&lobby, "cc03").await.unwrap();
+        let (online, last_seen) = query(&lobby, &mut receiver, "bb02").await;
+        assert!(!online);
+        assert!(chrono::DateTime::parse_from_rfc3339(&last_seen.unwrap()).is_ok());
+
+        // Not sharing looks the same as never having been seen
+        assert_eq!(query(&lobby, &mut receiver, "cc03").await, (false, None));
+        assert_eq!(query(&lobby, &mut receiver, "dd04").await, (false, None));
+    }
+
+    #[tokio::test]
+    async fn test_invisible_user_answered_as_offline() {
+        let lobby = Lobby::new();
+        let mut receiver = connect(&lobby, "aa01").await;
+        lobby.last_seen.set_sharing("bb02", true).await;
+        let _invisible = connect(&lobby, "bb02").await;
+
+        crate::lobby::set_status(&lobby, "bb02", Status::Invisible)
+            .await
+            .unwrap();
+        let (online, last_seen) = query(&lobby, &mut receiver, "BB02").await;
+        assert!(!online);
+        assert!(last_seen.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_query_rejected() {
+        let lobby = Lobby::new();
+        let result = handle_query_last_seen_request(
+            &lobby,
+            "aa01",
+            r#"{"type":"query_last_seen","publicKey":"bb02"}"#,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::NotAuthenticated { .. })
+        ));
+    }
+}
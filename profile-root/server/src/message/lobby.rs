@@ -0,0 +1,236 @@
+//! Paginated lobby queries
+//!
+//! Clients fetch online users a page at a time with a [`LobbyPageRequest`],
+//! optionally restricted to public keys starting with a hex prefix. The
+//! server answers with a [`profile_shared::Message::LobbyPage`] whose
+//! `nextCursor` is passed back to fetch the following page.
+
+use crate::lobby::Lobby;
+use crate::message::ValidationError;
+use crate::protocol::LobbyPageRequest;
+use profile_shared::config::lobby::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, MAX_SEARCH_PREFIX_LENGTH};
+
+/// Value of the `type` field identifying a lobby page request
+pub const LOBBY_PAGE_TYPE: &str = "lobby_page";
+
+/// Check whether a client message type is a lobby page request
+pub fn is_lobby_page_request(message_type: &str) -> bool {
+    message_type == LOBBY_PAGE_TYPE
+}
+
+/// Answer a lobby page request with one page of online users
+///
+/// A missing limit uses `DEFAULT_PAGE_SIZE`; larger limits are capped at
+/// `MAX_PAGE_SIZE`.
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `requester_public_key` - The public key of the authenticated requester
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the page was sent, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(requester = %requester_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_lobby_page_request(
+    lobby: &Lobby,
+    requester_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    const MAX_MSG_SIZE: usize = profile_shared::config::message::MAX_MESSAGE_SIZE;
+    if request_json.len() > MAX_MSG_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_MSG_SIZE,
+        });
+    }
+
+    let requester_conn = match crate::lobby::get_user(lobby, requester_public_key).await {
+        Ok(Some(conn)) => conn,
+        _ => {
+            return Err(ValidationError::NotAuthenticated {
+                details: format!("User {} is not authenticated", requester_public_key),
+            });
+        }
+    };
+
+    let request: LobbyPageRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+
+    let limit = match request.limit {
+        Some(0) => {
+            return Err(ValidationError::MalformedJson {
+                details: "Lobby page limit must be at least 1".to_string(),
+            });
+        }
+        Some(limit) => limit.min(MAX_PAGE_SIZE),
+        None => DEFAULT_PAGE_SIZE,
+    };
+
+    if let Some(ref prefix) = request.prefix {
+        if prefix.len() > MAX_SEARCH_PREFIX_LENGTH || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(ValidationError::MalformedJson {
+                details: "Search prefix must be at most 64 hex characters".to_string(),
+            });
+        }
+    }
+
+    let page = lobby
+        .get_page(request.cursor.as_deref(), limit, request.prefix.as_deref())
+        .await
+        .map_err(|e| ValidationError::MalformedJson {
+            details: format!("Lobby unavailable: {}", e),
+        })?;
+
+    tracing::debug!(
+        count = page.users.len(),
+        total = page.total,
+        "Lobby page sent"
+    );
+
+    let mut nicknames = lobby.nicknames.lookup(&page.users).await;
+    let mut names = lobby.names.lookup(&page.users).await;
+    let guests = lobby.ephemeral.lookup(&page.users).await;
+    let mut statuses = lobby.statuses(&page.users).await;
+    let users = page
+        .users
+        .into_iter()
+        .map(|key| {
+            let nickname = nicknames.remove(&key);
+            let name = names.remove(&key);
+            let ephemeral = guests.contains(&key);
+            let status = statuses.remove(&key).unwrap_or_default();
+            profile_shared::LobbyUser::new(key, nickname)
+                .with_name(name)
+                .with_ephemeral(ephemeral)
+                .with_status(status)
+        })
+        .collect();
+
+    let _ = requester_conn
+        .sender
+        .send(profile_shared::Message::new_lobby_page(
+            users,
+            page.next_cursor,
+            page.total,
+        ));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use profile_shared::Message;
+
+    async fn connect(lobby: &Lobby, key: &str) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        lobby.add_user(conn).await.unwrap();
+        receiver
+    }
+
+    #[test]
+    fn test_is_lobby_page_request() {
+        assert!(is_lobby_page_request("lobby_page"));
+        assert!(!is_lobby_page_request("message"));
+    }
+
+    #[tokio::test]
+    async fn test_lobby_page_sent_to_requester() {
+        let lobby = Lobby::new();
+        let mut receiver = connect(&lobby, "aa01").await;
+        let _other = connect(&lobby, "bb02").await;
+        let _third = connect(&lobby, "ab03").await;
+
+        handle_lobby_page_request(&lobby, "aa01", r#"{"type":"lobby_page","limit":2}"#)
+            .await
+            .unwrap();
+
+        match receiver.try_recv().unwrap() {
+            Message::LobbyPage {
+                users,
+                next_cursor,
+                total,
+            } => {
+                let keys: Vec<_> = users.into_iter().map(|u| u.public_key).collect();
+                assert_eq!(keys, vec!["aa01", "ab03"]);
+                assert_eq!(next_cursor.as_deref(), Some("ab03"));
+                assert_eq!(total, 3);
+            }
+            other => panic!("Expected LobbyPage, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lobby_page_includes_nicknames_and_guests() {
+        let lobby = Lobby::new();
+        let mut receiver = connect(&lobby, "aa01").await;
+        let _other = connect(&lobby, "bb02").await;
+        lobby.nicknames.set("bb02", "bob").await.unwrap();
+        lobby.ephemeral.set("bb02", true).await;
+
+        handle_lobby_page_request(&lobby, "aa01", r#"{"type":"lobby_page"}"#)
+            .await
+            .unwrap();
+
+        match receiver.try_recv().unwrap() {
+            Message::LobbyPage { users, .. } => {
+                assert_eq!(users[0].nickname, None);
+                assert_eq!(users[1].nickname.as_deref(), Some("bob"));
+                assert!(!users[0].ephemeral);
+                assert!(users[1].ephemeral);
+            }
+            other => panic!("Expected LobbyPage, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lobby_page_prefix_search() {
+        let lobby = Lobby::new();
+        let mut receiver = connect(&lobby, "aa01").await;
+        let _other = connect(&lobby, "bb02").await;
+
+        handle_lobby_page_request(&lobby, "aa01", r#"{"type":"lobby_page","prefix":"BB"}"#)
+            .await
+            .unwrap();
+
+        match receiver.try_recv().unwrap() {
+            Message::LobbyPage { users, total, .. } => {
+                assert_eq!(users.len(), 1);
+                assert_eq!(users[0].public_key, "bb02");
+                assert_eq!(total, 1);
+            }
+            other => panic!("Expected LobbyPage, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lobby_page_rejects_invalid_requests() {
+        let lobby = Lobby::new();
+        let _receiver = connect(&lobby, "aa01").await;
+
+        assert!(matches!(
+            handle_lobby_page_request(&lobby, "aa01", r#"{"type":"lobby_page","limit":0}"#).await,
+            Err(ValidationError::MalformedJson { .. })
+        ));
+        assert!(matches!(
+            handle_lobby_page_request(&lobby, "aa01", r#"{"type":"lobby_page","prefix":"xyz"}"#)
+                .await,
+            Err(ValidationError::MalformedJson { .. })
+        ));
+        assert!(matches!(
+            handle_lobby_page_request(&lobby, "nobody", r#"{"type":"lobby_page"}"#).await,
+            Err(ValidationError::NotAuthenticated { .. })
+        ));
+    }
+}
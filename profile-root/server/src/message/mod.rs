@@ -5,28 +5,86 @@
 //! - Fail-fast error handling
 //! - Message routing to online recipients
 //!
-//! Validation Sequence (AC1):
+//! Validation Sequence (AC1), run as the stages of a [`pipeline`]:
 //! 1. Check sender is authenticated (has active connection in lobby)
-//! 2. Check message format is valid JSON
-//! 3. Validate signature against sender's public key
+//! 2. Check message format is valid JSON, the text is within the maximum
+//!    length (`PROFILE_MAX_MESSAGE_LENGTH`) and the timestamp is within the
+//!    allowed skew of server time (`PROFILE_MAX_TIMESTAMP_SKEW_SECS`)
+//! 3. Validate signature against sender's public key, refusing the message
+//!    as `server_busy` if too many verifications are already pending
 //! 4. Check recipient exists in lobby
-//! 5. Route accordingly (deliver if online, error if not)
+//! 5. Reject sequence numbers that don't follow the sender's last one
+//! 6. Reject ids the sender already used within the duplicate window, then
+//!    accept the sequence number
+//! 7. Route accordingly (deliver if online, error if not)
+//!
+//! Read receipts, viewing hints, lobby pages, room requests and backups are
+//! handled separately in [`receipts`], [`viewing`], [`lobby`], [`rooms`] and
+//! [`backup`]. Sealed (ratchet-encrypted) messages can't be checked past
+//! their envelope and are relayed by [`sealed`]. Cover traffic is dropped
+//! unread (see [`cover`]). Identity key rotation is handled by [`rollover`].
+//! File attachments are relayed chunk by chunk by [`attachments`].
+//! Dev servers can delay and lose direct messages on their way to
+//! recipients with [`netsim`].
+
+pub mod attachments;
+pub mod backup;
+pub mod cover;
+pub mod dedup;
+pub mod edit;
+pub mod filters;
+pub mod last_seen;
+pub mod lobby;
+pub mod names;
+pub mod netsim;
+pub mod nickname;
+pub mod pipeline;
+pub mod reactions;
+pub mod receipts;
+pub mod retract;
+pub mod rollover;
+pub mod rooms;
+pub mod sealed;
+pub mod sequence;
+pub mod status;
+pub mod viewing;
 
+use crate::audit::AuditEvent;
+use crate::cluster::ClusterEvent;
+use crate::filters::FilterError;
 use crate::lobby::{ActiveConnection, Lobby};
+use crate::message::netsim::NetsimVerdict;
+use crate::names::NameError;
 use crate::protocol::{ErrorMessage, SendMessageRequest};
-use profile_shared::verify_signature;
-use std::sync::Arc;
+use profile_shared::canonical::CanonicalVersion;
+use profile_shared::{verify_signature, AttachmentError, BackupError, NicknameError, RoomError};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::sync::{Arc, OnceLock};
+use uuid::Uuid;
+
+/// Environment variable narrowing the accepted timestamp skew, in seconds
+pub const TIMESTAMP_SKEW_ENV_VAR: &str = "PROFILE_MAX_TIMESTAMP_SKEW_SECS";
+
+/// Environment variable overriding the maximum message text length, in bytes
+pub const MAX_MESSAGE_LENGTH_ENV_VAR: &str = "PROFILE_MAX_MESSAGE_LENGTH";
 
 /// Result of message validation
+///
+/// A valid message still borrows from the request JSON where it can; the
+/// text is only copied when [`route_message`] hands it to the recipient.
 #[derive(Debug, Clone, PartialEq)]
-pub enum MessageValidationResult {
+pub enum MessageValidationResult<'a> {
     /// Message is valid and ready for routing
     Valid {
-        sender_public_key: String,
-        recipient_public_key: String,
-        message: String,
-        signature: String,
-        timestamp: String,
+        message_id: Uuid,
+        sender_public_key: Cow<'a, str>,
+        recipient_public_key: Cow<'a, str>,
+        message: Cow<'a, str>,
+        signature: Cow<'a, str>,
+        timestamp: Cow<'a, str>,
+        canonical: CanonicalVersion,
+        sequence: Option<u64>,
     },
     /// Validation failed - message was rejected
     Invalid { reason: ValidationError },
@@ -47,7 +105,7 @@ pub enum ValidationError {
     CannotMessageSelf,
     /// Timestamp validation failed - timestamp too old or too far in the future.
     /// This prevents replay attacks by rejecting messages with timestamps
-    /// more than MAX_TIMESTAMP_DRIFT_SECS from server time.
+    /// more than [`max_timestamp_skew_secs`] from server time.
     StaleTimestamp { details: String },
     /// Message payload exceeds configured maximum size
     MessageTooLarge {
@@ -56,16 +114,51 @@ pub enum ValidationError {
         /// Maximum allowed size in bytes
         max: usize,
     },
+    /// Room operation was rejected (unknown room, not a member, etc.)
+    RoomRejected { error: RoomError },
+    /// The sender already sent a message with this id within the window
+    DuplicateMessage { message_id: Uuid },
+    /// Nickname change was rejected (invalid or taken)
+    NicknameRejected { error: NicknameError },
+    /// Name claim was rejected (registry disabled, invalid or taken)
+    NameRejected { error: NameError },
+    /// Sequence number is not greater than the last one the sender used on
+    /// this connection
+    ReplayDetected { sequence: u64, last_seen: u64 },
+    /// The sender numbered earlier messages on this connection but not this
+    /// one, as when the field is stripped from a captured frame
+    SequenceMissing { last_seen: u64 },
+    /// Backup store request was rejected (too large, stale version, etc.)
+    BackupRejected { error: BackupError },
+    /// Filter list was rejected (too long or naming an invalid key)
+    FiltersRejected { error: FilterError },
+    /// The sender's key was banned after they connected
+    SenderBanned { details: String },
+    /// Key rollover was refused (wrong old key, retired or banned key)
+    KeyRolloverRejected { details: String },
+    /// Attachment offer, accept, chunk or completion was refused
+    AttachmentRejected { error: AttachmentError },
+    /// Too many signature verifications are pending; the message may be
+    /// resent after `retry_after`
+    ServerBusy {
+        recipient_key: String,
+        retry_after: std::time::Duration,
+    },
 }
 
 /// Handle an incoming message from a client
 ///
-/// Implements the strict validation sequence from AC1:
-/// 1. Check sender is authenticated (has active connection)
-/// 2. Check message format is valid JSON
-/// 3. Validate signature against sender's public key
+/// Runs the message through the lobby's validation pipeline, which checks
+/// the AC1 sequence unless the lobby was given another one (see
+/// [`pipeline`]):
+/// 1. Check sender is authenticated (has active connection) and not banned
+/// 2. Check message format is valid JSON and the text isn't too long
+/// 3. Validate signature against sender's public key, unless the
+///    verification queue is full
 /// 4. Check recipient exists in lobby
-/// 5. Route accordingly (or return error)
+/// 5. Reject a sequence number that doesn't follow the sender's last one
+/// 6. Reject a message id the sender already used within the window, then
+///    accept the sequence number
 ///
 /// # Arguments
 /// * `lobby` - The lobby containing authenticated users
@@ -75,201 +168,190 @@ pub enum ValidationError {
 /// # Returns
 /// ValidationResult indicating success or specific error
 #[tracing::instrument(skip(lobby, message_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
-pub async fn handle_incoming_message(
-    lobby: &Lobby,
-    sender_public_key: &str,
-    message_json: &str,
-) -> MessageValidationResult {
-    // Check message size first (before JSON parsing) to prevent DoS
-    const MAX_MSG_SIZE: usize = profile_shared::config::message::MAX_MESSAGE_SIZE;
-    if message_json.len() > MAX_MSG_SIZE {
-        tracing::warn!(
-            sender = %sender_public_key,
-            size = message_json.len(),
-            max = MAX_MSG_SIZE,
-            "Message too large"
-        );
-        return MessageValidationResult::Invalid {
-            reason: ValidationError::MessageTooLarge {
-                size: message_json.len(),
-                max: MAX_MSG_SIZE,
-            },
-        };
-    }
-
-    // AC1 Step 1: Check sender is authenticated (has active connection in lobby)
-    // This is guaranteed by the handler - only authenticated users can send messages
-    // But we double-check to be safe
-    if get_sender_connection(lobby, sender_public_key)
+pub async fn handle_incoming_message<'a>(
+    lobby: &'a Lobby,
+    sender_public_key: &'a str,
+    message_json: &'a str,
+) -> MessageValidationResult<'a> {
+    lobby
+        .validation
+        .run(lobby, sender_public_key, message_json)
         .await
-        .is_none()
-    {
-        tracing::warn!(sender = %sender_public_key, "Sender not found in lobby - rejecting message");
-        return MessageValidationResult::Invalid {
-            reason: ValidationError::NotAuthenticated {
-                details: format!("User {} is not authenticated", sender_public_key),
-            },
-        };
-    }
+}
 
-    // AC1 Step 2: Check message format is valid JSON
-    let message_request: SendMessageRequest = match parse_message_json(message_json) {
-        Ok(msg) => msg,
-        Err(e) => {
-            tracing::warn!(error = %e, "Invalid JSON format from {}", sender_public_key);
-            return MessageValidationResult::Invalid {
-                reason: ValidationError::MalformedJson { details: e },
-            };
+/// Maximum accepted distance between a message timestamp and server time
+///
+/// Defaults to MAX_TIMESTAMP_DRIFT_SECS. `PROFILE_MAX_TIMESTAMP_SKEW_SECS`
+/// can narrow the window but not widen it: the duplicate-id window is sized
+/// from the default, so a wider skew would let a replay outlive the record
+/// of its id. Read once, on first use.
+pub fn max_timestamp_skew_secs() -> i64 {
+    static SKEW: OnceLock<i64> = OnceLock::new();
+    *SKEW
+        .get_or_init(|| parse_timestamp_skew(std::env::var(TIMESTAMP_SKEW_ENV_VAR).ok().as_deref()))
+}
+
+/// Parse a configured skew, falling back to the default when unset or invalid
+fn parse_timestamp_skew(value: Option<&str>) -> i64 {
+    const DEFAULT_SKEW_SECS: i64 = profile_shared::config::message::MAX_TIMESTAMP_DRIFT_SECS;
+    let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+        return DEFAULT_SKEW_SECS;
+    };
+    match value.parse::<i64>() {
+        Ok(secs) if secs > 0 => secs.min(DEFAULT_SKEW_SECS),
+        _ => {
+            tracing::warn!(
+                value,
+                "Ignoring invalid {}; using {} seconds",
+                TIMESTAMP_SKEW_ENV_VAR,
+                DEFAULT_SKEW_SECS
+            );
+            DEFAULT_SKEW_SECS
         }
+    }
+}
+
+/// Maximum length of a message's text in bytes
+///
+/// Defaults to MAX_MESSAGE_LENGTH; `PROFILE_MAX_MESSAGE_LENGTH` can set it
+/// anywhere up to MAX_MESSAGE_SIZE, beyond which the raw request would be
+/// rejected first. Read once, on first use.
+pub fn max_message_length() -> usize {
+    static LENGTH: OnceLock<usize> = OnceLock::new();
+    *LENGTH.get_or_init(|| {
+        parse_max_message_length(std::env::var(MAX_MESSAGE_LENGTH_ENV_VAR).ok().as_deref())
+    })
+}
+
+/// Parse a configured length, falling back to the default when unset or invalid
+fn parse_max_message_length(value: Option<&str>) -> usize {
+    use profile_shared::config::message::{MAX_MESSAGE_LENGTH, MAX_MESSAGE_SIZE};
+    let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+        return MAX_MESSAGE_LENGTH;
     };
+    match value.parse::<usize>() {
+        Ok(length) if length > 0 => length.min(MAX_MESSAGE_SIZE),
+        _ => {
+            tracing::warn!(
+                value,
+                "Ignoring invalid {}; using {} bytes",
+                MAX_MESSAGE_LENGTH_ENV_VAR,
+                MAX_MESSAGE_LENGTH
+            );
+            MAX_MESSAGE_LENGTH
+        }
+    }
+}
+
+/// Validate a message timestamp against the allowed drift window
+///
+/// Rejects timestamps more than [`max_timestamp_skew_secs`] from server time
+/// to prevent replay attacks.
+pub(crate) fn validate_timestamp(
+    sender_public_key: &str,
+    timestamp: &str,
+) -> Result<(), ValidationError> {
+    check_timestamp_skew(
+        sender_public_key,
+        timestamp,
+        chrono::Utc::now(),
+        max_timestamp_skew_secs(),
+    )
+}
 
-    // Validate timestamp to prevent replay attacks
-    const MAX_TIMESTAMP_DRIFT_SECS: i64 = profile_shared::config::message::MAX_TIMESTAMP_DRIFT_SECS;
+/// Check a timestamp against `now`, allowing `max_skew_secs` either way
+fn check_timestamp_skew(
+    sender_public_key: &str,
+    timestamp: &str,
+    now: chrono::DateTime<chrono::Utc>,
+    max_skew_secs: i64,
+) -> Result<(), ValidationError> {
     const MAX_TIMESTAMP_DRIFT_SECS_ABSOLUTE: i64 =
         profile_shared::config::message::MAX_TIMESTAMP_DRIFT_SECS_ABSOLUTE;
-    match chrono::DateTime::parse_from_rfc3339(&message_request.timestamp) {
+    match chrono::DateTime::parse_from_rfc3339(timestamp) {
         Ok(timestamp) => {
             let timestamp_utc = timestamp.with_timezone(&chrono::Utc);
-            let now = chrono::Utc::now();
             let drift = now.signed_duration_since(timestamp_utc).num_seconds().abs();
             // Hard limit check for extreme/malformed timestamps
             if drift > MAX_TIMESTAMP_DRIFT_SECS_ABSOLUTE {
-                return MessageValidationResult::Invalid {
-                    reason: ValidationError::StaleTimestamp {
-                        details: "Timestamp too far from current time".to_string(),
-                    },
-                };
+                return Err(ValidationError::StaleTimestamp {
+                    details: "Timestamp too far from current time".to_string(),
+                });
             }
-            if drift > MAX_TIMESTAMP_DRIFT_SECS {
+            if drift > max_skew_secs {
                 tracing::warn!(
                     sender = %sender_public_key,
                     drift_seconds = drift,
                     "Message timestamp outside acceptable window"
                 );
-                return MessageValidationResult::Invalid {
-                    reason: ValidationError::StaleTimestamp {
-                        details: format!(
-                            "Timestamp drift of {} seconds exceeds maximum of {} seconds",
-                            drift, MAX_TIMESTAMP_DRIFT_SECS
-                        ),
-                    },
-                };
+                return Err(ValidationError::StaleTimestamp {
+                    details: format!(
+                        "Timestamp drift of {} seconds exceeds maximum of {} seconds",
+                        drift, max_skew_secs
+                    ),
+                });
             }
+            Ok(())
         }
         Err(e) => {
             tracing::warn!(error = %e, "Invalid timestamp format from {}", sender_public_key);
-            return MessageValidationResult::Invalid {
-                reason: ValidationError::MalformedJson {
-                    details: format!("Invalid timestamp format: {}", e),
-                },
-            };
+            Err(ValidationError::MalformedJson {
+                details: format!("Invalid timestamp format: {}", e),
+            })
         }
     }
+}
 
-    // Validate recipient is not self
-    if message_request.recipient_public_key == sender_public_key {
-        return MessageValidationResult::Invalid {
-            reason: ValidationError::CannotMessageSelf,
-        };
-    }
-
-    // Validate recipient public key format
-    const EXPECTED_KEY_LEN: usize = 64;
-    if message_request.recipient_public_key.len() != EXPECTED_KEY_LEN
-        || !message_request
-            .recipient_public_key
-            .chars()
-            .all(|c| c.is_ascii_hexdigit())
-    {
-        return MessageValidationResult::Invalid {
-            reason: ValidationError::MalformedJson {
-                details: format!(
-                    "Invalid recipient public key format: expected {} hex characters",
-                    EXPECTED_KEY_LEN
-                ),
-            },
-        };
-    }
-
-    // AC1 Step 3: Validate signature against sender's public key
-    // The canonical message for verification is: message:timestamp
-    let canonical_message = format!("{}:{}", message_request.message, message_request.timestamp);
-    let sender_key_bytes = match hex::decode(sender_public_key) {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            return MessageValidationResult::Invalid {
-                reason: ValidationError::MalformedJson {
-                    details: format!("Invalid sender public key hex: {}", e),
-                },
-            };
-        }
-    };
+/// Verify a message signature against the sender's public key
+///
+/// Takes the canonical bytes already built (see
+/// [`profile_shared::canonical`]), so the text is copied once for
+/// verification rather than once per step.
+pub(crate) fn validate_canonical_signature(
+    sender_public_key: &str,
+    canonical_message: &[u8],
+    signature: &str,
+) -> Result<(), ValidationError> {
+    let sender_key_bytes =
+        hex::decode(sender_public_key).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid sender public key hex: {}", e),
+        })?;
 
-    let signature_bytes = match hex::decode(&message_request.signature) {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            return MessageValidationResult::Invalid {
-                reason: ValidationError::MalformedJson {
-                    details: format!("Invalid signature hex: {}", e),
-                },
-            };
-        }
-    };
+    let signature_bytes = hex::decode(signature).map_err(|e| ValidationError::MalformedJson {
+        details: format!("Invalid signature hex: {}", e),
+    })?;
 
-    let sender_public_key = match profile_shared::PublicKey::new(sender_key_bytes) {
-        Ok(key) => key,
-        Err(_) => {
-            return MessageValidationResult::Invalid {
-                reason: ValidationError::MalformedJson {
-                    details: "Invalid sender public key format".to_string(),
-                },
-            };
+    let public_key = profile_shared::PublicKey::new(sender_key_bytes).map_err(|_| {
+        ValidationError::MalformedJson {
+            details: "Invalid sender public key format".to_string(),
         }
-    };
+    })?;
 
-    match verify_signature(
-        &sender_public_key,
-        canonical_message.as_bytes(),
-        &signature_bytes,
-    ) {
-        Ok(()) => {
-            tracing::debug!(recipient = %message_request.recipient_public_key, "Signature verified");
-        }
-        Err(e) => {
-            tracing::warn!(error = %e, "Signature verification failed for {}", &sender_public_key);
-            return MessageValidationResult::Invalid {
-                reason: ValidationError::SignatureInvalid {
-                    details: "Signature did not verify against public key".to_string(),
-                },
-            };
+    verify_signature(&public_key, canonical_message, &signature_bytes).map_err(|e| {
+        tracing::warn!(error = %e, "Signature verification failed for {}", &public_key);
+        ValidationError::SignatureInvalid {
+            details: "Signature did not verify against public key".to_string(),
         }
-    }
-
-    // AC1 Step 4: Check recipient exists in lobby
-    let recipient_connection =
-        get_recipient_connection(lobby, &message_request.recipient_public_key).await;
+    })
+}
 
-    // AC1 Step 5: Route accordingly
-    match recipient_connection {
-        Some(_) => {
-            // Recipient is online - message is valid for routing
-            MessageValidationResult::Valid {
-                sender_public_key: sender_public_key.to_string(),
-                recipient_public_key: message_request.recipient_public_key,
-                message: message_request.message,
-                signature: message_request.signature,
-                timestamp: message_request.timestamp,
-            }
-        }
-        None => {
-            // Recipient is offline - return error
-            MessageValidationResult::Invalid {
-                reason: ValidationError::RecipientOffline {
-                    recipient_key: message_request.recipient_public_key,
-                },
-            }
-        }
-    }
+/// Verify a signature over `canonical` on the blocking pool, waiting for a
+/// verification slot
+///
+/// For requests the client can't retry; direct messages are shed instead
+/// when the queue is full (see [`crate::load`]).
+pub(crate) async fn validate_signature_queued(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    canonical: Vec<u8>,
+    signature: &str,
+) -> Result<(), ValidationError> {
+    lobby
+        .verifications
+        .reserve()
+        .await
+        .verify(sender_public_key, canonical, signature)
+        .await
 }
 
 /// Get the sender's connection from the lobby
@@ -280,19 +362,103 @@ async fn get_sender_connection(lobby: &Lobby, public_key: &str) -> Option<Arc<Ac
         .flatten()
 }
 
-/// Get the recipient's connection from the lobby
-async fn get_recipient_connection(
+/// Whether the recipient is connected to this node or, in a cluster, any node
+pub(crate) async fn recipient_is_online(lobby: &Lobby, public_key: &str) -> bool {
+    if lobby.users.get(public_key).await.is_some() {
+        return true;
+    }
+    match &lobby.cluster {
+        Some(cluster) => matches!(cluster.locate(public_key).await, Ok(Some(_))),
+        None => false,
+    }
+}
+
+/// Send a message to the recipient's connection, wherever it is connected
+///
+/// A recipient on another node is reached by publishing the message for
+/// that node to deliver. The recipient's sender filters, and any simulated
+/// latency or loss, apply on the node that delivers it.
+///
+/// # Returns
+/// false if the recipient is not online on any node
+pub(crate) async fn deliver(
     lobby: &Lobby,
     public_key: &str,
-) -> Option<Arc<ActiveConnection>> {
-    crate::lobby::get_user(lobby, public_key)
-        .await
+    message: profile_shared::Message,
+) -> bool {
+    if let Some(conn) = lobby.users.get(public_key).await {
+        if let Some(message) = lobby.filters.screen(public_key, message).await {
+            let verdict = lobby
+                .netsim
+                .as_ref()
+                .map_or(NetsimVerdict::Deliver, |netsim| netsim.verdict(public_key));
+            match verdict {
+                NetsimVerdict::Deliver => {
+                    let _ = conn.sender.send(message);
+                }
+                NetsimVerdict::Delay(delay) => {
+                    tokio::spawn(async move {
+                        tokio::time::sleep(delay).await;
+                        let _ = conn.sender.send(message);
+                    });
+                }
+                NetsimVerdict::Drop => {
+                    tracing::debug!(
+                        recipient = %public_key.chars().take(16).collect::<String>(),
+                        "Simulated loss of a direct message"
+                    );
+                }
+            }
+        }
+        return true;
+    }
+    let Some(cluster) = &lobby.cluster else {
+        return false;
+    };
+    let node = match cluster.locate(public_key).await {
+        Ok(Some(node)) => node,
+        Ok(None) => return false,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to locate recipient in the cluster");
+            return false;
+        }
+    };
+    let event = ClusterEvent::Direct {
+        origin: cluster.node_id().to_string(),
+        node,
+        recipient: public_key.to_string(),
+        message,
+    };
+    match cluster.publish(event) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to publish message to the cluster");
+            false
+        }
+    }
+}
+
+/// The `type` field of a raw client message, borrowed where possible
+#[derive(Deserialize)]
+struct Envelope<'a> {
+    #[serde(borrow)]
+    r#type: Cow<'a, str>,
+}
+
+/// Extract the `type` field from a raw client message
+///
+/// Used to dispatch to the right handler before a full parse. Other fields
+/// are skipped without being built into a value.
+pub(crate) fn message_type(json: &str) -> Option<Cow<'_, str>> {
+    serde_json::from_str::<Envelope>(json)
         .ok()
-        .flatten()
+        .map(|envelope| envelope.r#type)
 }
 
 /// Parse incoming JSON into a SendMessageRequest
-fn parse_message_json(json: &str) -> Result<SendMessageRequest, String> {
+///
+/// Public so the fuzz targets (`fuzz/`) can call it directly.
+pub fn parse_message_json(json: &str) -> Result<SendMessageRequest<'_>, String> {
     serde_json::from_str(json).map_err(|e| format!("Invalid JSON: {}", e))
 }
 
@@ -307,15 +473,18 @@ fn parse_message_json(json: &str) -> Result<SendMessageRequest, String> {
 #[tracing::instrument(skip(lobby, validated))]
 pub async fn route_message(
     lobby: &Lobby,
-    validated: &MessageValidationResult,
+    validated: &MessageValidationResult<'_>,
 ) -> Result<(), String> {
     match validated {
         MessageValidationResult::Valid {
+            message_id,
             sender_public_key,
             recipient_public_key,
             message,
             signature,
             timestamp,
+            canonical,
+            sequence,
         } => {
             tracing::debug!(
                 sender = %sender_public_key.chars().take(16).collect::<String>(),
@@ -323,21 +492,26 @@ pub async fn route_message(
                 "Routing message"
             );
 
-            // Get recipient's connection
-            let recipient_conn = match get_recipient_connection(lobby, recipient_public_key).await {
-                Some(conn) => conn,
-                None => {
-                    return Err("Recipient went offline".to_string());
-                }
+            // Send via the recipient's WebSocket sender, on whichever node
+            let text = profile_shared::Message::Text {
+                message_id: *message_id,
+                message: message.to_string(),
+                sender_public_key: sender_public_key.to_string(),
+                signature: signature.to_string(),
+                timestamp: timestamp.to_string(),
+                canonical: *canonical,
+                sequence: *sequence,
+                server_received_at: Some(chrono::Utc::now().to_rfc3339()),
             };
-
-            // Send via the recipient's WebSocket sender
-            let _ = recipient_conn.sender.send(profile_shared::Message::Text {
-                message: message.clone(),
-                sender_public_key: sender_public_key.clone(),
-                signature: signature.clone(),
-                timestamp: timestamp.clone(),
-            });
+            if !deliver(lobby, recipient_public_key, text).await {
+                let reason = "Recipient went offline".to_string();
+                lobby.audit.record(AuditEvent::RoutingFailed {
+                    sender_public_key: sender_public_key.to_string(),
+                    recipient_public_key: recipient_public_key.to_string(),
+                    reason: reason.clone(),
+                });
+                return Err(reason);
+            }
 
             tracing::info!(
                 from = %sender_public_key.chars().take(16).collect::<String>(),
@@ -378,9 +552,80 @@ pub fn create_error_response(error: &ValidationError) -> String {
             "message_too_large".to_string(),
             format!("Message size {} exceeds maximum {}", size, max),
         ),
+        ValidationError::RoomRejected { error } => (
+            crate::rooms::room_error_reason(error).to_string(),
+            error.to_string(),
+        ),
+        ValidationError::DuplicateMessage { message_id } => (
+            "duplicate_message".to_string(),
+            format!("Message {} was already delivered", message_id),
+        ),
+        ValidationError::NicknameRejected { error } => (
+            crate::lobby::nicknames::nickname_error_reason(error).to_string(),
+            error.to_string(),
+        ),
+        ValidationError::NameRejected { error } => (
+            crate::names::name_error_reason(error).to_string(),
+            error.to_string(),
+        ),
+        ValidationError::ReplayDetected {
+            sequence,
+            last_seen,
+        } => (
+            "replay_detected".to_string(),
+            format!(
+                "Sequence {} does not follow last sequence {}",
+                sequence, last_seen
+            ),
+        ),
+        ValidationError::SequenceMissing { last_seen } => (
+            "replay_detected".to_string(),
+            format!("Sequence missing after sequence {}", last_seen),
+        ),
+        ValidationError::BackupRejected { error } => (
+            crate::backup::backup_error_reason(error).to_string(),
+            error.to_string(),
+        ),
+        ValidationError::FiltersRejected { error } => (
+            crate::filters::filter_error_reason(error).to_string(),
+            error.to_string(),
+        ),
+        ValidationError::SenderBanned { details } => ("banned".to_string(), details.clone()),
+        ValidationError::KeyRolloverRejected { details } => {
+            ("key_rollover_rejected".to_string(), details.clone())
+        }
+        ValidationError::AttachmentRejected { error } => (
+            crate::attachments::attachment_error_reason(error).to_string(),
+            error.to_string(),
+        ),
+        ValidationError::ServerBusy {
+            recipient_key,
+            retry_after,
+        } => (
+            profile_shared::protocol::SERVER_BUSY_REASON.to_string(),
+            format!(
+                "Server is busy; retry the message to {} after {} ms",
+                recipient_key,
+                retry_after.as_millis()
+            ),
+        ),
     };
 
-    let error_msg = ErrorMessage::with_details(reason, details);
+    let mut error_msg = ErrorMessage::with_details(reason, details);
+    match error {
+        ValidationError::RecipientOffline { recipient_key } => {
+            error_msg.recipient_public_key = Some(recipient_key.to_ascii_lowercase());
+        }
+        ValidationError::ServerBusy {
+            recipient_key,
+            retry_after,
+        } => {
+            error_msg.recipient_public_key = Some(recipient_key.to_ascii_lowercase());
+            error_msg.retry_after_ms =
+                Some(u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX));
+        }
+        _ => {}
+    }
 
     serde_json::to_string(&error_msg)
         .unwrap_or_else(|_| r#"{"type":"error","reason":"unknown"}"#.to_string())
@@ -389,16 +634,76 @@ pub fn create_error_response(error: &ValidationError) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::connection::send_queue::send_queue;
     use crate::lobby::Lobby;
     use profile_shared::Message as SharedMessage;
-    use tokio::sync::mpsc;
 
     fn create_test_connection(key: &str) -> ActiveConnection {
-        let (sender, _) = mpsc::unbounded_channel::<SharedMessage>();
+        let (sender, _) = send_queue();
         ActiveConnection {
             public_key: key.to_string(),
             sender,
             connection_id: 1,
+            status: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_message_type_parsed_from_envelope() {
+        assert_eq!(
+            message_type(r#"{"type":"lobby_page","limit":5}"#).as_deref(),
+            Some("lobby_page")
+        );
+        assert!(matches!(
+            message_type(r#"{"limit":5,"type":"viewing"}"#),
+            Some(Cow::Borrowed("viewing"))
+        ));
+        // Escaped types can't be borrowed but still parse
+        assert_eq!(
+            message_type(r#"{"type":"cov\u0065r"}"#).as_deref(),
+            Some("cover")
+        );
+        assert_eq!(message_type(r#"{"type":7}"#), None);
+        assert_eq!(message_type(r#"{"limit":5}"#), None);
+        assert_eq!(message_type("not json"), None);
+    }
+
+    #[tokio::test]
+    async fn test_routed_message_stamped_with_server_time() {
+        let lobby = Lobby::new();
+        let recipient = "b".repeat(64);
+        let (sender, mut receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: recipient.clone(),
+            sender,
+            connection_id: 2,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(&lobby, recipient.clone(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        while receiver.try_recv().is_ok() {}
+
+        let validated = MessageValidationResult::Valid {
+            message_id: Uuid::new_v4(),
+            sender_public_key: "a".repeat(64).into(),
+            recipient_public_key: recipient.into(),
+            message: "hi".into(),
+            signature: "sig".into(),
+            timestamp: "2025-01-01T00:00:00Z".into(),
+            canonical: CanonicalVersion::CURRENT,
+            sequence: None,
+        };
+        route_message(&lobby, &validated).await.unwrap();
+
+        match receiver.try_recv().unwrap() {
+            SharedMessage::Text {
+                server_received_at, ..
+            } => assert!(server_received_at
+                .as_deref()
+                .is_some_and(|t| chrono::DateTime::parse_from_rfc3339(t).is_ok())),
+            other => panic!("Expected Text, got {:?}", other),
         }
     }
 
@@ -415,6 +720,36 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_handle_message_sender_banned() {
+        let lobby = Lobby::new();
+        let sender = "a".repeat(64);
+        let (tx, _rx) = send_queue();
+        let conn = ActiveConnection {
+            public_key: sender.clone(),
+            sender: tx,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(&lobby, sender.clone(), conn)
+            .await
+            .unwrap();
+        lobby
+            .moderation
+            .ban(&sender, None, chrono::Utc::now())
+            .await
+            .unwrap();
+
+        let result = handle_incoming_message(&lobby, &sender, r#"{"type":"message"}"#).await;
+
+        match result {
+            MessageValidationResult::Invalid { reason } => {
+                assert!(create_error_response(&reason).contains(r#""reason":"banned""#));
+            }
+            other => panic!("Expected banned sender to be rejected, got {:?}", other),
+        }
+    }
+
     #[tokio::test]
     async fn test_handle_message_malformed_json() {
         let lobby = Lobby::new();
@@ -436,6 +771,82 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_handle_message_text_too_long() {
+        let lobby = Lobby::new();
+        let sender_key = "abcd1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+        let sender_conn = create_test_connection(sender_key);
+        crate::lobby::add_user(&lobby, sender_key.to_string(), sender_conn)
+            .await
+            .unwrap();
+
+        // Fits in the raw payload limit, but the text itself is too long
+        let text = "x".repeat(max_message_length() + 1);
+        let request = serde_json::json!({
+            "type": "message",
+            "recipientPublicKey": "0".repeat(64),
+            "message": text,
+            "senderPublicKey": sender_key,
+            "signature": "ab".repeat(64),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        });
+        let json = request.to_string();
+        let result = handle_incoming_message(&lobby, sender_key, &json).await;
+
+        match result {
+            MessageValidationResult::Invalid {
+                reason: reason @ ValidationError::MessageTooLarge { size, max },
+            } => {
+                assert_eq!((size, max), (text.len(), max_message_length()));
+                assert!(create_error_response(&reason).contains("message_too_large"));
+            }
+            other => panic!("Expected MessageTooLarge, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_message_shed_when_verification_queue_full() {
+        let lobby = Lobby::new().with_verification_queue(
+            crate::load::VerificationQueue::with_capacity(0, std::time::Duration::from_millis(300)),
+        );
+        let sender_key = "abcd1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+        let sender_conn = create_test_connection(sender_key);
+        crate::lobby::add_user(&lobby, sender_key.to_string(), sender_conn)
+            .await
+            .unwrap();
+
+        let recipient_key = "0".repeat(64);
+        let request = serde_json::json!({
+            "type": "message",
+            "recipientPublicKey": recipient_key,
+            "message": "Hello",
+            "senderPublicKey": sender_key,
+            "signature": "ab".repeat(64),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        });
+        let json = request.to_string();
+        let result = handle_incoming_message(&lobby, sender_key, &json).await;
+
+        let MessageValidationResult::Invalid { reason } = result else {
+            panic!("Expected the message to be shed, got {:?}", result);
+        };
+        // The error response carries a hint the client can parse back
+        let response: ErrorMessage = serde_json::from_str(&create_error_response(&reason)).unwrap();
+        let response = SharedMessage::Error {
+            reason: response.reason,
+            details: response.details,
+            recipient_public_key: response.recipient_public_key,
+            retry_after_ms: response.retry_after_ms,
+        };
+        assert_eq!(
+            response.server_busy_retry(),
+            Some((
+                recipient_key.as_str(),
+                std::time::Duration::from_millis(300)
+            ))
+        );
+    }
+
     #[tokio::test]
     async fn test_handle_message_recipient_offline() {
         use profile_shared::{derive_public_key, generate_private_key, sign_message};
@@ -474,7 +885,8 @@ mod tests {
             "timestamp": timestamp
         });
 
-        let result = handle_incoming_message(&lobby, &sender_key, &message_json.to_string()).await;
+        let json = message_json.to_string();
+        let result = handle_incoming_message(&lobby, &sender_key, &json).await;
 
         // Should fail because recipient is offline
         assert!(matches!(
@@ -543,6 +955,7 @@ mod tests {
         assert!(response.contains(r#""type":"error""#));
         assert!(response.contains(r#""reason":"offline""#));
         assert!(response.contains("recipient_key_123"));
+        assert!(response.contains(r#""recipientPublicKey":"recipient_key_123""#));
     }
 
     #[test]
@@ -574,34 +987,37 @@ mod tests {
             .expect("Key derivation should succeed");
         let recipient_public_key_hex = hex::encode(recipient_key_bytes);
 
-        // Create message and timestamp (canonical format: message:timestamp)
-        let message_text = "Hello, world!";
+        // Create message and timestamp, with a colon version 1 can't frame
+        let message_text = "Hello: world!";
         let timestamp = chrono::Utc::now().to_rfc3339();
-        let canonical_message = format!("{}:{}", message_text, timestamp);
+        let canonical_message =
+            profile_shared::canonical::message(CanonicalVersion::CURRENT, message_text, &timestamp);
 
         // Sign using the shared library (same as client does)
-        let signature = profile_shared::sign_message(&private_key, canonical_message.as_bytes())
+        let signature = profile_shared::sign_message(&private_key, &canonical_message)
             .expect("Signing should succeed");
         let signature_hex = hex::encode(signature);
 
         // Set up lobby with sender and recipient
         let lobby = Lobby::new();
-        let (sender_tx, _) = tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
+        let (sender_tx, _) = send_queue();
         let sender_conn = ActiveConnection {
             public_key: public_key_hex.clone(),
             sender: sender_tx,
             connection_id: 1,
+            status: Default::default(),
         };
         crate::lobby::add_user(&lobby, public_key_hex.clone(), sender_conn)
             .await
             .unwrap();
 
         // Add recipient to lobby so message can be delivered
-        let (recipient_tx, _) = tokio::sync::mpsc::unbounded_channel::<profile_shared::Message>();
+        let (recipient_tx, _) = send_queue();
         let recipient_conn = ActiveConnection {
             public_key: recipient_public_key_hex.clone(),
             sender: recipient_tx,
             connection_id: 2,
+            status: Default::default(),
         };
         crate::lobby::add_user(&lobby, recipient_public_key_hex.clone(), recipient_conn)
             .await
@@ -615,7 +1031,8 @@ mod tests {
             "message": "{}",
             "senderPublicKey": "{}",
             "signature": "{}",
-            "timestamp": "{}"
+            "timestamp": "{}",
+            "canonical": 2
         }}"#,
             recipient_public_key_hex, message_text, public_key_hex, signature_hex, timestamp
         );
@@ -628,16 +1045,515 @@ mod tests {
                 sender_public_key,
                 recipient_public_key,
                 message,
-                signature: _,
-                timestamp: _,
+                canonical,
+                ..
             } => {
                 assert_eq!(sender_public_key, public_key_hex);
                 assert_eq!(recipient_public_key, recipient_public_key_hex);
                 assert_eq!(message, message_text);
+                assert_eq!(canonical, CanonicalVersion::CURRENT);
             }
             MessageValidationResult::Invalid { reason } => {
                 panic!("Expected Valid, got Invalid: {:?}", reason);
             }
         }
     }
+
+    /// Signatures are checked against the version the message names, and
+    /// messages without one are still taken as version 1
+    #[tokio::test]
+    async fn test_canonical_version_checked() {
+        use profile_shared::canonical;
+        use profile_shared::{derive_public_key, generate_private_key, sign_message};
+
+        let lobby = Lobby::new();
+        let recipient_key = "0000000000000000000000000000000000000000000000000000000000000003";
+        crate::lobby::add_user(
+            &lobby,
+            recipient_key.to_string(),
+            create_test_connection(recipient_key),
+        )
+        .await
+        .unwrap();
+        let private_key = generate_private_key().unwrap();
+        let sender_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        crate::lobby::add_user(
+            &lobby,
+            sender_key.clone(),
+            create_test_connection(&sender_key),
+        )
+        .await
+        .unwrap();
+
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let request = |signed: CanonicalVersion, claimed: Option<CanonicalVersion>| {
+            let signature =
+                sign_message(&private_key, &canonical::message(signed, "a:b", &timestamp)).unwrap();
+            let mut json = serde_json::json!({
+                "type": "message",
+                "recipientPublicKey": recipient_key,
+                "message": "a:b",
+                "senderPublicKey": sender_key,
+                "signature": hex::encode(signature),
+                "timestamp": timestamp
+            });
+            if let Some(claimed) = claimed {
+                json["canonical"] = serde_json::json!(claimed);
+            }
+            json.to_string()
+        };
+
+        let legacy_json = request(CanonicalVersion::V1, None);
+        let legacy = handle_incoming_message(&lobby, &sender_key, &legacy_json).await;
+        assert!(matches!(
+            legacy,
+            MessageValidationResult::Valid { canonical, .. } if canonical == CanonicalVersion::V1
+        ));
+
+        for mismatched in [
+            request(CanonicalVersion::V2, None),
+            request(CanonicalVersion::V1, Some(CanonicalVersion::V2)),
+        ] {
+            assert!(matches!(
+                handle_incoming_message(&lobby, &sender_key, &mismatched).await,
+                MessageValidationResult::Invalid {
+                    reason: ValidationError::SignatureInvalid { .. }
+                }
+            ));
+        }
+    }
+
+    /// Signed message JSON from a fresh sender, who is added to the lobby
+    async fn signed_message_with_id(
+        lobby: &Lobby,
+        recipient_key: &str,
+        message_id: Uuid,
+    ) -> (String, String) {
+        use profile_shared::{derive_public_key, generate_private_key, sign_message};
+
+        let private_key = generate_private_key().unwrap();
+        let sender_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        crate::lobby::add_user(
+            lobby,
+            sender_key.clone(),
+            create_test_connection(&sender_key),
+        )
+        .await
+        .unwrap();
+
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature =
+            sign_message(&private_key, format!("Hello:{}", timestamp).as_bytes()).unwrap();
+        let message_json = serde_json::json!({
+            "type": "message",
+            "messageId": message_id,
+            "recipientPublicKey": recipient_key,
+            "message": "Hello",
+            "senderPublicKey": sender_key,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp
+        });
+        (sender_key, message_json.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_message_id_rejected() {
+        let lobby = Lobby::new();
+        let recipient_key = "0000000000000000000000000000000000000000000000000000000000000002";
+        crate::lobby::add_user(
+            &lobby,
+            recipient_key.to_string(),
+            create_test_connection(recipient_key),
+        )
+        .await
+        .unwrap();
+        let message_id = Uuid::new_v4();
+        let (sender_key, message_json) =
+            signed_message_with_id(&lobby, recipient_key, message_id).await;
+
+        let first = handle_incoming_message(&lobby, &sender_key, &message_json).await;
+        assert!(matches!(
+            first,
+            MessageValidationResult::Valid { message_id: id, .. } if id == message_id
+        ));
+
+        let second = handle_incoming_message(&lobby, &sender_key, &message_json).await;
+        assert_eq!(
+            second,
+            MessageValidationResult::Invalid {
+                reason: ValidationError::DuplicateMessage { message_id }
+            }
+        );
+        assert!(
+            create_error_response(&ValidationError::DuplicateMessage { message_id })
+                .contains("duplicate_message")
+        );
+    }
+
+    /// Message JSON signed over its sequence number, with a fresh id
+    fn sequenced_message_json(
+        private_key: &profile_shared::PrivateKey,
+        sender_key: &str,
+        recipient_key: &str,
+        sequence: Option<u64>,
+    ) -> serde_json::Value {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signed = profile_shared::canonical::direct_message(
+            CanonicalVersion::CURRENT,
+            "Hello",
+            &timestamp,
+            sequence,
+        );
+        let signature = profile_shared::sign_message(private_key, &signed).unwrap();
+        let mut json = serde_json::json!({
+            "type": "message",
+            "messageId": Uuid::new_v4(),
+            "recipientPublicKey": recipient_key,
+            "message": "Hello",
+            "senderPublicKey": sender_key,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp,
+            "canonical": CanonicalVersion::CURRENT,
+        });
+        if let Some(sequence) = sequence {
+            json["sequence"] = sequence.into();
+        }
+        json
+    }
+
+    /// Lobby with a recipient and a fresh sender on connection 1
+    async fn sequenced_lobby(recipient_key: &str) -> (Lobby, profile_shared::PrivateKey, String) {
+        use profile_shared::{derive_public_key, generate_private_key};
+
+        let lobby = Lobby::new();
+        crate::lobby::add_user(
+            &lobby,
+            recipient_key.to_string(),
+            create_test_connection(recipient_key),
+        )
+        .await
+        .unwrap();
+        let private_key = generate_private_key().unwrap();
+        let sender_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        crate::lobby::add_user(
+            &lobby,
+            sender_key.clone(),
+            create_test_connection(&sender_key),
+        )
+        .await
+        .unwrap();
+        (lobby, private_key, sender_key)
+    }
+
+    async fn reconnect(lobby: &Lobby, key: &str, connection_id: u64) {
+        let (sender, _) = send_queue();
+        crate::lobby::add_user(
+            lobby,
+            key.to_string(),
+            ActiveConnection {
+                public_key: key.to_string(),
+                sender,
+                connection_id,
+                status: Default::default(),
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replayed_sequence_rejected_until_reconnect() {
+        let recipient_key = "0000000000000000000000000000000000000000000000000000000000000002";
+        let (lobby, private_key, sender_key) = sequenced_lobby(recipient_key).await;
+        let with_sequence = |sequence: u64| {
+            sequenced_message_json(&private_key, &sender_key, recipient_key, Some(sequence))
+                .to_string()
+        };
+
+        for sequence in [1, 2, 7] {
+            assert!(matches!(
+                handle_incoming_message(&lobby, &sender_key, &with_sequence(sequence)).await,
+                MessageValidationResult::Valid { sequence: Some(s), .. } if s == sequence
+            ));
+        }
+        for sequence in [7, 3] {
+            assert_eq!(
+                handle_incoming_message(&lobby, &sender_key, &with_sequence(sequence)).await,
+                MessageValidationResult::Invalid {
+                    reason: ValidationError::ReplayDetected {
+                        sequence,
+                        last_seen: 7
+                    }
+                }
+            );
+        }
+        assert!(create_error_response(&ValidationError::ReplayDetected {
+            sequence: 3,
+            last_seen: 7
+        })
+        .contains("replay_detected"));
+
+        // A reconnect starts numbering over
+        reconnect(&lobby, &sender_key, 2).await;
+        assert!(matches!(
+            handle_incoming_message(&lobby, &sender_key, &with_sequence(1)).await,
+            MessageValidationResult::Valid { .. }
+        ));
+
+        // As does leaving the lobby
+        crate::lobby::remove_user(&lobby, &sender_key)
+            .await
+            .unwrap();
+        assert_eq!(lobby.sequences.last_seen(&sender_key).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_does_not_advance_sequence() {
+        let recipient_key = "0000000000000000000000000000000000000000000000000000000000000002";
+        let (lobby, private_key, sender_key) = sequenced_lobby(recipient_key).await;
+
+        let first = sequenced_message_json(&private_key, &sender_key, recipient_key, Some(1));
+        assert!(matches!(
+            handle_incoming_message(&lobby, &sender_key, &first.to_string()).await,
+            MessageValidationResult::Valid { .. }
+        ));
+
+        // A message reusing the first one's id is refused as a duplicate
+        let mut duplicate =
+            sequenced_message_json(&private_key, &sender_key, recipient_key, Some(2));
+        duplicate["messageId"] = first["messageId"].clone();
+        assert!(matches!(
+            handle_incoming_message(&lobby, &sender_key, &duplicate.to_string()).await,
+            MessageValidationResult::Invalid {
+                reason: ValidationError::DuplicateMessage { .. }
+            }
+        ));
+        assert_eq!(lobby.sequences.last_seen(&sender_key).await, Some(1));
+
+        // ...without using up its sequence number
+        let next = sequenced_message_json(&private_key, &sender_key, recipient_key, Some(2));
+        assert!(matches!(
+            handle_incoming_message(&lobby, &sender_key, &next.to_string()).await,
+            MessageValidationResult::Valid {
+                sequence: Some(2),
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_sequence_downgrade_rejected() {
+        let recipient_key = "0000000000000000000000000000000000000000000000000000000000000002";
+        let (lobby, private_key, sender_key) = sequenced_lobby(recipient_key).await;
+
+        let captured = sequenced_message_json(&private_key, &sender_key, recipient_key, Some(5));
+        assert!(matches!(
+            handle_incoming_message(&lobby, &sender_key, &captured.to_string()).await,
+            MessageValidationResult::Valid { .. }
+        ));
+
+        // The captured frame replayed with the sequence stripped or bumped
+        // no longer matches its signature
+        let mut stripped = captured.clone();
+        stripped["messageId"] = Uuid::new_v4().to_string().into();
+        stripped.as_object_mut().unwrap().remove("sequence");
+        let mut bumped = captured.clone();
+        bumped["messageId"] = Uuid::new_v4().to_string().into();
+        bumped["sequence"] = 6.into();
+        for tampered in [stripped, bumped] {
+            assert!(matches!(
+                handle_incoming_message(&lobby, &sender_key, &tampered.to_string()).await,
+                MessageValidationResult::Invalid {
+                    reason: ValidationError::SignatureInvalid { .. }
+                }
+            ));
+        }
+
+        // An honestly signed message without a sequence is refused once the
+        // connection has numbered its messages
+        let unsequenced = sequenced_message_json(&private_key, &sender_key, recipient_key, None);
+        assert_eq!(
+            handle_incoming_message(&lobby, &sender_key, &unsequenced.to_string()).await,
+            MessageValidationResult::Invalid {
+                reason: ValidationError::SequenceMissing { last_seen: 5 }
+            }
+        );
+        assert!(
+            create_error_response(&ValidationError::SequenceMissing { last_seen: 5 })
+                .contains("replay_detected")
+        );
+
+        // A new connection may opt out again
+        reconnect(&lobby, &sender_key, 2).await;
+        let unsequenced = sequenced_message_json(&private_key, &sender_key, recipient_key, None);
+        assert!(matches!(
+            handle_incoming_message(&lobby, &sender_key, &unsequenced.to_string()).await,
+            MessageValidationResult::Valid { sequence: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_timestamp_skew_rejected_both_ways() {
+        let now = chrono::Utc::now();
+        let at = |offset_secs: i64| (now + chrono::Duration::seconds(offset_secs)).to_rfc3339();
+
+        assert_eq!(check_timestamp_skew("k", &at(-299), now, 300), Ok(()));
+        assert_eq!(check_timestamp_skew("k", &at(299), now, 300), Ok(()));
+        for offset in [-301, 301, -3600] {
+            assert!(matches!(
+                check_timestamp_skew("k", &at(offset), now, 300),
+                Err(ValidationError::StaleTimestamp { .. })
+            ));
+        }
+        // A narrower configured window rejects what the default allows
+        assert!(matches!(
+            check_timestamp_skew("k", &at(-60), now, 30),
+            Err(ValidationError::StaleTimestamp { .. })
+        ));
+        assert!(matches!(
+            check_timestamp_skew("k", "yesterday", now, 300),
+            Err(ValidationError::MalformedJson { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_timestamp_skew() {
+        assert_eq!(parse_timestamp_skew(None), 300);
+        assert_eq!(parse_timestamp_skew(Some(" 30 ")), 30);
+        // Can't widen past the duplicate-id window or be disabled
+        assert_eq!(parse_timestamp_skew(Some("3600")), 300);
+        assert_eq!(parse_timestamp_skew(Some("0")), 300);
+        assert_eq!(parse_timestamp_skew(Some("soon")), 300);
+    }
+
+    #[test]
+    fn test_parse_max_message_length() {
+        assert_eq!(parse_max_message_length(None), 2000);
+        assert_eq!(parse_max_message_length(Some(" 500 ")), 500);
+        assert_eq!(parse_max_message_length(Some("100000")), 4096);
+        assert_eq!(parse_max_message_length(Some("0")), 2000);
+        assert_eq!(parse_max_message_length(Some("big")), 2000);
+    }
+
+    #[tokio::test]
+    async fn test_replayed_message_with_old_timestamp_rejected() {
+        use profile_shared::{derive_public_key, generate_private_key, sign_message};
+
+        let lobby = Lobby::new();
+        let recipient_key = "0000000000000000000000000000000000000000000000000000000000000004";
+        crate::lobby::add_user(
+            &lobby,
+            recipient_key.to_string(),
+            create_test_connection(recipient_key),
+        )
+        .await
+        .unwrap();
+        let private_key = generate_private_key().unwrap();
+        let sender_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        crate::lobby::add_user(
+            &lobby,
+            sender_key.clone(),
+            create_test_connection(&sender_key),
+        )
+        .await
+        .unwrap();
+
+        // Validly signed, but captured an hour ago
+        let timestamp = (chrono::Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+        let signature =
+            sign_message(&private_key, format!("Hello:{}", timestamp).as_bytes()).unwrap();
+        let message_json = serde_json::json!({
+            "type": "message",
+            "recipientPublicKey": recipient_key,
+            "message": "Hello",
+            "senderPublicKey": sender_key,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp
+        });
+
+        let json = message_json.to_string();
+        let result = handle_incoming_message(&lobby, &sender_key, &json).await;
+        assert!(matches!(
+            result,
+            MessageValidationResult::Invalid {
+                reason: ValidationError::StaleTimestamp { .. }
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_uppercase_hex_routes_to_lowercase_lobby_key() {
+        let lobby = Lobby::new();
+        let recipient_key = "00000000000000000000000000000000000000000000000000000000000000ab";
+        crate::lobby::add_user(
+            &lobby,
+            recipient_key.to_string(),
+            create_test_connection(recipient_key),
+        )
+        .await
+        .unwrap();
+        let (sender_key, message_json) =
+            signed_message_with_id(&lobby, &recipient_key.to_uppercase(), Uuid::new_v4()).await;
+        let mut request: serde_json::Value = serde_json::from_str(&message_json).unwrap();
+        for field in ["senderPublicKey", "signature"] {
+            request[field] = request[field].as_str().unwrap().to_uppercase().into();
+        }
+
+        // Lobby lookup and signature check both succeed on the normalized form
+        let json = request.to_string();
+        let result = handle_incoming_message(&lobby, &sender_key, &json).await;
+        match result {
+            MessageValidationResult::Valid {
+                recipient_public_key,
+                signature,
+                ..
+            } => {
+                assert_eq!(recipient_public_key, recipient_key);
+                assert_eq!(
+                    signature,
+                    request["signature"].as_str().unwrap().to_lowercase()
+                );
+            }
+            MessageValidationResult::Invalid { reason } => {
+                panic!("Expected Valid, got Invalid: {:?}", reason);
+            }
+        }
+
+        // Messaging your own key in upper case is still messaging yourself
+        request["recipientPublicKey"] = sender_key.to_uppercase().into();
+        request["messageId"] = Uuid::new_v4().to_string().into();
+        assert_eq!(
+            handle_incoming_message(&lobby, &sender_key, &request.to_string()).await,
+            MessageValidationResult::Invalid {
+                reason: ValidationError::CannotMessageSelf
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_message_id_not_used_up_while_recipient_offline() {
+        let lobby = Lobby::new();
+        let recipient_key = "0000000000000000000000000000000000000000000000000000000000000003";
+        let (sender_key, message_json) =
+            signed_message_with_id(&lobby, recipient_key, Uuid::new_v4()).await;
+
+        let offline = handle_incoming_message(&lobby, &sender_key, &message_json).await;
+        assert!(matches!(
+            offline,
+            MessageValidationResult::Invalid {
+                reason: ValidationError::RecipientOffline { .. }
+            }
+        ));
+
+        // The queued message is resent with the same id once the recipient is back
+        crate::lobby::add_user(
+            &lobby,
+            recipient_key.to_string(),
+            create_test_connection(recipient_key),
+        )
+        .await
+        .unwrap();
+        let resent = handle_incoming_message(&lobby, &sender_key, &message_json).await;
+        assert!(matches!(resent, MessageValidationResult::Valid { .. }));
+    }
 }
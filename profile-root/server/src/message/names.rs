@@ -0,0 +1,224 @@
+//! Name claim request handling
+//!
+//! Authenticated clients claim or release a verified name with a signed
+//! [`ClaimNameRequest`]. Claims are refused unless the server runs a name
+//! registry (see [`crate::names`]). Accepted changes are broadcast to every
+//! online user, the requester included, as a
+//! [`profile_shared::Message::NameClaimed`].
+
+use crate::lobby::Lobby;
+use crate::message::{validate_signature_queued, validate_timestamp, ValidationError};
+use crate::protocol::ClaimNameRequest;
+use profile_shared::canonical;
+
+/// Value of the `type` field identifying a name claim
+pub const CLAIM_NAME_TYPE: &str = "claim_name";
+
+/// Check whether a client message type is a name claim
+pub fn is_claim_name_request(message_type: &str) -> bool {
+    message_type == CLAIM_NAME_TYPE
+}
+
+/// Handle a name claim from an authenticated user
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users and the registry
+/// * `sender_public_key` - The public key of the requesting user
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the claim was applied, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_claim_name_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    const MAX_MSG_SIZE: usize = profile_shared::config::message::MAX_MESSAGE_SIZE;
+    if request_json.len() > MAX_MSG_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_MSG_SIZE,
+        });
+    }
+
+    if !matches!(
+        crate::lobby::get_user(lobby, sender_public_key).await,
+        Ok(Some(_))
+    ) {
+        return Err(ValidationError::NotAuthenticated {
+            details: format!("User {} is not authenticated", sender_public_key),
+        });
+    }
+
+    let request: ClaimNameRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+    let name = request.name.filter(|n| !n.is_empty());
+
+    validate_timestamp(sender_public_key, &request.timestamp)?;
+    validate_signature_queued(
+        lobby,
+        sender_public_key,
+        canonical::claim_name(name.as_deref().unwrap_or(""), &request.timestamp),
+        &request.signature,
+    )
+    .await?;
+
+    let changed = match name {
+        Some(ref name) => lobby
+            .names
+            .claim(sender_public_key, name, chrono::Utc::now())
+            .await
+            .map_err(|error| ValidationError::NameRejected { error })?,
+        None => lobby
+            .names
+            .release(sender_public_key)
+            .await
+            .map_err(|error| ValidationError::NameRejected { error })?
+            .is_some(),
+    };
+    if !changed {
+        return Ok(());
+    }
+
+    tracing::debug!(?name, "Name claimed");
+    lobby
+        .broadcast(
+            profile_shared::Message::new_name_claimed(sender_public_key.to_string(), name),
+            None,
+        )
+        .map_err(|e| ValidationError::MalformedJson {
+            details: format!("Lobby unavailable: {}", e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::names::{NameError, NameRegistry};
+    use profile_shared::{
+        derive_public_key, generate_private_key, sign_message, Message, PrivateKey,
+    };
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, mut receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        while receiver.try_recv().is_ok() {}
+        receiver
+    }
+
+    fn new_user() -> (PrivateKey, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        (private_key, public_key)
+    }
+
+    fn request(private_key: &PrivateKey, name: &str) -> String {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = hex::encode(
+            sign_message(private_key, &canonical::claim_name(name, &timestamp)).unwrap(),
+        );
+        serde_json::json!({
+            "type": "claim_name",
+            "name": name,
+            "signature": signature,
+            "timestamp": timestamp,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_is_claim_name_request() {
+        assert!(is_claim_name_request("claim_name"));
+        assert!(!is_claim_name_request("set_nickname"));
+    }
+
+    #[tokio::test]
+    async fn test_claim_broadcast_and_kept_after_leaving() {
+        let lobby = Lobby::new().with_names(NameRegistry::in_memory());
+        let (alice_key, alice) = new_user();
+        let (bob_key, bob) = new_user();
+        let mut alice_rx = connect(&lobby, &alice, 1).await;
+        let mut bob_rx = connect(&lobby, &bob, 2).await;
+        while alice_rx.try_recv().is_ok() {}
+
+        handle_claim_name_request(&lobby, &alice, &request(&alice_key, "Alice"))
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        for rx in [&mut alice_rx, &mut bob_rx] {
+            assert_eq!(
+                rx.try_recv().unwrap(),
+                Message::new_name_claimed(alice.clone(), Some("Alice".to_string()))
+            );
+        }
+
+        // Unlike a nickname, the name stays with alice's key after she leaves
+        crate::lobby::remove_user(&lobby, &alice).await.unwrap();
+        assert_eq!(
+            handle_claim_name_request(&lobby, &bob, &request(&bob_key, "alice")).await,
+            Err(ValidationError::NameRejected {
+                error: NameError::NameTaken
+            })
+        );
+        assert_eq!(lobby.names.get(&alice).await.as_deref(), Some("Alice"));
+    }
+
+    #[tokio::test]
+    async fn test_release_and_signature_checked() {
+        let lobby = Lobby::new().with_names(NameRegistry::in_memory());
+        let (alice_key, alice) = new_user();
+        let (_, bob) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let mut bob_rx = connect(&lobby, &bob, 2).await;
+
+        // Signed by alice, claimed by bob
+        assert!(matches!(
+            handle_claim_name_request(&lobby, &bob, &request(&alice_key, "Bob")).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+
+        handle_claim_name_request(&lobby, &alice, &request(&alice_key, "Alice"))
+            .await
+            .unwrap();
+        handle_claim_name_request(&lobby, &alice, &request(&alice_key, ""))
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        bob_rx.try_recv().unwrap();
+        assert_eq!(
+            bob_rx.try_recv().unwrap(),
+            Message::new_name_claimed(alice.clone(), None)
+        );
+        assert!(lobby.names.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn test_claims_refused_without_registry() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let mut alice_rx = connect(&lobby, &alice, 1).await;
+
+        assert_eq!(
+            handle_claim_name_request(&lobby, &alice, &request(&alice_key, "Alice")).await,
+            Err(ValidationError::NameRejected {
+                error: NameError::Disabled
+            })
+        );
+        lobby.flush_broadcasts().await.unwrap();
+        assert!(alice_rx.try_recv().is_err());
+    }
+}
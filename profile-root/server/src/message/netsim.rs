@@ -0,0 +1,330 @@
+//! Simulated routing latency and loss for dev servers
+//!
+//! Debug builds can slow down and lose direct messages on their way to
+//! recipients on this node, configured in `PROFILE_DEV_NETSIM` (or built
+//! directly in tests), so a client's timeouts, resends and acknowledgements
+//! can be exercised on localhost:
+//!
+//! ```text
+//! PROFILE_DEV_NETSIM="latency=50..200,drop=5%,seed=3; ab12cd: latency=1500; ef: drop=0.5"
+//! ```
+//!
+//! Sections are separated by `;`. The first may apply to every recipient;
+//! the others start with a recipient key prefix and a `:`, and override
+//! only the settings they name for recipients whose key starts with it
+//! (the longest matching prefix wins).
+//!
+//! - `latency=MIN..MAX`: hold each message for a random MIN..MAX ms before
+//!   it is queued for the recipient; held messages can overtake each other
+//! - `drop=P`: lose each message with probability P, or `P%` percent. The
+//!   sender is told it was delivered, as if the network lost it
+//! - `seed=N`: seed for the random generator, so a run can be repeated
+//!
+//! Only direct messages, plain or sealed, are affected: lobby updates,
+//! receipts and errors go out as usual. Unlike [`crate::connection::chaos`],
+//! which disturbs whole connections, this acts on routing and can single
+//! out recipients. Release builds ignore `PROFILE_DEV_NETSIM`.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Environment variable holding the simulation spec; off when unset
+pub const NETSIM_ENV_VAR: &str = "PROFILE_DEV_NETSIM";
+
+/// Latency and loss for some recipients; unset fields fall back to the
+/// default profile
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetsimProfile {
+    /// Random hold before each message is queued (min, max)
+    pub latency: Option<(Duration, Duration)>,
+    /// Probability a message is lost
+    pub drop_rate: Option<f64>,
+}
+
+/// Simulation settings: a default profile and per-recipient overrides
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetsimConfig {
+    /// Profile for every recipient
+    pub default: NetsimProfile,
+    /// Overrides keyed by lowercase recipient key prefix
+    pub recipients: Vec<(String, NetsimProfile)>,
+    /// Seed for the random generator
+    pub seed: u64,
+}
+
+/// What to do with one message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetsimVerdict {
+    /// Queue it now
+    Deliver,
+    /// Queue it after the delay
+    Delay(Duration),
+    /// Lose it
+    Drop,
+}
+
+impl NetsimConfig {
+    /// Parse a spec such as `latency=5..50,drop=0.1; ab12: drop=50%`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut config = Self::default();
+        let sections = spec.split(';').map(str::trim).filter(|s| !s.is_empty());
+        for (index, section) in sections.enumerate() {
+            let prefix = match section.split_once(':') {
+                Some((prefix, _)) if !prefix.contains('=') => Some(prefix.trim()),
+                _ => None,
+            };
+            let settings = match prefix {
+                Some(prefix) => {
+                    if prefix.is_empty() || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return Err(format!("Invalid recipient key prefix '{}'", prefix));
+                    }
+                    &section[prefix.len() + 1..]
+                }
+                None if index == 0 => section,
+                None => {
+                    return Err(format!(
+                        "Expected a recipient key prefix before '{}'",
+                        section
+                    ))
+                }
+            };
+
+            let mut profile = NetsimProfile::default();
+            for setting in settings.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                let (name, value) = setting
+                    .split_once('=')
+                    .ok_or_else(|| format!("Expected name=value, got '{}'", setting))?;
+                match (name.trim(), prefix) {
+                    ("latency", _) => profile.latency = Some(parse_latency(value)?),
+                    ("drop", _) => profile.drop_rate = Some(parse_drop_rate(value)?),
+                    ("seed", None) => {
+                        config.seed = value
+                            .trim()
+                            .parse()
+                            .map_err(|e| format!("Invalid seed '{}': {}", value, e))?
+                    }
+                    ("seed", Some(_)) => {
+                        return Err("seed applies to every recipient, not a prefix".to_string())
+                    }
+                    (other, _) => return Err(format!("Unknown netsim setting '{}'", other)),
+                }
+            }
+            match prefix {
+                Some(prefix) => config.recipients.push((prefix.to_lowercase(), profile)),
+                None => config.default = profile,
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read the spec from `PROFILE_DEV_NETSIM` in debug builds
+    ///
+    /// # Returns
+    /// Ok(None) if the variable is unset or this is a release build
+    pub fn from_env() -> Result<Option<Self>, String> {
+        if !cfg!(debug_assertions) {
+            return Ok(None);
+        }
+        match std::env::var(NETSIM_ENV_VAR) {
+            Ok(spec) if !spec.trim().is_empty() => Ok(Some(Self::parse(&spec)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// The profile applying to `recipient`, overrides merged in
+    pub fn profile_for(&self, recipient: &str) -> NetsimProfile {
+        let recipient = recipient.to_lowercase();
+        let mut profile = self.default.clone();
+        if let Some((_, rule)) = self
+            .recipients
+            .iter()
+            .filter(|(prefix, _)| recipient.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+        {
+            profile.latency = rule.latency.or(profile.latency);
+            profile.drop_rate = rule.drop_rate.or(profile.drop_rate);
+        }
+        profile
+    }
+}
+
+fn parse_latency(value: &str) -> Result<(Duration, Duration), String> {
+    let millis = |v: &str| {
+        v.trim()
+            .parse::<u64>()
+            .map(Duration::from_millis)
+            .map_err(|e| format!("Invalid latency '{}': {}", value, e))
+    };
+    let (min, max) = value.split_once("..").unwrap_or((value, value));
+    let (min, max) = (millis(min)?, millis(max)?);
+    if min > max {
+        return Err(format!("Invalid latency '{}': min exceeds max", value));
+    }
+    Ok((min, max))
+}
+
+fn parse_drop_rate(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    let rate = match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+        None => value.parse::<f64>(),
+    };
+    match rate {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!(
+            "Invalid drop '{}': expected a probability between 0 and 1 or a percentage",
+            value
+        )),
+    }
+}
+
+/// Simulated network between the server and its recipients
+#[derive(Debug)]
+pub struct Netsim {
+    config: NetsimConfig,
+    rng: Mutex<StdRng>,
+}
+
+impl Netsim {
+    /// Create the simulation, seeding its generator from the config
+    pub fn new(config: NetsimConfig) -> Arc<Self> {
+        Arc::new(Self {
+            rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+            config,
+        })
+    }
+
+    /// The settings in use
+    pub fn config(&self) -> &NetsimConfig {
+        &self.config
+    }
+
+    /// Decide the fate of the next message to `recipient`
+    pub fn verdict(&self, recipient: &str) -> NetsimVerdict {
+        let profile = self.config.profile_for(recipient);
+        let mut rng = self.rng.lock().unwrap_or_else(|e| e.into_inner());
+        if profile
+            .drop_rate
+            .is_some_and(|rate| rate > 0.0 && rng.gen_bool(rate))
+        {
+            return NetsimVerdict::Drop;
+        }
+        match profile.latency {
+            Some((min, max)) if !max.is_zero() => NetsimVerdict::Delay(rng.gen_range(min..=max)),
+            _ => NetsimVerdict::Deliver,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default_and_recipient_sections() {
+        let config =
+            NetsimConfig::parse("latency=50..200,drop=5%,seed=3; AB12: latency=1500; ab: drop=0.5")
+                .unwrap();
+        assert_eq!(
+            config.default,
+            NetsimProfile {
+                latency: Some((Duration::from_millis(50), Duration::from_millis(200))),
+                drop_rate: Some(0.05),
+            }
+        );
+        assert_eq!(config.seed, 3);
+
+        // Longest prefix wins, and only overrides what it names
+        let profile = config.profile_for(&format!("ab12{}", "0".repeat(60)));
+        assert_eq!(
+            profile.latency,
+            Some((Duration::from_millis(1500), Duration::from_millis(1500)))
+        );
+        assert_eq!(profile.drop_rate, Some(0.05));
+        assert_eq!(
+            config
+                .profile_for(&format!("AB99{}", "0".repeat(60)))
+                .drop_rate,
+            Some(0.5)
+        );
+        assert_eq!(config.profile_for(&"cd".repeat(32)), config.default);
+
+        assert_eq!(NetsimConfig::parse("").unwrap(), NetsimConfig::default());
+        assert_eq!(
+            NetsimConfig::parse("ef: drop=1").unwrap().recipients,
+            vec![(
+                "ef".to_string(),
+                NetsimProfile {
+                    latency: None,
+                    drop_rate: Some(1.0)
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_settings() {
+        assert!(NetsimConfig::parse("drop=150%").is_err());
+        assert!(NetsimConfig::parse("latency=50..5").is_err());
+        assert!(NetsimConfig::parse("jitter=3").is_err());
+        assert!(NetsimConfig::parse("drop=0.1; latency=5").is_err());
+        assert!(NetsimConfig::parse("zz: drop=0.1").is_err());
+        assert!(NetsimConfig::parse("ab: seed=1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delivery_delayed_or_lost_per_recipient() {
+        use crate::connection::send_queue::send_queue;
+        use crate::lobby::{ActiveConnection, Lobby};
+        use profile_shared::Message;
+
+        let (slow, lost) = ("aa".repeat(32), "bb".repeat(32));
+        let lobby = Lobby::new().with_netsim(
+            NetsimConfig::parse(&format!("{}: latency=30; {}: drop=100%", slow, lost)).unwrap(),
+        );
+        let mut inboxes = Vec::new();
+        for (id, key) in [&slow, &lost].into_iter().enumerate() {
+            let (sender, inbox) = send_queue();
+            let conn = ActiveConnection {
+                public_key: key.clone(),
+                sender,
+                connection_id: id as u64 + 1,
+                status: Default::default(),
+            };
+            crate::lobby::add_user(&lobby, key.clone(), conn)
+                .await
+                .unwrap();
+            inboxes.push(inbox);
+        }
+        lobby.flush_broadcasts().await.unwrap();
+        for inbox in &mut inboxes {
+            while inbox.try_recv().is_ok() {}
+        }
+
+        // Both count as delivered, as a lossy network would look
+        assert!(crate::message::deliver(&lobby, &slow, Message::Close).await);
+        assert!(crate::message::deliver(&lobby, &lost, Message::Close).await);
+        assert!(inboxes[0].try_recv().is_err());
+        let held = tokio::time::timeout(Duration::from_secs(5), inboxes[0].recv()).await;
+        assert!(matches!(held, Ok(Some(Message::Close))));
+        assert!(inboxes[1].try_recv().is_err());
+    }
+
+    #[test]
+    fn test_verdicts_follow_profile() {
+        let config = NetsimConfig::parse("latency=10..20; aa: drop=1; bb: latency=0").unwrap();
+        let netsim = Netsim::new(config);
+        assert_eq!(netsim.verdict(&"aa".repeat(32)), NetsimVerdict::Drop);
+        assert_eq!(netsim.verdict(&"bb".repeat(32)), NetsimVerdict::Deliver);
+        for _ in 0..16 {
+            match netsim.verdict(&"cc".repeat(32)) {
+                NetsimVerdict::Delay(delay) => {
+                    assert!((10..=20).contains(&(delay.as_millis() as u64)))
+                }
+                other => panic!("Expected a delay, got {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Nickname request handling
+//!
+//! Authenticated clients set or clear their display name with a signed
+//! [`SetNicknameRequest`]. Accepted changes are recorded in the lobby's
+//! nickname registry and broadcast to every online user, the requester
+//! included, as a [`profile_shared::Message::Nickname`]. A nickname can't
+//! be a name another key claimed in the name registry.
+
+use crate::lobby::Lobby;
+use crate::message::{validate_signature_queued, validate_timestamp, ValidationError};
+use crate::protocol::SetNicknameRequest;
+use profile_shared::{canonical, NicknameError};
+
+/// Value of the `type` field identifying a nickname request
+pub const SET_NICKNAME_TYPE: &str = "set_nickname";
+
+/// Check whether a client message type is a nickname request
+pub fn is_set_nickname_request(message_type: &str) -> bool {
+    message_type == SET_NICKNAME_TYPE
+}
+
+/// Handle a nickname request from an authenticated user
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key of the requesting user
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the nickname was applied, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_set_nickname_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    const MAX_MSG_SIZE: usize = profile_shared::config::message::MAX_MESSAGE_SIZE;
+    if request_json.len() > MAX_MSG_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_MSG_SIZE,
+        });
+    }
+
+    if !matches!(
+        crate::lobby::get_user(lobby, sender_public_key).await,
+        Ok(Some(_))
+    ) {
+        return Err(ValidationError::NotAuthenticated {
+            details: format!("User {} is not authenticated", sender_public_key),
+        });
+    }
+
+    let request: SetNicknameRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+    let nickname = request.nickname.filter(|n| !n.is_empty());
+
+    validate_timestamp(sender_public_key, &request.timestamp)?;
+    validate_signature_queued(
+        lobby,
+        sender_public_key,
+        canonical::nickname(nickname.as_deref().unwrap_or(""), &request.timestamp),
+        &request.signature,
+    )
+    .await?;
+
+    if let Some(ref nickname) = nickname {
+        if lobby
+            .names
+            .owner(nickname)
+            .await
+            .is_some_and(|owner| owner != sender_public_key)
+        {
+            return Err(ValidationError::NicknameRejected {
+                error: NicknameError::NicknameTaken,
+            });
+        }
+    }
+
+    let changed = match nickname {
+        Some(ref nickname) => lobby
+            .nicknames
+            .set(sender_public_key, nickname)
+            .await
+            .map_err(|error| ValidationError::NicknameRejected { error })?,
+        None => lobby.nicknames.clear(sender_public_key).await.is_some(),
+    };
+    if !changed {
+        return Ok(());
+    }
+
+    tracing::debug!(?nickname, "Nickname changed");
+    lobby
+        .broadcast(
+            profile_shared::Message::new_nickname(sender_public_key.to_string(), nickname),
+            None,
+        )
+        .map_err(|e| ValidationError::MalformedJson {
+            details: format!("Lobby unavailable: {}", e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::names::NameRegistry;
+    use profile_shared::{
+        derive_public_key, generate_private_key, sign_message, Message, PrivateKey,
+    };
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    fn new_user() -> (PrivateKey, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        (private_key, public_key)
+    }
+
+    fn request(private_key: &PrivateKey, nickname: &str) -> String {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = hex::encode(
+            sign_message(private_key, &canonical::nickname(nickname, &timestamp)).unwrap(),
+        );
+        serde_json::json!({
+            "type": "set_nickname",
+            "nickname": nickname,
+            "signature": signature,
+            "timestamp": timestamp,
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_is_set_nickname_request() {
+        assert!(is_set_nickname_request("set_nickname"));
+        assert!(!is_set_nickname_request("message"));
+    }
+
+    #[tokio::test]
+    async fn test_nickname_change_broadcast_to_everyone() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (_, bob) = new_user();
+        let mut alice_rx = connect(&lobby, &alice, 1).await;
+        let mut bob_rx = connect(&lobby, &bob, 2).await;
+        while alice_rx.try_recv().is_ok() {}
+
+        handle_set_nickname_request(&lobby, &alice, &request(&alice_key, "alice"))
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+
+        assert_eq!(lobby.nicknames.get(&alice).await.as_deref(), Some("alice"));
+        for rx in [&mut alice_rx, &mut bob_rx] {
+            match rx.try_recv().unwrap() {
+                Message::Nickname {
+                    public_key,
+                    nickname,
+                } => {
+                    assert_eq!(public_key, alice);
+                    assert_eq!(nickname.as_deref(), Some("alice"));
+                }
+                other => panic!("Expected Nickname, got {:?}", other),
+            }
+        }
+
+        // Clearing is broadcast too
+        handle_set_nickname_request(&lobby, &alice, &request(&alice_key, ""))
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        assert_eq!(lobby.nicknames.get(&alice).await, None);
+        assert!(matches!(
+            bob_rx.try_recv(),
+            Ok(Message::Nickname { nickname: None, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_taken_or_invalid_nickname_rejected() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (bob_key, bob) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let _bob_rx = connect(&lobby, &bob, 2).await;
+
+        handle_set_nickname_request(&lobby, &alice, &request(&alice_key, "alice"))
+            .await
+            .unwrap();
+        assert_eq!(
+            handle_set_nickname_request(&lobby, &bob, &request(&bob_key, "ALICE")).await,
+            Err(ValidationError::NicknameRejected {
+                error: NicknameError::NicknameTaken
+            })
+        );
+        assert_eq!(
+            handle_set_nickname_request(&lobby, &bob, &request(&bob_key, &"b".repeat(33))).await,
+            Err(ValidationError::NicknameRejected {
+                error: NicknameError::InvalidNickname
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nickname_cannot_be_someone_elses_claimed_name() {
+        let lobby = Lobby::new().with_names(NameRegistry::in_memory());
+        let (alice_key, alice) = new_user();
+        let (bob_key, bob) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let _bob_rx = connect(&lobby, &bob, 2).await;
+        lobby
+            .names
+            .claim(&alice, "Alice", chrono::Utc::now())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            handle_set_nickname_request(&lobby, &bob, &request(&bob_key, "alice")).await,
+            Err(ValidationError::NicknameRejected {
+                error: NicknameError::NicknameTaken
+            })
+        );
+        handle_set_nickname_request(&lobby, &alice, &request(&alice_key, "alice"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_nickname_signature_checked() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (_, bob) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let _bob_rx = connect(&lobby, &bob, 2).await;
+
+        // Signed by alice, claimed by bob
+        assert!(matches!(
+            handle_set_nickname_request(&lobby, &bob, &request(&alice_key, "bob")).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+        // A signature over a different nickname doesn't carry over
+        let mut tampered: serde_json::Value =
+            serde_json::from_str(&request(&alice_key, "alice")).unwrap();
+        tampered["nickname"] = "mallory".into();
+        assert!(matches!(
+            handle_set_nickname_request(&lobby, &alice, &tampered.to_string()).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+        assert_eq!(lobby.nicknames.get(&alice).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_nickname_released_when_user_leaves() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (bob_key, bob) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let _bob_rx = connect(&lobby, &bob, 2).await;
+
+        handle_set_nickname_request(&lobby, &alice, &request(&alice_key, "alice"))
+            .await
+            .unwrap();
+        crate::lobby::remove_user(&lobby, &alice).await.unwrap();
+
+        handle_set_nickname_request(&lobby, &bob, &request(&bob_key, "alice"))
+            .await
+            .unwrap();
+        assert_eq!(lobby.nicknames.get(&bob).await.as_deref(), Some("alice"));
+    }
+}
@@ -0,0 +1,962 @@
+//! Direct message validation as a pipeline of stages
+//!
+//! [`super::handle_incoming_message`] runs every message through the
+//! lobby's [`ValidationPipeline`]: an ordered list of [`ValidationStage`]s,
+//! each of which either lets the message through or rejects it with a
+//! [`ValidationError`]. The first rejection wins, so a cheap check
+//! registered early spares the expensive ones after it. Stages share a
+//! [`MessageContext`], which carries what earlier stages established (the
+//! sender's connection, the parsed request, a verification slot) to later
+//! ones.
+//!
+//! [`ValidationPipeline::standard`] registers the AC1 sequence:
+//! 1. `size`: the raw request is within MAX_MESSAGE_SIZE
+//! 2. `auth`: the sender is in the lobby and not banned
+//! 3. `schema`: the request parses
+//! 4. `length`: the text is within the configured maximum length
+//! 5. `timestamp`: the timestamp is within the allowed skew
+//! 6. `addressing`: the recipient key is well formed and not the sender's
+//! 7. `rate_limit`: a verification slot is free, else `server_busy`
+//! 8. `signature`: the signature verifies against the sender's key
+//! 9. `recipient`: the recipient is online on some node
+//! 10. `sequence`: the sequence number follows the sender's last one
+//! 11. `duplicate`: the message id wasn't used within the window
+//! 12. `commit_sequence`: the sequence number becomes the sender's last one
+//!
+//! `sequence` only checks the number; it is accepted by `commit_sequence`
+//! once `duplicate` has passed, so a rejected duplicate can't move the
+//! sender's numbering forward.
+//!
+//! New checks plug in with [`ValidationPipeline::with_stage`] or
+//! [`ValidationPipeline::with_stage_before`]. Every stage counts the
+//! messages it passed and rejected and the time it took; see
+//! [`ValidationPipeline::stats`] and the admin `validation_stats` command.
+//!
+//! Pipelines are generic over the [`RequestKind`] they validate. Direct
+//! messages are [`DirectMessage`]; every other request parsed whole from
+//! JSON (a [`JsonRequest`]) is a kind of its own. The side handlers (rooms,
+//! receipts, reactions, edits, retractions, attachments) start from
+//! [`ValidationPipeline::authenticated`] or
+//! [`ValidationPipeline::timestamped`], which share the `size`, `auth`,
+//! `schema` and `timestamp` stages with direct messages, and add a final
+//! stage that checks and acts on their own request.
+
+use super::{
+    get_sender_connection, max_message_length, parse_message_json, recipient_is_online,
+    validate_timestamp, MessageValidationResult, ValidationError,
+};
+use crate::load::VerificationSlot;
+use crate::lobby::{ActiveConnection, Lobby};
+use crate::protocol::SendMessageRequest;
+use profile_shared::canonical;
+use profile_shared::config::message::MAX_MESSAGE_SIZE;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Length of a hex-encoded public key
+const PUBLIC_KEY_HEX_LEN: usize = 64;
+
+/// Future returned by [`ValidationStage::check`]
+pub type StageFuture<'a> = Pin<Box<dyn Future<Output = Result<(), ValidationError>> + Send + 'a>>;
+
+/// A kind of request a [`ValidationPipeline`] validates
+pub trait RequestKind: Send + Sync + fmt::Debug + 'static {
+    /// The parsed request, which may borrow from the raw JSON
+    type Request<'m>: Send + Sync + fmt::Debug;
+
+    /// Largest raw request the `size` stage lets through
+    const MAX_SIZE: usize = MAX_MESSAGE_SIZE;
+
+    /// Parse the raw request
+    fn parse(raw: &str) -> Result<Self::Request<'_>, String>;
+}
+
+/// A kind of request signed over a timestamp, which the `timestamp` stage
+/// checks
+pub trait Timestamped: RequestKind {
+    /// When the request was signed, as sent
+    fn timestamp<'a>(request: &'a Self::Request<'_>) -> &'a str;
+}
+
+/// Direct messages, parsed as a [`SendMessageRequest`]
+#[derive(Debug, Clone, Copy)]
+pub struct DirectMessage;
+
+impl RequestKind for DirectMessage {
+    type Request<'m> = SendMessageRequest<'m>;
+
+    fn parse(raw: &str) -> Result<SendMessageRequest<'_>, String> {
+        parse_message_json(raw)
+    }
+}
+
+impl Timestamped for DirectMessage {
+    fn timestamp<'a>(request: &'a SendMessageRequest<'_>) -> &'a str {
+        &request.timestamp
+    }
+}
+
+/// A request other than a direct message, parsed whole from JSON
+///
+/// Every such request type is a [`RequestKind`] of its own.
+pub trait JsonRequest: DeserializeOwned + Send + Sync + fmt::Debug + 'static {
+    /// Largest raw request the `size` stage lets through
+    const MAX_SIZE: usize = MAX_MESSAGE_SIZE;
+}
+
+impl<R: JsonRequest> RequestKind for R {
+    type Request<'m> = R;
+
+    const MAX_SIZE: usize = <R as JsonRequest>::MAX_SIZE;
+
+    fn parse(raw: &str) -> Result<R, String> {
+        serde_json::from_str(raw).map_err(|e| format!("Invalid JSON: {}", e))
+    }
+}
+
+/// One check in a validation pipeline for requests of kind `K`
+pub trait ValidationStage<K: RequestKind = DirectMessage>: Send + Sync + fmt::Debug {
+    /// Short name used in metrics
+    fn name(&self) -> &'static str;
+
+    /// Let the message in `ctx` through, or reject it
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m, K>) -> StageFuture<'a>;
+}
+
+/// A message on its way through the pipeline
+#[derive(Debug)]
+pub struct MessageContext<'m, K: RequestKind = DirectMessage> {
+    pub lobby: &'m Lobby,
+    pub sender_public_key: &'m str,
+    /// The request JSON as received
+    pub raw: &'m str,
+    /// The sender's connection, once the `auth` stage found it
+    pub sender: Option<Arc<ActiveConnection>>,
+    /// The parsed request, once the `schema` stage parsed it
+    pub request: Option<K::Request<'m>>,
+    /// A reserved verification slot, once the `rate_limit` stage took one
+    pub verification: Option<VerificationSlot>,
+}
+
+impl<'m, K: RequestKind> MessageContext<'m, K> {
+    /// Start validating `raw` from `sender_public_key`
+    pub fn new(lobby: &'m Lobby, sender_public_key: &'m str, raw: &'m str) -> Self {
+        Self {
+            lobby,
+            sender_public_key,
+            raw,
+            sender: None,
+            request: None,
+            verification: None,
+        }
+    }
+
+    /// The sender's connection
+    ///
+    /// # Returns
+    /// Err(NotAuthenticated) if no earlier stage looked it up
+    pub fn sender(&self) -> Result<&Arc<ActiveConnection>, ValidationError> {
+        self.sender
+            .as_ref()
+            .ok_or_else(|| ValidationError::NotAuthenticated {
+                details: format!("User {} is not authenticated", self.sender_public_key),
+            })
+    }
+
+    /// The parsed request
+    ///
+    /// # Returns
+    /// Err(MalformedJson) if no earlier stage parsed it
+    pub fn request(&self) -> Result<&K::Request<'m>, ValidationError> {
+        self.request.as_ref().ok_or_else(not_parsed)
+    }
+
+    /// Take the parsed request, for a final stage acting on it
+    ///
+    /// # Returns
+    /// Err(MalformedJson) if no earlier stage parsed it
+    pub fn take_request(&mut self) -> Result<K::Request<'m>, ValidationError> {
+        self.request.take().ok_or_else(not_parsed)
+    }
+}
+
+impl<'m> MessageContext<'m> {
+    /// The validated message, once every stage has passed
+    fn into_result(self) -> MessageValidationResult<'m> {
+        let Some(request) = self.request else {
+            return MessageValidationResult::Invalid {
+                reason: not_parsed(),
+            };
+        };
+        MessageValidationResult::Valid {
+            message_id: request.message_id,
+            sender_public_key: Cow::Borrowed(self.sender_public_key),
+            recipient_public_key: request.recipient_public_key,
+            message: request.message,
+            signature: request.signature,
+            timestamp: request.timestamp,
+            canonical: request.canonical,
+            sequence: request.sequence,
+        }
+    }
+}
+
+fn not_parsed() -> ValidationError {
+    ValidationError::MalformedJson {
+        details: "Message was not parsed".to_string(),
+    }
+}
+
+/// Counters kept for one stage
+#[derive(Debug, Default)]
+struct StageMetrics {
+    passed: AtomicU64,
+    rejected: AtomicU64,
+    total_micros: AtomicU64,
+}
+
+impl StageMetrics {
+    fn record(&self, passed: bool, elapsed: Duration) {
+        let counter = if passed { &self.passed } else { &self.rejected };
+        counter.fetch_add(1, Ordering::Relaxed);
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+    }
+}
+
+/// What one stage has done since startup, as reported by `validation_stats`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StageStats {
+    pub stage: &'static str,
+    /// Messages the stage let through
+    pub passed: u64,
+    /// Messages the stage rejected
+    pub rejected: u64,
+    /// Mean time spent in the stage per message, in microseconds
+    #[serde(rename = "meanMicros")]
+    pub mean_micros: u64,
+}
+
+#[derive(Debug)]
+struct RegisteredStage<K: RequestKind> {
+    stage: Box<dyn ValidationStage<K>>,
+    metrics: StageMetrics,
+}
+
+/// Ordered validation stages for requests of kind `K`, direct messages
+/// unless named
+#[derive(Debug)]
+pub struct ValidationPipeline<K: RequestKind = DirectMessage> {
+    stages: Vec<RegisteredStage<K>>,
+}
+
+impl<K: RequestKind> Default for ValidationPipeline<K> {
+    fn default() -> Self {
+        Self { stages: Vec::new() }
+    }
+}
+
+impl ValidationPipeline {
+    /// Create a pipeline running the AC1 sequence
+    pub fn standard() -> Self {
+        Self::new()
+            .with_stage(FrameSize)
+            .with_stage(SenderAuth)
+            .with_stage(Schema)
+            .with_stage(TextLength)
+            .with_stage(Timestamp)
+            .with_stage(Addressing)
+            .with_stage(VerificationRateLimit)
+            .with_stage(Signature)
+            .with_stage(RecipientOnline)
+            .with_stage(Sequence)
+            .with_stage(Duplicate)
+            .with_stage(CommitSequence)
+    }
+
+    /// Run `raw` from `sender_public_key` through every stage in order,
+    /// stopping at the first rejection
+    pub async fn run<'m>(
+        &self,
+        lobby: &'m Lobby,
+        sender_public_key: &'m str,
+        raw: &'m str,
+    ) -> MessageValidationResult<'m> {
+        match self.validate(lobby, sender_public_key, raw).await {
+            Ok(ctx) => ctx.into_result(),
+            Err(reason) => MessageValidationResult::Invalid { reason },
+        }
+    }
+}
+
+impl<K: RequestKind> ValidationPipeline<K> {
+    /// Create a pipeline with no stages, which for direct messages only
+    /// checks that something parsed the request
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a pipeline checking the request's size, that its sender is
+    /// in the lobby and not banned, and that it parses
+    pub fn authenticated() -> Self {
+        Self::new()
+            .with_stage(FrameSize)
+            .with_stage(SenderAuth)
+            .with_stage(Schema)
+    }
+
+    /// Run `stage` after the stages registered so far
+    pub fn with_stage(mut self, stage: impl ValidationStage<K> + 'static) -> Self {
+        self.stages.push(RegisteredStage {
+            stage: Box::new(stage),
+            metrics: StageMetrics::default(),
+        });
+        self
+    }
+
+    /// Run `stage` just before the stage named `before`, or last if there
+    /// is none
+    pub fn with_stage_before(
+        mut self,
+        before: &str,
+        stage: impl ValidationStage<K> + 'static,
+    ) -> Self {
+        let position = self
+            .stages
+            .iter()
+            .position(|registered| registered.stage.name() == before)
+            .unwrap_or(self.stages.len());
+        self.stages.insert(
+            position,
+            RegisteredStage {
+                stage: Box::new(stage),
+                metrics: StageMetrics::default(),
+            },
+        );
+        self
+    }
+
+    /// Names of the stages, in the order they run
+    pub fn stage_names(&self) -> Vec<&'static str> {
+        self.stages
+            .iter()
+            .map(|registered| registered.stage.name())
+            .collect()
+    }
+
+    /// Counters for every stage, in the order they run
+    pub fn stats(&self) -> Vec<StageStats> {
+        self.stages
+            .iter()
+            .map(|registered| {
+                let metrics = &registered.metrics;
+                let passed = metrics.passed.load(Ordering::Relaxed);
+                let rejected = metrics.rejected.load(Ordering::Relaxed);
+                StageStats {
+                    stage: registered.stage.name(),
+                    passed,
+                    rejected,
+                    mean_micros: metrics
+                        .total_micros
+                        .load(Ordering::Relaxed)
+                        .checked_div(passed + rejected)
+                        .unwrap_or(0),
+                }
+            })
+            .collect()
+    }
+
+    /// Run `raw` from `sender_public_key` through every stage in order,
+    /// stopping at the first rejection
+    ///
+    /// # Returns
+    /// The context the stages left behind, or the first rejection
+    pub async fn validate<'m>(
+        &self,
+        lobby: &'m Lobby,
+        sender_public_key: &'m str,
+        raw: &'m str,
+    ) -> Result<MessageContext<'m, K>, ValidationError> {
+        let mut ctx = MessageContext::new(lobby, sender_public_key, raw);
+        for registered in &self.stages {
+            let started = Instant::now();
+            let result = registered.stage.check(&mut ctx).await;
+            registered.metrics.record(result.is_ok(), started.elapsed());
+            result?;
+        }
+        Ok(ctx)
+    }
+}
+
+impl<K: Timestamped> ValidationPipeline<K> {
+    /// Create a pipeline running the [`ValidationPipeline::authenticated`]
+    /// stages, then checking the request's timestamp
+    pub fn timestamped() -> Self {
+        Self::authenticated().with_stage(Timestamp)
+    }
+}
+
+/// Reject requests over the kind's maximum size (MAX_MESSAGE_SIZE unless
+/// it says otherwise) before parsing them
+#[derive(Debug, Clone, Copy)]
+pub struct FrameSize;
+
+impl<K: RequestKind> ValidationStage<K> for FrameSize {
+    fn name(&self) -> &'static str {
+        "size"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m, K>) -> StageFuture<'a> {
+        Box::pin(async move {
+            if ctx.raw.len() > K::MAX_SIZE {
+                tracing::warn!(
+                    sender = %ctx.sender_public_key,
+                    size = ctx.raw.len(),
+                    max = K::MAX_SIZE,
+                    "Message too large"
+                );
+                return Err(ValidationError::MessageTooLarge {
+                    size: ctx.raw.len(),
+                    max: K::MAX_SIZE,
+                });
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Require the sender to be in the lobby and not banned
+///
+/// Only authenticated users reach the pipeline, but a ban may land while
+/// a message is in flight.
+#[derive(Debug, Clone, Copy)]
+pub struct SenderAuth;
+
+impl<K: RequestKind> ValidationStage<K> for SenderAuth {
+    fn name(&self) -> &'static str {
+        "auth"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m, K>) -> StageFuture<'a> {
+        Box::pin(async move {
+            let Some(sender) = get_sender_connection(ctx.lobby, ctx.sender_public_key).await else {
+                tracing::warn!(sender = %ctx.sender_public_key, "Sender not found in lobby - rejecting message");
+                return Err(ValidationError::NotAuthenticated {
+                    details: format!("User {} is not authenticated", ctx.sender_public_key),
+                });
+            };
+            if let Some(ban) = ctx.lobby.moderation.active_ban(ctx.sender_public_key).await {
+                tracing::warn!(sender = %ctx.sender_public_key, "Message from banned key rejected");
+                return Err(ValidationError::SenderBanned {
+                    details: ban.describe(),
+                });
+            }
+            ctx.sender = Some(sender);
+            Ok(())
+        })
+    }
+}
+
+/// Parse the request
+#[derive(Debug, Clone, Copy)]
+pub struct Schema;
+
+impl<K: RequestKind> ValidationStage<K> for Schema {
+    fn name(&self) -> &'static str {
+        "schema"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m, K>) -> StageFuture<'a> {
+        Box::pin(async move {
+            match K::parse(ctx.raw) {
+                Ok(request) => {
+                    ctx.request = Some(request);
+                    Ok(())
+                }
+                Err(e) => {
+                    tracing::warn!(error = %e, "Invalid JSON format from {}", ctx.sender_public_key);
+                    Err(ValidationError::MalformedJson { details: e })
+                }
+            }
+        })
+    }
+}
+
+/// Reject text longer than the configured maximum
+#[derive(Debug, Clone, Copy)]
+pub struct TextLength;
+
+impl ValidationStage for TextLength {
+    fn name(&self) -> &'static str {
+        "length"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+        Box::pin(async move {
+            let length = ctx.request()?.message.len();
+            let max_length = max_message_length();
+            if length > max_length {
+                tracing::warn!(
+                    sender = %ctx.sender_public_key,
+                    length,
+                    max = max_length,
+                    "Message text too long"
+                );
+                return Err(ValidationError::MessageTooLarge {
+                    size: length,
+                    max: max_length,
+                });
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Reject timestamps outside the allowed skew, which stops old messages
+/// being replayed
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamp;
+
+impl<K: Timestamped> ValidationStage<K> for Timestamp {
+    fn name(&self) -> &'static str {
+        "timestamp"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m, K>) -> StageFuture<'a> {
+        Box::pin(
+            async move { validate_timestamp(ctx.sender_public_key, K::timestamp(ctx.request()?)) },
+        )
+    }
+}
+
+/// Require a well-formed recipient key other than the sender's own
+#[derive(Debug, Clone, Copy)]
+pub struct Addressing;
+
+impl ValidationStage for Addressing {
+    fn name(&self) -> &'static str {
+        "addressing"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+        Box::pin(async move {
+            let recipient = &ctx.request()?.recipient_public_key;
+            if recipient == ctx.sender_public_key {
+                return Err(ValidationError::CannotMessageSelf);
+            }
+            if recipient.len() != PUBLIC_KEY_HEX_LEN
+                || !recipient.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Err(ValidationError::MalformedJson {
+                    details: format!(
+                        "Invalid recipient public key format: expected {} hex characters",
+                        PUBLIC_KEY_HEX_LEN
+                    ),
+                });
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Shed the message as `server_busy` if the verification queue is full
+#[derive(Debug, Clone, Copy)]
+pub struct VerificationRateLimit;
+
+impl ValidationStage for VerificationRateLimit {
+    fn name(&self) -> &'static str {
+        "rate_limit"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+        Box::pin(async move {
+            match ctx.lobby.verifications.try_reserve() {
+                Ok(slot) => {
+                    ctx.verification = Some(slot);
+                    Ok(())
+                }
+                Err(retry_after) => {
+                    tracing::warn!(
+                        sender = %ctx.sender_public_key,
+                        pending = ctx.lobby.verifications.pending(),
+                        "Verification queue full - shedding message"
+                    );
+                    Err(ValidationError::ServerBusy {
+                        recipient_key: ctx.request()?.recipient_public_key.to_string(),
+                        retry_after,
+                    })
+                }
+            }
+        })
+    }
+}
+
+/// Verify the signature against the sender's key
+///
+/// Uses the slot reserved by the `rate_limit` stage, or waits for one if
+/// the pipeline has no such stage.
+#[derive(Debug, Clone, Copy)]
+pub struct Signature;
+
+impl ValidationStage for Signature {
+    fn name(&self) -> &'static str {
+        "signature"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+        Box::pin(async move {
+            let slot = match ctx.verification.take() {
+                Some(slot) => slot,
+                None => ctx.lobby.verifications.reserve().await,
+            };
+            let request = ctx.request()?;
+            let canonical = canonical::direct_message(
+                request.canonical,
+                &request.message,
+                &request.timestamp,
+                request.sequence,
+            );
+            slot.verify(ctx.sender_public_key, canonical, &request.signature)
+                .await?;
+            tracing::debug!(recipient = %request.recipient_public_key, "Signature verified");
+            Ok(())
+        })
+    }
+}
+
+/// Require the recipient to be online on this or, in a cluster, any node
+#[derive(Debug, Clone, Copy)]
+pub struct RecipientOnline;
+
+impl ValidationStage for RecipientOnline {
+    fn name(&self) -> &'static str {
+        "recipient"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+        Box::pin(async move {
+            let recipient = &ctx.request()?.recipient_public_key;
+            if recipient_is_online(ctx.lobby, recipient).await {
+                Ok(())
+            } else {
+                Err(ValidationError::RecipientOffline {
+                    recipient_key: recipient.to_string(),
+                })
+            }
+        })
+    }
+}
+
+/// Reject a sequence number that doesn't follow the sender's last one on
+/// this connection, or a message without one once the sender has numbered
+/// a message on it
+///
+/// Only checks the number; see [`CommitSequence`].
+#[derive(Debug, Clone, Copy)]
+pub struct Sequence;
+
+impl ValidationStage for Sequence {
+    fn name(&self) -> &'static str {
+        "sequence"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+        Box::pin(async move {
+            let connection_id = ctx.sender()?.connection_id;
+            let Some(sequence) = ctx.request()?.sequence else {
+                let sequences = &ctx.lobby.sequences;
+                return match sequences
+                    .last_on_connection(ctx.sender_public_key, connection_id)
+                    .await
+                {
+                    Some(last_seen) => {
+                        tracing::warn!(
+                            sender = %ctx.sender_public_key,
+                            last_seen,
+                            "Sequence dropped from a numbered connection"
+                        );
+                        Err(ValidationError::SequenceMissing { last_seen })
+                    }
+                    None => Ok(()),
+                };
+            };
+            if let Err(last_seen) = ctx
+                .lobby
+                .sequences
+                .check(ctx.sender_public_key, connection_id, sequence)
+                .await
+            {
+                return Err(replay_detected(ctx.sender_public_key, sequence, last_seen));
+            }
+            Ok(())
+        })
+    }
+}
+
+fn replay_detected(sender_public_key: &str, sequence: u64, last_seen: u64) -> ValidationError {
+    tracing::warn!(
+        sender = %sender_public_key,
+        sequence,
+        last_seen,
+        "Replayed or out-of-order sequence number"
+    );
+    ValidationError::ReplayDetected {
+        sequence,
+        last_seen,
+    }
+}
+
+/// Reject a message id the sender already used within the window
+///
+/// Runs last, so only ids of messages that get routed are remembered and a
+/// message refused while the recipient was offline can be resent.
+#[derive(Debug, Clone, Copy)]
+pub struct Duplicate;
+
+impl ValidationStage for Duplicate {
+    fn name(&self) -> &'static str {
+        "duplicate"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+        Box::pin(async move {
+            let message_id = ctx.request()?.message_id;
+            if !ctx
+                .lobby
+                .message_ids
+                .record(ctx.sender_public_key, message_id, Instant::now())
+                .await
+            {
+                tracing::warn!(
+                    sender = %ctx.sender_public_key,
+                    message_id = %message_id,
+                    "Duplicate message id"
+                );
+                return Err(ValidationError::DuplicateMessage { message_id });
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Accept the message's sequence number as the sender's last one
+///
+/// Runs after `duplicate`, so only messages that get routed move the
+/// sender's numbering forward. Still rejects the number if a concurrent
+/// message took it since the `sequence` stage checked it.
+#[derive(Debug, Clone, Copy)]
+pub struct CommitSequence;
+
+impl ValidationStage for CommitSequence {
+    fn name(&self) -> &'static str {
+        "commit_sequence"
+    }
+
+    fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+        Box::pin(async move {
+            let connection_id = ctx.sender()?.connection_id;
+            let Some(sequence) = ctx.request()?.sequence else {
+                return Ok(());
+            };
+            ctx.lobby
+                .sequences
+                .advance(ctx.sender_public_key, connection_id, sequence)
+                .await
+                .map_err(|last_seen| replay_detected(ctx.sender_public_key, sequence, last_seen))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::send_queue;
+    use profile_shared::{derive_public_key, generate_private_key, sign_message};
+
+    /// Rejects every message whose text contains a word
+    #[derive(Debug)]
+    struct Blocklist(&'static str);
+
+    impl ValidationStage for Blocklist {
+        fn name(&self) -> &'static str {
+            "blocklist"
+        }
+
+        fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m>) -> StageFuture<'a> {
+            Box::pin(async move {
+                if ctx.request()?.message.contains(self.0) {
+                    return Err(ValidationError::MalformedJson {
+                        details: "Blocked".to_string(),
+                    });
+                }
+                Ok(())
+            })
+        }
+    }
+
+    async fn join(lobby: &Lobby, key: &str) {
+        let (sender, _) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stages_run_in_order_and_count_outcomes() {
+        let lobby = Lobby::new();
+        let private_key = generate_private_key().unwrap();
+        let sender = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let recipient = "b".repeat(64);
+        join(&lobby, &sender).await;
+        join(&lobby, &recipient).await;
+
+        let pipeline =
+            ValidationPipeline::standard().with_stage_before("signature", Blocklist("spam"));
+        let names = pipeline.stage_names();
+        assert_eq!(names.len(), 13);
+        assert_eq!(&names[6..9], ["rate_limit", "blocklist", "signature"]);
+
+        let request = |text: &str| {
+            let timestamp = chrono::Utc::now().to_rfc3339();
+            let signature =
+                sign_message(&private_key, format!("{}:{}", text, timestamp).as_bytes()).unwrap();
+            serde_json::json!({
+                "type": "message",
+                "recipientPublicKey": recipient,
+                "message": text,
+                "senderPublicKey": sender,
+                "signature": hex::encode(signature),
+                "timestamp": timestamp,
+            })
+            .to_string()
+        };
+        let hello = request("hello");
+        let spam = request("buy spam");
+        assert!(matches!(
+            pipeline.run(&lobby, &sender, &hello).await,
+            MessageValidationResult::Valid { ref message, .. } if message == "hello"
+        ));
+        assert!(matches!(
+            pipeline.run(&lobby, &sender, &spam).await,
+            MessageValidationResult::Invalid {
+                reason: ValidationError::MalformedJson { .. }
+            }
+        ));
+        assert!(matches!(
+            pipeline.run(&lobby, &sender, "{").await,
+            MessageValidationResult::Invalid {
+                reason: ValidationError::MalformedJson { .. }
+            }
+        ));
+
+        let stats = pipeline.stats();
+        let outcomes: Vec<(&str, u64, u64)> = stats
+            .iter()
+            .map(|s| (s.stage, s.passed, s.rejected))
+            .collect();
+        assert_eq!(outcomes[0], ("size", 3, 0));
+        assert_eq!(outcomes[2], ("schema", 2, 1));
+        assert_eq!(outcomes[7], ("blocklist", 1, 1));
+        assert_eq!(outcomes[8], ("signature", 1, 0));
+        assert_eq!(outcomes[11], ("duplicate", 1, 0));
+        assert_eq!(outcomes[12], ("commit_sequence", 1, 0));
+    }
+
+    /// A side request with a small size limit
+    #[derive(Debug, serde::Deserialize)]
+    struct Ping {
+        timestamp: String,
+    }
+
+    impl JsonRequest for Ping {
+        const MAX_SIZE: usize = 64;
+    }
+
+    impl Timestamped for Ping {
+        fn timestamp(request: &Self) -> &str {
+            &request.timestamp
+        }
+    }
+
+    /// Final stage acting on a ping
+    #[derive(Debug)]
+    struct Pong;
+
+    impl ValidationStage<Ping> for Pong {
+        fn name(&self) -> &'static str {
+            "pong"
+        }
+
+        fn check<'a, 'm>(&'a self, ctx: &'a mut MessageContext<'m, Ping>) -> StageFuture<'a> {
+            Box::pin(async move { ctx.take_request().map(|_| ()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_side_requests_share_the_common_stages() {
+        let lobby = Lobby::new();
+        let sender = "a".repeat(64);
+        let pipeline = ValidationPipeline::<Ping>::timestamped().with_stage(Pong);
+        assert_eq!(
+            pipeline.stage_names(),
+            ["size", "auth", "schema", "timestamp", "pong"]
+        );
+
+        let ping = |timestamp: &str| serde_json::json!({ "timestamp": timestamp }).to_string();
+        let now = chrono::Utc::now().to_rfc3339();
+        assert!(matches!(
+            pipeline.validate(&lobby, &sender, &ping(&now)).await,
+            Err(ValidationError::NotAuthenticated { .. })
+        ));
+        join(&lobby, &sender).await;
+        assert!(pipeline
+            .validate(&lobby, &sender, &ping(&now))
+            .await
+            .is_ok());
+        assert!(matches!(
+            pipeline
+                .validate(&lobby, &sender, &ping("2020-01-01T00:00:00Z"))
+                .await,
+            Err(ValidationError::StaleTimestamp { .. })
+        ));
+        // The kind's own size limit applies
+        let padded = format!("{}{}", ping(&now), " ".repeat(64));
+        assert!(matches!(
+            pipeline.validate(&lobby, &sender, &padded).await,
+            Err(ValidationError::MessageTooLarge { max: 64, .. })
+        ));
+
+        let outcomes: Vec<(&str, u64, u64)> = pipeline
+            .stats()
+            .iter()
+            .map(|s| (s.stage, s.passed, s.rejected))
+            .collect();
+        assert_eq!(outcomes[0], ("size", 3, 1));
+        assert_eq!(outcomes[4], ("pong", 1, 0));
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_without_schema_rejects_message() {
+        let lobby = Lobby::new();
+        let pipeline = ValidationPipeline::new().with_stage(FrameSize);
+        assert_eq!(
+            pipeline.run(&lobby, "a", "{}").await,
+            MessageValidationResult::Invalid {
+                reason: not_parsed()
+            }
+        );
+    }
+}
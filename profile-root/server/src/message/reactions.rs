@@ -0,0 +1,251 @@
+//! Reaction routing
+//!
+//! Either side of a conversation reacts to one of its messages with a
+//! signed [`ReactionRequest`] naming the message id and an emoji, and takes
+//! a reaction back with the same request and `removed` set. The server
+//! checks the emoji's length and the signature, then forwards a
+//! [`profile_shared::Message::Reaction`] to the recipient the same way as an
+//! edit (see [`crate::message::edit`]).
+//!
+//! The server doesn't keep messages or reactions; each client keeps the
+//! reactions to the messages it has. Nothing is queued for offline
+//! recipients.
+
+use crate::audit::AuditEvent;
+use crate::lobby::Lobby;
+use crate::message::pipeline::{
+    JsonRequest, MessageContext, StageFuture, Timestamped, ValidationPipeline, ValidationStage,
+};
+use crate::message::{validate_signature_queued, ValidationError};
+use crate::protocol::ReactionRequest;
+use profile_shared::canonical;
+use profile_shared::config::message::MAX_REACTION_LENGTH;
+
+/// Value of the `type` field identifying a reaction request
+pub const REACTION_TYPE: &str = "reaction";
+
+/// Check whether a client message type is a reaction request
+pub fn is_reaction_request(message_type: &str) -> bool {
+    message_type == REACTION_TYPE
+}
+
+impl JsonRequest for ReactionRequest {}
+
+impl Timestamped for ReactionRequest {
+    fn timestamp(request: &Self) -> &str {
+        &request.timestamp
+    }
+}
+
+/// Validate a reaction and forward it to the recipient
+///
+/// Runs the [`ValidationPipeline::timestamped`] stages, then
+/// [`ForwardReaction`].
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key of the authenticated sender
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the reaction was forwarded, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_reaction_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    ValidationPipeline::<ReactionRequest>::timestamped()
+        .with_stage(ForwardReaction)
+        .validate(lobby, sender_public_key, request_json)
+        .await
+        .map(|_| ())
+}
+
+/// Check the emoji and the signature, then forward the reaction
+#[derive(Debug, Clone, Copy)]
+pub struct ForwardReaction;
+
+impl ValidationStage<ReactionRequest> for ForwardReaction {
+    fn name(&self) -> &'static str {
+        "reaction"
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, ReactionRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.take_request()?;
+            let (lobby, sender_public_key) = (ctx.lobby, ctx.sender_public_key);
+
+            if request.emoji.trim().is_empty() {
+                return Err(ValidationError::MalformedJson {
+                    details: "Reaction must not be empty".to_string(),
+                });
+            }
+            if request.emoji.len() > MAX_REACTION_LENGTH {
+                return Err(ValidationError::MessageTooLarge {
+                    size: request.emoji.len(),
+                    max: MAX_REACTION_LENGTH,
+                });
+            }
+
+            if request.recipient_public_key == sender_public_key {
+                return Err(ValidationError::CannotMessageSelf);
+            }
+
+            validate_signature_queued(
+                lobby,
+                sender_public_key,
+                canonical::message_reaction(
+                    request.message_id,
+                    &request.emoji,
+                    request.removed,
+                    &request.timestamp,
+                ),
+                &request.signature,
+            )
+            .await?;
+
+            let reaction = profile_shared::Message::new_reaction(
+                request.message_id,
+                request.emoji,
+                request.removed,
+                sender_public_key.to_string(),
+                request.signature,
+                request.timestamp,
+            );
+            if !crate::message::deliver(lobby, &request.recipient_public_key, reaction).await {
+                lobby.audit.record(AuditEvent::RoutingFailed {
+                    sender_public_key: sender_public_key.to_string(),
+                    recipient_public_key: request.recipient_public_key.clone(),
+                    reason: "Recipient went offline".to_string(),
+                });
+                return Err(ValidationError::RecipientOffline {
+                    recipient_key: request.recipient_public_key,
+                });
+            }
+
+            tracing::debug!(
+                to = %request.recipient_public_key.chars().take(16).collect::<String>(),
+                message_id = %request.message_id,
+                removed = request.removed,
+                "Reaction forwarded"
+            );
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::message::message_type;
+    use profile_shared::{derive_public_key, generate_private_key, sign_message, Message};
+    use uuid::Uuid;
+
+    const RECIPIENT_KEY: &str = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+
+    async fn add_connection(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    /// A sender key and a reaction request signed with it
+    fn signed_reaction(message_id: Uuid, emoji: &str, removed: bool) -> (String, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            &private_key,
+            &canonical::message_reaction(message_id, emoji, removed, &timestamp),
+        )
+        .unwrap();
+        let request = serde_json::json!({
+            "type": "reaction",
+            "recipientPublicKey": RECIPIENT_KEY,
+            "messageId": message_id,
+            "emoji": emoji,
+            "removed": removed,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp,
+        });
+        (public_key, request.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_reaction_forwarded_with_sender_from_connection() {
+        let lobby = Lobby::new();
+        let message_id = Uuid::new_v4();
+        let (sender_key, request) = signed_reaction(message_id, "🎉", true);
+        assert!(is_reaction_request(&message_type(&request).unwrap()));
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let mut recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+
+        handle_reaction_request(&lobby, &sender_key, &request)
+            .await
+            .unwrap();
+
+        loop {
+            match recipient_rx.try_recv().unwrap() {
+                Message::Reaction {
+                    message_id: id,
+                    emoji,
+                    removed,
+                    sender_public_key,
+                    ..
+                } => {
+                    assert_eq!(id, message_id);
+                    assert_eq!(emoji, "🎉");
+                    assert!(removed);
+                    assert_eq!(sender_public_key, sender_key);
+                    break;
+                }
+                Message::LobbyUpdate { .. } => {}
+                other => panic!("Expected Reaction, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_removal_signed_as_addition_rejected() {
+        let lobby = Lobby::new();
+        let (sender_key, request) = signed_reaction(Uuid::new_v4(), "👍", false);
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let _recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+
+        let mut flipped: serde_json::Value = serde_json::from_str(&request).unwrap();
+        flipped["removed"] = true.into();
+        let result = handle_reaction_request(&lobby, &sender_key, &flipped.to_string()).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_overlong_reaction_rejected() {
+        let lobby = Lobby::new();
+        let (sender_key, request) = signed_reaction(Uuid::new_v4(), &"x".repeat(40), false);
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+
+        let result = handle_reaction_request(&lobby, &sender_key, &request).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::MessageTooLarge { max, .. }) if max == MAX_REACTION_LENGTH
+        ));
+    }
+}
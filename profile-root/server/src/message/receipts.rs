@@ -0,0 +1,242 @@
+//! Read receipt routing
+//!
+//! A recipient acknowledges a delivered message by sending a
+//! [`ReadReceiptRequest`] naming the original sender and the message ID.
+//! The server checks the reader is authenticated, the timestamp is within
+//! the allowed skew and the original sender is online, then forwards a
+//! [`profile_shared::Message::Read`] to the sender.
+//! Receipts are best-effort: nothing is queued for offline senders.
+
+use crate::lobby::Lobby;
+use crate::message::pipeline::{
+    JsonRequest, MessageContext, StageFuture, Timestamped, ValidationPipeline, ValidationStage,
+};
+use crate::message::ValidationError;
+use crate::protocol::ReadReceiptRequest;
+
+/// Value of the `type` field identifying a read receipt request
+pub const READ_RECEIPT_TYPE: &str = "read";
+
+/// Check whether a client message type is a read receipt request
+///
+/// Takes the already parsed `type` field so the caller can
+/// dispatch before committing to a full parse.
+pub fn is_read_receipt(message_type: &str) -> bool {
+    message_type == READ_RECEIPT_TYPE
+}
+
+impl JsonRequest for ReadReceiptRequest {}
+
+impl Timestamped for ReadReceiptRequest {
+    fn timestamp(request: &Self) -> &str {
+        &request.timestamp
+    }
+}
+
+/// Validate a read receipt and forward it to the original sender
+///
+/// Runs the [`ValidationPipeline::timestamped`] stages, then
+/// [`ForwardReceipt`].
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `reader_public_key` - The public key of the authenticated reader
+/// * `receipt_json` - Raw JSON receipt from the client
+///
+/// # Returns
+/// Ok(()) if the receipt was forwarded, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, receipt_json), fields(reader = %reader_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_read_receipt(
+    lobby: &Lobby,
+    reader_public_key: &str,
+    receipt_json: &str,
+) -> Result<(), ValidationError> {
+    ValidationPipeline::<ReadReceiptRequest>::timestamped()
+        .with_stage(ForwardReceipt)
+        .validate(lobby, reader_public_key, receipt_json)
+        .await
+        .map(|_| ())
+}
+
+/// Forward the receipt to the original sender, if they are online
+#[derive(Debug, Clone, Copy)]
+pub struct ForwardReceipt;
+
+impl ValidationStage<ReadReceiptRequest> for ForwardReceipt {
+    fn name(&self) -> &'static str {
+        "receipt"
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, ReadReceiptRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.take_request()?;
+            let reader_public_key = ctx.sender_public_key;
+
+            if request.recipient_public_key == reader_public_key {
+                return Err(ValidationError::CannotMessageSelf);
+            }
+
+            let sender_conn =
+                match crate::lobby::get_user(ctx.lobby, &request.recipient_public_key).await {
+                    Ok(Some(conn)) => conn,
+                    _ => {
+                        return Err(ValidationError::RecipientOffline {
+                            recipient_key: request.recipient_public_key,
+                        });
+                    }
+                };
+
+            let _ = sender_conn.sender.send(profile_shared::Message::new_read(
+                request.message_id,
+                reader_public_key.to_string(),
+                request.timestamp,
+            ));
+
+            tracing::debug!(
+                to = %request.recipient_public_key.chars().take(16).collect::<String>(),
+                "Read receipt forwarded"
+            );
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::message::message_type;
+    use profile_shared::Message as SharedMessage;
+
+    const READER_KEY: &str = "aaaa1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+    const SENDER_KEY: &str = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+    const MESSAGE_ID: &str = "6f1c3a52-8d4e-4b7a-9c2d-1e5f3a7b9c0d";
+
+    fn receipt_json(recipient: &str, message_id: &str) -> String {
+        receipt_json_at(recipient, message_id, &chrono::Utc::now().to_rfc3339())
+    }
+
+    fn receipt_json_at(recipient: &str, message_id: &str, timestamp: &str) -> String {
+        serde_json::json!({
+            "type": "read",
+            "recipientPublicKey": recipient,
+            "messageId": message_id,
+            "timestamp": timestamp
+        })
+        .to_string()
+    }
+
+    async fn add_connection(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        // Deliver the join broadcast so tests can drain it synchronously
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    #[test]
+    fn test_is_read_receipt() {
+        assert!(is_read_receipt(
+            &message_type(&receipt_json(SENDER_KEY, MESSAGE_ID)).unwrap()
+        ));
+        assert!(!is_read_receipt("message"));
+    }
+
+    #[tokio::test]
+    async fn test_read_receipt_forwarded_to_sender() {
+        let lobby = Lobby::new();
+        let _reader_rx = add_connection(&lobby, READER_KEY, 1).await;
+        let mut sender_rx = add_connection(&lobby, SENDER_KEY, 2).await;
+        // Drain the join notification for the reader
+        while sender_rx.try_recv().is_ok() {}
+
+        let result =
+            handle_read_receipt(&lobby, READER_KEY, &receipt_json(SENDER_KEY, MESSAGE_ID)).await;
+        assert!(result.is_ok());
+
+        match sender_rx.try_recv() {
+            Ok(SharedMessage::Read {
+                message_id,
+                reader_public_key,
+                ..
+            }) => {
+                assert_eq!(message_id.to_string(), MESSAGE_ID);
+                assert_eq!(reader_public_key, READER_KEY);
+            }
+            other => panic!("Expected Read message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_receipt_sender_offline() {
+        let lobby = Lobby::new();
+        let _reader_rx = add_connection(&lobby, READER_KEY, 1).await;
+
+        let result =
+            handle_read_receipt(&lobby, READER_KEY, &receipt_json(SENDER_KEY, MESSAGE_ID)).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::RecipientOffline { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_receipt_rejects_unauthenticated_reader() {
+        let lobby = Lobby::new();
+
+        let result =
+            handle_read_receipt(&lobby, READER_KEY, &receipt_json(SENDER_KEY, MESSAGE_ID)).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::NotAuthenticated { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_receipt_rejects_self_and_invalid_id() {
+        let lobby = Lobby::new();
+        let _reader_rx = add_connection(&lobby, READER_KEY, 1).await;
+
+        let result =
+            handle_read_receipt(&lobby, READER_KEY, &receipt_json(READER_KEY, MESSAGE_ID)).await;
+        assert!(matches!(result, Err(ValidationError::CannotMessageSelf)));
+
+        // Message IDs are the UUIDs assigned when the message was composed
+        for bad_id in ["", "msg-2025-12-27T10:00:00Z"] {
+            let result =
+                handle_read_receipt(&lobby, READER_KEY, &receipt_json(SENDER_KEY, bad_id)).await;
+            assert!(matches!(result, Err(ValidationError::MalformedJson { .. })));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_receipt_rejects_bad_timestamp() {
+        let lobby = Lobby::new();
+        let _reader_rx = add_connection(&lobby, READER_KEY, 1).await;
+        let _sender_rx = add_connection(&lobby, SENDER_KEY, 2).await;
+
+        let stale = receipt_json_at(SENDER_KEY, MESSAGE_ID, "2020-01-01T00:00:00Z");
+        assert!(matches!(
+            handle_read_receipt(&lobby, READER_KEY, &stale).await,
+            Err(ValidationError::StaleTimestamp { .. })
+        ));
+        let garbled = receipt_json_at(SENDER_KEY, MESSAGE_ID, "yesterday");
+        assert!(matches!(
+            handle_read_receipt(&lobby, READER_KEY, &garbled).await,
+            Err(ValidationError::MalformedJson { .. })
+        ));
+    }
+}
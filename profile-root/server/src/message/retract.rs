@@ -0,0 +1,210 @@
+//! Message retraction routing
+//!
+//! A sender withdraws a direct message they sent earlier with a signed
+//! [`RetractMessageRequest`] naming the message id. The server checks the
+//! signature over the id and timestamp, then forwards a
+//! [`profile_shared::Message::Retract`] to the recipient the same way as an
+//! edit (see [`crate::message::edit`]).
+//!
+//! Recipients replace the message with a tombstone, and only for a message
+//! from the same key. Like edits, retractions are not queued for offline
+//! recipients, so a message already delivered while they were away stays.
+
+use crate::audit::AuditEvent;
+use crate::lobby::Lobby;
+use crate::message::pipeline::{
+    JsonRequest, MessageContext, StageFuture, Timestamped, ValidationPipeline, ValidationStage,
+};
+use crate::message::{validate_signature_queued, ValidationError};
+use crate::protocol::RetractMessageRequest;
+use profile_shared::canonical;
+
+/// Value of the `type` field identifying a retraction request
+pub const RETRACT_MESSAGE_TYPE: &str = "retract_message";
+
+/// Check whether a client message type is a retraction request
+pub fn is_retract_message_request(message_type: &str) -> bool {
+    message_type == RETRACT_MESSAGE_TYPE
+}
+
+impl JsonRequest for RetractMessageRequest {}
+
+impl Timestamped for RetractMessageRequest {
+    fn timestamp(request: &Self) -> &str {
+        &request.timestamp
+    }
+}
+
+/// Validate a retraction and forward it to the recipient
+///
+/// Runs the [`ValidationPipeline::timestamped`] stages, then
+/// [`ForwardRetract`].
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key of the authenticated sender
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the retraction was forwarded, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_retract_message_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    ValidationPipeline::<RetractMessageRequest>::timestamped()
+        .with_stage(ForwardRetract)
+        .validate(lobby, sender_public_key, request_json)
+        .await
+        .map(|_| ())
+}
+
+/// Check the signature, then forward the retraction
+#[derive(Debug, Clone, Copy)]
+pub struct ForwardRetract;
+
+impl ValidationStage<RetractMessageRequest> for ForwardRetract {
+    fn name(&self) -> &'static str {
+        "retract"
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, RetractMessageRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.take_request()?;
+            let (lobby, sender_public_key) = (ctx.lobby, ctx.sender_public_key);
+
+            if request.recipient_public_key == sender_public_key {
+                return Err(ValidationError::CannotMessageSelf);
+            }
+
+            validate_signature_queued(
+                lobby,
+                sender_public_key,
+                canonical::message_retract(request.message_id, &request.timestamp),
+                &request.signature,
+            )
+            .await?;
+
+            let retract = profile_shared::Message::new_retract(
+                request.message_id,
+                sender_public_key.to_string(),
+                request.signature,
+                request.timestamp,
+            );
+            if !crate::message::deliver(lobby, &request.recipient_public_key, retract).await {
+                lobby.audit.record(AuditEvent::RoutingFailed {
+                    sender_public_key: sender_public_key.to_string(),
+                    recipient_public_key: request.recipient_public_key.clone(),
+                    reason: "Recipient went offline".to_string(),
+                });
+                return Err(ValidationError::RecipientOffline {
+                    recipient_key: request.recipient_public_key,
+                });
+            }
+
+            tracing::debug!(
+                to = %request.recipient_public_key.chars().take(16).collect::<String>(),
+                message_id = %request.message_id,
+                "Message retraction forwarded"
+            );
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::message::message_type;
+    use profile_shared::{derive_public_key, generate_private_key, sign_message, Message};
+    use uuid::Uuid;
+
+    const RECIPIENT_KEY: &str = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+
+    async fn add_connection(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    /// A sender key and a retraction request signed with it
+    fn signed_retract(message_id: Uuid) -> (String, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            &private_key,
+            &canonical::message_retract(message_id, &timestamp),
+        )
+        .unwrap();
+        let request = serde_json::json!({
+            "type": "retract_message",
+            "recipientPublicKey": RECIPIENT_KEY,
+            "messageId": message_id,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp,
+        });
+        (public_key, request.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_retract_forwarded_with_sender_from_connection() {
+        let lobby = Lobby::new();
+        let message_id = Uuid::new_v4();
+        let (sender_key, request) = signed_retract(message_id);
+        assert!(is_retract_message_request(&message_type(&request).unwrap()));
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let mut recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+
+        handle_retract_message_request(&lobby, &sender_key, &request)
+            .await
+            .unwrap();
+
+        loop {
+            match recipient_rx.try_recv().unwrap() {
+                Message::Retract {
+                    message_id: id,
+                    sender_public_key,
+                    ..
+                } => {
+                    assert_eq!(id, message_id);
+                    assert_eq!(sender_public_key, sender_key);
+                    break;
+                }
+                Message::LobbyUpdate { .. } => {}
+                other => panic!("Expected Retract, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retract_signed_for_another_message_rejected() {
+        let lobby = Lobby::new();
+        let (sender_key, request) = signed_retract(Uuid::new_v4());
+        let _sender_rx = add_connection(&lobby, &sender_key, 1).await;
+        let _recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+
+        let mut moved: serde_json::Value = serde_json::from_str(&request).unwrap();
+        moved["messageId"] = Uuid::new_v4().to_string().into();
+        let result = handle_retract_message_request(&lobby, &sender_key, &moved.to_string()).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+    }
+}
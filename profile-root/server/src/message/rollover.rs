@@ -0,0 +1,270 @@
+//! Identity key rotation
+//!
+//! A user replacing their identity key sends a [`KeyRolloverRequest`] on
+//! the connection signed in with the old key, carrying a
+//! [`KeyRollover`] signed by both keys. Once both signatures check out:
+//! - the old key is retired (see [`crate::lobby::retired`]): it can't sign
+//!   in again or vouch for another key
+//! - its nickname and claimed name move to the new key, so the new key
+//!   joins the lobby under the same names
+//! - the rollover is broadcast to every online user, the sender included,
+//!   as a [`profile_shared::Message::KeyRollover`] for clients to move
+//!   their contacts across
+//!
+//! The client then signs in again with the new key; the old connection
+//! stays open until it closes. Users offline at the time miss the
+//! broadcast and see the new key as a stranger.
+
+use crate::lobby::Lobby;
+use crate::message::{validate_signature_queued, validate_timestamp, ValidationError};
+use crate::protocol::KeyRolloverRequest;
+use profile_shared::crypto::rollover::KeyRollover;
+
+/// Value of the `type` field identifying a key rollover request
+pub const KEY_ROLLOVER_TYPE: &str = "key_rollover";
+
+/// Check whether a client message type is a key rollover request
+pub fn is_key_rollover_request(message_type: &str) -> bool {
+    message_type == KEY_ROLLOVER_TYPE
+}
+
+/// Handle a key rollover from an authenticated user
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key the connection signed in with
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the old key was retired and the rollover broadcast,
+/// Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_key_rollover_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    const MAX_MSG_SIZE: usize = profile_shared::config::message::MAX_MESSAGE_SIZE;
+    if request_json.len() > MAX_MSG_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_MSG_SIZE,
+        });
+    }
+
+    if !matches!(
+        crate::lobby::get_user(lobby, sender_public_key).await,
+        Ok(Some(_))
+    ) {
+        return Err(ValidationError::NotAuthenticated {
+            details: format!("User {} is not authenticated", sender_public_key),
+        });
+    }
+
+    let request: KeyRolloverRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+    let rollover = request.rollover;
+    check_rollover(lobby, sender_public_key, &rollover).await?;
+
+    if !lobby
+        .retired
+        .retire(&rollover.old_public_key, &rollover.new_public_key)
+        .await
+    {
+        return Err(ValidationError::KeyRolloverRejected {
+            details: "One of the keys was already rolled over".to_string(),
+        });
+    }
+    lobby
+        .nicknames
+        .transfer(&rollover.old_public_key, &rollover.new_public_key)
+        .await;
+    if let Err(e) = lobby
+        .names
+        .transfer(&rollover.old_public_key, &rollover.new_public_key)
+        .await
+    {
+        // The old key is retired either way; the name can be claimed again
+        tracing::warn!(error = %e, "Failed to move claimed name to the new key");
+    }
+
+    tracing::info!(
+        new_key = %rollover.new_public_key.chars().take(16).collect::<String>(),
+        "Key rolled over"
+    );
+    lobby
+        .broadcast(profile_shared::Message::new_key_rollover(rollover), None)
+        .map_err(|e| ValidationError::MalformedJson {
+            details: format!("Lobby unavailable: {}", e),
+        })
+}
+
+/// Check the rollover retires the sender's key for a usable new one, signed
+/// by both
+async fn check_rollover(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    rollover: &KeyRollover,
+) -> Result<(), ValidationError> {
+    let rejected = |details: &str| ValidationError::KeyRolloverRejected {
+        details: details.to_string(),
+    };
+    if rollover.old_public_key != sender_public_key {
+        return Err(rejected(
+            "Rollover must retire the key this connection signed in with",
+        ));
+    }
+    if rollover.new_public_key == rollover.old_public_key {
+        return Err(rejected("Rollover must name a different new key"));
+    }
+
+    validate_timestamp(sender_public_key, &rollover.timestamp)?;
+    let signed = rollover.signed_bytes();
+    validate_signature_queued(
+        lobby,
+        &rollover.old_public_key,
+        signed.clone(),
+        &rollover.old_signature,
+    )
+    .await?;
+    validate_signature_queued(
+        lobby,
+        &rollover.new_public_key,
+        signed,
+        &rollover.new_signature,
+    )
+    .await?;
+
+    if lobby
+        .moderation
+        .active_ban(&rollover.new_public_key)
+        .await
+        .is_some()
+    {
+        return Err(rejected("The new key is banned"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::message::message_type;
+    use profile_shared::{derive_public_key, generate_private_key, Message, PrivateKey};
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    fn new_user() -> (PrivateKey, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        (private_key, public_key)
+    }
+
+    fn request(old: &PrivateKey, new: &PrivateKey) -> String {
+        let rollover = KeyRollover::sign(old, new, &chrono::Utc::now().to_rfc3339()).unwrap();
+        serde_json::json!({ "type": KEY_ROLLOVER_TYPE, "rollover": rollover }).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_rollover_moves_nickname_and_is_broadcast() {
+        let lobby = Lobby::new();
+        let (alice_old, alice) = new_user();
+        let (alice_new, alice_next) = new_user();
+        let (_, bob) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let mut bob_rx = connect(&lobby, &bob, 2).await;
+        lobby.nicknames.set(&alice, "alice").await.unwrap();
+
+        let request_json = request(&alice_old, &alice_new);
+        assert!(is_key_rollover_request(
+            &message_type(&request_json).unwrap()
+        ));
+        handle_key_rollover_request(&lobby, &alice, &request_json)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+
+        assert_eq!(
+            lobby.nicknames.get(&alice_next).await.as_deref(),
+            Some("alice")
+        );
+        assert_eq!(
+            lobby.retired.replacement(&alice).await.as_deref(),
+            Some(alice_next.as_str())
+        );
+        match bob_rx.try_recv() {
+            Ok(Message::KeyRollover { rollover }) => {
+                assert_eq!(rollover.old_public_key, alice);
+                assert_eq!(rollover.new_public_key, alice_next);
+                assert!(rollover.verify().is_ok());
+            }
+            other => panic!("Expected KeyRollover, got {:?}", other),
+        }
+
+        // The retired key can't vouch for another
+        let (other_new, _) = new_user();
+        assert!(matches!(
+            handle_key_rollover_request(&lobby, &alice, &request(&alice_old, &other_new)).await,
+            Err(ValidationError::KeyRolloverRejected { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rollover_rejections() {
+        let lobby = Lobby::new();
+        let (alice_old, alice) = new_user();
+        let (bob_key, _) = new_user();
+        let (new_key, new_public) = new_user();
+        assert!(matches!(
+            handle_key_rollover_request(&lobby, &alice, &request(&alice_old, &new_key)).await,
+            Err(ValidationError::NotAuthenticated { .. })
+        ));
+
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        // Someone else's key can't be retired from this connection
+        assert!(matches!(
+            handle_key_rollover_request(&lobby, &alice, &request(&bob_key, &new_key)).await,
+            Err(ValidationError::KeyRolloverRejected { .. })
+        ));
+
+        // Both keys must sign
+        let mut rollover =
+            KeyRollover::sign(&alice_old, &new_key, &chrono::Utc::now().to_rfc3339()).unwrap();
+        rollover.new_signature = KeyRollover::sign(&alice_old, &bob_key, &rollover.timestamp)
+            .unwrap()
+            .new_signature;
+        let forged = serde_json::json!({ "type": KEY_ROLLOVER_TYPE, "rollover": rollover });
+        assert!(matches!(
+            handle_key_rollover_request(&lobby, &alice, &forged.to_string()).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+
+        lobby
+            .moderation
+            .ban(&new_public, None, chrono::Utc::now())
+            .await
+            .unwrap();
+        assert!(matches!(
+            handle_key_rollover_request(&lobby, &alice, &request(&alice_old, &new_key)).await,
+            Err(ValidationError::KeyRolloverRejected { .. })
+        ));
+        assert!(lobby.retired.is_empty().await);
+    }
+}
@@ -0,0 +1,319 @@
+//! Room request handling
+//!
+//! Dispatches `room_create`, `room_join`, `room_leave` and `room_message`
+//! requests from authenticated clients to the [`crate::rooms`] subsystem.
+//! Room messages go through the same timestamp and signature checks as
+//! direct messages before being fanned out to members.
+
+use crate::lobby::Lobby;
+use crate::message::pipeline::{
+    JsonRequest, MessageContext, StageFuture, Timestamped, ValidationPipeline, ValidationStage,
+};
+use crate::message::{message_type, validate_signature_queued, ValidationError};
+use crate::protocol::{RoomMembershipRequest, RoomMessageRequest};
+use crate::rooms::Rooms;
+use profile_shared::canonical;
+use profile_shared::RoomError;
+
+/// Request type for creating a room
+pub const ROOM_CREATE_TYPE: &str = "room_create";
+/// Request type for joining a room
+pub const ROOM_JOIN_TYPE: &str = "room_join";
+/// Request type for leaving a room
+pub const ROOM_LEAVE_TYPE: &str = "room_leave";
+/// Request type for posting a message to a room
+pub const ROOM_MESSAGE_TYPE: &str = "room_message";
+
+/// Check whether a client message type is a room request
+pub fn is_room_request(message_type: &str) -> bool {
+    matches!(
+        message_type,
+        ROOM_CREATE_TYPE | ROOM_JOIN_TYPE | ROOM_LEAVE_TYPE | ROOM_MESSAGE_TYPE
+    )
+}
+
+impl JsonRequest for RoomMembershipRequest {}
+
+impl JsonRequest for RoomMessageRequest {}
+
+impl Timestamped for RoomMessageRequest {
+    fn timestamp(request: &Self) -> &str {
+        &request.timestamp
+    }
+}
+
+/// Handle a room request from an authenticated user
+///
+/// Room messages run the [`ValidationPipeline::timestamped`] stages, then
+/// [`RouteRoomMessage`]; membership requests run the
+/// [`ValidationPipeline::authenticated`] stages, then [`ChangeMembership`].
+///
+/// # Arguments
+/// * `rooms` - The room registry
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key of the requesting user
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the request was applied, Err(ValidationError) otherwise
+#[tracing::instrument(skip(rooms, lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_room_request(
+    rooms: &Rooms,
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    let change = match message_type(request_json).as_deref() {
+        Some(ROOM_MESSAGE_TYPE) => {
+            return ValidationPipeline::<RoomMessageRequest>::timestamped()
+                .with_stage(RouteRoomMessage {
+                    rooms: rooms.clone(),
+                })
+                .validate(lobby, sender_public_key, request_json)
+                .await
+                .map(|_| ());
+        }
+        Some(ROOM_CREATE_TYPE) => MembershipChange::Create,
+        Some(ROOM_JOIN_TYPE) => MembershipChange::Join,
+        Some(ROOM_LEAVE_TYPE) => MembershipChange::Leave,
+        _ => {
+            return Err(ValidationError::MalformedJson {
+                details: "Unknown room request type".to_string(),
+            })
+        }
+    };
+    ValidationPipeline::<RoomMembershipRequest>::authenticated()
+        .with_stage(ChangeMembership {
+            rooms: rooms.clone(),
+            change,
+        })
+        .validate(lobby, sender_public_key, request_json)
+        .await
+        .map(|_| ())
+}
+
+fn room_error(error: RoomError) -> ValidationError {
+    ValidationError::RoomRejected { error }
+}
+
+/// Check a room message's signature, then fan it out to the room
+#[derive(Debug, Clone)]
+pub struct RouteRoomMessage {
+    pub rooms: Rooms,
+}
+
+impl ValidationStage<RoomMessageRequest> for RouteRoomMessage {
+    fn name(&self) -> &'static str {
+        "room_message"
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, RoomMessageRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let request = ctx.request()?;
+            validate_signature_queued(
+                ctx.lobby,
+                ctx.sender_public_key,
+                canonical::room_message(
+                    request.canonical,
+                    &request.room,
+                    &request.message,
+                    &request.timestamp,
+                ),
+                &request.signature,
+            )
+            .await?;
+            crate::rooms::route_room_message(&self.rooms, ctx.lobby, ctx.sender_public_key, request)
+                .await
+                .map(|_| ())
+                .map_err(room_error)
+        })
+    }
+}
+
+/// What a membership request does to its room
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MembershipChange {
+    Create,
+    Join,
+    Leave,
+}
+
+/// Create, join or leave the requested room
+#[derive(Debug, Clone)]
+pub struct ChangeMembership {
+    pub rooms: Rooms,
+    pub change: MembershipChange,
+}
+
+impl ValidationStage<RoomMembershipRequest> for ChangeMembership {
+    fn name(&self) -> &'static str {
+        match self.change {
+            MembershipChange::Create => ROOM_CREATE_TYPE,
+            MembershipChange::Join => ROOM_JOIN_TYPE,
+            MembershipChange::Leave => ROOM_LEAVE_TYPE,
+        }
+    }
+
+    fn check<'a, 'm>(
+        &'a self,
+        ctx: &'a mut MessageContext<'m, RoomMembershipRequest>,
+    ) -> StageFuture<'a> {
+        Box::pin(async move {
+            let room = &ctx.request()?.room;
+            let (rooms, lobby, sender) = (&self.rooms, ctx.lobby, ctx.sender_public_key);
+            match self.change {
+                MembershipChange::Create => {
+                    crate::rooms::create_room(rooms, lobby, room, sender).await
+                }
+                MembershipChange::Join => crate::rooms::join_room(rooms, lobby, room, sender).await,
+                MembershipChange::Leave => {
+                    crate::rooms::leave_room(rooms, lobby, room, sender).await
+                }
+            }
+            .map_err(room_error)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use profile_shared::canonical::CanonicalVersion;
+    use profile_shared::{
+        derive_public_key, generate_private_key, sign_message, Message, RoomError,
+    };
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        // Deliver the join broadcast so tests can drain it synchronously
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    #[test]
+    fn test_is_room_request() {
+        assert!(is_room_request("room_create"));
+        assert!(is_room_request("room_message"));
+        assert!(!is_room_request("message"));
+    }
+
+    #[tokio::test]
+    async fn test_signed_room_message_round_trip() {
+        let lobby = Lobby::new();
+        let rooms = Rooms::new();
+
+        let private_key = generate_private_key().unwrap();
+        let alice = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let bob = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let mut bob_rx = connect(&lobby, bob, 2).await;
+
+        handle_room_request(
+            &rooms,
+            &lobby,
+            &alice,
+            r#"{"type":"room_create","room":"general"}"#,
+        )
+        .await
+        .unwrap();
+        handle_room_request(
+            &rooms,
+            &lobby,
+            bob,
+            r#"{"type":"room_join","room":"general"}"#,
+        )
+        .await
+        .unwrap();
+        while bob_rx.try_recv().is_ok() {}
+
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            &private_key,
+            &canonical::room_message(CanonicalVersion::CURRENT, "general", "hi", &timestamp),
+        )
+        .unwrap();
+        let request = serde_json::json!({
+            "type": "room_message",
+            "room": "general",
+            "message": "hi",
+            "senderPublicKey": alice,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp,
+            "canonical": CanonicalVersion::CURRENT
+        })
+        .to_string();
+
+        handle_room_request(&rooms, &lobby, &alice, &request)
+            .await
+            .unwrap();
+
+        // Relayed with the version it was signed over
+        assert!(matches!(
+            bob_rx.try_recv(),
+            Ok(Message::RoomMessage { sender_public_key, canonical, .. })
+                if sender_public_key == alice && canonical == CanonicalVersion::CURRENT
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_room_message_bad_signature_rejected() {
+        let lobby = Lobby::new();
+        let rooms = Rooms::new();
+        let private_key = generate_private_key().unwrap();
+        let alice = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        rooms.create_room("general", &alice).await.unwrap();
+
+        let request = serde_json::json!({
+            "type": "room_message",
+            "room": "general",
+            "message": "hi",
+            "senderPublicKey": alice,
+            "signature": hex::encode([0u8; 64]),
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        })
+        .to_string();
+
+        let result = handle_room_request(&rooms, &lobby, &alice, &request).await;
+        assert!(matches!(
+            result,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_join_unknown_room_rejected() {
+        let lobby = Lobby::new();
+        let rooms = Rooms::new();
+        let alice = "aaaa1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+        let _alice_rx = connect(&lobby, alice, 1).await;
+
+        let result = handle_room_request(
+            &rooms,
+            &lobby,
+            alice,
+            r#"{"type":"room_join","room":"nope"}"#,
+        )
+        .await;
+        assert_eq!(
+            result,
+            Err(ValidationError::RoomRejected {
+                error: RoomError::RoomNotFound
+            })
+        );
+    }
+}
@@ -0,0 +1,207 @@
+//! Sealed (ratchet-encrypted) direct message routing
+//!
+//! Clients with forward secrecy on send direct messages as a
+//! [`SealedRequest`]: the signed text message, encrypted in a double-ratchet
+//! session with the recipient (see [`profile_shared::crypto::ratchet`]).
+//! The server can't check the signature or the text inside, so it only
+//! checks the sender is authenticated and the envelope is well formed, then
+//! forwards a [`profile_shared::Message::Sealed`] to the recipient, through
+//! the cluster and the recipient's sender filters like any direct message.
+//! The recipient checks everything the server would have on decrypting it.
+//!
+//! Sealed messages are larger than plain ones once padded and hex-encoded,
+//! so they have their own size limit, `config::message::MAX_SEALED_SIZE`.
+
+use crate::audit::AuditEvent;
+use crate::lobby::Lobby;
+use crate::message::ValidationError;
+use crate::protocol::SealedRequest;
+
+/// Value of the `type` field identifying a sealed message
+pub const SEALED_TYPE: &str = "sealed";
+
+/// Check whether a client message type is a sealed message
+///
+/// Takes the already parsed `type` field so the caller can
+/// dispatch before committing to a full parse.
+pub fn is_sealed_request(message_type: &str) -> bool {
+    message_type == SEALED_TYPE
+}
+
+/// Validate a sealed message and forward it to the recipient
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key of the authenticated sender
+/// * `request_json` - Raw JSON message from the client
+///
+/// # Returns
+/// Ok(()) if the message was forwarded, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_sealed_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    const MAX_SEALED_SIZE: usize = profile_shared::config::message::MAX_SEALED_SIZE;
+    if request_json.len() > MAX_SEALED_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_SEALED_SIZE,
+        });
+    }
+
+    if !matches!(
+        crate::lobby::get_user(lobby, sender_public_key).await,
+        Ok(Some(_))
+    ) {
+        return Err(ValidationError::NotAuthenticated {
+            details: format!("User {} is not authenticated", sender_public_key),
+        });
+    }
+
+    let request: SealedRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+
+    if request.recipient_public_key == sender_public_key {
+        return Err(ValidationError::CannotMessageSelf);
+    }
+
+    let message = profile_shared::Message::new_sealed(
+        request.message_id,
+        sender_public_key.to_string(),
+        request.envelope,
+    );
+    if !crate::message::deliver(lobby, &request.recipient_public_key, message).await {
+        lobby.audit.record(AuditEvent::RoutingFailed {
+            sender_public_key: sender_public_key.to_string(),
+            recipient_public_key: request.recipient_public_key.clone(),
+            reason: "Recipient went offline".to_string(),
+        });
+        return Err(ValidationError::RecipientOffline {
+            recipient_key: request.recipient_public_key,
+        });
+    }
+
+    tracing::debug!(
+        to = %request.recipient_public_key.chars().take(16).collect::<String>(),
+        "Sealed message forwarded"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::message::message_type;
+    use profile_shared::Message as SharedMessage;
+
+    const SENDER_KEY: &str = "aaaa1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+    const RECIPIENT_KEY: &str = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+
+    fn sealed_json(recipient: &str, ciphertext: &str) -> String {
+        serde_json::json!({
+            "type": "sealed",
+            "recipientPublicKey": recipient,
+            "envelope": {
+                "session": "cc".repeat(32),
+                "ratchetKey": "dd".repeat(32),
+                "previousCount": 0,
+                "index": 3,
+                "nonce": "ee".repeat(24),
+                "ciphertext": ciphertext
+            }
+        })
+        .to_string()
+    }
+
+    async fn add_connection(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    #[tokio::test]
+    async fn test_sealed_message_forwarded_unread() {
+        assert!(is_sealed_request(
+            &message_type(&sealed_json(RECIPIENT_KEY, "00")).unwrap()
+        ));
+        assert!(!is_sealed_request("message"));
+
+        let lobby = Lobby::new();
+        let _sender_rx = add_connection(&lobby, SENDER_KEY, 1).await;
+        let mut recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+        while recipient_rx.try_recv().is_ok() {}
+
+        handle_sealed_request(
+            &lobby,
+            SENDER_KEY,
+            &sealed_json(&RECIPIENT_KEY.to_uppercase(), "abcdef"),
+        )
+        .await
+        .unwrap();
+        match recipient_rx.try_recv() {
+            Ok(SharedMessage::Sealed {
+                sender_public_key,
+                envelope,
+                ..
+            }) => {
+                assert_eq!(sender_public_key, SENDER_KEY);
+                assert_eq!(envelope.index, 3);
+                assert_eq!(envelope.ciphertext, "abcdef");
+            }
+            other => panic!("Expected Sealed message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sealed_message_rejections() {
+        let lobby = Lobby::new();
+        assert!(matches!(
+            handle_sealed_request(&lobby, SENDER_KEY, &sealed_json(RECIPIENT_KEY, "00")).await,
+            Err(ValidationError::NotAuthenticated { .. })
+        ));
+
+        let _sender_rx = add_connection(&lobby, SENDER_KEY, 1).await;
+        assert!(matches!(
+            handle_sealed_request(&lobby, SENDER_KEY, &sealed_json(RECIPIENT_KEY, "00")).await,
+            Err(ValidationError::RecipientOffline { .. })
+        ));
+        assert!(matches!(
+            handle_sealed_request(&lobby, SENDER_KEY, &sealed_json(SENDER_KEY, "00")).await,
+            Err(ValidationError::CannotMessageSelf)
+        ));
+        assert!(matches!(
+            handle_sealed_request(&lobby, SENDER_KEY, r#"{"type":"sealed"}"#).await,
+            Err(ValidationError::MalformedJson { .. })
+        ));
+
+        // Larger than a plain message may be, within the sealed limit
+        let padded = "00".repeat(4096);
+        let _recipient_rx = add_connection(&lobby, RECIPIENT_KEY, 2).await;
+        assert!(
+            handle_sealed_request(&lobby, SENDER_KEY, &sealed_json(RECIPIENT_KEY, &padded))
+                .await
+                .is_ok()
+        );
+        let oversized = "00".repeat(profile_shared::config::message::MAX_SEALED_SIZE);
+        assert!(matches!(
+            handle_sealed_request(&lobby, SENDER_KEY, &sealed_json(RECIPIENT_KEY, &oversized))
+                .await,
+            Err(ValidationError::MessageTooLarge { .. })
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+//! Per-sender message sequence numbers
+//!
+//! Clients may number the direct messages they send on a connection with a
+//! strictly increasing `sequence`. The server remembers the last sequence it
+//! accepted from each sender and rejects any message that doesn't move it
+//! forward, so a captured frame can't be replayed on the same connection.
+//! Numbering starts over on each connection: a sender whose connection id
+//! changed (a reconnect) may start again from any value.
+//!
+//! Numbering is opt-in per connection: once a sender has numbered a message,
+//! every later one on that connection must carry a sequence too. The
+//! signature covers the number, so a frame can't be replayed with a higher
+//! one or with the field stripped.
+
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+/// Last accepted sequence number per sender
+#[derive(Debug, Default)]
+pub struct SenderSequences {
+    /// Sender public key -> (connection id, last accepted sequence)
+    last_seen: Mutex<HashMap<String, (u64, u64)>>,
+}
+
+impl SenderSequences {
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check that `sequence` follows the last one accepted from a sender,
+    /// without accepting it
+    ///
+    /// # Returns
+    /// Err with the last accepted sequence if `sequence` is not greater than
+    /// it on the same connection
+    pub async fn check(
+        &self,
+        sender_public_key: &str,
+        connection_id: u64,
+        sequence: u64,
+    ) -> Result<(), u64> {
+        match self
+            .last_on_connection(sender_public_key, connection_id)
+            .await
+        {
+            Some(last) if sequence <= last => Err(last),
+            _ => Ok(()),
+        }
+    }
+
+    /// Accept `sequence` from a sender if it follows the last one accepted
+    ///
+    /// # Arguments
+    /// * `sender_public_key` - The authenticated sender
+    /// * `connection_id` - The sender's current connection
+    /// * `sequence` - The sequence number from the message
+    ///
+    /// # Returns
+    /// Err with the last accepted sequence if `sequence` is not greater than
+    /// it on the same connection
+    pub async fn advance(
+        &self,
+        sender_public_key: &str,
+        connection_id: u64,
+        sequence: u64,
+    ) -> Result<(), u64> {
+        let mut last_seen = self.last_seen.lock().await;
+        match last_seen.get_mut(sender_public_key) {
+            Some((conn, last)) if *conn == connection_id => {
+                if sequence <= *last {
+                    return Err(*last);
+                }
+                *last = sequence;
+            }
+            // First message, or first since a reconnect
+            _ => {
+                last_seen.insert(sender_public_key.to_string(), (connection_id, sequence));
+            }
+        }
+        Ok(())
+    }
+
+    /// Last sequence accepted from a sender, if any
+    pub async fn last_seen(&self, sender_public_key: &str) -> Option<u64> {
+        self.last_seen
+            .lock()
+            .await
+            .get(sender_public_key)
+            .map(|(_, last)| *last)
+    }
+
+    /// Last sequence accepted from a sender on `connection_id`, if they
+    /// have numbered a message on it
+    pub async fn last_on_connection(
+        &self,
+        sender_public_key: &str,
+        connection_id: u64,
+    ) -> Option<u64> {
+        match self.last_seen.lock().await.get(sender_public_key) {
+            Some((conn, last)) if *conn == connection_id => Some(*last),
+            _ => None,
+        }
+    }
+
+    /// Forget a sender, when they leave the lobby
+    pub async fn forget(&self, sender_public_key: &str) {
+        self.last_seen.lock().await.remove(sender_public_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sequence_must_increase() {
+        let sequences = SenderSequences::new();
+
+        assert_eq!(sequences.advance("alice", 1, 1).await, Ok(()));
+        // Checking doesn't accept the sequence
+        assert_eq!(sequences.check("alice", 1, 5).await, Ok(()));
+        assert_eq!(sequences.check("alice", 1, 1).await, Err(1));
+        assert_eq!(sequences.last_seen("alice").await, Some(1));
+        assert_eq!(sequences.advance("alice", 1, 5).await, Ok(()));
+        assert_eq!(sequences.advance("alice", 1, 5).await, Err(5));
+        assert_eq!(sequences.advance("alice", 1, 3).await, Err(5));
+        // Other senders are tracked separately
+        assert_eq!(sequences.advance("bob", 2, 1).await, Ok(()));
+        assert_eq!(sequences.last_seen("alice").await, Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_or_leave_resets_sequence() {
+        let sequences = SenderSequences::new();
+        sequences.advance("alice", 1, 10).await.unwrap();
+
+        // New connection id: numbering starts over
+        assert_eq!(sequences.last_on_connection("alice", 2).await, None);
+        assert_eq!(sequences.advance("alice", 2, 1).await, Ok(()));
+        assert_eq!(sequences.last_on_connection("alice", 2).await, Some(1));
+        assert_eq!(sequences.advance("alice", 2, 1).await, Err(1));
+
+        sequences.forget("alice").await;
+        assert_eq!(sequences.last_seen("alice").await, None);
+        assert_eq!(sequences.advance("alice", 2, 1).await, Ok(()));
+    }
+}
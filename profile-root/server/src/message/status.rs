@@ -0,0 +1,265 @@
+//! Presence status request handling
+//!
+//! Authenticated clients mark themselves online, away, busy or invisible
+//! with a signed [`SetStatusRequest`]. The status is kept on the user's
+//! connection and announced to the rest of the lobby as a lobby update:
+//! away and busy users are re-sent as joined with their status, while an
+//! invisible user is announced as having left and drops out of lobby pages
+//! until they pick another status or disconnect.
+
+use crate::lobby::Lobby;
+use crate::message::{validate_signature_queued, validate_timestamp, ValidationError};
+use crate::protocol::SetStatusRequest;
+use profile_shared::canonical;
+
+/// Value of the `type` field identifying a status request
+pub const SET_STATUS_TYPE: &str = "set_status";
+
+/// Check whether a client message type is a status request
+pub fn is_set_status_request(message_type: &str) -> bool {
+    message_type == SET_STATUS_TYPE
+}
+
+/// Handle a status request from an authenticated user
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `sender_public_key` - The public key of the requesting user
+/// * `request_json` - Raw JSON request from the client
+///
+/// # Returns
+/// Ok(()) if the status was applied, Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_set_status_request(
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    const MAX_MSG_SIZE: usize = profile_shared::config::message::MAX_MESSAGE_SIZE;
+    if request_json.len() > MAX_MSG_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_MSG_SIZE,
+        });
+    }
+
+    if !matches!(
+        crate::lobby::get_user(lobby, sender_public_key).await,
+        Ok(Some(_))
+    ) {
+        return Err(ValidationError::NotAuthenticated {
+            details: format!("User {} is not authenticated", sender_public_key),
+        });
+    }
+
+    let request: SetStatusRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+    if !request.status.is_selectable() {
+        return Err(ValidationError::MalformedJson {
+            details: "Status must be online, away, busy or invisible".to_string(),
+        });
+    }
+
+    validate_timestamp(sender_public_key, &request.timestamp)?;
+    validate_signature_queued(
+        lobby,
+        sender_public_key,
+        canonical::set_status(request.status, &request.timestamp),
+        &request.signature,
+    )
+    .await?;
+
+    let changed = crate::lobby::set_status(lobby, sender_public_key, request.status)
+        .await
+        .map_err(|e| ValidationError::MalformedJson {
+            details: format!("Lobby unavailable: {}", e),
+        })?;
+    if changed {
+        tracing::debug!(status = request.status.as_str(), "Status changed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use profile_shared::protocol::Status;
+    use profile_shared::{
+        derive_public_key, generate_private_key, sign_message, Message, PrivateKey,
+    };
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    fn new_user() -> (PrivateKey, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        (private_key, public_key)
+    }
+
+    fn request(private_key: &PrivateKey, status: &str) -> String {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let parsed: Status = serde_json::from_value(serde_json::json!(status)).unwrap();
+        let signature = hex::encode(
+            sign_message(private_key, &canonical::set_status(parsed, &timestamp)).unwrap(),
+        );
+        serde_json::json!({
+            "type": "set_status",
+            "status": status,
+            "signature": signature,
+            "timestamp": timestamp,
+        })
+        .to_string()
+    }
+
+    async fn set(lobby: &Lobby, private_key: &PrivateKey, key: &str, status: &str) {
+        handle_set_status_request(lobby, key, &request(private_key, status))
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+    }
+
+    #[test]
+    fn test_is_set_status_request() {
+        assert!(is_set_status_request("set_status"));
+        assert!(!is_set_status_request("set_nickname"));
+    }
+
+    #[tokio::test]
+    async fn test_status_change_announced_to_others() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (_, bob) = new_user();
+        let mut alice_rx = connect(&lobby, &alice, 1).await;
+        let mut bob_rx = connect(&lobby, &bob, 2).await;
+        while alice_rx.try_recv().is_ok() {}
+
+        set(&lobby, &alice_key, &alice, "away").await;
+        match bob_rx.try_recv().unwrap() {
+            Message::LobbyUpdate { joined, left } => {
+                assert!(left.is_empty());
+                assert_eq!(joined.len(), 1);
+                assert_eq!(joined[0].public_key, alice);
+                assert_eq!(joined[0].status, Some(Status::Away));
+            }
+            other => panic!("Expected LobbyUpdate, got {:?}", other),
+        }
+        // The requester isn't told about their own change
+        assert!(alice_rx.try_recv().is_err());
+        assert_eq!(
+            lobby.statuses(&[alice.clone(), bob.clone()]).await,
+            [(alice.clone(), Status::Away)].into()
+        );
+
+        // Setting the same status again is a no-op
+        set(&lobby, &alice_key, &alice, "away").await;
+        assert!(bob_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invisible_user_looks_offline() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (_, bob) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let mut bob_rx = connect(&lobby, &bob, 2).await;
+
+        set(&lobby, &alice_key, &alice, "invisible").await;
+        assert!(matches!(
+            bob_rx.try_recv(),
+            Ok(Message::LobbyUpdate { left, .. }) if left == vec![alice.clone()]
+        ));
+        let page = lobby.get_page(None, 10, None).await.unwrap();
+        assert_eq!(page.users, vec![bob.clone()]);
+        assert_eq!(page.total, 1);
+
+        // Reconnecting keeps the status without announcing anything
+        let _alice_rx = connect(&lobby, &alice, 3).await;
+        assert!(lobby.is_invisible(&alice).await);
+        assert!(bob_rx.try_recv().is_err());
+
+        // Coming back is announced as a join
+        set(&lobby, &alice_key, &alice, "online").await;
+        match bob_rx.try_recv().unwrap() {
+            Message::LobbyUpdate { joined, .. } => {
+                assert_eq!(joined[0].public_key, alice);
+                assert_eq!(joined[0].status, None);
+            }
+            other => panic!("Expected LobbyUpdate, got {:?}", other),
+        }
+
+        // Nobody hears an invisible user leave
+        set(&lobby, &alice_key, &alice, "invisible").await;
+        while bob_rx.try_recv().is_ok() {}
+        crate::lobby::remove_user(&lobby, &alice).await.unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        assert!(bob_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_offline_and_forged_statuses_rejected() {
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let (_, bob) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+        let _bob_rx = connect(&lobby, &bob, 2).await;
+
+        assert!(matches!(
+            handle_set_status_request(&lobby, &alice, &request(&alice_key, "offline")).await,
+            Err(ValidationError::MalformedJson { .. })
+        ));
+        // Signed by alice, claimed by bob
+        assert!(matches!(
+            handle_set_status_request(&lobby, &bob, &request(&alice_key, "busy")).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+        let mut tampered: serde_json::Value =
+            serde_json::from_str(&request(&alice_key, "away")).unwrap();
+        tampered["status"] = "invisible".into();
+        assert!(matches!(
+            handle_set_status_request(&lobby, &alice, &tampered.to_string()).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+        assert!(lobby.statuses(&[alice, bob]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chat_signature_not_replayable_as_status() {
+        use profile_shared::canonical::CanonicalVersion;
+
+        let lobby = Lobby::new();
+        let (alice_key, alice) = new_user();
+        let _alice_rx = connect(&lobby, &alice, 1).await;
+
+        // A chat message reading `set_status:invisible`, signed the old way
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let chat = canonical::message(CanonicalVersion::V1, "set_status:invisible", &timestamp);
+        let replayed = serde_json::json!({
+            "type": "set_status",
+            "status": "invisible",
+            "signature": hex::encode(sign_message(&alice_key, &chat).unwrap()),
+            "timestamp": timestamp,
+        });
+        assert!(matches!(
+            handle_set_status_request(&lobby, &alice, &replayed.to_string()).await,
+            Err(ValidationError::SignatureInvalid { .. })
+        ));
+        assert!(!lobby.is_invisible(&alice).await);
+    }
+}
@@ -0,0 +1,162 @@
+//! "Viewing your conversation" hint routing
+//!
+//! Clients that opted in send a [`ViewingRequest`] when the user opens or
+//! leaves the conversation with a peer. The server checks the viewer is
+//! authenticated and forwards a [`profile_shared::Message::Viewing`] to the
+//! peer. Hints are ephemeral: nothing is stored, and a hint for an offline
+//! peer is dropped without an error, since reporting `recipient_offline`
+//! would make the client treat it like an undelivered chat message.
+
+use crate::lobby::Lobby;
+use crate::message::ValidationError;
+use crate::protocol::ViewingRequest;
+
+/// Value of the `type` field identifying a viewing hint
+pub const VIEWING_TYPE: &str = "viewing";
+
+/// Check whether a client message type is a viewing hint
+///
+/// Takes the already parsed `type` field so the caller can
+/// dispatch before committing to a full parse.
+pub fn is_viewing_request(message_type: &str) -> bool {
+    message_type == VIEWING_TYPE
+}
+
+/// Validate a viewing hint and forward it to the peer if they are online
+///
+/// # Arguments
+/// * `lobby` - The lobby containing authenticated users
+/// * `viewer_public_key` - The public key of the authenticated viewer
+/// * `request_json` - Raw JSON hint from the client
+///
+/// # Returns
+/// Ok(()) if the hint was forwarded or the peer is offline,
+/// Err(ValidationError) otherwise
+#[tracing::instrument(skip(lobby, request_json), fields(viewer = %viewer_public_key.chars().take(16).collect::<String>()))]
+pub async fn handle_viewing_request(
+    lobby: &Lobby,
+    viewer_public_key: &str,
+    request_json: &str,
+) -> Result<(), ValidationError> {
+    const MAX_MSG_SIZE: usize = profile_shared::config::message::MAX_MESSAGE_SIZE;
+    if request_json.len() > MAX_MSG_SIZE {
+        return Err(ValidationError::MessageTooLarge {
+            size: request_json.len(),
+            max: MAX_MSG_SIZE,
+        });
+    }
+
+    if !matches!(
+        crate::lobby::get_user(lobby, viewer_public_key).await,
+        Ok(Some(_))
+    ) {
+        return Err(ValidationError::NotAuthenticated {
+            details: format!("User {} is not authenticated", viewer_public_key),
+        });
+    }
+
+    let request: ViewingRequest =
+        serde_json::from_str(request_json).map_err(|e| ValidationError::MalformedJson {
+            details: format!("Invalid JSON: {}", e),
+        })?;
+
+    if request.recipient_public_key == viewer_public_key {
+        return Err(ValidationError::CannotMessageSelf);
+    }
+
+    let Ok(Some(peer_conn)) = crate::lobby::get_user(lobby, &request.recipient_public_key).await
+    else {
+        tracing::debug!("Viewing hint dropped, peer offline");
+        return Ok(());
+    };
+
+    let _ = peer_conn.sender.send(profile_shared::Message::new_viewing(
+        viewer_public_key.to_string(),
+        request.viewing,
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use crate::message::message_type;
+    use profile_shared::Message as SharedMessage;
+
+    const VIEWER_KEY: &str = "aaaa1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+    const PEER_KEY: &str = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+
+    fn viewing_json(recipient: &str, viewing: bool) -> String {
+        serde_json::json!({
+            "type": "viewing",
+            "recipientPublicKey": recipient,
+            "viewing": viewing
+        })
+        .to_string()
+    }
+
+    async fn add_connection(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    #[tokio::test]
+    async fn test_viewing_hint_forwarded_to_peer() {
+        assert!(is_viewing_request(
+            &message_type(&viewing_json(PEER_KEY, true)).unwrap()
+        ));
+        assert!(!is_viewing_request("read"));
+
+        let lobby = Lobby::new();
+        let _viewer_rx = add_connection(&lobby, VIEWER_KEY, 1).await;
+        let mut peer_rx = add_connection(&lobby, PEER_KEY, 2).await;
+        while peer_rx.try_recv().is_ok() {}
+
+        handle_viewing_request(&lobby, VIEWER_KEY, &viewing_json(PEER_KEY, false))
+            .await
+            .unwrap();
+        match peer_rx.try_recv() {
+            Ok(SharedMessage::Viewing {
+                viewer_public_key,
+                viewing,
+            }) => {
+                assert_eq!(viewer_public_key, VIEWER_KEY);
+                assert!(!viewing);
+            }
+            other => panic!("Expected Viewing message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_viewing_hint_dropped_for_offline_peer() {
+        let lobby = Lobby::new();
+        assert!(matches!(
+            handle_viewing_request(&lobby, VIEWER_KEY, &viewing_json(PEER_KEY, true)).await,
+            Err(ValidationError::NotAuthenticated { .. })
+        ));
+
+        let mut viewer_rx = add_connection(&lobby, VIEWER_KEY, 1).await;
+        assert!(
+            handle_viewing_request(&lobby, VIEWER_KEY, &viewing_json(PEER_KEY, true))
+                .await
+                .is_ok()
+        );
+        assert!(viewer_rx.try_recv().is_err());
+        assert!(matches!(
+            handle_viewing_request(&lobby, VIEWER_KEY, &viewing_json(VIEWER_KEY, true)).await,
+            Err(ValidationError::CannotMessageSelf)
+        ));
+    }
+}
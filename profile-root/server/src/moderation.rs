@@ -0,0 +1,344 @@
+//! Public key bans and kicks
+//!
+//! The server operator bans keys through the admin API, either permanently
+//! or until a given time. A banned key is refused at authentication with
+//! the `banned` reason, and any message it still sends is rejected. Bans
+//! are saved to a file (`PROFILE_BAN_FILE`) when one is configured, so
+//! they survive a restart; without it they are kept in memory only.
+//!
+//! Kicked connections have already been removed from the lobby by the
+//! admin API; their session closes the socket on the next frame it reads.
+
+use crate::lobby::ServerPublicKey;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+
+/// Environment variable naming the file bans are saved to
+pub const BAN_FILE_ENV_VAR: &str = "PROFILE_BAN_FILE";
+
+/// Error types for the ban list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModerationError {
+    /// Reading or writing the ban file failed
+    Io(String),
+    /// The ban file is not valid JSON
+    Parse(String),
+}
+
+impl Display for ModerationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ModerationError::Io(msg) => write!(f, "Failed to access ban file: {}", msg),
+            ModerationError::Parse(msg) => write!(f, "Failed to parse ban file: {}", msg),
+        }
+    }
+}
+
+impl Error for ModerationError {}
+
+/// One banned key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ban {
+    pub banned_at: DateTime<Utc>,
+    /// When the ban ends, None if it is permanent
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Ban {
+    /// Whether the ban is still in force at `now`
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_none_or(|expires| now < expires)
+    }
+
+    /// Explanation sent to the banned client
+    pub fn describe(&self) -> String {
+        match self.expires_at {
+            Some(expires) => format!(
+                "This key is banned from the server until {}.",
+                expires.to_rfc3339()
+            ),
+            None => "This key is banned from the server.".to_string(),
+        }
+    }
+}
+
+/// On-disk form of one ban
+#[derive(Debug, Serialize, Deserialize)]
+struct BanRecord {
+    #[serde(rename = "publicKey")]
+    public_key: String,
+    #[serde(rename = "bannedAt")]
+    banned_at: String,
+    #[serde(rename = "expiresAt", default)]
+    expires_at: Option<String>,
+}
+
+fn parse_time(text: &str) -> Result<DateTime<Utc>, ModerationError> {
+    DateTime::parse_from_rfc3339(text)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| ModerationError::Parse(format!("invalid time '{}': {}", text, e)))
+}
+
+/// Banned keys and kicked connections
+#[derive(Debug, Default)]
+pub struct Moderation {
+    bans: RwLock<HashMap<ServerPublicKey, Ban>>,
+    kicked: RwLock<HashSet<u64>>,
+    /// File bans are saved to, if persistent
+    path: Option<PathBuf>,
+}
+
+impl Moderation {
+    /// Create an in-memory moderation list with no bans
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the bans saved at `path`, dropping expired ones
+    ///
+    /// A missing file gives an empty list that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, ModerationError> {
+        let path = path.into();
+        let records: Vec<BanRecord> = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| ModerationError::Parse(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(ModerationError::Io(e.to_string())),
+        };
+
+        let now = Utc::now();
+        let mut bans = HashMap::new();
+        for record in records {
+            let ban = Ban {
+                banned_at: parse_time(&record.banned_at)?,
+                expires_at: record.expires_at.as_deref().map(parse_time).transpose()?,
+            };
+            if ban.is_active(now) {
+                bans.insert(record.public_key.to_ascii_lowercase(), ban);
+            }
+        }
+        Ok(Self {
+            bans: RwLock::new(bans),
+            kicked: RwLock::new(HashSet::new()),
+            path: Some(path),
+        })
+    }
+
+    /// Load bans from `PROFILE_BAN_FILE`, or start in memory if it is unset
+    pub fn from_env() -> Result<Self, ModerationError> {
+        match std::env::var_os(BAN_FILE_ENV_VAR).filter(|p| !p.is_empty()) {
+            Some(path) => Self::load(PathBuf::from(path)),
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// Write the bans to their file; in-memory lists are not saved
+    fn save(&self, bans: &HashMap<ServerPublicKey, Ban>) -> Result<(), ModerationError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| ModerationError::Io(e.to_string()))?;
+        }
+        let mut records: Vec<BanRecord> = bans
+            .iter()
+            .map(|(key, ban)| BanRecord {
+                public_key: key.clone(),
+                banned_at: ban.banned_at.to_rfc3339(),
+                expires_at: ban.expires_at.map(|t| t.to_rfc3339()),
+            })
+            .collect();
+        records.sort_by(|a, b| a.public_key.cmp(&b.public_key));
+        let json = serde_json::to_string_pretty(&records)
+            .map_err(|e| ModerationError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| ModerationError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| ModerationError::Io(e.to_string()))
+    }
+
+    /// Ban a key, replacing any existing ban on it
+    ///
+    /// # Arguments
+    /// * `public_key` - The key to ban
+    /// * `duration` - How long the ban lasts, None for a permanent ban
+    /// * `now` - Current time
+    pub async fn ban(
+        &self,
+        public_key: &str,
+        duration: Option<chrono::Duration>,
+        now: DateTime<Utc>,
+    ) -> Result<Ban, ModerationError> {
+        let ban = Ban {
+            banned_at: now,
+            expires_at: duration.map(|d| now + d),
+        };
+        let mut bans = self.bans.write().await;
+        bans.insert(public_key.to_string(), ban);
+        self.save(&bans)?;
+        Ok(ban)
+    }
+
+    /// Lift a ban
+    ///
+    /// # Returns
+    /// true if the key had an active ban
+    pub async fn unban(&self, public_key: &str) -> Result<bool, ModerationError> {
+        let mut bans = self.bans.write().await;
+        let Some(ban) = bans.remove(public_key) else {
+            return Ok(false);
+        };
+        self.save(&bans)?;
+        Ok(ban.is_active(Utc::now()))
+    }
+
+    /// The key's ban, if one is in force now
+    pub async fn active_ban(&self, public_key: &str) -> Option<Ban> {
+        let now = Utc::now();
+        self.bans
+            .read()
+            .await
+            .get(public_key)
+            .copied()
+            .filter(|ban| ban.is_active(now))
+    }
+
+    /// Check if a key is banned now
+    pub async fn is_banned(&self, public_key: &str) -> bool {
+        self.active_ban(public_key).await.is_some()
+    }
+
+    /// Bans in force now, ordered by key
+    pub async fn active_bans(&self) -> Vec<(ServerPublicKey, Ban)> {
+        let now = Utc::now();
+        let mut bans: Vec<_> = self
+            .bans
+            .read()
+            .await
+            .iter()
+            .filter(|(_, ban)| ban.is_active(now))
+            .map(|(key, ban)| (key.clone(), *ban))
+            .collect();
+        bans.sort_by(|a, b| a.0.cmp(&b.0));
+        bans
+    }
+
+    /// Add bans taken from a state snapshot, replacing bans on the same keys
+    ///
+    /// Bans that have expired since are skipped.
+    ///
+    /// # Returns
+    /// The number of bans added
+    pub async fn restore_bans(
+        &self,
+        restored: Vec<(ServerPublicKey, Ban)>,
+    ) -> Result<usize, ModerationError> {
+        let now = Utc::now();
+        let mut bans = self.bans.write().await;
+        let mut added = 0;
+        for (public_key, ban) in restored.into_iter().filter(|(_, ban)| ban.is_active(now)) {
+            bans.insert(public_key.to_ascii_lowercase(), ban);
+            added += 1;
+        }
+        if added > 0 {
+            self.save(&bans)?;
+        }
+        Ok(added)
+    }
+
+    /// Number of bans in force now
+    pub async fn banned_count(&self) -> usize {
+        let now = Utc::now();
+        self.bans
+            .read()
+            .await
+            .values()
+            .filter(|ban| ban.is_active(now))
+            .count()
+    }
+
+    /// Mark a connection as kicked
+    pub async fn kick(&self, connection_id: u64) {
+        self.kicked.write().await.insert(connection_id);
+    }
+
+    /// Check if a connection was kicked, clearing the mark
+    ///
+    /// Sessions call this for every frame, so the common case only takes
+    /// the read lock.
+    pub async fn take_kicked(&self, connection_id: u64) -> bool {
+        if !self.kicked.read().await.contains(&connection_id) {
+            return false;
+        }
+        self.kicked.write().await.remove(&connection_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_timed_ban_expires() {
+        let moderation = Moderation::new();
+        let key = "a".repeat(64);
+        let past = Utc::now() - chrono::Duration::hours(2);
+
+        moderation
+            .ban(&key, Some(chrono::Duration::hours(1)), past)
+            .await
+            .unwrap();
+        assert!(!moderation.is_banned(&key).await);
+        assert_eq!(moderation.banned_count().await, 0);
+
+        moderation
+            .ban(&key, Some(chrono::Duration::hours(1)), Utc::now())
+            .await
+            .unwrap();
+        assert!(moderation.is_banned(&key).await);
+        assert!(moderation.unban(&key).await.unwrap());
+        assert!(!moderation.unban(&key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_bans_survive_reload() {
+        let path = std::env::temp_dir().join(format!("profile-bans-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (permanent, timed, expired) = ("a".repeat(64), "b".repeat(64), "c".repeat(64));
+
+        let moderation = Moderation::load(&path).unwrap();
+        let now = Utc::now();
+        moderation.ban(&permanent, None, now).await.unwrap();
+        moderation
+            .ban(&timed, Some(chrono::Duration::days(1)), now)
+            .await
+            .unwrap();
+        moderation
+            .ban(
+                &expired,
+                Some(chrono::Duration::minutes(1)),
+                now - chrono::Duration::hours(1),
+            )
+            .await
+            .unwrap();
+
+        let reloaded = Moderation::load(&path).unwrap();
+        let keys: Vec<String> = reloaded
+            .active_bans()
+            .await
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec![permanent.clone(), timed.clone()]);
+        assert_eq!(
+            reloaded.active_ban(&permanent).await.unwrap().expires_at,
+            None
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,468 @@
+//! Registry of names claimed by users
+//!
+//! Nicknames are only unique among online users and are released when their
+//! owner leaves. A deployment that wants lasting human-readable identities
+//! can turn on the name registry by setting `PROFILE_NAME_FILE`:
+//! - a user claims a name with a signed `claim_name` request, or releases
+//!   theirs by claiming an empty one
+//! - a name belongs to the first key that claims it, online or not, until
+//!   that key releases it; each key holds at most one name
+//! - claimed names are sent in lobby metadata as verified names
+//!
+//! Claims are saved to the file, so they survive a restart. Without the
+//! variable the registry is disabled and claims are refused. Each server
+//! keeps its own registry, so clustered nodes should not enable it.
+
+use crate::lobby::nicknames::validate_nickname;
+use crate::lobby::ServerPublicKey;
+use chrono::{DateTime, Utc};
+use profile_shared::config::names::MAX_CLAIMED_NAMES;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+
+/// Environment variable naming the file claimed names are saved to; the
+/// registry is disabled when unset
+pub const NAME_FILE_ENV_VAR: &str = "PROFILE_NAME_FILE";
+
+/// Why a name claim was refused, or the registry could not be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameError {
+    /// The server has no name registry
+    Disabled,
+    /// The name is too long or contains invalid characters
+    InvalidName,
+    /// Another key already claimed the name
+    NameTaken,
+    /// The registry holds as many names as it may
+    RegistryFull { max: usize },
+    /// Reading or writing the name file failed
+    Io(String),
+    /// The name file is not valid JSON
+    Parse(String),
+}
+
+impl Display for NameError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Disabled => write!(f, "This server does not register names"),
+            NameError::InvalidName => write!(f, "Invalid name"),
+            NameError::NameTaken => write!(f, "Name is already claimed"),
+            NameError::RegistryFull { max } => {
+                write!(f, "The server already holds the maximum of {} names", max)
+            }
+            NameError::Io(msg) => write!(f, "Failed to access name file: {}", msg),
+            NameError::Parse(msg) => write!(f, "Failed to parse name file: {}", msg),
+        }
+    }
+}
+
+impl Error for NameError {}
+
+/// Map a name error to the protocol error reason sent to clients
+pub fn name_error_reason(error: &NameError) -> &'static str {
+    match error {
+        NameError::Disabled => "names_disabled",
+        NameError::InvalidName => "invalid_name",
+        NameError::NameTaken => "name_taken",
+        NameError::RegistryFull { .. } => "name_registry_full",
+        NameError::Io(_) | NameError::Parse(_) => "storage_error",
+    }
+}
+
+/// One claim, as saved to the name file and state snapshots
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameRecord {
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    pub name: String,
+    /// When the name was claimed (RFC 3339)
+    #[serde(rename = "claimedAt")]
+    pub claimed_at: String,
+}
+
+#[derive(Debug, Default)]
+struct Claims {
+    /// Claim held by each key
+    by_key: HashMap<ServerPublicKey, NameRecord>,
+    /// Owner of each name, keyed by the lowercased name
+    by_name: HashMap<String, ServerPublicKey>,
+}
+
+impl Claims {
+    fn insert(&mut self, record: NameRecord) {
+        self.by_name
+            .insert(record.name.to_lowercase(), record.public_key.clone());
+        self.by_key.insert(record.public_key.clone(), record);
+    }
+
+    fn remove(&mut self, public_key: &str) -> Option<NameRecord> {
+        let record = self.by_key.remove(public_key)?;
+        self.by_name.remove(&record.name.to_lowercase());
+        Some(record)
+    }
+}
+
+/// Thread-safe registry of claimed names
+#[derive(Debug, Default)]
+pub struct NameRegistry {
+    claims: RwLock<Claims>,
+    enabled: bool,
+    /// File claims are saved to, if persistent
+    path: Option<PathBuf>,
+    max_names: usize,
+}
+
+impl NameRegistry {
+    /// Create a disabled registry that refuses every claim
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an enabled registry kept in memory only
+    pub fn in_memory() -> Self {
+        Self::with_limit(MAX_CLAIMED_NAMES)
+    }
+
+    /// Create an enabled in-memory registry holding up to `max_names` names
+    pub fn with_limit(max_names: usize) -> Self {
+        Self {
+            claims: RwLock::new(Claims::default()),
+            enabled: true,
+            path: None,
+            max_names,
+        }
+    }
+
+    /// Load the claims saved at `path` into an enabled registry
+    ///
+    /// A missing file gives an empty registry that will be saved to `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, NameError> {
+        let path = path.into();
+        let records: Vec<NameRecord> = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| NameError::Parse(e.to_string()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(NameError::Io(e.to_string())),
+        };
+
+        let mut claims = Claims::default();
+        for mut record in records {
+            record.public_key.make_ascii_lowercase();
+            if validate_nickname(&record.name).is_err()
+                || claims
+                    .by_name
+                    .get(&record.name.to_lowercase())
+                    .is_some_and(|owner| *owner != record.public_key)
+            {
+                return Err(NameError::Parse(format!(
+                    "invalid or duplicate name '{}'",
+                    record.name
+                )));
+            }
+            claims.remove(&record.public_key);
+            claims.insert(record);
+        }
+        Ok(Self {
+            claims: RwLock::new(claims),
+            enabled: true,
+            path: Some(path),
+            max_names: MAX_CLAIMED_NAMES,
+        })
+    }
+
+    /// Load claims from `PROFILE_NAME_FILE`, or disable the registry if it
+    /// is unset
+    pub fn from_env() -> Result<Self, NameError> {
+        match std::env::var_os(NAME_FILE_ENV_VAR).filter(|p| !p.is_empty()) {
+            Some(path) => Self::load(PathBuf::from(path)),
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// Whether the server accepts name claims
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Write the claims to their file; in-memory registries are not saved
+    fn save(&self, claims: &Claims) -> Result<(), NameError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| NameError::Io(e.to_string()))?;
+        }
+        let mut records: Vec<&NameRecord> = claims.by_key.values().collect();
+        records.sort_by(|a, b| a.public_key.cmp(&b.public_key));
+        let json =
+            serde_json::to_string_pretty(&records).map_err(|e| NameError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| NameError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| NameError::Io(e.to_string()))
+    }
+
+    /// Claim a name for a key, releasing the key's previous name
+    ///
+    /// # Arguments
+    /// * `public_key` - The key claiming the name
+    /// * `name` - The name, compared case-insensitively with other claims
+    /// * `now` - Current time
+    ///
+    /// # Returns
+    /// Ok(true) if the key's name changed, Ok(false) if it already had it
+    pub async fn claim(
+        &self,
+        public_key: &str,
+        name: &str,
+        now: DateTime<Utc>,
+    ) -> Result<bool, NameError> {
+        if !self.enabled {
+            return Err(NameError::Disabled);
+        }
+        validate_nickname(name).map_err(|_| NameError::InvalidName)?;
+
+        let mut claims = self.claims.write().await;
+        match claims.by_name.get(&name.to_lowercase()) {
+            Some(owner) if owner != public_key => return Err(NameError::NameTaken),
+            _ => {}
+        }
+        let previous = claims.by_key.get(public_key);
+        if previous.is_some_and(|record| record.name == name) {
+            return Ok(false);
+        }
+        if previous.is_none() && claims.by_key.len() >= self.max_names {
+            return Err(NameError::RegistryFull {
+                max: self.max_names,
+            });
+        }
+
+        let previous = claims.remove(public_key);
+        claims.insert(NameRecord {
+            public_key: public_key.to_string(),
+            name: name.to_string(),
+            claimed_at: now.to_rfc3339(),
+        });
+        if let Err(e) = self.save(&claims) {
+            // Keep memory and file in step
+            claims.remove(public_key);
+            if let Some(previous) = previous {
+                claims.insert(previous);
+            }
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Release the name a key claimed
+    ///
+    /// # Returns
+    /// The released name, None if the key had none
+    pub async fn release(&self, public_key: &str) -> Result<Option<String>, NameError> {
+        if !self.enabled {
+            return Err(NameError::Disabled);
+        }
+        let mut claims = self.claims.write().await;
+        let Some(record) = claims.remove(public_key) else {
+            return Ok(None);
+        };
+        if let Err(e) = self.save(&claims) {
+            claims.insert(record);
+            return Err(e);
+        }
+        Ok(Some(record.name))
+    }
+
+    /// Move the name `old_public_key` claimed to `new_public_key`, after a
+    /// key rollover
+    ///
+    /// The claim keeps its original time. A name the new key claimed is
+    /// released. A disabled registry moves nothing.
+    ///
+    /// # Returns
+    /// The moved name, None if the old key had none
+    pub async fn transfer(
+        &self,
+        old_public_key: &str,
+        new_public_key: &str,
+    ) -> Result<Option<String>, NameError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let mut claims = self.claims.write().await;
+        let Some(mut record) = claims.remove(old_public_key) else {
+            return Ok(None);
+        };
+        let replaced = claims.remove(new_public_key);
+        record.public_key = new_public_key.to_string();
+        claims.insert(record.clone());
+        if let Err(e) = self.save(&claims) {
+            claims.remove(new_public_key);
+            record.public_key = old_public_key.to_string();
+            claims.insert(record);
+            if let Some(replaced) = replaced {
+                claims.insert(replaced);
+            }
+            return Err(e);
+        }
+        Ok(Some(record.name))
+    }
+
+    /// Get the name a key claimed
+    pub async fn get(&self, public_key: &str) -> Option<String> {
+        let claims = self.claims.read().await;
+        claims.by_key.get(public_key).map(|r| r.name.clone())
+    }
+
+    /// Get the key that claimed a name, compared case-insensitively
+    pub async fn owner(&self, name: &str) -> Option<ServerPublicKey> {
+        self.claims
+            .read()
+            .await
+            .by_name
+            .get(&name.to_lowercase())
+            .cloned()
+    }
+
+    /// Get the names of the given keys that claimed one
+    pub async fn lookup(&self, public_keys: &[ServerPublicKey]) -> HashMap<String, String> {
+        let claims = self.claims.read().await;
+        public_keys
+            .iter()
+            .filter_map(|key| Some((key.clone(), claims.by_key.get(key)?.name.clone())))
+            .collect()
+    }
+
+    /// Every claim, ordered by public key
+    pub async fn records(&self) -> Vec<NameRecord> {
+        let mut records: Vec<NameRecord> =
+            self.claims.read().await.by_key.values().cloned().collect();
+        records.sort_by(|a, b| a.public_key.cmp(&b.public_key));
+        records
+    }
+
+    /// Add claims taken from a state snapshot
+    ///
+    /// Keys that already hold a name keep it, and a name already claimed by
+    /// another key stays theirs. A disabled registry restores nothing.
+    ///
+    /// # Returns
+    /// The number of claims added
+    pub async fn restore(&self, records: Vec<NameRecord>) -> Result<usize, NameError> {
+        if !self.enabled {
+            return Ok(0);
+        }
+        let mut claims = self.claims.write().await;
+        let mut added = 0;
+        for mut record in records {
+            record.public_key.make_ascii_lowercase();
+            if validate_nickname(&record.name).is_err()
+                || claims.by_key.contains_key(&record.public_key)
+                || claims.by_name.contains_key(&record.name.to_lowercase())
+                || claims.by_key.len() >= self.max_names
+            {
+                continue;
+            }
+            claims.insert(record);
+            added += 1;
+        }
+        if added > 0 {
+            self.save(&claims)?;
+        }
+        Ok(added)
+    }
+
+    /// Number of claimed names
+    pub async fn len(&self) -> usize {
+        self.claims.read().await.by_key.len()
+    }
+
+    /// Whether no name is claimed
+    pub async fn is_empty(&self) -> bool {
+        self.claims.read().await.by_key.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_names_unique_across_keys_until_released() {
+        let names = NameRegistry::with_limit(2);
+        let now = Utc::now();
+
+        assert_eq!(names.claim("aa", "Alice", now).await, Ok(true));
+        assert_eq!(
+            names.claim("bb", "ALICE", now).await,
+            Err(NameError::NameTaken)
+        );
+        assert_eq!(names.claim("aa", "Alice", now).await, Ok(false));
+        assert_eq!(
+            names.claim("bb", "<bob>", now).await,
+            Err(NameError::InvalidName)
+        );
+        assert_eq!(names.owner("alice").await.as_deref(), Some("aa"));
+
+        // A new claim releases the old name
+        assert_eq!(names.claim("aa", "Ally", now).await, Ok(true));
+        assert_eq!(names.claim("bb", "Alice", now).await, Ok(true));
+        assert_eq!(
+            names.claim("cc", "Carol", now).await,
+            Err(NameError::RegistryFull { max: 2 })
+        );
+        assert_eq!(names.release("bb").await, Ok(Some("Alice".to_string())));
+        assert_eq!(names.release("bb").await, Ok(None));
+
+        let found = names.lookup(&["aa".to_string(), "bb".to_string()]).await;
+        assert_eq!(
+            found,
+            HashMap::from([("aa".to_string(), "Ally".to_string())])
+        );
+
+        // A rollover moves the claim to the new key
+        assert_eq!(
+            names.transfer("aa", "dd").await,
+            Ok(Some("Ally".to_string()))
+        );
+        assert_eq!(names.owner("ally").await.as_deref(), Some("dd"));
+        assert!(names.get("aa").await.is_none());
+        assert_eq!(names.transfer("aa", "ee").await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_registry_refuses_claims() {
+        let names = NameRegistry::new();
+        assert!(!names.is_enabled());
+        assert_eq!(
+            names.claim("aa", "Alice", Utc::now()).await,
+            Err(NameError::Disabled)
+        );
+        assert_eq!(names.release("aa").await, Err(NameError::Disabled));
+        assert!(names.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn test_claims_survive_reload() {
+        let path = std::env::temp_dir().join(format!("profile-names-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+
+        let names = NameRegistry::load(&path).unwrap();
+        names.claim(&alice, "Alice", Utc::now()).await.unwrap();
+        names.claim(&bob, "Bob", Utc::now()).await.unwrap();
+        names.release(&bob).await.unwrap();
+
+        let reloaded = NameRegistry::load(&path).unwrap();
+        assert!(reloaded.is_enabled());
+        assert_eq!(reloaded.get(&alice).await.as_deref(), Some("Alice"));
+        assert_eq!(reloaded.len().await, 1);
+        assert_eq!(
+            reloaded.claim(&bob, "alice", Utc::now()).await,
+            Err(NameError::NameTaken)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
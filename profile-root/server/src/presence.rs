@@ -0,0 +1,331 @@
+//! Lobby presence kept across server restarts
+//!
+//! Without it a restarted server comes back empty and reconnecting clients
+//! can't tell a restart from a network blip, so they keep showing lobby
+//! pages, rooms and pending messages the new server knows nothing about.
+//!
+//! When `PROFILE_PRESENCE_FILE` is set, the server records the keys in the
+//! lobby and a restart epoch in that file. Each start bumps the epoch, and
+//! every key that was connected when the previous run stopped is sent a
+//! `server_restarted` notice right after it authenticates again, telling
+//! the client to refresh its state. Without the file, presence is only
+//! tracked in memory and no notices are sent.
+//!
+//! Joins and leaves only mark the record dirty; [`spawn_flush_task`] writes
+//! it out periodically, so a busy lobby doesn't rewrite the file on every
+//! connection. On shutdown [`PresenceStore::seal`] writes it one last time
+//! and stops recording, so the users who leave as the server closes their
+//! connections still count as connected when it comes back. The same keys
+//! are admitted first after the restart (see [`crate::admission`]).
+
+use chrono::{DateTime, Utc};
+use profile_shared::Message;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// Environment variable naming the file presence is saved to
+pub const PRESENCE_FILE_ENV_VAR: &str = "PROFILE_PRESENCE_FILE";
+
+/// How often a changed presence record is written out
+pub const PRESENCE_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Error types for the presence file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresenceError {
+    /// Reading or writing the presence file failed
+    Io(String),
+    /// The presence file is not valid JSON
+    Parse(String),
+}
+
+impl Display for PresenceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PresenceError::Io(msg) => write!(f, "Failed to access presence file: {}", msg),
+            PresenceError::Parse(msg) => write!(f, "Failed to parse presence file: {}", msg),
+        }
+    }
+}
+
+impl Error for PresenceError {}
+
+/// On-disk form of the presence record
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PresenceRecord {
+    epoch: u64,
+    #[serde(rename = "startedAt", default)]
+    started_at: String,
+    /// Keys in the lobby when the record was written
+    #[serde(default)]
+    connected: BTreeSet<String>,
+}
+
+/// Connected keys and the restart epoch
+#[derive(Debug)]
+pub struct PresenceStore {
+    /// Starts of the server recorded in the file, this one included
+    epoch: u64,
+    started_at: DateTime<Utc>,
+    connected: RwLock<BTreeSet<String>>,
+    /// Keys connected when the previous run stopped, not yet notified
+    returning: RwLock<BTreeSet<String>>,
+    dirty: AtomicBool,
+    /// Set on shutdown; joins and leaves are no longer recorded
+    sealed: AtomicBool,
+    /// File presence is saved to, if persistent
+    path: Option<PathBuf>,
+}
+
+impl PresenceStore {
+    /// Create an in-memory store for a first start
+    pub fn new() -> Self {
+        Self {
+            epoch: 1,
+            started_at: Utc::now(),
+            connected: RwLock::new(BTreeSet::new()),
+            returning: RwLock::new(BTreeSet::new()),
+            dirty: AtomicBool::new(false),
+            sealed: AtomicBool::new(false),
+            path: None,
+        }
+    }
+
+    /// Start a new epoch from the record saved at `path`
+    ///
+    /// A missing file means this is the first start. The new epoch is saved
+    /// immediately, so a crash before the first flush still counts as a
+    /// restart.
+    pub fn load(path: impl Into<PathBuf>, now: DateTime<Utc>) -> Result<Self, PresenceError> {
+        let path = path.into();
+        let previous: PresenceRecord = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                serde_json::from_str(&text).map_err(|e| PresenceError::Parse(e.to_string()))?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => PresenceRecord::default(),
+            Err(e) => return Err(PresenceError::Io(e.to_string())),
+        };
+        let returning = previous
+            .connected
+            .into_iter()
+            .map(|key| key.to_ascii_lowercase())
+            .collect();
+        let store = Self {
+            epoch: previous.epoch + 1,
+            started_at: now,
+            connected: RwLock::new(BTreeSet::new()),
+            returning: RwLock::new(returning),
+            dirty: AtomicBool::new(false),
+            sealed: AtomicBool::new(false),
+            path: Some(path),
+        };
+        store.save(&BTreeSet::new())?;
+        Ok(store)
+    }
+
+    /// Load presence from `PROFILE_PRESENCE_FILE`, or track it in memory if
+    /// it is unset
+    pub fn from_env() -> Result<Self, PresenceError> {
+        match std::env::var_os(PRESENCE_FILE_ENV_VAR).filter(|p| !p.is_empty()) {
+            Some(path) => Self::load(PathBuf::from(path), Utc::now()),
+            None => Ok(Self::new()),
+        }
+    }
+
+    /// This run's restart epoch (1 on the first start)
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Whether this run follows an earlier one recorded in the file
+    pub fn is_restart(&self) -> bool {
+        self.epoch > 1
+    }
+
+    /// Number of keys still owed a `server_restarted` notice
+    pub async fn returning_count(&self) -> usize {
+        self.returning.read().await.len()
+    }
+
+    /// Whether a key was connected when the previous run stopped and has
+    /// not yet been sent its `server_restarted` notice
+    pub async fn is_returning(&self, public_key: &str) -> bool {
+        self.returning.read().await.contains(public_key)
+    }
+
+    /// Record a key entering the lobby
+    pub async fn joined(&self, public_key: &str) {
+        if self.sealed.load(Ordering::Relaxed) {
+            return;
+        }
+        if self.connected.write().await.insert(public_key.to_string()) {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Record a key leaving the lobby
+    pub async fn left(&self, public_key: &str) {
+        if self.sealed.load(Ordering::Relaxed) {
+            return;
+        }
+        if self.connected.write().await.remove(public_key) {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// The `server_restarted` notice owed to a key, at most once per key
+    ///
+    /// # Returns
+    /// The notice if the key was connected when the previous run stopped
+    pub async fn take_restart_notice(&self, public_key: &str) -> Option<Message> {
+        if !self.returning.write().await.remove(public_key) {
+            return None;
+        }
+        Some(Message::new_server_restarted(
+            self.epoch,
+            self.started_at.to_rfc3339(),
+        ))
+    }
+
+    /// Write the record out if it changed since the last flush
+    pub async fn flush(&self) -> Result<(), PresenceError> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let connected = self.connected.read().await;
+        self.save(&connected).inspect_err(|_| {
+            // Try again on the next flush
+            self.dirty.store(true, Ordering::Relaxed);
+        })
+    }
+
+    /// Write the record out one last time and stop recording joins and leaves
+    ///
+    /// Called on shutdown before connections are closed, so the keys
+    /// connected now are the ones told about the restart.
+    pub async fn seal(&self) -> Result<(), PresenceError> {
+        self.sealed.store(true, Ordering::Relaxed);
+        self.dirty.store(false, Ordering::Relaxed);
+        let connected = self.connected.read().await;
+        self.save(&connected)
+    }
+
+    /// Write the record to its file; in-memory stores are not saved
+    fn save(&self, connected: &BTreeSet<String>) -> Result<(), PresenceError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| PresenceError::Io(e.to_string()))?;
+        }
+        let record = PresenceRecord {
+            epoch: self.epoch,
+            started_at: self.started_at.to_rfc3339(),
+            connected: connected.clone(),
+        };
+        let json = serde_json::to_string_pretty(&record)
+            .map_err(|e| PresenceError::Parse(e.to_string()))?;
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, json).map_err(|e| PresenceError::Io(e.to_string()))?;
+        std::fs::rename(&temp, path).map_err(|e| PresenceError::Io(e.to_string()))
+    }
+}
+
+impl Default for PresenceStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Flush `store` every `interval` until the runtime shuts down
+pub fn spawn_flush_task(
+    store: Arc<PresenceStore>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            if let Err(e) = store.flush().await {
+                tracing::warn!(error = %e, "Failed to save lobby presence");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("profile-presence-{}-{}", std::process::id(), name))
+    }
+
+    #[tokio::test]
+    async fn test_restart_notifies_previously_connected_keys_once() {
+        let path = temp_path("restart.json");
+        let _ = std::fs::remove_file(&path);
+        let (alice, bob) = ("a".repeat(64), "b".repeat(64));
+
+        let first = PresenceStore::load(&path, Utc::now()).unwrap();
+        assert_eq!(first.epoch(), 1);
+        assert!(!first.is_restart());
+        first.joined(&alice).await;
+        first.joined(&bob).await;
+        first.left(&bob).await;
+        first.flush().await.unwrap();
+        assert!(first.take_restart_notice(&alice).await.is_none());
+
+        let second = PresenceStore::load(&path, Utc::now()).unwrap();
+        assert_eq!(second.epoch(), 2);
+        assert_eq!(second.returning_count().await, 1);
+        match second.take_restart_notice(&alice).await {
+            Some(Message::ServerRestarted { epoch, .. }) => assert_eq!(epoch, 2),
+            other => panic!("Expected ServerRestarted, got {:?}", other),
+        }
+        assert!(second.take_restart_notice(&alice).await.is_none());
+        assert!(second.take_restart_notice(&bob).await.is_none());
+
+        // Nobody reconnected before this run stopped, so nobody is owed a notice
+        let third = PresenceStore::load(&path, Utc::now()).unwrap();
+        assert_eq!(third.epoch(), 3);
+        assert_eq!(third.returning_count().await, 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sealed_store_keeps_keys_that_leave_on_shutdown() {
+        let path = temp_path("sealed.json");
+        let _ = std::fs::remove_file(&path);
+        let alice = "a".repeat(64);
+
+        let first = PresenceStore::load(&path, Utc::now()).unwrap();
+        first.joined(&alice).await;
+        first.seal().await.unwrap();
+        // Connections closed by the shutdown leave after the seal
+        first.left(&alice).await;
+        first.flush().await.unwrap();
+
+        let second = PresenceStore::load(&path, Utc::now()).unwrap();
+        assert!(second.is_returning(&alice).await);
+        assert!(second.take_restart_notice(&alice).await.is_some());
+        assert!(!second.is_returning(&alice).await);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_store_never_sends_notices() {
+        let store = PresenceStore::new();
+        store.joined(&"c".repeat(64)).await;
+        store.flush().await.unwrap();
+        assert_eq!(store.epoch(), 1);
+        assert!(store.take_restart_notice(&"c".repeat(64)).await.is_none());
+    }
+}
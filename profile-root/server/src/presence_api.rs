@@ -0,0 +1,565 @@
+//! Read-only HTTP presence API
+//!
+//! Dashboards and bots that only want to know how busy the lobby is, or
+//! whether someone is around, shouldn't need a key pair and a WebSocket
+//! session. When `PROFILE_PRESENCE_API_TOKEN` is set the server answers two
+//! requests on a separate listener (`PROFILE_PRESENCE_API_ADDR`, default
+//! [`DEFAULT_PRESENCE_API_ADDRESS`]):
+//!
+//! - `GET /v1/presence` → `{"onlineUsers": 12}`
+//! - `GET /v1/presence/<public key>` → `{"publicKey": "...", "online": true}`
+//!
+//! Both need `Authorization: Bearer <token>`. Looking up a key is
+//! privacy-gated: only keys listed in `PROFILE_PRESENCE_API_VISIBLE_KEYS`
+//! (comma-separated) are answered, and any other key gets 403 whether or
+//! not it is online, so the API can't be used to probe for users who never
+//! agreed to be watched. A user who chose to be invisible is reported
+//! offline. Errors are `{"error": "<reason>", "details": "..."}`.
+//!
+//! In a cluster both answers cover every node. Each connection serves one
+//! request and is closed.
+
+use crate::admin::commands::tokens_match;
+use crate::lobby::{normalize_key, Lobby};
+use profile_shared::config::presence_api::{
+    DEFAULT_PRESENCE_API_ADDRESS, MAX_PRESENCE_API_REQUEST_SIZE, PRESENCE_API_REQUEST_TIMEOUT,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Environment variable holding the API token; the API is off when unset
+pub const PRESENCE_API_TOKEN_ENV_VAR: &str = "PROFILE_PRESENCE_API_TOKEN";
+
+/// Environment variable overriding the API listen address
+pub const PRESENCE_API_ADDR_ENV_VAR: &str = "PROFILE_PRESENCE_API_ADDR";
+
+/// Environment variable listing the keys whose presence may be looked up
+pub const PRESENCE_API_VISIBLE_KEYS_ENV_VAR: &str = "PROFILE_PRESENCE_API_VISIBLE_KEYS";
+
+/// Shortest accepted API token
+pub const MIN_TOKEN_LENGTH: usize = 16;
+
+/// Error types for presence API configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresenceApiConfigError {
+    /// The address is not a valid socket address
+    InvalidAddress(String),
+    /// The token is shorter than [`MIN_TOKEN_LENGTH`]
+    TokenTooShort,
+    /// A visible key is not 64 hex characters
+    InvalidVisibleKey(String),
+}
+
+impl Display for PresenceApiConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PresenceApiConfigError::InvalidAddress(addr) => write!(
+                f,
+                "Invalid {} '{}': expected an address like {}",
+                PRESENCE_API_ADDR_ENV_VAR, addr, DEFAULT_PRESENCE_API_ADDRESS
+            ),
+            PresenceApiConfigError::TokenTooShort => write!(
+                f,
+                "{} must be at least {} characters",
+                PRESENCE_API_TOKEN_ENV_VAR, MIN_TOKEN_LENGTH
+            ),
+            PresenceApiConfigError::InvalidVisibleKey(key) => write!(
+                f,
+                "Invalid public key '{}' in {}",
+                key, PRESENCE_API_VISIBLE_KEYS_ENV_VAR
+            ),
+        }
+    }
+}
+
+impl Error for PresenceApiConfigError {}
+
+/// Where the presence API listens, its token and the keys it may disclose
+#[derive(Clone, PartialEq, Eq)]
+pub struct PresenceApiConfig {
+    pub address: SocketAddr,
+    token: String,
+    visible_keys: HashSet<String>,
+}
+
+impl fmt::Debug for PresenceApiConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PresenceApiConfig")
+            .field("address", &self.address)
+            .field("token", &"[redacted]")
+            .field("visible_keys", &self.visible_keys.len())
+            .finish()
+    }
+}
+
+impl PresenceApiConfig {
+    /// Read the presence API configuration from the environment
+    ///
+    /// # Returns
+    /// Ok(None) if `PROFILE_PRESENCE_API_TOKEN` is unset or empty
+    pub fn from_env() -> Result<Option<Self>, PresenceApiConfigError> {
+        let Some(token) = std::env::var(PRESENCE_API_TOKEN_ENV_VAR)
+            .ok()
+            .filter(|t| !t.is_empty())
+        else {
+            return Ok(None);
+        };
+        let address = std::env::var(PRESENCE_API_ADDR_ENV_VAR).ok();
+        let visible = std::env::var(PRESENCE_API_VISIBLE_KEYS_ENV_VAR).ok();
+        Self::parse(address.as_deref(), &token, visible.as_deref()).map(Some)
+    }
+
+    /// Build a configuration from an address (None for the default), token
+    /// and comma-separated visible keys
+    pub fn parse(
+        address: Option<&str>,
+        token: &str,
+        visible_keys: Option<&str>,
+    ) -> Result<Self, PresenceApiConfigError> {
+        let address = address.map(str::trim).filter(|a| !a.is_empty());
+        let text = address.unwrap_or(DEFAULT_PRESENCE_API_ADDRESS);
+        let address: SocketAddr = text
+            .parse()
+            .map_err(|_| PresenceApiConfigError::InvalidAddress(text.to_string()))?;
+        if token.chars().count() < MIN_TOKEN_LENGTH {
+            return Err(PresenceApiConfigError::TokenTooShort);
+        }
+        let visible_keys = visible_keys
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(|key| {
+                normalize_key(key)
+                    .ok_or_else(|| PresenceApiConfigError::InvalidVisibleKey(key.to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            address,
+            token: token.to_string(),
+            visible_keys,
+        })
+    }
+
+    /// Whether the presence of `public_key` (normalized) may be disclosed
+    pub fn is_visible(&self, public_key: &str) -> bool {
+        self.visible_keys.contains(public_key)
+    }
+}
+
+/// Error types for presence API requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PresenceApiError {
+    /// The bearer token is missing or wrong
+    Unauthorized,
+    /// The key isn't listed as visible
+    NotDisclosed,
+    /// The key in the path is not 64 hex characters
+    InvalidPublicKey,
+    /// No such endpoint
+    NotFound,
+    /// Only GET is supported
+    MethodNotAllowed,
+    /// The request is not valid HTTP
+    MalformedRequest(String),
+    /// The request head exceeds MAX_PRESENCE_API_REQUEST_SIZE
+    RequestTooLarge,
+    /// The lobby could not be read
+    Unavailable(String),
+}
+
+impl PresenceApiError {
+    /// HTTP status code and reason phrase
+    pub fn status(&self) -> (u16, &'static str) {
+        match self {
+            PresenceApiError::Unauthorized => (401, "Unauthorized"),
+            PresenceApiError::NotDisclosed => (403, "Forbidden"),
+            PresenceApiError::InvalidPublicKey | PresenceApiError::MalformedRequest(_) => {
+                (400, "Bad Request")
+            }
+            PresenceApiError::NotFound => (404, "Not Found"),
+            PresenceApiError::MethodNotAllowed => (405, "Method Not Allowed"),
+            PresenceApiError::RequestTooLarge => (431, "Request Header Fields Too Large"),
+            PresenceApiError::Unavailable(_) => (503, "Service Unavailable"),
+        }
+    }
+
+    /// Machine-readable reason sent in the error body
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PresenceApiError::Unauthorized => "unauthorized",
+            PresenceApiError::NotDisclosed => "not_disclosed",
+            PresenceApiError::InvalidPublicKey => "invalid_public_key",
+            PresenceApiError::NotFound => "not_found",
+            PresenceApiError::MethodNotAllowed => "method_not_allowed",
+            PresenceApiError::MalformedRequest(_) => "malformed_request",
+            PresenceApiError::RequestTooLarge => "request_too_large",
+            PresenceApiError::Unavailable(_) => "unavailable",
+        }
+    }
+}
+
+impl Display for PresenceApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PresenceApiError::Unauthorized => write!(f, "Missing or invalid bearer token"),
+            PresenceApiError::NotDisclosed => {
+                write!(f, "Presence of this key is not disclosed")
+            }
+            PresenceApiError::InvalidPublicKey => {
+                write!(f, "Public key must be 64 hex characters")
+            }
+            PresenceApiError::NotFound => write!(f, "No such endpoint"),
+            PresenceApiError::MethodNotAllowed => write!(f, "Only GET is supported"),
+            PresenceApiError::MalformedRequest(msg) => write!(f, "Malformed request: {}", msg),
+            PresenceApiError::RequestTooLarge => {
+                write!(f, "Request exceeds {} bytes", MAX_PRESENCE_API_REQUEST_SIZE)
+            }
+            PresenceApiError::Unavailable(msg) => write!(f, "Lobby unavailable: {}", msg),
+        }
+    }
+}
+
+impl Error for PresenceApiError {}
+
+/// Body of a successful presence API response
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum PresenceResponse {
+    /// Size of the lobby
+    Lobby {
+        #[serde(rename = "onlineUsers")]
+        online_users: usize,
+    },
+    /// Whether one key is online
+    Key {
+        #[serde(rename = "publicKey")]
+        public_key: String,
+        online: bool,
+    },
+}
+
+/// Authenticate and answer one request
+///
+/// # Arguments
+/// * `method` - The HTTP method
+/// * `path` - The request target; any query string is ignored
+/// * `authorization` - The `Authorization` header, if sent
+pub async fn handle_presence_request(
+    lobby: &Lobby,
+    config: &PresenceApiConfig,
+    method: &str,
+    path: &str,
+    authorization: Option<&str>,
+) -> Result<PresenceResponse, PresenceApiError> {
+    let token = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or(PresenceApiError::Unauthorized)?;
+    if !tokens_match(token, &config.token) {
+        tracing::warn!("Presence API request with invalid token rejected");
+        return Err(PresenceApiError::Unauthorized);
+    }
+
+    let path = path.split('?').next().unwrap_or_default();
+    let key = match path.trim_end_matches('/') {
+        "/v1/presence" => None,
+        other => match other.strip_prefix("/v1/presence/") {
+            Some(key) if !key.contains('/') => Some(key),
+            _ => return Err(PresenceApiError::NotFound),
+        },
+    };
+    if method != "GET" {
+        return Err(PresenceApiError::MethodNotAllowed);
+    }
+
+    let Some(key) = key else {
+        let page = lobby
+            .get_page(None, 0, None)
+            .await
+            .map_err(|e| PresenceApiError::Unavailable(e.to_string()))?;
+        return Ok(PresenceResponse::Lobby {
+            online_users: page.total,
+        });
+    };
+    let public_key = normalize_key(key).ok_or(PresenceApiError::InvalidPublicKey)?;
+    if !config.is_visible(&public_key) {
+        return Err(PresenceApiError::NotDisclosed);
+    }
+    // An invisible user looks offline here too
+    let online = crate::message::recipient_is_online(lobby, &public_key).await
+        && !lobby.is_invisible(&public_key).await;
+    Ok(PresenceResponse::Key { public_key, online })
+}
+
+/// Accept presence API connections until the listener fails
+pub async fn serve(
+    listener: TcpListener,
+    config: Arc<PresenceApiConfig>,
+    lobby: Arc<Lobby>,
+) -> std::io::Result<()> {
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        let config = Arc::clone(&config);
+        let lobby = Arc::clone(&lobby);
+        tokio::spawn(async move {
+            if let Err(e) = handle_presence_connection(stream, &config, &lobby).await {
+                tracing::debug!(client_ip = %addr, error = %e, "Presence API connection error");
+            }
+        });
+    }
+}
+
+/// Read one request, answer it and close the connection
+async fn handle_presence_connection(
+    mut stream: TcpStream,
+    config: &PresenceApiConfig,
+    lobby: &Lobby,
+) -> std::io::Result<()> {
+    let result =
+        match tokio::time::timeout(PRESENCE_API_REQUEST_TIMEOUT, read_head(&mut stream)).await {
+            Ok(Ok(Ok(head))) => respond_to_head(&head, config, lobby).await,
+            Ok(Ok(Err(e))) => Err(e),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Ok(()),
+        };
+
+    let (status, body) = match result {
+        Ok(response) => ((200, "OK"), serde_json::to_string(&response)),
+        Err(error) => (
+            error.status(),
+            serde_json::to_string(&serde_json::json!({
+                "error": error.reason(),
+                "details": error.to_string(),
+            })),
+        ),
+    };
+    let body = body.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let challenge = if status.0 == 401 {
+        "WWW-Authenticate: Bearer\r\n"
+    } else {
+        ""
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nCache-Control: no-store\r\n{}Connection: close\r\n\r\n{}",
+        status.0,
+        status.1,
+        body.len(),
+        challenge,
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Read up to the end of the request head
+///
+/// # Returns
+/// The head bytes, or RequestTooLarge once MAX_PRESENCE_API_REQUEST_SIZE is
+/// passed without one
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Result<Vec<u8>, PresenceApiError>> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    loop {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(Err(PresenceApiError::MalformedRequest(
+                "connection closed before the request was complete".to_string(),
+            )));
+        }
+        head.extend_from_slice(&buf[..read]);
+        if head.windows(4).any(|w| w == b"\r\n\r\n") {
+            return Ok(Ok(head));
+        }
+        if head.len() > MAX_PRESENCE_API_REQUEST_SIZE {
+            return Ok(Err(PresenceApiError::RequestTooLarge));
+        }
+    }
+}
+
+/// Parse a complete request head and answer it
+async fn respond_to_head(
+    head: &[u8],
+    config: &PresenceApiConfig,
+    lobby: &Lobby,
+) -> Result<PresenceResponse, PresenceApiError> {
+    let mut headers = [httparse::EMPTY_HEADER; 32];
+    let mut request = httparse::Request::new(&mut headers);
+    match request.parse(head) {
+        Ok(httparse::Status::Complete(_)) => {}
+        Ok(httparse::Status::Partial) => {
+            return Err(PresenceApiError::MalformedRequest(
+                "incomplete request".to_string(),
+            ))
+        }
+        Err(e) => return Err(PresenceApiError::MalformedRequest(e.to_string())),
+    }
+    let authorization = request
+        .headers
+        .iter()
+        .find(|h| h.name.eq_ignore_ascii_case("authorization"))
+        .and_then(|h| std::str::from_utf8(h.value).ok());
+    handle_presence_request(
+        lobby,
+        config,
+        request.method.unwrap_or_default(),
+        request.path.unwrap_or_default(),
+        authorization,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::send_queue;
+    use crate::lobby::ActiveConnection;
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    fn config(visible: &str) -> PresenceApiConfig {
+        PresenceApiConfig::parse(Some("127.0.0.1:0"), TOKEN, Some(visible)).unwrap()
+    }
+
+    async fn lobby_with(key: &str) -> Lobby {
+        let lobby = Lobby::new();
+        let (sender, _) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: 1,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(&lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        lobby
+    }
+
+    #[test]
+    fn test_config_checks_token_and_visible_keys() {
+        let config = PresenceApiConfig::parse(None, TOKEN, None).unwrap();
+        assert_eq!(config.address.to_string(), DEFAULT_PRESENCE_API_ADDRESS);
+        assert!(!format!("{:?}", config).contains(TOKEN));
+
+        let visible = format!(" {} ,", "A".repeat(64));
+        let config = PresenceApiConfig::parse(Some("0.0.0.0:9000"), TOKEN, Some(&visible)).unwrap();
+        assert!(config.is_visible(&"a".repeat(64)));
+
+        assert_eq!(
+            PresenceApiConfig::parse(None, "short", None),
+            Err(PresenceApiConfigError::TokenTooShort)
+        );
+        assert!(matches!(
+            PresenceApiConfig::parse(None, TOKEN, Some("not-a-key")),
+            Err(PresenceApiConfigError::InvalidVisibleKey(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_the_token() {
+        let lobby = Lobby::new();
+        let config = config("");
+        for authorization in [None, Some("Bearer wrong-token-value"), Some(TOKEN)] {
+            assert_eq!(
+                handle_presence_request(&lobby, &config, "GET", "/v1/presence", authorization)
+                    .await,
+                Err(PresenceApiError::Unauthorized)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lookups_are_limited_to_visible_keys() {
+        let (alice, bob, carol) = ("a".repeat(64), "b".repeat(64), "c".repeat(64));
+        let lobby = lobby_with(&alice).await;
+        let config = config(&format!("{},{}", alice, carol));
+        let bearer = format!("Bearer {}", TOKEN);
+        let get = |path: String| {
+            let (lobby, config, bearer) = (&lobby, &config, &bearer);
+            async move { handle_presence_request(lobby, config, "GET", &path, Some(bearer)).await }
+        };
+
+        assert_eq!(
+            get("/v1/presence".to_string()).await,
+            Ok(PresenceResponse::Lobby { online_users: 1 })
+        );
+        assert_eq!(
+            get(format!("/v1/presence/{}", alice.to_uppercase())).await,
+            Ok(PresenceResponse::Key {
+                public_key: alice.clone(),
+                online: true
+            })
+        );
+        assert_eq!(
+            get(format!("/v1/presence/{}?ts=1", carol)).await,
+            Ok(PresenceResponse::Key {
+                public_key: carol.clone(),
+                online: false
+            })
+        );
+        // Not listed: refused whether or not the key is online
+        assert_eq!(
+            get(format!("/v1/presence/{}", bob)).await,
+            Err(PresenceApiError::NotDisclosed)
+        );
+        assert_eq!(
+            get("/v1/presence/xyz".to_string()).await,
+            Err(PresenceApiError::InvalidPublicKey)
+        );
+        assert_eq!(
+            get("/v1/rooms".to_string()).await,
+            Err(PresenceApiError::NotFound)
+        );
+        assert_eq!(
+            handle_presence_request(&lobby, &config, "POST", "/v1/presence", Some(&bearer)).await,
+            Err(PresenceApiError::MethodNotAllowed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_round_trip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve(
+            listener,
+            Arc::new(config("")),
+            Arc::new(lobby_with(&"a".repeat(64)).await),
+        ));
+
+        let request = |head: String| async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream.write_all(head.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = request(format!(
+            "GET /v1/presence HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer {}\r\n\r\n",
+            TOKEN
+        ))
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("{\"onlineUsers\":1}"), "{}", response);
+
+        let response = request("GET /v1/presence HTTP/1.1\r\n\r\n".to_string()).await;
+        assert!(response.starts_with("HTTP/1.1 401 "), "{}", response);
+        assert!(response.contains("WWW-Authenticate: Bearer\r\n"));
+        assert!(response.contains("\"error\":\"unauthorized\""));
+
+        let oversized = format!(
+            "GET /v1/presence HTTP/1.1\r\nX-Padding: {}\r\n",
+            "x".repeat(MAX_PRESENCE_API_REQUEST_SIZE)
+        );
+        let response = request(oversized).await;
+        assert!(response.starts_with("HTTP/1.1 431 "), "{}", response);
+        server.abort();
+    }
+}
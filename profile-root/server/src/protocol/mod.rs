@@ -3,58 +3,382 @@
 //! This module defines the message formats for client-server communication
 //! required by Story 1.5 (Authentication) and subsequent stories.
 
+use crate::lobby::LobbyPage;
+use profile_shared::canonical::CanonicalVersion;
+use profile_shared::crypto::ratchet::RatchetEnvelope;
+use profile_shared::crypto::rollover::KeyRollover;
+use profile_shared::protocol::{lowercase_hex, AttachmentManifest};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use uuid::Uuid;
 
-/// Authentication message sent by client during WebSocket handshake
+pub use profile_shared::protocol::{
+    AuthErrorMessage, AuthMessage, AuthSuccessMessage, ErrorMessage, FilterAction, ResumeMessage,
+    SenderFilter, Status, AUTH_CHALLENGE,
+};
+
+/// Client message request for sending a message to another user
 ///
-/// Follows Architecture Decision 4: Uses `publicKey` and `signature` field names in JSON
-/// but snake_case in Rust to avoid compiler warnings
+/// Sent by client to server after Story 3.1 (composer implementation).
+/// String fields borrow from the request JSON where they can (no escapes,
+/// hex already lowercase), so validating a message doesn't copy its body.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuthMessage {
+pub struct SendMessageRequest<'a> {
+    #[serde(borrow)]
+    pub r#type: Cow<'a, str>,
+    /// Unique id chosen by the sender; requests without one get a fresh id
+    #[serde(rename = "messageId", default = "Uuid::new_v4")]
+    pub message_id: Uuid,
+    #[serde(
+        rename = "recipientPublicKey",
+        borrow,
+        deserialize_with = "lowercase_hex::deserialize_cow"
+    )]
+    pub recipient_public_key: Cow<'a, str>,
+    #[serde(borrow)]
+    pub message: Cow<'a, str>,
+    #[serde(
+        rename = "senderPublicKey",
+        borrow,
+        deserialize_with = "lowercase_hex::deserialize_cow"
+    )]
+    pub sender_public_key: Cow<'a, str>,
+    #[serde(borrow, deserialize_with = "lowercase_hex::deserialize_cow")]
+    pub signature: Cow<'a, str>,
+    #[serde(borrow)]
+    pub timestamp: Cow<'a, str>,
+    /// Canonical encoding the signature covers; absent means version 1
+    #[serde(default, skip_serializing_if = "CanonicalVersion::is_legacy")]
+    pub canonical: CanonicalVersion,
+    /// Per-connection sequence number; each must be greater than the last
+    /// the sender used on this connection, and the signature covers it (see
+    /// [`profile_shared::canonical::direct_message`]). Optional for older
+    /// clients, until the sender numbers a message on the connection.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+}
+
+/// Read receipt sent by a recipient to acknowledge a delivered message
+///
+/// The server forwards it to `recipientPublicKey` (the original sender) as
+/// a [`profile_shared::Message::Read`], filling in the reader from the
+/// authenticated connection rather than trusting the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadReceiptRequest {
     pub r#type: String,
-    #[serde(rename = "publicKey")]
-    pub public_key: String,
+    #[serde(
+        rename = "recipientPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub recipient_public_key: String,
+    #[serde(rename = "messageId")]
+    pub message_id: Uuid,
+    pub timestamp: String,
+}
+
+/// New text for a direct message the sender sent earlier (`edit_message`)
+///
+/// The signature covers [`profile_shared::canonical::message_edit`] of
+/// `messageId`, the new text and the timestamp. The server forwards it to
+/// `recipientPublicKey` as a [`profile_shared::Message::Edit`], filling in
+/// the sender from the authenticated connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EditMessageRequest {
+    pub r#type: String,
+    #[serde(
+        rename = "recipientPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub recipient_public_key: String,
+    /// Id of the message being edited
+    #[serde(rename = "messageId")]
+    pub message_id: Uuid,
+    pub message: String,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+    pub timestamp: String,
+}
+
+/// Withdrawal of a direct message the sender sent earlier (`retract_message`)
+///
+/// The signature covers [`profile_shared::canonical::message_retract`] of
+/// `messageId` and the timestamp. The server forwards it to
+/// `recipientPublicKey` as a [`profile_shared::Message::Retract`], filling in
+/// the sender from the authenticated connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetractMessageRequest {
+    pub r#type: String,
+    #[serde(
+        rename = "recipientPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub recipient_public_key: String,
+    /// Id of the message being retracted
+    #[serde(rename = "messageId")]
+    pub message_id: Uuid,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+    pub timestamp: String,
+}
+
+/// Emoji reaction to a direct message, or its removal (`reaction`)
+///
+/// The signature covers [`profile_shared::canonical::message_reaction`] of
+/// `messageId`, the emoji, `removed` and the timestamp. The server forwards
+/// it to `recipientPublicKey` as a [`profile_shared::Message::Reaction`],
+/// filling in the sender from the authenticated connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionRequest {
+    pub r#type: String,
+    #[serde(
+        rename = "recipientPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub recipient_public_key: String,
+    /// Id of the message reacted to
+    #[serde(rename = "messageId")]
+    pub message_id: Uuid,
+    pub emoji: String,
+    #[serde(default)]
+    pub removed: bool,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
     pub signature: String,
+    pub timestamp: String,
 }
 
-/// Successful authentication response with full lobby state
+/// Offer of a file to another user (`attachment_offer`)
+///
+/// The server checks the manifest and forwards it to `recipientPublicKey`
+/// as a [`profile_shared::Message::AttachmentOffer`]. The accepts, chunks
+/// and completion that follow name only the transfer id; the server knows
+/// who is on either end.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuthSuccessMessage {
+pub struct AttachmentOfferRequest {
     pub r#type: String,
-    pub users: Vec<String>, // List of online users (hex-encoded public keys)
+    #[serde(
+        rename = "recipientPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub recipient_public_key: String,
+    pub manifest: AttachmentManifest,
 }
 
-/// Authentication error response
+/// The recipient of an offered file asks for its chunks below `until`
+/// (`attachment_accept`)
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AuthErrorMessage {
+pub struct AttachmentAcceptRequest {
     pub r#type: String,
-    pub reason: String,
-    pub details: String,
+    #[serde(rename = "transferId")]
+    pub transfer_id: Uuid,
+    pub until: u32,
 }
 
-/// General error message for other protocol errors
+/// One hex-encoded chunk of an accepted file (`attachment_chunk`)
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ErrorMessage {
+pub struct AttachmentChunkRequest {
     pub r#type: String,
-    pub reason: String,
-    pub details: Option<String>,
+    #[serde(rename = "transferId")]
+    pub transfer_id: Uuid,
+    pub index: u32,
+    pub data: String,
 }
 
-/// Client message request for sending a message to another user
+/// End of a transfer: every chunk sent, or given up by either side
+/// (`attachment_complete`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentCompleteRequest {
+    pub r#type: String,
+    #[serde(rename = "transferId")]
+    pub transfer_id: Uuid,
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+/// Hint that the sender opened or closed the conversation with a peer
+///
+/// The server forwards it to `recipientPublicKey` as a
+/// [`profile_shared::Message::Viewing`], filling in the viewer from the
+/// authenticated connection rather than trusting the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewingRequest {
+    pub r#type: String,
+    #[serde(
+        rename = "recipientPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub recipient_public_key: String,
+    pub viewing: bool,
+}
+
+/// Direct message encrypted in a ratchet session
 ///
-/// Sent by client to server after Story 3.1 (composer implementation)
+/// The server can't read the envelope; it forwards it to
+/// `recipientPublicKey` as a [`profile_shared::Message::Sealed`], filling
+/// in the sender from the authenticated connection.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SendMessageRequest {
+pub struct SealedRequest {
     pub r#type: String,
-    #[serde(rename = "recipientPublicKey")]
+    /// Unique id chosen by the sender; requests without one get a fresh id
+    #[serde(rename = "messageId", default = "Uuid::new_v4")]
+    pub message_id: Uuid,
+    #[serde(
+        rename = "recipientPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
     pub recipient_public_key: String,
+    pub envelope: RatchetEnvelope,
+}
+
+/// Announcement that the sender replaced their identity key
+/// (`key_rollover`)
+///
+/// The rollover must retire the key the connection signed in with. Once
+/// accepted it is broadcast as a [`profile_shared::Message::KeyRollover`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRolloverRequest {
+    pub r#type: String,
+    pub rollover: KeyRollover,
+}
+
+/// Room membership request (`room_create`, `room_join` or `room_leave`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomMembershipRequest {
+    pub r#type: String,
+    pub room: String,
+}
+
+/// Signed message posted to a room (`room_message`)
+///
+/// Signed over the same canonical encoding as direct messages (see
+/// [`profile_shared::canonical`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomMessageRequest {
+    pub r#type: String,
+    pub room: String,
     pub message: String,
-    #[serde(rename = "senderPublicKey")]
+    #[serde(
+        rename = "senderPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
     pub sender_public_key: String,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+    pub timestamp: String,
+    /// Canonical encoding the signature covers; absent means version 1
+    #[serde(default, skip_serializing_if = "CanonicalVersion::is_legacy")]
+    pub canonical: CanonicalVersion,
+}
+
+/// Signed request to set or clear the sender's nickname (`set_nickname`)
+///
+/// The signature covers [`profile_shared::canonical::nickname`], with an
+/// empty nickname when clearing, so a signed chat message can't be replayed
+/// as a nickname change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetNicknameRequest {
+    pub r#type: String,
+    /// The new nickname, None (or empty) to clear it
+    #[serde(default)]
+    pub nickname: Option<String>,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+    pub timestamp: String,
+}
+
+/// Signed request to change the sender's presence status (`set_status`)
+///
+/// The signature covers [`profile_shared::canonical::set_status`], so a
+/// signed nickname change or chat message can't be replayed as a status
+/// change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetStatusRequest {
+    pub r#type: String,
+    pub status: Status,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+    pub timestamp: String,
+}
+
+/// Signed request to claim or release a verified name (`claim_name`)
+///
+/// The signature covers [`profile_shared::canonical::claim_name`], with an
+/// empty name when releasing, so a signed nickname change can't be replayed
+/// as a claim.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimNameRequest {
+    pub r#type: String,
+    /// The name to claim, None (or empty) to release the current one
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+    pub timestamp: String,
+}
+
+/// Signed request to store the sender's encrypted backup (`backup_store`)
+///
+/// The signature covers [`profile_shared::canonical::backup_store`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupStoreRequest {
+    pub r#type: String,
+    /// Must be greater than the version of the stored backup
+    pub version: u64,
+    /// Hex-encoded blob, encrypted by the client
+    pub blob: String,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+    pub timestamp: String,
+}
+
+/// Request for the sender's stored backup (`backup_fetch`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupFetchRequest {
+    pub r#type: String,
+}
+
+/// Signed request replacing the sender's filter list (`set_filters`)
+///
+/// The signature covers [`profile_shared::canonical::filters`], with the
+/// entries in the order sent and an empty list to clear every filter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetFiltersRequest {
+    pub r#type: String,
+    #[serde(default)]
+    pub filters: Vec<SenderFilter>,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
     pub signature: String,
     pub timestamp: String,
 }
 
+/// Request for the messages held from queue-only senders (`held_fetch`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldFetchRequest {
+    pub r#type: String,
+}
+
+/// Request for one page of online users (`lobby_page`)
+///
+/// `cursor` is the `nextCursor` from the previous page; `prefix` restricts
+/// results to public keys starting with the given hex characters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LobbyPageRequest {
+    pub r#type: String,
+    #[serde(default)]
+    pub cursor: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+/// Request for when a user was last online (`query_last_seen`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryLastSeenRequest {
+    pub r#type: String,
+    #[serde(rename = "publicKey", deserialize_with = "lowercase_hex::deserialize")]
+    pub public_key: String,
+}
+
 /// Close frame reason codes
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CloseReason {
@@ -88,55 +412,10 @@ impl CloseReason {
     }
 }
 
-impl AuthMessage {
-    /// Create a new authentication message
-    pub fn new(public_key: String, signature: String) -> Self {
-        Self {
-            r#type: "auth".to_string(),
-            public_key,
-            signature,
-        }
-    }
-}
-
-impl AuthSuccessMessage {
-    /// Create a new authentication success message
-    pub fn new(users: Vec<String>) -> Self {
-        Self {
-            r#type: "auth_success".to_string(),
-            users,
-        }
-    }
-}
-
-impl AuthErrorMessage {
-    /// Create a new authentication error message
-    pub fn new(reason: String, details: String) -> Self {
-        Self {
-            r#type: "error".to_string(),
-            reason,
-            details,
-        }
-    }
-}
-
-impl ErrorMessage {
-    /// Create a new general error message
-    pub fn new(reason: String) -> Self {
-        Self {
-            r#type: "error".to_string(),
-            reason,
-            details: None,
-        }
-    }
-
-    /// Create a new error message with details
-    pub fn with_details(reason: String, details: String) -> Self {
-        Self {
-            r#type: "error".to_string(),
-            reason,
-            details: Some(details),
-        }
+impl From<LobbyPage> for AuthSuccessMessage {
+    /// Create an authentication success message from the first lobby page
+    fn from(page: LobbyPage) -> Self {
+        AuthSuccessMessage::page(page.users, page.total, page.next_cursor)
     }
 }
 
@@ -145,46 +424,99 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_auth_message_creation() {
-        let msg = AuthMessage::new("abc123".to_string(), "def456".to_string());
-        assert_eq!(msg.r#type, "auth");
-        assert_eq!(msg.public_key, "abc123");
-        assert_eq!(msg.signature, "def456");
+    fn test_auth_success_message_from_page() {
+        let msg = AuthSuccessMessage::from(LobbyPage {
+            users: vec!["user1".to_string()],
+            next_cursor: Some("user1".to_string()),
+            total: 3,
+        });
+        assert_eq!(msg.r#type, "auth_success");
+        assert_eq!(msg.users, vec!["user1".to_string()]);
+        assert_eq!(msg.total, 3);
+        assert_eq!(msg.next_cursor.as_deref(), Some("user1"));
     }
 
     #[test]
-    fn test_auth_success_message_creation() {
-        let users = vec!["user1".to_string(), "user2".to_string()];
-        let msg = AuthSuccessMessage::new(users.clone());
-        assert_eq!(msg.r#type, "auth_success");
-        assert_eq!(msg.users, users);
+    fn test_lobby_page_request_defaults() {
+        let request: LobbyPageRequest = serde_json::from_str(r#"{"type":"lobby_page"}"#).unwrap();
+        assert_eq!(request.r#type, "lobby_page");
+        assert_eq!(request.cursor, None);
+        assert_eq!(request.limit, None);
+        assert_eq!(request.prefix, None);
+
+        let request: LobbyPageRequest =
+            serde_json::from_str(r#"{"type":"lobby_page","cursor":"ab","limit":5,"prefix":"a"}"#)
+                .unwrap();
+        assert_eq!(request.cursor.as_deref(), Some("ab"));
+        assert_eq!(request.limit, Some(5));
+        assert_eq!(request.prefix.as_deref(), Some("a"));
     }
 
     #[test]
-    fn test_auth_error_message_creation() {
-        let msg = AuthErrorMessage::new("auth_failed".to_string(), "Invalid signature".to_string());
-        assert_eq!(msg.r#type, "error");
-        assert_eq!(msg.reason, "auth_failed");
-        assert_eq!(msg.details, "Invalid signature");
+    fn test_read_receipt_request_deserialization() {
+        let json = r#"{"type":"read","recipientPublicKey":"abc","messageId":"6f1c3a52-8d4e-4b7a-9c2d-1e5f3a7b9c0d","timestamp":"2025-12-20T10:00:00Z"}"#;
+        let request: ReadReceiptRequest = serde_json::from_str(json).unwrap();
+
+        assert_eq!(request.r#type, "read");
+        assert_eq!(request.recipient_public_key, "abc");
+        assert_eq!(
+            request.message_id.to_string(),
+            "6f1c3a52-8d4e-4b7a-9c2d-1e5f3a7b9c0d"
+        );
+        assert!(serde_json::from_str::<ReadReceiptRequest>(
+            &json.replace("6f1c3a52-8d4e-4b7a-9c2d-1e5f3a7b9c0d", "msg-1")
+        )
+        .is_err());
+        assert_eq!(request.timestamp, "2025-12-20T10:00:00Z");
+    }
+
+    #[test]
+    fn test_room_request_deserialization() {
+        let json = r#"{"type":"room_join","room":"general"}"#;
+        let request: RoomMembershipRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.r#type, "room_join");
+        assert_eq!(request.room, "general");
+
+        let json = r#"{"type":"room_message","room":"general","message":"hi","senderPublicKey":"abc","signature":"sig","timestamp":"2025-12-20T10:00:00Z"}"#;
+        let request: RoomMessageRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.room, "general");
+        assert_eq!(request.message, "hi");
+        assert_eq!(request.canonical, CanonicalVersion::V1);
     }
 
     #[test]
-    fn test_error_message_creation() {
-        let msg = ErrorMessage::new("connection_lost".to_string());
-        assert_eq!(msg.r#type, "error");
-        assert_eq!(msg.reason, "connection_lost");
+    fn test_canonical_version_parsed() {
+        let json = r#"{"type":"message","recipientPublicKey":"abcd","message":"a:b","senderPublicKey":"ef01","signature":"aa","timestamp":"2025-01-01T00:00:00Z","canonical":2}"#;
+        let request: SendMessageRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.canonical, CanonicalVersion::V2);
+
+        // Versions this server doesn't know are refused, not guessed at
+        assert!(serde_json::from_str::<SendMessageRequest>(
+            &json.replace(r#""canonical":2"#, r#""canonical":9"#)
+        )
+        .is_err());
     }
 
     #[test]
-    fn test_serde_serialization() {
-        // Test that messages can be serialized and deserialized
-        let original = AuthMessage::new("abc123".to_string(), "def456".to_string());
-        let serialized = serde_json::to_string(&original).unwrap();
-        let deserialized: AuthMessage = serde_json::from_str(&serialized).unwrap();
-
-        assert_eq!(original.r#type, deserialized.r#type);
-        assert_eq!(original.public_key, deserialized.public_key);
-        assert_eq!(original.signature, deserialized.signature);
+    fn test_send_message_request_borrows_from_json() {
+        let json = r#"{"type":"message","recipientPublicKey":"abcd","message":"hello","senderPublicKey":"ef01","signature":"aa","timestamp":"2025-01-01T00:00:00Z"}"#;
+        let request: SendMessageRequest = serde_json::from_str(json).unwrap();
+        for field in [
+            &request.recipient_public_key,
+            &request.message,
+            &request.sender_public_key,
+            &request.signature,
+            &request.timestamp,
+        ] {
+            assert!(matches!(field, Cow::Borrowed(_)), "{:?} was copied", field);
+        }
+
+        // Escaped text and upper-case hex can't be borrowed as-is
+        let json = r#"{"type":"message","recipientPublicKey":"ABCD","message":"say \"hi\"","senderPublicKey":"ef01","signature":"aa","timestamp":"2025-01-01T00:00:00Z"}"#;
+        let request: SendMessageRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.recipient_public_key, "abcd");
+        assert_eq!(request.message, "say \"hi\"");
+        assert!(matches!(request.sender_public_key, Cow::Borrowed(_)));
     }
 
     #[test]
@@ -0,0 +1,248 @@
+use crate::lobby::Lobby;
+use crate::protocol::RoomMessageRequest;
+use crate::rooms::state::Rooms;
+use profile_shared::{Message, RoomError};
+
+/// Create a room and notify the creator
+///
+/// # Arguments
+/// * `rooms` - The room registry
+/// * `lobby` - The lobby used to reach member connections
+/// * `name` - The room name
+/// * `creator` - Public key of the creating user
+#[tracing::instrument(skip(rooms, lobby), fields(creator = %creator.chars().take(16).collect::<String>()))]
+pub async fn create_room(
+    rooms: &Rooms,
+    lobby: &Lobby,
+    name: &str,
+    creator: &str,
+) -> Result<(), RoomError> {
+    let members = rooms.create_room(name, creator).await?;
+    broadcast_room_update(lobby, name, &members, None).await;
+    Ok(())
+}
+
+/// Add a user to a room and notify all members
+#[tracing::instrument(skip(rooms, lobby), fields(key = %key.chars().take(16).collect::<String>()))]
+pub async fn join_room(
+    rooms: &Rooms,
+    lobby: &Lobby,
+    name: &str,
+    key: &str,
+) -> Result<(), RoomError> {
+    let members = rooms.join_room(name, key).await?;
+    broadcast_room_update(lobby, name, &members, None).await;
+    Ok(())
+}
+
+/// Remove a user from a room and notify the remaining members
+///
+/// The leaving user also receives the update so their client can drop the
+/// room from its active conversations.
+#[tracing::instrument(skip(rooms, lobby), fields(key = %key.chars().take(16).collect::<String>()))]
+pub async fn leave_room(
+    rooms: &Rooms,
+    lobby: &Lobby,
+    name: &str,
+    key: &str,
+) -> Result<(), RoomError> {
+    let members = rooms.leave_room(name, key).await?;
+    broadcast_room_update(lobby, name, &members, Some(key)).await;
+    Ok(())
+}
+
+/// Fan out a validated room message to every member except the sender
+///
+/// # Returns
+/// Number of members the message was queued for
+#[tracing::instrument(skip(rooms, lobby, request), fields(room = %request.room, sender = %sender_public_key.chars().take(16).collect::<String>()))]
+pub async fn route_room_message(
+    rooms: &Rooms,
+    lobby: &Lobby,
+    sender_public_key: &str,
+    request: &RoomMessageRequest,
+) -> Result<usize, RoomError> {
+    let name = request.room.as_str();
+    let members = rooms.members(name).await?;
+    if !members.iter().any(|m| m == sender_public_key) {
+        return Err(RoomError::NotAMember);
+    }
+
+    let room_message = Message::new_room_message(
+        name.to_string(),
+        request.message.clone(),
+        sender_public_key.to_string(),
+        request.signature.clone(),
+        request.timestamp.clone(),
+        request.canonical,
+    );
+
+    let mut delivered = 0;
+    for key in members
+        .iter()
+        .filter(|key| key.as_str() != sender_public_key)
+    {
+        if let Some(conn) = lobby.users.get(key).await {
+            if conn.sender.send(room_message.clone()).is_ok() {
+                delivered += 1;
+            }
+        }
+    }
+
+    tracing::debug!(room = %name, delivered, "Room message routed");
+    Ok(delivered)
+}
+
+/// Remove a disconnecting user from all rooms and notify remaining members
+pub async fn remove_user_from_rooms(rooms: &Rooms, lobby: &Lobby, key: &str) {
+    for (name, members) in rooms.remove_member_everywhere(key).await {
+        broadcast_room_update(lobby, &name, &members, None).await;
+    }
+}
+
+/// Send the current member list of a room to its members
+///
+/// `also_notify` receives the update too, for users that just left.
+async fn broadcast_room_update(
+    lobby: &Lobby,
+    name: &str,
+    members: &[String],
+    also_notify: Option<&str>,
+) {
+    let update = Message::new_room_update(name.to_string(), members.to_vec());
+
+    for key in members.iter().map(String::as_str).chain(also_notify) {
+        if let Some(conn) = lobby.users.get(key).await {
+            // Ignore send failures - user may have disconnected during broadcast
+            let _ = conn.sender.send(update.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::send_queue::{send_queue, OutboundQueue};
+    use crate::lobby::ActiveConnection;
+    use profile_shared::canonical::CanonicalVersion;
+
+    const ALICE: &str = "aaaa1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+    const BOB: &str = "bbbb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+    const CAROL: &str = "cccc1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab";
+
+    async fn connect(lobby: &Lobby, key: &str, id: u64) -> OutboundQueue {
+        let (sender, receiver) = send_queue();
+        let conn = ActiveConnection {
+            public_key: key.to_string(),
+            sender,
+            connection_id: id,
+            status: Default::default(),
+        };
+        crate::lobby::add_user(lobby, key.to_string(), conn)
+            .await
+            .unwrap();
+        // Deliver the join broadcast so tests can drain it synchronously
+        lobby.flush_broadcasts().await.unwrap();
+        receiver
+    }
+
+    fn request(message: &str) -> RoomMessageRequest {
+        RoomMessageRequest {
+            r#type: "room_message".to_string(),
+            room: "general".to_string(),
+            message: message.to_string(),
+            sender_public_key: String::new(),
+            signature: "sig".to_string(),
+            timestamp: "2025-12-27T10:00:00Z".to_string(),
+            canonical: CanonicalVersion::CURRENT,
+        }
+    }
+
+    fn drain(receiver: &mut OutboundQueue) -> Vec<Message> {
+        let mut messages = Vec::new();
+        while let Ok(msg) = receiver.try_recv() {
+            messages.push(msg);
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_room_message_fans_out_to_members() {
+        let lobby = Lobby::new();
+        let rooms = Rooms::new();
+        let mut alice_rx = connect(&lobby, ALICE, 1).await;
+        let mut bob_rx = connect(&lobby, BOB, 2).await;
+        let mut carol_rx = connect(&lobby, CAROL, 3).await;
+
+        create_room(&rooms, &lobby, "general", ALICE).await.unwrap();
+        join_room(&rooms, &lobby, "general", BOB).await.unwrap();
+        drain(&mut alice_rx);
+        drain(&mut bob_rx);
+        drain(&mut carol_rx);
+
+        let delivered = route_room_message(&rooms, &lobby, ALICE, &request("hello room"))
+            .await
+            .unwrap();
+        assert_eq!(delivered, 1);
+
+        let bob_messages = drain(&mut bob_rx);
+        assert!(matches!(
+            bob_messages.as_slice(),
+            [Message::RoomMessage { room, message, .. }] if room == "general" && message == "hello room"
+        ));
+        assert!(drain(&mut alice_rx).is_empty());
+        assert!(drain(&mut carol_rx).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_post() {
+        let lobby = Lobby::new();
+        let rooms = Rooms::new();
+        let _alice_rx = connect(&lobby, ALICE, 1).await;
+        let _carol_rx = connect(&lobby, CAROL, 3).await;
+        create_room(&rooms, &lobby, "general", ALICE).await.unwrap();
+
+        let result = route_room_message(&rooms, &lobby, CAROL, &request("intruder")).await;
+        assert_eq!(result, Err(RoomError::NotAMember));
+    }
+
+    #[tokio::test]
+    async fn test_leave_notifies_leaver_and_members() {
+        let lobby = Lobby::new();
+        let rooms = Rooms::new();
+        let mut alice_rx = connect(&lobby, ALICE, 1).await;
+        let mut bob_rx = connect(&lobby, BOB, 2).await;
+        create_room(&rooms, &lobby, "general", ALICE).await.unwrap();
+        join_room(&rooms, &lobby, "general", BOB).await.unwrap();
+        drain(&mut alice_rx);
+        drain(&mut bob_rx);
+
+        leave_room(&rooms, &lobby, "general", BOB).await.unwrap();
+
+        for rx in [&mut alice_rx, &mut bob_rx] {
+            assert!(matches!(
+                drain(rx).as_slice(),
+                [Message::RoomUpdate { members, .. }] if members == &vec![ALICE.to_string()]
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_removes_user_from_rooms() {
+        let lobby = Lobby::new();
+        let rooms = Rooms::new();
+        let mut alice_rx = connect(&lobby, ALICE, 1).await;
+        let _bob_rx = connect(&lobby, BOB, 2).await;
+        create_room(&rooms, &lobby, "general", ALICE).await.unwrap();
+        join_room(&rooms, &lobby, "general", BOB).await.unwrap();
+        drain(&mut alice_rx);
+
+        remove_user_from_rooms(&rooms, &lobby, BOB).await;
+
+        assert!(!rooms.is_member("general", BOB).await);
+        assert!(matches!(
+            drain(&mut alice_rx).as_slice(),
+            [Message::RoomUpdate { members, .. }] if members == &vec![ALICE.to_string()]
+        ));
+    }
+}
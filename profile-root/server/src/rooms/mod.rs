@@ -0,0 +1,14 @@
+//! Named rooms for group conversations
+//!
+//! Users can create, join and leave named rooms. Messages posted to a room
+//! are fanned out to every member through their lobby connection, so only
+//! users that are currently online in the lobby receive room traffic.
+//!
+//! Follows the same Arc<RwLock<HashMap>> pattern as the lobby. Members are
+//! kept in a BTreeSet so membership snapshots are sent in a stable order.
+
+pub mod manager;
+pub mod state;
+
+pub use manager::{create_room, join_room, leave_room, remove_user_from_rooms, route_room_message};
+pub use state::{room_error_reason, validate_room_name, RoomName, Rooms};
@@ -0,0 +1,257 @@
+use crate::lobby::ServerPublicKey;
+use profile_shared::config::rooms::{MAX_ROOMS, MAX_ROOM_MEMBERS, MAX_ROOM_NAME_LENGTH};
+use profile_shared::RoomError;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Type alias for room names
+pub type RoomName = String;
+
+/// Thread-safe registry of rooms and their members
+#[derive(Debug, Clone)]
+pub struct Rooms {
+    pub rooms: Arc<RwLock<HashMap<RoomName, BTreeSet<ServerPublicKey>>>>,
+}
+
+/// Validate a room name
+///
+/// Names must be non-empty, at most MAX_ROOM_NAME_LENGTH characters, and
+/// contain only ASCII alphanumerics, '-' and '_'.
+pub fn validate_room_name(name: &str) -> Result<(), RoomError> {
+    if name.is_empty()
+        || name.chars().count() > MAX_ROOM_NAME_LENGTH
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(RoomError::InvalidRoomName);
+    }
+    Ok(())
+}
+
+/// Map a room error to the protocol error reason sent to clients
+pub fn room_error_reason(error: &RoomError) -> &'static str {
+    match error {
+        RoomError::RoomNotFound => "room_not_found",
+        RoomError::RoomAlreadyExists => "room_exists",
+        RoomError::NotAMember => "not_room_member",
+        RoomError::InvalidRoomName => "invalid_room_name",
+        RoomError::RoomFull => "room_full",
+        RoomError::TooManyRooms => "too_many_rooms",
+    }
+}
+
+impl Rooms {
+    /// Create an empty room registry
+    pub fn new() -> Self {
+        Self {
+            rooms: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Create a room with the creator as its first member
+    ///
+    /// Returns the member list of the new room.
+    pub async fn create_room(
+        &self,
+        name: &str,
+        creator: &str,
+    ) -> Result<Vec<ServerPublicKey>, RoomError> {
+        validate_room_name(name)?;
+        let mut rooms = self.rooms.write().await;
+        if rooms.contains_key(name) {
+            return Err(RoomError::RoomAlreadyExists);
+        }
+        if rooms.len() >= MAX_ROOMS {
+            return Err(RoomError::TooManyRooms);
+        }
+        let members = BTreeSet::from([creator.to_string()]);
+        let snapshot = members.iter().cloned().collect();
+        rooms.insert(name.to_string(), members);
+        Ok(snapshot)
+    }
+
+    /// Add a user to an existing room
+    ///
+    /// Joining a room twice is a no-op. Returns the updated member list.
+    pub async fn join_room(
+        &self,
+        name: &str,
+        key: &str,
+    ) -> Result<Vec<ServerPublicKey>, RoomError> {
+        let mut rooms = self.rooms.write().await;
+        let members = rooms.get_mut(name).ok_or(RoomError::RoomNotFound)?;
+        if !members.contains(key) && members.len() >= MAX_ROOM_MEMBERS {
+            return Err(RoomError::RoomFull);
+        }
+        members.insert(key.to_string());
+        Ok(members.iter().cloned().collect())
+    }
+
+    /// Remove a user from a room
+    ///
+    /// The room is deleted once its last member leaves. Returns the
+    /// remaining member list.
+    pub async fn leave_room(
+        &self,
+        name: &str,
+        key: &str,
+    ) -> Result<Vec<ServerPublicKey>, RoomError> {
+        let mut rooms = self.rooms.write().await;
+        let members = rooms.get_mut(name).ok_or(RoomError::RoomNotFound)?;
+        if !members.remove(key) {
+            return Err(RoomError::NotAMember);
+        }
+        let remaining: Vec<ServerPublicKey> = members.iter().cloned().collect();
+        if remaining.is_empty() {
+            rooms.remove(name);
+        }
+        Ok(remaining)
+    }
+
+    /// Get the members of a room
+    pub async fn members(&self, name: &str) -> Result<Vec<ServerPublicKey>, RoomError> {
+        let rooms = self.rooms.read().await;
+        rooms
+            .get(name)
+            .map(|members| members.iter().cloned().collect())
+            .ok_or(RoomError::RoomNotFound)
+    }
+
+    /// Check if a user is a member of a room
+    pub async fn is_member(&self, name: &str, key: &str) -> bool {
+        let rooms = self.rooms.read().await;
+        rooms
+            .get(name)
+            .map(|members| members.contains(key))
+            .unwrap_or(false)
+    }
+
+    /// Remove a user from every room they belong to
+    ///
+    /// Returns each affected room with its remaining members. Rooms left
+    /// empty are deleted and not included.
+    pub async fn remove_member_everywhere(
+        &self,
+        key: &str,
+    ) -> Vec<(RoomName, Vec<ServerPublicKey>)> {
+        let mut rooms = self.rooms.write().await;
+        let mut affected = Vec::new();
+        for (name, members) in rooms.iter_mut() {
+            if members.remove(key) && !members.is_empty() {
+                affected.push((name.clone(), members.iter().cloned().collect()));
+            }
+        }
+        rooms.retain(|_, members| !members.is_empty());
+        affected
+    }
+
+    /// Get number of rooms
+    pub async fn room_count(&self) -> usize {
+        self.rooms.read().await.len()
+    }
+}
+
+impl Default for Rooms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_room_name() {
+        assert!(validate_room_name("general").is_ok());
+        assert!(validate_room_name("team-a_2").is_ok());
+        assert_eq!(validate_room_name(""), Err(RoomError::InvalidRoomName));
+        assert_eq!(
+            validate_room_name("has space"),
+            Err(RoomError::InvalidRoomName)
+        );
+        let too_long = "a".repeat(MAX_ROOM_NAME_LENGTH + 1);
+        assert_eq!(
+            validate_room_name(&too_long),
+            Err(RoomError::InvalidRoomName)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_and_join_room() {
+        let rooms = Rooms::new();
+        let members = rooms.create_room("general", "alice").await.unwrap();
+        assert_eq!(members, vec!["alice".to_string()]);
+
+        assert_eq!(
+            rooms.create_room("general", "bob").await,
+            Err(RoomError::RoomAlreadyExists)
+        );
+
+        let members = rooms.join_room("general", "bob").await.unwrap();
+        assert_eq!(members, vec!["alice".to_string(), "bob".to_string()]);
+        assert!(rooms.is_member("general", "bob").await);
+
+        // Joining twice is a no-op
+        let members = rooms.join_room("general", "bob").await.unwrap();
+        assert_eq!(members.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_join_missing_room() {
+        let rooms = Rooms::new();
+        assert_eq!(
+            rooms.join_room("nowhere", "alice").await,
+            Err(RoomError::RoomNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_leave_room_deletes_empty_room() {
+        let rooms = Rooms::new();
+        rooms.create_room("general", "alice").await.unwrap();
+        rooms.join_room("general", "bob").await.unwrap();
+
+        assert_eq!(
+            rooms.leave_room("general", "carol").await,
+            Err(RoomError::NotAMember)
+        );
+
+        let remaining = rooms.leave_room("general", "alice").await.unwrap();
+        assert_eq!(remaining, vec!["bob".to_string()]);
+
+        let remaining = rooms.leave_room("general", "bob").await.unwrap();
+        assert!(remaining.is_empty());
+        assert_eq!(rooms.room_count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_room_full() {
+        let rooms = Rooms::new();
+        rooms.create_room("busy", "member-0").await.unwrap();
+        for i in 1..MAX_ROOM_MEMBERS {
+            rooms
+                .join_room("busy", &format!("member-{}", i))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            rooms.join_room("busy", "latecomer").await,
+            Err(RoomError::RoomFull)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_member_everywhere() {
+        let rooms = Rooms::new();
+        rooms.create_room("one", "alice").await.unwrap();
+        rooms.join_room("one", "bob").await.unwrap();
+        rooms.create_room("two", "alice").await.unwrap();
+
+        let affected = rooms.remove_member_everywhere("alice").await;
+        assert_eq!(affected, vec![("one".to_string(), vec!["bob".to_string()])]);
+        assert_eq!(rooms.room_count().await, 1);
+    }
+}
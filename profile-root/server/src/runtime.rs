@@ -0,0 +1,271 @@
+//! Runtime topology and concurrency limits
+//!
+//! Large deployments can tune how the server uses the machine through
+//! environment variables, each defaulting to a value derived from the CPU
+//! count:
+//! - `PROFILE_WORKER_THREADS`: async worker threads (one per CPU)
+//! - `PROFILE_MAX_BLOCKING_THREADS`: blocking pool size, shared by signature
+//!   verification and file I/O (`BLOCKING_THREADS_PER_CPU` per CPU, at
+//!   least `MIN_BLOCKING_THREADS`)
+//! - `PROFILE_VERIFICATION_WORKERS`: signature verifications running at
+//!   once (one per CPU, at most the blocking pool size)
+//! - `PROFILE_MAX_PENDING_VERIFICATIONS`: verifications queued or running
+//!   before messages are shed as `server_busy`
+//! - `PROFILE_MAX_CONNECTIONS`: open client connections; further
+//!   connections are refused until one closes
+//! - `PROFILE_ADMISSION_RATE`: connections let into the lobby per second,
+//!   so reconnections after a restart spread out (see [`crate::admission`])
+//! - `PROFILE_ADMISSION_QUEUE`: connections that may wait for admission
+//!   before further ones are refused with a retry hint
+
+use crate::admission::AdmissionQueue;
+use crate::load::VerificationQueue;
+use profile_shared::config;
+use std::fmt::{self, Display, Formatter};
+
+/// Environment variable setting the number of async worker threads
+pub const WORKER_THREADS_ENV_VAR: &str = "PROFILE_WORKER_THREADS";
+
+/// Environment variable setting the blocking thread pool size
+pub const MAX_BLOCKING_THREADS_ENV_VAR: &str = "PROFILE_MAX_BLOCKING_THREADS";
+
+/// Environment variable setting how many verifications run at once
+pub const VERIFICATION_WORKERS_ENV_VAR: &str = "PROFILE_VERIFICATION_WORKERS";
+
+/// Environment variable setting how many verifications may be pending
+pub const MAX_PENDING_VERIFICATIONS_ENV_VAR: &str = "PROFILE_MAX_PENDING_VERIFICATIONS";
+
+/// Environment variable setting the maximum open client connections
+pub const MAX_CONNECTIONS_ENV_VAR: &str = "PROFILE_MAX_CONNECTIONS";
+
+/// Environment variable setting how many connections join the lobby per second
+pub const ADMISSION_RATE_ENV_VAR: &str = "PROFILE_ADMISSION_RATE";
+
+/// Environment variable setting how many connections may wait to join
+pub const ADMISSION_QUEUE_ENV_VAR: &str = "PROFILE_ADMISSION_QUEUE";
+
+/// Number of CPUs available to the server (1 if unknown)
+pub fn cpu_count() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Error for an invalid runtime setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeConfigError {
+    /// The setting is not a positive whole number
+    InvalidNumber { name: &'static str, value: String },
+    /// More verification workers than blocking threads to run them on
+    TooManyVerificationWorkers { workers: usize, blocking: usize },
+}
+
+impl Display for RuntimeConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeConfigError::InvalidNumber { name, value } => {
+                write!(
+                    f,
+                    "Invalid {}: {:?} (expected a positive number)",
+                    name, value
+                )
+            }
+            RuntimeConfigError::TooManyVerificationWorkers { workers, blocking } => write!(
+                f,
+                "{} ({}) must not exceed {} ({})",
+                VERIFICATION_WORKERS_ENV_VAR, workers, MAX_BLOCKING_THREADS_ENV_VAR, blocking
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeConfigError {}
+
+/// Thread pool sizes and per-subsystem concurrency limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    pub worker_threads: usize,
+    pub max_blocking_threads: usize,
+    pub verification_workers: usize,
+    pub max_pending_verifications: usize,
+    pub max_connections: usize,
+    pub admission_rate: usize,
+    pub admission_queue: usize,
+}
+
+impl RuntimeConfig {
+    /// Defaults for a machine with `cpus` CPUs
+    pub fn for_cpus(cpus: usize) -> Self {
+        use config::runtime::{BLOCKING_THREADS_PER_CPU, MIN_BLOCKING_THREADS};
+        let cpus = cpus.max(1);
+        Self {
+            worker_threads: cpus,
+            max_blocking_threads: (cpus * BLOCKING_THREADS_PER_CPU).max(MIN_BLOCKING_THREADS),
+            verification_workers: cpus,
+            max_pending_verifications: config::load::MAX_PENDING_VERIFICATIONS,
+            max_connections: config::server::MAX_CONCURRENT_CONNECTIONS,
+            admission_rate: config::reconnect::DEFAULT_ADMISSION_RATE,
+            admission_queue: config::reconnect::DEFAULT_ADMISSION_QUEUE,
+        }
+    }
+
+    /// Read the configuration from the `PROFILE_*` variables listed in the
+    /// module docs
+    pub fn from_env() -> Result<Self, RuntimeConfigError> {
+        Self::parse(cpu_count(), |name| std::env::var(name).ok())
+    }
+
+    /// Build a configuration for `cpus` CPUs, overriding defaults with the
+    /// values `lookup` returns (None or empty for the default)
+    pub fn parse(
+        cpus: usize,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, RuntimeConfigError> {
+        let defaults = Self::for_cpus(cpus);
+        let setting = |name: &'static str, default: usize| {
+            let Some(value) = lookup(name).filter(|v| !v.trim().is_empty()) else {
+                return Ok(default);
+            };
+            match value.trim().parse::<usize>() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(RuntimeConfigError::InvalidNumber { name, value }),
+            }
+        };
+        let config = Self {
+            worker_threads: setting(WORKER_THREADS_ENV_VAR, defaults.worker_threads)?,
+            max_blocking_threads: setting(
+                MAX_BLOCKING_THREADS_ENV_VAR,
+                defaults.max_blocking_threads,
+            )?,
+            verification_workers: setting(
+                VERIFICATION_WORKERS_ENV_VAR,
+                defaults.verification_workers,
+            )?,
+            max_pending_verifications: setting(
+                MAX_PENDING_VERIFICATIONS_ENV_VAR,
+                defaults.max_pending_verifications,
+            )?,
+            max_connections: setting(MAX_CONNECTIONS_ENV_VAR, defaults.max_connections)?,
+            admission_rate: setting(ADMISSION_RATE_ENV_VAR, defaults.admission_rate)?,
+            admission_queue: setting(ADMISSION_QUEUE_ENV_VAR, defaults.admission_queue)?,
+        };
+        if config.verification_workers > config.max_blocking_threads {
+            return Err(RuntimeConfigError::TooManyVerificationWorkers {
+                workers: config.verification_workers,
+                blocking: config.max_blocking_threads,
+            });
+        }
+        Ok(config)
+    }
+
+    /// Build the multi-threaded tokio runtime the server runs on
+    pub fn build_runtime(&self) -> std::io::Result<tokio::runtime::Runtime> {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.worker_threads)
+            .max_blocking_threads(self.max_blocking_threads)
+            .thread_name("profile-worker")
+            .enable_all()
+            .build()
+    }
+
+    /// Verification queue sized by this configuration
+    pub fn verification_queue(&self) -> VerificationQueue {
+        VerificationQueue::with_capacity(
+            self.max_pending_verifications,
+            config::load::SERVER_BUSY_RETRY_AFTER,
+        )
+        .with_workers(self.verification_workers)
+    }
+
+    /// Admission queue pacing joins at this configuration's rate
+    pub fn admission_queue(&self) -> AdmissionQueue {
+        AdmissionQueue::paced(self.admission_rate, self.admission_queue)
+    }
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self::for_cpus(cpu_count())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(cpus: usize, vars: &[(&str, &str)]) -> Result<RuntimeConfig, RuntimeConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        RuntimeConfig::parse(cpus, |name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_defaults_scale_with_cpus() {
+        let small = parse(1, &[]).unwrap();
+        assert_eq!(small.worker_threads, 1);
+        assert_eq!(
+            small.max_blocking_threads,
+            config::runtime::MIN_BLOCKING_THREADS
+        );
+        assert_eq!(small.verification_workers, 1);
+
+        let large = parse(32, &[]).unwrap();
+        assert_eq!(large.worker_threads, 32);
+        assert_eq!(
+            large.max_blocking_threads,
+            32 * config::runtime::BLOCKING_THREADS_PER_CPU
+        );
+        assert_eq!(
+            large.max_connections,
+            config::server::MAX_CONCURRENT_CONNECTIONS
+        );
+    }
+
+    #[test]
+    fn test_overrides_and_invalid_values() {
+        let config = parse(
+            4,
+            &[
+                (WORKER_THREADS_ENV_VAR, " 2 "),
+                (MAX_CONNECTIONS_ENV_VAR, "50000"),
+                (MAX_PENDING_VERIFICATIONS_ENV_VAR, ""),
+                (ADMISSION_RATE_ENV_VAR, "50"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.worker_threads, 2);
+        assert_eq!(config.max_connections, 50_000);
+        assert_eq!(config.admission_rate, 50);
+        assert_eq!(
+            config.admission_queue,
+            config::reconnect::DEFAULT_ADMISSION_QUEUE
+        );
+        assert!(config.admission_queue().is_paced());
+        assert_eq!(
+            config.max_pending_verifications,
+            config::load::MAX_PENDING_VERIFICATIONS
+        );
+
+        assert_eq!(
+            parse(4, &[(WORKER_THREADS_ENV_VAR, "0")]),
+            Err(RuntimeConfigError::InvalidNumber {
+                name: WORKER_THREADS_ENV_VAR,
+                value: "0".to_string(),
+            })
+        );
+        assert_eq!(
+            parse(
+                4,
+                &[
+                    (VERIFICATION_WORKERS_ENV_VAR, "8"),
+                    (MAX_BLOCKING_THREADS_ENV_VAR, "4"),
+                ]
+            ),
+            Err(RuntimeConfigError::TooManyVerificationWorkers {
+                workers: 8,
+                blocking: 4,
+            })
+        );
+    }
+}
@@ -0,0 +1,537 @@
+//! State snapshots for warm standby failover
+//!
+//! Connections can't move between servers, but the state users leave
+//! behind can: bans, retired keys, claimed names, encrypted backups, and
+//! sender filters with the messages held under them. With
+//! `PROFILE_SNAPSHOT_FILE` set, the server writes all of it to that file
+//! every `PROFILE_SNAPSHOT_INTERVAL` seconds and once more on shutdown, and
+//! loads it at startup. A standby instance pointed at the same file (on
+//! shared storage) therefore takes over with at most one interval of
+//! changes lost; clients reconnect to it as they would after a restart.
+//!
+//! Snapshots go through a [`SnapshotStore`], so they can be kept somewhere
+//! other than a file, such as an object store, by implementing the trait.
+//! [`FileSnapshotStore`] replaces the file atomically, so a standby never
+//! reads a half-written snapshot.
+//!
+//! Names are only restored if the standby has a name registry, and a
+//! snapshot's bans and names are merged into those loaded from
+//! `PROFILE_BAN_FILE` and `PROFILE_NAME_FILE`.
+
+use crate::backup::StoredBackup;
+use crate::lobby::Lobby;
+use crate::moderation::Ban;
+use crate::names::NameRecord;
+use chrono::{DateTime, Utc};
+use profile_shared::config;
+use profile_shared::protocol::SenderFilter;
+use profile_shared::Message;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Environment variable naming the snapshot file; snapshots are off when unset
+pub const SNAPSHOT_FILE_ENV_VAR: &str = "PROFILE_SNAPSHOT_FILE";
+
+/// Environment variable setting the seconds between snapshots
+pub const SNAPSHOT_INTERVAL_ENV_VAR: &str = "PROFILE_SNAPSHOT_INTERVAL";
+
+/// Snapshot format written by this version of the server
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Error types for state snapshots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Reading or writing the snapshot failed
+    Io(String),
+    /// The snapshot is not valid JSON or holds invalid values
+    Parse(String),
+    /// The snapshot was written by a newer server
+    UnsupportedVersion(u32),
+    /// A setting is not a positive whole number
+    InvalidSetting { name: &'static str, value: String },
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(msg) => write!(f, "Failed to access snapshot: {}", msg),
+            SnapshotError::Parse(msg) => write!(f, "Failed to parse snapshot: {}", msg),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "Snapshot version {} is newer than supported version {}",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::InvalidSetting { name, value } => write!(
+                f,
+                "Invalid {}: {:?} (expected a positive number)",
+                name, value
+            ),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+/// One ban in a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BanEntry {
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    #[serde(rename = "bannedAt")]
+    pub banned_at: String,
+    #[serde(rename = "expiresAt", default)]
+    pub expires_at: Option<String>,
+}
+
+/// One retired key in a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetiredEntry {
+    #[serde(rename = "oldPublicKey")]
+    pub old_public_key: String,
+    #[serde(rename = "newPublicKey")]
+    pub new_public_key: String,
+}
+
+/// One stored backup in a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    #[serde(rename = "publicKey")]
+    pub public_key: String,
+    pub version: u64,
+    /// Hex-encoded encrypted bytes
+    pub blob: String,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: String,
+}
+
+/// One recipient's filters and held messages in a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilterEntry {
+    #[serde(rename = "recipientPublicKey")]
+    pub recipient_public_key: String,
+    pub filters: Vec<SenderFilter>,
+    #[serde(default)]
+    pub held: Vec<Message>,
+}
+
+/// Everything a standby needs to take over, apart from connections
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub version: u32,
+    /// When the snapshot was taken (RFC 3339)
+    #[serde(rename = "takenAt")]
+    pub taken_at: String,
+    #[serde(default)]
+    pub bans: Vec<BanEntry>,
+    #[serde(rename = "retiredKeys", default)]
+    pub retired_keys: Vec<RetiredEntry>,
+    #[serde(default)]
+    pub names: Vec<NameRecord>,
+    #[serde(default)]
+    pub backups: Vec<BackupEntry>,
+    #[serde(default)]
+    pub filters: Vec<FilterEntry>,
+}
+
+/// What [`restore`] put back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RestoreSummary {
+    pub bans: usize,
+    pub retired_keys: usize,
+    pub names: usize,
+    pub backups: usize,
+    pub filters: usize,
+}
+
+fn parse_time(text: &str) -> Result<DateTime<Utc>, SnapshotError> {
+    DateTime::parse_from_rfc3339(text)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| SnapshotError::Parse(format!("invalid time '{}': {}", text, e)))
+}
+
+/// Take a snapshot of the lobby's state
+pub async fn capture(lobby: &Lobby) -> StateSnapshot {
+    StateSnapshot {
+        version: SNAPSHOT_VERSION,
+        taken_at: Utc::now().to_rfc3339(),
+        bans: lobby
+            .moderation
+            .active_bans()
+            .await
+            .into_iter()
+            .map(|(public_key, ban)| BanEntry {
+                public_key,
+                banned_at: ban.banned_at.to_rfc3339(),
+                expires_at: ban.expires_at.map(|t| t.to_rfc3339()),
+            })
+            .collect(),
+        retired_keys: lobby
+            .retired
+            .entries()
+            .await
+            .into_iter()
+            .map(|(old_public_key, new_public_key)| RetiredEntry {
+                old_public_key,
+                new_public_key,
+            })
+            .collect(),
+        names: lobby.names.records().await,
+        backups: lobby
+            .backups
+            .entries()
+            .await
+            .into_iter()
+            .map(|(public_key, backup)| BackupEntry {
+                public_key,
+                version: backup.version,
+                blob: hex::encode(&backup.blob),
+                updated_at: backup.updated_at,
+            })
+            .collect(),
+        filters: lobby
+            .filters
+            .entries()
+            .await
+            .into_iter()
+            .map(|(recipient_public_key, filters, held)| FilterEntry {
+                recipient_public_key,
+                filters,
+                held,
+            })
+            .collect(),
+    }
+}
+
+/// Load a snapshot's state into the lobby
+///
+/// Meant for a lobby that has just been created: state already in the
+/// lobby is kept where it conflicts with the snapshot, except bans, which
+/// the snapshot's replace. The whole snapshot is checked before anything
+/// is loaded.
+pub async fn restore(
+    lobby: &Lobby,
+    snapshot: StateSnapshot,
+) -> Result<RestoreSummary, SnapshotError> {
+    if snapshot.version > SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+    }
+    let bans = snapshot
+        .bans
+        .into_iter()
+        .map(|entry| {
+            let ban = Ban {
+                banned_at: parse_time(&entry.banned_at)?,
+                expires_at: entry.expires_at.as_deref().map(parse_time).transpose()?,
+            };
+            Ok((entry.public_key, ban))
+        })
+        .collect::<Result<Vec<_>, SnapshotError>>()?;
+    let backups = snapshot
+        .backups
+        .into_iter()
+        .map(|entry| {
+            let blob = hex::decode(&entry.blob).map_err(|e| {
+                SnapshotError::Parse(format!("invalid backup of {}: {}", entry.public_key, e))
+            })?;
+            let backup = StoredBackup {
+                version: entry.version,
+                blob,
+                updated_at: entry.updated_at,
+            };
+            Ok((entry.public_key, backup))
+        })
+        .collect::<Result<Vec<_>, SnapshotError>>()?;
+
+    let mut summary = RestoreSummary {
+        bans: lobby
+            .moderation
+            .restore_bans(bans)
+            .await
+            .map_err(|e| SnapshotError::Io(e.to_string()))?,
+        retired_keys: snapshot.retired_keys.len(),
+        names: lobby
+            .names
+            .restore(snapshot.names)
+            .await
+            .map_err(|e| SnapshotError::Io(e.to_string()))?,
+        ..RestoreSummary::default()
+    };
+    lobby
+        .retired
+        .restore(
+            snapshot
+                .retired_keys
+                .into_iter()
+                .map(|entry| (entry.old_public_key, entry.new_public_key))
+                .collect(),
+        )
+        .await;
+    for (public_key, backup) in backups {
+        // A backup the lobby already holds at a newer version is kept
+        if lobby
+            .backups
+            .store(&public_key, backup.version, backup.blob, backup.updated_at)
+            .await
+            .is_ok()
+        {
+            summary.backups += 1;
+        }
+    }
+    for entry in snapshot.filters {
+        match lobby
+            .filters
+            .restore(&entry.recipient_public_key, &entry.filters, entry.held)
+            .await
+        {
+            Ok(()) => summary.filters += 1,
+            Err(e) => tracing::warn!(error = %e, "Skipping invalid filters in snapshot"),
+        }
+    }
+    Ok(summary)
+}
+
+/// Future returned by snapshot store operations
+pub type SnapshotFuture<'a, T> =
+    Pin<Box<dyn Future<Output = Result<T, SnapshotError>> + Send + 'a>>;
+
+/// Somewhere snapshots are kept between a server and its standby
+pub trait SnapshotStore: Send + Sync + fmt::Debug {
+    /// Replace the stored snapshot
+    fn save<'a>(&'a self, snapshot: &'a StateSnapshot) -> SnapshotFuture<'a, ()>;
+
+    /// The stored snapshot, None if none was saved yet
+    fn load(&self) -> SnapshotFuture<'_, Option<StateSnapshot>>;
+}
+
+/// Snapshots kept in a file, replaced atomically on each save
+#[derive(Debug, Clone)]
+pub struct FileSnapshotStore {
+    path: PathBuf,
+}
+
+impl FileSnapshotStore {
+    /// Keep snapshots at `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The snapshot file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl SnapshotStore for FileSnapshotStore {
+    fn save<'a>(&'a self, snapshot: &'a StateSnapshot) -> SnapshotFuture<'a, ()> {
+        Box::pin(async move {
+            let io = |e: std::io::Error| SnapshotError::Io(e.to_string());
+            if let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                std::fs::create_dir_all(dir).map_err(io)?;
+            }
+            let json =
+                serde_json::to_string(snapshot).map_err(|e| SnapshotError::Parse(e.to_string()))?;
+            let temp = self.path.with_extension("tmp");
+            std::fs::write(&temp, json).map_err(io)?;
+            std::fs::rename(&temp, &self.path).map_err(io)
+        })
+    }
+
+    fn load(&self) -> SnapshotFuture<'_, Option<StateSnapshot>> {
+        Box::pin(async move {
+            match std::fs::read_to_string(&self.path) {
+                Ok(text) => serde_json::from_str(&text)
+                    .map(Some)
+                    .map_err(|e| SnapshotError::Parse(e.to_string())),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(SnapshotError::Io(e.to_string())),
+            }
+        })
+    }
+}
+
+/// Where snapshots go and how often
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotConfig {
+    pub path: PathBuf,
+    pub interval: Duration,
+}
+
+impl SnapshotConfig {
+    /// Read `PROFILE_SNAPSHOT_FILE` and `PROFILE_SNAPSHOT_INTERVAL`
+    ///
+    /// # Returns
+    /// Ok(None) if no snapshot file is configured
+    pub fn from_env() -> Result<Option<Self>, SnapshotError> {
+        let Some(path) = std::env::var_os(SNAPSHOT_FILE_ENV_VAR).filter(|p| !p.is_empty()) else {
+            return Ok(None);
+        };
+        let interval = match std::env::var(SNAPSHOT_INTERVAL_ENV_VAR) {
+            Ok(value) if !value.trim().is_empty() => value
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs)
+                .ok_or(SnapshotError::InvalidSetting {
+                    name: SNAPSHOT_INTERVAL_ENV_VAR,
+                    value,
+                })?,
+            _ => config::server::DEFAULT_SNAPSHOT_INTERVAL,
+        };
+        Ok(Some(Self {
+            path: PathBuf::from(path),
+            interval,
+        }))
+    }
+}
+
+/// Snapshot the lobby's state and save it to `store`
+pub async fn save_snapshot(lobby: &Lobby, store: &dyn SnapshotStore) -> Result<(), SnapshotError> {
+    store.save(&capture(lobby).await).await
+}
+
+/// Save a snapshot to `store` every `interval` until the runtime shuts down
+///
+/// The first snapshot is taken after one interval, so a standby starting
+/// up doesn't overwrite the snapshot it is about to load.
+pub fn spawn_snapshot_task(
+    lobby: Arc<Lobby>,
+    store: Arc<dyn SnapshotStore>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            if let Err(e) = save_snapshot(&lobby, store.as_ref()).await {
+                tracing::warn!(error = %e, "Failed to save state snapshot");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::names::NameRegistry;
+    use profile_shared::protocol::FilterAction;
+
+    fn key(c: char) -> String {
+        c.to_string().repeat(64)
+    }
+
+    #[tokio::test]
+    async fn test_standby_takes_over_state() {
+        let primary = Lobby::new().with_names(NameRegistry::in_memory());
+        primary
+            .moderation
+            .ban(&key('a'), None, Utc::now())
+            .await
+            .unwrap();
+        // A chain of rollovers, restored whatever the order
+        assert!(primary.retired.retire(&key('b'), &key('c')).await);
+        assert!(primary.retired.retire(&key('c'), &key('d')).await);
+        primary
+            .names
+            .claim(&key('d'), "dana", Utc::now())
+            .await
+            .unwrap();
+        primary
+            .backups
+            .store(&key('d'), 3, vec![1, 2, 3], Utc::now().to_rfc3339())
+            .await
+            .unwrap();
+        let filters = vec![SenderFilter {
+            sender_public_key: key('e'),
+            action: FilterAction::QueueOnly,
+        }];
+        primary.filters.set(&key('d'), &filters).await.unwrap();
+        let held = Message::new_text(
+            uuid::Uuid::new_v4(),
+            "while you were away".to_string(),
+            key('e'),
+            "00".to_string(),
+            "2025-01-01T00:00:00Z".to_string(),
+            profile_shared::canonical::CanonicalVersion::CURRENT,
+        );
+        assert!(primary
+            .filters
+            .screen(&key('d'), held.clone())
+            .await
+            .is_none());
+
+        let dir = std::env::temp_dir().join(format!("profile-snapshot-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FileSnapshotStore::new(dir.join("state.json"));
+        assert_eq!(store.load().await.unwrap(), None);
+        save_snapshot(&primary, &store).await.unwrap();
+
+        let mut snapshot = store.load().await.unwrap().unwrap();
+        snapshot.retired_keys.reverse();
+        let standby = Lobby::new().with_names(NameRegistry::in_memory());
+        let summary = restore(&standby, snapshot).await.unwrap();
+        assert_eq!(
+            summary,
+            RestoreSummary {
+                bans: 1,
+                retired_keys: 2,
+                names: 1,
+                backups: 1,
+                filters: 1,
+            }
+        );
+        assert!(standby.moderation.is_banned(&key('a')).await);
+        assert_eq!(
+            standby.retired.entries().await,
+            primary.retired.entries().await
+        );
+        assert_eq!(standby.names.get(&key('d')).await.as_deref(), Some("dana"));
+        assert_eq!(
+            standby.backups.get(&key('d')).await.unwrap().blob,
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            standby.filters.action(&key('d'), &key('e')).await,
+            Some(FilterAction::QueueOnly)
+        );
+        assert_eq!(standby.filters.take_held(&key('d')).await, vec![held]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_refuses_newer_or_invalid_snapshots() {
+        let lobby = Lobby::new();
+        let mut snapshot = capture(&lobby).await;
+        snapshot.version = SNAPSHOT_VERSION + 1;
+        assert_eq!(
+            restore(&lobby, snapshot.clone()).await,
+            Err(SnapshotError::UnsupportedVersion(SNAPSHOT_VERSION + 1))
+        );
+
+        snapshot.version = SNAPSHOT_VERSION;
+        snapshot.bans.push(BanEntry {
+            public_key: key('a'),
+            banned_at: Utc::now().to_rfc3339(),
+            expires_at: None,
+        });
+        snapshot.backups.push(BackupEntry {
+            public_key: key('b'),
+            version: 1,
+            blob: "not hex".to_string(),
+            updated_at: Utc::now().to_rfc3339(),
+        });
+        assert!(matches!(
+            restore(&lobby, snapshot).await,
+            Err(SnapshotError::Parse(_))
+        ));
+        // Nothing was loaded from the rejected snapshot
+        assert!(!lobby.moderation.is_banned(&key('a')).await);
+    }
+}
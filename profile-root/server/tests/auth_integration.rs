@@ -182,26 +182,25 @@ fn test_case_insensitive_hex_decoding() {
 // Story 1.6: Disconnection handling integration tests
 
 use std::sync::Arc;
-
 #[tokio::test]
 async fn test_lobby_removes_user_on_disconnect() {
     // Test that the lobby properly removes users when they disconnect (AC4)
 
+    use profile_server::connection::send_queue::send_queue;
     use profile_server::lobby::{ActiveConnection, Lobby};
-    use profile_shared::Message;
-    use tokio::sync::mpsc;
 
     let lobby = Arc::new(Lobby::new());
     let test_key = "1234567890abcdef1234567890abcdef".to_string(); // 32 char hex string
 
     // Create sender channel for the connection
-    let (sender, _) = mpsc::unbounded_channel::<Message>();
+    let (sender, _) = send_queue();
 
     // Add user to lobby
     let connection = ActiveConnection {
         public_key: test_key.clone(),
         sender,
         connection_id: 0,
+        status: Default::default(),
     };
     lobby.add_user(connection).await.unwrap();
     assert_eq!(lobby.user_count().await.unwrap(), 1);
@@ -218,21 +217,21 @@ async fn test_lobby_removes_user_on_disconnect() {
 async fn test_server_handles_unexpected_disconnect() {
     // Test that server properly cleans up lobby on unexpected disconnects
 
+    use profile_server::connection::send_queue::send_queue;
     use profile_server::lobby::{ActiveConnection, Lobby};
-    use profile_shared::Message;
-    use tokio::sync::mpsc;
 
     let lobby = Arc::new(Lobby::new());
     let test_key = "abcdef1234567890abcdef1234567890".to_string();
 
     // Create sender channel for the connection
-    let (sender, _) = mpsc::unbounded_channel::<Message>();
+    let (sender, _) = send_queue();
 
     // Add user
     let connection = ActiveConnection {
         public_key: test_key.clone(),
         sender,
         connection_id: 0,
+        status: Default::default(),
     };
     lobby.add_user(connection).await.unwrap();
 
@@ -248,21 +247,21 @@ async fn test_server_handles_unexpected_disconnect() {
 async fn test_server_handles_client_close_frame() {
     // Test that server properly handles client-initiated close frames
 
+    use profile_server::connection::send_queue::send_queue;
     use profile_server::lobby::{ActiveConnection, Lobby};
-    use profile_shared::Message;
-    use tokio::sync::mpsc;
 
     let lobby = Arc::new(Lobby::new());
     let test_key = "deadbeef12345678deadbeef12345678".to_string();
 
     // Create sender channel for the connection
-    let (sender, _) = mpsc::unbounded_channel::<Message>();
+    let (sender, _) = send_queue();
 
     // Add user
     let connection = ActiveConnection {
         public_key: test_key.clone(),
         sender,
         connection_id: 0,
+        status: Default::default(),
     };
     lobby.add_user(connection).await.unwrap();
 
@@ -0,0 +1,267 @@
+//! Server behaviour under injected network faults
+//!
+//! Runs a real server with a `ChaosConfig` (delays, dropped frames,
+//! mid-frame disconnects, slow writes) and checks that the lobby and message
+//! routing stay consistent: nobody is left in the lobby after their socket
+//! dies, and routed messages arrive once and in order. Seeds are fixed so a
+//! failure can be reproduced.
+
+use futures_util::{SinkExt, StreamExt};
+use profile_server::connection::chaos::ChaosConfig;
+use profile_server::connection::handler::handle_connection;
+use profile_server::connection::send_queue::send_queue;
+use profile_server::lobby::{add_user, ActiveConnection, Lobby};
+use profile_server::rate_limiter::AuthRateLimiter;
+use profile_server::rooms::Rooms;
+use profile_shared::canonical::{self, CanonicalVersion};
+use profile_shared::{derive_public_key, generate_private_key, sign_message, PrivateKey};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// Start a server with fault injection on an ephemeral port
+///
+/// # Returns
+/// The server's WebSocket URL and its lobby
+async fn start_server(chaos: ChaosConfig) -> (String, Arc<Lobby>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let lobby = Arc::new(Lobby::new());
+    let rooms = Arc::new(Rooms::new());
+    let rate_limiter = Arc::new(AuthRateLimiter::new());
+    let chaos = Arc::new(chaos);
+
+    let server_lobby = Arc::clone(&lobby);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_connection(
+                stream,
+                Arc::clone(&server_lobby),
+                Arc::clone(&rooms),
+                Arc::clone(&rate_limiter),
+                None,
+                Some(Arc::clone(&chaos)),
+            ));
+        }
+    });
+    (format!("ws://{}", addr), lobby)
+}
+
+fn new_identity() -> (PrivateKey, String) {
+    let private_key = generate_private_key().unwrap();
+    let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_slice());
+    (private_key, public_key)
+}
+
+fn auth_frame(private_key: &PrivateKey, public_key: &str) -> WsMessage {
+    let signature = hex::encode(sign_message(private_key, b"auth").unwrap());
+    WsMessage::Text(
+        serde_json::json!({
+            "type": "auth",
+            "publicKey": public_key,
+            "signature": signature,
+        })
+        .to_string(),
+    )
+}
+
+fn message_frame(
+    private_key: &PrivateKey,
+    sender: &str,
+    recipient: &str,
+    message: &str,
+) -> WsMessage {
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let canonical = canonical::message(CanonicalVersion::CURRENT, message, &timestamp);
+    let signature = hex::encode(sign_message(private_key, &canonical).unwrap());
+    WsMessage::Text(
+        serde_json::json!({
+            "type": "message",
+            "messageId": uuid::Uuid::new_v4(),
+            "recipientPublicKey": recipient,
+            "message": message,
+            "senderPublicKey": sender,
+            "signature": signature,
+            "timestamp": timestamp,
+            "canonical": CanonicalVersion::CURRENT,
+        })
+        .to_string(),
+    )
+}
+
+/// Poll until the lobby is empty, returning the last count seen
+async fn wait_for_empty_lobby(lobby: &Lobby, timeout: Duration) -> usize {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let count = lobby.user_count().await.unwrap();
+        if count == 0 || tokio::time::Instant::now() >= deadline {
+            return count;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// Clients joining and leaving through every kind of fault must not leave
+/// ghost entries in the lobby
+#[tokio::test]
+async fn test_lobby_has_no_ghost_users_under_chaos() {
+    let (url, lobby) = start_server(ChaosConfig {
+        delay: Some((Duration::ZERO, Duration::from_millis(20))),
+        drop_rate: 0.2,
+        disconnect_rate: 0.05,
+        slow_write: Some(Duration::from_millis(1)),
+        seed: 42,
+    })
+    .await;
+
+    let clients: Vec<_> = (0..24)
+        .map(|i| {
+            let url = url.clone();
+            tokio::spawn(async move {
+                let (private_key, public_key) = new_identity();
+                let (mut ws, _) = tokio_tungstenite::connect_async(url.as_str()).await.ok()?;
+                ws.send(auth_frame(&private_key, &public_key)).await.ok()?;
+
+                // The auth frame or its reply may be dropped; give up quietly
+                let authenticated = tokio::time::timeout(Duration::from_secs(2), ws.next())
+                    .await
+                    .is_ok_and(|frame| matches!(frame, Some(Ok(WsMessage::Text(_)))));
+
+                for _ in 0..3 {
+                    let page = WsMessage::Text(r#"{"type":"lobby_page"}"#.to_string());
+                    if ws.send(page).await.is_err() {
+                        break;
+                    }
+                }
+                // Half the clients close cleanly, the rest just vanish
+                if i % 2 == 0 {
+                    let _ = ws.close(None).await;
+                }
+                Some(authenticated)
+            })
+        })
+        .collect();
+
+    let mut authenticated = 0;
+    for client in clients {
+        if client.await.unwrap() == Some(true) {
+            authenticated += 1;
+        }
+    }
+    assert!(authenticated > 0, "no client got through the faults");
+
+    let remaining = wait_for_empty_lobby(&lobby, Duration::from_secs(10)).await;
+    assert_eq!(
+        remaining, 0,
+        "users left in lobby after every socket closed"
+    );
+}
+
+/// Messages sent over delayed, slowly written sockets reach the recipient
+/// exactly once and in the order each sender sent them
+#[tokio::test]
+async fn test_messages_routed_once_in_order_under_delays() {
+    const SENDERS: usize = 4;
+    const MESSAGES_PER_SENDER: usize = 5;
+
+    let (url, lobby) = start_server(ChaosConfig {
+        delay: Some((Duration::ZERO, Duration::from_millis(15))),
+        slow_write: Some(Duration::from_millis(1)),
+        seed: 7,
+        ..Default::default()
+    })
+    .await;
+
+    // The recipient lives directly in the lobby so the test can see what
+    // the router hands it
+    let (_, recipient) = new_identity();
+    let (sender, mut inbox) = send_queue();
+    add_user(
+        &lobby,
+        recipient.clone(),
+        ActiveConnection {
+            public_key: recipient.clone(),
+            sender,
+            connection_id: u64::MAX,
+            status: Default::default(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let senders: Vec<_> = (0..SENDERS)
+        .map(|_| {
+            let url = url.clone();
+            let recipient = recipient.clone();
+            tokio::spawn(async move {
+                let (private_key, public_key) = new_identity();
+                let (mut ws, _) = tokio_tungstenite::connect_async(url.as_str())
+                    .await
+                    .unwrap();
+                ws.send(auth_frame(&private_key, &public_key))
+                    .await
+                    .unwrap();
+                let reply = tokio::time::timeout(Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("auth reply timed out");
+                assert!(matches!(reply, Some(Ok(WsMessage::Text(_)))));
+
+                for n in 0..MESSAGES_PER_SENDER {
+                    let frame =
+                        message_frame(&private_key, &public_key, &recipient, &n.to_string());
+                    ws.send(frame).await.unwrap();
+                }
+                (public_key, ws)
+            })
+        })
+        .collect();
+
+    // Keep the sockets open until everything has been delivered
+    let mut sockets = Vec::new();
+    for sender in senders {
+        sockets.push(sender.await.unwrap());
+    }
+
+    // Lobby updates about the senders arrive alongside their messages
+    let mut received: HashMap<String, Vec<String>> = HashMap::new();
+    let mut delivered = 0;
+    while delivered < SENDERS * MESSAGES_PER_SENDER {
+        let message = tokio::time::timeout(Duration::from_secs(5), inbox.recv())
+            .await
+            .expect("message not delivered")
+            .unwrap();
+        if let profile_shared::Message::Text {
+            message,
+            sender_public_key,
+            ..
+        } = message
+        {
+            received.entry(sender_public_key).or_default().push(message);
+            delivered += 1;
+        }
+    }
+
+    let expected: Vec<String> = (0..MESSAGES_PER_SENDER).map(|n| n.to_string()).collect();
+    for (public_key, _) in &sockets {
+        assert_eq!(received.get(public_key), Some(&expected));
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    while let Ok(message) = inbox.try_recv() {
+        assert!(
+            !matches!(message, profile_shared::Message::Text { .. }),
+            "message delivered twice"
+        );
+    }
+
+    for (_, mut ws) in sockets {
+        let _ = ws.close(None).await;
+    }
+    // Only the in-process recipient should remain
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    while lobby.user_count().await.unwrap() > 1 && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(lobby.user_count().await.unwrap(), 1);
+}
@@ -12,12 +12,13 @@
 //! This file satisfies Story 2.1 requirement for E2E multi-client testing.
 
 use profile_server::lobby::{add_user, get_current_users, remove_user, ActiveConnection, Lobby};
+use profile_shared::canonical::CanonicalVersion;
 use profile_shared::{LobbyError, Message as SharedMessage};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
 
 mod test_utils;
+use profile_server::connection::send_queue::send_queue;
 use test_utils::create_test_connection;
 
 /// Generate a unique 64-char hex public key for testing based on index
@@ -53,11 +54,12 @@ async fn test_e2e_multiple_clients_lobby_consistency() {
 
         let handle = tokio::spawn(async move {
             // Create a connection for this client
-            let (sender, _) = mpsc::unbounded_channel::<SharedMessage>();
+            let (sender, _) = send_queue();
             let connection = ActiveConnection {
                 public_key: key_clone.clone(),
                 sender,
                 connection_id: (i + 1) as u64,
+                status: Default::default(),
             };
 
             // Client "authenticates" by adding themselves to lobby
@@ -336,8 +338,8 @@ async fn test_e2e_message_routing_sender_correct() {
     let lobby = Arc::new(Lobby::new());
 
     // Create channels for message routing
-    let (sender1, mut receiver1) = mpsc::unbounded_channel::<SharedMessage>();
-    let (sender2, _receiver2) = mpsc::unbounded_channel::<SharedMessage>();
+    let (sender1, mut receiver1) = send_queue();
+    let (sender2, _receiver2) = send_queue();
 
     let key1 = generate_test_key(30);
     let key2 = generate_test_key(31);
@@ -347,12 +349,14 @@ async fn test_e2e_message_routing_sender_correct() {
         public_key: key1.clone(),
         sender: sender1,
         connection_id: 1,
+        status: Default::default(),
     };
 
     let conn2 = ActiveConnection {
         public_key: key2.clone(),
         sender: sender2,
         connection_id: 2,
+        status: Default::default(),
     };
 
     // Add both to lobby
@@ -373,13 +377,16 @@ async fn test_e2e_message_routing_sender_correct() {
 
     // Send a message to user 1 via their returned sender
     let test_message = SharedMessage::new_text(
+        uuid::Uuid::new_v4(),
         "Hello User 1".to_string(),
         key2.clone(),
         "test_signature".to_string(),
         "2025-12-23T10:00:00Z".to_string(),
+        CanonicalVersion::CURRENT,
     );
 
     // Drain any broadcast messages that might be in the queue first
+    lobby.flush_broadcasts().await.unwrap();
     let _ = tokio::time::timeout(Duration::from_millis(10), receiver1.recv()).await;
 
     if let Some(conn) = user1_conn {
@@ -418,8 +425,8 @@ async fn test_e2e_complete_message_routing_path() {
     let lobby = Arc::new(Lobby::new());
 
     // Create sender/receiver pairs for two clients
-    let (sender_a, mut receiver_a) = mpsc::unbounded_channel::<SharedMessage>();
-    let (sender_b, mut receiver_b) = mpsc::unbounded_channel::<SharedMessage>();
+    let (sender_a, mut receiver_a) = send_queue();
+    let (sender_b, mut receiver_b) = send_queue();
 
     let key_a = generate_test_key(40);
     let key_b = generate_test_key(41);
@@ -429,11 +436,13 @@ async fn test_e2e_complete_message_routing_path() {
         public_key: key_a.clone(),
         sender: sender_a,
         connection_id: 1,
+        status: Default::default(),
     };
     let conn_b = ActiveConnection {
         public_key: key_b.clone(),
         sender: sender_b,
         connection_id: 2,
+        status: Default::default(),
     };
 
     // Both clients join lobby
@@ -463,10 +472,12 @@ async fn test_e2e_complete_message_routing_path() {
 
     // Step 4: Send message through recipient's sender (simulating server routing)
     let routing_message = SharedMessage::new_text(
+        uuid::Uuid::new_v4(),
         "Hello from A!".to_string(),
         key_a.clone(),
         "sig_abc123".to_string(),
         "2025-12-23T12:00:00Z".to_string(),
+        CanonicalVersion::CURRENT,
     );
 
     // Send through B's sender (as server would do for routing)
@@ -492,6 +503,7 @@ async fn test_e2e_complete_message_routing_path() {
 
     // Also verify A's receiver is empty (message was routed to B, not broadcast to A)
     // Drain any potential broadcast messages
+    lobby.flush_broadcasts().await.unwrap();
     let _ = tokio::time::timeout(Duration::from_millis(10), receiver_a.recv()).await;
 
     // Verify A did NOT receive the message (it was routed specifically to B)
@@ -6,12 +6,10 @@
 use profile_server::lobby::{ActiveConnection, Lobby};
 use profile_shared::Message as SharedMessage;
 
-use tokio::sync::mpsc;
+use profile_server::connection::send_queue::{send_queue, OutboundQueue};
 
-fn create_test_connection_with_sender(
-    key: &str,
-) -> (ActiveConnection, mpsc::UnboundedReceiver<SharedMessage>) {
-    let (sender, receiver) = mpsc::unbounded_channel::<SharedMessage>();
+fn create_test_connection_with_sender(key: &str) -> (ActiveConnection, OutboundQueue) {
+    let (sender, receiver) = send_queue();
 
     // Ensure key is exactly 64 characters (32 bytes hex-encoded) for validation
     let padded_key = if key.len() >= 64 {
@@ -35,6 +33,7 @@ fn create_test_connection_with_sender(
         public_key: padded_key,
         sender,
         connection_id: 1,
+        status: Default::default(),
     };
 
     (connection, receiver)
@@ -158,7 +157,8 @@ async fn test_leaving_user_excluded_from_broadcast() {
 
 #[tokio::test]
 async fn test_multiple_leaves_consistency() {
-    let lobby = Lobby::new();
+    // A long update window so both leaves land in the same update
+    let lobby = Lobby::new().with_update_window(std::time::Duration::from_secs(60));
 
     // Create 4 users
     let (conn1, mut rx1) = create_test_connection_with_sender("user1");
@@ -186,14 +186,9 @@ async fn test_multiple_leaves_consistency() {
         .unwrap();
 
     // Drain initial join messages for all remaining users
-    // User1 receives joins for User2, User3, User4
-    for _ in 0..3 {
-        let _ = tokio::time::timeout(std::time::Duration::from_millis(100), rx1.recv()).await;
-    }
-    // User4 receives joins for User2, User3
-    for _ in 0..2 {
-        let _ = tokio::time::timeout(std::time::Duration::from_millis(100), rx4.recv()).await;
-    }
+    lobby.flush_broadcasts().await.unwrap();
+    while rx1.try_recv().is_ok() {}
+    while rx4.try_recv().is_ok() {}
 
     // User2 and User3 disconnect simultaneously
     profile_server::lobby::remove_user(&lobby, &key2)
@@ -203,67 +198,44 @@ async fn test_multiple_leaves_consistency() {
         .await
         .unwrap();
 
-    // Assert: User1 received two separate leave notifications (one per user)
-    let msg1a = tokio::time::timeout(std::time::Duration::from_millis(100), rx1.recv())
-        .await
-        .expect("Timeout - should have received first leave notification")
-        .expect("Should have received first leave notification");
-
-    let msg1b = tokio::time::timeout(std::time::Duration::from_millis(100), rx1.recv())
-        .await
-        .expect("Timeout - should have received second leave notification")
-        .expect("Should have received second leave notification");
-
-    // Verify each message has ONE left user (per-departure notification)
-    match &msg1a {
-        SharedMessage::LobbyUpdate { left, .. } => {
-            assert_eq!(left.len(), 1, "Expected 1 user in first leave notification");
-        }
-        _ => panic!("Expected LobbyUpdate"),
-    }
+    // Assert: User1 received one update naming both departures, in order
+    lobby.flush_broadcasts().await.unwrap();
+    let msg1 = rx1
+        .try_recv()
+        .expect("Should have received leave notification");
+    assert!(
+        rx1.try_recv().is_err(),
+        "Leaves should be sent as one update"
+    );
 
-    match &msg1b {
-        SharedMessage::LobbyUpdate { left, .. } => {
-            assert_eq!(
-                left.len(),
-                1,
-                "Expected 1 user in second leave notification"
-            );
+    match msg1 {
+        SharedMessage::LobbyUpdate { joined, left } => {
+            assert!(joined.is_empty());
+            assert_eq!(left, vec![key2.clone(), key3.clone()]);
         }
         _ => panic!("Expected LobbyUpdate"),
     }
 
-    // Track which users left
-    let mut left_users = Vec::new();
-
-    for msg in [msg1a, msg1b] {
-        match msg {
-            SharedMessage::LobbyUpdate { left, .. } => {
-                for u in left {
-                    left_users.push(u);
-                }
-            }
-            _ => panic!("Expected LobbyUpdate"),
-        }
-    }
-
-    // Assert: Both user2 and user3 left
-    assert_eq!(left_users.len(), 2, "Expected 2 users to leave total");
-    assert!(left_users.contains(&key2), "Expected user2 to have left");
-    assert!(left_users.contains(&key3), "Expected user3 to have left");
-
-    // Assert: User4 also received notifications (verify consistency)
-    let msg4a = tokio::time::timeout(std::time::Duration::from_millis(100), rx4.recv())
-        .await
-        .expect("Timeout - User4 should have received notification")
+    // Assert: User4 received the same update (verify consistency)
+    let msg4 = rx4
+        .try_recv()
         .expect("User4 should have received notification");
 
-    match msg4a {
+    match msg4 {
         SharedMessage::LobbyUpdate { left, .. } => {
-            assert!(left.iter().any(|u| u == &key2) || left.iter().any(|u| u == &key3));
+            assert_eq!(left, vec![key2, key3]);
         }
         _ => panic!("Expected LobbyUpdate"),
     }
+    assert_eq!(
+        profile_server::lobby::get_current_users(&lobby)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(lobby.users.contains_key(&key1).await);
+    assert!(lobby.users.contains_key(&key4).await);
 
     println!("✅ Multiple leaves handled correctly - lobby state consistent");
 }
@@ -397,6 +369,7 @@ async fn test_broadcast_user_left_unit_behavior() {
         .unwrap();
 
     // Drain all join broadcasts
+    lobby.flush_broadcasts().await.unwrap();
     for _ in 0..6 {
         let _ = tokio::time::timeout(std::time::Duration::from_millis(10), rx1.recv()).await;
         let _ = tokio::time::timeout(std::time::Duration::from_millis(10), rx2.recv()).await;
@@ -11,13 +11,13 @@
 
 mod test_utils;
 
+use profile_server::connection::send_queue::send_queue;
 use profile_server::lobby::{
     add_user, get_current_users, get_user, remove_user, ActiveConnection, Lobby,
 };
 use profile_shared::Message;
 use std::sync::Arc;
 use test_utils::create_test_connection;
-use tokio::sync::mpsc;
 
 /// Test 1: test_lobby_adds_user_on_auth
 /// Verify successful auth adds user to lobby (AC1)
@@ -198,7 +198,7 @@ async fn test_lobby_broadcast_on_join() {
     let lobby = Arc::new(Lobby::new());
 
     // Create a dedicated channel for the existing user to receive broadcasts
-    let (broadcast_sender, mut broadcast_receiver) = mpsc::unbounded_channel::<Message>();
+    let (broadcast_sender, mut broadcast_receiver) = send_queue();
 
     // Create a connection for the existing user that uses our broadcast receiver
     // Use valid 64-char hex key
@@ -206,6 +206,7 @@ async fn test_lobby_broadcast_on_join() {
         public_key: "aabb1234567890abcdef1234567890abcdef1234567890abcdef1234567890ab".to_string(),
         sender: broadcast_sender,
         connection_id: 999,
+        status: Default::default(),
     };
 
     // Add existing user to lobby first (so they receive the broadcast when new user joins)
@@ -253,7 +254,7 @@ async fn test_lobby_broadcast_on_leave() {
     let lobby = Arc::new(Lobby::new());
 
     // Create a test message receiver to capture broadcast messages
-    let (test_sender, _test_receiver) = mpsc::unbounded_channel::<Message>();
+    let (test_sender, _test_receiver) = send_queue();
 
     // Create a mock connection that uses our test receiver
     // Use valid 64-char hex key
@@ -261,6 +262,7 @@ async fn test_lobby_broadcast_on_leave() {
         public_key: "eeff1234567890abcdef1234567890abcdef1234567890abcdef1234567890ef".to_string(),
         sender: test_sender,
         connection_id: 999,
+        status: Default::default(),
     };
 
     // Add user who will remain (using manager's add_user for consistency)
@@ -0,0 +1,153 @@
+//! Lobby load tests
+//!
+//! Exercises the sharded lobby with 10,000 concurrent connections:
+//! - Concurrent inserts and removals from many tasks are counted exactly once
+//! - Routing lookups run concurrently with joins
+//! - A join broadcast from the broadcast task reaches every connected user
+//!
+//! The lobby is filled directly through `Lobby::add_user` so the tests don't
+//! pay for 10,000 x 10,000 join notifications. Elapsed times are printed
+//! (run with `--nocapture`) but not asserted, so the tests stay reliable on
+//! slow machines.
+
+use profile_server::connection::send_queue::{send_queue, OutboundQueue};
+use profile_server::lobby::manager::{add_user, get_user, remove_user};
+use profile_server::lobby::state::{ActiveConnection, Lobby};
+use profile_shared::Message as SharedMessage;
+use std::time::Instant;
+
+const CONNECTIONS: usize = 10_000;
+
+fn key_for(index: usize) -> String {
+    format!("{:064x}", index)
+}
+
+/// Insert `count` users concurrently, returning their receivers
+async fn fill_lobby(lobby: &Lobby, count: usize) -> Vec<OutboundQueue> {
+    let mut handles = Vec::with_capacity(count);
+    for index in 0..count {
+        let lobby = lobby.clone();
+        handles.push(tokio::spawn(async move {
+            let (sender, receiver) = send_queue();
+            let conn = ActiveConnection {
+                public_key: key_for(index),
+                sender,
+                connection_id: index as u64,
+                status: Default::default(),
+            };
+            lobby.add_user(conn).await.unwrap();
+            receiver
+        }));
+    }
+
+    let mut receivers = Vec::with_capacity(count);
+    for handle in handles {
+        receivers.push(handle.await.unwrap());
+    }
+    receivers
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_ten_thousand_concurrent_joins_and_lookups() {
+    let lobby = Lobby::new();
+
+    let lookups = {
+        let lobby = lobby.clone();
+        tokio::spawn(async move {
+            let mut found = 0;
+            for index in (0..CONNECTIONS).rev() {
+                if get_user(&lobby, &key_for(index)).await.unwrap().is_some() {
+                    found += 1;
+                }
+            }
+            found
+        })
+    };
+
+    let started = Instant::now();
+    let _receivers = fill_lobby(&lobby, CONNECTIONS).await;
+    println!(
+        "{} concurrent joins took {:?}",
+        CONNECTIONS,
+        started.elapsed()
+    );
+    let found = lookups.await.unwrap();
+    assert!(found <= CONNECTIONS);
+
+    assert_eq!(lobby.user_count().await.unwrap(), CONNECTIONS);
+    assert_eq!(
+        lobby.get_full_lobby_state().await.unwrap().len(),
+        CONNECTIONS
+    );
+    assert!(get_user(&lobby, &key_for(CONNECTIONS - 1))
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_join_broadcast_reaches_ten_thousand_users() {
+    let lobby = Lobby::new();
+    // The newcomer takes the lobby to exactly MAX_LOBBY_SIZE
+    let mut receivers = fill_lobby(&lobby, CONNECTIONS - 1).await;
+
+    let newcomer = "f".repeat(64);
+    let (sender, _newcomer_rx) = send_queue();
+    let conn = ActiveConnection {
+        public_key: newcomer.clone(),
+        sender,
+        connection_id: u64::MAX,
+        status: Default::default(),
+    };
+
+    let started = Instant::now();
+    add_user(&lobby, newcomer.clone(), conn).await.unwrap();
+    let enqueued = started.elapsed();
+    lobby.flush_broadcasts().await.unwrap();
+    println!(
+        "join queued in {:?}, delivered to {} users in {:?}",
+        enqueued,
+        receivers.len(),
+        started.elapsed()
+    );
+
+    for receiver in receivers.iter_mut() {
+        match receiver.try_recv() {
+            Ok(SharedMessage::LobbyUpdate { joined, left }) => {
+                assert_eq!(joined.len(), 1);
+                assert_eq!(joined[0].public_key, newcomer);
+                assert!(left.is_empty());
+            }
+            other => panic!("Expected join broadcast, got {:?}", other),
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_ten_thousand_concurrent_removals() {
+    let lobby = Lobby::new();
+    let _receivers = fill_lobby(&lobby, CONNECTIONS).await;
+
+    let started = Instant::now();
+    let mut handles = Vec::with_capacity(CONNECTIONS);
+    for index in 0..CONNECTIONS {
+        let lobby = lobby.clone();
+        handles.push(tokio::spawn(async move {
+            lobby.remove_user(&key_for(index)).await.unwrap();
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    println!(
+        "{} concurrent removals took {:?}",
+        CONNECTIONS,
+        started.elapsed()
+    );
+
+    assert_eq!(lobby.user_count().await.unwrap(), 0);
+    assert!(lobby.get_full_lobby_state().await.unwrap().is_empty());
+
+    // Removing an absent user is still a no-op
+    remove_user(&lobby, &key_for(0)).await.unwrap();
+}
@@ -7,9 +7,9 @@
 //! - Selection-aware broadcasts (AC5)
 
 use profile_shared::Message as SharedMessage;
-use tokio::sync::mpsc;
 use tokio::time::{timeout, Duration};
 
+use profile_server::connection::send_queue::send_queue;
 use profile_server::lobby::manager::{add_user, get_current_users, get_user, remove_user};
 use profile_server::lobby::state::{ActiveConnection, Lobby};
 
@@ -22,7 +22,7 @@ fn create_test_lobby() -> Lobby {
 fn create_test_connection(key: &str) -> ActiveConnection {
     static CONNECTION_COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
 
-    let (sender, _) = mpsc::unbounded_channel::<SharedMessage>();
+    let (sender, _) = send_queue();
 
     // Generate a valid 64-char hex key
     let padded_key = generate_valid_key(key);
@@ -33,6 +33,7 @@ fn create_test_connection(key: &str) -> ActiveConnection {
         public_key: padded_key,
         sender,
         connection_id,
+        status: Default::default(),
     }
 }
 
@@ -62,13 +63,14 @@ async fn test_broadcast_delivery_within_100ms() {
     let lobby = create_test_lobby();
 
     // Create a test receiver
-    let (test_sender, mut test_receiver) = mpsc::unbounded_channel::<SharedMessage>();
+    let (test_sender, mut test_receiver) = send_queue();
 
     // Create a mock connection
     let mock_connection = ActiveConnection {
         public_key: generate_valid_key("existing_user"),
         sender: test_sender,
         connection_id: 999,
+        status: Default::default(),
     };
 
     // Add existing user to lobby
@@ -124,25 +126,28 @@ async fn test_multi_client_lobby_consistency() {
     let lobby = create_test_lobby();
 
     // Create receivers for 3 clients
-    let (sender1, mut receiver1) = mpsc::unbounded_channel::<SharedMessage>();
-    let (sender2, mut receiver2) = mpsc::unbounded_channel::<SharedMessage>();
-    let (sender3, mut receiver3) = mpsc::unbounded_channel::<SharedMessage>();
+    let (sender1, mut receiver1) = send_queue();
+    let (sender2, mut receiver2) = send_queue();
+    let (sender3, mut receiver3) = send_queue();
 
     // Create connections with distinct keys
     let conn1 = ActiveConnection {
         public_key: generate_valid_key("client_1"),
         sender: sender1,
         connection_id: 1,
+        status: Default::default(),
     };
     let conn2 = ActiveConnection {
         public_key: generate_valid_key("client_2"),
         sender: sender2,
         connection_id: 2,
+        status: Default::default(),
     };
     let conn3 = ActiveConnection {
         public_key: generate_valid_key("client_3"),
         sender: sender3,
         connection_id: 3,
+        status: Default::default(),
     };
 
     let key1 = conn1.public_key.clone();
@@ -155,16 +160,18 @@ async fn test_multi_client_lobby_consistency() {
     add_user(&lobby, key3.clone(), conn3).await.unwrap();
 
     // Drain all broadcasts
+    lobby.flush_broadcasts().await.unwrap();
     let _ = timeout(Duration::from_millis(10), receiver1.recv()).await;
     let _ = timeout(Duration::from_millis(10), receiver2.recv()).await;
     let _ = timeout(Duration::from_millis(10), receiver3.recv()).await;
 
     // Add a 4th client
-    let (sender4, mut receiver4) = mpsc::unbounded_channel::<SharedMessage>();
+    let (sender4, mut receiver4) = send_queue();
     let conn4 = ActiveConnection {
         public_key: generate_valid_key("client_4"),
         sender: sender4,
         connection_id: 4,
+        status: Default::default(),
     };
     let key4 = conn4.public_key.clone();
 
@@ -242,11 +249,12 @@ async fn test_reconnection_preserves_lobby_consistency() {
     let lobby = create_test_lobby();
 
     // Create a receiver for another client to observe broadcasts
-    let (observer_sender, mut observer_receiver) = mpsc::unbounded_channel::<SharedMessage>();
+    let (observer_sender, mut observer_receiver) = send_queue();
     let observer = ActiveConnection {
         public_key: generate_valid_key("observer"),
         sender: observer_sender,
         connection_id: 99,
+        status: Default::default(),
     };
     add_user(&lobby, observer.public_key.clone(), observer)
         .await
@@ -256,16 +264,18 @@ async fn test_reconnection_preserves_lobby_consistency() {
     let _ = timeout(Duration::from_millis(10), observer_receiver.recv()).await;
 
     // User connects
-    let (user_sender, _) = mpsc::unbounded_channel::<SharedMessage>();
+    let (user_sender, _) = send_queue();
     let user_conn = ActiveConnection {
         public_key: generate_valid_key("reconnecting_user"),
         sender: user_sender,
         connection_id: 1,
+        status: Default::default(),
     };
     let user_key = user_conn.public_key.clone();
     add_user(&lobby, user_key.clone(), user_conn).await.unwrap();
 
     // Drain join broadcast
+    lobby.flush_broadcasts().await.unwrap();
     let _ = timeout(Duration::from_millis(10), observer_receiver.recv()).await;
 
     // User disconnects
@@ -287,11 +297,12 @@ async fn test_reconnection_preserves_lobby_consistency() {
     }
 
     // User reconnects with new connection
-    let (user_sender2, _) = mpsc::unbounded_channel::<SharedMessage>();
+    let (user_sender2, _) = send_queue();
     let user_conn2 = ActiveConnection {
         public_key: generate_valid_key("reconnecting_user"),
         sender: user_sender2,
         connection_id: 2,
+        status: Default::default(),
     };
     let user_key2 = user_conn2.public_key.clone();
     assert_eq!(user_key, user_key2); // Same public key
@@ -329,11 +340,12 @@ async fn test_network_resilience_rapid_changes() {
     let lobby = create_test_lobby();
 
     // Observer to track broadcasts
-    let (observer_sender, mut observer_receiver) = mpsc::unbounded_channel::<SharedMessage>();
+    let (observer_sender, mut observer_receiver) = send_queue();
     let observer = ActiveConnection {
         public_key: generate_valid_key("observer"),
         sender: observer_sender,
         connection_id: 99,
+        status: Default::default(),
     };
     add_user(&lobby, observer.public_key.clone(), observer)
         .await
@@ -342,11 +354,12 @@ async fn test_network_resilience_rapid_changes() {
 
     // Rapid connect/disconnect cycles
     for i in 0..10 {
-        let (sender, _) = mpsc::unbounded_channel::<SharedMessage>();
+        let (sender, _) = send_queue();
         let temp_conn = ActiveConnection {
             public_key: generate_valid_key(&format!("temp_user_{}", i)),
             sender,
             connection_id: 1000 + i,
+            status: Default::default(),
         };
         let temp_key = temp_conn.public_key.clone();
 
@@ -358,22 +371,27 @@ async fn test_network_resilience_rapid_changes() {
     let users = get_current_users(&lobby).await.unwrap();
     assert_eq!(users.len(), 1, "Only observer should remain in lobby");
 
-    // Verify observer received broadcasts for all joins and leaves
-    let mut join_count = 0;
+    // Verify observer was told about every leave and ends up with an empty
+    // view; joins followed by a leave in the same update window are dropped
+    lobby.flush_broadcasts().await.unwrap();
     let mut leave_count = 0;
+    let mut view = std::collections::HashSet::new();
 
-    while let Ok(Some(msg)) = timeout(Duration::from_millis(50), observer_receiver.recv()).await {
+    while let Ok(msg) = observer_receiver.try_recv() {
         if let SharedMessage::LobbyUpdate { joined, left } = msg {
-            join_count += joined.len();
             leave_count += left.len();
+            for key in &left {
+                view.remove(key);
+            }
+            view.extend(joined.into_iter().map(|u| u.public_key));
         }
     }
 
-    assert_eq!(join_count, 10, "Observer should receive 10 join broadcasts");
     assert_eq!(
         leave_count, 10,
         "Observer should receive 10 leave broadcasts"
     );
+    assert!(view.is_empty(), "Observer should see no temporary users");
 }
 
 /// Test AC4: Lobby state query after network issues
@@ -426,11 +444,12 @@ async fn test_lobby_state_accuracy_after_network_issues() {
 async fn test_broadcast_excludes_sender() {
     let lobby = create_test_lobby();
 
-    let (sender, mut receiver) = mpsc::unbounded_channel::<SharedMessage>();
+    let (sender, mut receiver) = send_queue();
     let conn = ActiveConnection {
         public_key: generate_valid_key("new_user"),
         sender,
         connection_id: 1,
+        status: Default::default(),
     };
     let key = conn.public_key.clone();
 
@@ -457,28 +476,31 @@ async fn test_leave_broadcast_excludes_leaving_user() {
     let lobby = create_test_lobby();
 
     // Add a user who will leave
-    let (sender, _receiver) = mpsc::unbounded_channel::<SharedMessage>();
+    let (sender, _receiver) = send_queue();
     let conn = ActiveConnection {
         public_key: generate_valid_key("leaving_user"),
         sender,
         connection_id: 1,
+        status: Default::default(),
     };
     let key = conn.public_key.clone();
     add_user(&lobby, key.clone(), conn).await.unwrap();
 
     // Add an observer to receive the leave broadcast
-    let (observer_sender, mut observer_receiver) = mpsc::unbounded_channel::<SharedMessage>();
+    let (observer_sender, mut observer_receiver) = send_queue();
     let observer_key = generate_valid_key("observer");
     let observer = ActiveConnection {
         public_key: observer_key.clone(),
         sender: observer_sender,
         connection_id: 2,
+        status: Default::default(),
     };
     add_user(&lobby, observer_key.clone(), observer)
         .await
         .unwrap();
 
     // Drain any broadcasts
+    lobby.flush_broadcasts().await.unwrap();
     let _ = timeout(Duration::from_millis(10), observer_receiver.recv()).await;
 
     // Remove the leaving user
@@ -0,0 +1,402 @@
+//! Property tests for the server's request parsers
+//!
+//! Feeds connection sessions random frames and checks the server never
+//! panics on them, whatever they contain. Each case starts from a
+//! well-formed request of a random type and breaks it: fields are replaced
+//! with random JSON (unicode, huge strings, extreme numbers, nesting),
+//! dropped, added or renamed, the type is swapped, the encoded bytes are
+//! corrupted, or the frame is buried in thousands of nested arrays. Every case goes to a session waiting for auth, one signed in
+//! on JSON and one signed in on MessagePack, so the auth, resume, request
+//! and binary frame parsers all see it.
+//!
+//! Like the protocol fuzz tests in `profile_shared::protocol::fuzz`, this
+//! runs a few hundred cases by default; `PROFILE_FUZZ_ITERATIONS` runs more
+//! and `PROFILE_FUZZ_SEED` picks the first seed. A failure prints its seed.
+
+use profile_server::connection::session::ConnectionSession;
+use profile_server::lobby::Lobby;
+use profile_server::rate_limiter::AuthRateLimiter;
+use profile_server::rooms::Rooms;
+use profile_shared::protocol::encoding::Encoding;
+use profile_shared::{derive_public_key, generate_private_key, sign_message};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+/// Cases when `PROFILE_FUZZ_ITERATIONS` is unset
+const DEFAULT_ITERATIONS: u64 = 300;
+
+/// Longest random string, in characters
+const HUGE_TEXT_CHARS: usize = 64 * 1024;
+
+/// How deep frames are nested when they are, well past what the parsers
+/// accept (128)
+const NESTING: std::ops::Range<usize> = 200..5000;
+
+/// MessagePack header of a one-element array
+const MSGPACK_ARRAY_OF_ONE: u8 = 0x91;
+
+/// Every request type a client can send
+const REQUEST_TYPES: [&str; 18] = [
+    "auth",
+    "resume",
+    "logout",
+    "message",
+    "read",
+    "viewing",
+    "lobby_page",
+    "set_nickname",
+    "claim_name",
+    "room_create",
+    "room_join",
+    "room_leave",
+    "room_message",
+    "backup_store",
+    "backup_fetch",
+    "set_filters",
+    "held_fetch",
+    "cover",
+];
+
+/// Field names the request parsers look for
+const FIELD_NAMES: [&str; 24] = [
+    "type",
+    "publicKey",
+    "signature",
+    "encodings",
+    "compression",
+    "ephemeral",
+    "resumeToken",
+    "messageId",
+    "recipientPublicKey",
+    "senderPublicKey",
+    "message",
+    "timestamp",
+    "sequence",
+    "viewing",
+    "cursor",
+    "limit",
+    "prefix",
+    "nickname",
+    "name",
+    "room",
+    "version",
+    "blob",
+    "filters",
+    "action",
+];
+
+/// Characters JSON must escape, of every UTF-8 length, and invisible or
+/// direction-changing ones
+const AWKWARD_CHARS: [char; 14] = [
+    '"',
+    '\\',
+    '\0',
+    '\n',
+    '\u{7f}',
+    'é',
+    '€',
+    '中',
+    '\u{200b}',
+    '\u{202e}',
+    '\u{301}',
+    '\u{fffd}',
+    '😀',
+    '\u{10ffff}',
+];
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+/// Prints the seed of the case that was running if the test panics
+struct SeedReporter(u64);
+
+impl Drop for SeedReporter {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!(
+                "failed at seed {} (replay with PROFILE_FUZZ_SEED={0} PROFILE_FUZZ_ITERATIONS=1)",
+                self.0
+            );
+        }
+    }
+}
+
+fn hex_key(rng: &mut StdRng) -> String {
+    let mut bytes = [0u8; 32];
+    rng.fill(&mut bytes[..]);
+    hex::encode(bytes)
+}
+
+fn random_string(rng: &mut StdRng) -> String {
+    match rng.gen_range(0..7) {
+        0 => String::new(),
+        1 => hex_key(rng),
+        2 => chrono::Utc::now().to_rfc3339(),
+        3 => uuid::Uuid::from_u128(rng.gen()).to_string(),
+        4 => AWKWARD_CHARS
+            .choose(rng)
+            .unwrap()
+            .to_string()
+            .repeat(rng.gen_range(1..=HUGE_TEXT_CHARS)),
+        5 => (0..rng.gen_range(1..64))
+            .map(|_| *AWKWARD_CHARS.choose(rng).unwrap())
+            .collect(),
+        _ => (0..rng.gen_range(1..32))
+            .map(|_| rng.gen_range(' '..='~'))
+            .collect(),
+    }
+}
+
+fn random_number(rng: &mut StdRng) -> Value {
+    match rng.gen_range(0..4) {
+        0 => json!(*[0, -1, i64::MIN, i64::MAX].choose(rng).unwrap()),
+        1 => json!(u64::MAX),
+        2 => json!(*[0.5, -0.0, 1e308, f64::EPSILON].choose(rng).unwrap()),
+        _ => json!(rng.gen::<i64>()),
+    }
+}
+
+/// Any JSON value, nested at most `depth` deep
+fn random_value(rng: &mut StdRng, depth: usize) -> Value {
+    let kinds = if depth == 0 { 4 } else { 6 };
+    match rng.gen_range(0..kinds) {
+        0 => Value::Null,
+        1 => Value::Bool(rng.gen()),
+        2 => random_number(rng),
+        3 => Value::String(random_string(rng)),
+        4 => (0..rng.gen_range(0..4))
+            .map(|_| random_value(rng, depth - 1))
+            .collect(),
+        _ => Value::Object(random_fields(rng, depth - 1)),
+    }
+}
+
+fn random_fields(rng: &mut StdRng, depth: usize) -> Map<String, Value> {
+    (0..rng.gen_range(0..4))
+        .map(|_| {
+            let name = if rng.gen() {
+                FIELD_NAMES.choose(rng).unwrap().to_string()
+            } else {
+                random_string(rng)
+            };
+            (name, random_value(rng, depth))
+        })
+        .collect()
+}
+
+/// A request of type `request_type` that the parser would accept
+fn well_formed_request(rng: &mut StdRng, request_type: &str, sender: &str) -> Value {
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let signature = hex::encode([rng.gen::<u8>(); 64]);
+    let peer = hex_key(rng);
+    let mut request = match request_type {
+        "auth" => json!({
+            "publicKey": sender,
+            "signature": signature,
+            "encodings": ["msgpack", "json"],
+            "compression": ["deflate"],
+        }),
+        "resume" => json!({ "resumeToken": hex_key(rng), "encodings": ["json"] }),
+        "message" => json!({
+            "messageId": uuid::Uuid::from_u128(rng.gen()),
+            "recipientPublicKey": peer,
+            "message": random_string(rng),
+            "senderPublicKey": sender,
+            "signature": signature,
+            "timestamp": timestamp,
+            "sequence": rng.gen::<u64>(),
+        }),
+        "read" => json!({
+            "recipientPublicKey": peer,
+            "messageId": uuid::Uuid::from_u128(rng.gen()),
+            "timestamp": timestamp,
+        }),
+        "viewing" => json!({ "recipientPublicKey": peer, "viewing": rng.gen::<bool>() }),
+        "lobby_page" => json!({ "cursor": peer, "limit": rng.gen::<u8>(), "prefix": "ab" }),
+        "set_nickname" => json!({
+            "nickname": random_string(rng),
+            "signature": signature,
+            "timestamp": timestamp,
+        }),
+        "claim_name" => json!({
+            "name": random_string(rng),
+            "signature": signature,
+            "timestamp": timestamp,
+        }),
+        "room_create" | "room_join" | "room_leave" => json!({ "room": random_string(rng) }),
+        "room_message" => json!({
+            "room": random_string(rng),
+            "message": random_string(rng),
+            "senderPublicKey": sender,
+            "signature": signature,
+            "timestamp": timestamp,
+        }),
+        "backup_store" => json!({
+            "version": rng.gen::<u64>(),
+            "blob": random_string(rng),
+            "signature": signature,
+            "timestamp": timestamp,
+        }),
+        "set_filters" => json!({
+            "filters": [{ "senderPublicKey": peer, "action": "queue_only" }],
+            "signature": signature,
+            "timestamp": timestamp,
+        }),
+        _ => json!({}),
+    };
+    request["type"] = json!(request_type);
+    request
+}
+
+/// Break a request in one random way
+fn mutate_request(rng: &mut StdRng, request: &mut Value) {
+    let Some(fields) = request.as_object_mut() else {
+        *request = random_value(rng, 3);
+        return;
+    };
+    let names: Vec<String> = fields.keys().cloned().collect();
+    match rng.gen_range(0..6) {
+        0 if !names.is_empty() => {
+            let name = names.choose(rng).unwrap();
+            fields.insert(name.clone(), random_value(rng, 3));
+        }
+        1 if !names.is_empty() => {
+            fields.remove(names.choose(rng).unwrap());
+        }
+        2 => fields.extend(random_fields(rng, 3)),
+        3 => {
+            let request_type = REQUEST_TYPES.choose(rng).unwrap();
+            fields.insert("type".to_string(), json!(request_type));
+        }
+        4 if !names.is_empty() => {
+            let name = names.choose(rng).unwrap();
+            let value = fields.remove(name).unwrap();
+            fields.insert(random_string(rng), value);
+        }
+        _ => *request = random_value(rng, 3),
+    }
+}
+
+/// Flip, drop, insert or truncate a few bytes of an encoded request
+fn mutate_bytes(rng: &mut StdRng, bytes: &mut Vec<u8>) {
+    for _ in 0..rng.gen_range(1..=4) {
+        if bytes.is_empty() {
+            bytes.push(rng.gen());
+            continue;
+        }
+        let at = rng.gen_range(0..bytes.len());
+        match rng.gen_range(0..4) {
+            0 => bytes[at] ^= 1 << rng.gen_range(0..8),
+            1 => bytes.insert(at, rng.gen()),
+            2 => {
+                bytes.remove(at);
+            }
+            _ => bytes.truncate(at),
+        }
+    }
+}
+
+fn new_session(lobby: &Arc<Lobby>, connection_id: u64) -> ConnectionSession {
+    ConnectionSession::new(
+        Arc::clone(lobby),
+        Arc::new(Rooms::new()),
+        Arc::new(AuthRateLimiter::new()),
+        Default::default(),
+        connection_id,
+    )
+}
+
+/// A session signed in with a fresh key, offering `encodings`
+async fn signed_in_session(
+    lobby: &Arc<Lobby>,
+    connection_id: u64,
+    encodings: &[&str],
+) -> (ConnectionSession, String) {
+    let private_key = generate_private_key().unwrap();
+    let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+    let signature = hex::encode(sign_message(&private_key, b"auth").unwrap());
+    let auth = json!({
+        "type": "auth",
+        "publicKey": public_key,
+        "signature": signature,
+        "encodings": encodings,
+    });
+
+    let mut session = new_session(lobby, connection_id);
+    session
+        .on_frame(Ok(WsMessage::Text(auth.to_string())))
+        .await
+        .unwrap();
+    assert!(session.state().public_key().is_some(), "auth failed");
+    // Nothing drains replies here; dropping the queue discards them
+    drop(session.take_outbound());
+    (session, public_key)
+}
+
+#[tokio::test]
+async fn test_random_frames_never_panic() {
+    let first_seed = env_u64("PROFILE_FUZZ_SEED").unwrap_or(0);
+    let iterations = env_u64("PROFILE_FUZZ_ITERATIONS").unwrap_or(DEFAULT_ITERATIONS);
+    let lobby = Arc::new(Lobby::new());
+    let mut connection_id = 0;
+    let (mut json_session, mut json_key) = signed_in_session(&lobby, 0, &["json"]).await;
+    let (mut packed_session, mut packed_key) = signed_in_session(&lobby, 1, &["msgpack"]).await;
+
+    for seed in first_seed..first_seed.saturating_add(iterations) {
+        let _reporter = SeedReporter(seed);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        // Logging out or being refused closes a session; start another
+        if json_session.state().is_closing() {
+            connection_id += 2;
+            (json_session, json_key) = signed_in_session(&lobby, connection_id, &["json"]).await;
+        }
+        if packed_session.state().is_closing() {
+            connection_id += 2;
+            (packed_session, packed_key) =
+                signed_in_session(&lobby, connection_id + 1, &["msgpack"]).await;
+        }
+
+        let request_type = REQUEST_TYPES.choose(&mut rng).unwrap();
+        let mut request = well_formed_request(&mut rng, request_type, &json_key);
+        for _ in 0..rng.gen_range(1..=3) {
+            mutate_request(&mut rng, &mut request);
+        }
+        // Sometimes nested in more arrays than the parsers accept
+        let nesting = if rng.gen_bool(0.1) {
+            rng.gen_range(NESTING)
+        } else {
+            0
+        };
+
+        let mut text = request.to_string().into_bytes();
+        if rng.gen_bool(0.25) {
+            mutate_bytes(&mut rng, &mut text);
+        }
+        let text = format!(
+            "{}{}{}",
+            "[".repeat(nesting),
+            String::from_utf8_lossy(&text),
+            "]".repeat(nesting)
+        );
+
+        let mut pre_auth = new_session(&lobby, u64::MAX - seed);
+        let _ = pre_auth.on_frame(Ok(WsMessage::Text(text.clone()))).await;
+        let _ = json_session.on_frame(Ok(WsMessage::Text(text))).await;
+
+        if request.get("senderPublicKey").is_some() {
+            request["senderPublicKey"] = json!(packed_key);
+        }
+        let mut packed = vec![MSGPACK_ARRAY_OF_ONE; nesting];
+        packed.extend(Encoding::MessagePack.encode(&request).unwrap());
+        if rng.gen_bool(0.25) {
+            mutate_bytes(&mut rng, &mut packed);
+        }
+        let _ = packed_session.on_frame(Ok(WsMessage::Binary(packed))).await;
+    }
+}
@@ -3,9 +3,8 @@
 //! This module consolidates common test helper functions used across
 //! lobby tests to avoid code duplication.
 //!
+use profile_server::connection::send_queue::send_queue;
 use profile_server::lobby::ActiveConnection;
-use profile_shared::Message;
-use tokio::sync::mpsc;
 
 /// Create a test ActiveConnection with a given public key and connection ID
 ///
@@ -13,10 +12,11 @@ use tokio::sync::mpsc;
 /// For tests that need auto-generated 64-char hex keys, use
 /// `profile_server::lobby::manager::tests::create_test_connection` instead.
 pub fn create_test_connection(key: &str, connection_id: u64) -> ActiveConnection {
-    let (sender, _) = mpsc::unbounded_channel::<Message>();
+    let (sender, _) = send_queue();
     ActiveConnection {
         public_key: key.to_string(),
         sender,
         connection_id,
+        status: Default::default(),
     }
 }
@@ -0,0 +1,73 @@
+//! Benchmarks for the signing hot path
+//!
+//! Every auth and chat message goes through `sign_message` on the client
+//! and `verify_signature` on the server, so these are the numbers that
+//! bound throughput. `cargo xtask bench-gate` runs this suite and compares
+//! it against `benches/baseline.json`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use profile_shared::crypto::verification::SignedItem;
+use profile_shared::{
+    derive_public_key, generate_private_key, sign_message, verify_signature,
+    verify_signatures_batch, PrivateKey, PublicKey,
+};
+use std::hint::black_box;
+
+/// Signatures checked together in the batch benchmark
+const BATCH_SIZE: usize = 64;
+
+/// A typical canonical chat message
+const MESSAGE: &[u8] = b"Hello! Are we still meeting at the usual place tomorrow afternoon?";
+
+fn key_pair() -> (PrivateKey, PublicKey) {
+    let private_key = generate_private_key().unwrap();
+    let public_key = derive_public_key(&private_key).unwrap();
+    (private_key, public_key)
+}
+
+fn bench_crypto(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crypto");
+
+    group.bench_function("keygen", |b| {
+        b.iter(|| {
+            let private_key = generate_private_key().unwrap();
+            black_box(derive_public_key(&private_key).unwrap())
+        })
+    });
+
+    let (private_key, public_key) = key_pair();
+    group.bench_function("sign", |b| {
+        b.iter(|| sign_message(&private_key, black_box(MESSAGE)).unwrap())
+    });
+
+    let signature = sign_message(&private_key, MESSAGE).unwrap();
+    group.bench_function("verify", |b| {
+        b.iter(|| verify_signature(&public_key, black_box(MESSAGE), black_box(&signature)).unwrap())
+    });
+
+    let signers: Vec<(PublicKey, Vec<u8>)> = (0..BATCH_SIZE)
+        .map(|_| {
+            let (private_key, public_key) = key_pair();
+            let signature = sign_message(&private_key, MESSAGE).unwrap();
+            (public_key, signature)
+        })
+        .collect();
+    group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+    group.bench_function("verify_batch_64", |b| {
+        b.iter_batched(
+            || {
+                signers
+                    .iter()
+                    .map(|(public_key, signature)| (public_key, MESSAGE, signature.as_slice()))
+                    .collect::<Vec<SignedItem<'_>>>()
+            },
+            |items| verify_signatures_batch(&items).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_crypto);
+criterion_main!(benches);
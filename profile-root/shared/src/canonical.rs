@@ -0,0 +1,603 @@
+//! Canonical encoding of signed messages
+//!
+//! A signature covers bytes, so the sender and every verifier must turn a
+//! message and its timestamp into exactly the same ones. Version 1 joined
+//! them as `message:timestamp`, which is ambiguous once a message contains
+//! a colon: "a:b" sent at "c" and "a" sent at "b:c" sign the same bytes.
+//!
+//! Version 2 starts with a domain tag and gives each field as a netstring,
+//! its length in bytes in decimal, a colon, the field and a comma. Every
+//! field says where it ends, so no two different messages encode the same
+//! way, and the encoding stays UTF-8 as signing requires.
+//!
+//! Messages carry the version they were signed with in a `canonical` field.
+//! Frames without one are version 1, so older clients keep working; new
+//! signatures use [`CanonicalVersion::CURRENT`].
+//!
+//! A direct message numbered with a per-connection `sequence` signs the
+//! number too, in version 2's layout under a tag of its own, whatever its
+//! `canonical` field says. Changing the number or dropping the field then
+//! breaks the signature instead of slipping a replay past the server.
+//!
+//! Room messages in version 2 also cover the room name, under their own
+//! tag, so a signature made for one room can't be replayed into another.
+//!
+//! Key rollovers (see [`crate::crypto::rollover`]) were introduced after
+//! version 2 and only ever use its netstring layout, under their own domain
+//! tag so a rollover signature can never pass for a message signature.
+//! Message edits likewise cover the id of the message they replace, under
+//! a domain tag of their own, so an edit signature can neither pass for a
+//! new message nor be moved onto another message. Retractions sign just
+//! the id and a timestamp under another tag, and reactions the id, emoji
+//! and whether it is added or removed under a third.
+//!
+//! Attachment manifests (see [`crate::protocol::attachment`]) sign the file's
+//! name, size, chunk layout and hashes under a tag of their own; the chunks
+//! themselves aren't signed, each is checked against its hash instead. A
+//! preview image, when there is one, is one more field after the timestamp.
+//!
+//! Requests to the server that aren't messages (nicknames, statuses, name
+//! claims, key backups and sender filters) and signed contact list exports use the
+//! same netstring layout, each under a tag of its own, so none of them can
+//! be mistaken for a message or for one another. So do the sender keys
+//! room members wrap for each other (see [`crate::crypto::group_keys`]).
+
+use crate::protocol::attachment::AttachmentManifest;
+use crate::protocol::{SenderFilter, Status};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Tag that version 2 encodings start with
+const V2_DOMAIN: &str = "profile-message-v2\n";
+
+/// Tag that version 2 room message encodings start with
+const ROOM_MESSAGE_DOMAIN: &str = "profile-room-message-v2\n";
+
+/// Tag that sequenced direct message encodings start with
+const SEQUENCED_DOMAIN: &str = "profile-message-sequenced-v1\n";
+
+/// Tag that key rollover encodings start with
+const ROLLOVER_DOMAIN: &str = "profile-key-rollover-v1\n";
+
+/// Tag that message edit encodings start with
+const EDIT_DOMAIN: &str = "profile-message-edit-v1\n";
+
+/// Tag that message retraction encodings start with
+const RETRACT_DOMAIN: &str = "profile-message-retract-v1\n";
+
+/// Tag that reaction encodings start with
+const REACTION_DOMAIN: &str = "profile-message-reaction-v1\n";
+
+/// Tag that attachment manifest encodings start with
+const ATTACHMENT_DOMAIN: &str = "profile-attachment-manifest-v1\n";
+
+/// Tag that nickname request encodings start with
+const NICKNAME_DOMAIN: &str = "profile-set-nickname-v1\n";
+
+/// Tag that status request encodings start with
+const SET_STATUS_DOMAIN: &str = "profile-set-status-v1\n";
+
+/// Tag that name claim encodings start with
+const CLAIM_NAME_DOMAIN: &str = "profile-claim-name-v1\n";
+
+/// Tag that key backup upload encodings start with
+const BACKUP_STORE_DOMAIN: &str = "profile-backup-store-v1\n";
+
+/// Tag that sender filter list encodings start with
+const FILTERS_DOMAIN: &str = "profile-sender-filters-v1\n";
+
+/// Tag that contact list export encodings start with
+const CONTACT_EXPORT_DOMAIN: &str = "profile-contact-export-v1\n";
+
+/// Tag that wrapped sender key encodings start with
+const SENDER_KEY_DOMAIN: &str = "profile-sender-key-v1\n";
+
+/// Canonical encoding a signature was made over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum CanonicalVersion {
+    /// `message:timestamp`, what frames without a version were signed over
+    #[default]
+    V1,
+    /// Domain tag and fields as netstrings
+    V2,
+}
+
+impl CanonicalVersion {
+    /// The version new signatures are made with
+    pub const CURRENT: Self = Self::V2;
+
+    /// Whether this is the version a missing `canonical` field stands for,
+    /// so it can be left out when serializing
+    pub fn is_legacy(&self) -> bool {
+        *self == Self::V1
+    }
+}
+
+impl From<CanonicalVersion> for u8 {
+    fn from(version: CanonicalVersion) -> Self {
+        match version {
+            CanonicalVersion::V1 => 1,
+            CanonicalVersion::V2 => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for CanonicalVersion {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            other => Err(format!("unsupported canonical version {}", other)),
+        }
+    }
+}
+
+/// The bytes a message signature covers
+///
+/// # Arguments
+/// * `version` - The encoding the signature is made over
+/// * `message` - The message text
+/// * `timestamp` - When the message was sent, as sent on the wire
+pub fn message(version: CanonicalVersion, message: &str, timestamp: &str) -> Vec<u8> {
+    match version {
+        CanonicalVersion::V1 => format!("{}:{}", message, timestamp).into_bytes(),
+        CanonicalVersion::V2 => netstrings(V2_DOMAIN, &[message, timestamp]),
+    }
+}
+
+/// The bytes a room message signature covers
+///
+/// Version 2 also covers the room, under a tag of its own, so a room
+/// message can't be passed off as a direct message or replayed into
+/// another room. Version 1 is the same `message:timestamp` as [`message`].
+///
+/// # Arguments
+/// * `version` - The encoding the signature is made over
+/// * `room` - The room the message was posted to
+/// * `message` - The message text
+/// * `timestamp` - When the message was sent, as sent on the wire
+pub fn room_message(
+    version: CanonicalVersion,
+    room: &str,
+    message: &str,
+    timestamp: &str,
+) -> Vec<u8> {
+    match version {
+        CanonicalVersion::V1 => format!("{}:{}", message, timestamp).into_bytes(),
+        CanonicalVersion::V2 => netstrings(ROOM_MESSAGE_DOMAIN, &[room, message, timestamp]),
+    }
+}
+
+/// The bytes a sequenced direct message signature covers
+///
+/// # Arguments
+/// * `message` - The message text
+/// * `timestamp` - When the message was sent, as sent on the wire
+/// * `sequence` - The sender's sequence number on its connection
+pub fn sequenced_message(message: &str, timestamp: &str, sequence: u64) -> Vec<u8> {
+    netstrings(
+        SEQUENCED_DOMAIN,
+        &[message, timestamp, &sequence.to_string()],
+    )
+}
+
+/// The bytes a direct message signature covers
+///
+/// [`sequenced_message`] if the message carries a sequence number,
+/// otherwise [`message`] in `version`.
+pub fn direct_message(
+    version: CanonicalVersion,
+    text: &str,
+    timestamp: &str,
+    sequence: Option<u64>,
+) -> Vec<u8> {
+    match sequence {
+        Some(sequence) => sequenced_message(text, timestamp, sequence),
+        None => message(version, text, timestamp),
+    }
+}
+
+/// The bytes both signatures of a key rollover cover
+///
+/// # Arguments
+/// * `old_public_key` - The key being retired (hex)
+/// * `new_public_key` - The key replacing it (hex)
+/// * `timestamp` - When the rollover was signed, as sent on the wire
+pub fn key_rollover(old_public_key: &str, new_public_key: &str, timestamp: &str) -> Vec<u8> {
+    netstrings(
+        ROLLOVER_DOMAIN,
+        &[old_public_key, new_public_key, timestamp],
+    )
+}
+
+/// The bytes a message edit signature covers
+///
+/// # Arguments
+/// * `message_id` - The id of the message being edited
+/// * `message` - The new message text
+/// * `timestamp` - When the edit was made, as sent on the wire
+pub fn message_edit(message_id: Uuid, message: &str, timestamp: &str) -> Vec<u8> {
+    netstrings(
+        EDIT_DOMAIN,
+        &[&message_id.hyphenated().to_string(), message, timestamp],
+    )
+}
+
+/// The bytes a message retraction signature covers
+///
+/// # Arguments
+/// * `message_id` - The id of the message being retracted
+/// * `timestamp` - When it was retracted, as sent on the wire
+pub fn message_retract(message_id: Uuid, timestamp: &str) -> Vec<u8> {
+    netstrings(
+        RETRACT_DOMAIN,
+        &[&message_id.hyphenated().to_string(), timestamp],
+    )
+}
+
+/// The bytes a reaction signature covers
+///
+/// # Arguments
+/// * `message_id` - The id of the message reacted to
+/// * `emoji` - The reaction
+/// * `removed` - Whether the reaction is being taken back
+/// * `timestamp` - When the reaction was made, as sent on the wire
+pub fn message_reaction(message_id: Uuid, emoji: &str, removed: bool, timestamp: &str) -> Vec<u8> {
+    netstrings(
+        REACTION_DOMAIN,
+        &[
+            &message_id.hyphenated().to_string(),
+            emoji,
+            if removed { "remove" } else { "add" },
+            timestamp,
+        ],
+    )
+}
+
+/// The bytes an attachment manifest signature covers
+///
+/// The transfer id, file name, size, chunk size, file hash, number of
+/// chunks, each chunk hash in order, the timestamp and the preview image if
+/// there is one: everything in the manifest but the signature.
+pub fn attachment_manifest(manifest: &AttachmentManifest) -> Vec<u8> {
+    let mut fields = vec![
+        manifest.transfer_id.hyphenated().to_string(),
+        manifest.file_name.clone(),
+        manifest.size.to_string(),
+        manifest.chunk_size.to_string(),
+        manifest.file_hash.clone(),
+        manifest.chunk_hashes.len().to_string(),
+    ];
+    fields.extend(manifest.chunk_hashes.iter().cloned());
+    fields.push(manifest.timestamp.clone());
+    fields.extend(manifest.thumbnail.iter().cloned());
+    netstrings(
+        ATTACHMENT_DOMAIN,
+        &fields.iter().map(String::as_str).collect::<Vec<_>>(),
+    )
+}
+
+/// The bytes a nickname request signature covers
+///
+/// # Arguments
+/// * `nickname` - The trimmed nickname, empty when clearing it
+/// * `timestamp` - When the request was made, as sent on the wire
+pub fn nickname(nickname: &str, timestamp: &str) -> Vec<u8> {
+    netstrings(NICKNAME_DOMAIN, &[nickname, timestamp])
+}
+
+/// The bytes a status request signature covers
+///
+/// # Arguments
+/// * `status` - The status being shown
+/// * `timestamp` - When the request was made, as sent on the wire
+pub fn set_status(status: Status, timestamp: &str) -> Vec<u8> {
+    netstrings(SET_STATUS_DOMAIN, &[status.as_str(), timestamp])
+}
+
+/// The bytes a name claim signature covers
+///
+/// # Arguments
+/// * `name` - The trimmed name, empty when releasing it
+/// * `timestamp` - When the claim was made, as sent on the wire
+pub fn claim_name(name: &str, timestamp: &str) -> Vec<u8> {
+    netstrings(CLAIM_NAME_DOMAIN, &[name, timestamp])
+}
+
+/// The bytes a key backup upload signature covers
+///
+/// # Arguments
+/// * `version` - The version of the backup being stored
+/// * `blob` - The sealed escrow, hex-encoded as sent on the wire
+/// * `timestamp` - When the upload was made, as sent on the wire
+pub fn backup_store(version: u64, blob: &str, timestamp: &str) -> Vec<u8> {
+    netstrings(
+        BACKUP_STORE_DOMAIN,
+        &[&version.to_string(), blob, timestamp],
+    )
+}
+
+/// The bytes a sender filter list signature covers
+///
+/// The number of filters, then each filter's sender key and action in
+/// order, then the timestamp.
+pub fn filters(filters: &[SenderFilter], timestamp: &str) -> Vec<u8> {
+    let count = filters.len().to_string();
+    let mut fields = vec![count.as_str()];
+    for filter in filters {
+        fields.push(&filter.sender_public_key);
+        fields.push(filter.action.as_str());
+    }
+    fields.push(timestamp);
+    netstrings(FILTERS_DOMAIN, &fields)
+}
+
+/// The bytes a contact list export signature covers
+///
+/// # Arguments
+/// * `payload` - The export's JSON, everything in the file but the signature
+pub fn contact_export(payload: &str) -> Vec<u8> {
+    netstrings(CONTACT_EXPORT_DOMAIN, &[payload])
+}
+
+/// The bytes a wrapped sender key signature covers
+///
+/// # Arguments
+/// * `associated_data` - The fields the encryption is bound to
+/// * `nonce` - The encryption nonce (hex)
+/// * `ciphertext` - The sealed key (hex)
+pub fn sender_key(associated_data: &str, nonce: &str, ciphertext: &str) -> Vec<u8> {
+    netstrings(SENDER_KEY_DOMAIN, &[associated_data, nonce, ciphertext])
+}
+
+/// `domain` followed by each field as a netstring
+fn netstrings(domain: &str, fields: &[&str]) -> Vec<u8> {
+    let capacity = domain.len() + fields.iter().map(|f| f.len() + 8).sum::<usize>();
+    let mut encoded = String::with_capacity(capacity);
+    encoded.push_str(domain);
+    for field in fields {
+        encoded.push_str(&field.len().to_string());
+        encoded.push(':');
+        encoded.push_str(field);
+        encoded.push(',');
+    }
+    encoded.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::FilterAction;
+    use crate::{derive_public_key, generate_private_key, sign_message, verify_signature};
+
+    const TIMESTAMP: &str = "2025-12-20T10:00:00Z";
+
+    #[test]
+    fn test_v1_matches_legacy_format() {
+        assert_eq!(
+            message(CanonicalVersion::V1, "hello", TIMESTAMP),
+            format!("hello:{}", TIMESTAMP).into_bytes()
+        );
+        assert_eq!(CanonicalVersion::default(), CanonicalVersion::V1);
+    }
+
+    #[test]
+    fn test_v2_is_unambiguous_with_colons() {
+        // Both are "a:b:c" in version 1
+        assert_eq!(
+            message(CanonicalVersion::V1, "a:b", "c"),
+            message(CanonicalVersion::V1, "a", "b:c")
+        );
+        assert_ne!(
+            message(CanonicalVersion::V2, "a:b", "c"),
+            message(CanonicalVersion::V2, "a", "b:c")
+        );
+        assert_ne!(
+            message(CanonicalVersion::V2, "", "ab"),
+            message(CanonicalVersion::V2, "a", "b")
+        );
+
+        assert_eq!(
+            message(CanonicalVersion::V2, "hé:", "t"),
+            b"profile-message-v2\n4:h\xc3\xa9:,1:t,".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_room_message_covers_the_room() {
+        assert_eq!(
+            room_message(CanonicalVersion::V2, "general", "hi", "t"),
+            b"profile-room-message-v2\n7:general,2:hi,1:t,".to_vec()
+        );
+        assert_ne!(
+            room_message(CanonicalVersion::V2, "general", "hi", "t"),
+            room_message(CanonicalVersion::V2, "random", "hi", "t")
+        );
+        assert_ne!(
+            room_message(CanonicalVersion::V2, "general", "hi", "t"),
+            message(CanonicalVersion::V2, "hi", "t")
+        );
+        assert_eq!(
+            room_message(CanonicalVersion::V1, "general", "hi", "t"),
+            message(CanonicalVersion::V1, "hi", "t")
+        );
+    }
+
+    #[test]
+    fn test_key_rollover_has_its_own_domain() {
+        assert_eq!(
+            key_rollover("ab", "cd", "t"),
+            b"profile-key-rollover-v1\n2:ab,2:cd,1:t,".to_vec()
+        );
+        assert_ne!(
+            key_rollover("ab", "cd", "t"),
+            message(CanonicalVersion::V2, "ab", "cd")
+        );
+    }
+
+    #[test]
+    fn test_message_edit_covers_the_original_id() {
+        let id = Uuid::from_u128(0x5f0c_2a4e_8b1d_4c7a_9e36_d1f0_7b2a_c845);
+        assert_eq!(
+            message_edit(id, "hi", "t"),
+            b"profile-message-edit-v1\n36:5f0c2a4e-8b1d-4c7a-9e36-d1f07b2ac845,2:hi,1:t,".to_vec()
+        );
+        assert_ne!(
+            message_edit(id, "hi", "t"),
+            message_edit(Uuid::nil(), "hi", "t")
+        );
+    }
+
+    #[test]
+    fn test_message_retract_differs_from_an_empty_edit() {
+        let id = Uuid::from_u128(0x5f0c_2a4e_8b1d_4c7a_9e36_d1f0_7b2a_c845);
+        assert_eq!(
+            message_retract(id, "t"),
+            b"profile-message-retract-v1\n36:5f0c2a4e-8b1d-4c7a-9e36-d1f07b2ac845,1:t,".to_vec()
+        );
+        assert_ne!(message_retract(id, "t"), message_edit(id, "", "t"));
+    }
+
+    #[test]
+    fn test_message_reaction_says_whether_it_is_removed() {
+        let id = Uuid::from_u128(0x5f0c_2a4e_8b1d_4c7a_9e36_d1f0_7b2a_c845);
+        assert_eq!(
+            message_reaction(id, "👍", false, "t"),
+            "profile-message-reaction-v1\n36:5f0c2a4e-8b1d-4c7a-9e36-d1f07b2ac845,4:👍,3:add,1:t,"
+                .as_bytes()
+        );
+        assert_ne!(
+            message_reaction(id, "👍", false, "t"),
+            message_reaction(id, "👍", true, "t")
+        );
+    }
+
+    #[test]
+    fn test_request_encodings_are_unambiguous() {
+        assert_eq!(
+            nickname("a:b", "t"),
+            b"profile-set-nickname-v1\n3:a:b,1:t,".to_vec()
+        );
+        assert_ne!(nickname("a:b", "c"), nickname("a", "b:c"));
+        assert_ne!(nickname("alice", "t"), claim_name("alice", "t"));
+        assert_eq!(
+            set_status(Status::Invisible, "t"),
+            b"profile-set-status-v1\n9:invisible,1:t,".to_vec()
+        );
+        assert_ne!(
+            set_status(Status::Invisible, "t"),
+            message(CanonicalVersion::V1, "set_status:invisible", "t")
+        );
+        assert_ne!(backup_store(1, "ab", "t"), backup_store(1, "a", "bt"));
+
+        let filter = |key: &str, action| SenderFilter {
+            sender_public_key: key.to_string(),
+            action,
+        };
+        assert_eq!(
+            filters(&[filter("ab", FilterAction::Drop)], "t"),
+            b"profile-sender-filters-v1\n1:1,2:ab,4:drop,1:t,".to_vec()
+        );
+        assert_ne!(
+            filters(&[filter("ab", FilterAction::Drop)], "t"),
+            filters(&[], "t")
+        );
+    }
+
+    #[test]
+    fn test_chat_signature_does_not_verify_as_a_request() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let filter = [SenderFilter {
+            sender_public_key: "ab".repeat(32),
+            action: FilterAction::QueueOnly,
+        }];
+        let requests = [
+            nickname("alice", TIMESTAMP),
+            set_status(Status::Invisible, TIMESTAMP),
+            claim_name("alice", TIMESTAMP),
+            backup_store(1, "abcd", TIMESTAMP),
+            filters(&filter, TIMESTAMP),
+            contact_export("{\"contacts\":[]}"),
+        ];
+
+        for version in [CanonicalVersion::V1, CanonicalVersion::V2] {
+            // Chat messages whose text reads like each request
+            for text in [
+                "set_nickname:alice",
+                "set_status:invisible",
+                "claim_name:alice",
+                "backup_store:1:abcd",
+                "set_filters:",
+                "contacts_export",
+                "alice",
+            ] {
+                let signature =
+                    sign_message(&private_key, &message(version, text, TIMESTAMP)).unwrap();
+                for request in &requests {
+                    assert!(verify_signature(&public_key, request, &signature).is_err());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sequence_is_signed() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let signed = direct_message(CanonicalVersion::CURRENT, "hi", TIMESTAMP, Some(7));
+        assert_eq!(signed, sequenced_message("hi", TIMESTAMP, 7));
+        let signature = sign_message(&private_key, &signed).unwrap();
+
+        // Neither a changed number nor a dropped one verifies
+        for version in [CanonicalVersion::V1, CanonicalVersion::V2] {
+            for sequence in [None, Some(8)] {
+                let tampered = direct_message(version, "hi", TIMESTAMP, sequence);
+                assert!(verify_signature(&public_key, &tampered, &signature).is_err());
+            }
+        }
+        assert_eq!(
+            direct_message(CanonicalVersion::V1, "hi", TIMESTAMP, None),
+            message(CanonicalVersion::V1, "hi", TIMESTAMP)
+        );
+    }
+
+    #[test]
+    fn test_signature_does_not_carry_across_versions() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let signature = sign_message(
+            &private_key,
+            &message(CanonicalVersion::CURRENT, "hi", TIMESTAMP),
+        )
+        .unwrap();
+
+        assert!(verify_signature(
+            &public_key,
+            &message(CanonicalVersion::CURRENT, "hi", TIMESTAMP),
+            &signature
+        )
+        .is_ok());
+        assert!(verify_signature(
+            &public_key,
+            &message(CanonicalVersion::V1, "hi", TIMESTAMP),
+            &signature
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_version_serialized_as_number() {
+        assert_eq!(serde_json::to_string(&CanonicalVersion::V2).unwrap(), "2");
+        assert_eq!(
+            serde_json::from_str::<CanonicalVersion>("1").unwrap(),
+            CanonicalVersion::V1
+        );
+        assert!(serde_json::from_str::<CanonicalVersion>("3").is_err());
+        assert!(CanonicalVersion::V1.is_legacy());
+        assert!(!CanonicalVersion::CURRENT.is_legacy());
+    }
+}
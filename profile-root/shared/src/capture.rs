@@ -0,0 +1,241 @@
+//! Wire-level frame capture for debugging
+//!
+//! When `PROFILE_CAPTURE_FILE` is set, the client and server append every
+//! WebSocket frame they send or receive to that file as one JSON object per
+//! line (JSONL):
+//!
+//! ```text
+//! {"tsMs":1735293600000,"direction":"inbound","connection":3,"kind":"text","data":"{...}"}
+//! ```
+//!
+//! Secret fields (signatures and key material) are replaced with
+//! `"[redacted]"` before anything is written, so captures can be attached to
+//! bug reports. Binary frames are stored hex-encoded.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable naming the capture file; capture is off when unset
+pub const CAPTURE_ENV_VAR: &str = "PROFILE_CAPTURE_FILE";
+
+/// Replacement value for redacted fields
+pub const REDACTED: &str = "[redacted]";
+
+/// JSON field names whose values are never written to a capture
+const SECRET_FIELDS: &[&str] = &["signature", "privateKey", "private_key", "seed", "mnemonic"];
+
+/// Whether a frame was received or sent by the side writing the capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// WebSocket frame type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameKind {
+    Text,
+    Binary,
+    Ping,
+    Pong,
+    Close,
+}
+
+/// One captured frame
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Milliseconds since the Unix epoch
+    #[serde(rename = "tsMs")]
+    pub ts_ms: u64,
+    pub direction: Direction,
+    /// Connection id (server) or connection attempt number (client)
+    pub connection: u64,
+    pub kind: FrameKind,
+    /// Redacted text, hex-encoded binary payload, or close reason
+    pub data: String,
+}
+
+/// Replace secret fields in a JSON text frame
+///
+/// Nested objects and arrays are searched too. Frames that aren't JSON are
+/// returned unchanged.
+pub fn redact(text: &str) -> String {
+    fn scrub(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, field) in map.iter_mut() {
+                    if SECRET_FIELDS.contains(&key.as_str()) {
+                        *field = serde_json::Value::String(REDACTED.to_string());
+                    } else {
+                        scrub(field);
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(scrub),
+            _ => {}
+        }
+    }
+
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(mut value) => {
+            scrub(&mut value);
+            value.to_string()
+        }
+        Err(_) => text.to_string(),
+    }
+}
+
+/// Appends capture records to a file, shared between connections
+#[derive(Debug)]
+pub struct CaptureWriter {
+    file: Mutex<BufWriter<File>>,
+}
+
+impl CaptureWriter {
+    /// Open (or create) a capture file for appending
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Open the file named by `PROFILE_CAPTURE_FILE`
+    ///
+    /// # Returns
+    /// Ok(None) if capture is not enabled
+    pub fn from_env() -> io::Result<Option<Arc<Self>>> {
+        match std::env::var_os(CAPTURE_ENV_VAR) {
+            Some(path) if !path.is_empty() => Ok(Some(Arc::new(Self::create(path)?))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Append one frame, redacting text payloads
+    ///
+    /// Each record is flushed immediately so a crash doesn't lose the frames
+    /// leading up to it.
+    pub fn record(
+        &self,
+        direction: Direction,
+        connection: u64,
+        kind: FrameKind,
+        data: &str,
+    ) -> io::Result<()> {
+        let record = CaptureRecord {
+            ts_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            direction,
+            connection,
+            kind,
+            data: match kind {
+                FrameKind::Text => redact(data),
+                _ => data.to_string(),
+            },
+        };
+        let line = serde_json::to_string(&record)?;
+
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("capture file lock poisoned"))?;
+        writeln!(file, "{}", line)?;
+        file.flush()
+    }
+}
+
+/// Read every record from a capture file
+///
+/// Blank lines are skipped; a malformed line is reported with its line number.
+pub fn read_capture(path: impl AsRef<Path>) -> io::Result<Vec<CaptureRecord>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", index + 1, e),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("profile-capture-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_redact_replaces_nested_secrets() {
+        let text = r#"{"type":"auth","publicKey":"abcd","signature":"ffff","inner":[{"privateKey":"00"}]}"#;
+        let redacted: serde_json::Value = serde_json::from_str(&redact(text)).unwrap();
+
+        assert_eq!(redacted["signature"], REDACTED);
+        assert_eq!(redacted["inner"][0]["privateKey"], REDACTED);
+        assert_eq!(redacted["publicKey"], "abcd");
+    }
+
+    #[test]
+    fn test_redact_leaves_non_json_alone() {
+        assert_eq!(redact("not json"), "not json");
+    }
+
+    #[test]
+    fn test_records_round_trip_through_file() {
+        let path = temp_path("round-trip.jsonl");
+        let _ = std::fs::remove_file(&path);
+
+        let writer = CaptureWriter::create(&path).unwrap();
+        writer
+            .record(
+                Direction::Outbound,
+                7,
+                FrameKind::Text,
+                r#"{"type":"message","signature":"aa"}"#,
+            )
+            .unwrap();
+        writer
+            .record(Direction::Inbound, 7, FrameKind::Ping, "00ff")
+            .unwrap();
+
+        let records = read_capture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, Direction::Outbound);
+        assert_eq!(records[0].connection, 7);
+        assert!(!records[0].data.contains("\"aa\""));
+        assert_eq!(records[1].kind, FrameKind::Ping);
+        assert_eq!(records[1].data, "00ff");
+        assert!(records[0].ts_ms <= records[1].ts_ms);
+    }
+
+    #[test]
+    fn test_read_capture_reports_bad_line() {
+        let path = temp_path("bad-line.jsonl");
+        std::fs::write(&path, "\nnot a record\n").unwrap();
+
+        let err = read_capture(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 2:"));
+    }
+}
@@ -5,17 +5,36 @@
 
 /// Lobby configuration
 pub mod lobby {
+    use std::time::Duration;
+
     /// Maximum number of users allowed in the lobby
     /// Note: Client uses this for UI display, server enforces this limit
-    pub const MAX_LOBBY_SIZE: usize = 100;
+    pub const MAX_LOBBY_SIZE: usize = 10_000;
+
+    /// Number of independently locked shards the server splits the lobby into
+    pub const SHARD_COUNT: usize = 16;
 
     /// Maximum number of users to display in client UI
     /// This should be less than or equal to MAX_LOBBY_SIZE
     pub const MAX_DISPLAY_USERS: usize = 100;
+
+    /// Number of users returned by a lobby page request without a limit
+    pub const DEFAULT_PAGE_SIZE: usize = 20;
+
+    /// Largest page a client may request (also caps the auth success user list)
+    pub const MAX_PAGE_SIZE: usize = 100;
+
+    /// Maximum length of a public key prefix search
+    pub const MAX_SEARCH_PREFIX_LENGTH: usize = 64;
+
+    /// How long the server collects joins and leaves into one lobby update
+    pub const UPDATE_COALESCE_WINDOW: Duration = Duration::from_millis(50);
 }
 
 /// Message configuration
 pub mod message {
+    use std::time::Duration;
+
     /// Maximum number of messages to retain in memory
     /// Used for both client display and server history
     pub const MAX_MESSAGE_HISTORY: usize = 50;
@@ -23,11 +42,250 @@ pub mod message {
     /// Maximum message size in bytes
     pub const MAX_MESSAGE_SIZE: usize = 4096;
 
+    /// Default maximum length of a message's text in bytes
+    ///
+    /// Leaves room within [`MAX_MESSAGE_SIZE`] for the keys, signature and
+    /// JSON escaping of the rest of the request.
+    pub const MAX_MESSAGE_LENGTH: usize = 2000;
+
+    /// Maximum size in bytes of a sealed (ratchet-encrypted) message
+    ///
+    /// Room for a text message of up to [`MAX_MESSAGE_SIZE`], padded and
+    /// hex-encoded, with the envelope around it.
+    pub const MAX_SEALED_SIZE: usize = 16 * 1024;
+
     /// Maximum allowed timestamp drift in seconds (5 minutes)
     pub const MAX_TIMESTAMP_DRIFT_SECS: i64 = 300;
 
     /// Hard limit for extreme/malformed timestamps (24 hours)
     pub const MAX_TIMESTAMP_DRIFT_SECS_ABSOLUTE: i64 = 86400;
+
+    /// How long the server remembers a sender's message ids (10 minutes)
+    ///
+    /// Twice the drift window: a resend that still passes the timestamp
+    /// check is always caught as a duplicate.
+    pub const DUPLICATE_ID_WINDOW: Duration =
+        Duration::from_secs(2 * MAX_TIMESTAMP_DRIFT_SECS as u64);
+
+    /// Maximum message ids remembered per sender within the window
+    pub const MAX_REMEMBERED_IDS_PER_SENDER: usize = 1000;
+
+    /// Maximum length of a reaction emoji in bytes
+    ///
+    /// Enough for a flag or a skin-toned family sequence, not for text.
+    pub const MAX_REACTION_LENGTH: usize = 32;
+}
+
+/// File attachment configuration
+pub mod attachments {
+    use std::time::Duration;
+
+    /// Size of each chunk a file is sent in, in bytes (before hex encoding)
+    pub const CHUNK_SIZE: usize = 16 * 1024;
+
+    /// Maximum size of an attached file in bytes
+    pub const MAX_ATTACHMENT_SIZE: usize = 8 * 1024 * 1024;
+
+    /// Maximum number of chunks in one transfer
+    pub const MAX_CHUNKS: usize = MAX_ATTACHMENT_SIZE / CHUNK_SIZE;
+
+    /// Maximum length of an attachment's file name in bytes
+    pub const MAX_FILE_NAME_LENGTH: usize = 255;
+
+    /// Largest width or height of an attachment's preview image, in pixels
+    pub const MAX_THUMBNAIL_DIMENSION: u32 = 128;
+
+    /// Maximum size of an encoded preview image in bytes (before hex encoding)
+    pub const MAX_THUMBNAIL_SIZE: usize = 8 * 1024;
+
+    /// Maximum size of an `attachment_offer` request in bytes
+    ///
+    /// Room for a manifest listing [`MAX_CHUNKS`] chunk hashes and a
+    /// hex-encoded preview of [`MAX_THUMBNAIL_SIZE`] bytes, within the
+    /// server's frame limit.
+    pub const MAX_OFFER_SIZE: usize = 60 * 1024;
+
+    /// Maximum size of an `attachment_chunk` request in bytes
+    ///
+    /// Room for a hex-encoded chunk of [`CHUNK_SIZE`] bytes and its header.
+    pub const MAX_CHUNK_REQUEST_SIZE: usize = 2 * CHUNK_SIZE + 1024;
+
+    /// Number of chunks a recipient asks for at a time
+    ///
+    /// Keeps a transfer from filling the recipient's send queue on the
+    /// server.
+    pub const ACCEPT_WINDOW: u32 = 16;
+
+    /// Maximum number of transfers one sender can have in progress
+    pub const MAX_TRANSFERS_PER_SENDER: usize = 4;
+
+    /// How long a transfer may go without an accept or chunk before the
+    /// server forgets it
+    pub const TRANSFER_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+}
+
+/// Room (group conversation) configuration
+pub mod rooms {
+    /// Maximum length of a room name in characters
+    pub const MAX_ROOM_NAME_LENGTH: usize = 64;
+
+    /// Maximum number of members in a single room
+    pub const MAX_ROOM_MEMBERS: usize = 50;
+
+    /// Maximum number of rooms the server keeps at once
+    pub const MAX_ROOMS: usize = 1000;
+}
+
+/// Padding of end-to-end encrypted payloads
+pub mod padding {
+    /// Sizes in bytes that encrypted payloads are padded up to
+    ///
+    /// The largest fits a message of
+    /// [`super::message::MAX_MESSAGE_LENGTH`] plus the padding marker.
+    pub const DEFAULT_BUCKETS: &[usize] = &[64, 256, 1024, 2048];
+
+    /// Maximum number of buckets a padding setting may list
+    pub const MAX_BUCKETS: usize = 16;
+
+    /// Largest bucket a padding setting may use, in bytes
+    pub const MAX_BUCKET_SIZE: usize = 64 * 1024;
+}
+
+/// Cover traffic sent by clients that opt in
+pub mod cover {
+    use std::time::Duration;
+
+    /// Default shortest wait between cover frames
+    pub const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// Default longest wait between cover frames
+    pub const DEFAULT_MAX_INTERVAL: Duration = Duration::from_secs(30);
+
+    /// Default cap on cover traffic, in bytes per minute
+    pub const DEFAULT_MAX_BYTES_PER_MINUTE: usize = 16 * 1024;
+
+    /// Shortest wait between cover frames a setting may ask for
+    pub const MIN_INTERVAL: Duration = Duration::from_secs(1);
+}
+
+/// Nickname (display name) configuration
+pub mod nickname {
+    /// Maximum length of a nickname in characters
+    pub const MAX_NICKNAME_LENGTH: usize = 32;
+}
+
+/// Claimed name registry configuration
+pub mod names {
+    /// Maximum number of names the server keeps claims for
+    pub const MAX_CLAIMED_NAMES: usize = 100_000;
+}
+
+/// Encrypted backup storage configuration
+pub mod backup {
+    /// Maximum size of one stored backup in bytes (before hex encoding)
+    pub const MAX_BACKUP_SIZE: usize = 16 * 1024;
+
+    /// Maximum number of identities the server keeps a backup for
+    pub const MAX_STORED_BACKUPS: usize = 10_000;
+}
+
+/// Sender filter configuration
+pub mod filters {
+    /// Maximum number of senders one user can filter
+    pub const MAX_SENDER_FILTERS: usize = 256;
+
+    /// Maximum number of messages held for one user from queue-only
+    /// senders; the oldest are dropped beyond this
+    pub const MAX_HELD_MESSAGES: usize = 100;
+}
+
+/// Load shedding configuration
+pub mod load {
+    use std::time::Duration;
+
+    /// Maximum signature verifications queued or running on the server
+    /// before new messages are refused as `server_busy`
+    pub const MAX_PENDING_VERIFICATIONS: usize = 512;
+
+    /// Delay the server suggests before a refused message is resent
+    pub const SERVER_BUSY_RETRY_AFTER: Duration = Duration::from_millis(500);
+}
+
+/// Reconnection pacing after a server restart
+pub mod reconnect {
+    use std::time::Duration;
+
+    /// Admissions to the lobby per second while the server paces them,
+    /// when `PROFILE_ADMISSION_RATE` is unset
+    pub const DEFAULT_ADMISSION_RATE: usize = 200;
+
+    /// Connections that may wait for admission on each lane before further
+    /// ones are refused, when `PROFILE_ADMISSION_QUEUE` is unset
+    pub const DEFAULT_ADMISSION_QUEUE: usize = 2000;
+
+    /// A user who sent something this recently before a shutdown counts as
+    /// recently active and is asked to reconnect sooner
+    pub const RECENT_ACTIVITY: Duration = Duration::from_secs(5 * 60);
+
+    /// Shortest reconnect delay suggested to a recently active user on shutdown
+    pub const ACTIVE_RETRY_MIN: Duration = Duration::from_millis(500);
+
+    /// Longest reconnect delay suggested to a recently active user on shutdown
+    pub const ACTIVE_RETRY_MAX: Duration = Duration::from_secs(5);
+
+    /// Shortest reconnect delay suggested to an idle user on shutdown
+    pub const IDLE_RETRY_MIN: Duration = Duration::from_secs(5);
+
+    /// Longest reconnect delay suggested to an idle user on shutdown
+    pub const IDLE_RETRY_MAX: Duration = Duration::from_secs(30);
+}
+
+/// Server runtime topology defaults
+pub mod runtime {
+    /// Blocking pool threads per CPU when `PROFILE_MAX_BLOCKING_THREADS` is unset
+    pub const BLOCKING_THREADS_PER_CPU: usize = 4;
+
+    /// Smallest default blocking pool, so small machines still have
+    /// threads for file I/O while verifications run
+    pub const MIN_BLOCKING_THREADS: usize = 16;
+}
+
+/// Admin control API configuration
+pub mod admin {
+    /// Address the admin API listens on when enabled (loopback only)
+    pub const DEFAULT_ADMIN_ADDRESS: &str = "127.0.0.1:8081";
+
+    /// Maximum size of one admin request line in bytes
+    pub const MAX_ADMIN_REQUEST_SIZE: usize = 4096;
+
+    /// Maximum length of a broadcast announcement in characters
+    pub const MAX_ANNOUNCEMENT_LENGTH: usize = 1000;
+}
+
+/// Read-only HTTP presence API configuration
+pub mod presence_api {
+    use std::time::Duration;
+
+    /// Address the presence API listens on when enabled
+    pub const DEFAULT_PRESENCE_API_ADDRESS: &str = "127.0.0.1:8082";
+
+    /// Maximum size of a request head in bytes
+    pub const MAX_PRESENCE_API_REQUEST_SIZE: usize = 8192;
+
+    /// How long a client has to send its request before the connection is dropped
+    pub const PRESENCE_API_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+}
+
+/// Dev-mode echo bot
+pub mod echo_bot {
+    /// Seed of the echo bot's signing key; public, so never a real identity
+    pub const SEED: &[u8; 32] = b"profile-dev-echo-bot-seed-000001";
+
+    /// Well-known public key of the echo bot, derived from [`SEED`]
+    pub const PUBLIC_KEY: &str = "a97e434649f162f57544ba0af3744302930ed6ab18ec046931c514d8bf1d4504";
+
+    /// Nickname the echo bot is shown with in the lobby
+    pub const NICKNAME: &str = "echo-bot";
 }
 
 /// Connection configuration
@@ -40,6 +298,37 @@ pub mod connection {
     /// Keep-alive ping interval
     pub const PING_INTERVAL: Duration = Duration::from_secs(25);
 
+    /// How long the server waits for a pong before dropping the connection
+    pub const PONG_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Largest WebSocket frame or message the server reads, in bytes
+    ///
+    /// Large enough for a hex-encoded backup upload, the biggest request.
+    pub const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+    /// Messages queued for one connection before it counts as a slow consumer
+    pub const SEND_QUEUE_CAPACITY: usize = 256;
+
+    /// Per-message compression configuration
+    pub mod compression {
+        /// Smallest frame payload compressed by default, in bytes
+        ///
+        /// Below this deflate's overhead eats most of the saving.
+        pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+    }
+
+    /// Session resumption configuration
+    pub mod resume {
+        use std::time::Duration;
+
+        /// How long a resume token stays valid after it is issued
+        pub const RESUME_TOKEN_TTL: Duration = Duration::from_secs(10 * 60);
+
+        /// Maximum number of redeemed resume tokens and logouts the server
+        /// remembers until their tokens expire
+        pub const MAX_RESUME_TOKENS: usize = 10_000;
+    }
+
     /// Rate limiting configuration
     pub mod rate_limit {
         /// Maximum authentication attempts per time window
@@ -77,6 +366,18 @@ pub mod server {
 
     /// Graceful shutdown timeout
     pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Log filter used when `PROFILE_LOG_LEVEL` is unset
+    pub const DEFAULT_LOG_LEVEL: &str = "info";
+
+    /// Size at which the audit log is rotated when `PROFILE_AUDIT_LOG_MAX_BYTES` is unset
+    pub const DEFAULT_AUDIT_LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+    /// Rotated audit log files kept when `PROFILE_AUDIT_LOG_KEEP` is unset
+    pub const DEFAULT_AUDIT_LOG_KEEP: usize = 5;
+
+    /// How often state is snapshotted when `PROFILE_SNAPSHOT_INTERVAL` is unset
+    pub const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30);
 }
 
 #[cfg(test)]
@@ -99,6 +400,22 @@ mod tests {
                 "Display message limit should not exceed actual message history limit"
             )
         };
+
+        const {
+            assert!(
+                lobby::DEFAULT_PAGE_SIZE <= lobby::MAX_PAGE_SIZE,
+                "Default lobby page should not exceed the maximum page size"
+            )
+        };
+
+        const { assert!(lobby::SHARD_COUNT > 0, "Lobby must have at least one shard") };
+
+        const {
+            assert!(
+                rooms::MAX_ROOM_MEMBERS <= lobby::MAX_LOBBY_SIZE,
+                "Room membership limit should not exceed lobby size limit"
+            )
+        };
     }
 
     #[test]
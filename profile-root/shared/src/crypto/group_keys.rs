@@ -0,0 +1,831 @@
+//! Sender-key group encryption for rooms
+//!
+//! Every member of an encrypted room encrypts their room messages with a
+//! sender key of their own and hands that key to the other members:
+//! - A [`SenderKey`] is a hash chain. Each message is encrypted with a key
+//!   derived from the current link, then the chain moves on, so a key held
+//!   now cannot decrypt earlier messages.
+//! - The key is given to each member as a [`WrappedSenderKey`], sealed to
+//!   that member's identity key (ed25519 converted to X25519, with an
+//!   ephemeral key per wrap) and signed by the sender.
+//! - A [`RoomKeyRing`] tracks the room's members. When someone leaves, it
+//!   rotates to a new generation and wraps it for the members left; when
+//!   someone joins, it only wraps the current key for them.
+//!
+//! The server relays wrapped keys and [`GroupCiphertext`]s as they are; it
+//! learns who sent what to whom, but never a key or a message.
+//!
+//! Messages are padded before encryption (see [`super::padding`]), so
+//! their length only shows the bucket they fall in.
+//!
+//! Receivers read each sender's messages in order, which the server
+//! preserves. Up to [`MAX_SKIPPED_ITERATIONS`] lost messages are skipped
+//! over, but a message older than the last one read is refused.
+
+use crate::crypto::padding::{unpad, PaddingPolicy, PaddingStats};
+use crate::crypto::PrivateKey;
+use crate::errors::GroupKeyError;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use zeroize::Zeroizing;
+
+/// Most messages of one sender that may be missing between two read ones
+pub const MAX_SKIPPED_ITERATIONS: u32 = 2_000;
+
+/// Domain separation for the key wrapping a sender key
+const WRAP_DOMAIN: &[u8] = b"profile-sender-key-wrap-v1";
+const CHAIN_LABEL: &[u8] = b"profile-sender-key-chain";
+const MESSAGE_LABEL: &[u8] = b"profile-sender-key-message";
+
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+
+type ChainKey = Zeroizing<[u8; KEY_LENGTH]>;
+
+fn derive(label: &[u8], chain_key: &[u8; KEY_LENGTH]) -> ChainKey {
+    let mut hasher = Sha256::new();
+    hasher.update(label);
+    hasher.update(chain_key);
+    Zeroizing::new(hasher.finalize().into())
+}
+
+fn random_nonce() -> [u8; NONCE_LENGTH] {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+fn decode_array<const N: usize>(value: &str, field: &str) -> Result<[u8; N], GroupKeyError> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| GroupKeyError::Malformed(field.to_string()))
+}
+
+fn verifying_key(public_key: &str) -> Result<VerifyingKey, GroupKeyError> {
+    let bytes =
+        decode_array::<32>(public_key, "public key").map_err(|_| GroupKeyError::InvalidKey)?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| GroupKeyError::InvalidKey)
+}
+
+fn signing_key(private_key: &PrivateKey) -> Result<SigningKey, GroupKeyError> {
+    let bytes: [u8; 32] = private_key
+        .as_slice()
+        .try_into()
+        .map_err(|_| GroupKeyError::InvalidKey)?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+fn seal(
+    key: &[u8; KEY_LENGTH],
+    nonce: &[u8; NONCE_LENGTH],
+    aad: &str,
+    plaintext: &[u8],
+) -> Vec<u8> {
+    XChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: plaintext,
+                aad: aad.as_bytes(),
+            },
+        )
+        .expect("XChaCha20-Poly1305 encryption cannot fail for in-memory buffers")
+}
+
+fn open(
+    key: &[u8; KEY_LENGTH],
+    nonce: &str,
+    aad: &str,
+    ciphertext: &str,
+) -> Result<Zeroizing<Vec<u8>>, GroupKeyError> {
+    let nonce = decode_array::<NONCE_LENGTH>(nonce, "nonce")?;
+    let ciphertext =
+        hex::decode(ciphertext).map_err(|_| GroupKeyError::Malformed("ciphertext".to_string()))?;
+    XChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: &ciphertext,
+                aad: aad.as_bytes(),
+            },
+        )
+        .map(Zeroizing::new)
+        .map_err(|_| GroupKeyError::Tampered)
+}
+
+/// Key sealing a sender key to one member
+fn wrapping_key(shared: &MontgomeryPoint, ephemeral: &[u8; 32], recipient: &[u8; 32]) -> ChainKey {
+    let mut hasher = Sha256::new();
+    hasher.update(WRAP_DOMAIN);
+    hasher.update(shared.as_bytes());
+    hasher.update(ephemeral);
+    hasher.update(recipient);
+    Zeroizing::new(hasher.finalize().into())
+}
+
+/// A member's own sender key for one room
+pub struct SenderKey {
+    room: String,
+    generation: u32,
+    iteration: u32,
+    chain_key: ChainKey,
+}
+
+impl SenderKey {
+    /// Create a fresh random sender key
+    pub fn generate(room: &str, generation: u32) -> Self {
+        let mut chain_key = Zeroizing::new([0u8; KEY_LENGTH]);
+        OsRng.fill_bytes(chain_key.as_mut());
+        Self {
+            room: room.to_string(),
+            generation,
+            iteration: 0,
+            chain_key,
+        }
+    }
+
+    /// Room the key encrypts for
+    pub fn room(&self) -> &str {
+        &self.room
+    }
+
+    /// Generation of the key, bumped on every rotation
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Number of messages encrypted with this key so far
+    pub fn iteration(&self) -> u32 {
+        self.iteration
+    }
+
+    /// Pad and encrypt a room message from `sender_public_key`, and move
+    /// the chain on
+    pub fn encrypt(
+        &mut self,
+        sender_public_key: &str,
+        plaintext: &[u8],
+        padding: &PaddingPolicy,
+    ) -> GroupCiphertext {
+        let plaintext = padding.pad(plaintext);
+        let message_key = derive(MESSAGE_LABEL, &self.chain_key);
+        let mut ciphertext = GroupCiphertext {
+            room: self.room.clone(),
+            sender_public_key: sender_public_key.to_string(),
+            generation: self.generation,
+            iteration: self.iteration,
+            nonce: String::new(),
+            ciphertext: String::new(),
+        };
+        let nonce = random_nonce();
+        ciphertext.ciphertext = hex::encode(seal(
+            &message_key,
+            &nonce,
+            &ciphertext.associated_data(),
+            &plaintext,
+        ));
+        ciphertext.nonce = hex::encode(nonce);
+
+        self.chain_key = derive(CHAIN_LABEL, &self.chain_key);
+        self.iteration += 1;
+        ciphertext
+    }
+
+    /// Seal the current state of this key to one member
+    ///
+    /// The member can decrypt messages from the current iteration on, but
+    /// none encrypted before.
+    ///
+    /// # Arguments
+    /// * `sender` - The sender's identity key, which signs the wrap
+    /// * `recipient_public_key` - The member's public key (hex)
+    pub fn wrap_for(
+        &self,
+        sender: &PrivateKey,
+        recipient_public_key: &str,
+    ) -> Result<WrappedSenderKey, GroupKeyError> {
+        let signing_key = signing_key(sender)?;
+        let recipient = verifying_key(recipient_public_key)?;
+
+        let mut ephemeral_secret = Zeroizing::new([0u8; 32]);
+        OsRng.fill_bytes(ephemeral_secret.as_mut());
+        let ephemeral = MontgomeryPoint::mul_base_clamped(*ephemeral_secret);
+        let shared = recipient.to_montgomery().mul_clamped(*ephemeral_secret);
+        let key = wrapping_key(&shared, ephemeral.as_bytes(), recipient.as_bytes());
+
+        let mut plaintext = Zeroizing::new(Vec::with_capacity(4 + KEY_LENGTH));
+        plaintext.extend_from_slice(&self.iteration.to_be_bytes());
+        plaintext.extend_from_slice(self.chain_key.as_ref());
+
+        let mut wrapped = WrappedSenderKey {
+            room: self.room.clone(),
+            sender_public_key: hex::encode(signing_key.verifying_key().as_bytes()),
+            recipient_public_key: recipient_public_key.to_lowercase(),
+            generation: self.generation,
+            ephemeral_public_key: hex::encode(ephemeral.as_bytes()),
+            nonce: String::new(),
+            ciphertext: String::new(),
+            signature: String::new(),
+        };
+        let nonce = random_nonce();
+        wrapped.ciphertext =
+            hex::encode(seal(&key, &nonce, &wrapped.associated_data(), &plaintext));
+        wrapped.nonce = hex::encode(nonce);
+        let signature = crate::crypto::sign_message(sender, &wrapped.signed_content())
+            .map_err(|_| GroupKeyError::InvalidKey)?;
+        wrapped.signature = hex::encode(signature);
+        Ok(wrapped)
+    }
+}
+
+/// Another member's sender key, as received
+pub struct ReceivedSenderKey {
+    room: String,
+    sender_public_key: String,
+    generation: u32,
+    iteration: u32,
+    chain_key: ChainKey,
+}
+
+impl ReceivedSenderKey {
+    /// Public key of the member the key belongs to
+    pub fn sender_public_key(&self) -> &str {
+        &self.sender_public_key
+    }
+
+    /// Generation of the key
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Decrypt the next message from this sender
+    ///
+    /// # Errors
+    /// Returns an error if the message is for another key or was altered,
+    /// or if it is older than the last message read
+    pub fn decrypt(
+        &mut self,
+        message: &GroupCiphertext,
+    ) -> Result<Zeroizing<Vec<u8>>, GroupKeyError> {
+        if message.room != self.room {
+            return Err(GroupKeyError::WrongRoom);
+        }
+        if !message
+            .sender_public_key
+            .eq_ignore_ascii_case(&self.sender_public_key)
+        {
+            return Err(GroupKeyError::UnknownSender);
+        }
+        if message.generation != self.generation {
+            return Err(GroupKeyError::StaleGeneration {
+                held: self.generation,
+                got: message.generation,
+            });
+        }
+        let skipped = message
+            .iteration
+            .checked_sub(self.iteration)
+            .ok_or(GroupKeyError::Replayed)?;
+        if skipped > MAX_SKIPPED_ITERATIONS {
+            return Err(GroupKeyError::TooFarAhead {
+                skipped,
+                max: MAX_SKIPPED_ITERATIONS,
+            });
+        }
+
+        let mut chain_key = Zeroizing::new(*self.chain_key);
+        for _ in 0..skipped {
+            chain_key = derive(CHAIN_LABEL, &chain_key);
+        }
+        let message_key = derive(MESSAGE_LABEL, &chain_key);
+        let padded = open(
+            &message_key,
+            &message.nonce,
+            &message.associated_data(),
+            &message.ciphertext,
+        )?;
+        let plaintext = Zeroizing::new(unpad(&padded)?.to_vec());
+
+        // Only move on once the message proved genuine
+        self.chain_key = derive(CHAIN_LABEL, &chain_key);
+        self.iteration = message.iteration + 1;
+        Ok(plaintext)
+    }
+}
+
+/// A sender key sealed to one member, signed by its sender
+///
+/// Only the recipient can open it; the server relays it unread.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrappedSenderKey {
+    pub room: String,
+    #[serde(rename = "senderPublicKey")]
+    pub sender_public_key: String,
+    #[serde(rename = "recipientPublicKey")]
+    pub recipient_public_key: String,
+    pub generation: u32,
+    #[serde(rename = "ephemeralPublicKey")]
+    pub ephemeral_public_key: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub signature: String,
+}
+
+impl WrappedSenderKey {
+    /// Fields the encryption is bound to
+    fn associated_data(&self) -> String {
+        format!(
+            "sender_key:{}:{}:{}:{}:{}",
+            self.room,
+            self.sender_public_key.to_lowercase(),
+            self.recipient_public_key.to_lowercase(),
+            self.generation,
+            self.ephemeral_public_key.to_lowercase()
+        )
+    }
+
+    /// Bytes the sender signs
+    fn signed_content(&self) -> Vec<u8> {
+        crate::canonical::sender_key(
+            &self.associated_data(),
+            &self.nonce.to_lowercase(),
+            &self.ciphertext.to_lowercase(),
+        )
+    }
+
+    /// Check the sender's signature and open the key as its recipient
+    ///
+    /// # Arguments
+    /// * `recipient` - The identity key of the member the key was sealed to
+    pub fn unwrap(&self, recipient: &PrivateKey) -> Result<ReceivedSenderKey, GroupKeyError> {
+        let sender = verifying_key(&self.sender_public_key)?;
+        let signature = hex::decode(&self.signature)
+            .map_err(|_| GroupKeyError::Malformed("signature".to_string()))?;
+        let sender_key = crate::crypto::PublicKey::new(sender.as_bytes().to_vec())
+            .map_err(|_| GroupKeyError::InvalidKey)?;
+        crate::crypto::verify_signature(&sender_key, &self.signed_content(), &signature)
+            .map_err(|_| GroupKeyError::Tampered)?;
+
+        let signing_key = signing_key(recipient)?;
+        let recipient_public = signing_key.verifying_key();
+        if hex::encode(recipient_public.as_bytes()) != self.recipient_public_key.to_lowercase() {
+            return Err(GroupKeyError::NotForMe);
+        }
+
+        let ephemeral = decode_array::<32>(&self.ephemeral_public_key, "ephemeral key")?;
+        let scalar = Zeroizing::new(signing_key.to_scalar_bytes());
+        let shared = MontgomeryPoint(ephemeral).mul_clamped(*scalar);
+        if shared.as_bytes() == &[0u8; 32] {
+            return Err(GroupKeyError::Tampered);
+        }
+        let key = wrapping_key(&shared, &ephemeral, recipient_public.as_bytes());
+
+        let plaintext = open(&key, &self.nonce, &self.associated_data(), &self.ciphertext)?;
+        if plaintext.len() != 4 + KEY_LENGTH {
+            return Err(GroupKeyError::Tampered);
+        }
+        let iteration = u32::from_be_bytes(plaintext[..4].try_into().expect("4 bytes"));
+        let mut chain_key = Zeroizing::new([0u8; KEY_LENGTH]);
+        chain_key.copy_from_slice(&plaintext[4..]);
+
+        Ok(ReceivedSenderKey {
+            room: self.room.clone(),
+            sender_public_key: self.sender_public_key.to_lowercase(),
+            generation: self.generation,
+            iteration,
+            chain_key,
+        })
+    }
+}
+
+/// A room message encrypted with its sender's key
+///
+/// Room messages are still signed as usual; this only hides the text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupCiphertext {
+    pub room: String,
+    #[serde(rename = "senderPublicKey")]
+    pub sender_public_key: String,
+    pub generation: u32,
+    pub iteration: u32,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl GroupCiphertext {
+    /// Fields the encryption is bound to
+    fn associated_data(&self) -> String {
+        format!(
+            "room_message:{}:{}:{}:{}",
+            self.room,
+            self.sender_public_key.to_lowercase(),
+            self.generation,
+            self.iteration
+        )
+    }
+}
+
+/// One member's keys for an encrypted room
+///
+/// Holds the member's own sender key and the keys received from the other
+/// members, and keeps them in step with the room's membership.
+pub struct RoomKeyRing {
+    room: String,
+    own_public_key: String,
+    own_key: SenderKey,
+    members: BTreeSet<String>,
+    received: HashMap<String, ReceivedSenderKey>,
+    padding: PaddingPolicy,
+    padding_stats: PaddingStats,
+}
+
+impl RoomKeyRing {
+    /// Start the key ring of `own_public_key` for a room, with no other
+    /// members yet
+    pub fn new(room: &str, own_public_key: &str) -> Self {
+        let own_public_key = own_public_key.to_lowercase();
+        Self {
+            room: room.to_string(),
+            own_key: SenderKey::generate(room, 0),
+            members: BTreeSet::from([own_public_key.clone()]),
+            own_public_key,
+            received: HashMap::new(),
+            padding: PaddingPolicy::default(),
+            padding_stats: PaddingStats::default(),
+        }
+    }
+
+    /// Pad outgoing messages with `padding` instead of the default buckets
+    pub fn with_padding(mut self, padding: PaddingPolicy) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Bytes added by padding to the messages encrypted so far
+    pub fn padding_stats(&self) -> PaddingStats {
+        self.padding_stats
+    }
+
+    /// Room the ring holds keys for
+    pub fn room(&self) -> &str {
+        &self.room
+    }
+
+    /// Current members, this one included
+    pub fn members(&self) -> impl Iterator<Item = &str> {
+        self.members.iter().map(String::as_str)
+    }
+
+    /// Generation of this member's own sender key
+    pub fn generation(&self) -> u32 {
+        self.own_key.generation()
+    }
+
+    /// Apply a new membership list and say who needs which key
+    ///
+    /// If anyone left, the own sender key is rotated and the keys of those
+    /// who left are dropped, so they can read nothing sent from now on; the
+    /// new key is wrapped for every remaining member. If members only
+    /// joined, the current key is wrapped for the new members alone.
+    ///
+    /// # Returns
+    /// The wrapped keys to deliver, one per member that needs it
+    pub fn set_members<I, S>(
+        &mut self,
+        members: I,
+        own_private_key: &PrivateKey,
+    ) -> Result<Vec<WrappedSenderKey>, GroupKeyError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut members: BTreeSet<String> = members
+            .into_iter()
+            .map(|key| key.as_ref().to_lowercase())
+            .collect();
+        for key in &members {
+            verifying_key(key)?;
+        }
+        members.insert(self.own_public_key.clone());
+
+        let left = self.members.difference(&members).count() > 0;
+        let recipients: Vec<String> = if left {
+            self.received.retain(|sender, _| members.contains(sender));
+            self.own_key = SenderKey::generate(&self.room, self.own_key.generation() + 1);
+            members.iter().cloned().collect()
+        } else {
+            members.difference(&self.members).cloned().collect()
+        };
+        self.members = members;
+
+        recipients
+            .iter()
+            .filter(|key| **key != self.own_public_key)
+            .map(|key| self.own_key.wrap_for(own_private_key, key))
+            .collect()
+    }
+
+    /// Encrypt a message to the room with the own sender key
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> GroupCiphertext {
+        self.padding_stats
+            .record(plaintext.len(), self.padding.padded_len(plaintext.len()));
+        self.own_key
+            .encrypt(&self.own_public_key, plaintext, &self.padding)
+    }
+
+    /// Accept a sender key another member wrapped for this one
+    ///
+    /// A key of a newer generation replaces the one held for that sender.
+    pub fn accept(
+        &mut self,
+        wrapped: &WrappedSenderKey,
+        own_private_key: &PrivateKey,
+    ) -> Result<(), GroupKeyError> {
+        if wrapped.room != self.room {
+            return Err(GroupKeyError::WrongRoom);
+        }
+        let sender = wrapped.sender_public_key.to_lowercase();
+        if !self.members.contains(&sender) || sender == self.own_public_key {
+            return Err(GroupKeyError::NotAMember);
+        }
+        if let Some(held) = self.received.get(&sender) {
+            if wrapped.generation < held.generation {
+                return Err(GroupKeyError::StaleGeneration {
+                    held: held.generation,
+                    got: wrapped.generation,
+                });
+            }
+        }
+        let key = wrapped.unwrap(own_private_key)?;
+        self.received.insert(sender, key);
+        Ok(())
+    }
+
+    /// Decrypt a message another member sent to the room
+    pub fn decrypt(
+        &mut self,
+        message: &GroupCiphertext,
+    ) -> Result<Zeroizing<Vec<u8>>, GroupKeyError> {
+        if message.room != self.room {
+            return Err(GroupKeyError::WrongRoom);
+        }
+        let sender = message.sender_public_key.to_lowercase();
+        if !self.members.contains(&sender) {
+            return Err(GroupKeyError::NotAMember);
+        }
+        self.received
+            .get_mut(&sender)
+            .ok_or(GroupKeyError::UnknownSender)?
+            .decrypt(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{derive_public_key, generate_private_key};
+
+    struct Member {
+        private_key: PrivateKey,
+        public_key: String,
+        ring: RoomKeyRing,
+    }
+
+    fn member() -> Member {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let ring = RoomKeyRing::new("general", &public_key);
+        Member {
+            private_key,
+            public_key,
+            ring,
+        }
+    }
+
+    /// Tell every member the room's membership and deliver the keys it asks for
+    fn set_members(members: &mut [&mut Member]) {
+        let keys: Vec<String> = members.iter().map(|m| m.public_key.clone()).collect();
+        let mut wrapped = Vec::new();
+        for member in members.iter_mut() {
+            wrapped.extend(member.ring.set_members(&keys, &member.private_key).unwrap());
+        }
+        for key in wrapped {
+            let recipient = members
+                .iter_mut()
+                .find(|m| m.public_key == key.recipient_public_key)
+                .unwrap();
+            recipient.ring.accept(&key, &recipient.private_key).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_members_read_each_others_messages() {
+        let (mut alice, mut bob, mut carol) = (member(), member(), member());
+        set_members(&mut [&mut alice, &mut bob, &mut carol]);
+
+        for text in ["first", "second"] {
+            let message = alice.ring.encrypt(text.as_bytes());
+            assert_eq!(
+                bob.ring.decrypt(&message).unwrap().as_slice(),
+                text.as_bytes()
+            );
+            assert_eq!(
+                carol.ring.decrypt(&message).unwrap().as_slice(),
+                text.as_bytes()
+            );
+        }
+        let message = carol.ring.encrypt(b"from carol");
+        assert_eq!(
+            alice.ring.decrypt(&message).unwrap().as_slice(),
+            b"from carol"
+        );
+    }
+
+    #[test]
+    fn test_leaving_member_rekeyed_out() {
+        let (mut alice, mut bob, mut carol) = (member(), member(), member());
+        set_members(&mut [&mut alice, &mut bob, &mut carol]);
+        let before = alice.ring.encrypt(b"before");
+        carol.ring.decrypt(&before).unwrap();
+
+        // Carol leaves: alice and bob rotate and only rewrap for each other
+        let keys = [alice.public_key.clone(), bob.public_key.clone()];
+        let from_alice = alice.ring.set_members(&keys, &alice.private_key).unwrap();
+        let from_bob = bob.ring.set_members(&keys, &bob.private_key).unwrap();
+        assert_eq!(alice.ring.generation(), 1);
+        assert_eq!(from_alice.len(), 1);
+        assert_eq!(from_alice[0].recipient_public_key, bob.public_key);
+        bob.ring.accept(&from_alice[0], &bob.private_key).unwrap();
+        alice.ring.accept(&from_bob[0], &alice.private_key).unwrap();
+
+        // A wrapped key that reaches carol anyway is useless to her
+        assert_eq!(
+            from_alice[0].unwrap(&carol.private_key).err(),
+            Some(GroupKeyError::NotForMe)
+        );
+
+        let after = alice.ring.encrypt(b"after");
+        assert_eq!(bob.ring.decrypt(&after).unwrap().as_slice(), b"after");
+        assert_eq!(
+            carol.ring.decrypt(&after).err(),
+            Some(GroupKeyError::StaleGeneration { held: 0, got: 1 })
+        );
+
+        // Bob no longer takes keys or messages from carol
+        let from_carol = carol.ring.encrypt(b"still here");
+        assert_eq!(
+            bob.ring.decrypt(&from_carol).err(),
+            Some(GroupKeyError::NotAMember)
+        );
+    }
+
+    #[test]
+    fn test_joining_member_cannot_read_earlier_messages() {
+        let (mut alice, mut bob, mut dave) = (member(), member(), member());
+        set_members(&mut [&mut alice, &mut bob]);
+        let earlier = alice.ring.encrypt(b"earlier");
+        bob.ring.decrypt(&earlier).unwrap();
+
+        // Dave joins: no rotation, the current key is wrapped for dave only
+        let keys = [
+            alice.public_key.clone(),
+            bob.public_key.clone(),
+            dave.public_key.clone(),
+        ];
+        let wrapped = alice.ring.set_members(&keys, &alice.private_key).unwrap();
+        assert_eq!(alice.ring.generation(), 0);
+        assert_eq!(wrapped.len(), 1);
+        assert_eq!(wrapped[0].recipient_public_key, dave.public_key);
+        dave.ring.set_members(&keys, &dave.private_key).unwrap();
+        dave.ring.accept(&wrapped[0], &dave.private_key).unwrap();
+
+        assert_eq!(
+            dave.ring.decrypt(&earlier).err(),
+            Some(GroupKeyError::Replayed)
+        );
+        let later = alice.ring.encrypt(b"later");
+        assert_eq!(dave.ring.decrypt(&later).unwrap().as_slice(), b"later");
+        assert_eq!(bob.ring.decrypt(&later).unwrap().as_slice(), b"later");
+    }
+
+    #[test]
+    fn test_payloads_are_server_blind_and_authenticated() {
+        let (mut alice, mut bob, mallory) = (member(), member(), member());
+        let keys = [alice.public_key.clone(), bob.public_key.clone()];
+        let wrapped = alice.ring.set_members(&keys, &alice.private_key).unwrap();
+        bob.ring.set_members(&keys, &bob.private_key).unwrap();
+
+        // Nothing the server relays contains the key or the text
+        let chain_key = hex::encode(alice.ring.own_key.chain_key.as_ref());
+        let relayed = serde_json::to_string(&wrapped[0]).unwrap();
+        assert!(!relayed.contains(&chain_key));
+        let message = alice.ring.encrypt(b"secret plans");
+        let relayed = serde_json::to_string(&message).unwrap();
+        assert!(!relayed.contains(&hex::encode(b"secret plans")));
+        assert!(!relayed.contains("secret plans"));
+
+        // A key re-signed by someone else is refused
+        let mut forged = wrapped[0].clone();
+        forged.sender_public_key = mallory.public_key.clone();
+        assert_eq!(
+            forged.unwrap(&bob.private_key).err(),
+            Some(GroupKeyError::Tampered)
+        );
+
+        bob.ring.accept(&wrapped[0], &bob.private_key).unwrap();
+        let mut altered = message.clone();
+        altered.iteration += 1;
+        assert_eq!(
+            bob.ring.decrypt(&altered).err(),
+            Some(GroupKeyError::Tampered)
+        );
+        assert_eq!(
+            bob.ring.decrypt(&message).unwrap().as_slice(),
+            b"secret plans"
+        );
+        assert_eq!(
+            bob.ring.decrypt(&message).err(),
+            Some(GroupKeyError::Replayed)
+        );
+    }
+
+    #[test]
+    fn test_lost_messages_skipped_within_limit() {
+        let (mut alice, mut bob) = (member(), member());
+        set_members(&mut [&mut alice, &mut bob]);
+
+        alice.ring.encrypt(b"lost");
+        let third = {
+            alice.ring.encrypt(b"lost too");
+            alice.ring.encrypt(b"third")
+        };
+        assert_eq!(bob.ring.decrypt(&third).unwrap().as_slice(), b"third");
+
+        let mut far = alice.ring.encrypt(b"far");
+        far.iteration += MAX_SKIPPED_ITERATIONS + 1;
+        assert!(matches!(
+            bob.ring.decrypt(&far),
+            Err(GroupKeyError::TooFarAhead { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ciphertext_length_only_shows_bucket() {
+        let (mut alice, mut bob) = (member(), member());
+        set_members(&mut [&mut alice, &mut bob]);
+
+        let short = alice.ring.encrypt(b"hi");
+        let long = alice.ring.encrypt(&[b'x'; 60]);
+        assert_eq!(short.ciphertext.len(), long.ciphertext.len());
+        assert_eq!(bob.ring.decrypt(&short).unwrap().as_slice(), b"hi");
+        assert_eq!(bob.ring.decrypt(&long).unwrap().as_slice(), &[b'x'; 60]);
+
+        let stats = alice.ring.padding_stats();
+        assert_eq!(stats.payloads, 2);
+        assert_eq!(stats.payload_bytes, 62);
+        assert_eq!(stats.padded_bytes, 128);
+
+        // Without padding the lengths differ again
+        let mut alice = Member {
+            ring: alice.ring.with_padding(PaddingPolicy::disabled()),
+            ..alice
+        };
+        let short = alice.ring.encrypt(b"hi");
+        let long = alice.ring.encrypt(&[b'x'; 60]);
+        assert!(short.ciphertext.len() < long.ciphertext.len());
+        assert_eq!(bob.ring.decrypt(&short).unwrap().as_slice(), b"hi");
+    }
+
+    #[test]
+    fn test_older_generation_not_accepted_over_newer() {
+        let (mut alice, mut bob, carol) = (member(), member(), member());
+        let all = [
+            alice.public_key.clone(),
+            bob.public_key.clone(),
+            carol.public_key.clone(),
+        ];
+        bob.ring.set_members(&all, &bob.private_key).unwrap();
+        let old = alice.ring.set_members(&all, &alice.private_key).unwrap();
+        let old = old
+            .into_iter()
+            .find(|k| k.recipient_public_key == bob.public_key)
+            .unwrap();
+
+        let pair = [alice.public_key.clone(), bob.public_key.clone()];
+        let new = alice.ring.set_members(&pair, &alice.private_key).unwrap();
+        bob.ring.set_members(&pair, &bob.private_key).unwrap();
+        bob.ring.accept(&new[0], &bob.private_key).unwrap();
+        assert_eq!(
+            bob.ring.accept(&old, &bob.private_key),
+            Err(GroupKeyError::StaleGeneration { held: 1, got: 0 })
+        );
+    }
+}
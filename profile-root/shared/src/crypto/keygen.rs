@@ -2,6 +2,7 @@
 
 use crate::crypto::{PrivateKey, PublicKey};
 use crate::errors::CryptoError;
+use bip39::Mnemonic;
 use ed25519_dalek::{SigningKey, VerifyingKey};
 use rand::rngs::OsRng;
 use rand::RngCore;
@@ -71,6 +72,72 @@ pub fn derive_public_key(private_key: &PrivateKey) -> Result<PublicKey, CryptoEr
     PublicKey::new(public_key)
 }
 
+/// Number of words in a private key's BIP39 recovery phrase
+///
+/// A 32-byte key is exactly 256 bits of BIP39 entropy, which encodes to
+/// 24 words (including the 8-bit checksum).
+pub const MNEMONIC_WORD_COUNT: usize = 24;
+
+/// Export a private key as a 24-word BIP39 mnemonic (English word list)
+///
+/// The key bytes are used directly as the mnemonic's entropy, so the phrase
+/// round-trips through [`derive_private_key_from_mnemonic`] to the same key.
+pub fn export_private_key_mnemonic(private_key: &PrivateKey) -> Result<String, CryptoError> {
+    if private_key.len() != 32 {
+        return Err(CryptoError::InvalidKeyFormat(format!(
+            "Expected 32-byte private key, got {}",
+            private_key.len()
+        )));
+    }
+
+    let mnemonic = Mnemonic::from_entropy(private_key.as_slice())
+        .map_err(|e| CryptoError::InvalidKeyFormat(e.to_string()))?;
+    Ok(mnemonic.to_string())
+}
+
+/// Recover a private key from its 24-word BIP39 mnemonic
+///
+/// Words are matched case-insensitively and may be separated by any
+/// whitespace. Error messages refer to words by position only, so a typo
+/// never echoes part of the phrase back.
+pub fn derive_private_key_from_mnemonic(phrase: &str) -> Result<PrivateKey, CryptoError> {
+    let words: Vec<String> = phrase.split_whitespace().map(str::to_lowercase).collect();
+    if words.len() != MNEMONIC_WORD_COUNT {
+        return Err(CryptoError::InvalidMnemonic(format!(
+            "Expected {} words, got {}",
+            MNEMONIC_WORD_COUNT,
+            words.len()
+        )));
+    }
+
+    let mnemonic = Mnemonic::parse_normalized(&words.join(" ")).map_err(|e| {
+        CryptoError::InvalidMnemonic(match e {
+            bip39::Error::UnknownWord(index) => {
+                format!("Word {} is not in the BIP39 English word list", index + 1)
+            }
+            bip39::Error::InvalidChecksum => {
+                "Checksum does not match - check the words and their order".to_string()
+            }
+            other => other.to_string(),
+        })
+    })?;
+
+    let (mut entropy, len) = mnemonic.to_entropy_array();
+    let key_bytes = entropy[..len].to_vec();
+    entropy.zeroize();
+    let private_key = PrivateKey::new(key_bytes);
+
+    // Reject the all-zero key ("abandon abandon ... art") like key import does
+    if private_key.as_slice().iter().all(|&b| b == 0) {
+        return Err(CryptoError::InvalidMnemonic(
+            "Phrase encodes an all-zero key, which is not valid".into(),
+        ));
+    }
+
+    derive_public_key(&private_key)?;
+    Ok(private_key)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,4 +265,53 @@ mod tests {
             "Derived public key should not be all zeros"
         );
     }
+
+    // BIP39 reference vector for 32 bytes of 0x7f
+    const VECTOR_PHRASE: &str = "legal winner thank year wave sausage worth useful legal winner \
+        thank year wave sausage worth useful legal winner thank year wave sausage worth title";
+
+    #[test]
+    fn test_mnemonic_matches_bip39_vector() {
+        let private_key = PrivateKey::new(vec![0x7f; 32]);
+        let phrase = export_private_key_mnemonic(&private_key).unwrap();
+        assert_eq!(phrase, VECTOR_PHRASE);
+        assert_eq!(phrase.split(' ').count(), MNEMONIC_WORD_COUNT);
+    }
+
+    #[test]
+    fn test_mnemonic_round_trip() {
+        let private_key = generate_private_key().unwrap();
+        let phrase = export_private_key_mnemonic(&private_key).unwrap();
+
+        // Case and spacing don't matter when typing the phrase back in
+        let retyped = format!("  {}\n", phrase.to_uppercase().replace(' ', "  "));
+        let recovered = derive_private_key_from_mnemonic(&retyped).unwrap();
+        assert_eq!(recovered.as_slice(), private_key.as_slice());
+    }
+
+    #[test]
+    fn test_mnemonic_errors_name_the_problem() {
+        let words: Vec<&str> = VECTOR_PHRASE.split_whitespace().collect();
+
+        let short = words[..12].join(" ");
+        let err = derive_private_key_from_mnemonic(&short).unwrap_err();
+        assert!(err.to_string().contains("Expected 24 words, got 12"));
+
+        let mut typo = words.clone();
+        typo[4] = "wavy";
+        let err = derive_private_key_from_mnemonic(&typo.join(" ")).unwrap_err();
+        assert!(err.to_string().contains("Word 5"));
+        assert!(!err.to_string().contains("wavy"));
+
+        let mut swapped = words.clone();
+        swapped.swap(0, 2);
+        let err = derive_private_key_from_mnemonic(&swapped.join(" ")).unwrap_err();
+        assert!(err.to_string().contains("Checksum"));
+
+        let zero_phrase = format!("{} art", ["abandon"; 23].join(" "));
+        assert!(matches!(
+            derive_private_key_from_mnemonic(&zero_phrase),
+            Err(CryptoError::InvalidMnemonic(_))
+        ));
+    }
 }
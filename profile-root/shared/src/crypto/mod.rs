@@ -4,16 +4,27 @@
 //! - Key generation and derivation
 //! - Message signing (Story 1.5+)
 //! - Signature verification (Story 3.x+)
+//! - Sender-key encryption for rooms ([`group_keys`])
+//! - Double-ratchet sessions for direct messages ([`ratchet`])
+//! - Padding of encrypted payloads to size buckets ([`padding`])
+//! - Signed identity key rotation ([`rollover`])
 //!
 //! All operations use ed25519-dalek 2.1+ for deterministic, industry-standard signing.
 
+pub mod group_keys;
 pub mod keygen;
+pub mod padding;
+pub mod ratchet;
+pub mod rollover;
 pub mod signing;
 pub mod verification;
 
-pub use keygen::{derive_public_key, generate_private_key};
+pub use keygen::{
+    derive_private_key_from_mnemonic, derive_public_key, export_private_key_mnemonic,
+    generate_private_key, MNEMONIC_WORD_COUNT,
+};
 pub use signing::sign_message;
-pub use verification::verify_signature;
+pub use verification::{verify_signature, verify_signatures_batch};
 
 /// Secure private key wrapper with safe debug implementation
 ///
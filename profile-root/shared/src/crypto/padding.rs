@@ -0,0 +1,214 @@
+//! Padding of end-to-end encrypted payloads
+//!
+//! Ciphertext is as long as its plaintext, so anyone relaying it learns
+//! roughly how long each message is. Before encryption, payloads are padded
+//! up to the smallest of a few bucket sizes, leaving observers only the
+//! bucket. Payloads larger than every bucket are padded to a multiple of the
+//! largest one.
+//!
+//! The padding is a `0x80` marker followed by zeros (ISO/IEC 7816-4), so it
+//! is removed without knowing the bucket sizes the sender used. A policy
+//! with no buckets adds only the marker.
+
+use crate::config::padding::{DEFAULT_BUCKETS, MAX_BUCKETS, MAX_BUCKET_SIZE};
+use crate::errors::GroupKeyError;
+use zeroize::Zeroizing;
+
+const MARKER: u8 = 0x80;
+
+/// Sizes payloads are padded up to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaddingPolicy {
+    buckets: Vec<usize>,
+}
+
+impl PaddingPolicy {
+    /// Pad to the given bucket sizes, in bytes
+    ///
+    /// An empty list disables padding.
+    ///
+    /// # Errors
+    /// Returns an error for more than [`MAX_BUCKETS`] buckets, or a bucket
+    /// that is zero or larger than [`MAX_BUCKET_SIZE`]
+    pub fn new(buckets: &[usize]) -> Result<Self, GroupKeyError> {
+        if buckets.len() > MAX_BUCKETS {
+            return Err(GroupKeyError::InvalidPadding(format!(
+                "at most {} buckets are allowed",
+                MAX_BUCKETS
+            )));
+        }
+        if let Some(size) = buckets
+            .iter()
+            .find(|&&size| size == 0 || size > MAX_BUCKET_SIZE)
+        {
+            return Err(GroupKeyError::InvalidPadding(format!(
+                "bucket of {} bytes is not between 1 and {}",
+                size, MAX_BUCKET_SIZE
+            )));
+        }
+        let mut buckets = buckets.to_vec();
+        buckets.sort_unstable();
+        buckets.dedup();
+        Ok(Self { buckets })
+    }
+
+    /// Add only the padding marker
+    pub fn disabled() -> Self {
+        Self {
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Whether payloads are padded to buckets
+    pub fn is_enabled(&self) -> bool {
+        !self.buckets.is_empty()
+    }
+
+    /// Bucket sizes, smallest first
+    pub fn buckets(&self) -> &[usize] {
+        &self.buckets
+    }
+
+    /// Length a payload of `len` bytes is padded to
+    pub fn padded_len(&self, len: usize) -> usize {
+        let needed = len + 1;
+        match self.buckets.iter().find(|&&size| size >= needed) {
+            Some(&size) => size,
+            None => match self.buckets.last() {
+                Some(&largest) => needed.div_ceil(largest) * largest,
+                None => needed,
+            },
+        }
+    }
+
+    /// Pad a payload for encryption
+    pub fn pad(&self, payload: &[u8]) -> Zeroizing<Vec<u8>> {
+        let mut padded = Zeroizing::new(Vec::with_capacity(self.padded_len(payload.len())));
+        padded.extend_from_slice(payload);
+        padded.push(MARKER);
+        padded.resize(self.padded_len(payload.len()), 0);
+        padded
+    }
+}
+
+impl Default for PaddingPolicy {
+    /// Pad to [`DEFAULT_BUCKETS`]
+    fn default() -> Self {
+        Self {
+            buckets: DEFAULT_BUCKETS.to_vec(),
+        }
+    }
+}
+
+/// Remove the padding from a decrypted payload
+///
+/// # Errors
+/// Returns an error if the payload has no padding marker
+pub fn unpad(padded: &[u8]) -> Result<&[u8], GroupKeyError> {
+    match padded.iter().rposition(|&byte| byte != 0) {
+        Some(end) if padded[end] == MARKER => Ok(&padded[..end]),
+        _ => Err(GroupKeyError::InvalidPadding(
+            "payload has no padding marker".to_string(),
+        )),
+    }
+}
+
+/// Bytes added by padding, for judging what a policy costs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PaddingStats {
+    /// Payloads padded
+    pub payloads: u64,
+    /// Their total size before padding
+    pub payload_bytes: u64,
+    /// Their total size after padding
+    pub padded_bytes: u64,
+}
+
+impl PaddingStats {
+    /// Count one padded payload
+    pub fn record(&mut self, payload_len: usize, padded_len: usize) {
+        self.payloads += 1;
+        self.payload_bytes += payload_len as u64;
+        self.padded_bytes += padded_len as u64;
+    }
+
+    /// Bytes added by padding in total
+    pub fn overhead_bytes(&self) -> u64 {
+        self.padded_bytes.saturating_sub(self.payload_bytes)
+    }
+
+    /// Bytes added per byte of payload (0.0 before any payload)
+    pub fn overhead_ratio(&self) -> f64 {
+        if self.payload_bytes == 0 {
+            return 0.0;
+        }
+        self.overhead_bytes() as f64 / self.payload_bytes as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payloads_padded_to_buckets() {
+        let policy = PaddingPolicy::new(&[256, 64]).unwrap();
+        assert_eq!(policy.buckets(), &[64, 256]);
+
+        for (len, padded) in [
+            (0, 64),
+            (63, 64),
+            (64, 256),
+            (255, 256),
+            (256, 512),
+            (600, 768),
+        ] {
+            let payload = vec![0xab; len];
+            let padded_payload = policy.pad(&payload);
+            assert_eq!(padded_payload.len(), padded, "payload of {} bytes", len);
+            assert_eq!(unpad(&padded_payload).unwrap(), payload.as_slice());
+        }
+
+        // Trailing zeros in the payload survive
+        let payload = [1, 0, 0];
+        assert_eq!(unpad(&policy.pad(&payload)).unwrap(), &payload);
+    }
+
+    #[test]
+    fn test_disabled_policy_adds_only_the_marker() {
+        let policy = PaddingPolicy::new(&[]).unwrap();
+        assert!(!policy.is_enabled());
+        assert_eq!(policy, PaddingPolicy::disabled());
+        assert_eq!(policy.pad(b"hello").len(), 6);
+        assert_eq!(unpad(&policy.pad(b"hello")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_invalid_policies_and_padding_refused() {
+        assert!(PaddingPolicy::new(&[0]).is_err());
+        assert!(PaddingPolicy::new(&[MAX_BUCKET_SIZE + 1]).is_err());
+        assert!(PaddingPolicy::new(&[1; MAX_BUCKETS + 1]).is_err());
+
+        assert!(unpad(&[]).is_err());
+        assert!(unpad(&[0, 0]).is_err());
+        assert!(unpad(b"no marker").is_err());
+    }
+
+    #[test]
+    fn test_default_buckets_fit_longest_message() {
+        let policy = PaddingPolicy::default();
+        let longest = crate::config::message::MAX_MESSAGE_LENGTH;
+        assert_eq!(policy.padded_len(longest), *DEFAULT_BUCKETS.last().unwrap());
+    }
+
+    #[test]
+    fn test_overhead_stats() {
+        let mut stats = PaddingStats::default();
+        assert_eq!(stats.overhead_ratio(), 0.0);
+        stats.record(48, 64);
+        stats.record(16, 64);
+        assert_eq!(stats.payloads, 2);
+        assert_eq!(stats.overhead_bytes(), 64);
+        assert_eq!(stats.overhead_ratio(), 1.0);
+    }
+}
@@ -0,0 +1,160 @@
+//! Signed announcements of identity key rotation
+//!
+//! A user replacing their identity key signs a [`KeyRollover`] with both
+//! keys. The old key's signature vouches for the new key, so peers who
+//! trusted the old one can carry that trust over; the new key's signature
+//! proves its holder agreed, so nobody can claim someone else's key as
+//! their own. Both sign the same bytes, [`crate::canonical::key_rollover`].
+//!
+//! The server checks a rollover before broadcasting it and every client
+//! checks it again with [`KeyRollover::verify`] before acting on it.
+
+use crate::canonical;
+use crate::crypto::{derive_public_key, sign_message, verify_signature, PrivateKey, PublicKey};
+use crate::errors::CryptoError;
+use crate::protocol::lowercase_hex;
+use serde::{Deserialize, Serialize};
+
+/// Announcement that `old_public_key` was replaced by `new_public_key`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRollover {
+    /// The key being retired (hex)
+    #[serde(
+        rename = "oldPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub old_public_key: String,
+    /// The key replacing it (hex)
+    #[serde(
+        rename = "newPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub new_public_key: String,
+    /// When the rollover was signed (RFC 3339)
+    pub timestamp: String,
+    /// Signature by the old key (hex)
+    #[serde(
+        rename = "oldSignature",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub old_signature: String,
+    /// Signature by the new key (hex)
+    #[serde(
+        rename = "newSignature",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub new_signature: String,
+}
+
+impl KeyRollover {
+    /// Sign a rollover from `old_key` to `new_key`
+    ///
+    /// # Arguments
+    /// * `old_key` - The identity key being retired
+    /// * `new_key` - The identity key replacing it
+    /// * `timestamp` - When the rollover is made (RFC 3339)
+    pub fn sign(
+        old_key: &PrivateKey,
+        new_key: &PrivateKey,
+        timestamp: &str,
+    ) -> Result<Self, CryptoError> {
+        let old_public_key = hex::encode(derive_public_key(old_key)?);
+        let new_public_key = hex::encode(derive_public_key(new_key)?);
+        let signed = canonical::key_rollover(&old_public_key, &new_public_key, timestamp);
+        Ok(Self {
+            old_signature: hex::encode(sign_message(old_key, &signed)?),
+            new_signature: hex::encode(sign_message(new_key, &signed)?),
+            old_public_key,
+            new_public_key,
+            timestamp: timestamp.to_string(),
+        })
+    }
+
+    /// The bytes both signatures cover
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        canonical::key_rollover(&self.old_public_key, &self.new_public_key, &self.timestamp)
+    }
+
+    /// Check both signatures
+    ///
+    /// # Errors
+    /// Returns an error if the keys are the same or malformed, or either
+    /// signature doesn't verify
+    pub fn verify(&self) -> Result<(), CryptoError> {
+        if self.old_public_key == self.new_public_key {
+            return Err(CryptoError::InvalidKey(
+                "Rollover must name a different new key".into(),
+            ));
+        }
+        let signed = self.signed_bytes();
+        for (key, signature) in [
+            (&self.old_public_key, &self.old_signature),
+            (&self.new_public_key, &self.new_signature),
+        ] {
+            let key = hex::decode(key)
+                .map_err(|e| CryptoError::InvalidKey(e.to_string()))
+                .and_then(PublicKey::new)?;
+            let signature =
+                hex::decode(signature).map_err(|e| CryptoError::InvalidSignature(e.to_string()))?;
+            verify_signature(&key, &signed, &signature)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::generate_private_key;
+
+    const TIMESTAMP: &str = "2026-01-05T09:00:00Z";
+
+    #[test]
+    fn test_rollover_signed_by_both_keys_verifies() {
+        let (old, new) = (
+            generate_private_key().unwrap(),
+            generate_private_key().unwrap(),
+        );
+        let rollover = KeyRollover::sign(&old, &new, TIMESTAMP).unwrap();
+        assert_eq!(
+            rollover.old_public_key,
+            hex::encode(derive_public_key(&old).unwrap())
+        );
+        assert!(rollover.verify().is_ok());
+
+        // Hex is read in any case
+        let mut json = serde_json::to_value(&rollover).unwrap();
+        json["oldPublicKey"] = rollover.old_public_key.to_uppercase().into();
+        json["newSignature"] = rollover.new_signature.to_uppercase().into();
+        let parsed: KeyRollover = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, rollover);
+    }
+
+    #[test]
+    fn test_tampered_rollover_refused() {
+        let (old, new, other) = (
+            generate_private_key().unwrap(),
+            generate_private_key().unwrap(),
+            generate_private_key().unwrap(),
+        );
+        let rollover = KeyRollover::sign(&old, &new, TIMESTAMP).unwrap();
+
+        // Pointing the old key's vouch at a key its holder doesn't have
+        let mut redirected = rollover.clone();
+        redirected.new_public_key = hex::encode(derive_public_key(&other).unwrap());
+        assert!(redirected.verify().is_err());
+
+        // Only the new key signed: claiming an identity without its key
+        let forged = KeyRollover::sign(&other, &new, TIMESTAMP).unwrap();
+        let mut claimed = rollover.clone();
+        claimed.old_signature = forged.old_signature;
+        assert!(claimed.verify().is_err());
+
+        let mut backdated = rollover.clone();
+        backdated.timestamp = "2020-01-01T00:00:00Z".to_string();
+        assert!(backdated.verify().is_err());
+
+        let same = KeyRollover::sign(&old, &old, TIMESTAMP).unwrap();
+        assert!(matches!(same.verify(), Err(CryptoError::InvalidKey(_))));
+    }
+}
@@ -7,6 +7,11 @@ use super::signing::serialize_message_to_canonical_json;
 use crate::errors::CryptoError;
 use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 
+/// One signature to check in [`verify_signatures_batch`]
+///
+/// Fields are the arguments [`verify_signature`] takes.
+pub type SignedItem<'a> = (&'a crate::crypto::PublicKey, &'a [u8], &'a [u8]);
+
 /// Verify a message signature using canonical JSON serialization
 ///
 /// This function implements deterministic verification by:
@@ -29,6 +34,27 @@ pub fn verify_signature(
         })
 }
 
+/// Verify many signatures at once
+///
+/// Checks the same canonical JSON as [`verify_signature`], but in a single
+/// ed25519 batch equation, which is roughly twice as fast per signature for
+/// large batches. A failure only says that some signature is bad, not
+/// which; callers that need to know verify the items one by one.
+pub fn verify_signatures_batch(items: &[SignedItem<'_>]) -> Result<(), CryptoError> {
+    let mut messages = Vec::with_capacity(items.len());
+    let mut signatures = Vec::with_capacity(items.len());
+    let mut verifying_keys = Vec::with_capacity(items.len());
+    for (public_key, message, signature) in items {
+        messages.push(serialize_message_to_canonical_json(message)?);
+        signatures.push(convert_signature_to_ed25519_format(signature)?);
+        verifying_keys.push(convert_public_key_to_verifying_key(public_key)?);
+    }
+    let messages: Vec<&[u8]> = messages.iter().map(String::as_bytes).collect();
+
+    ed25519_dalek::verify_batch(&messages, &signatures, &verifying_keys)
+        .map_err(|e| CryptoError::VerificationFailed(format!("Batch verification failed: {}", e)))
+}
+
 /// Convert public key bytes to VerifyingKey
 fn convert_public_key_to_verifying_key(
     public_key: &crate::crypto::PublicKey,
@@ -98,6 +124,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_verify_signatures_batch() {
+        use crate::crypto::keygen::{derive_public_key, generate_private_key};
+
+        let keys: Vec<_> = (0..4)
+            .map(|_| {
+                let private_key = generate_private_key().unwrap();
+                let public_key = derive_public_key(&private_key).unwrap();
+                (private_key, public_key)
+            })
+            .collect();
+        let messages: Vec<String> = (0..4).map(|i| format!("message {}", i)).collect();
+        let signatures: Vec<Vec<u8>> = keys
+            .iter()
+            .zip(&messages)
+            .map(|((private_key, _), message)| {
+                sign_message(private_key, message.as_bytes()).unwrap()
+            })
+            .collect();
+
+        let items: Vec<SignedItem<'_>> = keys
+            .iter()
+            .zip(&messages)
+            .zip(&signatures)
+            .map(|(((_, public_key), message), signature)| {
+                (public_key, message.as_bytes(), signature.as_slice())
+            })
+            .collect();
+        assert!(verify_signatures_batch(&items).is_ok());
+        assert!(verify_signatures_batch(&[]).is_ok());
+
+        // One signature over the wrong message fails the whole batch
+        let mut tampered = items.clone();
+        tampered[2].1 = b"message 3";
+        assert!(verify_signatures_batch(&tampered).is_err());
+    }
+
     #[test]
     fn test_verify_signature_wrong_message() {
         let private_key = PrivateKey::new(vec![42u8; 32]);
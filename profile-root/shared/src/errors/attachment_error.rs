@@ -0,0 +1,70 @@
+//! Attachment transfer error types
+
+/// Reasons an attachment offer, accept, chunk or completion is refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttachmentError {
+    /// File exceeds the maximum attachment size
+    TooLarge { size: u64, max: u64 },
+    /// Manifest is inconsistent: empty file, bad name, wrong chunk layout
+    InvalidManifest { details: String },
+    /// No transfer with this id involves the user
+    UnknownTransfer,
+    /// Transfer id is already used by a transfer in progress
+    DuplicateTransfer,
+    /// The sender already has the maximum number of transfers in progress
+    TooManyTransfers { max: usize },
+    /// Chunk is out of order or the recipient hasn't asked for it yet
+    ChunkNotRequested { index: u32 },
+    /// Chunk data isn't hex or doesn't have the length the manifest gives
+    InvalidChunk { index: u32 },
+    /// Transfer was completed before every chunk was sent
+    Incomplete { sent: u32, total: u32 },
+}
+
+impl std::fmt::Display for AttachmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AttachmentError::TooLarge { size, max } => {
+                write!(f, "Attachment size {} exceeds maximum {}", size, max)
+            }
+            AttachmentError::InvalidManifest { details } => {
+                write!(f, "Invalid attachment manifest: {}", details)
+            }
+            AttachmentError::UnknownTransfer => write!(f, "No such attachment transfer"),
+            AttachmentError::DuplicateTransfer => {
+                write!(f, "Attachment transfer id is already in use")
+            }
+            AttachmentError::TooManyTransfers { max } => {
+                write!(f, "At most {} attachment transfers at a time", max)
+            }
+            AttachmentError::ChunkNotRequested { index } => {
+                write!(f, "Chunk {} was not requested", index)
+            }
+            AttachmentError::InvalidChunk { index } => {
+                write!(f, "Chunk {} does not match the manifest", index)
+            }
+            AttachmentError::Incomplete { sent, total } => {
+                write!(f, "Only {} of {} chunks were sent", sent, total)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AttachmentError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachment_error_display() {
+        assert_eq!(
+            AttachmentError::TooLarge { size: 10, max: 5 }.to_string(),
+            "Attachment size 10 exceeds maximum 5"
+        );
+        assert_eq!(
+            AttachmentError::Incomplete { sent: 2, total: 3 }.to_string(),
+            "Only 2 of 3 chunks were sent"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Backup-specific error types
+
+/// Errors that can occur when storing an encrypted backup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupError {
+    /// Backup exceeds the maximum stored size
+    TooLarge { size: usize, max: usize },
+    /// Backup blob is empty or not valid hex
+    InvalidBlob,
+    /// Version is not newer than the stored backup's
+    StaleVersion { current: u64 },
+    /// Server has reached the maximum number of stored backups
+    StorageFull,
+}
+
+impl std::fmt::Display for BackupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackupError::TooLarge { size, max } => {
+                write!(f, "Backup size {} exceeds maximum {}", size, max)
+            }
+            BackupError::InvalidBlob => write!(f, "Backup must be non-empty hex"),
+            BackupError::StaleVersion { current } => {
+                write!(f, "Backup version must be greater than {}", current)
+            }
+            BackupError::StorageFull => write!(f, "Backup storage is full"),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_error_display() {
+        assert_eq!(
+            BackupError::TooLarge { size: 10, max: 5 }.to_string(),
+            "Backup size 10 exceeds maximum 5"
+        );
+        assert_eq!(
+            BackupError::StaleVersion { current: 3 }.to_string(),
+            "Backup version must be greater than 3"
+        );
+    }
+}
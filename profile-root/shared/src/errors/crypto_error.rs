@@ -10,6 +10,7 @@ pub enum CryptoError {
     InvalidKey(String),
     InvalidSignature(String),
     SerializationError(String),
+    InvalidMnemonic(String),
 }
 
 impl fmt::Display for CryptoError {
@@ -25,6 +26,7 @@ impl fmt::Display for CryptoError {
             CryptoError::InvalidKey(msg) => write!(f, "Invalid key: {}", msg),
             CryptoError::InvalidSignature(msg) => write!(f, "Invalid signature: {}", msg),
             CryptoError::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
+            CryptoError::InvalidMnemonic(msg) => write!(f, "Invalid recovery phrase: {}", msg),
         }
     }
 }
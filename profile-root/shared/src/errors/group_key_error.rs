@@ -0,0 +1,73 @@
+//! Room group key error types
+
+/// Errors that can occur when distributing or using room sender keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupKeyError {
+    /// A public key is not 64 hex characters of a valid key
+    InvalidKey,
+    /// A hex field could not be decoded or has the wrong length
+    Malformed(String),
+    /// The payload belongs to another room
+    WrongRoom,
+    /// The wrapped key is addressed to someone else
+    NotForMe,
+    /// The sender is not a member of the room
+    NotAMember,
+    /// No sender key has been received from this sender yet
+    UnknownSender,
+    /// The payload uses an older key generation than the one held
+    StaleGeneration { held: u32, got: u32 },
+    /// The message was already read, or is older than the key held
+    Replayed,
+    /// The message is further ahead in the chain than is accepted
+    TooFarAhead { skipped: u32, max: u32 },
+    /// Signature, decryption or key agreement failed
+    Tampered,
+    /// A padding setting is invalid, or a payload's padding is malformed
+    InvalidPadding(String),
+}
+
+impl std::fmt::Display for GroupKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GroupKeyError::InvalidKey => write!(f, "Invalid public key"),
+            GroupKeyError::Malformed(field) => write!(f, "Malformed {}", field),
+            GroupKeyError::WrongRoom => write!(f, "Key belongs to another room"),
+            GroupKeyError::NotForMe => write!(f, "Key is addressed to another member"),
+            GroupKeyError::NotAMember => write!(f, "Sender is not a member of this room"),
+            GroupKeyError::UnknownSender => write!(f, "No key received from this sender"),
+            GroupKeyError::StaleGeneration { held, got } => write!(
+                f,
+                "Key generation {} is older than the one held ({})",
+                got, held
+            ),
+            GroupKeyError::Replayed => write!(f, "Message was already read"),
+            GroupKeyError::TooFarAhead { skipped, max } => write!(
+                f,
+                "Message skips {} keys, more than the {} allowed",
+                skipped, max
+            ),
+            GroupKeyError::Tampered => write!(f, "Payload failed authentication"),
+            GroupKeyError::InvalidPadding(msg) => write!(f, "Invalid padding: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for GroupKeyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_key_error_display() {
+        assert_eq!(
+            GroupKeyError::StaleGeneration { held: 2, got: 1 }.to_string(),
+            "Key generation 1 is older than the one held (2)"
+        );
+        assert_eq!(
+            GroupKeyError::Malformed("nonce".to_string()).to_string(),
+            "Malformed nonce"
+        );
+    }
+}
@@ -1,7 +1,19 @@
 //! Error types for cryptographic operations
 
+pub mod attachment_error;
+pub mod backup_error;
 pub mod crypto_error;
+pub mod group_key_error;
 pub mod lobby_error;
+pub mod nickname_error;
+pub mod ratchet_error;
+pub mod room_error;
 
+pub use attachment_error::AttachmentError;
+pub use backup_error::BackupError;
 pub use crypto_error::CryptoError;
+pub use group_key_error::GroupKeyError;
 pub use lobby_error::LobbyError;
+pub use nickname_error::NicknameError;
+pub use ratchet_error::RatchetError;
+pub use room_error::RoomError;
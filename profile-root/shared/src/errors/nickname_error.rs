@@ -0,0 +1,38 @@
+//! Nickname-specific error types
+
+/// Errors that can occur when setting a nickname
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NicknameError {
+    /// Nickname is too long or contains invalid characters
+    InvalidNickname,
+    /// Another online user already has this nickname
+    NicknameTaken,
+}
+
+impl std::fmt::Display for NicknameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NicknameError::InvalidNickname => write!(f, "Invalid nickname"),
+            NicknameError::NicknameTaken => write!(f, "Nickname is already taken"),
+        }
+    }
+}
+
+impl std::error::Error for NicknameError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nickname_error_display() {
+        assert_eq!(
+            NicknameError::InvalidNickname.to_string(),
+            "Invalid nickname"
+        );
+        assert_eq!(
+            NicknameError::NicknameTaken.to_string(),
+            "Nickname is already taken"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Double-ratchet session error types
+
+/// Errors from setting up a ratchet session or reading its messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RatchetError {
+    /// A public key is not 64 hex characters of a valid key
+    InvalidKey,
+    /// A hex field could not be decoded or has the wrong length
+    Malformed(String),
+    /// The message belongs to another session with the peer
+    WrongSession,
+    /// The message was already read
+    Replayed,
+    /// The message is further ahead in its chain than is accepted
+    TooFarAhead { skipped: u32, max: u32 },
+    /// Decryption or key agreement failed
+    Tampered,
+    /// The decrypted payload's padding is malformed
+    InvalidPadding(String),
+}
+
+impl std::fmt::Display for RatchetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RatchetError::InvalidKey => write!(f, "Invalid public key"),
+            RatchetError::Malformed(field) => write!(f, "Malformed {}", field),
+            RatchetError::WrongSession => write!(f, "Message belongs to another session"),
+            RatchetError::Replayed => write!(f, "Message was already read"),
+            RatchetError::TooFarAhead { skipped, max } => write!(
+                f,
+                "Message skips {} keys, more than the {} allowed",
+                skipped, max
+            ),
+            RatchetError::Tampered => write!(f, "Payload failed authentication"),
+            RatchetError::InvalidPadding(msg) => write!(f, "Invalid padding: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for RatchetError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ratchet_error_display() {
+        assert_eq!(
+            RatchetError::TooFarAhead {
+                skipped: 5000,
+                max: 1000
+            }
+            .to_string(),
+            "Message skips 5000 keys, more than the 1000 allowed"
+        );
+        assert_eq!(
+            RatchetError::Malformed("session".to_string()).to_string(),
+            "Malformed session"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Room-specific error types
+
+/// Errors that can occur during room operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomError {
+    /// Room does not exist
+    RoomNotFound,
+    /// A room with this name already exists
+    RoomAlreadyExists,
+    /// User is not a member of the room
+    NotAMember,
+    /// Room name is empty, too long, or contains invalid characters
+    InvalidRoomName,
+    /// Room has reached maximum membership
+    RoomFull,
+    /// Server has reached the maximum number of rooms
+    TooManyRooms,
+}
+
+impl std::fmt::Display for RoomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoomError::RoomNotFound => write!(f, "Room not found"),
+            RoomError::RoomAlreadyExists => write!(f, "Room already exists"),
+            RoomError::NotAMember => write!(f, "Not a member of this room"),
+            RoomError::InvalidRoomName => write!(f, "Invalid room name"),
+            RoomError::RoomFull => write!(f, "Room is full"),
+            RoomError::TooManyRooms => write!(f, "Too many rooms"),
+        }
+    }
+}
+
+impl std::error::Error for RoomError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_room_error_display() {
+        assert_eq!(RoomError::RoomNotFound.to_string(), "Room not found");
+        assert_eq!(
+            RoomError::RoomAlreadyExists.to_string(),
+            "Room already exists"
+        );
+        assert_eq!(
+            RoomError::NotAMember.to_string(),
+            "Not a member of this room"
+        );
+        assert_eq!(RoomError::InvalidRoomName.to_string(), "Invalid room name");
+        assert_eq!(RoomError::RoomFull.to_string(), "Room is full");
+    }
+
+    #[test]
+    fn test_room_error_equality() {
+        assert_eq!(RoomError::RoomNotFound, RoomError::RoomNotFound);
+        assert_ne!(RoomError::RoomNotFound, RoomError::NotAMember);
+    }
+}
@@ -1,14 +1,21 @@
 //! Profile shared cryptographic library.
 
+pub mod canonical;
+pub mod capture;
 pub mod config;
 pub mod crypto;
 pub mod errors;
 pub mod protocol;
 
 pub use crypto::{
-    derive_public_key, generate_private_key, sign_message, verify_signature, PrivateKey, PublicKey,
+    derive_private_key_from_mnemonic, derive_public_key, export_private_key_mnemonic,
+    generate_private_key, sign_message, verify_signature, verify_signatures_batch, PrivateKey,
+    PublicKey, MNEMONIC_WORD_COUNT,
+};
+pub use errors::{
+    AttachmentError, BackupError, CryptoError, GroupKeyError, LobbyError, NicknameError,
+    RatchetError, RoomError,
 };
-pub use errors::{CryptoError, LobbyError};
 pub use protocol::{LobbyUser, Message};
 
 #[cfg(test)]
@@ -24,6 +31,8 @@ mod tests {
         // Verify that all key functions are accessible at crate level
         let _ = generate_private_key;
         let _ = derive_public_key;
+        let _ = export_private_key_mnemonic;
+        let _ = derive_private_key_from_mnemonic;
         let _ = sign_message;
         let _ = verify_signature;
     }
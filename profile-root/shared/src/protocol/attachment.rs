@@ -0,0 +1,338 @@
+//! Signed manifests for file attachments
+//!
+//! A file is sent in chunks of at most [`CHUNK_SIZE`] bytes. Before any of
+//! them, the sender offers an [`AttachmentManifest`] naming the file, its
+//! size and the SHA-256 hash of the whole file and of every chunk, signed
+//! over [`crate::canonical::attachment_manifest`]. The chunks themselves are
+//! not signed: a recipient checks each against its hash in the manifest as
+//! it arrives, and the assembled file against the file hash.
+//!
+//! The server checks the manifest's signature and limits before relaying
+//! the offer, and its chunk layout to check the length of every chunk it
+//! relays (see [`AttachmentManifest::chunk_len`]).
+//!
+//! A manifest for an image may carry a small preview, encoded by the sender
+//! and covered by the signature, so the recipient can show it before
+//! deciding to download the file.
+
+use crate::canonical;
+use crate::config::attachments::{
+    CHUNK_SIZE, MAX_ATTACHMENT_SIZE, MAX_CHUNKS, MAX_FILE_NAME_LENGTH, MAX_THUMBNAIL_SIZE,
+};
+use crate::crypto::{sign_message, verify_signature, PrivateKey, PublicKey};
+use crate::errors::{AttachmentError, CryptoError};
+use crate::protocol::lowercase_hex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Description of an attached file, signed by its sender
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttachmentManifest {
+    /// Id the offer, accepts, chunks and completion of this transfer share
+    #[serde(rename = "transferId")]
+    pub transfer_id: Uuid,
+    /// Name of the file, without any directory
+    #[serde(rename = "fileName")]
+    pub file_name: String,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// Size of every chunk but the last, in bytes
+    #[serde(rename = "chunkSize")]
+    pub chunk_size: u32,
+    /// SHA-256 of each chunk in order (hex)
+    #[serde(
+        rename = "chunkHashes",
+        deserialize_with = "lowercase_hex::deserialize_vec"
+    )]
+    pub chunk_hashes: Vec<String>,
+    /// SHA-256 of the whole file (hex)
+    #[serde(rename = "fileHash", deserialize_with = "lowercase_hex::deserialize")]
+    pub file_hash: String,
+    /// When the file was offered (RFC 3339)
+    pub timestamp: String,
+    /// Encoded preview image of at most [`MAX_THUMBNAIL_SIZE`] bytes (hex)
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "lowercase_hex::deserialize_option"
+    )]
+    pub thumbnail: Option<String>,
+    /// Sender's signature over [`canonical::attachment_manifest`] (hex)
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+}
+
+impl AttachmentManifest {
+    /// Describe `data` as a file named `file_name` and sign the description
+    ///
+    /// # Arguments
+    /// * `private_key` - The sender's identity key
+    /// * `transfer_id` - Id for the transfer
+    /// * `file_name` - Name the recipient sees
+    /// * `data` - The file's contents
+    /// * `timestamp` - When the file is offered (RFC 3339)
+    pub fn sign(
+        private_key: &PrivateKey,
+        transfer_id: Uuid,
+        file_name: &str,
+        data: &[u8],
+        timestamp: &str,
+    ) -> Result<Self, CryptoError> {
+        Self::sign_with_thumbnail(private_key, transfer_id, file_name, data, None, timestamp)
+    }
+
+    /// Like [`AttachmentManifest::sign`], with an encoded preview image
+    /// covered by the signature
+    pub fn sign_with_thumbnail(
+        private_key: &PrivateKey,
+        transfer_id: Uuid,
+        file_name: &str,
+        data: &[u8],
+        thumbnail: Option<&[u8]>,
+        timestamp: &str,
+    ) -> Result<Self, CryptoError> {
+        let mut manifest = Self {
+            transfer_id,
+            file_name: file_name.to_string(),
+            size: data.len() as u64,
+            chunk_size: CHUNK_SIZE as u32,
+            chunk_hashes: data.chunks(CHUNK_SIZE).map(sha256_hex).collect(),
+            file_hash: sha256_hex(data),
+            timestamp: timestamp.to_string(),
+            thumbnail: thumbnail.map(hex::encode),
+            signature: String::new(),
+        };
+        manifest.signature = hex::encode(sign_message(private_key, &manifest.signed_bytes())?);
+        Ok(manifest)
+    }
+
+    /// The bytes the signature covers
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        canonical::attachment_manifest(self)
+    }
+
+    /// Check the signature under the sender's key (hex)
+    ///
+    /// # Errors
+    /// Returns an error if the key or signature is malformed or the
+    /// signature doesn't cover the manifest
+    pub fn verify(&self, sender_public_key: &str) -> Result<(), CryptoError> {
+        let key = hex::decode(sender_public_key)
+            .map_err(|e| CryptoError::InvalidKey(e.to_string()))
+            .and_then(PublicKey::new)?;
+        let signature = hex::decode(&self.signature)
+            .map_err(|e| CryptoError::InvalidSignature(e.to_string()))?;
+        verify_signature(&key, &self.signed_bytes(), &signature)
+    }
+
+    /// Check the manifest is within the attachment limits and consistent
+    ///
+    /// # Errors
+    /// Returns an error if the file is empty or too large, its name is empty,
+    /// too long or names a directory, the chunk layout doesn't add up, or
+    /// the preview is too large or not hex
+    pub fn check(&self) -> Result<(), AttachmentError> {
+        let invalid = |details: &str| AttachmentError::InvalidManifest {
+            details: details.to_string(),
+        };
+        if self.size == 0 {
+            return Err(invalid("File is empty"));
+        }
+        if self.size > MAX_ATTACHMENT_SIZE as u64 {
+            return Err(AttachmentError::TooLarge {
+                size: self.size,
+                max: MAX_ATTACHMENT_SIZE as u64,
+            });
+        }
+        if self.file_name.trim().is_empty()
+            || self.file_name.len() > MAX_FILE_NAME_LENGTH
+            || matches!(self.file_name.as_str(), "." | "..")
+            || self
+                .file_name
+                .chars()
+                .any(|c| c == '/' || c == '\\' || c.is_control())
+        {
+            return Err(invalid("File name must be a plain name"));
+        }
+        if self.chunk_size == 0 || self.chunk_size as usize > CHUNK_SIZE {
+            return Err(invalid("Chunk size out of range"));
+        }
+        let chunk_count = self.chunk_count();
+        if chunk_count as usize > MAX_CHUNKS || self.chunk_hashes.len() != chunk_count as usize {
+            return Err(invalid("Chunk hashes don't match the file size"));
+        }
+        if std::iter::once(&self.file_hash)
+            .chain(&self.chunk_hashes)
+            .any(|hash| !is_sha256_hex(hash))
+        {
+            return Err(invalid("Hashes must be SHA-256 hex"));
+        }
+        if let Some(thumbnail) = &self.thumbnail {
+            if thumbnail.is_empty()
+                || thumbnail.len() > 2 * MAX_THUMBNAIL_SIZE
+                || !thumbnail.len().is_multiple_of(2)
+                || !thumbnail.bytes().all(|b| b.is_ascii_hexdigit())
+            {
+                return Err(invalid("Thumbnail must be a small hex-encoded image"));
+            }
+        }
+        Ok(())
+    }
+
+    /// The encoded preview image, if the manifest carries a well-formed one
+    pub fn thumbnail_bytes(&self) -> Option<Vec<u8>> {
+        self.thumbnail
+            .as_deref()
+            .and_then(|thumbnail| hex::decode(thumbnail).ok())
+    }
+
+    /// Number of chunks the file is sent in
+    pub fn chunk_count(&self) -> u32 {
+        match self.chunk_size {
+            0 => 0,
+            chunk_size => self.size.div_ceil(chunk_size as u64) as u32,
+        }
+    }
+
+    /// Length of chunk `index` in bytes, None past the last chunk
+    pub fn chunk_len(&self, index: u32) -> Option<usize> {
+        if index >= self.chunk_count() {
+            return None;
+        }
+        let start = index as u64 * self.chunk_size as u64;
+        Some((self.size - start).min(self.chunk_size as u64) as usize)
+    }
+
+    /// Whether `data` is chunk `index` of the file: the right length and hash
+    pub fn chunk_matches(&self, index: u32, data: &[u8]) -> bool {
+        self.chunk_len(index) == Some(data.len())
+            && self
+                .chunk_hashes
+                .get(index as usize)
+                .is_some_and(|hash| *hash == sha256_hex(data))
+    }
+}
+
+/// SHA-256 of `data` as lowercase hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn is_sha256_hex(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{derive_public_key, generate_private_key};
+
+    const TIMESTAMP: &str = "2026-03-02T09:00:00Z";
+
+    fn signed(data: &[u8]) -> (AttachmentManifest, String) {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let manifest =
+            AttachmentManifest::sign(&private_key, Uuid::new_v4(), "notes.txt", data, TIMESTAMP)
+                .unwrap();
+        (manifest, public_key)
+    }
+
+    #[test]
+    fn test_manifest_describes_chunks_and_verifies() {
+        let data = vec![7u8; CHUNK_SIZE * 2 + 10];
+        let (manifest, public_key) = signed(&data);
+        assert!(manifest.check().is_ok());
+        assert!(manifest.verify(&public_key).is_ok());
+        assert_eq!(manifest.chunk_count(), 3);
+        assert_eq!(manifest.chunk_len(0), Some(CHUNK_SIZE));
+        assert_eq!(manifest.chunk_len(2), Some(10));
+        assert_eq!(manifest.chunk_len(3), None);
+        assert!(manifest.chunk_matches(2, &data[CHUNK_SIZE * 2..]));
+        assert!(!manifest.chunk_matches(1, &data[CHUNK_SIZE * 2..]));
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["chunkHashes"].as_array().unwrap().len(), 3);
+        let parsed: AttachmentManifest = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, manifest);
+    }
+
+    #[test]
+    fn test_tampered_manifest_does_not_verify() {
+        let (manifest, public_key) = signed(b"quarterly report");
+
+        let mut renamed = manifest.clone();
+        renamed.file_name = "invoice.exe".to_string();
+        assert!(renamed.verify(&public_key).is_err());
+
+        let mut swapped = manifest.clone();
+        swapped.chunk_hashes[0] = sha256_hex(b"something else");
+        assert!(swapped.verify(&public_key).is_err());
+    }
+
+    #[test]
+    fn test_manifest_limits() {
+        let (manifest, _) = signed(b"x");
+
+        let mut empty = manifest.clone();
+        empty.size = 0;
+        empty.chunk_hashes.clear();
+        assert!(matches!(
+            empty.check(),
+            Err(AttachmentError::InvalidManifest { .. })
+        ));
+
+        let mut huge = manifest.clone();
+        huge.size = MAX_ATTACHMENT_SIZE as u64 + 1;
+        assert!(matches!(
+            huge.check(),
+            Err(AttachmentError::TooLarge { .. })
+        ));
+
+        for name in ["../../.bashrc", "a\\b", "..", "   ", "bell\u{7}"] {
+            let mut named = manifest.clone();
+            named.file_name = name.to_string();
+            assert!(named.check().is_err(), "{:?} accepted", name);
+        }
+
+        let mut missing = manifest.clone();
+        missing.size = CHUNK_SIZE as u64 + 1;
+        assert!(missing.check().is_err());
+
+        let mut oversized = manifest.clone();
+        oversized.thumbnail = Some("ab".repeat(MAX_THUMBNAIL_SIZE + 1));
+        assert!(oversized.check().is_err());
+    }
+
+    #[test]
+    fn test_thumbnail_is_signed() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = hex::encode(derive_public_key(&private_key).unwrap().as_bytes());
+        let manifest = AttachmentManifest::sign_with_thumbnail(
+            &private_key,
+            Uuid::new_v4(),
+            "cat.jpg",
+            b"not really a jpeg",
+            Some(&[0xff, 0xd8, 0xff]),
+            TIMESTAMP,
+        )
+        .unwrap();
+        assert!(manifest.check().is_ok());
+        assert!(manifest.verify(&public_key).is_ok());
+        assert_eq!(manifest.thumbnail_bytes(), Some(vec![0xff, 0xd8, 0xff]));
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(json["thumbnail"], "ffd8ff");
+        let parsed: AttachmentManifest = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, manifest);
+
+        let mut swapped = manifest.clone();
+        swapped.thumbnail = Some("ffd8fe".to_string());
+        assert!(swapped.verify(&public_key).is_err());
+
+        let mut stripped = manifest;
+        stripped.thumbnail = None;
+        assert!(stripped.verify(&public_key).is_err());
+    }
+}
@@ -0,0 +1,483 @@
+//! Authentication handshake messages
+//!
+//! The client opens every connection with an [`AuthMessage`] carrying its
+//! public key and a signature over [`AUTH_CHALLENGE`]; the server answers
+//! with an [`AuthSuccessMessage`] holding the first lobby page, or an
+//! [`AuthErrorMessage`]. Server and client both (de)serialize these types,
+//! so the field names can't drift apart.
+//!
+//! The success message carries a short-lived resume token. A client that
+//! reconnects within its lifetime can open with a [`ResumeMessage`] instead
+//! and skip signing and signature verification. Each token works once; the
+//! reply to a resume carries the next one.
+//!
+//! A client using a throwaway guest identity says so with the `ephemeral`
+//! flag on either message, and the server tags its key as ephemeral in the
+//! lobby (see [`super::LobbyUser::ephemeral`]).
+//!
+//! A client willing to let others see when it was last online sets the
+//! `shareLastSeen` flag the same way; without it the server answers
+//! `query_last_seen` requests for the user with no time.
+
+use super::compression::{Compression, CompressionConfig};
+use super::encoding::Encoding;
+use super::lowercase_hex;
+use super::Status;
+use crate::errors::CryptoError;
+use crate::{sign_message, PrivateKey, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Bytes the client signs to prove it holds its private key
+pub const AUTH_CHALLENGE: &[u8] = b"auth";
+
+/// Authentication message sent by client during WebSocket handshake
+///
+/// Follows Architecture Decision 4: Uses `publicKey` and `signature` field names in JSON
+/// but snake_case in Rust to avoid compiler warnings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthMessage {
+    pub r#type: String,
+    #[serde(rename = "publicKey", deserialize_with = "lowercase_hex::deserialize")]
+    pub public_key: String,
+    #[serde(deserialize_with = "lowercase_hex::deserialize")]
+    pub signature: String,
+    /// Wire encodings the client can use, most preferred first
+    /// (see [`super::encoding`]); JSON if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encodings: Vec<String>,
+    /// Compressions the client can use, most preferred first
+    /// (see [`super::compression`]); none if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compression: Vec<String>,
+    /// The key is a throwaway guest identity that is never saved
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ephemeral: bool,
+    /// Others may ask when the user was last online
+    #[serde(
+        rename = "shareLastSeen",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub share_last_seen: bool,
+}
+
+impl AuthMessage {
+    /// Create a new authentication message from hex-encoded fields
+    pub fn new(public_key: String, signature: String) -> Self {
+        Self {
+            r#type: "auth".to_string(),
+            public_key,
+            signature,
+            encodings: Vec::new(),
+            compression: Vec::new(),
+            ephemeral: false,
+            share_last_seen: false,
+        }
+    }
+
+    /// Create an authentication message signing [`AUTH_CHALLENGE`] with
+    /// `private_key`
+    pub fn signed(public_key: &PublicKey, private_key: &PrivateKey) -> Result<Self, CryptoError> {
+        let signature = sign_message(private_key, AUTH_CHALLENGE)?;
+        Ok(Self::new(
+            hex::encode(public_key.as_slice()),
+            hex::encode(signature),
+        ))
+    }
+
+    /// Offer `preferred` to the server, falling back to JSON
+    pub fn with_preferred_encoding(mut self, preferred: Encoding) -> Self {
+        self.encodings = if preferred.is_binary() {
+            vec![
+                preferred.name().to_string(),
+                Encoding::Json.name().to_string(),
+            ]
+        } else {
+            Vec::new()
+        };
+        self
+    }
+
+    /// Offer the compressions enabled in `config`
+    pub fn with_compression(mut self, config: &CompressionConfig) -> Self {
+        self.compression = config.offered();
+        self
+    }
+
+    /// Mark the key as a throwaway guest identity
+    pub fn with_ephemeral(mut self, ephemeral: bool) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    /// Let others ask when the user was last online
+    pub fn with_share_last_seen(mut self, share: bool) -> Self {
+        self.share_last_seen = share;
+        self
+    }
+}
+
+/// Successful authentication response with the first lobby page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthSuccessMessage {
+    pub r#type: String,
+    /// First page of online users (hex-encoded public keys)
+    pub users: Vec<String>,
+    /// Number of online users across all pages
+    #[serde(default)]
+    pub total: usize,
+    /// Cursor for a `lobby_page` request fetching the remaining users
+    #[serde(
+        rename = "nextCursor",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub next_cursor: Option<String>,
+    /// Nicknames of the users on this page that have one
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub nicknames: HashMap<String, String>,
+    /// Verified names of the users on this page that claimed one
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub names: HashMap<String, String>,
+    /// Encoding for every later frame, when it isn't JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Compression either side may use for later frames, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    /// Token for a [`ResumeMessage`] on the next connection, if issued
+    #[serde(
+        rename = "resumeToken",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub resume_token: Option<String>,
+    /// Users on this page with a throwaway guest identity
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ephemeral: Vec<String>,
+    /// Statuses of the users on this page who aren't simply online
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub statuses: HashMap<String, Status>,
+    /// The signed-in user's own status, if it isn't online; an invisible
+    /// user is not on the page, so this is how they learn they still are
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<Status>,
+}
+
+impl AuthSuccessMessage {
+    /// Create a new authentication success message listing every user
+    pub fn new(users: Vec<String>) -> Self {
+        let total = users.len();
+        Self::page(users, total, None)
+    }
+
+    /// Create an authentication success message for one page of `total` users
+    pub fn page(users: Vec<String>, total: usize, next_cursor: Option<String>) -> Self {
+        Self {
+            r#type: "auth_success".to_string(),
+            users,
+            total,
+            next_cursor,
+            nicknames: HashMap::new(),
+            names: HashMap::new(),
+            encoding: None,
+            compression: None,
+            resume_token: None,
+            ephemeral: Vec::new(),
+            statuses: HashMap::new(),
+            status: None,
+        }
+    }
+
+    /// Attach the nicknames of users on the page
+    pub fn with_nicknames(mut self, nicknames: HashMap<String, String>) -> Self {
+        self.nicknames = nicknames;
+        self
+    }
+
+    /// Attach the verified names of users on the page
+    pub fn with_names(mut self, names: HashMap<String, String>) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// List the users on the page with a throwaway guest identity
+    pub fn with_ephemeral(mut self, ephemeral: Vec<String>) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    /// Attach the statuses of users on the page who aren't simply online
+    pub fn with_statuses(mut self, statuses: HashMap<String, Status>) -> Self {
+        self.statuses = statuses;
+        self
+    }
+
+    /// Tell the user the status they kept from an earlier connection
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = (status != Status::Online).then_some(status);
+        self
+    }
+
+    /// Announce the encoding negotiated for the rest of the connection
+    ///
+    /// JSON is left implicit so older clients see the same message as before.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding.is_binary().then(|| encoding.name().to_string());
+        self
+    }
+
+    /// Encoding announced by the server (JSON if none or unknown)
+    pub fn negotiated_encoding(&self) -> Encoding {
+        self.encoding
+            .as_deref()
+            .and_then(Encoding::from_name)
+            .unwrap_or_default()
+    }
+
+    /// Announce the compression negotiated for the rest of the connection
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression.name().map(str::to_string);
+        self
+    }
+
+    /// Compression announced by the server (none if absent or unknown)
+    pub fn negotiated_compression(&self) -> Compression {
+        self.compression
+            .as_deref()
+            .and_then(Compression::from_name)
+            .unwrap_or_default()
+    }
+
+    /// Hand the client a token to resume with on its next connection
+    pub fn with_resume_token(mut self, token: String) -> Self {
+        self.resume_token = Some(token);
+        self
+    }
+}
+
+/// Resumes an earlier session instead of authenticating again
+///
+/// Sent as the first frame of a connection in place of an [`AuthMessage`].
+/// The token is signed by the server and names the user, so the client
+/// signs nothing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResumeMessage {
+    pub r#type: String,
+    /// Token from the previous connection's [`AuthSuccessMessage`]
+    #[serde(
+        rename = "resumeToken",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub resume_token: String,
+    /// Wire encodings the client can use, as in [`AuthMessage`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encodings: Vec<String>,
+    /// Compressions the client can use, as in [`AuthMessage`]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub compression: Vec<String>,
+    /// The key is a throwaway guest identity, as in [`AuthMessage`]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ephemeral: bool,
+    /// Others may ask when the user was last online, as in [`AuthMessage`]
+    #[serde(
+        rename = "shareLastSeen",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub share_last_seen: bool,
+}
+
+impl ResumeMessage {
+    /// Create a resume message for `resume_token`
+    pub fn new(resume_token: String) -> Self {
+        Self {
+            r#type: "resume".to_string(),
+            resume_token,
+            encodings: Vec::new(),
+            compression: Vec::new(),
+            ephemeral: false,
+            share_last_seen: false,
+        }
+    }
+
+    /// Offer the same encodings and compressions as `auth`, and carry its
+    /// guest and last-seen flags
+    pub fn with_offers_from(mut self, auth: &AuthMessage) -> Self {
+        self.encodings = auth.encodings.clone();
+        self.compression = auth.compression.clone();
+        self.ephemeral = auth.ephemeral;
+        self.share_last_seen = auth.share_last_seen;
+        self
+    }
+}
+
+/// Authentication error response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthErrorMessage {
+    pub r#type: String,
+    pub reason: String,
+    pub details: String,
+}
+
+impl AuthErrorMessage {
+    /// Create a new authentication error message
+    pub fn new(reason: String, details: String) -> Self {
+        Self {
+            r#type: "error".to_string(),
+            reason,
+            details,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{derive_public_key, generate_private_key, verify_signature};
+
+    #[test]
+    fn test_auth_message_creation() {
+        let msg = AuthMessage::new("abc123".to_string(), "def456".to_string());
+        assert_eq!(msg.r#type, "auth");
+        assert_eq!(msg.public_key, "abc123");
+        assert_eq!(msg.signature, "def456");
+
+        let serialized = serde_json::to_string(&msg).unwrap();
+        assert!(!serialized.contains("encodings"));
+        let deserialized: AuthMessage = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.public_key, msg.public_key);
+        assert_eq!(deserialized.signature, msg.signature);
+    }
+
+    #[test]
+    fn test_signed_auth_message_verifies() {
+        let private_key = generate_private_key().unwrap();
+        let public_key = derive_public_key(&private_key).unwrap();
+        let msg = AuthMessage::signed(&public_key, &private_key).unwrap();
+
+        assert_eq!(msg.public_key, hex::encode(public_key.as_slice()));
+        assert_eq!(msg.signature.len(), 128);
+        let signature = hex::decode(&msg.signature).unwrap();
+        assert!(verify_signature(&public_key, AUTH_CHALLENGE, &signature).is_ok());
+
+        let offered = msg.with_preferred_encoding(Encoding::MessagePack);
+        assert_eq!(offered.encodings, vec!["msgpack", "json"]);
+        let offered = offered.with_compression(&CompressionConfig::default());
+        assert_eq!(offered.compression, vec!["deflate"]);
+        let json = serde_json::to_string(&offered.with_compression(&CompressionConfig::disabled()));
+        assert!(!json.unwrap().contains("compression"));
+    }
+
+    #[test]
+    fn test_auth_success_message_page() {
+        let msg = AuthSuccessMessage::page(vec!["user1".to_string()], 3, Some("user1".to_string()));
+        assert_eq!(msg.r#type, "auth_success");
+        assert_eq!(msg.total, 3);
+
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""nextCursor":"user1""#));
+        let json = serde_json::to_string(&AuthSuccessMessage::new(vec![])).unwrap();
+        assert!(!json.contains("nextCursor"));
+        assert!(!json.contains("nicknames"));
+        assert!(!json.contains("encoding"));
+    }
+
+    #[test]
+    fn test_auth_success_message_nicknames_and_encoding() {
+        let msg = AuthSuccessMessage::new(vec!["user1".to_string(), "user2".to_string()])
+            .with_nicknames(HashMap::from([("user1".to_string(), "alice".to_string())]))
+            .with_names(HashMap::from([("user2".to_string(), "Bob".to_string())]))
+            .with_encoding(Encoding::MessagePack);
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""nicknames":{"user1":"alice"}"#));
+        assert!(json.contains(r#""names":{"user2":"Bob"}"#));
+
+        let parsed: AuthSuccessMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.negotiated_encoding(), Encoding::MessagePack);
+        assert_eq!(
+            AuthSuccessMessage::new(vec![])
+                .with_encoding(Encoding::Json)
+                .negotiated_encoding(),
+            Encoding::Json
+        );
+    }
+
+    #[test]
+    fn test_resume_token_round_trip() {
+        let json = serde_json::to_string(&AuthSuccessMessage::new(vec![])).unwrap();
+        assert!(!json.contains("resumeToken"));
+
+        let token = "AB".repeat(32);
+        let msg = AuthSuccessMessage::new(vec![]).with_resume_token(token.clone());
+        let json = serde_json::to_string(&msg).unwrap();
+        let parsed: AuthSuccessMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.resume_token.as_deref(), Some(token.as_str()));
+
+        let auth =
+            AuthMessage::new("a".into(), "b".into()).with_preferred_encoding(Encoding::MessagePack);
+        let resume = ResumeMessage::new(token).with_offers_from(&auth);
+        let json = serde_json::to_string(&resume).unwrap();
+        assert!(json.contains(r#""type":"resume""#));
+        let parsed: ResumeMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.resume_token, "ab".repeat(32));
+        assert_eq!(parsed.encodings, vec!["msgpack", "json"]);
+    }
+
+    #[test]
+    fn test_guest_flag_round_trip() {
+        let auth = AuthMessage::new("a".into(), "b".into());
+        assert!(!serde_json::to_string(&auth).unwrap().contains("ephemeral"));
+
+        let auth = auth.with_ephemeral(true);
+        let json = serde_json::to_string(&auth).unwrap();
+        assert!(json.contains(r#""ephemeral":true"#));
+        assert!(
+            serde_json::from_str::<AuthMessage>(&json)
+                .unwrap()
+                .ephemeral
+        );
+        assert!(
+            ResumeMessage::new("ab".repeat(32))
+                .with_offers_from(&auth)
+                .ephemeral
+        );
+
+        let msg = AuthSuccessMessage::new(vec!["user1".to_string(), "user2".to_string()])
+            .with_ephemeral(vec!["user2".to_string()]);
+        let parsed: AuthSuccessMessage =
+            serde_json::from_str(&serde_json::to_string(&msg).unwrap()).unwrap();
+        assert_eq!(parsed.ephemeral, vec!["user2"]);
+    }
+
+    #[test]
+    fn test_share_last_seen_flag_round_trip() {
+        let auth = AuthMessage::new("a".into(), "b".into());
+        assert!(!serde_json::to_string(&auth)
+            .unwrap()
+            .contains("shareLastSeen"));
+
+        let auth = auth.with_share_last_seen(true);
+        let json = serde_json::to_string(&auth).unwrap();
+        assert!(json.contains(r#""shareLastSeen":true"#));
+        assert!(
+            serde_json::from_str::<AuthMessage>(&json)
+                .unwrap()
+                .share_last_seen
+        );
+        assert!(
+            ResumeMessage::new("ab".repeat(32))
+                .with_offers_from(&auth)
+                .share_last_seen
+        );
+    }
+
+    #[test]
+    fn test_auth_error_message_creation() {
+        let msg = AuthErrorMessage::new("auth_failed".to_string(), "Invalid signature".to_string());
+        assert_eq!(msg.r#type, "error");
+        assert_eq!(msg.reason, "auth_failed");
+        assert_eq!(msg.details, "Invalid signature");
+    }
+}
@@ -0,0 +1,384 @@
+//! Per-message compression negotiated per connection
+//!
+//! Lobby snapshots and long messages are mostly repeated hex keys and
+//! field names, which deflate shrinks several times over. The WebSocket
+//! library on both ends has no support for protocol extensions, so the
+//! standard `permessage-deflate` handshake can't be used; compression is
+//! negotiated in the auth exchange instead, the same way as the wire
+//! [`Encoding`](super::encoding):
+//!
+//! - A client offers it in its auth message (`"compression":["deflate"]`)
+//! - A server that has it enabled answers in `auth_success`
+//!   (`"compression":"deflate"`)
+//! - From then on either side may send a frame as a binary frame holding
+//!   [`COMPRESSED_FRAME_MARKER`] followed by the raw deflate of the payload
+//!   it would otherwise have sent (JSON text or MessagePack)
+//!
+//! Each side only compresses frames of at least its own threshold, so small
+//! frames don't pay for compression that wouldn't save anything. The marker
+//! is a byte MessagePack never uses and JSON only travels in text frames,
+//! so a compressed frame can't be mistaken for an uncompressed one. Peers
+//! that don't offer compression see no change.
+
+use super::encoding::{Encoding, EncodingError};
+use crate::config::connection::compression::DEFAULT_COMPRESSION_THRESHOLD;
+use crate::config::connection::MAX_FRAME_SIZE;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io::{Read, Write};
+
+/// Name of deflate compression on the wire
+pub const DEFLATE: &str = "deflate";
+
+/// Environment variable turning compression off (`off`) or on (`deflate`)
+pub const COMPRESSION_ENV_VAR: &str = "PROFILE_COMPRESSION";
+
+/// Environment variable with the smallest payload worth compressing, in bytes
+pub const COMPRESSION_THRESHOLD_ENV_VAR: &str = "PROFILE_COMPRESSION_THRESHOLD";
+
+/// First byte of every compressed frame (never used by MessagePack)
+pub const COMPRESSED_FRAME_MARKER: u8 = 0xC1;
+
+/// How frames are compressed on a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Compression {
+    /// Every frame is sent as it is
+    #[default]
+    None,
+    /// Frames over the threshold are deflated
+    Deflate,
+}
+
+impl Compression {
+    /// Name used for this compression in auth messages
+    ///
+    /// # Returns
+    /// None for no compression, which is never named on the wire
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Deflate => Some(DEFLATE),
+        }
+    }
+
+    /// Look up a compression by its wire name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            DEFLATE => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Pick the first offered compression this side supports
+    ///
+    /// # Returns
+    /// None if nothing offered is supported (or nothing was offered)
+    pub fn negotiate<S: AsRef<str>>(offered: &[S]) -> Self {
+        offered
+            .iter()
+            .find_map(|name| Self::from_name(name.as_ref()))
+            .unwrap_or_default()
+    }
+}
+
+/// Error types for compression settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressionConfigError {
+    /// `PROFILE_COMPRESSION` is neither `off` nor a supported compression
+    UnknownMode(String),
+    /// `PROFILE_COMPRESSION_THRESHOLD` is not a number of bytes
+    InvalidThreshold(String),
+}
+
+impl Display for CompressionConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressionConfigError::UnknownMode(mode) => {
+                write!(f, "Unknown compression mode: {}", mode)
+            }
+            CompressionConfigError::InvalidThreshold(value) => {
+                write!(f, "Invalid compression threshold: {}", value)
+            }
+        }
+    }
+}
+
+impl Error for CompressionConfigError {}
+
+/// Whether this side uses compression, and from what size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Offer (client) or accept (server) compression
+    pub enabled: bool,
+    /// Smallest payload compressed, in bytes
+    pub threshold: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Compression turned off
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    /// Read `PROFILE_COMPRESSION` and `PROFILE_COMPRESSION_THRESHOLD`
+    ///
+    /// Enabled with the default threshold when both are unset.
+    pub fn from_env() -> Result<Self, CompressionConfigError> {
+        Self::parse(
+            std::env::var(COMPRESSION_ENV_VAR).ok().as_deref(),
+            std::env::var(COMPRESSION_THRESHOLD_ENV_VAR).ok().as_deref(),
+        )
+    }
+
+    /// Build settings from a mode and threshold as configured
+    pub fn parse(
+        mode: Option<&str>,
+        threshold: Option<&str>,
+    ) -> Result<Self, CompressionConfigError> {
+        let enabled = match mode.map(str::trim).filter(|m| !m.is_empty()) {
+            None => true,
+            Some(mode) if mode.eq_ignore_ascii_case("off") => false,
+            Some(mode) if Compression::from_name(&mode.to_ascii_lowercase()).is_some() => true,
+            Some(mode) => return Err(CompressionConfigError::UnknownMode(mode.to_string())),
+        };
+        let threshold = match threshold.map(str::trim).filter(|t| !t.is_empty()) {
+            None => DEFAULT_COMPRESSION_THRESHOLD,
+            Some(value) => value
+                .parse()
+                .map_err(|_| CompressionConfigError::InvalidThreshold(value.to_string()))?,
+        };
+        Ok(Self { enabled, threshold })
+    }
+
+    /// Compression names to offer the server, most preferred first
+    pub fn offered(&self) -> Vec<String> {
+        if self.enabled {
+            vec![DEFLATE.to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Pick a compression from a client's offer
+    ///
+    /// # Returns
+    /// None when compression is disabled on this side
+    pub fn negotiate<S: AsRef<str>>(&self, offered: &[S]) -> Compression {
+        if self.enabled {
+            Compression::negotiate(offered)
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// A frame ready to be sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+/// Turns JSON text into frames for a connection, and binary frames back
+/// into JSON text, with its negotiated encoding and compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCodec {
+    pub encoding: Encoding,
+    pub compression: Compression,
+    /// Smallest payload compressed, in bytes
+    pub threshold: usize,
+}
+
+impl Default for FrameCodec {
+    fn default() -> Self {
+        Self::new(Encoding::Json)
+    }
+}
+
+impl FrameCodec {
+    /// A codec with no compression
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            compression: Compression::None,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+
+    /// Compress frames of at least `threshold` bytes with `compression`
+    pub fn with_compression(mut self, compression: Compression, threshold: usize) -> Self {
+        self.compression = compression;
+        self.threshold = threshold;
+        self
+    }
+
+    /// Whether binary frames can arrive on this connection
+    pub fn accepts_binary(&self) -> bool {
+        self.encoding.is_binary() || self.compression != Compression::None
+    }
+
+    /// Encode a JSON message as the frame to send
+    pub fn encode_json(&self, json: &str) -> Result<Frame, EncodingError> {
+        let compress = self.compression == Compression::Deflate && json.len() >= self.threshold;
+        if !compress {
+            return Ok(if self.encoding.is_binary() {
+                Frame::Binary(self.encoding.encode_json(json)?)
+            } else {
+                Frame::Text(json.to_string())
+            });
+        }
+        let payload = self.encoding.encode_json(json)?;
+        let mut encoder = DeflateEncoder::new(
+            vec![COMPRESSED_FRAME_MARKER],
+            flate2::Compression::default(),
+        );
+        encoder
+            .write_all(&payload)
+            .and_then(|_| encoder.finish())
+            .map(Frame::Binary)
+            .map_err(|e| EncodingError::Compression(e.to_string()))
+    }
+
+    /// Convert a received binary frame back to JSON text
+    ///
+    /// A compressed frame may inflate to at most MAX_FRAME_SIZE bytes, the
+    /// same limit as an uncompressed one.
+    pub fn decode_binary(&self, bytes: &[u8]) -> Result<String, EncodingError> {
+        match bytes.split_first() {
+            Some((&COMPRESSED_FRAME_MARKER, compressed))
+                if self.compression == Compression::Deflate =>
+            {
+                let payload = inflate(compressed, MAX_FRAME_SIZE)?;
+                if self.encoding.is_binary() {
+                    self.encoding.decode_json(&payload)
+                } else {
+                    String::from_utf8(payload)
+                        .map_err(|e| EncodingError::Compression(e.to_string()))
+                }
+            }
+            _ => self.encoding.decode_json(bytes),
+        }
+    }
+}
+
+/// Inflate raw deflate data, refusing output over `limit` bytes
+fn inflate(compressed: &[u8], limit: usize) -> Result<Vec<u8>, EncodingError> {
+    let mut payload = Vec::new();
+    DeflateDecoder::new(compressed)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut payload)
+        .map_err(|e| EncodingError::Compression(e.to_string()))?;
+    if payload.len() > limit {
+        return Err(EncodingError::Compression(format!(
+            "inflates to more than {} bytes",
+            limit
+        )));
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lobby_json(users: usize) -> String {
+        let users: Vec<String> = (0..users).map(|i| format!("{:064x}", i)).collect();
+        serde_json::json!({"type": "lobby", "users": users}).to_string()
+    }
+
+    #[test]
+    fn test_parse_config() {
+        assert_eq!(
+            CompressionConfig::parse(None, None).unwrap(),
+            CompressionConfig::default()
+        );
+        let config = CompressionConfig::parse(Some("deflate"), Some("256")).unwrap();
+        assert!(config.enabled);
+        assert_eq!(config.threshold, 256);
+        assert!(!CompressionConfig::parse(Some("OFF"), None).unwrap().enabled);
+        assert!(CompressionConfig::parse(Some("gzip"), None).is_err());
+        assert!(CompressionConfig::parse(None, Some("lots")).is_err());
+
+        assert_eq!(
+            CompressionConfig::default().negotiate(&["brotli", "deflate"]),
+            Compression::Deflate
+        );
+        assert_eq!(
+            CompressionConfig::disabled().negotiate(&["deflate"]),
+            Compression::None
+        );
+        assert!(CompressionConfig::disabled().offered().is_empty());
+    }
+
+    #[test]
+    fn test_large_frames_compress_and_round_trip() {
+        let json = lobby_json(100);
+        for encoding in Encoding::ALL {
+            let codec = FrameCodec::new(encoding).with_compression(Compression::Deflate, 1024);
+            let Frame::Binary(bytes) = codec.encode_json(&json).unwrap() else {
+                panic!("large frame should be compressed");
+            };
+            assert_eq!(bytes[0], COMPRESSED_FRAME_MARKER);
+            assert!(bytes.len() < json.len() / 2);
+            let back = codec.decode_binary(&bytes).unwrap();
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(&back).unwrap(),
+                serde_json::from_str::<serde_json::Value>(&json).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_small_frames_sent_as_they_are() {
+        let json = lobby_json(1);
+        let codec = FrameCodec::new(Encoding::Json).with_compression(Compression::Deflate, 1024);
+        assert_eq!(codec.encode_json(&json).unwrap(), Frame::Text(json.clone()));
+
+        let codec =
+            FrameCodec::new(Encoding::MessagePack).with_compression(Compression::Deflate, 1024);
+        let Frame::Binary(bytes) = codec.encode_json(&json).unwrap() else {
+            panic!("msgpack frames are binary");
+        };
+        assert_ne!(bytes[0], COMPRESSED_FRAME_MARKER);
+        assert!(codec.decode_binary(&bytes).is_ok());
+    }
+
+    #[test]
+    fn test_compressed_frames_need_negotiation_and_a_size_limit() {
+        let compressing = FrameCodec::new(Encoding::Json).with_compression(Compression::Deflate, 0);
+        let Frame::Binary(bytes) = compressing.encode_json(&lobby_json(10)).unwrap() else {
+            panic!("frame should be compressed");
+        };
+        assert!(matches!(
+            FrameCodec::new(Encoding::Json).decode_binary(&bytes),
+            Err(EncodingError::UnexpectedBinary)
+        ));
+
+        let bomb = vec![b' '; MAX_FRAME_SIZE + 1];
+        let Frame::Binary(bytes) = compressing
+            .encode_json(std::str::from_utf8(&bomb).unwrap())
+            .unwrap()
+        else {
+            panic!("frame should be compressed");
+        };
+        assert!(bytes.len() < 1024);
+        assert!(matches!(
+            compressing.decode_binary(&bytes),
+            Err(EncodingError::Compression(_))
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+//! Wire encodings negotiated per connection
+//!
+//! Every message is JSON by default and travels in a text frame. A client
+//! may offer other encodings in its auth message (`"encodings":["msgpack"]`);
+//! the server answers with the one it picked in `auth_success`
+//! (`"encoding":"msgpack"`), and from then on both sides send binary frames
+//! in that encoding. The auth exchange itself is always JSON, and a missing
+//! `encoding` field means the connection stays on JSON, so older peers are
+//! unaffected.
+//!
+//! MessagePack is written with field names (`to_vec_named`) so the same
+//! serde attributes, including internally tagged enums, work unchanged.
+//! Its nesting is limited to [`MAX_DEPTH`] like serde_json limits JSON's, so
+//! a small frame of nested arrays can't overflow the stack.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Name of the JSON encoding on the wire
+pub const JSON: &str = "json";
+
+/// Name of the MessagePack encoding on the wire
+pub const MESSAGE_PACK: &str = "msgpack";
+
+/// Deepest nesting accepted in MessagePack input (serde_json's JSON limit)
+pub const MAX_DEPTH: usize = 128;
+
+/// How messages are serialized on a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// JSON in text frames
+    #[default]
+    Json,
+    /// MessagePack in binary frames
+    MessagePack,
+}
+
+/// Errors that can occur when encoding or decoding a message
+#[derive(Debug)]
+pub enum EncodingError {
+    /// The message is not valid JSON or doesn't fit the expected type
+    Json(serde_json::Error),
+    /// The message could not be written as MessagePack
+    MessagePackEncode(rmp_serde::encode::Error),
+    /// The message is not valid MessagePack or doesn't fit the expected type
+    MessagePackDecode(rmp_serde::decode::Error),
+    /// The payload is binary but the connection uses a text encoding
+    UnexpectedBinary,
+    /// A compressed frame could not be written or inflated
+    Compression(String),
+}
+
+impl std::fmt::Display for EncodingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodingError::Json(e) => write!(f, "Invalid JSON: {}", e),
+            EncodingError::MessagePackEncode(e) => {
+                write!(f, "Failed to encode MessagePack: {}", e)
+            }
+            EncodingError::MessagePackDecode(e) => write!(f, "Invalid MessagePack: {}", e),
+            EncodingError::UnexpectedBinary => {
+                write!(f, "Binary frames require a negotiated binary encoding")
+            }
+            EncodingError::Compression(msg) => write!(f, "Invalid compressed frame: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for EncodingError {}
+
+impl From<serde_json::Error> for EncodingError {
+    fn from(e: serde_json::Error) -> Self {
+        EncodingError::Json(e)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for EncodingError {
+    fn from(e: rmp_serde::encode::Error) -> Self {
+        EncodingError::MessagePackEncode(e)
+    }
+}
+
+impl From<rmp_serde::decode::Error> for EncodingError {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        EncodingError::MessagePackDecode(e)
+    }
+}
+
+impl Encoding {
+    /// Every supported encoding, most preferred first
+    pub const ALL: [Encoding; 2] = [Encoding::MessagePack, Encoding::Json];
+
+    /// Name used for this encoding in auth messages
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Json => JSON,
+            Encoding::MessagePack => MESSAGE_PACK,
+        }
+    }
+
+    /// Look up an encoding by its wire name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            JSON => Some(Encoding::Json),
+            MESSAGE_PACK => Some(Encoding::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// Pick the first offered encoding this side supports
+    ///
+    /// # Returns
+    /// Json if nothing offered is supported (or nothing was offered)
+    pub fn negotiate<S: AsRef<str>>(offered: &[S]) -> Self {
+        offered
+            .iter()
+            .find_map(|name| Self::from_name(name.as_ref()))
+            .unwrap_or_default()
+    }
+
+    /// Whether messages in this encoding travel in binary frames
+    pub fn is_binary(self) -> bool {
+        self != Encoding::Json
+    }
+
+    /// Serialize a message
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Vec<u8>, EncodingError> {
+        match self {
+            Encoding::Json => Ok(serde_json::to_vec(value)?),
+            Encoding::MessagePack => Ok(rmp_serde::to_vec_named(value)?),
+        }
+    }
+
+    /// Deserialize a message
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, EncodingError> {
+        match self {
+            Encoding::Json => Ok(serde_json::from_slice(bytes)?),
+            Encoding::MessagePack => {
+                let mut deserializer = rmp_serde::Deserializer::from_read_ref(bytes);
+                deserializer.set_max_depth(MAX_DEPTH);
+                Ok(T::deserialize(&mut deserializer)?)
+            }
+        }
+    }
+
+    /// Re-encode a JSON message in this encoding
+    ///
+    /// Lets code that builds JSON text send it on any connection.
+    pub fn encode_json(self, json: &str) -> Result<Vec<u8>, EncodingError> {
+        match self {
+            Encoding::Json => Ok(json.as_bytes().to_vec()),
+            Encoding::MessagePack => {
+                let value: serde_json::Value = serde_json::from_str(json)?;
+                self.encode(&value)
+            }
+        }
+    }
+
+    /// Convert a binary frame in this encoding back to JSON text
+    ///
+    /// Lets code that parses JSON text read messages from any connection.
+    pub fn decode_json(self, bytes: &[u8]) -> Result<String, EncodingError> {
+        match self {
+            Encoding::Json => Err(EncodingError::UnexpectedBinary),
+            Encoding::MessagePack => {
+                let value: serde_json::Value = self.decode(bytes)?;
+                Ok(value.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Message;
+
+    #[test]
+    fn test_negotiate_picks_first_supported() {
+        assert_eq!(
+            Encoding::negotiate(&["cbor", "msgpack", "json"]),
+            Encoding::MessagePack
+        );
+        assert_eq!(Encoding::negotiate(&["cbor"]), Encoding::Json);
+        assert_eq!(Encoding::negotiate::<&str>(&[]), Encoding::Json);
+        for encoding in Encoding::ALL {
+            assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+        }
+    }
+
+    #[test]
+    fn test_message_round_trips_in_every_encoding() {
+        let message = Message::new_server_busy("abc", std::time::Duration::from_millis(500));
+        for encoding in Encoding::ALL {
+            let bytes = encoding.encode(&message).unwrap();
+            let decoded: Message = encoding.decode(&bytes).unwrap();
+            assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(&message).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_msgpack_is_smaller_and_converts_to_json() {
+        let json = r#"{"type":"message","recipientPublicKey":"abcd","message":"hi","sequence":7}"#;
+        let packed = Encoding::MessagePack.encode_json(json).unwrap();
+        assert!(packed.len() < json.len());
+
+        let back = Encoding::MessagePack.decode_json(&packed).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&back).unwrap(),
+            serde_json::from_str::<serde_json::Value>(json).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_binary_rejected_on_json_connection() {
+        assert!(matches!(
+            Encoding::Json.decode_json(b"\x81"),
+            Err(EncodingError::UnexpectedBinary)
+        ));
+        assert!(Encoding::MessagePack.decode_json(b"\xc1").is_err());
+    }
+
+    #[test]
+    fn test_msgpack_nesting_limited() {
+        // Each 0x91 opens an array holding the next
+        let nested = |depth| {
+            let mut bytes = vec![0x91; depth];
+            bytes.push(0xc0);
+            bytes
+        };
+        assert!(Encoding::MessagePack
+            .decode_json(&nested(MAX_DEPTH - 1))
+            .is_ok());
+        assert!(Encoding::MessagePack
+            .decode_json(&nested(MAX_DEPTH + 1))
+            .is_err());
+        // Deep enough to overflow the stack without the limit
+        assert!(Encoding::MessagePack.decode_json(&nested(100_000)).is_err());
+        assert!(Encoding::MessagePack
+            .decode::<Message>(&nested(100_000))
+            .is_err());
+    }
+}
@@ -0,0 +1,612 @@
+//! Arbitrary protocol values for property tests and fuzzing
+//!
+//! Every wire type implements [`Arbitrary`], so tests (and external fuzz
+//! targets, with the `arbitrary` feature) can build random but well-formed
+//! messages from raw bytes. The impls are written out rather than derived
+//! because hex fields are lowercased as they are parsed: a derived impl
+//! would produce "ABCD" keys that can never survive a round trip, so hex
+//! fields here are always lowercase hex, like a conforming peer sends.
+//!
+//! Free text fields are biased towards the awkward cases: characters of
+//! every UTF-8 length, ones JSON has to escape, and strings of up to
+//! [`HUGE_TEXT_CHARS`] characters.
+//!
+//! The tests check, for every type and encoding, that a value survives
+//! serialize → deserialize unchanged, that JSON objects decode the same
+//! whatever order their fields come in, and that randomly mutated encodings
+//! either fail to decode or decode to a value that is itself stable. They
+//! run a few hundred cases by default; for a longer run use the nextest
+//! profile, with as many iterations as there is time for:
+//!
+//! ```text
+//! PROFILE_FUZZ_ITERATIONS=100000 cargo nextest run --profile fuzz
+//! ```
+//!
+//! A failure reports its seed; `PROFILE_FUZZ_SEED=<seed>` with
+//! `PROFILE_FUZZ_ITERATIONS=1` replays exactly that case.
+
+use super::attachment::AttachmentManifest;
+use super::auth::{AuthErrorMessage, AuthMessage, AuthSuccessMessage, ResumeMessage};
+use super::encoding::Encoding;
+use super::{
+    ErrorMessage, FilterAction, LobbyMessage, LobbyUpdateMessage, LobbyUser, Message, SenderFilter,
+    Status,
+};
+use crate::canonical::CanonicalVersion;
+use crate::crypto::ratchet::RatchetEnvelope;
+use crate::crypto::rollover::KeyRollover;
+use arbitrary::{Arbitrary, Result, Unstructured};
+use uuid::Uuid;
+
+/// Longest string [`text`] generates, in characters
+pub const HUGE_TEXT_CHARS: usize = 64 * 1024;
+
+/// Characters strings are built from when not left to chance: one of each
+/// UTF-8 length, the ones JSON must escape, and invisible or
+/// direction-changing ones
+const AWKWARD_CHARS: [char; 16] = [
+    'a',
+    '"',
+    '\\',
+    '/',
+    '\0',
+    '\n',
+    '\u{7f}',
+    'é',
+    '€',
+    '中',
+    '\u{200b}',
+    '\u{202e}',
+    '\u{301}',
+    '\u{fffd}',
+    '😀',
+    '\u{10ffff}',
+];
+
+/// Free text, sometimes made of [`AWKWARD_CHARS`] and sometimes huge
+fn text(u: &mut Unstructured<'_>) -> Result<String> {
+    Ok(match u.int_in_range(0..=7)? {
+        0 => {
+            let len = u.arbitrary_len::<u8>()?;
+            (0..len)
+                .map(|_| u.choose(&AWKWARD_CHARS).copied())
+                .collect::<Result<_>>()?
+        }
+        1 => {
+            let repeated = u.choose(&AWKWARD_CHARS)?.to_string();
+            repeated.repeat(u.int_in_range(1..=HUGE_TEXT_CHARS)?)
+        }
+        _ => u.arbitrary()?,
+    })
+}
+
+fn optional_text(u: &mut Unstructured<'_>) -> Result<Option<String>> {
+    Ok(if u.arbitrary()? { Some(text(u)?) } else { None })
+}
+
+/// Lowercase hex of arbitrary bytes, as hex fields are after parsing
+fn hex_string(u: &mut Unstructured<'_>) -> Result<String> {
+    let bytes: Vec<u8> = u.arbitrary()?;
+    Ok(hex::encode(bytes))
+}
+
+fn optional_hex_string(u: &mut Unstructured<'_>) -> Result<Option<String>> {
+    Ok(if u.arbitrary()? {
+        Some(hex_string(u)?)
+    } else {
+        None
+    })
+}
+
+fn hex_strings(u: &mut Unstructured<'_>) -> Result<Vec<String>> {
+    let len = u.arbitrary_len::<[u8; 32]>()?;
+    (0..len).map(|_| hex_string(u)).collect()
+}
+
+fn uuid(u: &mut Unstructured<'_>) -> Result<Uuid> {
+    Ok(Uuid::from_u128(u.arbitrary()?))
+}
+
+impl<'a> Arbitrary<'a> for Status {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            Status::Online,
+            Status::Offline,
+            Status::Away,
+            Status::Busy,
+            Status::Invisible,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for CanonicalVersion {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[CanonicalVersion::V1, CanonicalVersion::V2])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for LobbyUser {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            public_key: hex_string(u)?,
+            status: u.arbitrary()?,
+            nickname: optional_text(u)?,
+            name: optional_text(u)?,
+            ephemeral: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Message {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=27)? {
+            0 => Message::Text {
+                message_id: uuid(u)?,
+                message: text(u)?,
+                sender_public_key: hex_string(u)?,
+                signature: hex_string(u)?,
+                timestamp: u.arbitrary()?,
+                canonical: u.arbitrary()?,
+                sequence: u.arbitrary()?,
+                server_received_at: u.arbitrary()?,
+            },
+            1 => Message::LobbyUpdate {
+                joined: u.arbitrary()?,
+                left: hex_strings(u)?,
+            },
+            2 => Message::Error {
+                reason: text(u)?,
+                details: optional_text(u)?,
+                recipient_public_key: optional_hex_string(u)?,
+                retry_after_ms: u.arbitrary()?,
+            },
+            3 => Message::Auth {
+                public_key: hex_string(u)?,
+                signature: hex_string(u)?,
+            },
+            4 => Message::Read {
+                message_id: uuid(u)?,
+                reader_public_key: hex_string(u)?,
+                timestamp: u.arbitrary()?,
+            },
+            5 => Message::Viewing {
+                viewer_public_key: hex_string(u)?,
+                viewing: u.arbitrary()?,
+            },
+            6 => Message::RoomMessage {
+                room: text(u)?,
+                message: text(u)?,
+                sender_public_key: hex_string(u)?,
+                signature: hex_string(u)?,
+                timestamp: u.arbitrary()?,
+                canonical: u.arbitrary()?,
+            },
+            7 => Message::RoomUpdate {
+                room: text(u)?,
+                members: hex_strings(u)?,
+            },
+            8 => Message::Nickname {
+                public_key: hex_string(u)?,
+                nickname: optional_text(u)?,
+            },
+            9 => Message::NameClaimed {
+                public_key: hex_string(u)?,
+                name: optional_text(u)?,
+            },
+            10 => Message::LobbyPage {
+                users: u.arbitrary()?,
+                next_cursor: u.arbitrary()?,
+                total: u.arbitrary()?,
+            },
+            11 => Message::Backup {
+                version: u.arbitrary()?,
+                blob: optional_text(u)?,
+                updated_at: u.arbitrary()?,
+            },
+            12 => Message::BackupStored {
+                version: u.arbitrary()?,
+            },
+            13 => Message::Announcement {
+                text: text(u)?,
+                timestamp: u.arbitrary()?,
+            },
+            14 => Message::ServerRestarted {
+                epoch: u.arbitrary()?,
+                started_at: u.arbitrary()?,
+            },
+            15 => Message::SenderFiltersSet {
+                count: u.arbitrary()?,
+                held: u.arbitrary()?,
+            },
+            16 => Message::Close,
+            17 => Message::Sealed {
+                message_id: uuid(u)?,
+                sender_public_key: hex_string(u)?,
+                envelope: u.arbitrary()?,
+            },
+            18 => Message::new_key_rollover(u.arbitrary()?),
+            19 => Message::LastSeen {
+                public_key: hex_string(u)?,
+                online: u.arbitrary()?,
+                last_seen: u.arbitrary()?,
+            },
+            20 => Message::Edit {
+                message_id: uuid(u)?,
+                message: text(u)?,
+                sender_public_key: hex_string(u)?,
+                signature: hex_string(u)?,
+                timestamp: u.arbitrary()?,
+            },
+            21 => Message::Retract {
+                message_id: uuid(u)?,
+                sender_public_key: hex_string(u)?,
+                signature: hex_string(u)?,
+                timestamp: u.arbitrary()?,
+            },
+            22 => Message::Reaction {
+                message_id: uuid(u)?,
+                emoji: text(u)?,
+                removed: u.arbitrary()?,
+                sender_public_key: hex_string(u)?,
+                signature: hex_string(u)?,
+                timestamp: u.arbitrary()?,
+            },
+            23 => Message::AttachmentOffer {
+                manifest: u.arbitrary()?,
+                sender_public_key: hex_string(u)?,
+            },
+            24 => Message::AttachmentAccept {
+                transfer_id: uuid(u)?,
+                until: u.arbitrary()?,
+                sender_public_key: hex_string(u)?,
+            },
+            25 => Message::AttachmentChunk {
+                transfer_id: uuid(u)?,
+                index: u.arbitrary()?,
+                data: hex_string(u)?,
+                sender_public_key: hex_string(u)?,
+            },
+            26 => Message::AttachmentComplete {
+                transfer_id: uuid(u)?,
+                cancelled: u.arbitrary()?,
+                sender_public_key: hex_string(u)?,
+            },
+            _ => Message::new_server_busy(
+                &hex_string(u)?,
+                std::time::Duration::from_millis(u.arbitrary()?),
+            ),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for RatchetEnvelope {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            session: hex_string(u)?,
+            ratchet_key: hex_string(u)?,
+            previous_count: u.arbitrary()?,
+            index: u.arbitrary()?,
+            nonce: hex_string(u)?,
+            ciphertext: hex_string(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for KeyRollover {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            old_public_key: hex_string(u)?,
+            new_public_key: hex_string(u)?,
+            timestamp: u.arbitrary()?,
+            old_signature: hex_string(u)?,
+            new_signature: hex_string(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for AttachmentManifest {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            transfer_id: uuid(u)?,
+            file_name: text(u)?,
+            size: u.arbitrary()?,
+            chunk_size: u.arbitrary()?,
+            chunk_hashes: hex_strings(u)?,
+            file_hash: hex_string(u)?,
+            timestamp: u.arbitrary()?,
+            thumbnail: if u.arbitrary()? {
+                Some(hex_string(u)?)
+            } else {
+                None
+            },
+            signature: hex_string(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ErrorMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            r#type: u.arbitrary()?,
+            reason: text(u)?,
+            details: optional_text(u)?,
+            recipient_public_key: optional_hex_string(u)?,
+            retry_after_ms: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for FilterAction {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[FilterAction::Drop, FilterAction::QueueOnly])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for SenderFilter {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            sender_public_key: hex_string(u)?,
+            action: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for LobbyMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            r#type: u.arbitrary()?,
+            users: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for LobbyUpdateMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            r#type: u.arbitrary()?,
+            joined: u.arbitrary()?,
+            left: hex_strings(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for AuthMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            r#type: u.arbitrary()?,
+            public_key: hex_string(u)?,
+            signature: hex_string(u)?,
+            encodings: u.arbitrary()?,
+            compression: u.arbitrary()?,
+            ephemeral: u.arbitrary()?,
+            share_last_seen: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for AuthSuccessMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            r#type: u.arbitrary()?,
+            users: u.arbitrary()?,
+            total: u.arbitrary()?,
+            next_cursor: u.arbitrary()?,
+            nicknames: u.arbitrary()?,
+            names: u.arbitrary()?,
+            encoding: u.arbitrary()?,
+            compression: u.arbitrary()?,
+            resume_token: u.arbitrary()?,
+            ephemeral: hex_strings(u)?,
+            statuses: u.arbitrary()?,
+            status: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ResumeMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            r#type: u.arbitrary()?,
+            resume_token: hex_string(u)?,
+            encodings: u.arbitrary()?,
+            compression: u.arbitrary()?,
+            ephemeral: u.arbitrary()?,
+            share_last_seen: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for AuthErrorMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Self {
+            r#type: u.arbitrary()?,
+            reason: text(u)?,
+            details: text(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Encoding {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&Encoding::ALL)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use serde_json::Value;
+    use std::fmt::Debug;
+
+    /// Cases per type when `PROFILE_FUZZ_ITERATIONS` is unset
+    const DEFAULT_ITERATIONS: u64 = 300;
+
+    /// Mutated copies decoded per case and encoding
+    const MUTATIONS_PER_CASE: usize = 8;
+
+    fn env_u64(name: &str) -> Option<u64> {
+        std::env::var(name).ok().and_then(|v| v.parse().ok())
+    }
+
+    /// Apply one random edit to an encoded message
+    fn mutate(rng: &mut StdRng, bytes: &mut Vec<u8>) {
+        if bytes.is_empty() {
+            bytes.push(rng.gen());
+            return;
+        }
+        let at = rng.gen_range(0..bytes.len());
+        match rng.gen_range(0..6) {
+            0 => bytes[at] ^= 1 << rng.gen_range(0..8),
+            1 => bytes[at] = rng.gen(),
+            2 => bytes.insert(at, rng.gen()),
+            3 => {
+                bytes.remove(at);
+            }
+            4 => bytes.truncate(at),
+            _ => {
+                let end = rng.gen_range(at..=bytes.len());
+                let chunk = bytes[at..end].to_vec();
+                bytes.splice(at..at, chunk);
+            }
+        }
+    }
+
+    /// `value` as JSON text, with the fields of every object in random order
+    fn shuffled_json(rng: &mut StdRng, value: &Value) -> String {
+        match value {
+            Value::Array(items) => {
+                let items: Vec<_> = items.iter().map(|item| shuffled_json(rng, item)).collect();
+                format!("[{}]", items.join(","))
+            }
+            Value::Object(fields) => {
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.shuffle(rng);
+                let fields: Vec<_> = fields
+                    .into_iter()
+                    .map(|(name, value)| {
+                        format!(
+                            "{}:{}",
+                            Value::from(name.as_str()),
+                            shuffled_json(rng, value)
+                        )
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(","))
+            }
+            leaf => leaf.to_string(),
+        }
+    }
+
+    /// Round-trip and mutation-fuzz random values of `T` in every encoding
+    fn fuzz<T>()
+    where
+        T: for<'a> Arbitrary<'a> + Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        let first_seed = env_u64("PROFILE_FUZZ_SEED").unwrap_or(0);
+        let iterations = env_u64("PROFILE_FUZZ_ITERATIONS").unwrap_or(DEFAULT_ITERATIONS);
+
+        for seed in first_seed..first_seed.saturating_add(iterations) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut raw = vec![0u8; rng.gen_range(0..2048)];
+            rng.fill(&mut raw[..]);
+            let Ok(value) = T::arbitrary_take_rest(Unstructured::new(&raw)) else {
+                continue;
+            };
+
+            let shuffled = shuffled_json(&mut rng, &serde_json::to_value(&value).unwrap());
+            let decoded: T = serde_json::from_str(&shuffled)
+                .unwrap_or_else(|e| panic!("seed {}: reordered JSON decode failed: {}", seed, e));
+            assert_eq!(decoded, value, "seed {}: reordered JSON", seed);
+
+            for encoding in Encoding::ALL {
+                let name = encoding.name();
+                let bytes = encoding
+                    .encode(&value)
+                    .unwrap_or_else(|e| panic!("seed {}: {} encode failed: {}", seed, name, e));
+                let decoded: T = encoding
+                    .decode(&bytes)
+                    .unwrap_or_else(|e| panic!("seed {}: {} decode failed: {}", seed, name, e));
+                assert_eq!(decoded, value, "seed {}: {} round trip", seed, name);
+
+                // Corrupt input may be rejected, never panic; whatever it
+                // decodes to must round-trip like any other value
+                for _ in 0..MUTATIONS_PER_CASE {
+                    let mut mutated = bytes.clone();
+                    for _ in 0..rng.gen_range(1..=4) {
+                        mutate(&mut rng, &mut mutated);
+                    }
+                    let Ok(decoded) = encoding.decode::<T>(&mutated) else {
+                        continue;
+                    };
+                    let again: T = encoding
+                        .decode(&encoding.encode(&decoded).unwrap())
+                        .unwrap_or_else(|e| {
+                            panic!("seed {}: {} re-decode failed: {}", seed, name, e)
+                        });
+                    assert_eq!(again, decoded, "seed {}: {} mutated round trip", seed, name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_message() {
+        fuzz::<Message>();
+    }
+
+    #[test]
+    fn fuzz_lobby_messages() {
+        fuzz::<LobbyUser>();
+        fuzz::<LobbyMessage>();
+        fuzz::<LobbyUpdateMessage>();
+        fuzz::<ErrorMessage>();
+    }
+
+    #[test]
+    fn fuzz_sender_filters() {
+        fuzz::<SenderFilter>();
+    }
+
+    #[test]
+    fn fuzz_auth_messages() {
+        fuzz::<AuthMessage>();
+        fuzz::<AuthSuccessMessage>();
+        fuzz::<ResumeMessage>();
+        fuzz::<AuthErrorMessage>();
+    }
+
+    #[test]
+    fn fuzz_generates_every_message_type() {
+        let mut seen = std::collections::BTreeSet::new();
+        for seed in 0..500u64 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut raw = vec![0u8; 256];
+            rng.fill(&mut raw[..]);
+            if let Ok(message) = Message::arbitrary(&mut Unstructured::new(&raw)) {
+                let json = serde_json::to_value(&message).unwrap();
+                seen.insert(json["message_type"].as_str().unwrap().to_string());
+            }
+        }
+        assert_eq!(seen.len(), 27, "missing message types, saw {:?}", seen);
+    }
+
+    #[test]
+    fn fuzz_generates_awkward_and_huge_text() {
+        let (mut non_ascii, mut huge) = (false, false);
+        for seed in 0..500u64 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut raw = vec![0u8; 64];
+            rng.fill(&mut raw[..]);
+            if let Ok(text) = text(&mut Unstructured::new(&raw)) {
+                non_ascii |= !text.is_ascii();
+                huge |= text.chars().count() > HUGE_TEXT_CHARS / 4;
+            }
+        }
+        assert!(non_ascii && huge);
+    }
+}
@@ -3,60 +3,628 @@
 //! This module defines all message types used in the WebSocket protocol
 //! for authentication, messaging, and lobby updates.
 
+use crate::canonical::CanonicalVersion;
+use crate::crypto::ratchet::RatchetEnvelope;
+use crate::crypto::rollover::KeyRollover;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub mod attachment;
+pub mod auth;
+pub mod compression;
+pub mod encoding;
+#[cfg(any(test, feature = "arbitrary"))]
+pub mod fuzz;
+
+pub use attachment::AttachmentManifest;
+pub use auth::{AuthErrorMessage, AuthMessage, AuthSuccessMessage, ResumeMessage, AUTH_CHALLENGE};
+pub use compression::{Compression, CompressionConfig, FrameCodec};
+pub use encoding::Encoding;
+
+/// Error reason sent when a message recipient is not online
+pub const RECIPIENT_OFFLINE_REASON: &str = "offline";
+
+/// Error reason sent when the server is too loaded to accept a message
+pub const SERVER_BUSY_REASON: &str = "server_busy";
+
+/// Close reason sent to every client when the server shuts down
+pub const SERVER_SHUTDOWN_REASON: &str = "server_shutdown";
+
+/// What joins a close reason to the retry hint appended to it
+const RETRY_HINT_SEPARATOR: &str = "; retry_after_ms=";
+
+/// Close frame reason asking the client to reconnect after `retry_after`
+///
+/// Close frames have no room for a JSON body, so the hint is appended to
+/// the reason: `server_shutdown; retry_after_ms=2500`. Use
+/// [`split_retry_hint`] to read it back.
+pub fn close_reason_with_retry(reason: &str, retry_after: std::time::Duration) -> String {
+    format!(
+        "{}{}{}",
+        reason,
+        RETRY_HINT_SEPARATOR,
+        retry_after.as_millis()
+    )
+}
+
+/// Split a close reason into the reason itself and its retry hint, if any
+///
+/// Reasons without a hint (or with one that doesn't parse) come back
+/// unchanged with None.
+pub fn split_retry_hint(reason: &str) -> (&str, Option<std::time::Duration>) {
+    let Some((base, millis)) = reason.split_once(RETRY_HINT_SEPARATOR) else {
+        return (reason, None);
+    };
+    match millis.parse() {
+        Ok(millis) => (base, Some(std::time::Duration::from_millis(millis))),
+        Err(_) => (reason, None),
+    }
+}
+
+/// Deserializers that lowercase hex fields (public keys and signatures)
+///
+/// Peers may send hex in any case, but keys are compared as strings
+/// throughout the lobby, rooms and client state. Normalizing once, as the
+/// JSON is parsed, keeps "ABCD..." and "abcd..." from being treated as two
+/// different keys. Use with `#[serde(deserialize_with = "...")]`.
+pub mod lowercase_hex {
+    use serde::de::{self, Visitor};
+    use serde::{Deserialize, Deserializer};
+    use std::borrow::Cow;
+
+    /// Deserialize a hex string, lowercased
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        String::deserialize(deserializer).map(|s| s.to_ascii_lowercase())
+    }
+
+    /// Deserialize a hex string, borrowing it from the input when it is
+    /// already lowercase and unescaped
+    ///
+    /// Use with `#[serde(borrow, deserialize_with = "...")]` on a
+    /// `Cow<'a, str>` field.
+    pub fn deserialize_cow<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Cow<'de, str>, D::Error> {
+        struct CowVisitor;
+
+        impl<'de> Visitor<'de> for CowVisitor {
+            type Value = Cow<'de, str>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a hex string")
+            }
+
+            fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+                Ok(if v.bytes().any(|b| b.is_ascii_uppercase()) {
+                    Cow::Owned(v.to_ascii_lowercase())
+                } else {
+                    Cow::Borrowed(v)
+                })
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Cow::Owned(v.to_ascii_lowercase()))
+            }
+        }
+
+        deserializer.deserialize_str(CowVisitor)
+    }
+
+    /// Deserialize an optional hex string, lowercased when present
+    pub fn deserialize_option<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<String>, D::Error> {
+        Option::<String>::deserialize(deserializer).map(|s| s.map(|s| s.to_ascii_lowercase()))
+    }
+
+    /// Deserialize a list of hex strings, each lowercased
+    pub fn deserialize_vec<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error> {
+        Vec::<String>::deserialize(deserializer)
+            .map(|v| v.into_iter().map(|s| s.to_ascii_lowercase()).collect())
+    }
+}
 
 /// General message type for WebSocket communication
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "message_type")]
 pub enum Message {
     /// Text message from one user to another
     Text {
+        /// Unique id chosen by the sender; frames without one get a fresh id
+        #[serde(rename = "messageId", default = "Uuid::new_v4")]
+        message_id: Uuid,
         message: String,
-        #[serde(rename = "senderPublicKey")]
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
         sender_public_key: String,
+        #[serde(deserialize_with = "lowercase_hex::deserialize")]
         signature: String,
         timestamp: String,
+        /// Canonical encoding the signature covers; absent means version 1
+        #[serde(default, skip_serializing_if = "CanonicalVersion::is_legacy")]
+        canonical: CanonicalVersion,
+        /// The sender's sequence number, which the signature then covers
+        /// (see [`crate::canonical::direct_message`])
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+        /// When the server received the message (RFC 3339), stamped on routing
+        #[serde(
+            rename = "serverReceivedAt",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        server_received_at: Option<String>,
     },
     /// Lobby update with user join/leave events
     LobbyUpdate {
         joined: Vec<LobbyUser>,
+        #[serde(deserialize_with = "lowercase_hex::deserialize_vec")]
         left: Vec<String>,
     },
     /// Error message
     Error {
         reason: String,
         details: Option<String>,
+        /// Recipient of the message the error is about, e.g. the offline
+        /// user of a `RECIPIENT_OFFLINE_REASON` error
+        #[serde(
+            rename = "recipientPublicKey",
+            default,
+            skip_serializing_if = "Option::is_none",
+            deserialize_with = "lowercase_hex::deserialize_option"
+        )]
+        recipient_public_key: Option<String>,
+        /// Delay in milliseconds before the message may be resent, set on
+        /// `SERVER_BUSY_REASON` errors
+        #[serde(
+            rename = "retryAfterMs",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        retry_after_ms: Option<u64>,
     },
     /// Authentication message
     Auth {
-        #[serde(rename = "publicKey")]
+        #[serde(rename = "publicKey", deserialize_with = "lowercase_hex::deserialize")]
+        public_key: String,
+        #[serde(deserialize_with = "lowercase_hex::deserialize")]
+        signature: String,
+    },
+    /// Read receipt from a recipient for a previously delivered message
+    Read {
+        #[serde(rename = "messageId")]
+        message_id: Uuid,
+        #[serde(
+            rename = "readerPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        reader_public_key: String,
+        timestamp: String,
+    },
+    /// Hint that a user has (or no longer has) the conversation with the
+    /// recipient open; only sent by clients that opted in
+    Viewing {
+        #[serde(
+            rename = "viewerPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        viewer_public_key: String,
+        viewing: bool,
+    },
+    /// Text message posted to a room, fanned out to every member
+    RoomMessage {
+        room: String,
+        message: String,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+        #[serde(deserialize_with = "lowercase_hex::deserialize")]
+        signature: String,
+        timestamp: String,
+        /// Canonical encoding the signature covers; absent means version 1
+        #[serde(default, skip_serializing_if = "CanonicalVersion::is_legacy")]
+        canonical: CanonicalVersion,
+    },
+    /// Room membership snapshot sent to members after a create/join/leave
+    RoomUpdate {
+        room: String,
+        #[serde(deserialize_with = "lowercase_hex::deserialize_vec")]
+        members: Vec<String>,
+    },
+    /// A user set or cleared their nickname
+    Nickname {
+        #[serde(rename = "publicKey", deserialize_with = "lowercase_hex::deserialize")]
         public_key: String,
+        /// The new nickname, None if it was cleared
+        nickname: Option<String>,
+    },
+    /// A user claimed or released a verified name
+    NameClaimed {
+        #[serde(rename = "publicKey", deserialize_with = "lowercase_hex::deserialize")]
+        public_key: String,
+        /// The claimed name, None if it was released
+        name: Option<String>,
+    },
+    /// One page of online users, answering a `lobby_page` request
+    LobbyPage {
+        users: Vec<LobbyUser>,
+        /// Cursor to request the following page, None on the last page
+        #[serde(rename = "nextCursor")]
+        next_cursor: Option<String>,
+        /// Number of online users matching the request's prefix
+        total: usize,
+    },
+    /// The requester's stored backup, answering a `backup_fetch` request
+    ///
+    /// The blob is encrypted by the client; the server never reads it.
+    Backup {
+        /// Version of the stored backup, 0 if there is none
+        version: u64,
+        /// Hex-encoded encrypted blob, None if there is no backup
+        blob: Option<String>,
+        /// When the backup was stored (RFC 3339)
+        #[serde(rename = "updatedAt")]
+        updated_at: Option<String>,
+    },
+    /// Acknowledges a `backup_store` request
+    BackupStored { version: u64 },
+    /// Acknowledges a `set_filters` request
+    SenderFiltersSet {
+        /// Number of senders now filtered
+        count: usize,
+        /// Messages held from queue-only senders, waiting for `held_fetch`
+        held: usize,
+    },
+    /// Announcement from the server operator to every online user
+    Announcement {
+        text: String,
+        /// When the announcement was sent (RFC 3339)
+        timestamp: String,
+    },
+    /// The server restarted since this user's previous connection; state
+    /// the client kept from before (lobby pages, rooms) is stale
+    ServerRestarted {
+        /// Number of server starts recorded, this one included
+        epoch: u64,
+        /// When this run started (RFC 3339)
+        #[serde(rename = "startedAt")]
+        started_at: String,
+    },
+    /// Direct message encrypted in a ratchet session, relayed unread
+    ///
+    /// The envelope holds the sender's signed text message (see
+    /// [`crate::crypto::ratchet`]).
+    Sealed {
+        /// Unique id chosen by the sender, for duplicate detection and
+        /// delivery tracking
+        #[serde(rename = "messageId", default = "Uuid::new_v4")]
+        message_id: Uuid,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+        envelope: RatchetEnvelope,
+    },
+    /// A user replaced their identity key, checked by the server; clients
+    /// check it again before moving anything to the new key
+    KeyRollover { rollover: KeyRollover },
+    /// New text for a direct message the sender sent earlier
+    ///
+    /// The signature covers [`crate::canonical::message_edit`] of the
+    /// original message id, the new text and the timestamp. Recipients
+    /// only apply it to a message from the same sender.
+    Edit {
+        /// Id of the message being edited
+        #[serde(rename = "messageId")]
+        message_id: Uuid,
+        message: String,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+        #[serde(deserialize_with = "lowercase_hex::deserialize")]
+        signature: String,
+        timestamp: String,
+    },
+    /// Withdrawal of a direct message the sender sent earlier
+    ///
+    /// The signature covers [`crate::canonical::message_retract`] of the
+    /// message id and the timestamp. Recipients only apply it to a message
+    /// from the same sender.
+    Retract {
+        /// Id of the message being retracted
+        #[serde(rename = "messageId")]
+        message_id: Uuid,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+        #[serde(deserialize_with = "lowercase_hex::deserialize")]
+        signature: String,
+        timestamp: String,
+    },
+    /// An emoji reaction to a direct message, or its removal
+    ///
+    /// The signature covers [`crate::canonical::message_reaction`] of the
+    /// message id, the emoji, `removed` and the timestamp. Either side of
+    /// a conversation can react to any of its messages.
+    Reaction {
+        /// Id of the message reacted to
+        #[serde(rename = "messageId")]
+        message_id: Uuid,
+        emoji: String,
+        /// Whether the sender takes back an earlier reaction
+        #[serde(default)]
+        removed: bool,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+        #[serde(deserialize_with = "lowercase_hex::deserialize")]
         signature: String,
+        timestamp: String,
+    },
+    /// A file offered by its sender, described by a signed manifest
+    ///
+    /// Nothing more is sent until the recipient accepts it. The server
+    /// checked the manifest's signature and limits; recipients check the
+    /// signature again, then every chunk against its hash.
+    AttachmentOffer {
+        manifest: AttachmentManifest,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+    },
+    /// The recipient of an offered file asks for its chunks below `until`
+    ///
+    /// Sent again for each further window of chunks; `sender_public_key` is
+    /// the recipient who accepted.
+    AttachmentAccept {
+        #[serde(rename = "transferId")]
+        transfer_id: Uuid,
+        until: u32,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+    },
+    /// One chunk of an accepted file, hex-encoded, relayed in order
+    AttachmentChunk {
+        #[serde(rename = "transferId")]
+        transfer_id: Uuid,
+        index: u32,
+        data: String,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+    },
+    /// The sender sent every chunk of a file, or either side gave up on it
+    AttachmentComplete {
+        #[serde(rename = "transferId")]
+        transfer_id: Uuid,
+        /// Whether the transfer was abandoned instead of finished
+        #[serde(default)]
+        cancelled: bool,
+        #[serde(
+            rename = "senderPublicKey",
+            deserialize_with = "lowercase_hex::deserialize"
+        )]
+        sender_public_key: String,
+    },
+    /// When a user was last online, answering a `query_last_seen` request
+    LastSeen {
+        #[serde(rename = "publicKey", deserialize_with = "lowercase_hex::deserialize")]
+        public_key: String,
+        /// Whether the user is in the lobby now
+        online: bool,
+        /// When the user last left the lobby (RFC 3339); None while they
+        /// are online, or if they don't share it or were never seen
+        #[serde(rename = "lastSeen", default, skip_serializing_if = "Option::is_none")]
+        last_seen: Option<String>,
     },
     /// Close frame
     Close,
 }
 
+/// What the server does with direct messages from a filtered sender
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// Discard the message; the sender is not told
+    Drop,
+    /// Hold the message on the server until the recipient asks for it
+    QueueOnly,
+}
+
+impl FilterAction {
+    /// Name used in the protocol and in signed filter lists
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FilterAction::Drop => "drop",
+            FilterAction::QueueOnly => "queue_only",
+        }
+    }
+}
+
+/// One entry of a recipient's sender filter list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SenderFilter {
+    #[serde(
+        rename = "senderPublicKey",
+        deserialize_with = "lowercase_hex::deserialize"
+    )]
+    pub sender_public_key: String,
+    pub action: FilterAction,
+}
+
+/// Presence of a lobby user, as sent in the `status` field
+///
+/// Users pick `Online`, `Away`, `Busy` or `Invisible` with a `set_status`
+/// request. The server never lists invisible users, so other clients only
+/// see that status on their own connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    #[default]
+    Online,
+    Offline,
+    Away,
+    Busy,
+    Invisible,
+}
+
+impl Status {
+    /// Name used in the protocol and in signed status requests
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Online => "online",
+            Status::Offline => "offline",
+            Status::Away => "away",
+            Status::Busy => "busy",
+            Status::Invisible => "invisible",
+        }
+    }
+
+    /// Whether a user can choose this status (everything but offline)
+    pub fn is_selectable(self) -> bool {
+        self != Status::Offline
+    }
+}
+
 /// Represents a user in the lobby with optional online status.
 ///
 /// This is the unified type for lobby users. The `status` field is optional:
-/// - `None` or `Some("online")` indicates the user is online
-/// - `Some("offline")` indicates the user is offline
+/// - `None` or `Some(Status::Online)` indicates the user is online
+/// - `Some(Status::Offline)` indicates the user is offline
+/// - `Some(Status::Away)` or `Some(Status::Busy)` is an online user's
+///   chosen status
+///
+/// `nickname` is the display name the user chose, if any, `name` the
+/// unique name they claimed on a server with a name registry, and
+/// `ephemeral` marks a guest whose key will be gone once they leave.
 ///
 /// This consolidation replaces the previous three types (`LobbyUser`,
 /// `LobbyUserCompact`, and `LobbyUserWithStatus`) into a single type
 /// to reduce bug risk and maintenance overhead.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LobbyUser {
-    #[serde(rename = "publicKey")]
+    #[serde(rename = "publicKey", deserialize_with = "lowercase_hex::deserialize")]
     pub public_key: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub status: Option<String>,
+    pub status: Option<Status>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    /// Name the user claimed, verified and kept unique by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The user has a throwaway guest identity that is never saved
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ephemeral: bool,
+}
+
+impl LobbyUser {
+    /// Create an online lobby user
+    pub fn new(public_key: String, nickname: Option<String>) -> Self {
+        Self {
+            public_key,
+            status: None,
+            nickname,
+            name: None,
+            ephemeral: false,
+        }
+    }
+
+    /// Attach the name the user claimed, if any
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Tag the user as having a throwaway guest identity
+    pub fn with_ephemeral(mut self, ephemeral: bool) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    /// Attach the user's chosen status; online is left implicit
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = (status != Status::Online).then_some(status);
+        self
+    }
+
+    /// Whether the user is online (a missing status means online)
+    ///
+    /// Away and busy users are online too.
+    pub fn is_online(&self) -> bool {
+        self.status != Some(Status::Offline)
+    }
+}
+
+/// General error message for protocol errors outside authentication
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorMessage {
+    pub r#type: String,
+    pub reason: String,
+    pub details: Option<String>,
+    /// Recipient the error is about, as in [`Message::Error`]
+    #[serde(
+        rename = "recipientPublicKey",
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "lowercase_hex::deserialize_option"
+    )]
+    pub recipient_public_key: Option<String>,
+    /// Retry delay in milliseconds, as in [`Message::Error`]
+    #[serde(
+        rename = "retryAfterMs",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub retry_after_ms: Option<u64>,
+}
+
+impl ErrorMessage {
+    /// Create a new general error message
+    pub fn new(reason: String) -> Self {
+        Self {
+            r#type: "error".to_string(),
+            reason,
+            details: None,
+            recipient_public_key: None,
+            retry_after_ms: None,
+        }
+    }
+
+    /// Create a new error message with details
+    pub fn with_details(reason: String, details: String) -> Self {
+        Self {
+            r#type: "error".to_string(),
+            reason,
+            details: Some(details),
+            recipient_public_key: None,
+            retry_after_ms: None,
+        }
+    }
 }
 
 /// Lobby message from server - sent on successful authentication
 /// Contains the initial state of all online users
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LobbyMessage {
     #[serde(default)]
     pub r#type: String,
@@ -70,42 +638,39 @@ pub struct LobbyMessage {
 /// Having a separate deserialization type prevents tight coupling between
 /// the protocol enum and incoming message formats.
 ///
-/// DESIGN RATIONALE (Per-Departure Notifications):
-/// When multiple users disconnect simultaneously, each disconnection triggers
-/// a separate broadcast rather than batching into a single message. This design:
-///
-/// - **Simplicity**: Each leave event is atomic and independent
-/// - **Timeliness**: Clients receive immediate feedback when any user leaves
-/// - **Consistency**: No edge cases around batch ordering or partial failures
-/// - **AC Compliance**: Matches AC#1 format `{left: [{publicKey: "..."}]}` (single user per message)
-///
-/// The alternative (batched notifications) would require:
-/// - Waiting for multiple disconnects before sending (delay)
-/// - Complex ordering guarantees
-/// - More complex client-side handling
-///
-/// See: Story 2.4 Review Follow-up [MEDIUM] - Document per-departure notification design
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// BATCHING: The server collects joins and leaves for
+/// `config::lobby::UPDATE_COALESCE_WINDOW` and sends them as one update, so
+/// either list may name several users. Apply `left` before `joined`: a user
+/// who reconnected within the window appears in both and must end up
+/// present.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LobbyUpdateMessage {
     #[serde(default)]
     pub r#type: String,
     pub joined: Vec<LobbyUser>,
+    #[serde(deserialize_with = "lowercase_hex::deserialize_vec")]
     pub left: Vec<String>,
 }
 
 impl Message {
-    /// Create a new text message
+    /// Create a new text message, signed over `canonical`
     pub fn new_text(
+        message_id: Uuid,
         message: String,
         sender_public_key: String,
         signature: String,
         timestamp: String,
+        canonical: CanonicalVersion,
     ) -> Self {
         Self::Text {
+            message_id,
             message,
             sender_public_key,
             signature,
             timestamp,
+            canonical,
+            sequence: None,
+            server_received_at: None,
         }
     }
 
@@ -127,7 +692,76 @@ impl Message {
 
     /// Create an error message
     pub fn new_error(reason: String, details: Option<String>) -> Self {
-        Self::Error { reason, details }
+        Self::Error {
+            reason,
+            details,
+            recipient_public_key: None,
+            retry_after_ms: None,
+        }
+    }
+
+    /// Create the error sent when a message recipient is not online
+    ///
+    /// The recipient key is carried in `recipientPublicKey`; use
+    /// [`Message::offline_recipient`] to read it back. `details` only
+    /// describes the error to the user.
+    pub fn new_recipient_offline(recipient_key: &str) -> Self {
+        Self::Error {
+            reason: RECIPIENT_OFFLINE_REASON.to_string(),
+            details: Some(format!("User {} is not currently online", recipient_key)),
+            recipient_public_key: Some(recipient_key.to_ascii_lowercase()),
+            retry_after_ms: None,
+        }
+    }
+
+    /// Get the recipient key from a recipient-offline error
+    ///
+    /// Returns None for any other message.
+    pub fn offline_recipient(&self) -> Option<&str> {
+        match self {
+            Self::Error {
+                reason,
+                recipient_public_key: Some(recipient_public_key),
+                ..
+            } if reason == RECIPIENT_OFFLINE_REASON => Some(recipient_public_key),
+            _ => None,
+        }
+    }
+
+    /// Create the error sent when the server sheds a message under load
+    ///
+    /// The message may be resent after `retry_after`. The recipient key and
+    /// delay are carried in `recipientPublicKey` and `retryAfterMs`; use
+    /// [`Message::server_busy_retry`] to read them back.
+    pub fn new_server_busy(recipient_key: &str, retry_after: std::time::Duration) -> Self {
+        let retry_after_ms = u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX);
+        Self::Error {
+            reason: SERVER_BUSY_REASON.to_string(),
+            details: Some(format!(
+                "Server is busy; retry the message to {} after {} ms",
+                recipient_key, retry_after_ms
+            )),
+            recipient_public_key: Some(recipient_key.to_ascii_lowercase()),
+            retry_after_ms: Some(retry_after_ms),
+        }
+    }
+
+    /// Get the recipient key and retry delay from a server-busy error
+    ///
+    /// Returns None for any other message.
+    pub fn server_busy_retry(&self) -> Option<(&str, std::time::Duration)> {
+        match self {
+            Self::Error {
+                reason,
+                recipient_public_key: Some(recipient_public_key),
+                retry_after_ms: Some(retry_after_ms),
+                ..
+            } if reason == SERVER_BUSY_REASON => Some((
+                recipient_public_key,
+                std::time::Duration::from_millis(*retry_after_ms),
+            )),
+            _ => None,
+        }
     }
 
     /// Create an authentication message
@@ -137,6 +771,221 @@ impl Message {
             signature,
         }
     }
+
+    /// Create a room message, signed over `canonical`
+    pub fn new_room_message(
+        room: String,
+        message: String,
+        sender_public_key: String,
+        signature: String,
+        timestamp: String,
+        canonical: CanonicalVersion,
+    ) -> Self {
+        Self::RoomMessage {
+            room,
+            message,
+            sender_public_key,
+            signature,
+            timestamp,
+            canonical,
+        }
+    }
+
+    /// Create a room membership update
+    pub fn new_room_update(room: String, members: Vec<String>) -> Self {
+        Self::RoomUpdate { room, members }
+    }
+
+    /// Create a page of lobby users
+    pub fn new_lobby_page(
+        users: Vec<LobbyUser>,
+        next_cursor: Option<String>,
+        total: usize,
+    ) -> Self {
+        Self::LobbyPage {
+            users,
+            next_cursor,
+            total,
+        }
+    }
+
+    /// Create a nickname change notification
+    pub fn new_nickname(public_key: String, nickname: Option<String>) -> Self {
+        Self::Nickname {
+            public_key,
+            nickname,
+        }
+    }
+
+    /// Create a name claim notification
+    pub fn new_name_claimed(public_key: String, name: Option<String>) -> Self {
+        Self::NameClaimed { public_key, name }
+    }
+
+    /// Create a backup fetch response
+    pub fn new_backup(version: u64, blob: Option<String>, updated_at: Option<String>) -> Self {
+        Self::Backup {
+            version,
+            blob,
+            updated_at,
+        }
+    }
+
+    /// Create a `set_filters` acknowledgement
+    pub fn new_sender_filters_set(count: usize, held: usize) -> Self {
+        Self::SenderFiltersSet { count, held }
+    }
+
+    /// Create a server announcement
+    pub fn new_announcement(text: String, timestamp: String) -> Self {
+        Self::Announcement { text, timestamp }
+    }
+
+    /// Create a notice that the server restarted
+    pub fn new_server_restarted(epoch: u64, started_at: String) -> Self {
+        Self::ServerRestarted { epoch, started_at }
+    }
+
+    /// Create a read receipt for a delivered message
+    pub fn new_read(message_id: Uuid, reader_public_key: String, timestamp: String) -> Self {
+        Self::Read {
+            message_id,
+            reader_public_key,
+            timestamp,
+        }
+    }
+
+    /// Create a sealed message from `sender_public_key`
+    pub fn new_sealed(
+        message_id: Uuid,
+        sender_public_key: String,
+        envelope: RatchetEnvelope,
+    ) -> Self {
+        Self::Sealed {
+            message_id,
+            sender_public_key,
+            envelope,
+        }
+    }
+
+    /// Create an announcement of a key rollover
+    pub fn new_key_rollover(rollover: KeyRollover) -> Self {
+        Self::KeyRollover { rollover }
+    }
+
+    /// Create a hint that `viewer_public_key` opened or closed the
+    /// conversation with the recipient
+    pub fn new_viewing(viewer_public_key: String, viewing: bool) -> Self {
+        Self::Viewing {
+            viewer_public_key,
+            viewing,
+        }
+    }
+
+    /// Create an edit of the message `message_id`
+    pub fn new_edit(
+        message_id: Uuid,
+        message: String,
+        sender_public_key: String,
+        signature: String,
+        timestamp: String,
+    ) -> Self {
+        Self::Edit {
+            message_id,
+            message,
+            sender_public_key,
+            signature,
+            timestamp,
+        }
+    }
+
+    /// Create a retraction of the message `message_id`
+    pub fn new_retract(
+        message_id: Uuid,
+        sender_public_key: String,
+        signature: String,
+        timestamp: String,
+    ) -> Self {
+        Self::Retract {
+            message_id,
+            sender_public_key,
+            signature,
+            timestamp,
+        }
+    }
+
+    /// Create a reaction to the message `message_id`, or its removal
+    pub fn new_reaction(
+        message_id: Uuid,
+        emoji: String,
+        removed: bool,
+        sender_public_key: String,
+        signature: String,
+        timestamp: String,
+    ) -> Self {
+        Self::Reaction {
+            message_id,
+            emoji,
+            removed,
+            sender_public_key,
+            signature,
+            timestamp,
+        }
+    }
+
+    /// Create an offer of the file `manifest` describes
+    pub fn new_attachment_offer(manifest: AttachmentManifest, sender_public_key: String) -> Self {
+        Self::AttachmentOffer {
+            manifest,
+            sender_public_key,
+        }
+    }
+
+    /// Create a request for the chunks of `transfer_id` below `until`
+    pub fn new_attachment_accept(transfer_id: Uuid, until: u32, sender_public_key: String) -> Self {
+        Self::AttachmentAccept {
+            transfer_id,
+            until,
+            sender_public_key,
+        }
+    }
+
+    /// Create chunk `index` of `transfer_id`, `data` being hex
+    pub fn new_attachment_chunk(
+        transfer_id: Uuid,
+        index: u32,
+        data: String,
+        sender_public_key: String,
+    ) -> Self {
+        Self::AttachmentChunk {
+            transfer_id,
+            index,
+            data,
+            sender_public_key,
+        }
+    }
+
+    /// Create the end of `transfer_id`, finished or abandoned
+    pub fn new_attachment_complete(
+        transfer_id: Uuid,
+        cancelled: bool,
+        sender_public_key: String,
+    ) -> Self {
+        Self::AttachmentComplete {
+            transfer_id,
+            cancelled,
+            sender_public_key,
+        }
+    }
+
+    /// Create an answer to a last-seen query for `public_key`
+    pub fn new_last_seen(public_key: String, online: bool, last_seen: Option<String>) -> Self {
+        Self::LastSeen {
+            public_key,
+            online,
+            last_seen,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -145,20 +994,26 @@ mod tests {
 
     #[test]
     fn test_message_text_creation() {
+        let id = Uuid::new_v4();
         let msg = Message::new_text(
+            id,
             "Hello".to_string(),
             "sender_key".to_string(),
             "signature".to_string(),
             "2025-12-20T10:00:00Z".to_string(),
+            CanonicalVersion::CURRENT,
         );
 
         match msg {
             Message::Text {
+                message_id,
                 message,
                 sender_public_key,
                 signature,
                 timestamp,
+                ..
             } => {
+                assert_eq!(message_id, id);
                 assert_eq!(message, "Hello");
                 assert_eq!(sender_public_key, "sender_key");
                 assert_eq!(signature, "signature");
@@ -173,29 +1028,39 @@ mod tests {
         let user = LobbyUser {
             public_key: "test_key".to_string(),
             status: None,
+            nickname: None,
+            name: None,
+            ephemeral: false,
         };
         assert_eq!(user.public_key, "test_key");
     }
 
     #[test]
     fn test_serialization() {
+        let id = Uuid::new_v4();
         let msg = Message::new_text(
+            id,
             "Test message".to_string(),
             "test_key".to_string(),
             "test_sig".to_string(),
             "2025-12-20T10:00:00Z".to_string(),
+            CanonicalVersion::CURRENT,
         );
 
         let serialized = serde_json::to_string(&msg).unwrap();
         let deserialized: Message = serde_json::from_str(&serialized).unwrap();
 
+        assert!(serialized.contains(&format!(r#""messageId":"{}""#, id)));
         match deserialized {
             Message::Text {
+                message_id,
                 message,
                 sender_public_key,
                 signature,
                 timestamp,
+                ..
             } => {
+                assert_eq!(message_id, id);
                 assert_eq!(message, "Test message");
                 assert_eq!(sender_public_key, "test_key");
                 assert_eq!(signature, "test_sig");
@@ -205,6 +1070,434 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_text_without_message_id_gets_fresh_id() {
+        let json = r#"{"message_type":"Text","message":"hi","senderPublicKey":"k","signature":"s","timestamp":"t"}"#;
+        let first: Message = serde_json::from_str(json).unwrap();
+        let second: Message = serde_json::from_str(json).unwrap();
+
+        match (first, second) {
+            (Message::Text { message_id: a, .. }, Message::Text { message_id: b, .. }) => {
+                assert!(!a.is_nil());
+                assert_ne!(a, b);
+            }
+            _ => panic!("Expected Text messages"),
+        }
+    }
+
+    #[test]
+    fn test_uppercase_hex_normalized_and_verifies() {
+        let private_key = crate::generate_private_key().unwrap();
+        let public_key = hex::encode(crate::derive_public_key(&private_key).unwrap().as_bytes());
+        let signature = hex::encode(crate::sign_message(&private_key, b"hi:t").unwrap());
+        let json = serde_json::json!({
+            "message_type": "Text",
+            "message": "hi",
+            "senderPublicKey": public_key.to_uppercase(),
+            "signature": signature.to_uppercase(),
+            "timestamp": "t",
+        })
+        .to_string();
+
+        match serde_json::from_str::<Message>(&json).unwrap() {
+            Message::Text {
+                sender_public_key,
+                signature: received,
+                ..
+            } => {
+                // Compares equal to the lowercase form everyone else uses
+                assert_eq!(sender_public_key, public_key);
+                assert_eq!(received, signature);
+                let key = crate::PublicKey::new(hex::decode(&sender_public_key).unwrap()).unwrap();
+                assert!(
+                    crate::verify_signature(&key, b"hi:t", &hex::decode(&received).unwrap())
+                        .is_ok()
+                );
+            }
+            other => panic!("Expected Text, got {:?}", other),
+        }
+
+        let update: Message = serde_json::from_str(
+            r#"{"message_type":"LobbyUpdate","joined":[{"publicKey":"ABCD"}],"left":["EF01"]}"#,
+        )
+        .unwrap();
+        match update {
+            Message::LobbyUpdate { joined, left } => {
+                assert_eq!(joined[0].public_key, "abcd");
+                assert_eq!(left, vec!["ef01".to_string()]);
+            }
+            other => panic!("Expected LobbyUpdate, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_receipt_serialization() {
+        let id = Uuid::parse_str("6f1c3a52-8d4e-4b7a-9c2d-1e5f3a7b9c0d").unwrap();
+        let msg = Message::new_read(
+            id,
+            "reader_key".to_string(),
+            "2025-12-20T10:00:05Z".to_string(),
+        );
+
+        let serialized = serde_json::to_string(&msg).unwrap();
+        assert!(serialized.contains(r#""message_type":"Read""#));
+        assert!(serialized.contains(r#""messageId":"6f1c3a52-8d4e-4b7a-9c2d-1e5f3a7b9c0d""#));
+        assert!(serialized.contains(r#""readerPublicKey":"reader_key""#));
+
+        match serde_json::from_str::<Message>(&serialized).unwrap() {
+            Message::Read {
+                message_id,
+                reader_public_key,
+                timestamp,
+            } => {
+                assert_eq!(message_id, id);
+                assert_eq!(reader_public_key, "reader_key");
+                assert_eq!(timestamp, "2025-12-20T10:00:05Z");
+            }
+            _ => panic!("Expected Read message after deserialization"),
+        }
+    }
+
+    #[test]
+    fn test_viewing_serialization() {
+        let msg = Message::new_viewing("ABCD".to_string(), true);
+        let serialized = serde_json::to_string(&msg).unwrap();
+        assert!(serialized.contains(r#""message_type":"Viewing""#));
+        assert!(serialized.contains(r#""viewerPublicKey":"ABCD""#));
+
+        match serde_json::from_str::<Message>(&serialized).unwrap() {
+            Message::Viewing {
+                viewer_public_key,
+                viewing,
+            } => {
+                assert_eq!(viewer_public_key, "abcd");
+                assert!(viewing);
+            }
+            _ => panic!("Expected Viewing message after deserialization"),
+        }
+    }
+
+    #[test]
+    fn test_edit_serialization() {
+        let id = Uuid::nil();
+        let msg = Message::new_edit(
+            id,
+            "Fixed typo".to_string(),
+            "ABCD".to_string(),
+            "EF01".to_string(),
+            "2025-12-20T10:00:00Z".to_string(),
+        );
+        let json: serde_json::Value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["message_type"], "Edit");
+        assert_eq!(json["messageId"], id.to_string());
+        assert_eq!(json["senderPublicKey"], "ABCD");
+
+        match serde_json::from_value::<Message>(json).unwrap() {
+            Message::Edit {
+                sender_public_key,
+                signature,
+                message,
+                ..
+            } => {
+                assert_eq!(sender_public_key, "abcd");
+                assert_eq!(signature, "ef01");
+                assert_eq!(message, "Fixed typo");
+            }
+            other => panic!("Expected Edit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_retract_serialization() {
+        let id = Uuid::nil();
+        let msg = Message::new_retract(
+            id,
+            "ABCD".to_string(),
+            "EF01".to_string(),
+            "2025-12-20T10:00:00Z".to_string(),
+        );
+        let json: serde_json::Value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["message_type"], "Retract");
+        assert_eq!(json["messageId"], id.to_string());
+
+        match serde_json::from_value::<Message>(json).unwrap() {
+            Message::Retract {
+                sender_public_key,
+                signature,
+                ..
+            } => {
+                assert_eq!(sender_public_key, "abcd");
+                assert_eq!(signature, "ef01");
+            }
+            other => panic!("Expected Retract, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reaction_serialization() {
+        let id = Uuid::nil();
+        let msg = Message::new_reaction(
+            id,
+            "👍".to_string(),
+            false,
+            "ABCD".to_string(),
+            "EF01".to_string(),
+            "2025-12-20T10:00:00Z".to_string(),
+        );
+        let json: serde_json::Value = serde_json::to_value(&msg).unwrap();
+        assert_eq!(json["message_type"], "Reaction");
+        assert_eq!(json["emoji"], "👍");
+        assert_eq!(json["removed"], false);
+
+        // Adding is the default
+        let mut without_removed = json.clone();
+        without_removed.as_object_mut().unwrap().remove("removed");
+        assert_eq!(
+            serde_json::from_value::<Message>(without_removed).unwrap(),
+            serde_json::from_value::<Message>(json).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_attachment_messages_serialization() {
+        let id = Uuid::nil();
+        let chunk = Message::new_attachment_chunk(id, 3, "00ff".to_string(), "ABCD".to_string());
+        let json: serde_json::Value = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json["message_type"], "AttachmentChunk");
+        assert_eq!(json["transferId"], id.to_string());
+        assert_eq!(json["index"], 3);
+        assert_eq!(
+            serde_json::from_value::<Message>(json).unwrap(),
+            Message::new_attachment_chunk(id, 3, "00ff".to_string(), "abcd".to_string())
+        );
+
+        // Finishing is the default
+        let complete = Message::new_attachment_complete(id, false, "abcd".to_string());
+        let mut json = serde_json::to_value(&complete).unwrap();
+        json.as_object_mut().unwrap().remove("cancelled");
+        assert_eq!(serde_json::from_value::<Message>(json).unwrap(), complete);
+    }
+
+    #[test]
+    fn test_last_seen_serialization() {
+        let msg = Message::new_last_seen(
+            "ABCD".to_string(),
+            false,
+            Some("2025-12-20T10:00:00Z".to_string()),
+        );
+        let serialized = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            serialized,
+            r#"{"message_type":"LastSeen","publicKey":"ABCD","online":false,"lastSeen":"2025-12-20T10:00:00Z"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Message>(&serialized).unwrap(),
+            Message::new_last_seen(
+                "abcd".to_string(),
+                false,
+                Some("2025-12-20T10:00:00Z".to_string())
+            )
+        );
+
+        // Hidden or unknown users have no time at all
+        let hidden = Message::new_last_seen("abcd".to_string(), false, None);
+        assert!(!serde_json::to_string(&hidden).unwrap().contains("lastSeen"));
+    }
+
+    #[test]
+    fn test_sealed_serialization() {
+        let id = Uuid::new_v4();
+        let envelope = RatchetEnvelope {
+            session: "aa".repeat(32),
+            ratchet_key: "bb".repeat(32),
+            previous_count: 2,
+            index: 7,
+            nonce: "cc".repeat(24),
+            ciphertext: "dd".repeat(64),
+        };
+        let msg = Message::new_sealed(id, "ABCD".to_string(), envelope.clone());
+        let serialized = serde_json::to_string(&msg).unwrap();
+        assert!(serialized.contains(r#""message_type":"Sealed""#));
+        assert!(serialized.contains(r#""senderPublicKey":"ABCD""#));
+        assert!(serialized.contains(r#""previousCount":2"#));
+
+        match serde_json::from_str::<Message>(&serialized).unwrap() {
+            Message::Sealed {
+                message_id,
+                sender_public_key,
+                envelope: parsed,
+            } => {
+                assert_eq!(message_id, id);
+                assert_eq!(sender_public_key, "abcd");
+                assert_eq!(parsed, envelope);
+            }
+            _ => panic!("Expected Sealed message after deserialization"),
+        }
+    }
+
+    #[test]
+    fn test_key_rollover_serialization() {
+        let rollover = KeyRollover {
+            old_public_key: "aa".repeat(32),
+            new_public_key: "bb".repeat(32),
+            timestamp: "2025-12-20T10:00:00Z".to_string(),
+            old_signature: "cc".repeat(64),
+            new_signature: "dd".repeat(64),
+        };
+        let msg = Message::new_key_rollover(rollover.clone());
+        let serialized = serde_json::to_string(&msg).unwrap();
+        assert!(serialized.contains(r#""message_type":"KeyRollover""#));
+        assert!(serialized.contains(r#""newPublicKey":"bbbb"#));
+        assert_eq!(
+            serde_json::from_str::<Message>(&serialized).unwrap(),
+            Message::KeyRollover { rollover }
+        );
+    }
+
+    #[test]
+    fn test_room_message_serialization() {
+        let msg = Message::new_room_message(
+            "general".to_string(),
+            "Hi all".to_string(),
+            "sender_key".to_string(),
+            "sig".to_string(),
+            "2025-12-20T10:00:00Z".to_string(),
+            CanonicalVersion::CURRENT,
+        );
+
+        let serialized = serde_json::to_string(&msg).unwrap();
+        assert!(serialized.contains(r#""message_type":"RoomMessage""#));
+        assert!(serialized.contains(r#""senderPublicKey":"sender_key""#));
+
+        match serde_json::from_str::<Message>(&serialized).unwrap() {
+            Message::RoomMessage { room, message, .. } => {
+                assert_eq!(room, "general");
+                assert_eq!(message, "Hi all");
+            }
+            _ => panic!("Expected RoomMessage after deserialization"),
+        }
+    }
+
+    #[test]
+    fn test_room_update_creation() {
+        let msg = Message::new_room_update("general".to_string(), vec!["key1".to_string()]);
+
+        match msg {
+            Message::RoomUpdate { room, members } => {
+                assert_eq!(room, "general");
+                assert_eq!(members, vec!["key1".to_string()]);
+            }
+            _ => panic!("Expected RoomUpdate message"),
+        }
+    }
+
+    #[test]
+    fn test_lobby_page_serialization() {
+        let msg = Message::new_lobby_page(
+            vec![
+                LobbyUser::new("key1".to_string(), Some("alice".to_string())),
+                LobbyUser::new("key2".to_string(), None),
+            ],
+            Some("key2".to_string()),
+            5,
+        );
+        let json = serde_json::to_string(&msg).unwrap();
+        assert!(json.contains(r#""message_type":"LobbyPage""#));
+        assert!(json.contains(r#""nextCursor":"key2""#));
+        assert!(json.contains(r#"{"publicKey":"key2"}"#));
+
+        match serde_json::from_str::<Message>(&json).unwrap() {
+            Message::LobbyPage {
+                users,
+                next_cursor,
+                total,
+            } => {
+                assert_eq!(users.len(), 2);
+                assert_eq!(users[0].public_key, "key1");
+                assert_eq!(users[0].nickname.as_deref(), Some("alice"));
+                assert_eq!(users[1].nickname, None);
+                assert_eq!(next_cursor, Some("key2".to_string()));
+                assert_eq!(total, 5);
+            }
+            _ => panic!("Expected LobbyPage message"),
+        }
+    }
+
+    #[test]
+    fn test_recipient_offline_round_trip() {
+        let msg = Message::new_recipient_offline("abc123");
+        let serialized = serde_json::to_string(&msg).unwrap();
+        let deserialized: Message = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(deserialized.offline_recipient(), Some("abc123"));
+        assert_eq!(
+            Message::new_error("offline".to_string(), None).offline_recipient(),
+            None
+        );
+        // The key is read from its own field, not the user-facing details
+        let json = r#"{"message_type":"Error","reason":"offline","details":"User abc is not currently online","recipientPublicKey":"ABC123"}"#;
+        let parsed: Message = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed.offline_recipient(), Some("abc123"));
+        let reworded = r#"{"message_type":"Error","reason":"offline","details":"Nobody home","recipientPublicKey":"abc123"}"#;
+        let parsed: Message = serde_json::from_str(reworded).unwrap();
+        assert_eq!(parsed.offline_recipient(), Some("abc123"));
+        assert_eq!(
+            Message::new_error("stale_timestamp".to_string(), Some("x".to_string()))
+                .offline_recipient(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_close_reason_retry_hint_round_trip() {
+        let reason = close_reason_with_retry(
+            SERVER_SHUTDOWN_REASON,
+            std::time::Duration::from_millis(2500),
+        );
+        assert_eq!(reason, "server_shutdown; retry_after_ms=2500");
+        // Close frame reasons are limited to 123 bytes
+        assert!(reason.len() <= 123);
+        assert_eq!(
+            split_retry_hint(&reason),
+            (
+                SERVER_SHUTDOWN_REASON,
+                Some(std::time::Duration::from_millis(2500))
+            )
+        );
+        assert_eq!(split_retry_hint("timeout"), ("timeout", None));
+        assert_eq!(
+            split_retry_hint("server_busy; retry_after_ms=soon"),
+            ("server_busy; retry_after_ms=soon", None)
+        );
+    }
+
+    #[test]
+    fn test_server_busy_round_trip() {
+        let msg = Message::new_server_busy("abc123", std::time::Duration::from_millis(750));
+        let serialized = serde_json::to_string(&msg).unwrap();
+        let deserialized: Message = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(
+            deserialized.server_busy_retry(),
+            Some(("abc123", std::time::Duration::from_millis(750)))
+        );
+        assert_eq!(deserialized.offline_recipient(), None);
+        assert_eq!(
+            Message::new_error(SERVER_BUSY_REASON.to_string(), None).server_busy_retry(),
+            None
+        );
+        // The delay is read from its own field, not the user-facing details
+        let json = r#"{"message_type":"Error","reason":"server_busy","details":"Try later","recipientPublicKey":"abc123","retryAfterMs":250}"#;
+        let parsed: Message = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            parsed.server_busy_retry(),
+            Some(("abc123", std::time::Duration::from_millis(250)))
+        );
+        let untyped = r#"{"message_type":"Error","reason":"server_busy","details":"Server is busy; retry the message to abc123 after 250 ms"}"#;
+        let parsed: Message = serde_json::from_str(untyped).unwrap();
+        assert_eq!(parsed.server_busy_retry(), None);
+    }
+
     #[test]
     fn test_lobby_message_deserialization() {
         let json = r#"{"type":"lobby","users":[{"publicKey":"key1","status":"online"},{"publicKey":"key2","status":"online"}]}"#;
@@ -212,9 +1505,9 @@ mod tests {
 
         assert_eq!(msg.users.len(), 2);
         assert_eq!(msg.users[0].public_key, "key1");
-        assert_eq!(msg.users[0].status, Some("online".to_string()));
+        assert_eq!(msg.users[0].status, Some(Status::Online));
         assert_eq!(msg.users[1].public_key, "key2");
-        assert_eq!(msg.users[1].status, Some("online".to_string()));
+        assert_eq!(msg.users[1].status, Some(Status::Online));
     }
 
     #[test]
@@ -242,6 +1535,9 @@ mod tests {
         let user = LobbyUser {
             public_key: "compact_key".to_string(),
             status: None,
+            nickname: None,
+            name: None,
+            ephemeral: false,
         };
         assert_eq!(user.public_key, "compact_key");
 
@@ -251,21 +1547,127 @@ mod tests {
         assert_eq!(deserialized.public_key, "compact_key");
     }
 
+    #[test]
+    fn test_nickname_message_serialization() {
+        let msg = Message::new_nickname("key1".to_string(), Some("alice".to_string()));
+        let json = serde_json::to_string(&msg).unwrap();
+        assert_eq!(
+            json,
+            r#"{"message_type":"Nickname","publicKey":"key1","nickname":"alice"}"#
+        );
+
+        let cleared: Message = serde_json::from_str(
+            r#"{"message_type":"Nickname","publicKey":"key1","nickname":null}"#,
+        )
+        .unwrap();
+        match cleared {
+            Message::Nickname {
+                public_key,
+                nickname,
+            } => {
+                assert_eq!(public_key, "key1");
+                assert_eq!(nickname, None);
+            }
+            _ => panic!("Expected Nickname message"),
+        }
+    }
+
+    #[test]
+    fn test_name_claim_serialization() {
+        let msg = Message::new_name_claimed("key1".to_string(), Some("Alice".to_string()));
+        assert_eq!(
+            serde_json::to_string(&msg).unwrap(),
+            r#"{"message_type":"NameClaimed","publicKey":"key1","name":"Alice"}"#
+        );
+
+        let user = LobbyUser::new("key1".to_string(), None).with_name(Some("Alice".to_string()));
+        let json = serde_json::to_string(&user).unwrap();
+        assert_eq!(json, r#"{"publicKey":"key1","name":"Alice"}"#);
+        assert_eq!(serde_json::from_str::<LobbyUser>(&json).unwrap(), user);
+    }
+
+    #[test]
+    fn test_backup_message_serialization() {
+        let msg = Message::new_backup(
+            2,
+            Some("c0ffee".to_string()),
+            Some("2025-01-01T00:00:00Z".to_string()),
+        );
+        assert_eq!(
+            serde_json::to_string(&msg).unwrap(),
+            r#"{"message_type":"Backup","version":2,"blob":"c0ffee","updatedAt":"2025-01-01T00:00:00Z"}"#
+        );
+
+        let stored: Message =
+            serde_json::from_str(r#"{"message_type":"BackupStored","version":3}"#).unwrap();
+        assert!(matches!(stored, Message::BackupStored { version: 3 }));
+    }
+
+    #[test]
+    fn test_sender_filter_serialization() {
+        let filter: SenderFilter =
+            serde_json::from_str(r#"{"senderPublicKey":"ABCD","action":"queue_only"}"#).unwrap();
+        assert_eq!(filter.sender_public_key, "abcd");
+        assert_eq!(filter.action, FilterAction::QueueOnly);
+        assert_eq!(FilterAction::Drop.as_str(), "drop");
+
+        assert_eq!(
+            serde_json::to_string(&Message::new_sender_filters_set(2, 1)).unwrap(),
+            r#"{"message_type":"SenderFiltersSet","count":2,"held":1}"#
+        );
+    }
+
     #[test]
     fn test_lobby_user_with_status() {
         let user = LobbyUser {
             public_key: "status_key".to_string(),
-            status: Some("online".to_string()),
+            status: Some(Status::Online),
+            nickname: None,
+            name: None,
+            ephemeral: false,
         };
         assert_eq!(user.public_key, "status_key");
-        assert_eq!(user.status, Some("online".to_string()));
+        assert_eq!(user.status, Some(Status::Online));
 
         // Test offline status
         let offline_user = LobbyUser {
             public_key: "offline_key".to_string(),
-            status: Some("offline".to_string()),
+            status: Some(Status::Offline),
+            nickname: None,
+            name: None,
+            ephemeral: false,
         };
-        assert_eq!(offline_user.status, Some("offline".to_string()));
+        assert_eq!(offline_user.status, Some(Status::Offline));
+        assert!(user.is_online());
+        assert!(!offline_user.is_online());
+        assert!(LobbyUser::new("key".to_string(), None).is_online());
+        let guest = LobbyUser::new("guest_key".to_string(), None).with_ephemeral(true);
+        assert_eq!(
+            serde_json::to_string(&guest).unwrap(),
+            r#"{"publicKey":"guest_key","ephemeral":true}"#
+        );
+        assert!(
+            !serde_json::from_str::<LobbyUser>(r#"{"publicKey":"guest_key"}"#)
+                .unwrap()
+                .ephemeral
+        );
+        assert_eq!(
+            serde_json::to_string(&offline_user).unwrap(),
+            r#"{"publicKey":"offline_key","status":"offline"}"#
+        );
+
+        // Chosen statuses keep the user online; online itself stays implicit
+        let away = LobbyUser::new("away_key".to_string(), None).with_status(Status::Away);
+        assert!(away.is_online());
+        assert_eq!(
+            serde_json::to_string(&away).unwrap(),
+            r#"{"publicKey":"away_key","status":"away"}"#
+        );
+        let back = away.with_status(Status::Online);
+        assert_eq!(back.status, None);
+        assert_eq!(Status::Invisible.as_str(), "invisible");
+        assert!(Status::Busy.is_selectable());
+        assert!(!Status::Offline.is_selectable());
     }
 
     #[test]
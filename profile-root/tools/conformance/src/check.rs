@@ -0,0 +1,333 @@
+//! Checking a candidate's answers against the shared library
+//!
+//! Keys and canonical strings must match the reference exactly. Signatures
+//! must verify under the vector's key. Frames must parse as the shared
+//! protocol types and carry the same fields as the reference, with a
+//! signature that verifies.
+
+use crate::vectors::{private_key_from_hex, Case, Vector};
+use profile_shared::canonical;
+use profile_shared::crypto::rollover::KeyRollover;
+use profile_shared::protocol::{AuthMessage, AUTH_CHALLENGE};
+use profile_shared::{derive_public_key, verify_signature, Message};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+
+/// Longest answer excerpt shown in a failure
+const EXCERPT_LEN: usize = 48;
+
+/// How a candidate did on one vector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    /// The answer was wrong, and why
+    Fail(String),
+    /// No answer was given
+    Missing,
+}
+
+/// The outcome for one vector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorResult {
+    pub id: String,
+    pub kind: &'static str,
+    pub outcome: Outcome,
+}
+
+/// Outcomes for every vector, in the order the vectors were given
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub results: Vec<VectorResult>,
+}
+
+impl Report {
+    /// Vectors that passed, failed and went unanswered, in that order
+    pub fn counts(&self) -> (usize, usize, usize) {
+        count(self.results.iter())
+    }
+
+    /// Whether every vector passed, or was skipped if `allow_missing`
+    pub fn passed(&self, allow_missing: bool) -> bool {
+        let (_, failed, missing) = self.counts();
+        failed == 0 && (allow_missing || missing == 0)
+    }
+}
+
+fn count<'a>(results: impl Iterator<Item = &'a VectorResult>) -> (usize, usize, usize) {
+    results.fold((0, 0, 0), |(pass, fail, missing), r| match r.outcome {
+        Outcome::Pass => (pass + 1, fail, missing),
+        Outcome::Fail(_) => (pass, fail + 1, missing),
+        Outcome::Missing => (pass, fail, missing + 1),
+    })
+}
+
+impl Display for Report {
+    /// One row per vector, then pass/fail/missing counts per kind
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let width = self.results.iter().map(|r| r.id.len()).max().unwrap_or(0);
+        for r in &self.results {
+            match &r.outcome {
+                Outcome::Pass => writeln!(f, "{:<width$}  pass", r.id)?,
+                Outcome::Fail(reason) => writeln!(f, "{:<width$}  FAIL     {}", r.id, reason)?,
+                Outcome::Missing => writeln!(f, "{:<width$}  missing", r.id)?,
+            }
+        }
+
+        let mut kinds: Vec<&str> = Vec::new();
+        for r in &self.results {
+            if !kinds.contains(&r.kind) {
+                kinds.push(r.kind);
+            }
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<20}  {:>4}  {:>4}  {:>7}",
+            "kind", "pass", "fail", "missing"
+        )?;
+        for kind in kinds {
+            let (pass, fail, missing) = count(self.results.iter().filter(|r| r.kind == kind));
+            writeln!(f, "{:<20}  {:>4}  {:>4}  {:>7}", kind, pass, fail, missing)?;
+        }
+        let (pass, fail, missing) = self.counts();
+        write!(
+            f,
+            "{:<20}  {:>4}  {:>4}  {:>7}",
+            "total", pass, fail, missing
+        )
+    }
+}
+
+/// Check a candidate's `answers`, keyed by vector id, against `vectors`
+///
+/// Answers to ids that aren't among the vectors are ignored.
+pub fn check(vectors: &[Vector], answers: &BTreeMap<String, Value>) -> Report {
+    let results = vectors
+        .iter()
+        .map(|vector| VectorResult {
+            id: vector.id.clone(),
+            kind: vector.case.kind(),
+            outcome: match answers.get(&vector.id) {
+                None | Some(Value::Null) => Outcome::Missing,
+                Some(answer) => match check_case(&vector.case, answer) {
+                    Ok(()) => Outcome::Pass,
+                    Err(reason) => Outcome::Fail(reason),
+                },
+            },
+        })
+        .collect();
+    Report { results }
+}
+
+fn check_case(case: &Case, answer: &Value) -> Result<(), String> {
+    let expected = case
+        .reference()
+        .map_err(|e| format!("vector has bad inputs: {}", e))?;
+    match case {
+        Case::PublicKey { .. } | Case::Canonical { .. } | Case::RolloverCanonical { .. } => {
+            let answer = answer.as_str().ok_or("answer is not a string")?;
+            let expected = expected.as_str().unwrap_or_default();
+            if answer == expected {
+                Ok(())
+            } else {
+                Err(format!(
+                    "expected {}, got {}",
+                    excerpt(expected),
+                    excerpt(answer)
+                ))
+            }
+        }
+        Case::Signature { private_key, data } => {
+            verify(private_key, data.as_bytes(), answer.as_str())
+        }
+        Case::MessageSignature {
+            private_key,
+            version,
+            message,
+            timestamp,
+        } => verify(
+            private_key,
+            &canonical::message(*version, message, timestamp),
+            answer.as_str(),
+        ),
+        Case::AuthFrame { private_key } => {
+            let auth: AuthMessage = frame(answer)?;
+            let expected: AuthMessage = frame(&expected)?;
+            if auth.r#type != expected.r#type {
+                return Err(format!("type is {}, not \"auth\"", excerpt(&auth.r#type)));
+            }
+            if auth.public_key != expected.public_key {
+                return Err("publicKey is not the signer's key".to_string());
+            }
+            verify(private_key, AUTH_CHALLENGE, Some(&auth.signature))
+        }
+        Case::TextFrame {
+            private_key,
+            version,
+            message,
+            timestamp,
+            ..
+        } => {
+            let text: Message = frame(answer)?;
+            let Message::Text { signature, .. } = &text else {
+                return Err("frame is not a text message".to_string());
+            };
+            verify(
+                private_key,
+                &canonical::message(*version, message, timestamp),
+                Some(signature),
+            )?;
+            // Everything but the signature must match the reference
+            let mut expected: Message = frame(&expected)?;
+            if let Message::Text {
+                signature: expected_signature,
+                ..
+            } = &mut expected
+            {
+                expected_signature.clone_from(signature);
+            }
+            if text == expected {
+                Ok(())
+            } else {
+                Err(format!(
+                    "fields differ from {}",
+                    excerpt(&serde_json::to_string(&expected).unwrap_or_default())
+                ))
+            }
+        }
+        Case::KeyRollover { .. } => {
+            let rollover: KeyRollover = frame(answer)?;
+            let expected: KeyRollover = frame(&expected)?;
+            if (
+                &rollover.old_public_key,
+                &rollover.new_public_key,
+                &rollover.timestamp,
+            ) != (
+                &expected.old_public_key,
+                &expected.new_public_key,
+                &expected.timestamp,
+            ) {
+                return Err("keys or timestamp differ from the vector".to_string());
+            }
+            rollover
+                .verify()
+                .map_err(|e| format!("rollover does not verify: {}", e))
+        }
+    }
+}
+
+/// Check that the hex `signature` is `private_key`'s signature over `data`
+fn verify(private_key: &str, data: &[u8], signature: Option<&str>) -> Result<(), String> {
+    let signature = signature.ok_or("answer is not a string")?;
+    let signature = hex::decode(signature).map_err(|e| format!("signature is not hex: {}", e))?;
+    let public_key = private_key_from_hex(private_key)
+        .and_then(|key| derive_public_key(&key))
+        .map_err(|e| format!("vector has bad inputs: {}", e))?;
+    verify_signature(&public_key, data, &signature).map_err(|e| e.to_string())
+}
+
+/// Parse a frame answered as a JSON object or as a string of JSON
+fn frame<T: DeserializeOwned>(answer: &Value) -> Result<T, String> {
+    match answer {
+        Value::String(json) => serde_json::from_str(json),
+        other => serde_json::from_value(other.clone()),
+    }
+    .map_err(|e| format!("frame does not parse: {}", e))
+}
+
+/// A short, escaped form of `text` for failure reasons
+fn excerpt(text: &str) -> String {
+    if text.chars().count() <= EXCERPT_LEN {
+        format!("{:?}", text)
+    } else {
+        let head: String = text.chars().take(EXCERPT_LEN).collect();
+        format!("{:?}...", head)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vectors::{public_key_hex, reference_answers, standard_vectors, ALICE_PRIVATE_KEY};
+
+    #[test]
+    fn test_reference_answers_pass() {
+        let vectors = standard_vectors();
+        let report = check(&vectors, &reference_answers(&vectors).unwrap());
+        assert_eq!(report.counts(), (vectors.len(), 0, 0));
+        assert!(report.passed(false));
+    }
+
+    #[test]
+    fn test_signature_by_another_key_fails() {
+        let vectors = standard_vectors();
+        let mut answers = reference_answers(&vectors).unwrap();
+        let bob_signature = answers["signature/escaped"].clone();
+        answers.insert("signature/auth-challenge".to_string(), bob_signature);
+        answers.insert("message_signature/v1".to_string(), "zz".into());
+        answers.remove("public_key/bob");
+
+        let report = check(&vectors, &answers);
+        assert_eq!(report.counts(), (vectors.len() - 3, 2, 1));
+        assert!(!report.passed(true));
+        let outcome = |id: &str| {
+            report
+                .results
+                .iter()
+                .find(|r| r.id == id)
+                .map(|r| r.outcome.clone())
+                .unwrap()
+        };
+        assert!(matches!(
+            outcome("signature/auth-challenge"),
+            Outcome::Fail(_)
+        ));
+        assert!(
+            matches!(outcome("message_signature/v1"), Outcome::Fail(reason) if reason.contains("not hex"))
+        );
+        assert_eq!(outcome("public_key/bob"), Outcome::Missing);
+    }
+
+    #[test]
+    fn test_frames_accepted_as_strings_with_any_field_order() {
+        let case = Case::AuthFrame {
+            private_key: ALICE_PRIVATE_KEY.to_string(),
+        };
+        let reference = case.reference().unwrap();
+        let reordered = format!(
+            r#"{{"signature":"{}","publicKey":"{}","type":"auth"}}"#,
+            reference["signature"].as_str().unwrap().to_uppercase(),
+            public_key_hex(ALICE_PRIVATE_KEY).unwrap()
+        );
+        assert_eq!(check_case(&case, &Value::String(reordered)), Ok(()));
+
+        let mut wrong_key = reference.clone();
+        wrong_key["publicKey"] = "ab".repeat(32).into();
+        assert!(check_case(&case, &wrong_key).is_err());
+    }
+
+    #[test]
+    fn test_report_lists_failures_and_kind_totals() {
+        let report = Report {
+            results: vec![
+                VectorResult {
+                    id: "canonical/v1".to_string(),
+                    kind: "canonical",
+                    outcome: Outcome::Pass,
+                },
+                VectorResult {
+                    id: "canonical/v2".to_string(),
+                    kind: "canonical",
+                    outcome: Outcome::Fail(format!("expected {}", excerpt(&"x".repeat(60)))),
+                },
+            ],
+        };
+        let table = report.to_string();
+        assert!(table.contains("canonical/v2  FAIL     expected \"xxx"));
+        assert!(table.contains("...\n"));
+        assert!(table.contains("canonical                1     1        0"));
+        assert!(table.ends_with("total                    1     1        0"));
+    }
+}
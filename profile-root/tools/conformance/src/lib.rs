@@ -0,0 +1,26 @@
+//! Protocol conformance checks for third-party implementations
+//!
+//! An alternative client has to derive the same public keys, build the same
+//! canonical strings, make signatures the shared library accepts and
+//! serialize frames the server can read. This crate publishes a fixed set of
+//! test vectors covering each of those, and checks a candidate's answers to
+//! them against the shared library.
+//!
+//! The workflow for an implementer:
+//!
+//! 1. `profile-conformance vectors > vectors.json` writes the inputs
+//! 2. the candidate computes an answer for each vector and writes them as a
+//!    JSON object keyed by vector id
+//! 3. `profile-conformance check answers.json` prints a pass/fail matrix
+//!
+//! `profile-conformance reference` prints the answers the shared library
+//! gives, to diff against when a vector fails.
+//!
+//! - [`vectors`] defines the vectors and computes the reference answers
+//! - [`check`] checks a candidate's answers and reports the results
+
+pub mod check;
+pub mod vectors;
+
+pub use check::{check, Outcome, Report, VectorResult};
+pub use vectors::{reference_answers, standard_vectors, Case, Vector};
@@ -0,0 +1,113 @@
+//! Check a third-party implementation against the shared library
+//!
+//! Usage:
+//! - `profile-conformance vectors` prints the test vectors as JSON
+//! - `profile-conformance reference` prints the shared library's answers
+//! - `profile-conformance check <answers.json> [--partial]` checks a
+//!   candidate's answers and prints a pass/fail matrix
+//!
+//! `check` exits non-zero if an answer is wrong, or missing without
+//! `--partial`.
+
+use profile_conformance::{check, reference_answers, standard_vectors};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::process::ExitCode;
+
+const USAGE: &str =
+    "Usage: profile-conformance vectors | reference | check <answers.json> [--partial]";
+
+enum Command {
+    Vectors,
+    Reference,
+    Check { answers: String, partial: bool },
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let command = match args.next().as_deref() {
+        Some("vectors") => Command::Vectors,
+        Some("reference") => Command::Reference,
+        Some("check") => {
+            let mut answers = None;
+            let mut partial = false;
+            for arg in args.by_ref() {
+                match arg.as_str() {
+                    "--partial" => partial = true,
+                    flag if flag.starts_with("--") => {
+                        return Err(format!("Unknown option {}", flag))
+                    }
+                    path if answers.is_none() => answers = Some(path.to_string()),
+                    extra => return Err(format!("Unexpected argument {}", extra)),
+                }
+            }
+            Command::Check {
+                answers: answers.ok_or("Missing answers file")?,
+                partial,
+            }
+        }
+        Some(other) => return Err(format!("Unknown command {}", other)),
+        None => return Err("Missing command".to_string()),
+    };
+    match args.next() {
+        Some(extra) => Err(format!("Unexpected argument {}", extra)),
+        None => Ok(command),
+    }
+}
+
+fn print_json(value: &impl serde::Serialize) -> ExitCode {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => {
+            println!("{}", json);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Failed to serialize: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let command = match parse_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let vectors = standard_vectors();
+    match command {
+        Command::Vectors => print_json(&vectors),
+        Command::Reference => match reference_answers(&vectors) {
+            Ok(answers) => print_json(&answers),
+            Err(e) => {
+                eprintln!("Failed to compute reference answers: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Check { answers, partial } => {
+            let parsed = std::fs::read_to_string(&answers)
+                .map_err(|e| e.to_string())
+                .and_then(|json| {
+                    serde_json::from_str::<BTreeMap<String, Value>>(&json)
+                        .map_err(|e| e.to_string())
+                });
+            let parsed = match parsed {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", answers, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let report = check(&vectors, &parsed);
+            println!("{}", report);
+            if report.passed(partial) {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
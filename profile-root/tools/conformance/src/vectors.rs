@@ -0,0 +1,364 @@
+//! Test vectors and their reference answers
+//!
+//! Every vector has a stable id and a [`Case`] saying what to compute. The
+//! inputs are fixed, so the vectors printed today are the ones an
+//! implementation was checked against yesterday; new vectors get new ids.
+//!
+//! The private keys are published test keys. Signatures are deterministic
+//! ed25519, but the checks only require that a signature verifies, so an
+//! implementation signing differently still passes.
+
+use profile_shared::canonical::{self, CanonicalVersion};
+use profile_shared::crypto::rollover::KeyRollover;
+use profile_shared::protocol::AuthMessage;
+use profile_shared::{derive_public_key, sign_message, CryptoError, Message, PrivateKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Test key signing as "alice"
+pub const ALICE_PRIVATE_KEY: &str =
+    "e2f60dc45ba11fb8be2570359c4c1a34dc9da4c22b032f9b3dc045ac30f6196e";
+
+/// Test key signing as "bob"
+pub const BOB_PRIVATE_KEY: &str =
+    "d5fc8edc38cbd3f658708c11b687304c68973a92840116b50f03d8150e6bbff7";
+
+/// Timestamp used by every vector that needs one
+pub const TIMESTAMP: &str = "2025-12-20T10:00:00Z";
+
+/// One input an implementation must answer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vector {
+    /// Stable name the answer is keyed by, `kind/detail`
+    pub id: String,
+    #[serde(flatten)]
+    pub case: Case,
+}
+
+/// What a vector asks for, and the inputs to compute it from
+///
+/// Hex is lowercase. Canonical strings are answered as JSON strings, since
+/// they are always UTF-8.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "snake_case",
+    rename_all_fields = "camelCase"
+)]
+pub enum Case {
+    /// Answer: the hex public key of `private_key`
+    PublicKey { private_key: String },
+    /// Answer: the canonical string a message signature covers
+    Canonical {
+        version: CanonicalVersion,
+        message: String,
+        timestamp: String,
+    },
+    /// Answer: the canonical string both key rollover signatures cover
+    RolloverCanonical {
+        old_public_key: String,
+        new_public_key: String,
+        timestamp: String,
+    },
+    /// Answer: the hex signature of `data` by `private_key`
+    Signature { private_key: String, data: String },
+    /// Answer: the hex signature of a message, made over its canonical string
+    MessageSignature {
+        private_key: String,
+        version: CanonicalVersion,
+        message: String,
+        timestamp: String,
+    },
+    /// Answer: the `auth` frame opening a connection as `private_key`
+    AuthFrame { private_key: String },
+    /// Answer: the text message frame carrying `message`, as recipients
+    /// receive it
+    TextFrame {
+        private_key: String,
+        message_id: Uuid,
+        message: String,
+        timestamp: String,
+        version: CanonicalVersion,
+    },
+    /// Answer: the key rollover from `old_private_key` to `new_private_key`
+    KeyRollover {
+        old_private_key: String,
+        new_private_key: String,
+        timestamp: String,
+    },
+}
+
+impl Case {
+    /// Name of the kind, as in the `kind` field
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Case::PublicKey { .. } => "public_key",
+            Case::Canonical { .. } => "canonical",
+            Case::RolloverCanonical { .. } => "rollover_canonical",
+            Case::Signature { .. } => "signature",
+            Case::MessageSignature { .. } => "message_signature",
+            Case::AuthFrame { .. } => "auth_frame",
+            Case::TextFrame { .. } => "text_frame",
+            Case::KeyRollover { .. } => "key_rollover",
+        }
+    }
+
+    /// The answer the shared library gives
+    ///
+    /// # Errors
+    /// Returns an error if a private key in the inputs is malformed
+    pub fn reference(&self) -> Result<Value, CryptoError> {
+        let answer = match self {
+            Case::PublicKey { private_key } => Value::String(public_key_hex(private_key)?),
+            Case::Canonical {
+                version,
+                message,
+                timestamp,
+            } => utf8(canonical::message(*version, message, timestamp)),
+            Case::RolloverCanonical {
+                old_public_key,
+                new_public_key,
+                timestamp,
+            } => utf8(canonical::key_rollover(
+                old_public_key,
+                new_public_key,
+                timestamp,
+            )),
+            Case::Signature { private_key, data } => {
+                let signature = sign_message(&private_key_from_hex(private_key)?, data.as_bytes())?;
+                Value::String(hex::encode(signature))
+            }
+            Case::MessageSignature {
+                private_key,
+                version,
+                message,
+                timestamp,
+            } => {
+                let signed = canonical::message(*version, message, timestamp);
+                let signature = sign_message(&private_key_from_hex(private_key)?, &signed)?;
+                Value::String(hex::encode(signature))
+            }
+            Case::AuthFrame { private_key } => {
+                let key = private_key_from_hex(private_key)?;
+                to_value(&AuthMessage::signed(&derive_public_key(&key)?, &key)?)
+            }
+            Case::TextFrame {
+                private_key,
+                message_id,
+                message,
+                timestamp,
+                version,
+            } => {
+                let key = private_key_from_hex(private_key)?;
+                let signed = canonical::message(*version, message, timestamp);
+                to_value(&Message::new_text(
+                    *message_id,
+                    message.clone(),
+                    hex::encode(derive_public_key(&key)?),
+                    hex::encode(sign_message(&key, &signed)?),
+                    timestamp.clone(),
+                    *version,
+                ))
+            }
+            Case::KeyRollover {
+                old_private_key,
+                new_private_key,
+                timestamp,
+            } => to_value(&KeyRollover::sign(
+                &private_key_from_hex(old_private_key)?,
+                &private_key_from_hex(new_private_key)?,
+                timestamp,
+            )?),
+        };
+        Ok(answer)
+    }
+}
+
+/// Parse a hex private key from a vector
+pub(crate) fn private_key_from_hex(private_key: &str) -> Result<PrivateKey, CryptoError> {
+    let bytes =
+        hex::decode(private_key).map_err(|e| CryptoError::InvalidKeyFormat(e.to_string()))?;
+    PrivateKey::from_bytes(bytes)
+}
+
+/// The hex public key of a hex private key
+pub(crate) fn public_key_hex(private_key: &str) -> Result<String, CryptoError> {
+    Ok(hex::encode(derive_public_key(&private_key_from_hex(
+        private_key,
+    )?)?))
+}
+
+fn utf8(bytes: Vec<u8>) -> Value {
+    Value::String(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn to_value(value: &impl Serialize) -> Value {
+    serde_json::to_value(value).expect("protocol types serialize to JSON")
+}
+
+/// The vectors every implementation is checked against
+pub fn standard_vectors() -> Vec<Vector> {
+    let vector = |id: &str, case| Vector {
+        id: id.to_string(),
+        case,
+    };
+    let canonical = |version, message: &str| Case::Canonical {
+        version,
+        message: message.to_string(),
+        timestamp: TIMESTAMP.to_string(),
+    };
+    let message_signature = |version, message: &str| Case::MessageSignature {
+        private_key: ALICE_PRIVATE_KEY.to_string(),
+        version,
+        message: message.to_string(),
+        timestamp: TIMESTAMP.to_string(),
+    };
+    let alice = public_key_hex(ALICE_PRIVATE_KEY).expect("test key is valid");
+    let bob = public_key_hex(BOB_PRIVATE_KEY).expect("test key is valid");
+
+    vec![
+        vector(
+            "public_key/alice",
+            Case::PublicKey {
+                private_key: ALICE_PRIVATE_KEY.to_string(),
+            },
+        ),
+        vector(
+            "public_key/bob",
+            Case::PublicKey {
+                private_key: BOB_PRIVATE_KEY.to_string(),
+            },
+        ),
+        vector(
+            "canonical/v1",
+            canonical(CanonicalVersion::V1, "Hello, Bob"),
+        ),
+        vector(
+            "canonical/v2",
+            canonical(CanonicalVersion::V2, "Hello, Bob"),
+        ),
+        vector("canonical/v2-colon", canonical(CanonicalVersion::V2, "a:b")),
+        vector("canonical/v2-empty", canonical(CanonicalVersion::V2, "")),
+        // Netstring lengths count bytes, not characters
+        vector(
+            "canonical/v2-unicode",
+            canonical(CanonicalVersion::V2, "héllo 👋\nwörld"),
+        ),
+        vector(
+            "rollover_canonical/alice-to-bob",
+            Case::RolloverCanonical {
+                old_public_key: alice,
+                new_public_key: bob,
+                timestamp: TIMESTAMP.to_string(),
+            },
+        ),
+        // Signatures cover the data as a JSON string, quotes and escapes
+        // included
+        vector(
+            "signature/auth-challenge",
+            Case::Signature {
+                private_key: ALICE_PRIVATE_KEY.to_string(),
+                data: "auth".to_string(),
+            },
+        ),
+        vector(
+            "signature/escaped",
+            Case::Signature {
+                private_key: BOB_PRIVATE_KEY.to_string(),
+                data: "quote \" backslash \\ tab \t".to_string(),
+            },
+        ),
+        vector(
+            "message_signature/v1",
+            message_signature(CanonicalVersion::V1, "Hello, Bob"),
+        ),
+        vector(
+            "message_signature/v2",
+            message_signature(CanonicalVersion::V2, "Hello, Bob"),
+        ),
+        vector(
+            "message_signature/v2-unicode",
+            message_signature(CanonicalVersion::V2, "héllo 👋\nwörld"),
+        ),
+        vector(
+            "auth_frame/alice",
+            Case::AuthFrame {
+                private_key: ALICE_PRIVATE_KEY.to_string(),
+            },
+        ),
+        vector(
+            "text_frame/v2",
+            Case::TextFrame {
+                private_key: ALICE_PRIVATE_KEY.to_string(),
+                message_id: Uuid::from_u128(0x5f0c_2a4e_8b1d_4c7a_9e36_d1f0_7b2a_c845),
+                message: "Hello, Bob".to_string(),
+                timestamp: TIMESTAMP.to_string(),
+                version: CanonicalVersion::V2,
+            },
+        ),
+        vector(
+            "key_rollover/alice-to-bob",
+            Case::KeyRollover {
+                old_private_key: ALICE_PRIVATE_KEY.to_string(),
+                new_private_key: BOB_PRIVATE_KEY.to_string(),
+                timestamp: TIMESTAMP.to_string(),
+            },
+        ),
+    ]
+}
+
+/// The shared library's answer to every vector, keyed by id
+///
+/// # Errors
+/// Returns an error if a vector's private key is malformed
+pub fn reference_answers(vectors: &[Vector]) -> Result<BTreeMap<String, Value>, CryptoError> {
+    vectors
+        .iter()
+        .map(|v| Ok((v.id.clone(), v.case.reference()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_vector_ids_are_unique_and_prefixed_by_kind() {
+        let vectors = standard_vectors();
+        let ids: HashSet<_> = vectors.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids.len(), vectors.len());
+        for v in &vectors {
+            assert!(v.id.starts_with(&format!("{}/", v.case.kind())), "{}", v.id);
+        }
+    }
+
+    #[test]
+    fn test_vectors_round_trip_as_json() {
+        let vectors = standard_vectors();
+        let json = serde_json::to_value(&vectors).unwrap();
+        assert_eq!(json[0]["kind"], "public_key");
+        assert_eq!(json[0]["privateKey"], ALICE_PRIVATE_KEY);
+        assert_eq!(json[2]["version"], 1);
+        assert_eq!(
+            serde_json::from_value::<Vec<Vector>>(json).unwrap(),
+            vectors
+        );
+    }
+
+    #[test]
+    fn test_reference_canonical_strings() {
+        let answers = reference_answers(&standard_vectors()).unwrap();
+        assert_eq!(answers["canonical/v1"], "Hello, Bob:2025-12-20T10:00:00Z");
+        assert_eq!(
+            answers["canonical/v2-colon"],
+            "profile-message-v2\n3:a:b,20:2025-12-20T10:00:00Z,"
+        );
+        assert_eq!(
+            answers["canonical/v2-unicode"],
+            "profile-message-v2\n18:héllo 👋\nwörld,20:2025-12-20T10:00:00Z,"
+        );
+    }
+}
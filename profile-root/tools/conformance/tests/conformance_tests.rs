@@ -0,0 +1,62 @@
+//! Running the conformance binary as an implementer would
+//!
+//! Writes the reference answers with one canonical string built the v1 way
+//! where v2 is expected, and checks the binary fails on exactly that vector.
+
+use std::process::Command;
+
+fn conformance(args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_profile-conformance"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn test_check_reports_each_vector_and_fails_on_a_wrong_answer() {
+    let (ok, vectors) = conformance(&["vectors"]);
+    assert!(ok);
+    let vectors: Vec<serde_json::Value> = serde_json::from_str(&vectors).unwrap();
+    assert!(vectors.iter().any(|v| v["id"] == "canonical/v2-colon"));
+
+    let (ok, reference) = conformance(&["reference"]);
+    assert!(ok);
+    let mut answers: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&reference).unwrap();
+    assert_eq!(answers.len(), vectors.len());
+
+    let dir = std::env::temp_dir().join(format!("profile-conformance-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("answers.json");
+    let path_arg = path.to_str().unwrap();
+
+    std::fs::write(&path, serde_json::to_string(&answers).unwrap()).unwrap();
+    let (ok, table) = conformance(&["check", path_arg]);
+    assert!(ok, "{}", table);
+    assert!(table.contains("canonical/v2-colon"));
+
+    answers.insert(
+        "canonical/v2-colon".to_string(),
+        "a:b:2025-12-20T10:00:00Z".into(),
+    );
+    answers.remove("key_rollover/alice-to-bob");
+    std::fs::write(&path, serde_json::to_string(&answers).unwrap()).unwrap();
+    let (ok, table) = conformance(&["check", path_arg]);
+    assert!(!ok);
+    assert!(table.contains("FAIL     expected \"profile-message-v2\\n3:a:b,"));
+    assert!(table
+        .lines()
+        .any(|line| line.starts_with("key_rollover/alice-to-bob ") && line.ends_with("missing")));
+
+    // Missing answers are allowed with --partial, wrong ones never are
+    answers.remove("canonical/v2-colon");
+    std::fs::write(&path, serde_json::to_string(&answers).unwrap()).unwrap();
+    assert!(!conformance(&["check", path_arg]).0);
+    assert!(conformance(&["check", path_arg, "--partial"]).0);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,73 @@
+//! Load test gate at full lobby size
+//!
+//! Starts an in-process server, signs in 1,000 simulated clients and runs
+//! the load test scenario against it, then checks the default thresholds
+//! (broadcast fan-out p95 under 100 ms, auth p99 under 1 s). Exits non-zero
+//! if any is broken, so CI can run it as a gate. `LOADTEST_CLIENTS`
+//! overrides the number of clients.
+//!
+//! Every client holds two sockets in this process (its own and the
+//! server's), so the open file limit must allow a little over 2,000.
+//!
+//! Run with `cargo bench -p profile-loadtest --bench fanout`.
+
+use profile_loadtest::{run, LoadConfig, Thresholds};
+use profile_server::connection::handler::handle_connection;
+use profile_server::lobby::Lobby;
+use profile_server::rate_limiter::AuthRateLimiter;
+use profile_server::rooms::Rooms;
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+/// Clients kept signed in, unless `LOADTEST_CLIENTS` says otherwise
+const CLIENTS: usize = 1000;
+
+async fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let lobby = Arc::new(Lobby::new());
+    let rooms = Arc::new(Rooms::new());
+    let rate_limiter = Arc::new(AuthRateLimiter::new());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_connection(
+                stream,
+                Arc::clone(&lobby),
+                Arc::clone(&rooms),
+                Arc::clone(&rate_limiter),
+                None,
+                None,
+            ));
+        }
+    });
+    format!("ws://{}", addr)
+}
+
+fn main() -> ExitCode {
+    let clients = std::env::var("LOADTEST_CLIENTS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(CLIENTS);
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let report = runtime.block_on(async {
+        let url = start_server().await;
+        run(&url, &LoadConfig::default().with_clients(clients)).await
+    });
+    println!("{}", report);
+
+    let violations = report.check(&Thresholds::default());
+    for violation in &violations {
+        println!("FAIL {}", violation);
+    }
+    if violations.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,273 @@
+//! Simulated authenticated clients
+//!
+//! Each client opens its own WebSocket connection and signs in with a fresh
+//! keypair. Once signed in, a reader task timestamps every frame the server
+//! sends and forwards it, tagged with the client's index, to a channel shared
+//! by the whole population, so a scenario can watch every client from one
+//! loop.
+
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use profile_shared::canonical::{self, CanonicalVersion};
+use profile_shared::{derive_public_key, generate_private_key, sign_message, CryptoError};
+use profile_shared::{Message as Frame, PrivateKey};
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Writer = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+/// Errors of a simulated client
+#[derive(Debug)]
+pub enum ClientError {
+    /// The keypair could not be generated or a frame signed
+    Crypto(CryptoError),
+    /// The connection failed
+    WebSocket(tungstenite::Error),
+    /// The server refused the sign-in, with its reason
+    Rejected(String),
+    /// The server did not answer the sign-in in time
+    Timeout,
+    /// The server closed the connection
+    Closed,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Crypto(e) => write!(f, "Crypto error: {}", e),
+            ClientError::WebSocket(e) => write!(f, "Connection failed: {}", e),
+            ClientError::Rejected(reason) => write!(f, "Sign-in refused: {}", reason),
+            ClientError::Timeout => write!(f, "Sign-in timed out"),
+            ClientError::Closed => write!(f, "Connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<CryptoError> for ClientError {
+    fn from(e: CryptoError) -> Self {
+        ClientError::Crypto(e)
+    }
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(e: tungstenite::Error) -> Self {
+        ClientError::WebSocket(e)
+    }
+}
+
+/// What a frame from the server means to the load test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Observed {
+    /// A lobby update announcing these public keys
+    Joined(Vec<String>),
+    /// A direct message from this sender
+    Text { sender_public_key: String },
+    /// An error frame, with its reason
+    Error(String),
+    /// Any other frame
+    Other,
+}
+
+impl Observed {
+    /// Classify a text frame from the server
+    pub fn from_text(text: &str) -> Self {
+        let Ok(value) = serde_json::from_str::<Value>(text) else {
+            return Observed::Other;
+        };
+        let is_error = value.get("type").and_then(Value::as_str) == Some("error")
+            || value.get("message_type").and_then(Value::as_str) == Some("Error");
+        if is_error {
+            let reason = value.get("reason").and_then(Value::as_str);
+            return Observed::Error(reason.unwrap_or("unknown").to_string());
+        }
+        match serde_json::from_value::<Frame>(value) {
+            Ok(Frame::LobbyUpdate { joined, .. }) if !joined.is_empty() => {
+                Observed::Joined(joined.into_iter().map(|user| user.public_key).collect())
+            }
+            Ok(Frame::Text {
+                sender_public_key, ..
+            }) => Observed::Text { sender_public_key },
+            _ => Observed::Other,
+        }
+    }
+}
+
+/// A frame received by one client
+#[derive(Debug, Clone)]
+pub struct Observation {
+    /// Index of the client that received it
+    pub client: usize,
+    /// When it was read off the socket
+    pub at: Instant,
+    /// What it was
+    pub observed: Observed,
+}
+
+/// One signed-in simulated user
+pub struct SimClient {
+    index: usize,
+    private_key: PrivateKey,
+    public_key: String,
+    auth_sent: Instant,
+    writer: Writer,
+    reader: JoinHandle<()>,
+}
+
+impl SimClient {
+    /// Connect to `url` and sign in as a new identity
+    ///
+    /// # Arguments
+    /// * `url` - WebSocket URL of the server
+    /// * `index` - Tag put on this client's observations
+    /// * `observations` - Where frames received after signing in are sent
+    /// * `timeout` - How long to wait for the server to answer the sign-in
+    ///
+    /// # Returns
+    /// The client and how long connecting and signing in took
+    pub async fn connect(
+        url: &str,
+        index: usize,
+        observations: UnboundedSender<Observation>,
+        timeout: Duration,
+    ) -> Result<(Self, Duration), ClientError> {
+        let private_key = generate_private_key()?;
+        let public_key = hex::encode(derive_public_key(&private_key)?.as_slice());
+        let auth = serde_json::json!({
+            "type": "auth",
+            "publicKey": public_key,
+            "signature": hex::encode(sign_message(&private_key, b"auth")?),
+        });
+
+        let started = Instant::now();
+        let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+        let auth_sent = Instant::now();
+        ws.send(Message::Text(auth.to_string())).await?;
+        tokio::time::timeout(timeout, wait_for_auth_success(&mut ws))
+            .await
+            .map_err(|_| ClientError::Timeout)??;
+        let latency = started.elapsed();
+
+        let (writer, mut read) = ws.split();
+        let reader = tokio::spawn(async move {
+            while let Some(Ok(frame)) = read.next().await {
+                let Message::Text(text) = frame else {
+                    continue;
+                };
+                let observation = Observation {
+                    client: index,
+                    at: Instant::now(),
+                    observed: Observed::from_text(&text),
+                };
+                if observations.send(observation).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let client = Self {
+            index,
+            private_key,
+            public_key,
+            auth_sent,
+            writer,
+            reader,
+        };
+        Ok((client, latency))
+    }
+
+    /// Index this client tags its observations with
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Public key of this client (hex)
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// When the auth frame was sent
+    pub fn auth_sent(&self) -> Instant {
+        self.auth_sent
+    }
+
+    /// Sign and send a direct message
+    pub async fn send_message(&mut self, recipient: &str, text: &str) -> Result<(), ClientError> {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let signature = sign_message(
+            &self.private_key,
+            &canonical::message(CanonicalVersion::CURRENT, text, &timestamp),
+        )?;
+        let frame = serde_json::json!({
+            "type": "message",
+            "recipientPublicKey": recipient,
+            "message": text,
+            "senderPublicKey": self.public_key,
+            "signature": hex::encode(signature),
+            "timestamp": timestamp,
+            "canonical": CanonicalVersion::CURRENT,
+        });
+        self.writer.send(Message::Text(frame.to_string())).await?;
+        Ok(())
+    }
+
+    /// Close the connection and stop reading
+    pub async fn close(mut self) {
+        let _ = self.writer.close().await;
+        self.reader.abort();
+    }
+}
+
+async fn wait_for_auth_success(
+    ws: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+) -> Result<(), ClientError> {
+    while let Some(frame) = ws.next().await {
+        let Message::Text(text) = frame? else {
+            continue;
+        };
+        let value: Value = serde_json::from_str(&text).unwrap_or_default();
+        if value.get("type").and_then(Value::as_str) == Some("auth_success") {
+            return Ok(());
+        }
+        if let Observed::Error(reason) = Observed::from_text(&text) {
+            return Err(ClientError::Rejected(reason));
+        }
+    }
+    Err(ClientError::Closed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_classified() {
+        assert_eq!(
+            Observed::from_text(
+                r#"{"message_type":"LobbyUpdate","joined":[{"publicKey":"aa"}],"left":[]}"#
+            ),
+            Observed::Joined(vec!["aa".to_string()])
+        );
+        assert_eq!(
+            Observed::from_text(r#"{"message_type":"LobbyUpdate","joined":[],"left":["aa"]}"#),
+            Observed::Other
+        );
+        assert_eq!(
+            Observed::from_text(r#"{"type":"error","reason":"server_busy","details":null}"#),
+            Observed::Error("server_busy".to_string())
+        );
+        assert_eq!(
+            Observed::from_text(r#"{"type":"auth_success","users":[]}"#),
+            Observed::Other
+        );
+        assert_eq!(Observed::from_text("not json"), Observed::Other);
+    }
+}
@@ -0,0 +1,24 @@
+//! Server load test harness
+//!
+//! Signs in a population of simulated clients against a running server and
+//! measures the three paths that grow with the lobby:
+//!
+//! - auth latency: connecting and signing in, up to `auth_success`
+//! - broadcast fan-out latency: a new user joining until every signed-in
+//!   client has seen them in a lobby update
+//! - routing throughput: signed direct messages delivered per second
+//!
+//! The results are checked against [`Thresholds`], so a CI job can fail on a
+//! regression (for example a broadcast slower than 100 ms at 1k users).
+//!
+//! - [`client`] opens one simulated client's connection and signs it in
+//! - [`scenario`] runs the measurements over the whole population
+//! - [`report`] summarizes the samples and checks the thresholds
+
+pub mod client;
+pub mod report;
+pub mod scenario;
+
+pub use client::{ClientError, Observation, Observed, SimClient};
+pub use report::{LatencyStats, LoadReport, Thresholds, Violation};
+pub use scenario::{run, LoadConfig};
@@ -0,0 +1,93 @@
+//! Load test a running server
+//!
+//! Usage:
+//! `loadtest [--url ws://127.0.0.1:8080] [--clients <n>] [--concurrency <n>]
+//! [--rounds <n>] [--messages <n>] [--timeout-ms <ms>] [--max-auth-p99-ms <ms>]
+//! [--max-broadcast-p95-ms <ms>] [--min-throughput <messages/s>]`
+//!
+//! Prints the measurements and exits non-zero if any threshold is broken,
+//! a client could not sign in, or traffic was lost. The server's connection
+//! limit (`config::server::MAX_CONCURRENT_CONNECTIONS`) caps `--clients`
+//! plus `--rounds`.
+
+use profile_loadtest::{run, LoadConfig, Thresholds};
+use profile_shared::config;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: loadtest [--url <ws-url>] [--clients <n>] [--concurrency <n>] [--rounds <n>] [--messages <n>] [--timeout-ms <ms>] [--max-auth-p99-ms <ms>] [--max-broadcast-p95-ms <ms>] [--min-throughput <messages/s>]";
+
+struct Options {
+    url: String,
+    config: LoadConfig,
+    thresholds: Thresholds,
+}
+
+fn parse<T: FromStr>(flag: &str, value: String) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| format!("Invalid {}: {}", flag, e))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        url: format!("ws://{}", config::server::BIND_ADDRESS),
+        config: LoadConfig::default(),
+        thresholds: Thresholds::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("{} needs a value", arg))?;
+        let millis = |value| parse(&arg, value).map(Duration::from_millis);
+        match arg.as_str() {
+            "--url" => options.url = value,
+            "--clients" => options.config.clients = parse(&arg, value)?,
+            "--concurrency" => {
+                options.config = options.config.with_connect_concurrency(parse(&arg, value)?)
+            }
+            "--rounds" => options.config.broadcast_rounds = parse(&arg, value)?,
+            "--messages" => options.config.messages_per_client = parse(&arg, value)?,
+            "--timeout-ms" => options.config.timeout = millis(value)?,
+            "--max-auth-p99-ms" => options.thresholds.max_auth_p99 = Some(millis(value)?),
+            "--max-broadcast-p95-ms" => options.thresholds.max_broadcast_p95 = Some(millis(value)?),
+            "--min-throughput" => options.thresholds.min_throughput = Some(parse(&arg, value)?),
+            other => return Err(format!("Unknown option {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!(
+        "Load testing {} with {} clients, {} broadcast rounds and {} messages per client",
+        options.url,
+        options.config.clients,
+        options.config.broadcast_rounds,
+        options.config.messages_per_client
+    );
+    let report = run(&options.url, &options.config).await;
+    println!("{}", report);
+
+    let violations = report.check(&options.thresholds);
+    for violation in &violations {
+        println!("FAIL {}", violation);
+    }
+    if violations.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
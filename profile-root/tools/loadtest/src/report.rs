@@ -0,0 +1,293 @@
+//! Load test results and the thresholds they are checked against
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+/// Default limit on the 99th percentile of auth latency
+pub const DEFAULT_MAX_AUTH_P99: Duration = Duration::from_secs(1);
+
+/// Default limit on the 95th percentile of broadcast fan-out latency
+pub const DEFAULT_MAX_BROADCAST_P95: Duration = Duration::from_millis(100);
+
+/// Summary of a set of latency samples
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    /// Number of samples
+    pub count: usize,
+    pub min: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Summarize `samples`; all zero when there are none
+    pub fn from_samples(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        Self {
+            count: samples.len(),
+            min: samples[0],
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+impl Display for LatencyStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p50 {:>8.1?}  p95 {:>8.1?}  p99 {:>8.1?}  max {:>8.1?}  ({} samples)",
+            self.p50, self.p95, self.p99, self.max, self.count
+        )
+    }
+}
+
+/// Limits a load test run must stay within
+///
+/// A limit of None is not checked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    /// Longest allowed 99th percentile of auth latency
+    pub max_auth_p99: Option<Duration>,
+    /// Longest allowed 95th percentile of broadcast fan-out latency
+    pub max_broadcast_p95: Option<Duration>,
+    /// Fewest direct messages per second that must be delivered
+    pub min_throughput: Option<f64>,
+}
+
+impl Default for Thresholds {
+    /// [`DEFAULT_MAX_AUTH_P99`] and [`DEFAULT_MAX_BROADCAST_P95`], with no
+    /// throughput floor since that depends most on the machine
+    fn default() -> Self {
+        Self {
+            max_auth_p99: Some(DEFAULT_MAX_AUTH_P99),
+            max_broadcast_p95: Some(DEFAULT_MAX_BROADCAST_P95),
+            min_throughput: None,
+        }
+    }
+}
+
+/// A run that broke a threshold or lost traffic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// What was measured
+    pub metric: &'static str,
+    /// The measurement against its limit
+    pub detail: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.metric, self.detail)
+    }
+}
+
+/// Results of one load test run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadReport {
+    /// Clients that signed in
+    pub clients: usize,
+    /// Clients, including broadcast probes, that could not sign in
+    pub failed_connections: usize,
+    /// Time from connecting to `auth_success`, per client
+    pub auth: LatencyStats,
+    /// Time from a probe's auth frame until every client saw it join, per
+    /// round
+    pub broadcast: LatencyStats,
+    /// Broadcast rounds some client never saw within the timeout
+    pub broadcast_timeouts: usize,
+    /// Direct messages sent
+    pub messages_sent: usize,
+    /// Direct messages delivered to their recipient
+    pub messages_delivered: usize,
+    /// Direct messages the server refused, e.g. with `server_busy`
+    pub messages_rejected: usize,
+    /// Time from the first message sent to the last one delivered
+    pub routing_elapsed: Duration,
+    /// Error frames from the server and failed sign-ins, counted by reason
+    pub errors: BTreeMap<String, usize>,
+}
+
+impl LoadReport {
+    /// Direct messages delivered per second
+    pub fn routing_throughput(&self) -> f64 {
+        if self.routing_elapsed.is_zero() {
+            return 0.0;
+        }
+        self.messages_delivered as f64 / self.routing_elapsed.as_secs_f64()
+    }
+
+    /// Total number of errors
+    pub fn error_count(&self) -> usize {
+        self.errors.values().sum()
+    }
+
+    /// Count one error with `reason`
+    pub fn record_error(&mut self, reason: impl Into<String>) {
+        *self.errors.entry(reason.into()).or_default() += 1;
+    }
+
+    /// Direct messages neither delivered nor refused
+    pub fn messages_lost(&self) -> usize {
+        self.messages_sent
+            .saturating_sub(self.messages_delivered + self.messages_rejected)
+    }
+
+    /// Every threshold the run broke
+    ///
+    /// Failed sign-ins, broadcasts that never completed and lost messages
+    /// always count as violations. Messages the server refused don't: that
+    /// is its backpressure working, and shows up in the throughput instead.
+    pub fn check(&self, thresholds: &Thresholds) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if self.failed_connections > 0 {
+            violations.push(Violation {
+                metric: "connections",
+                detail: format!("{} clients failed to sign in", self.failed_connections),
+            });
+        }
+        if let Some(limit) = thresholds.max_auth_p99 {
+            if self.auth.p99 > limit {
+                violations.push(Violation {
+                    metric: "auth latency",
+                    detail: format!("p99 {:.1?} exceeds {:?}", self.auth.p99, limit),
+                });
+            }
+        }
+        if self.broadcast_timeouts > 0 {
+            violations.push(Violation {
+                metric: "broadcast fan-out",
+                detail: format!(
+                    "{} rounds did not reach every client",
+                    self.broadcast_timeouts
+                ),
+            });
+        }
+        if let Some(limit) = thresholds.max_broadcast_p95 {
+            if self.broadcast.p95 > limit {
+                violations.push(Violation {
+                    metric: "broadcast fan-out",
+                    detail: format!("p95 {:.1?} exceeds {:?}", self.broadcast.p95, limit),
+                });
+            }
+        }
+        if self.messages_lost() > 0 {
+            violations.push(Violation {
+                metric: "routing",
+                detail: format!(
+                    "{} of {} messages were lost",
+                    self.messages_lost(),
+                    self.messages_sent
+                ),
+            });
+        }
+        if let Some(floor) = thresholds.min_throughput {
+            if self.routing_throughput() < floor {
+                violations.push(Violation {
+                    metric: "routing throughput",
+                    detail: format!(
+                        "{:.0} messages/s is below {:.0}",
+                        self.routing_throughput(),
+                        floor
+                    ),
+                });
+            }
+        }
+        violations
+    }
+}
+
+impl Display for LoadReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} clients signed in ({} failed)",
+            self.clients, self.failed_connections
+        )?;
+        writeln!(f, "auth       {}", self.auth)?;
+        writeln!(
+            f,
+            "broadcast  {}  ({} timed out)",
+            self.broadcast, self.broadcast_timeouts
+        )?;
+        write!(
+            f,
+            "routing    {} of {} messages delivered, {} refused in {:.1?}  ({:.0} messages/s)",
+            self.messages_delivered,
+            self.messages_sent,
+            self.messages_rejected,
+            self.routing_elapsed,
+            self.routing_throughput()
+        )?;
+        for (reason, count) in &self.errors {
+            write!(f, "\n  {:>6}  error: {}", count, reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let stats = LatencyStats::from_samples((1..=100).rev().map(ms).collect());
+        assert_eq!(stats.count, 100);
+        assert_eq!(stats.min, ms(1));
+        assert_eq!(stats.p50, ms(50));
+        assert_eq!(stats.p99, ms(99));
+        assert_eq!(stats.max, ms(100));
+
+        assert_eq!(LatencyStats::from_samples(vec![]), LatencyStats::default());
+    }
+
+    #[test]
+    fn test_thresholds_checked() {
+        let mut report = LoadReport {
+            clients: 10,
+            auth: LatencyStats::from_samples(vec![ms(5)]),
+            broadcast: LatencyStats::from_samples(vec![ms(40), ms(60)]),
+            messages_sent: 100,
+            messages_delivered: 100,
+            routing_elapsed: ms(500),
+            ..Default::default()
+        };
+        assert_eq!(report.routing_throughput(), 200.0);
+        assert!(report.check(&Thresholds::default()).is_empty());
+
+        // Refused messages are backpressure, not loss
+        report.messages_delivered = 90;
+        report.messages_rejected = 10;
+        assert!(report.check(&Thresholds::default()).is_empty());
+
+        report.broadcast = LatencyStats::from_samples(vec![ms(150)]);
+        report.messages_rejected = 5;
+        let thresholds = Thresholds {
+            min_throughput: Some(1000.0),
+            ..Default::default()
+        };
+        let metrics: Vec<_> = report
+            .check(&thresholds)
+            .into_iter()
+            .map(|v| v.metric)
+            .collect();
+        assert_eq!(
+            metrics,
+            ["broadcast fan-out", "routing", "routing throughput"]
+        );
+    }
+}
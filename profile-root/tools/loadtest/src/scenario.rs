@@ -0,0 +1,248 @@
+//! The load test run
+//!
+//! 1. Sign in `clients` simulated clients, a few at a time, timing each.
+//! 2. For each broadcast round, sign in one more probe client and wait
+//!    until every client from step 1 has seen it join.
+//! 3. Have every client send a burst of direct messages to the next one
+//!    and wait until each is delivered or refused by the server.
+//!
+//! Probes stay signed in until the end, so every round joins a slightly
+//! bigger lobby.
+
+use crate::client::{Observation, Observed, SimClient};
+use crate::report::{LatencyStats, LoadReport};
+use futures_util::StreamExt;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
+
+/// Shape of a load test run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadConfig {
+    /// Clients kept signed in
+    pub clients: usize,
+    /// Clients signing in at the same time
+    pub connect_concurrency: usize,
+    /// Probe joins whose fan-out is timed
+    pub broadcast_rounds: usize,
+    /// Direct messages each client sends
+    pub messages_per_client: usize,
+    /// How long to wait for a sign-in, a broadcast or the messages
+    pub timeout: Duration,
+}
+
+impl Default for LoadConfig {
+    fn default() -> Self {
+        Self {
+            clients: 100,
+            connect_concurrency: 50,
+            broadcast_rounds: 20,
+            messages_per_client: 10,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl LoadConfig {
+    /// Keep `clients` clients signed in
+    pub fn with_clients(mut self, clients: usize) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Sign in at most `concurrency` clients at a time
+    pub fn with_connect_concurrency(mut self, concurrency: usize) -> Self {
+        self.connect_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Time `rounds` probe joins
+    pub fn with_broadcast_rounds(mut self, rounds: usize) -> Self {
+        self.broadcast_rounds = rounds;
+        self
+    }
+
+    /// Have each client send `messages` direct messages
+    pub fn with_messages_per_client(mut self, messages: usize) -> Self {
+        self.messages_per_client = messages;
+        self
+    }
+
+    /// Give up on a sign-in, broadcast or message burst after `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Run the load test against the server at `url`
+///
+/// # Arguments
+/// * `url` - WebSocket URL of the server, e.g. `ws://127.0.0.1:8080`
+/// * `config` - Shape of the run
+pub async fn run(url: &str, config: &LoadConfig) -> LoadReport {
+    let mut report = LoadReport::default();
+    let (observations_tx, mut observations) = mpsc::unbounded_channel();
+
+    let mut clients = sign_in(url, config, &observations_tx, &mut report).await;
+    report.clients = clients.len();
+
+    let mut probes = Vec::new();
+    if !clients.is_empty() {
+        let watchers: HashSet<usize> = clients.iter().map(SimClient::index).collect();
+        let mut fan_outs = Vec::new();
+        for round in 0..config.broadcast_rounds {
+            let probe = match SimClient::connect(
+                url,
+                config.clients + round,
+                observations_tx.clone(),
+                config.timeout,
+            )
+            .await
+            {
+                Ok((probe, _)) => probe,
+                Err(e) => {
+                    report.failed_connections += 1;
+                    report.record_error(e.to_string());
+                    continue;
+                }
+            };
+            match await_fan_out(&probe, &watchers, &mut observations, config, &mut report).await {
+                Some(latency) => fan_outs.push(latency),
+                None => report.broadcast_timeouts += 1,
+            }
+            probes.push(probe);
+        }
+        report.broadcast = LatencyStats::from_samples(fan_outs);
+    }
+
+    if clients.len() >= 2 && config.messages_per_client > 0 {
+        route_messages(&mut clients, &mut observations, config, &mut report).await;
+    }
+
+    for client in clients.into_iter().chain(probes) {
+        client.close().await;
+    }
+    report
+}
+
+/// Sign in every client, `connect_concurrency` at a time
+async fn sign_in(
+    url: &str,
+    config: &LoadConfig,
+    observations: &UnboundedSender<Observation>,
+    report: &mut LoadReport,
+) -> Vec<SimClient> {
+    let results: Vec<_> = futures_util::stream::iter(0..config.clients)
+        .map(|index| SimClient::connect(url, index, observations.clone(), config.timeout))
+        .buffer_unordered(config.connect_concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut clients = Vec::with_capacity(results.len());
+    let mut latencies = Vec::with_capacity(results.len());
+    for result in results {
+        match result {
+            Ok((client, latency)) => {
+                clients.push(client);
+                latencies.push(latency);
+            }
+            Err(e) => {
+                report.failed_connections += 1;
+                report.record_error(e.to_string());
+            }
+        }
+    }
+    report.auth = LatencyStats::from_samples(latencies);
+    clients
+}
+
+/// Wait until every client in `watchers` has seen `probe` join
+///
+/// # Returns
+/// The time from the probe's auth frame to the last watcher seeing it, or
+/// None if some watcher didn't within the timeout
+async fn await_fan_out(
+    probe: &SimClient,
+    watchers: &HashSet<usize>,
+    observations: &mut UnboundedReceiver<Observation>,
+    config: &LoadConfig,
+    report: &mut LoadReport,
+) -> Option<Duration> {
+    let deadline = probe.auth_sent() + config.timeout;
+    let mut seen = HashSet::with_capacity(watchers.len());
+    loop {
+        let observation = tokio::time::timeout_at(deadline, observations.recv())
+            .await
+            .ok()??;
+        match observation.observed {
+            Observed::Joined(keys)
+                if watchers.contains(&observation.client)
+                    && keys.iter().any(|key| key == probe.public_key()) =>
+            {
+                seen.insert(observation.client);
+                if seen.len() == watchers.len() {
+                    return Some(observation.at.duration_since(probe.auth_sent()));
+                }
+            }
+            Observed::Error(reason) => report.record_error(reason),
+            _ => {}
+        }
+    }
+}
+
+/// Have every client send its messages to the next client, and time their
+/// delivery
+async fn route_messages(
+    clients: &mut [SimClient],
+    observations: &mut UnboundedReceiver<Observation>,
+    config: &LoadConfig,
+    report: &mut LoadReport,
+) {
+    let recipients: Vec<String> = clients
+        .iter()
+        .cycle()
+        .skip(1)
+        .take(clients.len())
+        .map(|client| client.public_key().to_string())
+        .collect();
+
+    let started = Instant::now();
+    let sent: Vec<usize> = futures_util::future::join_all(clients.iter_mut().zip(&recipients).map(
+        |(client, recipient)| async move {
+            let mut sent = 0;
+            for n in 0..config.messages_per_client {
+                let text = format!("load test {} from {}", n, client.index());
+                if client.send_message(recipient, &text).await.is_err() {
+                    break;
+                }
+                sent += 1;
+            }
+            sent
+        },
+    ))
+    .await;
+    report.messages_sent = sent.iter().sum();
+
+    let deadline = started + config.timeout;
+    let mut last_delivery = started;
+    while report.messages_delivered + report.messages_rejected < report.messages_sent {
+        let Ok(Some(observation)) = tokio::time::timeout_at(deadline, observations.recv()).await
+        else {
+            break;
+        };
+        match observation.observed {
+            Observed::Text { .. } => {
+                report.messages_delivered += 1;
+                last_delivery = observation.at;
+            }
+            Observed::Error(reason) => {
+                report.messages_rejected += 1;
+                report.record_error(reason);
+            }
+            _ => {}
+        }
+    }
+    report.routing_elapsed = last_delivery.duration_since(started);
+}
@@ -0,0 +1,84 @@
+//! Load test against an in-process server
+//!
+//! Runs a small population through every measurement and checks the server
+//! delivered everything, so the harness itself stays working.
+
+use profile_loadtest::{run, LoadConfig, Thresholds};
+use profile_server::connection::handler::handle_connection;
+use profile_server::lobby::Lobby;
+use profile_server::rate_limiter::AuthRateLimiter;
+use profile_server::rooms::Rooms;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+/// Start a server on an ephemeral port and return its WebSocket URL
+async fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let lobby = Arc::new(Lobby::new());
+    let rooms = Arc::new(Rooms::new());
+    let rate_limiter = Arc::new(AuthRateLimiter::new());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_connection(
+                stream,
+                Arc::clone(&lobby),
+                Arc::clone(&rooms),
+                Arc::clone(&rate_limiter),
+                None,
+                None,
+            ));
+        }
+    });
+    format!("ws://{}", addr)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_small_load_run_delivers_everything() {
+    let url = start_server().await;
+    let config = LoadConfig::default()
+        .with_clients(20)
+        .with_connect_concurrency(5)
+        .with_broadcast_rounds(3)
+        .with_messages_per_client(5);
+
+    let report = run(&url, &config).await;
+
+    assert_eq!(report.clients, 20);
+    assert_eq!(report.auth.count, 20);
+    assert_eq!(report.broadcast.count, 3);
+    assert_eq!(report.messages_sent, 100);
+    assert_eq!(report.messages_delivered, 100);
+    assert!(report.routing_throughput() > 0.0);
+    assert!(report.errors.is_empty(), "errors: {:?}", report.errors);
+    // Timing thresholds are left to the bench; a loaded CI machine may be slow
+    let no_limits = Thresholds {
+        max_auth_p99: None,
+        max_broadcast_p95: None,
+        min_throughput: None,
+    };
+    assert!(report.check(&no_limits).is_empty());
+}
+
+#[tokio::test]
+async fn test_unreachable_server_fails_every_client() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    drop(listener);
+    let config = LoadConfig::default()
+        .with_clients(3)
+        .with_timeout(Duration::from_secs(1));
+
+    let report = run(&url, &config).await;
+
+    assert_eq!(report.clients, 0);
+    assert_eq!(report.failed_connections, 3);
+    assert_eq!(report.broadcast.count, 0);
+    assert_eq!(report.messages_sent, 0);
+    assert_eq!(
+        report.check(&Thresholds::default())[0].metric,
+        "connections"
+    );
+}
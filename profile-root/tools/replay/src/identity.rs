@@ -0,0 +1,231 @@
+//! Stand-in identities for captured users
+//!
+//! Captures keep public keys but redact every signature, so frames can't be
+//! sent as recorded. Each captured public key gets a freshly generated
+//! keypair: the captured key is replaced wherever it appears in a frame, and
+//! auth frames and signed messages are re-signed with the new private key.
+//! Message timestamps are re-stamped at send time so they pass the server's
+//! drift check. Messages are re-signed over the canonical encoding they name,
+//! version 1 if they name none, as the original client did.
+
+use profile_shared::canonical::{self, CanonicalVersion};
+use profile_shared::{
+    derive_public_key, generate_private_key, sign_message, CryptoError, PrivateKey,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// A generated keypair standing in for a captured user
+struct Identity {
+    private_key: PrivateKey,
+    public_key_hex: String,
+}
+
+/// Mapping from captured public keys to generated keypairs
+#[derive(Default)]
+pub struct Identities {
+    by_captured_key: HashMap<String, Identity>,
+}
+
+impl Identities {
+    /// Generate a keypair for every captured public key
+    pub fn generate<'a>(
+        captured_keys: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, CryptoError> {
+        let mut by_captured_key = HashMap::new();
+        for captured in captured_keys {
+            if by_captured_key.contains_key(captured) {
+                continue;
+            }
+            let private_key = generate_private_key()?;
+            let public_key_hex = hex::encode(derive_public_key(&private_key)?.as_slice());
+            by_captured_key.insert(
+                captured.to_string(),
+                Identity {
+                    private_key,
+                    public_key_hex,
+                },
+            );
+        }
+        Ok(Self { by_captured_key })
+    }
+
+    /// Number of identities
+    pub fn len(&self) -> usize {
+        self.by_captured_key.len()
+    }
+
+    /// Whether no identities were generated
+    pub fn is_empty(&self) -> bool {
+        self.by_captured_key.is_empty()
+    }
+
+    /// Replacement public key for a captured key
+    pub fn public_key(&self, captured: &str) -> Option<&str> {
+        self.by_captured_key
+            .get(captured)
+            .map(|identity| identity.public_key_hex.as_str())
+    }
+
+    /// Rewrite a captured text frame so the server accepts it
+    ///
+    /// # Arguments
+    /// * `text` - Captured frame text
+    /// * `now` - RFC 3339 timestamp to stamp on timestamped frames
+    ///
+    /// # Returns
+    /// The frame with captured keys replaced, timestamps set to `now`, and
+    /// auth frames and signed messages re-signed. Frames that aren't JSON
+    /// objects are returned unchanged.
+    pub fn rewrite(&self, text: &str, now: &str) -> Result<String, CryptoError> {
+        let Ok(Value::Object(mut frame)) = serde_json::from_str::<Value>(text) else {
+            return Ok(text.to_string());
+        };
+
+        // Signers are looked up by their captured key, before replacement
+        let frame_type = frame
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let auth_signer = match frame_type {
+            "auth" => self.signer(frame.get("publicKey")),
+            _ => None,
+        };
+        let message_signer = match frame.get("message") {
+            Some(Value::String(_)) => self.signer(frame.get("senderPublicKey")),
+            _ => None,
+        };
+
+        if frame.contains_key("timestamp") {
+            frame.insert("timestamp".to_string(), Value::String(now.to_string()));
+        }
+        for value in frame.values_mut() {
+            self.replace_keys(value);
+        }
+
+        if let Some(identity) = auth_signer {
+            let signature = sign_message(&identity.private_key, b"auth")?;
+            frame.insert(
+                "signature".to_string(),
+                Value::String(hex::encode(signature)),
+            );
+        }
+        if let Some(identity) = message_signer {
+            let message = frame
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            // A version this build doesn't know is sent on as version 1
+            // for the server to refuse
+            let version = frame
+                .get("canonical")
+                .and_then(|v| serde_json::from_value::<CanonicalVersion>(v.clone()).ok())
+                .unwrap_or_default();
+            let canonical_message = canonical::message(version, message, now);
+            let signature = sign_message(&identity.private_key, &canonical_message)?;
+            frame.insert(
+                "signature".to_string(),
+                Value::String(hex::encode(signature)),
+            );
+        }
+
+        Ok(Value::Object(frame).to_string())
+    }
+
+    fn signer(&self, captured_key: Option<&Value>) -> Option<&Identity> {
+        self.by_captured_key.get(captured_key?.as_str()?)
+    }
+
+    /// Replace captured keys in string values, recursively
+    fn replace_keys(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => {
+                if let Some(identity) = self.by_captured_key.get(s.as_str()) {
+                    *s = identity.public_key_hex.clone();
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.replace_keys(item)),
+            Value::Object(map) => map.values_mut().for_each(|item| self.replace_keys(item)),
+            _ => {}
+        }
+    }
+}
+
+impl std::fmt::Debug for Identities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Identities")
+            .field("count", &self.by_captured_key.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use profile_shared::{verify_signature, PublicKey};
+
+    const NOW: &str = "2026-01-01T00:00:00+00:00";
+
+    fn public_key(hex_key: &str) -> PublicKey {
+        PublicKey::new(hex::decode(hex_key).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_auth_frame_is_re_signed() {
+        let identities = Identities::generate(["aa"]).unwrap();
+        let replacement = identities.public_key("aa").unwrap().to_string();
+
+        let frame = identities
+            .rewrite(
+                r#"{"type":"auth","publicKey":"aa","signature":"[redacted]"}"#,
+                NOW,
+            )
+            .unwrap();
+        let frame: Value = serde_json::from_str(&frame).unwrap();
+
+        assert_eq!(frame["publicKey"], replacement.as_str());
+        let signature = hex::decode(frame["signature"].as_str().unwrap()).unwrap();
+        assert!(verify_signature(&public_key(&replacement), b"auth", &signature).is_ok());
+    }
+
+    #[test]
+    fn test_message_keys_replaced_and_signature_covers_new_timestamp() {
+        let identities = Identities::generate(["aa", "bb"]).unwrap();
+        let sender = identities.public_key("aa").unwrap().to_string();
+        let recipient = identities.public_key("bb").unwrap().to_string();
+
+        let captured = r#"{"type":"message","recipientPublicKey":"bb","message":"hi","senderPublicKey":"aa","signature":"[redacted]","timestamp":"2020-01-01T00:00:00Z"}"#;
+        let frame: Value =
+            serde_json::from_str(&identities.rewrite(captured, NOW).unwrap()).unwrap();
+
+        assert_eq!(frame["senderPublicKey"], sender.as_str());
+        assert_eq!(frame["recipientPublicKey"], recipient.as_str());
+        assert_eq!(frame["timestamp"], NOW);
+        let signature = hex::decode(frame["signature"].as_str().unwrap()).unwrap();
+        let canonical_message = canonical::message(CanonicalVersion::V1, "hi", NOW);
+        assert!(verify_signature(&public_key(&sender), &canonical_message, &signature).is_ok());
+
+        // Re-signed over the version the captured message named
+        let captured = captured.replace(r#""timestamp""#, r#""canonical":2,"timestamp""#);
+        let frame: Value =
+            serde_json::from_str(&identities.rewrite(&captured, NOW).unwrap()).unwrap();
+        let signature = hex::decode(frame["signature"].as_str().unwrap()).unwrap();
+        let canonical_message = canonical::message(CanonicalVersion::V2, "hi", NOW);
+        assert!(verify_signature(&public_key(&sender), &canonical_message, &signature).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_keys_and_non_json_pass_through() {
+        let identities = Identities::generate(["aa"]).unwrap();
+
+        assert_eq!(identities.rewrite("not json", NOW).unwrap(), "not json");
+        let frame: Value = serde_json::from_str(
+            &identities
+                .rewrite(r#"{"type":"room_join","room":"cc"}"#, NOW)
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(frame["room"], "cc");
+        assert_eq!(identities.len(), 1);
+    }
+}
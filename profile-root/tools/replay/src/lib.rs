@@ -0,0 +1,19 @@
+//! Capture replay tool
+//!
+//! Reads a frame capture written with `PROFILE_CAPTURE_FILE` and drives a
+//! running server with the same traffic over real WebSocket connections,
+//! either at the original pace or accelerated. Used to reproduce field bugs
+//! and to benchmark realistic traffic shapes.
+//!
+//! - [`plan`] turns capture records into one script per captured connection
+//! - [`identity`] swaps captured public keys for fresh keypairs and re-signs
+//!   frames, since captures never contain signatures or private keys
+//! - [`runner`] plays the scripts against a server and collects statistics
+
+pub mod identity;
+pub mod plan;
+pub mod runner;
+
+pub use identity::Identities;
+pub use plan::{ReplayPlan, Side, Timing};
+pub use runner::{replay, ReplayStats};
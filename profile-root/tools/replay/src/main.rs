@@ -0,0 +1,119 @@
+//! Replay a frame capture against a running server
+//!
+//! Usage:
+//! `replay <capture.jsonl> [--url ws://127.0.0.1:8080] [--side server|client]
+//! [--speed original|max|<factor>] [--linger-ms <ms>]`
+//!
+//! Exits non-zero if a connection failed or the server answered with errors.
+
+use profile_replay::{replay, Identities, ReplayPlan, Side, Timing};
+use profile_shared::capture::read_capture;
+use profile_shared::config;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+const USAGE: &str = "Usage: replay <capture.jsonl> [--url <ws-url>] [--side server|client] [--speed original|max|<factor>] [--linger-ms <ms>]";
+
+struct Options {
+    capture: String,
+    url: String,
+    side: Side,
+    timing: Timing,
+    linger: Duration,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut capture = None;
+    let mut options = Options {
+        capture: String::new(),
+        url: format!("ws://{}", config::server::BIND_ADDRESS),
+        side: Side::Server,
+        timing: Timing::Original,
+        linger: Duration::from_millis(500),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--url" => options.url = value()?,
+            "--side" => options.side = value()?.parse()?,
+            "--speed" => options.timing = value()?.parse()?,
+            "--linger-ms" => {
+                let ms = value()?
+                    .parse()
+                    .map_err(|e| format!("Invalid --linger-ms: {}", e))?;
+                options.linger = Duration::from_millis(ms);
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option {}", flag)),
+            path if capture.is_none() => capture = Some(path.to_string()),
+            extra => return Err(format!("Unexpected argument {}", extra)),
+        }
+    }
+
+    options.capture = capture.ok_or("Missing capture file")?;
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let records = match read_capture(&options.capture) {
+        Ok(records) => records,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", options.capture, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let plan = ReplayPlan::from_records(&records, options.side);
+    let identities = match Identities::generate(plan.public_keys()) {
+        Ok(identities) => Arc::new(identities),
+        Err(e) => {
+            eprintln!("Failed to generate keys: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!(
+        "Replaying {} frames on {} connections ({:?} captured) to {} at {:?}",
+        plan.frame_count(),
+        plan.connections.len(),
+        plan.duration(),
+        options.url,
+        options.timing
+    );
+
+    let stats = replay(
+        &options.url,
+        &plan,
+        identities,
+        options.timing,
+        options.linger,
+    )
+    .await;
+
+    println!(
+        "{} connections ({} failed), {} frames sent, {} received in {:?}",
+        stats.connections,
+        stats.failed_connections,
+        stats.frames_sent,
+        stats.frames_received,
+        stats.elapsed
+    );
+    for (reason, count) in &stats.errors {
+        println!("  {:>6}  error: {}", count, reason);
+    }
+
+    if stats.failed_connections > 0 || stats.error_count() > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
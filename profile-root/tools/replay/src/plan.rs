@@ -0,0 +1,317 @@
+//! Replay scripts built from capture records
+//!
+//! Only frames the client sent are replayed; the server's replies are
+//! regenerated by the server under test. Pongs are dropped because the
+//! WebSocket library answers the server's pings by itself.
+
+use profile_shared::capture::{CaptureRecord, Direction, FrameKind};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Which side of the connection wrote the capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// Client capture: outbound frames were sent by the client
+    Client,
+    /// Server capture: inbound frames were sent by the client
+    Server,
+}
+
+impl Side {
+    /// Direction of client-sent frames in a capture written by this side
+    fn client_sent(&self) -> Direction {
+        match self {
+            Side::Client => Direction::Outbound,
+            Side::Server => Direction::Inbound,
+        }
+    }
+}
+
+impl FromStr for Side {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "client" => Ok(Side::Client),
+            "server" => Ok(Side::Server),
+            other => Err(format!(
+                "Unknown side '{}', expected client or server",
+                other
+            )),
+        }
+    }
+}
+
+/// Pace at which captured frames are replayed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    /// Same gaps between frames as in the capture
+    Original,
+    /// Gaps divided by the factor (2.0 replays twice as fast)
+    Accelerated(f64),
+    /// No gaps at all
+    Flood,
+}
+
+impl Timing {
+    /// Scale a captured offset to a replay offset
+    pub fn scale(&self, offset: Duration) -> Duration {
+        match self {
+            Timing::Original => offset,
+            Timing::Accelerated(factor) => offset.div_f64(*factor),
+            Timing::Flood => Duration::ZERO,
+        }
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    /// Parse `original`, `max` or a positive speed factor such as `10`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "original" | "1" => Ok(Timing::Original),
+            "max" => Ok(Timing::Flood),
+            factor => match factor.parse::<f64>() {
+                Ok(factor) if factor.is_finite() && factor > 0.0 => Ok(Timing::Accelerated(factor)),
+                _ => Err(format!(
+                    "Invalid speed '{}', expected original, max or a positive number",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// A frame the replayed client sends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptFrame {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Close,
+}
+
+/// A frame and when to send it, relative to the start of the capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptStep {
+    pub at: Duration,
+    pub frame: ScriptFrame,
+}
+
+/// Everything one captured connection sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionScript {
+    /// Connection id from the capture
+    pub connection: u64,
+    /// Public key from the connection's auth frame, if it was captured
+    pub public_key: Option<String>,
+    /// Steps in send order, ending at the first Close
+    pub steps: Vec<ScriptStep>,
+}
+
+impl ConnectionScript {
+    /// Offset of the first frame, which is when the connection is opened
+    pub fn starts_at(&self) -> Duration {
+        self.steps.first().map(|s| s.at).unwrap_or_default()
+    }
+}
+
+/// Scripts for every connection in a capture
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayPlan {
+    pub connections: Vec<ConnectionScript>,
+}
+
+impl ReplayPlan {
+    /// Build a plan from capture records
+    ///
+    /// # Arguments
+    /// * `records` - Records as returned by `read_capture`
+    /// * `side` - Which side wrote the capture
+    ///
+    /// # Returns
+    /// One script per connection that sent at least one frame, ordered by
+    /// start time. Binary payloads that aren't valid hex are skipped.
+    pub fn from_records(records: &[CaptureRecord], side: Side) -> Self {
+        let Some(origin) = records.iter().map(|r| r.ts_ms).min() else {
+            return Self::default();
+        };
+
+        let mut scripts: BTreeMap<u64, ConnectionScript> = BTreeMap::new();
+        for record in records {
+            if record.direction != side.client_sent() {
+                continue;
+            }
+            let frame = match record.kind {
+                FrameKind::Text => ScriptFrame::Text(record.data.clone()),
+                FrameKind::Binary => match hex::decode(&record.data) {
+                    Ok(data) => ScriptFrame::Binary(data),
+                    Err(_) => continue,
+                },
+                FrameKind::Ping => ScriptFrame::Ping(hex::decode(&record.data).unwrap_or_default()),
+                FrameKind::Pong => continue,
+                FrameKind::Close => ScriptFrame::Close,
+            };
+
+            let script = scripts
+                .entry(record.connection)
+                .or_insert_with(|| ConnectionScript {
+                    connection: record.connection,
+                    public_key: None,
+                    steps: Vec::new(),
+                });
+            if script.steps.last().map(|s| &s.frame) == Some(&ScriptFrame::Close) {
+                continue;
+            }
+            if script.public_key.is_none() {
+                if let ScriptFrame::Text(text) = &frame {
+                    script.public_key = auth_public_key(text);
+                }
+            }
+            script.steps.push(ScriptStep {
+                at: Duration::from_millis(record.ts_ms.saturating_sub(origin)),
+                frame,
+            });
+        }
+
+        let mut connections: Vec<ConnectionScript> = scripts.into_values().collect();
+        for script in &mut connections {
+            // Records from concurrent writers can land slightly out of order
+            script.steps.sort_by_key(|s| s.at);
+        }
+        connections.sort_by_key(|c| (c.starts_at(), c.connection));
+        Self { connections }
+    }
+
+    /// Total number of frames to send
+    pub fn frame_count(&self) -> usize {
+        self.connections.iter().map(|c| c.steps.len()).sum()
+    }
+
+    /// Offset of the last frame at original timing
+    pub fn duration(&self) -> Duration {
+        self.connections
+            .iter()
+            .filter_map(|c| c.steps.last())
+            .map(|s| s.at)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Every public key that authenticated in the capture
+    pub fn public_keys(&self) -> impl Iterator<Item = &str> {
+        self.connections
+            .iter()
+            .filter_map(|c| c.public_key.as_deref())
+    }
+}
+
+/// Public key of an auth frame, None for any other frame
+fn auth_public_key(text: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    if value.get("type")?.as_str()? != "auth" {
+        return None;
+    }
+    Some(value.get("publicKey")?.as_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(
+        ts_ms: u64,
+        direction: Direction,
+        connection: u64,
+        kind: FrameKind,
+        data: &str,
+    ) -> CaptureRecord {
+        CaptureRecord {
+            ts_ms,
+            direction,
+            connection,
+            kind,
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_server_capture_keeps_inbound_frames_per_connection() {
+        let records = vec![
+            record(
+                1_000,
+                Direction::Inbound,
+                2,
+                FrameKind::Text,
+                r#"{"type":"auth","publicKey":"bb","signature":"[redacted]"}"#,
+            ),
+            record(
+                1_001,
+                Direction::Outbound,
+                2,
+                FrameKind::Text,
+                r#"{"type":"auth_success","users":[]}"#,
+            ),
+            record(
+                900,
+                Direction::Inbound,
+                1,
+                FrameKind::Text,
+                r#"{"type":"auth","publicKey":"aa","signature":"[redacted]"}"#,
+            ),
+            record(1_500, Direction::Inbound, 2, FrameKind::Pong, "00"),
+            record(2_000, Direction::Inbound, 1, FrameKind::Close, ""),
+            record(2_100, Direction::Inbound, 1, FrameKind::Text, "after close"),
+        ];
+
+        let plan = ReplayPlan::from_records(&records, Side::Server);
+
+        assert_eq!(plan.connections.len(), 2);
+        assert_eq!(plan.connections[0].connection, 1);
+        assert_eq!(plan.connections[0].public_key.as_deref(), Some("aa"));
+        assert_eq!(plan.connections[0].steps.len(), 2);
+        assert_eq!(plan.connections[0].steps[1].frame, ScriptFrame::Close);
+        assert_eq!(plan.connections[1].starts_at(), Duration::from_millis(100));
+        assert_eq!(plan.connections[1].steps.len(), 1);
+        assert_eq!(plan.frame_count(), 3);
+        assert_eq!(plan.duration(), Duration::from_millis(1_100));
+        assert_eq!(plan.public_keys().collect::<Vec<_>>(), vec!["aa", "bb"]);
+    }
+
+    #[test]
+    fn test_client_capture_keeps_outbound_frames() {
+        let records = vec![
+            record(0, Direction::Outbound, 1, FrameKind::Ping, "0001"),
+            record(5, Direction::Inbound, 1, FrameKind::Pong, "0001"),
+        ];
+
+        let plan = ReplayPlan::from_records(&records, Side::Client);
+
+        assert_eq!(
+            plan.connections[0].steps[0].frame,
+            ScriptFrame::Ping(vec![0, 1])
+        );
+        assert!(ReplayPlan::from_records(&records, Side::Server)
+            .connections
+            .is_empty());
+    }
+
+    #[test]
+    fn test_timing_parse_and_scale() {
+        let offset = Duration::from_secs(10);
+        assert_eq!("original".parse::<Timing>().unwrap().scale(offset), offset);
+        assert_eq!(
+            "4".parse::<Timing>().unwrap().scale(offset),
+            Duration::from_millis(2_500)
+        );
+        assert_eq!(
+            "max".parse::<Timing>().unwrap().scale(offset),
+            Duration::ZERO
+        );
+        assert!("0".parse::<Timing>().is_err());
+        assert!("fast".parse::<Timing>().is_err());
+        assert!("browser".parse::<Side>().is_err());
+    }
+}
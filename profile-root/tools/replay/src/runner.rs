@@ -0,0 +1,200 @@
+//! Drives a server with a replay plan over real WebSocket connections
+//!
+//! Every captured connection gets its own socket, opened at the time of its
+//! first captured frame. Frames are sent at their (scaled) captured offsets
+//! while a reader task counts what the server sends back. After its last
+//! frame a connection lingers briefly to collect replies, then closes.
+
+use crate::identity::Identities;
+use crate::plan::{ConnectionScript, ReplayPlan, ScriptFrame, Timing};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+use tokio_tungstenite::tungstenite::Message;
+
+/// Totals across every replayed connection
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// Connections that were opened
+    pub connections: usize,
+    /// Connections that could not be opened or failed mid-replay
+    pub failed_connections: usize,
+    /// Frames sent to the server
+    pub frames_sent: usize,
+    /// Frames received from the server
+    pub frames_received: usize,
+    /// Error frames from the server, counted by reason
+    pub errors: BTreeMap<String, usize>,
+    /// Wall-clock time of the whole replay
+    pub elapsed: Duration,
+}
+
+impl ReplayStats {
+    fn merge(&mut self, other: ReplayStats) {
+        self.connections += other.connections;
+        self.failed_connections += other.failed_connections;
+        self.frames_sent += other.frames_sent;
+        self.frames_received += other.frames_received;
+        for (reason, count) in other.errors {
+            *self.errors.entry(reason).or_default() += count;
+        }
+    }
+
+    /// Total number of error frames
+    pub fn error_count(&self) -> usize {
+        self.errors.values().sum()
+    }
+}
+
+/// Reason of a server error frame, None for any other frame
+fn error_reason(text: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    let is_error = value.get("type").and_then(|t| t.as_str()) == Some("error")
+        || value.get("message_type").and_then(|t| t.as_str()) == Some("Error");
+    if !is_error {
+        return None;
+    }
+    Some(
+        value
+            .get("reason")
+            .and_then(|r| r.as_str())
+            .unwrap_or("unknown")
+            .to_string(),
+    )
+}
+
+/// Current time in the RFC 3339 format the server expects
+fn now_rfc3339() -> String {
+    chrono::Utc::now().to_rfc3339()
+}
+
+/// Replay every connection in `plan` against the server at `url`
+///
+/// # Arguments
+/// * `url` - WebSocket URL of the server, e.g. `ws://127.0.0.1:8080`
+/// * `plan` - Scripts to play
+/// * `identities` - Keypairs standing in for the captured users
+/// * `timing` - Pace of the replay
+/// * `linger` - How long each connection waits for replies after its last frame
+pub async fn replay(
+    url: &str,
+    plan: &ReplayPlan,
+    identities: Arc<Identities>,
+    timing: Timing,
+    linger: Duration,
+) -> ReplayStats {
+    let started = Instant::now();
+
+    let handles: Vec<_> = plan
+        .connections
+        .iter()
+        .cloned()
+        .map(|script| {
+            let url = url.to_string();
+            let identities = Arc::clone(&identities);
+            tokio::spawn(async move {
+                replay_connection(&url, script, &identities, timing, started, linger).await
+            })
+        })
+        .collect();
+
+    let mut stats = ReplayStats::default();
+    for handle in handles {
+        match handle.await {
+            Ok(connection_stats) => stats.merge(connection_stats),
+            Err(_) => stats.failed_connections += 1,
+        }
+    }
+    stats.elapsed = started.elapsed();
+    stats
+}
+
+async fn replay_connection(
+    url: &str,
+    script: ConnectionScript,
+    identities: &Identities,
+    timing: Timing,
+    started: Instant,
+    linger: Duration,
+) -> ReplayStats {
+    let mut stats = ReplayStats::default();
+
+    sleep_until(started + timing.scale(script.starts_at())).await;
+    let (mut write, mut read) = match tokio_tungstenite::connect_async(url).await {
+        Ok((ws_stream, _)) => ws_stream.split(),
+        Err(e) => {
+            eprintln!("connection {}: connect failed: {}", script.connection, e);
+            stats.failed_connections = 1;
+            return stats;
+        }
+    };
+    stats.connections = 1;
+
+    // Count replies until the server closes or we drop the socket
+    let reader = tokio::spawn(async move {
+        let mut received = ReplayStats::default();
+        while let Some(Ok(frame)) = read.next().await {
+            received.frames_received += 1;
+            if let Message::Text(text) = frame {
+                if let Some(reason) = error_reason(&text) {
+                    *received.errors.entry(reason).or_default() += 1;
+                }
+            }
+        }
+        received
+    });
+
+    for step in script.steps {
+        sleep_until(started + timing.scale(step.at)).await;
+        let frame = match step.frame {
+            ScriptFrame::Text(text) => match identities.rewrite(&text, &now_rfc3339()) {
+                Ok(text) => Message::Text(text),
+                Err(e) => {
+                    eprintln!(
+                        "connection {}: could not re-sign frame: {}",
+                        script.connection, e
+                    );
+                    continue;
+                }
+            },
+            ScriptFrame::Binary(data) => Message::Binary(data),
+            ScriptFrame::Ping(data) => Message::Ping(data),
+            ScriptFrame::Close => Message::Close(None),
+        };
+        if let Err(e) = write.send(frame).await {
+            eprintln!("connection {}: send failed: {}", script.connection, e);
+            stats.failed_connections = 1;
+            break;
+        }
+        stats.frames_sent += 1;
+    }
+
+    tokio::time::sleep(linger).await;
+    let _ = write.close().await;
+    match tokio::time::timeout(linger, reader).await {
+        Ok(Ok(received)) => stats.merge(received),
+        _ => eprintln!("connection {}: reader did not finish", script.connection),
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_reason_recognizes_both_error_shapes() {
+        assert_eq!(
+            error_reason(r#"{"type":"error","reason":"offline","details":"x"}"#),
+            Some("offline".to_string())
+        );
+        assert_eq!(
+            error_reason(r#"{"message_type":"Error","reason":"stale_timestamp","details":null}"#),
+            Some("stale_timestamp".to_string())
+        );
+        assert_eq!(error_reason(r#"{"type":"auth_success","users":[]}"#), None);
+        assert_eq!(error_reason("not json"), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! End-to-end replay against an in-process server
+//!
+//! Builds a server-side capture of two users exchanging a message, replays
+//! it over real sockets and checks the server accepted every frame.
+
+use profile_replay::{replay, Identities, ReplayPlan, Side, Timing};
+use profile_server::connection::handler::handle_connection;
+use profile_server::lobby::Lobby;
+use profile_server::rate_limiter::AuthRateLimiter;
+use profile_server::rooms::Rooms;
+use profile_shared::capture::{CaptureRecord, Direction, FrameKind};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+const ALICE: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+const BOB: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+/// Start a server on an ephemeral port and return its WebSocket URL
+async fn start_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let lobby = Arc::new(Lobby::new());
+    let rooms = Arc::new(Rooms::new());
+    let rate_limiter = Arc::new(AuthRateLimiter::new());
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_connection(
+                stream,
+                Arc::clone(&lobby),
+                Arc::clone(&rooms),
+                Arc::clone(&rate_limiter),
+                None,
+                None,
+            ));
+        }
+    });
+    format!("ws://{}", addr)
+}
+
+fn inbound(ts_ms: u64, connection: u64, kind: FrameKind, data: String) -> CaptureRecord {
+    CaptureRecord {
+        ts_ms,
+        direction: Direction::Inbound,
+        connection,
+        kind,
+        data,
+    }
+}
+
+fn auth(public_key: &str) -> String {
+    format!(
+        r#"{{"type":"auth","publicKey":"{}","signature":"[redacted]"}}"#,
+        public_key
+    )
+}
+
+#[tokio::test]
+async fn test_replayed_session_is_accepted_by_server() {
+    let url = start_server().await;
+    let message = format!(
+        r#"{{"type":"message","recipientPublicKey":"{}","message":"hello","senderPublicKey":"{}","signature":"[redacted]","timestamp":"2025-01-01T00:00:00Z"}}"#,
+        BOB, ALICE
+    );
+    let records = vec![
+        inbound(10_000, 1, FrameKind::Text, auth(ALICE)),
+        inbound(11_000, 2, FrameKind::Text, auth(BOB)),
+        inbound(13_000, 1, FrameKind::Text, message),
+        inbound(14_000, 1, FrameKind::Close, String::new()),
+    ];
+
+    let plan = ReplayPlan::from_records(&records, Side::Server);
+    let identities = Arc::new(Identities::generate(plan.public_keys()).unwrap());
+    assert_eq!(identities.len(), 2);
+
+    let stats = replay(
+        &url,
+        &plan,
+        identities,
+        Timing::Accelerated(20.0),
+        Duration::from_millis(200),
+    )
+    .await;
+
+    assert_eq!(stats.connections, 2);
+    assert_eq!(stats.failed_connections, 0);
+    assert_eq!(stats.frames_sent, 4);
+    // A bad auth or message signature would have come back as an error
+    assert!(stats.errors.is_empty(), "server errors: {:?}", stats.errors);
+    // At least one auth success per connection
+    assert!(
+        stats.frames_received >= 2,
+        "received {}",
+        stats.frames_received
+    );
+    // Four seconds of capture at 20x
+    assert!(stats.elapsed >= Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn test_unreachable_server_counts_failed_connections() {
+    let records = vec![inbound(0, 1, FrameKind::Text, auth(ALICE))];
+    let plan = ReplayPlan::from_records(&records, Side::Server);
+    let identities = Arc::new(Identities::generate(plan.public_keys()).unwrap());
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let stats = replay(&url, &plan, identities, Timing::Flood, Duration::ZERO).await;
+
+    assert_eq!(stats.connections, 0);
+    assert_eq!(stats.failed_connections, 1);
+    assert_eq!(stats.frames_sent, 0);
+}
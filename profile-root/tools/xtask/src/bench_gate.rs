@@ -0,0 +1,266 @@
+//! Performance regression gate for the shared crypto benchmarks
+//!
+//! Criterion writes each benchmark's estimates to
+//! `target/criterion/<group>/<name>/new/estimates.json`. The gate reads the
+//! mean of every benchmark in the group and compares it with the baseline
+//! recorded in `shared/benches/baseline.json`; a benchmark slower than its
+//! baseline by more than the threshold fails the gate.
+//!
+//! Baselines are machine-specific: record one with `--record` on the
+//! machine that runs the gate.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// Criterion group benchmarked by the gate
+pub const BENCH_GROUP: &str = "crypto";
+
+/// Allowed slowdown before the gate fails, in percent
+pub const DEFAULT_THRESHOLD_PERCENT: f64 = 15.0;
+
+/// Recorded mean time per benchmark, in nanoseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    /// Mean time per benchmark id (`group/name`), in nanoseconds
+    pub benchmarks: BTreeMap<String, f64>,
+}
+
+impl Baseline {
+    /// Load the baseline at `path`
+    ///
+    /// # Returns
+    /// Ok(None) if no baseline has been recorded
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map(Some)
+                .map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Save the baseline to `path`
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json + "\n")
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+}
+
+#[derive(Deserialize)]
+struct Estimates {
+    mean: Estimate,
+}
+
+#[derive(Deserialize)]
+struct Estimate {
+    point_estimate: f64,
+}
+
+/// Read the latest mean of every benchmark in `group`
+///
+/// # Arguments
+/// * `criterion_dir` - Criterion's output directory (`target/criterion`)
+/// * `group` - Benchmark group to read
+pub fn read_results(criterion_dir: &Path, group: &str) -> Result<Baseline, String> {
+    let group_dir = criterion_dir.join(group);
+    let entries = std::fs::read_dir(&group_dir)
+        .map_err(|e| format!("No results in {}: {}", group_dir.display(), e))?;
+
+    let mut benchmarks = BTreeMap::new();
+    for entry in entries.flatten() {
+        let estimates = entry.path().join("new").join("estimates.json");
+        let Ok(text) = std::fs::read_to_string(&estimates) else {
+            continue;
+        };
+        let parsed: Estimates = serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", estimates.display(), e))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        benchmarks.insert(format!("{}/{}", group, name), parsed.mean.point_estimate);
+    }
+    if benchmarks.is_empty() {
+        return Err(format!("No results in {}", group_dir.display()));
+    }
+    Ok(Baseline { benchmarks })
+}
+
+/// How one benchmark compares with its baseline
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// Within the threshold (change in percent, negative is faster)
+    Ok(f64),
+    /// Slower than the threshold allows (change in percent)
+    Regressed(f64),
+    /// Measured but not in the baseline
+    New,
+    /// In the baseline but not measured
+    Missing,
+}
+
+/// One line of the gate report
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub benchmark: String,
+    pub baseline_ns: Option<f64>,
+    pub current_ns: Option<f64>,
+    pub verdict: Verdict,
+}
+
+impl Comparison {
+    /// Whether this comparison fails the gate
+    pub fn fails(&self) -> bool {
+        matches!(self.verdict, Verdict::Regressed(_) | Verdict::Missing)
+    }
+}
+
+fn format_ns(ns: Option<f64>) -> String {
+    match ns {
+        Some(ns) if ns >= 1_000_000.0 => format!("{:.2} ms", ns / 1_000_000.0),
+        Some(ns) if ns >= 1_000.0 => format!("{:.2} µs", ns / 1_000.0),
+        Some(ns) => format!("{:.0} ns", ns),
+        None => "-".to_string(),
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let verdict = match self.verdict {
+            Verdict::Ok(change) => format!("ok ({:+.1}%)", change),
+            Verdict::Regressed(change) => format!("REGRESSED ({:+.1}%)", change),
+            Verdict::New => "new, not in baseline".to_string(),
+            Verdict::Missing => "MISSING, not measured".to_string(),
+        };
+        write!(
+            f,
+            "{:<28} {:>12} -> {:>12}  {}",
+            self.benchmark,
+            format_ns(self.baseline_ns),
+            format_ns(self.current_ns),
+            verdict
+        )
+    }
+}
+
+/// Compare measured results with the baseline
+///
+/// # Arguments
+/// * `baseline` - Recorded means
+/// * `current` - Means from this run
+/// * `threshold_percent` - Allowed slowdown before a benchmark regresses
+pub fn compare(baseline: &Baseline, current: &Baseline, threshold_percent: f64) -> Vec<Comparison> {
+    let mut names: Vec<&String> = baseline
+        .benchmarks
+        .keys()
+        .chain(current.benchmarks.keys())
+        .collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let baseline_ns = baseline.benchmarks.get(name).copied();
+            let current_ns = current.benchmarks.get(name).copied();
+            let verdict = match (baseline_ns, current_ns) {
+                (Some(before), Some(after)) => {
+                    let change = (after - before) / before * 100.0;
+                    if change > threshold_percent {
+                        Verdict::Regressed(change)
+                    } else {
+                        Verdict::Ok(change)
+                    }
+                }
+                (None, _) => Verdict::New,
+                (_, None) => Verdict::Missing,
+            };
+            Comparison {
+                benchmark: name.clone(),
+                baseline_ns,
+                current_ns,
+                verdict,
+            }
+        })
+        .collect()
+}
+
+/// Criterion's output directory for the workspace at `workspace_root`
+pub fn criterion_dir(workspace_root: &Path) -> PathBuf {
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| workspace_root.join("target"))
+        .join("criterion")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline(entries: &[(&str, f64)]) -> Baseline {
+        Baseline {
+            benchmarks: entries
+                .iter()
+                .map(|(name, ns)| (name.to_string(), *ns))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_compare_flags_regressions_and_missing_benchmarks() {
+        let recorded = baseline(&[
+            ("crypto/sign", 50_000.0),
+            ("crypto/verify", 65_000.0),
+            ("crypto/keygen", 60_000.0),
+        ]);
+        let current = baseline(&[
+            ("crypto/sign", 54_000.0),
+            ("crypto/verify", 80_000.0),
+            ("crypto/verify_batch_64", 2_000_000.0),
+        ]);
+
+        let report = compare(&recorded, &current, 15.0);
+        let verdicts: Vec<(&str, &Verdict)> = report
+            .iter()
+            .map(|c| (c.benchmark.as_str(), &c.verdict))
+            .collect();
+        assert_eq!(
+            verdicts,
+            vec![
+                ("crypto/keygen", &Verdict::Missing),
+                ("crypto/sign", &Verdict::Ok(8.0)),
+                ("crypto/verify", &Verdict::Regressed(23.076923076923077)),
+                ("crypto/verify_batch_64", &Verdict::New),
+            ]
+        );
+        let failing: Vec<&str> = report
+            .iter()
+            .filter(|c| c.fails())
+            .map(|c| c.benchmark.as_str())
+            .collect();
+        assert_eq!(failing, vec!["crypto/keygen", "crypto/verify"]);
+    }
+
+    #[test]
+    fn test_read_results_and_baseline_round_trip() {
+        let dir = std::env::temp_dir().join(format!("xtask-bench-{}", std::process::id()));
+        let estimates = dir.join("crypto").join("sign").join("new");
+        std::fs::create_dir_all(&estimates).unwrap();
+        std::fs::write(
+            estimates.join("estimates.json"),
+            r#"{"mean":{"point_estimate":51537.5,"standard_error":10.0}}"#,
+        )
+        .unwrap();
+
+        let results = read_results(&dir, "crypto").unwrap();
+        assert_eq!(results, baseline(&[("crypto/sign", 51_537.5)]));
+
+        let path = dir.join("baseline.json");
+        assert_eq!(Baseline::load(&path), Ok(None));
+        results.save(&path).unwrap();
+        assert_eq!(Baseline::load(&path), Ok(Some(results)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+//! Workspace maintenance tasks, run with `cargo xtask <task>`
+//!
+//! Tasks:
+//! - `bench-gate [--threshold <percent>] [--record] [--no-run]`: run the
+//!   shared crypto benchmarks and fail if any is slower than its recorded
+//!   baseline by more than the threshold (15% by default). `--record`
+//!   saves the results as the new baseline instead; `--no-run` compares
+//!   the results of the last `cargo bench` without running it again.
+
+mod bench_gate;
+
+use bench_gate::{
+    compare, criterion_dir, read_results, Baseline, BENCH_GROUP, DEFAULT_THRESHOLD_PERCENT,
+};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+const USAGE: &str = "Usage: cargo xtask bench-gate [--threshold <percent>] [--record] [--no-run]";
+
+struct GateOptions {
+    threshold: f64,
+    record: bool,
+    run: bool,
+}
+
+fn parse_gate_args(mut args: impl Iterator<Item = String>) -> Result<GateOptions, String> {
+    let mut options = GateOptions {
+        threshold: DEFAULT_THRESHOLD_PERCENT,
+        record: false,
+        run: true,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => {
+                let value = args.next().ok_or("--threshold needs a value")?;
+                options.threshold = value
+                    .parse()
+                    .ok()
+                    .filter(|t: &f64| t.is_finite() && *t >= 0.0)
+                    .ok_or(format!("Invalid --threshold: {}", value))?;
+            }
+            "--record" => options.record = true,
+            "--no-run" => options.run = false,
+            other => return Err(format!("Unexpected argument {}", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../..")
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from("."))
+}
+
+fn run_benches(root: &Path) -> Result<(), String> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .current_dir(root)
+        .args(["bench", "--package", "profile-shared", "--bench", "crypto"])
+        .status()
+        .map_err(|e| format!("Failed to run cargo bench: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("cargo bench failed: {}", status))
+    }
+}
+
+fn bench_gate(options: GateOptions) -> Result<bool, String> {
+    let root = workspace_root();
+    let baseline_path = root.join("shared").join("benches").join("baseline.json");
+    if options.run {
+        run_benches(&root)?;
+    }
+    let current = read_results(&criterion_dir(&root), BENCH_GROUP)?;
+
+    if options.record {
+        current.save(&baseline_path)?;
+        println!("Recorded baseline in {}", baseline_path.display());
+        return Ok(true);
+    }
+    let Some(baseline) = Baseline::load(&baseline_path)? else {
+        return Err(format!(
+            "No baseline at {}; record one with --record",
+            baseline_path.display()
+        ));
+    };
+
+    let report = compare(&baseline, &current, options.threshold);
+    for comparison in &report {
+        println!("{}", comparison);
+    }
+    let failed = report.iter().filter(|c| c.fails()).count();
+    if failed > 0 {
+        println!(
+            "{} benchmark(s) regressed more than {}% or went missing",
+            failed, options.threshold
+        );
+    }
+    Ok(failed == 0)
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let result = match args.next().as_deref() {
+        Some("bench-gate") => parse_gate_args(args).and_then(bench_gate),
+        _ => Err("Unknown task".to_string()),
+    };
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}